@@ -7,6 +7,7 @@ import (
 	"path/filepath"
 
 	"github.com/enigmampc/SecretNetwork/x/compute"
+	computeclient "github.com/enigmampc/SecretNetwork/x/compute/client"
 	reg "github.com/enigmampc/SecretNetwork/x/registration"
 	"github.com/enigmampc/SecretNetwork/x/tokenswap"
 	bam "github.com/enigmampc/cosmos-sdk/baseapp"
@@ -59,7 +60,7 @@ var (
 		staking.AppModuleBasic{},
 		mint.AppModuleBasic{},
 		distr.AppModuleBasic{},
-		gov.NewAppModuleBasic(paramsclient.ProposalHandler, distr.ProposalHandler, upgradeclient.ProposalHandler),
+		gov.NewAppModuleBasic(paramsclient.ProposalHandler, distr.ProposalHandler, upgradeclient.ProposalHandler, computeclient.TrackForeignChainProposalHandler),
 		params.AppModuleBasic{},
 		compute.AppModuleBasic{},
 		reg.AppModuleBasic{},
@@ -200,6 +201,7 @@ func NewSecretNetworkApp(
 	crisisSubspace := app.paramsKeeper.Subspace(crisis.DefaultParamspace)
 	evidenceSubspace := app.paramsKeeper.Subspace(evidence.DefaultParamspace)
 	tokenswapSubspace := app.paramsKeeper.Subspace(tokenswap.DefaultParamspace)
+	computeSubspace := app.paramsKeeper.Subspace(compute.DefaultParamspace)
 	// The AccountKeeper handles address -> account lookups
 	app.accountKeeper = auth.NewAccountKeeper(
 		app.cdc,
@@ -287,25 +289,45 @@ func NewSecretNetworkApp(
 	}
 	wasmConfig = wasmWrap.Wasm
 
+	supportedFeatures := "staking"
+	// replace with bootstrap flag when we figure out how to test properly and everything works
+	app.regKeeper = reg.NewKeeper(app.cdc, keys[reg.StoreKey], regRouter, reg.EnclaveApi{}, homeDir, app.bootstrap)
+	app.computeKeeper = compute.NewKeeper(
+		app.cdc,
+		keys[compute.StoreKey],
+		computeSubspace,
+		app.accountKeeper, &app.bankKeeper, &app.govKeeper, &app.distrKeeper, &app.mintKeeper, &stakingKeeper,
+		computeRouter, computeDir, wasmConfig, supportedFeatures, nil, nil)
+
+	// the compute keeper only keeps a pointer to the gov keeper, so the gov keeper is created after it
+	// to route the proposals to track other chains to it
 	govRouter := gov.NewRouter()
 	// register the proposal types
 	govRouter.AddRoute(gov.RouterKey, gov.ProposalHandler).
 		AddRoute(params.RouterKey, params.NewParamChangeProposalHandler(app.paramsKeeper)).
 		AddRoute(distr.RouterKey, distr.NewCommunityPoolSpendProposalHandler(app.distrKeeper)).
-		AddRoute(upgrade.RouterKey, upgrade.NewSoftwareUpgradeProposalHandler(app.upgradeKeeper))
+		AddRoute(upgrade.RouterKey, upgrade.NewSoftwareUpgradeProposalHandler(app.upgradeKeeper)).
+		AddRoute(compute.RouterKey, compute.NewForeignChainProposalHandler(app.computeKeeper))
 	app.govKeeper = gov.NewKeeper(
 		app.cdc, keys[gov.StoreKey], govSubspace,
 		app.supplyKeeper, &stakingKeeper, govRouter,
 	)
 
-	supportedFeatures := "staking"
-	// replace with bootstrap flag when we figure out how to test properly and everything works
-	app.regKeeper = reg.NewKeeper(app.cdc, keys[reg.StoreKey], regRouter, reg.EnclaveApi{}, homeDir, app.bootstrap)
-	app.computeKeeper = compute.NewKeeper(
-		app.cdc,
-		keys[compute.StoreKey],
-		app.accountKeeper, &app.bankKeeper, &app.govKeeper, &app.distrKeeper, &app.mintKeeper, &stakingKeeper,
-		computeRouter, computeDir, wasmConfig, supportedFeatures, nil, nil)
+	// the enclave verifies the headers of the chain with the commits that signed them, which only
+	// Tendermint has, so they're read from the RPC of this node
+	if rpcAddr := viper.GetString("rpc.laddr"); rpcAddr != "" {
+		headerSource, err := compute.NewRPCHeaderSource(rpcAddr)
+		if err != nil {
+			logger.Error("not submitting block headers to the enclave, failed to connect to the tendermint rpc", "error", err.Error())
+		} else {
+			app.computeKeeper.SetHeaderSource(headerSource, logger)
+		}
+	}
+
+	// the enclave keeps its own metrics, which are served along with the ones of Tendermint
+	if viper.GetBool("instrumentation.prometheus") {
+		app.computeKeeper.RegisterEnclaveMetrics()
+	}
 
 	// register the staking hooks
 	// NOTE: stakingKeeper above is passed by reference, so that it will contain these hooks
@@ -403,6 +425,9 @@ func NewSecretNetworkApp(
 		if err != nil {
 			tmos.Exit(err.Error())
 		}
+
+		// Outside of consensus, only makes the first calls to contracts after a restart faster
+		app.computeKeeper.PrewarmContracts(app.NewContext(true, abci.Header{Height: app.LastBlockHeight()}))
 	}
 
 	return app
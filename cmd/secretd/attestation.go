@@ -1,6 +1,8 @@
 package main
 
 import (
+	"bufio"
+	"crypto/ed25519"
 	"encoding/base64"
 	"encoding/hex"
 	"encoding/json"
@@ -9,6 +11,7 @@ import (
 	"io/ioutil"
 	"os"
 	"path/filepath"
+	"strings"
 
 	app "github.com/enigmampc/SecretNetwork"
 	"github.com/enigmampc/SecretNetwork/go-cosmwasm/api"
@@ -23,6 +26,7 @@ import (
 )
 
 const flagReset = "reset"
+const flagDeep = "deep"
 
 func InitAttestation(
 	_ *server.Context, _ *codec.Codec) *cobra.Command {
@@ -274,8 +278,9 @@ func HealthCheck(_ *server.Context, _ *codec.Codec) *cobra.Command {
 	cmd := &cobra.Command{
 		Use:   "check-enclave",
 		Short: "Test enclave status",
-		Long:  "Help diagnose issues by performing a basic sanity test that SGX is working properly",
-		Args:  cobra.ExactArgs(0),
+		Long: "Help diagnose issues by performing a basic sanity test that SGX is working properly. " +
+			"With --deep, also check that the enclave can unseal its keys, encrypt, access storage and load its attestation certificate",
+		Args: cobra.ExactArgs(0),
 		RunE: func(cmd *cobra.Command, args []string) error {
 
 			res, err := api.HealthCheck()
@@ -284,6 +289,122 @@ func HealthCheck(_ *server.Context, _ *codec.Codec) *cobra.Command {
 			}
 
 			fmt.Println(fmt.Sprintf("SGX enclave health status: %s", res))
+
+			capabilities, err := api.GetEnclaveCapabilities()
+			if err != nil {
+				return fmt.Errorf("failed to get the enclave capabilities. Enclave returned: %s", err)
+			}
+			fmt.Println(fmt.Sprintf("Enclave version: %s", capabilities.Version()))
+			fmt.Println(fmt.Sprintf("CosmWasm API versions: %d-%d", capabilities.CosmWasmAPIMin, capabilities.CosmWasmAPIMax))
+
+			deep, err := cmd.Flags().GetBool(flagDeep)
+			if err != nil || !deep {
+				return err
+			}
+
+			health, err := api.HealthCheckDeep()
+			if err != nil {
+				return fmt.Errorf("failed to run the deep health check. Enclave returned: %s", err)
+			}
+
+			fmt.Println(fmt.Sprintf("Consensus seed: %t (%s)", health.Seed, health.SeedStatus))
+			fmt.Println(fmt.Sprintf("Encryption: %t", health.Encryption))
+			fmt.Println(fmt.Sprintf("Storage: %t", health.Storage))
+			fmt.Println(fmt.Sprintf("Registration certificate: %t", health.RegistrationCert))
+			return nil
+		},
+	}
+	cmd.Flags().Bool(flagDeep, false, "Optional flag to also check the keys, storage access and attestation certificate of the enclave")
+
+	return cmd
+}
+
+// decommissionSignPrefix is prepended to the challenge of the enclave before the operator signs it
+const decommissionSignPrefix = "secret-enclave-decommission"
+
+// readOperatorKey reads the ed25519 key of the operator from a file that holds its hex encoded seed
+func readOperatorKey(path string) (ed25519.PrivateKey, error) {
+	keyHex, err := ioutil.ReadFile(path)
+	if err != nil {
+		return nil, err
+	}
+	keySeed, err := hex.DecodeString(strings.TrimSpace(string(keyHex)))
+	if err != nil || len(keySeed) != ed25519.SeedSize {
+		return nil, fmt.Errorf("operator key file must hold a hex encoded %d byte seed", ed25519.SeedSize)
+	}
+	return ed25519.NewKeyFromSeed(keySeed), nil
+}
+
+func PinDecommissionOperator(_ *server.Context, _ *codec.Codec) *cobra.Command {
+	cmd := &cobra.Command{
+		Use:   "pin-decommission-operator [operator-key-file]",
+		Short: "Set the operator who may decommission the enclave",
+		Long: "Seal the public key of the operator who may decommission the enclave with decommission-enclave. " +
+			"The operator key file holds the hex encoded seed of an ed25519 key. " +
+			"It must be done while the node is set up, before it registers and gets the consensus seed, and the operator can't be replaced afterwards",
+		Args: cobra.ExactArgs(1),
+		RunE: func(cmd *cobra.Command, args []string) error {
+			operatorKey, err := readOperatorKey(args[0])
+			if err != nil {
+				return err
+			}
+
+			operatorPublicKey := operatorKey.Public().(ed25519.PublicKey)
+			err = api.PinDecommissionOperator(operatorPublicKey)
+			if err != nil {
+				return fmt.Errorf("failed to pin the operator. Enclave returned: %s", err)
+			}
+
+			fmt.Println(fmt.Sprintf("Only %s may decommission the enclave", hex.EncodeToString(operatorPublicKey)))
+			return nil
+		},
+	}
+
+	return cmd
+}
+
+func DecommissionEnclave(_ *server.Context, _ *codec.Codec) *cobra.Command {
+	cmd := &cobra.Command{
+		Use:   "decommission-enclave [operator-key-file] [output-file]",
+		Short: "Erase the keys of the enclave for good",
+		Long: "Erase the consensus seed and registration key of the enclave, from its memory and from disk, and write the record of the erasure signed by the enclave to the output file. " +
+			"On SGX hardware the record also holds a DCAP quote of the enclave over it. " +
+			"The operator key file holds the hex encoded seed of the ed25519 key that was pinned with pin-decommission-operator, which signs the challenge of the enclave once it's typed back. " +
+			"The node can't run contracts or register again afterwards",
+		Args: cobra.ExactArgs(2),
+		RunE: func(cmd *cobra.Command, args []string) error {
+			operatorKey, err := readOperatorKey(args[0])
+			if err != nil {
+				return err
+			}
+
+			challenge, erasurePublicKey, err := api.DecommissionArm(operatorKey.Public().(ed25519.PublicKey))
+			if err != nil {
+				return fmt.Errorf("failed to arm the decommissioning. Enclave returned: %s", err)
+			}
+
+			fmt.Println(fmt.Sprintf("Challenge: %s", hex.EncodeToString(challenge)))
+			fmt.Print("This erases the keys of the node and can't be undone. Type the challenge to confirm: ")
+			typed, err := bufio.NewReader(cmd.InOrStdin()).ReadString('\n')
+			if err != nil {
+				return err
+			}
+			if strings.TrimSpace(typed) != hex.EncodeToString(challenge) {
+				return fmt.Errorf("the challenge doesn't match, the keys were not erased")
+			}
+
+			signature := ed25519.Sign(operatorKey, append([]byte(decommissionSignPrefix), challenge...))
+			record, err := api.DecommissionConfirm(signature)
+			if err != nil {
+				return fmt.Errorf("failed to decommission the enclave. Enclave returned: %s", err)
+			}
+
+			err = ioutil.WriteFile(args[1], record, 0644)
+			if err != nil {
+				return err
+			}
+
+			fmt.Println(fmt.Sprintf("Enclave decommissioned. The erasure record, signed by %s, was written to %s", hex.EncodeToString(erasurePublicKey), args[1]))
 			return nil
 		},
 	}
@@ -66,6 +66,8 @@ func main() {
 	rootCmd.AddCommand(ConfigureSecret(ctx, cdc))
 	rootCmd.AddCommand(HealthCheck(ctx, cdc))
 	rootCmd.AddCommand(ResetEnclave(ctx, cdc))
+	rootCmd.AddCommand(PinDecommissionOperator(ctx, cdc))
+	rootCmd.AddCommand(DecommissionEnclave(ctx, cdc))
 	rootCmd.AddCommand(InitBootstrapCmd(ctx, cdc, app.ModuleBasics))
 	rootCmd.AddCommand(updateTmParamsAndInit(ctx, cdc, app.ModuleBasics, app.DefaultNodeHome))
 	rootCmd.AddCommand(genutilcli.CollectGenTxsCmd(ctx, cdc, auth.GenesisAccountIterator{}, app.DefaultNodeHome))
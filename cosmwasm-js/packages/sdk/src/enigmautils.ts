@@ -9,7 +9,7 @@ const cryptoProvider = new miscreant.PolyfillCryptoProvider();
 
 export interface SecretUtils {
   getPubkey: () => Promise<Uint8Array>;
-  decrypt: (ciphertext: Uint8Array, nonce: Uint8Array) => Promise<Uint8Array>;
+  decrypt: (ciphertext: Uint8Array, nonce: Uint8Array, associatedData?: Uint8Array[]) => Promise<Uint8Array>;
   encrypt: (contractCodeHash: string, msg: object) => Promise<Uint8Array>;
   getTxEncryptionKey: (nonce: Uint8Array) => Promise<Uint8Array>;
 }
@@ -129,7 +129,11 @@ export default class EnigmaUtils implements SecretUtils {
     return Uint8Array.from([...nonce, ...this.pubkey, ...ciphertext]);
   }
 
-  public async decrypt(ciphertext: Uint8Array, nonce: Uint8Array): Promise<Uint8Array> {
+  public async decrypt(
+    ciphertext: Uint8Array,
+    nonce: Uint8Array,
+    associatedData: Uint8Array[] = [new Uint8Array()],
+  ): Promise<Uint8Array> {
     if (ciphertext.length === 0) {
       return new Uint8Array();
     }
@@ -138,7 +142,7 @@ export default class EnigmaUtils implements SecretUtils {
 
     const siv = await miscreant.SIV.importKey(txEncryptionKey, "AES-SIV", cryptoProvider);
 
-    const plaintext = await siv.open(ciphertext, [new Uint8Array()]);
+    const plaintext = await siv.open(ciphertext, associatedData);
     return plaintext;
   }
 
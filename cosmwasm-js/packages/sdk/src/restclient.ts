@@ -15,6 +15,29 @@ import {
 } from "./types";
 import EnigmaUtils, { SecretUtils } from "./enigmautils";
 
+/** The first byte of the plaintext of an encrypted data field, see `decryptDataField` */
+const rawDataVersion = 1;
+
+/** The first associated data of log attributes that are bound to their place in the tx */
+const logAttributeAdVersion = 1;
+
+/**
+ * The associated data that binds the key or value of a log attribute to its place in the tx:
+ * [version, path steps as u32 BE, index as u32 BE, "key" or "value"]
+ */
+function logAttributeAssociatedData(
+  executionPath: readonly number[],
+  index: number,
+  field: string,
+): Uint8Array[] {
+  const path = new Uint8Array(4 * executionPath.length);
+  executionPath.forEach((step, i) => new DataView(path.buffer).setUint32(4 * i, step));
+  const indexBz = new Uint8Array(4);
+  new DataView(indexBz.buffer).setUint32(0, index);
+
+  return [Uint8Array.from([logAttributeAdVersion]), path, indexBz, Encoding.toUtf8(field)];
+}
+
 export interface CosmosSdkAccount {
   /** Bech32 account address */
   readonly address: string;
@@ -563,11 +586,13 @@ export class RestClient {
     let error;
     for (const nonce of nonces) {
       try {
-        const data = Encoding.fromBase64(
-          Encoding.fromUtf8(await this.enigmautils.decrypt(wasmOutputDataCipherBz, nonce)),
-        );
+        const plaintext = await this.enigmautils.decrypt(wasmOutputDataCipherBz, nonce);
 
-        return data;
+        // Data of newer enclaves is prefixed with a version byte, older ones encrypted it base64 encoded
+        if (plaintext.length > 0 && plaintext[0] === rawDataVersion) {
+          return plaintext.slice(1);
+        }
+        return Encoding.fromBase64(Encoding.fromUtf8(plaintext));
       } catch (e) {
         error = e;
       }
@@ -576,28 +601,62 @@ export class RestClient {
     throw error;
   }
 
+  private async decryptLogField(
+    field: string,
+    name: string,
+    nonce: Uint8Array,
+    executionPath: readonly number[] | undefined,
+    index: number,
+  ): Promise<string> {
+    const associatedData =
+      executionPath === undefined ? undefined : logAttributeAssociatedData(executionPath, index, name);
+    return Encoding.fromUtf8(
+      await this.enigmautils.decrypt(Encoding.fromBase64(field), nonce, associatedData),
+    );
+  }
+
+  /**
+   * Decrypts the attributes of the "wasm" events. An event may hold the attributes of several
+   * contract calls. The attributes of each call start with its plaintext contract_address, and its
+   * execution_path if the enclave bound the attributes of the call to their place in the tx, in
+   * which case the attributes only decrypt at that place. Attributes that don't decrypt with any of
+   * the nonces are left as they are.
+   */
   public async decryptLogs(logs: readonly Log[], nonces: Array<Uint8Array>): Promise<readonly Log[]> {
     for (const l of logs) {
       for (const e of l.events) {
-        if (e.type === "wasm") {
+        if (e.type !== "wasm") {
+          continue;
+        }
+
+        let executionPath: number[] | undefined;
+        let index = 0;
+        // the module only tags a call with its path right after its address
+        let afterContractAddress = false;
+        for (const a of e.attributes) {
+          if (a.key === "contract_address") {
+            executionPath = undefined;
+            index = 0;
+            afterContractAddress = true;
+            continue;
+          }
+          if (a.key === "execution_path" && afterContractAddress) {
+            executionPath = JSON.parse(a.value);
+            afterContractAddress = false;
+            continue;
+          }
+          afterContractAddress = false;
+
           for (const nonce of nonces) {
-            let nonceOk = false;
-            for (const a of e.attributes) {
-              try {
-                a.key = Encoding.fromUtf8(await this.enigmautils.decrypt(Encoding.fromBase64(a.key), nonce));
-                nonceOk = true;
-              } catch (e) {}
-              try {
-                a.value = Encoding.fromUtf8(
-                  await this.enigmautils.decrypt(Encoding.fromBase64(a.value), nonce),
-                );
-                nonceOk = true;
-              } catch (e) {}
-            }
-            if (nonceOk) {
-              continue;
-            }
+            try {
+              const key = await this.decryptLogField(a.key, "key", nonce, executionPath, index);
+              const value = await this.decryptLogField(a.value, "value", nonce, executionPath, index);
+              a.key = key;
+              a.value = value;
+              break;
+            } catch (e) {}
           }
+          index++;
         }
       }
     }
@@ -1,6 +1,6 @@
 export interface SecretUtils {
   getPubkey: () => Promise<Uint8Array>;
-  decrypt: (ciphertext: Uint8Array, nonce: Uint8Array) => Promise<Uint8Array>;
+  decrypt: (ciphertext: Uint8Array, nonce: Uint8Array, associatedData?: Uint8Array[]) => Promise<Uint8Array>;
   encrypt: (contractCodeHash: string, msg: object) => Promise<Uint8Array>;
   getTxEncryptionKey: (nonce: Uint8Array) => Promise<Uint8Array>;
 }
@@ -25,6 +25,6 @@ export default class EnigmaUtils implements SecretUtils {
   private getConsensusIoPubKey;
   getTxEncryptionKey(nonce: Uint8Array): Promise<Uint8Array>;
   encrypt(contractCodeHash: string, msg: object): Promise<Uint8Array>;
-  decrypt(ciphertext: Uint8Array, nonce: Uint8Array): Promise<Uint8Array>;
+  decrypt(ciphertext: Uint8Array, nonce: Uint8Array, associatedData?: Uint8Array[]): Promise<Uint8Array>;
   getPubkey(): Promise<Uint8Array>;
 }
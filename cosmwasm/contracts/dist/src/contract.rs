@@ -51,6 +51,7 @@ pub fn try_query_rewards<S: Storage, A: Api, Q: Querier>(
         .0 as u64;
 
     Ok(HandleResponse {
+        submessages: vec![],
         messages: vec![],
         log: vec![],
         data: Some(Binary::from(active_proposal.to_be_bytes().to_vec())),
@@ -127,6 +127,7 @@ fn try_transfer<S: Storage, A: Api, Q: Querier>(
     )?;
 
     let res = HandleResponse {
+        submessages: vec![],
         messages: vec![],
         log: vec![
             log("action", "transfer"),
@@ -172,6 +173,7 @@ fn try_transfer_from<S: Storage, A: Api, Q: Querier>(
     )?;
 
     let res = HandleResponse {
+        submessages: vec![],
         messages: vec![],
         log: vec![
             log("action", "transfer_from"),
@@ -199,6 +201,7 @@ fn try_approve<S: Storage, A: Api, Q: Querier>(
         amount.u128(),
     )?;
     let res = HandleResponse {
+        submessages: vec![],
         messages: vec![],
         log: vec![
             log("action", "approve"),
@@ -247,6 +250,7 @@ fn try_burn<S: Storage, A: Api, Q: Querier>(
     config_store.set(KEY_TOTAL_SUPPLY, &total_supply.to_be_bytes());
 
     let res = HandleResponse {
+        submessages: vec![],
         messages: vec![],
         log: vec![
             log("action", "burn"),
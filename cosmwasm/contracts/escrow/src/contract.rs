@@ -107,6 +107,7 @@ fn send_tokens<A: Api>(
     let log = vec![log("action", action), log("to", to_human.as_str())];
 
     let r = HandleResponse {
+        submessages: vec![],
         messages: vec![CosmosMsg::Bank(BankMsg::Send {
             from_address: from_human,
             to_address: to_human,
@@ -30,6 +30,7 @@ pub fn try_vote<S: Storage, A: Api, Q: Querier>(
     env: Env,
 ) -> StdResult<HandleResponse> {
     Ok(HandleResponse {
+        submessages: vec![],
         messages: vec![CosmosMsg::Gov(GovMsg::Vote {
             proposal: 1,
             vote_option: VoteOption::Yes,
@@ -53,6 +54,7 @@ pub fn try_query_proposal<S: Storage, A: Api, Q: Querier>(
     let active_proposal = query_rewards.proposals.len() as u64;
 
     Ok(HandleResponse {
+        submessages: vec![],
         messages: vec![],
         log: vec![],
         data: Some(Binary::from(active_proposal.to_be_bytes().to_vec())),
@@ -41,6 +41,7 @@ pub fn try_inflation_rate<S: Storage, A: Api, Q: Querier>(
     let active_proposal = query_rewards.inflation_rate.as_bytes().to_vec();
 
     Ok(HandleResponse {
+        submessages: vec![],
         messages: vec![],
         log: vec![],
         data: Some(Binary::from(active_proposal)),
@@ -63,6 +64,7 @@ pub fn try_bonded_ratio<S: Storage, A: Api, Q: Querier>(
     let active_proposal = query_rewards.bonded_ratio.as_bytes().to_vec();
 
     Ok(HandleResponse {
+        submessages: vec![],
         messages: vec![],
         log: vec![],
         data: Some(Binary::from(active_proposal)),
@@ -46,6 +46,7 @@ pub fn try_reflect<S: Storage, A: Api, Q: Querier>(
         return Err(StdError::generic_err("Must reflect at least one message"));
     }
     let res = HandleResponse {
+        submessages: vec![],
         messages: msgs,
         log: vec![log("action", "reflect")],
         data: None,
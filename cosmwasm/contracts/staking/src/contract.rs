@@ -85,6 +85,7 @@ pub fn transfer<S: Storage, A: Api, Q: Querier>(
     })?;
 
     let res = HandleResponse {
+        submessages: vec![],
         messages: vec![],
         log: vec![
             log("action", "transfer"),
@@ -169,6 +170,7 @@ pub fn bond<S: Storage, A: Api, Q: Querier>(
 
     // bond them to the validator
     let res = HandleResponse {
+        submessages: vec![],
         messages: vec![StakingMsg::Delegate {
             validator: invest.validator,
             amount: payment.clone(),
@@ -238,6 +240,7 @@ pub fn unbond<S: Storage, A: Api, Q: Querier>(
 
     // unbond them
     let res = HandleResponse {
+        submessages: vec![],
         messages: vec![StakingMsg::Undelegate {
             validator: invest.validator,
             amount: coin(unbond.u128(), &invest.bond_denom),
@@ -287,6 +290,7 @@ pub fn claim<S: Storage, A: Api, Q: Querier>(
     // transfer tokens to the sender
     balance.amount = to_send;
     let res = HandleResponse {
+        submessages: vec![],
         messages: vec![BankMsg::Send {
             from_address: env.contract.address,
             to_address: env.message.sender.clone(),
@@ -316,6 +320,7 @@ pub fn reinvest<S: Storage, A: Api, Q: Querier>(
 
     // and bond them to the validator
     let res = HandleResponse {
+        submessages: vec![],
         messages: vec![
             StakingMsg::Withdraw {
                 validator: invest.validator,
@@ -368,6 +373,7 @@ pub fn _bond_all_tokens<S: Storage, A: Api, Q: Querier>(
 
     // and bond them to the validator
     let res = HandleResponse {
+        submessages: vec![],
         messages: vec![StakingMsg::Delegate {
             validator: invest.validator,
             amount: balance.clone(),
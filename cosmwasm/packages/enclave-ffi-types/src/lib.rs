@@ -4,9 +4,17 @@
 mod types;
 
 pub use types::{
-    Ctx, EnclaveBuffer, EnclaveError, HandleResult, InitResult, NodeAuthResult, OcallReturn,
-    QueryResult, UntrustedVmError, UserSpaceBuffer, HealthCheckResult
+    AllocationResult, Ctx, EnclaveBuffer, EnclaveCapabilities, EnclaveError,
+    ExportContractStateResult, GasBreakdown, HandleResult, HealthCheckDeepResult,
+    HealthCheckResult, IbcReceiveResult, IbcResult, ImportContractStateResult, InitResult,
+    InputVerificationStep, InvalidWasmReason, LastPanicReport, MigrateResult, NodeAuthResult,
+    OcallReturn, PrewarmStatus, QueryResult, RotateContractKeyResult, RuntimeConfiguration,
+    RuntimeStats, SeedStatus, UntrustedVmError, UpdateAdminResult, UserSpaceBuffer,
+    ValidateWasmResult, VerifyInputResult,
 };
 
 pub const ENCRYPTED_SEED_SIZE: usize = 48;
 pub const PUBLIC_KEY_SIZE: usize = 32;
+/// The size of an entry passed to `ecall_prewarm_contracts`: the code hash of the contract,
+/// followed by the offset and the length of its code in the code buffer, as big-endian u32s.
+pub const PREWARM_ENTRY_SIZE: usize = 32 + 4 + 4;
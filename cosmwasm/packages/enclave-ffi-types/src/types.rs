@@ -13,13 +13,33 @@ pub struct UserSpaceBuffer {
 #[repr(C)]
 pub struct EnclaveBuffer {
     pub ptr: *mut c_void,
+    /// Set by `ecall_allocate` to a value that is never reused, so that a buffer that was already
+    /// recovered can't be confused with a newer buffer that got the same address.
+    pub generation: u64,
 }
 
 impl EnclaveBuffer {
     /// # Safety
     /// Very unsafe. Much careful
     pub unsafe fn unsafe_clone(&self) -> Self {
-        EnclaveBuffer { ptr: self.ptr }
+        EnclaveBuffer {
+            ptr: self.ptr,
+            generation: self.generation,
+        }
+    }
+
+    /// The buffer `ecall_allocate` returns when asked to copy zero bytes. It doesn't point to an
+    /// allocation, and is recovered as an empty buffer, unlike the null default that means no
+    /// buffer at all.
+    pub fn empty() -> Self {
+        Self {
+            ptr: core::ptr::NonNull::<u8>::dangling().as_ptr() as *mut c_void,
+            generation: 0,
+        }
+    }
+
+    pub fn is_empty_buffer(&self) -> bool {
+        self.ptr == Self::empty().ptr && self.generation == 0
     }
 }
 
@@ -30,6 +50,7 @@ impl Default for EnclaveBuffer {
     fn default() -> Self {
         Self {
             ptr: core::ptr::null_mut(),
+            generation: 0,
         }
     }
 }
@@ -48,6 +69,138 @@ impl Ctx {
     }
 }
 
+/// Statistics about the memory usage of the enclave, as reported by `ecall_get_runtime_stats`.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RuntimeStats {
+    /// The amount of bytes currently allocated on the enclave heap
+    pub allocated_bytes: u64,
+    /// The most bytes that were allocated on the enclave heap at once since the last time the
+    /// stats were read
+    pub peak_allocated_bytes: u64,
+    /// The amount of 1 KiB chunks currently held by the OOM safety buffer
+    pub safety_buffer_chunks: u64,
+    /// The amount of 1 KiB chunks the OOM safety buffer tries to hold
+    pub safety_buffer_target_chunks: u64,
+    /// The amount of buffers allocated by `ecall_allocate` that weren't recovered yet
+    pub allocate_stack_len: u64,
+    /// The amount of panics caught in ecalls since the enclave started
+    pub panics_caught: u64,
+    /// The amount of contracts that were loaded because their module wasn't cached, since the
+    /// enclave started
+    pub module_cache_misses: u64,
+    /// The amount of storage reads answered with a value the enclave read ahead, since the
+    /// enclave started
+    pub prefetch_hits: u64,
+    /// The amount of storage reads that went to the host in executions that read ahead, since
+    /// the enclave started
+    pub prefetch_misses: u64,
+}
+
+/// The last panic in the enclave, as reported by `ecall_get_last_panic_report`.
+/// Both buffers are null when no ecall panicked since the enclave started.
+#[repr(C)]
+pub struct LastPanicReport {
+    /// The panic message, location and backtrace, sealed to the identity of the enclave
+    pub sealed_report: UserSpaceBuffer,
+    /// Which ecall panicked and where, without the panic message
+    pub summary: UserSpaceBuffer,
+}
+
+impl Default for LastPanicReport {
+    fn default() -> Self {
+        Self {
+            sealed_report: UserSpaceBuffer {
+                ptr: core::ptr::null_mut(),
+            },
+            summary: UserSpaceBuffer {
+                ptr: core::ptr::null_mut(),
+            },
+        }
+    }
+}
+
+/// Settings of the enclave that operators can change while it runs, see `ecall_configure_runtime`.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RuntimeConfiguration {
+    /// The most verbose level the enclave logs at:
+    /// 0 = off, 1 = error, 2 = warn, 3 = info, 4 = debug, 5 = trace.
+    /// The enclave never logs more than its build allows, whatever this is set to. `u8::MAX` keeps
+    /// the level the enclave has.
+    pub max_log_level: u8,
+    /// The gas limit, as wasm gas, of queries the host passes a limit of 0 or `u64::MAX` for.
+    /// 0 keeps the limit the enclave has.
+    pub default_query_gas_limit: u64,
+    /// The largest contract, in bytes, the enclave validates or runs. Larger contracts fail with
+    /// `EnclaveError::ContractTooLarge` before they are read. 0 keeps the limit the enclave has.
+    pub max_contract_size: u64,
+    /// The most bytes of results of client queries the enclave caches. The cache is off until
+    /// this is set. 0 keeps the size the enclave has, and `u64::MAX` turns the cache off.
+    pub query_cache_size: u64,
+    /// Whether the enclave traces its ecalls, see `ecall_get_trace`. 0 keeps tracing as it is, 1
+    /// turns it on, and 2 turns it off.
+    pub ecall_tracing: u8,
+}
+
+/// The gas used by a successful execution, split by where it was spent.
+/// The gas the enclave reports as used is `wasm + ocall`. `storage` was already charged by the
+/// host while the storage ocalls ran, so the total cost of the execution is the sum of all three.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct GasBreakdown {
+    /// Gas used by wasm instructions, minus the refund for storage the contract removed
+    pub wasm: u64,
+    /// Gas the host charged for the storage ocalls
+    pub storage: u64,
+    /// Fixed gas charged for every ocall, for the round trip out of the enclave
+    pub ocall: u64,
+}
+
+/// Why the WASM code of a contract was rejected. These are safe to show in plaintext, as they
+/// only describe the code, which is public.
+/// cbindgen:prefix-with-name
+#[repr(C)]
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidWasmReason {
+    #[display(fmt = "the module could not be parsed")]
+    Malformed,
+    #[display(fmt = "the module failed validation")]
+    FailedValidation,
+    #[display(fmt = "the imports of the module could not be resolved")]
+    UnresolvedImports,
+    #[display(fmt = "floating point operations are not allowed")]
+    FloatingPoint,
+    #[display(fmt = "SIMD instructions and types are not allowed")]
+    Simd,
+    #[display(fmt = "shared memory and atomic instructions are not allowed")]
+    Threads,
+    #[display(fmt = "reference types and multiple tables are not allowed")]
+    ReferenceTypes,
+    #[display(fmt = "bulk memory operations and passive segments are not allowed")]
+    BulkMemory,
+    #[display(fmt = "the module was built for a CosmWasm API version that is not supported")]
+    UnsupportedApiVersion,
+    #[display(fmt = "the module imports a function its CosmWasm API version does not provide")]
+    UnsupportedImport,
+    #[display(fmt = "start functions are not allowed")]
+    StartFunction,
+}
+
+/// Why a module that was loaded could not be instantiated. Like `InvalidWasmReason`, these only
+/// describe the code, so they are safe to show in plaintext.
+/// cbindgen:prefix-with-name
+#[repr(C)]
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
+pub enum InstantiationFailureReason {
+    #[display(fmt = "a data segment does not fit in the memory of the module")]
+    DataSegmentOutOfBounds,
+    #[display(fmt = "an element segment does not fit in the table of the module")]
+    ElementSegmentOutOfBounds,
+    #[display(fmt = "the module could not be instantiated")]
+    Other,
+}
+
 /// This type represents the possible error conditions that can be encountered in the enclave
 /// cbindgen:prefix-with-name
 #[repr(C)]
@@ -65,10 +218,13 @@ pub enum EnclaveError {
     ValidationFailure,
     // Problems with the module binary
     /// The WASM code was invalid and could not be loaded.
-    #[display(fmt = "tried to load invalid wasm code")]
-    InvalidWasm,
+    #[display(fmt = "tried to load invalid wasm code: {}", reason)]
+    InvalidWasm { reason: InvalidWasmReason },
     #[display(fmt = "failed to initialize wasm memory")]
     CannotInitializeWasmMemory,
+    /// Instantiating the module failed, e.g. because one of its segments doesn't fit.
+    #[display(fmt = "failed to instantiate wasm code: {}", reason)]
+    InstantiationFailed { reason: InstantiationFailureReason },
     /// The WASM module contained a start section, which is not allowed.
     WasmModuleWithStart,
     /// The WASM module contained floating point operations, which is not allowed.
@@ -77,15 +233,43 @@ pub enum EnclaveError {
     /// Fail to inject gas metering
     #[display(fmt = "failed to inject gas metering")]
     FailedGasMeteringInjection,
+    /// The contract is larger than the maximum contract size.
+    #[display(fmt = "contract exceeds the maximum contract size")]
+    ContractTooLarge,
+    /// A chunked wasm validation was started with the id of a session that is still open.
+    #[display(fmt = "a wasm validation session with this id is already open")]
+    WasmValidationSessionExists,
+    /// A chunk was sent to a wasm validation session that isn't open, or that expired.
+    #[display(fmt = "no open wasm validation session with this id")]
+    WasmValidationSessionNotFound,
+    /// A chunk of a wasm validation session arrived out of order.
+    #[display(fmt = "wasm chunk arrived out of order")]
+    WasmChunkOutOfOrder,
     #[display(fmt = "internal error during execution")]
     InternalError,
     // runtime issues with the module
     /// Ran out of gas
     #[display(fmt = "execution ran out of gas")]
     OutOfGas,
+    /// Ran out of the gas left in the block, which the chain set lower than the gas limit of the tx
+    #[display(fmt = "execution ran out of the gas left in the block")]
+    OutOfBlockGas,
+    /// Ran past the execution timeout the node set
+    #[display(fmt = "execution ran out of time")]
+    ExecutionTimeout,
     /// Calling a function in the contract failed.
     #[display(fmt = "calling a function in the contract failed for an unexpected reason")]
     FailedFunctionCall,
+    /// The contract panicked after it was refused memory past its limit.
+    #[display(fmt = "the contract exceeded its memory limit")]
+    ContractMemoryLimitExceeded,
+    /// A write would have brought the storage of the contract past the quota the chain set.
+    #[display(
+        fmt = "the contract would store {} bytes, more than its storage quota of {} bytes",
+        usage,
+        quota
+    )]
+    StorageQuotaExceeded { usage: u64, quota: u64 },
     // These variants mimic the variants of `wasmi::TrapKind`
     /// The contract panicked during execution.
     #[display(fmt = "the contract panicked")]
@@ -128,8 +312,41 @@ pub enum EnclaveError {
     FailedToSerialize,
     #[display(fmt = "failed to encrypt data")]
     EncryptionError,
-    #[display(fmt = "failed to decrypt data")]
+    /// The encrypted output of the contract is larger than the enclave may return.
+    #[display(fmt = "the contract returned a response larger than the maximum size")]
+    ResponseTooLarge,
+    /// The output of the contract has more messages, or more bytes before it was encrypted, than
+    /// the chain allows. Both limits are part of the error, so developers know what to shrink.
+    #[display(
+        fmt = "the contract returned {} messages in {} bytes, the maximum is {} messages in {} bytes",
+        messages,
+        bytes,
+        max_messages,
+        max_bytes
+    )]
+    ResultTooLarge {
+        messages: u32,
+        max_messages: u32,
+        bytes: u64,
+        max_bytes: u64,
+    },
+    /// The output of the contract isn't strict JSON: it has duplicate keys, invalid UTF-8, or is
+    /// nested too deep.
+    #[display(fmt = "the contract returned a malformed response")]
+    MalformedContractResponse,
+    /// The ciphertext doesn't authenticate under the key derived for it. Either it was encrypted
+    /// with another key, or it was changed after it was encrypted.
+    #[display(fmt = "failed to decrypt data: wrong encryption key or corrupted ciphertext")]
     DecryptionError,
+    /// The encrypted message is too short to hold its nonce, public key and ciphertext.
+    #[display(fmt = "failed to decrypt data: encrypted message is too short")]
+    CiphertextTooShort,
+    /// The encrypted message has a nonce the enclave can't decrypt it with.
+    #[display(fmt = "failed to decrypt data: invalid encryption nonce")]
+    InvalidNonce,
+    /// The enclave has no key yet to derive the encryption key of the message from.
+    #[display(fmt = "failed to decrypt data: failed to derive the encryption key")]
+    KeyDerivationFailed,
     #[display(fmt = "failed to allocate memory")]
     MemoryAllocationError,
     #[display(fmt = "failed to allocate minimal safety buffer")]
@@ -144,18 +361,71 @@ pub enum EnclaveError {
     FailedTxVerification,
     #[display(fmt = "contract tried to write to storage during a query")]
     UnauthorizedWrite,
+    #[display(fmt = "contract migration was not requested by the contract admin")]
+    UnauthorizedMigration,
+    #[display(fmt = "reply was not requested by a submessage of the contract")]
+    UnauthorizedReply,
+    #[display(fmt = "contract key rotation was not requested by the contract admin")]
+    UnauthorizedKeyRotation,
+    #[display(fmt = "contract admin update was not requested by the contract admin")]
+    UnauthorizedAdminUpdate,
+    #[display(fmt = "IBC message is not for the port of the contract")]
+    UnauthorizedIbcPort,
+    #[display(fmt = "contract can't send encrypted messages from this entry point")]
+    UnencryptableMessage,
+    /// A query sent with replay protection arrived outside of the block heights it may be answered
+    /// in, or without an env to tell the block height.
+    #[display(fmt = "query was sent outside of its block height window")]
+    QueryOutsideReplayWindow,
+    /// A query sent with replay protection has the nonce of a query that was already answered.
+    #[display(fmt = "query was already answered and can't be replayed")]
+    ReplayedQuery,
+    /// A tx message reuses the public key and encryption nonce of a message the contract executed
+    /// before.
+    #[display(fmt = "message reuses the encryption nonce of an earlier message")]
+    NonceReuse,
+    /// An entry of a contract state archive doesn't authenticate: it was changed, or it was
+    /// exported from another contract or as part of another snapshot.
+    #[display(fmt = "contract state archive was tampered with or belongs to another contract")]
+    TamperedStateArchive,
 
     // serious issues
     /// The host was caught trying to disrupt the enclave.
     /// This can happen if e.g. the host provides invalid pointers as responses from ocalls.
     #[display(fmt = "communication with the enclave's host failed")]
     HostMisbehavior,
+    /// The host passed a contract that isn't in its own memory.
+    #[display(fmt = "the host passed an invalid pointer to the contract")]
+    InvalidContractPointer,
+    /// The host passed an env that isn't in its own memory.
+    #[display(fmt = "the host passed an invalid pointer to the env")]
+    InvalidEnvPointer,
+    /// The host passed a message that isn't in its own memory.
+    #[display(fmt = "the host passed an invalid pointer to the message")]
+    InvalidMsgPointer,
+    /// The host passed signature info that isn't in its own memory.
+    #[display(fmt = "the host passed an invalid pointer to the signature info")]
+    InvalidSigInfoPointer,
+    /// The host asked for the used gas to be written outside of its own memory.
+    #[display(fmt = "the host passed an invalid pointer to the used gas")]
+    InvalidUsedGasPointer,
+    /// The response to a query of another contract wasn't encrypted by a contract with the code
+    /// hash the query was sent to, so the host delivered the query somewhere else.
+    #[display(fmt = "query response was not sent by a contract with the expected code hash")]
+    QueryCodeHashMismatch,
     #[display(fmt = "panicked due to unexpected behavior")]
     Panic,
     #[display(fmt = "enclave ran out of heap memory")]
     OutOfMemory,
     #[display(fmt = "depth of nested contract calls exceeded")]
     ExceededRecursionLimit,
+    /// The host tried to pass a response to an ocall that is larger than the enclave accepts.
+    #[display(fmt = "ocall response was larger than the enclave accepts")]
+    ExceededOcallResponseSize,
+    /// The consensus seed couldn't be unsealed, or the node was decommissioned, so no contract can
+    /// be run. The deep health check tells why.
+    #[display(fmt = "enclave is not initialized: the consensus seed is not available")]
+    EnclaveNotInitialized,
     /// Unexpected Error happened, no more details available
     #[display(fmt = "unknown error")]
     Unknown,
@@ -209,6 +479,14 @@ pub enum NodeAuthResult {
         fmt = "Unexpected panic during node authentication. Certificate may be malformed or invalid"
     )]
     Panic,
+    #[display(fmt = "The enclave was decommissioned and has no seed to share")]
+    EnclaveNotInitialized,
+    #[display(fmt = "Enclave quote status was OUT_OF_DATE which is not allowed")]
+    OutOfDate,
+    #[display(
+        fmt = "Enclave quote status was OUT_OF_DATE_CONFIGURATION_NEEDED which is not allowed"
+    )]
+    OutOfDateConfigurationNeeded,
 }
 
 /// This type represents the possible error conditions that can be encountered in the
@@ -226,6 +504,258 @@ impl Default for HealthCheckResult {
     }
 }
 
+/// Whether the enclave could unseal its consensus seed, or why it couldn't. Contract ecalls fail
+/// with `EnclaveError::EnclaveNotInitialized` unless it's `Ready`.
+/// cbindgen:prefix-with-name
+#[repr(C)]
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
+pub enum SeedStatus {
+    #[display(fmt = "ready")]
+    Ready,
+    /// There is no sealed seed, so the node was never initialized
+    #[display(fmt = "missing file")]
+    MissingFile,
+    /// The sealed seed doesn't authenticate under the sealing key of this enclave, so it was
+    /// sealed by another enclave or on another machine
+    #[display(fmt = "wrong measurement")]
+    WrongMeasurement,
+    /// The sealed seed can't be read, or is too short to hold a seed
+    #[display(fmt = "corrupted blob")]
+    CorruptedBlob,
+    /// The node was decommissioned, and every key it had was wiped
+    #[display(fmt = "decommissioned")]
+    Decommissioned,
+}
+
+/// The subsystems of the enclave that passed `ecall_health_check_deep`.
+/// Each bit of `passed` is set when the check of the matching subsystem succeeded.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HealthCheckDeepResult {
+    pub passed: u32,
+    /// Why the consensus seed couldn't be unsealed, if it couldn't
+    pub seed_status: SeedStatus,
+}
+
+impl Default for HealthCheckDeepResult {
+    fn default() -> Self {
+        Self {
+            passed: 0,
+            seed_status: SeedStatus::MissingFile,
+        }
+    }
+}
+
+impl HealthCheckDeepResult {
+    /// The consensus seed was unsealed, and a key could be derived from it
+    pub const SEED: u32 = 1;
+    /// A canary value survived an AES-SIV round trip under the derived key
+    pub const ENCRYPTION: u32 = 1 << 1;
+    /// A diagnostic value survived a write and read through the storage ocalls
+    pub const STORAGE: u32 = 1 << 2;
+    /// The attestation certificate of the node could be loaded
+    pub const REGISTRATION_CERT: u32 = 1 << 3;
+    /// All of the above
+    pub const ALL: u32 = Self::SEED | Self::ENCRYPTION | Self::STORAGE | Self::REGISTRATION_CERT;
+
+    pub fn all_passed(&self) -> bool {
+        self.passed == Self::ALL
+    }
+}
+
+/// The step at which `ecall_verify_input` stopped decrypting a message, in the order the enclave
+/// runs them when it executes a message.
+/// cbindgen:prefix-with-name
+#[repr(C)]
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
+pub enum InputVerificationStep {
+    #[display(fmt = "the message decrypted")]
+    Decrypted,
+    #[display(fmt = "the message is too short or too long to be an encrypted message")]
+    Parse,
+    #[display(fmt = "the message was encrypted by another public key than the one given")]
+    PublicKey,
+    #[display(fmt = "the encryption key could not be derived from the nonce and public key")]
+    KeyDerivation,
+    #[display(fmt = "the message could not be decrypted")]
+    Decryption,
+}
+
+/// What `ecall_verify_input` found out about an encrypted message. The plaintext itself is never
+/// returned.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VerifyInputResult {
+    /// `Decrypted` if the message decrypted, or the step that failed otherwise
+    pub step: InputVerificationStep,
+    /// The length of the decrypted message, or 0 if it didn't decrypt
+    pub plaintext_len: u64,
+}
+
+impl Default for VerifyInputResult {
+    fn default() -> Self {
+        Self {
+            step: InputVerificationStep::Parse,
+            plaintext_len: 0,
+        }
+    }
+}
+
+/// What `ecall_prewarm_contracts` did with one of the contracts it was given. It crosses the
+/// boundary as a single byte per contract.
+#[repr(u8)]
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
+pub enum PrewarmStatus {
+    /// The contract was validated, and its module is cached now
+    #[display(fmt = "warmed")]
+    Warmed = 0,
+    #[display(fmt = "already cached")]
+    AlreadyCached = 1,
+    /// The code of the entry isn't inside the code buffer
+    #[display(fmt = "invalid entry")]
+    InvalidEntry = 2,
+    /// The code doesn't hash to the code hash of the entry
+    #[display(fmt = "code hash mismatch")]
+    CodeHashMismatch = 3,
+    #[display(fmt = "invalid wasm")]
+    InvalidWasm = 4,
+    /// The contract was skipped because the time or bytes budget of the call ran out
+    #[display(fmt = "over budget")]
+    OverBudget = 5,
+}
+
+impl PrewarmStatus {
+    pub fn from_u8(status: u8) -> Option<Self> {
+        match status {
+            0 => Some(PrewarmStatus::Warmed),
+            1 => Some(PrewarmStatus::AlreadyCached),
+            2 => Some(PrewarmStatus::InvalidEntry),
+            3 => Some(PrewarmStatus::CodeHashMismatch),
+            4 => Some(PrewarmStatus::InvalidWasm),
+            5 => Some(PrewarmStatus::OverBudget),
+            _ => None,
+        }
+    }
+}
+
+/// What the enclave supports, as reported by `ecall_get_capabilities`, so that the host can refuse
+/// features the enclave is too old for instead of failing somewhere inside the enclave.
+///
+/// It crosses the boundary as consecutive little-endian u32s in the order of the fields, preceded
+/// by the amount of bytes written. Fields are only ever appended, so readers skip the fields they
+/// don't know, and read the fields that weren't written as 0, which means unsupported.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct EnclaveCapabilities {
+    pub version_major: u32,
+    pub version_minor: u32,
+    pub version_patch: u32,
+    /// The oldest `cosmwasm_vm_version_*` export of contracts the enclave runs
+    pub cosmwasm_api_min: u32,
+    /// The newest `cosmwasm_vm_version_*` export of contracts the enclave runs
+    pub cosmwasm_api_max: u32,
+    /// The optional entry points of contracts the enclave can call, as `ENTRY_POINT_*` bits
+    pub entry_points: u32,
+    /// The optional imports the enclave offers to contracts, as `HOST_*` bits
+    pub host_functions: u32,
+}
+
+impl EnclaveCapabilities {
+    pub const ENTRY_POINT_MIGRATE: u32 = 1;
+    pub const ENTRY_POINT_REPLY: u32 = 1 << 1;
+    /// All the `ibc_*` entry points
+    pub const ENTRY_POINT_IBC: u32 = 1 << 2;
+
+    /// `addr_validate`, `addr_canonicalize` and `addr_humanize`
+    pub const HOST_ADDR: u32 = 1;
+    /// `db_scan` and `db_next`
+    pub const HOST_DB_ITERATOR: u32 = 1 << 1;
+    /// `db_read_multi`
+    pub const HOST_DB_READ_MULTI: u32 = 1 << 2;
+    /// `ed25519_verify`, `ed25519_batch_verify` and `secp256k1_recover_pubkey`
+    pub const HOST_SIGNATURES: u32 = 1 << 3;
+    /// `hash_sha256`, `hash_keccak256` and `hash_ripemd160`
+    pub const HOST_HASHES: u32 = 1 << 4;
+    /// `get_random`
+    pub const HOST_RANDOM: u32 = 1 << 5;
+    /// `memory_grow`
+    pub const HOST_MEMORY_GROW: u32 = 1 << 6;
+    /// `hkdf_sha256`
+    pub const HOST_HKDF: u32 = 1 << 7;
+    /// `db_read_ns`, `db_write_ns`, `db_remove_ns` and `db_move_to_ns`
+    pub const HOST_DB_NAMESPACES: u32 = 1 << 8;
+    /// `secp256k1_generate_key`, `secp256k1_sign` and `secp256k1_public_key`
+    pub const HOST_SECP256K1_SIGNING: u32 = 1 << 9;
+    /// `gas_left` and `gas_limit`
+    pub const HOST_GAS_LEFT: u32 = 1 << 10;
+    /// `verify_foreign_header`
+    pub const HOST_FOREIGN_HEADERS: u32 = 1 << 11;
+    /// `assert_contract_code_hash`
+    pub const HOST_CONTRACT_CODE_HASH: u32 = 1 << 12;
+    /// `db_storage_usage`
+    pub const HOST_DB_STORAGE_USAGE: u32 = 1 << 13;
+
+    /// The amount of bytes `write_to` needs to write all the fields, including the length
+    pub const ENCODED_LEN: usize = 4 * 8;
+
+    fn fields(&self) -> [u32; 7] {
+        [
+            self.version_major,
+            self.version_minor,
+            self.version_patch,
+            self.cosmwasm_api_min,
+            self.cosmwasm_api_max,
+            self.entry_points,
+            self.host_functions,
+        ]
+    }
+
+    /// Write as many whole fields as fit in `buf`, and return the amount of bytes written
+    pub fn write_to(&self, buf: &mut [u8]) -> usize {
+        if buf.len() < 4 {
+            return 0;
+        }
+
+        let fields = self.fields();
+        let count = core::cmp::min(fields.len(), buf.len() / 4 - 1);
+        let written = 4 * (count + 1);
+
+        buf[..4].copy_from_slice(&(written as u32).to_le_bytes());
+        for (chunk, field) in buf[4..written].chunks_exact_mut(4).zip(fields.iter()) {
+            chunk.copy_from_slice(&field.to_le_bytes());
+        }
+
+        written
+    }
+
+    /// Read what `write_to` wrote, possibly by an enclave that knows more or fewer fields
+    pub fn read_from(buf: &[u8]) -> Self {
+        let mut fields = [0u32; 7];
+        if buf.len() >= 4 {
+            let mut len = [0u8; 4];
+            len.copy_from_slice(&buf[..4]);
+            let written = core::cmp::min(u32::from_le_bytes(len) as usize, buf.len());
+
+            if written > 4 {
+                for (field, chunk) in fields.iter_mut().zip(buf[4..written].chunks_exact(4)) {
+                    let mut bytes = [0u8; 4];
+                    bytes.copy_from_slice(chunk);
+                    *field = u32::from_le_bytes(bytes);
+                }
+            }
+        }
+
+        Self {
+            version_major: fields[0],
+            version_minor: fields[1],
+            version_patch: fields[2],
+            cosmwasm_api_min: fields[3],
+            cosmwasm_api_max: fields[4],
+            entry_points: fields[5],
+            host_functions: fields[6],
+        }
+    }
+}
+
 /// This type holds a pointer to a VmError that is boxed on the untrusted side
 // `VmError` is the standard error type for the `cosmwasm-sgx-vm` layer.
 // During an ocall, we call into the original implementation of `db_read`, `db_write`, and `db_remove`.
@@ -288,11 +818,17 @@ pub enum InitResult {
         output: UserSpaceBuffer,
         /// The contract_key for this contract.
         contract_key: [u8; 64],
+        /// Authenticates the admin of this contract, to be passed back in the env.
+        admin_proof: [u8; 32],
     },
     Failure {
         /// The error that happened in the enclave
         err: EnclaveError,
     },
+    ContractError {
+        /// A pointer to the error returned by the contract, encrypted to the sender of the tx
+        output: UserSpaceBuffer,
+    },
 }
 
 /// This struct is returned from ecall_handle.
@@ -302,11 +838,66 @@ pub enum HandleResult {
     Success {
         /// A pointer to the output of the calculation
         output: UserSpaceBuffer,
+        /// Where the gas used by the execution was spent
+        gas_breakdown: GasBreakdown,
+    },
+    Failure {
+        /// The error that happened in the enclave
+        err: EnclaveError,
+        /// A plaintext description of where the contract trapped, such as
+        /// "trap: unreachable in transfer::execute at offset 0x1a2". Only enclaves built with
+        /// the `debug-print` feature report it, otherwise it's null.
+        debug_info: UserSpaceBuffer,
+    },
+    ContractError {
+        /// A pointer to the error returned by the contract, encrypted to the sender of the tx
+        output: UserSpaceBuffer,
+    },
+}
+
+/// This struct is returned from the ecalls of the IBC entry points, except for
+/// ecall_ibc_packet_receive.
+/// cbindgen:prefix-with-name
+#[repr(C)]
+pub enum IbcResult {
+    Success {
+        /// A pointer to the output of the calculation. Nothing in these entry points is encrypted
+        /// to anyone, so the output is in plaintext.
+        output: UserSpaceBuffer,
+        /// Where the gas used by the execution was spent
+        gas_breakdown: GasBreakdown,
     },
     Failure {
         /// The error that happened in the enclave
         err: EnclaveError,
     },
+    ContractError {
+        /// A pointer to the error returned by the contract
+        output: UserSpaceBuffer,
+    },
+}
+
+/// This struct is returned from ecall_ibc_packet_receive.
+/// cbindgen:prefix-with-name
+#[repr(C)]
+pub enum IbcReceiveResult {
+    Success {
+        /// A pointer to the output of the calculation, encrypted to the sender of the packet
+        output: UserSpaceBuffer,
+        /// A pointer to the acknowledgement to write for the packet, encrypted to the sender of
+        /// the packet
+        acknowledgement: UserSpaceBuffer,
+        /// Where the gas used by the execution was spent
+        gas_breakdown: GasBreakdown,
+    },
+    Failure {
+        /// The error that happened in the enclave
+        err: EnclaveError,
+    },
+    ContractError {
+        /// A pointer to the error returned by the contract, encrypted to the sender of the packet
+        output: UserSpaceBuffer,
+    },
 }
 
 /// This struct is returned from ecall_query.
@@ -321,4 +912,121 @@ pub enum QueryResult {
         /// The error that happened in the enclave
         err: EnclaveError,
     },
+    ContractError {
+        /// A pointer to the error returned by the contract, encrypted to the sender of the tx
+        output: UserSpaceBuffer,
+    },
+}
+
+/// This struct is returned from ecall_migrate.
+/// cbindgen:prefix-with-name
+#[repr(C)]
+pub enum MigrateResult {
+    Success {
+        /// A pointer to the output of the calculation
+        output: UserSpaceBuffer,
+        /// The contract_key this contract should use from now on.
+        new_contract_key: [u8; 64],
+    },
+    Failure {
+        /// The error that happened in the enclave
+        err: EnclaveError,
+    },
+    ContractError {
+        /// A pointer to the error returned by the contract, encrypted to the sender of the tx
+        output: UserSpaceBuffer,
+    },
+}
+
+/// This struct is returned from the ecalls of a chunked wasm validation.
+/// cbindgen:prefix-with-name
+#[repr(C)]
+pub enum ValidateWasmResult {
+    Success {
+        /// The hash of the validated code. Only set by `ecall_validate_wasm_end`, and zeroed by
+        /// the ecalls that start the session and add chunks to it.
+        code_hash: [u8; 32],
+    },
+    Failure {
+        /// The error that happened in the enclave
+        err: EnclaveError,
+    },
+}
+
+/// This struct is returned from ecall_allocate.
+/// cbindgen:prefix-with-name
+#[repr(C)]
+pub enum AllocationResult {
+    Success {
+        /// The copy of the buffer in the enclave, or `EnclaveBuffer::empty` for an empty buffer
+        buffer: EnclaveBuffer,
+    },
+    Failure {
+        /// Why the buffer couldn't be copied into the enclave
+        err: EnclaveError,
+    },
+}
+
+/// This struct is returned from ecall_rotate_contract_key.
+/// cbindgen:prefix-with-name
+#[repr(C)]
+pub enum RotateContractKeyResult {
+    Success {
+        /// The contract_key this contract should use from now on.
+        new_contract_key: [u8; 64],
+        /// A pointer to the storage key the next chunk of the rotation should start from.
+        /// Empty once all of the contract storage was rotated.
+        next_cursor: UserSpaceBuffer,
+    },
+    Failure {
+        /// The error that happened in the enclave
+        err: EnclaveError,
+    },
+}
+
+/// This struct is returned from ecall_export_contract_state.
+/// cbindgen:prefix-with-name
+#[repr(C)]
+pub enum ExportContractStateResult {
+    Success {
+        /// The nonce of the snapshot, which the chunks after this one must be exported with.
+        snapshot_nonce: [u8; 32],
+        /// A pointer to the chunk of the archive, as JSON.
+        chunk: UserSpaceBuffer,
+        /// A pointer to the storage key the next chunk of the export should start from.
+        /// Empty once all of the contract storage was exported.
+        next_cursor: UserSpaceBuffer,
+    },
+    Failure {
+        /// The error that happened in the enclave
+        err: EnclaveError,
+    },
+}
+
+/// This struct is returned from ecall_import_contract_state.
+/// cbindgen:prefix-with-name
+#[repr(C)]
+pub enum ImportContractStateResult {
+    Success {
+        /// How many entries of the chunk were written to the contract storage.
+        imported_entries: u32,
+    },
+    Failure {
+        /// The error that happened in the enclave
+        err: EnclaveError,
+    },
+}
+
+/// This struct is returned from ecall_update_admin.
+/// cbindgen:prefix-with-name
+#[repr(C)]
+pub enum UpdateAdminResult {
+    Success {
+        /// Authenticates the new admin of this contract, replacing the proof of the previous one.
+        admin_proof: [u8; 32],
+    },
+    Failure {
+        /// The error that happened in the enclave
+        err: EnclaveError,
+    },
 }
@@ -1,4 +1,6 @@
+use std::ffi::c_void;
 use std::net::{SocketAddr, TcpStream};
+use std::os::raw::c_char;
 use std::os::unix::io::IntoRawFd;
 
 use std::{self};
@@ -7,9 +9,10 @@ use log::*;
 use sgx_types::*;
 use sgx_types::{sgx_status_t, SgxResult};
 
-use enclave_ffi_types::{NodeAuthResult, ENCRYPTED_SEED_SIZE};
+use enclave_ffi_types::{NodeAuthResult, UserSpaceBuffer, ENCRYPTED_SEED_SIZE};
 
 use crate::enclave::get_enclave;
+use crate::wasmi::recover_buffer;
 
 extern "C" {
     pub fn ecall_get_attestation_report(
@@ -20,11 +23,17 @@ extern "C" {
         api_key: *const u8,
         api_key_len: u32,
     ) -> sgx_status_t;
+    pub fn ecall_get_attestation_bundle(
+        eid: sgx_enclave_id_t,
+        retval: *mut sgx_status_t,
+        bundle: *mut UserSpaceBuffer,
+    ) -> sgx_status_t;
     pub fn ecall_authenticate_new_node(
         eid: sgx_enclave_id_t,
         retval: *mut NodeAuthResult,
         cert: *const u8,
         cert_len: u32,
+        block_time: u64,
         seed: &mut [u8; ENCRYPTED_SEED_SIZE],
     ) -> sgx_status_t;
 }
@@ -126,6 +135,289 @@ pub extern "C" fn ocall_get_update_info(
     unsafe { sgx_report_attestation_status(platform_blob, enclave_trusted, update_info) }
 }
 
+extern "C" {
+    fn dlopen(filename: *const c_char, flag: c_int) -> *mut c_void;
+    fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+}
+
+const DCAP_QUOTE_LIBRARY: &[u8] = b"libsgx_dcap_ql.so.1\0";
+const DCAP_QUOTE_PROVIDER_LIBRARY: &[u8] = b"libdcap_quoteprov.so.1\0";
+
+/// `quote3_error_t` of the DCAP libraries
+const SGX_QL_SUCCESS: u32 = 0;
+
+/// `sgx_ql_qve_collateral_t` of the DCAP quote provider
+#[repr(C)]
+struct QuoteVerificationCollateral {
+    version: u32,
+    tee_type: u32,
+    pck_crl_issuer_chain: *mut c_char,
+    pck_crl_issuer_chain_size: u32,
+    root_ca_crl: *mut c_char,
+    root_ca_crl_size: u32,
+    pck_crl: *mut c_char,
+    pck_crl_size: u32,
+    tcb_info_issuer_chain: *mut c_char,
+    tcb_info_issuer_chain_size: u32,
+    tcb_info: *mut c_char,
+    tcb_info_size: u32,
+    qe_identity_issuer_chain: *mut c_char,
+    qe_identity_issuer_chain_size: u32,
+    qe_identity: *mut c_char,
+    qe_identity_size: u32,
+}
+
+/// Find `symbol` in the DCAP library `library`. The DCAP libraries are only installed on platforms
+/// that attest with DCAP, so they are loaded when they are needed instead of linked.
+fn dcap_symbol(library: &[u8], symbol: &[u8]) -> Option<*mut c_void> {
+    const RTLD_NOW: c_int = 2;
+
+    let handle = unsafe { dlopen(library.as_ptr() as *const c_char, RTLD_NOW) };
+    if handle.is_null() {
+        warn!(
+            "Could not load {}, are the DCAP libraries installed?",
+            String::from_utf8_lossy(&library[..library.len() - 1])
+        );
+        return None;
+    }
+
+    let address = unsafe { dlsym(handle, symbol.as_ptr() as *const c_char) };
+    if address.is_null() {
+        warn!(
+            "{} has no {}",
+            String::from_utf8_lossy(&library[..library.len() - 1]),
+            String::from_utf8_lossy(&symbol[..symbol.len() - 1])
+        );
+        return None;
+    }
+
+    Some(address)
+}
+
+#[no_mangle]
+pub extern "C" fn ocall_get_dcap_target_info(ret_ti: *mut sgx_target_info_t) -> sgx_status_t {
+    trace!("Entering ocall_get_dcap_target_info");
+
+    let address = match dcap_symbol(DCAP_QUOTE_LIBRARY, b"sgx_qe_get_target_info\0") {
+        Some(address) => address,
+        None => return sgx_status_t::SGX_ERROR_UNEXPECTED,
+    };
+    let sgx_qe_get_target_info: extern "C" fn(*mut sgx_target_info_t) -> u32 =
+        unsafe { std::mem::transmute(address) };
+
+    let ret = sgx_qe_get_target_info(ret_ti);
+    if ret != SGX_QL_SUCCESS {
+        warn!("sgx_qe_get_target_info returned {:#x}", ret);
+        return sgx_status_t::SGX_ERROR_UNEXPECTED;
+    }
+
+    sgx_status_t::SGX_SUCCESS
+}
+
+#[no_mangle]
+pub extern "C" fn ocall_get_dcap_quote(
+    p_report: *const sgx_report_t,
+    p_quote: *mut u8,
+    maxlen: u32,
+    p_quote_len: *mut u32,
+) -> sgx_status_t {
+    trace!("Entering ocall_get_dcap_quote");
+
+    let (get_quote_size, get_quote) = match (
+        dcap_symbol(DCAP_QUOTE_LIBRARY, b"sgx_qe_get_quote_size\0"),
+        dcap_symbol(DCAP_QUOTE_LIBRARY, b"sgx_qe_get_quote\0"),
+    ) {
+        (Some(get_quote_size), Some(get_quote)) => (get_quote_size, get_quote),
+        _ => return sgx_status_t::SGX_ERROR_UNEXPECTED,
+    };
+    let sgx_qe_get_quote_size: extern "C" fn(*mut u32) -> u32 =
+        unsafe { std::mem::transmute(get_quote_size) };
+    let sgx_qe_get_quote: extern "C" fn(*const sgx_report_t, u32, *mut u8) -> u32 =
+        unsafe { std::mem::transmute(get_quote) };
+
+    let mut real_quote_len: u32 = 0;
+    let ret = sgx_qe_get_quote_size(&mut real_quote_len as *mut u32);
+    if ret != SGX_QL_SUCCESS {
+        warn!("sgx_qe_get_quote_size returned {:#x}", ret);
+        return sgx_status_t::SGX_ERROR_UNEXPECTED;
+    }
+
+    trace!("quote size = {}", real_quote_len);
+    unsafe {
+        *p_quote_len = real_quote_len;
+    }
+    if real_quote_len > maxlen {
+        warn!("The quote is larger than the buffer for it");
+        return sgx_status_t::SGX_ERROR_UNEXPECTED;
+    }
+
+    let ret = sgx_qe_get_quote(p_report, real_quote_len, p_quote);
+    if ret != SGX_QL_SUCCESS {
+        warn!("sgx_qe_get_quote returned {:#x}", ret);
+        return sgx_status_t::SGX_ERROR_UNEXPECTED;
+    }
+
+    sgx_status_t::SGX_SUCCESS
+}
+
+#[no_mangle]
+pub extern "C" fn ocall_get_dcap_collateral(
+    p_fmspc: *const u8,
+    fmspc_len: u32,
+    p_pck_ca: *const u8,
+    pck_ca_len: u32,
+    p_tcb_info: *mut u8,
+    tcb_info_maxlen: u32,
+    p_tcb_info_len: *mut u32,
+    p_issuer_chain: *mut u8,
+    issuer_chain_maxlen: u32,
+    p_issuer_chain_len: *mut u32,
+    p_root_ca_crl: *mut u8,
+    root_ca_crl_maxlen: u32,
+    p_root_ca_crl_len: *mut u32,
+    p_pck_crl: *mut u8,
+    pck_crl_maxlen: u32,
+    p_pck_crl_len: *mut u32,
+) -> sgx_status_t {
+    trace!("Entering ocall_get_dcap_collateral");
+
+    let (get_collateral, free_collateral) = match (
+        dcap_symbol(
+            DCAP_QUOTE_PROVIDER_LIBRARY,
+            b"sgx_ql_get_quote_verification_collateral\0",
+        ),
+        dcap_symbol(
+            DCAP_QUOTE_PROVIDER_LIBRARY,
+            b"sgx_ql_free_quote_verification_collateral\0",
+        ),
+    ) {
+        (Some(get_collateral), Some(free_collateral)) => (get_collateral, free_collateral),
+        _ => return sgx_status_t::SGX_ERROR_UNEXPECTED,
+    };
+    let sgx_ql_get_quote_verification_collateral: extern "C" fn(
+        *const u8,
+        u16,
+        *const c_char,
+        *mut *mut QuoteVerificationCollateral,
+    ) -> u32 = unsafe { std::mem::transmute(get_collateral) };
+    let sgx_ql_free_quote_verification_collateral: extern "C" fn(
+        *const QuoteVerificationCollateral,
+    ) -> u32 = unsafe { std::mem::transmute(free_collateral) };
+
+    // The quote provider takes the name of the CA as a C string
+    let mut pck_ca = unsafe { std::slice::from_raw_parts(p_pck_ca, pck_ca_len as usize) }.to_vec();
+    pck_ca.push(0);
+
+    let mut collateral: *mut QuoteVerificationCollateral = std::ptr::null_mut();
+    let ret = sgx_ql_get_quote_verification_collateral(
+        p_fmspc,
+        fmspc_len as u16,
+        pck_ca.as_ptr() as *const c_char,
+        &mut collateral,
+    );
+    if ret != SGX_QL_SUCCESS || collateral.is_null() {
+        warn!(
+            "sgx_ql_get_quote_verification_collateral returned {:#x}",
+            ret
+        );
+        return sgx_status_t::SGX_ERROR_UNEXPECTED;
+    }
+
+    let status = unsafe {
+        let root_ca_crl =
+            collateral_string((*collateral).root_ca_crl, (*collateral).root_ca_crl_size)
+                .and_then(crl_der);
+        let pck_crl =
+            collateral_string((*collateral).pck_crl, (*collateral).pck_crl_size).and_then(crl_der);
+
+        match (
+            collateral_string((*collateral).tcb_info, (*collateral).tcb_info_size).map(
+                |tcb_info| copy_to_enclave(tcb_info, p_tcb_info, tcb_info_maxlen, p_tcb_info_len),
+            ),
+            collateral_string(
+                (*collateral).tcb_info_issuer_chain,
+                (*collateral).tcb_info_issuer_chain_size,
+            )
+            .map(|issuer_chain| {
+                copy_to_enclave(
+                    issuer_chain,
+                    p_issuer_chain,
+                    issuer_chain_maxlen,
+                    p_issuer_chain_len,
+                )
+            }),
+            root_ca_crl.map(|crl| {
+                copy_to_enclave(&crl, p_root_ca_crl, root_ca_crl_maxlen, p_root_ca_crl_len)
+            }),
+            pck_crl.map(|crl| copy_to_enclave(&crl, p_pck_crl, pck_crl_maxlen, p_pck_crl_len)),
+        ) {
+            (Some(true), Some(true), Some(true), Some(true)) => sgx_status_t::SGX_SUCCESS,
+            _ => sgx_status_t::SGX_ERROR_UNEXPECTED,
+        }
+    };
+
+    sgx_ql_free_quote_verification_collateral(collateral);
+    status
+}
+
+/// A string of the collateral, without the NUL it ends with
+unsafe fn collateral_string<'a>(src: *const c_char, src_len: u32) -> Option<&'a [u8]> {
+    if src.is_null() {
+        warn!("The quote provider returned incomplete collateral");
+        return None;
+    }
+
+    let mut collateral = std::slice::from_raw_parts(src as *const u8, src_len as usize);
+    while let Some((&0, rest)) = collateral.split_last() {
+        collateral = rest;
+    }
+
+    Some(collateral)
+}
+
+/// The DER of a CRL of the collateral. Depending on their version, quote providers return CRLs as
+/// PEM, as hex encoded DER or as DER.
+fn crl_der(crl: &[u8]) -> Option<Vec<u8>> {
+    const PEM_BEGIN: &[u8] = b"-----BEGIN X509 CRL-----";
+
+    let der = if crl.starts_with(PEM_BEGIN) {
+        // The base64 goes up to the END line
+        let base64: Vec<u8> = crl[PEM_BEGIN.len()..]
+            .iter()
+            .take_while(|byte| **byte != b'-')
+            .filter(|byte| !byte.is_ascii_whitespace())
+            .cloned()
+            .collect();
+        base64::decode(&base64).ok()
+    } else if !crl.is_empty() && crl.iter().all(u8::is_ascii_hexdigit) {
+        hex::decode(crl).ok()
+    } else {
+        Some(crl.to_vec())
+    };
+
+    if der.is_none() {
+        warn!("The quote provider returned a CRL that is neither PEM, hex nor DER");
+    }
+    der
+}
+
+/// Copy `collateral` to a buffer of the enclave
+unsafe fn copy_to_enclave(
+    collateral: &[u8],
+    dst: *mut u8,
+    dst_maxlen: u32,
+    dst_len: *mut u32,
+) -> bool {
+    *dst_len = collateral.len() as u32;
+    if collateral.len() > dst_maxlen as usize {
+        warn!("The collateral is larger than the buffer for it");
+        return false;
+    }
+    std::ptr::copy_nonoverlapping(collateral.as_ptr(), dst, collateral.len());
+
+    true
+}
+
 pub fn create_attestation_report_u(spid: &[u8], api_key: &[u8]) -> SgxResult<()> {
     let enclave = get_enclave()?;
 
@@ -153,8 +445,34 @@ pub fn create_attestation_report_u(spid: &[u8], api_key: &[u8]) -> SgxResult<()>
     Ok(())
 }
 
+/// The registration public key of this node and the attestation report it registered with, as a
+/// protobuf encoded `AttestationBundle`
+pub fn untrusted_get_attestation_bundle() -> SgxResult<Vec<u8>> {
+    let enclave = get_enclave()?;
+
+    let eid = enclave.geteid();
+    let mut retval = sgx_status_t::SGX_SUCCESS;
+    let mut bundle = UserSpaceBuffer {
+        ptr: std::ptr::null_mut(),
+    };
+    let status = unsafe { ecall_get_attestation_bundle(eid, &mut retval, &mut bundle) };
+
+    if status != sgx_status_t::SGX_SUCCESS {
+        return Err(status);
+    }
+
+    if retval != sgx_status_t::SGX_SUCCESS {
+        return Err(retval);
+    }
+
+    unsafe { recover_buffer(bundle) }.ok_or(sgx_status_t::SGX_ERROR_UNEXPECTED)
+}
+
+/// Authenticate the node that registers with `cert` in a block at `block_time`, in seconds since
+/// the epoch, and encrypt the seed for it
 pub fn untrusted_get_encrypted_seed(
     cert: &[u8],
+    block_time: u64,
 ) -> SgxResult<Result<[u8; ENCRYPTED_SEED_SIZE], NodeAuthResult>> {
     let enclave = get_enclave()?;
     let eid = enclave.geteid();
@@ -166,6 +484,7 @@ pub fn untrusted_get_encrypted_seed(
             &mut retval,
             cert.as_ptr(),
             cert.len() as u32,
+            block_time,
             &mut seed,
         )
     };
@@ -3,6 +3,9 @@ use std::fs::{create_dir_all, File, OpenOptions};
 use std::io::{Read, Write};
 use std::marker::PhantomData;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use flate2::read::GzDecoder;
 
 /*
 use crate::backends::{backend, compile};
@@ -15,16 +18,22 @@ use crate::instance::Instance;
 use crate::modules::FileSystemCache;
 */
 use crate::traits::{Api, Extern, Querier, Storage};
+use crate::wasmi::{validate_wasm, WASM_VALIDATION_CHUNK_SIZE};
 
 const WASM_DIR: &str = "wasm";
+/// The magic bytes of gzip, followed by its deflate compression method
+const GZIP_MAGIC: [u8; 3] = [0x1f, 0x8b, 0x08];
+/// How many times its size a gzipped upload may decompress to. Matches the enclave.
+const MAX_GZIP_EXPANSION: u64 = 10;
 /*
 const MODULES_DIR: &str = "modules";
 */
 
-#[derive(Debug, Default, Clone)]
+/// Queries get instances from the cache concurrently, so the stats are counted atomically
+#[derive(Debug, Default)]
 struct Stats {
-    hits_module: u32,
-    misses: u32,
+    hits_module: AtomicU32,
+    misses: AtomicU32,
 }
 
 pub struct CosmCache<S: Storage + 'static, A: Api + 'static, Q: Querier + 'static> {
@@ -82,7 +91,15 @@ where
     }
 
     pub fn save_wasm(&mut self, wasm: &[u8]) -> VmResult<Checksum> {
+        if wasm.starts_with(&GZIP_MAGIC) {
+            return self.save_gzipped_wasm(wasm);
+        }
+
         check_wasm(wasm, &self.supported_features)?;
+        let code_hash = validate_wasm(wasm, WASM_VALIDATION_CHUNK_SIZE)?;
+        if Checksum::from(code_hash) != Checksum::generate(wasm) {
+            return Err(VmError::integrity_err());
+        }
         let checksum = save_wasm_to_disk(&self.wasm_path, wasm)?;
         /*
         let module = compile(wasm)?;
@@ -91,6 +108,19 @@ where
         Ok(checksum)
     }
 
+    /// The enclave gets a gzipped upload as it is, decompresses it within its own limits, and
+    /// returns the hash of the code it decompressed to. The upload is only decompressed here
+    /// after that, and the code that's stored has to match the hash.
+    fn save_gzipped_wasm(&mut self, gzipped: &[u8]) -> VmResult<Checksum> {
+        let code_hash = validate_wasm(gzipped, WASM_VALIDATION_CHUNK_SIZE)?;
+        let wasm = decompress_gzip(gzipped)?;
+        check_wasm(&wasm, &self.supported_features)?;
+        if Checksum::from(code_hash) != Checksum::generate(&wasm) {
+            return Err(VmError::integrity_err());
+        }
+        save_wasm_to_disk(&self.wasm_path, &wasm)
+    }
+
     /// Retrieves a Wasm blob that was previously stored via save_wasm.
     /// When the cache is instantiated with the same base dir, this finds Wasm files on disc across multiple cache instances (i.e. node restarts).
     /// This function is public to allow a checksum to Wasm lookup in the blockchain.
@@ -109,7 +139,7 @@ where
     /// Returns an Instance tied to a previously saved Wasm.
     /// Depending on availability, this is either generated from a cached instance, a cached module or Wasm code.
     pub fn get_instance(
-        &mut self,
+        &self,
         checksum: &Checksum,
         deps: Extern<S, A, Q>,
         gas_limit: u64,
@@ -118,18 +148,34 @@ where
         // try from the module cache
         let res = self.modules.load_with_backend(checksum, backend());
         if let Ok(module) = res {
-            self.stats.hits_module += 1;
+            self.stats.hits_module.fetch_add(1, Ordering::Relaxed);
             return Instance::from_module(&module, deps, gas_limit);
         }
         */
 
         // fall back to wasm cache (and re-compiling) - this is for backends that don't support serialization
         let wasm = self.load_wasm(checksum)?;
-        self.stats.misses += 1;
+        self.stats.misses.fetch_add(1, Ordering::Relaxed);
         Instance::from_code(&wasm, deps, gas_limit)
     }
 }
 
+fn decompress_gzip(gzipped: &[u8]) -> VmResult<Vec<u8>> {
+    let limit = gzipped.len() as u64 * MAX_GZIP_EXPANSION;
+    let mut wasm = Vec::new();
+    GzDecoder::new(gzipped)
+        .take(limit + 1)
+        .read_to_end(&mut wasm)
+        .map_err(|e| VmError::cache_err(format!("Error decompressing Wasm: {}", e)))?;
+    if wasm.len() as u64 > limit {
+        return Err(VmError::cache_err(format!(
+            "Decompressed Wasm is larger than {} bytes",
+            limit
+        )));
+    }
+    Ok(wasm)
+}
+
 /// save stores the wasm code in the given directory and returns an ID for lookup.
 /// It will create the directory if it doesn't exist.
 /// Saving the same byte code multiple times is allowed.
@@ -173,6 +219,8 @@ mod test {
     use crate::features::features_from_csv;
     use crate::testing::{mock_dependencies, mock_env, MockApi, MockQuerier, MockStorage};
     use cosmwasm_std::{coins, Empty};
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
     use std::fs::OpenOptions;
     use std::io::Write;
     use tempfile::TempDir;
@@ -306,8 +354,8 @@ mod test {
         let id = cache.save_wasm(CONTRACT).unwrap();
         let deps = mock_dependencies(20, &[]);
         let _instance = cache.get_instance(&id, deps, TESTING_GAS_LIMIT).unwrap();
-        assert_eq!(cache.stats.hits_module, 1);
-        assert_eq!(cache.stats.misses, 0);
+        assert_eq!(cache.stats.hits_module.load(Ordering::Relaxed), 1);
+        assert_eq!(cache.stats.misses.load(Ordering::Relaxed), 0);
     }
 
     #[test]
@@ -321,8 +369,8 @@ mod test {
         let _instance1 = cache.get_instance(&id, deps1, TESTING_GAS_LIMIT).unwrap();
         let _instance2 = cache.get_instance(&id, deps2, TESTING_GAS_LIMIT).unwrap();
         let _instance3 = cache.get_instance(&id, deps3, TESTING_GAS_LIMIT).unwrap();
-        assert_eq!(cache.stats.hits_module, 3);
-        assert_eq!(cache.stats.misses, 0);
+        assert_eq!(cache.stats.hits_module.load(Ordering::Relaxed), 3);
+        assert_eq!(cache.stats.misses.load(Ordering::Relaxed), 0);
     }
 
     #[test]
@@ -424,8 +472,8 @@ mod test {
 
         // Init from module cache
         let mut instance1 = cache.get_instance(&id, deps1, TESTING_GAS_LIMIT).unwrap();
-        assert_eq!(cache.stats.hits_module, 1);
-        assert_eq!(cache.stats.misses, 0);
+        assert_eq!(cache.stats.hits_module.load(Ordering::Relaxed), 1);
+        assert_eq!(cache.stats.misses.load(Ordering::Relaxed), 0);
         let original_gas = instance1.get_gas_left();
 
         // Consume some gas
@@ -438,8 +486,8 @@ mod test {
 
         // Init from instance cache
         let instance2 = cache.get_instance(&id, deps2, TESTING_GAS_LIMIT).unwrap();
-        assert_eq!(cache.stats.hits_module, 2);
-        assert_eq!(cache.stats.misses, 0);
+        assert_eq!(cache.stats.hits_module.load(Ordering::Relaxed), 2);
+        assert_eq!(cache.stats.misses.load(Ordering::Relaxed), 0);
         assert_eq!(instance2.get_gas_left(), TESTING_GAS_LIMIT);
     }
 
@@ -455,8 +503,8 @@ mod test {
 
         // Init from module cache
         let mut instance1 = cache.get_instance(&id, deps1, 10).unwrap();
-        assert_eq!(cache.stats.hits_module, 1);
-        assert_eq!(cache.stats.misses, 0);
+        assert_eq!(cache.stats.hits_module.load(Ordering::Relaxed), 1);
+        assert_eq!(cache.stats.misses.load(Ordering::Relaxed), 0);
 
         // Consume some gas. This fails
         let env1 = mock_env("owner1", &coins(1000, "earth"));
@@ -469,8 +517,8 @@ mod test {
 
         // Init from instance cache
         let mut instance2 = cache.get_instance(&id, deps2, TESTING_GAS_LIMIT).unwrap();
-        assert_eq!(cache.stats.hits_module, 2);
-        assert_eq!(cache.stats.misses, 0);
+        assert_eq!(cache.stats.hits_module.load(Ordering::Relaxed), 2);
+        assert_eq!(cache.stats.misses.load(Ordering::Relaxed), 0);
         assert_eq!(instance2.get_gas_left(), TESTING_GAS_LIMIT);
 
         // Now it works
@@ -500,6 +548,30 @@ mod test {
         assert!(res.is_err());
     }
 
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn decompress_gzip_works() {
+        let gzipped = gzip(CONTRACT);
+        assert!(gzipped.starts_with(&GZIP_MAGIC));
+        assert_eq!(decompress_gzip(&gzipped).unwrap(), CONTRACT);
+    }
+
+    #[test]
+    fn decompress_gzip_stops_at_limit() {
+        let bomb = gzip(&vec![0u8; 1024 * 1024]);
+        match decompress_gzip(&bomb).unwrap_err() {
+            VmError::CacheErr { msg, .. } => {
+                assert!(msg.starts_with("Decompressed Wasm is larger than"))
+            }
+            e => panic!("Unexpected error: {:?}", e),
+        }
+    }
+
     #[test]
     fn load_wasm_from_disk_works() {
         let tmp_dir = TempDir::new().unwrap();
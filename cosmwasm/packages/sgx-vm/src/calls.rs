@@ -10,6 +10,7 @@ use crate::instance::{Func, Instance};
 use crate::instance::Instance;
 use crate::serde::{from_slice, to_vec};
 use crate::traits::{Api, Querier, Storage};
+use crate::wasmi::IbcEntryPoint;
 use schemars::JsonSchema;
 
 /*
@@ -116,7 +117,19 @@ pub fn call_handle_raw<S: Storage + 'static, A: Api + 'static, Q: Querier + 'sta
     /*
     call_raw(instance, "handle", &[env, msg], MAX_LENGTH_HANDLE)
     */
-    instance.call_handle(env, msg, sig_info)
+    instance.call_handle(env, msg, sig_info, false)
+}
+
+/// Like `call_handle_raw`, but the enclave discards the storage changes of the contract
+/// instead of writing them. Gas is charged the same as in `call_handle_raw`.
+pub fn call_handle_simulate_raw<S: Storage + 'static, A: Api + 'static, Q: Querier + 'static>(
+    instance: &mut Instance<S, A, Q>,
+    env: &[u8],
+    msg: &[u8],
+    sig_info: &[u8],
+) -> VmResult<Vec<u8>> {
+    instance.set_storage_readonly(true);
+    instance.call_handle(env, msg, sig_info, true)
 }
 
 /// Calls Wasm export "migrate" and returns raw data from the contract.
@@ -125,25 +138,113 @@ pub fn call_migrate_raw<S: Storage + 'static, A: Api + 'static, Q: Querier + 'st
     instance: &mut Instance<S, A, Q>,
     env: &[u8],
     msg: &[u8],
+    sig_info: &[u8],
 ) -> VmResult<Vec<u8>> {
     instance.set_storage_readonly(false);
     /*
     call_raw(instance, "migrate", &[env, msg], MAX_LENGTH_MIGRATE)
     */
-    instance.call_migrate(env, msg)
+    instance.call_migrate(env, msg, sig_info)
+}
+
+/// Re-encrypts the next `chunk_size` storage entries of the contract, starting at `cursor`,
+/// under a new contract key. Returns the new contract key followed by the cursor to continue from,
+/// which is empty once every entry was re-encrypted.
+pub fn call_rotate_contract_key_raw<
+    S: Storage + 'static,
+    A: Api + 'static,
+    Q: Querier + 'static,
+>(
+    instance: &mut Instance<S, A, Q>,
+    env: &[u8],
+    sig_info: &[u8],
+    cursor: &[u8],
+    chunk_size: u32,
+) -> VmResult<Vec<u8>> {
+    instance.set_storage_readonly(false);
+    instance.call_rotate_contract_key(env, sig_info, cursor, chunk_size)
+}
+
+/// Exports the next `chunk_size` storage entries of the contract, starting at `cursor`, with a MAC
+/// over each of them. Returns the snapshot nonce, the length of the chunk as a big endian u32, the
+/// chunk and the cursor to continue from, which is empty once every entry was exported.
+pub fn call_export_contract_state_raw<
+    S: Storage + 'static,
+    A: Api + 'static,
+    Q: Querier + 'static,
+>(
+    instance: &mut Instance<S, A, Q>,
+    env: &[u8],
+    cursor: &[u8],
+    snapshot_nonce: &[u8],
+    chunk_size: u32,
+) -> VmResult<Vec<u8>> {
+    instance.set_storage_readonly(true);
+    instance.call_export_contract_state(env, cursor, snapshot_nonce, chunk_size)
+}
+
+/// Verifies a chunk that `call_export_contract_state_raw` returned, which the admin of the contract
+/// signed in the tx of `sig_info`, and writes its entries back to the storage of the contract.
+/// Returns how many entries were written.
+pub fn call_import_contract_state_raw<
+    S: Storage + 'static,
+    A: Api + 'static,
+    Q: Querier + 'static,
+>(
+    instance: &mut Instance<S, A, Q>,
+    env: &[u8],
+    sig_info: &[u8],
+    chunk: &[u8],
+) -> VmResult<u32> {
+    instance.set_storage_readonly(false);
+    instance.call_import_contract_state(env, sig_info, chunk)
+}
+
+/// Calls Wasm export "reply" and returns raw data from the contract.
+/// The result is length limited to prevent abuse but otherwise unchecked.
+pub fn call_reply_raw<S: Storage + 'static, A: Api + 'static, Q: Querier + 'static>(
+    instance: &mut Instance<S, A, Q>,
+    env: &[u8],
+    msg: &[u8],
+) -> VmResult<Vec<u8>> {
+    instance.set_storage_readonly(false);
+    instance.call_reply(env, msg)
+}
+
+/// Calls one of the plaintext IBC exports of the contract and returns raw data from the contract.
+pub fn call_ibc_raw<S: Storage + 'static, A: Api + 'static, Q: Querier + 'static>(
+    instance: &mut Instance<S, A, Q>,
+    entry_point: IbcEntryPoint,
+    env: &[u8],
+    msg: &[u8],
+) -> VmResult<Vec<u8>> {
+    instance.set_storage_readonly(false);
+    instance.call_ibc(entry_point, env, msg)
+}
+
+/// Calls Wasm export "ibc_packet_receive" and returns raw data from the contract, along with the
+/// acknowledgement of the packet, encrypted to its sender.
+pub fn call_ibc_packet_receive_raw<S: Storage + 'static, A: Api + 'static, Q: Querier + 'static>(
+    instance: &mut Instance<S, A, Q>,
+    env: &[u8],
+    msg: &[u8],
+) -> VmResult<(Vec<u8>, Vec<u8>)> {
+    instance.set_storage_readonly(false);
+    instance.call_ibc_packet_receive(env, msg)
 }
 
 /// Calls Wasm export "query" and returns raw data from the contract.
 /// The result is length limited to prevent abuse but otherwise unchecked.
 pub fn call_query_raw<S: Storage + 'static, A: Api + 'static, Q: Querier + 'static>(
     instance: &mut Instance<S, A, Q>,
+    env: &[u8],
     msg: &[u8],
 ) -> VmResult<Vec<u8>> {
     instance.set_storage_readonly(true);
     /*
     call_raw(instance, "query", &[msg], MAX_LENGTH_QUERY)
     */
-    instance.call_query(msg)
+    instance.call_query(env, msg)
 }
 
 #[cfg(not(feature = "default-enclave"))]
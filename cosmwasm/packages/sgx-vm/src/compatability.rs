@@ -7,14 +7,39 @@ use crate::errors::{VmError, VmResult};
 use crate::features::required_features_from_module;
 
 /// Lists all imports we provide upon instantiating the instance in Instance::from_module()
+/// to contracts of every CosmWasm API version.
 /// This should be updated when new imports are added
 const SUPPORTED_IMPORTS: &[&str] = &[
     "env.db_read",
+    "env.db_read_multi",
     "env.db_write",
     "env.db_remove",
-    "env.canonicalize_address",
-    "env.humanize_address",
+    "env.db_read_ns",
+    "env.db_write_ns",
+    "env.db_remove_ns",
+    "env.db_move_to_ns",
+    "env.db_storage_usage",
+    "env.db_try_write",
+    "env.db_try_write_ns",
     "env.query_chain",
+    "env.ed25519_verify",
+    "env.ed25519_batch_verify",
+    "env.secp256k1_recover_pubkey",
+    "env.get_random",
+    "env.addr_validate",
+    "env.addr_canonicalize",
+    "env.addr_humanize",
+    "env.hash_sha256",
+    "env.hash_keccak256",
+    "env.hash_ripemd160",
+    "env.hkdf_sha256",
+    "env.secp256k1_generate_key",
+    "env.secp256k1_sign",
+    "env.secp256k1_public_key",
+    "env.gas_left",
+    "env.gas_limit",
+    "env.verify_foreign_header",
+    "env.assert_contract_code_hash",
     #[cfg(feature = "iterator")]
     "env.db_scan",
     #[cfg(feature = "iterator")]
@@ -23,17 +48,22 @@ const SUPPORTED_IMPORTS: &[&str] = &[
     "env.debug_print",
 ];
 
+/// Imports we only provide to contracts built for CosmWasm 0.10
+const V0_10_IMPORTS: &[&str] = &["env.canonicalize_address", "env.humanize_address"];
+
+/// Imports we only provide to contracts built for CosmWasm 1.0
+const V1_IMPORTS: &[&str] = &["env.abort", "env.debug"];
+
+/// The export that marks contracts built for CosmWasm 0.10
+const V0_10_MARKER: &str = "cosmwasm_vm_version_3";
+/// The export that marks contracts built for CosmWasm 1.0
+const V1_MARKER: &str = "interface_version_8";
+
 /// Lists all entry points we expect to be present when calling a contract.
 /// Basically, anything that is used in calls.rs
-/// This is unlikely to change much, must be frozen at 1.0 to avoid breaking existing contracts
-const REQUIRED_EXPORTS: &[&str] = &[
-    "cosmwasm_vm_version_3",
-    "query",
-    "init",
-    "handle",
-    "allocate",
-    "deallocate",
-];
+/// This is unlikely to change much, must be frozen at 1.0 to avoid breaking existing contracts.
+/// On top of these, a contract must export the marker of one of the CosmWasm API versions.
+const REQUIRED_EXPORTS: &[&str] = &["query", "init", "handle", "allocate", "deallocate"];
 
 const MEMORY_LIMIT: u32 = 512; // in pages
 
@@ -100,6 +130,16 @@ fn check_wasm_exports(module: &Module) -> VmResult<()> {
             .collect()
     });
 
+    if !available_exports
+        .iter()
+        .any(|x| x == V0_10_MARKER || x == V1_MARKER)
+    {
+        return Err(VmError::static_validation_err(format!(
+            "Wasm contract doesn't have required export: \"{}\" or \"{}\". Exports required by VM: {:?}. Contract version too old for this VM?",
+            V0_10_MARKER, V1_MARKER, REQUIRED_EXPORTS
+        )));
+    }
+
     for required_export in REQUIRED_EXPORTS {
         if !available_exports.iter().any(|x| x == required_export) {
             return Err(VmError::static_validation_err(format!(
@@ -114,17 +154,33 @@ fn check_wasm_exports(module: &Module) -> VmResult<()> {
 /// Checks if the import requirements of the contract are satisfied.
 /// When this is not the case, we either have an incompatibility between contract and VM
 /// or a error in the contract.
+///
+/// Contracts that export the CosmWasm 1.0 marker get the imports of that version, and all other
+/// contracts get the imports of CosmWasm 0.10.
 fn check_wasm_imports(module: &Module) -> VmResult<()> {
     let required_imports: Vec<ImportEntry> = module
         .import_section()
         .map_or(vec![], |import_section| import_section.entries().to_vec());
 
+    let is_v1 = module.export_section().map_or(false, |export_section| {
+        export_section
+            .entries()
+            .iter()
+            .any(|entry| entry.field() == V1_MARKER)
+    });
+    let version_imports = if is_v1 { V1_IMPORTS } else { V0_10_IMPORTS };
+    let supported_imports: Vec<&str> = SUPPORTED_IMPORTS
+        .iter()
+        .chain(version_imports)
+        .copied()
+        .collect();
+
     for required_import in required_imports {
         let full_name = format!("{}.{}", required_import.module(), required_import.field());
-        if !SUPPORTED_IMPORTS.contains(&full_name.as_str()) {
+        if !supported_imports.contains(&full_name.as_str()) {
             return Err(VmError::static_validation_err(format!(
                 "Wasm contract requires unsupported import: \"{}\". Imports supported by VM: {:?}. Contract version too new for this VM?",
-                full_name, SUPPORTED_IMPORTS
+                full_name, supported_imports
             )));
         }
 
@@ -348,6 +404,59 @@ mod test {
         check_wasm_imports(&deserialize_buffer(&wasm).unwrap()).unwrap();
     }
 
+    #[test]
+    fn check_wasm_imports_of_v1_contract() {
+        let wasm = wat2wasm(
+            r#"(module
+            (import "env" "db_read" (func (param i32) (result i32)))
+            (import "env" "addr_canonicalize" (func (param i32 i32) (result i32)))
+            (import "env" "abort" (func (param i32)))
+            (func (export "interface_version_8"))
+        )"#,
+        )
+        .unwrap();
+        let module = deserialize_buffer(&wasm).unwrap();
+        check_wasm_imports(&module).unwrap();
+
+        // The marker satisfies the version check, so only the entry points are missing
+        match check_wasm_exports(&module) {
+            Err(VmError::StaticValidationErr { msg, .. }) => {
+                assert!(msg.starts_with("Wasm contract doesn't have required export: \"query\""));
+            }
+            Err(e) => panic!("Unexpected error {:?}", e),
+            Ok(_) => panic!("Didn't reject wasm with invalid api"),
+        }
+    }
+
+    #[test]
+    fn test_check_wasm_imports_of_another_version() {
+        let wasm = wat2wasm(
+            r#"(module
+            (import "env" "canonicalize_address" (func (param i32 i32) (result i32)))
+            (func (export "interface_version_8"))
+        )"#,
+        )
+        .unwrap();
+        match check_wasm_imports(&deserialize_buffer(&wasm).unwrap()) {
+            Err(VmError::StaticValidationErr { msg, .. }) => {
+                assert!(msg.starts_with(
+                    "Wasm contract requires unsupported import: \"env.canonicalize_address\""
+                ));
+            }
+            Err(e) => panic!("Unexpected error {:?}", e),
+            Ok(_) => panic!("Didn't reject wasm with invalid api"),
+        }
+
+        let wasm = wat2wasm(r#"(module (import "env" "abort" (func (param i32))))"#).unwrap();
+        match check_wasm_imports(&deserialize_buffer(&wasm).unwrap()) {
+            Err(VmError::StaticValidationErr { msg, .. }) => {
+                assert!(msg.starts_with("Wasm contract requires unsupported import: \"env.abort\""));
+            }
+            Err(e) => panic!("Unexpected error {:?}", e),
+            Ok(_) => panic!("Didn't reject wasm with invalid api"),
+        }
+    }
+
     #[test]
     fn test_check_wasm_imports_of_old_contract() {
         let module = deserialize_buffer(CONTRACT_0_7).unwrap();
@@ -2,6 +2,8 @@
 use std::collections::HashSet;
 */
 use std::marker::PhantomData;
+
+use enclave_ffi_types::GasBreakdown;
 /*
 use std::ptr::NonNull;
 */
@@ -38,7 +40,7 @@ use crate::memory::{get_memory_info, read_region, write_region};
 use crate::traits::{Api, Extern, Querier, Storage};
 
 use crate::enclave::get_enclave;
-use crate::wasmi::Module;
+use crate::wasmi::{IbcEntryPoint, Module};
 
 /*
 const WASM_PAGE_SIZE: u64 = 64 * 1024;
@@ -256,7 +258,9 @@ where
         let limit = self.inner.gas_limit();
         let remaining = self.inner.gas_left();
         let used_internally = self.inner.gas_used();
-        let used_externally = limit.saturating_sub(remaining).saturating_sub(used_internally);
+        let used_externally = limit
+            .saturating_sub(remaining)
+            .saturating_sub(used_internally);
         GasReport {
             limit,
             remaining,
@@ -265,6 +269,11 @@ where
         }
     }
 
+    /// Returns where the gas of the last successful handle or reply was spent.
+    pub fn get_gas_breakdown(&self) -> Option<GasBreakdown> {
+        self.inner.gas_breakdown()
+    }
+
     /// Returns the currently remaining gas.
     pub fn get_gas_used(&self) -> u64 {
         get_gas_used(&self.inner)
@@ -335,17 +344,84 @@ where
         Ok(init_result.into_output())
     }
 
-    pub fn call_handle(&mut self, env: &[u8], msg: &[u8], sig_info: &[u8]) -> VmResult<Vec<u8>> {
-        let init_result = self.inner.handle(env, msg, sig_info)?;
+    pub fn call_handle(
+        &mut self,
+        env: &[u8],
+        msg: &[u8],
+        sig_info: &[u8],
+        simulate: bool,
+    ) -> VmResult<Vec<u8>> {
+        let init_result = self.inner.handle(env, msg, sig_info, simulate)?;
         Ok(init_result.into_output())
     }
 
-    pub fn call_migrate(&mut self, _env: &[u8], _msg: &[u8]) -> VmResult<Vec<u8>> {
-        Ok(Vec::new())
-    }
-
-    pub fn call_query(&mut self, msg: &[u8]) -> VmResult<Vec<u8>> {
-        let init_result = self.inner.query(msg)?;
+    pub fn call_migrate(&mut self, env: &[u8], msg: &[u8], sig_info: &[u8]) -> VmResult<Vec<u8>> {
+        let migrate_result = self.inner.migrate(env, msg, sig_info)?;
+        Ok(migrate_result.into_output())
+    }
+
+    pub fn call_rotate_contract_key(
+        &mut self,
+        env: &[u8],
+        sig_info: &[u8],
+        cursor: &[u8],
+        chunk_size: u32,
+    ) -> VmResult<Vec<u8>> {
+        let rotate_result = self
+            .inner
+            .rotate_contract_key(env, sig_info, cursor, chunk_size)?;
+        Ok(rotate_result.into_output())
+    }
+
+    pub fn call_export_contract_state(
+        &mut self,
+        env: &[u8],
+        cursor: &[u8],
+        snapshot_nonce: &[u8],
+        chunk_size: u32,
+    ) -> VmResult<Vec<u8>> {
+        let export_result =
+            self.inner
+                .export_contract_state(env, cursor, snapshot_nonce, chunk_size)?;
+        Ok(export_result.into_output())
+    }
+
+    pub fn call_import_contract_state(
+        &mut self,
+        env: &[u8],
+        sig_info: &[u8],
+        chunk: &[u8],
+    ) -> VmResult<u32> {
+        self.inner.import_contract_state(env, sig_info, chunk)
+    }
+
+    pub fn call_reply(&mut self, env: &[u8], msg: &[u8]) -> VmResult<Vec<u8>> {
+        let reply_result = self.inner.reply(env, msg)?;
+        Ok(reply_result.into_output())
+    }
+
+    pub fn call_ibc(
+        &mut self,
+        entry_point: IbcEntryPoint,
+        env: &[u8],
+        msg: &[u8],
+    ) -> VmResult<Vec<u8>> {
+        let ibc_result = self.inner.ibc(entry_point, env, msg)?;
+        Ok(ibc_result.into_output())
+    }
+
+    /// Returns the output of the contract and the encrypted acknowledgement of the packet
+    pub fn call_ibc_packet_receive(
+        &mut self,
+        env: &[u8],
+        msg: &[u8],
+    ) -> VmResult<(Vec<u8>, Vec<u8>)> {
+        let receive_result = self.inner.ibc_packet_receive(env, msg)?;
+        Ok(receive_result.into_output())
+    }
+
+    pub fn call_query(&mut self, env: &[u8], msg: &[u8]) -> VmResult<Vec<u8>> {
+        let init_result = self.inner.query(env, msg)?;
         Ok(init_result.into_output())
     }
 }
@@ -27,7 +27,11 @@ mod wasmi;
 pub mod enclave_tests;
 
 pub use crate::cache::CosmCache;
-pub use crate::calls::{call_handle_raw, call_init_raw, call_migrate_raw, call_query_raw};
+pub use crate::calls::{
+    call_export_contract_state_raw, call_handle_raw, call_handle_simulate_raw,
+    call_ibc_packet_receive_raw, call_ibc_raw, call_import_contract_state_raw, call_init_raw,
+    call_migrate_raw, call_query_raw, call_reply_raw, call_rotate_contract_key_raw,
+};
 pub use crate::checksum::Checksum;
 pub use crate::errors::{
     CommunicationError, CommunicationResult, RegionValidationError, RegionValidationResult,
@@ -46,7 +50,20 @@ pub use crate::traits::{Api, Extern, Querier, Storage};
 pub use crate::traits::StorageIterator;
 
 // Secret Network specific exports
-pub use crate::attestation::{create_attestation_report_u, untrusted_get_encrypted_seed};
+pub use crate::attestation::{
+    create_attestation_report_u, untrusted_get_attestation_bundle, untrusted_get_encrypted_seed,
+};
 pub use crate::seed::{
-    untrusted_health_check, untrusted_init_bootstrap, untrusted_init_node, untrusted_key_gen,
+    untrusted_configure_runtime, untrusted_decommission_arm, untrusted_decommission_confirm,
+    untrusted_export_metrics, untrusted_get_capabilities, untrusted_get_last_panic_report,
+    untrusted_get_runtime_stats, untrusted_get_trace, untrusted_health_check,
+    untrusted_health_check_deep, untrusted_init_bootstrap, untrusted_init_node, untrusted_key_gen,
+    untrusted_pin_decommission_operator, untrusted_prewarm_contracts,
+    untrusted_submit_block_header, untrusted_submit_foreign_header, untrusted_submit_new_seed,
+    untrusted_track_foreign_chain, untrusted_verify_input, PanicReport,
+};
+pub use crate::wasmi::{update_admin, IbcEntryPoint};
+pub use enclave_ffi_types::{
+    EnclaveCapabilities, GasBreakdown, HealthCheckDeepResult, InputVerificationStep, PrewarmStatus,
+    VerifyInputResult,
 };
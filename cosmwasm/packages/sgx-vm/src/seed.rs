@@ -1,9 +1,25 @@
-use enclave_ffi_types::HealthCheckResult;
+use std::ffi::c_void;
+
+use enclave_ffi_types::{
+    Ctx, EnclaveCapabilities, HealthCheckDeepResult, HealthCheckResult, LastPanicReport,
+    PrewarmStatus, RuntimeConfiguration, RuntimeStats, UserSpaceBuffer, VerifyInputResult,
+    PREWARM_ENTRY_SIZE,
+};
 use sgx_types::*;
 
 use log::{debug, info};
 
+use crate::context::{move_into_context, setup_context};
 use crate::enclave::get_enclave;
+use crate::testing::{MockQuerier, MockStorage};
+use crate::wasmi::{recover_buffer, FullContext};
+
+/// The size of the buffer the metrics of the enclave are first exported to, which fits them unless
+/// they count failures with many different errors
+const METRICS_BUFFER_LEN: usize = 64 * 1024;
+/// The buffer the trace of the ecalls is first exported to. A full trace is larger, see
+/// `untrusted_get_trace`.
+const TRACE_BUFFER_LEN: usize = 64 * 1024;
 
 extern "C" {
     pub fn ecall_init_node(
@@ -15,6 +31,68 @@ extern "C" {
         encrypted_seed_len: u32,
     ) -> sgx_status_t;
 
+    /// Replace the consensus seed with the one in a signed seed rotation
+    pub fn ecall_submit_new_seed(
+        eid: sgx_enclave_id_t,
+        retval: *mut sgx_status_t,
+        rotation: *const u8,
+        rotation_len: u32,
+    ) -> sgx_status_t;
+
+    /// Pin the ed25519 key of the only operator who may decommission the node
+    pub fn ecall_pin_decommission_operator(
+        eid: sgx_enclave_id_t,
+        retval: *mut sgx_status_t,
+        operator_public_key: *const u8,
+    ) -> sgx_status_t;
+
+    /// Start decommissioning the node, for the operator with the given ed25519 key
+    pub fn ecall_decommission_arm(
+        eid: sgx_enclave_id_t,
+        retval: *mut sgx_status_t,
+        operator_public_key: *const u8,
+        challenge: &mut [u8; 32],
+        erasure_public_key: &mut [u8; 32],
+    ) -> sgx_status_t;
+
+    /// Erase the keys of the node, once the operator signed the challenge of the arming
+    pub fn ecall_decommission_confirm(
+        eid: sgx_enclave_id_t,
+        retval: *mut sgx_status_t,
+        signature: *const u8,
+        signature_len: u32,
+        erasure_record: *mut UserSpaceBuffer,
+    ) -> sgx_status_t;
+
+    /// Verify the header and commit of the next block
+    pub fn ecall_submit_block_header(
+        eid: sgx_enclave_id_t,
+        retval: *mut sgx_status_t,
+        submission: *const u8,
+        submission_len: usize,
+    ) -> sgx_status_t;
+
+    /// Start verifying the headers of another chain
+    pub fn ecall_track_foreign_chain(
+        eid: sgx_enclave_id_t,
+        retval: *mut sgx_status_t,
+        init: *const u8,
+        init_len: usize,
+        sealed_chain: *mut UserSpaceBuffer,
+    ) -> sgx_status_t;
+
+    /// Verify a header of another chain
+    pub fn ecall_submit_foreign_header(
+        eid: sgx_enclave_id_t,
+        retval: *mut sgx_status_t,
+        sealed_chain: *const u8,
+        sealed_chain_len: usize,
+        submission: *const u8,
+        submission_len: usize,
+        now: u64,
+        new_sealed_chain: *mut UserSpaceBuffer,
+    ) -> sgx_status_t;
+
     pub fn ecall_init_bootstrap(
         eid: sgx_enclave_id_t,
         retval: *mut sgx_status_t,
@@ -36,6 +114,88 @@ extern "C" {
         eid: sgx_enclave_id_t,
         retval: *mut HealthCheckResult,
     ) -> sgx_status_t;
+
+    /// Check that the enclave can unseal its keys, encrypt, and access storage
+    pub fn ecall_health_check_deep(
+        eid: sgx_enclave_id_t,
+        retval: *mut sgx_status_t,
+        context: Ctx,
+        result: *mut HealthCheckDeepResult,
+    ) -> sgx_status_t;
+
+    /// Check whether the encrypted message of a tx decrypts, without executing it
+    pub fn ecall_verify_input(
+        eid: sgx_enclave_id_t,
+        retval: *mut sgx_status_t,
+        msg: *const u8,
+        msg_len: usize,
+        tx_public_key: *const u8,
+        tx_public_key_len: usize,
+        result: *mut VerifyInputResult,
+    ) -> sgx_status_t;
+
+    /// Report the memory usage of the enclave
+    pub fn ecall_get_runtime_stats(
+        eid: sgx_enclave_id_t,
+        retval: *mut sgx_status_t,
+        stats: *mut RuntimeStats,
+    ) -> sgx_status_t;
+
+    /// Export the metrics of the enclave in the Prometheus text format
+    pub fn ecall_export_metrics(
+        eid: sgx_enclave_id_t,
+        retval: *mut sgx_status_t,
+        metrics: *mut u8,
+        metrics_len: usize,
+        written: *mut usize,
+    ) -> sgx_status_t;
+
+    /// Export the traces of the last ecalls, see `untrusted_get_trace`
+    pub fn ecall_get_trace(
+        eid: sgx_enclave_id_t,
+        retval: *mut sgx_status_t,
+        max_ecalls: u32,
+        trace: *mut u8,
+        trace_len: usize,
+        written: *mut usize,
+    ) -> sgx_status_t;
+
+    /// Validate contracts and cache their modules in the enclave
+    pub fn ecall_prewarm_contracts(
+        eid: sgx_enclave_id_t,
+        retval: *mut sgx_status_t,
+        entries: *const u8,
+        entries_len: usize,
+        code: *const u8,
+        code_len: usize,
+        max_bytes: u64,
+        max_millis: u64,
+        statuses: *mut u8,
+        statuses_len: usize,
+    ) -> sgx_status_t;
+
+    /// Report the version of the enclave and the features it supports
+    pub fn ecall_get_capabilities(
+        eid: sgx_enclave_id_t,
+        retval: *mut sgx_status_t,
+        capabilities: *mut u8,
+        capabilities_len: usize,
+        written: *mut usize,
+    ) -> sgx_status_t;
+
+    /// Export the last panic in the enclave
+    pub fn ecall_get_last_panic_report(
+        eid: sgx_enclave_id_t,
+        retval: *mut sgx_status_t,
+        report: *mut LastPanicReport,
+    ) -> sgx_status_t;
+
+    /// Change runtime settings of the enclave, such as the log level
+    pub fn ecall_configure_runtime(
+        eid: sgx_enclave_id_t,
+        retval: *mut sgx_status_t,
+        config: *const RuntimeConfiguration,
+    ) -> sgx_status_t;
 }
 
 pub fn untrusted_health_check() -> SgxResult<HealthCheckResult> {
@@ -55,6 +215,333 @@ pub fn untrusted_health_check() -> SgxResult<HealthCheckResult> {
     Ok(ret)
 }
 
+/// Run the deep health check of the enclave. The storage round trip of the check goes to an
+/// in-memory storage that only lives for the duration of the check, so that it never touches
+/// the state of the chain.
+pub fn untrusted_health_check_deep() -> SgxResult<HealthCheckDeepResult> {
+    let enclave = get_enclave()?;
+
+    let eid = enclave.geteid();
+    let mut ret = sgx_status_t::SGX_SUCCESS;
+    let mut result = HealthCheckDeepResult::default();
+
+    let (context_data, finalizer) = setup_context::<MockStorage, MockQuerier>(0);
+    let full_context = Box::into_raw(Box::new(FullContext::new::<MockStorage, MockQuerier>(
+        context_data,
+    )));
+    let mut context = Ctx {
+        data: full_context as *mut c_void,
+    };
+    move_into_context(&mut context, MockStorage::new(), MockQuerier::new(&[]));
+
+    let status = unsafe { ecall_health_check_deep(eid, &mut ret, context, &mut result) };
+
+    finalizer(context_data);
+    drop(unsafe { Box::from_raw(full_context) });
+
+    if status != sgx_status_t::SGX_SUCCESS {
+        return Err(status);
+    }
+
+    if ret != sgx_status_t::SGX_SUCCESS {
+        return Err(ret);
+    }
+
+    Ok(result)
+}
+
+/// Check whether `msg`, the encrypted message of a tx, decrypts in the enclave, and at which step
+/// it failed if it doesn't. `tx_public_key` is the public key the sender encrypted it with.
+pub fn untrusted_verify_input(msg: &[u8], tx_public_key: &[u8]) -> SgxResult<VerifyInputResult> {
+    let enclave = get_enclave()?;
+
+    let eid = enclave.geteid();
+    let mut ret = sgx_status_t::SGX_SUCCESS;
+    let mut result = VerifyInputResult::default();
+
+    let status = unsafe {
+        ecall_verify_input(
+            eid,
+            &mut ret,
+            msg.as_ptr(),
+            msg.len(),
+            tx_public_key.as_ptr(),
+            tx_public_key.len(),
+            &mut result,
+        )
+    };
+
+    if status != sgx_status_t::SGX_SUCCESS {
+        return Err(status);
+    }
+
+    if ret != sgx_status_t::SGX_SUCCESS {
+        return Err(ret);
+    }
+
+    Ok(result)
+}
+
+pub fn untrusted_get_runtime_stats() -> SgxResult<RuntimeStats> {
+    let enclave = get_enclave()?;
+
+    let eid = enclave.geteid();
+    let mut ret = sgx_status_t::SGX_SUCCESS;
+    let mut stats = RuntimeStats::default();
+
+    let status = unsafe { ecall_get_runtime_stats(eid, &mut ret, &mut stats) };
+
+    if status != sgx_status_t::SGX_SUCCESS {
+        return Err(status);
+    }
+
+    if ret != sgx_status_t::SGX_SUCCESS {
+        return Err(ret);
+    }
+
+    Ok(stats)
+}
+
+/// The metrics of the enclave, in the Prometheus text format
+pub fn untrusted_export_metrics() -> SgxResult<String> {
+    let enclave = get_enclave()?;
+
+    let eid = enclave.geteid();
+    let mut metrics = vec![0u8; METRICS_BUFFER_LEN];
+    // The enclave tells how large the buffer has to be if it's too small. The metrics may grow a
+    // little between the calls, so the second buffer leaves some room.
+    for _attempt in 0..2 {
+        let mut ret = sgx_status_t::SGX_SUCCESS;
+        let mut written = 0usize;
+        let status = unsafe {
+            ecall_export_metrics(
+                eid,
+                &mut ret,
+                metrics.as_mut_ptr(),
+                metrics.len(),
+                &mut written,
+            )
+        };
+
+        if status != sgx_status_t::SGX_SUCCESS {
+            return Err(status);
+        }
+
+        if ret != sgx_status_t::SGX_SUCCESS {
+            return Err(ret);
+        }
+
+        if written <= metrics.len() {
+            metrics.truncate(written);
+            return String::from_utf8(metrics).map_err(|_err| sgx_status_t::SGX_ERROR_UNEXPECTED);
+        }
+        metrics.resize(written + METRICS_BUFFER_LEN, 0);
+    }
+
+    Err(sgx_status_t::SGX_ERROR_UNEXPECTED)
+}
+
+/// The traces of the last `max_ecalls` ecalls, oldest first, in the binary format that
+/// `types.ParseEcallTraces` of go-cosmwasm reads. The enclave only keeps traces while tracing was
+/// turned on with `untrusted_configure_runtime`.
+pub fn untrusted_get_trace(max_ecalls: u32) -> SgxResult<Vec<u8>> {
+    let enclave = get_enclave()?;
+
+    let eid = enclave.geteid();
+    let mut trace = vec![0u8; TRACE_BUFFER_LEN];
+    // As with the metrics, the traces may grow a little between the calls
+    for _attempt in 0..2 {
+        let mut ret = sgx_status_t::SGX_SUCCESS;
+        let mut written = 0usize;
+        let status = unsafe {
+            ecall_get_trace(
+                eid,
+                &mut ret,
+                max_ecalls,
+                trace.as_mut_ptr(),
+                trace.len(),
+                &mut written,
+            )
+        };
+
+        if status != sgx_status_t::SGX_SUCCESS {
+            return Err(status);
+        }
+
+        if ret != sgx_status_t::SGX_SUCCESS {
+            return Err(ret);
+        }
+
+        if written <= trace.len() {
+            trace.truncate(written);
+            return Ok(trace);
+        }
+        trace.resize(written + TRACE_BUFFER_LEN, 0);
+    }
+
+    Err(sgx_status_t::SGX_ERROR_UNEXPECTED)
+}
+
+/// Validate `contracts`, pairs of a code hash and its code, and cache their modules in the
+/// enclave, so that the first call to each of them doesn't pay for it. The enclave stops
+/// validating contracts after `max_bytes` of code or `max_millis` milliseconds. Returns what the
+/// enclave did with every contract, in order.
+pub fn untrusted_prewarm_contracts(
+    contracts: &[([u8; 32], &[u8])],
+    max_bytes: u64,
+    max_millis: u64,
+) -> SgxResult<Vec<PrewarmStatus>> {
+    if contracts.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let mut entries = Vec::with_capacity(contracts.len() * PREWARM_ENTRY_SIZE);
+    let mut code = vec![];
+    for (code_hash, wasm) in contracts {
+        entries.extend_from_slice(code_hash);
+        entries.extend_from_slice(&(code.len() as u32).to_be_bytes());
+        entries.extend_from_slice(&(wasm.len() as u32).to_be_bytes());
+        code.extend_from_slice(wasm);
+    }
+    let enclave = get_enclave()?;
+
+    let eid = enclave.geteid();
+    let mut ret = sgx_status_t::SGX_SUCCESS;
+    let mut statuses = vec![0u8; contracts.len()];
+
+    let status = unsafe {
+        ecall_prewarm_contracts(
+            eid,
+            &mut ret,
+            entries.as_ptr(),
+            entries.len(),
+            code.as_ptr(),
+            code.len(),
+            max_bytes,
+            max_millis,
+            statuses.as_mut_ptr(),
+            statuses.len(),
+        )
+    };
+
+    if status != sgx_status_t::SGX_SUCCESS {
+        return Err(status);
+    }
+
+    if ret != sgx_status_t::SGX_SUCCESS {
+        return Err(ret);
+    }
+
+    statuses
+        .into_iter()
+        .map(|status| PrewarmStatus::from_u8(status).ok_or(sgx_status_t::SGX_ERROR_UNEXPECTED))
+        .collect()
+}
+
+/// Get the version of the enclave and the features it supports. Enclaves that predate
+/// `ecall_get_capabilities` report that they support none of the optional features.
+pub fn untrusted_get_capabilities() -> SgxResult<EnclaveCapabilities> {
+    let enclave = get_enclave()?;
+
+    let eid = enclave.geteid();
+    let mut ret = sgx_status_t::SGX_SUCCESS;
+    let mut buf = [0u8; EnclaveCapabilities::ENCODED_LEN];
+    let mut written = 0usize;
+
+    let status =
+        unsafe { ecall_get_capabilities(eid, &mut ret, buf.as_mut_ptr(), buf.len(), &mut written) };
+
+    if status == sgx_status_t::SGX_ERROR_INVALID_FUNCTION {
+        return Ok(EnclaveCapabilities::default());
+    }
+
+    if status != sgx_status_t::SGX_SUCCESS {
+        return Err(status);
+    }
+
+    if ret != sgx_status_t::SGX_SUCCESS {
+        return Err(ret);
+    }
+
+    Ok(EnclaveCapabilities::read_from(
+        &buf[..written.min(buf.len())],
+    ))
+}
+
+/// The last panic in the enclave
+pub struct PanicReport {
+    /// The panic message, location and backtrace, sealed to the identity of the enclave
+    pub sealed_report: Vec<u8>,
+    /// Which ecall panicked and where, without the panic message
+    pub summary: String,
+}
+
+/// Returns `None` if no ecall panicked since the enclave started
+pub fn untrusted_get_last_panic_report() -> SgxResult<Option<PanicReport>> {
+    let enclave = get_enclave()?;
+
+    let eid = enclave.geteid();
+    let mut ret = sgx_status_t::SGX_SUCCESS;
+    let mut report = LastPanicReport::default();
+
+    let status = unsafe { ecall_get_last_panic_report(eid, &mut ret, &mut report) };
+
+    if status != sgx_status_t::SGX_SUCCESS {
+        return Err(status);
+    }
+
+    if ret != sgx_status_t::SGX_SUCCESS {
+        return Err(ret);
+    }
+
+    let sealed_report = unsafe { recover_buffer(report.sealed_report) };
+    let summary = unsafe { recover_buffer(report.summary) };
+
+    match (sealed_report, summary) {
+        (Some(sealed_report), Some(summary)) => Ok(Some(PanicReport {
+            sealed_report,
+            summary: String::from_utf8_lossy(&summary).into_owned(),
+        })),
+        _ => Ok(None),
+    }
+}
+
+/// `default_query_gas_limit` is in wasm gas, and `max_contract_size` and `query_cache_size` in
+/// bytes. 0 keeps the limit the enclave has, and a `query_cache_size` of `u64::MAX` turns the query
+/// cache off. An `ecall_tracing` of 1 turns the tracing of ecalls on, 2 turns it off, and 0 keeps
+/// it as it is.
+pub fn untrusted_configure_runtime(
+    max_log_level: u8,
+    default_query_gas_limit: u64,
+    max_contract_size: u64,
+    query_cache_size: u64,
+    ecall_tracing: u8,
+) -> SgxResult<()> {
+    let enclave = get_enclave()?;
+
+    let eid = enclave.geteid();
+    let mut ret = sgx_status_t::SGX_SUCCESS;
+    let config = RuntimeConfiguration {
+        max_log_level,
+        default_query_gas_limit,
+        max_contract_size,
+        query_cache_size,
+        ecall_tracing,
+    };
+
+    let status = unsafe { ecall_configure_runtime(eid, &mut ret, &config) };
+
+    if status != sgx_status_t::SGX_SUCCESS {
+        return Err(status);
+    }
+
+    if ret != sgx_status_t::SGX_SUCCESS {
+        return Err(ret);
+    }
+
+    Ok(())
+}
+
 pub fn untrusted_init_node(master_cert: &[u8], encrypted_seed: &[u8]) -> SgxResult<()> {
     info!("Initializing enclave..");
     let enclave = get_enclave()?;
@@ -85,6 +572,210 @@ pub fn untrusted_init_node(master_cert: &[u8], encrypted_seed: &[u8]) -> SgxResu
     Ok(())
 }
 
+/// Submit a seed rotation to the enclave. The new seed is used from the activation height of the
+/// rotation on.
+pub fn untrusted_submit_new_seed(rotation: &[u8]) -> SgxResult<()> {
+    info!("Initializing enclave..");
+    let enclave = get_enclave()?;
+    info!("Initialized enclave successfully!");
+
+    let eid = enclave.geteid();
+    let mut ret = sgx_status_t::SGX_SUCCESS;
+
+    let status =
+        unsafe { ecall_submit_new_seed(eid, &mut ret, rotation.as_ptr(), rotation.len() as u32) };
+
+    if status != sgx_status_t::SGX_SUCCESS {
+        return Err(status);
+    }
+
+    if ret != sgx_status_t::SGX_SUCCESS {
+        return Err(ret);
+    }
+
+    Ok(())
+}
+
+/// Pin the operator who may decommission the node. It must be done while the node is set up,
+/// before it gets the consensus seed, and the operator can't be replaced afterwards.
+pub fn untrusted_pin_decommission_operator(operator_public_key: &[u8]) -> SgxResult<()> {
+    if operator_public_key.len() != 32 {
+        return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+    }
+    let enclave = get_enclave()?;
+
+    let eid = enclave.geteid();
+    let mut ret = sgx_status_t::SGX_SUCCESS;
+
+    let status =
+        unsafe { ecall_pin_decommission_operator(eid, &mut ret, operator_public_key.as_ptr()) };
+
+    if status != sgx_status_t::SGX_SUCCESS {
+        return Err(status);
+    }
+
+    if ret != sgx_status_t::SGX_SUCCESS {
+        return Err(ret);
+    }
+
+    Ok(())
+}
+
+/// Start decommissioning the node for the operator that was pinned. Returns the challenge the operator has to sign to confirm it,
+/// and the public key the record of the erasure will be signed with.
+pub fn untrusted_decommission_arm(operator_public_key: &[u8]) -> SgxResult<([u8; 32], [u8; 32])> {
+    if operator_public_key.len() != 32 {
+        return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+    }
+    let enclave = get_enclave()?;
+
+    let eid = enclave.geteid();
+    let mut ret = sgx_status_t::SGX_SUCCESS;
+    let mut challenge = [0u8; 32];
+    let mut erasure_public_key = [0u8; 32];
+
+    let status = unsafe {
+        ecall_decommission_arm(
+            eid,
+            &mut ret,
+            operator_public_key.as_ptr(),
+            &mut challenge,
+            &mut erasure_public_key,
+        )
+    };
+
+    if status != sgx_status_t::SGX_SUCCESS {
+        return Err(status);
+    }
+
+    if ret != sgx_status_t::SGX_SUCCESS {
+        return Err(ret);
+    }
+
+    Ok((challenge, erasure_public_key))
+}
+
+/// Confirm the decommissioning of the node with the operator's signature of the challenge. The
+/// enclave erases its keys and returns the signed record of the erasure, as JSON.
+pub fn untrusted_decommission_confirm(signature: &[u8]) -> SgxResult<Vec<u8>> {
+    let enclave = get_enclave()?;
+
+    let eid = enclave.geteid();
+    let mut ret = sgx_status_t::SGX_SUCCESS;
+    let mut erasure_record = UserSpaceBuffer {
+        ptr: std::ptr::null_mut(),
+    };
+
+    let status = unsafe {
+        ecall_decommission_confirm(
+            eid,
+            &mut ret,
+            signature.as_ptr(),
+            signature.len() as u32,
+            &mut erasure_record,
+        )
+    };
+
+    if status != sgx_status_t::SGX_SUCCESS {
+        return Err(status);
+    }
+
+    if ret != sgx_status_t::SGX_SUCCESS {
+        return Err(ret);
+    }
+
+    unsafe { recover_buffer(erasure_record) }.ok_or(sgx_status_t::SGX_ERROR_UNEXPECTED)
+}
+
+/// Hand the header of the next block to the enclave, with the commit and validators that signed
+/// it, as a JSON `BlockHeaderSubmission`
+pub fn untrusted_submit_block_header(submission: &[u8]) -> SgxResult<()> {
+    let enclave = get_enclave()?;
+
+    let eid = enclave.geteid();
+    let mut ret = sgx_status_t::SGX_SUCCESS;
+
+    let status =
+        unsafe { ecall_submit_block_header(eid, &mut ret, submission.as_ptr(), submission.len()) };
+
+    if status != sgx_status_t::SGX_SUCCESS {
+        return Err(status);
+    }
+
+    if ret != sgx_status_t::SGX_SUCCESS {
+        return Err(ret);
+    }
+
+    Ok(())
+}
+
+/// Start verifying the headers of another chain, from a header of it that governance approved, as
+/// a JSON `ForeignChainInit`. Returns what the enclave trusts about the chain, as a JSON
+/// `SealedForeignChain` for the chain to keep.
+pub fn untrusted_track_foreign_chain(init: &[u8]) -> SgxResult<Vec<u8>> {
+    let enclave = get_enclave()?;
+
+    let eid = enclave.geteid();
+    let mut ret = sgx_status_t::SGX_SUCCESS;
+    let mut sealed_chain = UserSpaceBuffer {
+        ptr: std::ptr::null_mut(),
+    };
+
+    let status = unsafe {
+        ecall_track_foreign_chain(eid, &mut ret, init.as_ptr(), init.len(), &mut sealed_chain)
+    };
+
+    if status != sgx_status_t::SGX_SUCCESS {
+        return Err(status);
+    }
+
+    if ret != sgx_status_t::SGX_SUCCESS {
+        return Err(ret);
+    }
+
+    unsafe { recover_buffer(sealed_chain) }.ok_or(sgx_status_t::SGX_ERROR_UNEXPECTED)
+}
+
+/// Hand a header of another chain to the enclave, with the commit and validators that signed it,
+/// as a JSON `ForeignHeader`, along with the `SealedForeignChain` the chain keeps for it. `now` is
+/// the time of the current block, in seconds. Returns the state to keep instead.
+pub fn untrusted_submit_foreign_header(
+    sealed_chain: &[u8],
+    submission: &[u8],
+    now: u64,
+) -> SgxResult<Vec<u8>> {
+    let enclave = get_enclave()?;
+
+    let eid = enclave.geteid();
+    let mut ret = sgx_status_t::SGX_SUCCESS;
+    let mut new_sealed_chain = UserSpaceBuffer {
+        ptr: std::ptr::null_mut(),
+    };
+
+    let status = unsafe {
+        ecall_submit_foreign_header(
+            eid,
+            &mut ret,
+            sealed_chain.as_ptr(),
+            sealed_chain.len(),
+            submission.as_ptr(),
+            submission.len(),
+            now,
+            &mut new_sealed_chain,
+        )
+    };
+
+    if status != sgx_status_t::SGX_SUCCESS {
+        return Err(status);
+    }
+
+    if ret != sgx_status_t::SGX_SUCCESS {
+        return Err(ret);
+    }
+
+    unsafe { recover_buffer(new_sealed_chain) }.ok_or(sgx_status_t::SGX_ERROR_UNEXPECTED)
+}
+
 pub fn untrusted_key_gen() -> SgxResult<[u8; 32]> {
     info!("Initializing enclave..");
     let enclave = get_enclave()?;
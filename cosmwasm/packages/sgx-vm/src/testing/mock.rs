@@ -1,5 +1,6 @@
 use cosmwasm_std::{
     Binary, BlockInfo, CanonicalAddr, Coin, ContractInfo, Env, HumanAddr, MessageInfo,
+    TransactionInfo,
 };
 
 use super::querier::MockQuerier;
@@ -141,6 +142,8 @@ pub fn mock_env<U: Into<HumanAddr>>(sender: U, sent: &[Coin]) -> Env {
             height: 12_345,
             time: 1_571_797_419,
             chain_id: "cosmos-testnet-14002".to_string(),
+            hash: None,
+            proposer_address: None,
         },
         message: MessageInfo {
             sender: sender.into(),
@@ -151,6 +154,9 @@ pub fn mock_env<U: Into<HumanAddr>>(sender: U, sent: &[Coin]) -> Env {
         },
         contract_key: Some("".to_string()),
         contract_code_hash: "".to_string(),
+        random: None,
+        transaction: TransactionInfo::default(),
+        query_sender: None,
     }
 }
 
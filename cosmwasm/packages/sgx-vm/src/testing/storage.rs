@@ -61,6 +61,10 @@ impl Storage for MockStorage {
         (Ok(self.data.get(key).cloned()), gas_info)
     }
 
+    fn get_unmetered(&self, key: &[u8]) -> FfiResult<Option<Vec<u8>>> {
+        (Ok(self.data.get(key).cloned()), GasInfo::free())
+    }
+
     #[cfg(feature = "iterator")]
     /// range allows iteration over a set of keys, either forwards or backwards
     /// uses standard rust range notation, and eg db.range(b"foo"..b"bar") also works reverse
@@ -141,6 +145,17 @@ mod test {
         assert_eq!(None, store.get(b"food").0.unwrap());
     }
 
+    #[test]
+    fn get_unmetered_is_free() {
+        let mut store = MockStorage::new();
+        store.set(b"foo", b"bar").0.unwrap();
+
+        let (value, gas_info) = store.get_unmetered(b"foo");
+        assert_eq!(Some(b"bar".to_vec()), value.unwrap());
+        assert_eq!(gas_info.externally_used, 0);
+        assert_eq!(None, store.get_unmetered(b"food").0.unwrap());
+    }
+
     #[test]
     fn delete() {
         let mut store = MockStorage::new();
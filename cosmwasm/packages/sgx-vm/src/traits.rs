@@ -2,9 +2,7 @@ use cosmwasm_std::{Binary, CanonicalAddr, HumanAddr, StdResult, SystemResult};
 #[cfg(feature = "iterator")]
 use cosmwasm_std::{Order, KV};
 
-#[cfg(feature = "iterator")]
-use crate::ffi::FfiError;
-use crate::ffi::FfiResult;
+use crate::ffi::{FfiError, FfiResult, GasInfo};
 
 /// Holds all external dependencies of the contract.
 /// Designed to allow easy dependency injection at runtime.
@@ -71,6 +69,20 @@ where
     /// is not great yet and might not be possible in all backends. But we're trying to get there.
     fn get(&self, key: &[u8]) -> FfiResult<Option<Vec<u8>>>;
 
+    /// Reads a key like `get`, without charging gas for it. The enclave uses this to read ahead
+    /// the keys it predicts a contract will read, and charges the contract itself when it does.
+    ///
+    /// Backends that can't read without charging gas keep this default, which fails, and the
+    /// enclave doesn't read ahead.
+    fn get_unmetered(&self, _key: &[u8]) -> FfiResult<Option<Vec<u8>>> {
+        (
+            Err(FfiError::unknown(
+                "the storage can't read without charging gas",
+            )),
+            GasInfo::free(),
+        )
+    }
+
     #[cfg(feature = "iterator")]
     /// Allows iteration over a set of key/value pairs, either forwards or backwards.
     ///
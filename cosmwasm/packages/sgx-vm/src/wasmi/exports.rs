@@ -1,10 +1,22 @@
 use enclave_ffi_types::{Ctx, EnclaveBuffer, OcallReturn, UntrustedVmError, UserSpaceBuffer};
+use lazy_static::lazy_static;
+use log::*;
 use std::ffi::c_void;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Instant;
+use std::{env, fs, io};
 
+#[cfg(feature = "iterator")]
+use crate::context::{add_iterator, with_iterator_from_context};
 use crate::context::{with_querier_from_context, with_storage_from_context};
 use crate::{Querier, Storage, VmError, VmResult};
+#[cfg(feature = "iterator")]
+use cosmwasm_std::Order;
 use cosmwasm_std::{Binary, StdResult, SystemResult};
 
+use super::query_slots::QueryOcallGuard;
+
 /// Copy a buffer from the enclave memory space, and return an opaque pointer to it.
 #[no_mangle]
 pub extern "C" fn ocall_allocate(buffer: *const u8, length: usize) -> UserSpaceBuffer {
@@ -71,6 +83,82 @@ pub extern "C" fn ocall_read_db(
         .unwrap_or(OcallReturn::Panic)
 }
 
+/// Read many keys from the contracts key-value store. The keys and the values are packed the way
+/// the enclave packs them, see `decode_sections` and `encode_optional_sections`.
+#[no_mangle]
+pub extern "C" fn ocall_read_db_multi(
+    context: Ctx,
+    vm_error: *mut UntrustedVmError,
+    gas_used: *mut u64,
+    values: *mut EnclaveBuffer,
+    keys: *const u8,
+    keys_len: usize,
+) -> OcallReturn {
+    let keys = unsafe { std::slice::from_raw_parts(keys, keys_len) };
+
+    let implementation = unsafe { get_implementations_from_context(&context).read_db_multi };
+
+    std::panic::catch_unwind(|| implementation(context, keys))
+        .map(|result| -> Result<EnclaveBuffer, OcallReturn> {
+            match result {
+                Ok((packed_values, gas_cost)) => {
+                    unsafe { *gas_used = gas_cost };
+                    super::allocate_enclave_buffer(&packed_values).map_err(|_| OcallReturn::Failure)
+                }
+                Err(err) => {
+                    unsafe { store_vm_error(err, vm_error) };
+                    Err(OcallReturn::Failure)
+                }
+            }
+        })
+        .map(|result| match result {
+            Ok(enclave_buffer) => {
+                unsafe { *values = enclave_buffer };
+                OcallReturn::Success
+            }
+            Err(err) => err,
+        })
+        // This will happen only when `catch_unwind` returns `Err`, which indicates a caught panic
+        .unwrap_or(OcallReturn::Panic)
+}
+
+/// Read many keys from the contracts key-value store without charging gas for them, for the
+/// enclave to read ahead. Packed like `ocall_read_db_multi`.
+#[no_mangle]
+pub extern "C" fn ocall_prefetch_db(
+    context: Ctx,
+    vm_error: *mut UntrustedVmError,
+    values: *mut EnclaveBuffer,
+    keys: *const u8,
+    keys_len: usize,
+) -> OcallReturn {
+    let keys = unsafe { std::slice::from_raw_parts(keys, keys_len) };
+
+    let implementation = unsafe { get_implementations_from_context(&context).prefetch_db };
+
+    std::panic::catch_unwind(|| implementation(context, keys))
+        .map(|result| -> Result<EnclaveBuffer, OcallReturn> {
+            match result {
+                Ok(packed_values) => {
+                    super::allocate_enclave_buffer(&packed_values).map_err(|_| OcallReturn::Failure)
+                }
+                Err(err) => {
+                    unsafe { store_vm_error(err, vm_error) };
+                    Err(OcallReturn::Failure)
+                }
+            }
+        })
+        .map(|result| match result {
+            Ok(enclave_buffer) => {
+                unsafe { *values = enclave_buffer };
+                OcallReturn::Success
+            }
+            Err(err) => err,
+        })
+        // This will happen only when `catch_unwind` returns `Err`, which indicates a caught panic
+        .unwrap_or(OcallReturn::Panic)
+}
+
 /// Read a key from the contracts key-value store.
 #[no_mangle]
 pub extern "C" fn ocall_query_chain(
@@ -86,6 +174,8 @@ pub extern "C" fn ocall_query_chain(
 
     let implementation = unsafe { get_implementations_from_context(&context).query_chain };
 
+    // The query reaches the enclave on the TCS of the ecall that made it
+    let _ocall = QueryOcallGuard::enter();
     std::panic::catch_unwind(|| implementation(context, query, gas_limit))
         // Get either an error(`OcallReturn`), or a response(`EnclaveBuffer`)
         // which will be converted to a success status.
@@ -151,6 +241,96 @@ pub extern "C" fn ocall_remove_db(
     .unwrap_or(OcallReturn::Panic)
 }
 
+/// Open an iterator over the keys of the contracts key-value store in the range `[start, end)`,
+/// where an empty bound leaves that end of the range open.
+/// The iterator is kept in the context until the context is dropped at the end of the ecall.
+#[no_mangle]
+pub extern "C" fn ocall_db_scan(
+    context: Ctx,
+    vm_error: *mut UntrustedVmError,
+    gas_used: *mut u64,
+    iterator_id: *mut u32,
+    start: *const u8,
+    start_len: usize,
+    end: *const u8,
+    end_len: usize,
+) -> OcallReturn {
+    let start = unsafe { std::slice::from_raw_parts(start, start_len) };
+    let end = unsafe { std::slice::from_raw_parts(end, end_len) };
+
+    let implementation = unsafe { get_implementations_from_context(&context).scan_db };
+
+    std::panic::catch_unwind(|| match implementation(context, start, end) {
+        Ok((new_iterator_id, gas_cost)) => {
+            unsafe {
+                *gas_used = gas_cost;
+                *iterator_id = new_iterator_id;
+            }
+            OcallReturn::Success
+        }
+        Err(err) => {
+            unsafe { store_vm_error(err, vm_error) };
+            OcallReturn::Failure
+        }
+    })
+    // This will happen only when `catch_unwind` returns `Err`, which indicates a caught panic
+    .unwrap_or(OcallReturn::Panic)
+}
+
+/// Read the next key and value from an iterator opened by `ocall_db_scan`.
+/// Both buffers are left empty when the iterator is exhausted.
+#[no_mangle]
+pub extern "C" fn ocall_db_next(
+    context: Ctx,
+    vm_error: *mut UntrustedVmError,
+    gas_used: *mut u64,
+    iterator_id: u32,
+    key: *mut EnclaveBuffer,
+    value: *mut EnclaveBuffer,
+) -> OcallReturn {
+    let implementation = unsafe { get_implementations_from_context(&context).next_db };
+
+    std::panic::catch_unwind(|| implementation(context, iterator_id))
+        // Get either an error(`OcallReturn`), or the key and value(`EnclaveBuffer`s)
+        // which will be converted to a success status.
+        .map(
+            |result| -> Result<(EnclaveBuffer, EnclaveBuffer), OcallReturn> {
+                match result {
+                    Ok((kv, gas_cost)) => {
+                        unsafe { *gas_used = gas_cost };
+                        match kv {
+                            Some((key, value)) => {
+                                let key = super::allocate_enclave_buffer(&key)
+                                    .map_err(|_| OcallReturn::Failure)?;
+                                let value = super::allocate_enclave_buffer(&value)
+                                    .map_err(|_| OcallReturn::Failure)?;
+                                Ok((key, value))
+                            }
+                            None => Ok((EnclaveBuffer::default(), EnclaveBuffer::default())),
+                        }
+                    }
+                    Err(err) => {
+                        unsafe { store_vm_error(err, vm_error) };
+                        Err(OcallReturn::Failure)
+                    }
+                }
+            },
+        )
+        // Return the result or report the error
+        .map(|result| match result {
+            Ok((key_buffer, value_buffer)) => {
+                unsafe {
+                    *key = key_buffer;
+                    *value = value_buffer;
+                }
+                OcallReturn::Success
+            }
+            Err(err) => err,
+        })
+        // This will happen only when `catch_unwind` returns `Err`, which indicates a caught panic
+        .unwrap_or(OcallReturn::Panic)
+}
+
 /// Write a value to the contracts key-value store.
 #[no_mangle]
 pub extern "C" fn ocall_write_db(
@@ -184,6 +364,98 @@ pub extern "C" fn ocall_write_db(
     .unwrap_or(OcallReturn::Panic)
 }
 
+lazy_static! {
+    static ref MONOTONIC_EPOCH: Instant = Instant::now();
+}
+
+/// The time on a monotonic clock, in nanoseconds, for the execution timeouts of the enclave.
+/// Only differences between two readings are meaningful.
+#[no_mangle]
+pub extern "C" fn ocall_monotonic_now() -> u64 {
+    MONOTONIC_EPOCH.elapsed().as_nanos() as u64
+}
+
+/// Print the log lines that an ecall of the enclave collected, see `log_buffer` in the enclave
+#[no_mangle]
+pub extern "C" fn ocall_write_log(lines: *const u8, lines_len: usize) {
+    let lines = unsafe { std::slice::from_raw_parts(lines, lines_len) };
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    // There is nowhere to report that printing failed
+    let _ = stdout.write_all(lines).and_then(|()| stdout.flush());
+}
+
+/// The size of the code hashes module artifacts are kept by
+const CODE_HASH_SIZE: usize = 32;
+
+/// Where the module artifact of the contract with this code hash is kept, next to the other files
+/// the enclave seals
+fn module_artifact_path(code_hash: &[u8]) -> PathBuf {
+    let storage = env::var("SCRT_SGX_STORAGE").unwrap_or_else(|_| "./.sgx_secrets/".to_string());
+    PathBuf::from(storage)
+        .join("module_artifacts")
+        .join(format!("{}.sealed", hex::encode(code_hash)))
+}
+
+fn write_module_artifact(code_hash: &[u8], artifact: &[u8]) -> io::Result<()> {
+    let path = module_artifact_path(code_hash);
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory)?;
+    }
+
+    // Write to a temporary file first, so that a crash doesn't leave half an artifact behind
+    let temporary_path = path.with_extension("tmp");
+    fs::write(&temporary_path, artifact)?;
+    fs::rename(&temporary_path, &path)
+}
+
+/// Keep the sealed module artifact of a contract, so that the enclave doesn't have to analyze the
+/// contract again after the node restarts. Artifacts are only a cache, so failures are ignored.
+#[no_mangle]
+pub extern "C" fn ocall_store_module_artifact(
+    code_hash: *const u8,
+    artifact: *const u8,
+    artifact_len: usize,
+) {
+    let code_hash = unsafe { std::slice::from_raw_parts(code_hash, CODE_HASH_SIZE) };
+    let artifact = unsafe { std::slice::from_raw_parts(artifact, artifact_len) };
+
+    match std::panic::catch_unwind(|| write_module_artifact(code_hash, artifact)) {
+        Ok(Ok(())) => {}
+        Ok(Err(err)) => warn!("Failed to store the module artifact: {}", err),
+        Err(_) => warn!("Panicked while storing the module artifact"),
+    }
+}
+
+/// Return the sealed module artifact of a contract, or an empty buffer if there isn't one.
+/// The enclave checks the artifact before it uses it.
+#[no_mangle]
+pub extern "C" fn ocall_load_module_artifact(
+    code_hash: *const u8,
+    artifact: *mut EnclaveBuffer,
+) -> OcallReturn {
+    let code_hash = unsafe { std::slice::from_raw_parts(code_hash, CODE_HASH_SIZE) };
+
+    std::panic::catch_unwind(|| match fs::read(module_artifact_path(code_hash)) {
+        Ok(sealed) => super::allocate_enclave_buffer(&sealed).map_err(|_| OcallReturn::Failure),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(EnclaveBuffer::default()),
+        Err(err) => {
+            warn!("Failed to read the module artifact: {}", err);
+            Ok(EnclaveBuffer::default())
+        }
+    })
+    .map(|result| match result {
+        Ok(enclave_buffer) => {
+            unsafe { *artifact = enclave_buffer };
+            OcallReturn::Success
+        }
+        Err(err) => err,
+    })
+    // This will happen only when `catch_unwind` returns `Err`, which indicates a caught panic
+    .unwrap_or(OcallReturn::Panic)
+}
+
 /// Box the error and return a pointer to it.
 /// This box will be recovered on the side that called the enclave.
 ///
@@ -202,12 +474,16 @@ unsafe fn store_vm_error(vm_err: VmError, location: *mut UntrustedVmError) {
 #[allow(clippy::type_complexity)]
 struct ExportImplementations {
     read_db: fn(context: Ctx, key: &[u8]) -> VmResult<(Option<Vec<u8>>, u64)>,
+    read_db_multi: fn(context: Ctx, keys: &[u8]) -> VmResult<(Vec<u8>, u64)>,
+    prefetch_db: fn(context: Ctx, keys: &[u8]) -> VmResult<Vec<u8>>,
     query_chain: fn(
         context: Ctx,
         query: &[u8],
         gas_limit: u64,
     ) -> VmResult<(SystemResult<StdResult<Binary>>, u64)>,
     remove_db: fn(context: Ctx, key: &[u8]) -> VmResult<u64>,
+    scan_db: fn(context: Ctx, start: &[u8], end: &[u8]) -> VmResult<(u32, u64)>,
+    next_db: fn(context: Ctx, iterator_id: u32) -> VmResult<(Option<(Vec<u8>, Vec<u8>)>, u64)>,
     write_db: fn(context: Ctx, key: &[u8], value: &[u8]) -> VmResult<u64>,
 }
 
@@ -219,8 +495,12 @@ impl ExportImplementations {
     {
         Self {
             read_db: ocall_read_db_impl::<S, Q>,
+            read_db_multi: ocall_read_db_multi_impl::<S, Q>,
+            prefetch_db: ocall_prefetch_db_impl::<S, Q>,
             query_chain: ocall_query_chain_impl::<S, Q>,
             remove_db: ocall_remove_db_impl::<S, Q>,
+            scan_db: ocall_db_scan_impl::<S, Q>,
+            next_db: ocall_db_next_impl::<S, Q>,
             write_db: ocall_write_db_impl::<S, Q>,
         }
     }
@@ -267,6 +547,90 @@ where
     })
 }
 
+fn ocall_read_db_multi_impl<S, Q>(mut context: Ctx, keys: &[u8]) -> VmResult<(Vec<u8>, u64)>
+where
+    S: Storage,
+    Q: Querier,
+{
+    let keys = decode_sections(keys)
+        .ok_or_else(|| VmError::generic_err("got malformed keys from the enclave"))?;
+
+    with_storage_from_context::<S, Q, _, _>(&mut context, |storage: &mut S| {
+        let mut values = Vec::with_capacity(keys.len());
+        let mut total_gas_used = 0_u64;
+        for key in keys {
+            let (ffi_result, gas_info) = storage.get(key);
+            values.push(ffi_result?);
+            total_gas_used = total_gas_used.saturating_add(gas_info.externally_used);
+        }
+        Ok((encode_optional_sections(&values), total_gas_used))
+    })
+}
+
+fn ocall_prefetch_db_impl<S, Q>(mut context: Ctx, keys: &[u8]) -> VmResult<Vec<u8>>
+where
+    S: Storage,
+    Q: Querier,
+{
+    let keys = decode_sections(keys)
+        .ok_or_else(|| VmError::generic_err("got malformed keys from the enclave"))?;
+
+    with_storage_from_context::<S, Q, _, _>(&mut context, |storage: &mut S| {
+        let mut values = Vec::with_capacity(keys.len());
+        for key in keys {
+            let (ffi_result, _gas_info) = storage.get_unmetered(key);
+            values.push(ffi_result?);
+        }
+        Ok(encode_optional_sections(&values))
+    })
+}
+
+/// Decode a list of byte slices that the enclave packed into a single buffer: each section is
+/// followed by its length as a big-endian u32.
+fn decode_sections(data: &[u8]) -> Option<Vec<&[u8]>> {
+    let mut sections = Vec::new();
+    let mut remaining = data;
+
+    while !remaining.is_empty() {
+        if remaining.len() < 4 {
+            return None;
+        }
+        let (rest, len_bytes) = remaining.split_at(remaining.len() - 4);
+        let len =
+            u32::from_be_bytes([len_bytes[0], len_bytes[1], len_bytes[2], len_bytes[3]]) as usize;
+        if len > rest.len() {
+            return None;
+        }
+        let (rest, section) = rest.split_at(rest.len() - len);
+        sections.push(section);
+        remaining = rest;
+    }
+
+    sections.reverse();
+    Some(sections)
+}
+
+/// Encode a list of optional values as sections, each starting with 1 if the value is there and
+/// 0 if it's missing
+fn encode_optional_sections(values: &[Option<Vec<u8>>]) -> Vec<u8> {
+    let mut data = Vec::new();
+    for value in values {
+        let section_len = match value {
+            Some(value) => {
+                data.push(1);
+                data.extend_from_slice(value);
+                value.len() + 1
+            }
+            None => {
+                data.push(0);
+                1
+            }
+        };
+        data.extend_from_slice(&(section_len as u32).to_be_bytes());
+    }
+    data
+}
+
 fn ocall_query_chain_impl<S, Q>(
     mut context: Ctx,
     query: &[u8],
@@ -297,6 +661,68 @@ where
     })
 }
 
+#[cfg(feature = "iterator")]
+fn ocall_db_scan_impl<S, Q>(mut context: Ctx, start: &[u8], end: &[u8]) -> VmResult<(u32, u64)>
+where
+    S: Storage,
+    Q: Querier,
+{
+    let (iterator, gas_cost) =
+        with_storage_from_context::<S, Q, _, _>(&mut context, |storage: &mut S| {
+            // An empty bound leaves that end of the range open
+            let start = Some(start).filter(|start| !start.is_empty());
+            let end = Some(end).filter(|end| !end.is_empty());
+            let (ffi_result, gas_info) = storage.range(start, end, Order::Ascending);
+            ffi_result
+                .map(|iterator| (iterator, gas_info.externally_used))
+                .map_err(Into::into)
+        })?;
+    let iterator_id = add_iterator::<S, Q>(&mut context, iterator);
+    Ok((iterator_id, gas_cost))
+}
+
+#[cfg(not(feature = "iterator"))]
+fn ocall_db_scan_impl<S, Q>(_context: Ctx, _start: &[u8], _end: &[u8]) -> VmResult<(u32, u64)>
+where
+    S: Storage,
+    Q: Querier,
+{
+    Err(VmError::generic_err(
+        "Iterating over storage requires the iterator feature",
+    ))
+}
+
+#[cfg(feature = "iterator")]
+fn ocall_db_next_impl<S, Q>(
+    mut context: Ctx,
+    iterator_id: u32,
+) -> VmResult<(Option<(Vec<u8>, Vec<u8>)>, u64)>
+where
+    S: Storage,
+    Q: Querier,
+{
+    with_iterator_from_context::<S, Q, _, _>(&mut context, iterator_id, |iterator| {
+        let (ffi_result, gas_info) = iterator.next();
+        ffi_result
+            .map(|kv| (kv, gas_info.externally_used))
+            .map_err(Into::into)
+    })
+}
+
+#[cfg(not(feature = "iterator"))]
+fn ocall_db_next_impl<S, Q>(
+    _context: Ctx,
+    _iterator_id: u32,
+) -> VmResult<(Option<(Vec<u8>, Vec<u8>)>, u64)>
+where
+    S: Storage,
+    Q: Querier,
+{
+    Err(VmError::generic_err(
+        "Iterating over storage requires the iterator feature",
+    ))
+}
+
 fn ocall_write_db_impl<S, Q>(mut context: Ctx, key: &[u8], value: &[u8]) -> VmResult<u64>
 where
     S: Storage,
@@ -309,3 +735,24 @@ where
             .map_err(Into::into)
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_db_multi_packing() {
+        let keys = b"key\x00\x00\x00\x03\x00\x00\x00\x00other key\x00\x00\x00\x09";
+        assert_eq!(
+            decode_sections(keys).unwrap(),
+            vec![&b"key"[..], &b""[..], &b"other key"[..]]
+        );
+        assert!(decode_sections(b"key\x00\x00\x00\x04").is_none());
+
+        // A missing value and an empty value are packed differently
+        assert_eq!(
+            encode_optional_sections(&[Some(b"value".to_vec()), None, Some(vec![])]),
+            b"\x01value\x00\x00\x00\x06\x00\x00\x00\x00\x01\x01\x00\x00\x00\x01".to_vec()
+        );
+    }
+}
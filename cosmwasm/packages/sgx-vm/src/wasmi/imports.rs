@@ -1,13 +1,17 @@
 //! This file should be autogenerated based on the headers created from the .edl file.
 
-use enclave_ffi_types::{Ctx, EnclaveBuffer, HandleResult, InitResult, QueryResult};
+use enclave_ffi_types::{
+    AllocationResult, Ctx, ExportContractStateResult, HandleResult, IbcReceiveResult, IbcResult,
+    ImportContractStateResult, InitResult, MigrateResult, QueryResult, RotateContractKeyResult,
+    UpdateAdminResult, ValidateWasmResult,
+};
 use sgx_types::{sgx_enclave_id_t, sgx_status_t};
 
 extern "C" {
     /// Copy a buffer into the enclave memory space, and receive an opaque pointer to it.
     pub fn ecall_allocate(
         eid: sgx_enclave_id_t,
-        retval: *mut EnclaveBuffer,
+        retval: *mut AllocationResult,
         buffer: *const u8,
         length: usize,
     ) -> sgx_status_t;
@@ -44,6 +48,209 @@ extern "C" {
         msg_len: usize,
         sig_info: *const u8,
         sig_info_len: usize,
+        simulate: u8,
+    ) -> sgx_status_t;
+
+    /// Trigger the migrate method in a wasm contract
+    pub fn ecall_migrate(
+        eid: sgx_enclave_id_t,
+        retval: *mut MigrateResult,
+        context: Ctx,
+        gas_limit: u64,
+        used_gas: *mut u64,
+        contract: *const u8,
+        contract_len: usize,
+        env: *const u8,
+        env_len: usize,
+        msg: *const u8,
+        msg_len: usize,
+        sig_info: *const u8,
+        sig_info_len: usize,
+    ) -> sgx_status_t;
+
+    /// Trigger the reply method in a wasm contract, after one of its submessages was executed
+    pub fn ecall_reply(
+        eid: sgx_enclave_id_t,
+        retval: *mut HandleResult,
+        context: Ctx,
+        gas_limit: u64,
+        used_gas: *mut u64,
+        contract: *const u8,
+        contract_len: usize,
+        env: *const u8,
+        env_len: usize,
+        msg: *const u8,
+        msg_len: usize,
+    ) -> sgx_status_t;
+
+    /// Trigger the ibc_channel_open method in a wasm contract
+    pub fn ecall_ibc_channel_open(
+        eid: sgx_enclave_id_t,
+        retval: *mut IbcResult,
+        context: Ctx,
+        gas_limit: u64,
+        used_gas: *mut u64,
+        contract: *const u8,
+        contract_len: usize,
+        env: *const u8,
+        env_len: usize,
+        msg: *const u8,
+        msg_len: usize,
+    ) -> sgx_status_t;
+
+    /// Trigger the ibc_channel_connect method in a wasm contract
+    pub fn ecall_ibc_channel_connect(
+        eid: sgx_enclave_id_t,
+        retval: *mut IbcResult,
+        context: Ctx,
+        gas_limit: u64,
+        used_gas: *mut u64,
+        contract: *const u8,
+        contract_len: usize,
+        env: *const u8,
+        env_len: usize,
+        msg: *const u8,
+        msg_len: usize,
+    ) -> sgx_status_t;
+
+    /// Trigger the ibc_channel_close method in a wasm contract
+    pub fn ecall_ibc_channel_close(
+        eid: sgx_enclave_id_t,
+        retval: *mut IbcResult,
+        context: Ctx,
+        gas_limit: u64,
+        used_gas: *mut u64,
+        contract: *const u8,
+        contract_len: usize,
+        env: *const u8,
+        env_len: usize,
+        msg: *const u8,
+        msg_len: usize,
+    ) -> sgx_status_t;
+
+    /// Trigger the ibc_packet_receive method in a wasm contract
+    pub fn ecall_ibc_packet_receive(
+        eid: sgx_enclave_id_t,
+        retval: *mut IbcReceiveResult,
+        context: Ctx,
+        gas_limit: u64,
+        used_gas: *mut u64,
+        contract: *const u8,
+        contract_len: usize,
+        env: *const u8,
+        env_len: usize,
+        msg: *const u8,
+        msg_len: usize,
+    ) -> sgx_status_t;
+
+    /// Trigger the ibc_packet_ack method in a wasm contract
+    pub fn ecall_ibc_packet_ack(
+        eid: sgx_enclave_id_t,
+        retval: *mut IbcResult,
+        context: Ctx,
+        gas_limit: u64,
+        used_gas: *mut u64,
+        contract: *const u8,
+        contract_len: usize,
+        env: *const u8,
+        env_len: usize,
+        msg: *const u8,
+        msg_len: usize,
+    ) -> sgx_status_t;
+
+    /// Trigger the ibc_packet_timeout method in a wasm contract
+    pub fn ecall_ibc_packet_timeout(
+        eid: sgx_enclave_id_t,
+        retval: *mut IbcResult,
+        context: Ctx,
+        gas_limit: u64,
+        used_gas: *mut u64,
+        contract: *const u8,
+        contract_len: usize,
+        env: *const u8,
+        env_len: usize,
+        msg: *const u8,
+        msg_len: usize,
+    ) -> sgx_status_t;
+
+    /// Re-encrypt the next chunk of a contract's storage under a freshly derived contract key
+    pub fn ecall_rotate_contract_key(
+        eid: sgx_enclave_id_t,
+        retval: *mut RotateContractKeyResult,
+        context: Ctx,
+        gas_limit: u64,
+        used_gas: *mut u64,
+        env: *const u8,
+        env_len: usize,
+        sig_info: *const u8,
+        sig_info_len: usize,
+        cursor: *const u8,
+        cursor_len: usize,
+        chunk_size: u32,
+    ) -> sgx_status_t;
+
+    /// Export the next chunk of a contract's storage, with a MAC over every entry
+    pub fn ecall_export_contract_state(
+        eid: sgx_enclave_id_t,
+        retval: *mut ExportContractStateResult,
+        context: Ctx,
+        env: *const u8,
+        env_len: usize,
+        cursor: *const u8,
+        cursor_len: usize,
+        snapshot_nonce: *const u8,
+        snapshot_nonce_len: usize,
+        chunk_size: u32,
+    ) -> sgx_status_t;
+
+    /// Verify a chunk of an exported contract state and write it back to the contract's storage
+    pub fn ecall_import_contract_state(
+        eid: sgx_enclave_id_t,
+        retval: *mut ImportContractStateResult,
+        context: Ctx,
+        env: *const u8,
+        env_len: usize,
+        sig_info: *const u8,
+        sig_info_len: usize,
+        chunk: *const u8,
+        chunk_len: usize,
+    ) -> sgx_status_t;
+
+    /// Check that the admin of a contract is changed by its current admin, and return the proof
+    /// for the new one
+    pub fn ecall_update_admin(
+        eid: sgx_enclave_id_t,
+        retval: *mut UpdateAdminResult,
+        env: *const u8,
+        env_len: usize,
+        sig_info: *const u8,
+        sig_info_len: usize,
+        new_admin: *const u8,
+        new_admin_len: usize,
+    ) -> sgx_status_t;
+
+    /// Start a session for validating a contract that is sent to the enclave in chunks
+    pub fn ecall_validate_wasm_begin(
+        eid: sgx_enclave_id_t,
+        retval: *mut ValidateWasmResult,
+        session_id: u64,
+    ) -> sgx_status_t;
+
+    /// Append the next chunk of the contract to a validation session
+    pub fn ecall_validate_wasm_chunk(
+        eid: sgx_enclave_id_t,
+        retval: *mut ValidateWasmResult,
+        session_id: u64,
+        chunk_index: u32,
+        chunk: *const u8,
+        chunk_len: usize,
+    ) -> sgx_status_t;
+
+    /// Validate the contract of a validation session, and return its code hash
+    pub fn ecall_validate_wasm_end(
+        eid: sgx_enclave_id_t,
+        retval: *mut ValidateWasmResult,
+        session_id: u64,
     ) -> sgx_status_t;
 
     /// Trigger a query method in a wasm contract
@@ -55,6 +262,8 @@ extern "C" {
         used_gas: *mut u64,
         contract: *const u8,
         contract_len: usize,
+        env: *const u8,
+        env_len: usize,
         msg: *const u8,
         msg_len: usize,
     ) -> sgx_status_t;
@@ -1,9 +1,11 @@
 mod exports;
 mod imports;
+mod query_slots;
 mod results;
 mod utils;
 mod wrapper;
 
-pub(crate) use exports::FullContext;
+pub(crate) use exports::{recover_buffer, FullContext};
 pub use imports::*;
+pub use query_slots::MAX_CONCURRENT_QUERIES;
 pub use wrapper::*;
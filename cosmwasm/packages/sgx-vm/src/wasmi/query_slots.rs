@@ -0,0 +1,141 @@
+//! Bounds how many queries run in the enclave at once.
+//!
+//! Every ecall that isn't nested in another one runs on a TCS of its own, and an ecall that finds
+//! no free TCS fails with `SGX_ERROR_OUT_OF_TCS`. The enclave has a TCS for each of
+//! `MAX_CONCURRENT_QUERIES` queries, and two more so that the execution of a block and one other
+//! ecall can always run next to them (see `TCSNum` in Enclave.config.xml). Queries past the limit
+//! wait here for a slot instead of failing.
+//!
+//! A query that a contract makes reaches the enclave through `ocall_query_chain`, on the thread and
+//! TCS of the ecall that made it, so it doesn't take a slot. Otherwise queries that hold every slot
+//! would wait forever for the queries they make.
+
+use std::cell::Cell;
+use std::sync::{Condvar, Mutex};
+
+use lazy_static::lazy_static;
+
+pub const MAX_CONCURRENT_QUERIES: usize = 16;
+
+lazy_static! {
+    /// The amount of queries that hold a slot, and a condition that is signaled when one is freed
+    static ref RUNNING_QUERIES: (Mutex<usize>, Condvar) = (Mutex::new(0), Condvar::new());
+}
+
+thread_local! {
+    /// How many `ocall_query_chain`s this thread is in
+    static QUERY_OCALL_DEPTH: Cell<usize> = Cell::new(0);
+}
+
+/// The right of a query to run in the enclave. Dropping it lets another query run.
+pub struct QuerySlot {
+    held: bool,
+}
+
+impl QuerySlot {
+    /// Wait for a free slot, unless the query was made by a contract that is already running
+    pub fn acquire() -> Self {
+        if QUERY_OCALL_DEPTH.with(|depth| depth.get()) > 0 {
+            return Self { held: false };
+        }
+
+        let (running, slot_freed) = &*RUNNING_QUERIES;
+        let mut running = running.lock().unwrap();
+        while *running >= MAX_CONCURRENT_QUERIES {
+            running = slot_freed.wait(running).unwrap();
+        }
+        *running += 1;
+
+        Self { held: true }
+    }
+}
+
+impl Drop for QuerySlot {
+    fn drop(&mut self) {
+        if self.held {
+            let (running, slot_freed) = &*RUNNING_QUERIES;
+            *running.lock().unwrap() -= 1;
+            slot_freed.notify_one();
+        }
+    }
+}
+
+/// Marks the thread as being in `ocall_query_chain` for as long as it lives
+pub struct QueryOcallGuard {
+    _private: (),
+}
+
+impl QueryOcallGuard {
+    pub fn enter() -> Self {
+        QUERY_OCALL_DEPTH.with(|depth| depth.set(depth.get() + 1));
+        Self { _private: () }
+    }
+}
+
+impl Drop for QueryOcallGuard {
+    fn drop(&mut self) {
+        QUERY_OCALL_DEPTH.with(|depth| depth.set(depth.get() - 1));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{mpsc, Arc, Barrier};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn queries_past_the_limit_wait_for_a_slot() {
+        let running = Arc::new(AtomicUsize::new(0));
+        let most_running = Arc::new(AtomicUsize::new(0));
+
+        let threads: Vec<_> = (0..4 * MAX_CONCURRENT_QUERIES)
+            .map(|_| {
+                let running = running.clone();
+                let most_running = most_running.clone();
+                thread::spawn(move || {
+                    let _slot = QuerySlot::acquire();
+                    let now_running = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    most_running.fetch_max(now_running, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(10));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert!(most_running.load(Ordering::SeqCst) <= MAX_CONCURRENT_QUERIES);
+    }
+
+    #[test]
+    fn nested_queries_dont_take_a_slot() {
+        // Hold every slot, and make a query from inside each of them
+        let all_held = Arc::new(Barrier::new(MAX_CONCURRENT_QUERIES));
+        let (done, finished) = mpsc::channel();
+
+        for _ in 0..MAX_CONCURRENT_QUERIES {
+            let all_held = all_held.clone();
+            let done = done.clone();
+            thread::spawn(move || {
+                let _slot = QuerySlot::acquire();
+                all_held.wait();
+
+                let _ocall = QueryOcallGuard::enter();
+                let nested = QuerySlot::acquire();
+                assert!(!nested.held);
+                done.send(()).unwrap();
+            });
+        }
+
+        for _ in 0..MAX_CONCURRENT_QUERIES {
+            finished
+                .recv_timeout(Duration::from_secs(10))
+                .expect("a nested query waited for a slot");
+        }
+    }
+}
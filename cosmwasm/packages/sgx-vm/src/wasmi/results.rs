@@ -1,6 +1,12 @@
+use log::*;
+
 use super::exports;
 use crate::VmResult;
-use enclave_ffi_types::{HandleResult, InitResult, QueryResult};
+use enclave_ffi_types::{
+    ExportContractStateResult, GasBreakdown, HandleResult, IbcReceiveResult, IbcResult,
+    ImportContractStateResult, InitResult, MigrateResult, QueryResult, RotateContractKeyResult,
+    UpdateAdminResult, ValidateWasmResult,
+};
 
 /// This struct is returned from module initialization.
 pub struct InitSuccess {
@@ -8,11 +14,14 @@ pub struct InitSuccess {
     output: Vec<u8>,
     /// The contract_key for this contract.
     contract_key: [u8; 64],
+    /// The proof that the enclave authenticates the admin of this contract with
+    admin_proof: [u8; 32],
 }
 
 impl InitSuccess {
     pub fn into_output(self) -> Vec<u8> {
         let mut out_vec = self.contract_key.to_vec();
+        out_vec.extend_from_slice(&self.admin_proof);
         out_vec.extend_from_slice(&self.output);
         out_vec
     }
@@ -23,11 +32,21 @@ pub fn init_result_to_vm_result(other: InitResult) -> VmResult<InitSuccess> {
         InitResult::Success {
             output,
             contract_key,
+            admin_proof,
         } => Ok(InitSuccess {
             output: unsafe { exports::recover_buffer(output) }.unwrap_or_else(Vec::new),
             contract_key,
+            admin_proof,
         }),
         InitResult::Failure { err } => Err(err.into()),
+        // Errors returned by the contract itself are passed on as a regular output, so that the
+        // encrypted error reaches the sender of the tx. There is no contract_key or admin_proof
+        // in this case.
+        InitResult::ContractError { output } => Ok(InitSuccess {
+            output: unsafe { exports::recover_buffer(output) }.unwrap_or_else(Vec::new),
+            contract_key: [0; 64],
+            admin_proof: [0; 32],
+        }),
     }
 }
 
@@ -35,9 +54,15 @@ pub fn init_result_to_vm_result(other: InitResult) -> VmResult<InitSuccess> {
 pub struct HandleSuccess {
     /// A pointer to the output of the execution
     output: Vec<u8>,
+    /// Where the gas used by the execution was spent. Not reported when the contract failed
+    gas_breakdown: Option<GasBreakdown>,
 }
 
 impl HandleSuccess {
+    pub fn gas_breakdown(&self) -> Option<GasBreakdown> {
+        self.gas_breakdown
+    }
+
     pub fn into_output(self) -> Vec<u8> {
         self.output
     }
@@ -45,10 +70,25 @@ impl HandleSuccess {
 
 pub fn handle_result_to_vm_result(other: HandleResult) -> VmResult<HandleSuccess> {
     match other {
-        HandleResult::Success { output } => Ok(HandleSuccess {
+        HandleResult::Success {
+            output,
+            gas_breakdown,
+        } => Ok(HandleSuccess {
             output: unsafe { exports::recover_buffer(output) }.unwrap_or_else(Vec::new),
+            gas_breakdown: Some(gas_breakdown),
+        }),
+        HandleResult::Failure { err, debug_info } => {
+            // Only enclaves built for local networks report where the contract trapped
+            if let Some(debug_info) = unsafe { exports::recover_buffer(debug_info) } {
+                warn!("{}", String::from_utf8_lossy(&debug_info));
+            }
+            Err(err.into())
+        }
+        // See `init_result_to_vm_result`
+        HandleResult::ContractError { output } => Ok(HandleSuccess {
+            output: unsafe { exports::recover_buffer(output) }.unwrap_or_else(Vec::new),
+            gas_breakdown: None,
         }),
-        HandleResult::Failure { err } => Err(err.into()),
     }
 }
 
@@ -70,5 +110,215 @@ pub fn query_result_to_vm_result(other: QueryResult) -> VmResult<QuerySuccess> {
             output: unsafe { exports::recover_buffer(output) }.unwrap_or_else(Vec::new),
         }),
         QueryResult::Failure { err } => Err(err.into()),
+        // See `init_result_to_vm_result`
+        QueryResult::ContractError { output } => Ok(QuerySuccess {
+            output: unsafe { exports::recover_buffer(output) }.unwrap_or_else(Vec::new),
+        }),
+    }
+}
+
+/// This struct is returned from a migrate method.
+pub struct MigrateSuccess {
+    /// A pointer to the output of the execution
+    output: Vec<u8>,
+    /// The contract_key for this contract after the migration.
+    new_contract_key: [u8; 64],
+}
+
+impl MigrateSuccess {
+    pub fn into_output(self) -> Vec<u8> {
+        let mut out_vec = self.new_contract_key.to_vec();
+        out_vec.extend_from_slice(&self.output);
+        out_vec
+    }
+}
+
+pub fn migrate_result_to_vm_result(other: MigrateResult) -> VmResult<MigrateSuccess> {
+    match other {
+        MigrateResult::Success {
+            output,
+            new_contract_key,
+        } => Ok(MigrateSuccess {
+            output: unsafe { exports::recover_buffer(output) }.unwrap_or_else(Vec::new),
+            new_contract_key,
+        }),
+        MigrateResult::Failure { err } => Err(err.into()),
+        // See `init_result_to_vm_result`
+        MigrateResult::ContractError { output } => Ok(MigrateSuccess {
+            output: unsafe { exports::recover_buffer(output) }.unwrap_or_else(Vec::new),
+            new_contract_key: [0; 64],
+        }),
+    }
+}
+
+/// This struct is returned from the IBC entry points, other than `ibc_packet_receive`.
+pub struct IbcSuccess {
+    /// A pointer to the output of the execution
+    output: Vec<u8>,
+    /// Where the gas used by the execution was spent. Not reported when the contract failed
+    gas_breakdown: Option<GasBreakdown>,
+}
+
+impl IbcSuccess {
+    pub fn gas_breakdown(&self) -> Option<GasBreakdown> {
+        self.gas_breakdown
+    }
+
+    pub fn into_output(self) -> Vec<u8> {
+        self.output
+    }
+}
+
+pub fn ibc_result_to_vm_result(other: IbcResult) -> VmResult<IbcSuccess> {
+    match other {
+        IbcResult::Success {
+            output,
+            gas_breakdown,
+        } => Ok(IbcSuccess {
+            output: unsafe { exports::recover_buffer(output) }.unwrap_or_else(Vec::new),
+            gas_breakdown: Some(gas_breakdown),
+        }),
+        IbcResult::Failure { err } => Err(err.into()),
+        // See `init_result_to_vm_result`
+        IbcResult::ContractError { output } => Ok(IbcSuccess {
+            output: unsafe { exports::recover_buffer(output) }.unwrap_or_else(Vec::new),
+            gas_breakdown: None,
+        }),
+    }
+}
+
+/// This struct is returned from `ibc_packet_receive`.
+pub struct IbcReceiveSuccess {
+    /// A pointer to the output of the execution
+    output: Vec<u8>,
+    /// The encrypted acknowledgement of the packet, empty when the contract failed
+    acknowledgement: Vec<u8>,
+    /// Where the gas used by the execution was spent. Not reported when the contract failed
+    gas_breakdown: Option<GasBreakdown>,
+}
+
+impl IbcReceiveSuccess {
+    pub fn gas_breakdown(&self) -> Option<GasBreakdown> {
+        self.gas_breakdown
+    }
+
+    /// Returns the output and the acknowledgement
+    pub fn into_output(self) -> (Vec<u8>, Vec<u8>) {
+        (self.output, self.acknowledgement)
+    }
+}
+
+pub fn ibc_receive_result_to_vm_result(other: IbcReceiveResult) -> VmResult<IbcReceiveSuccess> {
+    match other {
+        IbcReceiveResult::Success {
+            output,
+            acknowledgement,
+            gas_breakdown,
+        } => Ok(IbcReceiveSuccess {
+            output: unsafe { exports::recover_buffer(output) }.unwrap_or_else(Vec::new),
+            acknowledgement: unsafe { exports::recover_buffer(acknowledgement) }
+                .unwrap_or_else(Vec::new),
+            gas_breakdown: Some(gas_breakdown),
+        }),
+        IbcReceiveResult::Failure { err } => Err(err.into()),
+        // See `init_result_to_vm_result`
+        IbcReceiveResult::ContractError { output } => Ok(IbcReceiveSuccess {
+            output: unsafe { exports::recover_buffer(output) }.unwrap_or_else(Vec::new),
+            acknowledgement: vec![],
+            gas_breakdown: None,
+        }),
+    }
+}
+
+/// This struct is returned from a contract key rotation.
+pub struct RotateContractKeySuccess {
+    /// The contract_key for this contract after the rotation.
+    new_contract_key: [u8; 64],
+    /// The storage key to continue the rotation from, empty when the rotation is done
+    next_cursor: Vec<u8>,
+}
+
+impl RotateContractKeySuccess {
+    pub fn into_output(self) -> Vec<u8> {
+        let mut out_vec = self.new_contract_key.to_vec();
+        out_vec.extend_from_slice(&self.next_cursor);
+        out_vec
+    }
+}
+
+pub fn rotate_contract_key_result_to_vm_result(
+    other: RotateContractKeyResult,
+) -> VmResult<RotateContractKeySuccess> {
+    match other {
+        RotateContractKeyResult::Success {
+            new_contract_key,
+            next_cursor,
+        } => Ok(RotateContractKeySuccess {
+            new_contract_key,
+            next_cursor: unsafe { exports::recover_buffer(next_cursor) }.unwrap_or_else(Vec::new),
+        }),
+        RotateContractKeyResult::Failure { err } => Err(err.into()),
+    }
+}
+
+/// This struct is returned from exporting a chunk of a contract's state.
+pub struct ExportContractStateSuccess {
+    /// The nonce that every chunk of the same export is authenticated with
+    snapshot_nonce: [u8; 32],
+    /// The exported entries and their MACs, as JSON
+    chunk: Vec<u8>,
+    /// The storage key to continue the export from, empty when the export is done
+    next_cursor: Vec<u8>,
+}
+
+impl ExportContractStateSuccess {
+    /// The snapshot nonce, followed by the length of the chunk as a big endian u32, the chunk and
+    /// the cursor
+    pub fn into_output(self) -> Vec<u8> {
+        let mut out_vec = self.snapshot_nonce.to_vec();
+        out_vec.extend_from_slice(&(self.chunk.len() as u32).to_be_bytes());
+        out_vec.extend_from_slice(&self.chunk);
+        out_vec.extend_from_slice(&self.next_cursor);
+        out_vec
+    }
+}
+
+pub fn export_contract_state_result_to_vm_result(
+    other: ExportContractStateResult,
+) -> VmResult<ExportContractStateSuccess> {
+    match other {
+        ExportContractStateResult::Success {
+            snapshot_nonce,
+            chunk,
+            next_cursor,
+        } => Ok(ExportContractStateSuccess {
+            snapshot_nonce,
+            chunk: unsafe { exports::recover_buffer(chunk) }.unwrap_or_else(Vec::new),
+            next_cursor: unsafe { exports::recover_buffer(next_cursor) }.unwrap_or_else(Vec::new),
+        }),
+        ExportContractStateResult::Failure { err } => Err(err.into()),
+    }
+}
+
+pub fn import_contract_state_result_to_vm_result(
+    other: ImportContractStateResult,
+) -> VmResult<u32> {
+    match other {
+        ImportContractStateResult::Success { imported_entries } => Ok(imported_entries),
+        ImportContractStateResult::Failure { err } => Err(err.into()),
+    }
+}
+
+pub fn validate_wasm_result_to_vm_result(other: ValidateWasmResult) -> VmResult<[u8; 32]> {
+    match other {
+        ValidateWasmResult::Success { code_hash } => Ok(code_hash),
+        ValidateWasmResult::Failure { err } => Err(err.into()),
+    }
+}
+
+pub fn update_admin_result_to_vm_result(other: UpdateAdminResult) -> VmResult<[u8; 32]> {
+    match other {
+        UpdateAdminResult::Success { admin_proof } => Ok(admin_proof),
+        UpdateAdminResult::Failure { err } => Err(err.into()),
     }
 }
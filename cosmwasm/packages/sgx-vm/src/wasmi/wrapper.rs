@@ -3,29 +3,155 @@
 use std::ffi::c_void;
 use std::marker::PhantomData;
 use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicU64, Ordering};
 
 use crate::errors::{EnclaveError, VmResult};
 use crate::{Querier, Storage};
 
-use enclave_ffi_types::{Ctx, EnclaveBuffer, HandleResult, InitResult, QueryResult};
+use enclave_ffi_types::{
+    AllocationResult, Ctx, EnclaveBuffer, ExportContractStateResult, GasBreakdown, HandleResult,
+    IbcReceiveResult, IbcResult, ImportContractStateResult, InitResult, MigrateResult, QueryResult,
+    RotateContractKeyResult, UpdateAdminResult, ValidateWasmResult,
+};
 
-use sgx_types::{sgx_status_t, SgxResult};
+use sgx_types::sgx_status_t;
 use sgx_urts::SgxEnclave;
 
 use log::*;
 
 use super::exports::FullContext;
 use super::imports;
+use super::query_slots::QuerySlot;
 use super::results::{
-    handle_result_to_vm_result, init_result_to_vm_result, query_result_to_vm_result, HandleSuccess,
-    InitSuccess, QuerySuccess,
+    export_contract_state_result_to_vm_result, handle_result_to_vm_result,
+    ibc_receive_result_to_vm_result, ibc_result_to_vm_result,
+    import_contract_state_result_to_vm_result, init_result_to_vm_result,
+    migrate_result_to_vm_result, query_result_to_vm_result,
+    rotate_contract_key_result_to_vm_result, update_admin_result_to_vm_result,
+    validate_wasm_result_to_vm_result, ExportContractStateSuccess, HandleSuccess,
+    IbcReceiveSuccess, IbcSuccess, InitSuccess, MigrateSuccess, QuerySuccess,
+    RotateContractKeySuccess,
 };
 
+/// The size of the pieces that contracts are sent to the enclave in for validation
+pub const WASM_VALIDATION_CHUNK_SIZE: usize = 256 * 1024;
+
+/// The IBC entry points of a contract that are called with plaintext messages.
+/// `ibc_packet_receive` has its own call, since its message and acknowledgement are encrypted.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IbcEntryPoint {
+    ChannelOpen,
+    ChannelConnect,
+    ChannelClose,
+    PacketAck,
+    PacketTimeout,
+}
+
+/// Validation sessions in the enclave are keyed by this id, so every validation gets a new one
+static NEXT_VALIDATION_SESSION_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Send the contract to the enclave in chunks of `chunk_size` bytes, and have it run the same
+/// checks that the contract goes through when it's loaded.
+/// On success, the code hash that the enclave computed over the chunks is returned.
+pub fn validate_wasm(wasm: &[u8], chunk_size: usize) -> VmResult<[u8; 32]> {
+    let enclave_id = crate::enclave::get_enclave()
+        .map_err(EnclaveError::sdk_err)?
+        .geteid();
+    let session_id = NEXT_VALIDATION_SESSION_ID.fetch_add(1, Ordering::SeqCst);
+
+    trace!(
+        "validate_wasm() called with len: {:?} chunk_size: {:?} session_id: {:?} enclave_id: {:?}",
+        wasm.len(),
+        chunk_size,
+        session_id,
+        enclave_id
+    );
+
+    let mut result = MaybeUninit::<ValidateWasmResult>::uninit();
+    let status =
+        unsafe { imports::ecall_validate_wasm_begin(enclave_id, result.as_mut_ptr(), session_id) };
+    validate_wasm_status_to_vm_result(status, result)?;
+
+    for (chunk_index, chunk) in wasm.chunks(chunk_size).enumerate() {
+        let mut result = MaybeUninit::<ValidateWasmResult>::uninit();
+        let status = unsafe {
+            imports::ecall_validate_wasm_chunk(
+                enclave_id,
+                result.as_mut_ptr(),
+                session_id,
+                chunk_index as u32,
+                chunk.as_ptr(),
+                chunk.len(),
+            )
+        };
+        // If a chunk fails the session is left to expire in the enclave
+        validate_wasm_status_to_vm_result(status, result)?;
+    }
+
+    let mut result = MaybeUninit::<ValidateWasmResult>::uninit();
+    let status =
+        unsafe { imports::ecall_validate_wasm_end(enclave_id, result.as_mut_ptr(), session_id) };
+    validate_wasm_status_to_vm_result(status, result)
+}
+
+fn validate_wasm_status_to_vm_result(
+    status: sgx_status_t,
+    result: MaybeUninit<ValidateWasmResult>,
+) -> VmResult<[u8; 32]> {
+    match status {
+        sgx_status_t::SGX_SUCCESS => {
+            let result = unsafe { result.assume_init() };
+            validate_wasm_result_to_vm_result(result)
+        }
+        failure_status => Err(EnclaveError::sdk_err(failure_status).into()),
+    }
+}
+
+/// Have the enclave check that the admin of the contract in `env` is changed by its current
+/// admin, and return the proof that the enclave authenticates `new_admin` with.
+/// An empty `new_admin` clears the admin of the contract.
+pub fn update_admin(env: &[u8], sig_info: &[u8], new_admin: &[u8]) -> VmResult<[u8; 32]> {
+    let enclave_id = crate::enclave::get_enclave()
+        .map_err(EnclaveError::sdk_err)?
+        .geteid();
+
+    trace!(
+        "update_admin() called with env: {:?} enclave_id: {:?}",
+        String::from_utf8_lossy(env),
+        enclave_id
+    );
+
+    let mut result = MaybeUninit::<UpdateAdminResult>::uninit();
+    let status = unsafe {
+        imports::ecall_update_admin(
+            enclave_id,
+            result.as_mut_ptr(),
+            env.as_ptr(),
+            env.len(),
+            sig_info.as_ptr(),
+            sig_info.len(),
+            new_admin.as_ptr(),
+            new_admin.len(),
+        )
+    };
+
+    match status {
+        sgx_status_t::SGX_SUCCESS => {
+            let result = unsafe { result.assume_init() };
+            update_admin_result_to_vm_result(result)
+        }
+        failure_status => Err(EnclaveError::sdk_err(failure_status).into()),
+    }
+}
+
 /// This is a safe wrapper for allocating buffers inside the enclave.
-pub(super) fn allocate_enclave_buffer(buffer: &[u8]) -> SgxResult<EnclaveBuffer> {
+///
+/// The ocalls that call this fail when it does, since the enclave can't tell a buffer that wasn't
+/// copied from a missing one. The enclave remembers why the copy failed and reports that instead.
+pub(super) fn allocate_enclave_buffer(buffer: &[u8]) -> Result<EnclaveBuffer, EnclaveError> {
     let ptr = buffer.as_ptr();
     let len = buffer.len();
-    let mut enclave_buffer = EnclaveBuffer::default();
+    let mut result = MaybeUninit::<AllocationResult>::uninit();
 
     let enclave_id = crate::enclave::get_enclave()
         .expect("If we got here, surely the enclave has been loaded")
@@ -38,10 +164,20 @@ pub(super) fn allocate_enclave_buffer(buffer: &[u8]) -> SgxResult<EnclaveBuffer>
         enclave_id,
     );
 
-    match unsafe { imports::ecall_allocate(enclave_id, &mut enclave_buffer, ptr, len) } {
-        sgx_status_t::SGX_SUCCESS => Ok(enclave_buffer),
-        failure_status => Err(failure_status),
-    }
+    let status = unsafe { imports::ecall_allocate(enclave_id, result.as_mut_ptr(), ptr, len) };
+    let err = match status {
+        sgx_status_t::SGX_SUCCESS => match unsafe { result.assume_init() } {
+            AllocationResult::Success { buffer } => return Ok(buffer),
+            AllocationResult::Failure { err } => EnclaveError::enclave_err(err),
+        },
+        failure_status => EnclaveError::sdk_err(failure_status),
+    };
+
+    warn!(
+        "Failed to copy a buffer of {} bytes into the enclave: {}",
+        len, err
+    );
+    Err(err)
 }
 
 pub struct Module<S, Q>
@@ -52,6 +188,8 @@ where
     bytecode: Vec<u8>,
     gas_limit: u64,
     used_gas: u64,
+    /// Where the gas of the last successful handle, reply or IBC call was spent
+    gas_breakdown: Option<GasBreakdown>,
     enclave: &'static SgxEnclave,
     ctx: Ctx,
     finalizer: fn(*mut c_void),
@@ -81,6 +219,7 @@ where
             bytecode,
             gas_limit,
             used_gas: 0,
+            gas_breakdown: None,
             enclave,
             ctx,
             finalizer,
@@ -110,6 +249,10 @@ where
         self.used_gas
     }
 
+    pub fn gas_breakdown(&self) -> Option<GasBreakdown> {
+        self.gas_breakdown
+    }
+
     pub fn init(&mut self, env: &[u8], msg: &[u8], sig_info: &[u8]) -> VmResult<InitSuccess> {
         trace!(
             "init() called with env: {:?} msg: {:?} enclave_id: {:?} gas_left: {}",
@@ -156,17 +299,25 @@ where
         }
     }
 
-    pub fn handle(&mut self, env: &[u8], msg: &[u8], sig_info: &[u8]) -> VmResult<HandleSuccess> {
+    pub fn handle(
+        &mut self,
+        env: &[u8],
+        msg: &[u8],
+        sig_info: &[u8],
+        simulate: bool,
+    ) -> VmResult<HandleSuccess> {
         trace!(
-            "handle() called with env: {:?} msg: {:?} enclave_id: {:?} gas_left: {}",
+            "handle() called with env: {:?} msg: {:?} enclave_id: {:?} gas_left: {} simulate: {}",
             String::from_utf8_lossy(env),
             String::from_utf8_lossy(msg),
             self.enclave.geteid(),
-            self.gas_left()
+            self.gas_left(),
+            simulate
         );
 
         let mut handle_result = MaybeUninit::<HandleResult>::uninit();
         let mut used_gas = 0_u64;
+        self.gas_breakdown = None;
 
         let status = unsafe {
             imports::ecall_handle(
@@ -183,6 +334,7 @@ where
                 msg.len(),
                 sig_info.as_ptr(),
                 sig_info.len(),
+                simulate as u8,
             )
         };
 
@@ -196,15 +348,352 @@ where
         match status {
             sgx_status_t::SGX_SUCCESS => {
                 let handle_result = unsafe { handle_result.assume_init() };
-                handle_result_to_vm_result(handle_result)
+                let handle_success = handle_result_to_vm_result(handle_result)?;
+                self.gas_breakdown = handle_success.gas_breakdown();
+                Ok(handle_success)
             }
             failure_status => Err(EnclaveError::sdk_err(failure_status).into()),
         }
     }
 
-    pub fn query(&mut self, msg: &[u8]) -> VmResult<QuerySuccess> {
+    pub fn reply(&mut self, env: &[u8], msg: &[u8]) -> VmResult<HandleSuccess> {
         trace!(
-            "query() called with msg: {:?} enclave_id: {:?}",
+            "reply() called with env: {:?} msg: {:?} enclave_id: {:?} gas_left: {}",
+            String::from_utf8_lossy(env),
+            String::from_utf8_lossy(msg),
+            self.enclave.geteid(),
+            self.gas_left()
+        );
+
+        let mut reply_result = MaybeUninit::<HandleResult>::uninit();
+        let mut used_gas = 0_u64;
+        self.gas_breakdown = None;
+
+        let status = unsafe {
+            imports::ecall_reply(
+                self.enclave.geteid(),
+                reply_result.as_mut_ptr(),
+                self.ctx.unsafe_clone(),
+                self.gas_left(),
+                &mut used_gas,
+                self.bytecode.as_ptr(),
+                self.bytecode.len(),
+                env.as_ptr(),
+                env.len(),
+                msg.as_ptr(),
+                msg.len(),
+            )
+        };
+
+        trace!(
+            "reply() returned with gas_used: {} (gas_limit: {})",
+            used_gas,
+            self.gas_limit
+        );
+        self.consume_gas(used_gas);
+
+        match status {
+            sgx_status_t::SGX_SUCCESS => {
+                let reply_result = unsafe { reply_result.assume_init() };
+                let reply_success = handle_result_to_vm_result(reply_result)?;
+                self.gas_breakdown = reply_success.gas_breakdown();
+                Ok(reply_success)
+            }
+            failure_status => Err(EnclaveError::sdk_err(failure_status).into()),
+        }
+    }
+
+    pub fn ibc(
+        &mut self,
+        entry_point: IbcEntryPoint,
+        env: &[u8],
+        msg: &[u8],
+    ) -> VmResult<IbcSuccess> {
+        trace!(
+            "ibc() called with entry_point: {:?} env: {:?} msg: {:?} enclave_id: {:?} gas_left: {}",
+            entry_point,
+            String::from_utf8_lossy(env),
+            String::from_utf8_lossy(msg),
+            self.enclave.geteid(),
+            self.gas_left()
+        );
+
+        let ecall = match entry_point {
+            IbcEntryPoint::ChannelOpen => imports::ecall_ibc_channel_open,
+            IbcEntryPoint::ChannelConnect => imports::ecall_ibc_channel_connect,
+            IbcEntryPoint::ChannelClose => imports::ecall_ibc_channel_close,
+            IbcEntryPoint::PacketAck => imports::ecall_ibc_packet_ack,
+            IbcEntryPoint::PacketTimeout => imports::ecall_ibc_packet_timeout,
+        };
+
+        let mut ibc_result = MaybeUninit::<IbcResult>::uninit();
+        let mut used_gas = 0_u64;
+        self.gas_breakdown = None;
+
+        let status = unsafe {
+            ecall(
+                self.enclave.geteid(),
+                ibc_result.as_mut_ptr(),
+                self.ctx.unsafe_clone(),
+                self.gas_left(),
+                &mut used_gas,
+                self.bytecode.as_ptr(),
+                self.bytecode.len(),
+                env.as_ptr(),
+                env.len(),
+                msg.as_ptr(),
+                msg.len(),
+            )
+        };
+
+        trace!(
+            "ibc() returned with gas_used: {} (gas_limit: {})",
+            used_gas,
+            self.gas_limit
+        );
+        self.consume_gas(used_gas);
+
+        match status {
+            sgx_status_t::SGX_SUCCESS => {
+                let ibc_result = unsafe { ibc_result.assume_init() };
+                let ibc_success = ibc_result_to_vm_result(ibc_result)?;
+                self.gas_breakdown = ibc_success.gas_breakdown();
+                Ok(ibc_success)
+            }
+            failure_status => Err(EnclaveError::sdk_err(failure_status).into()),
+        }
+    }
+
+    pub fn ibc_packet_receive(&mut self, env: &[u8], msg: &[u8]) -> VmResult<IbcReceiveSuccess> {
+        trace!(
+            "ibc_packet_receive() called with env: {:?} msg: {:?} enclave_id: {:?} gas_left: {}",
+            String::from_utf8_lossy(env),
+            String::from_utf8_lossy(msg),
+            self.enclave.geteid(),
+            self.gas_left()
+        );
+
+        let mut receive_result = MaybeUninit::<IbcReceiveResult>::uninit();
+        let mut used_gas = 0_u64;
+        self.gas_breakdown = None;
+
+        let status = unsafe {
+            imports::ecall_ibc_packet_receive(
+                self.enclave.geteid(),
+                receive_result.as_mut_ptr(),
+                self.ctx.unsafe_clone(),
+                self.gas_left(),
+                &mut used_gas,
+                self.bytecode.as_ptr(),
+                self.bytecode.len(),
+                env.as_ptr(),
+                env.len(),
+                msg.as_ptr(),
+                msg.len(),
+            )
+        };
+
+        trace!(
+            "ibc_packet_receive() returned with gas_used: {} (gas_limit: {})",
+            used_gas,
+            self.gas_limit
+        );
+        self.consume_gas(used_gas);
+
+        match status {
+            sgx_status_t::SGX_SUCCESS => {
+                let receive_result = unsafe { receive_result.assume_init() };
+                let receive_success = ibc_receive_result_to_vm_result(receive_result)?;
+                self.gas_breakdown = receive_success.gas_breakdown();
+                Ok(receive_success)
+            }
+            failure_status => Err(EnclaveError::sdk_err(failure_status).into()),
+        }
+    }
+
+    pub fn migrate(&mut self, env: &[u8], msg: &[u8], sig_info: &[u8]) -> VmResult<MigrateSuccess> {
+        trace!(
+            "migrate() called with env: {:?} msg: {:?} enclave_id: {:?} gas_left: {}",
+            String::from_utf8_lossy(env),
+            String::from_utf8_lossy(msg),
+            self.enclave.geteid(),
+            self.gas_left()
+        );
+
+        let mut migrate_result = MaybeUninit::<MigrateResult>::uninit();
+        let mut used_gas = 0_u64;
+
+        let status = unsafe {
+            imports::ecall_migrate(
+                self.enclave.geteid(),
+                migrate_result.as_mut_ptr(),
+                self.ctx.unsafe_clone(),
+                self.gas_left(),
+                &mut used_gas,
+                self.bytecode.as_ptr(),
+                self.bytecode.len(),
+                env.as_ptr(),
+                env.len(),
+                msg.as_ptr(),
+                msg.len(),
+                sig_info.as_ptr(),
+                sig_info.len(),
+            )
+        };
+
+        trace!(
+            "migrate() returned with gas_used: {} (gas_limit: {})",
+            used_gas,
+            self.gas_limit
+        );
+        self.consume_gas(used_gas);
+
+        match status {
+            sgx_status_t::SGX_SUCCESS => {
+                let migrate_result = unsafe { migrate_result.assume_init() };
+                migrate_result_to_vm_result(migrate_result)
+            }
+            failure_status => Err(EnclaveError::sdk_err(failure_status).into()),
+        }
+    }
+
+    pub fn rotate_contract_key(
+        &mut self,
+        env: &[u8],
+        sig_info: &[u8],
+        cursor: &[u8],
+        chunk_size: u32,
+    ) -> VmResult<RotateContractKeySuccess> {
+        trace!(
+            "rotate_contract_key() called with env: {:?} cursor: {:?} chunk_size: {} enclave_id: {:?} gas_left: {}",
+            String::from_utf8_lossy(env),
+            cursor,
+            chunk_size,
+            self.enclave.geteid(),
+            self.gas_left()
+        );
+
+        let mut rotate_result = MaybeUninit::<RotateContractKeyResult>::uninit();
+        let mut used_gas = 0_u64;
+
+        let status = unsafe {
+            imports::ecall_rotate_contract_key(
+                self.enclave.geteid(),
+                rotate_result.as_mut_ptr(),
+                self.ctx.unsafe_clone(),
+                self.gas_left(),
+                &mut used_gas,
+                env.as_ptr(),
+                env.len(),
+                sig_info.as_ptr(),
+                sig_info.len(),
+                cursor.as_ptr(),
+                cursor.len(),
+                chunk_size,
+            )
+        };
+
+        trace!(
+            "rotate_contract_key() returned with gas_used: {} (gas_limit: {})",
+            used_gas,
+            self.gas_limit
+        );
+        self.consume_gas(used_gas);
+
+        match status {
+            sgx_status_t::SGX_SUCCESS => {
+                let rotate_result = unsafe { rotate_result.assume_init() };
+                rotate_contract_key_result_to_vm_result(rotate_result)
+            }
+            failure_status => Err(EnclaveError::sdk_err(failure_status).into()),
+        }
+    }
+
+    pub fn export_contract_state(
+        &mut self,
+        env: &[u8],
+        cursor: &[u8],
+        snapshot_nonce: &[u8],
+        chunk_size: u32,
+    ) -> VmResult<ExportContractStateSuccess> {
+        trace!(
+            "export_contract_state() called with env: {:?} cursor: {:?} chunk_size: {} enclave_id: {:?}",
+            String::from_utf8_lossy(env),
+            cursor,
+            chunk_size,
+            self.enclave.geteid()
+        );
+
+        let mut export_result = MaybeUninit::<ExportContractStateResult>::uninit();
+
+        let status = unsafe {
+            imports::ecall_export_contract_state(
+                self.enclave.geteid(),
+                export_result.as_mut_ptr(),
+                self.ctx.unsafe_clone(),
+                env.as_ptr(),
+                env.len(),
+                cursor.as_ptr(),
+                cursor.len(),
+                snapshot_nonce.as_ptr(),
+                snapshot_nonce.len(),
+                chunk_size,
+            )
+        };
+
+        match status {
+            sgx_status_t::SGX_SUCCESS => {
+                let export_result = unsafe { export_result.assume_init() };
+                export_contract_state_result_to_vm_result(export_result)
+            }
+            failure_status => Err(EnclaveError::sdk_err(failure_status).into()),
+        }
+    }
+
+    /// Returns how many entries were written to the storage of the contract
+    pub fn import_contract_state(
+        &mut self,
+        env: &[u8],
+        sig_info: &[u8],
+        chunk: &[u8],
+    ) -> VmResult<u32> {
+        trace!(
+            "import_contract_state() called with env: {:?} sig_info: {:?} chunk of {} bytes enclave_id: {:?}",
+            String::from_utf8_lossy(env),
+            String::from_utf8_lossy(sig_info),
+            chunk.len(),
+            self.enclave.geteid()
+        );
+
+        let mut import_result = MaybeUninit::<ImportContractStateResult>::uninit();
+
+        let status = unsafe {
+            imports::ecall_import_contract_state(
+                self.enclave.geteid(),
+                import_result.as_mut_ptr(),
+                self.ctx.unsafe_clone(),
+                env.as_ptr(),
+                env.len(),
+                sig_info.as_ptr(),
+                sig_info.len(),
+                chunk.as_ptr(),
+                chunk.len(),
+            )
+        };
+
+        match status {
+            sgx_status_t::SGX_SUCCESS => {
+                let import_result = unsafe { import_result.assume_init() };
+                import_contract_state_result_to_vm_result(import_result)
+            }
+            failure_status => Err(EnclaveError::sdk_err(failure_status).into()),
+        }
+    }
+
+    pub fn query(&mut self, env: &[u8], msg: &[u8]) -> VmResult<QuerySuccess> {
+        trace!(
+            "query() called with env: {:?} msg: {:?} enclave_id: {:?}",
+            String::from_utf8_lossy(env),
             String::from_utf8_lossy(msg),
             self.enclave.geteid()
         );
@@ -212,6 +701,8 @@ where
         let mut query_result = MaybeUninit::<QueryResult>::uninit();
         let mut used_gas = 0_u64;
 
+        // Queries run next to the execution of the block, as long as the enclave has a TCS for them
+        let slot = QuerySlot::acquire();
         let status = unsafe {
             imports::ecall_query(
                 self.enclave.geteid(),
@@ -221,10 +712,13 @@ where
                 &mut used_gas,
                 self.bytecode.as_ptr(),
                 self.bytecode.len(),
+                env.as_ptr(),
+                env.len(),
                 msg.as_ptr(),
                 msg.len(),
             )
         };
+        drop(slot);
 
         trace!(
             "query() returned with gas_used: {} (gas_limit: {})",
@@ -16,7 +16,10 @@ use crate::imports::{ExternalApi, ExternalQuerier, ExternalStorage};
 use crate::memory::{alloc, consume_region, release_buffer, Region};
 use crate::serde::{from_slice, to_vec};
 use crate::traits::Extern;
-use crate::{Env, HandleResult, InitResult, MigrateResult, QueryResponse, QueryResult};
+use crate::{
+    Env, HandleResult, IbcAcknowledgement, IbcChannel, IbcPacket, IbcReceiveResult, InitResult,
+    MigrateResult, QueryResponse, QueryResult, Reply,
+};
 
 #[cfg(feature = "staking")]
 #[no_mangle]
@@ -96,6 +99,24 @@ pub fn do_query<T: DeserializeOwned + JsonSchema>(
     release_buffer(v) as u32
 }
 
+/// do_query_with_env should be wrapped in an external "C" export named `query` that takes
+/// `(env_ptr, msg_ptr)`, containing a contract-specific function as arg.
+/// Use it instead of do_query when the query needs to see the block info.
+pub fn do_query_with_env<T: DeserializeOwned + JsonSchema>(
+    query_fn: &dyn Fn(
+        &Extern<ExternalStorage, ExternalApi, ExternalQuerier>,
+        Env,
+        T,
+    ) -> StdResult<QueryResponse>,
+    env_ptr: u32,
+    msg_ptr: u32,
+) -> u32 {
+    let res: QueryResult =
+        _do_query_with_env(query_fn, env_ptr as *mut Region, msg_ptr as *mut Region);
+    let v = to_vec(&res).unwrap();
+    release_buffer(v) as u32
+}
+
 /// do_migrate should be wrapped in an external "C" export, containing a contract-specific function as arg
 pub fn do_migrate<T, U>(
     migrate_fn: &dyn Fn(
@@ -116,6 +137,136 @@ where
     release_buffer(v) as u32
 }
 
+/// do_reply should be wrapped in an external "C" export named `reply`, containing a
+/// contract-specific function as arg. It receives the results of the contract's submessages.
+pub fn do_reply<U>(
+    reply_fn: &dyn Fn(
+        &mut Extern<ExternalStorage, ExternalApi, ExternalQuerier>,
+        Env,
+        Reply,
+    ) -> HandleResult<U>,
+    env_ptr: u32,
+    msg_ptr: u32,
+) -> u32
+where
+    U: Serialize + Clone + fmt::Debug + PartialEq + JsonSchema,
+{
+    let res: HandleResult<U> = _do_reply(reply_fn, env_ptr as *mut Region, msg_ptr as *mut Region);
+    let v = to_vec(&res).unwrap();
+    release_buffer(v) as u32
+}
+
+/// do_ibc_channel_open should be wrapped in an external "C" export named `ibc_channel_open`.
+/// Returning an error rejects the channel.
+pub fn do_ibc_channel_open<U>(
+    channel_fn: &dyn Fn(
+        &mut Extern<ExternalStorage, ExternalApi, ExternalQuerier>,
+        Env,
+        IbcChannel,
+    ) -> HandleResult<U>,
+    env_ptr: u32,
+    msg_ptr: u32,
+) -> u32
+where
+    U: Serialize + Clone + fmt::Debug + PartialEq + JsonSchema,
+{
+    let res: HandleResult<U> = _do_ibc(channel_fn, env_ptr as *mut Region, msg_ptr as *mut Region);
+    let v = to_vec(&res).unwrap();
+    release_buffer(v) as u32
+}
+
+/// do_ibc_channel_connect should be wrapped in an external "C" export named `ibc_channel_connect`
+pub fn do_ibc_channel_connect<U>(
+    channel_fn: &dyn Fn(
+        &mut Extern<ExternalStorage, ExternalApi, ExternalQuerier>,
+        Env,
+        IbcChannel,
+    ) -> HandleResult<U>,
+    env_ptr: u32,
+    msg_ptr: u32,
+) -> u32
+where
+    U: Serialize + Clone + fmt::Debug + PartialEq + JsonSchema,
+{
+    let res: HandleResult<U> = _do_ibc(channel_fn, env_ptr as *mut Region, msg_ptr as *mut Region);
+    let v = to_vec(&res).unwrap();
+    release_buffer(v) as u32
+}
+
+/// do_ibc_channel_close should be wrapped in an external "C" export named `ibc_channel_close`
+pub fn do_ibc_channel_close<U>(
+    channel_fn: &dyn Fn(
+        &mut Extern<ExternalStorage, ExternalApi, ExternalQuerier>,
+        Env,
+        IbcChannel,
+    ) -> HandleResult<U>,
+    env_ptr: u32,
+    msg_ptr: u32,
+) -> u32
+where
+    U: Serialize + Clone + fmt::Debug + PartialEq + JsonSchema,
+{
+    let res: HandleResult<U> = _do_ibc(channel_fn, env_ptr as *mut Region, msg_ptr as *mut Region);
+    let v = to_vec(&res).unwrap();
+    release_buffer(v) as u32
+}
+
+/// do_ibc_packet_receive should be wrapped in an external "C" export named `ibc_packet_receive`.
+/// Returning an error fails the receive, so no acknowledgement is written.
+pub fn do_ibc_packet_receive<U>(
+    receive_fn: &dyn Fn(
+        &mut Extern<ExternalStorage, ExternalApi, ExternalQuerier>,
+        Env,
+        IbcPacket,
+    ) -> IbcReceiveResult<U>,
+    env_ptr: u32,
+    msg_ptr: u32,
+) -> u32
+where
+    U: Serialize + Clone + fmt::Debug + PartialEq + JsonSchema,
+{
+    let res: IbcReceiveResult<U> =
+        _do_ibc(receive_fn, env_ptr as *mut Region, msg_ptr as *mut Region);
+    let v = to_vec(&res).unwrap();
+    release_buffer(v) as u32
+}
+
+/// do_ibc_packet_ack should be wrapped in an external "C" export named `ibc_packet_ack`
+pub fn do_ibc_packet_ack<U>(
+    ack_fn: &dyn Fn(
+        &mut Extern<ExternalStorage, ExternalApi, ExternalQuerier>,
+        Env,
+        IbcAcknowledgement,
+    ) -> HandleResult<U>,
+    env_ptr: u32,
+    msg_ptr: u32,
+) -> u32
+where
+    U: Serialize + Clone + fmt::Debug + PartialEq + JsonSchema,
+{
+    let res: HandleResult<U> = _do_ibc(ack_fn, env_ptr as *mut Region, msg_ptr as *mut Region);
+    let v = to_vec(&res).unwrap();
+    release_buffer(v) as u32
+}
+
+/// do_ibc_packet_timeout should be wrapped in an external "C" export named `ibc_packet_timeout`
+pub fn do_ibc_packet_timeout<U>(
+    timeout_fn: &dyn Fn(
+        &mut Extern<ExternalStorage, ExternalApi, ExternalQuerier>,
+        Env,
+        IbcPacket,
+    ) -> HandleResult<U>,
+    env_ptr: u32,
+    msg_ptr: u32,
+) -> u32
+where
+    U: Serialize + Clone + fmt::Debug + PartialEq + JsonSchema,
+{
+    let res: HandleResult<U> = _do_ibc(timeout_fn, env_ptr as *mut Region, msg_ptr as *mut Region);
+    let v = to_vec(&res).unwrap();
+    release_buffer(v) as u32
+}
+
 fn _do_init<T, U>(
     init_fn: &dyn Fn(
         &mut Extern<ExternalStorage, ExternalApi, ExternalQuerier>,
@@ -173,6 +324,24 @@ fn _do_query<T: DeserializeOwned + JsonSchema>(
     query_fn(&deps, msg)
 }
 
+fn _do_query_with_env<T: DeserializeOwned + JsonSchema>(
+    query_fn: &dyn Fn(
+        &Extern<ExternalStorage, ExternalApi, ExternalQuerier>,
+        Env,
+        T,
+    ) -> StdResult<QueryResponse>,
+    env_ptr: *mut Region,
+    msg_ptr: *mut Region,
+) -> StdResult<QueryResponse> {
+    let env: Vec<u8> = unsafe { consume_region(env_ptr) };
+    let msg: Vec<u8> = unsafe { consume_region(msg_ptr) };
+
+    let env: Env = from_slice(&env)?;
+    let msg: T = from_slice(&msg)?;
+    let deps = make_dependencies();
+    query_fn(&deps, env, msg)
+}
+
 fn _do_migrate<T, U>(
     migrate_fn: &dyn Fn(
         &mut Extern<ExternalStorage, ExternalApi, ExternalQuerier>,
@@ -194,6 +363,46 @@ where
     migrate_fn(&mut deps, env, msg)
 }
 
+fn _do_reply<U>(
+    reply_fn: &dyn Fn(
+        &mut Extern<ExternalStorage, ExternalApi, ExternalQuerier>,
+        Env,
+        Reply,
+    ) -> HandleResult<U>,
+    env_ptr: *mut Region,
+    msg_ptr: *mut Region,
+) -> HandleResult<U>
+where
+    U: Serialize + Clone + fmt::Debug + PartialEq + JsonSchema,
+{
+    let env: Vec<u8> = unsafe { consume_region(env_ptr) };
+    let msg: Vec<u8> = unsafe { consume_region(msg_ptr) };
+    let env: Env = from_slice(&env)?;
+    let msg: Reply = from_slice(&msg)?;
+    let mut deps = make_dependencies();
+    reply_fn(&mut deps, env, msg)
+}
+
+fn _do_ibc<T, R>(
+    ibc_fn: &dyn Fn(
+        &mut Extern<ExternalStorage, ExternalApi, ExternalQuerier>,
+        Env,
+        T,
+    ) -> StdResult<R>,
+    env_ptr: *mut Region,
+    msg_ptr: *mut Region,
+) -> StdResult<R>
+where
+    T: DeserializeOwned + JsonSchema,
+{
+    let env: Vec<u8> = unsafe { consume_region(env_ptr) };
+    let msg: Vec<u8> = unsafe { consume_region(msg_ptr) };
+    let env: Env = from_slice(&env)?;
+    let msg: T = from_slice(&msg)?;
+    let mut deps = make_dependencies();
+    ibc_fn(&mut deps, env, msg)
+}
+
 /// Makes all bridges to external dependencies (i.e. Wasm imports) that are injected by the VM
 fn make_dependencies() -> Extern<ExternalStorage, ExternalApi, ExternalQuerier> {
     Extern {
@@ -0,0 +1,120 @@
+//! Types for the IBC entry points of a contract. The port of a contract is `wasm.<address>`.
+//!
+//! Packets sent to a secret contract carry an encrypted message, like the messages of a tx, and
+//! the contract gets the decrypted data in `IbcPacket::data`. The acknowledgement the contract
+//! returns is encrypted to the sender of the packet before it's written.
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::encoding::Binary;
+use crate::errors::StdResult;
+use crate::init_handle::{CosmosMsg, LogAttribute};
+use crate::types::Empty;
+
+/// The port and channel of one end of a channel
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct IbcEndpoint {
+    pub port_id: String,
+    pub channel_id: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum IbcOrder {
+    Unordered,
+    Ordered,
+}
+
+/// Passed to `ibc_channel_open`, `ibc_channel_connect` and `ibc_channel_close`.
+/// Nothing in a channel is secret, so a contract should not return secrets from these.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct IbcChannel {
+    /// The end of the channel on this chain
+    pub endpoint: IbcEndpoint,
+    pub counterparty_endpoint: IbcEndpoint,
+    pub order: IbcOrder,
+    pub version: String,
+    pub connection_id: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct IbcTimeoutHeight {
+    pub revision_number: u64,
+    pub timeout_height: u64,
+}
+
+/// Passed to `ibc_packet_receive` and `ibc_packet_timeout`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct IbcPacket {
+    /// The data of the packet. In `ibc_packet_receive` this is already decrypted.
+    pub data: Binary,
+    /// The end of the channel the packet was sent from
+    pub src: IbcEndpoint,
+    /// The end of the channel the packet was sent to
+    pub dest: IbcEndpoint,
+    pub sequence: u64,
+    pub timeout_height: Option<IbcTimeoutHeight>,
+    /// Nanoseconds since epoch begin (Jan. 1, 1970)
+    pub timeout_timestamp: Option<u64>,
+}
+
+/// Passed to `ibc_packet_ack`, for a packet the contract sent
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct IbcAcknowledgement {
+    pub acknowledgement: Binary,
+    pub original_packet: IbcPacket,
+}
+
+/// Returned from `ibc_packet_receive`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct IbcReceiveResponse<T = Empty>
+where
+    T: Clone + fmt::Debug + PartialEq + JsonSchema,
+{
+    /// Written as the acknowledgement of the packet, after it's encrypted to the sender of the
+    /// packet
+    pub acknowledgement: Binary,
+    pub messages: Vec<CosmosMsg<T>>,
+    pub log: Vec<LogAttribute>,
+}
+
+pub type IbcReceiveResult<U = Empty> = StdResult<IbcReceiveResponse<U>>;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::serde::{from_slice, to_vec};
+
+    #[test]
+    fn ibc_packet_serializes_as_expected() {
+        let packet = IbcPacket {
+            data: Binary::from(b"ping"),
+            src: IbcEndpoint {
+                port_id: "transfer".to_string(),
+                channel_id: "channel-1".to_string(),
+            },
+            dest: IbcEndpoint {
+                port_id: "wasm.secret1contract".to_string(),
+                channel_id: "channel-7".to_string(),
+            },
+            sequence: 3,
+            timeout_height: None,
+            timeout_timestamp: Some(1_000),
+        };
+        let bin = to_vec(&packet).unwrap();
+        assert_eq!(
+            bin,
+            br#"{"data":"cGluZw==","src":{"port_id":"transfer","channel_id":"channel-1"},"dest":{"port_id":"wasm.secret1contract","channel_id":"channel-7"},"sequence":3,"timeout_height":null,"timeout_timestamp":1000}"#.to_vec()
+        );
+        let back: IbcPacket = from_slice(&bin).unwrap();
+        assert_eq!(back, packet);
+    }
+
+    #[test]
+    fn ibc_order_is_snake_case() {
+        assert_eq!(to_vec(&IbcOrder::Unordered).unwrap(), br#""unordered""#);
+        assert_eq!(to_vec(&IbcOrder::Ordered).unwrap(), br#""ordered""#);
+    }
+}
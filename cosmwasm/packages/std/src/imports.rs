@@ -19,9 +19,20 @@ const HUMAN_ADDRESS_BUFFER_LENGTH: usize = 90;
 // https://github.com/confio/cosmwasm/blob/0.7/lib/vm/src/instance.rs#L43
 extern "C" {
     fn db_read(key: u32) -> u32;
+    #[cfg(feature = "db-read-multi")]
+    fn db_read_multi(keys: u32) -> u32;
     fn db_write(key: u32, value: u32);
     fn db_remove(key: u32);
 
+    #[cfg(feature = "db-namespaces")]
+    fn db_read_ns(namespace: u32, key: u32) -> u32;
+    #[cfg(feature = "db-namespaces")]
+    fn db_write_ns(namespace: u32, key: u32, value: u32);
+    #[cfg(feature = "db-namespaces")]
+    fn db_remove_ns(namespace: u32, key: u32);
+    #[cfg(feature = "db-namespaces")]
+    fn db_move_to_ns(namespace: u32, key: u32) -> u32;
+
     // scan creates an iterator, which can be read by consecutive next() calls
     #[cfg(feature = "iterator")]
     fn db_scan(start_ptr: u32, end_ptr: u32, order: i32) -> u32;
@@ -31,6 +42,38 @@ extern "C" {
     fn canonicalize_address(source: u32, destination: u32) -> u32;
     fn humanize_address(source: u32, destination: u32) -> u32;
 
+    #[cfg(feature = "addr-imports")]
+    fn addr_validate(source: u32) -> u32;
+    #[cfg(feature = "addr-imports")]
+    fn addr_canonicalize(source: u32, destination: u32) -> u32;
+    #[cfg(feature = "addr-imports")]
+    fn addr_humanize(source: u32, destination: u32) -> u32;
+
+    #[cfg(feature = "secp256k1-signing")]
+    fn secp256k1_generate_key(path: u32) -> u32;
+    #[cfg(feature = "secp256k1-signing")]
+    fn secp256k1_sign(key_id: u32, message_hash: u32, signature: u32) -> u32;
+    #[cfg(feature = "secp256k1-signing")]
+    fn secp256k1_public_key(key_id: u32, public_key: u32) -> u32;
+
+    #[cfg(feature = "gas-left")]
+    fn gas_left() -> u64;
+    #[cfg(feature = "gas-left")]
+    fn gas_limit() -> u64;
+
+    #[cfg(feature = "foreign-headers")]
+    fn verify_foreign_header(chain_id: u32, header: u32) -> u32;
+
+    #[cfg(feature = "contract-code-hash")]
+    fn assert_contract_code_hash(contract_addr: u32, code_hash: u32) -> u32;
+
+    #[cfg(feature = "storage-usage")]
+    fn db_storage_usage() -> u64;
+    #[cfg(feature = "storage-usage")]
+    fn db_try_write(key: u32, value: u32) -> u32;
+    #[cfg(all(feature = "storage-usage", feature = "db-namespaces"))]
+    fn db_try_write_ns(namespace: u32, key: u32, value: u32) -> u32;
+
     /// Executes a query on the chain (import). Not to be confused with the
     /// query export, which queries the state of the contract.
     fn query_chain(request: u32) -> u32;
@@ -62,6 +105,22 @@ impl ReadonlyStorage for ExternalStorage {
         Some(data)
     }
 
+    #[cfg(feature = "db-read-multi")]
+    fn get_multi(&self, keys: &[&[u8]]) -> Vec<Option<Vec<u8>>> {
+        // Each key is followed by its length as a big endian u32
+        let mut packed_keys = Vec::new();
+        for key in keys {
+            packed_keys.extend_from_slice(key);
+            packed_keys.extend_from_slice(&(key.len() as u32).to_be_bytes());
+        }
+        let packed_keys = build_region(&packed_keys);
+        let packed_keys_ptr = &*packed_keys as *const Region as u32;
+
+        let read = unsafe { db_read_multi(packed_keys_ptr) };
+        let packed_values = unsafe { consume_region(read as *mut Region) };
+        unpack_values(&packed_values)
+    }
+
     #[cfg(feature = "iterator")]
     fn range(
         &self,
@@ -107,6 +166,301 @@ impl Storage for ExternalStorage {
     }
 }
 
+#[cfg(feature = "storage-usage")]
+impl ExternalStorage {
+    /// Like `Storage::set`, but a write that would bring the contract storage past the quota of
+    /// the chain returns an error instead of failing the whole execution
+    pub fn try_set(&mut self, key: &[u8], value: &[u8]) -> StdResult<()> {
+        let key = build_region(key);
+        let key_ptr = &*key as *const Region as u32;
+        let mut value = build_region(value);
+        let value_ptr = &mut *value as *mut Region as u32;
+
+        let result = unsafe { db_try_write(key_ptr, value_ptr) };
+        if result != 0 {
+            let error = unsafe { consume_string_region_written_by_vm(result as *mut Region) };
+            return Err(StdError::generic_err(format!(
+                "db_try_write errored: {}",
+                error
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// A namespace of the contract storage. The enclave encrypts the values of every namespace with a
+/// key derived from its name, so a key read from one namespace never returns a value written to
+/// another, or to the `ExternalStorage`, even if the contract mixes up its keys.
+///
+/// Namespaced values aren't listed by `ReadonlyStorage::range`.
+#[cfg(feature = "db-namespaces")]
+pub struct ExternalNamespacedStorage {
+    namespace: Vec<u8>,
+}
+
+#[cfg(feature = "db-namespaces")]
+impl ExternalNamespacedStorage {
+    /// The namespace must be 1 to 64 bytes long
+    pub fn new(namespace: &[u8]) -> ExternalNamespacedStorage {
+        ExternalNamespacedStorage {
+            namespace: namespace.to_vec(),
+        }
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        let namespace = build_region(&self.namespace);
+        let namespace_ptr = &*namespace as *const Region as u32;
+        let key = build_region(key);
+        let key_ptr = &*key as *const Region as u32;
+
+        let read = unsafe { db_read_ns(namespace_ptr, key_ptr) };
+        if read == 0 {
+            return None;
+        }
+
+        let data = unsafe { consume_region(read as *mut Region) };
+        Some(data)
+    }
+
+    pub fn set(&mut self, key: &[u8], value: &[u8]) {
+        let namespace = build_region(&self.namespace);
+        let namespace_ptr = &*namespace as *const Region as u32;
+        let key = build_region(key);
+        let key_ptr = &*key as *const Region as u32;
+        let mut value = build_region(value);
+        let value_ptr = &mut *value as *mut Region as u32;
+        unsafe { db_write_ns(namespace_ptr, key_ptr, value_ptr) };
+    }
+
+    pub fn remove(&mut self, key: &[u8]) {
+        let namespace = build_region(&self.namespace);
+        let namespace_ptr = &*namespace as *const Region as u32;
+        let key = build_region(key);
+        let key_ptr = &*key as *const Region as u32;
+        unsafe { db_remove_ns(namespace_ptr, key_ptr) };
+    }
+
+    /// Move the value of `key` from the `ExternalStorage` into this namespace, for contracts that
+    /// start keeping existing state in namespaces. Returns false if there was no value to move.
+    pub fn move_from_storage(&mut self, key: &[u8]) -> bool {
+        let namespace = build_region(&self.namespace);
+        let namespace_ptr = &*namespace as *const Region as u32;
+        let key = build_region(key);
+        let key_ptr = &*key as *const Region as u32;
+        unsafe { db_move_to_ns(namespace_ptr, key_ptr) == 1 }
+    }
+
+    /// Like `set`, but a write that would bring the contract storage past the quota of the chain
+    /// returns an error instead of failing the whole execution
+    #[cfg(feature = "storage-usage")]
+    pub fn try_set(&mut self, key: &[u8], value: &[u8]) -> StdResult<()> {
+        let namespace = build_region(&self.namespace);
+        let namespace_ptr = &*namespace as *const Region as u32;
+        let key = build_region(key);
+        let key_ptr = &*key as *const Region as u32;
+        let mut value = build_region(value);
+        let value_ptr = &mut *value as *mut Region as u32;
+
+        let result = unsafe { db_try_write_ns(namespace_ptr, key_ptr, value_ptr) };
+        if result != 0 {
+            let error = unsafe { consume_string_region_written_by_vm(result as *mut Region) };
+            return Err(StdError::generic_err(format!(
+                "db_try_write_ns errored: {}",
+                error
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// The length of the uncompressed public key written by `secp256k1_public_key`
+#[cfg(feature = "secp256k1-signing")]
+const SECP256K1_PUBLIC_KEY_LENGTH: usize = 65;
+/// The length of the `r || s` signature and recovery id written by `secp256k1_sign`
+#[cfg(feature = "secp256k1-signing")]
+const SECP256K1_SIGNATURE_WITH_RECOVERY_ID_LENGTH: usize = 65;
+
+/// A secp256k1 key that the enclave derives for this contract from a path, and never reveals.
+/// The same path gives the same key on every node and in every execution, so a contract can
+/// register the public key elsewhere once and sign with the key from then on. No other contract
+/// can derive it.
+///
+/// The key is derived from the address of the contract, so it stays the same when the contract is
+/// migrated, or its contract key or the consensus seed is rotated. Queries without an env can't
+/// derive keys.
+#[cfg(feature = "secp256k1-signing")]
+pub struct ContractSigningKey {
+    id: u32,
+}
+
+#[cfg(feature = "secp256k1-signing")]
+impl ContractSigningKey {
+    /// The path may be up to 64 bytes long
+    pub fn derive(path: &[u8]) -> StdResult<ContractSigningKey> {
+        let path = build_region(path);
+        let path_ptr = &*path as *const Region as u32;
+
+        let id = unsafe { secp256k1_generate_key(path_ptr) };
+        if id == 0 {
+            return Err(StdError::generic_err(
+                "secp256k1_generate_key errored: the path must be up to 64 bytes long, and queries must have an env",
+            ));
+        }
+
+        Ok(ContractSigningKey { id })
+    }
+
+    /// The 65 bytes uncompressed public key
+    pub fn public_key(&self) -> StdResult<Vec<u8>> {
+        let public_key = alloc(SECP256K1_PUBLIC_KEY_LENGTH);
+
+        let result = unsafe { secp256k1_public_key(self.id, public_key as u32) };
+        let out = unsafe { consume_region(public_key) };
+        if result != 0 {
+            return Err(StdError::generic_err(format!(
+                "secp256k1_public_key errored: {}",
+                result
+            )));
+        }
+
+        Ok(out)
+    }
+
+    /// Sign a 32 bytes message hash. Returns the 64 bytes `r || s` signature and its recovery
+    /// id. Signing the same hash again gives the same signature.
+    pub fn sign(&self, message_hash: &[u8]) -> StdResult<(Vec<u8>, u8)> {
+        let message_hash = build_region(message_hash);
+        let message_hash_ptr = &*message_hash as *const Region as u32;
+        let signature = alloc(SECP256K1_SIGNATURE_WITH_RECOVERY_ID_LENGTH);
+
+        let result = unsafe { secp256k1_sign(self.id, message_hash_ptr, signature as u32) };
+        let mut out = unsafe { consume_region(signature) };
+        if result != 0 {
+            return Err(StdError::generic_err(format!(
+                "secp256k1_sign errored: {}",
+                result
+            )));
+        }
+
+        let recovery_id = out.pop().unwrap_or_default();
+        Ok((out, recovery_id))
+    }
+}
+
+/// The gas of the current call. Both values are the same on every node, so contracts may branch
+/// on them, e.g. to process a batch until little gas is left and return where they stopped.
+#[cfg(feature = "gas-left")]
+pub struct ExternalGas {}
+
+#[cfg(feature = "gas-left")]
+impl ExternalGas {
+    /// The gas left, after paying for this call
+    pub fn left() -> u64 {
+        unsafe { gas_left() }
+    }
+
+    /// The gas limit of the call
+    pub fn limit() -> u64 {
+        unsafe { gas_limit() }
+    }
+}
+
+/// Headers of other chains, verified by the enclave against the headers it tracks for them
+#[cfg(feature = "foreign-headers")]
+pub struct ExternalForeignHeaders {}
+
+#[cfg(feature = "foreign-headers")]
+#[derive(serde::Deserialize)]
+struct ForeignHeaderVerification {
+    verified: bool,
+    app_hash: Option<Binary>,
+}
+
+#[cfg(feature = "foreign-headers")]
+impl ExternalForeignHeaders {
+    /// Returns the app hash of `header` if the enclave verified it as a header of `chain_id`, and
+    /// `None` if it didn't. `header` is the JSON encoding of the header with the commit and
+    /// validators that signed it, and for headers that skip blocks, the validators the last
+    /// header the enclave verified chose.
+    pub fn verify(chain_id: &str, header: &[u8]) -> StdResult<Option<Binary>> {
+        let chain_id = build_region(chain_id.as_bytes());
+        let chain_id_ptr = &*chain_id as *const Region as u32;
+        let header = build_region(header);
+        let header_ptr = &*header as *const Region as u32;
+
+        let result = unsafe { verify_foreign_header(chain_id_ptr, header_ptr) };
+        if result == 0 {
+            return Err(StdError::generic_err(
+                "verify_foreign_header returned no result",
+            ));
+        }
+        let verification = unsafe { consume_region(result as *mut Region) };
+        let verification: ForeignHeaderVerification = from_slice(&verification)?;
+
+        if !verification.verified {
+            return Ok(None);
+        }
+        Ok(verification.app_hash)
+    }
+}
+
+/// Other contracts on the chain, as the enclave knows them
+#[cfg(feature = "contract-code-hash")]
+pub struct ExternalContracts {}
+
+#[cfg(feature = "contract-code-hash")]
+impl ExternalContracts {
+    /// Returns true if the contract at `contract_addr` runs the code with the hex encoded
+    /// `code_hash`, as authenticated by the enclave. Returns false if it runs other code, or if
+    /// there's no contract at the address.
+    pub fn assert_code_hash(contract_addr: &HumanAddr, code_hash: &str) -> bool {
+        let contract_addr = build_region(contract_addr.as_str().as_bytes());
+        let contract_addr_ptr = &*contract_addr as *const Region as u32;
+        let code_hash = build_region(code_hash.as_bytes());
+        let code_hash_ptr = &*code_hash as *const Region as u32;
+
+        unsafe { assert_contract_code_hash(contract_addr_ptr, code_hash_ptr) == 1 }
+    }
+}
+
+/// How much the contract stores, as the enclave accounts for it. Writes that would bring it past
+/// the storage quota of the chain fail, so contracts may check it before they grow their state,
+/// or write with `ExternalStorage::try_set` to handle the error.
+///
+/// Storage is only accounted for while the chain sets a quota.
+#[cfg(feature = "storage-usage")]
+pub struct ExternalStorageUsage {}
+
+#[cfg(feature = "storage-usage")]
+impl ExternalStorageUsage {
+    /// The bytes of the keys and values the contract wrote while the chain set a quota, including
+    /// the changes it made so far in this call
+    pub fn bytes() -> u64 {
+        unsafe { db_storage_usage() }
+    }
+}
+
+/// Unpacks the values returned by `db_read_multi`. Like the keys, each value is followed by its
+/// length as a big endian u32, and starts with 1 if the key exists and 0 if it doesn't.
+#[cfg(feature = "db-read-multi")]
+fn unpack_values(mut packed: &[u8]) -> Vec<Option<Vec<u8>>> {
+    let mut values = Vec::new();
+    while packed.len() >= 4 {
+        let (rest, len) = packed.split_at(packed.len() - 4);
+        let len = u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize;
+        let (rest, value) = rest.split_at(rest.len() - len);
+        values.push(match value.split_first() {
+            Some((1, value)) => Some(value.to_vec()),
+            _ => None,
+        });
+        packed = rest;
+    }
+    values.reverse();
+    values
+}
+
 #[cfg(feature = "iterator")]
 /// ExternalIterator makes a call out to next.
 /// We use the pointer to differentiate between multiple open iterators.
@@ -187,6 +541,61 @@ impl Api for ExternalApi {
         let address = unsafe { consume_string_region_written_by_vm(human) };
         Ok(address.into())
     }
+
+    #[cfg(feature = "addr-imports")]
+    fn addr_validate(&self, human: &str) -> StdResult<HumanAddr> {
+        let source = build_region(human.as_bytes());
+        let source_ptr = &*source as *const Region as u32;
+
+        let result = unsafe { addr_validate(source_ptr) };
+        if result != 0 {
+            let error = unsafe { consume_string_region_written_by_vm(result as *mut Region) };
+            return Err(StdError::generic_err(format!(
+                "addr_validate errored: {}",
+                error
+            )));
+        }
+
+        Ok(human.into())
+    }
+
+    #[cfg(feature = "addr-imports")]
+    fn addr_canonicalize(&self, human: &str) -> StdResult<CanonicalAddr> {
+        let send = build_region(human.as_bytes());
+        let send_ptr = &*send as *const Region as u32;
+        let canon = alloc(CANONICAL_ADDRESS_BUFFER_LENGTH);
+
+        let result = unsafe { addr_canonicalize(send_ptr, canon as u32) };
+        if result != 0 {
+            let error = unsafe { consume_string_region_written_by_vm(result as *mut Region) };
+            return Err(StdError::generic_err(format!(
+                "addr_canonicalize errored: {}",
+                error
+            )));
+        }
+
+        let out = unsafe { consume_region(canon) };
+        Ok(CanonicalAddr(Binary(out)))
+    }
+
+    #[cfg(feature = "addr-imports")]
+    fn addr_humanize(&self, canonical: &CanonicalAddr) -> StdResult<HumanAddr> {
+        let send = build_region(canonical.as_slice());
+        let send_ptr = &*send as *const Region as u32;
+        let human = alloc(HUMAN_ADDRESS_BUFFER_LENGTH);
+
+        let result = unsafe { addr_humanize(send_ptr, human as u32) };
+        if result != 0 {
+            let error = unsafe { consume_string_region_written_by_vm(result as *mut Region) };
+            return Err(StdError::generic_err(format!(
+                "addr_humanize errored: {}",
+                error
+            )));
+        }
+
+        let address = unsafe { consume_string_region_written_by_vm(human) };
+        Ok(address.into())
+    }
 }
 
 /// Takes a pointer to a Region and reads the data into a String.
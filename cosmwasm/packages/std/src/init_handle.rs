@@ -141,6 +141,81 @@ impl<T: Clone + fmt::Debug + PartialEq + JsonSchema> From<WasmMsg> for CosmosMsg
 pub struct LogAttribute {
     pub key: String,
     pub value: String,
+    /// Plaintext attributes are emitted as is, instead of being encrypted to the sender of the tx.
+    /// Use this only for data that is public anyway, like the name of the action.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub plaintext: bool,
+    /// The public key of the account the attribute is encrypted to, instead of the sender of the
+    /// tx. It's the public key the account encrypts its txs with. Ignored for plaintext attributes.
+    /// A recipient that isn't a valid public key fails the call, so no attribute is silently lost.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recipient: Option<Binary>,
+}
+
+fn is_false(b: &bool) -> bool {
+    !*b
+}
+
+/// Use this to control when the contract gets a `reply` for a submessage.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplyOn {
+    /// Always get a reply with the result of the submessage
+    Always,
+    /// Only get a reply if the submessage failed. If it succeeded, the tx goes on as if it
+    /// was a regular message
+    Error,
+    /// Only get a reply if the submessage succeeded. If it failed, the whole tx fails
+    Success,
+    /// Never get a reply. If the submessage failed, the whole tx fails
+    Never,
+}
+
+impl Default for ReplyOn {
+    fn default() -> Self {
+        ReplyOn::Always
+    }
+}
+
+/// A message that is dispatched after the contract returns, and whose result is passed
+/// back to the contract's `reply` entry point, as selected by `reply_on`.
+/// If the submessage fails and a reply is requested, its state changes are reverted but the
+/// tx keeps going.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SubMsg<T = Empty>
+where
+    T: Clone + fmt::Debug + PartialEq + JsonSchema,
+{
+    /// Passed back to the contract in the `Reply`, to tell the submessages apart
+    pub id: u64,
+    pub msg: CosmosMsg<T>,
+    pub gas_limit: Option<u64>,
+    #[serde(default)]
+    pub reply_on: ReplyOn,
+}
+
+/// The result of a submessage, passed to the contract's `reply` entry point
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Reply {
+    /// The id of the submessage, as set by the contract
+    pub id: u64,
+    pub result: SubMsgResult,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SubMsgResult {
+    Ok(SubMsgExecutionResponse),
+    /// The error returned by the submessage. Errors of other contracts are encrypted to the
+    /// sender of the tx, so the contract can't read them.
+    Error(String),
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq, JsonSchema)]
+pub struct SubMsgExecutionResponse {
+    /// The data returned by the submessage. When instantiating a contract, this is the
+    /// address of the new contract.
+    pub data: Option<Binary>,
 }
 
 /// A shorthand to produce a log attribute
@@ -148,6 +223,29 @@ pub fn log<K: ToString, V: ToString>(key: K, value: V) -> LogAttribute {
     LogAttribute {
         key: key.to_string(),
         value: value.to_string(),
+        plaintext: false,
+        recipient: None,
+    }
+}
+
+/// A shorthand to produce a log attribute that only `recipient` can read, see
+/// `LogAttribute::recipient`
+pub fn log_to<K: ToString, V: ToString>(recipient: Binary, key: K, value: V) -> LogAttribute {
+    LogAttribute {
+        key: key.to_string(),
+        value: value.to_string(),
+        plaintext: false,
+        recipient: Some(recipient),
+    }
+}
+
+/// A shorthand to produce a log attribute that everyone can read, see `LogAttribute::plaintext`
+pub fn plaintext_log<K: ToString, V: ToString>(key: K, value: V) -> LogAttribute {
+    LogAttribute {
+        key: key.to_string(),
+        value: value.to_string(),
+        plaintext: true,
+        recipient: None,
     }
 }
 
@@ -156,6 +254,8 @@ pub struct InitResponse<T = Empty>
 where
     T: Clone + fmt::Debug + PartialEq + JsonSchema,
 {
+    #[serde(default)]
+    pub submessages: Vec<SubMsg<T>>,
     pub messages: Vec<CosmosMsg<T>>,
     pub log: Vec<LogAttribute>,
 }
@@ -168,6 +268,7 @@ where
 {
     fn default() -> Self {
         InitResponse {
+            submessages: vec![],
             messages: vec![],
             log: vec![],
         }
@@ -187,6 +288,7 @@ where
             ))
         } else {
             Ok(InitResponse {
+                submessages: ctx.submessages,
                 messages: ctx.messages,
                 log: ctx.log,
             })
@@ -199,6 +301,8 @@ pub struct HandleResponse<T = Empty>
 where
     T: Clone + fmt::Debug + PartialEq + JsonSchema,
 {
+    #[serde(default)]
+    pub submessages: Vec<SubMsg<T>>,
     pub messages: Vec<CosmosMsg<T>>,
     pub log: Vec<LogAttribute>,
     pub data: Option<Binary>,
@@ -212,6 +316,7 @@ where
 {
     fn default() -> Self {
         HandleResponse {
+            submessages: vec![],
             messages: vec![],
             log: vec![],
             data: None,
@@ -225,6 +330,7 @@ where
 {
     fn from(ctx: Context<T>) -> Self {
         HandleResponse {
+            submessages: ctx.submessages,
             messages: ctx.messages,
             log: ctx.log,
             data: ctx.data,
@@ -275,6 +381,7 @@ pub struct Context<T = Empty>
 where
     T: Clone + fmt::Debug + PartialEq + JsonSchema,
 {
+    submessages: Vec<SubMsg<T>>,
     messages: Vec<CosmosMsg<T>>,
     log: Vec<LogAttribute>,
     data: Option<Binary>,
@@ -286,6 +393,7 @@ where
 {
     fn default() -> Self {
         Context {
+            submessages: vec![],
             messages: vec![],
             log: vec![],
             data: None,
@@ -309,6 +417,15 @@ where
         self.messages.push(msg.into());
     }
 
+    pub fn add_submessage<U: Into<CosmosMsg<T>>>(&mut self, id: u64, msg: U, reply_on: ReplyOn) {
+        self.submessages.push(SubMsg {
+            id,
+            msg: msg.into(),
+            gas_limit: None,
+            reply_on,
+        });
+    }
+
     pub fn set_data<U: Into<Binary>>(&mut self, data: U) {
         self.data = Some(data.into());
     }
@@ -326,6 +443,8 @@ mod test {
         let expeceted = LogAttribute {
             key: "foo".to_string(),
             value: "42".to_string(),
+            plaintext: false,
+            recipient: None,
         };
 
         assert_eq!(log("foo", "42"), expeceted);
@@ -336,6 +455,32 @@ mod test {
         assert_eq!(log("foo", 42), expeceted);
     }
 
+    #[test]
+    fn plaintext_log_is_serialized_only_when_set() {
+        assert_eq!(
+            to_vec(&log("foo", 42)).unwrap(),
+            br#"{"key":"foo","value":"42"}"#
+        );
+        let plaintext = plaintext_log("foo", 42);
+        assert_eq!(
+            to_vec(&plaintext).unwrap(),
+            br#"{"key":"foo","value":"42","plaintext":true}"#
+        );
+        let back: LogAttribute = from_slice(br#"{"key":"foo","value":"42"}"#).unwrap();
+        assert_eq!(back, log("foo", 42));
+    }
+
+    #[test]
+    fn log_to_serializes_the_recipient() {
+        let attribute = log_to(Binary(vec![1, 2, 3]), "foo", 42);
+        assert_eq!(
+            to_vec(&attribute).unwrap(),
+            br#"{"key":"foo","value":"42","recipient":"AQID"}"#
+        );
+        let back: LogAttribute = from_slice(&to_vec(&attribute).unwrap()).unwrap();
+        assert_eq!(back, attribute);
+    }
+
     #[test]
     fn can_deser_error_result() {
         let fail = InitResult::Err(StdError::Unauthorized { backtrace: None });
@@ -348,6 +493,7 @@ mod test {
     #[test]
     fn can_deser_ok_result() {
         let send = InitResult::Ok(InitResponse {
+            submessages: vec![],
             messages: vec![BankMsg::Send {
                 from_address: HumanAddr("me".to_string()),
                 to_address: HumanAddr("you".to_string()),
@@ -357,6 +503,8 @@ mod test {
             log: vec![LogAttribute {
                 key: "action".to_string(),
                 value: "release".to_string(),
+                plaintext: false,
+                recipient: None,
             }],
         });
         let bin = to_vec(&send).expect("encode contract result");
@@ -365,6 +513,45 @@ mod test {
         assert_eq!(send, back);
     }
 
+    #[test]
+    fn can_deser_response_without_submessages() {
+        let back: HandleResponse = from_slice(br#"{"messages":[],"log":[],"data":null}"#).unwrap();
+        assert_eq!(back, HandleResponse::default());
+    }
+
+    #[test]
+    fn submsg_reply_on_defaults_to_always() {
+        let back: SubMsg = from_slice(
+            br#"{"id":7,"msg":{"bank":{"send":{"from_address":"me","to_address":"you","amount":[]}}},"gas_limit":null}"#,
+        )
+        .unwrap();
+        assert_eq!(back.id, 7);
+        assert_eq!(back.reply_on, ReplyOn::Always);
+    }
+
+    #[test]
+    fn reply_serializes_as_expected() {
+        let reply = Reply {
+            id: 1,
+            result: SubMsgResult::Ok(SubMsgExecutionResponse {
+                data: Some(Binary::from(b"addr")),
+            }),
+        };
+        assert_eq!(
+            to_vec(&reply).unwrap(),
+            br#"{"id":1,"result":{"ok":{"data":"YWRkcg=="}}}"#.to_vec()
+        );
+
+        let reply = Reply {
+            id: 2,
+            result: SubMsgResult::Error("failed".to_string()),
+        };
+        assert_eq!(
+            to_vec(&reply).unwrap(),
+            br#"{"id":2,"result":{"error":"failed"}}"#.to_vec()
+        );
+    }
+
     #[test]
     fn msg_from_works() {
         let from_address = HumanAddr("me".to_string());
@@ -6,6 +6,7 @@ mod debug_print;
 mod encoding;
 mod entry_points;
 mod errors;
+mod ibc;
 mod init_handle;
 #[cfg(feature = "iterator")]
 mod iterator;
@@ -21,9 +22,14 @@ pub use crate::coins::{coin, coins, has_coins, Coin};
 pub use crate::debug_print::debug_print;
 pub use crate::encoding::Binary;
 pub use crate::errors::{StdError, StdResult, SystemError, SystemResult};
+pub use crate::ibc::{
+    IbcAcknowledgement, IbcChannel, IbcEndpoint, IbcOrder, IbcPacket, IbcReceiveResponse,
+    IbcReceiveResult, IbcTimeoutHeight,
+};
 pub use crate::init_handle::{
-    log, BankMsg, Context, CosmosMsg, GovMsg, HandleResponse, HandleResult, InitResponse,
-    InitResult, LogAttribute, MigrateResponse, MigrateResult, StakingMsg, VoteOption, WasmMsg,
+    log, log_to, plaintext_log, BankMsg, Context, CosmosMsg, GovMsg, HandleResponse, HandleResult,
+    InitResponse, InitResult, LogAttribute, MigrateResponse, MigrateResult, Reply, ReplyOn,
+    StakingMsg, SubMsg, SubMsgExecutionResponse, SubMsgResult, VoteOption, WasmMsg,
 };
 #[cfg(feature = "iterator")]
 pub use crate::iterator::{Order, KV};
@@ -37,7 +43,7 @@ pub use crate::query::{
 pub use crate::serde::{from_binary, from_slice, to_binary, to_vec};
 pub use crate::storage::MemoryStorage;
 pub use crate::traits::{Api, Extern, Querier, QuerierResult, ReadonlyStorage, Storage};
-pub use crate::types::{BlockInfo, ContractInfo, Empty, Env, MessageInfo};
+pub use crate::types::{BlockInfo, ContractInfo, Empty, Env, MessageInfo, TransactionInfo};
 
 // Exposed in wasm build only
 
@@ -49,7 +55,23 @@ mod imports;
 mod memory; // Used by exports and imports only. This assumes pointers are 32 bit long, which makes it untestable on dev machines.
 
 #[cfg(target_arch = "wasm32")]
-pub use crate::exports::{do_handle, do_init, do_migrate, do_query};
+pub use crate::exports::{
+    do_handle, do_ibc_channel_close, do_ibc_channel_connect, do_ibc_channel_open,
+    do_ibc_packet_ack, do_ibc_packet_receive, do_ibc_packet_timeout, do_init, do_migrate, do_query,
+    do_query_with_env, do_reply,
+};
+#[cfg(all(target_arch = "wasm32", feature = "secp256k1-signing"))]
+pub use crate::imports::ContractSigningKey;
+#[cfg(all(target_arch = "wasm32", feature = "contract-code-hash"))]
+pub use crate::imports::ExternalContracts;
+#[cfg(all(target_arch = "wasm32", feature = "foreign-headers"))]
+pub use crate::imports::ExternalForeignHeaders;
+#[cfg(all(target_arch = "wasm32", feature = "gas-left"))]
+pub use crate::imports::ExternalGas;
+#[cfg(all(target_arch = "wasm32", feature = "db-namespaces"))]
+pub use crate::imports::ExternalNamespacedStorage;
+#[cfg(all(target_arch = "wasm32", feature = "storage-usage"))]
+pub use crate::imports::ExternalStorageUsage;
 #[cfg(target_arch = "wasm32")]
 pub use crate::imports::{ExternalApi, ExternalQuerier, ExternalStorage};
 
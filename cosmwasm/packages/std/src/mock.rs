@@ -13,7 +13,7 @@ use crate::query::{
 use crate::serde::{from_slice, to_binary};
 use crate::storage::MemoryStorage;
 use crate::traits::{Api, Extern, Querier, QuerierResult};
-use crate::types::{BlockInfo, ContractInfo, Empty, Env, MessageInfo};
+use crate::types::{BlockInfo, ContractInfo, Empty, Env, MessageInfo, TransactionInfo};
 use crate::{RewardsResponse, UnbondingDelegationsResponse};
 
 pub const MOCK_CONTRACT_ADDR: &str = "cosmos2contract";
@@ -120,6 +120,8 @@ pub fn mock_env<U: Into<HumanAddr>>(sender: U, sent: &[Coin]) -> Env {
             height: 12_345,
             time: 1_571_797_419,
             chain_id: "cosmos-testnet-14002".to_string(),
+            hash: None,
+            proposer_address: None,
         },
         message: MessageInfo {
             sender: sender.into(),
@@ -130,6 +132,9 @@ pub fn mock_env<U: Into<HumanAddr>>(sender: U, sent: &[Coin]) -> Env {
         },
         contract_key: Some("".to_string()),
         contract_code_hash: "".to_string(),
+        random: None,
+        transaction: TransactionInfo::default(),
+        query_sender: None,
     }
 }
 
@@ -425,6 +430,22 @@ mod test {
         assert_eq!(human, recovered);
     }
 
+    #[test]
+    fn addr_validate_round_trips() {
+        let api = MockApi::new(20);
+        assert_eq!(
+            api.addr_validate("shorty").unwrap(),
+            HumanAddr::from("shorty")
+        );
+
+        // the mock drops the zero bytes, so this doesn't come back the same
+        let err = api.addr_validate("sho\0rty").unwrap_err();
+        assert!(err.to_string().contains("not normalized"), "{}", err);
+
+        let err = api.addr_validate("1").unwrap_err();
+        assert!(err.to_string().contains("address too short"), "{}", err);
+    }
+
     #[test]
     #[should_panic(expected = "length not correct")]
     fn human_address_input_length() {
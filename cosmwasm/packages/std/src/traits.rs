@@ -47,6 +47,12 @@ pub trait ReadonlyStorage {
     /// is not great yet and might not be possible in all backends. But we're trying to get there.
     fn get(&self, key: &[u8]) -> Option<Vec<u8>>;
 
+    /// Returns the values of all the `keys`, in the same order, like calling `get` for each of them.
+    /// With the `db-read-multi` feature, the contract storage reads them all in a single call.
+    fn get_multi(&self, keys: &[&[u8]]) -> Vec<Option<Vec<u8>>> {
+        keys.iter().map(|key| self.get(key)).collect()
+    }
+
     #[cfg(feature = "iterator")]
     /// Allows iteration over a set of key/value pairs, either forwards or backwards.
     ///
@@ -83,6 +89,29 @@ pub trait Storage: ReadonlyStorage {
 pub trait Api: Copy + Clone + Send {
     fn canonical_address(&self, human: &HumanAddr) -> StdResult<CanonicalAddr>;
     fn human_address(&self, canonical: &CanonicalAddr) -> StdResult<HumanAddr>;
+
+    /// Checks that `human` is a valid address, written in its normalized form.
+    /// By default this round trips the address through `canonical_address` and `human_address`.
+    fn addr_validate(&self, human: &str) -> StdResult<HumanAddr> {
+        let canonical = self.addr_canonicalize(human)?;
+        let normalized = self.addr_humanize(&canonical)?;
+        if normalized.as_str() != human {
+            return Err(StdError::generic_err(format!(
+                "address {} is not normalized",
+                human
+            )));
+        }
+        Ok(normalized)
+    }
+
+    /// Like `canonical_address`, but strict about its input. By default it's the same.
+    fn addr_canonicalize(&self, human: &str) -> StdResult<CanonicalAddr> {
+        self.canonical_address(&HumanAddr::from(human))
+    }
+
+    fn addr_humanize(&self, canonical: &CanonicalAddr) -> StdResult<HumanAddr> {
+        self.human_address(canonical)
+    }
 }
 
 /// A short-hand alias for the two-level query result (1. accessing the contract, 2. executing query in the contract)
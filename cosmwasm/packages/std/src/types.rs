@@ -3,6 +3,7 @@ use serde::{Deserialize, Serialize};
 
 use crate::addresses::HumanAddr;
 use crate::coins::Coin;
+use crate::encoding::Binary;
 
 #[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq, JsonSchema)]
 pub struct Env {
@@ -12,6 +13,32 @@ pub struct Env {
     pub contract_key: Option<String>,
     #[serde(default)]
     pub contract_code_hash: String,
+    /// Random bytes derived by the enclave for this message. All nodes get the same value,
+    /// but it can't be predicted outside the enclave. Not set for queries.
+    #[serde(default)]
+    pub random: Option<Binary>,
+    #[serde(default)]
+    pub transaction: TransactionInfo,
+    /// The address that signed the permit the query was sent with. The enclave only sets it for
+    /// queries with a valid permit, so it can be trusted. Never set for txs.
+    #[serde(default)]
+    pub query_sender: Option<HumanAddr>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq, JsonSchema)]
+pub struct TransactionInfo {
+    /// The memo of the tx. The enclave verifies it against the signed tx, so it can be trusted.
+    /// Empty for queries and for messages sent by contracts.
+    #[serde(default)]
+    pub memo: String,
+    /// The account that paid the fee of the tx: the fee granter if there is one, else the first
+    /// signer of the tx. The enclave verifies it against the signed tx, so it can be trusted.
+    /// None for queries and for messages sent by contracts.
+    #[serde(default)]
+    pub fee_payer: Option<HumanAddr>,
+    /// The fee of the tx, verified and set like `fee_payer`
+    #[serde(default)]
+    pub fee_amount: Option<Vec<Coin>>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq, JsonSchema)]
@@ -20,6 +47,13 @@ pub struct BlockInfo {
     // time is seconds since epoch begin (Jan. 1, 1970)
     pub time: u64,
     pub chain_id: String,
+    /// The hash of the block, which the enclave computes from the header of the block the chain
+    /// executes. Empty on chains that don't pass the header.
+    #[serde(default)]
+    pub hash: Option<Binary>,
+    /// The address of the validator that proposed the block, set by the enclave like `hash`
+    #[serde(default)]
+    pub proposer_address: Option<Binary>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq, JsonSchema)]
@@ -10,6 +10,16 @@ pub(crate) fn get_with_prefix<S: ReadonlyStorage>(
     storage.get(&concat(namespace, key))
 }
 
+pub(crate) fn get_multi_with_prefix<S: ReadonlyStorage>(
+    storage: &S,
+    namespace: &[u8],
+    keys: &[&[u8]],
+) -> Vec<Option<Vec<u8>>> {
+    let keys: Vec<Vec<u8>> = keys.iter().map(|key| concat(namespace, key)).collect();
+    let keys: Vec<&[u8]> = keys.iter().map(Vec::as_slice).collect();
+    storage.get_multi(&keys)
+}
+
 pub(crate) fn set_with_prefix<S: Storage>(
     storage: &mut S,
     namespace: &[u8],
@@ -103,6 +113,17 @@ mod test {
         assert_eq!(collision, None);
     }
 
+    #[test]
+    fn prefix_get_multi() {
+        let mut storage = MockStorage::new();
+        let prefix = to_length_prefixed(b"foo");
+
+        set_with_prefix(&mut storage, &prefix, b"bar", b"gotcha");
+        set_with_prefix(&mut storage, &prefix, b"empty", b"");
+        let values = get_multi_with_prefix(&storage, &prefix, &[b"bar", b"missing", b"empty"]);
+        assert_eq!(values, vec![Some(b"gotcha".to_vec()), None, Some(vec![])]);
+    }
+
     #[test]
     #[cfg(feature = "iterator")]
     fn test_range() {
@@ -5,7 +5,9 @@ use cosmwasm_std::{ReadonlyStorage, Storage};
 use crate::length_prefixed::{to_length_prefixed, to_length_prefixed_nested};
 #[cfg(feature = "iterator")]
 use crate::namespace_helpers::range_with_prefix;
-use crate::namespace_helpers::{get_with_prefix, remove_with_prefix, set_with_prefix};
+use crate::namespace_helpers::{
+    get_multi_with_prefix, get_with_prefix, remove_with_prefix, set_with_prefix,
+};
 
 // prefixed_read is a helper function for less verbose usage
 pub fn prefixed_read<'a, T: ReadonlyStorage>(
@@ -48,6 +50,10 @@ impl<'a, T: ReadonlyStorage> ReadonlyStorage for ReadonlyPrefixedStorage<'a, T>
         get_with_prefix(self.storage, &self.prefix, key)
     }
 
+    fn get_multi(&self, keys: &[&[u8]]) -> Vec<Option<Vec<u8>>> {
+        get_multi_with_prefix(self.storage, &self.prefix, keys)
+    }
+
     #[cfg(feature = "iterator")]
     /// range allows iteration over a set of keys, either forwards or backwards
     fn range<'b>(
@@ -88,6 +94,10 @@ impl<'a, T: Storage> ReadonlyStorage for PrefixedStorage<'a, T> {
         get_with_prefix(self.storage, &self.prefix, key)
     }
 
+    fn get_multi(&self, keys: &[&[u8]]) -> Vec<Option<Vec<u8>>> {
+        get_multi_with_prefix(self.storage, &self.prefix, keys)
+    }
+
     #[cfg(feature = "iterator")]
     /// range allows iteration over a set of keys, either forwards or backwards
     /// uses standard rust range notation, and eg db.range(b"foo"..b"bar") also works reverse
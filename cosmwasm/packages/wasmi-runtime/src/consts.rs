@@ -9,6 +9,121 @@ pub const CERTEXPIRYDAYS: i64 = 3652i64;
 
 pub const BECH32_PREFIX_ACC_ADDR: &str = "secret";
 
+/// The largest buffer the host may copy into the enclave through `ecall_allocate`.
+/// Ocall responses (storage values, query results) are expected to be well below this.
+pub const MAX_OCALL_RESPONSE_SIZE: usize = 8 * 1024 * 1024;
+
+/// The largest output, after it was encrypted, that an ecall may return for a contract. Larger
+/// outputs fail the execution with `EnclaveError::ResponseTooLarge`.
+pub const MAX_RESPONSE_SIZE: usize = 4 * 1024 * 1024;
+
+/// The most messages and submessages a contract may return from one call, unless the chain sets
+/// another limit in the env. Outputs with more fail with `EnclaveError::ResultTooLarge`.
+pub const MAX_RESULT_MESSAGES: u32 = 128;
+
+/// The largest output a contract may return from one call, before it's encrypted, unless the chain
+/// sets another limit in the env. Outputs that are larger fail with `EnclaveError::ResultTooLarge`
+/// without being encrypted. Encryption grows the output by about a third, which keeps it below
+/// `MAX_RESPONSE_SIZE`.
+pub const MAX_RESULT_SIZE: u64 = 2 * 1024 * 1024;
+
+/// The largest output of a contract the enclave parses. This is above `MAX_RESULT_SIZE` so the
+/// chain can raise that limit, but bounds the work spent on an output no matter the limits. Larger
+/// outputs fail with `EnclaveError::ResponseTooLarge`.
+pub const MAX_RESPONSE_JSON_SIZE: usize = 2 * MAX_RESPONSE_SIZE;
+
+/// How deep the arrays and objects in the output of a contract may be nested. Deeper outputs fail
+/// with `EnclaveError::MalformedContractResponse`. This is below the recursion limit of
+/// serde_json, so outputs never hit that one.
+pub const MAX_RESPONSE_JSON_DEPTH: usize = 64;
+
+/// The maximum amount of nested ecalls (e.g. contract -> query -> contract -> query...).
+/// All nodes must use the same value, or they will get different results for nested queries.
+pub const RECURSION_LIMIT: u8 = 10;
+
+/// The maximum length of a tx memo, in characters. Must match the `max_memo_characters` param of the chain.
+pub const MAX_MEMO_CHARACTERS: usize = 256;
+
+/// The most keys a contract may read with a single call to `db_read_multi`. All their values are
+/// returned through one ocall response, which must fit in `MAX_OCALL_RESPONSE_SIZE`.
+pub const MAX_READ_DB_MULTI_KEYS: usize = 64;
+
+/// The longest namespace a contract may pass to the `db_*_ns` imports
+pub const MAX_DB_NAMESPACE_LENGTH: usize = 64;
+
+/// The longest derivation path a contract may pass to `secp256k1_generate_key`
+pub const MAX_SIGNING_KEY_PATH_LENGTH: usize = 64;
+
+/// How much gas a contract with an execution timeout may use between two reads of the clock of
+/// the host. Reading it takes an ocall, so it isn't done on every call to `gas`.
+pub const EXECUTION_DEADLINE_CHECK_INTERVAL: u64 = 1_000_000;
+
+/// The total size of contract bytecode whose analyzed modules are kept in the module cache.
+/// Analyzed modules take more memory than their bytecode, so this is kept well below the heap size.
+pub const MODULE_CACHE_SIZE_BYTES: usize = 16 * 1024 * 1024;
+
+/// The most chains whose headers contracts may verify, see `track_foreign_chain`. The chain passes
+/// what the enclave trusts about each of them in the env, so it must keep to this as well.
+pub const MAX_FOREIGN_CHAINS: usize = 32;
+/// The amount of verified headers kept for each of them
+pub const FOREIGN_CHAIN_HEADERS_KEPT: usize = 8;
+/// The most validators a header of another chain may be signed by
+pub const MAX_FOREIGN_VALIDATORS: usize = 300;
+/// How far past the block time of this chain the time of a header of another chain may be, in
+/// seconds, to allow for clocks that aren't in sync
+pub const FOREIGN_HEADER_MAX_CLOCK_DRIFT_SECS: u64 = 10;
+
+/// The most memory a contract instance may have, in 64KiB pages (512 pages are 32MiB). The chain
+/// may raise the limit up to this through the env. All nodes must use the same value, or they will
+/// disagree on which executions ran out of memory.
+pub const MAX_WASM_MEMORY_PAGES: u32 = 512;
+
+/// The most memory a contract instance may have while the chain hasn't raised the limit, in 64KiB
+/// pages (192 pages are 12MiB). Contracts may also start with no more memory than this, so that
+/// which contracts can be stored doesn't depend on the limit.
+pub const LEGACY_WASM_MEMORY_PAGES: u32 = 192;
+
+/// The size of the nonce of a query sent with replay protection
+pub const QUERY_REPLAY_NONCE_SIZE: usize = 32;
+
+/// The most blocks a query sent with replay protection may be answered in. Its nonce is
+/// remembered until its window ends, so this bounds how long nonces must be kept.
+pub const QUERY_REPLAY_MAX_WINDOW: u64 = 100;
+
+/// The amount of nonces of queries sent with replay protection that the enclave remembers
+pub const SEEN_QUERY_NONCES_CACHE_SIZE: usize = 10_000;
+
+/// The most query results the query cache keeps, whatever its size in bytes, see `query_cache`
+pub const QUERY_CACHE_MAX_ENTRIES: usize = 1024;
+
+/// How many bytes of log lines an ecall keeps before it writes them out, see `log_buffer`
+pub const LOG_BATCH_SIZE: usize = 64 * 1024;
+
+/// The amount of recent ecalls whose traces are kept while tracing is on, see `ecall_trace`
+pub const ECALL_TRACES_KEPT: usize = 64;
+
+/// The most spans the trace of one ecall holds. Spans past it are counted but not kept
+pub const MAX_SPANS_PER_ECALL_TRACE: usize = 512;
+
+/// The largest contract that can be uploaded or run, unless the host configured another limit, see
+/// `max_contract_size`. Matches `MaxWasmSize` in x/compute
+pub const MAX_CODE_SIZE: usize = 2 * 1024 * 1024;
+
+/// A gzipped contract may decompress to at most this many times its size, see `decompress_code`
+pub const MAX_GZIP_EXPANSION: usize = 10;
+
+/// The longest message `ecall_verify_input` decrypts. Anyone with access to the node can call it,
+/// so the work it does is bounded. Matches the default `max_tx_bytes` of Tendermint.
+pub const MAX_VERIFY_INPUT_SIZE: usize = 1024 * 1024;
+
+/// Chunked wasm validation sessions that weren't touched for this many seconds are dropped
+pub const WASM_VALIDATION_SESSION_TTL_SECS: u64 = 10 * 60;
+
+/// The hex address of the multisig that must sign a rotation of the consensus seed. It's set when
+/// the enclave is built, so it's part of the measurement nodes attest to. Enclaves built without it
+/// reject every rotation, see `seed_rotation_quorum_address`.
+pub const SEED_ROTATION_QUORUM_ADDRESS: Option<&str> = option_env!("SEED_ROTATION_QUORUM_ADDRESS");
+
 #[allow(dead_code)]
 #[derive(PartialEq, Eq)]
 pub enum SigningMethod {
@@ -51,12 +166,37 @@ lazy_static! {
     pub static ref REGISTRATION_KEY_SEALING_PATH: String = env::var(SCRT_SGX_STORAGE_ENV_VAR)
         .unwrap_or_else(|_| "./.sgx_secrets/".to_string())
         + "new_node_seed_exchange_keypair.sealed";
+    /// The ed25519 public key of the operator who may decommission the node, see `pin_operator`
+    pub static ref DECOMMISSION_OPERATOR_SEALING_PATH: String = env::var(SCRT_SGX_STORAGE_ENV_VAR)
+        .unwrap_or_else(|_| "./.sgx_secrets/".to_string())
+        + "decommission_operator.sealed";
+}
+
+/// Where the consensus seed of `generation` is sealed. The first seed keeps the path it always had.
+pub fn consensus_seed_sealing_path(generation: u8) -> String {
+    if generation == 0 {
+        CONSENSUS_SEED_SEALING_PATH.clone()
+    } else {
+        format!("{}.{}", *CONSENSUS_SEED_SEALING_PATH, generation)
+    }
+}
+
+/// Where the height from which the consensus seed of `generation` is used is sealed
+pub fn consensus_seed_activation_path(generation: u8) -> String {
+    format!("{}.activation", consensus_seed_sealing_path(generation))
 }
 
 pub const CONSENSUS_SEED_EXCHANGE_KEYPAIR_DERIVE_ORDER: u32 = 1;
 pub const CONSENSUS_IO_EXCHANGE_KEYPAIR_DERIVE_ORDER: u32 = 2;
 pub const CONSENSUS_STATE_IKM_DERIVE_ORDER: u32 = 3;
 pub const CONSENSUS_CALLBACK_SECRET_DERIVE_ORDER: u32 = 4;
+pub const CONSENSUS_RANDOMNESS_SECRET_DERIVE_ORDER: u32 = 5;
+/// Only used to derive a throwaway key in `ecall_health_check_deep`
+pub const HEALTH_CHECK_KEY_DERIVE_ORDER: u32 = 6;
+/// The secret the signing keys of contracts are derived from, see `ContractSigningKey`
+pub const CONSENSUS_SIGNING_SECRET_DERIVE_ORDER: u32 = 7;
+/// Derived from the registration key rather than the consensus seed, see `ecall_decommission_arm`
+pub const ERASURE_KEY_DERIVE_ORDER: u32 = 8;
 
 pub const LOG_LEVEL_ENV_VAR: &str = "LOG_LEVEL";
 pub const SCRT_SGX_STORAGE_ENV_VAR: &str = "SCRT_SGX_STORAGE";
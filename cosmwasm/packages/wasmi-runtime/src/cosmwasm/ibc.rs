@@ -0,0 +1,80 @@
+//! must keep this file in sync with cosmwasm/packages/std/src/ibc.rs
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::encoding::Binary;
+use super::types::{CosmosMsg, HumanAddr, LogAttribute};
+
+/// The port of a contract is this prefix followed by the address of the contract
+pub const CONTRACT_PORT_PREFIX: &str = "wasm.";
+
+pub fn contract_port_id(contract_address: &HumanAddr) -> String {
+    format!("{}{}", CONTRACT_PORT_PREFIX, contract_address)
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct IbcEndpoint {
+    pub port_id: String,
+    pub channel_id: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum IbcOrder {
+    Unordered,
+    Ordered,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct IbcChannel {
+    pub endpoint: IbcEndpoint,
+    pub counterparty_endpoint: IbcEndpoint,
+    pub order: IbcOrder,
+    pub version: String,
+    pub connection_id: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct IbcTimeoutHeight {
+    pub revision_number: u64,
+    pub timeout_height: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct IbcPacket {
+    pub data: Binary,
+    pub src: IbcEndpoint,
+    pub dest: IbcEndpoint,
+    pub sequence: u64,
+    pub timeout_height: Option<IbcTimeoutHeight>,
+    pub timeout_timestamp: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct IbcAcknowledgement {
+    pub acknowledgement: Binary,
+    pub original_packet: IbcPacket,
+}
+
+/// The output of `ibc_packet_receive`, like `WasmOutput` is for the other entry points
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum IbcReceiveOutput {
+    ErrObject {
+        #[serde(rename = "Err")]
+        err: Value,
+    },
+    OkObject {
+        #[serde(rename = "Ok")]
+        ok: IbcReceiveResponse,
+    },
+}
+
+// This should be in correlation with cosmwasm-std/ibc's IbcReceiveResponse
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct IbcReceiveResponse {
+    pub acknowledgement: Binary,
+    pub messages: Vec<CosmosMsg>,
+    pub log: Vec<LogAttribute>,
+}
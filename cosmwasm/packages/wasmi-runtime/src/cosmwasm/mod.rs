@@ -1,7 +1,9 @@
 pub mod coins;
 pub mod encoding;
+pub mod ibc;
 pub mod math;
 pub mod query;
+pub mod sign_mode_direct;
 pub mod std_error;
 pub mod system_error;
 pub mod types;
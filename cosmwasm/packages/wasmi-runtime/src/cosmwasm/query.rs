@@ -53,6 +53,8 @@ pub enum GovQuery {
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 #[serde(rename_all = "snake_case")]
 pub struct ProposalsResponse {
+    /// Left out by x/compute when there are no active proposals
+    #[serde(default)]
     pub proposals: Vec<Proposal>,
 }
 
@@ -155,6 +157,35 @@ pub enum StakingQuery {
     UnbondingDelegations { delegator: HumanAddr },
 }
 
+/// BondedDenomResponse is data format returned from StakingRequest::BondedDenom query
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct BondedDenomResponse {
+    pub denom: String,
+}
+
+/// AllDelegationsResponse is data format returned from StakingRequest::AllDelegations query
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct AllDelegationsResponse {
+    pub delegations: Vec<Delegation>,
+}
+
+/// DelegationResponse is data format returned from StakingRequest::Delegation query
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct DelegationResponse {
+    /// None when the delegator has no delegation with the validator
+    pub delegation: Option<FullDelegation>,
+}
+
+/// ValidatorsResponse is data format returned from StakingRequest::Validators query
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct ValidatorsResponse {
+    pub validators: Vec<Validator>,
+}
+
 /// Delegation is basic (cheap to query) data about a delegation
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Delegation {
@@ -209,7 +240,10 @@ pub struct Validator {
 /// Delegation is basic (cheap to query) data about a delegation
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct RewardsResponse {
+    /// Left out by x/compute when there are no rewards
+    #[serde(default)]
     pub rewards: Vec<ValidatorRewards>,
+    #[serde(default)]
     pub total: Vec<Coin>,
 }
 
@@ -0,0 +1,254 @@
+//! Sign docs of txs signed with `SIGN_MODE_DIRECT`. Unlike amino sign docs, these are protobuf
+//! encoded, and the tx body and auth info are signed as the bytes the client encoded them to.
+//! The parts of the schema that the enclave reads are in `sign_mode_direct.proto` next to this
+//! file.
+use log::*;
+use prost::Message;
+
+use enclave_ffi_types::EnclaveError;
+
+use super::encoding::Binary;
+use super::types::{CanonicalAddr, Coin, HumanAddr, SignDoc, SignDocWasmMsg, StdFee};
+use crate::crypto::secp256k1::Secp256k1PubKey;
+use crate::crypto::traits::PubKey;
+
+const MSG_EXECUTE_CONTRACT_TYPE_URL: &str = "/secret.compute.v1beta1.MsgExecuteContract";
+const MSG_INSTANTIATE_CONTRACT_TYPE_URL: &str = "/secret.compute.v1beta1.MsgInstantiateContract";
+const SECP256K1_PUBKEY_TYPE_URL: &str = "/cosmos.crypto.secp256k1.PubKey";
+
+#[derive(Clone, PartialEq, Message)]
+pub struct DirectSignDoc {
+    #[prost(bytes, tag = "1")]
+    pub body_bytes: Vec<u8>,
+    #[prost(bytes, tag = "2")]
+    pub auth_info_bytes: Vec<u8>,
+    #[prost(string, tag = "3")]
+    pub chain_id: String,
+    #[prost(uint64, tag = "4")]
+    pub account_number: u64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct TxBody {
+    #[prost(message, repeated, tag = "1")]
+    pub messages: Vec<Any>,
+    #[prost(string, tag = "2")]
+    pub memo: String,
+    #[prost(uint64, tag = "3")]
+    pub timeout_height: u64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Any {
+    #[prost(string, tag = "1")]
+    pub type_url: String,
+    #[prost(bytes, tag = "2")]
+    pub value: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct AuthInfo {
+    #[prost(message, repeated, tag = "1")]
+    pub signer_infos: Vec<TxSignerInfo>,
+    #[prost(message, optional, tag = "2")]
+    pub fee: Option<Fee>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct TxSignerInfo {
+    #[prost(message, optional, tag = "1")]
+    pub public_key: Option<Any>,
+    #[prost(uint64, tag = "3")]
+    pub sequence: u64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Fee {
+    #[prost(message, repeated, tag = "1")]
+    pub amount: Vec<ProtoCoin>,
+    #[prost(uint64, tag = "2")]
+    pub gas_limit: u64,
+    /// The bech32 address of the signer that pays the fee, empty for the first signer
+    #[prost(string, tag = "3")]
+    pub payer: String,
+    /// The bech32 address whose fee allowance pays the fee, empty if there is none
+    #[prost(string, tag = "4")]
+    pub granter: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ProtoCoin {
+    #[prost(string, tag = "1")]
+    pub denom: String,
+    #[prost(string, tag = "2")]
+    pub amount: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ProtoSecp256k1PubKey {
+    #[prost(bytes, tag = "1")]
+    pub key: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct MsgExecuteContract {
+    #[prost(bytes, tag = "1")]
+    pub sender: Vec<u8>,
+    #[prost(bytes, tag = "2")]
+    pub contract: Vec<u8>,
+    #[prost(bytes, tag = "3")]
+    pub msg: Vec<u8>,
+    #[prost(string, tag = "4")]
+    pub callback_code_hash: String,
+    #[prost(message, repeated, tag = "5")]
+    pub sent_funds: Vec<ProtoCoin>,
+    #[prost(bytes, tag = "6")]
+    pub callback_sig: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct MsgInstantiateContract {
+    #[prost(bytes, tag = "1")]
+    pub sender: Vec<u8>,
+    #[prost(string, tag = "2")]
+    pub callback_code_hash: String,
+    #[prost(uint64, tag = "3")]
+    pub code_id: u64,
+    #[prost(string, tag = "4")]
+    pub label: String,
+    #[prost(bytes, tag = "5")]
+    pub init_msg: Vec<u8>,
+    #[prost(message, repeated, tag = "6")]
+    pub init_funds: Vec<ProtoCoin>,
+    #[prost(bytes, tag = "7")]
+    pub callback_sig: Vec<u8>,
+    /// The bech32 address allowed to migrate the contract, empty if there is none
+    #[prost(string, tag = "8")]
+    pub admin: String,
+}
+
+impl From<ProtoCoin> for Coin {
+    fn from(coin: ProtoCoin) -> Self {
+        Coin {
+            denom: coin.denom,
+            amount: coin.amount,
+        }
+    }
+}
+
+/// Parse the `SIGN_MODE_DIRECT` sign doc in `sign_bytes` into the same form as amino sign docs,
+/// for the signer with the address `signer`.
+///
+/// `signer` must be one of the signers of the tx in its auth info, and its sequence is taken from
+/// there. Only the compute messages sent by `signer` are kept, like amino sign docs only keep the
+/// compute messages of the tx.
+pub fn parse_direct_sign_doc(
+    sign_bytes: &[u8],
+    signer: &CanonicalAddr,
+) -> Result<SignDoc, EnclaveError> {
+    let sign_doc = DirectSignDoc::decode(sign_bytes).map_err(|err| {
+        warn!(
+            "got an error while trying to decode a direct sign doc: {:?}",
+            err
+        );
+        EnclaveError::FailedToDeserialize
+    })?;
+    let body = TxBody::decode(sign_doc.body_bytes.as_slice()).map_err(|err| {
+        warn!("got an error while trying to decode the tx body: {:?}", err);
+        EnclaveError::FailedToDeserialize
+    })?;
+    let auth_info = AuthInfo::decode(sign_doc.auth_info_bytes.as_slice()).map_err(|err| {
+        warn!(
+            "got an error while trying to decode the tx auth info: {:?}",
+            err
+        );
+        EnclaveError::FailedToDeserialize
+    })?;
+
+    let signer_info = auth_info
+        .signer_infos
+        .iter()
+        .find(|signer_info| signer_info_address(signer_info).as_ref() == Some(signer))
+        .ok_or_else(|| {
+            warn!("The signer of the sign doc is not one of the signers of the tx");
+            EnclaveError::FailedTxVerification
+        })?;
+
+    let first_signer = auth_info.signer_infos.first().and_then(signer_info_address);
+    let fee = auth_info.fee.unwrap_or_default();
+    let fee = StdFee {
+        amount: fee.amount.into_iter().map(Coin::from).collect(),
+        gas: fee.gas_limit.to_string(),
+        payer: Some(fee.payer)
+            .filter(|payer| !payer.is_empty())
+            .map(HumanAddr),
+        granter: Some(fee.granter)
+            .filter(|granter| !granter.is_empty())
+            .map(HumanAddr),
+    };
+    let msgs = body
+        .messages
+        .into_iter()
+        .filter_map(|msg| parse_wasm_msg(msg, signer))
+        .collect();
+
+    Ok(SignDoc {
+        account_number: sign_doc.account_number.to_string(),
+        chain_id: sign_doc.chain_id,
+        // Serializing a StdFee can't fail
+        fee: serde_json::to_value(&fee).unwrap_or_default(),
+        memo: body.memo,
+        msgs,
+        sequence: signer_info.sequence.to_string(),
+        first_signer,
+    })
+}
+
+/// The address of a signer in the auth info. Multisig signers aren't supported, since they sign
+/// with amino even in txs that are otherwise signed with `SIGN_MODE_DIRECT`.
+fn signer_info_address(signer_info: &TxSignerInfo) -> Option<CanonicalAddr> {
+    let public_key = signer_info.public_key.as_ref()?;
+    if public_key.type_url != SECP256K1_PUBKEY_TYPE_URL {
+        return None;
+    }
+
+    let public_key = ProtoSecp256k1PubKey::decode(public_key.value.as_slice()).ok()?;
+    Some(Secp256k1PubKey::new(public_key.key).get_address())
+}
+
+/// Convert a compute message of `sender` to its amino form. Other messages are skipped.
+fn parse_wasm_msg(msg: Any, sender: &CanonicalAddr) -> Option<SignDocWasmMsg> {
+    match msg.type_url.as_str() {
+        MSG_EXECUTE_CONTRACT_TYPE_URL => {
+            let msg = MsgExecuteContract::decode(msg.value.as_slice()).ok()?;
+            if msg.sender != sender.as_slice() {
+                return None;
+            }
+
+            Some(SignDocWasmMsg::Execute {
+                contract: HumanAddr::from_canonical(&CanonicalAddr(Binary(msg.contract))).ok()?,
+                msg: Binary(msg.msg).to_base64(),
+                sent_funds: msg.sent_funds.into_iter().map(Coin::from).collect(),
+                callback_sig: None,
+            })
+        }
+        MSG_INSTANTIATE_CONTRACT_TYPE_URL => {
+            let msg = MsgInstantiateContract::decode(msg.value.as_slice()).ok()?;
+            if msg.sender != sender.as_slice() {
+                return None;
+            }
+
+            Some(SignDocWasmMsg::Instantiate {
+                code_id: msg.code_id.to_string(),
+                init_msg: Binary(msg.init_msg).to_base64(),
+                init_funds: msg.init_funds.into_iter().map(Coin::from).collect(),
+                label: Some(msg.label),
+                callback_sig: None,
+                admin: Some(msg.admin)
+                    .filter(|admin| !admin.is_empty())
+                    .map(HumanAddr),
+            })
+        }
+        _ => None,
+    }
+}
@@ -17,6 +17,7 @@ use crate::crypto::multisig::MultisigThresholdPubKey;
 use crate::crypto::secp256k1::Secp256k1PubKey;
 use crate::crypto::traits::PubKey;
 use crate::crypto::CryptoError;
+use crate::wasm::{OcallGasCosts, OutputLimits};
 use bech32::{FromBase32, ToBase32};
 use serde_json::Value;
 
@@ -96,6 +97,111 @@ pub struct Env {
     pub contract_key: Option<String>,
     #[serde(default)]
     pub contract_code_hash: String,
+    /// Set by the enclave, see `MessageRandomness`
+    #[serde(default)]
+    pub random: Option<Binary>,
+    /// Verified by the enclave against the signed tx, see `verify_params`
+    #[serde(default)]
+    pub transaction: TransactionInfo,
+    /// Set by the enclave for queries that carry a valid permit, see `verify_query_permit`. The
+    /// chain can't set it.
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub query_sender: Option<HumanAddr>,
+    /// Set by the chain, and taken out of the env before it's passed to the contract
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ocall_gas_costs: Option<OcallGasCosts>,
+    /// Set by the chain for the `addr_*` imports, and taken out of the env like `ocall_gas_costs`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bech32_prefix: Option<String>,
+    /// Set by the chain on init, see `verify_signature_params`. Taken out of the env like
+    /// `ocall_gas_costs`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instantiation: Option<ContractInstantiation>,
+    /// The header of the current block, see `verify_block_header`. Taken out of the env like
+    /// `ocall_gas_costs`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_header: Option<BlockHeader>,
+    /// Set by the node to stop contracts that run for longer, see `ExecutionDeadline`. Taken out of
+    /// the env like `ocall_gas_costs`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub execution_timeout_ms: Option<u64>,
+    /// Set by the chain to change how much contracts may return, see `OutputLimits`. Taken out of
+    /// the env like `ocall_gas_costs`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_limits: Option<OutputLimits>,
+    /// Set by the chain to the gas left in the block, when that's less than the gas the tx has
+    /// left. Running out of it is reported as `OutOfBlockGas` instead of `OutOfGas`. Taken out of
+    /// the env like `ocall_gas_costs`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_gas_remaining: Option<u64>,
+    /// Set by the chain to the most bytes a contract may store, see `StorageUsage`. Taken out of
+    /// the env like `ocall_gas_costs`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub storage_quota: Option<u64>,
+    /// Set by the chain to let contracts grow their memory past `LEGACY_WASM_MEMORY_PAGES`, up to
+    /// `MAX_WASM_MEMORY_PAGES`. Taken out of the env like `ocall_gas_costs`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_memory_pages: Option<u32>,
+    /// Set by the chain when the tx only runs to be simulated, so that its nonce isn't used up, see
+    /// `check_tx_nonce`. Never passed to the contract
+    #[serde(default, skip_serializing)]
+    pub simulation: bool,
+    /// Set by the node on queries that clients sent to it, as opposed to queries contracts make
+    /// during txs, which have to run the same on every node. Only these use up the nonce of a replay
+    /// protected query, see `check_query_replay`. Never passed to the contract
+    #[serde(default, skip_serializing)]
+    pub client_query: bool,
+    /// Set by the chain to the proof the enclave returned for the admin of the contract, see
+    /// `generate_admin_proof`. Never passed to the contract
+    #[serde(default, skip_serializing)]
+    pub admin_proof: Option<Binary>,
+    /// Set by the chain to where the call is in the tree of calls that a message of a tx causes.
+    /// The message is at the empty path, and every call it causes is at the path of its parent
+    /// plus one step: step `i` for message `i` of the parent, and steps `m + 2 * i` and
+    /// `m + 2 * i + 1` for submessage `i` and the reply to it, where `m` is the amount of messages.
+    /// That's the order the chain runs them in, so the log attributes of a message sort
+    /// depth-first by path and then by index, see `encrypt_logs`. Taken out of the env like
+    /// `ocall_gas_costs`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub execution_path: Option<Vec<u32>>,
+    /// Set by the chain when the call runs for a submessage that calls a contract, or is the reply
+    /// of a contract that runs for one. The enclave signs the result of the call for the reply to
+    /// that submessage, see `verify_reply_to`. Never passed to the contract
+    #[serde(default, skip_serializing)]
+    pub reply_to: Option<ReplyTo>,
+    /// Set by the chain to what the enclave trusts about the chains it tracks, for the
+    /// `verify_foreign_header` import. Taken out of the env like `ocall_gas_costs`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub foreign_chains: Vec<SealedForeignChain>,
+}
+
+/// The submessage a call runs for, see `Env::reply_to`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ReplyTo {
+    pub id: u64,
+    /// The contract that sent the submessage
+    pub contract: HumanAddr,
+    /// The `reply_sig` the enclave set on the submessage
+    pub reply_sig: Binary,
+}
+
+/// The code a new contract is instantiated from, which has to be the one the sender signed
+#[derive(Serialize, Deserialize, Clone, Copy, Default, Debug, PartialEq)]
+pub struct ContractInstantiation {
+    pub code_id: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
+pub struct TransactionInfo {
+    #[serde(default)]
+    pub memo: String,
+    /// Set by the enclave to the account that paid the fee of the tx, see `verified_fee_payer`.
+    /// The chain can't set it, and messages sent by contracts have none.
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub fee_payer: Option<HumanAddr>,
+    /// Set by the enclave like `fee_payer`, to the fee the signers of the tx agreed to pay
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub fee_amount: Option<Vec<Coin>>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
@@ -103,7 +209,153 @@ pub struct BlockInfo {
     pub height: u64,
     // time is seconds since epoch begin (Jan. 1, 1970)
     pub time: u64,
+    /// The nanoseconds of the block time past `time`. Only contracts built for CosmWasm 1.0 get
+    /// them, as part of their nanosecond timestamp, see `contract_env`.
+    #[serde(default, skip_serializing)]
+    pub time_nanos: u32,
+    pub chain_id: String,
+    /// Set by the enclave from the header in the env, see `verify_block_header`. The chain can't
+    /// set it.
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<Binary>,
+    /// Set by the enclave like `hash`
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub proposer_address: Option<Binary>,
+}
+
+/// A Tendermint block header. Empty hashes are left out by the chain.
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
+pub struct BlockHeader {
+    pub version: HeaderVersion,
     pub chain_id: String,
+    pub height: u64,
+    pub time: HeaderTime,
+    pub last_block_id: BlockId,
+    #[serde(default)]
+    pub last_commit_hash: Binary,
+    #[serde(default)]
+    pub data_hash: Binary,
+    #[serde(default)]
+    pub validators_hash: Binary,
+    #[serde(default)]
+    pub next_validators_hash: Binary,
+    #[serde(default)]
+    pub consensus_hash: Binary,
+    #[serde(default)]
+    pub app_hash: Binary,
+    #[serde(default)]
+    pub last_results_hash: Binary,
+    #[serde(default)]
+    pub evidence_hash: Binary,
+    #[serde(default)]
+    pub proposer_address: Binary,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
+pub struct HeaderVersion {
+    pub block: u64,
+    pub app: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
+pub struct HeaderTime {
+    pub seconds: i64,
+    pub nanos: i32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
+pub struct BlockId {
+    #[serde(default)]
+    pub hash: Binary,
+    pub parts: PartSetHeader,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
+pub struct PartSetHeader {
+    pub total: u32,
+    #[serde(default)]
+    pub hash: Binary,
+}
+
+/// The precommits of the validators that committed a block
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
+pub struct Commit {
+    pub height: u64,
+    pub round: u32,
+    pub block_id: BlockId,
+    /// One per validator, in the order of the validator set
+    pub signatures: Vec<CommitSig>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
+pub struct CommitSig {
+    /// Tendermint's `BlockIDFlag`: 1 if the validator didn't vote, 2 if it voted for the block
+    /// and 3 if it voted nil
+    pub block_id_flag: u8,
+    #[serde(default)]
+    pub validator_address: Binary,
+    #[serde(default)]
+    pub timestamp: HeaderTime,
+    #[serde(default)]
+    pub signature: Binary,
+}
+
+/// A validator with an ed25519 consensus key
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
+pub struct Validator {
+    pub address: Binary,
+    pub pub_key: Binary,
+    pub voting_power: i64,
+}
+
+/// What the chain submits to the enclave for every block, see `submit_block_header`
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
+pub struct BlockHeaderSubmission {
+    pub header: BlockHeader,
+    pub commit: Commit,
+    /// The validators of the block, in the order of the signatures in the commit
+    pub validators: Vec<Validator>,
+}
+
+/// A header of another chain, with the commit and validators that signed it. See
+/// `submit_foreign_header`.
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
+pub struct ForeignHeader {
+    pub header: BlockHeader,
+    pub commit: Commit,
+    /// The validators of the header, in the order of the signatures in the commit
+    pub validators: Vec<Validator>,
+    /// The validators the last trusted header of the chain chose for the block after it. Only
+    /// needed for headers that skip blocks.
+    #[serde(default)]
+    pub trusted_validators: Vec<Validator>,
+}
+
+/// What governance approved to start verifying the headers of another chain, see
+/// `track_foreign_chain`
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
+pub struct ForeignChainInit {
+    /// Trusted as it is
+    pub header: BlockHeader,
+    /// For how long after its time a trusted header may be used to verify newer headers
+    pub trusting_period_seconds: u64,
+}
+
+/// What the enclave trusts about another chain, as the chain keeps it. The enclave only reads
+/// `state` along with the `proof` it made for it, see `track_foreign_chain`.
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
+pub struct SealedForeignChain {
+    pub state: Binary,
+    pub proof: Binary,
+}
+
+/// What the `verify_foreign_header` import returns to contracts
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
+pub struct ForeignHeaderVerification {
+    pub verified: bool,
+    /// The app hash of the header, if it was verified
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub app_hash: Option<Binary>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
@@ -125,6 +377,10 @@ pub struct MessageInfo {
 #[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
 pub struct ContractInfo {
     pub address: HumanAddr,
+    /// The address allowed to migrate this contract, if any. The contract only sees it if the
+    /// enclave authenticated it, see `is_authenticated_admin`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub admin: Option<HumanAddr>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
@@ -153,9 +409,85 @@ pub enum WasmOutput {
 // This should be in correlation with cosmwasm-std/init_handle's InitResponse and HandleResponse
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct ContractResult {
+    #[serde(default)]
+    pub submessages: Vec<SubMsg>,
     pub messages: Vec<CosmosMsg>,
     pub log: Vec<LogAttribute>,
     pub data: Option<Binary>,
+    /// Set by the enclave to the `execution_path` of the call, which the log attributes are bound
+    /// to. Contracts can't set it.
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub execution_path: Option<Vec<u32>>,
+    /// Set by the enclave when the call runs for a submessage, see `create_reply_result_signature`.
+    /// Contracts can't set it.
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub reply_result_sig: Option<Binary>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+// This should be in correlation with cosmwasm-std/init_handle's ReplyOn
+pub enum ReplyOn {
+    Always,
+    Error,
+    Success,
+    Never,
+}
+
+impl Default for ReplyOn {
+    fn default() -> Self {
+        ReplyOn::Always
+    }
+}
+
+// This should be in correlation with cosmwasm-std/init_handle's SubMsg
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SubMsg {
+    pub id: u64,
+    pub msg: CosmosMsg,
+    pub gas_limit: Option<u64>,
+    #[serde(default)]
+    pub reply_on: ReplyOn,
+    /// Set by the enclave when the contract returns the submessage. The module passes it back
+    /// with the reply, to prove that the contract really sent a submessage with this id.
+    #[serde(default)]
+    pub reply_sig: Option<Binary>,
+}
+
+// This should be in correlation with cosmwasm-std/init_handle's Reply
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Reply {
+    pub id: u64,
+    pub result: SubMsgResult,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SubMsgResult {
+    Ok(SubMsgExecutionResponse),
+    Error(String),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SubMsgExecutionResponse {
+    pub data: Option<Binary>,
+}
+
+/// The message the compute module passes to `ecall_reply` after executing a submessage
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SignedReply {
+    pub id: u64,
+    pub result: SubMsgResult,
+    /// The `reply_sig` the enclave set on the submessage
+    pub reply_sig: Binary,
+    /// The `reply_result_sig` the enclave set on the output of the contract the submessage called,
+    /// if it called one and succeeded. Other results aren't signed.
+    #[serde(default)]
+    pub result_sig: Option<Binary>,
+    /// The nonce and public key of the tx that caused the submessage. The reply's output is
+    /// encrypted with them, just like the output of the original message.
+    pub nonce: Binary,
+    pub user_public_key: Binary,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
@@ -255,10 +587,16 @@ pub enum WasmMsg {
         contract_addr: HumanAddr,
         /// callback_code_hash is the hex encoded hash of the code. This is used by Secret Network to harden against replaying the contract
         /// It is used to bind the request to a destination contract in a stronger way than just the contract address which can be faked
+        #[serde(alias = "code_hash")]
         callback_code_hash: String,
         /// msg is the json-encoded HandleMsg struct (as raw Binary)
         msg: Binary,
+        #[serde(default)]
         send: Vec<Coin>,
+        /// The funds of a message built with CosmWasm 1.0, which calls `send` this. They are moved
+        /// to `send` when the message is encrypted, and never passed to the chain.
+        #[serde(default, skip_serializing)]
+        funds: Option<Vec<Coin>>,
         callback_sig: Option<Vec<u8>>,
     },
     /// this instantiates a new contracts from previously uploaded wasm code
@@ -266,10 +604,15 @@ pub enum WasmMsg {
         code_id: u64,
         /// callback_code_hash is the hex encoded hash of the code. This is used by Secret Network to harden against replaying the contract
         /// It is used to bind the request to a destination contract in a stronger way than just the contract address which can be faked
+        #[serde(alias = "code_hash")]
         callback_code_hash: String,
         /// msg is the json-encoded InitMsg struct (as raw Binary)
         msg: Binary,
+        #[serde(default)]
         send: Vec<Coin>,
+        /// See `WasmMsg::Execute::funds`
+        #[serde(default, skip_serializing)]
+        funds: Option<Vec<Coin>>,
         /// Human-readable label for the contract
         #[serde(default)]
         label: String,
@@ -306,6 +649,14 @@ impl<T: Clone + fmt::Debug + PartialEq> From<WasmMsg> for CosmosMsg<T> {
 pub struct LogAttribute {
     pub key: String,
     pub value: String,
+    /// Set by the contract for attributes that aren't encrypted. The output of the enclave
+    /// doesn't carry it, since the chain handles all attributes the same way.
+    #[serde(default, skip_serializing)]
+    pub plaintext: bool,
+    /// Set by the contract for attributes that are encrypted to another account than the sender.
+    /// The output doesn't carry it either, so the chain doesn't learn who the recipient is.
+    #[serde(default, skip_serializing)]
+    pub recipient: Option<Binary>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
@@ -342,6 +693,8 @@ pub fn log(key: &str, value: &str) -> LogAttribute {
     LogAttribute {
         key: key.to_string(),
         value: value.to_string(),
+        plaintext: false,
+        recipient: None,
     }
 }
 
@@ -354,6 +707,10 @@ pub struct CosmosSignature {
 }
 
 impl CosmosSignature {
+    pub fn new(pub_key: PubKeyKind, signature: Binary) -> Self {
+        Self { pub_key, signature }
+    }
+
     pub fn get_public_key(&self) -> PubKeyKind {
         self.pub_key.clone()
     }
@@ -398,17 +755,122 @@ impl PubKey for PubKeyKind {
 pub struct SignDoc {
     pub account_number: String,
     pub chain_id: String,
+    /// Kept as it was signed, so sign docs with fees of any shape verify. See `StdFee::from_fee`
+    /// for the fields the enclave reads out of it.
     pub fee: Value,
     pub memo: String,
+    /// Only the compute messages of the tx are kept here. Other messages that may be signed
+    /// alongside them (e.g. a bank `MsgMultiSend`) are irrelevant to the enclave and are skipped.
+    #[serde(deserialize_with = "deserialize_wasm_msgs")]
     pub msgs: Vec<SignDocWasmMsg>,
     pub sequence: String,
+    /// The first signer of the tx, who pays its fee unless the fee names someone else. Set when
+    /// the sign doc is verified, see `verify_params`, and None if it can't be told.
+    #[serde(skip)]
+    pub first_signer: Option<CanonicalAddr>,
+}
+
+/// The fee of a tx, as its signers signed it
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
+pub struct StdFee {
+    pub amount: Vec<Coin>,
+    pub gas: String,
+    /// The signer that pays the fee, if it isn't the first signer of the tx
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payer: Option<HumanAddr>,
+    /// The account whose fee allowance pays the fee instead of the payer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub granter: Option<HumanAddr>,
+}
+
+impl StdFee {
+    /// The fields of a signed fee the enclave reads. Fields that are missing or of another shape
+    /// are left empty, so that a fee never fails the sign doc it's in.
+    pub fn from_fee(fee: &Value) -> Self {
+        let address = |field: &str| {
+            fee.get(field)
+                .and_then(Value::as_str)
+                .filter(|address| !address.is_empty())
+                .map(|address| HumanAddr(address.to_string()))
+        };
+
+        StdFee {
+            amount: fee
+                .get("amount")
+                .and_then(|amount| serde_json::from_value(amount.clone()).ok())
+                .unwrap_or_default(),
+            gas: match fee.get("gas") {
+                Some(Value::String(gas)) => gas.clone(),
+                Some(Value::Number(gas)) => gas.to_string(),
+                _ => String::new(),
+            },
+            payer: address("payer"),
+            granter: address("granter"),
+        }
+    }
+}
+
+fn deserialize_wasm_msgs<'de, D>(deserializer: D) -> Result<Vec<SignDocWasmMsg>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let msgs: Vec<Value> = Deserialize::deserialize(deserializer)?;
+
+    Ok(msgs
+        .into_iter()
+        .filter_map(|msg| serde_json::from_value(msg).ok())
+        .collect())
+}
+
+/// The signature of a single tx signer, along with the bytes that this signer signed.
+///
+/// In amino txs every signer signs a slightly different sign doc, since the account number and
+/// sequence are part of it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SignerInfo {
+    pub sign_bytes: Binary,
+    pub signature: CosmosSignature,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct SigInfo {
     pub sign_bytes: Binary,
     pub signature: CosmosSignature,
+    /// All the other signers of the tx, if there are more than one
+    #[serde(default)]
+    pub additional_signers: Vec<SignerInfo>,
     pub callback_sig: Option<Binary>,
+    /// How the signers encoded the sign docs they signed
+    #[serde(default)]
+    pub sign_mode: SignMode,
+}
+
+/// Should be in sync with the sign mode names in cosmos/tx/signing/v1beta1/signing.proto
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum SignMode {
+    /// The sign doc is the amino JSON `StdSignDoc`
+    #[serde(rename = "SIGN_MODE_LEGACY_AMINO_JSON")]
+    LegacyAminoJson,
+    /// The sign doc is the protobuf `SignDoc` of the tx
+    #[serde(rename = "SIGN_MODE_DIRECT")]
+    Direct,
+}
+
+impl Default for SignMode {
+    fn default() -> Self {
+        SignMode::LegacyAminoJson
+    }
+}
+
+impl SigInfo {
+    /// Iterate over all the signers of the tx, starting with the one in `sign_bytes` and `signature`
+    pub fn signers(&self) -> impl Iterator<Item = (&Binary, &CosmosSignature)> {
+        std::iter::once((&self.sign_bytes, &self.signature)).chain(
+            self.additional_signers
+                .iter()
+                .map(|signer| (&signer.sign_bytes, &signer.signature)),
+        )
+    }
 }
 
 // This struct is basically the smae as WasmMsg, but serializes/deserializes differently
@@ -430,5 +892,30 @@ pub enum SignDocWasmMsg {
         init_funds: Vec<Coin>,
         label: Option<String>,
         callback_sig: Option<Vec<u8>>,
+        /// The address allowed to migrate the new contract, if any
+        #[serde(default)]
+        admin: Option<HumanAddr>,
+    },
+    #[serde(alias = "wasm/MsgMigrateContract")]
+    Migrate {
+        contract: HumanAddr,
+        code_id: String,
+        /// msg is the json-encoded MigrateMsg struct (as raw Binary)
+        msg: String,
+    },
+    #[serde(alias = "wasm/MsgRotateContractKey")]
+    RotateContractKey { contract: HumanAddr },
+    #[serde(alias = "wasm/MsgUpdateAdmin")]
+    UpdateAdmin {
+        contract: HumanAddr,
+        new_admin: HumanAddr,
+    },
+    #[serde(alias = "wasm/MsgClearAdmin")]
+    ClearAdmin { contract: HumanAddr },
+    #[serde(alias = "wasm/MsgImportContractState")]
+    ImportContractState {
+        contract: HumanAddr,
+        /// The chunk of the archive as the export returned it
+        chunk: Binary,
     },
 }
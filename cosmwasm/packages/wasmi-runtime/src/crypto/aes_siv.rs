@@ -21,6 +21,9 @@ use aes_siv::aead::generic_array::GenericArray;
 use aes_siv::siv::Aes128Siv;
 use log::*;
 
+/// Every ciphertext starts with the synthetic IV, which also authenticates it
+const SIV_TAG_SIZE: usize = 16;
+
 impl SIVEncryptable for AESKey {
     fn encrypt_siv(&self, plaintext: &[u8], ad: Option<&[&[u8]]>) -> Result<Vec<u8>, CryptoError> {
         aes_siv_encrypt(plaintext, ad, self.get())
@@ -50,6 +53,14 @@ fn aes_siv_decrypt(
     ad: Option<&[&[u8]]>,
     key: &SymmetricKey,
 ) -> Result<Vec<u8>, CryptoError> {
+    if ciphertext.len() < SIV_TAG_SIZE {
+        warn!(
+            "aes_siv_decrypt error: ciphertext of {} bytes is shorter than the tag",
+            ciphertext.len()
+        );
+        return Err(CryptoError::ImproperEncryption);
+    }
+
     let ad = ad.unwrap_or(&[&[]]);
 
     let mut cipher = Aes128Siv::new(GenericArray::clone_from_slice(key));
@@ -0,0 +1,85 @@
+//! Comparisons of values derived from the secrets of the enclave.
+//!
+//! `==` on slices returns at the first byte that differs, so the time it takes tells an attacker
+//! how many bytes of a guessed MAC were right. Values derived from secrets are compared with
+//! `constant_time_eq` instead, which always looks at every byte.
+
+use ring::constant_time::verify_slices_are_equal;
+
+use super::HASH_SIZE;
+
+/// Whether `a` and `b` are equal, in a time that only depends on their lengths
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    verify_slices_are_equal(a, b).is_ok()
+}
+
+/// A MAC the enclave computed with one of its secrets.
+///
+/// It doesn't implement `PartialEq`, so the only way to check what the host sent against it is
+/// `verify`, which takes constant time.
+pub struct AuthenticationCode([u8; HASH_SIZE]);
+
+impl AuthenticationCode {
+    pub fn new(code: [u8; HASH_SIZE]) -> Self {
+        Self(code)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; HASH_SIZE] {
+        &self.0
+    }
+
+    pub fn to_vec(&self) -> Vec<u8> {
+        self.0.to_vec()
+    }
+
+    /// Whether `code` is this code
+    pub fn verify(&self, code: &[u8]) -> bool {
+        constant_time_eq(&self.0, code)
+    }
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    pub fn test_constant_time_eq() {
+        let value = [7u8; HASH_SIZE];
+        let copy = value;
+        assert!(constant_time_eq(&value, &copy));
+        assert!(constant_time_eq(&[], &[]));
+
+        for index in &[0, HASH_SIZE / 2, HASH_SIZE - 1] {
+            let mut other = value;
+            other[*index] ^= 1;
+            assert!(!constant_time_eq(&value, &other));
+        }
+        assert!(!constant_time_eq(&value, &value[..HASH_SIZE - 1]));
+        assert!(!constant_time_eq(&value, &[]));
+    }
+
+    pub fn test_authentication_code_verify() {
+        let code = AuthenticationCode::new([7u8; HASH_SIZE]);
+        assert!(code.verify(&[7u8; HASH_SIZE]));
+        assert_eq!(code.to_vec(), code.as_bytes().to_vec());
+
+        let mut forged = [7u8; HASH_SIZE];
+        forged[HASH_SIZE - 1] = 8;
+        assert!(!code.verify(&forged));
+        assert!(!code.verify(&[7u8; HASH_SIZE + 1]));
+        assert!(!code.verify(&[]));
+    }
+
+    /// Doesn't compile if `AuthenticationCode` implements `PartialEq`, because then the call to
+    /// `some_item` is ambiguous. That keeps `==` from being used on MACs again.
+    pub fn test_authentication_code_is_not_partial_eq() {
+        trait AmbiguousIfPartialEq<A> {
+            fn some_item() {}
+        }
+        impl<T: ?Sized> AmbiguousIfPartialEq<()> for T {}
+
+        struct Invalid;
+        impl<T: ?Sized + PartialEq> AmbiguousIfPartialEq<Invalid> for T {}
+
+        <AuthenticationCode as AmbiguousIfPartialEq<_>>::some_item();
+    }
+}
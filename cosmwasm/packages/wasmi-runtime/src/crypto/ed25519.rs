@@ -8,16 +8,21 @@ use sgx_types::sgx_align_ec256_private_t;
 use crate::crypto::CryptoError;
 
 use super::rng::rand_slice;
+use log::*;
+use ring::signature::{UnparsedPublicKey, ED25519};
+use zeroize::{Zeroize, Zeroizing};
 
 pub const SECRET_KEY_SIZE: usize = 32;
 pub const PUBLIC_KEY_SIZE: usize = 32;
+pub const SIGNATURE_SIZE: usize = 64;
 
 type AlignedEc256PrivateKey = sgx_align_ec256_private_t;
 
 pub type Ed25519PublicKey = [u8; 32];
 
+/// Wiped when dropped, so it isn't `Copy`. Every clone is wiped on its own.
 #[repr(C, align(64))]
-#[derive(Clone, Copy, Default)]
+#[derive(Clone, Default)]
 pub struct Ed25519PrivateKey {
     pub key: AlignedEc256PrivateKey,
 }
@@ -32,13 +37,25 @@ impl Ed25519PrivateKey {
     }
 }
 
+impl Zeroize for Ed25519PrivateKey {
+    fn zeroize(&mut self) {
+        self.as_mut().zeroize();
+    }
+}
+
+impl Drop for Ed25519PrivateKey {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
 impl ExportECKey for Ed25519PrivateKey {
     fn key_ref(&self) -> &[u8; EC_256_PRIVATE_KEY_SIZE] {
         &self.key.key.r as &[u8; EC_256_PRIVATE_KEY_SIZE]
     }
 }
 
-#[derive(Clone, Copy, Default)]
+#[derive(Clone, Default)]
 pub struct KeyPair {
     secret_key: Ed25519PrivateKey,
     public_key: Ed25519PublicKey,
@@ -58,14 +75,15 @@ impl KeyPair {
         })
     }
 
-    pub fn diffie_hellman(&self, your_public: &[u8; SECRET_KEY_SIZE]) -> DhKey {
+    pub fn diffie_hellman(&self, your_public: &[u8; SECRET_KEY_SIZE]) -> Zeroizing<DhKey> {
         let my_secret =
             x25519_dalek::StaticSecret::from(self.secret_key.to_owned().key.r as [u8; 32]);
         let pk = x25519_dalek::PublicKey::from(*your_public);
         let ss = my_secret.diffie_hellman(&pk);
 
-        *ss.as_bytes()
+        Zeroizing::new(*ss.as_bytes())
     }
+
     pub fn get_privkey(&self) -> &[u8; SECRET_KEY_SIZE] {
         self.secret_key.key_ref()
     }
@@ -93,3 +111,177 @@ impl<T: AlignedMemory + ExportECKey> From<T> for KeyPair {
         }
     }
 }
+
+/// Verify an ed25519 signature over `message`.
+///
+/// Public keys that are not valid point encodings fail the same way as invalid signatures.
+pub fn ed25519_verify(
+    message: &[u8],
+    signature: &[u8],
+    public_key: &[u8],
+) -> Result<(), CryptoError> {
+    if signature.len() != SIGNATURE_SIZE {
+        warn!("Malformed ed25519 signature of length {}", signature.len());
+        return Err(CryptoError::ParsingError);
+    }
+    if public_key.len() != PUBLIC_KEY_SIZE {
+        warn!(
+            "Malformed ed25519 public key of length {}",
+            public_key.len()
+        );
+        return Err(CryptoError::KeyError);
+    }
+
+    UnparsedPublicKey::new(&ED25519, public_key)
+        .verify(message, signature)
+        .map_err(|_| CryptoError::VerificationError)
+}
+
+/// Verify a batch of ed25519 signatures.
+///
+/// There must be one message and one public key per signature, except that a single message or
+/// a single public key may be shared by all the signatures.
+///
+/// Every signature is checked on its own rather than with a randomized batch equation, so the
+/// result is exactly the same as verifying each signature with `ed25519_verify`. This keeps
+/// the outcome identical on every node.
+pub fn ed25519_batch_verify(
+    messages: &[&[u8]],
+    signatures: &[&[u8]],
+    public_keys: &[&[u8]],
+) -> Result<(), CryptoError> {
+    let count = signatures.len();
+    let shared_message = messages.len() == 1 && count != 1;
+    let shared_public_key = public_keys.len() == 1 && count != 1;
+
+    if (messages.len() != count && !shared_message)
+        || (public_keys.len() != count && !shared_public_key)
+    {
+        warn!(
+            "Mismatched ed25519 batch: {} messages, {} signatures, {} public keys",
+            messages.len(),
+            count,
+            public_keys.len()
+        );
+        return Err(CryptoError::ParsingError);
+    }
+
+    for (i, signature) in signatures.iter().enumerate() {
+        let message = if shared_message {
+            messages[0]
+        } else {
+            messages[i]
+        };
+        let public_key = if shared_public_key {
+            public_keys[0]
+        } else {
+            public_keys[i]
+        };
+
+        ed25519_verify(message, signature, public_key)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use ring::signature::{Ed25519KeyPair, KeyPair as _};
+
+    fn signed_batch(count: u8) -> (Vec<Vec<u8>>, Vec<Vec<u8>>, Vec<Vec<u8>>) {
+        let mut messages = vec![];
+        let mut signatures = vec![];
+        let mut public_keys = vec![];
+        for i in 0..count {
+            let key_pair = Ed25519KeyPair::from_seed_unchecked(&[i; 32]).unwrap();
+            let message = vec![i; i as usize + 1];
+            signatures.push(key_pair.sign(&message).as_ref().to_vec());
+            public_keys.push(key_pair.public_key().as_ref().to_vec());
+            messages.push(message);
+        }
+        (messages, signatures, public_keys)
+    }
+
+    fn as_slices(items: &[Vec<u8>]) -> Vec<&[u8]> {
+        items.iter().map(Vec::as_slice).collect()
+    }
+
+    pub fn test_ed25519_batch_verify_valid() {
+        let (messages, signatures, public_keys) = signed_batch(50);
+        assert!(ed25519_batch_verify(
+            &as_slices(&messages),
+            &as_slices(&signatures),
+            &as_slices(&public_keys)
+        )
+        .is_ok());
+    }
+
+    pub fn test_ed25519_batch_verify_shared_message_and_key() {
+        let key_pair = Ed25519KeyPair::from_seed_unchecked(&[7; 32]).unwrap();
+        let public_key = key_pair.public_key().as_ref().to_vec();
+        let first = key_pair.sign(b"first");
+        let second = key_pair.sign(b"second");
+
+        // One key for many messages
+        assert!(ed25519_batch_verify(
+            &[b"first", b"second"],
+            &[first.as_ref(), second.as_ref()],
+            &[&public_key]
+        )
+        .is_ok());
+
+        // One message for many keys
+        let other_key_pair = Ed25519KeyPair::from_seed_unchecked(&[8; 32]).unwrap();
+        let other_public_key = other_key_pair.public_key().as_ref().to_vec();
+        let other_first = other_key_pair.sign(b"first");
+        assert!(ed25519_batch_verify(
+            &[b"first"],
+            &[first.as_ref(), other_first.as_ref()],
+            &[&public_key, &other_public_key]
+        )
+        .is_ok());
+    }
+
+    pub fn test_ed25519_batch_verify_invalid_signature() {
+        let (messages, mut signatures, public_keys) = signed_batch(5);
+        signatures[3][0] ^= 1;
+        assert!(matches!(
+            ed25519_batch_verify(
+                &as_slices(&messages),
+                &as_slices(&signatures),
+                &as_slices(&public_keys)
+            ),
+            Err(CryptoError::VerificationError)
+        ));
+    }
+
+    pub fn test_ed25519_batch_verify_malformed_input() {
+        let (messages, signatures, public_keys) = signed_batch(3);
+
+        // Mismatched lengths
+        assert!(matches!(
+            ed25519_batch_verify(
+                &as_slices(&messages[..2]),
+                &as_slices(&signatures),
+                &as_slices(&public_keys)
+            ),
+            Err(CryptoError::ParsingError)
+        ));
+
+        // Truncated signature
+        assert!(matches!(
+            ed25519_batch_verify(&[&messages[0]], &[&signatures[0][..63]], &[&public_keys[0]]),
+            Err(CryptoError::ParsingError)
+        ));
+
+        // Public key that isn't a canonical point encoding
+        let mut bad_point = [0xff; PUBLIC_KEY_SIZE];
+        bad_point[PUBLIC_KEY_SIZE - 1] = 0x7f;
+        assert!(matches!(
+            ed25519_batch_verify(&[&messages[0]], &[&signatures[0]], &[&bad_point]),
+            Err(CryptoError::VerificationError)
+        ));
+    }
+}
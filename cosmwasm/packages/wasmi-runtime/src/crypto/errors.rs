@@ -1,5 +1,7 @@
 use derive_more::Display;
 
+use enclave_ffi_types::EnclaveError;
+
 #[derive(Debug, Display)]
 pub enum CryptoError {
     /// The ECDH process failed.
@@ -27,3 +29,18 @@ pub enum CryptoError {
     /// An error related to signature verification
     VerificationError,
 }
+
+impl From<CryptoError> for EnclaveError {
+    fn from(err: CryptoError) -> Self {
+        match err {
+            CryptoError::DerivingKeyError | CryptoError::KeyError => {
+                EnclaveError::KeyDerivationFailed
+            }
+            CryptoError::DecryptionError => EnclaveError::DecryptionError,
+            CryptoError::ImproperEncryption => EnclaveError::CiphertextTooShort,
+            CryptoError::EncryptionError => EnclaveError::EncryptionError,
+            // Unexpected CryptoError variant
+            _other => EnclaveError::Unknown,
+        }
+    }
+}
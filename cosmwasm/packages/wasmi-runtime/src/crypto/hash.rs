@@ -0,0 +1,72 @@
+use ripemd160::Ripemd160;
+use sha3::{Digest, Keccak256};
+
+pub const KECCAK_256_HASH_SIZE: usize = 32;
+pub const RIPEMD_160_HASH_SIZE: usize = 20;
+
+/// The original Keccak, as used by Ethereum. Its padding differs from the standardized SHA3-256.
+pub fn keccak_256(data: &[u8]) -> [u8; KECCAK_256_HASH_SIZE] {
+    let mut result = [0u8; KECCAK_256_HASH_SIZE];
+    result.copy_from_slice(&Keccak256::digest(data));
+
+    result
+}
+
+pub fn ripemd_160(data: &[u8]) -> [u8; RIPEMD_160_HASH_SIZE] {
+    let mut result = [0u8; RIPEMD_160_HASH_SIZE];
+    result.copy_from_slice(&Ripemd160::digest(data));
+
+    result
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+    use crate::crypto::sha_256;
+
+    // 448 bits, so the padding spills over to a second block
+    const TWO_BLOCK_MESSAGE: &[u8] = b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq";
+
+    pub fn test_sha_256_known_vectors() {
+        // NIST FIPS 180-2 examples
+        assert_eq!(
+            hex::encode(sha_256(b"")),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            hex::encode(sha_256(b"abc")),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            hex::encode(sha_256(TWO_BLOCK_MESSAGE)),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+    }
+
+    pub fn test_keccak_256_known_vectors() {
+        assert_eq!(
+            hex::encode(keccak_256(b"")),
+            "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+        );
+        assert_eq!(
+            hex::encode(keccak_256(b"abc")),
+            "4e03657aea45a94fc7d47ba826c8d667c0d1e6e33a64a036ec44f58fa12d6c45"
+        );
+    }
+
+    pub fn test_ripemd_160_known_vectors() {
+        // From the RIPEMD-160 reference
+        assert_eq!(
+            hex::encode(ripemd_160(b"")),
+            "9c1185a5c5e9fc54612808977ee8f548b2258d31"
+        );
+        assert_eq!(
+            hex::encode(ripemd_160(b"abc")),
+            "8eb208f7e05d987a9b044a8e98c6b087f15a0bfc"
+        );
+        assert_eq!(
+            hex::encode(ripemd_160(b"message digest")),
+            "5d0689ef49d2fae572b881b123a85ffa21595f36"
+        );
+    }
+}
@@ -1,7 +1,8 @@
 use crate::crypto::traits::Kdf;
-use crate::crypto::{AESKey, Seed, SECRET_KEY_SIZE};
+use crate::crypto::{AESKey, CryptoError, Seed, HASH_SIZE, SECRET_KEY_SIZE};
 
 use ring::hkdf;
+use zeroize::Zeroizing;
 
 // Bitcoin halving block hash https://www.blockchain.com/btc/block/000000000000000000024bead8df69990852c202db0e0097c1a12ea637d7e96d
 const KDF_SALT: [u8; 32] = [
@@ -11,23 +12,27 @@ const KDF_SALT: [u8; 32] = [
 
 impl Kdf<AESKey> for AESKey {
     fn derive_key_from_this(&self, data: &[u8]) -> Self {
-        let mut input_bytes: Vec<u8> = self.get().to_vec();
-        input_bytes.extend_from_slice(data);
-
-        derive_key(&input_bytes, &[])
+        derive_key(&input_key_material(self.get(), data), &[])
     }
 }
 
 impl Kdf<AESKey> for Seed {
     //
     fn derive_key_from_this(&self, data: &[u8]) -> AESKey {
-        let mut input_bytes: Vec<u8> = self.as_slice().to_vec();
-        input_bytes.extend_from_slice(data);
-
-        derive_key(&input_bytes, &[b"seed"])
+        derive_key(&input_key_material(self.as_slice(), data), &[b"seed"])
     }
 }
 
+/// The secret followed by `data`. The buffer is allocated at its final size, so the secret isn't
+/// left behind in a smaller allocation when `data` is appended, and it is wiped when dropped.
+fn input_key_material(secret: &[u8], data: &[u8]) -> Zeroizing<Vec<u8>> {
+    let mut input_bytes = Zeroizing::new(Vec::with_capacity(secret.len() + data.len()));
+    input_bytes.extend_from_slice(secret);
+    input_bytes.extend_from_slice(data);
+
+    input_bytes
+}
+
 fn derive_key(input_bytes: &[u8], info: &[&[u8]]) -> AESKey {
     let salt = hkdf::Salt::new(hkdf::HKDF_SHA256, &KDF_SALT);
 
@@ -42,6 +47,35 @@ fn derive_key(input_bytes: &[u8], info: &[&[u8]]) -> AESKey {
     result
 }
 
+/// The most bytes HKDF-SHA256 can derive, 255 blocks of the hash (RFC 5869)
+pub const HKDF_SHA256_MAX_OUTPUT_SIZE: usize = 255 * HASH_SIZE;
+
+/// HKDF-SHA256 as defined by RFC 5869, for contracts that derive keys of their own. An empty salt
+/// is the same as no salt. The output is wiped when dropped, as it's usually a key.
+///
+/// Fails if `output_len` is 0 or more than `HKDF_SHA256_MAX_OUTPUT_SIZE`.
+pub fn hkdf_sha256(
+    ikm: &[u8],
+    salt: &[u8],
+    info: &[u8],
+    output_len: usize,
+) -> Result<Zeroizing<Vec<u8>>, CryptoError> {
+    if output_len == 0 || output_len > HKDF_SHA256_MAX_OUTPUT_SIZE {
+        return Err(CryptoError::DerivingKeyError);
+    }
+
+    let prk = hkdf::Salt::new(hkdf::HKDF_SHA256, salt).extract(ikm);
+    let okm = prk
+        .expand(&[info], My(output_len))
+        .map_err(|_| CryptoError::DerivingKeyError)?;
+
+    let mut output = Zeroizing::new(vec![0u8; output_len]);
+    okm.fill(&mut output)
+        .map_err(|_| CryptoError::DerivingKeyError)?;
+
+    Ok(output)
+}
+
 /// https://github.com/briansmith/ring/blob/master/tests/hkdf_tests.rs
 /// Generic newtype wrapper that lets us implement traits for externally-defined
 /// types.
@@ -65,7 +99,37 @@ impl From<hkdf::Okm<'_, My<usize>>> for My<Vec<u8>> {
 #[cfg(feature = "test")]
 pub mod tests {
     use crate::crypto::CryptoError;
-    use crate::crypto::{Kdf, KeyPair, Seed};
+    use crate::crypto::{AESKey, Kdf, KeyPair, Seed};
+
+    /// Drop the value in `slot` in place, and return what is left in its memory afterwards
+    unsafe fn bytes_after_drop<T>(
+        slot: &mut std::mem::MaybeUninit<T>,
+        bytes: fn(&T) -> &[u8; 32],
+    ) -> [u8; 32] {
+        std::ptr::drop_in_place(slot.as_mut_ptr());
+        // The value was dropped, but the memory it was in still belongs to `slot`
+        *bytes(&*slot.as_ptr())
+    }
+
+    pub fn test_derived_key_is_zeroed_on_drop() {
+        const CANARY: [u8; 32] = [0xa5; 32];
+
+        let mut seed_slot = std::mem::MaybeUninit::<Seed>::uninit();
+        let mut key_slot = std::mem::MaybeUninit::<AESKey>::uninit();
+        unsafe {
+            let mut seed = Seed::default();
+            seed.as_mut().copy_from_slice(&CANARY);
+            seed_slot.as_mut_ptr().write(seed);
+            assert_eq!((*seed_slot.as_ptr()).as_slice(), &CANARY);
+
+            let key = (*seed_slot.as_ptr()).derive_key_from_this(&1u32.to_be_bytes());
+            key_slot.as_mut_ptr().write(key);
+            assert_ne!((*key_slot.as_ptr()).get(), &[0u8; 32]);
+
+            assert_eq!(bytes_after_drop(&mut key_slot, AESKey::get), [0u8; 32]);
+            assert_eq!(bytes_after_drop(&mut seed_slot, Seed::as_slice), [0u8; 32]);
+        }
+    }
 
     // commented since this is all outdated
     // // todo: fix test vectors to actually work
@@ -1,29 +1,57 @@
 use crate::consts::*;
 use crate::crypto::traits::*;
 use crate::crypto::CryptoError;
-use crate::crypto::{AESKey, KeyPair, Seed};
+use crate::crypto::{seal_activation_height, unseal_activation_height, AESKey, KeyPair, Seed};
+use crate::trace_secret;
 use enclave_ffi_types::EnclaveError;
 use lazy_static::lazy_static;
 use log::*;
+use std::sync::{SgxRwLock, SgxRwLockReadGuard};
+
+/// Which consensus seed a value was encrypted under. The seed the network started with is
+/// generation 0, and every rotation of the seed adds one.
+pub type KeyGeneration = u8;
 
 pub struct Keychain {
+    /// Only written when the keychain is set up, or when `erase` wipes it
+    keys: SgxRwLock<Keys>,
+}
+
+#[derive(Default)]
+struct Keys {
     consensus_seed: Option<Seed>,
+    consensus_generation: KeyGeneration,
+    /// The state keys of the seeds before the current one, indexed by their generation. They are
+    /// kept so that state written before a rotation can still be decrypted.
+    previous_consensus_state_ikms: Vec<AESKey>,
     consensus_state_ikm: Option<AESKey>,
+    /// Seeds of rotations that were sealed but aren't used yet, by generation
+    pending_generations: Vec<PendingGeneration>,
     consensus_seed_exchange_keypair: Option<KeyPair>,
     consensus_io_exchange_keypair: Option<KeyPair>,
     consensus_callback_secret: Option<AESKey>,
+    consensus_randomness_secret: Option<AESKey>,
+    /// Derived from the seed of generation 0, unlike the other secrets, see
+    /// `derive_consensus_master_keys`
+    consensus_signing_secret: Option<AESKey>,
     registration_key: Option<KeyPair>,
 }
 
+/// A rotated consensus seed, and the first block height whose executions use it
+struct PendingGeneration {
+    activation_height: u64,
+    seed: Seed,
+}
+
 lazy_static! {
     pub static ref KEY_MANAGER: Keychain = Keychain::new();
 }
 
 impl Keychain {
     pub fn new() -> Self {
-        let consensus_seed = match Seed::unseal(&CONSENSUS_SEED_SEALING_PATH) {
-            Ok(k) => Some(k),
-            Err(_e) => None,
+        let consensus_seeds = match Seed::unseal(&CONSENSUS_SEED_SEALING_PATH) {
+            Ok(seed) => vec![seed],
+            Err(_e) => vec![],
         };
 
         let registration_key = match KeyPair::unseal(&REGISTRATION_KEY_SEALING_PATH) {
@@ -31,13 +59,59 @@ impl Keychain {
             Err(_e) => None,
         };
 
+        let mut keychain = Self::from_consensus_seeds(consensus_seeds, registration_key);
+
+        // Every rotation sealed the new seed next to the ones before it. They are used again once
+        // an execution reaches their activation height, see `activate_generations`.
+        let keys = keychain.keys_mut();
+        if keys.consensus_seed.is_some() {
+            for generation in 1..=KeyGeneration::MAX {
+                let seed = match Seed::unseal(&consensus_seed_sealing_path(generation)) {
+                    Ok(seed) => seed,
+                    Err(_e) => break,
+                };
+                let activation_height =
+                    match unseal_activation_height(&consensus_seed_activation_path(generation)) {
+                        Ok(activation_height) => activation_height,
+                        Err(_e) => {
+                            error!(
+                                "The consensus seed of generation {} has no activation height",
+                                generation
+                            );
+                            break;
+                        }
+                    };
+                keys.pending_generations.push(PendingGeneration {
+                    activation_height,
+                    seed,
+                });
+            }
+        }
+
+        keychain
+    }
+
+    /// `consensus_seeds` are ordered by generation, so the last one is the current seed
+    pub(crate) fn from_consensus_seeds(
+        mut consensus_seeds: Vec<Seed>,
+        registration_key: Option<KeyPair>,
+    ) -> Self {
+        let consensus_signing_secret = consensus_seeds.first().map(derive_consensus_signing_secret);
+        let consensus_seed = consensus_seeds.pop();
+        let previous_consensus_state_ikms: Vec<AESKey> = consensus_seeds
+            .iter()
+            .map(derive_consensus_state_ikm)
+            .collect();
+
         let mut x = Keychain {
-            consensus_seed,
-            registration_key,
-            consensus_state_ikm: None,
-            consensus_seed_exchange_keypair: None,
-            consensus_io_exchange_keypair: None,
-            consensus_callback_secret: None,
+            keys: SgxRwLock::new(Keys {
+                consensus_seed,
+                consensus_generation: previous_consensus_state_ikms.len() as KeyGeneration,
+                previous_consensus_state_ikms,
+                consensus_signing_secret,
+                registration_key,
+                ..Keys::default()
+            }),
         };
 
         let _ = x.generate_consensus_master_keys();
@@ -45,6 +119,21 @@ impl Keychain {
         x
     }
 
+    fn keys(&self) -> SgxRwLockReadGuard<Keys> {
+        self.keys.read().unwrap()
+    }
+
+    fn keys_mut(&mut self) -> &mut Keys {
+        self.keys.get_mut().unwrap()
+    }
+
+    /// Wipe every key in the keychain, which is then that of a node that was never initialized.
+    /// The sealed copies of the seeds and of the registration key are left as they are.
+    pub fn erase(&self) {
+        // Every key is wiped when it's dropped
+        *self.keys.write().unwrap() = Keys::default();
+    }
+
     pub fn create_consensus_seed(&mut self) -> Result<(), CryptoError> {
         match Seed::new() {
             Ok(seed) => {
@@ -70,46 +159,122 @@ impl Keychain {
     }
 
     pub fn is_consensus_seed_set(&self) -> bool {
-        self.consensus_seed.is_some()
+        self.keys().consensus_seed.is_some()
     }
 
     pub fn get_consensus_state_ikm(&self) -> Result<AESKey, CryptoError> {
-        self.consensus_state_ikm.ok_or_else(|| {
+        self.keys().consensus_state_ikm.clone().ok_or_else(|| {
             error!("Error accessing base_state_key (does not exist, or was not initialized)");
             CryptoError::ParsingError
         })
     }
 
+    pub fn get_consensus_generation(&self) -> KeyGeneration {
+        self.keys().consensus_generation
+    }
+
+    /// The generation of the last seed this node has, including the ones that aren't used yet
+    pub fn get_latest_generation(&self) -> KeyGeneration {
+        let keys = self.keys();
+        keys.consensus_generation + keys.pending_generations.len() as KeyGeneration
+    }
+
+    /// Start using the rotated seeds whose activation height is at most `height`. Every node
+    /// switches at the same height, so they all keep deriving the same keys.
+    pub fn activate_generations(&self, height: u64) {
+        let is_due = |keys: &Keys| {
+            keys.pending_generations
+                .first()
+                .map_or(false, |pending| pending.activation_height <= height)
+        };
+        if !is_due(&self.keys()) {
+            return;
+        }
+
+        let mut keys = self.keys.write().unwrap();
+        while is_due(&keys) {
+            let pending = keys.pending_generations.remove(0);
+            if let Some(state_ikm) = keys.consensus_state_ikm.take() {
+                keys.previous_consensus_state_ikms.push(state_ikm);
+            }
+            keys.consensus_generation += 1;
+            keys.consensus_seed = Some(pending.seed);
+            keys.derive_consensus_master_keys();
+            info!(
+                "Using the consensus seed of generation {} from height {}",
+                keys.consensus_generation, pending.activation_height
+            );
+        }
+    }
+
+    /// The state key of the consensus seed of `generation`, if this node has that seed
+    pub fn get_consensus_state_ikm_of(&self, generation: KeyGeneration) -> Option<AESKey> {
+        let keys = self.keys();
+        if generation == keys.consensus_generation {
+            keys.consensus_state_ikm.clone()
+        } else {
+            keys.previous_consensus_state_ikms
+                .get(generation as usize)
+                .cloned()
+        }
+    }
+
+    /// The state keys of all the consensus seeds this node has, the current one first
+    pub fn get_all_consensus_state_ikms(&self) -> Vec<AESKey> {
+        let keys = self.keys();
+        keys.consensus_state_ikm
+            .iter()
+            .chain(keys.previous_consensus_state_ikms.iter().rev())
+            .cloned()
+            .collect()
+    }
+
     pub fn get_consensus_seed(&self) -> Result<Seed, CryptoError> {
-        self.consensus_seed.ok_or_else(|| {
+        self.keys().consensus_seed.clone().ok_or_else(|| {
             error!("Error accessing consensus_seed (does not exist, or was not initialized)");
             CryptoError::ParsingError
         })
     }
 
     pub fn seed_exchange_key(&self) -> Result<KeyPair, CryptoError> {
-        self.consensus_seed_exchange_keypair.ok_or_else(|| {
+        self.keys().consensus_seed_exchange_keypair.clone().ok_or_else(|| {
             error!("Error accessing consensus_seed_exchange_keypair (does not exist, or was not initialized)");
             CryptoError::ParsingError
         })
     }
 
     pub fn get_consensus_io_exchange_keypair(&self) -> Result<KeyPair, CryptoError> {
-        self.consensus_io_exchange_keypair.ok_or_else(|| {
+        self.keys().consensus_io_exchange_keypair.clone().ok_or_else(|| {
             error!("Error accessing consensus_io_exchange_keypair (does not exist, or was not initialized)");
             CryptoError::ParsingError
         })
     }
 
     pub fn get_consensus_callback_secret(&self) -> Result<AESKey, CryptoError> {
-        self.consensus_callback_secret.ok_or_else(|| {
+        self.keys().consensus_callback_secret.clone().ok_or_else(|| {
             error!("Error accessing consensus_callback_secret (does not exist, or was not initialized)");
             CryptoError::ParsingError
         })
     }
 
+    pub fn get_consensus_randomness_secret(&self) -> Result<AESKey, CryptoError> {
+        self.keys().consensus_randomness_secret.clone().ok_or_else(|| {
+            error!("Error accessing consensus_randomness_secret (does not exist, or was not initialized)");
+            CryptoError::ParsingError
+        })
+    }
+
+    pub fn get_consensus_signing_secret(&self) -> Result<AESKey, CryptoError> {
+        self.keys().consensus_signing_secret.clone().ok_or_else(|| {
+            error!(
+                "Error accessing consensus_signing_secret (does not exist, or was not initialized)"
+            );
+            CryptoError::ParsingError
+        })
+    }
+
     pub fn get_registration_key(&self) -> Result<KeyPair, CryptoError> {
-        self.registration_key.ok_or_else(|| {
+        self.keys().registration_key.clone().ok_or_else(|| {
             error!("Error accessing registration_key (does not exist, or was not initialized)");
             CryptoError::ParsingError
         })
@@ -120,24 +285,32 @@ impl Keychain {
             error!("Error sealing registration key");
             return Err(e);
         }
-        self.registration_key = Some(kp);
+        self.keys_mut().registration_key = Some(kp);
         Ok(())
     }
 
     pub fn set_consensus_seed_exchange_keypair(&mut self, kp: KeyPair) {
-        self.consensus_seed_exchange_keypair = Some(kp)
+        self.keys_mut().consensus_seed_exchange_keypair = Some(kp)
     }
 
     pub fn set_consensus_io_exchange_keypair(&mut self, kp: KeyPair) {
-        self.consensus_io_exchange_keypair = Some(kp)
+        self.keys_mut().consensus_io_exchange_keypair = Some(kp)
     }
 
     pub fn set_consensus_state_ikm(&mut self, consensus_state_ikm: AESKey) {
-        self.consensus_state_ikm = Some(consensus_state_ikm);
+        self.keys_mut().consensus_state_ikm = Some(consensus_state_ikm);
     }
 
     pub fn set_consensus_callback_secret(&mut self, consensus_callback_secret: AESKey) {
-        self.consensus_callback_secret = Some(consensus_callback_secret);
+        self.keys_mut().consensus_callback_secret = Some(consensus_callback_secret);
+    }
+
+    pub fn set_consensus_randomness_secret(&mut self, consensus_randomness_secret: AESKey) {
+        self.keys_mut().consensus_randomness_secret = Some(consensus_randomness_secret);
+    }
+
+    pub fn set_consensus_signing_secret(&mut self, consensus_signing_secret: AESKey) {
+        self.keys_mut().consensus_signing_secret = Some(consensus_signing_secret);
     }
 
     pub fn set_consensus_seed(&mut self, consensus_seed: Seed) -> Result<(), EnclaveError> {
@@ -145,64 +318,137 @@ impl Keychain {
             error!("Error sealing consensus_seed");
             return Err(e);
         }
-        self.consensus_seed = Some(consensus_seed);
+        self.keys_mut().consensus_seed = Some(consensus_seed);
         Ok(())
     }
 
-    pub fn generate_consensus_master_keys(&mut self) -> Result<(), EnclaveError> {
-        if !self.is_consensus_seed_set() {
-            trace!("Seed not initialized, skipping derivation of enclave keys");
-            return Ok(());
+    /// Seal `new_seed` as the next generation, to be used from `activation_height` on. Until then
+    /// the current seed is used, and after it the state key of the current seed is kept so state
+    /// encrypted under it can still be read.
+    pub fn rotate_consensus_seed(
+        &self,
+        new_seed: Seed,
+        activation_height: u64,
+    ) -> Result<KeyGeneration, EnclaveError> {
+        let mut keys = self.keys.write().unwrap();
+        if keys.consensus_seed.is_none() {
+            error!("Can't rotate the consensus seed before it was initialized");
+            return Err(EnclaveError::FailedSeal);
         }
+        let latest_generation = keys.consensus_generation as usize + keys.pending_generations.len();
+        if latest_generation >= KeyGeneration::MAX as usize {
+            error!("The consensus seed was rotated too many times");
+            return Err(EnclaveError::FailedSeal);
+        }
+        let generation = latest_generation as KeyGeneration + 1;
+        if let Some(last) = keys.pending_generations.last() {
+            if activation_height <= last.activation_height {
+                error!(
+                    "Generation {} must be activated after height {}",
+                    generation, last.activation_height
+                );
+                return Err(EnclaveError::FailedSeal);
+            }
+        }
+
+        if let Err(e) = new_seed.seal(&consensus_seed_sealing_path(generation)) {
+            error!("Error sealing consensus_seed of generation {}", generation);
+            return Err(e);
+        }
+        if let Err(e) = seal_activation_height(
+            activation_height,
+            &consensus_seed_activation_path(generation),
+        ) {
+            error!(
+                "Error sealing the activation height of generation {}",
+                generation
+            );
+            return Err(e);
+        }
+
+        keys.pending_generations.push(PendingGeneration {
+            activation_height,
+            seed: new_seed,
+        });
+
+        Ok(generation)
+    }
+
+    pub fn generate_consensus_master_keys(&mut self) -> Result<(), EnclaveError> {
+        self.keys_mut().derive_consensus_master_keys();
+        Ok(())
+    }
+}
+
+impl Keys {
+    fn derive_consensus_master_keys(&mut self) {
+        let consensus_seed = match self.consensus_seed.clone() {
+            Some(consensus_seed) => consensus_seed,
+            None => {
+                trace!("Seed not initialized, skipping derivation of enclave keys");
+                return;
+            }
+        };
 
         // consensus_seed_exchange_keypair
 
-        let consensus_seed_exchange_keypair_bytes = self
-            .consensus_seed
-            .unwrap()
+        let consensus_seed_exchange_keypair_bytes = consensus_seed
             .derive_key_from_this(&CONSENSUS_SEED_EXCHANGE_KEYPAIR_DERIVE_ORDER.to_be_bytes());
         let consensus_seed_exchange_keypair = KeyPair::from(consensus_seed_exchange_keypair_bytes);
         trace!(
             "consensus_seed_exchange_keypair: {:?}",
             consensus_seed_exchange_keypair.get_pubkey()
         );
-        self.set_consensus_seed_exchange_keypair(consensus_seed_exchange_keypair);
+        self.consensus_seed_exchange_keypair = Some(consensus_seed_exchange_keypair);
 
         // consensus_io_exchange_keypair
 
-        let consensus_io_exchange_keypair_bytes = self
-            .consensus_seed
-            .unwrap()
+        let consensus_io_exchange_keypair_bytes = consensus_seed
             .derive_key_from_this(&CONSENSUS_IO_EXCHANGE_KEYPAIR_DERIVE_ORDER.to_be_bytes());
         let consensus_io_exchange_keypair = KeyPair::from(consensus_io_exchange_keypair_bytes);
         trace!(
             "consensus_io_exchange_keypair: {:?}",
             consensus_io_exchange_keypair.get_pubkey()
         );
-        self.set_consensus_io_exchange_keypair(consensus_io_exchange_keypair);
+        self.consensus_io_exchange_keypair = Some(consensus_io_exchange_keypair);
 
         // consensus_state_ikm
 
-        let consensus_state_ikm = self
-            .consensus_seed
-            .unwrap()
-            .derive_key_from_this(&CONSENSUS_STATE_IKM_DERIVE_ORDER.to_be_bytes());
+        let consensus_state_ikm = derive_consensus_state_ikm(&consensus_seed);
 
-        trace!("consensus_state_ikm: {:?}", consensus_state_ikm.get());
-        self.set_consensus_state_ikm(consensus_state_ikm);
+        trace_secret!("consensus_state_ikm: {:?}", consensus_state_ikm.get());
+        self.consensus_state_ikm = Some(consensus_state_ikm);
 
-        let consensus_callback_secret = self
-            .consensus_seed
-            .unwrap()
+        let consensus_callback_secret = consensus_seed
             .derive_key_from_this(&CONSENSUS_CALLBACK_SECRET_DERIVE_ORDER.to_be_bytes());
 
-        trace!("consensus_state_ikm: {:?}", consensus_state_ikm.get());
-        self.set_consensus_callback_secret(consensus_callback_secret);
+        trace_secret!(
+            "consensus_callback_secret: {:?}",
+            consensus_callback_secret.get()
+        );
+        self.consensus_callback_secret = Some(consensus_callback_secret);
 
-        Ok(())
+        let consensus_randomness_secret = consensus_seed
+            .derive_key_from_this(&CONSENSUS_RANDOMNESS_SECRET_DERIVE_ORDER.to_be_bytes());
+
+        self.consensus_randomness_secret = Some(consensus_randomness_secret);
+
+        // Contracts register the public keys of their signing keys with other chains, so the
+        // signing secret stays the one of the seed of generation 0 when the seed is rotated
+        if self.consensus_signing_secret.is_none() {
+            self.consensus_signing_secret = Some(derive_consensus_signing_secret(&consensus_seed));
+        }
     }
 }
 
+fn derive_consensus_signing_secret(consensus_seed: &Seed) -> AESKey {
+    consensus_seed.derive_key_from_this(&CONSENSUS_SIGNING_SECRET_DERIVE_ORDER.to_be_bytes())
+}
+
+fn derive_consensus_state_ikm(consensus_seed: &Seed) -> AESKey {
+    consensus_seed.derive_key_from_this(&CONSENSUS_STATE_IKM_DERIVE_ORDER.to_be_bytes())
+}
+
 #[cfg(feature = "test")]
 pub mod tests {
 
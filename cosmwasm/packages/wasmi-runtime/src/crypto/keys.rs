@@ -5,6 +5,7 @@ use crate::crypto::CryptoError;
 
 use crate::crypto::ed25519::Ed25519PrivateKey;
 use sgx_types::sgx_align_ec256_private_t;
+use zeroize::Zeroize;
 
 pub const SEED_KEY_SIZE: usize = 32;
 
@@ -29,8 +30,9 @@ pub type DhKey = SymmetricKey;
 // #[derive(Debug, Clone, Copy)]
 // pub struct AESKey(SymmetricKey);
 
+/// Wiped when dropped, so it isn't `Copy`. Every clone is wiped on its own.
 #[repr(C, align(64))]
-#[derive(Clone, Copy, Default)]
+#[derive(Clone, Default)]
 pub struct AESKey {
     pub key: AlignedKey,
 }
@@ -58,6 +60,18 @@ impl AESKey {
     }
 }
 
+impl Zeroize for AESKey {
+    fn zeroize(&mut self) {
+        self.as_mut().zeroize();
+    }
+}
+
+impl Drop for AESKey {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
 impl AlignedMemory for AESKey {}
 impl ExportECKey for AESKey {
     fn key_ref(&self) -> &[u8; EC_256_PRIVATE_KEY_SIZE] {
@@ -74,8 +88,9 @@ impl From<Ed25519PrivateKey> for AESKey {
     }
 }
 
+/// Wiped when dropped, so it isn't `Copy`. Every clone is wiped on its own.
 #[repr(C, align(64))]
-#[derive(Clone, Copy, Default)]
+#[derive(Clone, Default)]
 pub struct Seed {
     pub key: AlignedSeed,
 }
@@ -111,4 +126,16 @@ impl From<Ed25519PrivateKey> for Seed {
     }
 }
 
+impl Zeroize for Seed {
+    fn zeroize(&mut self) {
+        self.as_mut().zeroize();
+    }
+}
+
+impl Drop for Seed {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
 impl AlignedMemory for Seed {}
@@ -1,4 +1,6 @@
+mod constant_time;
 mod errors;
+mod hash;
 pub(crate) mod kdf;
 pub mod key_manager;
 mod keys;
@@ -15,14 +17,25 @@ mod ed25519;
 pub mod multisig;
 pub mod secp256k1;
 
+pub use constant_time::{constant_time_eq, AuthenticationCode};
 pub use errors::CryptoError;
-pub use key_manager::Keychain;
+pub use hash::{keccak_256, ripemd_160, KECCAK_256_HASH_SIZE, RIPEMD_160_HASH_SIZE};
+pub use kdf::{hkdf_sha256, HKDF_SHA256_MAX_OUTPUT_SIZE};
 pub use key_manager::KEY_MANAGER;
+pub use key_manager::{KeyGeneration, Keychain};
 pub use keys::{AESKey, Seed, SymmetricKey, SEED_KEY_SIZE};
+pub use rng::rand_slice;
 
-pub use ed25519::{Ed25519PublicKey, KeyPair, PUBLIC_KEY_SIZE, SECRET_KEY_SIZE};
+pub use ed25519::{
+    ed25519_batch_verify, ed25519_verify, Ed25519PublicKey, KeyPair, PUBLIC_KEY_SIZE,
+    SECRET_KEY_SIZE, SIGNATURE_SIZE,
+};
 
-pub use sha::{sha_256, HASH_SIZE};
+pub use sha::{sha_256, Sha256, HASH_SIZE};
+pub use storage::{
+    seal_activation_height, seal_operator_public_key, seal_to_enclave, unseal_activation_height,
+    unseal_from_enclave, unseal_operator_public_key,
+};
 pub use traits::{Encryptable, Hmac, Kdf, SIVEncryptable, SealedKey, HMAC_SIGNATURE_SIZE};
 
 #[cfg(feature = "test")]
@@ -36,6 +49,10 @@ pub mod tests {
 
         count_failures!(failures, {
             // kdf::tests::test_derive_key();
+            kdf::tests::test_derived_key_is_zeroed_on_drop();
+            constant_time::tests::test_constant_time_eq();
+            constant_time::tests::test_authentication_code_verify();
+            constant_time::tests::test_authentication_code_is_not_partial_eq();
             // storage::tests::test_open();
             // storage::tests::test_seal();
             multisig::tests_decode_multisig_signature::test_decode_sig_sanity();
@@ -44,6 +61,18 @@ pub mod tests {
             multisig::tests_decode_multisig_signature::test_decode_malformed_sig_only_prefix();
             multisig::tests_decode_multisig_signature::test_decode_sig_length_zero();
             multisig::tests_decode_multisig_signature::test_decode_malformed_sig_wrong_length();
+            ed25519::tests::test_ed25519_batch_verify_valid();
+            ed25519::tests::test_ed25519_batch_verify_shared_message_and_key();
+            ed25519::tests::test_ed25519_batch_verify_invalid_signature();
+            ed25519::tests::test_ed25519_batch_verify_malformed_input();
+            secp256k1::tests::test_secp256k1_recover_pubkey_ethereum_personal_sign();
+            secp256k1::tests::test_secp256k1_recover_pubkey_invalid_signature();
+            secp256k1::tests::test_contract_signing_key_signatures_verify();
+            secp256k1::tests::test_contract_signing_key_rfc6979_vector();
+            secp256k1::tests::test_contract_signing_keys_are_scoped();
+            hash::tests::test_sha_256_known_vectors();
+            hash::tests::test_keccak_256_known_vectors();
+            hash::tests::test_ripemd_160_known_vectors();
         });
 
         if failures != 0 {
@@ -17,6 +17,12 @@ pub struct MultisigThresholdPubKey {
     pubkeys: Vec<PubKeyKind>,
 }
 
+impl MultisigThresholdPubKey {
+    pub fn new(threshold: u8, pubkeys: Vec<PubKeyKind>) -> Self {
+        Self { threshold, pubkeys }
+    }
+}
+
 impl PubKey for MultisigThresholdPubKey {
     fn get_address(&self) -> CanonicalAddr {
         // Spec: https://docs.tendermint.com/master/spec/core/encoding.html#key-types
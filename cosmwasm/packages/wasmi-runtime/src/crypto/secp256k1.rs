@@ -2,19 +2,32 @@ use log::*;
 
 use crate::cosmwasm::encoding::Binary;
 use crate::cosmwasm::types::CanonicalAddr;
-use crate::crypto::traits::PubKey;
-use crate::crypto::CryptoError;
+use crate::crypto::traits::{Kdf, PubKey};
+use crate::crypto::{AESKey, CryptoError};
 use ripemd160::{Digest, Ripemd160};
+use secp256k1::key::SecretKey;
+use secp256k1::recovery::{RecoverableSignature, RecoveryId};
 use secp256k1::Secp256k1;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest as Sha2Digest, Sha256};
+use zeroize::Zeroizing;
 
 const SECP256K1_PREFIX: [u8; 4] = [235, 90, 233, 135];
 
+pub const MESSAGE_HASH_SIZE: usize = 32;
+pub const SIGNATURE_SIZE: usize = 64;
+pub const UNCOMPRESSED_PUBLIC_KEY_SIZE: usize = 65;
+
 // TODO: Find a way to implement this better. secp256k1 is not ported to sgx, thus does not implement mesalock's serde and cannot be used
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Secp256k1PubKey(Vec<u8>);
 
+impl Secp256k1PubKey {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+}
+
 impl PubKey for Secp256k1PubKey {
     fn get_address(&self) -> CanonicalAddr {
         // Ref: https://github.com/tendermint/spec/blob/master/spec/blockchain/encoding.md#secp256k1
@@ -81,6 +94,94 @@ impl PubKey for Secp256k1PubKey {
     }
 }
 
+/// Recover the uncompressed public key that produced a compact `r || s` signature over
+/// `message_hash`.
+///
+/// Signatures whose r or s are out of range fail with `CryptoError::ParsingError`, and ones from
+/// which no public key can be recovered fail with `CryptoError::RecoveryError`.
+pub fn secp256k1_recover_pubkey(
+    message_hash: &[u8; MESSAGE_HASH_SIZE],
+    signature: &[u8; SIGNATURE_SIZE],
+    recovery_id: RecoveryId,
+) -> Result<[u8; UNCOMPRESSED_PUBLIC_KEY_SIZE], CryptoError> {
+    let msg = secp256k1::Message::from_slice(message_hash).map_err(|err| {
+        warn!("Failed to create a secp256k1 message from hash: {:?}", err);
+        CryptoError::ParsingError
+    })?;
+    let signature = RecoverableSignature::from_compact(signature, recovery_id).map_err(|err| {
+        debug!("Malformed recoverable signature: {:?}", err);
+        CryptoError::ParsingError
+    })?;
+
+    let public_key = Secp256k1::verification_only()
+        .recover(&msg, &signature)
+        .map_err(|err| {
+            debug!("Failed to recover public key from signature: {:?}", err);
+            CryptoError::RecoveryError
+        })?;
+
+    Ok(public_key.serialize_uncompressed())
+}
+
+/// A secp256k1 key the enclave derived for one contract. It never leaves the enclave: contracts
+/// only get its public key and the signatures it makes.
+pub struct ContractSigningKey(SecretKey);
+
+impl ContractSigningKey {
+    /// Derive the key of `path` for the contract at the canonical `contract_address`, from the
+    /// signing secret of the consensus seed. Every node derives the same key, and no other
+    /// contract or path does.
+    ///
+    /// The address of a contract never changes, so its keys stay the same when it's migrated or
+    /// its contract key is rotated.
+    pub fn derive(signing_secret: &AESKey, contract_address: &[u8], path: &[u8]) -> Self {
+        // The address is prefixed with its length and the counter comes last, so no two
+        // contracts or paths derive from the same data
+        let mut counter: u32 = 0;
+        loop {
+            let mut data = Zeroizing::new(Vec::with_capacity(
+                1 + contract_address.len() + path.len() + 4,
+            ));
+            data.push(contract_address.len() as u8);
+            data.extend_from_slice(contract_address);
+            data.extend_from_slice(path);
+            data.extend_from_slice(&counter.to_be_bytes());
+
+            // Fails only for the 2^-128 of secrets that are 0 or not below the curve order
+            let secret = signing_secret.derive_key_from_this(&data);
+            if let Ok(secret_key) = SecretKey::from_slice(secret.get()) {
+                return Self(secret_key);
+            }
+            counter += 1;
+        }
+    }
+
+    /// The uncompressed public key
+    pub fn public_key(&self) -> [u8; UNCOMPRESSED_PUBLIC_KEY_SIZE] {
+        secp256k1::PublicKey::from_secret_key(&Secp256k1::signing_only(), &self.0)
+            .serialize_uncompressed()
+    }
+
+    /// Sign `message_hash` with a nonce derived from the key and the hash as in RFC 6979, so
+    /// signing the same hash twice gives the same signature on every node. Returns the compact
+    /// `r || s` signature, with a low s, and its recovery id.
+    pub fn sign(
+        &self,
+        message_hash: &[u8; MESSAGE_HASH_SIZE],
+    ) -> Result<([u8; SIGNATURE_SIZE], u8), CryptoError> {
+        let msg = secp256k1::Message::from_slice(message_hash).map_err(|err| {
+            warn!("Failed to create a secp256k1 message from hash: {:?}", err);
+            CryptoError::SigningError
+        })?;
+
+        let (recovery_id, signature) = Secp256k1::signing_only()
+            .sign_recoverable(&msg, &self.0)
+            .serialize_compact();
+
+        Ok((signature, recovery_id.to_i32() as u8))
+    }
+}
+
 // TODO: Can we get rid of this comment below?
 
 // use super::keys::SECRET_KEY_SIZE;
@@ -239,3 +340,140 @@ impl PubKey for Secp256k1PubKey {
 //         assert_eq!(dhkey, b"SOME EXPECTED KEY");
 //     }
 // }
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    // web3.eth.accounts.sign("Some data", "0x4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318")
+    // The hash is keccak256("\x19Ethereum Signed Message:\n9Some data"), and the key belongs to
+    // 0x2c7536E3605D9C16a7a3D7b1898e529396a65c23
+    const ETH_MESSAGE_HASH: &str =
+        "1da44b586eb0729ff70a73c326926f6ed5a25f5b056e7f47fbc6e58d86871655";
+    const ETH_SIGNATURE: &str = "b91467e570a6466aa9e9876cbcd013baba02900b8979d43fe208a4a4f339f5fd6007e74cd82e037b800186422fc2da167c747ef045e5d18a5f5d4300f8e1a029";
+    // v = 0x1c
+    const ETH_RECOVERY_ID: i32 = 1;
+    const ETH_PUBLIC_KEY: &str = "044e3b81af9c2234cad09d679ce6035ed1392347ce64ce405f5dcd36228a25de6e47fd35c4215d1edf53e6f83de344615ce719bdb0fd878f6ed76f06dd277956de";
+
+    fn eth_vector() -> ([u8; MESSAGE_HASH_SIZE], [u8; SIGNATURE_SIZE]) {
+        let mut message_hash = [0u8; MESSAGE_HASH_SIZE];
+        message_hash.copy_from_slice(&hex::decode(ETH_MESSAGE_HASH).unwrap());
+        let mut signature = [0u8; SIGNATURE_SIZE];
+        signature.copy_from_slice(&hex::decode(ETH_SIGNATURE).unwrap());
+        (message_hash, signature)
+    }
+
+    pub fn test_secp256k1_recover_pubkey_ethereum_personal_sign() {
+        let (message_hash, signature) = eth_vector();
+        let recovery_id = RecoveryId::from_i32(ETH_RECOVERY_ID).unwrap();
+
+        let public_key = secp256k1_recover_pubkey(&message_hash, &signature, recovery_id).unwrap();
+        assert_eq!(hex::encode(&public_key[..]), ETH_PUBLIC_KEY);
+
+        // The other recovery id gives a different key
+        let other_id = RecoveryId::from_i32(0).unwrap();
+        let other_key = secp256k1_recover_pubkey(&message_hash, &signature, other_id);
+        assert!(other_key.map_or(true, |key| hex::encode(&key[..]) != ETH_PUBLIC_KEY));
+    }
+
+    pub fn test_secp256k1_recover_pubkey_invalid_signature() {
+        let (message_hash, mut signature) = eth_vector();
+        let recovery_id = RecoveryId::from_i32(ETH_RECOVERY_ID).unwrap();
+
+        // r is larger than the curve order
+        let mut overflowing = signature;
+        overflowing[..32].copy_from_slice(&[0xff; 32]);
+        assert!(matches!(
+            secp256k1_recover_pubkey(&message_hash, &overflowing, recovery_id),
+            Err(CryptoError::ParsingError)
+        ));
+
+        // r = 0 parses, but no key can be recovered from it
+        signature[..32].copy_from_slice(&[0; 32]);
+        assert!(matches!(
+            secp256k1_recover_pubkey(&message_hash, &signature, recovery_id),
+            Err(CryptoError::RecoveryError)
+        ));
+    }
+
+    fn signing_secret() -> AESKey {
+        AESKey::new_from_slice(&[7u8; 32])
+    }
+
+    /// Checks a signature the way anyone outside the enclave would, with only the public key
+    fn verify_externally(
+        public_key: &[u8; UNCOMPRESSED_PUBLIC_KEY_SIZE],
+        message_hash: &[u8; MESSAGE_HASH_SIZE],
+        signature: &[u8; SIGNATURE_SIZE],
+    ) -> bool {
+        let public_key = secp256k1::PublicKey::from_slice(public_key).unwrap();
+        let msg = secp256k1::Message::from_slice(message_hash).unwrap();
+        let signature = secp256k1::Signature::from_compact(signature).unwrap();
+        Secp256k1::verification_only()
+            .verify(&msg, &signature, &public_key)
+            .is_ok()
+    }
+
+    pub fn test_contract_signing_key_signatures_verify() {
+        let key = ContractSigningKey::derive(&signing_secret(), &[1u8; 20], b"bridge");
+        let public_key = key.public_key();
+        let message_hash = [0x42u8; MESSAGE_HASH_SIZE];
+
+        let (signature, recovery_id) = key.sign(&message_hash).unwrap();
+        assert!(verify_externally(&public_key, &message_hash, &signature));
+        let recovered = secp256k1_recover_pubkey(
+            &message_hash,
+            &signature,
+            RecoveryId::from_i32(recovery_id as i32).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(recovered[..], public_key[..]);
+
+        // The nonce is deterministic, so every node makes the same signature
+        assert_eq!(key.sign(&message_hash).unwrap(), (signature, recovery_id));
+
+        let mut other_hash = message_hash;
+        other_hash[0] ^= 1;
+        assert!(!verify_externally(&public_key, &other_hash, &signature));
+    }
+
+    /// The RFC 6979 vector that bitcoinjs and trezor test against: the key 1 signing
+    /// sha256("Satoshi Nakamoto")
+    pub fn test_contract_signing_key_rfc6979_vector() {
+        let mut one = [0u8; 32];
+        one[31] = 1;
+        let key = ContractSigningKey(SecretKey::from_slice(&one).unwrap());
+
+        let mut message_hash = [0u8; MESSAGE_HASH_SIZE];
+        message_hash.copy_from_slice(&Sha256::digest(b"Satoshi Nakamoto"));
+        let (signature, _) = key.sign(&message_hash).unwrap();
+        assert_eq!(
+            hex::encode(&signature[..]),
+            "934b1ea10a4b3c1757e2b0c017d0b6143ce3c9a7e6a4a49860d7a6ab210ee3d8\
+             2442ce9d2b916064108014783e923ec36b49743e2ffa1c4496f01a512aafd9e5"
+        );
+    }
+
+    pub fn test_contract_signing_keys_are_scoped() {
+        let public_key = |contract_address: &[u8], path: &[u8]| {
+            ContractSigningKey::derive(&signing_secret(), contract_address, path)
+                .public_key()
+                .to_vec()
+        };
+
+        let key = public_key(&[1u8; 20], b"bridge");
+        assert_eq!(public_key(&[1u8; 20], b"bridge"), key);
+        assert_ne!(public_key(&[2u8; 20], b"bridge"), key);
+        assert_ne!(public_key(&[1u8; 20], b"bridge2"), key);
+        assert_ne!(public_key(&[1u8; 20], b""), key);
+
+        // The path can't make up for a shorter address
+        let mut longer_address = vec![1u8; 20];
+        longer_address.push(b'b');
+        assert_ne!(public_key(&longer_address, b"ridge"), key);
+
+        let other_secret = AESKey::new_from_slice(&[8u8; 32]);
+        let other = ContractSigningKey::derive(&other_secret, &[1u8; 20], b"bridge");
+        assert_ne!(other.public_key().to_vec(), key);
+    }
+}
@@ -11,6 +11,37 @@ pub fn sha_256(data: &[u8]) -> [u8; HASH_SIZE] {
     result
 }
 
+/// Computes the SHA-256 digest of data that arrives in pieces
+#[derive(Clone)]
+pub struct Sha256 {
+    context: digest::Context,
+}
+
+impl Sha256 {
+    pub fn new() -> Self {
+        Self {
+            context: digest::Context::new(&digest::SHA256),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.context.update(data);
+    }
+
+    pub fn finish(self) -> [u8; HASH_SIZE] {
+        let mut result = [0u8; HASH_SIZE];
+        result.copy_from_slice(self.context.finish().as_ref());
+
+        result
+    }
+}
+
+impl Default for Sha256 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 // #[cfg(feature = "test")]
 // pub mod tests {
 //     use super::{
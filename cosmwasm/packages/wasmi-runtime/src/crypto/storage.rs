@@ -1,8 +1,13 @@
 use crate::crypto::ed25519::Ed25519PrivateKey;
 use crate::crypto::traits::SealedKey;
-use crate::crypto::{AESKey, KeyPair, Seed, SECRET_KEY_SIZE};
+use crate::crypto::{AESKey, KeyPair, Seed, PUBLIC_KEY_SIZE, SECRET_KEY_SIZE};
 use enclave_ffi_types::EnclaveError;
 use log::*;
+use sgx_tseal::SgxSealedData;
+use sgx_types::{
+    sgx_attributes_t, sgx_sealed_data_t, SGX_KEYPOLICY_MRENCLAVE, TSEAL_DEFAULT_FLAGSMASK,
+    TSEAL_DEFAULT_MISCMASK,
+};
 use std::io::{Read, Write};
 use std::sgxfs::SgxFile;
 
@@ -65,6 +70,118 @@ fn open(filepath: &str) -> Result<Ed25519PrivateKey, EnclaveError> {
     Ok(buf)
 }
 
+/// Seals the height from which a rotated consensus seed is used, see `rotate_consensus_seed`
+pub fn seal_activation_height(height: u64, filepath: &str) -> Result<(), EnclaveError> {
+    let mut file = SgxFile::create(filepath).map_err(|_err| EnclaveError::FailedSeal)?;
+
+    file.write_all(&height.to_be_bytes())
+        .map_err(|_err| EnclaveError::FailedSeal)
+}
+
+pub fn unseal_activation_height(filepath: &str) -> Result<u64, EnclaveError> {
+    let mut file = SgxFile::open(filepath).map_err(|_err| EnclaveError::FailedUnseal)?;
+
+    let mut height = [0u8; 8];
+    file.read_exact(&mut height)
+        .map_err(|_err| EnclaveError::FailedUnseal)?;
+    Ok(u64::from_be_bytes(height))
+}
+
+/// Seals the public key of the operator who may decommission the node, see `pin_operator`
+pub fn seal_operator_public_key(
+    public_key: &[u8; PUBLIC_KEY_SIZE],
+    filepath: &str,
+) -> Result<(), EnclaveError> {
+    let mut file = SgxFile::create(filepath).map_err(|_err| EnclaveError::FailedSeal)?;
+
+    file.write_all(public_key)
+        .map_err(|_err| EnclaveError::FailedSeal)
+}
+
+pub fn unseal_operator_public_key(filepath: &str) -> Result<[u8; PUBLIC_KEY_SIZE], EnclaveError> {
+    let mut file = SgxFile::open(filepath).map_err(|_err| EnclaveError::FailedUnseal)?;
+
+    let mut public_key = [0u8; PUBLIC_KEY_SIZE];
+    file.read_exact(&mut public_key)
+        .map_err(|_err| EnclaveError::FailedUnseal)?;
+    Ok(public_key)
+}
+
+/// Seals `data` to the identity of this enclave, so that only this enclave can read it.
+/// `additional_data` is sealed along with it, so it's authenticated but readable.
+pub fn seal_to_enclave(additional_data: &[u8], data: &[u8]) -> Result<Vec<u8>, EnclaveError> {
+    let attribute_mask = sgx_attributes_t {
+        flags: TSEAL_DEFAULT_FLAGSMASK,
+        xfrm: 0,
+    };
+    let sealed = SgxSealedData::<[u8]>::seal_data_ex(
+        SGX_KEYPOLICY_MRENCLAVE,
+        attribute_mask,
+        TSEAL_DEFAULT_MISCMASK,
+        additional_data,
+        data,
+    )
+    .map_err(|err| {
+        warn!("Failed to seal data: {}", err);
+        EnclaveError::FailedSeal
+    })?;
+
+    let sealed_len = SgxSealedData::<[u8]>::calc_raw_sealed_data_size(
+        additional_data.len() as u32,
+        data.len() as u32,
+    );
+    if sealed_len == u32::MAX {
+        warn!("Data is too large to seal");
+        return Err(EnclaveError::FailedSeal);
+    }
+
+    let mut buffer = aligned_buffer(sealed_len as usize);
+    let raw_sealed = buffer.as_mut_ptr() as *mut sgx_sealed_data_t;
+    if unsafe { sealed.to_raw_sealed_data_t(raw_sealed, sealed_len) }.is_none() {
+        warn!("Failed to write the sealed data");
+        return Err(EnclaveError::FailedSeal);
+    }
+
+    let bytes =
+        unsafe { std::slice::from_raw_parts(buffer.as_ptr() as *const u8, sealed_len as usize) };
+    Ok(bytes.to_vec())
+}
+
+/// Opens data sealed by `seal_to_enclave`, and returns its additional data and the data itself.
+/// This only works in the enclave that sealed it.
+pub fn unseal_from_enclave(sealed: &[u8]) -> Result<(Vec<u8>, Vec<u8>), EnclaveError> {
+    if sealed.len() > u32::MAX as usize {
+        return Err(EnclaveError::FailedUnseal);
+    }
+
+    let mut buffer = aligned_buffer(sealed.len());
+    let raw_sealed = buffer.as_mut_ptr() as *mut u8;
+    unsafe { std::ptr::copy_nonoverlapping(sealed.as_ptr(), raw_sealed, sealed.len()) };
+
+    let sealed = unsafe {
+        SgxSealedData::<[u8]>::from_raw_sealed_data_t(
+            raw_sealed as *mut sgx_sealed_data_t,
+            sealed.len() as u32,
+        )
+    }
+    .ok_or(EnclaveError::FailedUnseal)?;
+
+    let unsealed = sealed.unseal_data().map_err(|err| {
+        warn!("Failed to unseal data: {}", err);
+        EnclaveError::FailedUnseal
+    })?;
+
+    Ok((
+        unsealed.get_additional_txt().to_vec(),
+        unsealed.get_decrypt_txt().to_vec(),
+    ))
+}
+
+/// `sgx_sealed_data_t` has 8 byte fields, so it can't be read from an arbitrary `Vec<u8>`
+fn aligned_buffer(len: usize) -> Vec<u64> {
+    vec![0u64; (len + 7) / 8]
+}
+
 #[cfg(feature = "test")]
 pub mod tests {
 
@@ -0,0 +1,462 @@
+//! Timing the phases of ecalls, so that operators can see where the enclave spends its time.
+//!
+//! While tracing is on, every ecall that goes through `run_ecall` records a trace on its thread.
+//! The trace is a list of spans, each opened by `trace_span!` and closed when the value the macro
+//! returned is dropped: decrypting the input, loading the module, instantiating it, executing the
+//! contract, encrypting the output, and every ocall the contract causes. Ecalls that a contract
+//! makes through an ocall run on the same thread, and show up as a `NestedEcall` span in the trace
+//! of the ecall that is already running. The enclave keeps the traces of the last
+//! `ECALL_TRACES_KEPT` ecalls, and `ecall_get_trace` exports them.
+//!
+//! Tracing is off until the host turns it on with `ecall_configure_runtime`. While it's off, a span
+//! costs the branch in `trace_span!`, and nothing is written. SGX1 enclaves can't read the TSC, so
+//! the time is read from the monotonic clock of the host through `ocall_monotonic_now`. That costs
+//! an ocall when a span is opened and when it's closed, so traced ecalls run slower, and the
+//! durations include the time it takes to read the clock.
+//!
+//! The traces are exported in a compact binary format, where every integer is big-endian:
+//!
+//! ```text
+//! version: u8, spans_recorded: u64, spans_dropped: u64, ecall_count: u32,
+//! ecall_count times:
+//!     name_len: u8, name: [u8; name_len], dropped_spans: u32, span_count: u16,
+//!     span_count times:
+//!         phase: u8, depth: u8, start_ns: u64, duration_ns: u64
+//! ```
+//!
+//! Spans are in the order they were opened, and `start_ns` is the time since the ecall began. The
+//! first span of every ecall is its `Ecall` span, at depth 0, and the parent of a span is the last
+//! span before it with a depth of one less. `spans_recorded` and `spans_dropped` count the spans
+//! since the enclave started, `dropped_spans` those that didn't fit in the trace of the ecall.
+
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::sync::SgxMutex;
+
+use lazy_static::lazy_static;
+use sgx_types::sgx_status_t;
+
+use crate::consts::{ECALL_TRACES_KEPT, MAX_SPANS_PER_ECALL_TRACE};
+use crate::imports;
+use crate::metrics::Ocall;
+
+/// The version of the format `export` writes
+const TRACE_FORMAT_VERSION: u8 = 1;
+
+/// The phase of an ocall is this plus the index of the ocall in `Ocall`
+const OCALL_PHASE_BASE: u8 = 16;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static SPANS_RECORDED: AtomicU64 = AtomicU64::new(0);
+static SPANS_DROPPED: AtomicU64 = AtomicU64::new(0);
+
+lazy_static! {
+    /// The traces of the last `ECALL_TRACES_KEPT` ecalls, oldest first
+    static ref TRACES: SgxMutex<VecDeque<EcallTrace>> = SgxMutex::new(VecDeque::new());
+}
+
+thread_local! {
+    static ACTIVE_TRACE: RefCell<Option<ActiveTrace>> = RefCell::new(None);
+}
+
+/// What the enclave does during a span
+#[derive(Clone, Copy)]
+pub enum Phase {
+    Ecall,
+    NestedEcall,
+    DecryptInput,
+    LoadModule,
+    Instantiate,
+    Execute,
+    EncryptOutput,
+    Ocall(Ocall),
+}
+
+impl Phase {
+    fn code(self) -> u8 {
+        match self {
+            Phase::Ecall => 0,
+            Phase::NestedEcall => 1,
+            Phase::DecryptInput => 2,
+            Phase::LoadModule => 3,
+            Phase::Instantiate => 4,
+            Phase::Execute => 5,
+            Phase::EncryptOutput => 6,
+            Phase::Ocall(ocall) => OCALL_PHASE_BASE + ocall as u8,
+        }
+    }
+}
+
+#[inline(always)]
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Turn tracing on or off. The traces that were kept can still be exported after it's turned off.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+struct SpanRecord {
+    phase: u8,
+    depth: u8,
+    start: u64,
+    duration: u64,
+}
+
+struct EcallTrace {
+    ecall: String,
+    spans: Vec<SpanRecord>,
+    dropped_spans: u32,
+}
+
+/// The trace of the outermost ecall that runs on a thread
+struct ActiveTrace {
+    trace: EcallTrace,
+    started_at: u64,
+    /// The amount of spans that are open
+    depth: u8,
+}
+
+impl ActiveTrace {
+    fn new(ecall_name: &str, now: u64) -> Self {
+        Self {
+            trace: EcallTrace {
+                ecall: ecall_name.to_string(),
+                spans: vec![],
+                dropped_spans: 0,
+            },
+            started_at: now,
+            depth: 0,
+        }
+    }
+
+    /// Open a span, and return its index, unless the trace is full
+    fn open(&mut self, phase: Phase, now: u64) -> Option<usize> {
+        if self.trace.spans.len() >= MAX_SPANS_PER_ECALL_TRACE {
+            self.trace.dropped_spans = self.trace.dropped_spans.saturating_add(1);
+            SPANS_DROPPED.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+
+        self.trace.spans.push(SpanRecord {
+            phase: phase.code(),
+            depth: self.depth,
+            start: now.saturating_sub(self.started_at),
+            duration: 0,
+        });
+        self.depth = self.depth.saturating_add(1);
+        SPANS_RECORDED.fetch_add(1, Ordering::Relaxed);
+        Some(self.trace.spans.len() - 1)
+    }
+
+    fn close(&mut self, index: usize, now: u64) {
+        if let Some(span) = self.trace.spans.get_mut(index) {
+            span.duration = now
+                .saturating_sub(self.started_at)
+                .saturating_sub(span.start);
+        }
+        self.depth = self.depth.saturating_sub(1);
+    }
+}
+
+/// A span of the trace of the ecall that runs on this thread, which is closed when it's dropped
+pub struct Span {
+    index: usize,
+}
+
+impl Span {
+    /// Open a span of `phase`. Returns `None` if no ecall is traced on this thread, or its trace is
+    /// full. Use `trace_span!` instead, which doesn't read the clock while tracing is off.
+    pub fn enter(phase: Phase) -> Option<Self> {
+        let now = monotonic_now();
+        ACTIVE_TRACE.with(|active| {
+            let mut active = active.try_borrow_mut().ok()?;
+            let index = active.as_mut()?.open(phase, now)?;
+            Some(Self { index })
+        })
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        let now = monotonic_now();
+        ACTIVE_TRACE.with(|active| {
+            if let Ok(mut active) = active.try_borrow_mut() {
+                if let Some(active) = active.as_mut() {
+                    active.close(self.index, now);
+                }
+            }
+        });
+    }
+}
+
+/// Traces an ecall until it's dropped
+pub struct EcallTraceGuard {
+    span: Option<Span>,
+    /// Only the guard of the outermost ecall keeps the trace
+    outermost: bool,
+}
+
+impl EcallTraceGuard {
+    /// Begin the trace of `ecall_name`, or open a `NestedEcall` span if an ecall is already traced
+    /// on this thread. Returns `None` while tracing is off.
+    pub fn begin(ecall_name: &str) -> Option<Self> {
+        if !is_enabled() {
+            return None;
+        }
+
+        let now = monotonic_now();
+        let outermost = ACTIVE_TRACE.with(|active| {
+            let mut active = active.borrow_mut();
+            if active.is_some() {
+                return false;
+            }
+            *active = Some(ActiveTrace::new(ecall_name, now));
+            true
+        });
+
+        let phase = if outermost {
+            Phase::Ecall
+        } else {
+            Phase::NestedEcall
+        };
+        Some(Self {
+            span: Span::enter(phase),
+            outermost,
+        })
+    }
+}
+
+impl Drop for EcallTraceGuard {
+    fn drop(&mut self) {
+        // Closed before the trace is kept
+        drop(self.span.take());
+        if !self.outermost {
+            return;
+        }
+
+        if let Some(active) = ACTIVE_TRACE.with(|active| active.borrow_mut().take()) {
+            let mut traces = TRACES.lock().unwrap();
+            if traces.len() >= ECALL_TRACES_KEPT {
+                traces.pop_front();
+            }
+            traces.push_back(active.trace);
+        }
+    }
+}
+
+/// The traces of the last `max_ecalls` ecalls, oldest first, in the format described above
+pub fn export(max_ecalls: usize) -> Vec<u8> {
+    let traces = TRACES.lock().unwrap();
+    let skipped = traces.len().saturating_sub(max_ecalls);
+
+    let mut out = vec![TRACE_FORMAT_VERSION];
+    out.extend_from_slice(&SPANS_RECORDED.load(Ordering::Relaxed).to_be_bytes());
+    out.extend_from_slice(&SPANS_DROPPED.load(Ordering::Relaxed).to_be_bytes());
+    out.extend_from_slice(&((traces.len() - skipped) as u32).to_be_bytes());
+
+    for trace in traces.iter().skip(skipped) {
+        let name = &trace.ecall.as_bytes()[..trace.ecall.len().min(u8::MAX as usize)];
+        out.push(name.len() as u8);
+        out.extend_from_slice(name);
+        out.extend_from_slice(&trace.dropped_spans.to_be_bytes());
+        // `MAX_SPANS_PER_ECALL_TRACE` fits in a u16
+        out.extend_from_slice(&(trace.spans.len() as u16).to_be_bytes());
+        for span in &trace.spans {
+            out.push(span.phase);
+            out.push(span.depth);
+            out.extend_from_slice(&span.start.to_be_bytes());
+            out.extend_from_slice(&span.duration.to_be_bytes());
+        }
+    }
+
+    out
+}
+
+/// The time on the monotonic clock of the host, in nanoseconds, or 0 if it couldn't be read
+fn monotonic_now() -> u64 {
+    let mut now = 0u64;
+    let status = unsafe { imports::ocall_monotonic_now(&mut now) };
+    if status != sgx_status_t::SGX_SUCCESS {
+        return 0;
+    }
+    now
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+    use crate::count_failures;
+    use crate::trace_span;
+
+    use std::panic;
+
+    pub fn run_tests() {
+        println!();
+        let mut failures = 0;
+
+        count_failures!(failures, {
+            test_span_nesting();
+            test_disabled_tracing_writes_nothing();
+            test_traces_of_last_ecalls_are_kept();
+            test_full_trace_drops_spans();
+            test_export_format();
+        });
+
+        set_enabled(false);
+        TRACES.lock().unwrap().clear();
+
+        if failures != 0 {
+            panic!("{}: {} tests failed", file!(), failures);
+        }
+    }
+
+    /// Run `ecall` as a traced ecall called `name`, and return its trace
+    fn trace_ecall<F: FnOnce()>(name: &str, ecall: F) -> EcallTrace {
+        set_enabled(true);
+        TRACES.lock().unwrap().clear();
+        {
+            let _trace = EcallTraceGuard::begin(name);
+            ecall();
+        }
+        set_enabled(false);
+        TRACES.lock().unwrap().pop_back().unwrap()
+    }
+
+    fn phases(trace: &EcallTrace) -> Vec<(u8, u8)> {
+        trace
+            .spans
+            .iter()
+            .map(|span| (span.phase, span.depth))
+            .collect()
+    }
+
+    /// Check that every span lies within its parent
+    fn assert_nested(trace: &EcallTrace) {
+        let mut open: Vec<&SpanRecord> = vec![];
+        for span in &trace.spans {
+            open.truncate(span.depth as usize);
+            assert_eq!(open.len(), span.depth as usize, "span without a parent");
+            if let Some(parent) = open.last() {
+                assert!(span.start >= parent.start);
+                assert!(span.start + span.duration <= parent.start + parent.duration);
+            }
+            open.push(span);
+        }
+    }
+
+    fn test_span_nesting() {
+        let trace = trace_ecall("ecall_handle", || {
+            {
+                let _span = trace_span!(Phase::DecryptInput);
+            }
+            {
+                let _span = trace_span!(Phase::Execute);
+                {
+                    let _span = trace_span!(Phase::Ocall(Ocall::ReadDb));
+                }
+                let _span = trace_span!(Phase::Ocall(Ocall::QueryChain));
+                let _nested = EcallTraceGuard::begin("ecall_query");
+                let _span = trace_span!(Phase::Execute);
+            }
+            let _span = trace_span!(Phase::EncryptOutput);
+        });
+
+        assert_eq!(trace.ecall, "ecall_handle");
+        assert_eq!(
+            phases(&trace),
+            vec![
+                (Phase::Ecall.code(), 0),
+                (Phase::DecryptInput.code(), 1),
+                (Phase::Execute.code(), 1),
+                (Phase::Ocall(Ocall::ReadDb).code(), 2),
+                (Phase::Ocall(Ocall::QueryChain).code(), 2),
+                (Phase::NestedEcall.code(), 3),
+                (Phase::Execute.code(), 4),
+                (Phase::EncryptOutput.code(), 1),
+            ]
+        );
+        assert_nested(&trace);
+        assert!(ACTIVE_TRACE.with(|active| active.borrow().is_none()));
+    }
+
+    fn test_disabled_tracing_writes_nothing() {
+        set_enabled(false);
+        let traces_before = TRACES.lock().unwrap().len();
+        let recorded_before = SPANS_RECORDED.load(Ordering::Relaxed);
+        let dropped_before = SPANS_DROPPED.load(Ordering::Relaxed);
+
+        {
+            let trace = EcallTraceGuard::begin("ecall_handle");
+            assert!(trace.is_none());
+            let span = trace_span!(Phase::Execute);
+            assert!(span.is_none());
+            let _span = trace_span!(Phase::Ocall(Ocall::WriteDb));
+        }
+
+        assert!(ACTIVE_TRACE.with(|active| active.borrow().is_none()));
+        assert_eq!(TRACES.lock().unwrap().len(), traces_before);
+        assert_eq!(SPANS_RECORDED.load(Ordering::Relaxed), recorded_before);
+        assert_eq!(SPANS_DROPPED.load(Ordering::Relaxed), dropped_before);
+    }
+
+    fn test_traces_of_last_ecalls_are_kept() {
+        set_enabled(true);
+        TRACES.lock().unwrap().clear();
+        for index in 0..ECALL_TRACES_KEPT + 3 {
+            let _trace = EcallTraceGuard::begin(&format!("ecall_{}", index));
+        }
+        set_enabled(false);
+
+        let traces = TRACES.lock().unwrap();
+        assert_eq!(traces.len(), ECALL_TRACES_KEPT);
+        assert_eq!(traces.front().unwrap().ecall, "ecall_3");
+        assert_eq!(
+            traces.back().unwrap().ecall,
+            format!("ecall_{}", ECALL_TRACES_KEPT + 2)
+        );
+    }
+
+    fn test_full_trace_drops_spans() {
+        let trace = trace_ecall("ecall_handle", || {
+            for _ in 0..MAX_SPANS_PER_ECALL_TRACE + 5 {
+                let _span = trace_span!(Phase::Ocall(Ocall::DbNext));
+            }
+        });
+
+        assert_eq!(trace.spans.len(), MAX_SPANS_PER_ECALL_TRACE);
+        // The `Ecall` span took one of the places
+        assert_eq!(trace.dropped_spans, 6);
+        assert_nested(&trace);
+    }
+
+    fn test_export_format() {
+        set_enabled(true);
+        TRACES.lock().unwrap().clear();
+        for name in &["ecall_init", "ecall_query"] {
+            let _trace = EcallTraceGuard::begin(name);
+            let _span = trace_span!(Phase::Execute);
+        }
+        set_enabled(false);
+
+        let exported = export(1);
+        assert_eq!(exported[0], TRACE_FORMAT_VERSION);
+        // Only the last ecall
+        assert_eq!(&exported[17..21], &1u32.to_be_bytes());
+        assert_eq!(exported[21] as usize, "ecall_query".len());
+        assert_eq!(&exported[22..33], b"ecall_query");
+        assert_eq!(&exported[33..37], &0u32.to_be_bytes());
+        assert_eq!(&exported[37..39], &2u16.to_be_bytes());
+        assert_eq!(exported.len(), 39 + 2 * 18);
+        assert_eq!(exported[39..41], [Phase::Ecall.code(), 0]);
+        assert_eq!(exported[57..59], [Phase::Execute.code(), 1]);
+
+        let ecall_len = |name: &str| 1 + name.len() + 4 + 2 + 2 * 18;
+        assert_eq!(
+            export(10).len(),
+            21 + ecall_len("ecall_init") + ecall_len("ecall_query")
+        );
+    }
+}
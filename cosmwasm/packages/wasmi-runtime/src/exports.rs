@@ -1,24 +1,90 @@
-use lazy_static::lazy_static;
+//! The ecalls of the enclave.
+//!
+//! The host runs queries next to the execution of a block, so ecalls may run concurrently, each on
+//! a TCS of its own (see `TCSNum` in Enclave.config.xml). An ecall that a contract causes through
+//! an ocall, like the query it makes or `ecall_allocate`, runs on the thread of the ecall that made
+//! the ocall.
+//!
+//! The state of an execution is kept per thread: its gas (`wasm::gas`), the buffers of
+//! `ecall_allocate`, its log lines (`log_buffer`) and its recursion depth (`recursion_depth`). The
+//! keys in `KEY_MANAGER` are behind a read-write lock that only `ecall_decommission_confirm` ever
+//! takes for writing, so ecalls read them side by side. The caches that are shared between ecalls
+//! are behind mutexes that are never held while a contract runs or makes an ocall: the module
+//! cache, the query cache, the seen query nonces and the nonce registry. What remains serialized by
+//! its mutex are the writes of the execution of a block, such as the light client.
+//!
+//! Running out of memory is handled per call as well (`oom_handler`). The queries of the host
+//! share a safety buffer of their own and may only hold `QUERY_MEMORY_LIMIT` bytes together, so
+//! they can't take the memory the execution needs, nor the buffer it unwinds with.
+//!
+//! Storage isn't cached in the enclave. Every read of an ecall goes through the `Ctx` it got, and
+//! the host gives each query a view of the state at the height of its env.
+
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use log::*;
+use std::cell::{Cell, RefCell};
 use std::ffi::c_void;
 
 use enclave_ffi_types::{
-    Ctx, EnclaveBuffer, EnclaveError, HandleResult, HealthCheckResult, InitResult, QueryResult,
+    AllocationResult, Ctx, EnclaveBuffer, EnclaveCapabilities, EnclaveError,
+    ExportContractStateResult, HandleResult, HealthCheckDeepResult, HealthCheckResult,
+    IbcReceiveResult, IbcResult, ImportContractStateResult, InitResult, InputVerificationStep,
+    LastPanicReport, MigrateResult, QueryResult, RotateContractKeyResult, RuntimeConfiguration,
+    RuntimeStats, UpdateAdminResult, UserSpaceBuffer, ValidateWasmResult, VerifyInputResult,
+    PREWARM_ENTRY_SIZE,
 };
+use sgx_types::sgx_status_t;
 use std::panic;
-use std::sync::SgxMutex;
+use std::time::Duration;
 
-use crate::results::{
-    result_handle_success_to_handleresult, result_init_success_to_initresult,
-    result_query_success_to_queryresult,
+use crate::cosmwasm::types::{
+    BlockHeaderSubmission, ForeignChainInit, ForeignHeader, SealedForeignChain,
 };
+use crate::registration::decommission::ensure_not_decommissioned;
+use crate::results::{allocate_user_buffer, IntoEnclaveResult};
+use crate::wasm::IbcEntryPoint;
 use crate::{
-    oom_handler, recursion_depth,
+    consts::MAX_OCALL_RESPONSE_SIZE,
+    crypto::PUBLIC_KEY_SIZE,
+    ecall_trace, health_check, log_buffer, logger, metrics, oom_handler, panic_report,
+    recursion_depth, runtime_stats, seed_readiness,
     utils::{validate_const_ptr, validate_mut_ptr},
+    wasm,
 };
 
-lazy_static! {
-    static ref ECALL_ALLOCATE_STACK: SgxMutex<Vec<EnclaveBuffer>> = SgxMutex::new(Vec::new());
+/// The buffers `ecall_allocate` returned on one thread that were not recovered yet.
+///
+/// An ocall and the `ecall_allocate` it makes run on the thread of the ecall that made the ocall,
+/// so the buffers of concurrent ecalls are kept apart, and looking them up needs no lock.
+/// Buffers that are left when the thread is torn down are freed.
+#[derive(Default)]
+struct AllocateStack(Vec<EnclaveBuffer>);
+
+impl Drop for AllocateStack {
+    fn drop(&mut self) {
+        free_buffers(&mut self.0, 0);
+    }
+}
+
+thread_local! {
+    static ECALL_ALLOCATE_STACK: RefCell<AllocateStack> = RefCell::new(AllocateStack::default());
+
+    /// Set when `ecall_allocate` fails to copy a buffer, so that the ocall wrapper that receives
+    /// the failed ocall, or a null buffer from a host that ignored the failure, can tell why.
+    static ALLOCATION_FAILURE: Cell<Option<BufferRecoveryError>> = Cell::new(None);
+}
+
+/// The amount of buffers not recovered yet, on all threads
+static ALLOCATED_BUFFERS: AtomicUsize = AtomicUsize::new(0);
+
+/// The generation of the last buffer returned by `ecall_allocate`. Starts at 0 so that the first
+/// buffer gets generation 1, and a default `EnclaveBuffer` never matches a real one.
+static ECALL_ALLOCATE_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Counts a panic that `ecall_name` caught, and marks the report of the panic with it
+fn record_caught_panic(ecall_name: &str) {
+    runtime_stats::record_caught_panic();
+    panic_report::record_ecall(ecall_name);
 }
 
 /// Allocate a buffer in the enclave and return a pointer to it. This is useful for ocalls that
@@ -27,18 +93,45 @@ lazy_static! {
 /// it.
 ///
 /// host -> ecall_x -> ocall_x -> ecall_allocate
+///
+/// An empty buffer is returned as `EnclaveBuffer::empty`, which isn't allocated. When the buffer
+/// can't be copied, the error is also remembered for `take_allocation_failure`.
 /// # Safety
 /// Always use protection
 #[no_mangle]
-pub unsafe extern "C" fn ecall_allocate(buffer: *const u8, length: usize) -> EnclaveBuffer {
+pub unsafe extern "C" fn ecall_allocate(buffer: *const u8, length: usize) -> AllocationResult {
+    match allocate(buffer, length) {
+        Ok(buffer) => AllocationResult::Success { buffer },
+        Err(err) => {
+            ALLOCATION_FAILURE.with(|failure| failure.set(Some(err)));
+            AllocationResult::Failure {
+                err: err.into_enclave_error(),
+            }
+        }
+    }
+}
+
+unsafe fn allocate(buffer: *const u8, length: usize) -> Result<EnclaveBuffer, BufferRecoveryError> {
     if let Err(_err) = oom_handler::register_oom_handler() {
         error!("Could not register OOM handler!");
-        return EnclaveBuffer::default();
+        return Err(BufferRecoveryError::SafetyBufferUnavailable);
+    }
+
+    if length == 0 {
+        return Ok(EnclaveBuffer::empty());
     }
 
     if let Err(_e) = validate_const_ptr(buffer, length as usize) {
         error!("Tried to access data outside enclave memory space!");
-        return EnclaveBuffer::default();
+        return Err(BufferRecoveryError::InvalidPointer);
+    }
+
+    if length > MAX_OCALL_RESPONSE_SIZE {
+        error!(
+            "Refusing to allocate {} bytes in the enclave, the limit is {} bytes",
+            length, MAX_OCALL_RESPONSE_SIZE
+        );
+        return Err(BufferRecoveryError::ExceededOcallResponseSize);
     }
 
     let slice = std::slice::from_raw_parts(buffer, length);
@@ -48,41 +141,93 @@ pub unsafe extern "C" fn ecall_allocate(buffer: *const u8, length: usize) -> Enc
         let heap_pointer = Box::into_raw(boxed_vector);
         let enclave_buffer = EnclaveBuffer {
             ptr: heap_pointer as *mut c_void,
+            generation: ECALL_ALLOCATE_GENERATION.fetch_add(1, Ordering::SeqCst) + 1,
         };
-        ECALL_ALLOCATE_STACK
-            .lock()
-            .unwrap()
-            .push(enclave_buffer.unsafe_clone());
+        ECALL_ALLOCATE_STACK.with(|alloc_stack| {
+            alloc_stack
+                .borrow_mut()
+                .0
+                .push(enclave_buffer.unsafe_clone())
+        });
+        ALLOCATED_BUFFERS.fetch_add(1, Ordering::SeqCst);
         enclave_buffer
     });
 
     if let Err(_err) = oom_handler::restore_safety_buffer() {
         error!("Could not restore OOM safety buffer!");
-        return EnclaveBuffer::default();
+        return Err(BufferRecoveryError::SafetyBufferUnavailable);
     }
 
-    result.unwrap_or_else(|err| {
+    result.map_err(|err| {
         // We can get here only by failing to allocate memory,
         // so there's no real need here to test if oom happened
         error!("Enclave ran out of memory: {:?}", err);
+        record_caught_panic("ecall_allocate");
         oom_handler::get_then_clear_oom_happened();
-        EnclaveBuffer::default()
+        BufferRecoveryError::OutOfMemory
     })
 }
 
-#[derive(Debug, PartialEq)]
-pub struct BufferRecoveryError;
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BufferRecoveryError {
+    /// The buffer was not allocated by `ecall_allocate`, or was already recovered.
+    UnknownBuffer,
+    /// `ecall_allocate` refused to copy the buffer because it was too large.
+    ExceededOcallResponseSize,
+    /// `ecall_allocate` ran out of memory while copying the buffer.
+    OutOfMemory,
+    /// `ecall_allocate` was passed a pointer to a buffer that isn't in the memory of the host.
+    InvalidPointer,
+    /// `ecall_allocate` couldn't set aside the memory to recover from running out of it.
+    SafetyBufferUnavailable,
+}
+
+impl BufferRecoveryError {
+    fn into_enclave_error(self) -> EnclaveError {
+        match self {
+            BufferRecoveryError::UnknownBuffer | BufferRecoveryError::InvalidPointer => {
+                EnclaveError::HostMisbehavior
+            }
+            BufferRecoveryError::ExceededOcallResponseSize => {
+                EnclaveError::ExceededOcallResponseSize
+            }
+            BufferRecoveryError::OutOfMemory => EnclaveError::OutOfMemory,
+            BufferRecoveryError::SafetyBufferUnavailable => {
+                EnclaveError::MemorySafetyAllocationError
+            }
+        }
+    }
+}
+
+/// Why the last `ecall_allocate` on the calling thread failed, if it did and this wasn't asked
+/// yet. Ocalls fail when their response can't be copied, and their wrappers report this instead.
+pub fn take_allocation_failure() -> Option<BufferRecoveryError> {
+    ALLOCATION_FAILURE.with(|failure| failure.take())
+}
 
 /// Take a pointer as returned by `ecall_allocate` and recover the Vec<u8> inside of it.
+/// Only buffers that were allocated on the calling thread can be recovered.
 /// # Safety
 ///  This is a text
 pub unsafe fn recover_buffer(ptr: EnclaveBuffer) -> Result<Option<Vec<u8>>, BufferRecoveryError> {
     if ptr.ptr.is_null() {
+        if let Some(err) = take_allocation_failure() {
+            return Err(err);
+        }
         return Ok(None);
     }
+    if ptr.is_empty_buffer() {
+        return Ok(Some(vec![]));
+    }
 
-    let mut alloc_stack = ECALL_ALLOCATE_STACK.lock().unwrap();
+    ECALL_ALLOCATE_STACK
+        .with(|alloc_stack| recover_from_stack(&mut alloc_stack.borrow_mut().0, ptr))
+}
 
+unsafe fn recover_from_stack(
+    alloc_stack: &mut Vec<EnclaveBuffer>,
+    ptr: EnclaveBuffer,
+) -> Result<Option<Vec<u8>>, BufferRecoveryError> {
     // search the stack from the end for this pointer
     let maybe_index = alloc_stack
         .iter()
@@ -92,111 +237,347 @@ pub unsafe fn recover_buffer(ptr: EnclaveBuffer) -> Result<Option<Vec<u8>>, Buff
         // This index is probably at the end of the stack, but we give it a little more flexibility
         // in case access patterns change in the future
         let index = alloc_stack.len() - index_from_the_end - 1;
+        // The address may have been reused by a newer allocation after this buffer was already
+        // recovered. Leave the newer buffer alone so its rightful owner can still recover it.
+        if alloc_stack[index].generation != ptr.generation {
+            warn!(
+                "Refusing to recover a stale enclave buffer: expected generation {} but got {}",
+                alloc_stack[index].generation, ptr.generation
+            );
+            return Ok(None);
+        }
         alloc_stack.swap_remove(index);
+        ALLOCATED_BUFFERS.fetch_sub(1, Ordering::SeqCst);
     } else {
-        return Err(BufferRecoveryError);
+        return Err(BufferRecoveryError::UnknownBuffer);
     }
     let boxed_vector = Box::from_raw(ptr.ptr as *mut Vec<u8>);
     Ok(Some(*boxed_vector))
 }
 
-/// # Safety
-/// Always use protection
-#[no_mangle]
-pub unsafe extern "C" fn ecall_init(
-    context: Ctx,
-    gas_limit: u64,
-    used_gas: *mut u64,
-    contract: *const u8,
-    contract_len: usize,
-    env: *const u8,
-    env_len: usize,
-    msg: *const u8,
-    msg_len: usize,
-    sig_info: *const u8,
-    sig_info_len: usize,
-) -> InitResult {
-    let _recursion_guard = match recursion_depth::guard() {
-        Ok(rg) => rg,
-        Err(err) => {
-            // https://github.com/enigmampc/SecretNetwork/pull/517#discussion_r481924571
-            // I believe that this error condition is currently unreachable.
-            // I think we can safely remove it completely right now, and have
-            // recursion_depth::increment() simply increment the counter with no further checks,
-            // but i wanted to stay on the safe side here, in case something changes in the
-            // future, and we can easily spot that we forgot to add a limit somewhere.
-            error!("recursion limit exceeded, can not perform init!");
-            return InitResult::Failure { err };
+/// The amount of buffers allocated on the calling thread that were not recovered yet
+fn allocate_stack_len() -> usize {
+    ECALL_ALLOCATE_STACK.with(|alloc_stack| alloc_stack.borrow().0.len())
+}
+
+/// Free the buffers above `depth` in `alloc_stack`
+fn free_buffers(alloc_stack: &mut Vec<EnclaveBuffer>, depth: usize) {
+    if alloc_stack.len() > depth {
+        warn!(
+            "Freeing {} enclave buffers that were never recovered",
+            alloc_stack.len() - depth
+        );
+    }
+    while alloc_stack.len() > depth {
+        if let Some(buffer) = alloc_stack.pop() {
+            drop(unsafe { Box::from_raw(buffer.ptr as *mut Vec<u8>) });
+            ALLOCATED_BUFFERS.fetch_sub(1, Ordering::SeqCst);
         }
-    };
-    if let Err(err) = oom_handler::register_oom_handler() {
-        error!("Could not register OOM handler!");
-        return InitResult::Failure { err };
     }
-    if let Err(_e) = validate_mut_ptr(used_gas as _, std::mem::size_of::<u64>()) {
-        error!("Tried to access data outside enclave memory!");
-        return result_init_success_to_initresult(Err(EnclaveError::FailedFunctionCall));
+}
+
+/// Remembers the depth of the `ECALL_ALLOCATE_STACK` of its thread when an ecall starts. When the
+/// guard is dropped, any buffer that was pushed above that depth and never recovered is freed.
+///
+/// Buffers are normally recovered by the ocall wrapper that received them, so this only does
+/// something when the ecall panicked between the `ecall_allocate` and the `recover_buffer`.
+/// Nested ecalls (host -> ecall -> ocall -> ecall) each hold their own guard, so they only free
+/// the buffers that were allocated after they started.
+struct AllocateStackGuard {
+    depth: usize,
+}
+
+impl AllocateStackGuard {
+    fn new() -> Self {
+        Self {
+            depth: allocate_stack_len(),
+        }
     }
-    if let Err(_e) = validate_const_ptr(env, env_len as usize) {
-        error!("Tried to access data outside enclave memory!");
-        return result_init_success_to_initresult(Err(EnclaveError::FailedFunctionCall));
+}
+
+impl Drop for AllocateStackGuard {
+    fn drop(&mut self) {
+        ECALL_ALLOCATE_STACK
+            .with(|alloc_stack| free_buffers(&mut alloc_stack.borrow_mut().0, self.depth));
     }
-    if let Err(_e) = validate_const_ptr(msg, msg_len as usize) {
-        error!("Tried to access data outside enclave memory!");
-        return result_init_success_to_initresult(Err(EnclaveError::FailedFunctionCall));
+}
+
+/// The argument of an ecall that a buffer was passed in, so that the ecall can tell the host which
+/// of its pointers was invalid.
+#[derive(Clone, Copy)]
+enum EcallArg {
+    Contract,
+    Env,
+    Msg,
+    SigInfo,
+    Cursor,
+    NewAdmin,
+    SnapshotNonce,
+    StateArchive,
+}
+
+impl EcallArg {
+    fn invalid_pointer(self) -> EnclaveError {
+        match self {
+            EcallArg::Contract => EnclaveError::InvalidContractPointer,
+            EcallArg::Env => EnclaveError::InvalidEnvPointer,
+            EcallArg::Msg => EnclaveError::InvalidMsgPointer,
+            EcallArg::SigInfo => EnclaveError::InvalidSigInfoPointer,
+            EcallArg::Cursor
+            | EcallArg::NewAdmin
+            | EcallArg::SnapshotNonce
+            | EcallArg::StateArchive => EnclaveError::HostMisbehavior,
+        }
     }
-    if let Err(_e) = validate_const_ptr(contract, contract_len as usize) {
-        error!("Tried to access data outside enclave memory!");
-        return result_init_success_to_initresult(Err(EnclaveError::FailedFunctionCall));
+}
+
+/// A buffer the host passed to an ecall.
+#[derive(Clone, Copy)]
+struct EcallInput {
+    arg: EcallArg,
+    ptr: *const u8,
+    len: usize,
+    /// Whether the host may leave the buffer empty. An empty optional buffer isn't read.
+    optional: bool,
+}
+
+impl EcallInput {
+    fn new(arg: EcallArg, ptr: *const u8, len: usize) -> Self {
+        Self {
+            arg,
+            ptr,
+            len,
+            optional: false,
+        }
     }
-    if let Err(_e) = validate_const_ptr(sig_info, sig_info_len as usize) {
-        error!("Tried to access data outside enclave memory!");
-        return result_init_success_to_initresult(Err(EnclaveError::FailedFunctionCall));
+
+    fn optional(arg: EcallArg, ptr: *const u8, len: usize) -> Self {
+        Self {
+            arg,
+            ptr,
+            len,
+            optional: true,
+        }
     }
 
-    let contract = std::slice::from_raw_parts(contract, contract_len);
-    let env = std::slice::from_raw_parts(env, env_len);
-    let msg = std::slice::from_raw_parts(msg, msg_len);
-    let sig_info = std::slice::from_raw_parts(sig_info, sig_info_len);
+    fn is_empty_optional(&self) -> bool {
+        self.optional && self.len == 0
+    }
+
+    /// # Safety
+    /// The buffer must have been validated by `run_ecall`.
+    unsafe fn as_slice<'a>(self) -> &'a [u8] {
+        if self.is_empty_optional() {
+            &[]
+        } else {
+            std::slice::from_raw_parts(self.ptr, self.len)
+        }
+    }
+}
+
+/// How an ecall accounts for the gas of the contract it runs.
+#[derive(Clone, Copy)]
+enum EcallGas {
+    /// The contract runs with `gas_limit`, and the gas it used is written to `used_gas`.
+    Metered { gas_limit: u64, used_gas: *mut u64 },
+    /// The ecall doesn't run a contract, so there is no gas to account for.
+    Unmetered,
+}
+
+/// Run `operation` for the ecall `ecall_name`, and convert what it returns to the result of the
+/// ecall.
+///
+/// Before the operation runs, a metered ecall validates `used_gas` and enters another level of
+/// nested contract calls, then all of `inputs` are validated in order, and the ecall fails with
+/// `EnclaveError::EnclaveNotInitialized` if the consensus seed can't be unsealed or the node was
+/// decommissioned. The operation is passed the gas used so far, which is written back to `used_gas`
+/// when it returns. If it panics, or the enclave runs out of memory, the ecall fails and is charged
+/// `wasm::failed_ecall_gas`.
+///
+/// Every call is counted in `metrics`, along with the error it failed with and the gas it used.
+unsafe fn run_ecall<R, F>(ecall_name: &str, gas: EcallGas, inputs: &[EcallInput], operation: F) -> R
+where
+    R: IntoEnclaveResult,
+    F: FnOnce(&mut u64) -> Result<R::Success, R::Error> + panic::UnwindSafe,
+{
+    let result = run_ecall_operation(ecall_name, gas, inputs, operation);
+
+    // `used_gas` was only written if it's valid
+    let used_gas = match gas {
+        EcallGas::Metered { used_gas, .. }
+            if validate_mut_ptr(used_gas as _, std::mem::size_of::<u64>()).is_ok() =>
+        {
+            Some(*used_gas)
+        }
+        _ => None,
+    };
+    metrics::record_ecall(ecall_name, result.failure_err(), used_gas);
+
+    result
+}
+
+/// Everything `run_ecall` does, except for counting the call
+unsafe fn run_ecall_operation<R, F>(
+    ecall_name: &str,
+    gas: EcallGas,
+    inputs: &[EcallInput],
+    operation: F,
+) -> R
+where
+    R: IntoEnclaveResult,
+    F: FnOnce(&mut u64) -> Result<R::Success, R::Error> + panic::UnwindSafe,
+{
+    // Ends after the log lines were written out, so the trace includes their ocall
+    let _trace = ecall_trace::EcallTraceGuard::begin(ecall_name);
+    // Written out when the ecall returns, after whatever it logged about a panic
+    let _log_batch = log_buffer::LogBatch::begin();
+    let _recursion_guard = if let EcallGas::Metered { used_gas, .. } = gas {
+        if let Err(_e) = validate_mut_ptr(used_gas as _, std::mem::size_of::<u64>()) {
+            error!("Tried to access data outside enclave memory!");
+            return R::failure(EnclaveError::InvalidUsedGasPointer);
+        }
+        // Whatever the host left in `used_gas` must not leak into the result. Nothing was executed
+        // yet, so if any of the checks below fail, no gas is charged.
+        *used_gas = 0;
+        match recursion_depth::guard() {
+            Ok(rg) => Some(rg),
+            Err(err) => {
+                // https://github.com/enigmampc/SecretNetwork/pull/517#discussion_r481924571
+                // I believe that this error condition is currently unreachable.
+                // I think we can safely remove it completely right now, and have
+                // recursion_depth::increment() simply increment the counter with no further checks,
+                // but i wanted to stay on the safe side here, in case something changes in the
+                // future, and we can easily spot that we forgot to add a limit somewhere.
+                error!(
+                    "recursion limit exceeded, can not perform {}!",
+                    ecall_name.trim_start_matches("ecall_")
+                );
+                return R::failure(err);
+            }
+        }
+    } else {
+        None
+    };
+    if let Err(err) = oom_handler::register_oom_handler() {
+        error!("Could not register OOM handler!");
+        return R::failure(err);
+    }
+    for input in inputs {
+        if input.is_empty_optional() {
+            continue;
+        }
+        if let Err(_e) = validate_const_ptr(input.ptr, input.len) {
+            error!("Tried to access data outside enclave memory!");
+            return R::failure(input.arg.invalid_pointer());
+        }
+        // Checked before anything reads the contract, so an oversized one isn't parsed
+        if let EcallArg::Contract = input.arg {
+            if input.len > wasm::max_contract_size() {
+                warn!(
+                    "Got a contract of {} bytes, the limit is {} bytes",
+                    input.len,
+                    wasm::max_contract_size()
+                );
+                return R::failure(EnclaveError::ContractTooLarge);
+            }
+        }
+    }
+    // No contract can be decrypted without the consensus seed, so don't even read the inputs
+    if let Err(err) = seed_readiness::ensure_seed_ready() {
+        return R::failure(err);
+    }
+
+    // Frees buffers that ocalls allocated in the enclave if this ecall panics before recovering them
+    let _allocate_stack_guard = AllocateStackGuard::new();
+    if let EcallGas::Metered { .. } = gas {
+        // The gas metered until a panic is what the ecall is charged for it
+        wasm::clear_metered_gas();
+    }
+    #[cfg(feature = "debug-print")]
+    wasm::clear_trap_debug_info();
     let result = panic::catch_unwind(|| {
-        let mut local_used_gas = *used_gas;
-        let result = crate::wasm::init(
-            context,
-            gas_limit,
-            &mut local_used_gas,
-            contract,
-            env,
-            msg,
-            sig_info,
-        );
-        *used_gas = local_used_gas;
-        result_init_success_to_initresult(result)
+        let mut local_used_gas = match gas {
+            EcallGas::Metered { used_gas, .. } => *used_gas,
+            EcallGas::Unmetered => 0,
+        };
+        let result = operation(&mut local_used_gas);
+        if let EcallGas::Metered { used_gas, .. } = gas {
+            *used_gas = local_used_gas;
+        }
+        R::from_result(result)
     });
 
     if let Err(err) = oom_handler::restore_safety_buffer() {
         error!("Could not restore OOM safety buffer!");
-        return InitResult::Failure { err };
+        return R::failure(err);
     }
 
     if let Ok(res) = result {
         res
     } else {
-        *used_gas = gas_limit / 2;
+        if let EcallGas::Metered {
+            gas_limit,
+            used_gas,
+        } = gas
+        {
+            *used_gas = wasm::failed_ecall_gas(gas_limit);
+        }
+        record_caught_panic(ecall_name);
 
         if oom_handler::get_then_clear_oom_happened() {
-            error!("Call ecall_init failed because the enclave ran out of memory!");
-            InitResult::Failure {
-                err: EnclaveError::OutOfMemory,
-            }
+            error!(
+                "Call {} failed because the enclave ran out of memory!",
+                ecall_name
+            );
+            R::failure(EnclaveError::OutOfMemory)
         } else {
-            error!("Call ecall_init panicked unexpectedly!");
-            InitResult::Failure {
-                err: EnclaveError::Panic,
-            }
+            error!("Call {} panicked unexpectedly!", ecall_name);
+            R::failure(EnclaveError::Panic)
         }
     }
 }
 
+/// # Safety
+/// Always use protection
+#[no_mangle]
+pub unsafe extern "C" fn ecall_init(
+    context: Ctx,
+    gas_limit: u64,
+    used_gas: *mut u64,
+    contract: *const u8,
+    contract_len: usize,
+    env: *const u8,
+    env_len: usize,
+    msg: *const u8,
+    msg_len: usize,
+    sig_info: *const u8,
+    sig_info_len: usize,
+) -> InitResult {
+    let contract = EcallInput::new(EcallArg::Contract, contract, contract_len);
+    let env = EcallInput::new(EcallArg::Env, env, env_len);
+    let msg = EcallInput::new(EcallArg::Msg, msg, msg_len);
+    let sig_info = EcallInput::new(EcallArg::SigInfo, sig_info, sig_info_len);
+    run_ecall(
+        "ecall_init",
+        EcallGas::Metered {
+            gas_limit,
+            used_gas,
+        },
+        &[env, msg, contract, sig_info],
+        |used_gas| {
+            crate::wasm::init(
+                context,
+                gas_limit,
+                used_gas,
+                contract.as_slice(),
+                env.as_slice(),
+                msg.as_slice(),
+                sig_info.as_slice(),
+            )
+        },
+    )
+}
+
 /// # Safety
 /// Always use protection
 #[no_mangle]
@@ -212,177 +593,1271 @@ pub unsafe extern "C" fn ecall_handle(
     msg_len: usize,
     sig_info: *const u8,
     sig_info_len: usize,
+    // Nonzero to run the handle without changing the contract storage.
+    // Not a `bool`, since the host could pass a value that isn't a valid `bool`.
+    simulate: u8,
 ) -> HandleResult {
-    let _recursion_guard = match recursion_depth::guard() {
-        Ok(rg) => rg,
-        Err(err) => {
-            // https://github.com/enigmampc/SecretNetwork/pull/517#discussion_r481924571
-            // I believe that this error condition is currently unreachable.
-            // I think we can safely remove it completely right now, and have
-            // recursion_depth::increment() simply increment the counter with no further checks,
-            // but i wanted to stay on the safe side here, in case something changes in the
-            // future, and we can easily spot that we forgot to add a limit somewhere.
-            error!("recursion limit exceeded, can not perform handle!");
-            return HandleResult::Failure { err };
-        }
-    };
-    if let Err(err) = oom_handler::register_oom_handler() {
-        error!("Could not register OOM handler!");
-        return HandleResult::Failure { err };
-    }
-    if let Err(_e) = validate_mut_ptr(used_gas as _, std::mem::size_of::<u64>()) {
-        error!("Tried to access data outside enclave memory!");
-        return result_handle_success_to_handleresult(Err(EnclaveError::FailedFunctionCall));
-    }
-    if let Err(_e) = validate_const_ptr(env, env_len as usize) {
-        error!("Tried to access data outside enclave memory!");
-        return result_handle_success_to_handleresult(Err(EnclaveError::FailedFunctionCall));
-    }
-    if let Err(_e) = validate_const_ptr(msg, msg_len as usize) {
-        error!("Tried to access data outside enclave memory!");
-        return result_handle_success_to_handleresult(Err(EnclaveError::FailedFunctionCall));
-    }
-    if let Err(_e) = validate_const_ptr(contract, contract_len as usize) {
-        error!("Tried to access data outside enclave memory!");
-        return result_handle_success_to_handleresult(Err(EnclaveError::FailedFunctionCall));
-    }
-    if let Err(_e) = validate_const_ptr(sig_info, sig_info_len as usize) {
-        error!("Tried to access data outside enclave memory!");
-        return result_handle_success_to_handleresult(Err(EnclaveError::FailedFunctionCall));
-    }
-
-    let contract = std::slice::from_raw_parts(contract, contract_len);
-    let env = std::slice::from_raw_parts(env, env_len);
-    let msg = std::slice::from_raw_parts(msg, msg_len);
-    let sig_info = std::slice::from_raw_parts(sig_info, sig_info_len);
-    let result = panic::catch_unwind(|| {
-        let mut local_used_gas = *used_gas;
-        let result = crate::wasm::handle(
-            context,
+    let contract = EcallInput::new(EcallArg::Contract, contract, contract_len);
+    let env = EcallInput::new(EcallArg::Env, env, env_len);
+    let msg = EcallInput::new(EcallArg::Msg, msg, msg_len);
+    let sig_info = EcallInput::new(EcallArg::SigInfo, sig_info, sig_info_len);
+    run_ecall(
+        "ecall_handle",
+        EcallGas::Metered {
             gas_limit,
-            &mut local_used_gas,
-            contract,
-            env,
-            msg,
-            sig_info,
-        );
-        *used_gas = local_used_gas;
-        result_handle_success_to_handleresult(result)
-    });
-
-    if let Err(err) = oom_handler::restore_safety_buffer() {
-        error!("Could not restore OOM safety buffer!");
-        return HandleResult::Failure { err };
-    }
-
-    if let Ok(res) = result {
-        res
-    } else {
-        *used_gas = gas_limit / 2;
-
-        if oom_handler::get_then_clear_oom_happened() {
-            error!("Call ecall_handle failed because the enclave ran out of memory!");
-            HandleResult::Failure {
-                err: EnclaveError::OutOfMemory,
-            }
-        } else {
-            error!("Call ecall_handle panicked unexpectedly!");
-            HandleResult::Failure {
-                err: EnclaveError::Panic,
-            }
-        }
-    }
+            used_gas,
+        },
+        &[env, msg, contract, sig_info],
+        |used_gas| {
+            crate::wasm::handle(
+                context,
+                gas_limit,
+                used_gas,
+                contract.as_slice(),
+                env.as_slice(),
+                msg.as_slice(),
+                sig_info.as_slice(),
+                simulate != 0,
+            )
+        },
+    )
 }
 
 /// # Safety
 /// Always use protection
 #[no_mangle]
-pub unsafe extern "C" fn ecall_query(
+pub unsafe extern "C" fn ecall_reply(
     context: Ctx,
     gas_limit: u64,
     used_gas: *mut u64,
     contract: *const u8,
     contract_len: usize,
+    env: *const u8,
+    env_len: usize,
     msg: *const u8,
     msg_len: usize,
-) -> QueryResult {
-    let _recursion_guard = match recursion_depth::guard() {
-        Ok(rg) => rg,
-        Err(err) => {
-            // https://github.com/enigmampc/SecretNetwork/pull/517#discussion_r481924571
-            // I believe that this error condition is currently unreachable.
-            // I think we can safely remove it completely right now, and have
-            // recursion_depth::increment() simply increment the counter with no further checks,
-            // but i wanted to stay on the safe side here, in case something changes in the
-            // future, and we can easily spot that we forgot to add a limit somewhere.
-            error!("recursion limit exceeded, can not perform query!");
-            return QueryResult::Failure { err };
-        }
-    };
-    if let Err(err) = oom_handler::register_oom_handler() {
-        error!("Could not register OOM handler!");
-        return QueryResult::Failure { err };
-    }
-    if let Err(_e) = validate_mut_ptr(used_gas as _, std::mem::size_of::<u64>()) {
-        error!("Tried to access data outside enclave memory!");
-        return result_query_success_to_queryresult(Err(EnclaveError::FailedFunctionCall));
-    }
-    if let Err(_e) = validate_const_ptr(msg, msg_len as usize) {
-        error!("Tried to access data outside enclave memory!");
-        return result_query_success_to_queryresult(Err(EnclaveError::FailedFunctionCall));
-    }
-    if let Err(_e) = validate_const_ptr(contract, contract_len as usize) {
-        error!("Tried to access data outside enclave memory!");
-        return result_query_success_to_queryresult(Err(EnclaveError::FailedFunctionCall));
-    }
-
-    let contract = std::slice::from_raw_parts(contract, contract_len);
-    let msg = std::slice::from_raw_parts(msg, msg_len);
-    let result = panic::catch_unwind(|| {
-        let mut local_used_gas = *used_gas;
-        let result = crate::wasm::query(context, gas_limit, &mut local_used_gas, contract, msg);
-        *used_gas = local_used_gas;
-        result_query_success_to_queryresult(result)
-    });
-
-    if let Err(err) = oom_handler::restore_safety_buffer() {
-        error!("Could not restore OOM safety buffer!");
-        return QueryResult::Failure { err };
-    }
-
-    if let Ok(res) = result {
-        res
-    } else {
-        *used_gas = gas_limit / 2;
-
-        if oom_handler::get_then_clear_oom_happened() {
-            error!("Call ecall_query failed because the enclave ran out of memory!");
-            QueryResult::Failure {
-                err: EnclaveError::OutOfMemory,
-            }
-        } else {
-            error!("Call ecall_query panicked unexpectedly!");
-            QueryResult::Failure {
-                err: EnclaveError::Panic,
-            }
-        }
-    }
+) -> HandleResult {
+    let contract = EcallInput::new(EcallArg::Contract, contract, contract_len);
+    let env = EcallInput::new(EcallArg::Env, env, env_len);
+    let msg = EcallInput::new(EcallArg::Msg, msg, msg_len);
+    run_ecall(
+        "ecall_reply",
+        EcallGas::Metered {
+            gas_limit,
+            used_gas,
+        },
+        &[env, msg, contract],
+        |used_gas| {
+            crate::wasm::reply(
+                context,
+                gas_limit,
+                used_gas,
+                contract.as_slice(),
+                env.as_slice(),
+                msg.as_slice(),
+            )
+        },
+    )
 }
 
 /// # Safety
 /// Always use protection
 #[no_mangle]
-pub unsafe extern "C" fn ecall_health_check() -> HealthCheckResult {
-    HealthCheckResult::Success
+pub unsafe extern "C" fn ecall_migrate(
+    context: Ctx,
+    gas_limit: u64,
+    used_gas: *mut u64,
+    contract: *const u8,
+    contract_len: usize,
+    env: *const u8,
+    env_len: usize,
+    msg: *const u8,
+    msg_len: usize,
+    sig_info: *const u8,
+    sig_info_len: usize,
+) -> MigrateResult {
+    let contract = EcallInput::new(EcallArg::Contract, contract, contract_len);
+    let env = EcallInput::new(EcallArg::Env, env, env_len);
+    let msg = EcallInput::new(EcallArg::Msg, msg, msg_len);
+    let sig_info = EcallInput::new(EcallArg::SigInfo, sig_info, sig_info_len);
+    run_ecall(
+        "ecall_migrate",
+        EcallGas::Metered {
+            gas_limit,
+            used_gas,
+        },
+        &[env, msg, contract, sig_info],
+        |used_gas| {
+            crate::wasm::migrate(
+                context,
+                gas_limit,
+                used_gas,
+                contract.as_slice(),
+                env.as_slice(),
+                msg.as_slice(),
+                sig_info.as_slice(),
+            )
+        },
+    )
 }
 
-#[cfg(feature = "test")]
-pub mod tests {
-    use super::*;
-    use crate::count_failures;
+/// # Safety
+/// Always use protection
+#[no_mangle]
+pub unsafe extern "C" fn ecall_ibc_channel_open(
+    context: Ctx,
+    gas_limit: u64,
+    used_gas: *mut u64,
+    contract: *const u8,
+    contract_len: usize,
+    env: *const u8,
+    env_len: usize,
+    msg: *const u8,
+    msg_len: usize,
+) -> IbcResult {
+    ecall_ibc(
+        IbcEntryPoint::ChannelOpen,
+        context,
+        gas_limit,
+        used_gas,
+        contract,
+        contract_len,
+        env,
+        env_len,
+        msg,
+        msg_len,
+    )
+}
 
-    pub fn run_tests() {
-        println!();
+/// # Safety
+/// Always use protection
+#[no_mangle]
+pub unsafe extern "C" fn ecall_ibc_channel_connect(
+    context: Ctx,
+    gas_limit: u64,
+    used_gas: *mut u64,
+    contract: *const u8,
+    contract_len: usize,
+    env: *const u8,
+    env_len: usize,
+    msg: *const u8,
+    msg_len: usize,
+) -> IbcResult {
+    ecall_ibc(
+        IbcEntryPoint::ChannelConnect,
+        context,
+        gas_limit,
+        used_gas,
+        contract,
+        contract_len,
+        env,
+        env_len,
+        msg,
+        msg_len,
+    )
+}
+
+/// # Safety
+/// Always use protection
+#[no_mangle]
+pub unsafe extern "C" fn ecall_ibc_channel_close(
+    context: Ctx,
+    gas_limit: u64,
+    used_gas: *mut u64,
+    contract: *const u8,
+    contract_len: usize,
+    env: *const u8,
+    env_len: usize,
+    msg: *const u8,
+    msg_len: usize,
+) -> IbcResult {
+    ecall_ibc(
+        IbcEntryPoint::ChannelClose,
+        context,
+        gas_limit,
+        used_gas,
+        contract,
+        contract_len,
+        env,
+        env_len,
+        msg,
+        msg_len,
+    )
+}
+
+/// # Safety
+/// Always use protection
+#[no_mangle]
+pub unsafe extern "C" fn ecall_ibc_packet_receive(
+    context: Ctx,
+    gas_limit: u64,
+    used_gas: *mut u64,
+    contract: *const u8,
+    contract_len: usize,
+    env: *const u8,
+    env_len: usize,
+    msg: *const u8,
+    msg_len: usize,
+) -> IbcReceiveResult {
+    let contract = EcallInput::new(EcallArg::Contract, contract, contract_len);
+    let env = EcallInput::new(EcallArg::Env, env, env_len);
+    let msg = EcallInput::new(EcallArg::Msg, msg, msg_len);
+    run_ecall(
+        "ecall_ibc_packet_receive",
+        EcallGas::Metered {
+            gas_limit,
+            used_gas,
+        },
+        &[env, msg, contract],
+        |used_gas| {
+            crate::wasm::ibc_packet_receive(
+                context,
+                gas_limit,
+                used_gas,
+                contract.as_slice(),
+                env.as_slice(),
+                msg.as_slice(),
+            )
+        },
+    )
+}
+
+/// # Safety
+/// Always use protection
+#[no_mangle]
+pub unsafe extern "C" fn ecall_ibc_packet_ack(
+    context: Ctx,
+    gas_limit: u64,
+    used_gas: *mut u64,
+    contract: *const u8,
+    contract_len: usize,
+    env: *const u8,
+    env_len: usize,
+    msg: *const u8,
+    msg_len: usize,
+) -> IbcResult {
+    ecall_ibc(
+        IbcEntryPoint::PacketAck,
+        context,
+        gas_limit,
+        used_gas,
+        contract,
+        contract_len,
+        env,
+        env_len,
+        msg,
+        msg_len,
+    )
+}
+
+/// # Safety
+/// Always use protection
+#[no_mangle]
+pub unsafe extern "C" fn ecall_ibc_packet_timeout(
+    context: Ctx,
+    gas_limit: u64,
+    used_gas: *mut u64,
+    contract: *const u8,
+    contract_len: usize,
+    env: *const u8,
+    env_len: usize,
+    msg: *const u8,
+    msg_len: usize,
+) -> IbcResult {
+    ecall_ibc(
+        IbcEntryPoint::PacketTimeout,
+        context,
+        gas_limit,
+        used_gas,
+        contract,
+        contract_len,
+        env,
+        env_len,
+        msg,
+        msg_len,
+    )
+}
+
+/// The IBC entry points other than `ibc_packet_receive` all take the same arguments, and are
+/// handled the same way. See `crate::wasm::ibc`.
+#[allow(clippy::too_many_arguments)]
+unsafe fn ecall_ibc(
+    entry_point: IbcEntryPoint,
+    context: Ctx,
+    gas_limit: u64,
+    used_gas: *mut u64,
+    contract: *const u8,
+    contract_len: usize,
+    env: *const u8,
+    env_len: usize,
+    msg: *const u8,
+    msg_len: usize,
+) -> IbcResult {
+    let contract = EcallInput::new(EcallArg::Contract, contract, contract_len);
+    let env = EcallInput::new(EcallArg::Env, env, env_len);
+    let msg = EcallInput::new(EcallArg::Msg, msg, msg_len);
+    run_ecall(
+        entry_point.ecall_name(),
+        EcallGas::Metered {
+            gas_limit,
+            used_gas,
+        },
+        &[env, msg, contract],
+        |used_gas| {
+            crate::wasm::ibc(
+                context,
+                gas_limit,
+                used_gas,
+                contract.as_slice(),
+                env.as_slice(),
+                msg.as_slice(),
+                entry_point,
+            )
+        },
+    )
+}
+
+/// Rotate the key of a contract, one chunk of its storage at a time.
+/// See `crate::wasm::rotate_contract_key`.
+/// # Safety
+/// Always use protection
+#[no_mangle]
+pub unsafe extern "C" fn ecall_rotate_contract_key(
+    context: Ctx,
+    gas_limit: u64,
+    used_gas: *mut u64,
+    env: *const u8,
+    env_len: usize,
+    sig_info: *const u8,
+    sig_info_len: usize,
+    cursor: *const u8,
+    cursor_len: usize,
+    chunk_size: u32,
+) -> RotateContractKeyResult {
+    let env = EcallInput::new(EcallArg::Env, env, env_len);
+    let sig_info = EcallInput::new(EcallArg::SigInfo, sig_info, sig_info_len);
+    let cursor = EcallInput::new(EcallArg::Cursor, cursor, cursor_len);
+    run_ecall(
+        "ecall_rotate_contract_key",
+        EcallGas::Metered {
+            gas_limit,
+            used_gas,
+        },
+        &[env, sig_info, cursor],
+        |used_gas| {
+            crate::wasm::rotate_contract_key(
+                context,
+                gas_limit,
+                used_gas,
+                env.as_slice(),
+                sig_info.as_slice(),
+                cursor.as_slice(),
+                chunk_size,
+            )
+        },
+    )
+}
+
+/// Export one chunk of the storage of a contract, for a backup that can be imported later.
+/// See `crate::wasm::export_contract_state`.
+/// # Safety
+/// Always use protection
+#[no_mangle]
+pub unsafe extern "C" fn ecall_export_contract_state(
+    context: Ctx,
+    env: *const u8,
+    env_len: usize,
+    cursor: *const u8,
+    cursor_len: usize,
+    snapshot_nonce: *const u8,
+    snapshot_nonce_len: usize,
+    chunk_size: u32,
+) -> ExportContractStateResult {
+    let env = EcallInput::new(EcallArg::Env, env, env_len);
+    // Both are empty when an export starts
+    let cursor = EcallInput::optional(EcallArg::Cursor, cursor, cursor_len);
+    let snapshot_nonce =
+        EcallInput::optional(EcallArg::SnapshotNonce, snapshot_nonce, snapshot_nonce_len);
+    run_ecall(
+        "ecall_export_contract_state",
+        EcallGas::Unmetered,
+        &[env, cursor, snapshot_nonce],
+        |_used_gas| {
+            crate::wasm::export_contract_state(
+                context,
+                env.as_slice(),
+                cursor.as_slice(),
+                snapshot_nonce.as_slice(),
+                chunk_size,
+            )
+        },
+    )
+}
+
+/// Write one chunk of an exported archive back to the storage of the contract it was exported
+/// from, in a tx its admin signed. See `crate::wasm::import_contract_state`.
+/// # Safety
+/// Always use protection
+#[no_mangle]
+pub unsafe extern "C" fn ecall_import_contract_state(
+    context: Ctx,
+    env: *const u8,
+    env_len: usize,
+    sig_info: *const u8,
+    sig_info_len: usize,
+    chunk: *const u8,
+    chunk_len: usize,
+) -> ImportContractStateResult {
+    let env = EcallInput::new(EcallArg::Env, env, env_len);
+    let sig_info = EcallInput::new(EcallArg::SigInfo, sig_info, sig_info_len);
+    let chunk = EcallInput::new(EcallArg::StateArchive, chunk, chunk_len);
+    run_ecall(
+        "ecall_import_contract_state",
+        EcallGas::Unmetered,
+        &[env, sig_info, chunk],
+        |_used_gas| {
+            crate::wasm::import_contract_state(
+                context,
+                env.as_slice(),
+                sig_info.as_slice(),
+                chunk.as_slice(),
+            )
+        },
+    )
+}
+
+/// Authenticate the new admin of a contract after its admin replaced or removed it.
+/// See `crate::wasm::update_admin`.
+/// # Safety
+/// Always use protection
+#[no_mangle]
+pub unsafe extern "C" fn ecall_update_admin(
+    env: *const u8,
+    env_len: usize,
+    sig_info: *const u8,
+    sig_info_len: usize,
+    new_admin: *const u8,
+    new_admin_len: usize,
+) -> UpdateAdminResult {
+    let env = EcallInput::new(EcallArg::Env, env, env_len);
+    let sig_info = EcallInput::new(EcallArg::SigInfo, sig_info, sig_info_len);
+    // Empty when the admin is cleared
+    let new_admin = EcallInput::optional(EcallArg::NewAdmin, new_admin, new_admin_len);
+    run_ecall(
+        "ecall_update_admin",
+        EcallGas::Unmetered,
+        &[env, sig_info, new_admin],
+        |_used_gas| {
+            crate::wasm::update_admin(env.as_slice(), sig_info.as_slice(), new_admin.as_slice())
+        },
+    )
+}
+
+/// Start a session for validating a contract that is sent to the enclave in chunks, so that a
+/// large contract doesn't have to be copied into the enclave in one piece.
+/// # Safety
+/// Always use protection
+#[no_mangle]
+pub unsafe extern "C" fn ecall_validate_wasm_begin(session_id: u64) -> ValidateWasmResult {
+    validate_wasm_catch_unwind("ecall_validate_wasm_begin", || {
+        crate::wasm::validate_wasm_begin(session_id).map(|()| [0u8; 32])
+    })
+}
+
+/// Append chunk number `chunk_index` of the contract to the session. Chunks are numbered from 0.
+/// # Safety
+/// Always use protection
+#[no_mangle]
+pub unsafe extern "C" fn ecall_validate_wasm_chunk(
+    session_id: u64,
+    chunk_index: u32,
+    chunk: *const u8,
+    chunk_len: usize,
+) -> ValidateWasmResult {
+    if let Err(_e) = validate_const_ptr(chunk, chunk_len as usize) {
+        error!("Tried to access data outside enclave memory!");
+        return ValidateWasmResult::Failure {
+            err: EnclaveError::HostMisbehavior,
+        };
+    }
+
+    let chunk = std::slice::from_raw_parts(chunk, chunk_len);
+    validate_wasm_catch_unwind("ecall_validate_wasm_chunk", || {
+        crate::wasm::validate_wasm_chunk(session_id, chunk_index, chunk).map(|()| [0u8; 32])
+    })
+}
+
+/// Close the session and validate the contract it received, the same way it would be validated
+/// when it's first called. On success, the code hash of the contract is returned.
+/// # Safety
+/// Always use protection
+#[no_mangle]
+pub unsafe extern "C" fn ecall_validate_wasm_end(session_id: u64) -> ValidateWasmResult {
+    validate_wasm_catch_unwind("ecall_validate_wasm_end", || {
+        crate::wasm::validate_wasm_end(session_id)
+    })
+}
+
+/// The chunked wasm validation ecalls don't make ocalls, so unlike the contract ecalls they only
+/// need to guard against panics and running out of memory.
+fn validate_wasm_catch_unwind<F>(ecall_name: &str, f: F) -> ValidateWasmResult
+where
+    F: FnOnce() -> Result<[u8; 32], EnclaveError> + panic::UnwindSafe,
+{
+    if let Err(err) = ensure_not_decommissioned() {
+        return ValidateWasmResult::Failure { err };
+    }
+
+    if let Err(err) = oom_handler::register_oom_handler() {
+        error!("Could not register OOM handler!");
+        return ValidateWasmResult::Failure { err };
+    }
+
+    let result = panic::catch_unwind(f);
+
+    if let Err(err) = oom_handler::restore_safety_buffer() {
+        error!("Could not restore OOM safety buffer!");
+        return ValidateWasmResult::Failure { err };
+    }
+
+    match result {
+        Ok(result) => ValidateWasmResult::from_result(result),
+        Err(_err) => {
+            record_caught_panic(ecall_name);
+
+            if oom_handler::get_then_clear_oom_happened() {
+                error!(
+                    "Call {} failed because the enclave ran out of memory!",
+                    ecall_name
+                );
+                ValidateWasmResult::Failure {
+                    err: EnclaveError::OutOfMemory,
+                }
+            } else {
+                error!("Call {} panicked unexpectedly!", ecall_name);
+                ValidateWasmResult::Failure {
+                    err: EnclaveError::Panic,
+                }
+            }
+        }
+    }
+}
+
+/// Validate the contracts in `entries` and cache their modules, so that the first call to each of
+/// them after the node starts doesn't pay for it. Every entry is `PREWARM_ENTRY_SIZE` bytes, and
+/// points to the code of its contract in `code`. The `PrewarmStatus` of every entry is written to
+/// `statuses`, one byte per entry. An entry that fails doesn't stop the ones after it.
+///
+/// No more than `max_bytes` of code is validated, and no contract is validated after
+/// `max_millis` milliseconds.
+/// # Safety
+/// Always use protection
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn ecall_prewarm_contracts(
+    entries: *const u8,
+    entries_len: usize,
+    code: *const u8,
+    code_len: usize,
+    max_bytes: u64,
+    max_millis: u64,
+    statuses: *mut u8,
+    statuses_len: usize,
+) -> sgx_status_t {
+    if let Err(_e) = ensure_not_decommissioned() {
+        return sgx_status_t::SGX_ERROR_INVALID_STATE;
+    }
+
+    if entries_len % PREWARM_ENTRY_SIZE != 0 || statuses_len != entries_len / PREWARM_ENTRY_SIZE {
+        error!("The amount of prewarm entries doesn't match the size of the statuses buffer");
+        return sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+    }
+    if entries_len == 0 {
+        return sgx_status_t::SGX_SUCCESS;
+    }
+    if let Err(_e) = validate_const_ptr(entries, entries_len) {
+        error!("Tried to access data outside enclave memory!");
+        return sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+    }
+    if let Err(_e) = validate_mut_ptr(statuses, statuses_len) {
+        error!("Tried to access data outside enclave memory!");
+        return sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+    }
+    // The code may only be empty if all the entries are, and those fail on their own
+    let code = if code_len == 0 {
+        &[]
+    } else {
+        if let Err(_e) = validate_const_ptr(code, code_len) {
+            error!("Tried to access data outside enclave memory!");
+            return sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+        }
+        std::slice::from_raw_parts(code, code_len)
+    };
+    let entries = std::slice::from_raw_parts(entries, entries_len);
+    let budget = wasm::PrewarmBudget {
+        max_bytes,
+        max_duration: Duration::from_millis(max_millis),
+    };
+
+    if let Err(_err) = oom_handler::register_oom_handler() {
+        error!("Could not register OOM handler!");
+        return sgx_status_t::SGX_ERROR_UNEXPECTED;
+    }
+
+    let result = panic::catch_unwind(|| wasm::prewarm_contracts(entries, code, budget));
+
+    if let Err(_err) = oom_handler::restore_safety_buffer() {
+        error!("Could not restore OOM safety buffer!");
+        return sgx_status_t::SGX_ERROR_UNEXPECTED;
+    }
+
+    match result {
+        Ok(results) => {
+            let statuses = std::slice::from_raw_parts_mut(statuses, statuses_len);
+            for (status, result) in statuses.iter_mut().zip(results) {
+                *status = result as u8;
+            }
+            sgx_status_t::SGX_SUCCESS
+        }
+        Err(_err) => {
+            record_caught_panic("ecall_prewarm_contracts");
+            if oom_handler::get_then_clear_oom_happened() {
+                error!(
+                    "Call ecall_prewarm_contracts failed because the enclave ran out of memory!"
+                );
+                sgx_status_t::SGX_ERROR_OUT_OF_MEMORY
+            } else {
+                error!("Call ecall_prewarm_contracts panicked unexpectedly!");
+                sgx_status_t::SGX_ERROR_UNEXPECTED
+            }
+        }
+    }
+}
+
+/// # Safety
+/// Always use protection
+#[no_mangle]
+pub unsafe extern "C" fn ecall_query(
+    context: Ctx,
+    gas_limit: u64,
+    used_gas: *mut u64,
+    contract: *const u8,
+    contract_len: usize,
+    env: *const u8,
+    env_len: usize,
+    msg: *const u8,
+    msg_len: usize,
+) -> QueryResult {
+    let contract = EcallInput::new(EcallArg::Contract, contract, contract_len);
+    // Queries from hosts that don't provide an env are still allowed
+    let env = EcallInput::optional(EcallArg::Env, env, env_len);
+    let msg = EcallInput::new(EcallArg::Msg, msg, msg_len);
+    // Queries the host didn't limit run with the limit the node configured
+    let gas_limit = wasm::query_gas_limit(gas_limit);
+    // Keeps the memory of the query apart from that of the execution it may run next to
+    let _query_memory = oom_handler::QueryMemoryGuard::begin();
+    run_ecall(
+        "ecall_query",
+        EcallGas::Metered {
+            gas_limit,
+            used_gas,
+        },
+        &[env, msg, contract],
+        |used_gas| {
+            crate::wasm::query(
+                context,
+                gas_limit,
+                used_gas,
+                contract.as_slice(),
+                env.as_slice(),
+                msg.as_slice(),
+            )
+        },
+    )
+}
+
+/// A liveness probe. It doesn't touch any state, so it stays cheap and takes the same time on
+/// every call. See `ecall_health_check_deep` for checking that the enclave can do its work.
+/// # Safety
+/// Always use protection
+#[no_mangle]
+pub unsafe extern "C" fn ecall_health_check() -> HealthCheckResult {
+    HealthCheckResult::Success
+}
+
+/// Fill `result` with the subsystems of the enclave that work: unsealing the consensus seed,
+/// encrypting with a key derived from it, a storage round trip through the ocalls of `context`,
+/// and loading the attestation certificate. It also gets why the seed couldn't be unsealed, if it
+/// couldn't.
+/// # Safety
+/// Always use protection
+#[no_mangle]
+pub unsafe extern "C" fn ecall_health_check_deep(
+    context: Ctx,
+    result: *mut HealthCheckDeepResult,
+) -> sgx_status_t {
+    if let Err(_e) = validate_mut_ptr(result as _, std::mem::size_of::<HealthCheckDeepResult>()) {
+        error!("Tried to access data outside enclave memory!");
+        return sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+    }
+
+    // Frees buffers that ocalls allocated in the enclave if this ecall panics before recovering them
+    let _allocate_stack_guard = AllocateStackGuard::new();
+    match panic::catch_unwind(|| health_check::deep_health_check(&context)) {
+        Ok(health) => {
+            *result = health;
+            sgx_status_t::SGX_SUCCESS
+        }
+        Err(_err) => {
+            record_caught_panic("ecall_health_check_deep");
+            error!("Call ecall_health_check_deep panicked unexpectedly!");
+            sgx_status_t::SGX_ERROR_UNEXPECTED
+        }
+    }
+}
+
+/// Check whether `msg`, the encrypted message of a tx, decrypts in this enclave, without executing
+/// it or accessing any state. `tx_public_key` is the public key the sender encrypted it with.
+/// `result` gets the step that failed and the length of the plaintext, but never the plaintext.
+/// # Safety
+/// Always use protection
+#[no_mangle]
+pub unsafe extern "C" fn ecall_verify_input(
+    msg: *const u8,
+    msg_len: usize,
+    tx_public_key: *const u8,
+    tx_public_key_len: usize,
+    result: *mut VerifyInputResult,
+) -> sgx_status_t {
+    if let Err(_e) = ensure_not_decommissioned() {
+        return sgx_status_t::SGX_ERROR_INVALID_STATE;
+    }
+
+    if let Err(_e) = validate_mut_ptr(result as _, std::mem::size_of::<VerifyInputResult>()) {
+        error!("Tried to access data outside enclave memory!");
+        return sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+    }
+    if let Err(_e) = validate_const_ptr(msg, msg_len) {
+        error!("Tried to access data outside enclave memory!");
+        return sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+    }
+    if let Err(_e) = validate_const_ptr(tx_public_key, tx_public_key_len) {
+        error!("Tried to access data outside enclave memory!");
+        return sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+    }
+
+    let msg = std::slice::from_raw_parts(msg, msg_len);
+    let tx_public_key = std::slice::from_raw_parts(tx_public_key, tx_public_key_len);
+    let mut public_key = [0u8; PUBLIC_KEY_SIZE];
+    if tx_public_key.len() != PUBLIC_KEY_SIZE {
+        *result = VerifyInputResult {
+            step: InputVerificationStep::PublicKey,
+            plaintext_len: 0,
+        };
+        return sgx_status_t::SGX_SUCCESS;
+    }
+    public_key.copy_from_slice(tx_public_key);
+
+    match panic::catch_unwind(|| wasm::verify_input(msg, &public_key)) {
+        Ok(verified) => {
+            *result = verified;
+            sgx_status_t::SGX_SUCCESS
+        }
+        Err(_err) => {
+            record_caught_panic("ecall_verify_input");
+            error!("Call ecall_verify_input panicked unexpectedly!");
+            sgx_status_t::SGX_ERROR_UNEXPECTED
+        }
+    }
+}
+
+/// Verify the header of the next block with the commit of its validators, a JSON
+/// `BlockHeaderSubmission`, and keep it as the last header this node verified. Headers that aren't
+/// signed by the validator set the enclave tracks are rejected with `SGX_ERROR_INVALID_SIGNATURE`.
+/// # Safety
+/// Always use protection
+#[no_mangle]
+pub unsafe extern "C" fn ecall_submit_block_header(
+    submission: *const u8,
+    submission_len: usize,
+) -> sgx_status_t {
+    if let Err(_e) = ensure_not_decommissioned() {
+        return sgx_status_t::SGX_ERROR_INVALID_STATE;
+    }
+
+    if let Err(_e) = validate_const_ptr(submission, submission_len) {
+        error!("Tried to access data outside enclave memory!");
+        return sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+    }
+    let submission = std::slice::from_raw_parts(submission, submission_len);
+
+    let submission: BlockHeaderSubmission = match serde_json::from_slice(submission) {
+        Ok(submission) => submission,
+        Err(err) => {
+            warn!("Got a malformed block header submission: {:?}", err);
+            return sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+        }
+    };
+
+    match panic::catch_unwind(|| wasm::submit_block_header(&submission)) {
+        Ok(Ok(())) => sgx_status_t::SGX_SUCCESS,
+        Ok(Err(_err)) => sgx_status_t::SGX_ERROR_INVALID_SIGNATURE,
+        Err(_err) => {
+            record_caught_panic("ecall_submit_block_header");
+            error!("Call ecall_submit_block_header panicked unexpectedly!");
+            sgx_status_t::SGX_ERROR_UNEXPECTED
+        }
+    }
+}
+
+/// Start verifying the headers of another chain for contracts, from a header of it that governance
+/// approved, a JSON `ForeignChainInit`. What the enclave trusts about the chain is written to
+/// `sealed_chain`, as a JSON `SealedForeignChain` for the chain to keep. See
+/// `wasm::track_foreign_chain`.
+/// # Safety
+/// Always use protection
+#[no_mangle]
+pub unsafe extern "C" fn ecall_track_foreign_chain(
+    init: *const u8,
+    init_len: usize,
+    sealed_chain: *mut UserSpaceBuffer,
+) -> sgx_status_t {
+    if let Err(_e) = ensure_not_decommissioned() {
+        return sgx_status_t::SGX_ERROR_INVALID_STATE;
+    }
+
+    if let Err(_e) = validate_const_ptr(init, init_len) {
+        error!("Tried to access data outside enclave memory!");
+        return sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+    }
+    if let Err(_e) = validate_mut_ptr(sealed_chain as _, std::mem::size_of::<UserSpaceBuffer>()) {
+        error!("Tried to access data outside enclave memory!");
+        return sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+    }
+    let init = std::slice::from_raw_parts(init, init_len);
+
+    let init: ForeignChainInit = match serde_json::from_slice(init) {
+        Ok(init) => init,
+        Err(err) => {
+            warn!("Got a malformed foreign chain: {:?}", err);
+            return sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+        }
+    };
+
+    match panic::catch_unwind(|| wasm::track_foreign_chain(&init)) {
+        Ok(Ok(sealed)) => export_sealed_chain(&sealed, sealed_chain),
+        Ok(Err(_err)) => sgx_status_t::SGX_ERROR_INVALID_PARAMETER,
+        Err(_err) => {
+            record_caught_panic("ecall_track_foreign_chain");
+            error!("Call ecall_track_foreign_chain panicked unexpectedly!");
+            sgx_status_t::SGX_ERROR_UNEXPECTED
+        }
+    }
+}
+
+/// Verify a header of another chain, a JSON `ForeignHeader`, against what the enclave trusts about
+/// that chain, a JSON `SealedForeignChain` the chain kept. The state with the header as the one
+/// newer headers are verified against is written to `new_sealed_chain`. `now` is the block time of
+/// this chain, in seconds. Headers that don't verify are rejected with
+/// `SGX_ERROR_INVALID_SIGNATURE`.
+/// # Safety
+/// Always use protection
+#[no_mangle]
+pub unsafe extern "C" fn ecall_submit_foreign_header(
+    sealed_chain: *const u8,
+    sealed_chain_len: usize,
+    submission: *const u8,
+    submission_len: usize,
+    now: u64,
+    new_sealed_chain: *mut UserSpaceBuffer,
+) -> sgx_status_t {
+    if let Err(_e) = ensure_not_decommissioned() {
+        return sgx_status_t::SGX_ERROR_INVALID_STATE;
+    }
+
+    if let Err(_e) = validate_const_ptr(sealed_chain, sealed_chain_len) {
+        error!("Tried to access data outside enclave memory!");
+        return sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+    }
+    if let Err(_e) = validate_const_ptr(submission, submission_len) {
+        error!("Tried to access data outside enclave memory!");
+        return sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+    }
+    if let Err(_e) = validate_mut_ptr(
+        new_sealed_chain as _,
+        std::mem::size_of::<UserSpaceBuffer>(),
+    ) {
+        error!("Tried to access data outside enclave memory!");
+        return sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+    }
+    let sealed_chain = std::slice::from_raw_parts(sealed_chain, sealed_chain_len);
+    let submission = std::slice::from_raw_parts(submission, submission_len);
+
+    let sealed_chain: SealedForeignChain = match serde_json::from_slice(sealed_chain) {
+        Ok(sealed_chain) => sealed_chain,
+        Err(err) => {
+            warn!("Got a malformed foreign chain state: {:?}", err);
+            return sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+        }
+    };
+    let submission: ForeignHeader = match serde_json::from_slice(submission) {
+        Ok(submission) => submission,
+        Err(err) => {
+            warn!("Got a malformed foreign header: {:?}", err);
+            return sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+        }
+    };
+
+    match panic::catch_unwind(|| wasm::submit_foreign_header(&sealed_chain, &submission, now)) {
+        Ok(Ok(sealed)) => export_sealed_chain(&sealed, new_sealed_chain),
+        Ok(Err(_err)) => sgx_status_t::SGX_ERROR_INVALID_SIGNATURE,
+        Err(_err) => {
+            record_caught_panic("ecall_submit_foreign_header");
+            error!("Call ecall_submit_foreign_header panicked unexpectedly!");
+            sgx_status_t::SGX_ERROR_UNEXPECTED
+        }
+    }
+}
+
+/// Write the state of a foreign chain to a buffer outside the enclave, for the chain to keep
+unsafe fn export_sealed_chain(
+    sealed: &SealedForeignChain,
+    output: *mut UserSpaceBuffer,
+) -> sgx_status_t {
+    let sealed = match serde_json::to_vec(sealed) {
+        Ok(sealed) => sealed,
+        Err(err) => {
+            error!(
+                "Could not serialize the state of a foreign chain: {:?}",
+                err
+            );
+            return sgx_status_t::SGX_ERROR_UNEXPECTED;
+        }
+    };
+
+    match allocate_user_buffer(&sealed) {
+        Ok(buffer) => {
+            *output = buffer;
+            sgx_status_t::SGX_SUCCESS
+        }
+        Err(err) => {
+            error!("Could not export the state of a foreign chain: {}", err);
+            sgx_status_t::SGX_ERROR_UNEXPECTED
+        }
+    }
+}
+
+/// Write what this enclave supports to `capabilities`, so that the node can refuse features the
+/// enclave is too old for. See `EnclaveCapabilities` for the encoding, which lets nodes and
+/// enclaves of different versions read each other. `written` is set to the amount of bytes
+/// written, which is less than `EnclaveCapabilities::ENCODED_LEN` if the buffer is too small.
+/// # Safety
+/// Always use protection
+#[no_mangle]
+pub unsafe extern "C" fn ecall_get_capabilities(
+    capabilities: *mut u8,
+    capabilities_len: usize,
+    written: *mut usize,
+) -> sgx_status_t {
+    if let Err(_e) = validate_mut_ptr(capabilities, capabilities_len) {
+        error!("Tried to access data outside enclave memory!");
+        return sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+    }
+    if let Err(_e) = validate_mut_ptr(written as _, std::mem::size_of::<usize>()) {
+        error!("Tried to access data outside enclave memory!");
+        return sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+    }
+    if capabilities.is_null() {
+        *written = 0;
+        return sgx_status_t::SGX_SUCCESS;
+    }
+
+    let buf = std::slice::from_raw_parts_mut(capabilities, capabilities_len);
+    *written = enclave_capabilities().write_to(buf);
+
+    sgx_status_t::SGX_SUCCESS
+}
+
+fn enclave_capabilities() -> EnclaveCapabilities {
+    let version = |part: &str| part.parse().unwrap_or(0);
+
+    EnclaveCapabilities {
+        version_major: version(env!("CARGO_PKG_VERSION_MAJOR")),
+        version_minor: version(env!("CARGO_PKG_VERSION_MINOR")),
+        version_patch: version(env!("CARGO_PKG_VERSION_PATCH")),
+        // contracts built for CosmWasm 0.10 export `cosmwasm_vm_version_3`. Contracts built for
+        // CosmWasm 1.0 export `interface_version_8` instead, see `wasm::api_version`
+        cosmwasm_api_min: 3,
+        cosmwasm_api_max: 3,
+        entry_points: EnclaveCapabilities::ENTRY_POINT_MIGRATE
+            | EnclaveCapabilities::ENTRY_POINT_REPLY
+            | EnclaveCapabilities::ENTRY_POINT_IBC,
+        host_functions: EnclaveCapabilities::HOST_ADDR
+            | EnclaveCapabilities::HOST_DB_ITERATOR
+            | EnclaveCapabilities::HOST_DB_READ_MULTI
+            | EnclaveCapabilities::HOST_SIGNATURES
+            | EnclaveCapabilities::HOST_HASHES
+            | EnclaveCapabilities::HOST_RANDOM
+            | EnclaveCapabilities::HOST_MEMORY_GROW
+            | EnclaveCapabilities::HOST_HKDF
+            | EnclaveCapabilities::HOST_DB_NAMESPACES
+            | EnclaveCapabilities::HOST_SECP256K1_SIGNING
+            | EnclaveCapabilities::HOST_GAS_LEFT
+            | EnclaveCapabilities::HOST_FOREIGN_HEADERS
+            | EnclaveCapabilities::HOST_CONTRACT_CODE_HASH
+            | EnclaveCapabilities::HOST_DB_STORAGE_USAGE,
+    }
+}
+
+/// Fill `stats` with information about the memory usage of the enclave.
+/// This is meant to help operators debug out-of-memory failures.
+/// # Safety
+/// Always use protection
+#[no_mangle]
+pub unsafe extern "C" fn ecall_get_runtime_stats(stats: *mut RuntimeStats) -> sgx_status_t {
+    if let Err(_e) = validate_mut_ptr(stats as _, std::mem::size_of::<RuntimeStats>()) {
+        error!("Tried to access data outside enclave memory!");
+        return sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+    }
+
+    let (safety_buffer_chunks, safety_buffer_target_chunks) = oom_handler::safety_buffer_state();
+    let allocated_buffers = ALLOCATED_BUFFERS.load(Ordering::SeqCst);
+
+    *stats = RuntimeStats {
+        allocated_bytes: runtime_stats::allocated_bytes() as u64,
+        peak_allocated_bytes: runtime_stats::take_peak_allocated_bytes() as u64,
+        safety_buffer_chunks: safety_buffer_chunks as u64,
+        safety_buffer_target_chunks: safety_buffer_target_chunks as u64,
+        allocate_stack_len: allocated_buffers as u64,
+        panics_caught: runtime_stats::panics_caught(),
+        module_cache_misses: runtime_stats::module_cache_misses(),
+        prefetch_hits: runtime_stats::prefetch_hits(),
+        prefetch_misses: runtime_stats::prefetch_misses(),
+    };
+
+    sgx_status_t::SGX_SUCCESS
+}
+
+/// Write the metrics of the enclave to `metrics`, in the Prometheus text format, see `metrics`.
+/// `written` is set to the length of the metrics. If that's more than `metrics_len`, nothing is
+/// written to `metrics`, and the host should call again with a buffer of at least that length.
+/// # Safety
+/// Always use protection
+#[no_mangle]
+pub unsafe extern "C" fn ecall_export_metrics(
+    metrics: *mut u8,
+    metrics_len: usize,
+    written: *mut usize,
+) -> sgx_status_t {
+    if let Err(_e) = validate_mut_ptr(metrics, metrics_len) {
+        error!("Tried to access data outside enclave memory!");
+        return sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+    }
+    if let Err(_e) = validate_mut_ptr(written as _, std::mem::size_of::<usize>()) {
+        error!("Tried to access data outside enclave memory!");
+        return sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+    }
+
+    match panic::catch_unwind(metrics::export) {
+        Ok(exported) => {
+            *written = exported.len();
+            if !metrics.is_null() && exported.len() <= metrics_len {
+                std::slice::from_raw_parts_mut(metrics, exported.len())
+                    .copy_from_slice(exported.as_bytes());
+            }
+            sgx_status_t::SGX_SUCCESS
+        }
+        Err(_err) => {
+            record_caught_panic("ecall_export_metrics");
+            error!("Call ecall_export_metrics panicked unexpectedly!");
+            sgx_status_t::SGX_ERROR_UNEXPECTED
+        }
+    }
+}
+
+/// Write the traces of the last `max_ecalls` ecalls to `trace`, in the binary format described in
+/// `ecall_trace`. `written` is set to the length of the traces. If that's more than `trace_len`,
+/// nothing is written to `trace`, and the host should call again with a buffer of at least that
+/// length.
+/// # Safety
+/// Always use protection
+#[no_mangle]
+pub unsafe extern "C" fn ecall_get_trace(
+    max_ecalls: u32,
+    trace: *mut u8,
+    trace_len: usize,
+    written: *mut usize,
+) -> sgx_status_t {
+    if let Err(_e) = validate_mut_ptr(trace, trace_len) {
+        error!("Tried to access data outside enclave memory!");
+        return sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+    }
+    if let Err(_e) = validate_mut_ptr(written as _, std::mem::size_of::<usize>()) {
+        error!("Tried to access data outside enclave memory!");
+        return sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+    }
+
+    match panic::catch_unwind(|| ecall_trace::export(max_ecalls as usize)) {
+        Ok(exported) => {
+            *written = exported.len();
+            if !trace.is_null() && exported.len() <= trace_len {
+                std::slice::from_raw_parts_mut(trace, exported.len()).copy_from_slice(&exported);
+            }
+            sgx_status_t::SGX_SUCCESS
+        }
+        Err(_err) => {
+            record_caught_panic("ecall_get_trace");
+            error!("Call ecall_get_trace panicked unexpectedly!");
+            sgx_status_t::SGX_ERROR_UNEXPECTED
+        }
+    }
+}
+
+/// Fill `report` with the last panic in the enclave, so it can be debugged offline.
+/// The panic message may contain secrets, so it's only exported sealed to the identity of the
+/// enclave, next to a summary that leaves it out.
+/// # Safety
+/// Always use protection
+#[no_mangle]
+pub unsafe extern "C" fn ecall_get_last_panic_report(report: *mut LastPanicReport) -> sgx_status_t {
+    if let Err(_e) = validate_mut_ptr(report as _, std::mem::size_of::<LastPanicReport>()) {
+        error!("Tried to access data outside enclave memory!");
+        return sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+    }
+
+    let last_report = match panic_report::last_report() {
+        Some(last_report) => last_report,
+        None => {
+            *report = LastPanicReport::default();
+            return sgx_status_t::SGX_SUCCESS;
+        }
+    };
+
+    let sealed_report = match panic_report::seal_report(&last_report) {
+        Ok(sealed_report) => sealed_report,
+        Err(err) => {
+            error!("Could not seal the last panic report: {}", err);
+            return sgx_status_t::SGX_ERROR_UNEXPECTED;
+        }
+    };
+
+    let buffers = allocate_user_buffer(&sealed_report).and_then(|sealed_report| {
+        let summary = allocate_user_buffer(last_report.summary().as_bytes())?;
+        Ok(LastPanicReport {
+            sealed_report,
+            summary,
+        })
+    });
+
+    match buffers {
+        Ok(buffers) => {
+            *report = buffers;
+            sgx_status_t::SGX_SUCCESS
+        }
+        Err(err) => {
+            error!("Could not export the last panic report: {}", err);
+            sgx_status_t::SGX_ERROR_UNEXPECTED
+        }
+    }
+}
+
+/// Apply `config` to the running enclave, so operators can e.g. get more verbose logs from a
+/// misbehaving node without restarting it.
+/// # Safety
+/// Always use protection
+#[no_mangle]
+pub unsafe extern "C" fn ecall_configure_runtime(
+    config: *const RuntimeConfiguration,
+) -> sgx_status_t {
+    if let Err(_e) = validate_const_ptr(config as _, std::mem::size_of::<RuntimeConfiguration>()) {
+        error!("Tried to access data outside enclave memory!");
+        return sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+    }
+    let config = *config;
+
+    let requested_level = match config.max_log_level {
+        u8::MAX => None,
+        max_log_level => match logger::level_filter_from_u8(max_log_level) {
+            Some(level) => Some(level),
+            None => {
+                warn!("Got an invalid log level: {}", max_log_level);
+                return sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+            }
+        },
+    };
+    // Checked before anything is applied, so an invalid config changes nothing
+    if config.ecall_tracing > 2 {
+        warn!(
+            "Got an invalid ecall tracing setting: {}",
+            config.ecall_tracing
+        );
+        return sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+    }
+
+    if let Some(requested_level) = requested_level {
+        let level = logger::set_max_log_level(requested_level);
+        info!("Log level set to {}", level);
+    }
+
+    if config.default_query_gas_limit != 0 {
+        wasm::set_default_query_gas_limit(config.default_query_gas_limit);
+        info!(
+            "Default query gas limit set to {}",
+            config.default_query_gas_limit
+        );
+    }
+
+    if config.max_contract_size != 0 {
+        wasm::set_max_contract_size(config.max_contract_size as usize);
+        info!("Max contract size set to {}", config.max_contract_size);
+    }
+
+    match config.query_cache_size {
+        0 => {}
+        u64::MAX => {
+            wasm::set_query_cache_size(0);
+            info!("Query cache turned off");
+        }
+        size => {
+            wasm::set_query_cache_size(size as usize);
+            info!("Query cache size set to {}", size);
+        }
+    }
+
+    match config.ecall_tracing {
+        0 => {}
+        1 => {
+            ecall_trace::set_enabled(true);
+            info!("Ecall tracing turned on");
+        }
+        2 => {
+            ecall_trace::set_enabled(false);
+            info!("Ecall tracing turned off");
+        }
+        _ => {}
+    }
+
+    sgx_status_t::SGX_SUCCESS
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+    use crate::consts::MAX_CODE_SIZE;
+    use crate::count_failures;
+    use crate::oom_handler::tests::inject_allocation_failures;
+    use crate::registration::decommission::tests::{
+        decommission_throwaway_node, reset_decommission,
+    };
+    use crate::registration::{
+        ecall_authenticate_new_node, ecall_decommission_arm, ecall_decommission_confirm,
+        ecall_get_attestation_bundle, ecall_get_attestation_report, ecall_init_bootstrap,
+        ecall_init_node, ecall_key_gen, ecall_pin_decommission_operator, ecall_submit_new_seed,
+    };
+    use enclave_ffi_types::{NodeAuthResult, PrewarmStatus, SeedStatus};
+
+    pub fn run_tests() {
+        println!();
         let mut failures = 0;
 
         count_failures!(failures, {
@@ -394,200 +1869,1529 @@ pub mod tests {
             test_recover_enclave_buffer_in_recursion_invalid();
             test_recover_enclave_buffer_multiple_out_of_order_valid();
             test_recover_enclave_buffer_multiple_out_of_order_invalid();
+            test_ecall_allocate_at_size_limit();
+            test_ecall_allocate_over_size_limit();
+            test_ecall_allocate_empty_buffer();
+            test_ecall_allocate_invalid_pointer();
+            test_ecall_allocate_out_of_memory();
+            test_ecall_init_used_gas_on_invalid_pointer();
+            test_ecall_handle_used_gas_on_invalid_pointer();
+            test_ecall_query_used_gas_on_invalid_pointer();
+            test_ecalls_report_which_pointer_is_invalid();
+            test_ecall_allocate_rejects_invalid_ranges();
+            test_contract_ecalls_reject_invalid_ranges();
+            test_ecall_validate_wasm_chunk_rejects_invalid_ranges();
+            test_ecall_prewarm_contracts_rejects_invalid_ranges();
+            test_ecall_verify_input_rejects_invalid_ranges();
+            test_header_ecalls_reject_invalid_ranges();
+            test_output_ecalls_reject_invalid_ranges();
+            test_ecalls_reject_oversized_contract_before_reading_it();
+            test_ecall_configure_runtime_overrides_max_contract_size();
+            test_ecalls_refuse_to_run_after_decommission();
+            test_ecall_get_runtime_stats_counts_unrecovered_buffers();
+            test_ecall_prewarm_contracts_skips_validation_on_next_call();
+            test_ecall_prewarm_contracts_rejects_mismatched_statuses();
+            test_ecall_get_capabilities();
+            test_ecall_export_metrics_counts_failed_ecalls();
+            test_capabilities_for_older_reader();
+            test_capabilities_from_older_enclave();
+            test_capabilities_from_newer_enclave();
+            test_allocate_stack_guard_frees_buffers_after_panic();
+            test_allocate_stack_guard_in_nested_ecalls();
+            test_recover_enclave_buffer_replayed();
+            test_recover_enclave_buffer_stale_generation();
+            test_recover_enclave_buffer_interleaved();
+        });
+
+        if failures != 0 {
+            println!("{}: {} tests failed", file!(), failures);
+            panic!()
+        }
+    }
+
+    fn test_recover_enclave_buffer_valid() {
+        let message = b"some example text";
+        assert_eq!(allocate_stack_len(), 0);
+        let enclave_buffer = allocate_buffer(message);
+        assert_eq!(allocate_stack_len(), 1);
+        let recovered = unsafe { recover_buffer(enclave_buffer) };
+        assert_eq!(allocate_stack_len(), 0);
+        assert_eq!(recovered.unwrap().unwrap(), message);
+    }
+
+    fn test_recover_enclave_buffer_invalid() {
+        let enclave_buffer = EnclaveBuffer {
+            ptr: 0x12345678_usize as _,
+            generation: 1,
+        };
+        assert_eq!(allocate_stack_len(), 0);
+        let recovered = unsafe { recover_buffer(enclave_buffer) };
+        assert_eq!(allocate_stack_len(), 0);
+        assert_eq!(recovered.unwrap_err(), BufferRecoveryError::UnknownBuffer);
+    }
+
+    fn test_recover_enclave_buffer_invalid_but_similar() {
+        let message = Box::new(Vec::<u8>::from(&b"some example text"[..]));
+        let enclave_buffer = EnclaveBuffer {
+            ptr: message.as_ptr() as _,
+            generation: 1,
+        };
+        assert_eq!(allocate_stack_len(), 0);
+        let recovered = unsafe { recover_buffer(enclave_buffer) };
+        assert_eq!(allocate_stack_len(), 0);
+        assert_eq!(recovered.unwrap_err(), BufferRecoveryError::UnknownBuffer);
+    }
+
+    fn test_recover_enclave_buffer_invalid_null() {
+        let enclave_buffer = EnclaveBuffer {
+            ptr: std::ptr::null_mut(),
+            generation: 0,
+        };
+        assert_eq!(allocate_stack_len(), 0);
+        let recovered = unsafe { recover_buffer(enclave_buffer) };
+        assert_eq!(allocate_stack_len(), 0);
+        assert_eq!(recovered.unwrap(), None);
+    }
+
+    fn test_recover_enclave_buffer_in_recursion_valid() {
+        let recursion_depth = 10;
+        let messages: Vec<String> = (0..recursion_depth)
+            .map(|num| format!("message-{}", num))
+            .collect();
+
+        // simulate building up the stack recursively
+        let enclave_buffers: Vec<EnclaveBuffer> = messages
+            .iter()
+            .enumerate()
+            .map(|(index, message)| {
+                assert_eq!(allocate_stack_len(), index);
+                allocate_buffer(message)
+            })
+            .collect();
+
+        // simulate clearing the stack recursively
+        for (index, (message, enclave_buffer)) in messages
+            .into_iter()
+            .zip(enclave_buffers.into_iter())
+            .enumerate()
+            .rev()
+        {
+            assert_eq!(allocate_stack_len(), index + 1);
+            let recovered = unsafe { recover_buffer(enclave_buffer) };
+            assert_eq!(recovered.unwrap().unwrap(), message.as_bytes())
+        }
+        assert_eq!(allocate_stack_len(), 0)
+    }
+
+    // This test is very similar to the test above, except it tries to give incorrect
+    // inputs in the deepest part of the recursion
+    fn test_recover_enclave_buffer_in_recursion_invalid() {
+        let recursion_depth = 10;
+        let messages: Vec<String> = (0..recursion_depth)
+            .map(|num| format!("message-{}", num))
+            .collect();
+
+        // simulate building up the stack recursively
+        let enclave_buffers: Vec<EnclaveBuffer> = messages
+            .iter()
+            .enumerate()
+            .map(|(index, message)| {
+                assert_eq!(allocate_stack_len(), index);
+                allocate_buffer(message)
+            })
+            .collect();
+
+        let message = Box::new(Vec::<u8>::from(&b"some example text"[..]));
+        let enclave_buffer = EnclaveBuffer {
+            ptr: message.as_ptr() as _,
+            generation: 1,
+        };
+        assert_eq!(allocate_stack_len(), recursion_depth);
+        let recovered = unsafe { recover_buffer(enclave_buffer) };
+        assert_eq!(allocate_stack_len(), recursion_depth);
+        assert_eq!(recovered.unwrap_err(), BufferRecoveryError::UnknownBuffer);
+
+        // simulate clearing the stack recursively
+        for (index, (message, enclave_buffer)) in messages
+            .into_iter()
+            .zip(enclave_buffers.into_iter())
+            .enumerate()
+            .rev()
+        {
+            assert_eq!(allocate_stack_len(), index + 1);
+            let recovered = unsafe { recover_buffer(enclave_buffer) };
+            assert_eq!(recovered.unwrap().unwrap(), message.as_bytes())
+        }
+        assert_eq!(allocate_stack_len(), 0)
+    }
+
+    // These tests are vry similar to the recursion tests,
+    // except that they don't release the buffers in a FIFO order.
+    // In this case, I just release them in a FILO order, which should be worst-case.
+
+    fn test_recover_enclave_buffer_multiple_out_of_order_valid() {
+        let recursion_depth = 10;
+        let messages: Vec<String> = (0..recursion_depth)
+            .map(|num| format!("message-{}", num))
+            .collect();
+
+        // simulate building up the stack recursively
+        let enclave_buffers: Vec<EnclaveBuffer> = messages
+            .iter()
+            .enumerate()
+            .map(|(index, message)| {
+                assert_eq!(allocate_stack_len(), index);
+                allocate_buffer(message)
+            })
+            .collect();
+
+        // simulate clearing the stack recursively
+        // `.rev().enumerate().rev()` means that we'll be iterating over the lists in order, with reversed indexes.
+        for (index, (message, enclave_buffer)) in messages
+            .into_iter()
+            .zip(enclave_buffers.into_iter())
+            .rev()
+            .enumerate()
+            .rev()
+        {
+            assert_eq!(allocate_stack_len(), index + 1);
+            let recovered = unsafe { recover_buffer(enclave_buffer) };
+            assert_eq!(recovered.unwrap().unwrap(), message.as_bytes())
+        }
+        assert_eq!(allocate_stack_len(), 0)
+    }
+
+    fn test_recover_enclave_buffer_multiple_out_of_order_invalid() {
+        let recursion_depth = 10;
+        let messages: Vec<String> = (0..recursion_depth)
+            .map(|num| format!("message-{}", num))
+            .collect();
+
+        // simulate building up the stack recursively
+        let enclave_buffers: Vec<EnclaveBuffer> = messages
+            .iter()
+            .enumerate()
+            .map(|(index, message)| {
+                assert_eq!(allocate_stack_len(), index);
+                allocate_buffer(message)
+            })
+            .collect();
+
+        let message = Box::new(Vec::<u8>::from(&b"some example text"[..]));
+        let enclave_buffer = EnclaveBuffer {
+            ptr: message.as_ptr() as _,
+            generation: 1,
+        };
+        assert_eq!(allocate_stack_len(), recursion_depth);
+        let recovered = unsafe { recover_buffer(enclave_buffer) };
+        assert_eq!(allocate_stack_len(), recursion_depth);
+        assert_eq!(recovered.unwrap_err(), BufferRecoveryError::UnknownBuffer);
+
+        // simulate clearing the stack recursively
+        // `.rev().enumerate().rev()` means that we'll be iterating over the lists in order, with reversed indexes.
+        for (index, (message, enclave_buffer)) in messages
+            .into_iter()
+            .zip(enclave_buffers.into_iter())
+            .rev()
+            .enumerate()
+            .rev()
+        {
+            assert_eq!(allocate_stack_len(), index + 1);
+            let recovered = unsafe { recover_buffer(enclave_buffer) };
+            assert_eq!(recovered.unwrap().unwrap(), message.as_bytes())
+        }
+        assert_eq!(allocate_stack_len(), 0)
+    }
+
+    fn test_ecall_allocate_at_size_limit() {
+        let message = vec![7u8; MAX_OCALL_RESPONSE_SIZE];
+        assert_eq!(allocate_stack_len(), 0);
+        let enclave_buffer = allocate_buffer(&message);
+        assert!(!enclave_buffer.ptr.is_null());
+        assert_eq!(allocate_stack_len(), 1);
+        let recovered = unsafe { recover_buffer(enclave_buffer) };
+        assert_eq!(allocate_stack_len(), 0);
+        assert_eq!(recovered.unwrap().unwrap(), message);
+    }
+
+    fn test_ecall_allocate_over_size_limit() {
+        let message = vec![7u8; MAX_OCALL_RESPONSE_SIZE + 1];
+        assert_eq!(allocate_stack_len(), 0);
+        let result = unsafe { ecall_allocate(message.as_ptr(), message.len()) };
+        assert!(matches!(
+            result,
+            AllocationResult::Failure {
+                err: EnclaveError::ExceededOcallResponseSize
+            }
+        ));
+        assert_eq!(allocate_stack_len(), 0);
+
+        // A host that ignores the failure and passes a null buffer doesn't hide it
+        let recovered = unsafe { recover_buffer(EnclaveBuffer::default()) };
+        assert_eq!(
+            recovered.unwrap_err(),
+            BufferRecoveryError::ExceededOcallResponseSize
+        );
+
+        // The failure is only reported once
+        let enclave_buffer = EnclaveBuffer::default();
+        let recovered = unsafe { recover_buffer(enclave_buffer) };
+        assert_eq!(recovered.unwrap(), None);
+    }
+
+    fn test_ecall_allocate_empty_buffer() {
+        let message = b"";
+        for ptr in &[message.as_ptr(), std::ptr::null()] {
+            let result = unsafe { ecall_allocate(*ptr, 0) };
+            let enclave_buffer = match result {
+                AllocationResult::Success { buffer } => buffer,
+                AllocationResult::Failure { err } => panic!("ecall_allocate failed: {}", err),
+            };
+            assert!(!enclave_buffer.ptr.is_null());
+            assert!(enclave_buffer.is_empty_buffer());
+            // Nothing was allocated for it
+            assert_eq!(allocate_stack_len(), 0);
+
+            let recovered = unsafe { recover_buffer(enclave_buffer) };
+            assert_eq!(recovered.unwrap(), Some(vec![]));
+        }
+
+        // A null buffer still means there is no buffer
+        let recovered = unsafe { recover_buffer(EnclaveBuffer::default()) };
+        assert_eq!(recovered.unwrap(), None);
+    }
+
+    fn test_ecall_allocate_invalid_pointer() {
+        let result = unsafe { ecall_allocate(std::ptr::null(), 5) };
+        assert!(matches!(
+            result,
+            AllocationResult::Failure {
+                err: EnclaveError::HostMisbehavior
+            }
+        ));
+        assert_eq!(allocate_stack_len(), 0);
+        assert_eq!(
+            take_allocation_failure(),
+            Some(BufferRecoveryError::InvalidPointer)
+        );
+        assert_eq!(take_allocation_failure(), None);
+    }
+
+    fn test_ecall_allocate_out_of_memory() {
+        let message = vec![7u8; 64 * 1024];
+        oom_handler::restore_safety_buffer().unwrap();
+
+        // Fail the copy, and its retries after freeing the safety buffer and the module cache
+        inject_allocation_failures(3, message.len());
+        let result = unsafe { ecall_allocate(message.as_ptr(), message.len()) };
+        inject_allocation_failures(0, 0);
+
+        assert!(matches!(
+            result,
+            AllocationResult::Failure {
+                err: EnclaveError::OutOfMemory
+            }
+        ));
+        assert_eq!(allocate_stack_len(), 0);
+        assert_eq!(
+            take_allocation_failure(),
+            Some(BufferRecoveryError::OutOfMemory)
+        );
+
+        // The enclave recovered, and the same buffer can be copied now
+        let enclave_buffer = allocate_buffer(&message);
+        let recovered = unsafe { recover_buffer(enclave_buffer) };
+        assert_eq!(recovered.unwrap().unwrap(), message);
+    }
+
+    /// Copy `buffer` into the enclave the way an ocall does, and fail the test if it can't be
+    fn allocate_buffer<B: AsRef<[u8]> + ?Sized>(buffer: &B) -> EnclaveBuffer {
+        let buffer = buffer.as_ref();
+        match unsafe { ecall_allocate(buffer.as_ptr(), buffer.len()) } {
+            AllocationResult::Success { buffer } => buffer,
+            AllocationResult::Failure { err } => panic!("ecall_allocate failed: {}", err),
+        }
+    }
+
+    fn test_ecall_init_used_gas_on_invalid_pointer() {
+        let contract = b"contract";
+        for garbage in &[0_u64, 0xdead_beef, u64::MAX] {
+            let mut used_gas = *garbage;
+            let result = unsafe {
+                ecall_init(
+                    Ctx {
+                        data: std::ptr::null_mut(),
+                    },
+                    1_000_000,
+                    &mut used_gas,
+                    contract.as_ptr(),
+                    contract.len(),
+                    std::ptr::null(),
+                    0,
+                    std::ptr::null(),
+                    0,
+                    std::ptr::null(),
+                    0,
+                )
+            };
+            assert!(matches!(
+                result,
+                InitResult::Failure {
+                    err: EnclaveError::InvalidEnvPointer
+                }
+            ));
+            assert_eq!(used_gas, 0);
+        }
+    }
+
+    fn test_ecall_handle_used_gas_on_invalid_pointer() {
+        let contract = b"contract";
+        for garbage in &[0_u64, 0xdead_beef, u64::MAX] {
+            let mut used_gas = *garbage;
+            let result = unsafe {
+                ecall_handle(
+                    Ctx {
+                        data: std::ptr::null_mut(),
+                    },
+                    1_000_000,
+                    &mut used_gas,
+                    contract.as_ptr(),
+                    contract.len(),
+                    std::ptr::null(),
+                    0,
+                    std::ptr::null(),
+                    0,
+                    std::ptr::null(),
+                    0,
+                    0,
+                )
+            };
+            assert!(matches!(
+                result,
+                HandleResult::Failure {
+                    err: EnclaveError::InvalidEnvPointer,
+                    ..
+                }
+            ));
+            assert_eq!(used_gas, 0);
+        }
+    }
+
+    fn test_ecall_query_used_gas_on_invalid_pointer() {
+        let contract = b"contract";
+        for garbage in &[0_u64, 0xdead_beef, u64::MAX] {
+            let mut used_gas = *garbage;
+            let result = unsafe {
+                ecall_query(
+                    Ctx {
+                        data: std::ptr::null_mut(),
+                    },
+                    1_000_000,
+                    &mut used_gas,
+                    contract.as_ptr(),
+                    contract.len(),
+                    std::ptr::null(),
+                    0,
+                    std::ptr::null(),
+                    0,
+                )
+            };
+            assert!(matches!(
+                result,
+                QueryResult::Failure {
+                    err: EnclaveError::InvalidEnvPointer
+                }
+            ));
+            assert_eq!(used_gas, 0);
+        }
+    }
+
+    /// A pointer that the host passes to a contract ecall
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    enum EcallPointer {
+        Contract,
+        Env,
+        Msg,
+        SigInfo,
+        UsedGas,
+    }
+
+    /// Call `ecall` with valid buffers, except for `null`, and return the error it failed with
+    fn ecall_with_null_pointer(ecall: &str, null: EcallPointer) -> Option<EnclaveError> {
+        ecall_with_range(ecall, null, (0, 16))
+    }
+
+    /// Call `ecall` with valid buffers, except for `pointer`, which gets the address and length of
+    /// `range`, and return the error it failed with. `used_gas` only gets the address.
+    fn ecall_with_range(
+        ecall: &str,
+        pointer: EcallPointer,
+        (ptr, len): (usize, usize),
+    ) -> Option<EnclaveError> {
+        let input = |input: EcallPointer, data: &'static [u8]| {
+            if input == pointer {
+                (ptr as *const u8, len)
+            } else {
+                (data.as_ptr(), data.len())
+            }
+        };
+        let (contract, contract_len) = input(EcallPointer::Contract, b"contract");
+        let (env, env_len) = input(EcallPointer::Env, b"env");
+        let (msg, msg_len) = input(EcallPointer::Msg, b"msg");
+        let (sig_info, sig_info_len) = input(EcallPointer::SigInfo, b"sig_info");
+        let mut gas = 0_u64;
+        let used_gas: *mut u64 = if pointer == EcallPointer::UsedGas {
+            ptr as _
+        } else {
+            &mut gas
+        };
+
+        unsafe {
+            call_contract_ecall(
+                ecall,
+                used_gas,
+                (contract, contract_len),
+                (env, env_len),
+                (msg, msg_len),
+                (sig_info, sig_info_len),
+            )
+        }
+    }
+
+    /// Call `ecall` with the given buffers, and return the error it failed with
+    unsafe fn call_contract_ecall(
+        ecall: &str,
+        used_gas: *mut u64,
+        (contract, contract_len): (*const u8, usize),
+        (env, env_len): (*const u8, usize),
+        (msg, msg_len): (*const u8, usize),
+        (sig_info, sig_info_len): (*const u8, usize),
+    ) -> Option<EnclaveError> {
+        let context = Ctx {
+            data: std::ptr::null_mut(),
+        };
+
+        match ecall {
+            "ecall_init" => match ecall_init(
+                context,
+                1_000_000,
+                used_gas,
+                contract,
+                contract_len,
+                env,
+                env_len,
+                msg,
+                msg_len,
+                sig_info,
+                sig_info_len,
+            ) {
+                InitResult::Failure { err } => Some(err),
+                _ => None,
+            },
+            "ecall_handle" => match ecall_handle(
+                context,
+                1_000_000,
+                used_gas,
+                contract,
+                contract_len,
+                env,
+                env_len,
+                msg,
+                msg_len,
+                sig_info,
+                sig_info_len,
+                0,
+            ) {
+                HandleResult::Failure { err, .. } => Some(err),
+                _ => None,
+            },
+            "ecall_query" => match ecall_query(
+                context,
+                1_000_000,
+                used_gas,
+                contract,
+                contract_len,
+                env,
+                env_len,
+                msg,
+                msg_len,
+            ) {
+                QueryResult::Failure { err } => Some(err),
+                _ => None,
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    /// Every pointer of a contract ecall, and the error the ecall fails with when it's invalid
+    fn pointer_errors() -> [(EcallPointer, EnclaveError); 5] {
+        [
+            (EcallPointer::Contract, EnclaveError::InvalidContractPointer),
+            (EcallPointer::Env, EnclaveError::InvalidEnvPointer),
+            (EcallPointer::Msg, EnclaveError::InvalidMsgPointer),
+            (EcallPointer::SigInfo, EnclaveError::InvalidSigInfoPointer),
+            (EcallPointer::UsedGas, EnclaveError::InvalidUsedGasPointer),
+        ]
+    }
+
+    fn test_ecalls_report_which_pointer_is_invalid() {
+        for ecall in &["ecall_init", "ecall_handle", "ecall_query"] {
+            for (null, expected) in pointer_errors().iter() {
+                if *ecall == "ecall_query" && *null == EcallPointer::SigInfo {
+                    // Queries aren't signed
+                    continue;
+                }
+                let err = ecall_with_null_pointer(ecall, *null);
+                assert_eq!(
+                    format!("{:?}", err),
+                    format!("{:?}", Some(expected)),
+                    "{} with a null {:?} pointer",
+                    ecall,
+                    null
+                );
+            }
+        }
+    }
+
+    /// Ranges of `len` bytes, as an address and a length, that no ecall may accept for a buffer:
+    /// null, wrapping around the address space, and before, crossing into, crossing out of and
+    /// after enclave memory. Buffers of a fixed size only get the address.
+    fn ranges_outside_enclave(len: usize) -> Vec<(usize, usize)> {
+        assert!(len > 4);
+        let enclave = crate::utils::enclave_range();
+        vec![
+            (0, len),
+            (usize::MAX - 3, len),
+            (enclave.start - len, len),
+            (enclave.start - 4, len),
+            (enclave.end - 4, len),
+            (enclave.end, len),
+        ]
+    }
+
+    /// `ranges_outside_enclave` for buffers that the host passes the length of, along with the
+    /// ranges that only their length makes invalid: one that starts in enclave memory but wraps
+    /// around the address space, and one that is empty but not null
+    fn invalid_buffers() -> Vec<(usize, usize)> {
+        let enclave = crate::utils::enclave_range();
+        let mut ranges = ranges_outside_enclave(16);
+        ranges.push((enclave.start + 16, usize::MAX - 15));
+        ranges.push((enclave.start + 16, 0));
+        ranges
+    }
+
+    fn non_empty(ranges: Vec<(usize, usize)>) -> Vec<(usize, usize)> {
+        ranges.into_iter().filter(|&(_, len)| len != 0).collect()
+    }
+
+    /// Check that every range in `ranges` is rejected for the buffer `what`. `call` passes the
+    /// range to the ecall, and returns whether the ecall rejected it.
+    fn assert_rejects_ranges(
+        what: &str,
+        ranges: &[(usize, usize)],
+        call: impl Fn(usize, usize) -> bool,
+    ) {
+        for &(ptr, len) in ranges {
+            assert!(
+                call(ptr, len),
+                "{} accepted {} bytes at {:#x}",
+                what,
+                len,
+                ptr
+            );
+        }
+    }
+
+    fn test_ecall_allocate_rejects_invalid_ranges() {
+        // An empty buffer is never read, see test_ecall_allocate_empty_buffer
+        let ranges = non_empty(invalid_buffers());
+        assert_rejects_ranges("ecall_allocate buffer", &ranges, |ptr, len| {
+            let result = unsafe { ecall_allocate(ptr as _, len) };
+            take_allocation_failure() == Some(BufferRecoveryError::InvalidPointer)
+                && matches!(
+                    result,
+                    AllocationResult::Failure {
+                        err: EnclaveError::HostMisbehavior
+                    }
+                )
+        });
+        assert_eq!(allocate_stack_len(), 0);
+    }
+
+    /// All the other contract ecalls validate their pointers in `run_ecall` the same way
+    fn test_contract_ecalls_reject_invalid_ranges() {
+        for ecall in &["ecall_init", "ecall_handle", "ecall_query"] {
+            for (pointer, expected) in pointer_errors().iter() {
+                let ranges = match pointer {
+                    // Queries aren't signed
+                    EcallPointer::SigInfo if *ecall == "ecall_query" => continue,
+                    // Queries may leave the env empty
+                    EcallPointer::Env if *ecall == "ecall_query" => non_empty(invalid_buffers()),
+                    EcallPointer::UsedGas => ranges_outside_enclave(std::mem::size_of::<u64>()),
+                    _ => invalid_buffers(),
+                };
+                let what = format!("{} {:?}", ecall, pointer);
+                assert_rejects_ranges(&what, &ranges, |ptr, len| {
+                    let err = ecall_with_range(ecall, *pointer, (ptr, len));
+                    format!("{:?}", err) == format!("{:?}", Some(expected))
+                });
+            }
+        }
+    }
+
+    fn test_ecall_validate_wasm_chunk_rejects_invalid_ranges() {
+        let ranges = invalid_buffers();
+        assert_rejects_ranges("ecall_validate_wasm_chunk chunk", &ranges, |ptr, len| {
+            let result = unsafe { ecall_validate_wasm_chunk(1, 0, ptr as _, len) };
+            matches!(
+                result,
+                ValidateWasmResult::Failure {
+                    err: EnclaveError::HostMisbehavior
+                }
+            )
+        });
+    }
+
+    fn test_ecall_prewarm_contracts_rejects_invalid_ranges() {
+        const ENTRIES: usize = 16;
+        let entries = [0u8; PREWARM_ENTRY_SIZE * ENTRIES];
+        let code = b"contract";
+        let mut statuses = [u8::MAX; ENTRIES];
+        let entries = (entries.as_ptr() as usize, entries.len());
+        let code = (code.as_ptr() as usize, code.len());
+        let statuses_buf = (statuses.as_mut_ptr() as usize, statuses.len());
+        let prewarm = |entries: (usize, usize), code: (usize, usize), statuses: (usize, usize)| {
+            let status = unsafe {
+                ecall_prewarm_contracts(
+                    entries.0 as _,
+                    entries.1,
+                    code.0 as _,
+                    code.1,
+                    u64::MAX,
+                    u64::MAX,
+                    statuses.0 as _,
+                    statuses.1,
+                )
+            };
+            status == sgx_status_t::SGX_ERROR_INVALID_PARAMETER
+        };
+
+        // The lengths of the entries and the statuses have to match, or they're rejected for that
+        let ranges = ranges_outside_enclave(entries.1);
+        assert_rejects_ranges("ecall_prewarm_contracts entries", &ranges, |ptr, len| {
+            prewarm((ptr, len), code, statuses_buf)
+        });
+        let ranges = ranges_outside_enclave(statuses_buf.1);
+        assert_rejects_ranges("ecall_prewarm_contracts statuses", &ranges, |ptr, len| {
+            prewarm(entries, code, (ptr, len))
+        });
+        // Empty code is never read, the entries fail on their own
+        let ranges = non_empty(invalid_buffers());
+        assert_rejects_ranges("ecall_prewarm_contracts code", &ranges, |ptr, len| {
+            prewarm(entries, (ptr, len), statuses_buf)
+        });
+        assert_eq!(statuses, [u8::MAX; ENTRIES]);
+    }
+
+    fn test_ecall_verify_input_rejects_invalid_ranges() {
+        let msg = b"msg";
+        let tx_public_key = [0u8; PUBLIC_KEY_SIZE];
+        let mut result = VerifyInputResult::default();
+        let msg = (msg.as_ptr() as usize, msg.len());
+        let tx_public_key = (tx_public_key.as_ptr() as usize, tx_public_key.len());
+        let result = &mut result as *mut VerifyInputResult as usize;
+        let verify = |msg: (usize, usize), tx_public_key: (usize, usize), result: usize| {
+            let status = unsafe {
+                ecall_verify_input(
+                    msg.0 as _,
+                    msg.1,
+                    tx_public_key.0 as _,
+                    tx_public_key.1,
+                    result as _,
+                )
+            };
+            status == sgx_status_t::SGX_ERROR_INVALID_PARAMETER
+        };
+
+        let ranges = invalid_buffers();
+        assert_rejects_ranges("ecall_verify_input msg", &ranges, |ptr, len| {
+            verify((ptr, len), tx_public_key, result)
+        });
+        assert_rejects_ranges("ecall_verify_input tx_public_key", &ranges, |ptr, len| {
+            verify(msg, (ptr, len), result)
+        });
+        let ranges = ranges_outside_enclave(std::mem::size_of::<VerifyInputResult>());
+        assert_rejects_ranges("ecall_verify_input result", &ranges, |ptr, _| {
+            verify(msg, tx_public_key, ptr)
         });
+    }
 
-        if failures != 0 {
-            println!("{}: {} tests failed", file!(), failures);
-            panic!()
-        }
+    fn test_header_ecalls_reject_invalid_ranges() {
+        let invalid = |status: sgx_status_t| status == sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+        let ranges = invalid_buffers();
+        assert_rejects_ranges(
+            "ecall_submit_block_header submission",
+            &ranges,
+            |ptr, len| invalid(unsafe { ecall_submit_block_header(ptr as _, len) }),
+        );
+        let mut output = UserSpaceBuffer {
+            ptr: std::ptr::null_mut(),
+        };
+        let output = &mut output as *mut UserSpaceBuffer as usize;
+        let input = [0u8; 16];
+        let input = (input.as_ptr() as usize, input.len());
+        let output_ranges = ranges_outside_enclave(std::mem::size_of::<UserSpaceBuffer>());
+
+        assert_rejects_ranges("ecall_track_foreign_chain init", &ranges, |ptr, len| {
+            invalid(unsafe { ecall_track_foreign_chain(ptr as _, len, output as _) })
+        });
+        assert_rejects_ranges(
+            "ecall_track_foreign_chain sealed_chain",
+            &output_ranges,
+            |ptr, _| invalid(unsafe { ecall_track_foreign_chain(input.0 as _, input.1, ptr as _) }),
+        );
+        let submit = |sealed_chain: (usize, usize), submission: (usize, usize), output: usize| {
+            invalid(unsafe {
+                ecall_submit_foreign_header(
+                    sealed_chain.0 as _,
+                    sealed_chain.1,
+                    submission.0 as _,
+                    submission.1,
+                    0,
+                    output as _,
+                )
+            })
+        };
+        assert_rejects_ranges(
+            "ecall_submit_foreign_header sealed_chain",
+            &ranges,
+            |ptr, len| submit((ptr, len), input, output),
+        );
+        assert_rejects_ranges(
+            "ecall_submit_foreign_header submission",
+            &ranges,
+            |ptr, len| submit(input, (ptr, len), output),
+        );
+        assert_rejects_ranges(
+            "ecall_submit_foreign_header new_sealed_chain",
+            &output_ranges,
+            |ptr, _| submit(input, input, ptr),
+        );
     }
 
-    fn test_recover_enclave_buffer_valid() {
-        let message = b"some example text";
-        assert_eq!(ECALL_ALLOCATE_STACK.lock().unwrap().len(), 0);
-        let enclave_buffer = unsafe { ecall_allocate(message.as_ptr(), message.len()) };
-        assert_eq!(ECALL_ALLOCATE_STACK.lock().unwrap().len(), 1);
-        let recovered = unsafe { recover_buffer(enclave_buffer) };
-        assert_eq!(ECALL_ALLOCATE_STACK.lock().unwrap().len(), 0);
-        assert_eq!(recovered.unwrap().unwrap(), message);
+    fn test_output_ecalls_reject_invalid_ranges() {
+        let invalid = |status: sgx_status_t| status == sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+        let mut buf = [0u8; 64];
+        let mut written = 0usize;
+        let buf = (buf.as_mut_ptr() as usize, buf.len());
+        let written = &mut written as *mut usize as usize;
+        let buffers = invalid_buffers();
+        let written_ranges = ranges_outside_enclave(std::mem::size_of::<usize>());
+
+        assert_rejects_ranges(
+            "ecall_health_check_deep result",
+            &ranges_outside_enclave(std::mem::size_of::<HealthCheckDeepResult>()),
+            |ptr, _| {
+                let context = Ctx {
+                    data: std::ptr::null_mut(),
+                };
+                invalid(unsafe { ecall_health_check_deep(context, ptr as _) })
+            },
+        );
+
+        assert_rejects_ranges(
+            "ecall_get_capabilities capabilities",
+            &buffers,
+            |ptr, len| invalid(unsafe { ecall_get_capabilities(ptr as _, len, written as _) }),
+        );
+        assert_rejects_ranges(
+            "ecall_get_capabilities written",
+            &written_ranges,
+            |ptr, _| invalid(unsafe { ecall_get_capabilities(buf.0 as _, buf.1, ptr as _) }),
+        );
+
+        assert_rejects_ranges(
+            "ecall_get_runtime_stats stats",
+            &ranges_outside_enclave(std::mem::size_of::<RuntimeStats>()),
+            |ptr, _| invalid(unsafe { ecall_get_runtime_stats(ptr as _) }),
+        );
+
+        assert_rejects_ranges("ecall_export_metrics metrics", &buffers, |ptr, len| {
+            invalid(unsafe { ecall_export_metrics(ptr as _, len, written as _) })
+        });
+        assert_rejects_ranges("ecall_export_metrics written", &written_ranges, |ptr, _| {
+            invalid(unsafe { ecall_export_metrics(buf.0 as _, buf.1, ptr as _) })
+        });
+
+        assert_rejects_ranges("ecall_get_trace trace", &buffers, |ptr, len| {
+            invalid(unsafe { ecall_get_trace(1, ptr as _, len, written as _) })
+        });
+        assert_rejects_ranges("ecall_get_trace written", &written_ranges, |ptr, _| {
+            invalid(unsafe { ecall_get_trace(1, buf.0 as _, buf.1, ptr as _) })
+        });
+
+        assert_rejects_ranges(
+            "ecall_get_last_panic_report report",
+            &ranges_outside_enclave(std::mem::size_of::<LastPanicReport>()),
+            |ptr, _| invalid(unsafe { ecall_get_last_panic_report(ptr as _) }),
+        );
+
+        assert_rejects_ranges(
+            "ecall_configure_runtime config",
+            &ranges_outside_enclave(std::mem::size_of::<RuntimeConfiguration>()),
+            |ptr, _| invalid(unsafe { ecall_configure_runtime(ptr as _) }),
+        );
     }
 
-    fn test_recover_enclave_buffer_invalid() {
-        let enclave_buffer = EnclaveBuffer {
-            ptr: 0x12345678_usize as _,
+    /// Call `ecall` with a contract of `contract_len` bytes that only has a few bytes behind it,
+    /// and return the error it failed with and the most bytes the enclave allocated meanwhile
+    fn ecall_with_contract_len(ecall: &str, contract_len: usize) -> (Option<EnclaveError>, u64) {
+        let contract = b"contract";
+        let env = b"env";
+        let msg = b"msg";
+        let sig_info = b"sig_info";
+        let mut used_gas = 0_u64;
+
+        // Reading the stats starts a new peak at what is allocated now
+        let mut stats = RuntimeStats::default();
+        assert_eq!(
+            unsafe { ecall_get_runtime_stats(&mut stats) },
+            sgx_status_t::SGX_SUCCESS
+        );
+        let allocated_before = runtime_stats::allocated_bytes() as u64;
+
+        let err = unsafe {
+            call_contract_ecall(
+                ecall,
+                &mut used_gas,
+                (contract.as_ptr(), contract_len),
+                (env.as_ptr(), env.len()),
+                (msg.as_ptr(), msg.len()),
+                (sig_info.as_ptr(), sig_info.len()),
+            )
         };
-        assert_eq!(ECALL_ALLOCATE_STACK.lock().unwrap().len(), 0);
-        let recovered = unsafe { recover_buffer(enclave_buffer) };
-        assert_eq!(ECALL_ALLOCATE_STACK.lock().unwrap().len(), 0);
-        assert_eq!(recovered.unwrap_err(), BufferRecoveryError);
+
+        assert_eq!(
+            unsafe { ecall_get_runtime_stats(&mut stats) },
+            sgx_status_t::SGX_SUCCESS
+        );
+        (err, stats.peak_allocated_bytes - allocated_before)
     }
 
-    fn test_recover_enclave_buffer_invalid_but_similar() {
-        let message = Box::new(Vec::<u8>::from(&b"some example text"[..]));
-        let enclave_buffer = EnclaveBuffer {
-            ptr: message.as_ptr() as _,
+    fn configure_max_contract_size(max_contract_size: u64) {
+        let config = RuntimeConfiguration {
+            max_log_level: u8::MAX,
+            default_query_gas_limit: 0,
+            max_contract_size,
+            query_cache_size: 0,
+            ecall_tracing: 0,
         };
-        assert_eq!(ECALL_ALLOCATE_STACK.lock().unwrap().len(), 0);
-        let recovered = unsafe { recover_buffer(enclave_buffer) };
-        assert_eq!(ECALL_ALLOCATE_STACK.lock().unwrap().len(), 0);
-        assert_eq!(recovered.unwrap_err(), BufferRecoveryError);
+        let status = unsafe { ecall_configure_runtime(&config) };
+        assert_eq!(status, sgx_status_t::SGX_SUCCESS);
     }
 
-    fn test_recover_enclave_buffer_invalid_null() {
-        let enclave_buffer = EnclaveBuffer {
+    fn test_ecalls_reject_oversized_contract_before_reading_it() {
+        // The ecall only allocates the result and its log lines, never anything the size of the
+        // contract
+        const MAX_ALLOCATED: u64 = 16 * 1024;
+
+        for ecall in &["ecall_init", "ecall_handle", "ecall_query"] {
+            let (err, allocated) = ecall_with_contract_len(ecall, wasm::max_contract_size() + 1);
+            assert_eq!(
+                format!("{:?}", err),
+                format!("{:?}", Some(EnclaveError::ContractTooLarge)),
+                "{} with an oversized contract",
+                ecall
+            );
+            assert!(
+                allocated < MAX_ALLOCATED,
+                "{} allocated {} bytes",
+                ecall,
+                allocated
+            );
+        }
+    }
+
+    /// Call every ecall that needs a key, with inputs that are fine as far as the ecall can tell
+    /// before it uses one, and return what each of them returned
+    unsafe fn call_ecalls_that_need_keys() -> Vec<(&'static str, String)> {
+        let context = || Ctx {
+            data: std::ptr::null_mut(),
+        };
+        let (contract, env, msg, sig_info) = (b"contract", b"env", b"msg", b"sig_info");
+        let mut used_gas = 0_u64;
+        let mut results = vec![];
+
+        for ecall in &["ecall_init", "ecall_handle", "ecall_query"] {
+            let err = call_contract_ecall(
+                ecall,
+                &mut used_gas,
+                (contract.as_ptr(), contract.len()),
+                (env.as_ptr(), env.len()),
+                (msg.as_ptr(), msg.len()),
+                (sig_info.as_ptr(), sig_info.len()),
+            );
+            results.push((*ecall, format!("{:?}", err)));
+        }
+
+        macro_rules! contract_ecall {
+            ($ecall: ident, $result: ident, $($arg: expr),*) => {
+                let err = match $ecall($($arg),*) {
+                    $result::Failure { err, .. } => Some(err),
+                    _ => None,
+                };
+                results.push((stringify!($ecall), format!("{:?}", err)));
+            };
+        }
+        macro_rules! entry_point_ecall {
+            ($ecall: ident, $result: ident) => {
+                contract_ecall!(
+                    $ecall,
+                    $result,
+                    context(),
+                    1_000_000,
+                    &mut used_gas,
+                    contract.as_ptr(),
+                    contract.len(),
+                    env.as_ptr(),
+                    env.len(),
+                    msg.as_ptr(),
+                    msg.len()
+                );
+            };
+        }
+        entry_point_ecall!(ecall_reply, HandleResult);
+        entry_point_ecall!(ecall_ibc_channel_open, IbcResult);
+        entry_point_ecall!(ecall_ibc_channel_connect, IbcResult);
+        entry_point_ecall!(ecall_ibc_channel_close, IbcResult);
+        entry_point_ecall!(ecall_ibc_packet_receive, IbcReceiveResult);
+        entry_point_ecall!(ecall_ibc_packet_ack, IbcResult);
+        entry_point_ecall!(ecall_ibc_packet_timeout, IbcResult);
+        contract_ecall!(
+            ecall_migrate,
+            MigrateResult,
+            context(),
+            1_000_000,
+            &mut used_gas,
+            contract.as_ptr(),
+            contract.len(),
+            env.as_ptr(),
+            env.len(),
+            msg.as_ptr(),
+            msg.len(),
+            sig_info.as_ptr(),
+            sig_info.len()
+        );
+        contract_ecall!(
+            ecall_rotate_contract_key,
+            RotateContractKeyResult,
+            context(),
+            env.as_ptr(),
+            env.len(),
+            sig_info.as_ptr(),
+            sig_info.len(),
+            msg.as_ptr(),
+            msg.len(),
+            1
+        );
+        contract_ecall!(
+            ecall_export_contract_state,
+            ExportContractStateResult,
+            context(),
+            env.as_ptr(),
+            env.len(),
+            msg.as_ptr(),
+            msg.len(),
+            msg.as_ptr(),
+            msg.len(),
+            1
+        );
+        contract_ecall!(
+            ecall_import_contract_state,
+            ImportContractStateResult,
+            context(),
+            env.as_ptr(),
+            env.len(),
+            sig_info.as_ptr(),
+            sig_info.len(),
+            msg.as_ptr(),
+            msg.len()
+        );
+        contract_ecall!(
+            ecall_update_admin,
+            UpdateAdminResult,
+            env.as_ptr(),
+            env.len(),
+            sig_info.as_ptr(),
+            sig_info.len(),
+            msg.as_ptr(),
+            msg.len()
+        );
+        contract_ecall!(ecall_validate_wasm_begin, ValidateWasmResult, 1);
+        contract_ecall!(
+            ecall_validate_wasm_chunk,
+            ValidateWasmResult,
+            1,
+            0,
+            contract.as_ptr(),
+            contract.len()
+        );
+        contract_ecall!(ecall_validate_wasm_end, ValidateWasmResult, 1);
+
+        let mut statuses = [0u8; 1];
+        let entry = [0u8; PREWARM_ENTRY_SIZE];
+        let status = ecall_prewarm_contracts(
+            entry.as_ptr(),
+            entry.len(),
+            contract.as_ptr(),
+            contract.len(),
+            u64::MAX,
+            u64::MAX,
+            statuses.as_mut_ptr(),
+            statuses.len(),
+        );
+        results.push(("ecall_prewarm_contracts", format!("{:?}", status)));
+        let mut verification = VerifyInputResult::default();
+        let status = ecall_verify_input(
+            msg.as_ptr(),
+            msg.len(),
+            env.as_ptr(),
+            env.len(),
+            &mut verification,
+        );
+        results.push(("ecall_verify_input", format!("{:?}", status)));
+        let status = ecall_submit_block_header(msg.as_ptr(), msg.len());
+        results.push(("ecall_submit_block_header", format!("{:?}", status)));
+
+        let mut public_key = [0u8; PUBLIC_KEY_SIZE];
+        let status = ecall_init_bootstrap(
+            &mut public_key,
+            msg.as_ptr(),
+            msg.len() as u32,
+            msg.as_ptr(),
+            msg.len() as u32,
+        );
+        results.push(("ecall_init_bootstrap", format!("{:?}", status)));
+        let status = ecall_key_gen(&mut public_key);
+        results.push(("ecall_key_gen", format!("{:?}", status)));
+        let status = ecall_init_node(
+            msg.as_ptr(),
+            msg.len() as u32,
+            msg.as_ptr(),
+            msg.len() as u32,
+        );
+        results.push(("ecall_init_node", format!("{:?}", status)));
+        let status = ecall_get_attestation_report(
+            msg.as_ptr(),
+            msg.len() as u32,
+            msg.as_ptr(),
+            msg.len() as u32,
+        );
+        results.push(("ecall_get_attestation_report", format!("{:?}", status)));
+        let mut bundle = UserSpaceBuffer {
             ptr: std::ptr::null_mut(),
         };
-        assert_eq!(ECALL_ALLOCATE_STACK.lock().unwrap().len(), 0);
+        let status = ecall_get_attestation_bundle(&mut bundle);
+        results.push(("ecall_get_attestation_bundle", format!("{:?}", status)));
+        let status = ecall_submit_new_seed(msg.as_ptr(), msg.len() as u32);
+        results.push(("ecall_submit_new_seed", format!("{:?}", status)));
+        let mut seed = [0u8; crate::consts::ENCRYPTED_SEED_SIZE];
+        let result = ecall_authenticate_new_node(msg.as_ptr(), msg.len() as u32, 0, &mut seed);
+        results.push(("ecall_authenticate_new_node", format!("{:?}", result)));
+
+        let status = ecall_pin_decommission_operator(public_key.as_ptr());
+        results.push(("ecall_pin_decommission_operator", format!("{:?}", status)));
+        let mut challenge = [0u8; 32];
+        let mut erasure_public_key = [0u8; PUBLIC_KEY_SIZE];
+        let status =
+            ecall_decommission_arm(public_key.as_ptr(), &mut challenge, &mut erasure_public_key);
+        results.push(("ecall_decommission_arm", format!("{:?}", status)));
+        let mut erasure_record = UserSpaceBuffer {
+            ptr: std::ptr::null_mut(),
+        };
+        let status =
+            ecall_decommission_confirm(msg.as_ptr(), msg.len() as u32, &mut erasure_record);
+        results.push(("ecall_decommission_confirm", format!("{:?}", status)));
+
+        results
+    }
+
+    fn test_ecalls_refuse_to_run_after_decommission() {
+        let _ = decommission_throwaway_node();
+        let seed_status = seed_readiness::seed_status();
+        let results = unsafe { call_ecalls_that_need_keys() };
+        reset_decommission();
+
+        assert_eq!(seed_status, SeedStatus::Decommissioned);
+        let refusals = [
+            format!("{:?}", Some(EnclaveError::EnclaveNotInitialized)),
+            format!("{:?}", sgx_status_t::SGX_ERROR_INVALID_STATE),
+            format!("{:?}", NodeAuthResult::EnclaveNotInitialized),
+        ];
+        for (ecall, result) in results {
+            assert!(
+                refusals.contains(&result),
+                "{} returned {} after the node was decommissioned",
+                ecall,
+                result
+            );
+        }
+    }
+
+    fn test_ecall_configure_runtime_overrides_max_contract_size() {
+        let contract_len = b"contract".len();
+
+        configure_max_contract_size(contract_len as u64 - 1);
+        let (err, _) = ecall_with_contract_len("ecall_query", contract_len);
+        // 0 keeps the limit
+        configure_max_contract_size(0);
+        let kept_limit = wasm::max_contract_size();
+        wasm::set_max_contract_size(MAX_CODE_SIZE);
+
+        assert_eq!(
+            format!("{:?}", err),
+            format!("{:?}", Some(EnclaveError::ContractTooLarge))
+        );
+        assert_eq!(kept_limit, contract_len - 1);
+    }
+
+    fn test_ecall_get_runtime_stats_counts_unrecovered_buffers() {
+        let mut stats_before = RuntimeStats::default();
+        let status = unsafe { ecall_get_runtime_stats(&mut stats_before) };
+        assert_eq!(status, sgx_status_t::SGX_SUCCESS);
+
+        // Allocate a buffer and deliberately don't recover it yet
+        let message = b"leaked";
+        let enclave_buffer = allocate_buffer(message);
+        assert!(!enclave_buffer.ptr.is_null());
+
+        let mut stats_after = RuntimeStats::default();
+        let status = unsafe { ecall_get_runtime_stats(&mut stats_after) };
+        assert_eq!(status, sgx_status_t::SGX_SUCCESS);
+        assert_eq!(
+            stats_after.allocate_stack_len,
+            stats_before.allocate_stack_len + 1
+        );
+        assert!(stats_after.allocated_bytes > 0);
+        assert!(stats_after.safety_buffer_chunks <= stats_after.safety_buffer_target_chunks);
+
+        // Clean up so other tests see an empty stack
         let recovered = unsafe { recover_buffer(enclave_buffer) };
-        assert_eq!(ECALL_ALLOCATE_STACK.lock().unwrap().len(), 0);
-        assert_eq!(recovered.unwrap(), None);
+        assert_eq!(recovered.unwrap().unwrap(), message.to_vec());
     }
 
-    fn test_recover_enclave_buffer_in_recursion_valid() {
-        let recursion_depth = 10;
-        let messages: Vec<String> = (0..recursion_depth)
-            .map(|num| format!("message-{}", num))
-            .collect();
+    fn module_cache_misses() -> u64 {
+        let mut stats = RuntimeStats::default();
+        let status = unsafe { ecall_get_runtime_stats(&mut stats) };
+        assert_eq!(status, sgx_status_t::SGX_SUCCESS);
+        stats.module_cache_misses
+    }
 
-        // simulate building up the stack recursively
-        let enclave_buffers: Vec<EnclaveBuffer> = messages
-            .iter()
-            .enumerate()
-            .map(|(index, message)| {
-                assert_eq!(ECALL_ALLOCATE_STACK.lock().unwrap().len(), index);
-                unsafe { ecall_allocate(message.as_ptr(), message.len()) }
-            })
-            .collect();
+    fn test_ecall_prewarm_contracts_skips_validation_on_next_call() {
+        let contracts: [&[u8]; 3] = [
+            include_bytes!("../testdata/api-versions/v0_10.wasm"),
+            include_bytes!("../testdata/api-versions/v1.wasm"),
+            include_bytes!("../testdata/api-versions/unmarked_v1.wasm"),
+        ];
+        let mut entries = vec![];
+        let mut code = vec![];
+        for contract in contracts.iter() {
+            entries.extend_from_slice(&crate::crypto::sha_256(contract));
+            entries.extend_from_slice(&(code.len() as u32).to_be_bytes());
+            entries.extend_from_slice(&(contract.len() as u32).to_be_bytes());
+            code.extend_from_slice(contract);
+        }
+        wasm::module_cache::evict_under_memory_pressure();
+        let misses_before = module_cache_misses();
 
-        // simulate clearing the stack recursively
-        for (index, (message, enclave_buffer)) in messages
-            .into_iter()
-            .zip(enclave_buffers.into_iter())
-            .enumerate()
-            .rev()
-        {
-            assert_eq!(ECALL_ALLOCATE_STACK.lock().unwrap().len(), index + 1);
-            let recovered = unsafe { recover_buffer(enclave_buffer) };
-            assert_eq!(recovered.unwrap().unwrap(), message.as_bytes())
+        let mut statuses = [u8::MAX; 3];
+        let status = unsafe {
+            ecall_prewarm_contracts(
+                entries.as_ptr(),
+                entries.len(),
+                code.as_ptr(),
+                code.len(),
+                u64::MAX,
+                u64::MAX,
+                statuses.as_mut_ptr(),
+                statuses.len(),
+            )
+        };
+        assert_eq!(status, sgx_status_t::SGX_SUCCESS);
+        assert_eq!(statuses, [PrewarmStatus::Warmed as u8; 3]);
+        assert_eq!(module_cache_misses(), misses_before + 3);
+
+        // Executions load their module the same way, and find it cached
+        for contract in contracts.iter() {
+            wasm::module_cache::get_or_analyze(
+                &crate::crypto::sha_256(contract),
+                contract.len(),
+                || unreachable!("the contract was validated again"),
+            )
+            .unwrap();
         }
-        assert_eq!(ECALL_ALLOCATE_STACK.lock().unwrap().len(), 0)
+        assert_eq!(module_cache_misses(), misses_before + 3);
     }
 
-    // This test is very similar to the test above, except it tries to give incorrect
-    // inputs in the deepest part of the recursion
-    fn test_recover_enclave_buffer_in_recursion_invalid() {
-        let recursion_depth = 10;
-        let messages: Vec<String> = (0..recursion_depth)
-            .map(|num| format!("message-{}", num))
-            .collect();
+    fn test_ecall_prewarm_contracts_rejects_mismatched_statuses() {
+        let entries = [0u8; PREWARM_ENTRY_SIZE * 2];
+        let mut statuses = [u8::MAX; 1];
+        for entries_len in &[entries.len(), PREWARM_ENTRY_SIZE + 1] {
+            let status = unsafe {
+                ecall_prewarm_contracts(
+                    entries.as_ptr(),
+                    *entries_len,
+                    std::ptr::null(),
+                    0,
+                    u64::MAX,
+                    u64::MAX,
+                    statuses.as_mut_ptr(),
+                    statuses.len(),
+                )
+            };
+            assert_eq!(status, sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        assert_eq!(statuses, [u8::MAX]);
+    }
 
-        // simulate building up the stack recursively
-        let enclave_buffers: Vec<EnclaveBuffer> = messages
-            .iter()
-            .enumerate()
-            .map(|(index, message)| {
-                assert_eq!(ECALL_ALLOCATE_STACK.lock().unwrap().len(), index);
-                unsafe { ecall_allocate(message.as_ptr(), message.len()) }
-            })
-            .collect();
+    fn test_ecall_get_capabilities() {
+        let mut buf = [0u8; 64];
+        let mut written = 0usize;
+        let status = unsafe { ecall_get_capabilities(buf.as_mut_ptr(), buf.len(), &mut written) };
+        assert_eq!(status, sgx_status_t::SGX_SUCCESS);
+        assert_eq!(written, EnclaveCapabilities::ENCODED_LEN);
 
-        let message = Box::new(Vec::<u8>::from(&b"some example text"[..]));
-        let enclave_buffer = EnclaveBuffer {
-            ptr: message.as_ptr() as _,
-        };
-        assert_eq!(ECALL_ALLOCATE_STACK.lock().unwrap().len(), recursion_depth);
-        let recovered = unsafe { recover_buffer(enclave_buffer) };
-        assert_eq!(ECALL_ALLOCATE_STACK.lock().unwrap().len(), recursion_depth);
-        assert_eq!(recovered.unwrap_err(), BufferRecoveryError);
+        let capabilities = EnclaveCapabilities::read_from(&buf[..written]);
+        assert_eq!(capabilities, enclave_capabilities());
+        assert_ne!(
+            capabilities.entry_points & EnclaveCapabilities::ENTRY_POINT_REPLY,
+            0
+        );
+        assert_ne!(
+            capabilities.host_functions & EnclaveCapabilities::HOST_DB_READ_MULTI,
+            0
+        );
+    }
 
-        // simulate clearing the stack recursively
-        for (index, (message, enclave_buffer)) in messages
-            .into_iter()
-            .zip(enclave_buffers.into_iter())
-            .enumerate()
-            .rev()
-        {
-            assert_eq!(ECALL_ALLOCATE_STACK.lock().unwrap().len(), index + 1);
-            let recovered = unsafe { recover_buffer(enclave_buffer) };
-            assert_eq!(recovered.unwrap().unwrap(), message.as_bytes())
+    fn export_metrics() -> String {
+        let mut written = 0usize;
+        let status = unsafe { ecall_export_metrics(std::ptr::null_mut(), 0, &mut written) };
+        assert_eq!(status, sgx_status_t::SGX_SUCCESS);
+
+        // Nothing is written to a buffer that is too small
+        let mut buf = vec![0u8; written + 1024];
+        let status = unsafe { ecall_export_metrics(buf.as_mut_ptr(), 16, &mut written) };
+        assert_eq!(status, sgx_status_t::SGX_SUCCESS);
+        assert!(written > 16);
+        assert!(buf.iter().all(|byte| *byte == 0));
+
+        let status = unsafe { ecall_export_metrics(buf.as_mut_ptr(), buf.len(), &mut written) };
+        assert_eq!(status, sgx_status_t::SGX_SUCCESS);
+        buf.truncate(written);
+        String::from_utf8(buf).unwrap()
+    }
+
+    /// The value of the sample `series` in `metrics`, or 0 if it isn't there
+    fn sample_value(metrics: &str, series: &str) -> u64 {
+        metrics
+            .lines()
+            .find_map(|line| line.strip_prefix(series)?.strip_prefix(' '))
+            .map_or(0, |value| value.parse().unwrap())
+    }
+
+    fn test_ecall_export_metrics_counts_failed_ecalls() {
+        let calls = "secret_enclave_ecalls_total{ecall=\"query\"}";
+        let failures =
+            "secret_enclave_ecall_failures_total{ecall=\"query\",error=\"InvalidEnvPointer\"}";
+        let before = export_metrics();
+
+        for _ in 0..3 {
+            assert_eq!(
+                format!(
+                    "{:?}",
+                    ecall_with_null_pointer("ecall_query", EcallPointer::Env)
+                ),
+                format!("{:?}", Some(EnclaveError::InvalidEnvPointer))
+            );
         }
-        assert_eq!(ECALL_ALLOCATE_STACK.lock().unwrap().len(), 0)
+
+        let after = export_metrics();
+        assert_eq!(
+            sample_value(&after, calls),
+            sample_value(&before, calls) + 3
+        );
+        assert_eq!(
+            sample_value(&after, failures),
+            sample_value(&before, failures) + 3
+        );
     }
 
-    // These tests are vry similar to the recursion tests,
-    // except that they don't release the buffers in a FIFO order.
-    // In this case, I just release them in a FILO order, which should be worst-case.
+    fn test_capabilities_for_older_reader() {
+        // a node that only knows the version and API fields gives the enclave a smaller buffer
+        let mut buf = [0u8; 4 * 6];
+        let written = enclave_capabilities().write_to(&mut buf);
+        assert_eq!(written, buf.len());
 
-    fn test_recover_enclave_buffer_multiple_out_of_order_valid() {
-        let recursion_depth = 10;
-        let messages: Vec<String> = (0..recursion_depth)
-            .map(|num| format!("message-{}", num))
-            .collect();
+        let capabilities = EnclaveCapabilities::read_from(&buf);
+        assert_eq!(
+            capabilities.version_major,
+            enclave_capabilities().version_major
+        );
+        assert_eq!(capabilities.cosmwasm_api_max, 3);
+        assert_eq!(capabilities.entry_points, 0);
+        assert_eq!(capabilities.host_functions, 0);
 
-        // simulate building up the stack recursively
-        let enclave_buffers: Vec<EnclaveBuffer> = messages
-            .iter()
-            .enumerate()
-            .map(|(index, message)| {
-                assert_eq!(ECALL_ALLOCATE_STACK.lock().unwrap().len(), index);
-                unsafe { ecall_allocate(message.as_ptr(), message.len()) }
-            })
-            .collect();
+        // too small for anything
+        assert_eq!(enclave_capabilities().write_to(&mut [0u8; 3]), 0);
+    }
+
+    fn test_capabilities_from_older_enclave() {
+        // an enclave that didn't know about host functions yet
+        let mut buf = vec![28, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 3, 0, 0, 0, 3, 0, 0, 0];
+        buf.extend_from_slice(&[3, 0, 0, 0, 1, 0, 0, 0]);
+
+        let capabilities = EnclaveCapabilities::read_from(&buf);
+        assert_eq!(
+            capabilities,
+            EnclaveCapabilities {
+                version_major: 1,
+                version_minor: 2,
+                version_patch: 3,
+                cosmwasm_api_min: 3,
+                cosmwasm_api_max: 3,
+                entry_points: EnclaveCapabilities::ENTRY_POINT_MIGRATE,
+                host_functions: 0,
+            }
+        );
+
+        // an enclave from before the capabilities existed writes nothing
+        assert_eq!(
+            EnclaveCapabilities::read_from(&[]),
+            EnclaveCapabilities::default()
+        );
+    }
+
+    fn test_capabilities_from_newer_enclave() {
+        // fields appended after the ones this node knows are skipped
+        let mut buf = [0u8; EnclaveCapabilities::ENCODED_LEN + 8];
+        let written = enclave_capabilities().write_to(&mut buf);
+        buf[..4].copy_from_slice(&(buf.len() as u32).to_le_bytes());
+        buf[written..].copy_from_slice(&[0xff; 8]);
+
+        assert_eq!(EnclaveCapabilities::read_from(&buf), enclave_capabilities());
+    }
+
+    fn test_allocate_stack_guard_frees_buffers_after_panic() {
+        let stack_len_before = allocate_stack_len();
+        let allocated_bytes_before = runtime_stats::allocated_bytes();
+        let message = vec![7u8; 1024 * 1024];
 
-        // simulate clearing the stack recursively
-        // `.rev().enumerate().rev()` means that we'll be iterating over the lists in order, with reversed indexes.
-        for (index, (message, enclave_buffer)) in messages
-            .into_iter()
-            .zip(enclave_buffers.into_iter())
-            .rev()
-            .enumerate()
-            .rev()
         {
-            assert_eq!(ECALL_ALLOCATE_STACK.lock().unwrap().len(), index + 1);
-            let recovered = unsafe { recover_buffer(enclave_buffer) };
-            assert_eq!(recovered.unwrap().unwrap(), message.as_bytes())
+            let _allocate_stack_guard = AllocateStackGuard::new();
+            let result = panic::catch_unwind(|| {
+                // An ocall allocated its response in the enclave...
+                let enclave_buffer = allocate_buffer(&message);
+                assert!(!enclave_buffer.ptr.is_null());
+                // ...but the ecall panicked before recovering it
+                panic!("panic after ocall");
+            });
+            assert!(result.is_err());
+            assert_eq!(allocate_stack_len(), stack_len_before + 1);
         }
-        assert_eq!(ECALL_ALLOCATE_STACK.lock().unwrap().len(), 0)
+
+        assert_eq!(allocate_stack_len(), stack_len_before);
+        assert!(runtime_stats::allocated_bytes() < allocated_bytes_before + message.len());
     }
 
-    fn test_recover_enclave_buffer_multiple_out_of_order_invalid() {
-        let recursion_depth = 10;
-        let messages: Vec<String> = (0..recursion_depth)
-            .map(|num| format!("message-{}", num))
-            .collect();
+    fn test_allocate_stack_guard_in_nested_ecalls() {
+        let outer_message = b"outer";
+        let inner_message = b"inner";
 
-        // simulate building up the stack recursively
-        let enclave_buffers: Vec<EnclaveBuffer> = messages
-            .iter()
-            .enumerate()
-            .map(|(index, message)| {
-                assert_eq!(ECALL_ALLOCATE_STACK.lock().unwrap().len(), index);
-                unsafe { ecall_allocate(message.as_ptr(), message.len()) }
-            })
-            .collect();
+        let _outer_guard = AllocateStackGuard::new();
+        let outer_buffer = allocate_buffer(outer_message);
 
-        let message = Box::new(Vec::<u8>::from(&b"some example text"[..]));
-        let enclave_buffer = EnclaveBuffer {
-            ptr: message.as_ptr() as _,
-        };
-        assert_eq!(ECALL_ALLOCATE_STACK.lock().unwrap().len(), recursion_depth);
+        {
+            let _inner_guard = AllocateStackGuard::new();
+            let inner_buffer = allocate_buffer(inner_message);
+            assert!(!inner_buffer.ptr.is_null());
+            // The inner ecall never recovers its buffer
+        }
+
+        // Only the inner ecall's buffer was freed, the outer one can still be recovered
+        let recovered = unsafe { recover_buffer(outer_buffer) };
+        assert_eq!(recovered.unwrap().unwrap(), outer_message.to_vec());
+    }
+
+    fn test_recover_enclave_buffer_replayed() {
+        let message = b"some example text";
+        let enclave_buffer = allocate_buffer(message);
+        let replayed_buffer = unsafe { enclave_buffer.unsafe_clone() };
         let recovered = unsafe { recover_buffer(enclave_buffer) };
-        assert_eq!(ECALL_ALLOCATE_STACK.lock().unwrap().len(), recursion_depth);
-        assert_eq!(recovered.unwrap_err(), BufferRecoveryError);
+        assert_eq!(recovered.unwrap().unwrap(), message);
 
-        // simulate clearing the stack recursively
-        // `.rev().enumerate().rev()` means that we'll be iterating over the lists in order, with reversed indexes.
-        for (index, (message, enclave_buffer)) in messages
-            .into_iter()
-            .zip(enclave_buffers.into_iter())
-            .rev()
-            .enumerate()
-            .rev()
-        {
-            assert_eq!(ECALL_ALLOCATE_STACK.lock().unwrap().len(), index + 1);
-            let recovered = unsafe { recover_buffer(enclave_buffer) };
-            assert_eq!(recovered.unwrap().unwrap(), message.as_bytes())
+        // The buffer was already freed, so returning it again must not free it a second time
+        let recovered = unsafe { recover_buffer(replayed_buffer) };
+        assert_eq!(recovered.unwrap_err(), BufferRecoveryError::UnknownBuffer);
+        assert_eq!(allocate_stack_len(), 0);
+    }
+
+    fn test_recover_enclave_buffer_stale_generation() {
+        let message = b"some example text";
+        let enclave_buffer = allocate_buffer(message);
+        assert_ne!(enclave_buffer.generation, 0);
+
+        // Same address as a live buffer, as if the allocator reused the address of a buffer that
+        // was already recovered
+        for generation in &[
+            0,
+            enclave_buffer.generation - 1,
+            enclave_buffer.generation + 1,
+        ] {
+            let stale_buffer = EnclaveBuffer {
+                ptr: enclave_buffer.ptr,
+                generation: *generation,
+            };
+            let recovered = unsafe { recover_buffer(stale_buffer) };
+            assert_eq!(recovered.unwrap(), None);
+            assert_eq!(allocate_stack_len(), 1);
+        }
+
+        // The live buffer is left intact for its owner
+        let recovered = unsafe { recover_buffer(enclave_buffer) };
+        assert_eq!(recovered.unwrap().unwrap(), message);
+        assert_eq!(allocate_stack_len(), 0);
+    }
+
+    fn test_recover_enclave_buffer_interleaved() {
+        let allocated_buffers_before = ALLOCATED_BUFFERS.load(Ordering::SeqCst);
+        let messages: Vec<String> = (0..64).map(|num| format!("message-{}", num)).collect();
+
+        // Allocate two buffers and recover one, over and over, like ocalls of concurrent queries
+        // would if they shared a stack
+        let mut pending = Vec::new();
+        for pair in messages.chunks(2) {
+            for message in pair {
+                let buffer = allocate_buffer(message);
+                pending.push((message.clone(), buffer));
+            }
+            let (message, buffer) = pending.remove(pending.len() / 2);
+            let recovered = unsafe { recover_buffer(buffer) };
+            assert_eq!(recovered.unwrap().unwrap(), message.as_bytes());
+            assert_eq!(allocate_stack_len(), pending.len());
+            assert_eq!(
+                ALLOCATED_BUFFERS.load(Ordering::SeqCst),
+                allocated_buffers_before + pending.len()
+            );
+        }
+
+        for (message, buffer) in pending {
+            let recovered = unsafe { recover_buffer(buffer) };
+            assert_eq!(recovered.unwrap().unwrap(), message.as_bytes());
         }
-        assert_eq!(ECALL_ALLOCATE_STACK.lock().unwrap().len(), 0)
+        assert_eq!(allocate_stack_len(), 0);
+        assert_eq!(
+            ALLOCATED_BUFFERS.load(Ordering::SeqCst),
+            allocated_buffers_before
+        );
     }
 }
@@ -0,0 +1,128 @@
+use std::io::Read;
+use std::untrusted::fs::File;
+
+use enclave_ffi_types::{Ctx, HealthCheckDeepResult};
+use log::*;
+
+use crate::consts::{ATTESTATION_CERTIFICATE_SAVE_PATH, HEALTH_CHECK_KEY_DERIVE_ORDER};
+use crate::crypto::{AESKey, Kdf, SIVEncryptable, Seed, KEY_MANAGER};
+use crate::registration::get_netscape_comment;
+use crate::seed_readiness::seed_status;
+use crate::wasm::storage_round_trip;
+
+/// The value the checks encrypt and store, and expect to get back unchanged
+const CANARY: &[u8] = b"secret health check canary";
+
+/// Check the subsystems the enclave needs to process transactions, and report which of them work.
+/// The seed status is the one contract ecalls go by, see `seed_readiness`.
+pub fn deep_health_check(context: &Ctx) -> HealthCheckDeepResult {
+    let seed_status = seed_status();
+    let mut passed = crypto_checks(KEY_MANAGER.get_consensus_seed().ok());
+
+    match storage_round_trip(context, CANARY) {
+        Ok(true) => passed |= HealthCheckDeepResult::STORAGE,
+        Ok(false) => warn!("Health check read back a different value than it wrote to storage"),
+        Err(err) => warn!("Health check failed to access storage: {:?}", err),
+    }
+
+    if registration_cert_loads(ATTESTATION_CERTIFICATE_SAVE_PATH) {
+        passed |= HealthCheckDeepResult::REGISTRATION_CERT;
+    }
+
+    HealthCheckDeepResult {
+        passed,
+        seed_status,
+    }
+}
+
+/// Derive a key from the consensus seed, and encrypt and decrypt the canary with it.
+/// The seed is `None` when it couldn't be unsealed.
+fn crypto_checks(seed: Option<Seed>) -> u32 {
+    let seed = match seed {
+        Some(seed) => seed,
+        None => {
+            warn!("Health check could not access the consensus seed");
+            return 0;
+        }
+    };
+    let key: AESKey = seed.derive_key_from_this(&HEALTH_CHECK_KEY_DERIVE_ORDER.to_be_bytes());
+    let mut passed = HealthCheckDeepResult::SEED;
+
+    let round_trip = key
+        .encrypt_siv(CANARY, None)
+        .and_then(|ciphertext| key.decrypt_siv(&ciphertext, None));
+    match round_trip {
+        Ok(plaintext) if plaintext == CANARY => passed |= HealthCheckDeepResult::ENCRYPTION,
+        Ok(_) => warn!("Health check decrypted a different value than it encrypted"),
+        Err(err) => warn!("Health check failed to encrypt and decrypt: {:?}", err),
+    }
+
+    passed
+}
+
+/// Check that the attestation certificate at `path` can be read, and holds registration data
+fn registration_cert_loads(path: &str) -> bool {
+    let mut cert = vec![];
+    if let Err(err) = File::open(path).and_then(|mut file| file.read_to_end(&mut cert)) {
+        warn!(
+            "Health check could not read the attestation certificate: {:?}",
+            err
+        );
+        return false;
+    }
+
+    if get_netscape_comment(&cert).is_err() {
+        warn!("Health check found no registration data in the attestation certificate");
+        return false;
+    }
+
+    true
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+    use crate::count_failures;
+    use crate::crypto::SealedKey;
+
+    pub fn run_tests() {
+        println!();
+        let mut failures = 0;
+
+        count_failures!(failures, {
+            test_crypto_checks_pass();
+            test_crypto_checks_seed_unseal_fails();
+            test_registration_cert_loads();
+        });
+
+        if failures != 0 {
+            panic!("{}: {} tests failed", file!(), failures);
+        }
+    }
+
+    fn test_crypto_checks_pass() {
+        let seed = Seed::new().unwrap();
+        assert_eq!(
+            crypto_checks(Some(seed)),
+            HealthCheckDeepResult::SEED | HealthCheckDeepResult::ENCRYPTION
+        );
+    }
+
+    fn test_crypto_checks_seed_unseal_fails() {
+        let seed = Seed::unseal("./.sgx_secrets/no_such_seed.sealed");
+        assert!(seed.is_err());
+
+        let passed = crypto_checks(seed.ok());
+        assert_eq!(passed & HealthCheckDeepResult::SEED, 0);
+        assert_eq!(passed & HealthCheckDeepResult::ENCRYPTION, 0);
+    }
+
+    fn test_registration_cert_loads() {
+        assert!(registration_cert_loads(
+            "../wasmi-runtime/src/registration/fixtures/attestation_cert_sw"
+        ));
+        assert!(!registration_cert_loads(
+            "../wasmi-runtime/src/registration/fixtures/no_such_cert.der"
+        ));
+    }
+}
@@ -20,6 +20,25 @@ extern "C" {
         key_len: usize,
     ) -> sgx_status_t;
 
+    pub fn ocall_read_db_multi(
+        retval: *mut OcallReturn,
+        context: Ctx,
+        vm_error: *mut UntrustedVmError,
+        gas_used: *mut u64,
+        values: *mut EnclaveBuffer,
+        keys: *const u8,
+        keys_len: usize,
+    ) -> sgx_status_t;
+
+    pub fn ocall_prefetch_db(
+        retval: *mut OcallReturn,
+        context: Ctx,
+        vm_error: *mut UntrustedVmError,
+        values: *mut EnclaveBuffer,
+        keys: *const u8,
+        keys_len: usize,
+    ) -> sgx_status_t;
+
     pub fn ocall_query_chain(
         retval: *mut OcallReturn,
         context: Ctx,
@@ -40,6 +59,28 @@ extern "C" {
         key_len: usize,
     ) -> sgx_status_t;
 
+    pub fn ocall_db_scan(
+        retval: *mut OcallReturn,
+        context: Ctx,
+        vm_error: *mut UntrustedVmError,
+        gas_used: *mut u64,
+        iterator_id: *mut u32,
+        start: *const u8,
+        start_len: usize,
+        end: *const u8,
+        end_len: usize,
+    ) -> sgx_status_t;
+
+    pub fn ocall_db_next(
+        retval: *mut OcallReturn,
+        context: Ctx,
+        vm_error: *mut UntrustedVmError,
+        gas_used: *mut u64,
+        iterator_id: u32,
+        key: *mut EnclaveBuffer,
+        value: *mut EnclaveBuffer,
+    ) -> sgx_status_t;
+
     pub fn ocall_write_db(
         retval: *mut OcallReturn,
         context: Ctx,
@@ -50,6 +91,22 @@ extern "C" {
         value: *const u8,
         value_len: usize,
     ) -> sgx_status_t;
+
+    pub fn ocall_monotonic_now(retval: *mut u64) -> sgx_status_t;
+
+    pub fn ocall_write_log(lines: *const u8, lines_len: usize) -> sgx_status_t;
+
+    pub fn ocall_store_module_artifact(
+        code_hash: *const u8,
+        artifact: *const u8,
+        artifact_len: usize,
+    ) -> sgx_status_t;
+
+    pub fn ocall_load_module_artifact(
+        retval: *mut OcallReturn,
+        code_hash: *const u8,
+        artifact: *mut EnclaveBuffer,
+    ) -> sgx_status_t;
 }
 
 extern "C" {
@@ -72,4 +129,34 @@ extern "C" {
         maxlen: u32,
         p_quote_len: *mut u32,
     ) -> sgx_status_t;
+    pub fn ocall_get_dcap_target_info(
+        ret_val: *mut sgx_status_t,
+        ret_ti: *mut sgx_target_info_t,
+    ) -> sgx_status_t;
+    pub fn ocall_get_dcap_quote(
+        ret_val: *mut sgx_status_t,
+        p_report: *const sgx_report_t,
+        p_quote: *mut u8,
+        maxlen: u32,
+        p_quote_len: *mut u32,
+    ) -> sgx_status_t;
+    pub fn ocall_get_dcap_collateral(
+        ret_val: *mut sgx_status_t,
+        p_fmspc: *const u8,
+        fmspc_len: u32,
+        p_pck_ca: *const u8,
+        pck_ca_len: u32,
+        p_tcb_info: *mut u8,
+        tcb_info_maxlen: u32,
+        p_tcb_info_len: *mut u32,
+        p_issuer_chain: *mut u8,
+        issuer_chain_maxlen: u32,
+        p_issuer_chain_len: *mut u32,
+        p_root_ca_crl: *mut u8,
+        root_ca_crl_maxlen: u32,
+        p_root_ca_crl_len: *mut u32,
+        p_pck_crl: *mut u8,
+        pck_crl_maxlen: u32,
+        p_pck_crl_len: *mut u32,
+    ) -> sgx_status_t;
 }
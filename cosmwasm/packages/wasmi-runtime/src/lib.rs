@@ -17,12 +17,19 @@ use crate::logger::*;
 
 mod macros;
 
+mod ecall_trace;
 pub mod exports;
+mod health_check;
 pub mod imports;
+mod log_buffer;
 pub mod logger;
+mod metrics;
 mod oom_handler;
+mod panic_report;
 mod recursion_depth;
 pub mod registration;
+mod runtime_stats;
+mod seed_readiness;
 use std::env;
 
 mod consts;
@@ -82,16 +89,20 @@ fn set_log_level_or_default(default: LevelFilter, max_level: LevelFilter) {
         }
     }
 
-    log::set_max_level(log_level);
+    init_log_levels(log_level, max_level);
 }
 
 #[cfg(feature = "test")]
 pub mod logging_tests {
+    use crate::exports::ecall_configure_runtime;
+    use crate::logger::log_enabled;
     use crate::{count_failures, set_log_level_or_default};
     use ctor::*;
+    use enclave_ffi_types::RuntimeConfiguration;
     use lazy_static::lazy_static;
     use log::*;
     use log::{Metadata, Record};
+    use sgx_types::sgx_status_t;
     use std::sync::SgxMutex;
     use std::{env, panic};
 
@@ -100,10 +111,13 @@ pub mod logging_tests {
     }
     pub struct TestLogger;
     impl log::Log for TestLogger {
-        fn enabled(&self, _metadata: &Metadata) -> bool {
-            true
+        fn enabled(&self, metadata: &Metadata) -> bool {
+            log_enabled(metadata)
         }
         fn log(&self, record: &Record) {
+            if !self.enabled(record.metadata()) {
+                return;
+            }
             LOG_BUF.lock().unwrap().push(format!(
                 "{}  [{}] {}",
                 record.level(),
@@ -126,6 +140,7 @@ pub mod logging_tests {
         count_failures!(failures, {
             test_log_level();
             test_log_default_greater_than_max();
+            test_configure_runtime_log_level();
         });
 
         if failures != 0 {
@@ -162,4 +177,61 @@ pub mod logging_tests {
         });
         assert!(result.is_err());
     }
+
+    fn log_at_every_level() {
+        error!("error");
+        warn!("warn");
+        info!("info");
+        debug!("debug");
+        trace!("trace");
+    }
+
+    fn configure_log_level(max_log_level: u8) -> sgx_status_t {
+        let config = RuntimeConfiguration {
+            max_log_level,
+            default_query_gas_limit: 0,
+            max_contract_size: 0,
+            query_cache_size: 0,
+            ecall_tracing: 0,
+        };
+        let status = unsafe { ecall_configure_runtime(&config) };
+        // Ignore whatever the ecall itself logged
+        LOG_BUF.lock().unwrap().clear();
+        status
+    }
+
+    fn test_configure_runtime_log_level() {
+        env::set_var("LOG_LEVEL", "WARN");
+        set_log_level_or_default(LevelFilter::Warn, LevelFilter::Debug);
+
+        assert_eq!(
+            configure_log_level(LevelFilter::Warn as u8),
+            sgx_status_t::SGX_SUCCESS
+        );
+        log_at_every_level();
+        assert_eq!(LOG_BUF.lock().unwrap().len(), 2);
+
+        assert_eq!(
+            configure_log_level(LevelFilter::Info as u8),
+            sgx_status_t::SGX_SUCCESS
+        );
+        log_at_every_level();
+        assert_eq!(LOG_BUF.lock().unwrap().len(), 3);
+
+        // The level is capped at what the build allows
+        assert_eq!(
+            configure_log_level(LevelFilter::Trace as u8),
+            sgx_status_t::SGX_SUCCESS
+        );
+        log_at_every_level();
+        assert_eq!(LOG_BUF.lock().unwrap().len(), 4);
+
+        assert_eq!(
+            configure_log_level(6),
+            sgx_status_t::SGX_ERROR_INVALID_PARAMETER
+        );
+        log_at_every_level();
+        assert_eq!(LOG_BUF.lock().unwrap().len(), 4);
+        LOG_BUF.lock().unwrap().clear();
+    }
 }
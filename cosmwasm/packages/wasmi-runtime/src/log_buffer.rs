@@ -0,0 +1,241 @@
+//! Batching the log lines of an ecall into one ocall.
+//!
+//! Printing a line from the enclave takes an ocall, so a contract that calls `debug_print` in a loop
+//! spent most of its time leaving the enclave. While a contract ecall runs, the lines it logs,
+//! including those of `debug_print`, are appended to a buffer of its thread instead, and written out
+//! by a single `ocall_write_log` when the ecall returns, or earlier when `LOG_BATCH_SIZE` bytes were
+//! collected. Lines are written in the order they were logged. The buffer is flushed when the
+//! `LogBatch` of the ecall is dropped, so the lines also come out when the contract trapped or the
+//! ecall panicked.
+//!
+//! Ecalls that a contract makes through an ocall run on the same thread, and log into the batch of
+//! the ecall that is already running. A line that doesn't fit in the buffer on its own is cut, and
+//! ends with `TRUNCATED_LOG_MARKER`.
+
+use std::cell::RefCell;
+
+use sgx_types::sgx_status_t;
+
+use crate::consts::LOG_BATCH_SIZE;
+use crate::ecall_trace::Phase;
+use crate::imports;
+use crate::metrics::{self, Ocall};
+use crate::trace_span;
+
+/// Ends a line that was cut to fit in the buffer
+pub const TRUNCATED_LOG_MARKER: &str = " [truncated]";
+
+thread_local! {
+    static LOG_BATCH: RefCell<Option<LogBuffer>> = RefCell::new(None);
+}
+
+/// Batches the lines that are logged on this thread until it's dropped
+pub struct LogBatch {
+    /// Only the batch of the outermost ecall flushes the lines
+    outermost: bool,
+}
+
+impl LogBatch {
+    pub fn begin() -> Self {
+        let outermost = LOG_BATCH.with(|batch| {
+            let mut batch = batch.borrow_mut();
+            if batch.is_some() {
+                return false;
+            }
+            *batch = Some(LogBuffer::new(LOG_BATCH_SIZE));
+            true
+        });
+
+        Self { outermost }
+    }
+}
+
+impl Drop for LogBatch {
+    fn drop(&mut self) {
+        if !self.outermost {
+            return;
+        }
+
+        if let Some(mut buffer) = LOG_BATCH.with(|batch| batch.borrow_mut().take()) {
+            buffer.flush(&mut write_log);
+        }
+    }
+}
+
+/// Print `line`, or add it to the batch of the ecall that is running on this thread
+pub fn write_line(line: &str) {
+    let batched = LOG_BATCH.with(|batch| match batch.try_borrow_mut() {
+        Ok(mut batch) => match batch.as_mut() {
+            Some(buffer) => {
+                buffer.push(line, &mut write_log);
+                true
+            }
+            None => false,
+        },
+        // The line was logged while the batch is being written out
+        Err(_) => false,
+    });
+
+    if !batched {
+        println!("{}", line);
+    }
+}
+
+fn write_log(lines: &[u8]) {
+    metrics::record_ocall(Ocall::WriteLog);
+    let _span = trace_span!(Phase::Ocall(Ocall::WriteLog));
+    let status = unsafe { imports::ocall_write_log(lines.as_ptr(), lines.len()) };
+    if status != sgx_status_t::SGX_SUCCESS {
+        // Not logged, since that would only add the failure to the lines that were lost
+        println!("ocall_write_log failed: {}", status);
+    }
+}
+
+/// Newline terminated lines, up to `capacity` bytes
+pub struct LogBuffer {
+    lines: Vec<u8>,
+    capacity: usize,
+}
+
+impl LogBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            lines: vec![],
+            capacity,
+        }
+    }
+
+    /// Append `line`, after passing the lines before it to `write` if it doesn't fit
+    pub fn push(&mut self, line: &str, write: &mut dyn FnMut(&[u8])) {
+        if self.lines.len() + line.len() + 1 > self.capacity {
+            self.flush(write);
+        }
+
+        if line.len() + 1 > self.capacity {
+            let mut end = self.capacity.saturating_sub(TRUNCATED_LOG_MARKER.len() + 1);
+            while !line.is_char_boundary(end) {
+                end -= 1;
+            }
+            self.lines.extend_from_slice(line[..end].as_bytes());
+            self.lines
+                .extend_from_slice(TRUNCATED_LOG_MARKER.as_bytes());
+        } else {
+            self.lines.extend_from_slice(line.as_bytes());
+        }
+        self.lines.push(b'\n');
+    }
+
+    /// Pass the lines to `write`, if there are any
+    pub fn flush(&mut self, write: &mut dyn FnMut(&[u8])) {
+        if !self.lines.is_empty() {
+            write(&self.lines);
+            self.lines.clear();
+        }
+    }
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+    use crate::count_failures;
+
+    use std::panic;
+
+    pub fn run_tests() {
+        println!();
+        let mut failures = 0;
+
+        count_failures!(failures, {
+            test_log_buffer_batches_lines();
+            test_log_buffer_flushes_when_full();
+            test_log_buffer_truncates_long_lines();
+            test_log_batch_flushes_after_panic();
+            test_nested_log_batch();
+        });
+
+        if failures != 0 {
+            panic!("{}: {} tests failed", file!(), failures);
+        }
+    }
+
+    /// Collects what the buffer writes, one entry per write
+    fn collect_writes(buffer: &mut LogBuffer, lines: &[&str]) -> Vec<String> {
+        let mut writes = vec![];
+        {
+            let mut write = |lines: &[u8]| writes.push(String::from_utf8(lines.to_vec()).unwrap());
+            for line in lines {
+                buffer.push(line, &mut write);
+            }
+            buffer.flush(&mut write);
+        }
+        writes
+    }
+
+    fn test_log_buffer_batches_lines() {
+        let lines: Vec<String> = (0..1_000).map(|i| format!("debug_print: {}", i)).collect();
+        let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+
+        let writes = collect_writes(&mut LogBuffer::new(LOG_BATCH_SIZE), &lines);
+        assert_eq!(writes.len(), 1);
+        assert_eq!(writes[0], lines.join("\n") + "\n");
+
+        // Nothing is written when nothing was logged
+        assert!(collect_writes(&mut LogBuffer::new(LOG_BATCH_SIZE), &[]).is_empty());
+    }
+
+    fn test_log_buffer_flushes_when_full() {
+        let writes = collect_writes(&mut LogBuffer::new(16), &["first", "second", "third"]);
+        assert_eq!(writes, vec!["first\nsecond\n", "third\n"]);
+    }
+
+    fn test_log_buffer_truncates_long_lines() {
+        let capacity = TRUNCATED_LOG_MARKER.len() + 8;
+        let writes = collect_writes(
+            &mut LogBuffer::new(capacity),
+            &["short", "a line that is much longer than the buffer", "end"],
+        );
+        assert_eq!(
+            writes,
+            vec![
+                "short\n".to_string(),
+                format!("a line {}\n", TRUNCATED_LOG_MARKER),
+                "end\n".to_string(),
+            ]
+        );
+
+        // Lines are only cut between characters
+        let writes = collect_writes(&mut LogBuffer::new(capacity), &["ééééééééé"]);
+        assert_eq!(writes, vec![format!("ééé{}\n", TRUNCATED_LOG_MARKER)]);
+    }
+
+    pub fn batched_lines() -> Option<Vec<u8>> {
+        LOG_BATCH.with(|batch| batch.borrow().as_ref().map(|buffer| buffer.lines.clone()))
+    }
+
+    fn test_log_batch_flushes_after_panic() {
+        let result = panic::catch_unwind(|| {
+            let _batch = LogBatch::begin();
+            write_line("before the panic");
+            assert_eq!(batched_lines().unwrap(), b"before the panic\n".to_vec());
+            panic!("the ecall panicked");
+        });
+        assert!(result.is_err());
+
+        // The batch was written out while unwinding
+        assert!(batched_lines().is_none());
+    }
+
+    fn test_nested_log_batch() {
+        let outer = LogBatch::begin();
+        write_line("outer");
+        {
+            let _inner = LogBatch::begin();
+            write_line("inner");
+        }
+        // Only the outer batch writes the lines out
+        assert_eq!(batched_lines().unwrap(), b"outer\ninner\n".to_vec());
+
+        drop(outer);
+        assert!(batched_lines().is_none());
+    }
+}
@@ -1,21 +1,74 @@
-use log::{Metadata, Record};
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use lazy_static::lazy_static;
+use log::{LevelFilter, Metadata, Record};
+
+use crate::log_buffer;
+
+lazy_static! {
+    /// The most verbose level that is currently logged. Operators can change it at runtime
+    /// through `ecall_configure_runtime`.
+    static ref MAX_LOG_LEVEL: AtomicU8 = AtomicU8::new(LevelFilter::Off as u8);
+    /// The most verbose level this build of the enclave may ever log at
+    static ref ALLOWED_LOG_LEVEL: AtomicU8 = AtomicU8::new(LevelFilter::Off as u8);
+}
 
 pub struct SimpleLogger;
 
 impl log::Log for SimpleLogger {
-    fn enabled(&self, _metadata: &Metadata) -> bool {
-        // Not really needed since we set logging level at lib.rs in the init function
-        true
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        log_enabled(metadata)
     }
 
     fn log(&self, record: &Record) {
-        println!(
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        log_buffer::write_line(&format!(
             "{}  [{}] {}",
             record.level(),
             record.target(),
             record.args()
-        );
+        ));
     }
 
     fn flush(&self) {}
 }
+
+pub fn level_filter_from_u8(level: u8) -> Option<LevelFilter> {
+    match level {
+        0 => Some(LevelFilter::Off),
+        1 => Some(LevelFilter::Error),
+        2 => Some(LevelFilter::Warn),
+        3 => Some(LevelFilter::Info),
+        4 => Some(LevelFilter::Debug),
+        5 => Some(LevelFilter::Trace),
+        _ => None,
+    }
+}
+
+fn load_level(level: &AtomicU8) -> LevelFilter {
+    level_filter_from_u8(level.load(Ordering::SeqCst)).unwrap_or(LevelFilter::Off)
+}
+
+/// Whether a log line should be emitted at the current runtime log level
+pub fn log_enabled(metadata: &Metadata) -> bool {
+    metadata.level() <= load_level(&MAX_LOG_LEVEL)
+}
+
+/// Set the log level when the enclave starts, and the most verbose level it may later be set to
+pub fn init_log_levels(level: LevelFilter, allowed_level: LevelFilter) {
+    ALLOWED_LOG_LEVEL.store(allowed_level as u8, Ordering::SeqCst);
+    MAX_LOG_LEVEL.store(level as u8, Ordering::SeqCst);
+    log::set_max_level(level);
+}
+
+/// Change the log level at runtime. Levels more verbose than the build allows are capped.
+/// Returns the level that was set.
+pub fn set_max_log_level(requested_level: LevelFilter) -> LevelFilter {
+    let level = std::cmp::min(requested_level, load_level(&ALLOWED_LOG_LEVEL));
+    MAX_LOG_LEVEL.store(level as u8, Ordering::SeqCst);
+    log::set_max_level(level);
+    level
+}
@@ -51,3 +51,29 @@ macro_rules! coalesce {
         wrapper()
     }};
 }
+
+/// Like `trace!`, but for log lines that contain secrets such as keys or decrypted messages.
+///
+/// These lines are compiled out of release builds, so the runtime log level can't expose them.
+#[macro_export]
+macro_rules! trace_secret {
+    ($($arg: tt)+) => {
+        if cfg!(debug_assertions) {
+            log::trace!($($arg)+);
+        }
+    };
+}
+
+/// Open a span of the `ecall_trace::Phase` `$phase` in the trace of the ecall that runs on this
+/// thread. The span is closed when the value the macro returns is dropped, so bind it to a name
+/// such as `_span`, not to `_`. While tracing is off this is a single branch.
+#[macro_export]
+macro_rules! trace_span {
+    ($phase: expr) => {
+        if $crate::ecall_trace::is_enabled() {
+            $crate::ecall_trace::Span::enter($phase)
+        } else {
+            None
+        }
+    };
+}
@@ -0,0 +1,681 @@
+//! Counters of what the enclave does, exported in the Prometheus text format by
+//! `ecall_export_metrics`, so that operators can watch the enclave without parsing its logs.
+//!
+//! Every counter is an atomic that is allocated when the registry is first used, so recording
+//! never allocates. Labels only take values from fixed sets (the ecalls, the ocalls and the
+//! variants of `EnclaveError`), so the amount of series is bounded. Enclaves built with the
+//! `contract-metrics` feature also count the calls to the `CONTRACT_METRICS_TOP_N` most called
+//! contracts, labeled by their code hash.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+use std::fmt::Write;
+use std::sync::SgxMutex;
+
+use lazy_static::lazy_static;
+
+use enclave_ffi_types::EnclaveError;
+
+use crate::crypto::HASH_SIZE;
+use crate::runtime_stats;
+
+/// The ecalls that run contracts, as they are passed to `run_ecall`
+const ECALL_NAMES: &[&str] = &[
+    "ecall_init",
+    "ecall_handle",
+    "ecall_reply",
+    "ecall_migrate",
+    "ecall_query",
+    "ecall_ibc_channel_open",
+    "ecall_ibc_channel_connect",
+    "ecall_ibc_channel_close",
+    "ecall_ibc_packet_receive",
+    "ecall_ibc_packet_ack",
+    "ecall_ibc_packet_timeout",
+    "ecall_rotate_contract_key",
+    "ecall_update_admin",
+];
+
+/// The label of ecalls that aren't in `ECALL_NAMES`
+const OTHER_ECALL: &str = "other";
+
+/// The upper bounds of the buckets of the gas histogram. Calls that used more gas than the last
+/// bound only count in the `+Inf` bucket.
+const ECALL_GAS_BUCKETS: [u64; 8] = [
+    10_000, 50_000, 100_000, 250_000, 500_000, 1_000_000, 2_500_000, 10_000_000,
+];
+
+/// The amount of contracts whose calls are counted in enclaves built with `contract-metrics`
+const CONTRACT_METRICS_TOP_N: usize = 16;
+
+/// The ocalls that contracts cause
+#[derive(Clone, Copy)]
+pub enum Ocall {
+    ReadDb,
+    ReadDbMulti,
+    WriteDb,
+    RemoveDb,
+    DbScan,
+    DbNext,
+    QueryChain,
+    WriteLog,
+    PrefetchDb,
+}
+
+impl Ocall {
+    const ALL: [Ocall; 9] = [
+        Ocall::ReadDb,
+        Ocall::ReadDbMulti,
+        Ocall::WriteDb,
+        Ocall::RemoveDb,
+        Ocall::DbScan,
+        Ocall::DbNext,
+        Ocall::QueryChain,
+        Ocall::WriteLog,
+        Ocall::PrefetchDb,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            Ocall::ReadDb => "read_db",
+            Ocall::ReadDbMulti => "read_db_multi",
+            Ocall::WriteDb => "write_db",
+            Ocall::RemoveDb => "remove_db",
+            Ocall::DbScan => "db_scan",
+            Ocall::DbNext => "db_next",
+            Ocall::QueryChain => "query_chain",
+            Ocall::WriteLog => "write_log",
+            Ocall::PrefetchDb => "prefetch_db",
+        }
+    }
+}
+
+/// Defines `ERROR_LABELS`, the label of every variant of `EnclaveError`, and `error_label`. The
+/// match in `error_label` is exhaustive, so a new variant has to be added here too.
+macro_rules! enclave_error_labels {
+    ($($variant: ident,)*) => {
+        const ERROR_LABELS: &[&str] = &[$(stringify!($variant),)*];
+
+        fn error_label(err: &EnclaveError) -> &'static str {
+            match err {
+                $(EnclaveError::$variant { .. } => stringify!($variant),)*
+            }
+        }
+    };
+}
+
+enclave_error_labels!(
+    FailedOcall,
+    ValidationFailure,
+    InvalidWasm,
+    CannotInitializeWasmMemory,
+    InstantiationFailed,
+    WasmModuleWithStart,
+    WasmModuleWithFP,
+    FailedGasMeteringInjection,
+    ContractTooLarge,
+    WasmValidationSessionExists,
+    WasmValidationSessionNotFound,
+    WasmChunkOutOfOrder,
+    InternalError,
+    OutOfGas,
+    OutOfBlockGas,
+    ExecutionTimeout,
+    FailedFunctionCall,
+    ContractMemoryLimitExceeded,
+    StorageQuotaExceeded,
+    ContractPanicUnreachable,
+    ContractPanicMemoryAccessOutOfBounds,
+    ContractPanicTableAccessOutOfBounds,
+    ContractPanicElemUninitialized,
+    ContractPanicDivisionByZero,
+    ContractPanicInvalidConversionToInt,
+    ContractPanicStackOverflow,
+    ContractPanicUnexpectedSignature,
+    FailedSeal,
+    FailedUnseal,
+    FailedContractAuthentication,
+    FailedToDeserialize,
+    FailedToSerialize,
+    EncryptionError,
+    ResponseTooLarge,
+    ResultTooLarge,
+    MalformedContractResponse,
+    DecryptionError,
+    CiphertextTooShort,
+    InvalidNonce,
+    KeyDerivationFailed,
+    MemoryAllocationError,
+    MemorySafetyAllocationError,
+    MemoryReadError,
+    MemoryWriteError,
+    NotImplemented,
+    FailedTxVerification,
+    UnauthorizedWrite,
+    UnauthorizedMigration,
+    UnauthorizedReply,
+    UnauthorizedKeyRotation,
+    UnauthorizedAdminUpdate,
+    UnauthorizedIbcPort,
+    UnencryptableMessage,
+    QueryOutsideReplayWindow,
+    ReplayedQuery,
+    NonceReuse,
+    TamperedStateArchive,
+    HostMisbehavior,
+    InvalidContractPointer,
+    InvalidEnvPointer,
+    InvalidMsgPointer,
+    InvalidSigInfoPointer,
+    InvalidUsedGasPointer,
+    QueryCodeHashMismatch,
+    Panic,
+    OutOfMemory,
+    ExceededRecursionLimit,
+    ExceededOcallResponseSize,
+    EnclaveNotInitialized,
+    Unknown,
+);
+
+lazy_static! {
+    static ref REGISTRY: Registry = Registry::new();
+    static ref TOP_CONTRACTS: SgxMutex<TopContracts> = SgxMutex::new(TopContracts::new());
+}
+
+/// Count a call to `ecall_name`, which failed with `failure` if it's set. `used_gas` is the gas
+/// the ecall was charged, if it runs metered.
+pub fn record_ecall(ecall_name: &str, failure: Option<&EnclaveError>, used_gas: Option<u64>) {
+    REGISTRY.record_ecall(ecall_name, failure, used_gas);
+}
+
+pub fn record_ocall(ocall: Ocall) {
+    REGISTRY.record_ocall(ocall);
+}
+
+/// Count a call to the contract with `code_hash`. Does nothing unless the enclave was built with
+/// the `contract-metrics` feature.
+pub fn record_contract_call(code_hash: &[u8; HASH_SIZE]) {
+    if cfg!(feature = "contract-metrics") {
+        TOP_CONTRACTS.lock().unwrap().record(code_hash);
+    }
+}
+
+/// All the metrics of the enclave, in the Prometheus text format
+pub fn export() -> String {
+    let mut out = String::new();
+    REGISTRY.write_to(&mut out);
+
+    write_header(
+        &mut out,
+        "secret_enclave_allocated_bytes",
+        "gauge",
+        "Bytes currently allocated on the heap of the enclave",
+    );
+    let _ = writeln!(
+        out,
+        "secret_enclave_allocated_bytes {}",
+        runtime_stats::allocated_bytes()
+    );
+    write_header(
+        &mut out,
+        "secret_enclave_panics_caught_total",
+        "counter",
+        "Panics caught by ecalls",
+    );
+    let _ = writeln!(
+        out,
+        "secret_enclave_panics_caught_total {}",
+        runtime_stats::panics_caught()
+    );
+    write_header(
+        &mut out,
+        "secret_enclave_module_cache_misses_total",
+        "counter",
+        "Contracts that were loaded because their module wasn't cached",
+    );
+    let _ = writeln!(
+        out,
+        "secret_enclave_module_cache_misses_total {}",
+        runtime_stats::module_cache_misses()
+    );
+    write_header(
+        &mut out,
+        "secret_enclave_prefetch_hits_total",
+        "counter",
+        "Storage reads answered with a value the enclave read ahead",
+    );
+    let _ = writeln!(
+        out,
+        "secret_enclave_prefetch_hits_total {}",
+        runtime_stats::prefetch_hits()
+    );
+    write_header(
+        &mut out,
+        "secret_enclave_prefetch_misses_total",
+        "counter",
+        "Storage reads that went to the host in executions that read ahead",
+    );
+    let _ = writeln!(
+        out,
+        "secret_enclave_prefetch_misses_total {}",
+        runtime_stats::prefetch_misses()
+    );
+
+    if cfg!(feature = "contract-metrics") {
+        TOP_CONTRACTS.lock().unwrap().write_to(&mut out);
+    }
+
+    out
+}
+
+fn write_header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn zeroed_counters(len: usize) -> Vec<AtomicU64> {
+    (0..len).map(|_| AtomicU64::new(0)).collect()
+}
+
+/// The counters of the ecalls and ocalls. The counters of an ecall are at the index of its name in
+/// `ECALL_NAMES`, and the ones of ecalls that aren't there are last.
+struct Registry {
+    ecalls: Vec<AtomicU64>,
+    /// `ERROR_LABELS.len()` counters per ecall
+    ecall_failures: Vec<AtomicU64>,
+    /// `ECALL_GAS_BUCKETS.len() + 1` counters per ecall. Every call counts only in the first bucket
+    /// its gas fits in, and the last one is for calls that fit in none.
+    ecall_gas_buckets: Vec<AtomicU64>,
+    ecall_gas_sum: Vec<AtomicU64>,
+    ocalls: Vec<AtomicU64>,
+}
+
+impl Registry {
+    fn new() -> Self {
+        let ecalls = ECALL_NAMES.len() + 1;
+        Self {
+            ecalls: zeroed_counters(ecalls),
+            ecall_failures: zeroed_counters(ecalls * ERROR_LABELS.len()),
+            ecall_gas_buckets: zeroed_counters(ecalls * (ECALL_GAS_BUCKETS.len() + 1)),
+            ecall_gas_sum: zeroed_counters(ecalls),
+            ocalls: zeroed_counters(Ocall::ALL.len()),
+        }
+    }
+
+    fn record_ecall(
+        &self,
+        ecall_name: &str,
+        failure: Option<&EnclaveError>,
+        used_gas: Option<u64>,
+    ) {
+        let ecall = ECALL_NAMES
+            .iter()
+            .position(|name| *name == ecall_name)
+            .unwrap_or_else(|| ECALL_NAMES.len());
+        self.ecalls[ecall].fetch_add(1, Ordering::Relaxed);
+
+        if let Some(err) = failure {
+            let label = error_label(err);
+            if let Some(error) = ERROR_LABELS.iter().position(|l| *l == label) {
+                self.ecall_failures[ecall * ERROR_LABELS.len() + error]
+                    .fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        if let Some(used_gas) = used_gas {
+            let bucket = ECALL_GAS_BUCKETS
+                .iter()
+                .position(|bound| used_gas <= *bound)
+                .unwrap_or_else(|| ECALL_GAS_BUCKETS.len());
+            self.ecall_gas_buckets[ecall * (ECALL_GAS_BUCKETS.len() + 1) + bucket]
+                .fetch_add(1, Ordering::Relaxed);
+            self.ecall_gas_sum[ecall].fetch_add(used_gas, Ordering::Relaxed);
+        }
+    }
+
+    fn record_ocall(&self, ocall: Ocall) {
+        self.ocalls[ocall as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn write_to(&self, out: &mut String) {
+        let ecall_label = |ecall: usize| {
+            ECALL_NAMES
+                .get(ecall)
+                .map_or(OTHER_ECALL, |name| name.trim_start_matches("ecall_"))
+        };
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+
+        write_header(
+            out,
+            "secret_enclave_ecalls_total",
+            "counter",
+            "Ecalls that run contracts",
+        );
+        for (ecall, calls) in self.ecalls.iter().enumerate() {
+            let _ = writeln!(
+                out,
+                "secret_enclave_ecalls_total{{ecall=\"{}\"}} {}",
+                ecall_label(ecall),
+                load(calls)
+            );
+        }
+
+        // Most ecalls never fail with most errors, so only the failures that happened are listed
+        write_header(
+            out,
+            "secret_enclave_ecall_failures_total",
+            "counter",
+            "Ecalls that failed, by the error they failed with",
+        );
+        for (index, failures) in self.ecall_failures.iter().enumerate() {
+            let failures = load(failures);
+            if failures != 0 {
+                let _ = writeln!(
+                    out,
+                    "secret_enclave_ecall_failures_total{{ecall=\"{}\",error=\"{}\"}} {}",
+                    ecall_label(index / ERROR_LABELS.len()),
+                    ERROR_LABELS[index % ERROR_LABELS.len()],
+                    failures
+                );
+            }
+        }
+
+        write_header(
+            out,
+            "secret_enclave_ecall_gas",
+            "histogram",
+            "Gas used by metered ecalls",
+        );
+        let buckets = self.ecall_gas_buckets.chunks(ECALL_GAS_BUCKETS.len() + 1);
+        for (ecall, buckets) in buckets.enumerate() {
+            let mut cumulative = 0;
+            for (bucket, count) in buckets.iter().enumerate() {
+                cumulative += load(count);
+                let _ = match ECALL_GAS_BUCKETS.get(bucket) {
+                    Some(bound) => writeln!(
+                        out,
+                        "secret_enclave_ecall_gas_bucket{{ecall=\"{}\",le=\"{}\"}} {}",
+                        ecall_label(ecall),
+                        bound,
+                        cumulative
+                    ),
+                    None => writeln!(
+                        out,
+                        "secret_enclave_ecall_gas_bucket{{ecall=\"{}\",le=\"+Inf\"}} {}",
+                        ecall_label(ecall),
+                        cumulative
+                    ),
+                };
+            }
+            let _ = writeln!(
+                out,
+                "secret_enclave_ecall_gas_sum{{ecall=\"{}\"}} {}",
+                ecall_label(ecall),
+                load(&self.ecall_gas_sum[ecall])
+            );
+            let _ = writeln!(
+                out,
+                "secret_enclave_ecall_gas_count{{ecall=\"{}\"}} {}",
+                ecall_label(ecall),
+                cumulative
+            );
+        }
+
+        write_header(
+            out,
+            "secret_enclave_ocalls_total",
+            "counter",
+            "Ocalls made on behalf of contracts",
+        );
+        for ocall in Ocall::ALL.iter() {
+            let _ = writeln!(
+                out,
+                "secret_enclave_ocalls_total{{ocall=\"{}\"}} {}",
+                ocall.label(),
+                load(&self.ocalls[*ocall as usize])
+            );
+        }
+    }
+}
+
+/// Approximate call counts of the most called contracts, kept with the Space-Saving algorithm: a
+/// contract that isn't tracked takes the place of the least called one, and inherits its count.
+/// The count of a contract is never too low, and too high by at most the count it inherited.
+struct TopContracts {
+    /// The code hash, calls and inherited calls of every tracked contract
+    entries: [([u8; HASH_SIZE], u64, u64); CONTRACT_METRICS_TOP_N],
+}
+
+impl TopContracts {
+    fn new() -> Self {
+        Self {
+            entries: [([0; HASH_SIZE], 0, 0); CONTRACT_METRICS_TOP_N],
+        }
+    }
+
+    fn record(&mut self, code_hash: &[u8; HASH_SIZE]) {
+        let tracked = self
+            .entries
+            .iter()
+            .position(|(hash, calls, _)| *calls != 0 && hash == code_hash);
+        if let Some(index) = tracked {
+            self.entries[index].1 += 1;
+            return;
+        }
+
+        if let Some(least_called) = self.entries.iter_mut().min_by_key(|(_, calls, _)| *calls) {
+            let inherited = least_called.1;
+            *least_called = (*code_hash, inherited + 1, inherited);
+        }
+    }
+
+    fn write_to(&self, out: &mut String) {
+        write_header(
+            out,
+            "secret_enclave_contract_calls_total",
+            "counter",
+            "Calls to the most called contracts, by code hash",
+        );
+        for (code_hash, calls, _) in self.entries.iter().filter(|(_, calls, _)| *calls != 0) {
+            let _ = writeln!(
+                out,
+                "secret_enclave_contract_calls_total{{code_hash=\"{}\"}} {}",
+                hex::encode(code_hash),
+                calls
+            );
+        }
+    }
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+    use crate::count_failures;
+
+    pub fn run_tests() {
+        println!();
+        let mut failures = 0;
+
+        count_failures!(failures, {
+            test_ecall_counters();
+            test_ecall_gas_histogram();
+            test_ocall_counters();
+            test_unknown_ecall_is_other();
+            test_top_contracts();
+            test_export_includes_runtime_stats();
+        });
+
+        if failures != 0 {
+            panic!("{}: {} tests failed", file!(), failures);
+        }
+    }
+
+    fn scrape(registry: &Registry) -> String {
+        let mut out = String::new();
+        registry.write_to(&mut out);
+        out
+    }
+
+    fn assert_sample(scraped: &str, sample: &str) {
+        assert!(
+            scraped.lines().any(|line| line == sample),
+            "missing `{}` in:\n{}",
+            sample,
+            scraped
+        );
+    }
+
+    fn test_ecall_counters() {
+        let registry = Registry::new();
+        registry.record_ecall("ecall_handle", None, Some(1_000));
+        registry.record_ecall("ecall_handle", Some(&EnclaveError::OutOfGas), Some(1_000));
+        registry.record_ecall("ecall_handle", Some(&EnclaveError::OutOfGas), Some(1_000));
+        registry.record_ecall(
+            "ecall_query",
+            Some(&EnclaveError::ResultTooLarge {
+                messages: 1,
+                max_messages: 1,
+                bytes: 1,
+                max_bytes: 1,
+            }),
+            Some(1_000),
+        );
+
+        let scraped = scrape(&registry);
+        assert_sample(&scraped, "secret_enclave_ecalls_total{ecall=\"handle\"} 3");
+        assert_sample(&scraped, "secret_enclave_ecalls_total{ecall=\"query\"} 1");
+        assert_sample(&scraped, "secret_enclave_ecalls_total{ecall=\"init\"} 0");
+        assert_sample(
+            &scraped,
+            "secret_enclave_ecall_failures_total{ecall=\"handle\",error=\"OutOfGas\"} 2",
+        );
+        assert_sample(
+            &scraped,
+            "secret_enclave_ecall_failures_total{ecall=\"query\",error=\"ResultTooLarge\"} 1",
+        );
+        // Only the failures that happened are listed
+        assert_eq!(
+            scraped
+                .lines()
+                .filter(|line| line.starts_with("secret_enclave_ecall_failures_total{"))
+                .count(),
+            2
+        );
+    }
+
+    fn test_ecall_gas_histogram() {
+        let registry = Registry::new();
+        for used_gas in &[5_000, 10_000, 60_000, 20_000_000] {
+            registry.record_ecall("ecall_init", None, Some(*used_gas));
+        }
+        // Unmetered calls count as calls, but not in the histogram
+        registry.record_ecall("ecall_init", None, None);
+
+        let scraped = scrape(&registry);
+        assert_sample(&scraped, "secret_enclave_ecalls_total{ecall=\"init\"} 5");
+        assert_sample(
+            &scraped,
+            "secret_enclave_ecall_gas_bucket{ecall=\"init\",le=\"10000\"} 2",
+        );
+        assert_sample(
+            &scraped,
+            "secret_enclave_ecall_gas_bucket{ecall=\"init\",le=\"50000\"} 2",
+        );
+        assert_sample(
+            &scraped,
+            "secret_enclave_ecall_gas_bucket{ecall=\"init\",le=\"100000\"} 3",
+        );
+        assert_sample(
+            &scraped,
+            "secret_enclave_ecall_gas_bucket{ecall=\"init\",le=\"10000000\"} 3",
+        );
+        assert_sample(
+            &scraped,
+            "secret_enclave_ecall_gas_bucket{ecall=\"init\",le=\"+Inf\"} 4",
+        );
+        assert_sample(
+            &scraped,
+            "secret_enclave_ecall_gas_sum{ecall=\"init\"} 20075000",
+        );
+        assert_sample(&scraped, "secret_enclave_ecall_gas_count{ecall=\"init\"} 4");
+        assert_sample(
+            &scraped,
+            "secret_enclave_ecall_gas_count{ecall=\"handle\"} 0",
+        );
+    }
+
+    fn test_ocall_counters() {
+        let registry = Registry::new();
+        registry.record_ocall(Ocall::ReadDb);
+        registry.record_ocall(Ocall::ReadDb);
+        registry.record_ocall(Ocall::QueryChain);
+
+        let scraped = scrape(&registry);
+        assert_sample(&scraped, "secret_enclave_ocalls_total{ocall=\"read_db\"} 2");
+        assert_sample(
+            &scraped,
+            "secret_enclave_ocalls_total{ocall=\"query_chain\"} 1",
+        );
+        assert_sample(
+            &scraped,
+            "secret_enclave_ocalls_total{ocall=\"write_db\"} 0",
+        );
+    }
+
+    fn test_unknown_ecall_is_other() {
+        let registry = Registry::new();
+        registry.record_ecall("ecall_something_new", Some(&EnclaveError::Panic), None);
+
+        let scraped = scrape(&registry);
+        assert_sample(&scraped, "secret_enclave_ecalls_total{ecall=\"other\"} 1");
+        assert_sample(
+            &scraped,
+            "secret_enclave_ecall_failures_total{ecall=\"other\",error=\"Panic\"} 1",
+        );
+    }
+
+    fn test_top_contracts() {
+        let mut top = TopContracts::new();
+        for _ in 0..3 {
+            top.record(&[1; HASH_SIZE]);
+        }
+        // More contracts than are tracked, called once each
+        for index in 0..CONTRACT_METRICS_TOP_N as u8 + 4 {
+            top.record(&[index + 10; HASH_SIZE]);
+        }
+
+        let mut scraped = String::new();
+        top.write_to(&mut scraped);
+        assert_sample(
+            &scraped,
+            &format!(
+                "secret_enclave_contract_calls_total{{code_hash=\"{}\"}} 3",
+                hex::encode([1; HASH_SIZE])
+            ),
+        );
+        assert_eq!(
+            scraped
+                .lines()
+                .filter(|line| line.starts_with("secret_enclave_contract_calls_total{"))
+                .count(),
+            CONTRACT_METRICS_TOP_N
+        );
+        // Every count is at least the true count, and over it by at most what it inherited
+        assert!(top
+            .entries
+            .iter()
+            .all(|(hash, calls, inherited)| hash[0] == 1 || *calls - *inherited == 1));
+    }
+
+    fn test_export_includes_runtime_stats() {
+        record_ocall(Ocall::DbNext);
+
+        let exported = export();
+        assert!(exported.contains("# TYPE secret_enclave_ecall_gas histogram"));
+        assert!(exported
+            .lines()
+            .any(|line| line.starts_with("secret_enclave_allocated_bytes ")));
+        assert!(exported
+            .lines()
+            .any(|line| line.starts_with("secret_enclave_ocalls_total{ocall=\"db_next\"} ")));
+    }
+}
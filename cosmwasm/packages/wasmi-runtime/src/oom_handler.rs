@@ -1,6 +1,9 @@
-use core::sync::atomic::{AtomicBool, Ordering};
+use core::sync::atomic::{AtomicUsize, Ordering};
 use enclave_ffi_types::EnclaveError;
 use lazy_static::lazy_static;
+use std::cell::Cell;
+use std::sync::Once;
+use std::thread::LocalKey;
 
 #[cfg(not(feature = "production"))]
 use std::backtrace::{self, PrintFormat};
@@ -11,6 +14,8 @@ use std::sync::SgxMutex;
 /// This is needed because while unwinding from panic some destructors try
 /// to allocate more memory which causes a double fault. This way we can
 /// make sure the unwind process has enough free memory to work properly.
+/// Before giving up on an allocation, it's retried with the memory this buffer frees, see
+/// `allocate_or_retry`.
 struct SafetyBuffer {
     chunks: usize,
     min_chunks: usize,
@@ -78,10 +83,233 @@ lazy_static! {
     /// 2 MiB is the minimum allowed buffer. If we don't succeed to allocate 2 MiB, we throw a panic,
     /// if we do succeed to allocate 2 MiB but less than 4 MiB than we move on and will try to allocate
     /// the rest on the next entry to the enclave.
+    ///
+    /// It's reserved for the execution of the block and the other ecalls that aren't queries, so a
+    /// query that runs out of memory next to the execution can't leave it without one.
     static ref SAFETY_BUFFER: SgxMutex<SafetyBuffer> = SgxMutex::new(SafetyBuffer::new(4 * 1024, 2 * 1024));
+
+    /// The safety buffer of the queries the host runs, which they share. A query that spends it
+    /// may leave the queries next to it without one, but never the execution.
+    static ref QUERY_SAFETY_BUFFER: SgxMutex<SafetyBuffer> = SgxMutex::new(SafetyBuffer::new(4 * 1024, 2 * 1024));
+}
+
+/// The most bytes the queries the host runs may hold at once, together. Allocations of a query
+/// past it fail as if the enclave ran out of memory, so the rest of the heap (`HeapMaxSize` in
+/// Enclave.config.xml is 256 MiB) stays free for the execution of the block.
+pub const QUERY_MEMORY_LIMIT: usize = 128 * 1024 * 1024;
+
+/// The bytes held by the queries that run now, see `QueryMemoryGuard`
+static QUERY_ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// Set when an allocation failed for good, and the call on this thread is unwinding
+    static OOM_HAPPENED: Cell<bool> = Cell::new(false);
+
+    /// Set when a failed allocation was retried after freeing the safety buffer. Until the safety
+    /// buffer is restored, the next allocation that fails isn't retried, and aborts the ecall.
+    static SAFETY_BUFFER_SPENT: Cell<bool> = Cell::new(false);
+
+    /// Set while memory is being released for a retry, so allocations that fail while doing so
+    /// aren't retried themselves
+    static RELEASING_MEMORY: Cell<bool> = Cell::new(false);
+
+    /// The bytes the query that runs on this thread holds, or `None` when no query the host made
+    /// runs on it. Queries that a contract makes run on the thread of the contract, and are
+    /// accounted for with it.
+    static QUERY_BYTES: Cell<Option<usize>> = Cell::new(None);
+}
+
+// A call runs on a thread of its own, and the ecalls it makes through ocalls run on the same
+// thread, so the state of a call is kept per thread. The allocator may run while a thread is torn
+// down, so these never panic when the thread locals are gone.
+
+fn thread_flag(flag: &'static LocalKey<Cell<bool>>) -> bool {
+    flag.try_with(Cell::get).unwrap_or(false)
 }
 
-static OOM_HAPPENED: AtomicBool = AtomicBool::new(false);
+fn set_thread_flag(flag: &'static LocalKey<Cell<bool>>, value: bool) {
+    let _ = flag.try_with(|flag| flag.set(value));
+}
+
+fn swap_thread_flag(flag: &'static LocalKey<Cell<bool>>, value: bool) -> bool {
+    flag.try_with(|flag| flag.replace(value)).unwrap_or(false)
+}
+
+fn in_query() -> bool {
+    QUERY_BYTES
+        .try_with(|bytes| bytes.get().is_some())
+        .unwrap_or(false)
+}
+
+fn safety_buffer() -> &'static SgxMutex<SafetyBuffer> {
+    if in_query() {
+        &QUERY_SAFETY_BUFFER
+    } else {
+        &SAFETY_BUFFER
+    }
+}
+
+/// Accounts the memory of a query the host made to `QUERY_MEMORY_LIMIT`, until it's dropped. What
+/// the query still holds when it ends, like the modules and results it cached, is left to the
+/// limits of those caches.
+pub struct QueryMemoryGuard {
+    active: bool,
+}
+
+impl QueryMemoryGuard {
+    /// Start accounting the memory of the query that is about to run on this thread. A query that
+    /// a contract makes is accounted for with the contract, so nothing changes for it.
+    pub fn begin() -> Self {
+        let active = !crate::recursion_depth::is_nested() && !in_query();
+        if active {
+            let _ = QUERY_BYTES.try_with(|bytes| bytes.set(Some(0)));
+        }
+        QueryMemoryGuard { active }
+    }
+}
+
+impl Drop for QueryMemoryGuard {
+    fn drop(&mut self) {
+        if self.active {
+            let held = QUERY_BYTES
+                .try_with(|bytes| bytes.take())
+                .unwrap_or_default()
+                .unwrap_or(0);
+            QUERY_ALLOCATED_BYTES.fetch_sub(held, Ordering::SeqCst);
+        }
+    }
+}
+
+/// Counts `size` bytes to the query that runs on this thread, if any. Returns false if that would
+/// take the queries past `QUERY_MEMORY_LIMIT`. A query that is unwinding after running out of
+/// memory may go past it, as the unwind has to be able to allocate.
+fn reserve_query_memory(size: usize) -> bool {
+    QUERY_BYTES
+        .try_with(|bytes| {
+            let held = match bytes.get() {
+                Some(held) => held,
+                None => return true,
+            };
+            let allocated = QUERY_ALLOCATED_BYTES.fetch_add(size, Ordering::SeqCst);
+            if allocated.saturating_add(size) > QUERY_MEMORY_LIMIT && !thread_flag(&OOM_HAPPENED) {
+                QUERY_ALLOCATED_BYTES.fetch_sub(size, Ordering::SeqCst);
+                return false;
+            }
+            bytes.set(Some(held.saturating_add(size)));
+            true
+        })
+        .unwrap_or(true)
+}
+
+/// Called by the global allocator when `size` bytes are freed, or weren't allocated after all.
+/// Only what the query on this thread counted is taken off of it, as the memory it frees may have
+/// been allocated before it started.
+pub fn release_query_memory(size: usize) {
+    let _ = QUERY_BYTES.try_with(|bytes| {
+        if let Some(held) = bytes.get() {
+            let released = size.min(held);
+            QUERY_ALLOCATED_BYTES.fetch_sub(released, Ordering::SeqCst);
+            bytes.set(Some(held - released));
+        }
+    });
+}
+
+lazy_static! {
+    /// Called when an allocation still fails after the safety buffer was freed, to drop memory
+    /// the enclave can do without. They may run while any lock is held, so they must only
+    /// `try_lock`.
+    static ref MEMORY_PRESSURE_CALLBACKS: SgxMutex<Vec<fn()>> = SgxMutex::new(Vec::new());
+}
+
+static REGISTER_MODULE_CACHE_CALLBACK: Once = Once::new();
+
+/// Register `callback` to be called when the enclave runs out of memory, before an allocation
+/// is given up on
+pub fn register_memory_pressure_callback(callback: fn()) {
+    MEMORY_PRESSURE_CALLBACKS.lock().unwrap().push(callback);
+}
+
+fn run_memory_pressure_callbacks() {
+    if let Ok(callbacks) = MEMORY_PRESSURE_CALLBACKS.try_lock() {
+        for callback in callbacks.iter() {
+            callback();
+        }
+    }
+}
+
+/// Frees the safety buffer of this call, unless it's in use. Returns whether it was freed.
+fn clear_safety_buffer() -> bool {
+    match safety_buffer().try_lock() {
+        Ok(mut safety_buffer) => {
+            safety_buffer.clear();
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// Called by the global allocator with the allocation to make, of `size` bytes. If it fails the
+/// first time, the safety buffer is freed and the allocation is tried again, so a small allocation
+/// late in an ecall doesn't fail it. If it still fails, the memory pressure callbacks get to free
+/// memory before the last try.
+///
+/// Once the safety buffer is freed, failed allocations aren't retried until it's restored, since
+/// there would be no memory left to unwind with. Contracts restore it between steps, see
+/// `restore_spent_safety_buffer`.
+///
+/// An allocation that would take the queries past `QUERY_MEMORY_LIMIT` fails right away, as no
+/// retry would make room for it.
+pub fn allocate_or_retry(size: usize, allocate: impl Fn() -> *mut u8) -> *mut u8 {
+    if !reserve_query_memory(size) {
+        return std::ptr::null_mut();
+    }
+    let ptr = allocate_with_retry(size, allocate);
+    if ptr.is_null() {
+        release_query_memory(size);
+    }
+    ptr
+}
+
+fn allocate_with_retry(size: usize, allocate: impl Fn() -> *mut u8) -> *mut u8 {
+    let ptr = try_allocate(size, &allocate);
+    if !ptr.is_null() || thread_flag(&RELEASING_MEMORY) {
+        return ptr;
+    }
+    if swap_thread_flag(&SAFETY_BUFFER_SPENT, true) {
+        return ptr;
+    }
+
+    set_thread_flag(&RELEASING_MEMORY, true);
+    let ptr = if clear_safety_buffer() {
+        let ptr = try_allocate(size, &allocate);
+        if ptr.is_null() {
+            run_memory_pressure_callbacks();
+            try_allocate(size, &allocate)
+        } else {
+            ptr
+        }
+    } else {
+        // The allocation was made while restoring the safety buffer, which doesn't need it
+        set_thread_flag(&SAFETY_BUFFER_SPENT, false);
+        ptr
+    };
+    set_thread_flag(&RELEASING_MEMORY, false);
+
+    ptr
+}
+
+#[cfg(not(feature = "test"))]
+fn try_allocate(_size: usize, allocate: &impl Fn() -> *mut u8) -> *mut u8 {
+    allocate()
+}
+
+#[cfg(feature = "test")]
+fn try_allocate(size: usize, allocate: &impl Fn() -> *mut u8) -> *mut u8 {
+    if tests::take_injected_failure(size) {
+        return std::ptr::null_mut();
+    }
+    allocate()
+}
 
 #[cfg(not(feature = "production"))]
 fn enable_backtraces() {
@@ -94,18 +322,20 @@ fn enable_backtraces() {}
 pub fn register_oom_handler() -> Result<(), EnclaveError> {
     enable_backtraces();
 
-    {
-        SAFETY_BUFFER.lock().unwrap().restore()?;
-    }
+    REGISTER_MODULE_CACHE_CALLBACK.call_once(|| {
+        register_memory_pressure_callback(crate::wasm::module_cache::evict_under_memory_pressure)
+    });
+
+    restore_safety_buffer()?;
 
     get_then_clear_oom_happened();
 
+    // Only called once the allocation was retried, see `allocate_or_retry`
     std::alloc::set_alloc_error_hook(|layout| {
-        OOM_HAPPENED.store(true, Ordering::SeqCst);
+        set_thread_flag(&OOM_HAPPENED, true);
 
-        {
-            SAFETY_BUFFER.lock().unwrap().clear();
-        }
+        clear_safety_buffer();
+        run_memory_pressure_callbacks();
 
         panic!(
             "SGX: Memory allocation of {} bytes failed. Trying to recover...\n",
@@ -116,10 +346,234 @@ pub fn register_oom_handler() -> Result<(), EnclaveError> {
     Ok(())
 }
 
+/// Whether the call on this thread ran out of memory, which is cleared for the next call
 pub fn get_then_clear_oom_happened() -> bool {
-    OOM_HAPPENED.swap(false, Ordering::SeqCst)
+    swap_thread_flag(&OOM_HAPPENED, false)
 }
 
+/// Restore the safety buffer of the call on this thread
 pub fn restore_safety_buffer() -> Result<(), EnclaveError> {
-    SAFETY_BUFFER.lock().unwrap().restore()
+    safety_buffer().lock().unwrap().restore()?;
+    set_thread_flag(&SAFETY_BUFFER_SPENT, false);
+    Ok(())
+}
+
+/// Restore the safety buffer if a retried allocation freed it. Contracts call this between steps,
+/// and stop with `OutOfMemory` if there isn't enough memory to restore it, since the next failed
+/// allocation couldn't be recovered from.
+pub fn restore_spent_safety_buffer() -> Result<(), EnclaveError> {
+    if !thread_flag(&SAFETY_BUFFER_SPENT) {
+        return Ok(());
+    }
+
+    restore_safety_buffer().map_err(|_err| EnclaveError::OutOfMemory)
+}
+
+/// Returns the amount of chunks held by the safety buffer of the execution, and the amount it
+/// tries to hold
+pub fn safety_buffer_state() -> (usize, usize) {
+    let safety_buffer = SAFETY_BUFFER.lock().unwrap();
+    (safety_buffer.buffer.len(), safety_buffer.chunks)
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+    use crate::count_failures;
+    use core::sync::atomic::AtomicBool;
+
+    /// The amount of allocations that fail before the system allocator is asked for them
+    static INJECTED_FAILURES: AtomicUsize = AtomicUsize::new(0);
+
+    /// Smaller allocations aren't failed by `INJECTED_FAILURES`
+    static INJECTED_FAILURES_MIN_SIZE: AtomicUsize = AtomicUsize::new(0);
+
+    static CALLBACK_CALLED: AtomicBool = AtomicBool::new(false);
+
+    /// Fail the next `failures` allocations of at least `min_size` bytes, including their retries
+    pub fn inject_allocation_failures(failures: usize, min_size: usize) {
+        INJECTED_FAILURES_MIN_SIZE.store(min_size, Ordering::SeqCst);
+        INJECTED_FAILURES.store(failures, Ordering::SeqCst);
+    }
+
+    pub(super) fn take_injected_failure(size: usize) -> bool {
+        let failures = INJECTED_FAILURES.load(Ordering::SeqCst);
+        if failures == 0 || size < INJECTED_FAILURES_MIN_SIZE.load(Ordering::SeqCst) {
+            return false;
+        }
+        INJECTED_FAILURES.store(failures - 1, Ordering::SeqCst);
+        true
+    }
+
+    pub fn run_tests() {
+        println!();
+        let mut failures = 0;
+
+        count_failures!(failures, {
+            test_small_allocation_after_oom_is_retried();
+            test_allocation_is_not_retried_until_safety_buffer_is_restored();
+            test_memory_pressure_callbacks_run_before_giving_up();
+            test_query_spends_its_own_safety_buffer();
+            test_queries_are_held_to_their_memory_limit();
+            test_nested_query_is_accounted_for_with_its_caller();
+        });
+
+        if failures != 0 {
+            panic!("{}: {} tests failed", file!(), failures);
+        }
+    }
+
+    /// An allocation that fails `failures` times, and then returns a pointer that must not be used
+    fn flaky_allocation(failures: usize, attempts: &Cell<usize>) -> impl Fn() -> *mut u8 + '_ {
+        move || {
+            attempts.set(attempts.get() + 1);
+            if attempts.get() > failures {
+                std::ptr::NonNull::dangling().as_ptr()
+            } else {
+                std::ptr::null_mut()
+            }
+        }
+    }
+
+    fn record_callback() {
+        CALLBACK_CALLED.store(true, Ordering::SeqCst);
+    }
+
+    fn test_small_allocation_after_oom_is_retried() {
+        restore_safety_buffer().unwrap();
+        let (_, target_chunks) = safety_buffer_state();
+
+        // This used to go straight to the alloc error hook, and fail the ecall
+        inject_allocation_failures(1, 0);
+        let late = vec![7u8; 64];
+        assert_eq!(INJECTED_FAILURES.load(Ordering::SeqCst), 0);
+        assert!(late.iter().all(|byte| *byte == 7));
+
+        assert_eq!(safety_buffer_state().0, 0);
+        assert!(thread_flag(&SAFETY_BUFFER_SPENT));
+
+        restore_spent_safety_buffer().unwrap();
+        assert_eq!(safety_buffer_state(), (target_chunks, target_chunks));
+        assert!(!thread_flag(&SAFETY_BUFFER_SPENT));
+    }
+
+    fn test_allocation_is_not_retried_until_safety_buffer_is_restored() {
+        restore_safety_buffer().unwrap();
+
+        let attempts = Cell::new(0);
+        assert!(!allocate_or_retry(0, flaky_allocation(1, &attempts)).is_null());
+        assert_eq!(attempts.get(), 2);
+
+        // The safety buffer was spent on the last retry
+        let attempts = Cell::new(0);
+        assert!(allocate_or_retry(0, flaky_allocation(1, &attempts)).is_null());
+        assert_eq!(attempts.get(), 1);
+
+        restore_spent_safety_buffer().unwrap();
+        let attempts = Cell::new(0);
+        assert!(!allocate_or_retry(0, flaky_allocation(1, &attempts)).is_null());
+        assert_eq!(attempts.get(), 2);
+
+        restore_safety_buffer().unwrap();
+    }
+
+    fn test_memory_pressure_callbacks_run_before_giving_up() {
+        restore_safety_buffer().unwrap();
+        register_memory_pressure_callback(record_callback);
+        CALLBACK_CALLED.store(false, Ordering::SeqCst);
+
+        // Freeing the safety buffer wasn't enough, but the callbacks were
+        let attempts = Cell::new(0);
+        assert!(!allocate_or_retry(0, flaky_allocation(2, &attempts)).is_null());
+        assert_eq!(attempts.get(), 3);
+        assert!(CALLBACK_CALLED.load(Ordering::SeqCst));
+
+        restore_safety_buffer().unwrap();
+    }
+
+    fn query_safety_buffer_chunks() -> usize {
+        QUERY_SAFETY_BUFFER.lock().unwrap().buffer.len()
+    }
+
+    fn test_query_spends_its_own_safety_buffer() {
+        restore_safety_buffer().unwrap();
+        let (_, target_chunks) = safety_buffer_state();
+
+        let query = QueryMemoryGuard::begin();
+        restore_safety_buffer().unwrap();
+        let attempts = Cell::new(0);
+        assert!(!allocate_or_retry(0, flaky_allocation(1, &attempts)).is_null());
+        assert_eq!(attempts.get(), 2);
+        assert_eq!(query_safety_buffer_chunks(), 0);
+        assert!(thread_flag(&SAFETY_BUFFER_SPENT));
+
+        // The execution still has all of its buffer to unwind with
+        assert_eq!(safety_buffer_state(), (target_chunks, target_chunks));
+
+        restore_spent_safety_buffer().unwrap();
+        assert!(query_safety_buffer_chunks() > 0);
+        drop(query);
+    }
+
+    fn test_queries_are_held_to_their_memory_limit() {
+        restore_safety_buffer().unwrap();
+        let allocated_before = QUERY_ALLOCATED_BYTES.load(Ordering::SeqCst);
+
+        let query = QueryMemoryGuard::begin();
+        let attempts = Cell::new(0);
+        assert!(
+            !allocate_or_retry(QUERY_MEMORY_LIMIT / 2, flaky_allocation(0, &attempts)).is_null()
+        );
+        assert_eq!(
+            QUERY_ALLOCATED_BYTES.load(Ordering::SeqCst),
+            allocated_before + QUERY_MEMORY_LIMIT / 2
+        );
+
+        // Past the limit the allocation isn't even tried, nor is the safety buffer spent on it
+        let attempts = Cell::new(0);
+        assert!(allocate_or_retry(QUERY_MEMORY_LIMIT, flaky_allocation(0, &attempts)).is_null());
+        assert_eq!(attempts.get(), 0);
+        assert!(!thread_flag(&SAFETY_BUFFER_SPENT));
+
+        // Only what the query counted is taken off of it
+        release_query_memory(QUERY_MEMORY_LIMIT);
+        assert_eq!(
+            QUERY_ALLOCATED_BYTES.load(Ordering::SeqCst),
+            allocated_before
+        );
+
+        assert!(
+            !allocate_or_retry(QUERY_MEMORY_LIMIT / 2, flaky_allocation(0, &attempts)).is_null()
+        );
+        drop(query);
+        assert_eq!(
+            QUERY_ALLOCATED_BYTES.load(Ordering::SeqCst),
+            allocated_before
+        );
+
+        // The execution isn't held to the limit of the queries
+        let attempts = Cell::new(0);
+        assert!(
+            !allocate_or_retry(QUERY_MEMORY_LIMIT + 1, flaky_allocation(0, &attempts)).is_null()
+        );
+        assert_eq!(attempts.get(), 1);
+    }
+
+    fn test_nested_query_is_accounted_for_with_its_caller() {
+        let allocated_before = QUERY_ALLOCATED_BYTES.load(Ordering::SeqCst);
+
+        let execution = crate::recursion_depth::guard().unwrap();
+        let nested_query = QueryMemoryGuard::begin();
+        assert!(!in_query());
+        let attempts = Cell::new(0);
+        assert!(
+            !allocate_or_retry(QUERY_MEMORY_LIMIT + 1, flaky_allocation(0, &attempts)).is_null()
+        );
+        assert_eq!(
+            QUERY_ALLOCATED_BYTES.load(Ordering::SeqCst),
+            allocated_before
+        );
+        drop(nested_query);
+        drop(execution);
+    }
 }
@@ -0,0 +1,142 @@
+use ctor::*;
+use lazy_static::lazy_static;
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::backtrace::Backtrace;
+use std::collections::VecDeque;
+use std::panic::{self, PanicInfo};
+use std::sync::SgxMutex;
+
+use enclave_ffi_types::EnclaveError;
+
+use crate::crypto::{seal_to_enclave, unseal_from_enclave};
+
+/// The amount of panics the enclave remembers
+const MAX_PANIC_REPORTS: usize = 8;
+
+/// The amount of backtrace lines kept in a report
+const MAX_BACKTRACE_LINES: usize = 32;
+
+lazy_static! {
+    static ref PANIC_REPORTS: SgxMutex<VecDeque<PanicReport>> =
+        SgxMutex::new(VecDeque::with_capacity(MAX_PANIC_REPORTS));
+}
+
+/// What the enclave knows about a panic.
+///
+/// The message and backtrace may contain secrets, so they only leave the enclave sealed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PanicReport {
+    /// The ecall that caught the panic. Empty until the ecall records itself
+    pub ecall: String,
+    pub message: String,
+    pub location: String,
+    pub backtrace: Vec<String>,
+}
+
+impl PanicReport {
+    /// Which ecall panicked and where, without anything the panic message or backtrace could reveal
+    pub fn summary(&self) -> String {
+        let ecall = if self.ecall.is_empty() {
+            "unknown ecall"
+        } else {
+            &self.ecall
+        };
+        format!("{} panicked at {}", ecall, self.location)
+    }
+}
+
+/// Replaces the default panic hook, which prints the panic message outside the enclave
+#[cfg(not(feature = "test"))]
+#[ctor]
+fn install_panic_hook() {
+    panic::set_hook(Box::new(record_panic));
+}
+
+/// Tests keep the default panic hook as well, so that failed assertions are printed
+#[cfg(feature = "test")]
+#[ctor]
+fn install_panic_hook() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        record_panic(info);
+        default_hook(info);
+    }));
+}
+
+fn record_panic(info: &PanicInfo) {
+    let payload = info.payload();
+    let message = if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "Box<Any>".to_string()
+    };
+
+    let location = match info.location() {
+        Some(location) => format!(
+            "{}:{}:{}",
+            location.file(),
+            location.line(),
+            location.column()
+        ),
+        None => "unknown location".to_string(),
+    };
+
+    let backtrace = Backtrace::force_capture()
+        .to_string()
+        .lines()
+        .take(MAX_BACKTRACE_LINES)
+        .map(str::to_string)
+        .collect();
+
+    error!("Enclave panicked at {}", location);
+
+    // Don't wait on a lock that the panicking thread may be holding
+    if let Ok(mut reports) = PANIC_REPORTS.try_lock() {
+        if reports.len() == MAX_PANIC_REPORTS {
+            reports.pop_front();
+        }
+        reports.push_back(PanicReport {
+            ecall: String::new(),
+            message,
+            location,
+            backtrace,
+        });
+    }
+}
+
+/// Marks the last panic with the ecall that caught it. Call this whenever an ecall catches a panic.
+pub fn record_ecall(ecall: &str) {
+    if let Ok(mut reports) = PANIC_REPORTS.lock() {
+        if let Some(report) = reports.back_mut().filter(|report| report.ecall.is_empty()) {
+            report.ecall = ecall.to_string();
+        }
+    }
+}
+
+pub fn last_report() -> Option<PanicReport> {
+    PANIC_REPORTS.lock().ok()?.back().cloned()
+}
+
+/// Seals the report to the identity of this enclave, so that only this enclave can read it.
+/// The summary is sealed along with it as additional data, so it's authenticated but readable.
+pub fn seal_report(report: &PanicReport) -> Result<Vec<u8>, EnclaveError> {
+    let serialized = serde_json::to_vec(report).map_err(|err| {
+        warn!("Failed to serialize the panic report: {}", err);
+        EnclaveError::FailedToSerialize
+    })?;
+
+    seal_to_enclave(report.summary().as_bytes(), &serialized)
+}
+
+/// Opens a report sealed by `seal_report`. This only works in the enclave that sealed it.
+pub fn unseal_report(sealed: &[u8]) -> Result<PanicReport, EnclaveError> {
+    let (_summary, serialized) = unseal_from_enclave(sealed)?;
+
+    serde_json::from_slice(&serialized).map_err(|err| {
+        warn!("Failed to deserialize the panic report: {}", err);
+        EnclaveError::FailedToDeserialize
+    })
+}
@@ -1,36 +1,43 @@
-use std::sync::SgxMutex;
-
-use lazy_static::lazy_static;
+use std::cell::Cell;
 
 use enclave_ffi_types::EnclaveError;
 
-const RECURSION_LIMIT: u8 = 5;
+use crate::consts::RECURSION_LIMIT;
 
-lazy_static! {
+thread_local! {
     /// This counter tracks the recursion depth of queries,
     /// and effectively the amount of loaded instances of WASMI.
     ///
     /// It is incremented before each computation begins and is decremented after each computation ends.
-    static ref RECURSION_DEPTH: SgxMutex<u8> = SgxMutex::new(0);
+    ///
+    /// A query that a contract makes is a nested ecall, which runs on the thread of the ecall that
+    /// made it, while unrelated queries run on threads of their own. So the counter is kept per
+    /// thread, and a query that runs next to a deep chain of queries isn't charged for its depth.
+    static RECURSION_DEPTH: Cell<u8> = Cell::new(0);
 }
 
 fn increment() -> Result<(), EnclaveError> {
-    let mut depth = RECURSION_DEPTH.lock().unwrap();
-    if *depth == RECURSION_LIMIT {
-        return Err(EnclaveError::ExceededRecursionLimit);
-    }
-    *depth = depth.saturating_add(1);
-    Ok(())
+    RECURSION_DEPTH.with(|depth| {
+        if depth.get() == RECURSION_LIMIT {
+            return Err(EnclaveError::ExceededRecursionLimit);
+        }
+        depth.set(depth.get().saturating_add(1));
+        Ok(())
+    })
 }
 
 fn decrement() {
-    let mut depth = RECURSION_DEPTH.lock().unwrap();
-    *depth = depth.saturating_sub(1);
+    RECURSION_DEPTH.with(|depth| depth.set(depth.get().saturating_sub(1)));
+}
+
+/// Returns whether an ecall already runs on this thread, which makes the next one a nested one
+pub fn is_nested() -> bool {
+    RECURSION_DEPTH.with(|depth| depth.get() > 0)
 }
 
 /// Returns whether or not this is the last possible level of recursion
 pub fn limit_reached() -> bool {
-    *RECURSION_DEPTH.lock().unwrap() == RECURSION_LIMIT
+    RECURSION_DEPTH.with(|depth| depth.get() == RECURSION_LIMIT)
 }
 
 pub struct RecursionGuard {
@@ -53,3 +60,41 @@ impl Drop for RecursionGuard {
 pub fn guard() -> Result<RecursionGuard, EnclaveError> {
     RecursionGuard::new()
 }
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+    use crate::count_failures;
+
+    pub fn run_tests() {
+        println!();
+        let mut failures = 0;
+
+        count_failures!(failures, {
+            test_guard_enforces_recursion_limit();
+        });
+
+        if failures != 0 {
+            panic!("{}: {} tests failed", file!(), failures);
+        }
+    }
+
+    fn test_guard_enforces_recursion_limit() {
+        let mut guards = Vec::new();
+        for _ in 0..RECURSION_LIMIT {
+            assert!(!limit_reached());
+            guards.push(guard().unwrap());
+        }
+        assert!(limit_reached());
+        assert!(matches!(guard(), Err(EnclaveError::ExceededRecursionLimit)));
+
+        // Leaving a level allows entering it again
+        guards.pop();
+        assert!(!limit_reached());
+        guards.push(guard().unwrap());
+
+        drop(guards);
+        assert!(!limit_reached());
+        assert_eq!(RECURSION_DEPTH.with(|depth| depth.get()), 0);
+    }
+}
@@ -7,7 +7,7 @@ use log::*;
 use sgx_rand::{os, Rng};
 use sgx_tcrypto::{rsgx_sha256_slice, SgxEccHandle};
 #[cfg(feature = "SGX_MODE_HW")]
-use sgx_tse::{rsgx_create_report, rsgx_verify_report};
+use sgx_tse::{rsgx_create_report, rsgx_self_report, rsgx_verify_report};
 
 #[cfg(not(feature = "SGX_MODE_HW"))]
 use sgx_types::{sgx_create_report, SgxResult};
@@ -35,7 +35,15 @@ use std::vec::Vec;
 
 use crate::crypto::KeyPair;
 #[cfg(feature = "SGX_MODE_HW")]
-use crate::imports::{ocall_get_ias_socket, ocall_get_quote, ocall_sgx_init_quote};
+use crate::imports::{
+    ocall_get_dcap_collateral, ocall_get_dcap_quote, ocall_get_dcap_target_info,
+    ocall_get_ias_socket, ocall_get_quote, ocall_sgx_init_quote,
+};
+#[cfg(feature = "SGX_MODE_HW")]
+use crate::registration::cert::INTEL_SGX_PCK_ROOT_CA;
+use crate::registration::dcap::EndorsedDcapQuote;
+#[cfg(feature = "SGX_MODE_HW")]
+use crate::registration::dcap::{platform_of_quote, DcapReport};
 use crate::registration::report::EndorsedAttestationReport;
 
 use super::hex;
@@ -131,17 +139,31 @@ pub fn create_attestation_certificate(
     // use ephemeral key
     let (prv_k, pub_k) = ecc_handle.create_key_pair().unwrap();
 
-    // call create_report using the secp256k1 public key, and __not__ the P256 one
-    let signed_report = match create_attestation_report(&kp.get_pubkey(), sign_type, spid, api_key)
-    {
-        Ok(r) => r,
-        Err(e) => {
-            error!("Error creating attestation report");
-            return Err(e);
-        }
+    // Newer platforms can't create EPID quotes anymore, those attest with DCAP instead
+    let payload = if is_epid_available() {
+        // call create_report using the secp256k1 public key, and __not__ the P256 one
+        let signed_report =
+            match create_attestation_report(&kp.get_pubkey(), sign_type, spid, api_key) {
+                Ok(r) => r,
+                Err(e) => {
+                    error!("Error creating attestation report");
+                    return Err(e);
+                }
+            };
+        serde_json::to_string(&signed_report)
+    } else {
+        info!("EPID attestation is not available on this platform, attesting with DCAP");
+        let endorsed_quote = match create_dcap_quote(&kp.get_pubkey()) {
+            Ok(q) => q,
+            Err(e) => {
+                error!("Error creating DCAP quote");
+                return Err(e);
+            }
+        };
+        serde_json::to_string(&endorsed_quote)
     };
 
-    let payload: String = serde_json::to_string(&signed_report).map_err(|_| {
+    let payload: String = payload.map_err(|_| {
         error!("Error serializing report. May be malformed, or badly encoded");
         sgx_status_t::SGX_ERROR_UNEXPECTED
     })?;
@@ -153,6 +175,16 @@ pub fn create_attestation_certificate(
 
 #[cfg(feature = "SGX_MODE_HW")]
 pub fn get_mr_enclave() -> Result<[u8; 32], sgx_status_t> {
+    // Asking the quoting enclave for a target would fail on platforms without EPID
+    let mr_enclave = rsgx_self_report().body.mr_enclave.m;
+    trace!("This enclave MR_ENCLAVE is: {:?}", mr_enclave);
+
+    Ok(mr_enclave)
+}
+
+/// Whether the platform can still create EPID quotes
+#[cfg(feature = "SGX_MODE_HW")]
+fn is_epid_available() -> bool {
     let mut ti: sgx_target_info_t = sgx_target_info_t::default();
     let mut eg: sgx_epid_group_id_t = sgx_epid_group_id_t::default();
     let mut rt: sgx_status_t = sgx_status_t::SGX_ERROR_UNEXPECTED;
@@ -165,28 +197,171 @@ pub fn get_mr_enclave() -> Result<[u8; 32], sgx_status_t> {
         )
     };
 
+    res == sgx_status_t::SGX_SUCCESS && rt == sgx_status_t::SGX_SUCCESS
+}
+
+/// Create a DCAP quote with `pub_k` in its report data, like the EPID quote has it, and fetch the
+/// TCB info other nodes verify it against
+#[cfg(feature = "SGX_MODE_HW")]
+pub fn create_dcap_quote(pub_k: &[u8; 32]) -> Result<EndorsedDcapQuote, sgx_status_t> {
+    // Workflow:
+    // (1) ocall to get the target_info structure (ti) of the quoting enclave
+    // (2) call sgx_create_report with ti+data, produce an sgx_report_t
+    // (3) ocall to sgx_qe_get_quote to generate the quote
+    // (4) ocall to get the TCB info of the platform from the provisioning certification service
+
+    // (1) get ti
+    let mut ti: sgx_target_info_t = sgx_target_info_t::default();
+    let mut rt: sgx_status_t = sgx_status_t::SGX_ERROR_UNEXPECTED;
+
+    let res = unsafe {
+        ocall_get_dcap_target_info(
+            &mut rt as *mut sgx_status_t,
+            &mut ti as *mut sgx_target_info_t,
+        )
+    };
+
     if res != sgx_status_t::SGX_SUCCESS {
         return Err(res);
     }
+
     if rt != sgx_status_t::SGX_SUCCESS {
         return Err(rt);
     }
 
-    // placeholder report
-    let report_data: sgx_report_data_t = sgx_report_data_t::default();
+    // (2) Generate the report
+    let mut report_data: sgx_report_data_t = sgx_report_data_t::default();
+    report_data.d[..32].copy_from_slice(pub_k);
 
     let rep = match rsgx_create_report(&ti, &report_data) {
-        Ok(r) => {
-            trace!("This enclave MR_ENCLAVE is: {:?}", r.body.mr_enclave.m);
-            r.body.mr_enclave.m
-        }
-        Err(_e) => {
-            error!("Failed to get local MR_ENCLAVE. Corrupted enclave or other unknown error");
+        Ok(r) => r,
+        Err(e) => {
+            error!("Report creation => failed {:?}", e);
             return Err(sgx_status_t::SGX_ERROR_UNEXPECTED);
         }
     };
 
-    Ok(rep)
+    // (3) Generate the quote. It holds the PCK certificate chain, so it is much larger than an
+    // EPID quote
+    const RET_QUOTE_BUF_LEN: u32 = 8192;
+    let mut quote = vec![0u8; RET_QUOTE_BUF_LEN as usize];
+    let mut quote_len: u32 = 0;
+
+    let res = unsafe {
+        ocall_get_dcap_quote(
+            &mut rt as *mut sgx_status_t,
+            &rep as *const sgx_report_t,
+            quote.as_mut_ptr(),
+            RET_QUOTE_BUF_LEN,
+            &mut quote_len as *mut u32,
+        )
+    };
+
+    if res != sgx_status_t::SGX_SUCCESS {
+        warn!("ocall_get_dcap_quote returned {}", res);
+        return Err(res);
+    }
+
+    if rt != sgx_status_t::SGX_SUCCESS {
+        warn!("ocall_get_dcap_quote returned {}", rt);
+        return Err(rt);
+    }
+
+    if quote_len > RET_QUOTE_BUF_LEN {
+        error!("The quote does not fit in its buffer");
+        return Err(sgx_status_t::SGX_ERROR_UNEXPECTED);
+    }
+    quote.truncate(quote_len as usize);
+
+    // (4) Get the TCB info of the platform and the CRLs of the CAs
+    let (fmspc, pck_ca) = platform_of_quote(&quote).map_err(|_| {
+        error!("Could not read the PCK certificate of the quote");
+        sgx_status_t::SGX_ERROR_UNEXPECTED
+    })?;
+
+    const RET_TCB_INFO_BUF_LEN: u32 = 16384;
+    const RET_ISSUER_CHAIN_BUF_LEN: u32 = 8192;
+    const RET_CRL_BUF_LEN: u32 = 65535;
+    let mut tcb_info = vec![0u8; RET_TCB_INFO_BUF_LEN as usize];
+    let mut tcb_info_len: u32 = 0;
+    let mut issuer_chain = vec![0u8; RET_ISSUER_CHAIN_BUF_LEN as usize];
+    let mut issuer_chain_len: u32 = 0;
+    let mut root_ca_crl = vec![0u8; RET_CRL_BUF_LEN as usize];
+    let mut root_ca_crl_len: u32 = 0;
+    let mut pck_crl = vec![0u8; RET_CRL_BUF_LEN as usize];
+    let mut pck_crl_len: u32 = 0;
+
+    let res = unsafe {
+        ocall_get_dcap_collateral(
+            &mut rt as *mut sgx_status_t,
+            fmspc.as_ptr(),
+            fmspc.len() as u32,
+            pck_ca.as_ptr(),
+            pck_ca.len() as u32,
+            tcb_info.as_mut_ptr(),
+            RET_TCB_INFO_BUF_LEN,
+            &mut tcb_info_len as *mut u32,
+            issuer_chain.as_mut_ptr(),
+            RET_ISSUER_CHAIN_BUF_LEN,
+            &mut issuer_chain_len as *mut u32,
+            root_ca_crl.as_mut_ptr(),
+            RET_CRL_BUF_LEN,
+            &mut root_ca_crl_len as *mut u32,
+            pck_crl.as_mut_ptr(),
+            RET_CRL_BUF_LEN,
+            &mut pck_crl_len as *mut u32,
+        )
+    };
+
+    if res != sgx_status_t::SGX_SUCCESS {
+        warn!("ocall_get_dcap_collateral returned {}", res);
+        return Err(res);
+    }
+
+    if rt != sgx_status_t::SGX_SUCCESS {
+        warn!("ocall_get_dcap_collateral returned {}", rt);
+        return Err(rt);
+    }
+
+    if tcb_info_len > RET_TCB_INFO_BUF_LEN
+        || issuer_chain_len > RET_ISSUER_CHAIN_BUF_LEN
+        || root_ca_crl_len > RET_CRL_BUF_LEN
+        || pck_crl_len > RET_CRL_BUF_LEN
+    {
+        error!("The collateral does not fit in its buffers");
+        return Err(sgx_status_t::SGX_ERROR_UNEXPECTED);
+    }
+    tcb_info.truncate(tcb_info_len as usize);
+    issuer_chain.truncate(issuer_chain_len as usize);
+    root_ca_crl.truncate(root_ca_crl_len as usize);
+    pck_crl.truncate(pck_crl_len as usize);
+
+    let endorsed_quote = EndorsedDcapQuote {
+        quote,
+        tcb_info,
+        tcb_info_issuer_chain: issuer_chain,
+        root_ca_crl,
+        pck_crl,
+    };
+
+    // Nothing outside the enclave can be trusted with the quote, so verify it the way other nodes
+    // will before handing it out. The collateral was just fetched, other nodes check that it's
+    // current.
+    match DcapReport::verify(&endorsed_quote, INTEL_SGX_PCK_ROOT_CA, None) {
+        Ok(report) if report.isv_enclave_report.report_data[..32] == pub_k[..] => {
+            trace!("DCAP quote check passed");
+        }
+        Ok(_) => {
+            error!("Quote is tampered!");
+            return Err(sgx_status_t::SGX_ERROR_UNEXPECTED);
+        }
+        Err(e) => {
+            error!("Could not verify the DCAP quote: {:?}", e);
+            return Err(sgx_status_t::SGX_ERROR_UNEXPECTED);
+        }
+    }
+
+    Ok(endorsed_quote)
 }
 
 //input: pub_k: &sgx_ec256_public_t, todo: make this the pubkey of the node
@@ -0,0 +1,285 @@
+//! Everything a third party needs to verify the attestation of this node, without reading it from
+//! the chain. The bundle is protobuf encoded, see `attestation_bundle.proto` next to this file.
+use log::*;
+use prost::Message;
+use sgx_types::sgx_status_t;
+use std::io::Read;
+use std::untrusted::fs::File;
+
+use enclave_ffi_types::UserSpaceBuffer;
+
+use crate::consts::ATTESTATION_CERTIFICATE_SAVE_PATH;
+use crate::crypto::KEY_MANAGER;
+use crate::results::allocate_user_buffer;
+use crate::utils::validate_mut_ptr;
+
+use super::cert::{get_ias_auth_config, get_netscape_comment};
+use super::dcap::{parse_pem_chain, EcdsaQuote, EndorsedDcapQuote};
+use super::decommission::ensure_not_decommissioned;
+use super::report::{EndorsedAttestationReport, Error};
+
+/// How the node proved that it runs in an enclave
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum AttestationType {
+    Unspecified = 0,
+    /// A quote signed with EPID and endorsed by the Intel Attestation Service
+    Epid = 1,
+    /// A quote signed with ECDSA and verified against DCAP collateral
+    Dcap = 2,
+    /// The enclave ran in simulation mode, so there is nothing to verify
+    Software = 3,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct AttestationBundle {
+    #[prost(bytes, tag = "1")]
+    pub registration_public_key: Vec<u8>,
+    #[prost(enumeration = "AttestationType", tag = "2")]
+    pub attestation_type: i32,
+    /// The attestation report as the attestation service returned it. For DCAP, the quote
+    #[prost(bytes, tag = "3")]
+    pub report: Vec<u8>,
+    /// The signature of the attestation service over `report`. Empty for DCAP, the quote holds its
+    /// signatures
+    #[prost(bytes, tag = "4")]
+    pub report_signature: Vec<u8>,
+    /// DER certificates from the one that signed `report` up to the root CA. For DCAP, the PCK
+    /// certificate chain in the quote
+    #[prost(bytes, repeated, tag = "5")]
+    pub signing_cert_chain: Vec<Vec<u8>>,
+    /// When the attestation service created `report`, as it reported it. For DCAP, when the TCB
+    /// info was issued
+    #[prost(string, tag = "6")]
+    pub timestamp: String,
+    /// The signed TCB info the quote is evaluated against, as the provisioning certification
+    /// service returned it. DCAP only
+    #[prost(bytes, tag = "7")]
+    pub tcb_info: Vec<u8>,
+    /// DER certificates from the one that signed `tcb_info` up to the root CA. DCAP only
+    #[prost(bytes, repeated, tag = "8")]
+    pub tcb_info_signing_chain: Vec<Vec<u8>>,
+    /// The DER CRL of the root CA. DCAP only
+    #[prost(bytes, tag = "9")]
+    pub root_ca_crl: Vec<u8>,
+    /// The DER CRL of the CA that issued the PCK certificate. DCAP only
+    #[prost(bytes, tag = "10")]
+    pub pck_crl: Vec<u8>,
+}
+
+/// Package the registration data in the attestation certificate `cert` of this node
+pub fn build_attestation_bundle(
+    cert: &[u8],
+    registration_public_key: &[u8],
+) -> Result<AttestationBundle, Error> {
+    let payload = get_netscape_comment(cert).map_err(|_err| Error::ReportParseError)?;
+
+    let mut bundle = AttestationBundle {
+        registration_public_key: registration_public_key.to_vec(),
+        ..AttestationBundle::default()
+    };
+
+    // Certificates of nodes that registered in simulation mode only hold their public key
+    if let Ok(endorsed) = serde_json::from_slice::<EndorsedAttestationReport>(&payload) {
+        let report: serde_json::Value = serde_json::from_slice(&endorsed.report)?;
+        let (ias_root_cert, _) = get_ias_auth_config();
+
+        bundle.set_attestation_type(AttestationType::Epid);
+        bundle.timestamp = report["timestamp"]
+            .as_str()
+            .ok_or(Error::ReportParseError)?
+            .to_string();
+        bundle.report = endorsed.report;
+        bundle.report_signature = endorsed.signature;
+        bundle.signing_cert_chain = vec![endorsed.signing_cert, ias_root_cert];
+    } else if let Ok(endorsed) = serde_json::from_slice::<EndorsedDcapQuote>(&payload) {
+        let tcb_info: serde_json::Value = serde_json::from_slice(&endorsed.tcb_info)?;
+        let quote = EcdsaQuote::parse(&endorsed.quote)?;
+
+        bundle.set_attestation_type(AttestationType::Dcap);
+        bundle.timestamp = tcb_info["tcbInfo"]["issueDate"]
+            .as_str()
+            .ok_or(Error::ReportParseError)?
+            .to_string();
+        bundle.signing_cert_chain = parse_pem_chain(quote.pck_cert_chain)?;
+        bundle.tcb_info_signing_chain = parse_pem_chain(&endorsed.tcb_info_issuer_chain)?;
+        bundle.report = endorsed.quote;
+        bundle.tcb_info = endorsed.tcb_info;
+        bundle.root_ca_crl = endorsed.root_ca_crl;
+        bundle.pck_crl = endorsed.pck_crl;
+    } else if base64::decode(&payload).is_ok() {
+        bundle.set_attestation_type(AttestationType::Software);
+    } else {
+        warn!("The attestation certificate holds no registration data this enclave knows");
+        return Err(Error::ReportParseError);
+    }
+
+    Ok(bundle)
+}
+
+///
+/// `ecall_get_attestation_bundle`
+///
+/// Export the registration public key of this node and the attestation report it registered with,
+/// as an `AttestationBundle`. The bundle is written to a buffer allocated outside the enclave.
+///
+/// # Safety
+/// `bundle` is an `[out]` parameter, so the edger8r bridge passes a pointer to a
+/// `UserSpaceBuffer` in enclave memory, and copies it out to the host when the ecall returns. The
+/// pointer is checked to be valid for writing a whole `UserSpaceBuffer` before it's used. It's
+/// only written on success, with a buffer allocated in user space, which the host has to free.
+#[no_mangle]
+pub unsafe extern "C" fn ecall_get_attestation_bundle(
+    bundle: *mut UserSpaceBuffer,
+) -> sgx_status_t {
+    if let Err(_e) = ensure_not_decommissioned() {
+        return sgx_status_t::SGX_ERROR_INVALID_STATE;
+    }
+
+    if let Err(_e) = validate_mut_ptr(bundle as _, std::mem::size_of::<UserSpaceBuffer>()) {
+        error!("Tried to access data outside enclave memory!");
+        return sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+    }
+
+    let registration_key = match KEY_MANAGER.get_registration_key() {
+        Ok(registration_key) => registration_key,
+        Err(_err) => {
+            error!("The node has no registration key, was it initialized?");
+            return sgx_status_t::SGX_ERROR_UNEXPECTED;
+        }
+    };
+
+    let mut cert = vec![];
+    if let Err(err) = File::open(ATTESTATION_CERTIFICATE_SAVE_PATH)
+        .and_then(|mut file| file.read_to_end(&mut cert))
+    {
+        error!("Could not read the attestation certificate: {:?}", err);
+        return sgx_status_t::SGX_ERROR_UNEXPECTED;
+    }
+
+    let attestation_bundle =
+        match build_attestation_bundle(&cert, &registration_key.get_pubkey().to_vec()) {
+            Ok(attestation_bundle) => attestation_bundle,
+            Err(err) => {
+                error!("Could not package the attestation certificate: {:?}", err);
+                return sgx_status_t::SGX_ERROR_UNEXPECTED;
+            }
+        };
+
+    let mut encoded = Vec::with_capacity(attestation_bundle.encoded_len());
+    if let Err(err) = attestation_bundle.encode(&mut encoded) {
+        error!("Could not encode the attestation bundle: {:?}", err);
+        return sgx_status_t::SGX_ERROR_UNEXPECTED;
+    }
+
+    match allocate_user_buffer(&encoded) {
+        Ok(buffer) => {
+            *bundle = buffer;
+            sgx_status_t::SGX_SUCCESS
+        }
+        Err(err) => {
+            error!("Could not export the attestation bundle: {}", err);
+            sgx_status_t::SGX_ERROR_UNEXPECTED
+        }
+    }
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use sgx_tcrypto::SgxEccHandle;
+
+    use super::super::cert::gen_ecc_cert;
+    use super::*;
+
+    fn cert(name: &str) -> Vec<u8> {
+        let mut cert = vec![];
+        let mut f = File::open(format!(
+            "../wasmi-runtime/src/registration/fixtures/{}",
+            name
+        ))
+        .unwrap();
+        f.read_to_end(&mut cert).unwrap();
+
+        cert
+    }
+
+    fn encode_then_decode(bundle: &AttestationBundle) -> AttestationBundle {
+        let mut encoded = vec![];
+        bundle.encode(&mut encoded).unwrap();
+        AttestationBundle::decode(encoded.as_slice()).unwrap()
+    }
+
+    pub fn test_attestation_bundle_epid() {
+        let public_key = [7u8; 32];
+        let bundle =
+            build_attestation_bundle(&cert("attestation_cert_out_of_date.der"), &public_key)
+                .unwrap();
+        let bundle = encode_then_decode(&bundle);
+
+        assert_eq!(bundle.attestation_type(), AttestationType::Epid);
+        assert_eq!(bundle.registration_public_key, public_key.to_vec());
+        assert_eq!(bundle.timestamp, "2020-08-20T11:57:22.984303");
+        assert_eq!(bundle.report_signature.len(), 256);
+
+        let report: serde_json::Value = serde_json::from_slice(&bundle.report).unwrap();
+        assert_eq!(report["version"], 4);
+
+        // The chain ends at the root CA of the attestation service
+        assert_eq!(bundle.signing_cert_chain.len(), 2);
+        assert_eq!(bundle.signing_cert_chain[1], cert("ias_root_ca_cert.der"));
+    }
+
+    pub fn test_attestation_bundle_dcap() {
+        let endorsed = EndorsedDcapQuote {
+            quote: cert("dcap_quote.bin"),
+            tcb_info: cert("dcap_tcb_info.json"),
+            tcb_info_issuer_chain: cert("dcap_tcb_info_issuer_chain.pem"),
+            root_ca_crl: cert("dcap_root_ca_crl.der"),
+            pck_crl: cert("dcap_pck_crl.der"),
+        };
+        let payload = serde_json::to_string(&endorsed).unwrap();
+
+        let ecc_handle = SgxEccHandle::new();
+        ecc_handle.open().unwrap();
+        let (prv_k, pub_k) = ecc_handle.create_key_pair().unwrap();
+        let (_key_der, attestation_cert) =
+            gen_ecc_cert(payload, &prv_k, &pub_k, &ecc_handle).unwrap();
+        ecc_handle.close().unwrap();
+
+        let public_key = [7u8; 32];
+        let bundle = build_attestation_bundle(&attestation_cert, &public_key).unwrap();
+        let bundle = encode_then_decode(&bundle);
+
+        assert_eq!(bundle.attestation_type(), AttestationType::Dcap);
+        assert_eq!(bundle.registration_public_key, public_key.to_vec());
+        assert_eq!(bundle.timestamp, "2024-05-01T00:00:00Z");
+        assert_eq!(bundle.report, endorsed.quote);
+        assert!(bundle.report_signature.is_empty());
+        assert_eq!(bundle.tcb_info, endorsed.tcb_info);
+        assert_eq!(bundle.root_ca_crl, endorsed.root_ca_crl);
+        assert_eq!(bundle.pck_crl, endorsed.pck_crl);
+
+        // Both chains end at the root CA
+        let root_ca = cert("dcap_test_root_ca.der");
+        assert_eq!(bundle.signing_cert_chain.len(), 3);
+        assert_eq!(bundle.signing_cert_chain[2], root_ca);
+        assert_eq!(bundle.tcb_info_signing_chain.len(), 2);
+        assert_eq!(bundle.tcb_info_signing_chain[1], root_ca);
+    }
+
+    pub fn test_attestation_bundle_software() {
+        let public_key = [7u8; 32];
+        let bundle = build_attestation_bundle(&cert("attestation_cert_sw"), &public_key).unwrap();
+        let bundle = encode_then_decode(&bundle);
+
+        assert_eq!(bundle.attestation_type(), AttestationType::Software);
+        assert_eq!(bundle.registration_public_key, public_key.to_vec());
+        assert!(bundle.report.is_empty());
+        assert!(bundle.signing_cert_chain.is_empty());
+        assert!(bundle.timestamp.is_empty());
+    }
+
+    pub fn test_attestation_bundle_unknown_cert() {
+        assert!(build_attestation_bundle(b"not a certificate", &[7u8; 32]).is_err());
+    }
+}
@@ -26,6 +26,9 @@ use crate::consts::CERTEXPIRYDAYS;
 #[cfg(feature = "SGX_MODE_HW")]
 use crate::consts::{SigningMethod, MRSIGNER, SIGNING_METHOD};
 
+use super::dcap::CollateralTime;
+#[cfg(feature = "SGX_MODE_HW")]
+use super::dcap::DcapReport;
 #[cfg(feature = "SGX_MODE_HW")]
 use super::report::{AttestationReport, SgxQuoteStatus};
 use crate::registration::report::AdvisoryIDs;
@@ -41,6 +44,10 @@ extern "C" {
 }
 
 pub const IAS_REPORT_CA: &[u8] = include_bytes!("../../Intel_SGX_Attestation_RootCA.pem");
+/// The root CA of the PCK certificates that endorse DCAP quotes, and of the TCB info signing
+/// certificate. The Makefile downloads it from Intel
+pub const INTEL_SGX_PCK_ROOT_CA: &[u8] =
+    include_bytes!("../../Intel_SGX_Provisioning_Certification_RootCA.cer");
 
 const ISSUER: &str = "SecretTEE";
 const SUBJECT: &str = "Secret Network Node Certificate";
@@ -274,7 +281,10 @@ pub fn get_ias_auth_config() -> (Vec<u8>, rustls::RootCertStore) {
 }
 
 #[cfg(not(feature = "SGX_MODE_HW"))]
-pub fn verify_ra_cert(cert_der: &[u8]) -> Result<Vec<u8>, NodeAuthResult> {
+pub fn verify_ra_cert(
+    cert_der: &[u8],
+    _collateral_time: CollateralTime,
+) -> Result<Vec<u8>, NodeAuthResult> {
     let payload = get_netscape_comment(cert_der).map_err(|_err| NodeAuthResult::InvalidCert)?;
 
     let pk = base64::decode(&payload).map_err(|_err| NodeAuthResult::InvalidCert)?;
@@ -287,19 +297,41 @@ pub fn verify_ra_cert(cert_der: &[u8]) -> Result<Vec<u8>, NodeAuthResult> {
 /// Logic:
 /// 1. Extract public key
 /// 2. Extract netscape comment - where the attestation report is located
-/// 3. Parse the report itself (verify it is signed by intel)
+/// 3. Parse the report itself (verify it is signed by intel). It is either an IAS report of an
+///    EPID quote, or a DCAP quote with its collateral
 /// 4. Extract public key from report body
 /// 5. Verify enclave signature (mr enclave/signer)
 ///
+/// The collateral of a DCAP quote is checked at `collateral_time`.
+///
 #[cfg(feature = "SGX_MODE_HW")]
-pub fn verify_ra_cert(cert_der: &[u8]) -> Result<Vec<u8>, NodeAuthResult> {
+pub fn verify_ra_cert(
+    cert_der: &[u8],
+    collateral_time: CollateralTime,
+) -> Result<Vec<u8>, NodeAuthResult> {
     // Before we reach here, Webpki already verifed the cert is properly signed
 
-    let report = AttestationReport::from_cert(cert_der).map_err(|_| NodeAuthResult::InvalidCert)?;
+    let (quote_status, advisories, isv_enclave_report) =
+        match AttestationReport::from_cert(cert_der) {
+            Ok(report) => (
+                report.sgx_quote_status,
+                report.advisroy_ids,
+                report.sgx_quote_body.isv_enclave_report,
+            ),
+            Err(_) => {
+                let report = DcapReport::from_cert(cert_der, collateral_time)
+                    .map_err(|_| NodeAuthResult::InvalidCert)?;
+                (
+                    report.sgx_quote_status,
+                    report.advisory_ids,
+                    report.isv_enclave_report,
+                )
+            }
+        };
 
     // 2. Verify quote status (mandatory field)
 
-    verify_quote_status(&report.sgx_quote_status, &report.advisroy_ids)?;
+    verify_quote_status(&quote_status, &advisories)?;
 
     // verify certificate
     match SIGNING_METHOD {
@@ -312,21 +344,21 @@ pub fn verify_ra_cert(cert_der: &[u8]) -> Result<Vec<u8>, NodeAuthResult> {
                 }
             };
 
-            if report.sgx_quote_body.isv_enclave_report.mr_enclave != this_mr_enclave {
+            if isv_enclave_report.mr_enclave != this_mr_enclave {
                 error!("Got a different mr_enclave than expected. Invalid certificate");
                 warn!(
                     "received: {:?} \n expected: {:?}",
-                    report.sgx_quote_body.isv_enclave_report.mr_enclave, this_mr_enclave
+                    isv_enclave_report.mr_enclave, this_mr_enclave
                 );
                 return Err(NodeAuthResult::MrEnclaveMismatch);
             }
         }
         SigningMethod::MRSIGNER => {
-            if report.sgx_quote_body.isv_enclave_report.mr_signer != MRSIGNER {
+            if isv_enclave_report.mr_signer != MRSIGNER {
                 error!("Got a different mrsigner than expected. Invalid certificate");
                 warn!(
                     "received: {:?} \n expected: {:?}",
-                    report.sgx_quote_body.isv_enclave_report.mr_signer, MRSIGNER
+                    isv_enclave_report.mr_signer, MRSIGNER
                 );
                 return Err(NodeAuthResult::MrSignerMismatch);
             }
@@ -334,7 +366,7 @@ pub fn verify_ra_cert(cert_der: &[u8]) -> Result<Vec<u8>, NodeAuthResult> {
         SigningMethod::NONE => {}
     }
 
-    let report_public_key = report.sgx_quote_body.isv_enclave_report.report_data[0..32].to_vec();
+    let report_public_key = isv_enclave_report.report_data[0..32].to_vec();
     Ok(report_public_key)
 }
 
@@ -377,7 +409,8 @@ pub fn verify_quote_status(
     match quote_status {
         SgxQuoteStatus::OK => Ok(()),
         SgxQuoteStatus::SwHardeningNeeded => Ok(()),
-        SgxQuoteStatus::GroupOutOfDate => {
+        // DCAP reports an outdated TCB level as OUT_OF_DATE
+        SgxQuoteStatus::GroupOutOfDate | SgxQuoteStatus::OutOfDate => {
             warn!("TCB level of SGX platform service is outdated. You should check for firmware updates");
             warn!(
                 "The following vulnerabilities must be mitigated: {:?}",
@@ -413,11 +446,15 @@ pub mod tests {
     use crate::crypto::KeyPair;
 
     use super::verify_ra_cert;
+    use crate::registration::dcap::CollateralTime;
     use crate::registration::report::AttestationReport;
     use enclave_ffi_types::NodeAuthResult;
     use std::io::Read;
     use std::untrusted::fs::File;
 
+    /// The time of the block the certificates are registered in
+    const BLOCK_TIME: u64 = 1_715_299_200;
+
     fn tls_ra_cert_der_out_of_date() -> Vec<u8> {
         let mut cert = vec![];
         let mut f = File::open(
@@ -469,8 +506,8 @@ pub mod tests {
         let report = AttestationReport::from_cert(&tls_ra_cert);
         assert!(report.is_ok());
 
-        let result =
-            verify_ra_cert(&tls_ra_cert).expect_err("Certificate should not pass validation");
+        let result = verify_ra_cert(&tls_ra_cert, CollateralTime::BlockTime(BLOCK_TIME))
+            .expect_err("Certificate should not pass validation");
 
         assert_eq!(result, NodeAuthResult::SwHardeningAndConfigurationNeeded)
     }
@@ -484,14 +521,14 @@ pub mod tests {
         let report = AttestationReport::from_cert(&tls_ra_cert);
         assert!(report.is_ok());
 
-        let result =
-            verify_ra_cert(&tls_ra_cert).expect_err("Certificate should not pass validation");
+        let result = verify_ra_cert(&tls_ra_cert, CollateralTime::BlockTime(BLOCK_TIME))
+            .expect_err("Certificate should not pass validation");
 
         assert_eq!(result, NodeAuthResult::GroupOutOfDate)
     }
 
     pub fn test_certificate_valid() {
         let tls_ra_cert = tls_ra_cert_der_valid();
-        let result = verify_ra_cert(&tls_ra_cert).unwrap();
+        let result = verify_ra_cert(&tls_ra_cert, CollateralTime::BlockTime(BLOCK_TIME)).unwrap();
     }
 }
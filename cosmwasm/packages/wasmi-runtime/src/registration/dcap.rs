@@ -0,0 +1,1059 @@
+//! Attestation with ECDSA quotes, as the quoting enclave of the DCAP libraries creates them.
+//! There is no attestation service in between: the quote is endorsed by the PCK certificate of the
+//! platform, which chains up to the Intel SGX root CA, and the TCB status of the platform is looked
+//! up in the TCB info Intel publishes for it. The CRLs Intel publishes for the CAs tell whether any
+//! of the certificates was revoked.
+//! https://download.01.org/intel-sgx/latest/dcap-latest/linux/docs/Intel_SGX_ECDSA_QuoteLibReference_DCAP_API.pdf
+#![cfg_attr(not(feature = "SGX_MODE_HW"), allow(unused))]
+
+use log::*;
+use num_bigint::BigUint;
+use ring::signature::{UnparsedPublicKey, ECDSA_P256_SHA256_FIXED};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sgx_tcrypto::rsgx_sha256_slice;
+use std::convert::TryFrom;
+use std::string::String;
+use std::vec::Vec;
+
+use super::cert::{get_netscape_comment, INTEL_SGX_PCK_ROOT_CA};
+
+use super::report::{
+    as_base64, from_base64, AdvisoryIDs, Error, SgxEnclaveReport, SgxQuoteStatus,
+    SUPPORTED_SIG_ALGS,
+};
+
+const QUOTE_VERSION: u16 = 3;
+const ATTESTATION_KEY_TYPE_ECDSA_P256: u16 = 2;
+/// Certification data that is the PEM certificate chain of the PCK certificate
+const CERTIFICATION_DATA_PCK_CERT_CHAIN: u16 = 5;
+
+const QUOTE_HEADER_SIZE: usize = 48;
+const REPORT_BODY_SIZE: usize = 384;
+const ECDSA_SIGNATURE_SIZE: usize = 64;
+const ECDSA_PUBLIC_KEY_SIZE: usize = 64;
+
+/// MRSIGNER of the quoting enclave of Intel
+const QE_MRSIGNER: [u8; 32] = [
+    0x8c, 0x4f, 0x57, 0x75, 0xd7, 0x96, 0x50, 0x3e, 0x96, 0x13, 0x7f, 0x77, 0xc6, 0x8a, 0x82, 0x9a,
+    0x00, 0x56, 0xac, 0x8d, 0xed, 0x70, 0x14, 0x0b, 0x08, 0x1b, 0x09, 0x44, 0x90, 0xc5, 0x7b, 0xff,
+];
+const QE_ISV_PROD_ID: u16 = 1;
+
+/// The SGX extension of PCK certificates, 1.2.840.113741.1.13.1
+const SGX_EXTENSION_OID: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF8, 0x4D, 0x01, 0x0D, 0x01];
+
+/// ecdsa-with-SHA256, 1.2.840.10045.4.3.2, the signature algorithm of the CRLs of Intel
+const ECDSA_WITH_SHA256_OID: &[u8] = &[0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x04, 0x03, 0x02];
+
+const DER_BOOLEAN: u8 = 0x01;
+const DER_INTEGER: u8 = 0x02;
+const DER_BIT_STRING: u8 = 0x03;
+const DER_OCTET_STRING: u8 = 0x04;
+const DER_OID: u8 = 0x06;
+const DER_UTC_TIME: u8 = 0x17;
+const DER_GENERALIZED_TIME: u8 = 0x18;
+const DER_SEQUENCE: u8 = 0x30;
+/// The explicit version of a certificate, `[0]`
+const DER_CONTEXT_0: u8 = 0xA0;
+
+/// The registration data of a node that attested with DCAP, as its attestation certificate holds it
+#[derive(Default, Serialize, Deserialize)]
+pub struct EndorsedDcapQuote {
+    /// The quote, with the certificate chain of the PCK certificate that endorses it
+    #[serde(serialize_with = "as_base64", deserialize_with = "from_base64")]
+    pub quote: Vec<u8>,
+    /// The TCB info of the platform with its signature, as the provisioning certification service
+    /// returned it
+    #[serde(serialize_with = "as_base64", deserialize_with = "from_base64")]
+    pub tcb_info: Vec<u8>,
+    /// PEM certificates from the one that signed `tcb_info` up to the root CA
+    #[serde(serialize_with = "as_base64", deserialize_with = "from_base64")]
+    pub tcb_info_issuer_chain: Vec<u8>,
+    /// The DER CRL of the root CA, which lists the CAs under it that were revoked
+    #[serde(serialize_with = "as_base64", deserialize_with = "from_base64")]
+    pub root_ca_crl: Vec<u8>,
+    /// The DER CRL of the CA that issued the PCK certificate of the platform
+    #[serde(serialize_with = "as_base64", deserialize_with = "from_base64")]
+    pub pck_crl: Vec<u8>,
+}
+
+/// A DCAP quote whose signatures, certificates and TCB info were all verified
+#[derive(Debug)]
+pub struct DcapReport {
+    /// The TCB status of the platform, as the quote status of IAS it stands for
+    pub sgx_quote_status: SgxQuoteStatus,
+    pub isv_enclave_report: SgxEnclaveReport,
+    pub advisory_ids: AdvisoryIDs,
+    /// When the TCB info the platform was evaluated against was issued
+    pub tcb_info_issue_date: String,
+}
+
+/// The time the collateral of a DCAP quote is checked at
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CollateralTime {
+    /// The time of the block a registration tx is executed in, in seconds since the epoch. It's
+    /// part of the consensus, so every node checks the collateral at the same time and comes to
+    /// the same result.
+    BlockTime(u64),
+    /// The issue date of the TCB info, for collateral whose freshness is vouched for otherwise
+    IssueDate,
+}
+
+impl CollateralTime {
+    /// The time to verify the collateral at in seconds since the epoch, or None for its issue date
+    fn trusted_time(self) -> Option<u64> {
+        match self {
+            CollateralTime::BlockTime(time) => Some(time),
+            CollateralTime::IssueDate => None,
+        }
+    }
+}
+
+impl DcapReport {
+    /// The report of the DCAP quote in the attestation certificate `cert`, with its collateral
+    /// checked at `time`
+    pub fn from_cert(cert: &[u8], time: CollateralTime) -> Result<Self, Error> {
+        let payload = get_netscape_comment(cert).map_err(|_err| Error::ReportParseError)?;
+        let endorsed: EndorsedDcapQuote = serde_json::from_slice(&payload)?;
+
+        Self::verify(&endorsed, INTEL_SGX_PCK_ROOT_CA, time.trusted_time())
+    }
+
+    /// Verify `endorsed` against `root_ca`, the DER certificate both the PCK certificate and the
+    /// TCB info signing certificate must chain up to, and whose CRL is `endorsed.root_ca_crl`.
+    ///
+    /// `trusted_time` is a time the enclave trusts, in seconds since the epoch. The certificates
+    /// must be valid then, and the TCB info and the CRLs must be current: issued before it, and not
+    /// due to be updated yet. Otherwise a node could register with the status Intel gave its
+    /// platform before a vulnerability was found, or with a PCK certificate that was revoked since.
+    ///
+    /// Without a trusted time, the certificates are checked at the issue date of the TCB info and
+    /// the freshness of the collateral isn't checked. That's only for collateral that is known to be
+    /// fresh otherwise, like collateral this enclave just fetched itself.
+    pub fn verify(
+        endorsed: &EndorsedDcapQuote,
+        root_ca: &[u8],
+        trusted_time: Option<u64>,
+    ) -> Result<Self, Error> {
+        // 1. The TCB info, signed by Intel
+        let (tcb_info_raw, tcb_info_signature) = split_signed_tcb_info(&endorsed.tcb_info)?;
+        let tcb_info: Value = serde_json::from_slice(tcb_info_raw)?;
+        let tcb_info_issue_date = tcb_info["issueDate"]
+            .as_str()
+            .ok_or(Error::ReportParseError)?
+            .to_string();
+        let issued = rfc3339_seconds(&tcb_info_issue_date)?;
+        let next_update = rfc3339_seconds(
+            tcb_info["nextUpdate"]
+                .as_str()
+                .ok_or(Error::ReportParseError)?,
+        )?;
+        if let Some(now) = trusted_time {
+            check_current("TCB info", issued, next_update, now)?;
+        }
+        let time = webpki::Time::from_seconds_since_unix_epoch(trusted_time.unwrap_or(issued));
+
+        // The CAs under the root CA that were revoked
+        let root_ca_crl = Crl::verify(&endorsed.root_ca_crl, root_ca, trusted_time)?;
+
+        let tcb_signing_chain = parse_pem_chain(&endorsed.tcb_info_issuer_chain)?;
+        let tcb_signing_cert = verify_cert_chain(&tcb_signing_chain, root_ca, time)?;
+        root_ca_crl.check_not_revoked(&tcb_signing_chain[0])?;
+        verify_raw_signature(&tcb_signing_cert, tcb_info_raw, &tcb_info_signature)?;
+
+        // 2. The quoting enclave, endorsed by the PCK certificate of the platform, which neither the
+        //    CA that issued it nor the root CA revoked
+        let quote = EcdsaQuote::parse(&endorsed.quote)?;
+        let pck_cert_chain = parse_pem_chain(quote.pck_cert_chain)?;
+        let pck_cert = verify_cert_chain(&pck_cert_chain, root_ca, time)?;
+        let pck_ca = match pck_cert_chain.get(1) {
+            Some(pck_ca) if pck_ca.as_slice() != root_ca => pck_ca,
+            _ => {
+                warn!("The PCK certificate was not issued by an intermediate CA");
+                return Err(Error::ReportValidationError);
+            }
+        };
+        root_ca_crl.check_not_revoked(pck_ca)?;
+        Crl::verify(&endorsed.pck_crl, pck_ca, trusted_time)?
+            .check_not_revoked(&pck_cert_chain[0])?;
+        verify_raw_signature(&pck_cert, quote.qe_report_raw, quote.qe_report_signature)?;
+
+        if quote.qe_report.mr_signer != QE_MRSIGNER || quote.qe_report.isv_prod_id != QE_ISV_PROD_ID
+        {
+            warn!("The quote was not created by the quoting enclave of Intel");
+            return Err(Error::ReportValidationError);
+        }
+
+        // 3. The attestation key, which the quoting enclave vouches for in its report
+        let mut attestation_key_hash_input = quote.attestation_key.to_vec();
+        attestation_key_hash_input.extend_from_slice(quote.qe_auth_data);
+        let attestation_key_hash = rsgx_sha256_slice(&attestation_key_hash_input)
+            .map_err(|_| Error::ReportValidationError)?;
+        if quote.qe_report.report_data[..32] != attestation_key_hash[..]
+            || quote.qe_report.report_data[32..]
+                .iter()
+                .any(|byte| *byte != 0)
+        {
+            warn!("The attestation key of the quote is not the one of the quoting enclave");
+            return Err(Error::ReportValidationError);
+        }
+
+        // 4. The report of the enclave, signed by the attestation key
+        let mut attestation_key = vec![0x04];
+        attestation_key.extend_from_slice(quote.attestation_key);
+        UnparsedPublicKey::new(&ECDSA_P256_SHA256_FIXED, &attestation_key)
+            .verify(quote.signed, quote.isv_signature)
+            .map_err(|_| {
+                warn!("The signature of the quote is invalid");
+                Error::ReportValidationError
+            })?;
+
+        // 5. The TCB status of the platform
+        let pck = PckExtensions::from_cert(&pck_cert_chain[0])?;
+        let (sgx_quote_status, advisories) = tcb_status(&tcb_info, &pck)?;
+
+        Ok(Self {
+            sgx_quote_status,
+            isv_enclave_report: quote.isv_enclave_report,
+            advisory_ids: AdvisoryIDs(advisories),
+            tcb_info_issue_date,
+        })
+    }
+}
+
+/// The parts of a version 3 quote, borrowed from its bytes
+pub struct EcdsaQuote<'a> {
+    /// The header and the report of the enclave, which the attestation key signed
+    signed: &'a [u8],
+    pub isv_enclave_report: SgxEnclaveReport,
+    isv_signature: &'a [u8],
+    attestation_key: &'a [u8],
+    qe_report_raw: &'a [u8],
+    qe_report: SgxEnclaveReport,
+    qe_report_signature: &'a [u8],
+    qe_auth_data: &'a [u8],
+    /// PEM certificates from the PCK certificate of the platform up to the root CA
+    pub pck_cert_chain: &'a [u8],
+}
+
+impl<'a> EcdsaQuote<'a> {
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, Error> {
+        let mut pos: usize = 0;
+        let mut take = |n: usize| -> Result<&'a [u8], Error> {
+            if bytes.len() >= pos + n {
+                let ret = &bytes[pos..pos + n];
+                pos += n;
+                Ok(ret)
+            } else {
+                warn!("Quote parsing error - bad quote size");
+                Err(Error::ReportParseError)
+            }
+        };
+
+        let header = take(QUOTE_HEADER_SIZE)?;
+        let version = u16::from_le_bytes(<[u8; 2]>::try_from(&header[0..2])?);
+        let attestation_key_type = u16::from_le_bytes(<[u8; 2]>::try_from(&header[2..4])?);
+        if version != QUOTE_VERSION || attestation_key_type != ATTESTATION_KEY_TYPE_ECDSA_P256 {
+            warn!(
+                "Unsupported quote version {} with attestation key type {}",
+                version, attestation_key_type
+            );
+            return Err(Error::ReportParseError);
+        }
+
+        let isv_enclave_report_raw = take(REPORT_BODY_SIZE)?;
+        let signature_data_len = u32::from_le_bytes(<[u8; 4]>::try_from(take(4)?)?) as usize;
+
+        let isv_signature = take(ECDSA_SIGNATURE_SIZE)?;
+        let attestation_key = take(ECDSA_PUBLIC_KEY_SIZE)?;
+        let qe_report_raw = take(REPORT_BODY_SIZE)?;
+        let qe_report_signature = take(ECDSA_SIGNATURE_SIZE)?;
+        let qe_auth_data_len = u16::from_le_bytes(<[u8; 2]>::try_from(take(2)?)?) as usize;
+        let qe_auth_data = take(qe_auth_data_len)?;
+
+        let certification_data_type = u16::from_le_bytes(<[u8; 2]>::try_from(take(2)?)?);
+        let certification_data_len = u32::from_le_bytes(<[u8; 4]>::try_from(take(4)?)?) as usize;
+        let certification_data = take(certification_data_len)?;
+
+        let signed_len = QUOTE_HEADER_SIZE + REPORT_BODY_SIZE;
+        if pos != bytes.len() || signature_data_len != bytes.len() - signed_len - 4 {
+            warn!("Quote parsing error - bad signature data size");
+            return Err(Error::ReportParseError);
+        }
+
+        if certification_data_type != CERTIFICATION_DATA_PCK_CERT_CHAIN {
+            warn!(
+                "Unsupported certification data type {}",
+                certification_data_type
+            );
+            return Err(Error::ReportParseError);
+        }
+
+        Ok(Self {
+            signed: &bytes[..signed_len],
+            isv_enclave_report: SgxEnclaveReport::parse_from(isv_enclave_report_raw)?,
+            isv_signature,
+            attestation_key,
+            qe_report_raw,
+            qe_report: SgxEnclaveReport::parse_from(qe_report_raw)?,
+            qe_report_signature,
+            qe_auth_data,
+            pck_cert_chain: certification_data,
+        })
+    }
+}
+
+/// What the PCK certificate of a platform states about it
+#[derive(Debug, Default)]
+pub struct PckExtensions {
+    pub fmspc: Vec<u8>,
+    pub pce_id: Vec<u8>,
+    /// SVNs of the TCB components of the CPU
+    pub cpu_svn_components: [u8; 16],
+    pub pce_svn: u16,
+}
+
+impl PckExtensions {
+    pub fn from_cert(cert: &[u8]) -> Result<Self, Error> {
+        let mut extension_id = vec![DER_OID, SGX_EXTENSION_OID.len() as u8];
+        extension_id.extend_from_slice(SGX_EXTENSION_OID);
+        let position = cert
+            .windows(extension_id.len())
+            .position(|window| window == &extension_id[..])
+            .ok_or_else(|| {
+                warn!("The PCK certificate has no SGX extension");
+                Error::ReportParseError
+            })?;
+
+        // Skip the critical flag, if it is encoded
+        let mut extension = der_element(&cert[position + extension_id.len()..])?;
+        if extension.0 == DER_BOOLEAN {
+            extension = der_element(extension.2)?;
+        }
+        if extension.0 != DER_OCTET_STRING {
+            return Err(Error::ReportParseError);
+        }
+        let (mut items, _) = der_expect(DER_SEQUENCE, extension.1)?;
+
+        let mut pck = Self::default();
+        let mut has_tcb = false;
+        while !items.is_empty() {
+            let (item, rest) = der_expect(DER_SEQUENCE, items)?;
+            items = rest;
+            let (oid, value) = der_expect(DER_OID, item)?;
+            match sgx_extension_arcs(oid) {
+                Some(&[2]) => {
+                    pck.parse_tcb(value)?;
+                    has_tcb = true;
+                }
+                Some(&[3]) => pck.pce_id = der_expect(DER_OCTET_STRING, value)?.0.to_vec(),
+                Some(&[4]) => pck.fmspc = der_expect(DER_OCTET_STRING, value)?.0.to_vec(),
+                _ => {}
+            }
+        }
+
+        if !has_tcb || pck.fmspc.is_empty() || pck.pce_id.is_empty() {
+            warn!("The SGX extension of the PCK certificate is incomplete");
+            return Err(Error::ReportParseError);
+        }
+
+        Ok(pck)
+    }
+
+    fn parse_tcb(&mut self, tcb: &[u8]) -> Result<(), Error> {
+        let (mut components, _) = der_expect(DER_SEQUENCE, tcb)?;
+        while !components.is_empty() {
+            let (component, rest) = der_expect(DER_SEQUENCE, components)?;
+            components = rest;
+            let (oid, value) = der_expect(DER_OID, component)?;
+            match sgx_extension_arcs(oid) {
+                Some(&[2, n]) if (1..=16).contains(&n) => {
+                    self.cpu_svn_components[n as usize - 1] =
+                        u8::try_from(der_uint(value)?).map_err(|_| Error::ReportParseError)?;
+                }
+                Some(&[2, 17]) => self.pce_svn = der_uint(value)?,
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The FMSPC of the platform that created `quote` and which CA issued its PCK certificate, as the
+/// provisioning certification service names it, "platform" or "processor"
+pub fn platform_of_quote(quote: &[u8]) -> Result<(Vec<u8>, &'static str), Error> {
+    let quote = EcdsaQuote::parse(quote)?;
+    let pck_cert_chain = parse_pem_chain(quote.pck_cert_chain)?;
+    let pck = PckExtensions::from_cert(&pck_cert_chain[0])?;
+
+    let issuer = pck_cert_chain.get(1).ok_or(Error::ReportParseError)?;
+    let ca = if contains(issuer, b"Processor CA") {
+        "processor"
+    } else if contains(issuer, b"Platform CA") {
+        "platform"
+    } else {
+        warn!("The PCK certificate was issued by an unknown CA");
+        return Err(Error::ReportParseError);
+    };
+
+    Ok((pck.fmspc, ca))
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack
+        .windows(needle.len())
+        .any(|window| window == needle)
+}
+
+/// The status of the first TCB level in `tcb_info` that the platform of `pck` is at, with the
+/// advisories that apply to it
+fn tcb_status(
+    tcb_info: &Value,
+    pck: &PckExtensions,
+) -> Result<(SgxQuoteStatus, Vec<String>), Error> {
+    let version = tcb_info["version"]
+        .as_u64()
+        .ok_or(Error::ReportParseError)?;
+    if version != 2 && version != 3 {
+        warn!("Unsupported TCB info version {}", version);
+        return Err(Error::ReportParseError);
+    }
+
+    if !hex_matches(&tcb_info["fmspc"], &pck.fmspc) || !hex_matches(&tcb_info["pceId"], &pck.pce_id)
+    {
+        warn!("The TCB info is not the one of the platform");
+        return Err(Error::ReportValidationError);
+    }
+
+    let levels = tcb_info["tcbLevels"]
+        .as_array()
+        .ok_or(Error::ReportParseError)?;
+    for level in levels {
+        let tcb = &level["tcb"];
+        let svns: Option<Vec<u64>> = if version == 3 {
+            tcb["sgxtcbcomponents"]
+                .as_array()
+                .and_then(|components| components.iter().map(|c| c["svn"].as_u64()).collect())
+        } else {
+            (1..=16)
+                .map(|i| tcb[format!("sgxtcbcomp{:02}svn", i).as_str()].as_u64())
+                .collect()
+        };
+        let svns = svns.ok_or(Error::ReportParseError)?;
+        let pce_svn = tcb["pcesvn"].as_u64().ok_or(Error::ReportParseError)?;
+        if svns.len() != pck.cpu_svn_components.len() {
+            return Err(Error::ReportParseError);
+        }
+
+        let is_at_level = pck
+            .cpu_svn_components
+            .iter()
+            .zip(svns.iter())
+            .all(|(svn, level_svn)| u64::from(*svn) >= *level_svn)
+            && u64::from(pck.pce_svn) >= pce_svn;
+        if !is_at_level {
+            continue;
+        }
+
+        let status = level["tcbStatus"].as_str().ok_or(Error::ReportParseError)?;
+        let advisories: Vec<String> = match level.get("advisoryIDs") {
+            Some(advisories) => serde_json::from_value(advisories.clone())?,
+            None => vec![],
+        };
+        return Ok((quote_status_of_tcb_status(status), advisories));
+    }
+
+    warn!("The TCB of the platform is below every level of its TCB info");
+    Ok((SgxQuoteStatus::UnknownBadStatus, vec![]))
+}
+
+/// The quote status of IAS that means the same as the TCB status `status`, so both kinds of
+/// attestation go through the same policy
+fn quote_status_of_tcb_status(status: &str) -> SgxQuoteStatus {
+    match status {
+        "UpToDate" => SgxQuoteStatus::OK,
+        "SWHardeningNeeded" => SgxQuoteStatus::SwHardeningNeeded,
+        "ConfigurationNeeded" => SgxQuoteStatus::ConfigurationNeeded,
+        "ConfigurationAndSWHardeningNeeded" => SgxQuoteStatus::ConfigurationAndSwHardeningNeeded,
+        "OutOfDate" => SgxQuoteStatus::OutOfDate,
+        "OutOfDateConfigurationNeeded" => SgxQuoteStatus::OutOfDateConfigurationNeeded,
+        "Revoked" => SgxQuoteStatus::KeyRevoked,
+        _ => SgxQuoteStatus::UnknownBadStatus,
+    }
+}
+
+fn hex_matches(value: &Value, bytes: &[u8]) -> bool {
+    value
+        .as_str()
+        .and_then(|encoded| hex::decode(encoded).ok())
+        .map_or(false, |decoded| decoded == bytes)
+}
+
+/// Split the signed TCB info into the exact bytes of the TCB info, which are what Intel signed, and
+/// the signature
+fn split_signed_tcb_info(signed: &[u8]) -> Result<(&[u8], Vec<u8>), Error> {
+    const PREFIX: &[u8] = b"{\"tcbInfo\":";
+
+    if !signed.starts_with(PREFIX) {
+        warn!("The TCB info is not in the format of the provisioning certification service");
+        return Err(Error::ReportParseError);
+    }
+    let body = &signed[PREFIX.len()..];
+    let body_len = json_object_len(body).ok_or(Error::ReportParseError)?;
+
+    let document: Value = serde_json::from_slice(signed)?;
+    let signature = document["signature"]
+        .as_str()
+        .and_then(|encoded| hex::decode(encoded).ok())
+        .ok_or(Error::ReportParseError)?;
+
+    Ok((&body[..body_len], signature))
+}
+
+/// The length of the JSON object at the start of `json`
+fn json_object_len(json: &[u8]) -> Option<usize> {
+    if json.first() != Some(&b'{') {
+        return None;
+    }
+
+    let mut depth: usize = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, byte) in json.iter().enumerate() {
+        if in_string {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+
+        match byte {
+            b'"' => in_string = true,
+            b'{' => depth += 1,
+            b'}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+fn rfc3339_seconds(rfc3339: &str) -> Result<u64, Error> {
+    let time = chrono::DateTime::parse_from_rfc3339(rfc3339).map_err(|_| {
+        warn!("Error parsing a date of the TCB info");
+        Error::ReportParseError
+    })?;
+
+    u64::try_from(time.timestamp()).map_err(|_| Error::ReportParseError)
+}
+
+/// The DER certificates of the PEM certificate chain `chain`, in its order
+pub fn parse_pem_chain(chain: &[u8]) -> Result<Vec<Vec<u8>>, Error> {
+    const BEGIN: &str = "-----BEGIN CERTIFICATE-----";
+    const END: &str = "-----END CERTIFICATE-----";
+
+    let mut rest = std::str::from_utf8(chain).map_err(|_| Error::ReportParseError)?;
+    let mut certs = vec![];
+    while let Some(begin) = rest.find(BEGIN) {
+        let cert = &rest[begin + BEGIN.len()..];
+        let end = cert.find(END).ok_or(Error::ReportParseError)?;
+        let encoded: String = cert[..end].split_whitespace().collect();
+        certs.push(base64::decode(&encoded).map_err(|_| Error::ReportParseError)?);
+        rest = &cert[end + END.len()..];
+    }
+
+    if certs.is_empty() {
+        warn!("Found no certificates in the certificate chain");
+        return Err(Error::ReportParseError);
+    }
+
+    Ok(certs)
+}
+
+/// Verify that the first certificate of `chain` is endorsed by the others, up to `root_ca`
+fn verify_cert_chain<'a>(
+    chain: &'a [Vec<u8>],
+    root_ca: &[u8],
+    time: webpki::Time,
+) -> Result<webpki::EndEntityCert<'a>, Error> {
+    let (leaf, intermediates) = chain.split_first().ok_or(Error::ReportParseError)?;
+    let leaf = webpki::EndEntityCert::from(leaf).map_err(|_| Error::ReportParseError)?;
+
+    let mut root_store = rustls::RootCertStore::empty();
+    root_store
+        .add(&rustls::Certificate(root_ca.to_vec()))
+        .map_err(|_| Error::ReportValidationError)?;
+    let trust_anchors: Vec<webpki::TrustAnchor> = root_store
+        .roots
+        .iter()
+        .map(|cert| cert.to_trust_anchor())
+        .collect();
+
+    // The chains end with the root CA, which webpki doesn't expect among the intermediates
+    let intermediates: Vec<&[u8]> = intermediates
+        .iter()
+        .map(|cert| cert.as_slice())
+        .filter(|cert| *cert != root_ca)
+        .collect();
+
+    match leaf.verify_is_valid_tls_server_cert(
+        SUPPORTED_SIG_ALGS,
+        &webpki::TLSServerTrustAnchors(&trust_anchors),
+        &intermediates,
+        time,
+    ) {
+        Ok(_) => Ok(leaf),
+        Err(e) => {
+            warn!("Certificate verification error {:?}", e);
+            Err(Error::ReportValidationError)
+        }
+    }
+}
+
+/// Verify `signature`, the r and s of an ECDSA P-256 signature, against the key of `cert`
+fn verify_raw_signature(
+    cert: &webpki::EndEntityCert,
+    message: &[u8],
+    signature: &[u8],
+) -> Result<(), Error> {
+    if signature.len() != ECDSA_SIGNATURE_SIZE {
+        return Err(Error::ReportParseError);
+    }
+
+    // webpki only takes DER encoded signatures
+    let der_signature = yasna::construct_der(|writer| {
+        writer.write_sequence(|writer| {
+            writer
+                .next()
+                .write_biguint(&BigUint::from_bytes_be(&signature[..32]));
+            writer
+                .next()
+                .write_biguint(&BigUint::from_bytes_be(&signature[32..]));
+        });
+    });
+
+    cert.verify_signature(&webpki::ECDSA_P256_SHA256, message, &der_signature)
+        .map_err(|e| {
+            warn!("Signature verification error {:?}", e);
+            Error::ReportValidationError
+        })
+}
+
+/// Check that collateral that was issued at `issued` and is due to be updated at `next_update` is
+/// current at `now`
+fn check_current(collateral: &str, issued: u64, next_update: u64, now: u64) -> Result<(), Error> {
+    if now < issued || now >= next_update {
+        warn!(
+            "The {} is not current at {}, it was issued at {} and is due to be updated at {}",
+            collateral, now, issued, next_update
+        );
+        return Err(Error::ReportValidationError);
+    }
+
+    Ok(())
+}
+
+/// A CRL whose signature was verified
+struct Crl<'a> {
+    /// The serial numbers of the certificates it revokes, as the contents of their DER INTEGER
+    revoked_serials: Vec<&'a [u8]>,
+}
+
+impl<'a> Crl<'a> {
+    /// Verify that the DER CRL `crl` was signed by the DER certificate `issuer`, and when there is a
+    /// trusted time, that it is current then
+    fn verify(crl: &'a [u8], issuer: &[u8], trusted_time: Option<u64>) -> Result<Self, Error> {
+        let (cert_list, _) = der_expect(DER_SEQUENCE, crl)?;
+        let (tag, tbs, rest) = der_element(cert_list)?;
+        if tag != DER_SEQUENCE {
+            return Err(Error::ReportParseError);
+        }
+        // The signature is over the whole TBSCertList, with its tag and length
+        let tbs_raw = &cert_list[..cert_list.len() - rest.len()];
+
+        let (algorithm, rest) = der_expect(DER_SEQUENCE, rest)?;
+        if der_expect(DER_OID, algorithm)?.0 != ECDSA_WITH_SHA256_OID {
+            warn!("The CRL is not signed with ECDSA and SHA-256");
+            return Err(Error::ReportParseError);
+        }
+        let (signature, _) = der_expect(DER_BIT_STRING, rest)?;
+        let signature = match signature.split_first() {
+            Some((0, signature)) => signature,
+            _ => return Err(Error::ReportParseError),
+        };
+
+        let issuer = webpki::EndEntityCert::from(issuer).map_err(|_| Error::ReportParseError)?;
+        issuer
+            .verify_signature(&webpki::ECDSA_P256_SHA256, tbs_raw, signature)
+            .map_err(|e| {
+                warn!("CRL signature verification error {:?}", e);
+                Error::ReportValidationError
+            })?;
+
+        // The version is only there in v2 CRLs, then come the signature algorithm and the issuer
+        let mut fields = tbs;
+        if fields.first() == Some(&DER_INTEGER) {
+            fields = der_element(fields)?.2;
+        }
+        fields = der_expect(DER_SEQUENCE, fields)?.1;
+        fields = der_expect(DER_SEQUENCE, fields)?.1;
+
+        let (tag, this_update, rest) = der_element(fields)?;
+        let this_update = der_time_seconds(tag, this_update)?;
+        let (tag, next_update, rest) = der_element(rest)?;
+        let next_update = der_time_seconds(tag, next_update)?;
+        if let Some(now) = trusted_time {
+            check_current("CRL", this_update, next_update, now)?;
+        }
+
+        // There are no revoked certificates when the CRL goes on with its extensions or ends
+        let mut revoked_serials = vec![];
+        if rest.first() == Some(&DER_SEQUENCE) {
+            let (mut entries, _) = der_expect(DER_SEQUENCE, rest)?;
+            while !entries.is_empty() {
+                let (entry, next) = der_expect(DER_SEQUENCE, entries)?;
+                revoked_serials.push(der_expect(DER_INTEGER, entry)?.0);
+                entries = next;
+            }
+        }
+
+        Ok(Self { revoked_serials })
+    }
+
+    /// Fail if the DER certificate `cert` is revoked. The CRL must be the one of the CA that
+    /// issued `cert`, serial numbers are only unique per CA.
+    fn check_not_revoked(&self, cert: &[u8]) -> Result<(), Error> {
+        let serial = cert_serial(cert)?;
+        if self.revoked_serials.contains(&serial) {
+            warn!("A certificate of the DCAP collateral was revoked");
+            return Err(Error::ReportValidationError);
+        }
+
+        Ok(())
+    }
+}
+
+/// The serial number of the DER certificate `cert`, as the contents of its DER INTEGER
+fn cert_serial(cert: &[u8]) -> Result<&[u8], Error> {
+    let (cert, _) = der_expect(DER_SEQUENCE, cert)?;
+    let (tbs, _) = der_expect(DER_SEQUENCE, cert)?;
+    let (tag, _, rest) = der_element(tbs)?;
+    let fields = if tag == DER_CONTEXT_0 { rest } else { tbs };
+
+    Ok(der_expect(DER_INTEGER, fields)?.0)
+}
+
+/// Seconds since the epoch of a DER UTCTime or GeneralizedTime. Certificates and CRLs always write
+/// them in UTC and with seconds, e.g. 240501000000Z.
+fn der_time_seconds(tag: u8, value: &[u8]) -> Result<u64, Error> {
+    let (year, rest) = match (tag, value.len()) {
+        // RFC 5280 puts two digit years from 50 in the 1900s
+        (DER_UTC_TIME, 13) => {
+            let year = ascii_number(&value[..2])?;
+            (
+                if year < 50 { 2000 + year } else { 1900 + year },
+                &value[2..],
+            )
+        }
+        (DER_GENERALIZED_TIME, 15) => (ascii_number(&value[..4])?, &value[4..]),
+        _ => return Err(Error::ReportParseError),
+    };
+    if rest[10] != b'Z' {
+        return Err(Error::ReportParseError);
+    }
+
+    let time = chrono::NaiveDate::from_ymd_opt(
+        year as i32,
+        ascii_number(&rest[0..2])?,
+        ascii_number(&rest[2..4])?,
+    )
+    .and_then(|date| {
+        date.and_hms_opt(
+            ascii_number(&rest[4..6]).ok()?,
+            ascii_number(&rest[6..8]).ok()?,
+            ascii_number(&rest[8..10]).ok()?,
+        )
+    })
+    .ok_or(Error::ReportParseError)?;
+
+    u64::try_from(time.timestamp()).map_err(|_| Error::ReportParseError)
+}
+
+fn ascii_number(digits: &[u8]) -> Result<u32, Error> {
+    if !digits.iter().all(u8::is_ascii_digit) {
+        return Err(Error::ReportParseError);
+    }
+
+    Ok(digits
+        .iter()
+        .fold(0, |number, digit| number * 10 + u32::from(digit - b'0')))
+}
+
+/// The contents of the DER element at the start of `input` if it has the tag `tag`, and what
+/// follows it
+fn der_expect(tag: u8, input: &[u8]) -> Result<(&[u8], &[u8]), Error> {
+    let (actual_tag, contents, rest) = der_element(input)?;
+    if actual_tag != tag {
+        return Err(Error::ReportParseError);
+    }
+
+    Ok((contents, rest))
+}
+
+/// The tag and the contents of the DER element at the start of `input`, and what follows it
+fn der_element(input: &[u8]) -> Result<(u8, &[u8], &[u8]), Error> {
+    let (tag, rest) = input.split_first().ok_or(Error::ReportParseError)?;
+    let (first_len_byte, rest) = rest.split_first().ok_or(Error::ReportParseError)?;
+
+    let (len, rest) = if *first_len_byte < 0x80 {
+        (*first_len_byte as usize, rest)
+    } else {
+        let len_size = (*first_len_byte & 0x7F) as usize;
+        if len_size == 0 || len_size > 2 || rest.len() < len_size {
+            return Err(Error::ReportParseError);
+        }
+        let len = rest[..len_size]
+            .iter()
+            .fold(0, |len, byte| (len << 8) | *byte as usize);
+        (len, &rest[len_size..])
+    };
+
+    if rest.len() < len {
+        return Err(Error::ReportParseError);
+    }
+
+    Ok((*tag, &rest[..len], &rest[len..]))
+}
+
+/// A non-negative DER INTEGER that fits in 16 bits
+fn der_uint(input: &[u8]) -> Result<u16, Error> {
+    let (mut value, _) = der_expect(DER_INTEGER, input)?;
+    if value.first().map_or(true, |byte| *byte & 0x80 != 0) {
+        return Err(Error::ReportParseError);
+    }
+    if value.len() > 1 && value[0] == 0 {
+        value = &value[1..];
+    }
+    if value.len() > 2 {
+        return Err(Error::ReportParseError);
+    }
+
+    Ok(value
+        .iter()
+        .fold(0, |uint, byte| (uint << 8) | u16::from(*byte)))
+}
+
+/// The arcs of `oid` below the SGX extension OID, if it is below it
+fn sgx_extension_arcs(oid: &[u8]) -> Option<&[u8]> {
+    if oid.len() > SGX_EXTENSION_OID.len() && oid.starts_with(SGX_EXTENSION_OID) {
+        Some(&oid[SGX_EXTENSION_OID.len()..])
+    } else {
+        None
+    }
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use std::io::Read;
+    use std::untrusted::fs::File;
+
+    use super::*;
+
+    fn read_fixture(name: &str) -> Vec<u8> {
+        let mut fixture = vec![];
+        let mut f = File::open(format!(
+            "../wasmi-runtime/src/registration/fixtures/{}",
+            name
+        ))
+        .unwrap();
+        f.read_to_end(&mut fixture).unwrap();
+
+        fixture
+    }
+
+    /// A quote and its collateral, endorsed by the test root CA of `gen_dcap_fixtures.py`
+    fn endorsed_dcap_quote() -> EndorsedDcapQuote {
+        EndorsedDcapQuote {
+            quote: read_fixture("dcap_quote.bin"),
+            tcb_info: read_fixture("dcap_tcb_info.json"),
+            tcb_info_issuer_chain: read_fixture("dcap_tcb_info_issuer_chain.pem"),
+            root_ca_crl: read_fixture("dcap_root_ca_crl.der"),
+            pck_crl: read_fixture("dcap_pck_crl.der"),
+        }
+    }
+
+    fn test_root_ca() -> Vec<u8> {
+        read_fixture("dcap_test_root_ca.der")
+    }
+
+    /// 2024-05-10T00:00:00Z, while the collateral of the fixtures is current
+    const COLLATERAL_CURRENT: u64 = 1_715_299_200;
+    /// 2024-06-01T00:00:00Z, after the collateral of the fixtures was due to be updated
+    const COLLATERAL_STALE: u64 = 1_717_200_000;
+
+    pub fn test_dcap_quote_verify() {
+        let report = DcapReport::verify(
+            &endorsed_dcap_quote(),
+            &test_root_ca(),
+            Some(COLLATERAL_CURRENT),
+        )
+        .unwrap();
+
+        assert_eq!(report.sgx_quote_status, SgxQuoteStatus::SwHardeningNeeded);
+        assert_eq!(
+            report.advisory_ids.0,
+            vec!["INTEL-SA-00334".to_string(), "INTEL-SA-00615".to_string()]
+        );
+        assert_eq!(report.tcb_info_issue_date, "2024-05-01T00:00:00Z");
+
+        let registration_key: Vec<u8> = (0..32).collect();
+        assert_eq!(
+            report.isv_enclave_report.report_data[..32].to_vec(),
+            registration_key
+        );
+        assert_eq!(report.isv_enclave_report.mr_enclave, [0x42; 32]);
+    }
+
+    pub fn test_dcap_quote_tampered_report_body() {
+        let mut endorsed = endorsed_dcap_quote();
+        // The first byte of the report data of the enclave
+        endorsed.quote[QUOTE_HEADER_SIZE + 320] ^= 1;
+
+        let result = DcapReport::verify(&endorsed, &test_root_ca(), Some(COLLATERAL_CURRENT));
+        assert!(matches!(result, Err(Error::ReportValidationError)));
+    }
+
+    pub fn test_dcap_quote_tampered_tcb_info() {
+        let mut endorsed = endorsed_dcap_quote();
+        let tcb_info = String::from_utf8(endorsed.tcb_info).unwrap();
+        endorsed.tcb_info = tcb_info
+            .replacen("SWHardeningNeeded", "UpToDate", 1)
+            .into_bytes();
+
+        let result = DcapReport::verify(&endorsed, &test_root_ca(), Some(COLLATERAL_CURRENT));
+        assert!(matches!(result, Err(Error::ReportValidationError)));
+    }
+
+    pub fn test_dcap_quote_unknown_root_ca() {
+        let result = DcapReport::verify(
+            &endorsed_dcap_quote(),
+            &read_fixture("ias_root_ca_cert.der"),
+            Some(COLLATERAL_CURRENT),
+        );
+        assert!(matches!(result, Err(Error::ReportValidationError)));
+    }
+
+    pub fn test_dcap_quote_stale_collateral() {
+        let result = DcapReport::verify(
+            &endorsed_dcap_quote(),
+            &test_root_ca(),
+            Some(COLLATERAL_STALE),
+        );
+        assert!(matches!(result, Err(Error::ReportValidationError)));
+
+        // Collateral that isn't issued yet is no better
+        let result = DcapReport::verify(
+            &endorsed_dcap_quote(),
+            &test_root_ca(),
+            Some(COLLATERAL_CURRENT - 30 * 24 * 60 * 60),
+        );
+        assert!(matches!(result, Err(Error::ReportValidationError)));
+
+        // Without a trusted time, the collateral is taken at its issue date
+        let report = DcapReport::verify(&endorsed_dcap_quote(), &test_root_ca(), None).unwrap();
+        assert_eq!(report.sgx_quote_status, SgxQuoteStatus::SwHardeningNeeded);
+    }
+
+    pub fn test_dcap_collateral_time() {
+        // A registration is checked at the time of its block, whatever this node verified before
+        assert_eq!(
+            CollateralTime::BlockTime(COLLATERAL_CURRENT).trusted_time(),
+            Some(COLLATERAL_CURRENT)
+        );
+        assert_eq!(CollateralTime::IssueDate.trusted_time(), None);
+
+        let at_block_time = |time| {
+            DcapReport::verify(
+                &endorsed_dcap_quote(),
+                &test_root_ca(),
+                CollateralTime::BlockTime(time).trusted_time(),
+            )
+        };
+        assert!(at_block_time(COLLATERAL_CURRENT).is_ok());
+        assert!(matches!(
+            at_block_time(COLLATERAL_STALE),
+            Err(Error::ReportValidationError)
+        ));
+    }
+
+    pub fn test_dcap_quote_revoked_pck_cert() {
+        let mut endorsed = endorsed_dcap_quote();
+        endorsed.pck_crl = read_fixture("dcap_pck_crl_revoked.der");
+
+        let result = DcapReport::verify(&endorsed, &test_root_ca(), Some(COLLATERAL_CURRENT));
+        assert!(matches!(result, Err(Error::ReportValidationError)));
+    }
+
+    pub fn test_dcap_quote_crl_of_other_ca() {
+        // The CRL of the root CA doesn't say which PCK certificates were revoked
+        let mut endorsed = endorsed_dcap_quote();
+        endorsed.pck_crl = endorsed.root_ca_crl.clone();
+
+        let result = DcapReport::verify(&endorsed, &test_root_ca(), Some(COLLATERAL_CURRENT));
+        assert!(matches!(result, Err(Error::ReportValidationError)));
+    }
+
+    pub fn test_dcap_crl_parse() {
+        let crl = read_fixture("dcap_pck_crl_revoked.der");
+        let endorsed = endorsed_dcap_quote();
+        let quote = EcdsaQuote::parse(&endorsed.quote).unwrap();
+        let pck_cert_chain = parse_pem_chain(quote.pck_cert_chain).unwrap();
+
+        let crl = Crl::verify(&crl, &pck_cert_chain[1], None).unwrap();
+        assert_eq!(crl.revoked_serials.len(), 2);
+        assert_eq!(crl.revoked_serials[0], &[0x12, 0x34][..]);
+        assert_eq!(
+            crl.revoked_serials[1],
+            cert_serial(&pck_cert_chain[0]).unwrap()
+        );
+
+        assert_eq!(
+            der_time_seconds(DER_UTC_TIME, b"240510000000Z").unwrap(),
+            COLLATERAL_CURRENT
+        );
+        assert_eq!(
+            der_time_seconds(DER_GENERALIZED_TIME, b"20240510000000Z").unwrap(),
+            COLLATERAL_CURRENT
+        );
+        assert!(der_time_seconds(DER_UTC_TIME, b"2405100000Z").is_err());
+    }
+
+    pub fn test_dcap_quote_pck_extensions() {
+        let endorsed = endorsed_dcap_quote();
+        let quote = EcdsaQuote::parse(&endorsed.quote).unwrap();
+        let pck_cert_chain = parse_pem_chain(quote.pck_cert_chain).unwrap();
+        let pck = PckExtensions::from_cert(&pck_cert_chain[0]).unwrap();
+
+        assert_eq!(pck.fmspc, vec![0x00, 0x90, 0x6e, 0xd5, 0x00, 0x00]);
+        assert_eq!(pck.pce_id, vec![0x00, 0x00]);
+        assert_eq!(
+            pck.cpu_svn_components,
+            [5, 5, 2, 2, 3, 1, 0, 3, 0, 0, 0, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(pck.pce_svn, 11);
+
+        let (fmspc, ca) = platform_of_quote(&endorsed.quote).unwrap();
+        assert_eq!(fmspc, pck.fmspc);
+        assert_eq!(ca, "platform");
+    }
+}
@@ -0,0 +1,709 @@
+//! Taking a node out of service for good.
+//!
+//! Decommissioning wipes every key the enclave holds: the consensus seeds, every key derived from
+//! them, and the registration key. Their sealed copies are removed too, so a restart doesn't bring
+//! them back. From then on, every ecall that would use a key, run a contract, or set the node up
+//! again refuses to: those that return an `EnclaveError` fail with
+//! `EnclaveError::EnclaveNotInitialized`, and those that return an `sgx_status_t` fail with
+//! `SGX_ERROR_INVALID_STATE`. The ecalls that only report on the enclave keep answering, and the
+//! deep health check reports `SeedStatus::Decommissioned`.
+//!
+//! Only the operator the node was set up for may decommission it. Their ed25519 public key is
+//! pinned with `ecall_pin_decommission_operator`, which seals it next to the registration key. It
+//! can only be pinned before the node gets the consensus seed, and never replaced, so a host that
+//! gets hold of a running node can't put a key of its own in its place.
+//!
+//! It takes two ecalls, so that it can't happen by mistake:
+//! 1. `ecall_decommission_arm` gets the public key of the operator, which must be the pinned one,
+//!    and returns a random challenge and the public key of the erasure key of the node.
+//! 2. `ecall_decommission_confirm` gets the signature of the operator over the challenge, see
+//!    `decommission_sign_bytes`. Only then are the keys wiped, and the ecall returns an
+//!    `ErasureRecord` signed by the erasure key, for the operator to archive.
+//!
+//! Arming again replaces the challenge, and a confirmation that fails disarms the enclave, so every
+//! attempt needs a signature of its own. A malicious host can still delete the sealed seed, but it
+//! can't get the enclave to sign a record of an erasure the operator didn't ask for.
+//!
+//! The erasure key is derived from the registration key, so it doesn't change between attempts,
+//! and it is wiped with everything else once the record is signed. On hardware, the record is also
+//! attested: it comes with a DCAP quote of the enclave over its hash, so it can be checked against
+//! the measurement of the enclave without trusting the erasure key.
+
+use std::slice;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::SgxMutex;
+
+use lazy_static::lazy_static;
+use log::*;
+use ring::signature::{Ed25519KeyPair, KeyPair as _};
+use serde::Serialize;
+use sgx_types::sgx_status_t;
+
+use enclave_ffi_types::{EnclaveError, UserSpaceBuffer};
+
+use crate::consts::{
+    consensus_seed_activation_path, consensus_seed_sealing_path,
+    DECOMMISSION_OPERATOR_SEALING_PATH, ERASURE_KEY_DERIVE_ORDER, REGISTRATION_KEY_SEALING_PATH,
+};
+use crate::cosmwasm::encoding::Binary;
+#[cfg(feature = "SGX_MODE_HW")]
+use crate::crypto::sha_256;
+use crate::crypto::{
+    ed25519_verify, rand_slice, seal_operator_public_key, unseal_operator_public_key, AESKey, Kdf,
+    KeyGeneration, KeyPair, Keychain, KEY_MANAGER, PUBLIC_KEY_SIZE,
+};
+#[cfg(feature = "SGX_MODE_HW")]
+use crate::registration::attestation::create_dcap_quote;
+use crate::results::allocate_user_buffer;
+use crate::utils::{validate_const_ptr, validate_mut_ptr, validate_mut_slice};
+use crate::wasm;
+
+pub const CHALLENGE_SIZE: usize = 32;
+
+/// Prefixed to the challenge the operator signs, so a signature of the operator over anything
+/// else can't be presented as an authorization to decommission
+const DECOMMISSION_SIGN_PREFIX: &[u8] = b"secret-enclave-decommission";
+/// Prefixed to the erasure record the erasure key signs
+const ERASURE_RECORD_SIGN_PREFIX: &[u8] = b"secret-enclave-erasure-record";
+
+/// Set once the keys were wiped, and never cleared while the enclave runs
+static DECOMMISSIONED: AtomicBool = AtomicBool::new(false);
+
+lazy_static! {
+    /// The decommission that was armed and not confirmed yet
+    static ref ARMED: SgxMutex<Option<ArmedDecommission>> = SgxMutex::new(None);
+}
+
+struct ArmedDecommission {
+    operator_public_key: [u8; PUBLIC_KEY_SIZE],
+    challenge: [u8; CHALLENGE_SIZE],
+}
+
+/// What the operator needs to confirm an armed decommission
+#[derive(Debug)]
+pub struct Arming {
+    /// Signed by the operator to confirm, see `decommission_sign_bytes`
+    pub challenge: [u8; CHALLENGE_SIZE],
+    /// The key that will sign the erasure record
+    pub erasure_public_key: [u8; PUBLIC_KEY_SIZE],
+}
+
+/// What a decommissioned node attests to, as JSON
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct ErasureRecord {
+    /// The registration public key the node had, which identifies it on chain
+    pub registration_public_key: Binary,
+    pub operator_public_key: Binary,
+    /// The challenge the operator signed
+    pub challenge: Binary,
+    /// The generation of the last consensus seed the node had, so every seed up to it was wiped.
+    /// `None` if the node had no seed.
+    pub consensus_generation: Option<KeyGeneration>,
+    /// The sealed keys that were removed
+    pub removed_files: Vec<String>,
+}
+
+/// What `ecall_decommission_confirm` returns, as JSON
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct SignedErasureRecord {
+    /// The JSON `ErasureRecord`
+    pub record: Binary,
+    pub erasure_public_key: Binary,
+    /// The ed25519 signature of the erasure key over `ERASURE_RECORD_SIGN_PREFIX || record`
+    pub signature: Binary,
+    /// The JSON `EndorsedDcapQuote` of the enclave, with the SHA-256 of
+    /// `ERASURE_RECORD_SIGN_PREFIX || record` in its report data. `None` outside of hardware mode,
+    /// or if the platform couldn't create a quote once the keys were gone.
+    pub attestation: Option<Binary>,
+}
+
+/// Whether the keys of this node were wiped
+pub fn is_decommissioned() -> bool {
+    DECOMMISSIONED.load(Ordering::SeqCst)
+}
+
+/// Fail with `EnclaveError::EnclaveNotInitialized` once the keys of this node were wiped
+pub fn ensure_not_decommissioned() -> Result<(), EnclaveError> {
+    if is_decommissioned() {
+        warn!("The node was decommissioned, it has no keys left");
+        return Err(EnclaveError::EnclaveNotInitialized);
+    }
+
+    Ok(())
+}
+
+/// The bytes the operator signs to confirm the decommission armed with `challenge`
+pub fn decommission_sign_bytes(challenge: &[u8; CHALLENGE_SIZE]) -> Vec<u8> {
+    let mut sign_bytes = DECOMMISSION_SIGN_PREFIX.to_vec();
+    sign_bytes.extend_from_slice(challenge);
+    sign_bytes
+}
+
+/// The key that signs the erasure record of the node with `registration_key`
+fn erasure_key(registration_key: &KeyPair) -> Result<Ed25519KeyPair, sgx_status_t> {
+    let erasure_secret = AESKey::new_from_slice(registration_key.get_privkey())
+        .derive_key_from_this(&ERASURE_KEY_DERIVE_ORDER.to_be_bytes());
+
+    Ed25519KeyPair::from_seed_unchecked(erasure_secret.get()).map_err(|err| {
+        error!("Could not derive the erasure key: {:?}", err);
+        sgx_status_t::SGX_ERROR_UNEXPECTED
+    })
+}
+
+fn registration_key_of(keychain: &Keychain) -> Result<KeyPair, sgx_status_t> {
+    keychain.get_registration_key().map_err(|_err| {
+        error!("The node has no registration key, was it initialized?");
+        sgx_status_t::SGX_ERROR_INVALID_STATE
+    })
+}
+
+/// Pin `operator_public_key` as the key of the only operator who may decommission the node with
+/// `keychain`, by sealing it to `pin_path`. That is only possible before the node gets the
+/// consensus seed, and pinning another key than the one that is pinned fails.
+pub fn pin_operator(
+    keychain: &Keychain,
+    pin_path: &str,
+    operator_public_key: &[u8; PUBLIC_KEY_SIZE],
+) -> Result<(), sgx_status_t> {
+    if is_decommissioned() {
+        warn!("The node was already decommissioned");
+        return Err(sgx_status_t::SGX_ERROR_INVALID_STATE);
+    }
+
+    if let Ok(pinned) = unseal_operator_public_key(pin_path) {
+        if pinned == *operator_public_key {
+            return Ok(());
+        }
+        warn!("Another operator was already pinned, it can't be replaced");
+        return Err(sgx_status_t::SGX_ERROR_INVALID_STATE);
+    }
+    if keychain.is_consensus_seed_set() {
+        warn!("The operator can only be pinned before the node gets the consensus seed");
+        return Err(sgx_status_t::SGX_ERROR_INVALID_STATE);
+    }
+
+    seal_operator_public_key(operator_public_key, pin_path).map_err(|err| {
+        error!("Could not seal the key of the operator: {:?}", err);
+        sgx_status_t::SGX_ERROR_UNEXPECTED
+    })
+}
+
+/// Start decommissioning the node with `keychain`, replacing any decommission that was armed
+/// before. `operator_public_key` must be the one that was pinned to `pin_path`. Nothing is wiped
+/// until `confirm`.
+pub fn arm(
+    keychain: &Keychain,
+    pin_path: &str,
+    operator_public_key: &[u8; PUBLIC_KEY_SIZE],
+) -> Result<Arming, sgx_status_t> {
+    if is_decommissioned() {
+        warn!("The node was already decommissioned");
+        return Err(sgx_status_t::SGX_ERROR_INVALID_STATE);
+    }
+
+    let pinned = unseal_operator_public_key(pin_path).map_err(|_err| {
+        warn!("No operator was pinned when the node was set up, so it can't be decommissioned");
+        sgx_status_t::SGX_ERROR_INVALID_STATE
+    })?;
+    if pinned != *operator_public_key {
+        warn!("Decommissioning was armed with another key than that of the pinned operator");
+        return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+    }
+
+    let erasure_key = erasure_key(&registration_key_of(keychain)?)?;
+    let mut erasure_public_key = [0u8; PUBLIC_KEY_SIZE];
+    erasure_public_key.copy_from_slice(erasure_key.public_key().as_ref());
+
+    let mut challenge = [0u8; CHALLENGE_SIZE];
+    rand_slice(&mut challenge).map_err(|_err| {
+        error!("Could not generate a decommission challenge");
+        sgx_status_t::SGX_ERROR_UNEXPECTED
+    })?;
+
+    *ARMED.lock().unwrap() = Some(ArmedDecommission {
+        operator_public_key: *operator_public_key,
+        challenge,
+    });
+    warn!("Decommissioning was armed, it wipes every key of this node once it's confirmed");
+
+    Ok(Arming {
+        challenge,
+        erasure_public_key,
+    })
+}
+
+/// The sealed keys of the node with `keychain`, and the key of its operator, so the node can be set
+/// up again from scratch
+pub fn sealed_key_files(keychain: &Keychain) -> Vec<String> {
+    let mut files = vec![
+        REGISTRATION_KEY_SEALING_PATH.clone(),
+        DECOMMISSION_OPERATOR_SEALING_PATH.clone(),
+    ];
+    if keychain.is_consensus_seed_set() {
+        files.push(consensus_seed_sealing_path(0));
+        for generation in 1..=keychain.get_latest_generation() {
+            files.push(consensus_seed_sealing_path(generation));
+            files.push(consensus_seed_activation_path(generation));
+        }
+    }
+    files
+}
+
+/// Verify that the operator signed the armed challenge, then wipe every key of `keychain`, remove
+/// `sealed_files`, and mark the enclave as decommissioned
+pub fn confirm(
+    keychain: &Keychain,
+    sealed_files: &[String],
+    signature: &[u8],
+) -> Result<SignedErasureRecord, sgx_status_t> {
+    // Whatever happens, the challenge can't be used again
+    let armed = ARMED.lock().unwrap().take().ok_or_else(|| {
+        warn!("Got a decommission confirmation, but no decommission was armed");
+        sgx_status_t::SGX_ERROR_INVALID_STATE
+    })?;
+    if is_decommissioned() {
+        warn!("The node was already decommissioned");
+        return Err(sgx_status_t::SGX_ERROR_INVALID_STATE);
+    }
+
+    let sign_bytes = decommission_sign_bytes(&armed.challenge);
+    if let Err(err) = ed25519_verify(&sign_bytes, signature, &armed.operator_public_key) {
+        warn!(
+            "Failed to verify the signature of the operator over the decommission: {:?}",
+            err
+        );
+        return Err(sgx_status_t::SGX_ERROR_INVALID_SIGNATURE);
+    }
+
+    let registration_key = registration_key_of(keychain)?;
+    let erasure_key = erasure_key(&registration_key)?;
+    let consensus_generation = if keychain.is_consensus_seed_set() {
+        Some(keychain.get_consensus_generation())
+    } else {
+        None
+    };
+
+    // No ecall that starts from here on gets to use a key
+    DECOMMISSIONED.store(true, Ordering::SeqCst);
+    keychain.erase();
+    // Query results were encrypted with keys derived from the seed
+    wasm::set_query_cache_size(0);
+
+    let mut removed_files = vec![];
+    for path in sealed_files {
+        match std::sgxfs::remove(path) {
+            Ok(()) => removed_files.push(path.clone()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => error!("Could not remove the sealed key {}: {:?}", path, err),
+        }
+    }
+
+    let record = ErasureRecord {
+        registration_public_key: Binary(registration_key.get_pubkey().to_vec()),
+        operator_public_key: Binary(armed.operator_public_key.to_vec()),
+        challenge: Binary(armed.challenge.to_vec()),
+        consensus_generation,
+        removed_files,
+    };
+    let record = serde_json::to_vec(&record).map_err(|err| {
+        error!("Could not serialize the erasure record: {:?}", err);
+        sgx_status_t::SGX_ERROR_UNEXPECTED
+    })?;
+
+    let mut record_sign_bytes = ERASURE_RECORD_SIGN_PREFIX.to_vec();
+    record_sign_bytes.extend_from_slice(&record);
+    let signature = erasure_key.sign(&record_sign_bytes);
+    let attestation = attest_erasure_record(&record_sign_bytes);
+    warn!("The node was decommissioned, every key it had was wiped");
+
+    Ok(SignedErasureRecord {
+        record: Binary(record),
+        erasure_public_key: Binary(erasure_key.public_key().as_ref().to_vec()),
+        signature: Binary(signature.as_ref().to_vec()),
+        attestation,
+    })
+}
+
+/// A DCAP quote of this enclave with the hash of `record_sign_bytes` in its report data, as JSON.
+/// The keys are gone by now, so a quote that can't be created doesn't fail the decommission.
+#[cfg(feature = "SGX_MODE_HW")]
+fn attest_erasure_record(record_sign_bytes: &[u8]) -> Option<Binary> {
+    let quote = match create_dcap_quote(&sha_256(record_sign_bytes)) {
+        Ok(quote) => quote,
+        Err(err) => {
+            error!("Could not attest the erasure record: {:?}", err);
+            return None;
+        }
+    };
+    match serde_json::to_vec(&quote) {
+        Ok(quote) => Some(Binary(quote)),
+        Err(err) => {
+            error!(
+                "Could not serialize the attestation of the erasure record: {:?}",
+                err
+            );
+            None
+        }
+    }
+}
+
+#[cfg(not(feature = "SGX_MODE_HW"))]
+fn attest_erasure_record(_record_sign_bytes: &[u8]) -> Option<Binary> {
+    None
+}
+
+///
+/// `ecall_pin_decommission_operator`
+///
+/// Pin the ed25519 `operator_public_key` as that of the only operator who may decommission this
+/// node. It must be called while the node is set up, before it gets the consensus seed, see
+/// `pin_operator`.
+///
+/// # Safety
+/// `operator_public_key` is an `[in]` parameter of `PUBLIC_KEY_SIZE` bytes, so the edger8r bridge
+/// passes a copy of it in enclave memory. The pointer is checked to be valid for reading that many
+/// bytes before it's read, and it's never written.
+#[no_mangle]
+pub unsafe extern "C" fn ecall_pin_decommission_operator(
+    operator_public_key: *const u8,
+) -> sgx_status_t {
+    if let Err(_e) = ensure_not_decommissioned() {
+        return sgx_status_t::SGX_ERROR_INVALID_STATE;
+    }
+
+    if let Err(_e) = validate_const_ptr(operator_public_key, PUBLIC_KEY_SIZE) {
+        error!("Tried to access data outside enclave memory!");
+        return sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+    }
+    let mut operator_key = [0u8; PUBLIC_KEY_SIZE];
+    operator_key.copy_from_slice(slice::from_raw_parts(operator_public_key, PUBLIC_KEY_SIZE));
+
+    match pin_operator(
+        &KEY_MANAGER,
+        &DECOMMISSION_OPERATOR_SEALING_PATH,
+        &operator_key,
+    ) {
+        Ok(()) => sgx_status_t::SGX_SUCCESS,
+        Err(status) => status,
+    }
+}
+
+///
+/// `ecall_decommission_arm`
+///
+/// Arm the decommission of this node for the operator with the ed25519 `operator_public_key`, who
+/// must be the pinned operator. The challenge the operator has to sign, and the public key of the
+/// key that will sign the erasure record, are written to `challenge` and `erasure_public_key`.
+///
+/// # Safety
+/// `operator_public_key` is an `[in]` parameter of `PUBLIC_KEY_SIZE` bytes, and `challenge` and
+/// `erasure_public_key` are `[out]` parameters of `CHALLENGE_SIZE` and `PUBLIC_KEY_SIZE` bytes. The
+/// edger8r bridge passes all of them in enclave memory, and copies the outputs to the host when
+/// the ecall returns. Every pointer is checked to be valid for its size before it's used. The
+/// outputs are only written on success, and the input is never written.
+#[no_mangle]
+pub unsafe extern "C" fn ecall_decommission_arm(
+    operator_public_key: *const u8,
+    challenge: &mut [u8; CHALLENGE_SIZE],
+    erasure_public_key: &mut [u8; PUBLIC_KEY_SIZE],
+) -> sgx_status_t {
+    if let Err(_e) = ensure_not_decommissioned() {
+        return sgx_status_t::SGX_ERROR_INVALID_STATE;
+    }
+
+    if let Err(_e) = validate_const_ptr(operator_public_key, PUBLIC_KEY_SIZE) {
+        error!("Tried to access data outside enclave memory!");
+        return sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+    }
+    if validate_mut_slice(challenge).is_err() || validate_mut_slice(erasure_public_key).is_err() {
+        error!("Tried to access data outside enclave memory!");
+        return sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+    }
+    let mut operator_key = [0u8; PUBLIC_KEY_SIZE];
+    operator_key.copy_from_slice(slice::from_raw_parts(operator_public_key, PUBLIC_KEY_SIZE));
+
+    match arm(
+        &KEY_MANAGER,
+        &DECOMMISSION_OPERATOR_SEALING_PATH,
+        &operator_key,
+    ) {
+        Ok(arming) => {
+            *challenge = arming.challenge;
+            *erasure_public_key = arming.erasure_public_key;
+            sgx_status_t::SGX_SUCCESS
+        }
+        Err(status) => status,
+    }
+}
+
+///
+/// `ecall_decommission_confirm`
+///
+/// Confirm the armed decommission with the ed25519 `signature` of the operator over the bytes of
+/// `decommission_sign_bytes`, and wipe every key of this node. The JSON `SignedErasureRecord` is
+/// written to a buffer allocated outside the enclave.
+///
+/// # Safety
+/// `signature` is an `[in]` parameter of `signature_len` bytes, and `erasure_record` an `[out]`
+/// parameter that points to a `UserSpaceBuffer`. The edger8r bridge passes both in enclave memory,
+/// and copies `erasure_record` to the host when the ecall returns. Both pointers are checked to be
+/// valid for their size before they're used. `erasure_record` is only written on success, with a
+/// buffer allocated in user space, which the host has to free.
+#[no_mangle]
+pub unsafe extern "C" fn ecall_decommission_confirm(
+    signature: *const u8,
+    signature_len: u32,
+    erasure_record: *mut UserSpaceBuffer,
+) -> sgx_status_t {
+    if let Err(_e) = ensure_not_decommissioned() {
+        return sgx_status_t::SGX_ERROR_INVALID_STATE;
+    }
+
+    if let Err(_e) = validate_const_ptr(signature, signature_len as usize) {
+        error!("Tried to access data outside enclave memory!");
+        return sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+    }
+    if let Err(_e) = validate_mut_ptr(erasure_record as _, std::mem::size_of::<UserSpaceBuffer>()) {
+        error!("Tried to access data outside enclave memory!");
+        return sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+    }
+    let signature = slice::from_raw_parts(signature, signature_len as usize);
+
+    let sealed_files = sealed_key_files(&KEY_MANAGER);
+    let signed_record = match confirm(&KEY_MANAGER, &sealed_files, signature) {
+        Ok(signed_record) => signed_record,
+        Err(status) => return status,
+    };
+
+    // The keys are gone whether or not the record makes it out
+    let signed_record = match serde_json::to_vec(&signed_record) {
+        Ok(signed_record) => signed_record,
+        Err(err) => {
+            error!("Could not serialize the signed erasure record: {:?}", err);
+            return sgx_status_t::SGX_ERROR_UNEXPECTED;
+        }
+    };
+
+    match allocate_user_buffer(&signed_record) {
+        Ok(buffer) => {
+            *erasure_record = buffer;
+            sgx_status_t::SGX_SUCCESS
+        }
+        Err(err) => {
+            error!("Could not export the erasure record: {}", err);
+            sgx_status_t::SGX_ERROR_UNEXPECTED
+        }
+    }
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use std::sgxfs::SgxFile;
+
+    use crate::crypto::{SealedKey, Seed};
+
+    const SEALED_TEST_KEY: &str = "./.sgx_secrets/decommission_test_key.sealed";
+    const TEST_OPERATOR_PIN: &str = "./.sgx_secrets/decommission_test_operator.sealed";
+
+    fn operator_key() -> Ed25519KeyPair {
+        Ed25519KeyPair::from_seed_unchecked(&[7u8; 32]).unwrap()
+    }
+
+    fn operator_public_key() -> [u8; PUBLIC_KEY_SIZE] {
+        let mut public_key = [0u8; PUBLIC_KEY_SIZE];
+        public_key.copy_from_slice(operator_key().public_key().as_ref());
+        public_key
+    }
+
+    fn node_keychain() -> Keychain {
+        let registration_key = KeyPair::from(AESKey::new_from_slice(&[9u8; 32]));
+        Keychain::from_consensus_seeds(vec![Seed::new().unwrap()], Some(registration_key))
+    }
+
+    /// The keychain of the node before it got the consensus seed, when the operator is pinned
+    fn new_node_keychain() -> Keychain {
+        let registration_key = KeyPair::from(AESKey::new_from_slice(&[9u8; 32]));
+        Keychain::from_consensus_seeds(vec![], Some(registration_key))
+    }
+
+    /// Pin the operator of the test node, as if it was done when the node was set up
+    fn pin_test_operator() {
+        let _ = std::sgxfs::remove(TEST_OPERATOR_PIN);
+        pin_operator(
+            &new_node_keychain(),
+            TEST_OPERATOR_PIN,
+            &operator_public_key(),
+        )
+        .unwrap();
+    }
+
+    /// Let the enclave run again after a test decommissioned it
+    pub fn reset_decommission() {
+        DECOMMISSIONED.store(false, Ordering::SeqCst);
+        *ARMED.lock().unwrap() = None;
+    }
+
+    /// Arm and confirm the decommission of a throwaway node, which marks this enclave as
+    /// decommissioned without touching the keys it runs with. Undo it with `reset_decommission`.
+    pub fn decommission_throwaway_node() -> (Keychain, Arming, SignedErasureRecord) {
+        let keychain = node_keychain();
+        Seed::new().unwrap().seal(SEALED_TEST_KEY).unwrap();
+        pin_test_operator();
+
+        let arming = arm(&keychain, TEST_OPERATOR_PIN, &operator_public_key()).unwrap();
+        let signature = operator_key().sign(&decommission_sign_bytes(&arming.challenge));
+        let signed_record = confirm(
+            &keychain,
+            &[SEALED_TEST_KEY.to_string(), TEST_OPERATOR_PIN.to_string()],
+            signature.as_ref(),
+        )
+        .unwrap();
+
+        (keychain, arming, signed_record)
+    }
+
+    pub fn test_decommission_wipes_keys() {
+        let (keychain, arming, signed_record) = decommission_throwaway_node();
+        let decommissioned = is_decommissioned();
+        reset_decommission();
+
+        assert!(decommissioned);
+        assert!(!keychain.is_consensus_seed_set());
+        assert!(keychain.get_consensus_state_ikm().is_err());
+        assert!(keychain.get_all_consensus_state_ikms().is_empty());
+        assert!(keychain.get_consensus_io_exchange_keypair().is_err());
+        assert!(keychain.get_consensus_callback_secret().is_err());
+        assert!(keychain.get_consensus_signing_secret().is_err());
+        assert!(keychain.get_registration_key().is_err());
+        assert!(SgxFile::open(SEALED_TEST_KEY).is_err());
+        assert!(SgxFile::open(TEST_OPERATOR_PIN).is_err());
+
+        let expected_record = ErasureRecord {
+            registration_public_key: Binary(
+                KeyPair::from(AESKey::new_from_slice(&[9u8; 32]))
+                    .get_pubkey()
+                    .to_vec(),
+            ),
+            operator_public_key: Binary(operator_public_key().to_vec()),
+            challenge: Binary(arming.challenge.to_vec()),
+            consensus_generation: Some(0),
+            removed_files: vec![SEALED_TEST_KEY.to_string(), TEST_OPERATOR_PIN.to_string()],
+        };
+        assert_eq!(
+            signed_record.record.0,
+            serde_json::to_vec(&expected_record).unwrap()
+        );
+        assert_eq!(
+            signed_record.erasure_public_key.0,
+            arming.erasure_public_key.to_vec()
+        );
+
+        let mut record_sign_bytes = ERASURE_RECORD_SIGN_PREFIX.to_vec();
+        record_sign_bytes.extend_from_slice(&signed_record.record.0);
+        ed25519_verify(
+            &record_sign_bytes,
+            &signed_record.signature.0,
+            &arming.erasure_public_key,
+        )
+        .unwrap();
+        // Only hardware can attest to the record
+        assert_eq!(
+            signed_record.attestation.is_some(),
+            cfg!(feature = "SGX_MODE_HW")
+        );
+    }
+
+    pub fn test_decommission_needs_arming() {
+        let keychain = node_keychain();
+        let challenge = [0u8; CHALLENGE_SIZE];
+        let signature = operator_key().sign(&decommission_sign_bytes(&challenge));
+
+        let result = confirm(&keychain, &[], signature.as_ref());
+
+        assert_eq!(result, Err(sgx_status_t::SGX_ERROR_INVALID_STATE));
+        assert!(!is_decommissioned());
+        assert!(keychain.is_consensus_seed_set());
+    }
+
+    pub fn test_decommission_bad_signature() {
+        let keychain = node_keychain();
+        pin_test_operator();
+        let arming = arm(&keychain, TEST_OPERATOR_PIN, &operator_public_key()).unwrap();
+
+        // Signed by someone else
+        let outsider = Ed25519KeyPair::from_seed_unchecked(&[8u8; 32]).unwrap();
+        let signature = outsider.sign(&decommission_sign_bytes(&arming.challenge));
+        let result = confirm(&keychain, &[], signature.as_ref());
+        assert_eq!(result, Err(sgx_status_t::SGX_ERROR_INVALID_SIGNATURE));
+
+        // The failed confirmation disarmed it, so not even the right signature is accepted now
+        let signature = operator_key().sign(&decommission_sign_bytes(&arming.challenge));
+        let result = confirm(&keychain, &[], signature.as_ref());
+        assert_eq!(result, Err(sgx_status_t::SGX_ERROR_INVALID_STATE));
+
+        assert!(!is_decommissioned());
+        assert!(keychain.is_consensus_seed_set());
+        assert!(keychain.get_registration_key().is_ok());
+    }
+
+    pub fn test_decommission_challenge_of_earlier_arming() {
+        let keychain = node_keychain();
+        pin_test_operator();
+        let first = arm(&keychain, TEST_OPERATOR_PIN, &operator_public_key()).unwrap();
+        let second = arm(&keychain, TEST_OPERATOR_PIN, &operator_public_key()).unwrap();
+        assert_ne!(first.challenge, second.challenge);
+        assert_eq!(first.erasure_public_key, second.erasure_public_key);
+
+        let signature = operator_key().sign(&decommission_sign_bytes(&first.challenge));
+        let result = confirm(&keychain, &[], signature.as_ref());
+
+        assert_eq!(result, Err(sgx_status_t::SGX_ERROR_INVALID_SIGNATURE));
+        assert!(!is_decommissioned());
+    }
+
+    pub fn test_decommission_operator_is_pinned() {
+        let keychain = node_keychain();
+        let outsider = Ed25519KeyPair::from_seed_unchecked(&[8u8; 32]).unwrap();
+        let mut outsider_public_key = [0u8; PUBLIC_KEY_SIZE];
+        outsider_public_key.copy_from_slice(outsider.public_key().as_ref());
+        let _ = std::sgxfs::remove(TEST_OPERATOR_PIN);
+
+        // A node that no operator was pinned for can't be decommissioned
+        let result = arm(&keychain, TEST_OPERATOR_PIN, &operator_public_key());
+        assert_eq!(result.err(), Some(sgx_status_t::SGX_ERROR_INVALID_STATE));
+
+        // Nor can an operator be pinned once the node has the consensus seed
+        let result = pin_operator(&keychain, TEST_OPERATOR_PIN, &operator_public_key());
+        assert_eq!(result, Err(sgx_status_t::SGX_ERROR_INVALID_STATE));
+
+        pin_test_operator();
+        // Pinning the same operator again changes nothing, but no one may take their place
+        pin_operator(&keychain, TEST_OPERATOR_PIN, &operator_public_key()).unwrap();
+        let result = pin_operator(
+            &new_node_keychain(),
+            TEST_OPERATOR_PIN,
+            &outsider_public_key,
+        );
+        assert_eq!(result, Err(sgx_status_t::SGX_ERROR_INVALID_STATE));
+        assert_eq!(
+            unseal_operator_public_key(TEST_OPERATOR_PIN).unwrap(),
+            operator_public_key()
+        );
+
+        // The host can't arm the decommission with a key of its own
+        let result = arm(&keychain, TEST_OPERATOR_PIN, &outsider_public_key);
+        assert_eq!(
+            result.err(),
+            Some(sgx_status_t::SGX_ERROR_INVALID_PARAMETER)
+        );
+        let signature = outsider.sign(&decommission_sign_bytes(&[0u8; CHALLENGE_SIZE]));
+        let result = confirm(&keychain, &[], signature.as_ref());
+        assert_eq!(result, Err(sgx_status_t::SGX_ERROR_INVALID_STATE));
+
+        assert!(!is_decommissioned());
+        assert!(keychain.is_consensus_seed_set());
+        let _ = std::sgxfs::remove(TEST_OPERATOR_PIN);
+    }
+}
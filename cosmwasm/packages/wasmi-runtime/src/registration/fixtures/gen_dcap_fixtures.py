@@ -0,0 +1,305 @@
+#!/usr/bin/env python3
+"""
+Generates the DCAP fixtures of the registration tests: an ECDSA quote, as the quoting enclave of
+the DCAP libraries produces it, and the TCB info and CRL collateral that goes with it.
+
+Quotes of real platforms can only be verified against the Intel SGX root CA, and would tie the tests
+to the TCB info Intel published for that platform at the time. The quote here has the same format,
+but is endorsed by a chain under a test root CA (dcap_test_root_ca.der) that the tests pin instead.
+
+Run from this directory: python3 gen_dcap_fixtures.py
+"""
+import datetime
+import hashlib
+import json
+import struct
+
+from cryptography import x509
+from cryptography.hazmat.primitives import hashes, serialization
+from cryptography.hazmat.primitives.asymmetric import ec
+from cryptography.hazmat.primitives.asymmetric.utils import decode_dss_signature
+from cryptography.x509.oid import NameOID
+
+# The public key of the registering node, in the report data of the enclave
+REGISTRATION_PUBLIC_KEY = bytes(range(32))
+# MRSIGNER of the enclave, the same as consts::MRSIGNER
+MRSIGNER = bytes.fromhex("83d719e77deaca1470f6baf62a4d774303c899db69020f9c70ee1dfc08c7ce9e")
+MRENCLAVE = bytes([0x42] * 32)
+
+# The identity of the quoting enclave of Intel
+QE_MRSIGNER = bytes.fromhex("8c4f5775d796503e96137f77c68a829a0056ac8ded70140b081b094490c57bff")
+QE_VENDOR_ID = bytes.fromhex("939a7233f79c4ca9940a0db3957f0607")
+
+FMSPC = bytes.fromhex("00906ed50000")
+PCE_ID = bytes.fromhex("0000")
+# The TCB of the platform, as its PCK certificate states it
+CPU_SVN = bytes([5, 5, 2, 2, 3, 1, 0, 3, 0, 0, 0, 0, 0, 0, 0, 0])
+PCE_SVN = 11
+
+NOT_BEFORE = datetime.datetime(2020, 1, 1, tzinfo=datetime.timezone.utc)
+NOT_AFTER = datetime.datetime(2049, 12, 31, tzinfo=datetime.timezone.utc)
+# The collateral is current for the same 30 days as the TCB info
+COLLATERAL_ISSUE_DATE = datetime.datetime(2024, 5, 1, tzinfo=datetime.timezone.utc)
+COLLATERAL_NEXT_UPDATE = datetime.datetime(2024, 5, 31, tzinfo=datetime.timezone.utc)
+# A certificate that some CA revoked, so the CRLs aren't empty
+OTHER_REVOKED_SERIAL = 0x1234
+
+
+def der(tag, value):
+    if len(value) < 0x80:
+        length = bytes([len(value)])
+    elif len(value) < 0x100:
+        length = bytes([0x81, len(value)])
+    else:
+        length = bytes([0x82]) + struct.pack(">H", len(value))
+    return bytes([tag]) + length + value
+
+
+def der_oid(dotted):
+    arcs = [int(arc) for arc in dotted.split(".")]
+    encoded = bytes([40 * arcs[0] + arcs[1]])
+    for arc in arcs[2:]:
+        chunk = [arc & 0x7F]
+        arc >>= 7
+        while arc:
+            chunk.insert(0, 0x80 | (arc & 0x7F))
+            arc >>= 7
+        encoded += bytes(chunk)
+    return der(0x06, encoded)
+
+
+def der_int(value):
+    encoded = value.to_bytes(max(1, (value.bit_length() + 8) // 8), "big")
+    return der(0x02, encoded)
+
+
+SGX_EXTENSIONS = "1.2.840.113741.1.13.1"
+
+
+def sgx_extensions():
+    tcb = b"".join(
+        der(0x30, der_oid(f"{SGX_EXTENSIONS}.2.{i + 1}") + der_int(svn))
+        for i, svn in enumerate(CPU_SVN)
+    )
+    tcb += der(0x30, der_oid(f"{SGX_EXTENSIONS}.2.17") + der_int(PCE_SVN))
+    tcb += der(0x30, der_oid(f"{SGX_EXTENSIONS}.2.18") + der(0x04, CPU_SVN))
+    return der(
+        0x30,
+        der(0x30, der_oid(f"{SGX_EXTENSIONS}.1") + der(0x04, bytes([0xAA] * 16)))
+        + der(0x30, der_oid(f"{SGX_EXTENSIONS}.2") + der(0x30, tcb))
+        + der(0x30, der_oid(f"{SGX_EXTENSIONS}.3") + der(0x04, PCE_ID))
+        + der(0x30, der_oid(f"{SGX_EXTENSIONS}.4") + der(0x04, FMSPC))
+        + der(0x30, der_oid(f"{SGX_EXTENSIONS}.5") + der(0x0A, b"\x00")),
+    )
+
+
+def name(common_name):
+    return x509.Name(
+        [
+            x509.NameAttribute(NameOID.COMMON_NAME, common_name),
+            x509.NameAttribute(NameOID.ORGANIZATION_NAME, "Secret Network test fixtures"),
+        ]
+    )
+
+
+def certificate(subject, key, issuer, issuer_key, ca, path_length=None, extensions=()):
+    builder = (
+        x509.CertificateBuilder()
+        .subject_name(name(subject))
+        .issuer_name(name(issuer))
+        .public_key(key.public_key())
+        .serial_number(x509.random_serial_number())
+        .not_valid_before(NOT_BEFORE)
+        .not_valid_after(NOT_AFTER)
+        .add_extension(x509.BasicConstraints(ca=ca, path_length=path_length), critical=True)
+        .add_extension(
+            x509.KeyUsage(
+                digital_signature=not ca,
+                content_commitment=False,
+                key_encipherment=False,
+                data_encipherment=False,
+                key_agreement=False,
+                key_cert_sign=ca,
+                crl_sign=ca,
+                encipher_only=False,
+                decipher_only=False,
+            ),
+            critical=True,
+        )
+    )
+    for extension in extensions:
+        builder = builder.add_extension(extension, critical=False)
+    return builder.sign(issuer_key, hashes.SHA256())
+
+
+def crl(issuer, issuer_key, revoked_serials):
+    builder = (
+        x509.CertificateRevocationListBuilder()
+        .issuer_name(name(issuer))
+        .last_update(COLLATERAL_ISSUE_DATE)
+        .next_update(COLLATERAL_NEXT_UPDATE)
+    )
+    for serial in revoked_serials:
+        builder = builder.add_revoked_certificate(
+            x509.RevokedCertificateBuilder()
+            .serial_number(serial)
+            .revocation_date(COLLATERAL_ISSUE_DATE)
+            .build()
+        )
+    return builder.sign(issuer_key, hashes.SHA256()).public_bytes(serialization.Encoding.DER)
+
+
+def raw_signature(key, message):
+    r, s = decode_dss_signature(key.sign(message, ec.ECDSA(hashes.SHA256())))
+    return r.to_bytes(32, "big") + s.to_bytes(32, "big")
+
+
+def raw_public_key(key):
+    return key.public_key().public_bytes(
+        serialization.Encoding.X962, serialization.PublicFormat.UncompressedPoint
+    )[1:]
+
+
+def pem(cert):
+    return cert.public_bytes(serialization.Encoding.PEM)
+
+
+def report_body(cpu_svn, attributes, mr_enclave, mr_signer, isv_prod_id, isv_svn, report_data):
+    body = cpu_svn + struct.pack("<I", 0) + bytes(28) + attributes + mr_enclave + bytes(32)
+    body += mr_signer + bytes(96) + struct.pack("<HH", isv_prod_id, isv_svn) + bytes(60)
+    body += report_data
+    assert len(body) == 384
+    return body
+
+
+def main():
+    root_key = ec.generate_private_key(ec.SECP256R1())
+    root = certificate(
+        "Test SGX Root CA", root_key, "Test SGX Root CA", root_key, ca=True, path_length=1
+    )
+
+    platform_ca_key = ec.generate_private_key(ec.SECP256R1())
+    platform_ca = certificate(
+        "Test SGX PCK Platform CA",
+        platform_ca_key,
+        "Test SGX Root CA",
+        root_key,
+        ca=True,
+        path_length=0,
+    )
+
+    pck_key = ec.generate_private_key(ec.SECP256R1())
+    pck = certificate(
+        "Test SGX PCK Certificate",
+        pck_key,
+        "Test SGX PCK Platform CA",
+        platform_ca_key,
+        ca=False,
+        extensions=[
+            x509.UnrecognizedExtension(x509.ObjectIdentifier(SGX_EXTENSIONS), sgx_extensions())
+        ],
+    )
+
+    tcb_signing_key = ec.generate_private_key(ec.SECP256R1())
+    tcb_signing = certificate(
+        "Test SGX TCB Signing", tcb_signing_key, "Test SGX Root CA", root_key, ca=False
+    )
+
+    # The quote: the header, the report of the enclave, and the signature data
+    attestation_key = ec.generate_private_key(ec.SECP256R1())
+    header = struct.pack("<HHIHH", 3, 2, 0, 8, PCE_SVN) + QE_VENDOR_ID + bytes(20)
+    isv_report = report_body(
+        CPU_SVN,
+        bytes([0x07] + [0] * 7 + [0xE7] + [0] * 7),
+        MRENCLAVE,
+        MRSIGNER,
+        0,
+        0,
+        REGISTRATION_PUBLIC_KEY + bytes(32),
+    )
+
+    qe_auth_data = bytes(range(32))
+    qe_report = report_body(
+        CPU_SVN,
+        bytes([0x15] + [0] * 7 + [0xE7] + [0] * 7),
+        bytes([0x11] * 32),
+        QE_MRSIGNER,
+        1,
+        8,
+        hashlib.sha256(raw_public_key(attestation_key) + qe_auth_data).digest() + bytes(32),
+    )
+
+    certification_data = pem(pck) + pem(platform_ca) + pem(root)
+    signature_data = raw_signature(attestation_key, header + isv_report)
+    signature_data += raw_public_key(attestation_key)
+    signature_data += qe_report
+    signature_data += raw_signature(pck_key, qe_report)
+    signature_data += struct.pack("<H", len(qe_auth_data)) + qe_auth_data
+    signature_data += struct.pack("<HI", 5, len(certification_data)) + certification_data
+
+    quote = header + isv_report + struct.pack("<I", len(signature_data)) + signature_data
+
+    # The TCB info of the FMSPC of the platform, as the provisioning certification service returns
+    # it. The platform is below the first level, so its status is the one of the second level
+    def tcb_level(cpu_svn, pce_svn, date, status, advisories):
+        return {
+            "tcb": {
+                "sgxtcbcomponents": [{"svn": svn} for svn in cpu_svn],
+                "pcesvn": pce_svn,
+            },
+            "tcbDate": date,
+            "tcbStatus": status,
+            "advisoryIDs": advisories,
+        }
+
+    newer = bytes([7, 7, 2, 2, 3, 1, 0, 3, 0, 0, 0, 0, 0, 0, 0, 0])
+    tcb_info = {
+        "id": "SGX",
+        "version": 3,
+        "issueDate": "2024-05-01T00:00:00Z",
+        "nextUpdate": "2024-05-31T00:00:00Z",
+        "fmspc": FMSPC.hex(),
+        "pceId": PCE_ID.hex(),
+        "tcbType": 0,
+        "tcbEvaluationDataNumber": 16,
+        "tcbLevels": [
+            tcb_level(newer, 13, "2024-03-13T00:00:00Z", "UpToDate", []),
+            tcb_level(
+                CPU_SVN,
+                PCE_SVN,
+                "2023-08-09T00:00:00Z",
+                "SWHardeningNeeded",
+                ["INTEL-SA-00334", "INTEL-SA-00615"],
+            ),
+            tcb_level(bytes(16), 0, "2018-01-04T00:00:00Z", "OutOfDate", ["INTEL-SA-00161"]),
+        ],
+    }
+    tcb_info_body = json.dumps(tcb_info, separators=(",", ":")).encode()
+    signature = raw_signature(tcb_signing_key, tcb_info_body)
+    signed_tcb_info = b'{"tcbInfo":%s,"signature":"%s"}' % (tcb_info_body, signature.hex().encode())
+
+    with open("dcap_quote.bin", "wb") as f:
+        f.write(quote)
+    with open("dcap_tcb_info.json", "wb") as f:
+        f.write(signed_tcb_info)
+    with open("dcap_tcb_info_issuer_chain.pem", "wb") as f:
+        f.write(pem(tcb_signing) + pem(root))
+    with open("dcap_test_root_ca.der", "wb") as f:
+        f.write(root.public_bytes(serialization.Encoding.DER))
+    with open("dcap_root_ca_crl.der", "wb") as f:
+        f.write(crl("Test SGX Root CA", root_key, [OTHER_REVOKED_SERIAL]))
+    with open("dcap_pck_crl.der", "wb") as f:
+        f.write(crl("Test SGX PCK Platform CA", platform_ca_key, [OTHER_REVOKED_SERIAL]))
+    # The same CRL, after the PCK certificate of the platform was revoked
+    with open("dcap_pck_crl_revoked.der", "wb") as f:
+        f.write(
+            crl(
+                "Test SGX PCK Platform CA",
+                platform_ca_key,
+                [OTHER_REVOKED_SERIAL, pck.serial_number],
+            )
+        )
+
+
+if __name__ == "__main__":
+    main()
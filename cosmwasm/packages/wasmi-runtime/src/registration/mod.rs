@@ -1,15 +1,25 @@
 pub use attestation::create_attestation_certificate;
+pub use bundle::ecall_get_attestation_bundle;
+pub use cert::get_netscape_comment;
+pub use decommission::{
+    ecall_decommission_arm, ecall_decommission_confirm, ecall_pin_decommission_operator,
+};
 pub use offchain::{ecall_get_attestation_report, ecall_init_bootstrap, ecall_init_node};
 pub use onchain::ecall_authenticate_new_node;
+pub use seed_rotation::ecall_submit_new_seed;
 
 mod attestation;
+mod bundle;
 mod cert;
+mod dcap;
+pub mod decommission;
 mod hex;
 mod offchain;
 mod onchain;
 mod report;
 
 mod seed_exchange;
+mod seed_rotation;
 
 #[cfg(feature = "test")]
 pub mod tests {
@@ -26,6 +36,32 @@ pub mod tests {
             report::tests::test_attestation_report_from_cert_api_version_not_compatible();
             cert::tests::test_certificate_valid();
             cert::tests::test_certificate_invalid_configuration_needed();
+            dcap::tests::test_dcap_quote_verify();
+            dcap::tests::test_dcap_quote_tampered_report_body();
+            dcap::tests::test_dcap_quote_tampered_tcb_info();
+            dcap::tests::test_dcap_quote_unknown_root_ca();
+            dcap::tests::test_dcap_quote_pck_extensions();
+            dcap::tests::test_dcap_quote_stale_collateral();
+            dcap::tests::test_dcap_collateral_time();
+            dcap::tests::test_dcap_quote_revoked_pck_cert();
+            dcap::tests::test_dcap_quote_crl_of_other_ca();
+            dcap::tests::test_dcap_crl_parse();
+            bundle::tests::test_attestation_bundle_epid();
+            bundle::tests::test_attestation_bundle_dcap();
+            bundle::tests::test_attestation_bundle_software();
+            bundle::tests::test_attestation_bundle_unknown_cert();
+            seed_rotation::tests::test_rotate_seed();
+            seed_rotation::tests::test_rotate_seed_wrong_generation();
+            seed_rotation::tests::test_rotate_seed_bad_signature();
+            seed_rotation::tests::test_rotate_seed_unknown_quorum();
+            seed_rotation::tests::test_rotate_seed_resubmitted();
+            seed_rotation::tests::test_rotate_seed_without_seed_for_node();
+            seed_rotation::tests::test_seed_rotation_quorum_address();
+            decommission::tests::test_decommission_wipes_keys();
+            decommission::tests::test_decommission_needs_arming();
+            decommission::tests::test_decommission_bad_signature();
+            decommission::tests::test_decommission_challenge_of_earlier_arming();
+            decommission::tests::test_decommission_operator_is_pinned();
         });
 
         if failures != 0 {
@@ -17,6 +17,8 @@ use crate::consts::{
 };
 use crate::crypto::{Keychain, KEY_MANAGER, PUBLIC_KEY_SIZE};
 #[cfg(feature = "SGX_MODE_HW")]
+use crate::registration::dcap::DcapReport;
+#[cfg(feature = "SGX_MODE_HW")]
 use crate::registration::report::AttestationReport;
 use crate::storage::write_to_untrusted;
 use crate::utils::{attest_from_key, validate_const_ptr, validate_mut_ptr, validate_mut_slice};
@@ -25,6 +27,8 @@ use super::attestation::create_attestation_certificate;
 use super::cert::verify_ra_cert;
 #[cfg(feature = "SGX_MODE_HW")]
 use super::cert::{ocall_get_update_info, verify_quote_status};
+use super::dcap::CollateralTime;
+use super::decommission::ensure_not_decommissioned;
 use super::seed_exchange::decrypt_seed;
 
 ///
@@ -45,6 +49,10 @@ pub unsafe extern "C" fn ecall_init_bootstrap(
     api_key: *const u8,
     api_key_len: u32,
 ) -> sgx_status_t {
+    if let Err(_e) = ensure_not_decommissioned() {
+        return sgx_status_t::SGX_ERROR_INVALID_STATE;
+    }
+
     if let Err(_e) = validate_mut_ptr(public_key.as_mut_ptr(), public_key.len()) {
         return sgx_status_t::SGX_ERROR_UNEXPECTED;
     }
@@ -120,6 +128,10 @@ pub unsafe extern "C" fn ecall_init_node(
     encrypted_seed: *const u8,
     encrypted_seed_len: u32,
 ) -> sgx_status_t {
+    if let Err(_e) = ensure_not_decommissioned() {
+        return sgx_status_t::SGX_ERROR_INVALID_STATE;
+    }
+
     if let Err(_e) = validate_const_ptr(master_cert, master_cert_len as usize) {
         return sgx_status_t::SGX_ERROR_UNEXPECTED;
     }
@@ -146,8 +158,9 @@ pub unsafe extern "C" fn ecall_init_node(
     // public keys in certificates don't have 0x04, so we'll copy it here
     let mut target_public_key: [u8; PUBLIC_KEY_SIZE] = [0u8; PUBLIC_KEY_SIZE];
 
-    // validate certificate w/ attestation report
-    let pk = match verify_ra_cert(cert_slice) {
+    // validate certificate w/ attestation report. The master certificate comes from the genesis
+    // file, which is trusted, and the enclave didn't verify any block header yet
+    let pk = match verify_ra_cert(cert_slice, CollateralTime::IssueDate) {
         Err(e) => {
             error!("Error in validating certificate: {:?}", e);
             return sgx_status_t::SGX_ERROR_UNEXPECTED;
@@ -203,6 +216,10 @@ pub unsafe extern "C" fn ecall_get_attestation_report(
     api_key: *const u8,
     api_key_len: u32,
 ) -> sgx_status_t {
+    if let Err(_e) = ensure_not_decommissioned() {
+        return sgx_status_t::SGX_ERROR_INVALID_STATE;
+    }
+
     if let Err(_e) = validate_const_ptr(spid, spid_len as usize) {
         return sgx_status_t::SGX_ERROR_UNEXPECTED;
     }
@@ -248,6 +265,10 @@ pub unsafe extern "C" fn ecall_get_attestation_report(
 pub unsafe extern "C" fn ecall_key_gen(
     public_key: &mut [u8; PUBLIC_KEY_SIZE],
 ) -> sgx_types::sgx_status_t {
+    if let Err(_e) = ensure_not_decommissioned() {
+        return sgx_status_t::SGX_ERROR_INVALID_STATE;
+    }
+
     if let Err(_e) = validate_mut_slice(public_key) {
         return sgx_status_t::SGX_ERROR_UNEXPECTED;
     }
@@ -272,7 +293,7 @@ fn print_local_report_info(cert: &[u8]) {
     let report = match AttestationReport::from_cert(cert) {
         Ok(r) => r,
         Err(_) => {
-            error!("Error parsing report");
+            print_local_dcap_report_info(cert);
             return;
         }
     };
@@ -303,6 +324,25 @@ fn print_local_report_info(cert: &[u8]) {
     }
 }
 
+#[cfg(feature = "SGX_MODE_HW")]
+fn print_local_dcap_report_info(cert: &[u8]) {
+    let report = match DcapReport::from_cert(cert, CollateralTime::IssueDate) {
+        Ok(r) => r,
+        Err(_) => {
+            error!("Error parsing report");
+            return;
+        }
+    };
+
+    match verify_quote_status(&report.sgx_quote_status, &report.advisory_ids) {
+        Err(NodeAuthResult::OutOfDate) => {
+            println!("Platform status is OUT_OF_DATE. This means that one of the system components is missing a security update");
+        }
+        Err(status) => println!("Platform status is {}", status),
+        Ok(()) => println!("Platform Okay!"),
+    }
+}
+
 #[cfg(feature = "SGX_MODE_HW")]
 unsafe fn print_platform_info(report: &AttestationReport) {
     if let Some(platform_info) = &report.platform_info_blob {
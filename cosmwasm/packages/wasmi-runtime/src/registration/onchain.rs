@@ -14,6 +14,8 @@ use crate::{
 };
 
 use super::cert::verify_ra_cert;
+use super::dcap::CollateralTime;
+use super::decommission::ensure_not_decommissioned;
 use super::seed_exchange::encrypt_seed;
 
 ///
@@ -26,6 +28,9 @@ use super::seed_exchange::encrypt_seed;
 /// The seed is encrypted with a key derived from the secret master key of the chain, and the public
 /// key of the requesting chain
 ///
+/// `block_time` is the time of the block the registration is executed in, in seconds since the
+/// epoch. The collateral of a DCAP quote is checked at that time, which is the same on every node.
+///
 /// This function happens on-chain, so any panic here might cause the chain to go boom
 ///
 /// # Safety
@@ -34,8 +39,13 @@ use super::seed_exchange::encrypt_seed;
 pub unsafe extern "C" fn ecall_authenticate_new_node(
     cert: *const u8,
     cert_len: u32,
+    block_time: u64,
     seed: &mut [u8; ENCRYPTED_SEED_SIZE],
 ) -> NodeAuthResult {
+    if let Err(_e) = ensure_not_decommissioned() {
+        return NodeAuthResult::EnclaveNotInitialized;
+    }
+
     if let Err(_err) = oom_handler::register_oom_handler() {
         error!("Could not register OOM handler!");
         return NodeAuthResult::MemorySafetyAllocationError;
@@ -50,8 +60,10 @@ pub unsafe extern "C" fn ecall_authenticate_new_node(
     let cert_slice = std::slice::from_raw_parts(cert, cert_len as usize);
 
     let result = panic::catch_unwind(|| -> Result<Vec<u8>, NodeAuthResult> {
-        // verify certificate, and return the public key in the extra data of the report
-        let pk = verify_ra_cert(cert_slice)?;
+        // verify certificate, and return the public key in the extra data of the report. The
+        // collateral of a DCAP quote must be current on chain, or a node could register with
+        // collateral from before its platform was found vulnerable
+        let pk = verify_ra_cert(cert_slice, CollateralTime::BlockTime(block_time))?;
 
         // just make sure the length isn't wrong for some reason (certificate may be malformed)
         if pk.len() != PUBLIC_KEY_SIZE {
@@ -52,14 +52,14 @@ pub struct EndorsedAttestationReport {
     pub signing_cert: Vec<u8>,
 }
 
-fn as_base64<S>(key: &[u8], serializer: S) -> Result<S::Ok, S::Error>
+pub(super) fn as_base64<S>(key: &[u8], serializer: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
 {
     serializer.serialize_str(&base64::encode(&key[..]))
 }
 
-fn from_base64<'de, D>(deserializer: D) -> Result<Vec<u8>, D::Error>
+pub(super) fn from_base64<'de, D>(deserializer: D) -> Result<Vec<u8>, D::Error>
 where
     D: Deserializer<'de>,
 {
@@ -83,7 +83,7 @@ where
 }
 
 type SignatureAlgorithms = &'static [&'static webpki::SignatureAlgorithm];
-static SUPPORTED_SIG_ALGS: SignatureAlgorithms = &[
+pub(super) static SUPPORTED_SIG_ALGS: SignatureAlgorithms = &[
     &webpki::ECDSA_P256_SHA256,
     &webpki::ECDSA_P256_SHA384,
     &webpki::ECDSA_P384_SHA256,
@@ -341,6 +341,10 @@ impl From<&SgxQuoteStatus> for NodeAuthResult {
             }
             SgxQuoteStatus::ConfigurationNeeded => NodeAuthResult::ConfigurationNeeded,
             SgxQuoteStatus::GroupOutOfDate => NodeAuthResult::GroupOutOfDate,
+            SgxQuoteStatus::OutOfDate => NodeAuthResult::OutOfDate,
+            SgxQuoteStatus::OutOfDateConfigurationNeeded => {
+                NodeAuthResult::OutOfDateConfigurationNeeded
+            }
             SgxQuoteStatus::KeyRevoked => NodeAuthResult::KeyRevoked,
             SgxQuoteStatus::SigrlVersionMismatch => NodeAuthResult::SigrlVersionMismatch,
             SgxQuoteStatus::SignatureRevoked => NodeAuthResult::SignatureRevoked,
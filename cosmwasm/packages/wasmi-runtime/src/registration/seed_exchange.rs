@@ -1,5 +1,6 @@
 use log::*;
 use sgx_types::{sgx_status_t, SgxResult};
+use zeroize::Zeroizing;
 
 use crate::consts::ENCRYPTED_SEED_SIZE;
 use crate::crypto::{
@@ -69,7 +70,8 @@ pub fn decrypt_seed(
                     );
                     return Err(sgx_status_t::SGX_ERROR_UNEXPECTED);
                 }
-                r
+                // The decrypted seed is copied into `seed`, this copy is wiped
+                Zeroizing::new(r)
             }
             Err(_e) => return Err(sgx_status_t::SGX_ERROR_UNEXPECTED),
         });
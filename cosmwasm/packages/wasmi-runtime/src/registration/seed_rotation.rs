@@ -0,0 +1,432 @@
+//! Replacing the consensus seed of the network. The new seed is encrypted to the registration key
+//! of every node, the same way a new node receives the seed when it registers, and is only accepted
+//! with the signature of the quorum that governs rotations. Every node starts using it at the
+//! activation height the quorum signed, so they all switch keys at the same block.
+use log::*;
+use serde::Deserialize;
+use sgx_types::sgx_status_t;
+use std::slice;
+
+use crate::consts::{ENCRYPTED_SEED_SIZE, SEED_ROTATION_QUORUM_ADDRESS};
+use crate::cosmwasm::encoding::Binary;
+use crate::crypto::multisig::MultisigThresholdPubKey;
+use crate::crypto::traits::PubKey;
+use crate::crypto::{KeyGeneration, Keychain, KEY_MANAGER, PUBLIC_KEY_SIZE};
+use crate::utils::validate_const_ptr;
+
+use super::decommission::ensure_not_decommissioned;
+use super::seed_exchange::decrypt_seed;
+
+/// Prefixed to the signed bytes, so a signature of the quorum over anything else can't be
+/// presented as an approval of a rotation
+const SEED_ROTATION_SIGN_PREFIX: &[u8] = b"secret-consensus-seed-rotation";
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct SeedRotation {
+    /// The generation the new seed will have. Must follow the last one.
+    pub generation: KeyGeneration,
+    /// The first block height whose executions use the new seed
+    pub activation_height: u64,
+    /// The new seed, encrypted to the registration key of every node that has the current seed
+    pub encrypted_seeds: Vec<NodeSeed>,
+    pub quorum_pubkey: MultisigThresholdPubKey,
+    /// The signature of the quorum over the bytes returned by `seed_rotation_sign_bytes`
+    pub quorum_signature: Binary,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct NodeSeed {
+    /// The registration key of the node
+    pub node_public_key: Binary,
+    /// The new seed, encrypted to `node_public_key` by the seed exchange key of the current seed
+    pub encrypted_seed: Binary,
+}
+
+/// The bytes the quorum signs. The keys and seeds in `rotation` must have their fixed sizes, so
+/// the bytes of one rotation can't be read as another.
+pub fn seed_rotation_sign_bytes(rotation: &SeedRotation) -> Vec<u8> {
+    let mut sign_bytes = SEED_ROTATION_SIGN_PREFIX.to_vec();
+    sign_bytes.push(rotation.generation);
+    sign_bytes.extend_from_slice(&rotation.activation_height.to_be_bytes());
+    for node_seed in &rotation.encrypted_seeds {
+        sign_bytes.extend_from_slice(&node_seed.node_public_key.0);
+        sign_bytes.extend_from_slice(&node_seed.encrypted_seed.0);
+    }
+    sign_bytes
+}
+
+/// The address of the seed rotation quorum this enclave was built with, if it was built with a
+/// valid one
+pub fn seed_rotation_quorum_address() -> Option<Vec<u8>> {
+    parse_quorum_address(SEED_ROTATION_QUORUM_ADDRESS)
+}
+
+fn parse_quorum_address(quorum_address: Option<&str>) -> Option<Vec<u8>> {
+    let address = match hex::decode(quorum_address?) {
+        Ok(address) => address,
+        Err(err) => {
+            error!("SEED_ROTATION_QUORUM_ADDRESS is not hex: {:?}", err);
+            return None;
+        }
+    };
+    // No key has the all-zero address, so a quorum with it could never sign
+    if address.len() != 20 || address.iter().all(|byte| *byte == 0) {
+        error!("SEED_ROTATION_QUORUM_ADDRESS is not the address of a quorum");
+        return None;
+    }
+    Some(address)
+}
+
+/// Verify `rotation` and seal the seed it carries for this node, to be used from its activation
+/// height on. `quorum_address` is the address of the only quorum that may rotate the seed.
+pub fn rotate_seed(
+    key_manager: &Keychain,
+    rotation: &SeedRotation,
+    quorum_address: &[u8],
+) -> Result<KeyGeneration, sgx_status_t> {
+    if rotation.quorum_pubkey.get_address().as_slice() != quorum_address {
+        warn!("The seed rotation was not signed by the seed rotation quorum");
+        return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+    }
+
+    for node_seed in &rotation.encrypted_seeds {
+        if node_seed.node_public_key.len() != PUBLIC_KEY_SIZE
+            || node_seed.encrypted_seed.len() != ENCRYPTED_SEED_SIZE
+        {
+            warn!(
+                "Got a node key of {} bytes with an encrypted seed of {} bytes",
+                node_seed.node_public_key.len(),
+                node_seed.encrypted_seed.len()
+            );
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+    }
+
+    let sign_bytes = seed_rotation_sign_bytes(rotation);
+    if let Err(err) = rotation
+        .quorum_pubkey
+        .verify_bytes(&sign_bytes, &rotation.quorum_signature.0)
+    {
+        warn!(
+            "Failed to verify the signature of the seed rotation: {:?}",
+            err
+        );
+        return Err(sgx_status_t::SGX_ERROR_INVALID_SIGNATURE);
+    }
+
+    // The host submits the rotation until its activation height, and may do it again after a
+    // restart
+    let latest_generation = key_manager.get_latest_generation();
+    if rotation.generation <= latest_generation && rotation.generation > 0 {
+        info!(
+            "The consensus seed of generation {} was already sealed",
+            rotation.generation
+        );
+        return Ok(rotation.generation);
+    }
+    if latest_generation.checked_add(1) != Some(rotation.generation) {
+        warn!(
+            "Got a rotation to seed generation {} while the last generation is {}",
+            rotation.generation, latest_generation
+        );
+        return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+    }
+
+    let registration_key = key_manager.get_registration_key().map_err(|_err| {
+        error!("The node has no registration key, was it initialized?");
+        sgx_status_t::SGX_ERROR_UNEXPECTED
+    })?;
+    let node_seed = rotation
+        .encrypted_seeds
+        .iter()
+        .find(|node_seed| node_seed.node_public_key.0[..] == registration_key.get_pubkey()[..])
+        .ok_or_else(|| {
+            warn!("The seed rotation has no seed for this node, it must register again");
+            sgx_status_t::SGX_ERROR_INVALID_PARAMETER
+        })?;
+    let mut encrypted_seed = [0u8; ENCRYPTED_SEED_SIZE];
+    encrypted_seed.copy_from_slice(&node_seed.encrypted_seed.0);
+
+    let seed_exchange_key = key_manager.seed_exchange_key().map_err(|_err| {
+        error!("The node has no consensus seed, was it initialized?");
+        sgx_status_t::SGX_ERROR_UNEXPECTED
+    })?;
+    let seed = decrypt_seed(key_manager, seed_exchange_key.get_pubkey(), encrypted_seed)?;
+
+    key_manager
+        .rotate_consensus_seed(seed, rotation.activation_height)
+        .map_err(|_err| sgx_status_t::SGX_ERROR_UNEXPECTED)
+}
+
+///
+/// `ecall_submit_new_seed`
+///
+/// Seal the seed in `rotation`, a JSON `SeedRotation`, as the next consensus seed of this node.
+///
+/// The new seed is used by the executions of blocks from the activation height of the rotation
+/// on. The seeds before it stay sealed, so state written under them can still be read.
+///
+/// # Safety
+/// `rotation` is an `[in]` parameter of `rotation_len` bytes, so the edger8r bridge passes a copy
+/// of it in enclave memory. The pointer is checked to be valid for reading `rotation_len` bytes
+/// before it's read, and it's never written.
+#[no_mangle]
+pub unsafe extern "C" fn ecall_submit_new_seed(
+    rotation: *const u8,
+    rotation_len: u32,
+) -> sgx_status_t {
+    if let Err(_e) = ensure_not_decommissioned() {
+        return sgx_status_t::SGX_ERROR_INVALID_STATE;
+    }
+
+    if let Err(_e) = validate_const_ptr(rotation, rotation_len as usize) {
+        error!("Tried to access data outside enclave memory!");
+        return sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+    }
+    let rotation_slice = slice::from_raw_parts(rotation, rotation_len as usize);
+
+    let rotation: SeedRotation = match serde_json::from_slice(rotation_slice) {
+        Ok(rotation) => rotation,
+        Err(err) => {
+            warn!("Got a malformed seed rotation: {:?}", err);
+            return sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+        }
+    };
+
+    let quorum_address = match seed_rotation_quorum_address() {
+        Some(quorum_address) => quorum_address,
+        None => {
+            warn!("This enclave was built without a seed rotation quorum, it can't rotate seeds");
+            return sgx_status_t::SGX_ERROR_FEATURE_NOT_SUPPORTED;
+        }
+    };
+
+    match rotate_seed(&KEY_MANAGER, &rotation, &quorum_address) {
+        Ok(generation) => {
+            info!(
+                "Sealed the consensus seed of generation {}, used from height {}",
+                generation, rotation.activation_height
+            );
+            sgx_status_t::SGX_SUCCESS
+        }
+        Err(status) => status,
+    }
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use crate::consts::{consensus_seed_activation_path, consensus_seed_sealing_path};
+    use crate::cosmwasm::types::PubKeyKind;
+    use crate::crypto::secp256k1::Secp256k1PubKey;
+    use crate::crypto::{AESKey, KeyPair, SIVEncryptable, Seed};
+
+    fn seed(byte: u8) -> Seed {
+        let mut seed = Seed::default();
+        seed.as_mut().copy_from_slice(&[byte; 32]);
+        seed
+    }
+
+    fn node_keychain() -> Keychain {
+        let registration_key = KeyPair::from(AESKey::new_from_slice(&[9u8; 32]));
+        Keychain::from_consensus_seeds(vec![seed(1)], Some(registration_key))
+    }
+
+    /// Encrypt `new_seed` the way the node that generated it would
+    fn encrypt_seed_to(key_manager: &Keychain, new_seed: &Seed) -> Vec<u8> {
+        let registration_pubkey = key_manager.get_registration_key().unwrap().get_pubkey();
+        let shared_enc_key = key_manager
+            .seed_exchange_key()
+            .unwrap()
+            .diffie_hellman(&registration_pubkey);
+
+        AESKey::new_from_slice(&shared_enc_key)
+            .encrypt_siv(new_seed.as_slice(), Some(&[&registration_pubkey[..]]))
+            .unwrap()
+    }
+
+    /// A 1 of 1 multisig of a secp256k1 key, and its signature over `sign_bytes`
+    fn quorum_sign(secret: u8, sign_bytes: &[u8]) -> (MultisigThresholdPubKey, Vec<u8>) {
+        use sha2::{Digest, Sha256};
+
+        let secp = secp256k1::Secp256k1::new();
+        let secret_key = secp256k1::SecretKey::from_slice(&[secret; 32]).unwrap();
+        let public_key = secp256k1::PublicKey::from_secret_key(&secp, &secret_key);
+        let message = secp256k1::Message::from_slice(&Sha256::digest(sign_bytes)).unwrap();
+        let signature = secp.sign(&message, &secret_key).serialize_compact();
+
+        let quorum_pubkey = MultisigThresholdPubKey::new(
+            1,
+            vec![PubKeyKind::Secp256k1(Secp256k1PubKey::new(
+                public_key.serialize().to_vec(),
+            ))],
+        );
+
+        // The amino encoding of a multisig signature: 7 bytes of bit array, then the signatures
+        let mut quorum_signature = vec![0u8; 7];
+        quorum_signature.push(0x12);
+        quorum_signature.push(signature.len() as u8);
+        quorum_signature.extend_from_slice(&signature);
+
+        (quorum_pubkey, quorum_signature)
+    }
+
+    fn signed_rotation(
+        key_manager: &Keychain,
+        generation: KeyGeneration,
+        activation_height: u64,
+        new_seed: &Seed,
+    ) -> SeedRotation {
+        let mut rotation = SeedRotation {
+            generation,
+            activation_height,
+            encrypted_seeds: vec![
+                // Another node, whose seed this node can't decrypt
+                NodeSeed {
+                    node_public_key: Binary(vec![7u8; PUBLIC_KEY_SIZE]),
+                    encrypted_seed: Binary(vec![7u8; ENCRYPTED_SEED_SIZE]),
+                },
+                NodeSeed {
+                    node_public_key: Binary(
+                        key_manager
+                            .get_registration_key()
+                            .unwrap()
+                            .get_pubkey()
+                            .to_vec(),
+                    ),
+                    encrypted_seed: Binary(encrypt_seed_to(key_manager, new_seed)),
+                },
+            ],
+            quorum_pubkey: MultisigThresholdPubKey::new(1, vec![]),
+            quorum_signature: Binary(vec![]),
+        };
+        sign_rotation(&mut rotation);
+        rotation
+    }
+
+    fn sign_rotation(rotation: &mut SeedRotation) {
+        let (quorum_pubkey, quorum_signature) = quorum_sign(1, &seed_rotation_sign_bytes(rotation));
+        rotation.quorum_pubkey = quorum_pubkey;
+        rotation.quorum_signature = Binary(quorum_signature);
+    }
+
+    fn remove_sealed_seed(generation: KeyGeneration) {
+        let _ = std::sgxfs::remove(&consensus_seed_sealing_path(generation));
+        let _ = std::sgxfs::remove(&consensus_seed_activation_path(generation));
+    }
+
+    pub fn test_rotate_seed() {
+        let key_manager = node_keychain();
+        let state_ikm = key_manager.get_consensus_state_ikm().unwrap();
+        let signing_secret = key_manager.get_consensus_signing_secret().unwrap();
+
+        let rotation = signed_rotation(&key_manager, 1, 100, &seed(2));
+        let quorum_address = rotation.quorum_pubkey.get_address();
+        let result = rotate_seed(&key_manager, &rotation, quorum_address.as_slice());
+        remove_sealed_seed(1);
+        assert_eq!(result.unwrap(), 1);
+        assert_eq!(key_manager.get_latest_generation(), 1);
+
+        // The current seed is used until the activation height
+        key_manager.activate_generations(99);
+        assert_eq!(key_manager.get_consensus_generation(), 0);
+        assert_eq!(
+            key_manager.get_consensus_seed().unwrap().as_slice(),
+            seed(1).as_slice()
+        );
+
+        key_manager.activate_generations(100);
+        assert_eq!(key_manager.get_consensus_generation(), 1);
+        assert_eq!(
+            key_manager.get_consensus_seed().unwrap().as_slice(),
+            seed(2).as_slice()
+        );
+        // State written under the first seed can still be decrypted
+        assert_eq!(
+            key_manager.get_consensus_state_ikm_of(0).unwrap().get(),
+            state_ikm.get()
+        );
+        assert_ne!(
+            key_manager.get_consensus_state_ikm().unwrap().get(),
+            state_ikm.get()
+        );
+        // Contracts keep their signing keys
+        assert_eq!(
+            key_manager.get_consensus_signing_secret().unwrap().get(),
+            signing_secret.get()
+        );
+    }
+
+    pub fn test_rotate_seed_resubmitted() {
+        let key_manager = node_keychain();
+
+        let rotation = signed_rotation(&key_manager, 1, 100, &seed(2));
+        let quorum_address = rotation.quorum_pubkey.get_address();
+        let first = rotate_seed(&key_manager, &rotation, quorum_address.as_slice());
+        let second = rotate_seed(&key_manager, &rotation, quorum_address.as_slice());
+        remove_sealed_seed(1);
+
+        assert_eq!(first, Ok(1));
+        assert_eq!(second, Ok(1));
+        assert_eq!(key_manager.get_latest_generation(), 1);
+    }
+
+    pub fn test_rotate_seed_wrong_generation() {
+        let key_manager = node_keychain();
+
+        let rotation = signed_rotation(&key_manager, 2, 100, &seed(2));
+        let quorum_address = rotation.quorum_pubkey.get_address();
+        let result = rotate_seed(&key_manager, &rotation, quorum_address.as_slice());
+
+        assert_eq!(result, Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER));
+        assert_eq!(key_manager.get_latest_generation(), 0);
+    }
+
+    pub fn test_rotate_seed_bad_signature() {
+        let key_manager = node_keychain();
+
+        // The quorum signed another activation height than the one submitted
+        let mut rotation = signed_rotation(&key_manager, 1, 100, &seed(2));
+        rotation.activation_height = 50;
+        let quorum_address = rotation.quorum_pubkey.get_address();
+        let result = rotate_seed(&key_manager, &rotation, quorum_address.as_slice());
+
+        assert_eq!(result, Err(sgx_status_t::SGX_ERROR_INVALID_SIGNATURE));
+        assert_eq!(key_manager.get_latest_generation(), 0);
+    }
+
+    pub fn test_rotate_seed_unknown_quorum() {
+        let key_manager = node_keychain();
+
+        let rotation = signed_rotation(&key_manager, 1, 100, &seed(2));
+        let result = rotate_seed(&key_manager, &rotation, &[1u8; 20]);
+
+        assert_eq!(result, Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER));
+        assert_eq!(key_manager.get_latest_generation(), 0);
+    }
+
+    pub fn test_rotate_seed_without_seed_for_node() {
+        let key_manager = node_keychain();
+
+        let mut rotation = signed_rotation(&key_manager, 1, 100, &seed(2));
+        rotation.encrypted_seeds.pop();
+        sign_rotation(&mut rotation);
+        let quorum_address = rotation.quorum_pubkey.get_address();
+        let result = rotate_seed(&key_manager, &rotation, quorum_address.as_slice());
+
+        assert_eq!(result, Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER));
+        assert_eq!(key_manager.get_latest_generation(), 0);
+    }
+
+    pub fn test_seed_rotation_quorum_address() {
+        assert_eq!(parse_quorum_address(None), None);
+        assert_eq!(parse_quorum_address(Some(&"00".repeat(20))), None);
+        assert_eq!(parse_quorum_address(Some("not hex")), None);
+        assert_eq!(parse_quorum_address(Some(&"ab".repeat(19))), None);
+        assert_eq!(
+            parse_quorum_address(Some(&"ab".repeat(20))),
+            Some(vec![0xab; 20])
+        );
+    }
+}
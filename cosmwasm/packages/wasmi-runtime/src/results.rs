@@ -1,44 +1,122 @@
 use enclave_ffi_types::{
-    EnclaveError, HandleResult, InitResult, QueryResult, UntrustedVmError, UserSpaceBuffer,
+    EnclaveError, ExportContractStateResult, GasBreakdown, HandleResult, IbcReceiveResult,
+    IbcResult, ImportContractStateResult, InitResult, MigrateResult, QueryResult,
+    RotateContractKeyResult, UntrustedVmError, UpdateAdminResult, UserSpaceBuffer,
+    ValidateWasmResult,
 };
 use sgx_types::sgx_status_t;
 
 use crate::imports::ocall_allocate;
 
+/// This type represents the ways a call into a contract can fail.
+pub enum CallError {
+    /// The contract returned an error. It is already encrypted to the sender of the tx.
+    Contract(Vec<u8>),
+    /// Any other failure, including contract panics and VM traps. These stay opaque.
+    Enclave(EnclaveError),
+}
+
+impl From<EnclaveError> for CallError {
+    fn from(err: EnclaveError) -> Self {
+        CallError::Enclave(err)
+    }
+}
+
+/// Copy `output` to a buffer in user space, so it can be returned from an ecall.
+pub fn allocate_user_buffer(output: &[u8]) -> Result<UserSpaceBuffer, EnclaveError> {
+    unsafe {
+        let mut user_buffer = std::mem::MaybeUninit::<UserSpaceBuffer>::uninit();
+        match ocall_allocate(user_buffer.as_mut_ptr(), output.as_ptr(), output.len()) {
+            sgx_status_t::SGX_SUCCESS => Ok(user_buffer.assume_init()),
+            _ => Err(EnclaveError::FailedOcall {
+                vm_error: UntrustedVmError::default(),
+            }),
+        }
+    }
+}
+
+/// The result types the ecalls return to the host.
+///
+/// The host can't read memory in the enclave, so building a result copies its outputs to user
+/// space. Whatever fails on the way, including that copy, is returned as the `Failure` variant.
+pub trait IntoEnclaveResult: Sized {
+    /// What the operation behind the ecall returns when it succeeds.
+    type Success;
+    /// What the operation behind the ecall returns when it fails.
+    type Error: From<EnclaveError>;
+
+    /// The result of an operation that succeeded.
+    fn success(success: Self::Success) -> Result<Self, EnclaveError>;
+
+    /// The result of an operation that failed.
+    fn error(err: Self::Error) -> Self;
+
+    /// The result of an ecall that failed in the enclave.
+    fn failure(err: EnclaveError) -> Self;
+
+    /// The error of the `Failure` variant, if the result is one.
+    fn failure_err(&self) -> Option<&EnclaveError>;
+
+    /// The result of the ecall, given what the operation behind it returned.
+    fn from_result(result: Result<Self::Success, Self::Error>) -> Self {
+        match result {
+            Ok(success) => Self::success(success).unwrap_or_else(Self::failure),
+            Err(err) => Self::error(err),
+        }
+    }
+}
+
+/// The result of a call into a contract that failed. A contract error is returned to the host as
+/// `contract_error`, and anything else as the `Failure` variant of `R`.
+fn call_error_to_result<R, F>(err: CallError, contract_error: F) -> R
+where
+    R: IntoEnclaveResult,
+    F: FnOnce(UserSpaceBuffer) -> R,
+{
+    match err {
+        CallError::Contract(output) => match allocate_user_buffer(&output) {
+            Ok(user_buffer) => contract_error(user_buffer),
+            Err(err) => R::failure(err),
+        },
+        CallError::Enclave(err) => R::failure(err),
+    }
+}
+
 /// This struct is returned from module initialization.
 pub struct InitSuccess {
     /// The output of the calculation
     pub output: Vec<u8>,
     /// The contract_key of this contract.
     pub contract_key: [u8; 64],
+    /// Authenticates the admin of this contract, see `generate_admin_proof`.
+    pub admin_proof: [u8; 32],
 }
 
-pub fn result_init_success_to_initresult(result: Result<InitSuccess, EnclaveError>) -> InitResult {
-    match result {
-        Ok(InitSuccess {
-            output,
-            contract_key,
-        }) => {
-            let user_buffer = unsafe {
-                let mut user_buffer = std::mem::MaybeUninit::<UserSpaceBuffer>::uninit();
-                match ocall_allocate(user_buffer.as_mut_ptr(), output.as_ptr(), output.len()) {
-                    sgx_status_t::SGX_SUCCESS => { /* continue */ }
-                    _ => {
-                        return InitResult::Failure {
-                            err: EnclaveError::FailedOcall {
-                                vm_error: UntrustedVmError::default(),
-                            },
-                        }
-                    }
-                }
-                user_buffer.assume_init()
-            };
-            InitResult::Success {
-                output: user_buffer,
-                contract_key,
-            }
+impl IntoEnclaveResult for InitResult {
+    type Success = InitSuccess;
+    type Error = CallError;
+
+    fn success(success: InitSuccess) -> Result<Self, EnclaveError> {
+        Ok(InitResult::Success {
+            output: allocate_user_buffer(&success.output)?,
+            contract_key: success.contract_key,
+            admin_proof: success.admin_proof,
+        })
+    }
+
+    fn error(err: CallError) -> Self {
+        call_error_to_result(err, |output| InitResult::ContractError { output })
+    }
+
+    fn failure(err: EnclaveError) -> Self {
+        InitResult::Failure { err }
+    }
+
+    fn failure_err(&self) -> Option<&EnclaveError> {
+        match self {
+            InitResult::Failure { err } => Some(err),
+            _ => None,
         }
-        Err(err) => InitResult::Failure { err },
     }
 }
 
@@ -46,32 +124,128 @@ pub fn result_init_success_to_initresult(result: Result<InitSuccess, EnclaveErro
 pub struct HandleSuccess {
     /// The output of the calculation
     pub output: Vec<u8>,
+    /// Where the gas used by the calculation was spent
+    pub gas_breakdown: GasBreakdown,
 }
 
-pub fn result_handle_success_to_handleresult(
-    result: Result<HandleSuccess, EnclaveError>,
-) -> HandleResult {
-    match result {
-        Ok(HandleSuccess { output }) => {
-            let user_buffer = unsafe {
-                let mut user_buffer = std::mem::MaybeUninit::<UserSpaceBuffer>::uninit();
-                match ocall_allocate(user_buffer.as_mut_ptr(), output.as_ptr(), output.len()) {
-                    sgx_status_t::SGX_SUCCESS => { /* continue */ }
-                    _ => {
-                        return HandleResult::Failure {
-                            err: EnclaveError::FailedOcall {
-                                vm_error: UntrustedVmError::default(),
-                            },
-                        }
-                    }
-                }
-                user_buffer.assume_init()
-            };
-            HandleResult::Success {
-                output: user_buffer,
-            }
+impl IntoEnclaveResult for HandleResult {
+    type Success = HandleSuccess;
+    type Error = CallError;
+
+    fn success(success: HandleSuccess) -> Result<Self, EnclaveError> {
+        Ok(HandleResult::Success {
+            output: allocate_user_buffer(&success.output)?,
+            gas_breakdown: success.gas_breakdown,
+        })
+    }
+
+    fn error(err: CallError) -> Self {
+        call_error_to_result(err, |output| HandleResult::ContractError { output })
+    }
+
+    fn failure(err: EnclaveError) -> Self {
+        HandleResult::Failure {
+            err,
+            debug_info: trap_debug_info(),
+        }
+    }
+
+    fn failure_err(&self) -> Option<&EnclaveError> {
+        match self {
+            HandleResult::Failure { err, .. } => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// The debug info of the trap that stopped the contract, in user space. Null if the contract
+/// didn't trap, or the enclave wasn't built to report traps.
+#[cfg(feature = "debug-print")]
+fn trap_debug_info() -> UserSpaceBuffer {
+    crate::wasm::take_trap_debug_info()
+        .and_then(|debug_info| allocate_user_buffer(debug_info.as_bytes()).ok())
+        .unwrap_or(UserSpaceBuffer {
+            ptr: std::ptr::null_mut(),
+        })
+}
+
+#[cfg(not(feature = "debug-print"))]
+fn trap_debug_info() -> UserSpaceBuffer {
+    UserSpaceBuffer {
+        ptr: std::ptr::null_mut(),
+    }
+}
+
+/// This struct is returned from the IBC entry points, except for `ibc_packet_receive`.
+pub struct IbcSuccess {
+    /// The output of the calculation
+    pub output: Vec<u8>,
+    /// Where the gas used by the calculation was spent
+    pub gas_breakdown: GasBreakdown,
+}
+
+impl IntoEnclaveResult for IbcResult {
+    type Success = IbcSuccess;
+    type Error = CallError;
+
+    fn success(success: IbcSuccess) -> Result<Self, EnclaveError> {
+        Ok(IbcResult::Success {
+            output: allocate_user_buffer(&success.output)?,
+            gas_breakdown: success.gas_breakdown,
+        })
+    }
+
+    fn error(err: CallError) -> Self {
+        call_error_to_result(err, |output| IbcResult::ContractError { output })
+    }
+
+    fn failure(err: EnclaveError) -> Self {
+        IbcResult::Failure { err }
+    }
+
+    fn failure_err(&self) -> Option<&EnclaveError> {
+        match self {
+            IbcResult::Failure { err } => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// This struct is returned from `ibc_packet_receive`.
+pub struct IbcReceiveSuccess {
+    /// The output of the calculation
+    pub output: Vec<u8>,
+    /// The acknowledgement to write for the packet
+    pub acknowledgement: Vec<u8>,
+    /// Where the gas used by the calculation was spent
+    pub gas_breakdown: GasBreakdown,
+}
+
+impl IntoEnclaveResult for IbcReceiveResult {
+    type Success = IbcReceiveSuccess;
+    type Error = CallError;
+
+    fn success(success: IbcReceiveSuccess) -> Result<Self, EnclaveError> {
+        Ok(IbcReceiveResult::Success {
+            output: allocate_user_buffer(&success.output)?,
+            acknowledgement: allocate_user_buffer(&success.acknowledgement)?,
+            gas_breakdown: success.gas_breakdown,
+        })
+    }
+
+    fn error(err: CallError) -> Self {
+        call_error_to_result(err, |output| IbcReceiveResult::ContractError { output })
+    }
+
+    fn failure(err: EnclaveError) -> Self {
+        IbcReceiveResult::Failure { err }
+    }
+
+    fn failure_err(&self) -> Option<&EnclaveError> {
+        match self {
+            IbcReceiveResult::Failure { err } => Some(err),
+            _ => None,
         }
-        Err(err) => HandleResult::Failure { err },
     }
 }
 
@@ -81,29 +255,383 @@ pub struct QuerySuccess {
     pub output: Vec<u8>,
 }
 
-pub fn result_query_success_to_queryresult(
-    result: Result<QuerySuccess, EnclaveError>,
-) -> QueryResult {
-    match result {
-        Ok(QuerySuccess { output }) => {
-            let user_buffer = unsafe {
-                let mut user_buffer = std::mem::MaybeUninit::<UserSpaceBuffer>::uninit();
-                match ocall_allocate(user_buffer.as_mut_ptr(), output.as_ptr(), output.len()) {
-                    sgx_status_t::SGX_SUCCESS => { /* continue */ }
-                    _ => {
-                        return QueryResult::Failure {
-                            err: EnclaveError::FailedOcall {
-                                vm_error: UntrustedVmError::default(),
-                            },
+impl IntoEnclaveResult for QueryResult {
+    type Success = QuerySuccess;
+    type Error = CallError;
+
+    fn success(success: QuerySuccess) -> Result<Self, EnclaveError> {
+        Ok(QueryResult::Success {
+            output: allocate_user_buffer(&success.output)?,
+        })
+    }
+
+    fn error(err: CallError) -> Self {
+        call_error_to_result(err, |output| QueryResult::ContractError { output })
+    }
+
+    fn failure(err: EnclaveError) -> Self {
+        QueryResult::Failure { err }
+    }
+
+    fn failure_err(&self) -> Option<&EnclaveError> {
+        match self {
+            QueryResult::Failure { err } => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// This struct is returned from a migrate method.
+pub struct MigrateSuccess {
+    /// The output of the calculation
+    pub output: Vec<u8>,
+    /// The contract_key of this contract after the migration.
+    pub new_contract_key: [u8; 64],
+}
+
+impl IntoEnclaveResult for MigrateResult {
+    type Success = MigrateSuccess;
+    type Error = CallError;
+
+    fn success(success: MigrateSuccess) -> Result<Self, EnclaveError> {
+        Ok(MigrateResult::Success {
+            output: allocate_user_buffer(&success.output)?,
+            new_contract_key: success.new_contract_key,
+        })
+    }
+
+    fn error(err: CallError) -> Self {
+        call_error_to_result(err, |output| MigrateResult::ContractError { output })
+    }
+
+    fn failure(err: EnclaveError) -> Self {
+        MigrateResult::Failure { err }
+    }
+
+    fn failure_err(&self) -> Option<&EnclaveError> {
+        match self {
+            MigrateResult::Failure { err } => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// This struct is returned from a contract key rotation.
+pub struct RotateContractKeySuccess {
+    /// The contract_key of this contract after the rotation.
+    pub new_contract_key: [u8; 64],
+    /// The storage key the next chunk of the rotation starts from, empty if it's done.
+    pub next_cursor: Vec<u8>,
+}
+
+impl IntoEnclaveResult for RotateContractKeyResult {
+    type Success = RotateContractKeySuccess;
+    type Error = EnclaveError;
+
+    fn success(success: RotateContractKeySuccess) -> Result<Self, EnclaveError> {
+        Ok(RotateContractKeyResult::Success {
+            new_contract_key: success.new_contract_key,
+            next_cursor: allocate_user_buffer(&success.next_cursor)?,
+        })
+    }
+
+    fn error(err: EnclaveError) -> Self {
+        RotateContractKeyResult::Failure { err }
+    }
+
+    fn failure(err: EnclaveError) -> Self {
+        RotateContractKeyResult::Failure { err }
+    }
+
+    fn failure_err(&self) -> Option<&EnclaveError> {
+        match self {
+            RotateContractKeyResult::Failure { err } => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// This struct is returned from an export of the contract storage.
+pub struct ExportContractStateSuccess {
+    /// The nonce of the snapshot the chunk is a part of.
+    pub snapshot_nonce: [u8; 32],
+    /// The chunk of the archive, as JSON.
+    pub chunk: Vec<u8>,
+    /// The storage key the next chunk of the export starts from, empty if it's done.
+    pub next_cursor: Vec<u8>,
+}
+
+impl IntoEnclaveResult for ExportContractStateResult {
+    type Success = ExportContractStateSuccess;
+    type Error = EnclaveError;
+
+    fn success(success: ExportContractStateSuccess) -> Result<Self, EnclaveError> {
+        Ok(ExportContractStateResult::Success {
+            snapshot_nonce: success.snapshot_nonce,
+            chunk: allocate_user_buffer(&success.chunk)?,
+            next_cursor: allocate_user_buffer(&success.next_cursor)?,
+        })
+    }
+
+    fn error(err: EnclaveError) -> Self {
+        ExportContractStateResult::Failure { err }
+    }
+
+    fn failure(err: EnclaveError) -> Self {
+        ExportContractStateResult::Failure { err }
+    }
+
+    fn failure_err(&self) -> Option<&EnclaveError> {
+        match self {
+            ExportContractStateResult::Failure { err } => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// An import succeeds with the number of entries it wrote to the contract storage.
+impl IntoEnclaveResult for ImportContractStateResult {
+    type Success = u32;
+    type Error = EnclaveError;
+
+    fn success(imported_entries: u32) -> Result<Self, EnclaveError> {
+        Ok(ImportContractStateResult::Success { imported_entries })
+    }
+
+    fn error(err: EnclaveError) -> Self {
+        ImportContractStateResult::Failure { err }
+    }
+
+    fn failure(err: EnclaveError) -> Self {
+        ImportContractStateResult::Failure { err }
+    }
+
+    fn failure_err(&self) -> Option<&EnclaveError> {
+        match self {
+            ImportContractStateResult::Failure { err } => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// An admin update succeeds with the proof of the new admin of the contract.
+impl IntoEnclaveResult for UpdateAdminResult {
+    type Success = [u8; 32];
+    type Error = EnclaveError;
+
+    fn success(admin_proof: [u8; 32]) -> Result<Self, EnclaveError> {
+        Ok(UpdateAdminResult::Success { admin_proof })
+    }
+
+    fn error(err: EnclaveError) -> Self {
+        UpdateAdminResult::Failure { err }
+    }
+
+    fn failure(err: EnclaveError) -> Self {
+        UpdateAdminResult::Failure { err }
+    }
+
+    fn failure_err(&self) -> Option<&EnclaveError> {
+        match self {
+            UpdateAdminResult::Failure { err } => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// A chunked wasm validation succeeds with the code hash of the contract, which is zeroed until
+/// the session is closed.
+impl IntoEnclaveResult for ValidateWasmResult {
+    type Success = [u8; 32];
+    type Error = EnclaveError;
+
+    fn success(code_hash: [u8; 32]) -> Result<Self, EnclaveError> {
+        Ok(ValidateWasmResult::Success { code_hash })
+    }
+
+    fn error(err: EnclaveError) -> Self {
+        ValidateWasmResult::Failure { err }
+    }
+
+    fn failure(err: EnclaveError) -> Self {
+        ValidateWasmResult::Failure { err }
+    }
+
+    fn failure_err(&self) -> Option<&EnclaveError> {
+        match self {
+            ValidateWasmResult::Failure { err } => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+    use crate::count_failures;
+    use enclave_ffi_types::{InstantiationFailureReason, InvalidWasmReason};
+
+    pub fn run_tests() {
+        println!();
+        let mut failures = 0;
+
+        count_failures!(failures, {
+            test_every_error_converts_to_failure();
+        });
+
+        if failures != 0 {
+            panic!("{}: {} tests failed", file!(), failures);
+        }
+    }
+
+    /// Build one of every variant of `EnclaveError`. This stops compiling when a variant is
+    /// missing from the list.
+    macro_rules! every_enclave_error {
+        ($($variant: ident $({ $($field: ident: $value: expr),* })?,)*) => {{
+            #[allow(dead_code)]
+            fn exhaustive(err: &EnclaveError) {
+                match err {
+                    $(EnclaveError::$variant { .. } => {})*
+                }
+            }
+            vec![$(EnclaveError::$variant $({ $($field: $value),* })?,)*]
+        }};
+    }
+
+    fn every_enclave_error() -> Vec<EnclaveError> {
+        every_enclave_error!(
+            FailedOcall {
+                vm_error: UntrustedVmError::default()
+            },
+            ValidationFailure,
+            InvalidWasm {
+                reason: InvalidWasmReason::Malformed
+            },
+            CannotInitializeWasmMemory,
+            InstantiationFailed {
+                reason: InstantiationFailureReason::Other
+            },
+            WasmModuleWithStart,
+            WasmModuleWithFP,
+            FailedGasMeteringInjection,
+            ContractTooLarge,
+            WasmValidationSessionExists,
+            WasmValidationSessionNotFound,
+            WasmChunkOutOfOrder,
+            InternalError,
+            OutOfGas,
+            OutOfBlockGas,
+            ExecutionTimeout,
+            FailedFunctionCall,
+            ContractMemoryLimitExceeded,
+            StorageQuotaExceeded { usage: 0, quota: 0 },
+            ContractPanicUnreachable,
+            ContractPanicMemoryAccessOutOfBounds,
+            ContractPanicTableAccessOutOfBounds,
+            ContractPanicElemUninitialized,
+            ContractPanicDivisionByZero,
+            ContractPanicInvalidConversionToInt,
+            ContractPanicStackOverflow,
+            ContractPanicUnexpectedSignature,
+            FailedSeal,
+            FailedUnseal,
+            FailedContractAuthentication,
+            FailedToDeserialize,
+            FailedToSerialize,
+            EncryptionError,
+            ResponseTooLarge,
+            ResultTooLarge {
+                messages: 0,
+                max_messages: 0,
+                bytes: 0,
+                max_bytes: 0
+            },
+            MalformedContractResponse,
+            DecryptionError,
+            CiphertextTooShort,
+            InvalidNonce,
+            KeyDerivationFailed,
+            MemoryAllocationError,
+            MemorySafetyAllocationError,
+            MemoryReadError,
+            MemoryWriteError,
+            NotImplemented,
+            FailedTxVerification,
+            UnauthorizedWrite,
+            UnauthorizedMigration,
+            UnauthorizedReply,
+            UnauthorizedKeyRotation,
+            UnauthorizedAdminUpdate,
+            UnauthorizedIbcPort,
+            UnencryptableMessage,
+            QueryOutsideReplayWindow,
+            ReplayedQuery,
+            NonceReuse,
+            TamperedStateArchive,
+            HostMisbehavior,
+            InvalidContractPointer,
+            InvalidEnvPointer,
+            InvalidMsgPointer,
+            InvalidSigInfoPointer,
+            InvalidUsedGasPointer,
+            QueryCodeHashMismatch,
+            Panic,
+            OutOfMemory,
+            ExceededRecursionLimit,
+            ExceededOcallResponseSize,
+            EnclaveNotInitialized,
+            Unknown,
+        )
+    }
+
+    /// Check that every `EnclaveError` is returned unchanged as the `Failure` variant of every
+    /// result type, both when the operation fails with it and when the ecall itself does, and that
+    /// `failure_err` finds it there.
+    macro_rules! assert_every_error_converts_to_failure {
+        ($($result: ident,)*) => {
+            $(
+                for (from_result, failure) in every_enclave_error()
+                    .into_iter()
+                    .zip(every_enclave_error())
+                {
+                    let expected = format!("{:?}", from_result);
+                    let converted = [
+                        $result::from_result(Err(from_result.into())),
+                        $result::failure(failure),
+                    ];
+                    for result in converted.iter() {
+                        assert_eq!(
+                            format!("{:?}", result.failure_err()),
+                            format!("Some({})", expected)
+                        );
+                        match result {
+                            $result::Failure { err, .. } => assert_eq!(
+                                format!("{:?}", err),
+                                expected,
+                                "{} returned another error",
+                                stringify!($result)
+                            ),
+                            _ => panic!("{} did not return a failure", stringify!($result)),
                         }
                     }
                 }
-                user_buffer.assume_init()
-            };
-            QueryResult::Success {
-                output: user_buffer,
-            }
-        }
-        Err(err) => QueryResult::Failure { err },
+            )*
+        };
+    }
+
+    fn test_every_error_converts_to_failure() {
+        assert_every_error_converts_to_failure!(
+            InitResult,
+            HandleResult,
+            IbcResult,
+            IbcReceiveResult,
+            QueryResult,
+            MigrateResult,
+            RotateContractKeyResult,
+            ExportContractStateResult,
+            ImportContractStateResult,
+            UpdateAdminResult,
+            ValidateWasmResult,
+        );
     }
 }
@@ -0,0 +1,115 @@
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::alloc::{GlobalAlloc, Layout, System};
+
+use crate::oom_handler::{allocate_or_retry, release_query_memory};
+
+/// The amount of bytes currently allocated on the heap
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+/// The most bytes that were allocated at once since `take_peak_allocated_bytes` was last called
+static PEAK_ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+/// The amount of panics caught by ecalls since the enclave was started
+static PANICS_CAUGHT: AtomicU64 = AtomicU64::new(0);
+
+/// The amount of contracts that were loaded because their module wasn't cached
+static MODULE_CACHE_MISSES: AtomicU64 = AtomicU64::new(0);
+
+/// The amount of storage reads answered with a value the enclave read ahead
+static PREFETCH_HITS: AtomicU64 = AtomicU64::new(0);
+
+/// The amount of storage reads that went to the host in executions that read ahead
+static PREFETCH_MISSES: AtomicU64 = AtomicU64::new(0);
+
+/// Wraps the system allocator and keeps track of how much memory is allocated through it.
+/// Failed allocations get another try, see `allocate_or_retry`.
+struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = allocate_or_retry(layout.size(), || System.alloc(layout));
+        if !ptr.is_null() {
+            record_allocation(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        ALLOCATED_BYTES.fetch_sub(layout.size(), Ordering::Relaxed);
+        release_query_memory(layout.size());
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = allocate_or_retry(layout.size(), || System.alloc_zeroed(layout));
+        if !ptr.is_null() {
+            record_allocation(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = allocate_or_retry(new_size, || System.realloc(ptr, layout, new_size));
+        // If reallocation failed, the original allocation is left untouched
+        if !new_ptr.is_null() {
+            record_allocation(new_size);
+            ALLOCATED_BYTES.fetch_sub(layout.size(), Ordering::Relaxed);
+            release_query_memory(layout.size());
+        }
+        new_ptr
+    }
+}
+
+fn record_allocation(size: usize) {
+    let allocated = ALLOCATED_BYTES.fetch_add(size, Ordering::Relaxed) + size;
+    PEAK_ALLOCATED_BYTES.fetch_max(allocated, Ordering::Relaxed);
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+pub fn allocated_bytes() -> usize {
+    ALLOCATED_BYTES.load(Ordering::Relaxed)
+}
+
+/// The most bytes that were allocated at once since the last call. The next period starts at what
+/// is allocated now.
+pub fn take_peak_allocated_bytes() -> usize {
+    PEAK_ALLOCATED_BYTES.swap(allocated_bytes(), Ordering::Relaxed)
+}
+
+/// Call this whenever an ecall catches a panic
+pub fn record_caught_panic() {
+    PANICS_CAUGHT.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn panics_caught() -> u64 {
+    PANICS_CAUGHT.load(Ordering::Relaxed)
+}
+
+/// Call this whenever a contract is loaded because its module isn't cached
+pub fn record_module_cache_miss() {
+    MODULE_CACHE_MISSES.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn module_cache_misses() -> u64 {
+    MODULE_CACHE_MISSES.load(Ordering::Relaxed)
+}
+
+/// Call this whenever a storage read is answered with a value that was read ahead
+pub fn record_prefetch_hit() {
+    PREFETCH_HITS.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn prefetch_hits() -> u64 {
+    PREFETCH_HITS.load(Ordering::Relaxed)
+}
+
+/// Call this whenever a storage read of an execution that reads ahead goes to the host
+pub fn record_prefetch_miss() {
+    PREFETCH_MISSES.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn prefetch_misses() -> u64 {
+    PREFETCH_MISSES.load(Ordering::Relaxed)
+}
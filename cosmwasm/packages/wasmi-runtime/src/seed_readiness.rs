@@ -0,0 +1,240 @@
+//! Checking that the consensus seed can be unsealed before running contracts.
+//!
+//! A node whose seed is missing, or was sealed by another enclave, can't decrypt anything, but
+//! without this check it only finds out deep inside the first contract it runs, with an error that
+//! looks like the fault of the contract. Instead the seed is unsealed once, on the first contract
+//! ecall or deep health check, and the outcome is kept for the lifetime of the enclave. While it
+//! isn't `SeedStatus::Ready`, every contract ecall fails with `EnclaveError::EnclaveNotInitialized`
+//! before its inputs are read, and the deep health check reports the `SeedStatus`. The same happens
+//! once the node was decommissioned.
+
+use std::io::{self, Read};
+use std::sgxfs::SgxFile;
+use std::sync::SgxMutex;
+
+use lazy_static::lazy_static;
+use log::*;
+use sgx_types::sgx_status_t;
+
+use enclave_ffi_types::{EnclaveError, SeedStatus};
+
+use crate::consts::consensus_seed_sealing_path;
+use crate::crypto::{Seed, SEED_KEY_SIZE};
+use crate::registration::decommission::is_decommissioned;
+
+lazy_static! {
+    static ref SEED_READINESS: SgxMutex<SeedReadiness> = SgxMutex::new(SeedReadiness::default());
+}
+
+/// Whether the consensus seed of this node can be unsealed, checked on the first call
+pub fn seed_status() -> SeedStatus {
+    if is_decommissioned() {
+        return SeedStatus::Decommissioned;
+    }
+
+    SEED_READINESS
+        .lock()
+        .unwrap()
+        .check(&SealedSeedFile::consensus())
+}
+
+/// Fail with `EnclaveError::EnclaveNotInitialized` unless the consensus seed can be unsealed
+pub fn ensure_seed_ready() -> Result<(), EnclaveError> {
+    match seed_status() {
+        SeedStatus::Ready => Ok(()),
+        _ => Err(EnclaveError::EnclaveNotInitialized),
+    }
+}
+
+/// Where the seed that the readiness check unseals comes from
+pub trait SeedProvider {
+    fn unseal_seed(&self) -> Result<Seed, SeedStatus>;
+}
+
+/// The seed sealed at `path` by `SealedKey::seal`
+pub struct SealedSeedFile {
+    path: String,
+}
+
+impl SealedSeedFile {
+    /// The seed of generation 0. Every initialized node has it, and the seeds of later generations
+    /// are sealed next to it by the same enclave.
+    pub fn consensus() -> Self {
+        Self::new(consensus_seed_sealing_path(0))
+    }
+
+    pub fn new(path: String) -> Self {
+        Self { path }
+    }
+}
+
+impl SeedProvider for SealedSeedFile {
+    fn unseal_seed(&self) -> Result<Seed, SeedStatus> {
+        let mut file = SgxFile::open(&self.path).map_err(|err| open_error_status(&err))?;
+
+        let mut seed = Seed::default();
+        let read = file
+            .read(seed.as_mut())
+            .map_err(|_err| SeedStatus::CorruptedBlob)?;
+        if read < SEED_KEY_SIZE {
+            return Err(SeedStatus::CorruptedBlob);
+        }
+
+        Ok(seed)
+    }
+}
+
+/// Tell why `SgxFile::open` failed. The protected file system reports its own failures as the
+/// `sgx_status_t` of the error.
+fn open_error_status(err: &io::Error) -> SeedStatus {
+    if err.kind() == io::ErrorKind::NotFound {
+        SeedStatus::MissingFile
+    } else if err.raw_os_error() == Some(sgx_status_t::SGX_ERROR_MAC_MISMATCH as i32) {
+        // The metadata of the file doesn't authenticate under the key this enclave derives for it
+        SeedStatus::WrongMeasurement
+    } else {
+        SeedStatus::CorruptedBlob
+    }
+}
+
+/// The outcome of unsealing the seed, once it was tried
+#[derive(Default)]
+pub struct SeedReadiness {
+    status: Option<SeedStatus>,
+}
+
+impl SeedReadiness {
+    pub fn check(&mut self, provider: &dyn SeedProvider) -> SeedStatus {
+        if let Some(status) = self.status {
+            return status;
+        }
+
+        let status = match provider.unseal_seed() {
+            Ok(_seed) => SeedStatus::Ready,
+            Err(status) => {
+                error!(
+                    "Could not unseal the consensus seed ({}), contracts can't be run",
+                    status
+                );
+                status
+            }
+        };
+        self.status = Some(status);
+
+        status
+    }
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+    use crate::count_failures;
+
+    use std::cell::Cell;
+    use std::untrusted::fs;
+
+    pub fn run_tests() {
+        println!();
+        let mut failures = 0;
+
+        count_failures!(failures, {
+            test_seed_readiness_ready();
+            test_seed_readiness_failure_classes();
+            test_seed_readiness_is_cached();
+            test_sealed_seed_file_missing();
+            test_sealed_seed_file_corrupted();
+            test_open_error_status();
+        });
+
+        if failures != 0 {
+            panic!("{}: {} tests failed", file!(), failures);
+        }
+    }
+
+    /// Fails with `failure`, if there is one, and counts how often it was asked for the seed
+    struct FakeSeedProvider {
+        failure: Option<SeedStatus>,
+        calls: Cell<u32>,
+    }
+
+    impl FakeSeedProvider {
+        fn new(failure: Option<SeedStatus>) -> Self {
+            Self {
+                failure,
+                calls: Cell::new(0),
+            }
+        }
+    }
+
+    impl SeedProvider for FakeSeedProvider {
+        fn unseal_seed(&self) -> Result<Seed, SeedStatus> {
+            self.calls.set(self.calls.get() + 1);
+            match self.failure {
+                Some(status) => Err(status),
+                None => Ok(Seed::new().unwrap()),
+            }
+        }
+    }
+
+    fn test_seed_readiness_ready() {
+        let provider = FakeSeedProvider::new(None);
+        assert_eq!(SeedReadiness::default().check(&provider), SeedStatus::Ready);
+    }
+
+    fn test_seed_readiness_failure_classes() {
+        for failure in &[
+            SeedStatus::MissingFile,
+            SeedStatus::WrongMeasurement,
+            SeedStatus::CorruptedBlob,
+        ] {
+            let provider = FakeSeedProvider::new(Some(*failure));
+            assert_eq!(SeedReadiness::default().check(&provider), *failure);
+        }
+    }
+
+    fn test_seed_readiness_is_cached() {
+        let mut readiness = SeedReadiness::default();
+        let missing = FakeSeedProvider::new(Some(SeedStatus::MissingFile));
+        assert_eq!(readiness.check(&missing), SeedStatus::MissingFile);
+        assert_eq!(readiness.check(&missing), SeedStatus::MissingFile);
+        assert_eq!(missing.calls.get(), 1);
+
+        // A seed that shows up later doesn't change the outcome
+        let present = FakeSeedProvider::new(None);
+        assert_eq!(readiness.check(&present), SeedStatus::MissingFile);
+        assert_eq!(present.calls.get(), 0);
+    }
+
+    fn test_sealed_seed_file_missing() {
+        let file = SealedSeedFile::new("./.sgx_secrets/no_such_seed.sealed".to_string());
+        assert!(matches!(file.unseal_seed(), Err(SeedStatus::MissingFile)));
+    }
+
+    fn test_sealed_seed_file_corrupted() {
+        let path = "./.sgx_secrets/corrupted_seed.sealed";
+        fs::write(path, vec![0xab; 4096]).unwrap();
+        let unsealed = SealedSeedFile::new(path.to_string()).unseal_seed();
+        fs::remove_file(path).unwrap();
+
+        assert!(matches!(unsealed, Err(SeedStatus::CorruptedBlob)));
+    }
+
+    fn test_open_error_status() {
+        assert_eq!(
+            open_error_status(&io::Error::from(io::ErrorKind::NotFound)),
+            SeedStatus::MissingFile
+        );
+        assert_eq!(
+            open_error_status(&io::Error::from_raw_os_error(
+                sgx_status_t::SGX_ERROR_MAC_MISMATCH as i32
+            )),
+            SeedStatus::WrongMeasurement
+        );
+        assert_eq!(
+            open_error_status(&io::Error::from_raw_os_error(
+                sgx_status_t::SGX_ERROR_FILE_NOT_SGX_FILE as i32
+            )),
+            SeedStatus::CorruptedBlob
+        );
+    }
+}
@@ -38,6 +38,16 @@ mod test {
             crate::wasm::tests::run_tests();
             crate::registration::tests::run_tests();
             crate::logging_tests::run_tests();
+            crate::recursion_depth::tests::run_tests();
+            crate::health_check::tests::run_tests();
+            crate::seed_readiness::tests::run_tests();
+            crate::log_buffer::tests::run_tests();
+            crate::ecall_trace::tests::run_tests();
+            crate::metrics::tests::run_tests();
+            crate::oom_handler::tests::run_tests();
+            crate::results::tests::run_tests();
+            crate::utils::tests::run_tests();
+            crate::exports::tests::run_tests();
 
             // example failing tests:
             // panic!("AAAAA");
@@ -1,8 +1,9 @@
 use log::*;
 
-use sgx_trts::trts::{
-    rsgx_lfence, rsgx_raw_is_outside_enclave, rsgx_sfence, rsgx_slice_is_outside_enclave,
-};
+use std::ops::Range;
+
+use sgx_trts::enclave::SgxGlobalData;
+use sgx_trts::trts::{rsgx_lfence, rsgx_sfence};
 use sgx_types::*;
 
 use crate::crypto::KeyPair;
@@ -35,30 +36,71 @@ impl<T, S> UnwrapOrSgxErrorUnexpected for Result<T, S> {
     }
 }
 
+/// Validate that the host passed a buffer of `ptr_len` bytes at `ptr` that the enclave may write
+/// to. The buffer must be entirely in enclave memory, see `validate_range`, except that a null
+/// pointer with a length of 0 means that the host passed no buffer at all. That is how the bridge
+/// passes an empty output buffer, e.g. to ask how large the buffer has to be.
 pub fn validate_mut_ptr(ptr: *mut u8, ptr_len: usize) -> SgxResult<()> {
-    if rsgx_raw_is_outside_enclave(ptr, ptr_len) {
-        warn!("Tried to access memory outside enclave -- rsgx_slice_is_outside_enclave");
-        return Err(sgx_status_t::SGX_ERROR_UNEXPECTED);
+    if !(ptr.is_null() && ptr_len == 0) {
+        validate_range(ptr as usize, ptr_len, &enclave_range())?;
     }
     rsgx_sfence();
     Ok(())
 }
 
+/// Validate that the host passed a buffer of `ptr_len` bytes at `ptr` that the enclave may read.
+/// The buffer must be entirely in enclave memory, see `validate_range`. Callers that accept an
+/// empty input check for it before validating.
 pub fn validate_const_ptr(ptr: *const u8, ptr_len: usize) -> SgxResult<()> {
-    if ptr.is_null() || ptr_len == 0 {
-        warn!("Tried to access an empty pointer - ptr.is_null()");
-        return Err(sgx_status_t::SGX_ERROR_UNEXPECTED);
-    }
+    validate_range(ptr as usize, ptr_len, &enclave_range())?;
     rsgx_lfence();
     Ok(())
 }
 
 pub fn validate_mut_slice(mut_slice: &mut [u8]) -> SgxResult<()> {
-    if rsgx_slice_is_outside_enclave(mut_slice) {
-        warn!("Tried to access memory outside enclave -- rsgx_slice_is_outside_enclave");
+    validate_mut_ptr(mut_slice.as_mut_ptr(), mut_slice.len())
+}
+
+/// The addresses of enclave memory
+pub(crate) fn enclave_range() -> Range<usize> {
+    let global_data = SgxGlobalData::new();
+    let base = global_data.enclave_base() as usize;
+    base..base + global_data.enclave_size() as usize
+}
+
+/// Check that the `len` bytes at `ptr` are all in `enclave`.
+///
+/// Every buffer the host passes to an ecall is copied into enclave memory by the bridge code that
+/// the EDL generates, so a buffer anywhere else, even partly, means that the host went around the
+/// bridge. A range may end exactly where the enclave ends, but may not wrap around the address
+/// space. Null pointers and empty ranges are rejected, since the bridge passes no pointer at all
+/// for an empty buffer.
+fn validate_range(ptr: usize, len: usize, enclave: &Range<usize>) -> SgxResult<()> {
+    if ptr == 0 {
+        warn!("Tried to access a null pointer");
+        return Err(sgx_status_t::SGX_ERROR_UNEXPECTED);
+    }
+    if len == 0 {
+        warn!("Tried to access an empty buffer at {:#x}", ptr);
+        return Err(sgx_status_t::SGX_ERROR_UNEXPECTED);
+    }
+    let end = match ptr.checked_add(len) {
+        Some(end) => end,
+        None => {
+            warn!(
+                "Tried to access {} bytes at {:#x}, which wraps around the address space",
+                len, ptr
+            );
+            return Err(sgx_status_t::SGX_ERROR_UNEXPECTED);
+        }
+    };
+    if ptr < enclave.start || end > enclave.end {
+        warn!(
+            "Tried to access memory outside enclave -- {:#x}..{:#x} is not in {:#x}..{:#x}",
+            ptr, end, enclave.start, enclave.end
+        );
         return Err(sgx_status_t::SGX_ERROR_UNEXPECTED);
     }
-    rsgx_sfence();
     Ok(())
 }
 
@@ -87,3 +129,102 @@ pub fn attest_from_key(
 
     Ok(())
 }
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+    use crate::count_failures;
+
+    pub fn run_tests() {
+        println!();
+        let mut failures = 0;
+
+        count_failures!(failures, {
+            test_validate_range_null();
+            test_validate_range_empty();
+            test_validate_range_wrapping();
+            test_validate_range_at_enclave_bounds();
+            test_validate_range_crossing_enclave_bounds();
+            test_validate_range_outside_enclave();
+            test_validate_ptrs_in_enclave();
+            test_validate_mut_ptr_without_buffer();
+        });
+
+        if failures != 0 {
+            println!("{}: {} tests failed", file!(), failures);
+            panic!()
+        }
+    }
+
+    const ENCLAVE: Range<usize> = 0x1000..0x2000;
+
+    fn is_valid(ptr: usize, len: usize) -> bool {
+        validate_range(ptr, len, &ENCLAVE).is_ok()
+    }
+
+    fn test_validate_range_null() {
+        assert!(!is_valid(0, 0));
+        assert!(!is_valid(0, 16));
+        // Even if the enclave were mapped at 0
+        assert!(validate_range(0, 16, &(0..0x1000)).is_err());
+    }
+
+    fn test_validate_range_empty() {
+        assert!(!is_valid(0x1800, 0));
+        assert!(!is_valid(0x3000, 0));
+    }
+
+    fn test_validate_range_wrapping() {
+        assert!(!is_valid(usize::MAX - 3, 16));
+        assert!(!is_valid(0x1800, usize::MAX));
+        assert!(!is_valid(usize::MAX, 1));
+        // A range that wraps around to end in the enclave
+        assert!(!is_valid(0x1800, usize::MAX - 0x7ff));
+        // An enclave at the top of the address space
+        let top = usize::MAX - 0xfff..usize::MAX;
+        assert!(validate_range(usize::MAX - 16, 16, &top).is_ok());
+        assert!(validate_range(usize::MAX - 16, 17, &top).is_err());
+    }
+
+    fn test_validate_range_at_enclave_bounds() {
+        assert!(is_valid(0x1000, 0x1000));
+        assert!(is_valid(0x1000, 1));
+        assert!(is_valid(0x1ff0, 16));
+        assert!(is_valid(0x1fff, 1));
+    }
+
+    fn test_validate_range_crossing_enclave_bounds() {
+        assert!(!is_valid(0xff0, 17));
+        assert!(!is_valid(0xfff, 2));
+        assert!(!is_valid(0x1ff0, 17));
+        assert!(!is_valid(0x1fff, 2));
+        assert!(!is_valid(0xfff, 0x1002));
+    }
+
+    fn test_validate_range_outside_enclave() {
+        assert!(!is_valid(0xff0, 16));
+        assert!(!is_valid(0x2000, 1));
+        assert!(!is_valid(0x3000, 16));
+    }
+
+    fn test_validate_ptrs_in_enclave() {
+        let mut buf = [0u8; 16];
+        assert!(validate_const_ptr(buf.as_ptr(), buf.len()).is_ok());
+        assert!(validate_mut_ptr(buf.as_mut_ptr(), buf.len()).is_ok());
+        assert!(validate_mut_slice(&mut buf).is_ok());
+
+        let enclave = enclave_range();
+        assert!(enclave.contains(&(buf.as_ptr() as usize)));
+        let outside = (enclave.end + 16) as *mut u8;
+        assert!(validate_const_ptr(outside, 16).is_err());
+        assert!(validate_mut_ptr(outside, 16).is_err());
+        assert!(validate_const_ptr(buf.as_ptr(), 0).is_err());
+        assert!(validate_mut_ptr(buf.as_mut_ptr(), 0).is_err());
+    }
+
+    fn test_validate_mut_ptr_without_buffer() {
+        assert!(validate_mut_ptr(std::ptr::null_mut(), 0).is_ok());
+        assert!(validate_mut_ptr(std::ptr::null_mut(), 16).is_err());
+        assert!(validate_const_ptr(std::ptr::null(), 0).is_err());
+    }
+}
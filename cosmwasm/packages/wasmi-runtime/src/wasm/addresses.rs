@@ -0,0 +1,182 @@
+//! Bech32 addresses for the `addr_validate`, `addr_canonicalize` and `addr_humanize` imports.
+//! The prefix is set by the chain, so networks with a different prefix work the same way.
+
+use std::fmt;
+
+use bech32::{FromBase32, ToBase32};
+
+/// The longest canonical address we humanize. Longer addresses don't fit in the 90 characters
+/// bech32 allows, so they could never be canonicalized back.
+pub const MAX_CANONICAL_ADDRESS_LENGTH: usize = 32;
+
+/// These are returned to the contract as the error message of the import
+#[derive(Debug, PartialEq)]
+pub enum AddressError {
+    Empty,
+    InvalidUtf8,
+    Bech32(bech32::Error),
+    WrongPrefix {
+        expected: String,
+        found: String,
+    },
+    InvalidLength(usize),
+    /// The address decodes correctly, but isn't written in its normalized (lowercase) form
+    NotNormalized,
+}
+
+impl fmt::Display for AddressError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AddressError::Empty => write!(f, "input is empty"),
+            AddressError::InvalidUtf8 => write!(f, "input is not valid UTF-8"),
+            AddressError::Bech32(err) => write!(f, "invalid bech32 address: {}", err),
+            AddressError::WrongPrefix { expected, found } => write!(
+                f,
+                "wrong address prefix: {:?} (expected {:?})",
+                found, expected
+            ),
+            AddressError::InvalidLength(len) => write!(
+                f,
+                "canonical address length {} is not between 1 and {}",
+                len, MAX_CANONICAL_ADDRESS_LENGTH
+            ),
+            AddressError::NotNormalized => write!(f, "address is not normalized"),
+        }
+    }
+}
+
+impl From<bech32::Error> for AddressError {
+    fn from(err: bech32::Error) -> Self {
+        AddressError::Bech32(err)
+    }
+}
+
+/// A prefix the chain may set. It must be a valid bech32 human readable part, in lowercase.
+pub fn is_valid_prefix(prefix: &str) -> bool {
+    prefix == prefix.to_lowercase() && bech32::encode(prefix, Vec::<bech32::u5>::new()).is_ok()
+}
+
+/// Decode an address with the given prefix. As required by BIP-173, addresses that mix upper
+/// and lower case are rejected, as are addresses with an invalid checksum.
+pub fn addr_canonicalize(human: &str, prefix: &str) -> Result<Vec<u8>, AddressError> {
+    if human.is_empty() {
+        return Err(AddressError::Empty);
+    }
+
+    let (decoded_prefix, data) = bech32::decode(human)?;
+    if decoded_prefix != prefix {
+        return Err(AddressError::WrongPrefix {
+            expected: prefix.to_string(),
+            found: decoded_prefix,
+        });
+    }
+
+    let canonical = Vec::<u8>::from_base32(&data)?;
+    if canonical.is_empty() || canonical.len() > MAX_CANONICAL_ADDRESS_LENGTH {
+        return Err(AddressError::InvalidLength(canonical.len()));
+    }
+
+    Ok(canonical)
+}
+
+pub fn addr_humanize(canonical: &[u8], prefix: &str) -> Result<String, AddressError> {
+    if canonical.is_empty() || canonical.len() > MAX_CANONICAL_ADDRESS_LENGTH {
+        return Err(AddressError::InvalidLength(canonical.len()));
+    }
+
+    Ok(bech32::encode(prefix, canonical.to_base32())?)
+}
+
+/// An address is valid only in its normalized form, so every account has exactly one valid
+/// address and contracts can compare addresses as strings.
+pub fn addr_validate(human: &str, prefix: &str) -> Result<(), AddressError> {
+    let canonical = addr_canonicalize(human, prefix)?;
+    if addr_humanize(&canonical, prefix)? != human {
+        return Err(AddressError::NotNormalized);
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    const ADDRESS: &str = "secret1qypqxpq9qcrsszg2pvxq6rs0zqg3yyc5a8chmq";
+
+    pub fn test_addr_round_trip() {
+        let canonical = addr_canonicalize(ADDRESS, "secret").unwrap();
+        assert_eq!(canonical.len(), 20);
+        assert_eq!(addr_humanize(&canonical, "secret").unwrap(), ADDRESS);
+        assert_eq!(addr_validate(ADDRESS, "secret"), Ok(()));
+
+        // The same account on a network with another prefix
+        let testnet_address = addr_humanize(&canonical, "test").unwrap();
+        assert!(testnet_address.starts_with("test1"));
+        assert_eq!(addr_validate(&testnet_address, "test"), Ok(()));
+        assert_eq!(
+            addr_canonicalize(&testnet_address, "secret"),
+            Err(AddressError::WrongPrefix {
+                expected: "secret".to_string(),
+                found: "test".to_string(),
+            })
+        );
+    }
+
+    pub fn test_addr_invalid_checksum() {
+        let mut address = ADDRESS.to_string();
+        address.pop();
+        address.push('4');
+
+        assert_eq!(
+            addr_canonicalize(&address, "secret"),
+            Err(AddressError::Bech32(bech32::Error::InvalidChecksum))
+        );
+        assert_eq!(
+            addr_validate(&address, "secret"),
+            Err(AddressError::Bech32(bech32::Error::InvalidChecksum))
+        );
+    }
+
+    pub fn test_addr_case() {
+        let mixed_case = ADDRESS.replacen("secret", "SECRET", 1);
+        assert_eq!(
+            addr_canonicalize(&mixed_case, "secret"),
+            Err(AddressError::Bech32(bech32::Error::MixedCase))
+        );
+
+        // An all uppercase address is valid bech32, but it isn't the normalized form
+        let upper_case = ADDRESS.to_uppercase();
+        assert_eq!(
+            addr_canonicalize(&upper_case, "secret"),
+            addr_canonicalize(ADDRESS, "secret")
+        );
+        assert_eq!(
+            addr_validate(&upper_case, "secret"),
+            Err(AddressError::NotNormalized)
+        );
+    }
+
+    pub fn test_addr_invalid_input() {
+        assert_eq!(addr_validate("", "secret"), Err(AddressError::Empty));
+        assert_eq!(
+            addr_validate(&format!(" {}", ADDRESS), "secret"),
+            Err(AddressError::Bech32(bech32::Error::InvalidChar(' ')))
+        );
+        assert_eq!(
+            addr_humanize(&[], "secret"),
+            Err(AddressError::InvalidLength(0))
+        );
+        assert_eq!(
+            addr_humanize(&[1; MAX_CANONICAL_ADDRESS_LENGTH + 1], "secret"),
+            Err(AddressError::InvalidLength(
+                MAX_CANONICAL_ADDRESS_LENGTH + 1
+            ))
+        );
+
+        assert!(is_valid_prefix("secret"));
+        assert!(!is_valid_prefix(""));
+        assert!(!is_valid_prefix("Secret"));
+        assert!(!is_valid_prefix("sec ret"));
+    }
+}
@@ -0,0 +1,294 @@
+//! Contracts are built against one of two generations of the CosmWasm API, which import different
+//! host functions. Contracts built for CosmWasm 0.10 convert addresses with
+//! `canonicalize_address` and `humanize_address`, while contracts built for CosmWasm 1.0 use the
+//! `addr_*` functions, and report their panics through `abort` and their debug messages through
+//! `debug`.
+//!
+//! The version of a contract is detected when it's loaded, and its imports are resolved against
+//! the functions of that version only.
+
+use log::*;
+use parity_wasm::elements::{External, Module};
+
+use enclave_ffi_types::{EnclaveError, InvalidWasmReason};
+
+/// The export that marks contracts built for CosmWasm 0.10
+const V0_10_MARKER: &str = "cosmwasm_vm_version_3";
+/// The export that marks contracts built for CosmWasm 1.0
+const V1_MARKER: &str = "interface_version_8";
+
+/// The module that contracts import host functions from
+const IMPORT_MODULE: &str = "env";
+
+/// Host functions contracts of every version may import
+const COMMON_IMPORTS: &[&str] = &[
+    "db_read",
+    "db_read_multi",
+    "db_write",
+    "db_remove",
+    "db_scan",
+    "db_next",
+    "db_read_ns",
+    "db_write_ns",
+    "db_remove_ns",
+    "db_move_to_ns",
+    "db_storage_usage",
+    "db_try_write",
+    "db_try_write_ns",
+    "addr_validate",
+    "addr_canonicalize",
+    "addr_humanize",
+    "query_chain",
+    "ed25519_verify",
+    "ed25519_batch_verify",
+    "secp256k1_recover_pubkey",
+    "get_random",
+    "hash_sha256",
+    "hash_keccak256",
+    "hash_ripemd160",
+    "hkdf_sha256",
+    "secp256k1_generate_key",
+    "secp256k1_sign",
+    "secp256k1_public_key",
+    "gas_left",
+    "gas_limit",
+    "verify_foreign_header",
+    "assert_contract_code_hash",
+    #[cfg(feature = "test")]
+    "test_panic",
+    #[cfg(feature = "debug-print")]
+    "debug_print",
+];
+
+/// Host functions only contracts built for CosmWasm 0.10 may import
+const V0_10_IMPORTS: &[&str] = &["canonicalize_address", "humanize_address"];
+
+/// Host functions only contracts built for CosmWasm 1.0 may import
+const V1_IMPORTS: &[&str] = &["abort", "debug"];
+
+/// Host functions that aren't part of any version, but are added to every contract when it's
+/// loaded, see `hook_memory_grow`, `pwasm_utils::inject_gas_counter` and `hook_unreachable`
+const INJECTED_IMPORTS: &[&str] = &[
+    "gas",
+    "memory_grow",
+    #[cfg(feature = "debug-print")]
+    "trap_location",
+];
+
+/// The generation of the CosmWasm API a contract was built for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiVersion {
+    V0_10,
+    V1,
+}
+
+impl ApiVersion {
+    /// The host functions only contracts of this version may import
+    fn own_imports(self) -> &'static [&'static str] {
+        match self {
+            ApiVersion::V0_10 => V0_10_IMPORTS,
+            ApiVersion::V1 => V1_IMPORTS,
+        }
+    }
+
+    /// Whether contracts of this version may import the host function `name`
+    pub fn provides(self, name: &str) -> bool {
+        COMMON_IMPORTS.contains(&name) || self.own_imports().contains(&name)
+    }
+
+    /// Whether the host function `name` can be resolved for contracts of this version. This also
+    /// includes the functions that are added to contracts when they're loaded.
+    pub fn resolves(self, name: &str) -> bool {
+        self.provides(name) || INJECTED_IMPORTS.contains(&name)
+    }
+}
+
+/// Detect the version of the CosmWasm API the module was built for, and check that it only
+/// imports functions of that version.
+///
+/// The version is taken from the marker the module exports. A module without a marker is
+/// detected by the functions it imports that only one version provides, and is a CosmWasm 0.10
+/// contract if it imports none of them.
+pub fn detect_api_version(module: &Module) -> Result<ApiVersion, EnclaveError> {
+    let version = match marked_api_version(module)? {
+        Some(version) => version,
+        None => imported_api_version(module)?,
+    };
+    debug!("Contract was built for CosmWasm API {:?}", version);
+
+    for import in module
+        .import_section()
+        .map_or(&[][..], |section| section.entries())
+    {
+        let is_function = matches!(import.external(), External::Function(_));
+        if import.module() != IMPORT_MODULE || !is_function || !version.provides(import.field()) {
+            warn!(
+                "Contract built for CosmWasm API {:?} imports {}.{}, which it doesn't provide",
+                version,
+                import.module(),
+                import.field()
+            );
+            return Err(EnclaveError::InvalidWasm {
+                reason: InvalidWasmReason::UnsupportedImport,
+            });
+        }
+    }
+
+    Ok(version)
+}
+
+fn marked_api_version(module: &Module) -> Result<Option<ApiVersion>, EnclaveError> {
+    let mut version = None;
+    for export in module
+        .export_section()
+        .map_or(&[][..], |section| section.entries())
+    {
+        let marked = match export.field() {
+            V0_10_MARKER => ApiVersion::V0_10,
+            V1_MARKER => ApiVersion::V1,
+            field
+                if field.starts_with("cosmwasm_vm_version_")
+                    || field.starts_with("interface_version_") =>
+            {
+                warn!(
+                    "Contract exports an unsupported API version marker {}",
+                    field
+                );
+                return Err(unsupported_api_version());
+            }
+            _ => continue,
+        };
+
+        if version.map_or(false, |version| version != marked) {
+            warn!("Contract exports the markers of more than one API version");
+            return Err(unsupported_api_version());
+        }
+        version = Some(marked);
+    }
+
+    Ok(version)
+}
+
+fn imported_api_version(module: &Module) -> Result<ApiVersion, EnclaveError> {
+    let imports = module
+        .import_section()
+        .map_or(&[][..], |section| section.entries());
+    let imports_any_of = |names: &[&str]| {
+        imports
+            .iter()
+            .any(|import| import.module() == IMPORT_MODULE && names.contains(&import.field()))
+    };
+
+    match (imports_any_of(V0_10_IMPORTS), imports_any_of(V1_IMPORTS)) {
+        (true, true) => {
+            warn!("Contract imports functions of more than one API version");
+            Err(EnclaveError::InvalidWasm {
+                reason: InvalidWasmReason::UnsupportedImport,
+            })
+        }
+        (false, true) => Ok(ApiVersion::V1),
+        _ => Ok(ApiVersion::V0_10),
+    }
+}
+
+fn unsupported_api_version() -> EnclaveError {
+    EnclaveError::InvalidWasm {
+        reason: InvalidWasmReason::UnsupportedApiVersion,
+    }
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use parity_wasm::elements;
+    use wasmi::ModuleInstance;
+
+    use crate::crypto::sha_256;
+    use crate::wasm::contract_operations::validate_code;
+    use crate::wasm::runtime::{create_builder, WasmiImportResolver};
+
+    const V0_10_WASM: &[u8] = include_bytes!("../../testdata/api-versions/v0_10.wasm");
+    const V1_WASM: &[u8] = include_bytes!("../../testdata/api-versions/v1.wasm");
+
+    fn detect(wasm: &[u8]) -> Result<ApiVersion, Option<InvalidWasmReason>> {
+        let module: Module = elements::deserialize_buffer(wasm).unwrap();
+        detect_api_version(&module).map_err(|err| match err {
+            EnclaveError::InvalidWasm { reason } => Some(reason),
+            _ => None,
+        })
+    }
+
+    fn instantiates_as(wasm: &[u8], api_version: ApiVersion) -> bool {
+        let module = wasmi::Module::from_buffer(wasm).unwrap();
+        let resolver = WasmiImportResolver { api_version };
+        ModuleInstance::new(&module, &create_builder(&resolver)).is_ok()
+    }
+
+    pub fn test_detect_api_version() {
+        assert_eq!(detect(V0_10_WASM), Ok(ApiVersion::V0_10));
+        assert_eq!(detect(V1_WASM), Ok(ApiVersion::V1));
+        // Without a marker, the version is told by the imports
+        assert_eq!(
+            detect(include_bytes!(
+                "../../testdata/api-versions/unmarked_v1.wasm"
+            )),
+            Ok(ApiVersion::V1)
+        );
+        // An empty module
+        assert_eq!(detect(b"\0asm\x01\0\0\0"), Ok(ApiVersion::V0_10));
+    }
+
+    pub fn test_reject_unsupported_api_version() {
+        assert_eq!(
+            detect(include_bytes!(
+                "../../testdata/api-versions/unknown_version.wasm"
+            )),
+            Err(Some(InvalidWasmReason::UnsupportedApiVersion))
+        );
+    }
+
+    pub fn test_reject_imports_of_another_version() {
+        assert_eq!(
+            detect(include_bytes!(
+                "../../testdata/api-versions/v1_legacy_import.wasm"
+            )),
+            Err(Some(InvalidWasmReason::UnsupportedImport))
+        );
+        assert_eq!(
+            detect(include_bytes!(
+                "../../testdata/api-versions/unmarked_mixed.wasm"
+            )),
+            Err(Some(InvalidWasmReason::UnsupportedImport))
+        );
+
+        // The reason is part of the error that the sender of the contract sees
+        let err = validate_code(
+            &sha_256(b"v1_legacy_import"),
+            include_bytes!("../../testdata/api-versions/v1_legacy_import.wasm"),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "tried to load invalid wasm code: the module imports a function its CosmWasm API version does not provide"
+        );
+    }
+
+    pub fn test_both_versions_run_side_by_side() {
+        validate_code(&sha_256(V0_10_WASM), V0_10_WASM).unwrap();
+        validate_code(&sha_256(V1_WASM), V1_WASM).unwrap();
+
+        // Each version is only linked against its own functions
+        assert!(instantiates_as(V0_10_WASM, ApiVersion::V0_10));
+        assert!(instantiates_as(V1_WASM, ApiVersion::V1));
+        assert!(!instantiates_as(V0_10_WASM, ApiVersion::V1));
+        assert!(!instantiates_as(V1_WASM, ApiVersion::V0_10));
+
+        // The functions we add to contracts are resolved for both
+        for api_version in &[ApiVersion::V0_10, ApiVersion::V1] {
+            assert!(api_version.resolves("gas"));
+            assert!(api_version.resolves("memory_grow"));
+            assert!(!api_version.provides("gas"));
+        }
+    }
+}
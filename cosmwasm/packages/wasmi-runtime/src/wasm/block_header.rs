@@ -0,0 +1,264 @@
+//! Verification of the block header the chain passes in the env, which gives contracts the hash
+//! and proposer of the current block.
+//!
+//! The header is the one the chain is executing the block of, so it's the same on every node. The
+//! enclave hashes it like Tendermint does and checks it against the height, time and chain id of
+//! the block in the env, and nothing else: whether a contract gets the hash, and which one, must
+//! not depend on what the enclave of a node saw before, or nodes would disagree on the state the
+//! contract writes. A header that doesn't match the block fails the execution on every node.
+//!
+//! The enclave can't check the signatures of the validators on the header of the current block,
+//! as its commit is only part of the next block. The hash is as trustworthy as the height and time
+//! of the env, which the chain passes the same way.
+
+use log::*;
+
+use enclave_ffi_types::EnclaveError;
+
+use crate::cosmwasm::types::{BlockHeader, BlockInfo};
+use crate::crypto::{sha_256, HASH_SIZE};
+
+pub type BlockHash = [u8; HASH_SIZE];
+
+/// Verify the header the chain passed for the block in the env, and return its hash
+pub fn verify_block_header(
+    header: &BlockHeader,
+    block: &BlockInfo,
+) -> Result<BlockHash, EnclaveError> {
+    if header.height != block.height
+        || header.chain_id != block.chain_id
+        || header.time.seconds < 0
+        || header.time.seconds as u64 != block.time
+        || header.time.nanos < 0
+        || header.time.nanos as u32 != block.time_nanos
+    {
+        warn!(
+            "got a header of block {} on chain {:?} at {}.{:09}, for block {} on chain {:?} at {}.{:09}",
+            header.height,
+            header.chain_id,
+            header.time.seconds,
+            header.time.nanos,
+            block.height,
+            block.chain_id,
+            block.time,
+            block.time_nanos
+        );
+        return Err(EnclaveError::ValidationFailure);
+    }
+
+    // Tendermint doesn't hash headers without validators
+    if header.validators_hash.is_empty() {
+        warn!("got a header without a validators hash");
+        return Err(EnclaveError::ValidationFailure);
+    }
+
+    Ok(header_hash(header))
+}
+
+/// The hash of a header, the same as Tendermint's `Header.Hash`: the root of a merkle tree over
+/// the amino encoding of each of the fields, in order.
+pub fn header_hash(header: &BlockHeader) -> BlockHash {
+    let mut version = vec![];
+    encode_varint_field(1, header.version.block, &mut version);
+    encode_varint_field(2, header.version.app, &mut version);
+
+    let mut time = vec![];
+    encode_varint_field(1, header.time.seconds as u64, &mut time);
+    encode_varint_field(2, header.time.nanos as i64 as u64, &mut time);
+
+    let mut parts = vec![];
+    encode_varint_field(1, header.last_block_id.parts.total as u64, &mut parts);
+    encode_bytes_field(2, header.last_block_id.parts.hash.as_slice(), &mut parts);
+
+    let mut last_block_id = vec![];
+    encode_bytes_field(1, header.last_block_id.hash.as_slice(), &mut last_block_id);
+    encode_bytes_field(2, &parts, &mut last_block_id);
+
+    let mut height = vec![];
+    encode_uvarint(header.height, &mut height);
+
+    let fields = [
+        version,
+        encode_bytes(header.chain_id.as_bytes()),
+        height,
+        time,
+        last_block_id,
+        encode_bytes(header.last_commit_hash.as_slice()),
+        encode_bytes(header.data_hash.as_slice()),
+        encode_bytes(header.validators_hash.as_slice()),
+        encode_bytes(header.next_validators_hash.as_slice()),
+        encode_bytes(header.consensus_hash.as_slice()),
+        encode_bytes(header.app_hash.as_slice()),
+        encode_bytes(header.last_results_hash.as_slice()),
+        encode_bytes(header.evidence_hash.as_slice()),
+        encode_bytes(header.proposer_address.as_slice()),
+    ];
+
+    merkle_root(&fields)
+}
+
+/// The root of an RFC 6962 merkle tree, like Tendermint's `SimpleHashFromByteSlices`
+pub(super) fn merkle_root(items: &[Vec<u8>]) -> BlockHash {
+    match items.len() {
+        0 => sha_256(&[]),
+        1 => {
+            let mut leaf = vec![0u8];
+            leaf.extend_from_slice(&items[0]);
+            sha_256(&leaf)
+        }
+        len => {
+            // The largest power of two that is smaller than `len`
+            let split = 1 << (63 - (len as u64 - 1).leading_zeros());
+            let mut inner = vec![1u8];
+            inner.extend_from_slice(&merkle_root(&items[..split]));
+            inner.extend_from_slice(&merkle_root(&items[split..]));
+            sha_256(&inner)
+        }
+    }
+}
+
+pub(super) fn encode_uvarint(mut value: u64, out: &mut Vec<u8>) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+pub(super) fn encode_bytes(bytes: &[u8]) -> Vec<u8> {
+    let mut out = vec![];
+    encode_uvarint(bytes.len() as u64, &mut out);
+    out.extend_from_slice(bytes);
+    out
+}
+
+/// Amino leaves out fields with default values
+pub(super) fn encode_varint_field(field_number: u8, value: u64, out: &mut Vec<u8>) {
+    if value != 0 {
+        out.push(field_number << 3);
+        encode_uvarint(value, out);
+    }
+}
+
+pub(super) fn encode_bytes_field(field_number: u8, bytes: &[u8], out: &mut Vec<u8>) {
+    if !bytes.is_empty() {
+        out.push(field_number << 3 | 2);
+        out.extend_from_slice(&encode_bytes(bytes));
+    }
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use crate::cosmwasm::encoding::Binary;
+    use crate::cosmwasm::types::{BlockId, HeaderTime, HeaderVersion, PartSetHeader};
+
+    const CHAIN_ID: &str = "secret-testnet";
+
+    fn header(height: u64, last_block_hash: &[u8]) -> BlockHeader {
+        BlockHeader {
+            version: HeaderVersion { block: 10, app: 0 },
+            chain_id: CHAIN_ID.to_string(),
+            height,
+            time: HeaderTime {
+                seconds: 1_600_000_000 + height as i64 * 6,
+                nanos: 123_456_789,
+            },
+            last_block_id: BlockId {
+                hash: Binary(last_block_hash.to_vec()),
+                parts: PartSetHeader {
+                    total: 1,
+                    hash: Binary(vec![0x22; 32]),
+                },
+            },
+            last_commit_hash: Binary(vec![0x33; 32]),
+            data_hash: Binary(vec![]),
+            validators_hash: Binary(vec![0x44; 32]),
+            next_validators_hash: Binary(vec![0x44; 32]),
+            consensus_hash: Binary(vec![0x55; 32]),
+            app_hash: Binary(vec![0x66; 32]),
+            last_results_hash: Binary(vec![]),
+            evidence_hash: Binary(vec![]),
+            proposer_address: Binary(vec![0x77; 20]),
+        }
+    }
+
+    fn block(header: &BlockHeader) -> BlockInfo {
+        BlockInfo {
+            height: header.height,
+            time: header.time.seconds as u64,
+            time_nanos: header.time.nanos as u32,
+            chain_id: header.chain_id.clone(),
+            hash: None,
+            proposer_address: None,
+        }
+    }
+
+    /// The same header is hashed with Tendermint in `TestBlockHeaderHash` in x/compute
+    pub fn test_header_hash() {
+        assert_eq!(
+            hex::encode(header_hash(&header(100, &[0x11; 32]))),
+            "057a1cb16ca5bdb2b9811534ed685688edc4201b8b4411774c4de117f1ff5db3"
+        );
+
+        // Every field is hashed
+        let mut other = header(100, &[0x11; 32]);
+        other.proposer_address = Binary(vec![0x78; 20]);
+        assert_ne!(header_hash(&other), header_hash(&header(100, &[0x11; 32])));
+    }
+
+    pub fn test_verify_block_header() {
+        let first = header(100, &[0x11; 32]);
+        assert_eq!(
+            verify_block_header(&first, &block(&first)).unwrap(),
+            header_hash(&first)
+        );
+
+        // The hash only depends on the header, so it's the same whatever was verified before,
+        // e.g. for another message in the block or on a node that just started
+        let second = header(101, &header_hash(&first));
+        assert_eq!(
+            verify_block_header(&second, &block(&second)).unwrap(),
+            header_hash(&second)
+        );
+        assert_eq!(
+            verify_block_header(&first, &block(&first)).unwrap(),
+            header_hash(&first)
+        );
+
+        // A header that doesn't link to the one before it still gets its own hash
+        let unlinked = header(102, &[0x12; 32]);
+        assert_eq!(
+            verify_block_header(&unlinked, &block(&unlinked)).unwrap(),
+            header_hash(&unlinked)
+        );
+    }
+
+    pub fn test_verify_header_of_another_block() {
+        let header = header(100, &[0x11; 32]);
+
+        let mut other_height = block(&header);
+        other_height.height = 101;
+        let mut other_time = block(&header);
+        other_time.time += 1;
+        let mut other_nanos = block(&header);
+        other_nanos.time_nanos += 1;
+        let mut other_chain = block(&header);
+        other_chain.chain_id = "secret-2".to_string();
+
+        for block in &[other_height, other_time, other_nanos, other_chain] {
+            assert!(matches!(
+                verify_block_header(&header, block),
+                Err(EnclaveError::ValidationFailure)
+            ));
+        }
+
+        let mut without_validators = header.clone();
+        without_validators.validators_hash = Binary(vec![]);
+        assert!(matches!(
+            verify_block_header(&without_validators, &block(&without_validators)),
+            Err(EnclaveError::ValidationFailure)
+        ));
+    }
+}
@@ -0,0 +1,394 @@
+//! The env in the shape of the CosmWasm API a contract was built for.
+//!
+//! The chain passes one `Env`, and the enclave fills it in and checks it the same way for every
+//! contract. Contracts built for CosmWasm 0.10 get it as is, with the sender and the funds in
+//! `env.message` and the block time in seconds. Contracts built for CosmWasm 1.0 get an env
+//! without the message, and a separate `MessageInfo` on `instantiate` and `execute`. Their block
+//! time is a `Timestamp` in nanoseconds, built from the seconds and the nanoseconds of the block
+//! time, so neither version loses any of the precision it has.
+
+use std::fmt;
+
+use log::*;
+use serde::de::{self, Deserializer, Visitor};
+use serde::{Deserialize, Serialize, Serializer};
+
+use enclave_ffi_types::EnclaveError;
+
+use crate::cosmwasm::encoding::Binary;
+use crate::cosmwasm::types::{Coin, Env, HumanAddr};
+
+use super::api_version::ApiVersion;
+
+const NANOS_PER_SECOND: u64 = 1_000_000_000;
+
+/// A point in time in nanoseconds since the unix epoch, which CosmWasm 1.0 serializes as a string
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Timestamp(u64);
+
+impl Timestamp {
+    /// None if the time doesn't fit in 64 bits of nanoseconds, which is in the year 2554
+    pub fn from_parts(seconds: u64, subsec_nanos: u32) -> Option<Self> {
+        seconds
+            .checked_mul(NANOS_PER_SECOND)?
+            .checked_add(subsec_nanos as u64)
+            .map(Timestamp)
+    }
+
+    pub fn seconds(self) -> u64 {
+        self.0 / NANOS_PER_SECOND
+    }
+
+    pub fn subsec_nanos(self) -> u32 {
+        (self.0 % NANOS_PER_SECOND) as u32
+    }
+}
+
+impl Serialize for Timestamp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Timestamp {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_str(TimestampVisitor)
+    }
+}
+
+struct TimestampVisitor;
+
+impl<'de> Visitor<'de> for TimestampVisitor {
+    type Value = Timestamp;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a string of nanoseconds")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Timestamp, E> {
+        value
+            .parse()
+            .map(Timestamp)
+            .map_err(|err| E::custom(format!("invalid timestamp {:?}: {}", value, err)))
+    }
+}
+
+/// The `Env` of CosmWasm 1.0, with the fields Secret adds to it
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct EnvV1 {
+    pub block: BlockInfoV1,
+    pub contract: ContractInfoV1,
+    /// See `Env::query_sender`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub query_sender: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct BlockInfoV1 {
+    pub height: u64,
+    pub time: Timestamp,
+    pub chain_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub random: Option<Binary>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<Binary>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proposer_address: Option<Binary>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct ContractInfoV1 {
+    pub address: String,
+    pub code_hash: String,
+    /// See `ContractInfo::admin`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub admin: Option<String>,
+}
+
+/// The `MessageInfo` of CosmWasm 1.0, which is passed next to the env
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct MessageInfoV1 {
+    pub sender: String,
+    pub funds: Vec<Coin>,
+}
+
+/// The env to pass to a contract built for `api_version`
+pub fn encode_env(env: &Env, api_version: ApiVersion) -> Result<Vec<u8>, EnclaveError> {
+    match api_version {
+        ApiVersion::V0_10 => to_vec(env),
+        ApiVersion::V1 => to_vec(&env_v1(env)?),
+    }
+}
+
+/// The message info to pass next to the env, for contracts that take it separately. Contracts
+/// built for CosmWasm 0.10 find it in `env.message`.
+pub fn encode_message_info(
+    env: &Env,
+    api_version: ApiVersion,
+) -> Result<Option<Vec<u8>>, EnclaveError> {
+    match api_version {
+        ApiVersion::V0_10 => Ok(None),
+        ApiVersion::V1 => to_vec(&MessageInfoV1 {
+            sender: address(&env.message.sender),
+            funds: env.message.sent_funds.clone(),
+        })
+        .map(Some),
+    }
+}
+
+fn env_v1(env: &Env) -> Result<EnvV1, EnclaveError> {
+    let time = Timestamp::from_parts(env.block.time, env.block.time_nanos).ok_or_else(|| {
+        warn!(
+            "got a block time of {}.{:09} that doesn't fit in a timestamp",
+            env.block.time, env.block.time_nanos
+        );
+        EnclaveError::FailedToSerialize
+    })?;
+
+    Ok(EnvV1 {
+        block: BlockInfoV1 {
+            height: env.block.height,
+            time,
+            chain_id: env.block.chain_id.clone(),
+            random: env.random.clone(),
+            hash: env.block.hash.clone(),
+            proposer_address: env.block.proposer_address.clone(),
+        },
+        contract: ContractInfoV1 {
+            address: address(&env.contract.address),
+            code_hash: env.contract_code_hash.clone(),
+            admin: env.contract.admin.as_ref().map(address),
+        },
+        query_sender: env.query_sender.as_ref().map(address),
+    })
+}
+
+/// `HumanAddr` and CosmWasm 1.0's `Addr` are both the bech32 string of the address
+fn address(human: &HumanAddr) -> String {
+    human.0.clone()
+}
+
+fn to_vec<T: Serialize + fmt::Debug>(value: &T) -> Result<Vec<u8>, EnclaveError> {
+    serde_json::to_vec(value).map_err(|err| {
+        warn!(
+            "got an error while trying to serialize parsed_env into bytes {:?}: {}",
+            value, err
+        );
+        EnclaveError::FailedToSerialize
+    })
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use parity_wasm::elements::{self, Module};
+    use wasmi::ModuleInstance;
+
+    use enclave_ffi_types::Ctx;
+
+    use crate::cosmwasm::types::{BlockInfo, ContractInfo, MessageInfo, TransactionInfo};
+    use crate::wasm::api_version::detect_api_version;
+    use crate::wasm::gas::WasmCosts;
+    use crate::wasm::runtime::{
+        create_builder, ContractInstance, ContractOperation, Engine, WasmiImportResolver,
+    };
+
+    const V0_10_ENV_WASM: &[u8] = include_bytes!("../../testdata/env-versions/v0_10_env.wasm");
+    const V1_ENV_WASM: &[u8] = include_bytes!("../../testdata/env-versions/v1_env.wasm");
+
+    const CONTRACT_ADDRESS: &str = "secret1qypqxpq9qcrsszg2pvxq6rs0zqg3yyc5a8chmq";
+    const SENDER: &str = "secret1zg69v7ys40x77y352eufp27daufrg4ncnjqz7q";
+
+    fn env() -> Env {
+        Env {
+            block: BlockInfo {
+                height: 12_345,
+                time: 1_600_000_000,
+                time_nanos: 123_456_789,
+                chain_id: "secret-4".to_string(),
+                hash: Some(Binary(vec![0x11; 32])),
+                proposer_address: None,
+            },
+            message: MessageInfo {
+                sender: HumanAddr(SENDER.to_string()),
+                sent_funds: vec![Coin {
+                    denom: "uscrt".to_string(),
+                    amount: "1000".to_string(),
+                }],
+            },
+            contract: ContractInfo {
+                address: HumanAddr(CONTRACT_ADDRESS.to_string()),
+                admin: None,
+            },
+            contract_key: Some("a2V5".to_string()),
+            contract_code_hash: "ab".repeat(32),
+            random: Some(Binary(vec![0x22; 32])),
+            transaction: TransactionInfo::default(),
+            query_sender: None,
+            ocall_gas_costs: None,
+            bech32_prefix: None,
+            instantiation: None,
+            block_header: None,
+            execution_timeout_ms: None,
+            output_limits: None,
+            block_gas_remaining: None,
+            storage_quota: None,
+            max_memory_pages: None,
+            simulation: false,
+            client_query: false,
+            admin_proof: None,
+            execution_path: None,
+            reply_to: None,
+            foreign_chains: vec![],
+        }
+    }
+
+    /// Load `wasm` with the API version it's detected as
+    fn engine(wasm: &[u8]) -> Engine {
+        let parsed: Module = elements::deserialize_buffer(wasm).unwrap();
+        let api_version = detect_api_version(&parsed).unwrap();
+
+        let module = wasmi::Module::from_buffer(wasm).unwrap();
+        let resolver = WasmiImportResolver { api_version };
+        let module = ModuleInstance::new(&module, &create_builder(&resolver))
+            .unwrap()
+            .assert_no_start();
+        let instance = ContractInstance::new(
+            Ctx {
+                data: std::ptr::null_mut(),
+            },
+            module.clone(),
+            10_000_000,
+            WasmCosts::default(),
+            [0u8; 64],
+            ContractOperation::Init,
+            [0u8; 32],
+            [0u8; 32],
+            None,
+        );
+        Engine::new(instance, module, api_version)
+    }
+
+    /// Call the entry point of `engine` with the env and message info of `env`, and return what
+    /// it returned
+    fn echo(engine: &mut Engine, handle: bool, env: &Env) -> Vec<u8> {
+        let env_ptr = engine
+            .write_to_memory(&encode_env(env, engine.api_version()).unwrap())
+            .unwrap();
+        let info_ptr = encode_message_info(env, engine.api_version())
+            .unwrap()
+            .map(|info| engine.write_to_memory(&info).unwrap());
+        let msg_ptr = engine.write_to_memory(b"{}").unwrap();
+
+        let vec_ptr = if handle {
+            engine.handle(env_ptr, info_ptr, msg_ptr).unwrap()
+        } else {
+            engine.init(env_ptr, info_ptr, msg_ptr).unwrap()
+        };
+        engine.extract_vector(vec_ptr).unwrap()
+    }
+
+    pub fn test_v0_10_env_is_unchanged() {
+        let env = env();
+        assert_eq!(
+            encode_env(&env, ApiVersion::V0_10).unwrap(),
+            serde_json::to_vec(&env).unwrap()
+        );
+        assert_eq!(encode_message_info(&env, ApiVersion::V0_10).unwrap(), None);
+    }
+
+    pub fn test_v1_env_shape() {
+        let env = env();
+        let encoded: serde_json::Value =
+            serde_json::from_slice(&encode_env(&env, ApiVersion::V1).unwrap()).unwrap();
+        assert_eq!(
+            encoded,
+            serde_json::json!({
+                "block": {
+                    "height": 12_345,
+                    "time": "1600000000123456789",
+                    "chain_id": "secret-4",
+                    "random": base64::encode(&[0x22; 32]),
+                    "hash": base64::encode(&[0x11; 32]),
+                },
+                "contract": {
+                    "address": CONTRACT_ADDRESS,
+                    "code_hash": "ab".repeat(32),
+                },
+            })
+        );
+
+        let info: serde_json::Value =
+            serde_json::from_slice(&encode_message_info(&env, ApiVersion::V1).unwrap().unwrap())
+                .unwrap();
+        assert_eq!(
+            info,
+            serde_json::json!({
+                "sender": SENDER,
+                "funds": [{ "denom": "uscrt", "amount": "1000" }],
+            })
+        );
+    }
+
+    pub fn test_timestamp_round_trip() {
+        for (seconds, nanos) in &[
+            (0, 0),
+            (1_600_000_000, 0),
+            (1_600_000_000, 1),
+            (1_600_000_000, 999_999_999),
+            (u64::MAX / NANOS_PER_SECOND - 1, 999_999_999),
+        ] {
+            let time = Timestamp::from_parts(*seconds, *nanos).unwrap();
+            let encoded = serde_json::to_string(&time).unwrap();
+            let decoded: Timestamp = serde_json::from_str(&encoded).unwrap();
+            assert_eq!(decoded, time);
+            assert_eq!(
+                (decoded.seconds(), decoded.subsec_nanos()),
+                (*seconds, *nanos)
+            );
+        }
+
+        assert_eq!(Timestamp::from_parts(u64::MAX, 0), None);
+        let mut far_future = env();
+        far_future.block.time = u64::MAX / NANOS_PER_SECOND + 1;
+        assert!(matches!(
+            encode_env(&far_future, ApiVersion::V1),
+            Err(EnclaveError::FailedToSerialize)
+        ));
+
+        assert!(serde_json::from_str::<Timestamp>("1600000000").is_err());
+        assert!(serde_json::from_str::<Timestamp>(r#""-1""#).is_err());
+    }
+
+    pub fn test_v0_10_contract_reads_its_env() {
+        let mut engine = engine(V0_10_ENV_WASM);
+        assert_eq!(engine.api_version(), ApiVersion::V0_10);
+
+        let env = env();
+        let received: Env = serde_json::from_slice(&echo(&mut engine, false, &env)).unwrap();
+        assert_eq!(received.block.time, env.block.time);
+        // CosmWasm 0.10 only has the block time in seconds
+        assert_eq!(received.block.time_nanos, 0);
+        assert_eq!(received.message, env.message);
+        assert_eq!(received.contract, env.contract);
+        assert_eq!(received.random, env.random);
+    }
+
+    pub fn test_v1_contract_reads_its_env() {
+        let mut engine = engine(V1_ENV_WASM);
+        assert_eq!(engine.api_version(), ApiVersion::V1);
+
+        let env = env();
+        let received: EnvV1 = serde_json::from_slice(&echo(&mut engine, false, &env)).unwrap();
+        assert_eq!(received.block.height, env.block.height);
+        assert_eq!(received.block.time.seconds(), env.block.time);
+        assert_eq!(received.block.time.subsec_nanos(), env.block.time_nanos);
+        assert_eq!(received.contract.address, CONTRACT_ADDRESS);
+        assert_eq!(received.contract.code_hash, env.contract_code_hash);
+
+        let info: MessageInfoV1 = serde_json::from_slice(&echo(&mut engine, true, &env)).unwrap();
+        assert_eq!(info.sender, SENDER);
+        assert_eq!(info.funds, env.message.sent_funds);
+    }
+}
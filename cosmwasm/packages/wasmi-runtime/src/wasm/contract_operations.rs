@@ -1,28 +1,67 @@
+use std::sync::Arc;
+
 use log::*;
 use parity_wasm::elements;
 use parity_wasm::elements::Module;
-use wasmi::ModuleInstance;
+use wasmi::{ModuleInstance, NotStartedModuleRef};
 
-use enclave_ffi_types::{Ctx, EnclaveError};
+use enclave_ffi_types::{Ctx, EnclaveError, InstantiationFailureReason, InvalidWasmReason};
 
-use crate::cosmwasm::types::{CanonicalAddr, Env, SigInfo};
-use crate::crypto::Ed25519PublicKey;
-use crate::results::{HandleSuccess, InitSuccess, QuerySuccess};
+use crate::consts::MAX_FOREIGN_CHAINS;
+use crate::cosmwasm::ibc::{IbcAcknowledgement, IbcChannel, IbcPacket};
+use crate::cosmwasm::types::{
+    CanonicalAddr, Env, HumanAddr, Reply, SigInfo, SignDocWasmMsg, SignedReply, TransactionInfo,
+};
+use crate::crypto::{sha_256, Ed25519PublicKey, HASH_SIZE, KEY_MANAGER};
+use crate::metrics;
+use crate::results::{
+    CallError, HandleSuccess, IbcReceiveSuccess, IbcSuccess, InitSuccess, MigrateSuccess,
+    QuerySuccess, RotateContractKeySuccess,
+};
 use crate::wasm::types::{IoNonce, SecretMessage};
 
+use super::addresses::is_valid_prefix;
+use super::block_header::verify_block_header;
 use super::contract_validation::{
-    calc_contract_hash, extract_contract_key, generate_encryption_key, validate_contract_key,
-    validate_msg, verify_params, ContractKey, CONTRACT_KEY_LENGTH,
+    calc_contract_hash, extract_contract_code_hash, extract_contract_key, generate_admin_proof,
+    generate_encryption_key, generate_migrated_contract_key, generate_rotated_contract_key,
+    is_authenticated_admin, validate_contract_key_for_hash, validate_msg_for_hash,
+    verify_admin_update_params, verify_contract_address, verify_contract_admin,
+    verify_contract_code_hash, verify_ibc_port, verify_key_rotation_params, verify_params,
+    verify_reply, verify_reply_to, verify_reported_admin, ContractKey, VerifiedTx,
+    CONTRACT_KEY_LENGTH,
+};
+use super::db::{
+    read_previous_contract_key, rotate_encrypted_key, scan_encrypted_keys, split_rotation_chunk,
+    write_previous_contract_key,
 };
-use super::gas::{gas_rules, WasmCosts};
-use super::io::encrypt_output;
+use super::gas::{check_gas_limit, gas_rules, WasmCosts};
+use super::io::{
+    check_public_output, decrypt_query, encrypt_output, encrypt_query_output,
+    encrypt_receive_output, MessageRandomness, ReplyTarget,
+};
+#[cfg(not(feature = "debug-print"))]
+use super::module_artifact;
+use super::nonce_registry::check_tx_nonce;
+use super::query_cache::{cache_query, get_cached_query, CachedQuery, QueryCacheKey};
+use super::query_permit::{permit_revocation, take_query_permit, verify_query_permit, QueryPermit};
+use super::query_replay::{check_query_replay, take_replay_protection};
+#[cfg(feature = "debug-print")]
+use super::trap_info;
 use super::{
-    memory::validate_memory,
+    api_version::{detect_api_version, ApiVersion},
+    contract_env::{encode_env, encode_message_info},
+    memory::{hook_memory_grow, validate_memory},
+    module_cache::{self, AnalyzedModule},
     runtime::{create_builder, ContractInstance, ContractOperation, Engine, WasmiImportResolver},
+    wasm_features::reject_forbidden_features,
 };
 
 use crate::coalesce;
 use crate::cosmwasm::encoding::Binary;
+use crate::ecall_trace::Phase;
+use crate::trace_secret;
+use crate::trace_span;
 
 /*
 Each contract is compiled with these functions already implemented in wasm:
@@ -31,7 +70,13 @@ fn allocate(size: usize) -> *mut c_void;
 fn deallocate(pointer: *mut c_void);
 fn init(env_ptr: *mut c_void, msg_ptr: *mut c_void) -> *mut c_void
 fn handle(env_ptr: *mut c_void, msg_ptr: *mut c_void) -> *mut c_void
-fn query(msg_ptr: *mut c_void) -> *mut c_void
+// Contracts built for CosmWasm 1.0 export these instead of `init` and `handle`, see `contract_env`:
+fn instantiate(env_ptr: *mut c_void, info_ptr: *mut c_void, msg_ptr: *mut c_void) -> *mut c_void
+fn execute(env_ptr: *mut c_void, info_ptr: *mut c_void, msg_ptr: *mut c_void) -> *mut c_void
+fn query(msg_ptr: *mut c_void) -> *mut c_void // or query(env_ptr, msg_ptr) to receive `env`
+fn migrate(env_ptr: *mut c_void, msg_ptr: *mut c_void) -> *mut c_void
+fn reply(env_ptr: *mut c_void, msg_ptr: *mut c_void) -> *mut c_void
+fn ibc_channel_open(env_ptr: *mut c_void, msg_ptr: *mut c_void) -> *mut c_void // and the other ibc_* entry points
 
 Re `init`, `handle` and `query`: We need to pass `env` & `msg`
 down to the wasm implementations, but because they are buffers
@@ -47,7 +92,11 @@ pub fn init(
     env: &[u8],         // blockchain state
     msg: &[u8],         // probably function call and args
     sig_info: &[u8],    // info about signature verification
-) -> Result<InitSuccess, EnclaveError> {
+) -> Result<InitSuccess, CallError> {
+    check_gas_limit(gas_limit, used_gas)?;
+
+    let contract_hash = calc_contract_hash(contract);
+
     let mut parsed_env: Env = serde_json::from_slice(env).map_err(|err| {
         warn!(
             "got an error while trying to deserialize env input bytes into json {:?}: {}",
@@ -56,6 +105,7 @@ pub fn init(
         );
         EnclaveError::FailedToDeserialize
     })?;
+    activate_key_generations(&parsed_env);
 
     let canonical_contract_address = CanonicalAddr::from_human(&parsed_env.contract.address).map_err(|err| {
         warn!(
@@ -64,9 +114,15 @@ pub fn init(
         );
         EnclaveError::FailedToDeserialize
     })?;
+    verify_contract_address(
+        &parsed_env,
+        &canonical_contract_address,
+        &contract_hash,
+        msg,
+    )?;
     let contract_key =
         generate_encryption_key(&parsed_env, contract, &(canonical_contract_address.0).0)?;
-    trace!("Init: Contract Key: {:?}", contract_key.to_vec().as_slice());
+    trace_secret!("Init: Contract Key: {:?}", contract_key.to_vec().as_slice());
 
     let parsed_sig_info: SigInfo = serde_json::from_slice(sig_info).map_err(|err| {
         warn!(
@@ -83,45 +139,78 @@ pub fn init(
         String::from_utf8_lossy(&msg)
     );
 
-    verify_params(&parsed_sig_info, &parsed_env, &secret_msg)?;
+    let verified_tx = verify_params(&parsed_sig_info, &parsed_env, &secret_msg, &contract_hash)?;
+    if let Some(verified_tx) = &verified_tx {
+        verified_tx.set_fee_info(&mut parsed_env);
+    }
+    let reply_target = verify_reply_to(
+        &mut parsed_env,
+        &secret_msg.nonce,
+        &secret_msg.user_public_key,
+    )?
+    .map(|target| ReplyTarget {
+        instantiated: Some(canonical_contract_address.clone()),
+        ..target
+    });
+
+    // The admin is whoever the sender signed, whatever the host reports in the env
+    parsed_env.contract.admin = match verified_tx {
+        Some(VerifiedTx {
+            msg: SignDocWasmMsg::Instantiate { admin, .. },
+            ..
+        }) => admin,
+        _ => None,
+    };
+    let admin_proof = admin_proof_for(
+        &canonical_contract_address,
+        parsed_env.contract.admin.as_ref(),
+    )?;
+    parsed_env.admin_proof = Some(Binary(admin_proof.to_vec()));
 
     let decrypted_msg = secret_msg.decrypt()?;
 
-    let validated_msg = validate_msg(&decrypted_msg, contract)?;
+    let validated_msg = validate_msg_for_hash(&decrypted_msg, &contract_hash)?;
 
-    trace!(
+    trace_secret!(
         "Init input after decryption: {:?}",
         String::from_utf8_lossy(&validated_msg)
     );
 
+    let mut randomness = MessageRandomness::new(
+        parsed_env.block.height,
+        &canonical_contract_address,
+        &secret_msg,
+        &parsed_sig_info,
+    )?;
+    parsed_env.random = Some(Binary(randomness.next_random().to_vec()));
+
     let mut engine = start_engine(
         context,
         gas_limit,
         contract,
+        &contract_hash,
         &contract_key,
         ContractOperation::Init,
         secret_msg.nonce,
         secret_msg.user_public_key,
+        Some(randomness),
     )?;
+    engine.set_contract_address(canonical_contract_address.clone());
+    apply_chain_settings(&mut engine, &mut parsed_env)?;
+    check_tx_nonce(&mut engine, &parsed_env, &parsed_sig_info, &secret_msg)?;
 
-    parsed_env.contract_code_hash = hex::encode(calc_contract_hash(contract));
-
-    let new_env = serde_json::to_vec(&parsed_env).map_err(|err| {
-        warn!(
-            "got an error while trying to serialize parsed_env into bytes {:?}: {}",
-            parsed_env, err
-        );
-        EnclaveError::FailedToSerialize
-    })?;
+    parsed_env.contract_code_hash = hex::encode(contract_hash);
 
-    let env_ptr = engine.write_to_memory(&new_env)?;
+    let env_ptr = write_env(&mut engine, &parsed_env)?;
+    let info_ptr = write_message_info(&mut engine, &parsed_env)?;
     let msg_ptr = engine.write_to_memory(&validated_msg)?;
 
     // This wrapper is used to coalesce all errors in this block to one object
     // so we can `.map_err()` in one place for all of them
-    let output = coalesce!(EnclaveError, {
-        let vec_ptr = engine.init(env_ptr, msg_ptr)?;
+    let output = coalesce!(CallError, {
+        let vec_ptr = engine.init(env_ptr, info_ptr, msg_ptr)?;
         let output = engine.extract_vector(vec_ptr)?;
+        engine.flush_storage()?;
         // TODO: copy cosmwasm's structures to enclave
         // TODO: ref: https://github.com/CosmWasm/cosmwasm/blob/b971c037a773bf6a5f5d08a88485113d9b9e8e7b/packages/std/src/init_handle.rs#L129
         // TODO: ref: https://github.com/CosmWasm/cosmwasm/blob/b971c037a773bf6a5f5d08a88485113d9b9e8e7b/packages/std/src/query.rs#L13
@@ -130,6 +219,9 @@ pub fn init(
             secret_msg.nonce,
             secret_msg.user_public_key,
             &canonical_contract_address,
+            engine.output_limits(),
+            engine.execution_path(),
+            reply_target.as_ref(),
         )?;
 
         Ok(output)
@@ -145,9 +237,13 @@ pub fn init(
     Ok(InitSuccess {
         output,
         contract_key,
+        admin_proof,
     })
 }
 
+/// When `simulate` is set, the contract runs and is charged exactly like it would be otherwise,
+/// but its storage changes are discarded at the end instead of being written.
+#[allow(clippy::too_many_arguments)]
 pub fn handle(
     context: Ctx,
     gas_limit: u64,
@@ -156,7 +252,10 @@ pub fn handle(
     env: &[u8],
     msg: &[u8],
     sig_info: &[u8],
-) -> Result<HandleSuccess, EnclaveError> {
+    simulate: bool,
+) -> Result<HandleSuccess, CallError> {
+    check_gas_limit(gas_limit, used_gas)?;
+
     let mut parsed_env: Env = serde_json::from_slice(env).map_err(|err| {
         warn!(
             "got an error while trying to deserialize env input bytes into json {:?}: {}",
@@ -164,9 +263,15 @@ pub fn handle(
         );
         EnclaveError::FailedToDeserialize
     })?;
+    activate_key_generations(&parsed_env);
 
     trace!("handle parsed_env: {:?}", parsed_env);
 
+    // Everything below is checked against this hash, so it's computed once from the code the
+    // host actually passed in
+    let contract_hash = calc_contract_hash(contract);
+    verify_contract_code_hash(&parsed_env, &contract_hash)?;
+
     let parsed_sig_info: SigInfo = serde_json::from_slice(sig_info).map_err(|err| {
         warn!(
             "got an error while trying to deserialize env input bytes into json {:?}: {}",
@@ -179,16 +284,25 @@ pub fn handle(
     let secret_msg = SecretMessage::from_slice(msg)?;
 
     // Verify env parameters against the signed tx
-    verify_params(&parsed_sig_info, &parsed_env, &secret_msg)?;
+    if let Some(verified_tx) =
+        verify_params(&parsed_sig_info, &parsed_env, &secret_msg, &contract_hash)?
+    {
+        verified_tx.set_fee_info(&mut parsed_env);
+    }
+    let reply_target = verify_reply_to(
+        &mut parsed_env,
+        &secret_msg.nonce,
+        &secret_msg.user_public_key,
+    )?;
 
     let contract_key = extract_contract_key(&parsed_env)?;
 
     let secret_msg = SecretMessage::from_slice(msg)?;
     let decrypted_msg = secret_msg.decrypt()?;
 
-    let validated_msg = validate_msg(&decrypted_msg, contract)?;
+    let validated_msg = validate_msg_for_hash(&decrypted_msg, &contract_hash)?;
 
-    trace!(
+    trace_secret!(
         "Handle input afer decryption: {:?}",
         String::from_utf8_lossy(&validated_msg)
     );
@@ -201,47 +315,69 @@ pub fn handle(
         EnclaveError::FailedToDeserialize
     })?;
 
-    if !validate_contract_key(&contract_key, &(canonical_contract_address.0).0, contract) {
+    if !validate_contract_key_for_hash(
+        &contract_key,
+        &(canonical_contract_address.0).0,
+        &contract_hash,
+    ) {
         warn!("got an error while trying to deserialize output bytes");
-        return Err(EnclaveError::FailedContractAuthentication);
+        return Err(EnclaveError::FailedContractAuthentication.into());
     }
 
     trace!("Successfully authenticated the contract!");
 
-    trace!(
+    trace_secret!(
         "Handle: Contract Key: {:?}",
         contract_key.to_vec().as_slice()
     );
 
+    let mut randomness = MessageRandomness::new(
+        parsed_env.block.height,
+        &canonical_contract_address,
+        &secret_msg,
+        &parsed_sig_info,
+    )?;
+    parsed_env.random = Some(Binary(randomness.next_random().to_vec()));
+
     let mut engine = start_engine(
         context,
         gas_limit,
         contract,
+        &contract_hash,
         &contract_key,
         ContractOperation::Handle,
         secret_msg.nonce,
         secret_msg.user_public_key,
+        Some(randomness),
     )?;
+    engine.set_contract_address(canonical_contract_address.clone());
+    apply_chain_settings(&mut engine, &mut parsed_env)?;
+    if simulate {
+        engine.use_storage_overlay();
+    } else {
+        // A simulated execution doesn't use up the nonce of the tx that's broadcast after it
+        check_tx_nonce(&mut engine, &parsed_env, &parsed_sig_info, &secret_msg)?;
+    }
 
-    parsed_env.contract_code_hash = hex::encode(calc_contract_hash(contract));
-
-    let new_env = serde_json::to_vec(&parsed_env).map_err(|err| {
-        warn!(
-            "got an error while trying to serialize parsed_env into bytes {:?}: {}",
-            parsed_env, err
-        );
-        EnclaveError::FailedToSerialize
-    })?;
+    parsed_env.contract_code_hash = hex::encode(contract_hash);
 
-    let env_ptr = engine.write_to_memory(&new_env)?;
+    let env_ptr = write_env(&mut engine, &parsed_env)?;
+    let info_ptr = write_message_info(&mut engine, &parsed_env)?;
     let msg_ptr = engine.write_to_memory(&validated_msg)?;
 
     // This wrapper is used to coalesce all errors in this block to one object
     // so we can `.map_err()` in one place for all of them
-    let output = coalesce!(EnclaveError, {
-        let vec_ptr = engine.handle(env_ptr, msg_ptr)?;
+    let output = coalesce!(CallError, {
+        if let Some(permit_name) = permit_revocation(&validated_msg) {
+            let sender = CanonicalAddr::from_human(&parsed_env.message.sender)
+                .map_err(|_| EnclaveError::FailedToDeserialize)?;
+            engine.revoke_query_permit(&sender, &permit_name)?;
+        }
+
+        let vec_ptr = engine.handle(env_ptr, info_ptr, msg_ptr)?;
 
         let output = engine.extract_vector(vec_ptr)?;
+        engine.flush_storage()?;
 
         debug!(
             "(2) nonce just before encrypt_output: nonce = {:?} pubkey = {:?}",
@@ -252,6 +388,9 @@ pub fn handle(
             secret_msg.nonce,
             secret_msg.user_public_key,
             &canonical_contract_address,
+            engine.output_limits(),
+            engine.execution_path(),
+            reply_target.as_ref(),
         )?;
         Ok(output)
     })
@@ -260,8 +399,12 @@ pub fn handle(
         err
     })?;
 
-    *used_gas = engine.gas_used();
-    Ok(HandleSuccess { output })
+    // Storage removed by the contract is only refunded if the execution succeeded
+    *used_gas = engine.gas_used_after_refund();
+    Ok(HandleSuccess {
+        output,
+        gas_breakdown: engine.gas_breakdown(),
+    })
 }
 
 pub fn query(
@@ -269,11 +412,45 @@ pub fn query(
     gas_limit: u64,
     used_gas: &mut u64,
     contract: &[u8],
+    env: &[u8], // may be empty if the host didn't provide an env
     msg: &[u8],
-) -> Result<QuerySuccess, EnclaveError> {
+) -> Result<QuerySuccess, CallError> {
+    check_gas_limit(gas_limit, used_gas)?;
+
     if msg.len() < CONTRACT_KEY_LENGTH {
         warn!("Input query is shorter than the minimum expected. Msg is malformed");
-        return Err(EnclaveError::FailedFunctionCall);
+        return Err(EnclaveError::FailedFunctionCall.into());
+    }
+
+    let contract_hash = calc_contract_hash(contract);
+
+    // The env is verified even if the contract doesn't get to see it
+    let parsed_env: Option<Env> = if env.is_empty() {
+        None
+    } else {
+        let parsed_env: Env = serde_json::from_slice(env).map_err(|err| {
+            warn!(
+                "got an error while trying to deserialize env input bytes into json {:?}: {}",
+                String::from_utf8_lossy(&env),
+                err
+            );
+            EnclaveError::FailedToDeserialize
+        })?;
+        activate_key_generations(&parsed_env);
+        verify_contract_code_hash(&parsed_env, &contract_hash)?;
+        Some(parsed_env)
+    };
+
+    // The same encrypted query of a client gets the same answer until the state changes, see
+    // `query_cache`
+    let cache_key = QueryCacheKey::for_client_query(&contract_hash, parsed_env.as_ref(), msg);
+    if let Some((key, state)) = &cache_key {
+        if let Some(cached) = get_cached_query(key, *state, gas_limit) {
+            *used_gas = cached.used_gas;
+            return Ok(QuerySuccess {
+                output: cached.output,
+            });
+        }
     }
 
     let (key, msg) = msg.split_at(CONTRACT_KEY_LENGTH);
@@ -281,43 +458,84 @@ pub fn query(
     let mut contract_key = [0; CONTRACT_KEY_LENGTH];
     contract_key.copy_from_slice(key);
 
-    trace!(
+    trace_secret!(
         "Query: Contract Key: {:?}",
         contract_key.to_vec().as_slice()
     );
 
     let secret_msg = SecretMessage::from_slice(msg)?;
-    let decrypted_msg = secret_msg.decrypt()?;
-    trace!(
+    let (decrypted_msg, sender) = decrypt_query(&secret_msg, &contract_hash)?;
+    trace_secret!(
         "Query input afer decryption: {:?}",
         String::from_utf8_lossy(&decrypted_msg)
     );
-    let validated_msg = validate_msg(&decrypted_msg, contract)?;
+    let validated_msg = validate_msg_for_hash(&decrypted_msg, &contract_hash)?;
+    let (validated_msg, replay_protection) = take_replay_protection(validated_msg)?;
+    if let Some(replay_protection) = &replay_protection {
+        check_query_replay(replay_protection, parsed_env.as_ref())?;
+    }
+    let (validated_msg, permit) = take_query_permit(validated_msg);
 
     let mut engine = start_engine(
         context,
         gas_limit,
         contract,
+        &contract_hash,
         &contract_key,
         ContractOperation::Query,
         secret_msg.nonce,
         secret_msg.user_public_key,
+        None,
     )?;
 
+    // A contract can only sign in a query with a key that is authenticated for its address
+    if let Some(contract_address) = parsed_env
+        .as_ref()
+        .and_then(|env| authenticated_contract_address(env, &contract_key, &contract_hash))
+    {
+        engine.set_contract_address(contract_address);
+    }
+
+    // Only contracts that export `query(env, msg)` get to see the env
+    let env_ptr = match parsed_env {
+        Some(mut parsed_env) if engine.query_accepts_env() => {
+            apply_chain_settings(&mut engine, &mut parsed_env)?;
+            parsed_env.contract_code_hash = hex::encode(contract_hash);
+            // Queries are not part of consensus, so they don't get randomness
+            parsed_env.random = None;
+            // Queries are not signed, so there's no memo to verify
+            parsed_env.transaction = TransactionInfo::default();
+            parsed_env.query_sender = match permit {
+                Some(permit) => query_permit_sender(
+                    &mut engine,
+                    &permit,
+                    &parsed_env,
+                    &contract_key,
+                    &contract_hash,
+                )?,
+                None => None,
+            };
+
+            Some(write_env(&mut engine, &parsed_env)?)
+        }
+        _ => None,
+    };
     let msg_ptr = engine.write_to_memory(&validated_msg)?;
 
     // This wrapper is used to coalesce all errors in this block to one object
     // so we can `.map_err()` in one place for all of them
-    let output = coalesce!(EnclaveError, {
-        let vec_ptr = engine.query(msg_ptr)?;
+    let output = coalesce!(CallError, {
+        let vec_ptr = engine.query(env_ptr, msg_ptr)?;
 
         let output = engine.extract_vector(vec_ptr)?;
 
-        let output = encrypt_output(
+        let output = encrypt_query_output(
             output,
             secret_msg.nonce,
             secret_msg.user_public_key,
-            &CanonicalAddr(Binary(Vec::new())), // Not used for queries
+            sender,
+            &contract_hash,
+            engine.output_limits(),
         )?;
         Ok(output)
     })
@@ -327,77 +545,1431 @@ pub fn query(
     })?;
 
     *used_gas = engine.gas_used();
+    if let (Some((key, state)), None) = (cache_key, replay_protection) {
+        let cached = CachedQuery {
+            output: output.clone(),
+            used_gas: *used_gas,
+        };
+        cache_query(key, state, cached);
+    }
     Ok(QuerySuccess { output })
 }
 
-fn start_engine(
+pub fn migrate(
+    context: Ctx,
+    gas_limit: u64,
+    used_gas: &mut u64,
+    contract: &[u8], // the new contract wasm bytes
+    env: &[u8],
+    msg: &[u8],
+    sig_info: &[u8],
+) -> Result<MigrateSuccess, CallError> {
+    check_gas_limit(gas_limit, used_gas)?;
+
+    let contract_hash = calc_contract_hash(contract);
+
+    let mut parsed_env: Env = serde_json::from_slice(env).map_err(|err| {
+        warn!(
+            "got an error while trying to deserialize env input bytes into json {:?}: {}",
+            String::from_utf8_lossy(&env),
+            err
+        );
+        EnclaveError::FailedToDeserialize
+    })?;
+    activate_key_generations(&parsed_env);
+
+    trace!("migrate parsed_env: {:?}", parsed_env);
+
+    let parsed_sig_info: SigInfo = serde_json::from_slice(sig_info).map_err(|err| {
+        warn!(
+            "got an error while trying to deserialize env input bytes into json {:?}: {}",
+            String::from_utf8_lossy(&sig_info),
+            err
+        );
+        EnclaveError::FailedToDeserialize
+    })?;
+
+    let secret_msg = SecretMessage::from_slice(msg)?;
+
+    // Verify env parameters against the signed tx
+    if let Some(verified_tx) =
+        verify_params(&parsed_sig_info, &parsed_env, &secret_msg, &contract_hash)?
+    {
+        verified_tx.set_fee_info(&mut parsed_env);
+    }
+
+    // Only the admin of the contract may migrate it
+    verify_contract_admin(&parsed_env)?;
+
+    let contract_key = extract_contract_key(&parsed_env)?;
+
+    let canonical_contract_address = CanonicalAddr::from_human(&parsed_env.contract.address).map_err(|err| {
+        warn!(
+            "got an error while trying to deserialize parsed_env.contract.address from bech32 string to bytes {:?}: {}",
+            parsed_env.contract.address, err
+        );
+        EnclaveError::FailedToDeserialize
+    })?;
+
+    // The current contract key was authenticated for the code the contract is migrating from,
+    // which the host reports in the env.
+    let old_contract_hash = extract_contract_code_hash(&parsed_env)?;
+
+    if !validate_contract_key_for_hash(
+        &contract_key,
+        &(canonical_contract_address.0).0,
+        &old_contract_hash,
+    ) {
+        warn!("Contract key does not match the code the contract is migrating from");
+        return Err(EnclaveError::FailedContractAuthentication.into());
+    }
+
+    trace!("Successfully authenticated the contract!");
+
+    let new_contract_key =
+        generate_migrated_contract_key(&contract_key, &(canonical_contract_address.0).0, contract)?;
+    trace_secret!(
+        "Migrate: New Contract Key: {:?}",
+        new_contract_key.to_vec().as_slice()
+    );
+
+    let decrypted_msg = secret_msg.decrypt()?;
+
+    let validated_msg = validate_msg_for_hash(&decrypted_msg, &contract_hash)?;
+
+    trace_secret!(
+        "Migrate input afer decryption: {:?}",
+        String::from_utf8_lossy(&validated_msg)
+    );
+
+    let mut randomness = MessageRandomness::new(
+        parsed_env.block.height,
+        &canonical_contract_address,
+        &secret_msg,
+        &parsed_sig_info,
+    )?;
+    parsed_env.random = Some(Binary(randomness.next_random().to_vec()));
+
+    let mut engine = start_engine(
+        context,
+        gas_limit,
+        contract,
+        &contract_hash,
+        &new_contract_key,
+        ContractOperation::Migrate,
+        secret_msg.nonce,
+        secret_msg.user_public_key,
+        Some(randomness),
+    )?;
+    engine.set_contract_address(canonical_contract_address.clone());
+    apply_chain_settings(&mut engine, &mut parsed_env)?;
+
+    parsed_env.contract_code_hash = hex::encode(contract_hash);
+    parsed_env.contract_key = Some(base64::encode(&new_contract_key[..]));
+
+    let env_ptr = write_env(&mut engine, &parsed_env)?;
+    let msg_ptr = engine.write_to_memory(&validated_msg)?;
+
+    // This wrapper is used to coalesce all errors in this block to one object
+    // so we can `.map_err()` in one place for all of them
+    let output = coalesce!(CallError, {
+        // State written before the migration stays encrypted under the old contract key.
+        // Link it to the new key before running the new code, so the new code can read it.
+        engine.link_previous_contract_key(&contract_key)?;
+
+        let vec_ptr = engine.migrate(env_ptr, msg_ptr)?;
+
+        let output = engine.extract_vector(vec_ptr)?;
+        engine.flush_storage()?;
+
+        let output = encrypt_output(
+            output,
+            secret_msg.nonce,
+            secret_msg.user_public_key,
+            &canonical_contract_address,
+            engine.output_limits(),
+            engine.execution_path(),
+            None,
+        )?;
+        Ok(output)
+    })
+    .map_err(|err| {
+        *used_gas = engine.gas_used();
+        err
+    })?;
+
+    *used_gas = engine.gas_used();
+    Ok(MigrateSuccess {
+        output,
+        new_contract_key,
+    })
+}
+
+pub fn reply(
     context: Ctx,
     gas_limit: u64,
+    used_gas: &mut u64,
     contract: &[u8],
-    contract_key: &ContractKey,
-    operation: ContractOperation,
-    nonce: IoNonce,
-    user_public_key: Ed25519PublicKey,
-) -> Result<Engine, EnclaveError> {
-    info!("Deserializing Wasm contract");
+    env: &[u8],
+    msg: &[u8], // a `SignedReply`
+) -> Result<HandleSuccess, CallError> {
+    check_gas_limit(gas_limit, used_gas)?;
 
-    // Create a parity-wasm module first, so we can inject gas metering to it
-    // (you need a parity-wasm module to use the pwasm-utils crate)
-    let mut p_modlue: Module =
-        elements::deserialize_buffer(contract).map_err(|_| EnclaveError::InvalidWasm)?;
+    let contract_hash = calc_contract_hash(contract);
 
-    info!("Deserialized Wasm contract");
+    let mut parsed_env: Env = serde_json::from_slice(env).map_err(|err| {
+        warn!(
+            "got an error while trying to deserialize env input bytes into json {:?}: {}",
+            String::from_utf8_lossy(&env),
+            err
+        );
+        EnclaveError::FailedToDeserialize
+    })?;
+    activate_key_generations(&parsed_env);
 
-    info!("Validating WASM memory demands");
+    trace!("reply parsed_env: {:?}", parsed_env);
 
-    validate_memory(&mut p_modlue)?;
+    let signed_reply: SignedReply = serde_json::from_slice(msg).map_err(|err| {
+        warn!(
+            "got an error while trying to deserialize reply input bytes into json {:?}: {}",
+            String::from_utf8_lossy(&msg),
+            err
+        );
+        EnclaveError::FailedToDeserialize
+    })?;
 
-    info!("Validated WASM memory demands");
+    if signed_reply.nonce.len() != 32 || signed_reply.user_public_key.len() != 32 {
+        warn!("Reply has an invalid nonce or public key");
+        return Err(EnclaveError::FailedToDeserialize.into());
+    }
+    let mut nonce: IoNonce = [0u8; 32];
+    nonce.copy_from_slice(signed_reply.nonce.as_slice());
+    let mut user_public_key: Ed25519PublicKey = [0u8; 32];
+    user_public_key.copy_from_slice(signed_reply.user_public_key.as_slice());
 
-    // Set the gas costs for wasm op-codes (there is an inline stack_height limit in WasmCosts)
-    let wasm_costs = WasmCosts::default();
+    let contract_key = extract_contract_key(&parsed_env)?;
 
-    // Inject gas metering to pwasm module
-    let contract_module = pwasm_utils::inject_gas_counter(p_modlue, &gas_rules(&wasm_costs))
-        .map_err(|_| EnclaveError::FailedGasMeteringInjection)?;
+    let canonical_contract_address = CanonicalAddr::from_human(&parsed_env.contract.address).map_err(|err| {
+        warn!(
+            "got an error while trying to deserialize parsed_env.contract.address from bech32 string to bytes {:?}: {}",
+            parsed_env.contract.address, err
+        );
+        EnclaveError::FailedToDeserialize
+    })?;
 
-    info!("Trying to create Wasmi module from parity...");
+    if !validate_contract_key_for_hash(
+        &contract_key,
+        &(canonical_contract_address.0).0,
+        &contract_hash,
+    ) {
+        warn!("got an error while trying to deserialize output bytes");
+        return Err(EnclaveError::FailedContractAuthentication.into());
+    }
 
-    // Create a wasmi module from the parity module
-    let module = wasmi::Module::from_parity_wasm_module(contract_module)
-        .map_err(|_err| EnclaveError::InvalidWasm)?;
+    trace!("Successfully authenticated the contract!");
 
-    info!("Created Wasmi module from parity. Now checking for floating points...");
+    // Only the contract that sent the submessage may get a reply for it
+    verify_reply(
+        &canonical_contract_address,
+        &signed_reply,
+        &nonce,
+        &user_public_key,
+    )?;
+    // A reply can itself run for a submessage of the contract that called this one
+    let reply_target = verify_reply_to(&mut parsed_env, &nonce, &user_public_key)?;
+
+    let reply = Reply {
+        id: signed_reply.id,
+        result: signed_reply.result,
+    };
+    let reply_msg = serde_json::to_vec(&reply).map_err(|err| {
+        warn!(
+            "got an error while trying to serialize reply into bytes {:?}: {}",
+            reply, err
+        );
+        EnclaveError::FailedToSerialize
+    })?;
 
-    module
-        .deny_floating_point()
-        .map_err(|_err| EnclaveError::WasmModuleWithFP)?;
+    let mut engine = start_engine(
+        context,
+        gas_limit,
+        contract,
+        &contract_hash,
+        &contract_key,
+        ContractOperation::Reply,
+        nonce,
+        user_public_key,
+        None,
+    )?;
+    engine.set_contract_address(canonical_contract_address.clone());
+    apply_chain_settings(&mut engine, &mut parsed_env)?;
 
-    // Create new imports resolver.
-    // These are the signatures of rust functions available to invoke from wasm code.
-    let resolver = WasmiImportResolver {};
-    let imports_builder = create_builder(&resolver);
+    parsed_env.contract_code_hash = hex::encode(contract_hash);
+    // Replies are not signed by the user, so there is no randomness or memo to give the contract
+    parsed_env.random = None;
+    parsed_env.transaction = TransactionInfo::default();
 
-    // Instantiate a module with our imports and assert that there is no `start` function.
-    let module_instance = ModuleInstance::new(&module, &imports_builder).map_err(|err| {
-        warn!("Error in instantiation: {:?}", err);
-        EnclaveError::InvalidWasm
+    let env_ptr = write_env(&mut engine, &parsed_env)?;
+    let msg_ptr = engine.write_to_memory(&reply_msg)?;
+
+    // This wrapper is used to coalesce all errors in this block to one object
+    // so we can `.map_err()` in one place for all of them
+    let output = coalesce!(CallError, {
+        let vec_ptr = engine.reply(env_ptr, msg_ptr)?;
+
+        let output = engine.extract_vector(vec_ptr)?;
+        engine.flush_storage()?;
+
+        let output = encrypt_output(
+            output,
+            nonce,
+            user_public_key,
+            &canonical_contract_address,
+            engine.output_limits(),
+            engine.execution_path(),
+            reply_target.as_ref(),
+        )?;
+        Ok(output)
+    })
+    .map_err(|err| {
+        *used_gas = engine.gas_used();
+        err
     })?;
-    if module_instance.has_start() {
-        return Err(EnclaveError::WasmModuleWithStart);
+
+    *used_gas = engine.gas_used_after_refund();
+    Ok(HandleSuccess {
+        output,
+        gas_breakdown: engine.gas_breakdown(),
+    })
+}
+
+/// The IBC entry points that have nothing secret in them: the channel handshake, and the
+/// acknowledgements and timeouts of packets the contract sent. Packets sent to the contract go
+/// through `ibc_packet_receive`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IbcEntryPoint {
+    ChannelOpen,
+    ChannelConnect,
+    ChannelClose,
+    PacketAck,
+    PacketTimeout,
+}
+
+impl IbcEntryPoint {
+    pub fn export_name(self) -> &'static str {
+        match self {
+            IbcEntryPoint::ChannelOpen => "ibc_channel_open",
+            IbcEntryPoint::ChannelConnect => "ibc_channel_connect",
+            IbcEntryPoint::ChannelClose => "ibc_channel_close",
+            IbcEntryPoint::PacketAck => "ibc_packet_ack",
+            IbcEntryPoint::PacketTimeout => "ibc_packet_timeout",
+        }
+    }
+
+    /// The name of the ecall that runs this entry point.
+    pub fn ecall_name(self) -> &'static str {
+        match self {
+            IbcEntryPoint::ChannelOpen => "ecall_ibc_channel_open",
+            IbcEntryPoint::ChannelConnect => "ecall_ibc_channel_connect",
+            IbcEntryPoint::ChannelClose => "ecall_ibc_channel_close",
+            IbcEntryPoint::PacketAck => "ecall_ibc_packet_ack",
+            IbcEntryPoint::PacketTimeout => "ecall_ibc_packet_timeout",
+        }
     }
-    let module = module_instance.not_started_instance().clone();
+}
+
+/// Run one of the IBC entry points that aren't encrypted to anyone. The message must be for the
+/// port of the contract, and the output of the contract is passed on in plaintext.
+pub fn ibc(
+    context: Ctx,
+    gas_limit: u64,
+    used_gas: &mut u64,
+    contract: &[u8],
+    env: &[u8],
+    msg: &[u8],
+    entry_point: IbcEntryPoint,
+) -> Result<IbcSuccess, CallError> {
+    check_gas_limit(gas_limit, used_gas)?;
+
+    let contract_hash = calc_contract_hash(contract);
+    let (parsed_env, contract_key, canonical_contract_address) =
+        authenticate_ibc_env(env, &contract_hash)?;
+
+    // The contract gets the message as it was checked here
+    let (port_id, ibc_msg) = match entry_point {
+        IbcEntryPoint::ChannelOpen
+        | IbcEntryPoint::ChannelConnect
+        | IbcEntryPoint::ChannelClose => {
+            let channel: IbcChannel = deserialize_ibc_msg(msg)?;
+            (
+                channel.endpoint.port_id.clone(),
+                serialize_ibc_msg(&channel)?,
+            )
+        }
+        IbcEntryPoint::PacketAck => {
+            let ack: IbcAcknowledgement = deserialize_ibc_msg(msg)?;
+            (
+                ack.original_packet.src.port_id.clone(),
+                serialize_ibc_msg(&ack)?,
+            )
+        }
+        IbcEntryPoint::PacketTimeout => {
+            let packet: IbcPacket = deserialize_ibc_msg(msg)?;
+            (packet.src.port_id.clone(), serialize_ibc_msg(&packet)?)
+        }
+    };
+    verify_ibc_port(&port_id, &parsed_env.contract.address)?;
+
+    // There is no sender to encrypt to, but the contract may still query other contracts, and
+    // queries are encrypted. Nobody has the private key of a public key that is derived from a
+    // hash, so only the enclave can read these queries.
+    let nonce: IoNonce = sha_256(&ibc_msg);
+    let user_public_key: Ed25519PublicKey = sha_256(&nonce);
 
-    let contract_instance = ContractInstance::new(
+    let mut engine = start_engine(
         context,
-        module.clone(),
         gas_limit,
-        wasm_costs,
-        *contract_key,
-        operation,
+        contract,
+        &contract_hash,
+        &contract_key,
+        ContractOperation::Ibc,
         nonce,
         user_public_key,
-    );
+        None,
+    )?;
+    engine.set_contract_address(canonical_contract_address);
+
+    let env_ptr = write_ibc_env(&mut engine, parsed_env, &contract_hash)?;
+    let msg_ptr = engine.write_to_memory(&ibc_msg)?;
 
-    Ok(Engine::new(contract_instance, module))
+    // This wrapper is used to coalesce all errors in this block to one object
+    // so we can `.map_err()` in one place for all of them
+    let output = coalesce!(CallError, {
+        let vec_ptr = engine.ibc(entry_point.export_name(), env_ptr, msg_ptr)?;
+
+        let output = engine.extract_vector(vec_ptr)?;
+        engine.flush_storage()?;
+
+        let output = check_public_output(output, engine.output_limits())?;
+        Ok(output)
+    })
+    .map_err(|err| {
+        *used_gas = engine.gas_used();
+        err
+    })?;
+
+    *used_gas = engine.gas_used_after_refund();
+    Ok(IbcSuccess {
+        output,
+        gas_breakdown: engine.gas_breakdown(),
+    })
+}
+
+/// Run `ibc_packet_receive` for a packet sent to the contract.
+///
+/// The data of the packet is a `SecretMessage`, encrypted like the message of a tx and bound to
+/// the code hash of the contract. The contract gets it decrypted, and its output and
+/// acknowledgement are encrypted to the sender of the packet, see `encrypt_ack`.
+pub fn ibc_packet_receive(
+    context: Ctx,
+    gas_limit: u64,
+    used_gas: &mut u64,
+    contract: &[u8],
+    env: &[u8],
+    msg: &[u8], // an `IbcPacket`
+) -> Result<IbcReceiveSuccess, CallError> {
+    check_gas_limit(gas_limit, used_gas)?;
+
+    let contract_hash = calc_contract_hash(contract);
+    let (parsed_env, contract_key, canonical_contract_address) =
+        authenticate_ibc_env(env, &contract_hash)?;
+
+    let mut packet: IbcPacket = deserialize_ibc_msg(msg)?;
+    verify_ibc_port(&packet.dest.port_id, &parsed_env.contract.address)?;
+
+    let secret_msg = SecretMessage::from_slice(packet.data.as_slice())?;
+    let decrypted_msg = secret_msg.decrypt()?;
+    packet.data = Binary(validate_msg_for_hash(&decrypted_msg, &contract_hash)?);
+
+    trace_secret!(
+        "IBC packet data after decryption: {:?}",
+        String::from_utf8_lossy(packet.data.as_slice())
+    );
+
+    let ibc_msg = serialize_ibc_msg(&packet)?;
+
+    let mut engine = start_engine(
+        context,
+        gas_limit,
+        contract,
+        &contract_hash,
+        &contract_key,
+        ContractOperation::Ibc,
+        secret_msg.nonce,
+        secret_msg.user_public_key,
+        None,
+    )?;
+    engine.set_contract_address(canonical_contract_address.clone());
+
+    let env_ptr = write_ibc_env(&mut engine, parsed_env, &contract_hash)?;
+    let msg_ptr = engine.write_to_memory(&ibc_msg)?;
+
+    // This wrapper is used to coalesce all errors in this block to one object
+    // so we can `.map_err()` in one place for all of them
+    let (output, acknowledgement) = coalesce!(CallError, {
+        let vec_ptr = engine.ibc("ibc_packet_receive", env_ptr, msg_ptr)?;
+
+        let output = engine.extract_vector(vec_ptr)?;
+        engine.flush_storage()?;
+
+        encrypt_receive_output(
+            output,
+            secret_msg.nonce,
+            secret_msg.user_public_key,
+            &packet.src,
+            packet.sequence,
+            &canonical_contract_address,
+            engine.output_limits(),
+        )
+    })
+    .map_err(|err| {
+        *used_gas = engine.gas_used();
+        err
+    })?;
+
+    *used_gas = engine.gas_used_after_refund();
+    Ok(IbcReceiveSuccess {
+        output,
+        acknowledgement,
+        gas_breakdown: engine.gas_breakdown(),
+    })
+}
+
+/// Parse the env of an IBC entry point, and authenticate the contract it's for
+fn authenticate_ibc_env(
+    env: &[u8],
+    contract_hash: &module_cache::CodeHash,
+) -> Result<(Env, ContractKey, CanonicalAddr), EnclaveError> {
+    let parsed_env: Env = serde_json::from_slice(env).map_err(|err| {
+        warn!(
+            "got an error while trying to deserialize env input bytes into json {:?}: {}",
+            String::from_utf8_lossy(&env),
+            err
+        );
+        EnclaveError::FailedToDeserialize
+    })?;
+    activate_key_generations(&parsed_env);
+
+    trace!("ibc parsed_env: {:?}", parsed_env);
+
+    verify_contract_code_hash(&parsed_env, contract_hash)?;
+
+    let contract_key = extract_contract_key(&parsed_env)?;
+
+    let canonical_contract_address = CanonicalAddr::from_human(&parsed_env.contract.address).map_err(|err| {
+        warn!(
+            "got an error while trying to deserialize parsed_env.contract.address from bech32 string to bytes {:?}: {}",
+            parsed_env.contract.address, err
+        );
+        EnclaveError::FailedToDeserialize
+    })?;
+
+    if !validate_contract_key_for_hash(
+        &contract_key,
+        &(canonical_contract_address.0).0,
+        contract_hash,
+    ) {
+        warn!("got an error while trying to deserialize output bytes");
+        return Err(EnclaveError::FailedContractAuthentication);
+    }
+
+    trace!("Successfully authenticated the contract!");
+
+    Ok((parsed_env, contract_key, canonical_contract_address))
+}
+
+fn write_ibc_env(
+    engine: &mut Engine,
+    mut env: Env,
+    contract_hash: &module_cache::CodeHash,
+) -> Result<u32, EnclaveError> {
+    apply_chain_settings(engine, &mut env)?;
+
+    env.contract_code_hash = hex::encode(contract_hash);
+    // IBC messages are relayed, not signed by a user, so there is no randomness or memo to give
+    // the contract
+    env.random = None;
+    env.transaction = TransactionInfo::default();
+
+    write_env(engine, &env)
+}
+
+/// Write the env to the memory of the contract, in the shape of the API version it was built for
+/// Start using the rotated consensus seeds that are active at the height of `env`, before any key
+/// is derived for the execution
+fn activate_key_generations(env: &Env) {
+    KEY_MANAGER.activate_generations(env.block.height);
+}
+
+fn write_env(engine: &mut Engine, env: &Env) -> Result<u32, EnclaveError> {
+    let encoded = encode_env(env, engine.api_version())?;
+    Ok(engine.write_to_memory(&encoded)?)
+}
+
+/// Write the message info to the memory of the contract, if it takes it apart from the env
+fn write_message_info(engine: &mut Engine, env: &Env) -> Result<Option<u32>, EnclaveError> {
+    match encode_message_info(env, engine.api_version())? {
+        Some(encoded) => Ok(Some(engine.write_to_memory(&encoded)?)),
+        None => Ok(None),
+    }
+}
+
+fn deserialize_ibc_msg<T: serde::de::DeserializeOwned>(msg: &[u8]) -> Result<T, EnclaveError> {
+    serde_json::from_slice(msg).map_err(|err| {
+        warn!(
+            "got an error while trying to deserialize IBC message bytes into json {:?}: {}",
+            String::from_utf8_lossy(&msg),
+            err
+        );
+        EnclaveError::FailedToDeserialize
+    })
+}
+
+fn serialize_ibc_msg<T: serde::Serialize>(msg: &T) -> Result<Vec<u8>, EnclaveError> {
+    serde_json::to_vec(msg).map_err(|err| {
+        warn!(
+            "got an error while trying to serialize IBC message into bytes: {}",
+            err
+        );
+        EnclaveError::FailedToSerialize
+    })
+}
+
+/// Rotate the key of a contract, re-encrypting one chunk of its storage under the new key.
+///
+/// A rotation starts with an empty cursor. That derives a new key and links it to the current
+/// one, so the contract can read its whole storage from the moment the host switches to the new
+/// key. Each call then moves up to `chunk_size` values to the new key, and returns the storage
+/// key the host should pass as the cursor of the next call, which is empty once all the values
+/// were moved. Calls that continue a rotation get the new key in the env.
+///
+/// The link to the previous key is kept after the rotation is done, since values written before
+/// range queries were supported have no key index, so they can't be listed and moved.
+///
+/// The rotation is charged like a contract: the enclave reports the gas of its own work in
+/// `used_gas`, and the storage gas the host charged counts towards `gas_limit` on top of it.
+pub fn rotate_contract_key(
+    context: Ctx,
+    gas_limit: u64,
+    used_gas: &mut u64,
+    env: &[u8],
+    sig_info: &[u8],
+    cursor: &[u8],
+    chunk_size: u32,
+) -> Result<RotateContractKeySuccess, EnclaveError> {
+    check_gas_limit(gas_limit, used_gas)?;
+
+    let parsed_env: Env = serde_json::from_slice(env).map_err(|err| {
+        warn!(
+            "got an error while trying to deserialize env input bytes into json {:?}: {}",
+            String::from_utf8_lossy(&env),
+            err
+        );
+        EnclaveError::FailedToDeserialize
+    })?;
+    activate_key_generations(&parsed_env);
+
+    trace!("rotate_contract_key parsed_env: {:?}", parsed_env);
+
+    let parsed_sig_info: SigInfo = serde_json::from_slice(sig_info).map_err(|err| {
+        warn!(
+            "got an error while trying to deserialize env input bytes into json {:?}: {}",
+            String::from_utf8_lossy(&sig_info),
+            err
+        );
+        EnclaveError::FailedToDeserialize
+    })?;
+
+    if chunk_size == 0 {
+        warn!("Tried to rotate a contract key with an empty chunk");
+        return Err(EnclaveError::ValidationFailure);
+    }
+
+    // Verify env parameters against the signed tx
+    verify_key_rotation_params(&parsed_sig_info, &parsed_env)?;
+
+    // Only the admin of the contract may rotate its key. Contracts that were instantiated before
+    // their admin was authenticated have no admin proof, and for them the admin the host reports
+    // is trusted, as it was before.
+    let admin_verification = match parsed_env.admin_proof {
+        Some(_) => verify_contract_admin(&parsed_env),
+        None => verify_reported_admin(&parsed_env),
+    };
+    admin_verification.map_err(|_err| EnclaveError::UnauthorizedKeyRotation)?;
+
+    let contract_key = extract_contract_key(&parsed_env)?;
+
+    let canonical_contract_address = CanonicalAddr::from_human(&parsed_env.contract.address).map_err(|err| {
+        warn!(
+            "got an error while trying to deserialize parsed_env.contract.address from bech32 string to bytes {:?}: {}",
+            parsed_env.contract.address, err
+        );
+        EnclaveError::FailedToDeserialize
+    })?;
+
+    let contract_hash = extract_contract_code_hash(&parsed_env)?;
+
+    if !validate_contract_key_for_hash(
+        &contract_key,
+        &(canonical_contract_address.0).0,
+        &contract_hash,
+    ) {
+        warn!("Contract key does not match the code of the contract");
+        return Err(EnclaveError::FailedContractAuthentication);
+    }
+
+    trace!("Successfully authenticated the contract!");
+
+    let mut gas = RotationGas::new(gas_limit);
+    let result = rotate_chunk(
+        &context,
+        &mut gas,
+        &parsed_env,
+        contract_key,
+        &canonical_contract_address,
+        &contract_hash,
+        cursor,
+        chunk_size,
+    );
+    *used_gas = gas.gas_used;
+    result
+}
+
+/// Gas the enclave charges for deriving the new key of a contract and linking it to the current one
+const ROTATE_KEY_GAS: u64 = 10_000;
+/// Gas the enclave charges for decrypting a value of the contract with the previous key and
+/// encrypting it again with the new one
+const ROTATE_VALUE_GAS: u64 = 2_000;
+
+/// The gas of a contract key rotation, which runs no contract to charge it to.
+struct RotationGas {
+    gas_limit: u64,
+    /// The gas of the enclave's own work, which is reported to the host
+    gas_used: u64,
+    /// The gas the host charged for storage, which only counts towards the limit
+    gas_used_by_storage: u64,
+}
+
+impl RotationGas {
+    fn new(gas_limit: u64) -> Self {
+        RotationGas {
+            gas_limit,
+            gas_used: 0,
+            gas_used_by_storage: 0,
+        }
+    }
+
+    fn use_gas(&mut self, gas_amount: u64) -> Result<(), EnclaveError> {
+        self.gas_used = self.gas_used.saturating_add(gas_amount);
+        self.check_gas_usage()
+    }
+
+    fn use_storage_gas(&mut self, gas_amount: u64) -> Result<(), EnclaveError> {
+        self.gas_used_by_storage = self.gas_used_by_storage.saturating_add(gas_amount);
+        self.check_gas_usage()
+    }
+
+    fn check_gas_usage(&self) -> Result<(), EnclaveError> {
+        if self.gas_used.saturating_add(self.gas_used_by_storage) > self.gas_limit {
+            warn!("Ran out of gas while rotating a contract key");
+            return Err(EnclaveError::OutOfGas);
+        }
+        Ok(())
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn rotate_chunk(
+    context: &Ctx,
+    gas: &mut RotationGas,
+    env: &Env,
+    contract_key: ContractKey,
+    contract_address: &CanonicalAddr,
+    contract_hash: &[u8; HASH_SIZE],
+    cursor: &[u8],
+    chunk_size: u32,
+) -> Result<RotateContractKeySuccess, EnclaveError> {
+    let (new_contract_key, previous_contract_key) = if cursor.is_empty() {
+        gas.use_gas(ROTATE_KEY_GAS)?;
+        let new_contract_key = generate_rotated_contract_key(
+            &contract_key,
+            env,
+            &(contract_address.0).0,
+            contract_hash,
+        )?;
+        gas.use_storage_gas(write_previous_contract_key(
+            context,
+            None,
+            &new_contract_key,
+            &contract_key,
+        )?)?;
+        (new_contract_key, contract_key)
+    } else {
+        let (previous_contract_key, gas_used) =
+            read_previous_contract_key(context, None, &contract_key)?;
+        gas.use_storage_gas(gas_used)?;
+        match previous_contract_key {
+            Some(previous_contract_key) => (contract_key, previous_contract_key),
+            None => {
+                warn!("Tried to continue a contract key rotation that was never started");
+                return Err(EnclaveError::ValidationFailure);
+            }
+        }
+    };
+    trace_secret!(
+        "Rotate: New Contract Key: {:?}",
+        new_contract_key.to_vec().as_slice()
+    );
+
+    // Values before the cursor were already moved to the new key
+    let start = Some(cursor).filter(|cursor| !cursor.is_empty());
+    let (keys, gas_used) = scan_encrypted_keys(start, None, context, None, &new_contract_key)?;
+    gas.use_storage_gas(gas_used)?;
+    let (chunk, next_cursor) = split_rotation_chunk(keys, chunk_size as usize);
+
+    info!("Rotating {} values of the contract storage", chunk.len());
+    for key in chunk {
+        gas.use_gas(ROTATE_VALUE_GAS)?;
+        gas.use_storage_gas(rotate_encrypted_key(
+            &key,
+            context,
+            &new_contract_key,
+            &previous_contract_key,
+        )?)?;
+    }
+
+    Ok(RotateContractKeySuccess {
+        new_contract_key,
+        next_cursor,
+    })
+}
+
+/// Authenticate the new admin of a contract, after its admin replaced it with `new_admin`, or
+/// removed it if `new_admin` is empty.
+///
+/// The host reports the current admin and its proof in the env. The sender must be that admin,
+/// and must have signed a `MsgUpdateAdmin` to `new_admin`, or a `MsgClearAdmin`, for this
+/// contract. The proof that is returned replaces the one the host stored for the contract.
+pub fn update_admin(
+    env: &[u8],
+    sig_info: &[u8],
+    new_admin: &[u8],
+) -> Result<[u8; HASH_SIZE], EnclaveError> {
+    let parsed_env: Env = serde_json::from_slice(env).map_err(|err| {
+        warn!(
+            "got an error while trying to deserialize env input bytes into json {:?}: {}",
+            String::from_utf8_lossy(&env),
+            err
+        );
+        EnclaveError::FailedToDeserialize
+    })?;
+    activate_key_generations(&parsed_env);
+
+    trace!("update_admin parsed_env: {:?}", parsed_env);
+
+    let parsed_sig_info: SigInfo = serde_json::from_slice(sig_info).map_err(|err| {
+        warn!(
+            "got an error while trying to deserialize env input bytes into json {:?}: {}",
+            String::from_utf8_lossy(&sig_info),
+            err
+        );
+        EnclaveError::FailedToDeserialize
+    })?;
+
+    let new_admin = if new_admin.is_empty() {
+        None
+    } else {
+        let new_admin = String::from_utf8(new_admin.to_vec()).map_err(|err| {
+            warn!("got a new admin that isn't a valid string: {}", err);
+            EnclaveError::FailedToDeserialize
+        })?;
+        Some(HumanAddr(new_admin))
+    };
+
+    // Verify env parameters against the signed tx
+    verify_admin_update_params(&parsed_sig_info, &parsed_env, new_admin.as_ref())?;
+
+    // Only the admin of the contract may replace it
+    verify_contract_admin(&parsed_env).map_err(|_err| EnclaveError::UnauthorizedAdminUpdate)?;
+
+    let canonical_contract_address = CanonicalAddr::from_human(&parsed_env.contract.address).map_err(|err| {
+        warn!(
+            "got an error while trying to deserialize parsed_env.contract.address from bech32 string to bytes {:?}: {}",
+            parsed_env.contract.address, err
+        );
+        EnclaveError::FailedToDeserialize
+    })?;
+
+    admin_proof_for(&canonical_contract_address, new_admin.as_ref())
+}
+
+/// The proof of `admin`, which was verified against a signed tx, see `generate_admin_proof`
+fn admin_proof_for(
+    contract_address: &CanonicalAddr,
+    admin: Option<&HumanAddr>,
+) -> Result<[u8; HASH_SIZE], EnclaveError> {
+    let admin = admin
+        .map(|admin| {
+            CanonicalAddr::from_human(admin).map_err(|err| {
+                warn!(
+                    "got an error while trying to deserialize the admin from bech32 string to bytes {:?}: {}",
+                    admin, err
+                );
+                EnclaveError::FailedToDeserialize
+            })
+        })
+        .transpose()?;
+
+    Ok(*generate_admin_proof(contract_address, admin.as_ref())?.as_bytes())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn start_engine(
+    context: Ctx,
+    gas_limit: u64,
+    contract: &[u8],
+    contract_hash: &module_cache::CodeHash,
+    contract_key: &ContractKey,
+    operation: ContractOperation,
+    nonce: IoNonce,
+    user_public_key: Ed25519PublicKey,
+    randomness: Option<MessageRandomness>,
+) -> Result<Engine, EnclaveError> {
+    metrics::record_contract_call(contract_hash);
+    let analyzed = {
+        let _span = trace_span!(Phase::LoadModule);
+        module_cache::get_or_analyze(contract_hash, contract.len(), || {
+            load_or_analyze_module(contract_hash, contract)
+        })?
+    };
+
+    // Set the gas costs for wasm op-codes (there is an inline stack_height limit in WasmCosts)
+    let wasm_costs = WasmCosts::for_api_version(analyzed.api_version);
+
+    // Instantiating the module copies its segments before the gas metering in it runs, so it's
+    // charged up front, and a contract that can't pay for it isn't instantiated at all
+    let instantiation_gas = analyzed
+        .segment_bytes
+        .saturating_mul(wasm_costs.memcpy as u64);
+    if instantiation_gas > gas_limit {
+        debug!(
+            "Instantiating the contract costs {} gas, more than its gas limit of {}",
+            instantiation_gas, gas_limit
+        );
+        return Err(EnclaveError::OutOfGas);
+    }
+
+    let _span = trace_span!(Phase::Instantiate);
+    let module = instantiate_module(&analyzed.module, analyzed.api_version)?
+        .not_started_instance()
+        .clone();
+
+    let is_query = operation.is_query();
+    let mut contract_instance = ContractInstance::new(
+        context,
+        module.clone(),
+        gas_limit,
+        wasm_costs,
+        *contract_key,
+        operation,
+        nonce,
+        user_public_key,
+        randomness,
+    );
+    contract_instance.use_instantiation_gas(instantiation_gas)?;
+
+    let mut engine = Engine::new(contract_instance, module, analyzed.api_version);
+    if !is_query {
+        engine.buffer_storage_writes();
+    }
+    #[cfg(feature = "debug-print")]
+    engine.set_trap_locations(analyzed.trap_locations);
+
+    Ok(engine)
+}
+
+/// The sender that `permit` authenticates for a query of the contract in `env`, see
+/// `query_permit`. Queries don't authenticate the contract key like txs do, so the permit is
+/// ignored unless the key the host passed is the one the enclave authenticated for the address of
+/// the contract, which the permit is checked against.
+fn query_permit_sender(
+    engine: &mut Engine,
+    permit: &QueryPermit,
+    env: &Env,
+    contract_key: &ContractKey,
+    contract_hash: &[u8; HASH_SIZE],
+) -> Result<Option<HumanAddr>, EnclaveError> {
+    if authenticated_contract_address(env, contract_key, contract_hash).is_none() {
+        warn!("Ignoring a query permit for a contract whose key isn't authenticated");
+        return Ok(None);
+    }
+
+    let sender = match verify_query_permit(permit, env, engine.bech32_prefix()) {
+        Some(sender) => sender,
+        None => return Ok(None),
+    };
+    if engine.is_query_permit_revoked(&permit.signer(), &permit.params.permit_name)? {
+        warn!(
+            "got a query permit that its signer revoked: {:?}",
+            permit.params.permit_name
+        );
+        return Ok(None);
+    }
+
+    Ok(Some(sender))
+}
+
+/// The address of the contract in `env`, if `contract_key` is the key of the contract at that
+/// address. Queries don't get the key authenticated otherwise.
+fn authenticated_contract_address(
+    env: &Env,
+    contract_key: &ContractKey,
+    contract_hash: &[u8; HASH_SIZE],
+) -> Option<CanonicalAddr> {
+    CanonicalAddr::from_human(&env.contract.address)
+        .ok()
+        .filter(|address| {
+            validate_contract_key_for_hash(contract_key, address.as_slice(), contract_hash)
+        })
+}
+
+/// Apply the settings the chain passes in the env, and take them out of it so the contract
+/// doesn't see them
+fn apply_chain_settings(engine: &mut Engine, env: &mut Env) -> Result<(), EnclaveError> {
+    if let Some(ocall_gas_costs) = env.ocall_gas_costs.take() {
+        engine.set_ocall_gas_costs(ocall_gas_costs);
+    }
+
+    if let Some(bech32_prefix) = env.bech32_prefix.take() {
+        if !is_valid_prefix(&bech32_prefix) {
+            warn!(
+                "got an invalid bech32 prefix in the env: {:?}",
+                bech32_prefix
+            );
+            return Err(EnclaveError::FailedToDeserialize);
+        }
+        engine.set_bech32_prefix(bech32_prefix);
+    }
+
+    // Only init uses it, and it already verified it by now
+    env.instantiation.take();
+
+    if let Some(header) = env.block_header.take() {
+        let hash = verify_block_header(&header, &env.block)?;
+        env.block.hash = Some(Binary(hash.to_vec()));
+        env.block.proposer_address = Some(header.proposer_address);
+    }
+
+    engine.set_block_time(env.block.time);
+
+    if env.foreign_chains.len() > MAX_FOREIGN_CHAINS {
+        warn!(
+            "got {} foreign chains in the env, more than the {} allowed",
+            env.foreign_chains.len(),
+            MAX_FOREIGN_CHAINS
+        );
+        return Err(EnclaveError::FailedToDeserialize);
+    }
+    engine.set_foreign_chains(std::mem::take(&mut env.foreign_chains));
+
+    if let Some(timeout_ms) = env.execution_timeout_ms.take() {
+        engine.set_execution_timeout(timeout_ms)?;
+    }
+
+    if let Some(output_limits) = env.output_limits.take() {
+        engine.set_output_limits(output_limits);
+    }
+
+    if let Some(block_gas_remaining) = env.block_gas_remaining.take() {
+        engine.limit_to_block_gas(block_gas_remaining);
+    }
+
+    if let Some(storage_quota) = env.storage_quota.take() {
+        engine.set_storage_quota(storage_quota);
+    }
+
+    if let Some(max_memory_pages) = env.max_memory_pages.take() {
+        engine.set_max_memory_pages(max_memory_pages);
+    }
+
+    if let Some(execution_path) = env.execution_path.take() {
+        engine.set_execution_path(execution_path);
+    }
+
+    // The contract only sees an admin the enclave authenticated
+    if env.contract.admin.is_some() && !is_authenticated_admin(env) {
+        debug!("Not passing an admin that wasn't authenticated to the contract");
+        env.contract.admin = None;
+    }
+    env.admin_proof.take();
+
+    Ok(())
+}
+
+/// Run all the checks a contract goes through when it's loaded, without running it, and cache the
+/// analyzed module so that the first call to the contract doesn't analyze it again.
+pub fn validate_code(
+    code_hash: &module_cache::CodeHash,
+    contract: &[u8],
+) -> Result<(), EnclaveError> {
+    warm_code(code_hash, contract).map(|_loaded| ())
+}
+
+/// Like `validate_code`, but also return whether the contract was loaded, or its module was
+/// already cached.
+pub fn warm_code(
+    code_hash: &module_cache::CodeHash,
+    contract: &[u8],
+) -> Result<bool, EnclaveError> {
+    let mut loaded = false;
+    let analyzed = module_cache::get_or_analyze(code_hash, contract.len(), || {
+        loaded = true;
+        load_or_analyze_module(code_hash, contract)
+    })?;
+    instantiate_module(&analyzed.module, analyzed.api_version)?;
+
+    Ok(loaded)
+}
+
+/// Resolve the imports of the module against the functions we provide to contracts of its
+/// CosmWasm API version, initialize its memory and table from its segments, and assert that it
+/// has no `start` function.
+fn instantiate_module(
+    module: &wasmi::Module,
+    api_version: ApiVersion,
+) -> Result<NotStartedModuleRef, EnclaveError> {
+    // Create new imports resolver.
+    // These are the signatures of rust functions available to invoke from wasm code.
+    let resolver = WasmiImportResolver { api_version };
+    let imports_builder = create_builder(&resolver);
+
+    let module_instance = ModuleInstance::new(module, &imports_builder).map_err(|err| {
+        warn!("Error in instantiation: {:?}", err);
+        instantiation_error(&err)
+    })?;
+    // Modules with a start function are rejected when they're analyzed, so this only keeps one
+    // that got here another way from running it
+    if module_instance.has_start() {
+        return Err(EnclaveError::InvalidWasm {
+            reason: InvalidWasmReason::StartFunction,
+        });
+    }
+
+    Ok(module_instance)
+}
+
+/// The error to report for a module wasmi failed to instantiate. The reasons only tell which part
+/// of the module failed, since the messages of wasmi may change between versions.
+fn instantiation_error(err: &wasmi::Error) -> EnclaveError {
+    let reason = match err {
+        // Data segments are copied into the memory with `MemoryInstance::set`, which fails for a
+        // segment that doesn't fit
+        wasmi::Error::Memory(_) => InstantiationFailureReason::DataSegmentOutOfBounds,
+        wasmi::Error::Table(_) => InstantiationFailureReason::ElementSegmentOutOfBounds,
+        // Element segments are checked before they're copied, and reported like an import that
+        // failed to resolve
+        wasmi::Error::Instantiation(message) if message.starts_with("elements segment") => {
+            InstantiationFailureReason::ElementSegmentOutOfBounds
+        }
+        wasmi::Error::Instantiation(_) => {
+            return EnclaveError::InvalidWasm {
+                reason: InvalidWasmReason::UnresolvedImports,
+            }
+        }
+        _ => InstantiationFailureReason::Other,
+    };
+
+    EnclaveError::InstantiationFailed { reason }
+}
+
+/// How many bytes instantiating the module copies into its memory and table: the contents of its
+/// data segments, and the index of every function in its element segments
+fn segment_bytes(module: &Module) -> u64 {
+    let data_bytes: u64 = module.data_section().map_or(0, |section| {
+        section
+            .entries()
+            .iter()
+            .map(|segment| segment.value().len() as u64)
+            .sum()
+    });
+    let element_members: u64 = module.elements_section().map_or(0, |section| {
+        section
+            .entries()
+            .iter()
+            .map(|segment| segment.members().len() as u64)
+            .sum()
+    });
+
+    data_bytes.saturating_add(element_members.saturating_mul(std::mem::size_of::<u32>() as u64))
+}
+
+/// Use the module the host kept from an earlier analysis of the contract, see `module_artifact`,
+/// or analyze the contract if there isn't a valid one.
+fn load_or_analyze_module(
+    code_hash: &module_cache::CodeHash,
+    contract: &[u8],
+) -> Result<AnalyzedModule, EnclaveError> {
+    if let Some(analyzed) = load_module_artifact(code_hash) {
+        return Ok(analyzed);
+    }
+
+    analyze_module(code_hash, contract)
+}
+
+#[cfg(not(feature = "debug-print"))]
+fn load_module_artifact(code_hash: &module_cache::CodeHash) -> Option<AnalyzedModule> {
+    let (instrumented, api_version) = module_artifact::load(code_hash)?;
+    let segment_bytes = segment_bytes(&instrumented);
+    let module = match build_wasmi_module(instrumented) {
+        Ok(module) => module,
+        Err(err) => {
+            debug!("Ignoring a module artifact that can't be loaded: {}", err);
+            return None;
+        }
+    };
+
+    trace!("Using the module artifact for code hash {:?}", code_hash);
+    Some(AnalyzedModule {
+        module: Arc::new(module),
+        api_version,
+        segment_bytes,
+    })
+}
+
+/// The trap locations of debug builds are found in the original bytecode, which artifacts don't
+/// keep, so these builds always analyze contracts.
+#[cfg(feature = "debug-print")]
+fn load_module_artifact(_code_hash: &module_cache::CodeHash) -> Option<AnalyzedModule> {
+    None
+}
+
+/// Deserialize and validate the contract, detect the CosmWasm API version it was built for, and
+/// instrument it with gas metering. The instrumented module is handed to the host to keep.
+#[cfg_attr(feature = "debug-print", allow(unused_variables))]
+fn analyze_module(
+    code_hash: &module_cache::CodeHash,
+    contract: &[u8],
+) -> Result<AnalyzedModule, EnclaveError> {
+    info!("Checking the Wasm contract for forbidden features");
+
+    reject_forbidden_features(contract)?;
+
+    info!("Deserializing Wasm contract");
+
+    // Create a parity-wasm module first, so we can inject gas metering to it
+    // (you need a parity-wasm module to use the pwasm-utils crate)
+    let mut p_modlue: Module =
+        elements::deserialize_buffer(contract).map_err(|_| EnclaveError::InvalidWasm {
+            reason: InvalidWasmReason::Malformed,
+        })?;
+
+    info!("Deserialized Wasm contract");
+
+    // These run before we add imports of our own to the module
+    let api_version = detect_api_version(&p_modlue)?;
+    #[cfg(feature = "debug-print")]
+    let trap_locations = trap_info::find_trap_locations(&p_modlue, contract);
+
+    info!("Validating WASM memory demands");
+
+    validate_memory(&mut p_modlue)?;
+
+    info!("Validated WASM memory demands");
+
+    let segment_bytes = segment_bytes(&p_modlue);
+
+    let p_modlue = hook_memory_grow(p_modlue);
+
+    // Set the gas costs for wasm op-codes (there is an inline stack_height limit in WasmCosts)
+    let wasm_costs = WasmCosts::default();
+
+    // Inject gas metering to pwasm module. The static cost of every block is charged once when
+    // the block starts, so the gas import isn't called for every instruction.
+    let contract_module = pwasm_utils::inject_gas_counter(p_modlue, &gas_rules(&wasm_costs))
+        .map_err(|_| EnclaveError::FailedGasMeteringInjection)?;
+
+    #[cfg(feature = "debug-print")]
+    let contract_module = trap_info::hook_unreachable(contract_module);
+
+    #[cfg(not(feature = "debug-print"))]
+    let instrumented = contract_module.clone();
+
+    let module = build_wasmi_module(contract_module)?;
+
+    #[cfg(not(feature = "debug-print"))]
+    module_artifact::store(code_hash, instrumented, api_version);
+
+    Ok(AnalyzedModule {
+        module: Arc::new(module),
+        api_version,
+        segment_bytes,
+        #[cfg(feature = "debug-print")]
+        trap_locations: Arc::new(trap_locations),
+    })
+}
+
+/// Create a wasmi module from an instrumented parity module
+fn build_wasmi_module(contract_module: Module) -> Result<wasmi::Module, EnclaveError> {
+    info!("Trying to create Wasmi module from parity...");
+
+    let module = wasmi::Module::from_parity_wasm_module(contract_module).map_err(|_err| {
+        EnclaveError::InvalidWasm {
+            reason: InvalidWasmReason::FailedValidation,
+        }
+    })?;
+
+    info!("Created Wasmi module from parity. Now checking for floating points...");
+
+    module
+        .deny_floating_point()
+        .map_err(|_err| EnclaveError::WasmModuleWithFP)?;
+
+    Ok(module)
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use parity_wasm::elements::{
+        DataSection, DataSegment, ExportEntry, ExportSection, InitExpr, Instruction, Internal,
+        MemorySection, MemoryType, Section,
+    };
+
+    const PAGE_SIZE: usize = 64 * 1024;
+
+    /// A module with `pages` pages of data, in a data segment for each page. Modules this large
+    /// are built here rather than kept in `testdata`.
+    fn module_with_data(pages: usize) -> Vec<u8> {
+        let segments = (0..pages)
+            .map(|page| {
+                let offset = InitExpr::new(vec![
+                    Instruction::I32Const((page * PAGE_SIZE) as i32),
+                    Instruction::End,
+                ]);
+                DataSegment::new(0, Some(offset), vec![0xab; PAGE_SIZE])
+            })
+            .collect();
+        let module = Module::new(vec![
+            Section::Memory(MemorySection::with_entries(vec![MemoryType::new(
+                pages as u32,
+                None,
+            )])),
+            Section::Export(ExportSection::with_entries(vec![ExportEntry::new(
+                "memory".to_string(),
+                Internal::Memory(0),
+            )])),
+            Section::Data(DataSection::with_entries(segments)),
+        ]);
+        elements::serialize(module).unwrap()
+    }
+
+    fn start(wasm: &[u8], gas_limit: u64) -> Result<Engine, EnclaveError> {
+        start_engine(
+            Ctx {
+                data: std::ptr::null_mut(),
+            },
+            gas_limit,
+            wasm,
+            &sha_256(wasm),
+            &[0u8; CONTRACT_KEY_LENGTH],
+            ContractOperation::Init,
+            [0u8; 32],
+            [0u8; 32],
+            None,
+        )
+    }
+
+    pub fn test_start_function_is_rejected_when_validated() {
+        let wasm = include_bytes!("../../testdata/wasm-features/start_function.wasm");
+        assert!(matches!(
+            validate_code(&sha_256(wasm), wasm),
+            Err(EnclaveError::InvalidWasm {
+                reason: InvalidWasmReason::StartFunction
+            })
+        ));
+    }
+
+    pub fn test_segments_that_dont_fit_fail_instantiation() {
+        let fixtures: &[(&[u8], InstantiationFailureReason)] = &[
+            (
+                include_bytes!("../../testdata/instantiation/out_of_bounds_element.wasm"),
+                InstantiationFailureReason::ElementSegmentOutOfBounds,
+            ),
+            (
+                include_bytes!("../../testdata/instantiation/out_of_bounds_data.wasm"),
+                InstantiationFailureReason::DataSegmentOutOfBounds,
+            ),
+        ];
+
+        for (wasm, expected) in fixtures {
+            match validate_code(&sha_256(wasm), wasm) {
+                Err(EnclaveError::InstantiationFailed { reason }) => assert_eq!(reason, *expected),
+                other => panic!("unexpected result {:?}", other),
+            }
+            assert!(matches!(
+                start(wasm, 1_000_000),
+                Err(EnclaveError::InstantiationFailed { .. })
+            ));
+        }
+
+        // The reason is part of the error that the sender of the contract sees
+        let wasm = include_bytes!("../../testdata/instantiation/out_of_bounds_element.wasm");
+        assert_eq!(
+            validate_code(&sha_256(wasm), wasm).unwrap_err().to_string(),
+            "failed to instantiate wasm code: an element segment does not fit in the table of the module"
+        );
+    }
+
+    pub fn test_segments_are_charged_before_instantiation() {
+        // 5MB of data segments
+        let wasm = module_with_data(80);
+        let data_bytes = (80 * PAGE_SIZE) as u64;
+
+        assert!(matches!(
+            start(&wasm, data_bytes - 1),
+            Err(EnclaveError::OutOfGas)
+        ));
+
+        let engine = start(&wasm, data_bytes).unwrap();
+        assert_eq!(engine.gas_used(), data_bytes);
+
+        // Element segments are charged for the index of each function
+        let wasm = include_bytes!("../../testdata/wasm-features/valid.wasm");
+        let module: Module = elements::deserialize_buffer(wasm).unwrap();
+        assert_eq!(segment_bytes(&module), 2 + 2 * 4);
+    }
+
+    pub fn test_rotation_gas_reports_only_the_enclave_gas() {
+        let mut gas = RotationGas::new(ROTATE_KEY_GAS + 3 * ROTATE_VALUE_GAS + 500);
+        gas.use_gas(ROTATE_KEY_GAS).unwrap();
+        gas.use_storage_gas(400).unwrap();
+        for _ in 0..3 {
+            gas.use_gas(ROTATE_VALUE_GAS).unwrap();
+        }
+        // The host already charged the storage gas
+        assert_eq!(gas.gas_used, ROTATE_KEY_GAS + 3 * ROTATE_VALUE_GAS);
+
+        // but it counts towards the limit
+        gas.use_storage_gas(100).unwrap();
+        assert!(matches!(
+            gas.use_storage_gas(1),
+            Err(EnclaveError::OutOfGas)
+        ));
+        assert!(matches!(
+            gas.use_gas(ROTATE_VALUE_GAS),
+            Err(EnclaveError::OutOfGas)
+        ));
+        assert_eq!(gas.gas_used, ROTATE_KEY_GAS + 4 * ROTATE_VALUE_GAS);
+    }
 }
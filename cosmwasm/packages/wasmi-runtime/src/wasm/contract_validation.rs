@@ -1,13 +1,20 @@
 use log::*;
+use serde_json::Value;
 
+use crate::consts::MAX_MEMO_CHARACTERS;
 use crate::cosmwasm::encoding::Binary;
+use crate::cosmwasm::ibc::contract_port_id;
+use crate::cosmwasm::sign_mode_direct::parse_direct_sign_doc;
 use crate::cosmwasm::types::{
-    CanonicalAddr, Coin, CosmosSignature, Env, SigInfo, SignDoc, SignDocWasmMsg,
+    CanonicalAddr, Coin, CosmosSignature, Env, HumanAddr, PubKeyKind, SigInfo, SignDoc,
+    SignDocWasmMsg, SignMode, SignedReply, StdFee, SubMsgResult,
 };
 use crate::crypto::traits::PubKey;
-use crate::crypto::{sha_256, AESKey, Hmac, Kdf, HASH_SIZE, KEY_MANAGER};
-use crate::wasm::io;
-use crate::wasm::types::SecretMessage;
+use crate::crypto::{
+    sha_256, AESKey, AuthenticationCode, Ed25519PublicKey, Hmac, Kdf, HASH_SIZE, KEY_MANAGER,
+};
+use crate::wasm::io::{self, ReplyTarget};
+use crate::wasm::types::{IoNonce, SecretMessage};
 use enclave_ffi_types::EnclaveError;
 
 pub type ContractKey = [u8; CONTRACT_KEY_LENGTH];
@@ -46,7 +53,7 @@ pub fn generate_encryption_key(
     );
 
     encryption_key[0..32].copy_from_slice(&sender_id);
-    encryption_key[32..].copy_from_slice(&authenticated_contract_id);
+    encryption_key[32..].copy_from_slice(authenticated_contract_id.as_bytes());
 
     Ok(encryption_key)
 }
@@ -89,58 +96,369 @@ pub fn generate_contract_id(
     sender_id: &[u8; HASH_SIZE],
     code_hash: &[u8; HASH_SIZE],
     contract_address: &[u8],
-) -> [u8; HASH_SIZE] {
+) -> AuthenticationCode {
     let authentication_key = consensus_state_ikm.derive_key_from_this(sender_id.as_ref());
 
     let mut input_data = sender_id.to_vec();
     input_data.extend_from_slice(code_hash);
     input_data.extend_from_slice(contract_address);
-    authentication_key.sign_sha_256(&input_data)
+    AuthenticationCode::new(authentication_key.sign_sha_256(&input_data))
 }
 
 pub fn calc_contract_hash(contract_bytes: &[u8]) -> [u8; HASH_SIZE] {
     sha_256(&contract_bytes)
 }
 
-pub fn validate_contract_key(
+pub fn validate_contract_key_for_hash(
     contract_key: &[u8; CONTRACT_KEY_LENGTH],
     contract_address: &[u8],
-    contract_code: &[u8],
+    contract_hash: &[u8; HASH_SIZE],
 ) -> bool {
     // parse contract key -> < signer_id || authentication_code >
     let mut signer_id: [u8; HASH_SIZE] = [0u8; HASH_SIZE];
     signer_id.copy_from_slice(&contract_key[0..HASH_SIZE]);
 
-    let mut expected_authentication_id: [u8; HASH_SIZE] = [0u8; HASH_SIZE];
-    expected_authentication_id.copy_from_slice(&contract_key[HASH_SIZE..]);
+    let expected_authentication_id = &contract_key[HASH_SIZE..];
 
-    // calculate contract hash
-    let contract_hash = calc_contract_hash(contract_code);
+    // the contract may have been instantiated under any of the consensus seeds before this one
+    KEY_MANAGER
+        .get_all_consensus_state_ikms()
+        .iter()
+        .any(|enclave_key| {
+            // calculate the authentication_id
+            let calculated_authentication_id =
+                generate_contract_id(enclave_key, &signer_id, contract_hash, contract_address);
 
-    // get the enclave key
-    let enclave_key = KEY_MANAGER
-        .get_consensus_state_ikm()
-        .map_err(|_err| {
-            warn!("Error extracting consensus_state_key");
-            false
+            calculated_authentication_id.verify(expected_authentication_id)
         })
-        .unwrap();
+}
+
+/// Whether the contract at `contract_address` runs the code with `contract_hash`, going by what
+/// the chain state has for it: the contract key, and the hash of the code its contract info points
+/// to. Both must be for `contract_hash`, and the enclave must have authenticated the key for it.
+/// There's no contract at an address the host has no key for.
+///
+/// A migration changes the code of the contract info and its key together. The key from before a
+/// migration still authenticates the old code hash, but it no longer matches the code of the
+/// contract info, so it doesn't vouch for the old code.
+pub fn contract_key_matches_code_hash(
+    contract_key: Option<&[u8]>,
+    registered_code_hash: &[u8],
+    contract_address: &[u8],
+    contract_hash: &[u8; HASH_SIZE],
+) -> bool {
+    let contract_key = match contract_key {
+        Some(contract_key) if contract_key.len() == CONTRACT_KEY_LENGTH => contract_key,
+        _ => return false,
+    };
+    if registered_code_hash != contract_hash {
+        return false;
+    }
+
+    let mut key_as_bytes = [0u8; CONTRACT_KEY_LENGTH];
+    key_as_bytes.copy_from_slice(contract_key);
+    validate_contract_key_for_hash(&key_as_bytes, contract_address, contract_hash)
+}
+
+/// Re-authenticate an existing contract key for new contract code.
+///
+/// The signer id of the contract is kept as is, so the new key can still be traced back to
+/// the original instantiation. Only the authentication code is re-derived for the new code hash.
+pub fn generate_migrated_contract_key(
+    contract_key: &[u8; CONTRACT_KEY_LENGTH],
+    contract_address: &[u8],
+    new_contract_code: &[u8],
+) -> Result<[u8; CONTRACT_KEY_LENGTH], EnclaveError> {
+    let consensus_state_ikm = KEY_MANAGER.get_consensus_state_ikm().map_err(|_err| {
+        warn!("Error extracting consensus_state_key");
+        EnclaveError::FailedContractAuthentication
+    })?;
+
+    let mut signer_id: [u8; HASH_SIZE] = [0u8; HASH_SIZE];
+    signer_id.copy_from_slice(&contract_key[0..HASH_SIZE]);
+
+    let new_contract_hash = calc_contract_hash(new_contract_code);
+
+    let authenticated_contract_id = generate_contract_id(
+        &consensus_state_ikm,
+        &signer_id,
+        &new_contract_hash,
+        contract_address,
+    );
+
+    let mut new_contract_key = [0u8; CONTRACT_KEY_LENGTH];
+    new_contract_key[0..HASH_SIZE].copy_from_slice(&signer_id);
+    new_contract_key[HASH_SIZE..].copy_from_slice(authenticated_contract_id.as_bytes());
+
+    Ok(new_contract_key)
+}
+
+/// Authenticate `admin` as the admin of the contract at `contract_address`, or that the contract has
+/// no admin if it's None.
+///
+/// The host stores the proof next to the contract key, and passes it back in the env along with
+/// the admin, so the enclave doesn't have to trust the host about who the admin is. The proof only
+/// changes when the admin does, so a host that kept the proof of a replaced admin can still claim
+/// that admin.
+pub fn generate_admin_proof(
+    contract_address: &CanonicalAddr,
+    admin: Option<&CanonicalAddr>,
+) -> Result<AuthenticationCode, EnclaveError> {
+    let consensus_state_ikm = KEY_MANAGER.get_consensus_state_ikm().map_err(|_err| {
+        warn!("Error extracting consensus_state_key");
+        EnclaveError::FailedContractAuthentication
+    })?;
+
+    Ok(admin_proof_with(
+        &consensus_state_ikm,
+        contract_address,
+        admin,
+    ))
+}
+
+fn admin_proof_with(
+    consensus_state_ikm: &AESKey,
+    contract_address: &CanonicalAddr,
+    admin: Option<&CanonicalAddr>,
+) -> AuthenticationCode {
+    let authentication_key = consensus_state_ikm.derive_key_from_this(b"contract_admin");
+
+    // The address is length prefixed, so it can't be confused with the admin that follows it
+    let mut input_data = vec![contract_address.len() as u8];
+    input_data.extend_from_slice(contract_address.as_slice());
+    if let Some(admin) = admin {
+        input_data.extend_from_slice(admin.as_slice());
+    }
+    AuthenticationCode::new(authentication_key.sign_sha_256(&input_data))
+}
+
+/// Whether the admin the host reports in the env, or that there is none, is what the enclave
+/// authenticated with `env.admin_proof`.
+pub fn is_authenticated_admin(env: &Env) -> bool {
+    let admin_proof = match &env.admin_proof {
+        Some(admin_proof) => admin_proof,
+        None => return false,
+    };
+    let contract_address = match CanonicalAddr::from_human(&env.contract.address) {
+        Ok(contract_address) => contract_address,
+        Err(_) => return false,
+    };
+    let admin = match env.contract.admin.as_ref().map(CanonicalAddr::from_human) {
+        Some(Ok(admin)) => Some(admin),
+        Some(Err(_)) => return false,
+        None => None,
+    };
+
+    // The proof may have been generated under any of the consensus seeds before this one
+    KEY_MANAGER
+        .get_all_consensus_state_ikms()
+        .iter()
+        .any(|consensus_state_ikm| {
+            admin_proof_with(consensus_state_ikm, &contract_address, admin.as_ref())
+                .verify(admin_proof.as_slice())
+        })
+}
+
+/// Verify that the sender of a migration is the admin of the contract, as reported in the env and
+/// authenticated by `env.admin_proof`.
+pub fn verify_contract_admin(env: &Env) -> Result<(), EnclaveError> {
+    if !is_authenticated_admin(env) {
+        warn!(
+            "The admin {:?} in the env is not the one the enclave authenticated",
+            env.contract.admin
+        );
+        return Err(EnclaveError::UnauthorizedMigration);
+    }
+
+    verify_reported_admin(env)
+}
+
+/// Verify that the sender is the admin of the contract as the host reports it in the env, without
+/// authenticating it. Only for contracts whose admin proof the chain doesn't keep.
+pub fn verify_reported_admin(env: &Env) -> Result<(), EnclaveError> {
+    match &env.contract.admin {
+        Some(admin) if *admin == env.message.sender => Ok(()),
+        Some(admin) => {
+            warn!(
+                "Sender {:?} is not the contract admin {:?}",
+                env.message.sender, admin
+            );
+            Err(EnclaveError::UnauthorizedMigration)
+        }
+        None => {
+            warn!("Tried to modify a contract that has no admin");
+            Err(EnclaveError::UnauthorizedMigration)
+        }
+    }
+}
+
+/// Parse the hex encoded code hash that the host reports in the env.
+pub fn extract_contract_code_hash(env: &Env) -> Result<[u8; HASH_SIZE], EnclaveError> {
+    let code_hash = hex::decode(&env.contract_code_hash).map_err(|err| {
+        warn!(
+            "got an error while trying to decode the code hash of the contract {:?}: {}",
+            env.contract_code_hash, err
+        );
+        EnclaveError::FailedToDeserialize
+    })?;
+    if code_hash.len() != HASH_SIZE {
+        warn!("Contract code hash has an invalid length");
+        return Err(EnclaveError::FailedToDeserialize);
+    }
+
+    let mut contract_hash = [0u8; HASH_SIZE];
+    contract_hash.copy_from_slice(&code_hash);
+    Ok(contract_hash)
+}
+
+/// Derive a fresh key for a contract whose key is rotated.
+///
+/// The signer id of the new key is bound to the key it replaces, so every rotation yields a
+/// different key, even if the admin rotates the key twice in the same block.
+pub fn generate_rotated_contract_key(
+    contract_key: &[u8; CONTRACT_KEY_LENGTH],
+    env: &Env,
+    contract_address: &[u8],
+    contract_hash: &[u8; HASH_SIZE],
+) -> Result<[u8; CONTRACT_KEY_LENGTH], EnclaveError> {
+    let consensus_state_ikm = KEY_MANAGER.get_consensus_state_ikm().map_err(|_err| {
+        warn!("Error extracting consensus_state_key");
+        EnclaveError::FailedContractAuthentication
+    })?;
+
+    let admin = CanonicalAddr::from_human(&env.message.sender).map_err(|err| {
+        warn!(
+            "got an error while trying to deserialize env.message.sender from bech32 string to bytes {:?}: {}",
+            env.message.sender, err
+        );
+        EnclaveError::FailedToDeserialize
+    })?;
+
+    let mut signer_id_data = generate_sender_id(&(admin.0).0, env.block.height).to_vec();
+    signer_id_data.extend_from_slice(contract_key);
+    let signer_id = sha_256(&signer_id_data);
+
+    let authenticated_contract_id = generate_contract_id(
+        &consensus_state_ikm,
+        &signer_id,
+        contract_hash,
+        contract_address,
+    );
+
+    let mut new_contract_key = [0u8; CONTRACT_KEY_LENGTH];
+    new_contract_key[0..HASH_SIZE].copy_from_slice(&signer_id);
+    new_contract_key[HASH_SIZE..].copy_from_slice(authenticated_contract_id.as_bytes());
+
+    Ok(new_contract_key)
+}
+
+/// Verify that a reply is for a submessage that this contract sent, in a tx encrypted with
+/// this nonce and public key, and that a submessage that called a contract gets the result that
+/// contract returned.
+///
+/// Errors, and the results of submessages that don't call a contract, come from the module, so
+/// there's no enclave that could sign them.
+pub fn verify_reply(
+    contract_address: &CanonicalAddr,
+    reply: &SignedReply,
+    nonce: &IoNonce,
+    user_public_key: &Ed25519PublicKey,
+) -> Result<(), EnclaveError> {
+    let sent_submessage = |calls_contract: bool| {
+        io::create_reply_signature(
+            contract_address,
+            reply.id,
+            calls_contract,
+            nonce,
+            user_public_key,
+        )
+        .verify(reply.reply_sig.as_slice())
+    };
+
+    let calls_contract = if sent_submessage(true) {
+        true
+    } else if sent_submessage(false) {
+        false
+    } else {
+        warn!(
+            "Reply signature does not match the submessage {} of the contract",
+            reply.id
+        );
+        return Err(EnclaveError::UnauthorizedReply);
+    };
+
+    if calls_contract && matches!(reply.result, SubMsgResult::Ok(_)) {
+        let result_sig = io::create_reply_result_signature(
+            contract_address,
+            reply.id,
+            nonce,
+            user_public_key,
+            &reply.result,
+        );
+        let signed = match &reply.result_sig {
+            Some(sig) => result_sig.verify(sig.as_slice()),
+            None => false,
+        };
+        if !signed {
+            warn!(
+                "The result of the submessage {} isn't the one the called contract returned",
+                reply.id
+            );
+            return Err(EnclaveError::UnauthorizedReply);
+        }
+    }
+
+    Ok(())
+}
+
+/// Check that the call runs for a submessage that `env.reply_to` says was sent in a tx encrypted
+/// with this nonce and public key. The result of the call is then signed for the reply to that
+/// submessage, see `create_reply_result_signature`.
+pub fn verify_reply_to(
+    env: &mut Env,
+    nonce: &IoNonce,
+    user_public_key: &Ed25519PublicKey,
+) -> Result<Option<ReplyTarget>, EnclaveError> {
+    let reply_to = match env.reply_to.take() {
+        Some(reply_to) => reply_to,
+        None => return Ok(None),
+    };
+
+    let contract = CanonicalAddr::from_human(&reply_to.contract).map_err(|err| {
+        warn!(
+            "got an error while trying to deserialize reply_to.contract from bech32 string to bytes {:?}: {}",
+            reply_to.contract, err
+        );
+        EnclaveError::FailedToDeserialize
+    })?;
 
-    // calculate the authentication_id
-    let calculated_authentication_id =
-        generate_contract_id(&enclave_key, &signer_id, &contract_hash, contract_address);
+    let reply_sig =
+        io::create_reply_signature(&contract, reply_to.id, true, nonce, user_public_key);
+    if !reply_sig.verify(reply_to.reply_sig.as_slice()) {
+        warn!(
+            "The call doesn't run for the submessage {} of {}",
+            reply_to.id, reply_to.contract
+        );
+        return Err(EnclaveError::UnauthorizedReply);
+    }
 
-    calculated_authentication_id == expected_authentication_id
+    Ok(Some(ReplyTarget {
+        contract,
+        id: reply_to.id,
+        instantiated: None,
+    }))
 }
 
-pub fn validate_msg(msg: &[u8], contract_code: &[u8]) -> Result<Vec<u8>, EnclaveError> {
+/// Check the message was sent to the code with this hash, and strip the hash from it
+pub fn validate_msg_for_hash(
+    msg: &[u8],
+    contract_hash: &[u8; HASH_SIZE],
+) -> Result<Vec<u8>, EnclaveError> {
     if msg.len() < HEX_ENCODED_HASH_SIZE {
         warn!("Malformed message - expected contract code hash to be prepended to the msg");
         return Err(EnclaveError::ValidationFailure);
     }
 
-    let calc_contract_hash = calc_contract_hash(contract_code);
-
     let mut received_contract_hash: [u8; HEX_ENCODED_HASH_SIZE] = [0u8; HEX_ENCODED_HASH_SIZE];
     received_contract_hash.copy_from_slice(&msg[0..HEX_ENCODED_HASH_SIZE]);
 
@@ -149,7 +467,7 @@ pub fn validate_msg(msg: &[u8], contract_code: &[u8]) -> Result<Vec<u8>, Enclave
         EnclaveError::ValidationFailure
     })?;
 
-    if decoded_hash != calc_contract_hash {
+    if decoded_hash != contract_hash {
         warn!("Message contains mismatched contract hash");
         return Err(EnclaveError::ValidationFailure);
     }
@@ -157,170 +475,675 @@ pub fn validate_msg(msg: &[u8], contract_code: &[u8]) -> Result<Vec<u8>, Enclave
     Ok(msg[HEX_ENCODED_HASH_SIZE..].to_vec())
 }
 
+/// The host reports the code hash of the contract in the env. It must be the hash of the code it
+/// passed to the enclave, otherwise the host is lying about what's being executed.
+pub fn verify_contract_code_hash(
+    env: &Env,
+    contract_hash: &[u8; HASH_SIZE],
+) -> Result<(), EnclaveError> {
+    if env.contract_code_hash.is_empty() {
+        warn!("Got an env without the contract code hash");
+        return Err(EnclaveError::ValidationFailure);
+    }
+
+    let claimed_hash = hex::decode(&env.contract_code_hash).map_err(|_| {
+        warn!("Got an env with a malformed contract code hash");
+        EnclaveError::ValidationFailure
+    })?;
+
+    if claimed_hash != contract_hash {
+        warn!(
+            "The env claims the contract code hash is {}, but the code has hash {}",
+            env.contract_code_hash,
+            hex::encode(contract_hash)
+        );
+        return Err(EnclaveError::ValidationFailure);
+    }
+
+    Ok(())
+}
+
+/// The address the compute module gives the contract `creator` instantiates with `init_msg` from
+/// the code with `code_hash`. This must stay the same as `contractAddress` in the keeper.
+pub fn derive_contract_address(
+    creator: &CanonicalAddr,
+    code_hash: &[u8; HASH_SIZE],
+    init_msg: &[u8],
+) -> CanonicalAddr {
+    let mut preimage = b"contract_instance".to_vec();
+    preimage.push(creator.len() as u8);
+    preimage.extend_from_slice(creator.as_slice());
+    preimage.extend_from_slice(code_hash);
+    preimage.extend_from_slice(&sha_256(init_msg));
+
+    CanonicalAddr(Binary(sha_256(&preimage)[..20].to_vec()))
+}
+
+/// The host picks the address of a new contract, and the contract key is derived from it. The
+/// address is derived again from the sender, the code and the encrypted init message, which the
+/// enclave verifies on its own, so the host can't make up a contract at the address of another
+/// one. Chain state like the instance counter of the compute module isn't used, as no signature
+/// covers it.
+pub fn verify_contract_address(
+    env: &Env,
+    contract_address: &CanonicalAddr,
+    contract_hash: &[u8; HASH_SIZE],
+    init_msg: &[u8],
+) -> Result<(), EnclaveError> {
+    let creator = CanonicalAddr::from_human(&env.message.sender).map_err(|err| {
+        warn!(
+            "Got an init from a sender that isn't an address {:?}: {}",
+            env.message.sender, err
+        );
+        EnclaveError::ValidationFailure
+    })?;
+
+    let expected_address = derive_contract_address(&creator, contract_hash, init_msg);
+    if expected_address != *contract_address {
+        warn!(
+            "The env claims the new contract is {}, but the instantiation of {} is at {:?}",
+            env.contract.address,
+            env.message.sender,
+            HumanAddr::from_canonical(&expected_address)
+        );
+        return Err(EnclaveError::ValidationFailure);
+    }
+
+    Ok(())
+}
+
+/// IBC messages are addressed to a port, and each contract has its own port. The address of the
+/// contract is authenticated by its key, so checking the port keeps the host from passing a
+/// packet or a channel of one contract to another.
+pub fn verify_ibc_port(port_id: &str, contract_address: &HumanAddr) -> Result<(), EnclaveError> {
+    let contract_port = contract_port_id(contract_address);
+    if port_id != contract_port {
+        warn!(
+            "Got an IBC message for port {:?}, but the contract has port {:?}",
+            port_id, contract_port
+        );
+        return Err(EnclaveError::UnauthorizedIbcPort);
+    }
+
+    Ok(())
+}
+
+/// What verifying a message against the signed tx it came in established
+#[derive(Debug)]
+pub struct VerifiedTx {
+    /// The signed message that matched the message sent to the contract
+    pub msg: SignDocWasmMsg,
+    /// Who paid the fee of the tx, see `verified_fee_payer`
+    pub fee_payer: Option<HumanAddr>,
+    /// The fee that was signed
+    pub fee_amount: Vec<Coin>,
+}
+
+impl VerifiedTx {
+    /// Set the fields of `env.transaction` that only the enclave may set
+    pub fn set_fee_info(&self, env: &mut Env) {
+        env.transaction.fee_payer = self.fee_payer.clone();
+        env.transaction.fee_amount = Some(self.fee_amount.clone());
+    }
+}
+
+/// Verify the env and the message against the signed tx, and return what was verified, or None
+/// for a message sent by a contract. `contract_hash` is the hash of the code the message is
+/// passed to, which the signatures of messages from CosmWasm 1.0 contracts cover.
 pub fn verify_params(
     sig_info: &SigInfo,
     env: &Env,
     msg: &SecretMessage,
-) -> Result<(), EnclaveError> {
+    contract_hash: &[u8; HASH_SIZE],
+) -> Result<Option<VerifiedTx>, EnclaveError> {
     info!("Verifying message signatures..");
 
+    if env.transaction.memo.chars().count() > MAX_MEMO_CHARACTERS {
+        warn!("Memo is longer than {} characters", MAX_MEMO_CHARACTERS);
+        return Err(EnclaveError::FailedTxVerification);
+    }
+
     // If there's no callback signature - it's not a callback and there has to be a tx signer + signature
     if let Some(callback_sig) = &sig_info.callback_sig {
+        // Messages sent by contracts aren't signed, so there's no memo to verify them against
+        if !env.transaction.memo.is_empty() {
+            warn!("Got a memo for a message sent by a contract");
+            return Err(EnclaveError::FailedTxVerification);
+        }
+
         if verify_callback_sig(
             callback_sig.as_slice(),
             &CanonicalAddr::from_human(&env.message.sender)
                 .or(Err(EnclaveError::FailedToSerialize))?,
             msg,
             &env.message.sent_funds,
+            contract_hash,
         ) {
             info!("Message verified! msg.sender is the calling contract");
-            return Ok(());
+            return Ok(None);
         }
 
         warn!("Callback signature verification failed");
     } else {
-        trace!(
-            "Sign bytes are: {:?}",
-            String::from_utf8_lossy(sig_info.sign_bytes.as_slice())
-        );
+        let msg_sender = CanonicalAddr::from_human(&env.message.sender).map_err(|err| {
+            warn!(
+                "got an error while trying to deserialize env.message.sender from bech32 string to bytes {:?}: {}",
+                env.message.sender, err
+            );
+            EnclaveError::FailedTxVerification
+        })?;
 
-        let sign_doc: SignDoc =
-            serde_json::from_slice(sig_info.sign_bytes.as_slice()).map_err(|err| {
-                warn!(
-                    "got an error while trying to deserialize sign doc bytes into json {:?}: {}",
-                    sig_info.sign_bytes.as_slice(),
-                    err
-                );
-                EnclaveError::FailedToDeserialize
-            })?;
+        // Every signer of the tx has to be verified, even if it is not the sender of this message
+        let mut verified_tx = None;
+        for (sign_bytes, signature) in sig_info.signers() {
+            let mut sign_doc = verify_signer(sign_bytes, signature, sig_info.sign_mode)?;
+            if sig_info.sign_mode == SignMode::LegacyAminoJson {
+                sign_doc.first_signer = first_amino_signer(sign_bytes.as_slice(), sig_info);
+            }
 
-        trace!("sign doc: {:?}", sign_doc);
+            // A multisig sender is matched by the address of the aggregate multisig pubkey
+            if verified_tx.is_none() && verify_sender(signature, &msg_sender) {
+                match verify_signature_params(&sign_doc, env, msg) {
+                    Some(signed_msg) => {
+                        verified_tx = Some(VerifiedTx {
+                            msg: signed_msg.clone(),
+                            fee_payer: verified_fee_payer(&sign_doc, sig_info)?,
+                            fee_amount: StdFee::from_fee(&sign_doc.fee).amount,
+                        })
+                    }
+                    None => {
+                        warn!("Parameter verification failed");
+                        return Err(EnclaveError::FailedTxVerification);
+                    }
+                }
 
-        // This verifies that signatures and sign bytes are self consistent
-        sig_info
-            .signature
-            .get_public_key()
-            .verify_bytes(
-                &sig_info.sign_bytes.as_slice(),
-                &sig_info.signature.get_signature().as_slice(),
-            )
-            .map_err(|err| {
-                warn!("Signature verification failed: {:?}", err);
-                EnclaveError::FailedTxVerification
-            })?;
+                info!("Parameters verified successfully");
+            }
+        }
 
-        if verify_signature_params(&sign_doc, sig_info, env, msg) {
-            info!("Parameters verified successfully");
-            return Ok(());
+        if verified_tx.is_some() {
+            return Ok(verified_tx);
         }
 
-        warn!("Parameter verification failed");
+        warn!("Sender verification failed!");
+        trace!(
+            "Message sender {:?} does not match with any of the message signers {:?}",
+            &env.message.sender,
+            sig_info
+                .signers()
+                .map(|(_, signature)| signature.get_public_key().get_address())
+                .collect::<Vec<CanonicalAddr>>()
+        );
     }
 
     Err(EnclaveError::FailedTxVerification)
 }
 
-fn verify_callback_sig(
-    callback_signature: &[u8],
-    sender: &CanonicalAddr,
-    msg: &SecretMessage,
-    sent_funds: &[Coin],
-) -> bool {
-    if callback_signature.is_empty() {
-        return false;
-    }
+/// Verify that the sender of a contract key rotation signed a request to rotate the key of this
+/// contract. Unlike other messages, a rotation carries no message for the contract to match.
+pub fn verify_key_rotation_params(sig_info: &SigInfo, env: &Env) -> Result<(), EnclaveError> {
+    info!("Verifying key rotation signatures..");
 
-    let callback_sig = io::create_callback_signature(sender, msg, sent_funds);
+    // Only the admin may rotate the key, so a rotation can't be sent by a contract
+    if sig_info.callback_sig.is_some() {
+        warn!("Got a contract key rotation that was sent by a contract");
+        return Err(EnclaveError::UnauthorizedKeyRotation);
+    }
 
-    if !callback_signature.eq(callback_sig.as_slice()) {
-        trace!(
-            "Contract signature does not match with the one sent: {:?}",
-            callback_signature
+    let msg_sender = CanonicalAddr::from_human(&env.message.sender).map_err(|err| {
+        warn!(
+            "got an error while trying to deserialize env.message.sender from bech32 string to bytes {:?}: {}",
+            env.message.sender, err
         );
-        return false;
+        EnclaveError::FailedTxVerification
+    })?;
+
+    // Every signer of the tx has to be verified, even if it is not the sender of this message
+    let mut sender_verified = false;
+    for (sign_bytes, signature) in sig_info.signers() {
+        let sign_doc = verify_signer(sign_bytes, signature, sig_info.sign_mode)?;
+
+        if !sender_verified && verify_sender(signature, &msg_sender) {
+            let signed_rotation = sign_doc.msgs.iter().any(|msg| {
+                matches!(msg, SignDocWasmMsg::RotateContractKey { .. })
+                    && verify_contract(msg, env)
+                    && verify_funds(msg, env)
+            });
+            if !signed_rotation {
+                warn!("The sender did not sign a key rotation for this contract");
+                return Err(EnclaveError::FailedTxVerification);
+            }
+
+            info!("Key rotation verified successfully");
+            sender_verified = true;
+        }
     }
 
-    true
+    if sender_verified {
+        return Ok(());
+    }
+
+    warn!("Sender verification failed!");
+    Err(EnclaveError::FailedTxVerification)
 }
 
-fn verify_sender(signature: &CosmosSignature, msg_sender: &CanonicalAddr) -> bool {
-    let sender_pubkey = signature.get_public_key();
-    let address = sender_pubkey.get_address();
+/// Verify that the sender of an admin update signed a request to make `new_admin` the admin of this
+/// contract, or to clear its admin if `new_admin` is None.
+pub fn verify_admin_update_params(
+    sig_info: &SigInfo,
+    env: &Env,
+    new_admin: Option<&HumanAddr>,
+) -> Result<(), EnclaveError> {
+    info!("Verifying admin update signatures..");
 
-    if address.eq(&msg_sender) {
-        return true;
+    // Only the admin may update the admin, so an update can't be sent by a contract
+    if sig_info.callback_sig.is_some() {
+        warn!("Got an admin update that was sent by a contract");
+        return Err(EnclaveError::UnauthorizedAdminUpdate);
     }
 
-    false
-}
+    let msg_sender = CanonicalAddr::from_human(&env.message.sender).map_err(|err| {
+        warn!(
+            "got an error while trying to deserialize env.message.sender from bech32 string to bytes {:?}: {}",
+            env.message.sender, err
+        );
+        EnclaveError::FailedTxVerification
+    })?;
 
-fn get_verified_msg<'a>(
-    sign_doc: &'a SignDoc,
-    sent_msg: &'a SecretMessage,
-) -> Option<&'a SignDocWasmMsg> {
-    sign_doc.msgs.iter().find(|&m| match m {
-        SignDocWasmMsg::Execute { msg, .. } | SignDocWasmMsg::Instantiate { init_msg: msg, .. } => {
-            let binary_msg_result = Binary::from_base64(msg);
-            if let Ok(binary_msg) = binary_msg_result {
-                return Binary(sent_msg.to_vec()) == binary_msg;
-            }
+    // Every signer of the tx has to be verified, even if it is not the sender of this message
+    let mut sender_verified = false;
+    for (sign_bytes, signature) in sig_info.signers() {
+        let sign_doc = verify_signer(sign_bytes, signature, sig_info.sign_mode)?;
 
-            false
-        }
-    })
-}
+        if !sender_verified && verify_sender(signature, &msg_sender) {
+            let signed_update = sign_doc.msgs.iter().any(|msg| {
+                let signed_admin = match msg {
+                    SignDocWasmMsg::UpdateAdmin { new_admin, .. } => Some(new_admin),
+                    SignDocWasmMsg::ClearAdmin { .. } => None,
+                    _ => return false,
+                };
+                signed_admin == new_admin && verify_contract(msg, env) && verify_funds(msg, env)
+            });
+            if !signed_update {
+                warn!("The sender did not sign this admin update for this contract");
+                return Err(EnclaveError::FailedTxVerification);
+            }
 
-fn verify_contract(msg: &SignDocWasmMsg, env: &Env) -> bool {
-    // Contract address is relevant only to execute, since during sending an instantiate message the contract address is not yet known
-    if let SignDocWasmMsg::Execute { contract, .. } = msg {
-        info!("Verifying contract address..");
-        if env.contract.address != *contract {
-            trace!(
-                "Contract address sent to enclave {:?} is not the same as the signed one {:?}",
-                env.contract.address,
-                *contract
-            );
-            return false;
+            info!("Admin update verified successfully");
+            sender_verified = true;
         }
     }
 
-    true
-}
-
-fn verify_funds(msg: &SignDocWasmMsg, env: &Env) -> bool {
-    match msg {
-        SignDocWasmMsg::Execute { sent_funds, .. }
-        | SignDocWasmMsg::Instantiate {
-            init_funds: sent_funds,
-            ..
-        } => &env.message.sent_funds == sent_funds,
+    if sender_verified {
+        return Ok(());
     }
+
+    warn!("Sender verification failed!");
+    Err(EnclaveError::FailedTxVerification)
 }
 
-fn verify_signature_params(
-    sign_doc: &SignDoc,
+/// Verify that the sender of a state import signed a request to import exactly `chunk` into this
+/// contract.
+pub fn verify_state_import_params(
     sig_info: &SigInfo,
     env: &Env,
-    sent_msg: &SecretMessage,
-) -> bool {
-    info!("Verifying sender..");
+    chunk: &[u8],
+) -> Result<(), EnclaveError> {
+    info!("Verifying state import signatures..");
 
-    let msg_sender = if let Ok(msg_sender) = CanonicalAddr::from_human(&env.message.sender) {
-        msg_sender
-    } else {
-        return false;
+    // Only the admin may import state, so an import can't be sent by a contract
+    if sig_info.callback_sig.is_some() {
+        warn!("Got a state import that was sent by a contract");
+        return Err(EnclaveError::UnauthorizedMigration);
+    }
+
+    let msg_sender = CanonicalAddr::from_human(&env.message.sender).map_err(|err| {
+        warn!(
+            "got an error while trying to deserialize env.message.sender from bech32 string to bytes {:?}: {}",
+            env.message.sender, err
+        );
+        EnclaveError::FailedTxVerification
+    })?;
+
+    // Every signer of the tx has to be verified, even if it is not the sender of this message
+    let mut sender_verified = false;
+    for (sign_bytes, signature) in sig_info.signers() {
+        let sign_doc = verify_signer(sign_bytes, signature, sig_info.sign_mode)?;
+
+        if !sender_verified && verify_sender(signature, &msg_sender) {
+            let signed_import = sign_doc.msgs.iter().any(|msg| match msg {
+                SignDocWasmMsg::ImportContractState {
+                    chunk: signed_chunk,
+                    ..
+                } => {
+                    signed_chunk.as_slice() == chunk
+                        && verify_contract(msg, env)
+                        && verify_funds(msg, env)
+                }
+                _ => false,
+            });
+            if !signed_import {
+                warn!("The sender did not sign this state import for this contract");
+                return Err(EnclaveError::FailedTxVerification);
+            }
+
+            info!("State import verified successfully");
+            sender_verified = true;
+        }
+    }
+
+    if sender_verified {
+        return Ok(());
+    }
+
+    warn!("Sender verification failed!");
+    Err(EnclaveError::FailedTxVerification)
+}
+
+/// Who paid the fee of the tx in `sign_doc`: the granter of the fee if it has one, else the payer
+/// the fee names, else the first signer of the tx, who pays by default. A payer has to be one of
+/// the signers of the tx, which were all verified, so the host can't make anyone else the payer.
+/// None if the fee names no one and the sign doc doesn't tell who signed first.
+fn verified_fee_payer(
+    sign_doc: &SignDoc,
+    sig_info: &SigInfo,
+) -> Result<Option<HumanAddr>, EnclaveError> {
+    let parse_address = |address: &HumanAddr| {
+        CanonicalAddr::from_human(address).map_err(|err| {
+            warn!("Got an invalid fee payer or granter {:?}: {}", address, err);
+            EnclaveError::FailedTxVerification
+        })
     };
 
-    if !verify_sender(&sig_info.signature, &msg_sender) {
-        warn!("Sender verification failed!");
+    let fee = StdFee::from_fee(&sign_doc.fee);
+    if let Some(payer) = &fee.payer {
+        let canonical_payer = parse_address(payer)?;
+        if !sig_info
+            .signers()
+            .any(|(_, signature)| signature.get_public_key().get_address() == canonical_payer)
+        {
+            warn!("The fee payer {:?} did not sign the tx", payer);
+            return Err(EnclaveError::FailedTxVerification);
+        }
+    }
+
+    if let Some(granter) = fee.granter {
+        parse_address(&granter)?;
+        return Ok(Some(granter));
+    }
+    if let Some(payer) = fee.payer {
+        return Ok(Some(payer));
+    }
+
+    Ok(sign_doc
+        .first_signer
+        .as_ref()
+        .and_then(|signer| HumanAddr::from_canonical(signer).ok()))
+}
+
+/// The fields that hold the signer of the common amino messages, e.g. `from_address` of a bank
+/// `MsgSend` or `sender` of the compute messages. A bank `MsgMultiSend` names its signers as the
+/// addresses of its `inputs` instead.
+const AMINO_SIGNER_FIELDS: &[&str] = &[
+    "sender",
+    "from_address",
+    "delegator_address",
+    "depositor",
+    "voter",
+    "proposer",
+    "validator_address",
+    "granter",
+    "signer",
+];
+
+/// The first signer of an amino tx, the signer of its first message. The signatures of the tx
+/// don't come in the order of the signers, so it's the only signer of a tx with one, and otherwise
+/// the signer the first message names in one of `AMINO_SIGNER_FIELDS`. Only a signer of the tx
+/// can be the first signer. None if the first message names none of them.
+fn first_amino_signer(sign_bytes: &[u8], sig_info: &SigInfo) -> Option<CanonicalAddr> {
+    let signers: Vec<CanonicalAddr> = sig_info
+        .signers()
+        .map(|(_, signature)| signature.get_public_key().get_address())
+        .collect();
+    if let [signer] = signers.as_slice() {
+        return Some(signer.clone());
+    }
+
+    let sign_doc: Value = serde_json::from_slice(sign_bytes).ok()?;
+    let first_msg = sign_doc.get("msgs")?.get(0)?.get("value")?;
+    let first_input = first_msg
+        .get("inputs")
+        .and_then(|inputs| inputs.get(0)?.get("address"));
+    AMINO_SIGNER_FIELDS
+        .iter()
+        .filter_map(|field| first_msg.get(field))
+        .chain(first_input)
+        .filter_map(Value::as_str)
+        .filter_map(|address| CanonicalAddr::from_human(&HumanAddr(address.to_string())).ok())
+        .find(|address| signers.contains(address))
+}
+
+/// Verify that the signature of a single signer matches the bytes it claims to have signed,
+/// and return the sign doc that was signed.
+fn verify_signer(
+    sign_bytes: &Binary,
+    signature: &CosmosSignature,
+    sign_mode: SignMode,
+) -> Result<SignDoc, EnclaveError> {
+    trace!(
+        "Sign bytes are: {:?}",
+        String::from_utf8_lossy(sign_bytes.as_slice())
+    );
+
+    let sign_doc: SignDoc = match sign_mode {
+        SignMode::LegacyAminoJson => {
+            serde_json::from_slice(sign_bytes.as_slice()).map_err(|err| {
+                warn!(
+                    "got an error while trying to deserialize sign doc bytes into json {:?}: {}",
+                    sign_bytes.as_slice(),
+                    err
+                );
+                EnclaveError::FailedToDeserialize
+            })?
+        }
+        SignMode::Direct => {
+            // Multisig signers can only sign amino sign docs
+            if let PubKeyKind::Multisig(_) = signature.get_public_key() {
+                warn!("Got a multisig signer for a SIGN_MODE_DIRECT sign doc");
+                return Err(EnclaveError::FailedTxVerification);
+            }
+
+            parse_direct_sign_doc(
+                sign_bytes.as_slice(),
+                &signature.get_public_key().get_address(),
+            )?
+        }
+    };
+
+    trace!("sign doc: {:?}", sign_doc);
+
+    // This verifies that signatures and sign bytes are self consistent
+    signature
+        .get_public_key()
+        .verify_bytes(sign_bytes.as_slice(), signature.get_signature().as_slice())
+        .map_err(|err| {
+            warn!("Signature verification failed: {:?}", err);
+            EnclaveError::FailedTxVerification
+        })?;
+
+    Ok(sign_doc)
+}
+
+fn verify_callback_sig(
+    callback_signature: &[u8],
+    sender: &CanonicalAddr,
+    msg: &SecretMessage,
+    sent_funds: &[Coin],
+    contract_hash: &[u8; HASH_SIZE],
+) -> bool {
+    // Signatures of CosmWasm 1.0 messages start with a version byte, older ones are just the hash
+    let (callback_sig, callback_signature) = match callback_signature.split_first() {
+        Some((&io::CALLBACK_SIG_V1, signature)) if signature.len() == HASH_SIZE => (
+            io::create_callback_signature_v1(sender, contract_hash, msg, sent_funds),
+            signature,
+        ),
+        _ if callback_signature.len() == HASH_SIZE => (
+            io::create_callback_signature(sender, msg, sent_funds),
+            callback_signature,
+        ),
+        _ => return false,
+    };
+
+    if !callback_sig.verify(callback_signature) {
         trace!(
-            "Message sender {:?} does not match with the message signer {:?}",
-            &env.message.sender,
-            &sig_info.signature.get_public_key().get_address()
+            "Contract signature does not match with the one sent: {:?}",
+            callback_signature
         );
         return false;
     }
 
+    true
+}
+
+fn verify_sender(signature: &CosmosSignature, msg_sender: &CanonicalAddr) -> bool {
+    let sender_pubkey = signature.get_public_key();
+    let address = sender_pubkey.get_address();
+
+    if address.eq(&msg_sender) {
+        return true;
+    }
+
+    false
+}
+
+fn get_verified_msg<'a>(
+    sign_doc: &'a SignDoc,
+    sent_msg: &'a SecretMessage,
+) -> Option<&'a SignDocWasmMsg> {
+    sign_doc.msgs.iter().find(|&m| match m {
+        SignDocWasmMsg::Execute { msg, .. }
+        | SignDocWasmMsg::Instantiate { init_msg: msg, .. }
+        | SignDocWasmMsg::Migrate { msg, .. } => {
+            let binary_msg_result = Binary::from_base64(msg);
+            if let Ok(binary_msg) = binary_msg_result {
+                return Binary(sent_msg.to_vec()) == binary_msg;
+            }
+
+            false
+        }
+        // Key rotations, admin updates and state imports carry no message for the contract
+        SignDocWasmMsg::RotateContractKey { .. }
+        | SignDocWasmMsg::UpdateAdmin { .. }
+        | SignDocWasmMsg::ClearAdmin { .. }
+        | SignDocWasmMsg::ImportContractState { .. } => false,
+    })
+}
+
+fn verify_contract(msg: &SignDocWasmMsg, env: &Env) -> bool {
+    // The contract address isn't known yet when an instantiate message is signed, it's derived
+    // from the signed message instead, see `verify_contract_address`
+    match msg {
+        SignDocWasmMsg::Execute { contract, .. }
+        | SignDocWasmMsg::Migrate { contract, .. }
+        | SignDocWasmMsg::RotateContractKey { contract }
+        | SignDocWasmMsg::UpdateAdmin { contract, .. }
+        | SignDocWasmMsg::ClearAdmin { contract }
+        | SignDocWasmMsg::ImportContractState { contract, .. } => {
+            info!("Verifying contract address..");
+            if env.contract.address != *contract {
+                trace!(
+                    "Contract address sent to enclave {:?} is not the same as the signed one {:?}",
+                    env.contract.address,
+                    *contract
+                );
+                return false;
+            }
+        }
+        // The code the contract is instantiated from must be the code the sender instantiated
+        SignDocWasmMsg::Instantiate { code_id, .. } => {
+            info!("Verifying code id..");
+            let instantiated_code_id = env
+                .instantiation
+                .map(|instantiation| instantiation.code_id.to_string());
+            if instantiated_code_id.as_ref() != Some(code_id) {
+                trace!(
+                    "Code id sent to enclave {:?} is not the same as the signed one {:?}",
+                    instantiated_code_id,
+                    code_id
+                );
+                return false;
+            }
+        }
+    }
+
+    true
+}
+
+fn verify_funds(msg: &SignDocWasmMsg, env: &Env) -> bool {
+    match msg {
+        SignDocWasmMsg::Execute { sent_funds, .. }
+        | SignDocWasmMsg::Instantiate {
+            init_funds: sent_funds,
+            ..
+        } => match (
+            canonical_funds(&env.message.sent_funds),
+            canonical_funds(sent_funds),
+        ) {
+            (Some(env_funds), Some(signed_funds)) => {
+                if env_funds != signed_funds {
+                    trace!(
+                        "Funds sent to enclave {:?} are not the same as the signed ones {:?}",
+                        env.message.sent_funds,
+                        sent_funds
+                    );
+                    return false;
+                }
+                true
+            }
+            _ => {
+                trace!(
+                    "Got a denom more than once in the funds sent to enclave {:?} or in the signed ones {:?}",
+                    env.message.sent_funds,
+                    sent_funds
+                );
+                false
+            }
+        },
+        // Migrations, key rotations, admin updates and state imports can't carry funds
+        SignDocWasmMsg::Migrate { .. }
+        | SignDocWasmMsg::RotateContractKey { .. }
+        | SignDocWasmMsg::UpdateAdmin { .. }
+        | SignDocWasmMsg::ClearAdmin { .. }
+        | SignDocWasmMsg::ImportContractState { .. } => env.message.sent_funds.is_empty(),
+    }
+}
+
+/// Sort funds by denom and drop zero amounts, so that lists that transfer the same funds compare
+/// equal. Returns None if a denom appears more than once, since the host could use that to make
+/// a contract count the same funds twice.
+fn canonical_funds(funds: &[Coin]) -> Option<Vec<&Coin>> {
+    let mut canonical: Vec<&Coin> = funds
+        .iter()
+        .filter(|coin| coin.amount.parse::<u128>() != Ok(0))
+        .collect();
+    canonical.sort_by(|a, b| a.denom.cmp(&b.denom));
+
+    if canonical
+        .windows(2)
+        .any(|pair| pair[0].denom == pair[1].denom)
+    {
+        return None;
+    }
+
+    Some(canonical)
+}
+
+/// The signed message that matches the sent message and the env, if there is one
+fn verify_signature_params<'a>(
+    sign_doc: &'a SignDoc,
+    env: &Env,
+    sent_msg: &SecretMessage,
+) -> Option<&'a SignDocWasmMsg> {
     info!("Verifying message..");
     // If msg is not found (is None) then it means message verification failed,
     // since it didn't find a matching signed message
@@ -332,20 +1155,1790 @@ fn verify_signature_params(
             sent_msg.to_vec(),
             sign_doc.msgs
         );
-        return false;
+        return None;
     }
     let msg = msg.unwrap();
 
     if !verify_contract(msg, env) {
         warn!("Contract address verification failed!");
-        return false;
+        return None;
     }
 
     info!("Verifying funds..");
     if !verify_funds(msg, env) {
         warn!("Funds verification failed!");
-        return false;
+        return None;
     }
 
-    true
+    info!("Verifying memo..");
+    if sign_doc.memo != env.transaction.memo {
+        warn!("Memo verification failed!");
+        trace!(
+            "Memo in env {:?} is not equal to the signed memo {:?}",
+            env.transaction.memo,
+            sign_doc.memo
+        );
+        return None;
+    }
+
+    Some(msg)
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use secp256k1::{Message, PublicKey, Secp256k1, SecretKey};
+    use serde_json::{json, Value};
+
+    use crate::cosmwasm::sign_mode_direct::{
+        Any, AuthInfo, DirectSignDoc, Fee, MsgExecuteContract, ProtoCoin, ProtoSecp256k1PubKey,
+        TxBody, TxSignerInfo,
+    };
+    use crate::cosmwasm::types::{
+        BlockInfo, ContractInfo, ContractInstantiation, CosmosMsg, HumanAddr, MessageInfo, ReplyTo,
+        SignerInfo, SubMsgExecutionResponse, SubMsgResult, TransactionInfo, WasmMsg, WasmOutput,
+    };
+    use crate::crypto::multisig::MultisigThresholdPubKey;
+    use crate::crypto::secp256k1::Secp256k1PubKey;
+
+    fn secret_key(seed: u8) -> SecretKey {
+        SecretKey::from_slice(&[seed; 32]).unwrap()
+    }
+
+    fn public_key(secret_key: &SecretKey) -> PubKeyKind {
+        let public_key = PublicKey::from_secret_key(&Secp256k1::signing_only(), secret_key);
+        PubKeyKind::Secp256k1(Secp256k1PubKey::new(public_key.serialize().to_vec()))
+    }
+
+    fn sign(secret_key: &SecretKey, sign_bytes: &[u8]) -> Vec<u8> {
+        let msg = Message::from_slice(&sha_256(sign_bytes)).unwrap();
+        Secp256k1::signing_only()
+            .sign(&msg, secret_key)
+            .serialize_compact()
+            .to_vec()
+    }
+
+    /// Amino encoding of a multisig signature: a 7 bytes header with the signers bit array,
+    /// followed by the prefixed and length delimited signatures.
+    fn multisig_signature(signatures: &[Vec<u8>]) -> Vec<u8> {
+        let mut encoded = vec![0u8; 7];
+        for signature in signatures {
+            encoded.push(0x12);
+            encoded.push(signature.len() as u8);
+            encoded.extend_from_slice(signature);
+        }
+        encoded
+    }
+
+    fn contract_address() -> HumanAddr {
+        HumanAddr::from_canonical(&CanonicalAddr(Binary(vec![1u8; 20]))).unwrap()
+    }
+
+    fn human_address(public_key: &PubKeyKind) -> HumanAddr {
+        HumanAddr::from_canonical(&public_key.get_address()).unwrap()
+    }
+
+    /// The hash of the code of `contract_address()`
+    fn contract_hash() -> [u8; HASH_SIZE] {
+        calc_contract_hash(b"the code of the contract")
+    }
+
+    fn secret_msg() -> SecretMessage {
+        SecretMessage {
+            nonce: [0u8; 32],
+            user_public_key: [0u8; 32],
+            msg: b"{\"nop\":{}}".to_vec(),
+        }
+    }
+
+    fn execute_msg(sender: &HumanAddr) -> Value {
+        json!({
+            "type": "wasm/MsgExecuteContract",
+            "value": {
+                "sender": sender.as_str(),
+                "contract": contract_address().as_str(),
+                "msg": Binary(secret_msg().to_vec()).to_base64(),
+                "sent_funds": []
+            }
+        })
+    }
+
+    fn execute_msg_with_funds(sender: &HumanAddr, sent_funds: Value) -> Value {
+        let mut msg = execute_msg(sender);
+        msg["value"]["sent_funds"] = sent_funds;
+        msg
+    }
+
+    fn coin(denom: &str, amount: u128) -> Coin {
+        Coin {
+            denom: denom.to_string(),
+            amount: amount.to_string(),
+        }
+    }
+
+    fn sign_doc_bytes(account_number: u64, msgs: Vec<Value>) -> Vec<u8> {
+        sign_doc_bytes_with_memo(account_number, msgs, "")
+    }
+
+    fn sign_doc_bytes_with_memo(account_number: u64, msgs: Vec<Value>, memo: &str) -> Vec<u8> {
+        serde_json::to_vec(&json!({
+            "account_number": account_number.to_string(),
+            "chain_id": "secret-testnet",
+            "fee": { "amount": [], "gas": "200000" },
+            "memo": memo,
+            "msgs": msgs,
+            "sequence": "0"
+        }))
+        .unwrap()
+    }
+
+    fn signer(secret_key: &SecretKey, sign_bytes: Vec<u8>) -> SignerInfo {
+        let signature = sign(secret_key, &sign_bytes);
+        SignerInfo {
+            sign_bytes: Binary(sign_bytes),
+            signature: CosmosSignature::new(public_key(secret_key), Binary(signature)),
+        }
+    }
+
+    fn sig_info(signer: SignerInfo, additional_signers: Vec<SignerInfo>) -> SigInfo {
+        SigInfo {
+            sign_bytes: signer.sign_bytes,
+            signature: signer.signature,
+            additional_signers,
+            callback_sig: None,
+            sign_mode: SignMode::LegacyAminoJson,
+        }
+    }
+
+    fn env(sender: HumanAddr) -> Env {
+        Env {
+            block: BlockInfo {
+                height: 1,
+                time: 1,
+                time_nanos: 0,
+                chain_id: "secret-testnet".to_string(),
+                hash: None,
+                proposer_address: None,
+            },
+            message: MessageInfo {
+                sender,
+                sent_funds: vec![],
+            },
+            contract: ContractInfo {
+                address: contract_address(),
+                admin: None,
+            },
+            contract_key: None,
+            contract_code_hash: String::new(),
+            random: None,
+            transaction: TransactionInfo::default(),
+            query_sender: None,
+            ocall_gas_costs: None,
+            bech32_prefix: None,
+            instantiation: None,
+            block_header: None,
+            execution_timeout_ms: None,
+            output_limits: None,
+            block_gas_remaining: None,
+            storage_quota: None,
+            max_memory_pages: None,
+            simulation: false,
+            client_query: false,
+            admin_proof: None,
+            execution_path: None,
+            reply_to: None,
+            foreign_chains: vec![],
+        }
+    }
+
+    fn env_with_funds(sender: HumanAddr, sent_funds: Vec<Coin>) -> Env {
+        let mut env = env(sender);
+        env.message.sent_funds = sent_funds;
+        env
+    }
+
+    fn env_with_memo(sender: HumanAddr, memo: &str) -> Env {
+        let mut env = env(sender);
+        env.transaction.memo = memo.to_string();
+        env
+    }
+
+    pub fn test_verify_params_single_signer() {
+        let key = secret_key(1);
+        let sender = human_address(&public_key(&key));
+        let sig_info = sig_info(
+            signer(&key, sign_doc_bytes(1, vec![execute_msg(&sender)])),
+            vec![],
+        );
+
+        assert!(verify_params(&sig_info, &env(sender), &secret_msg(), &contract_hash()).is_ok());
+    }
+
+    pub fn test_verify_params_sender_is_additional_signer() {
+        let other_key = secret_key(1);
+        let other = human_address(&public_key(&other_key));
+        let sender_key = secret_key(2);
+        let sender = human_address(&public_key(&sender_key));
+
+        let msgs = vec![execute_msg(&other), execute_msg(&sender)];
+        let sig_info = sig_info(
+            signer(&other_key, sign_doc_bytes(1, msgs.clone())),
+            vec![signer(&sender_key, sign_doc_bytes(2, msgs))],
+        );
+
+        assert!(verify_params(&sig_info, &env(sender), &secret_msg(), &contract_hash()).is_ok());
+    }
+
+    pub fn test_verify_params_amino_multisig_sender() {
+        let keys = vec![secret_key(1), secret_key(2), secret_key(3)];
+        let multisig_pubkey = PubKeyKind::Multisig(MultisigThresholdPubKey::new(
+            2,
+            keys.iter().map(public_key).collect(),
+        ));
+        let sender = human_address(&multisig_pubkey);
+
+        let sign_bytes = sign_doc_bytes(1, vec![execute_msg(&sender)]);
+        let signature =
+            multisig_signature(&[sign(&keys[0], &sign_bytes), sign(&keys[2], &sign_bytes)]);
+        let sig_info = sig_info(
+            SignerInfo {
+                sign_bytes: Binary(sign_bytes),
+                signature: CosmosSignature::new(multisig_pubkey, Binary(signature)),
+            },
+            vec![],
+        );
+
+        assert!(verify_params(&sig_info, &env(sender), &secret_msg(), &contract_hash()).is_ok());
+    }
+
+    pub fn test_verify_params_multi_send_payload() {
+        let first_key = secret_key(1);
+        let first = human_address(&public_key(&first_key));
+        let second_key = secret_key(2);
+        let second = human_address(&public_key(&second_key));
+
+        // A tx with a bank MsgMultiSend that has two inputs, bundled with a compute message
+        let multi_send = json!({
+            "type": "cosmos-sdk/MsgMultiSend",
+            "value": {
+                "inputs": [
+                    { "address": first.as_str(), "coins": [{ "denom": "uscrt", "amount": "1" }] },
+                    { "address": second.as_str(), "coins": [{ "denom": "uscrt", "amount": "1" }] }
+                ],
+                "outputs": [
+                    { "address": contract_address().as_str(), "coins": [{ "denom": "uscrt", "amount": "2" }] }
+                ]
+            }
+        });
+        let msgs = vec![multi_send, execute_msg(&second)];
+
+        let sig_info = sig_info(
+            signer(&first_key, sign_doc_bytes(1, msgs.clone())),
+            vec![signer(&second_key, sign_doc_bytes(2, msgs))],
+        );
+
+        assert!(verify_params(&sig_info, &env(second), &secret_msg(), &contract_hash()).is_ok());
+    }
+
+    pub fn test_verify_params_invalid_additional_signature() {
+        let sender_key = secret_key(1);
+        let sender = human_address(&public_key(&sender_key));
+        let other_key = secret_key(2);
+
+        let msgs = vec![execute_msg(&sender)];
+        let mut other_signer = signer(&other_key, sign_doc_bytes(2, msgs.clone()));
+        // Signed by the wrong key
+        other_signer.signature = CosmosSignature::new(
+            public_key(&other_key),
+            Binary(sign(&sender_key, other_signer.sign_bytes.as_slice())),
+        );
+
+        let sig_info = sig_info(
+            signer(&sender_key, sign_doc_bytes(1, msgs)),
+            vec![other_signer],
+        );
+
+        assert!(matches!(
+            verify_params(&sig_info, &env(sender), &secret_msg(), &contract_hash()),
+            Err(EnclaveError::FailedTxVerification)
+        ));
+    }
+
+    pub fn test_verify_params_sender_not_a_signer() {
+        let first_key = secret_key(1);
+        let first = human_address(&public_key(&first_key));
+        let second_key = secret_key(2);
+        let sender = human_address(&public_key(&secret_key(3)));
+
+        let msgs = vec![execute_msg(&first)];
+        let sig_info = sig_info(
+            signer(&first_key, sign_doc_bytes(1, msgs.clone())),
+            vec![signer(&second_key, sign_doc_bytes(2, msgs))],
+        );
+
+        assert!(matches!(
+            verify_params(&sig_info, &env(sender), &secret_msg(), &contract_hash()),
+            Err(EnclaveError::FailedTxVerification)
+        ));
+    }
+
+    fn funds_sig_info(key: &SecretKey, sender: &HumanAddr) -> SigInfo {
+        let sent_funds = json!([
+            { "denom": "uatom", "amount": "5" },
+            { "denom": "uscrt", "amount": "1000" }
+        ]);
+        sig_info(
+            signer(
+                key,
+                sign_doc_bytes(1, vec![execute_msg_with_funds(sender, sent_funds)]),
+            ),
+            vec![],
+        )
+    }
+
+    pub fn test_verify_params_sent_funds() {
+        let key = secret_key(1);
+        let sender = human_address(&public_key(&key));
+        let sig_info = funds_sig_info(&key, &sender);
+
+        let sent_funds = vec![coin("uatom", 5), coin("uscrt", 1000)];
+        assert!(verify_params(
+            &sig_info,
+            &env_with_funds(sender.clone(), sent_funds),
+            &secret_msg(),
+            &contract_hash()
+        )
+        .is_ok());
+
+        // The same funds in another order, with a zero amount
+        let sent_funds = vec![coin("uscrt", 1000), coin("ukrw", 0), coin("uatom", 5)];
+        assert!(verify_params(
+            &sig_info,
+            &env_with_funds(sender, sent_funds),
+            &secret_msg(),
+            &contract_hash()
+        )
+        .is_ok());
+    }
+
+    pub fn test_verify_params_sent_funds_mismatched_denom() {
+        let key = secret_key(1);
+        let sender = human_address(&public_key(&key));
+        let sig_info = funds_sig_info(&key, &sender);
+
+        let sent_funds = vec![coin("uatom", 5), coin("uscrx", 1000)];
+        assert!(matches!(
+            verify_params(
+                &sig_info,
+                &env_with_funds(sender, sent_funds),
+                &secret_msg(),
+                &contract_hash()
+            ),
+            Err(EnclaveError::FailedTxVerification)
+        ));
+    }
+
+    pub fn test_verify_params_sent_funds_mismatched_amount() {
+        let key = secret_key(1);
+        let sender = human_address(&public_key(&key));
+        let sig_info = funds_sig_info(&key, &sender);
+
+        let sent_funds = vec![coin("uatom", 5), coin("uscrt", 1001)];
+        assert!(matches!(
+            verify_params(
+                &sig_info,
+                &env_with_funds(sender.clone(), sent_funds),
+                &secret_msg(),
+                &contract_hash()
+            ),
+            Err(EnclaveError::FailedTxVerification)
+        ));
+        // Dropping a coin that was sent is a mismatch too
+        let sent_funds = vec![coin("uscrt", 1000)];
+        assert!(matches!(
+            verify_params(
+                &sig_info,
+                &env_with_funds(sender, sent_funds),
+                &secret_msg(),
+                &contract_hash()
+            ),
+            Err(EnclaveError::FailedTxVerification)
+        ));
+    }
+
+    pub fn test_verify_params_sent_funds_injected_coin() {
+        let key = secret_key(1);
+        let sender = human_address(&public_key(&key));
+        let sig_info = funds_sig_info(&key, &sender);
+
+        let sent_funds = vec![coin("uatom", 5), coin("uscrt", 1000), coin("ukrw", 7)];
+        assert!(matches!(
+            verify_params(
+                &sig_info,
+                &env_with_funds(sender.clone(), sent_funds),
+                &secret_msg(),
+                &contract_hash()
+            ),
+            Err(EnclaveError::FailedTxVerification)
+        ));
+        // The same denom twice, even if it adds up to a signed coin
+        let sent_funds = vec![coin("uatom", 5), coin("uscrt", 500), coin("uscrt", 500)];
+        assert!(matches!(
+            verify_params(
+                &sig_info,
+                &env_with_funds(sender.clone(), sent_funds),
+                &secret_msg(),
+                &contract_hash()
+            ),
+            Err(EnclaveError::FailedTxVerification)
+        ));
+        // Funds on a message that was signed without any
+        let no_funds_sig_info = sig_info(
+            signer(&key, sign_doc_bytes(1, vec![execute_msg(&sender)])),
+            vec![],
+        );
+        assert!(matches!(
+            verify_params(
+                &no_funds_sig_info,
+                &env_with_funds(sender, vec![coin("uscrt", 1)]),
+                &secret_msg(),
+                &contract_hash()
+            ),
+            Err(EnclaveError::FailedTxVerification)
+        ));
+    }
+
+    pub fn test_verify_params_memo() {
+        let key = secret_key(1);
+        let sender = human_address(&public_key(&key));
+        let sig_info = sig_info(
+            signer(
+                &key,
+                sign_doc_bytes_with_memo(1, vec![execute_msg(&sender)], "deposit 42"),
+            ),
+            vec![],
+        );
+
+        assert!(verify_params(
+            &sig_info,
+            &env_with_memo(sender, "deposit 42"),
+            &secret_msg(),
+            &contract_hash()
+        )
+        .is_ok());
+    }
+
+    pub fn test_verify_params_forged_memo() {
+        let key = secret_key(1);
+        let sender = human_address(&public_key(&key));
+        let sig_info = sig_info(
+            signer(
+                &key,
+                sign_doc_bytes_with_memo(1, vec![execute_msg(&sender)], "deposit 42"),
+            ),
+            vec![],
+        );
+
+        assert!(matches!(
+            verify_params(
+                &sig_info,
+                &env_with_memo(sender.clone(), "deposit 43"),
+                &secret_msg(),
+                &contract_hash()
+            ),
+            Err(EnclaveError::FailedTxVerification)
+        ));
+        // A memo that was signed can't be dropped either
+        assert!(matches!(
+            verify_params(&sig_info, &env(sender), &secret_msg(), &contract_hash()),
+            Err(EnclaveError::FailedTxVerification)
+        ));
+    }
+
+    pub fn test_verify_params_memo_too_long() {
+        let key = secret_key(1);
+        let sender = human_address(&public_key(&key));
+        let memo = "a".repeat(MAX_MEMO_CHARACTERS + 1);
+        let sig_info = sig_info(
+            signer(
+                &key,
+                sign_doc_bytes_with_memo(1, vec![execute_msg(&sender)], &memo),
+            ),
+            vec![],
+        );
+
+        assert!(matches!(
+            verify_params(
+                &sig_info,
+                &env_with_memo(sender, &memo),
+                &secret_msg(),
+                &contract_hash()
+            ),
+            Err(EnclaveError::FailedTxVerification)
+        ));
+    }
+
+    /// A `SIGN_MODE_DIRECT` sign doc of a tx with a single `MsgExecuteContract` of `secret_msg()`
+    /// to `contract_address()`, sent by the address of `secret_key(1)` with account number 1 and
+    /// sequence 0, and a fee of 50000uscrt for 200000 gas
+    const DIRECT_SIGN_DOC: &str =
+        "0aa9010aa6010a2a2f7365637265742e636f6d707574652e763162657461312e4d736745786563757465436f\
+         6e747261637412780a1479b000887626b294a914501a4cd226b58b2359831214010101010101010101010101\
+         01010101010101011a4a00000000000000000000000000000000000000000000000000000000000000000000\
+         0000000000000000000000000000000000000000000000000000000000007b226e6f70223a7b7d7d12660a4e\
+         0a460a1f2f636f736d6f732e63727970746f2e736563703235366b312e5075624b657912230a21031b84c556\
+         7b126440995d3ed5aaba0565d71e1834604819ff9c17f5e9d5dd078f12040a02080112140a0e0a0575736372\
+         741205353030303010c09a0c1a0e7365637265742d746573746e65742001";
+    /// The signature of `secret_key(1)` over `DIRECT_SIGN_DOC`
+    const DIRECT_SIGNATURE: &str =
+        "47d62b810ae3130dc2bbc9b13dac2c81e51b534321d5ea8404191d87f286fe35451047a544aee16fdf8d63a6\
+         ed2ed735e86a2d196cf853caee95c4ce19272169";
+
+    fn direct_sig_info(signer: SignerInfo) -> SigInfo {
+        SigInfo {
+            sign_mode: SignMode::Direct,
+            ..sig_info(signer, vec![])
+        }
+    }
+
+    pub fn test_verify_params_sign_mode_direct() {
+        let key = secret_key(1);
+        let sender = human_address(&public_key(&key));
+        let sig_info = direct_sig_info(SignerInfo {
+            sign_bytes: Binary(hex::decode(DIRECT_SIGN_DOC).unwrap()),
+            signature: CosmosSignature::new(
+                public_key(&key),
+                Binary(hex::decode(DIRECT_SIGNATURE).unwrap()),
+            ),
+        });
+
+        assert!(verify_params(&sig_info, &env(sender), &secret_msg(), &contract_hash()).is_ok());
+    }
+
+    pub fn test_parse_direct_sign_doc() {
+        let key = secret_key(1);
+        let sign_doc = parse_direct_sign_doc(
+            &hex::decode(DIRECT_SIGN_DOC).unwrap(),
+            &public_key(&key).get_address(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            serde_json::to_value(&sign_doc).unwrap(),
+            json!({
+                "account_number": "1",
+                "chain_id": "secret-testnet",
+                "fee": { "amount": [{ "denom": "uscrt", "amount": "50000" }], "gas": "200000" },
+                "memo": "",
+                "msgs": [{
+                    "type": "execute",
+                    "value": {
+                        "contract": contract_address().as_str(),
+                        "msg": Binary(secret_msg().to_vec()).to_base64(),
+                        "sent_funds": [],
+                        "callback_sig": null
+                    }
+                }],
+                "sequence": "0"
+            })
+        );
+    }
+
+    pub fn test_verify_params_sign_mode_direct_not_a_signer() {
+        // The sign doc is signed correctly, but its auth info and message are of another signer
+        let key = secret_key(2);
+        let sender = human_address(&public_key(&key));
+        let sig_info = direct_sig_info(signer(&key, hex::decode(DIRECT_SIGN_DOC).unwrap()));
+
+        assert!(matches!(
+            verify_params(&sig_info, &env(sender), &secret_msg(), &contract_hash()),
+            Err(EnclaveError::FailedTxVerification)
+        ));
+    }
+
+    pub fn test_verify_params_sign_mode_direct_forged_memo() {
+        let key = secret_key(1);
+        let sender = human_address(&public_key(&key));
+        let sig_info = direct_sig_info(signer(&key, hex::decode(DIRECT_SIGN_DOC).unwrap()));
+
+        assert!(matches!(
+            verify_params(
+                &sig_info,
+                &env_with_memo(sender, "deposit 42"),
+                &secret_msg(),
+                &contract_hash()
+            ),
+            Err(EnclaveError::FailedTxVerification)
+        ));
+    }
+
+    pub fn test_verify_params_sign_mode_mismatch() {
+        let key = secret_key(1);
+        let sender = human_address(&public_key(&key));
+
+        // Protobuf sign bytes can't be verified as amino sign bytes
+        let sig_info = sig_info(signer(&key, hex::decode(DIRECT_SIGN_DOC).unwrap()), vec![]);
+        assert!(matches!(
+            verify_params(
+                &sig_info,
+                &env(sender.clone()),
+                &secret_msg(),
+                &contract_hash()
+            ),
+            Err(EnclaveError::FailedToDeserialize)
+        ));
+
+        // And amino sign bytes can't be verified as protobuf sign bytes
+        let sig_info = direct_sig_info(signer(&key, sign_doc_bytes(1, vec![execute_msg(&sender)])));
+        assert!(verify_params(&sig_info, &env(sender), &secret_msg(), &contract_hash()).is_err());
+    }
+
+    fn proto_encode<M: prost::Message>(msg: &M) -> Vec<u8> {
+        let mut encoded = Vec::with_capacity(msg.encoded_len());
+        msg.encode(&mut encoded).unwrap();
+        encoded
+    }
+
+    fn proto_signer_info(secret_key: &SecretKey) -> TxSignerInfo {
+        let public_key = PublicKey::from_secret_key(&Secp256k1::signing_only(), secret_key);
+        TxSignerInfo {
+            public_key: Some(Any {
+                type_url: "/cosmos.crypto.secp256k1.PubKey".to_string(),
+                value: proto_encode(&ProtoSecp256k1PubKey {
+                    key: public_key.serialize().to_vec(),
+                }),
+            }),
+            sequence: 0,
+        }
+    }
+
+    /// The `SIGN_MODE_DIRECT` sign docs of a tx that sends `secret_msg()` to `contract_address()`
+    /// from the address of the first of `keys`, with a fee of 50000uscrt that names `payer` and
+    /// `granter`. All of `keys` sign the tx, in order, each with its own account number.
+    fn direct_signers(keys: &[SecretKey], payer: &str, granter: &str) -> Vec<SignerInfo> {
+        let body = TxBody {
+            messages: vec![Any {
+                type_url: "/secret.compute.v1beta1.MsgExecuteContract".to_string(),
+                value: proto_encode(&MsgExecuteContract {
+                    sender: public_key(&keys[0]).get_address().as_slice().to_vec(),
+                    contract: CanonicalAddr::from_human(&contract_address())
+                        .unwrap()
+                        .as_slice()
+                        .to_vec(),
+                    msg: secret_msg().to_vec(),
+                    ..Default::default()
+                }),
+            }],
+            ..Default::default()
+        };
+        let auth_info = AuthInfo {
+            signer_infos: keys.iter().map(proto_signer_info).collect(),
+            fee: Some(Fee {
+                amount: vec![ProtoCoin {
+                    denom: "uscrt".to_string(),
+                    amount: "50000".to_string(),
+                }],
+                gas_limit: 200000,
+                payer: payer.to_string(),
+                granter: granter.to_string(),
+            }),
+        };
+
+        keys.iter()
+            .enumerate()
+            .map(|(index, key)| {
+                let sign_doc = DirectSignDoc {
+                    body_bytes: proto_encode(&body),
+                    auth_info_bytes: proto_encode(&auth_info),
+                    chain_id: "secret-testnet".to_string(),
+                    account_number: index as u64 + 1,
+                };
+                signer(key, proto_encode(&sign_doc))
+            })
+            .collect()
+    }
+
+    fn direct_multi_sig_info(mut signers: Vec<SignerInfo>) -> SigInfo {
+        let first_signer = signers.remove(0);
+        SigInfo {
+            sign_mode: SignMode::Direct,
+            ..sig_info(first_signer, signers)
+        }
+    }
+
+    pub fn test_fee_payer_without_granter_is_first_signer() {
+        let key = secret_key(1);
+        let sender = human_address(&public_key(&key));
+        let sig_info = direct_multi_sig_info(direct_signers(&[key], "", ""));
+
+        let verified = verify_params(
+            &sig_info,
+            &env(sender.clone()),
+            &secret_msg(),
+            &contract_hash(),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(verified.fee_payer, Some(sender.clone()));
+        assert_eq!(verified.fee_amount, vec![coin("uscrt", 50000)]);
+
+        let mut env = env(sender.clone());
+        verified.set_fee_info(&mut env);
+        assert_eq!(env.transaction.fee_payer, Some(sender));
+        assert_eq!(env.transaction.fee_amount, Some(vec![coin("uscrt", 50000)]));
+    }
+
+    pub fn test_fee_payer_with_granter_is_granter() {
+        let key = secret_key(1);
+        let sender = human_address(&public_key(&key));
+        // The granter only grants an allowance, so it doesn't have to sign the tx
+        let granter = human_address(&public_key(&secret_key(9)));
+        let sig_info = direct_multi_sig_info(direct_signers(&[key], "", granter.as_str()));
+
+        let verified = verify_params(&sig_info, &env(sender), &secret_msg(), &contract_hash())
+            .unwrap()
+            .unwrap();
+        assert_eq!(verified.fee_payer, Some(granter));
+        assert_eq!(verified.fee_amount, vec![coin("uscrt", 50000)]);
+    }
+
+    pub fn test_fee_payer_named_in_fee() {
+        let sender_key = secret_key(1);
+        let payer_key = secret_key(2);
+        let sender = human_address(&public_key(&sender_key));
+        let payer = human_address(&public_key(&payer_key));
+        let sig_info =
+            direct_multi_sig_info(direct_signers(&[sender_key, payer_key], payer.as_str(), ""));
+
+        let verified = verify_params(&sig_info, &env(sender), &secret_msg(), &contract_hash())
+            .unwrap()
+            .unwrap();
+        assert_eq!(verified.fee_payer, Some(payer));
+    }
+
+    pub fn test_fee_payer_has_to_sign() {
+        let key = secret_key(1);
+        let sender = human_address(&public_key(&key));
+        let payer = human_address(&public_key(&secret_key(3)));
+        let sig_info = direct_multi_sig_info(direct_signers(&[key], payer.as_str(), ""));
+
+        assert!(matches!(
+            verify_params(&sig_info, &env(sender), &secret_msg(), &contract_hash()),
+            Err(EnclaveError::FailedTxVerification)
+        ));
+    }
+
+    pub fn test_fee_payer_amino_is_sender_of_first_msg() {
+        let key = secret_key(1);
+        let sender = human_address(&public_key(&key));
+        let sig_info = sig_info(
+            signer(&key, sign_doc_bytes(1, vec![execute_msg(&sender)])),
+            vec![],
+        );
+
+        let verified = verify_params(
+            &sig_info,
+            &env(sender.clone()),
+            &secret_msg(),
+            &contract_hash(),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(verified.fee_payer, Some(sender));
+        assert!(verified.fee_amount.is_empty());
+    }
+
+    fn send_msg(from_address: &HumanAddr) -> Value {
+        json!({
+            "type": "cosmos-sdk/MsgSend",
+            "value": {
+                "from_address": from_address.as_str(),
+                "to_address": contract_address().as_str(),
+                "amount": [{ "denom": "uscrt", "amount": "1" }]
+            }
+        })
+    }
+
+    pub fn test_fee_payer_amino_single_signer() {
+        let key = secret_key(1);
+        let sender = human_address(&public_key(&key));
+        let msgs = vec![send_msg(&sender), execute_msg(&sender)];
+        let sig_info = sig_info(signer(&key, sign_doc_bytes(1, msgs)), vec![]);
+
+        let verified = verify_params(
+            &sig_info,
+            &env(sender.clone()),
+            &secret_msg(),
+            &contract_hash(),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(verified.fee_payer, Some(sender));
+    }
+
+    pub fn test_fee_payer_amino_is_signer_of_first_msg() {
+        let sender_key = secret_key(1);
+        let payer_key = secret_key(2);
+        let sender = human_address(&public_key(&sender_key));
+        let payer = human_address(&public_key(&payer_key));
+        let msgs = vec![send_msg(&payer), execute_msg(&sender)];
+        let sig_info = sig_info(
+            signer(&sender_key, sign_doc_bytes(1, msgs.clone())),
+            vec![signer(&payer_key, sign_doc_bytes(2, msgs.clone()))],
+        );
+
+        let verified = verify_params(&sig_info, &env(sender), &secret_msg(), &contract_hash())
+            .unwrap()
+            .unwrap();
+        assert_eq!(verified.fee_payer, Some(payer.clone()));
+
+        // A first msg that names no signer of the tx tells nothing
+        let stranger = human_address(&public_key(&secret_key(3)));
+        let msgs = vec![send_msg(&stranger), execute_msg(&sender)];
+        let sig_info = sig_info(
+            signer(&sender_key, sign_doc_bytes(1, msgs.clone())),
+            vec![signer(&payer_key, sign_doc_bytes(2, msgs))],
+        );
+        let verified = verify_params(&sig_info, &env(sender), &secret_msg(), &contract_hash())
+            .unwrap()
+            .unwrap();
+        assert_eq!(verified.fee_payer, None);
+    }
+
+    pub fn test_fee_of_any_shape_verifies() {
+        let key = secret_key(1);
+        let sender = human_address(&public_key(&key));
+        let sign_bytes = serde_json::to_vec(&json!({
+            "account_number": "1",
+            "chain_id": "secret-testnet",
+            "fee": {
+                "amount": [{ "denom": "uscrt", "amount": "50000" }],
+                "gas": 200000,
+                "payer": "",
+                "tip": { "amount": [], "tipper": sender.as_str() }
+            },
+            "memo": "",
+            "msgs": [execute_msg(&sender)],
+            "sequence": "0"
+        }))
+        .unwrap();
+        let sig_info = sig_info(signer(&key, sign_bytes), vec![]);
+
+        let verified = verify_params(
+            &sig_info,
+            &env(sender.clone()),
+            &secret_msg(),
+            &contract_hash(),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(verified.fee_payer, Some(sender));
+        assert_eq!(verified.fee_amount, vec![coin("uscrt", 50000)]);
+    }
+
+    pub fn test_fee_payer_not_taken_from_host() {
+        let forged_payer = human_address(&public_key(&secret_key(3)));
+        let transaction: TransactionInfo = serde_json::from_value(json!({
+            "memo": "",
+            "fee_payer": forged_payer.as_str(),
+            "fee_amount": [{ "denom": "uscrt", "amount": "1" }]
+        }))
+        .unwrap();
+
+        assert_eq!(transaction.fee_payer, None);
+        assert_eq!(transaction.fee_amount, None);
+    }
+
+    fn rotate_contract_key_msg(sender: &HumanAddr, contract: &HumanAddr) -> Value {
+        json!({
+            "type": "wasm/MsgRotateContractKey",
+            "value": {
+                "sender": sender.as_str(),
+                "contract": contract.as_str(),
+                "chunk_size": 500
+            }
+        })
+    }
+
+    pub fn test_verify_key_rotation_params() {
+        let key = secret_key(1);
+        let sender = human_address(&public_key(&key));
+        let sig_info = sig_info(
+            signer(
+                &key,
+                sign_doc_bytes(
+                    1,
+                    vec![rotate_contract_key_msg(&sender, &contract_address())],
+                ),
+            ),
+            vec![],
+        );
+
+        assert!(verify_key_rotation_params(&sig_info, &env(sender)).is_ok());
+    }
+
+    pub fn test_verify_key_rotation_params_other_contract() {
+        let key = secret_key(1);
+        let sender = human_address(&public_key(&key));
+        let other_contract = human_address(&public_key(&secret_key(2)));
+        let sig_info = sig_info(
+            signer(
+                &key,
+                sign_doc_bytes(1, vec![rotate_contract_key_msg(&sender, &other_contract)]),
+            ),
+            vec![],
+        );
+
+        assert!(matches!(
+            verify_key_rotation_params(&sig_info, &env(sender)),
+            Err(EnclaveError::FailedTxVerification)
+        ));
+    }
+
+    pub fn test_verify_key_rotation_params_not_signed() {
+        // The sender only signed an execute, which must not authorize a rotation
+        let key = secret_key(1);
+        let sender = human_address(&public_key(&key));
+        let sig_info = sig_info(
+            signer(&key, sign_doc_bytes(1, vec![execute_msg(&sender)])),
+            vec![],
+        );
+
+        assert!(matches!(
+            verify_key_rotation_params(&sig_info, &env(sender)),
+            Err(EnclaveError::FailedTxVerification)
+        ));
+    }
+
+    pub fn test_verify_key_rotation_params_from_contract() {
+        let key = secret_key(1);
+        let sender = human_address(&public_key(&key));
+        let mut sig_info = sig_info(
+            signer(
+                &key,
+                sign_doc_bytes(
+                    1,
+                    vec![rotate_contract_key_msg(&sender, &contract_address())],
+                ),
+            ),
+            vec![],
+        );
+        sig_info.callback_sig = Some(Binary(vec![1u8; 32]));
+
+        assert!(matches!(
+            verify_key_rotation_params(&sig_info, &env(sender)),
+            Err(EnclaveError::UnauthorizedKeyRotation)
+        ));
+    }
+
+    pub fn test_verify_contract_code_hash() {
+        let code = b"the contract the host passed in";
+        let contract_hash = calc_contract_hash(code);
+        let mut env = env(contract_address());
+
+        // The host must report the hash
+        assert!(matches!(
+            verify_contract_code_hash(&env, &contract_hash),
+            Err(EnclaveError::ValidationFailure)
+        ));
+
+        env.contract_code_hash = hex::encode(contract_hash);
+        assert!(verify_contract_code_hash(&env, &contract_hash).is_ok());
+
+        env.contract_code_hash = hex::encode(calc_contract_hash(b"some other contract"));
+        assert!(matches!(
+            verify_contract_code_hash(&env, &contract_hash),
+            Err(EnclaveError::ValidationFailure)
+        ));
+
+        env.contract_code_hash = "not hex".to_string();
+        assert!(matches!(
+            verify_contract_code_hash(&env, &contract_hash),
+            Err(EnclaveError::ValidationFailure)
+        ));
+    }
+
+    pub fn test_derive_contract_address() {
+        // Addresses the compute module gives these contracts, see `TestContractAddress` in the keeper
+        let vectors: [(&[u8], [u8; HASH_SIZE], &[u8], &str); 5] = [
+            (
+                &[1; 20],
+                [0; HASH_SIZE],
+                b"",
+                "secret1vwe256uasx3447tv4jhzry8xxrtd3pvtyxdxch",
+            ),
+            (
+                &[1; 20],
+                [0; HASH_SIZE],
+                b"init",
+                "secret1mc39zgkzgaq2dn4k5k0hztpjpwth9f4cvw7qjn",
+            ),
+            (
+                &[2; 20],
+                [0; HASH_SIZE],
+                b"init",
+                "secret163uatd6vp8p0luxxq2h8s7vfde2l6vsg3tkch5",
+            ),
+            (
+                &[2; 20],
+                [7; HASH_SIZE],
+                b"init",
+                "secret15v7tau6az309jkpeeecj38ffsuwsswxucju3yn",
+            ),
+            (
+                &[3; 32],
+                [7; HASH_SIZE],
+                b"init",
+                "secret1s7nw7kgqt228jr2k2lgd7jpdlhsy6tm20527um",
+            ),
+        ];
+
+        for (creator, code_hash, init_msg, address) in vectors.iter() {
+            let creator = CanonicalAddr(Binary(creator.to_vec()));
+            assert_eq!(
+                HumanAddr::from_canonical(&derive_contract_address(&creator, code_hash, init_msg))
+                    .unwrap(),
+                HumanAddr(address.to_string())
+            );
+        }
+    }
+
+    pub fn test_verify_contract_address() {
+        let sender = human_address(&public_key(&secret_key(1)));
+        let creator = CanonicalAddr::from_human(&sender).unwrap();
+        let init_msg = secret_msg().to_vec();
+        let contract = derive_contract_address(&creator, &contract_hash(), &init_msg);
+        let mut env = env(sender);
+        env.contract.address = HumanAddr::from_canonical(&contract).unwrap();
+        assert!(verify_contract_address(&env, &contract, &contract_hash(), &init_msg).is_ok());
+
+        // The address the host picked doesn't depend on what it says about the instantiation
+        env.instantiation = None;
+        assert!(verify_contract_address(&env, &contract, &contract_hash(), &init_msg).is_ok());
+
+        // The address of a contract instantiated with another message
+        assert!(matches!(
+            verify_contract_address(&env, &contract, &contract_hash(), b"other"),
+            Err(EnclaveError::ValidationFailure)
+        ));
+
+        // The address of a contract instantiated from other code
+        assert!(matches!(
+            verify_contract_address(&env, &contract, &[1; HASH_SIZE], &init_msg),
+            Err(EnclaveError::ValidationFailure)
+        ));
+
+        // The address of a contract instantiated by someone else
+        env.message.sender = human_address(&public_key(&secret_key(2)));
+        assert!(matches!(
+            verify_contract_address(&env, &contract, &contract_hash(), &init_msg),
+            Err(EnclaveError::ValidationFailure)
+        ));
+    }
+
+    pub fn test_verify_params_instantiate_code_id() {
+        let key = secret_key(1);
+        let sender = human_address(&public_key(&key));
+        let instantiate_msg = json!({
+            "type": "wasm/MsgInstantiateContract",
+            "value": {
+                "sender": sender.as_str(),
+                "code_id": "7",
+                "init_msg": Binary(secret_msg().to_vec()).to_base64(),
+                "init_funds": [],
+                "label": "label"
+            }
+        });
+        let sig_info = sig_info(
+            signer(&key, sign_doc_bytes(1, vec![instantiate_msg])),
+            vec![],
+        );
+
+        let mut env = env(sender);
+        env.instantiation = Some(ContractInstantiation { code_id: 7 });
+        assert!(verify_params(&sig_info, &env, &secret_msg(), &contract_hash()).is_ok());
+
+        // The address was derived from code the sender didn't instantiate
+        env.instantiation = Some(ContractInstantiation { code_id: 8 });
+        assert!(matches!(
+            verify_params(&sig_info, &env, &secret_msg(), &contract_hash()),
+            Err(EnclaveError::FailedTxVerification)
+        ));
+
+        env.instantiation = None;
+        assert!(matches!(
+            verify_params(&sig_info, &env, &secret_msg(), &contract_hash()),
+            Err(EnclaveError::FailedTxVerification)
+        ));
+    }
+
+    fn instantiate_msg(sender: &HumanAddr, admin: Option<&HumanAddr>) -> Value {
+        let mut msg = json!({
+            "type": "wasm/MsgInstantiateContract",
+            "value": {
+                "sender": sender.as_str(),
+                "code_id": "7",
+                "init_msg": Binary(secret_msg().to_vec()).to_base64(),
+                "init_funds": [],
+                "label": "label"
+            }
+        });
+        if let Some(admin) = admin {
+            msg["value"]["admin"] = json!(admin.as_str());
+        }
+        msg
+    }
+
+    fn instantiate_env(sender: HumanAddr) -> Env {
+        let mut env = env(sender);
+        env.instantiation = Some(ContractInstantiation { code_id: 7 });
+        env
+    }
+
+    pub fn test_verify_params_instantiate_with_admin() {
+        let key = secret_key(1);
+        let sender = human_address(&public_key(&key));
+        let admin = human_address(&public_key(&secret_key(2)));
+        let sig_info = sig_info(
+            signer(
+                &key,
+                sign_doc_bytes(1, vec![instantiate_msg(&sender, Some(&admin))]),
+            ),
+            vec![],
+        );
+
+        let signed_msg = verify_params(
+            &sig_info,
+            &instantiate_env(sender),
+            &secret_msg(),
+            &contract_hash(),
+        )
+        .unwrap()
+        .map(|verified| verified.msg);
+        match signed_msg {
+            Some(SignDocWasmMsg::Instantiate {
+                admin: signed_admin,
+                ..
+            }) => {
+                assert_eq!(signed_admin, Some(admin))
+            }
+            other => panic!("got an unexpected signed message {:?}", other),
+        }
+    }
+
+    pub fn test_verify_params_instantiate_without_admin() {
+        let key = secret_key(1);
+        let sender = human_address(&public_key(&key));
+        let sig_info = sig_info(
+            signer(
+                &key,
+                sign_doc_bytes(1, vec![instantiate_msg(&sender, None)]),
+            ),
+            vec![],
+        );
+
+        let signed_msg = verify_params(
+            &sig_info,
+            &instantiate_env(sender),
+            &secret_msg(),
+            &contract_hash(),
+        )
+        .unwrap()
+        .map(|verified| verified.msg);
+        assert!(matches!(
+            signed_msg,
+            Some(SignDocWasmMsg::Instantiate { admin: None, .. })
+        ));
+    }
+
+    fn env_with_admin(
+        sender: HumanAddr,
+        admin: Option<HumanAddr>,
+        proof_of: Option<&HumanAddr>,
+    ) -> Env {
+        let mut env = env(sender);
+        let proof_of = proof_of.map(|admin| CanonicalAddr::from_human(admin).unwrap());
+        let admin_proof = generate_admin_proof(
+            &CanonicalAddr::from_human(&contract_address()).unwrap(),
+            proof_of.as_ref(),
+        )
+        .unwrap();
+        env.contract.admin = admin;
+        env.admin_proof = Some(Binary(admin_proof.as_bytes().to_vec()));
+        env
+    }
+
+    pub fn test_verify_contract_admin_authenticated() {
+        let admin = human_address(&public_key(&secret_key(1)));
+        let other = human_address(&public_key(&secret_key(2)));
+
+        let env = env_with_admin(admin.clone(), Some(admin.clone()), Some(&admin));
+        assert!(is_authenticated_admin(&env));
+        assert!(verify_contract_admin(&env).is_ok());
+
+        // Only the admin may migrate
+        let env = env_with_admin(other.clone(), Some(admin.clone()), Some(&admin));
+        assert!(matches!(
+            verify_contract_admin(&env),
+            Err(EnclaveError::UnauthorizedMigration)
+        ));
+
+        // A contract that was instantiated without an admin can't be migrated
+        let env = env_with_admin(admin.clone(), None, None);
+        assert!(is_authenticated_admin(&env));
+        assert!(matches!(
+            verify_contract_admin(&env),
+            Err(EnclaveError::UnauthorizedMigration)
+        ));
+    }
+
+    pub fn test_verify_contract_admin_host_lies_about_admin() {
+        let admin = human_address(&public_key(&secret_key(1)));
+        let host = human_address(&public_key(&secret_key(2)));
+
+        // The host reports itself as the admin, with the proof of the real admin
+        let env = env_with_admin(host.clone(), Some(host.clone()), Some(&admin));
+        assert!(!is_authenticated_admin(&env));
+        assert!(matches!(
+            verify_contract_admin(&env),
+            Err(EnclaveError::UnauthorizedMigration)
+        ));
+
+        // Or with the proof of a contract that has no admin
+        let env = env_with_admin(host.clone(), Some(host.clone()), None);
+        assert!(matches!(
+            verify_contract_admin(&env),
+            Err(EnclaveError::UnauthorizedMigration)
+        ));
+
+        // Or without a proof at all
+        let mut env = env_with_admin(host.clone(), Some(host.clone()), Some(&host));
+        env.admin_proof = None;
+        assert!(matches!(
+            verify_contract_admin(&env),
+            Err(EnclaveError::UnauthorizedMigration)
+        ));
+
+        // A proof of this admin for another contract doesn't do either
+        let mut env = env_with_admin(host.clone(), Some(host.clone()), Some(&host));
+        env.contract.address = human_address(&public_key(&secret_key(3)));
+        assert!(matches!(
+            verify_contract_admin(&env),
+            Err(EnclaveError::UnauthorizedMigration)
+        ));
+    }
+
+    fn update_admin_msg(sender: &HumanAddr, new_admin: Option<&HumanAddr>) -> Value {
+        match new_admin {
+            Some(new_admin) => json!({
+                "type": "wasm/MsgUpdateAdmin",
+                "value": {
+                    "sender": sender.as_str(),
+                    "new_admin": new_admin.as_str(),
+                    "contract": contract_address().as_str()
+                }
+            }),
+            None => json!({
+                "type": "wasm/MsgClearAdmin",
+                "value": {
+                    "sender": sender.as_str(),
+                    "contract": contract_address().as_str()
+                }
+            }),
+        }
+    }
+
+    pub fn test_verify_admin_update_params() {
+        let key = secret_key(1);
+        let sender = human_address(&public_key(&key));
+        let new_admin = human_address(&public_key(&secret_key(2)));
+        let other = human_address(&public_key(&secret_key(3)));
+
+        let update = sig_info(
+            signer(
+                &key,
+                sign_doc_bytes(1, vec![update_admin_msg(&sender, Some(&new_admin))]),
+            ),
+            vec![],
+        );
+        assert!(
+            verify_admin_update_params(&update, &env(sender.clone()), Some(&new_admin)).is_ok()
+        );
+        // The host can't replace the new admin, or clear the admin instead
+        for forged_admin in &[Some(&other), None] {
+            assert!(matches!(
+                verify_admin_update_params(&update, &env(sender.clone()), *forged_admin),
+                Err(EnclaveError::FailedTxVerification)
+            ));
+        }
+
+        let clear = sig_info(
+            signer(
+                &key,
+                sign_doc_bytes(1, vec![update_admin_msg(&sender, None)]),
+            ),
+            vec![],
+        );
+        assert!(verify_admin_update_params(&clear, &env(sender.clone()), None).is_ok());
+        assert!(matches!(
+            verify_admin_update_params(&clear, &env(sender.clone()), Some(&new_admin)),
+            Err(EnclaveError::FailedTxVerification)
+        ));
+
+        let mut from_contract = update;
+        from_contract.callback_sig = Some(Binary(vec![1u8; 32]));
+        assert!(matches!(
+            verify_admin_update_params(&from_contract, &env(sender), Some(&new_admin)),
+            Err(EnclaveError::UnauthorizedAdminUpdate)
+        ));
+    }
+
+    fn import_state_msg(sender: &HumanAddr, chunk: &[u8]) -> Value {
+        json!({
+            "type": "wasm/MsgImportContractState",
+            "value": {
+                "sender": sender.as_str(),
+                "contract": contract_address().as_str(),
+                "chunk": Binary(chunk.to_vec()).to_base64()
+            }
+        })
+    }
+
+    pub fn test_verify_state_import_params() {
+        let key = secret_key(1);
+        let sender = human_address(&public_key(&key));
+        let chunk = br#"{"contract_address":"secret1contract","entries":[]}"#;
+
+        let import = sig_info(
+            signer(
+                &key,
+                sign_doc_bytes(1, vec![import_state_msg(&sender, chunk)]),
+            ),
+            vec![],
+        );
+        assert!(verify_state_import_params(&import, &env(sender.clone()), chunk).is_ok());
+
+        // The host can't import another chunk than the one the sender signed
+        assert!(matches!(
+            verify_state_import_params(&import, &env(sender.clone()), b"another chunk"),
+            Err(EnclaveError::FailedTxVerification)
+        ));
+
+        // Or pass off another signed message as an import
+        let update = sig_info(
+            signer(
+                &key,
+                sign_doc_bytes(1, vec![update_admin_msg(&sender, Some(&sender))]),
+            ),
+            vec![],
+        );
+        assert!(matches!(
+            verify_state_import_params(&update, &env(sender.clone()), chunk),
+            Err(EnclaveError::FailedTxVerification)
+        ));
+
+        let mut from_contract = import;
+        from_contract.callback_sig = Some(Binary(vec![1u8; 32]));
+        assert!(matches!(
+            verify_state_import_params(&from_contract, &env(sender), chunk),
+            Err(EnclaveError::UnauthorizedMigration)
+        ));
+    }
+
+    pub fn test_validate_msg_for_mismatched_code() {
+        let contract_hash = calc_contract_hash(b"the contract the user sent the message to");
+        let mut msg = hex::encode(contract_hash).into_bytes();
+        msg.extend_from_slice(br#"{"nop":{}}"#);
+
+        assert_eq!(
+            validate_msg_for_hash(&msg, &contract_hash).unwrap(),
+            br#"{"nop":{}}"#.to_vec()
+        );
+
+        let other_hash = calc_contract_hash(b"the contract the host passed in");
+        assert!(matches!(
+            validate_msg_for_hash(&msg, &other_hash),
+            Err(EnclaveError::ValidationFailure)
+        ));
+    }
+
+    pub fn test_verify_ibc_port() {
+        let contract = contract_address();
+        assert!(verify_ibc_port(&format!("wasm.{}", contract), &contract).is_ok());
+
+        let other_contract =
+            HumanAddr::from_canonical(&CanonicalAddr(Binary(vec![2u8; 20]))).unwrap();
+        for port_id in &[
+            format!("wasm.{}", other_contract),
+            contract.to_string(),
+            "transfer".to_string(),
+            String::new(),
+        ] {
+            assert!(matches!(
+                verify_ibc_port(port_id, &contract),
+                Err(EnclaveError::UnauthorizedIbcPort)
+            ));
+        }
+    }
+
+    fn contract_key_for(contract_hash: &[u8; HASH_SIZE], contract_address: &[u8]) -> ContractKey {
+        let signer_id = [1u8; HASH_SIZE];
+        let consensus_state_ikm = KEY_MANAGER.get_consensus_state_ikm().unwrap();
+        let authentication_code = generate_contract_id(
+            &consensus_state_ikm,
+            &signer_id,
+            contract_hash,
+            contract_address,
+        );
+
+        let mut contract_key = [0u8; CONTRACT_KEY_LENGTH];
+        contract_key[..HASH_SIZE].copy_from_slice(&signer_id);
+        contract_key[HASH_SIZE..].copy_from_slice(authentication_code.as_bytes());
+        contract_key
+    }
+
+    pub fn test_validate_contract_key_for_hash() {
+        let contract_hash = calc_contract_hash(b"contract");
+        let contract_address = [1u8; 20];
+        let contract_key = contract_key_for(&contract_hash, &contract_address);
+        assert!(validate_contract_key_for_hash(
+            &contract_key,
+            &contract_address,
+            &contract_hash
+        ));
+
+        for index in &[0, HASH_SIZE, CONTRACT_KEY_LENGTH - 1] {
+            let mut forged = contract_key;
+            forged[*index] ^= 1;
+            assert!(!validate_contract_key_for_hash(
+                &forged,
+                &contract_address,
+                &contract_hash
+            ));
+        }
+        assert!(!validate_contract_key_for_hash(
+            &contract_key,
+            &[2u8; 20],
+            &contract_hash
+        ));
+        assert!(!validate_contract_key_for_hash(
+            &contract_key,
+            &contract_address,
+            &calc_contract_hash(b"another contract")
+        ));
+    }
+
+    pub fn test_contract_key_matches_code_hash() {
+        let contract_hash = calc_contract_hash(b"contract");
+        let contract_address = [1u8; 20];
+        let contract_key = contract_key_for(&contract_hash, &contract_address);
+        assert!(contract_key_matches_code_hash(
+            Some(&contract_key),
+            &contract_hash,
+            &contract_address,
+            &contract_hash
+        ));
+
+        // After a migration, only the new code hash matches the key the host stores
+        let new_contract_hash = calc_contract_hash(b"new contract");
+        let migrated_key =
+            generate_migrated_contract_key(&contract_key, &contract_address, b"new contract")
+                .unwrap();
+        assert!(contract_key_matches_code_hash(
+            Some(&migrated_key),
+            &new_contract_hash,
+            &contract_address,
+            &new_contract_hash
+        ));
+        assert!(!contract_key_matches_code_hash(
+            Some(&migrated_key),
+            &new_contract_hash,
+            &contract_address,
+            &contract_hash
+        ));
+
+        // The key from before the migration doesn't vouch for the old code, since the contract
+        // info points to the new code
+        assert!(!contract_key_matches_code_hash(
+            Some(&contract_key),
+            &new_contract_hash,
+            &contract_address,
+            &contract_hash
+        ));
+        // and a host claiming the new code can't pass the old key off for it
+        assert!(!contract_key_matches_code_hash(
+            Some(&contract_key),
+            &new_contract_hash,
+            &contract_address,
+            &new_contract_hash
+        ));
+
+        // No contract at the address, or a key that isn't one
+        assert!(!contract_key_matches_code_hash(
+            None,
+            &contract_hash,
+            &contract_address,
+            &contract_hash
+        ));
+        assert!(!contract_key_matches_code_hash(
+            Some(&contract_key[..HASH_SIZE]),
+            &contract_hash,
+            &contract_address,
+            &contract_hash
+        ));
+    }
+
+    pub fn test_verify_reply() {
+        let contract = CanonicalAddr(Binary(vec![1u8; 20]));
+        let nonce = [2u8; 32];
+        let user_public_key = [3u8; 32];
+        let reply = SignedReply {
+            id: 1,
+            result: SubMsgResult::Error("failed".to_string()),
+            reply_sig: Binary(
+                io::create_reply_signature(&contract, 1, true, &nonce, &user_public_key).to_vec(),
+            ),
+            result_sig: None,
+            nonce: Binary(nonce.to_vec()),
+            user_public_key: Binary(user_public_key.to_vec()),
+        };
+        assert!(verify_reply(&contract, &reply, &nonce, &user_public_key).is_ok());
+
+        let mut forged = reply.clone();
+        forged.reply_sig.0[HASH_SIZE - 1] ^= 1;
+        let mut other_id = reply.clone();
+        other_id.id = 2;
+        let mut truncated = reply.clone();
+        truncated.reply_sig.0.pop();
+        for reply in &[forged, other_id, truncated] {
+            assert!(matches!(
+                verify_reply(&contract, reply, &nonce, &user_public_key),
+                Err(EnclaveError::UnauthorizedReply)
+            ));
+        }
+        assert!(matches!(
+            verify_reply(&contract, &reply, &[4u8; 32], &user_public_key),
+            Err(EnclaveError::UnauthorizedReply)
+        ));
+
+        // The result of a submessage that called a contract must be signed by that contract's call
+        let result = SubMsgResult::Ok(SubMsgExecutionResponse {
+            data: Some(Binary(b"data".to_vec())),
+        });
+        let result_sig =
+            io::create_reply_result_signature(&contract, 1, &nonce, &user_public_key, &result);
+        let mut ok = reply.clone();
+        ok.result = result;
+        assert!(matches!(
+            verify_reply(&contract, &ok, &nonce, &user_public_key),
+            Err(EnclaveError::UnauthorizedReply)
+        ));
+        ok.result_sig = Some(Binary(result_sig.to_vec()));
+        assert!(verify_reply(&contract, &ok, &nonce, &user_public_key).is_ok());
+
+        let mut swapped = ok.clone();
+        swapped.result = SubMsgResult::Ok(SubMsgExecutionResponse {
+            data: Some(Binary(b"other".to_vec())),
+        });
+        assert!(matches!(
+            verify_reply(&contract, &swapped, &nonce, &user_public_key),
+            Err(EnclaveError::UnauthorizedReply)
+        ));
+
+        // A submessage that doesn't call a contract gets its result from the module
+        let mut bank = swapped;
+        bank.reply_sig = Binary(
+            io::create_reply_signature(&contract, 1, false, &nonce, &user_public_key).to_vec(),
+        );
+        bank.result_sig = None;
+        assert!(verify_reply(&contract, &bank, &nonce, &user_public_key).is_ok());
+    }
+
+    pub fn test_verify_reply_to() {
+        let contract = contract_address();
+        let canonical = CanonicalAddr::from_human(&contract).unwrap();
+        let nonce = [2u8; 32];
+        let user_public_key = [3u8; 32];
+        let mut env = env(HumanAddr("sender".to_string()));
+        assert!(verify_reply_to(&mut env, &nonce, &user_public_key)
+            .unwrap()
+            .is_none());
+
+        let reply_sig = io::create_reply_signature(&canonical, 7, true, &nonce, &user_public_key);
+        env.reply_to = Some(ReplyTo {
+            id: 7,
+            contract: contract.clone(),
+            reply_sig: Binary(reply_sig.to_vec()),
+        });
+        let target = verify_reply_to(&mut env.clone(), &nonce, &user_public_key)
+            .unwrap()
+            .unwrap();
+        assert_eq!(target.contract, canonical);
+        assert_eq!(target.id, 7);
+
+        // A submessage that doesn't call a contract can't have its result signed
+        let bank_sig = io::create_reply_signature(&canonical, 7, false, &nonce, &user_public_key);
+        let mut bank = env.clone();
+        bank.reply_to.as_mut().unwrap().reply_sig = Binary(bank_sig.to_vec());
+        let mut other_id = env.clone();
+        other_id.reply_to.as_mut().unwrap().id = 8;
+        for env in &mut [bank, other_id] {
+            assert!(matches!(
+                verify_reply_to(env, &nonce, &user_public_key),
+                Err(EnclaveError::UnauthorizedReply)
+            ));
+        }
+        assert!(matches!(
+            verify_reply_to(&mut env, &[4u8; 32], &user_public_key),
+            Err(EnclaveError::UnauthorizedReply)
+        ));
+    }
+
+    pub fn test_verify_callback_sig() {
+        let sender = CanonicalAddr(Binary(vec![1u8; 20]));
+        let msg = secret_msg();
+        let sent_funds = vec![coin("uscrt", 10)];
+        let code_hash = contract_hash();
+        let callback_sig = io::create_callback_signature(&sender, &msg, &sent_funds).to_vec();
+        let verify = |callback_sig: &[u8], sent_funds: &[Coin], code_hash: &[u8; HASH_SIZE]| {
+            verify_callback_sig(callback_sig, &sender, &msg, sent_funds, code_hash)
+        };
+        assert!(verify(&callback_sig, &sent_funds, &code_hash));
+
+        let mut forged = callback_sig.clone();
+        forged[0] ^= 1;
+        assert!(!verify(&forged, &sent_funds, &code_hash));
+        assert!(!verify(&callback_sig, &[coin("uscrt", 11)], &code_hash));
+        assert!(!verify(&[], &sent_funds, &code_hash));
+
+        // Signatures with a version byte also cover the code hash
+        let mut callback_sig_v1 = vec![io::CALLBACK_SIG_V1];
+        callback_sig_v1.extend_from_slice(
+            &io::create_callback_signature_v1(&sender, &code_hash, &msg, &sent_funds).to_vec(),
+        );
+        assert!(verify(&callback_sig_v1, &sent_funds, &code_hash));
+        assert!(!verify(&callback_sig_v1, &[coin("uscrt", 11)], &code_hash));
+        assert!(!verify(
+            &callback_sig_v1,
+            &sent_funds,
+            &calc_contract_hash(b"another contract")
+        ));
+        // Without the version byte, it isn't a signature of either kind
+        assert!(!verify(&callback_sig_v1[1..], &sent_funds, &code_hash));
+        let mut wrong_version = callback_sig_v1.clone();
+        wrong_version[0] = io::CALLBACK_SIG_V1 + 1;
+        assert!(!verify(&wrong_version, &sent_funds, &code_hash));
+    }
+
+    /// Encrypt the output of `sender` that sends `wasm_msg`, and return the message, the funds and
+    /// the callback signature that the chain passes on to the contract it's sent to
+    fn send_from_contract(
+        sender: &CanonicalAddr,
+        wasm_msg: Value,
+    ) -> (SecretMessage, Vec<Coin>, Binary) {
+        let output =
+            serde_json::to_vec(&json!({"Ok": {"messages": [{ "wasm": wasm_msg }], "log": []}}))
+                .unwrap();
+        let encrypted = io::encrypt_output(
+            output,
+            [4u8; 32],
+            [7u8; 32],
+            sender,
+            &io::OutputLimits::default(),
+            None,
+            None,
+        )
+        .unwrap_or_else(|_| panic!("the output should be encrypted"));
+
+        let messages = match serde_json::from_slice(&encrypted).unwrap() {
+            WasmOutput::OkObject { ok } => ok.messages,
+            _ => panic!("the output should stay an object"),
+        };
+        match messages.into_iter().next() {
+            Some(CosmosMsg::Wasm(WasmMsg::Execute {
+                msg,
+                send,
+                callback_sig,
+                ..
+            })) => (
+                SecretMessage::from_slice(msg.as_slice()).unwrap(),
+                send,
+                Binary(callback_sig.unwrap()),
+            ),
+            other => panic!("expected an execute message, got {:?}", other),
+        }
+    }
+
+    pub fn test_verify_params_from_contract_with_funds() {
+        let contract_a = CanonicalAddr(Binary(vec![2u8; 20]));
+        let sender = HumanAddr::from_canonical(&contract_a).unwrap();
+        let code_hash_b = contract_hash();
+        let key = secret_key(1);
+
+        let legacy = json!({"execute": {
+            "contract_addr": contract_address(),
+            "callback_code_hash": hex::encode(code_hash_b),
+            "msg": Binary(b"{\"nop\":{}}".to_vec()),
+            "send": [{ "denom": "uscrt", "amount": "1000" }],
+        }});
+        let v1 = json!({"execute": {
+            "contract_addr": contract_address(),
+            "code_hash": hex::encode(code_hash_b),
+            "msg": Binary(b"{\"nop\":{}}".to_vec()),
+            "funds": [{ "denom": "uscrt", "amount": "1000" }],
+        }});
+
+        for (wasm_msg, is_v1) in vec![(legacy, false), (v1, true)] {
+            let (msg, funds, callback_sig) = send_from_contract(&contract_a, wasm_msg);
+            assert_eq!(funds, vec![coin("uscrt", 1000)]);
+            assert_eq!(callback_sig.as_slice()[0] == io::CALLBACK_SIG_V1, is_v1);
+
+            let mut sig_info = sig_info(signer(&key, sign_doc_bytes(1, vec![])), vec![]);
+            sig_info.callback_sig = Some(callback_sig);
+            let env = env_with_funds(sender.clone(), funds);
+
+            assert!(matches!(
+                verify_params(&sig_info, &env, &msg, &code_hash_b),
+                Ok(None)
+            ));
+            assert!(matches!(
+                verify_params(
+                    &sig_info,
+                    &env_with_funds(sender.clone(), vec![coin("uscrt", 1)]),
+                    &msg,
+                    &code_hash_b
+                ),
+                Err(EnclaveError::FailedTxVerification)
+            ));
+            // Only the signatures of CosmWasm 1.0 messages are bound to the code they're sent to
+            let other_code_hash = calc_contract_hash(b"another contract");
+            assert_eq!(
+                verify_params(&sig_info, &env, &msg, &other_code_hash).is_ok(),
+                !is_v1
+            );
+        }
+
+        // A message can't carry funds under both names
+        let both = json!({"execute": {
+            "contract_addr": contract_address(),
+            "code_hash": hex::encode(code_hash_b),
+            "msg": Binary(b"{\"nop\":{}}".to_vec()),
+            "send": [{ "denom": "uscrt", "amount": "1000" }],
+            "funds": [{ "denom": "uscrt", "amount": "1000" }],
+        }});
+        let output =
+            serde_json::to_vec(&json!({"Ok": {"messages": [{ "wasm": both }], "log": []}}))
+                .unwrap();
+        assert!(io::encrypt_output(
+            output,
+            [4u8; 32],
+            [7u8; 32],
+            &contract_a,
+            &io::OutputLimits::default(),
+            None,
+            None,
+        )
+        .is_err());
+    }
 }
@@ -1,17 +1,313 @@
-use super::contract_validation::ContractKey;
+use super::contract_validation::{ContractKey, CONTRACT_KEY_LENGTH};
 use super::errors::WasmEngineError;
-use crate::crypto::{sha_256, AESKey, Kdf, SIVEncryptable, KEY_MANAGER};
+use super::query_cache::invalidate_query_cache;
+use super::runtime::sections::{decode_optional_sections, encode_sections};
+use crate::crypto::{
+    constant_time_eq, hkdf_sha256, sha_256, AESKey, Kdf, KeyGeneration, Keychain, SIVEncryptable,
+    HASH_SIZE, KEY_MANAGER,
+};
+use crate::ecall_trace::Phase;
+use crate::metrics::{self, Ocall};
+use crate::runtime_stats;
+use crate::trace_span;
 use crate::{exports, imports};
 
+use std::cell::Cell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Bound;
+
 use enclave_ffi_types::{Ctx, EnclaveBuffer, OcallReturn, UntrustedVmError};
 
 use log::*;
 use sgx_types::sgx_status_t;
 
+/// Suffix of the field that points from a migrated contract's key to the key it had before the
+/// migration. It is appended to the contract key (rather than prepended like user field names)
+/// so it can't collide with a field name chosen by the contract.
+const PREVIOUS_CONTRACT_KEY_FIELD: &[u8] = b"previous_contract_key";
+
+/// Suffix of the field that holds how many bytes the contract stores, see `StorageUsage`. It is
+/// appended to the contract key like `PREVIOUS_CONTRACT_KEY_FIELD`.
+const STORAGE_USAGE_FIELD: &[u8] = b"storage_usage";
+
+/// Suffix of the fields that mark the query permits their signers revoked, see `query_permit`.
+/// It is appended to the contract key like `PREVIOUS_CONTRACT_KEY_FIELD`, followed by the signer
+/// and the name of the permit.
+const REVOKED_QUERY_PERMIT_FIELD: &[u8] = b"revoked_query_permit";
+
+/// Suffix of the fields that mark the public key and nonce of every tx message the contract
+/// executed, see `nonce_registry`. It is appended to the contract key like
+/// `PREVIOUS_CONTRACT_KEY_FIELD`, followed by the public key and the nonce.
+const USED_TX_NONCE_FIELD: &[u8] = b"used_tx_nonce";
+
+/// Prefix of the fields that hold the plaintext key of every value the contract wrote.
+/// The scrambled field names of the values don't preserve the plaintext keys or their order, so
+/// these fields are what range queries iterate over. The prefix is long enough that no scrambled
+/// field name (a 32 byte digest) will realistically start with it.
+const KEY_INDEX_PREFIX: &[u8] = b"\x00__secret_key_index__";
+/// The first key after all the keys that start with `KEY_INDEX_PREFIX`
+const KEY_INDEX_PREFIX_END: &[u8] = b"\x00__secret_key_index_`";
+
+/// Salt of the KDF that derives the key of a storage namespace from the contract key, see
+/// `namespaced_contract_key`
+const NAMESPACE_KDF_SALT: &[u8] = b"secret_storage_namespace";
+
+/// The key `ecall_health_check_deep` writes to. The host backs the check with a storage of its own,
+/// so this never touches the storage of a contract.
+const HEALTH_CHECK_KEY: &[u8] = b"\x00__secret_health_check__";
+
+/// Marks a value that is encrypted with associated data binding it to the contract and the key it
+/// is stored under, see `encrypt_key`. Values written before it have the generation of their seed,
+/// or nothing, in its place.
+const STATE_ENCRYPTION_VERSION: u8 = 0x80;
+
+/// Tracks how much a contract removed from its storage during an execution, so part of the gas
+/// it used can be refunded. See `gas::apply_storage_refund`.
+#[derive(Debug, Default)]
+pub struct RemovedStorage {
+    bytes: u64,
+}
+
+impl RemovedStorage {
+    pub fn record(&mut self, key_len: usize, value_len: usize) {
+        self.bytes = self
+            .bytes
+            .saturating_add(key_len as u64)
+            .saturating_add(value_len as u64);
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+}
+
+/// How many bytes a contract stores: the plaintext keys and values of its entries, in and out of
+/// namespaces. It's kept in the contract storage next to the entries, see `read_storage_usage`,
+/// and changes with every write and removal while the chain sets a quota, so it's the same on
+/// every node.
+///
+/// Entries written before the usage was tracked, or while there was no quota, aren't counted, so
+/// removing them can't bring it below zero.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct StorageUsage {
+    bytes: u64,
+    /// What the contract storage holds, to tell whether `bytes` has to be written back
+    stored_bytes: u64,
+}
+
+impl StorageUsage {
+    /// The usage that was read from the contract storage
+    pub fn stored(bytes: u64) -> Self {
+        Self {
+            bytes,
+            stored_bytes: bytes,
+        }
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Whether the usage changed since it was read
+    pub fn changed(&self) -> bool {
+        self.bytes != self.stored_bytes
+    }
+
+    /// Account for entries of `removed` bytes being replaced with entries of `added` bytes, see
+    /// `entry_len`.
+    ///
+    /// Fails if the usage would grow past `quota`, and leaves it as it was. Changes that don't
+    /// grow it always succeed, so a contract that is above its quota, e.g. because the chain
+    /// lowered it, can still remove entries.
+    pub fn change(
+        &mut self,
+        removed: u64,
+        added: u64,
+        quota: Option<u64>,
+    ) -> Result<(), WasmEngineError> {
+        if added <= removed {
+            self.bytes = self.bytes.saturating_sub(removed - added);
+            return Ok(());
+        }
+
+        let bytes = self.bytes.saturating_add(added - removed);
+        match quota {
+            Some(quota) if bytes > quota => {
+                debug!(
+                    "A change would bring the contract storage to {} bytes, past its quota of {}",
+                    bytes, quota
+                );
+                Err(WasmEngineError::StorageQuotaExceeded {
+                    usage: bytes,
+                    quota,
+                })
+            }
+            _ => {
+                self.bytes = bytes;
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The bytes an entry counts for in `StorageUsage`: its key and value, or nothing if there's no
+/// value
+pub fn entry_len(key_len: usize, value_len: Option<usize>) -> u64 {
+    value_len.map_or(0, |value_len| {
+        (key_len as u64).saturating_add(value_len as u64)
+    })
+}
+
+// The gas the host charges for storage access. These mirror the default `KVGasConfig` of the
+// cosmos-sdk, so storage served from a `StorageOverlay` costs the same as the real thing.
+const READ_COST_FLAT: u64 = 1000;
+const READ_COST_PER_BYTE: u64 = 3;
+const WRITE_COST_FLAT: u64 = 2000;
+const WRITE_COST_PER_BYTE: u64 = 30;
+const DELETE_COST: u64 = 1000;
+const ITER_NEXT_COST_FLAT: u64 = 30;
+
+/// Storage changes made during an execution. They are kept in the enclave instead of being sent
+/// to the host, and later reads in the same execution see them.
+///
+/// When simulating, the changes are discarded at the end, so a simulation never changes the
+/// contract storage. Otherwise they are sent to the host with `flush_storage_overlay` when the
+/// execution succeeds, so a key the contract wrote many times costs a single ocall. Every change
+/// is still charged when it's made, like the host would have charged it.
+///
+/// The overlay only lives for a single execution. A submessage runs in an execution of its own,
+/// and the host reverts its changes when it fails, so the overlay never has to undo a change.
+///
+/// The overlay also keeps the values that were read ahead, see `prefetch_encrypted_keys`. They
+/// are served like changes, and a value that is changed is dropped from them, so they never
+/// hide a change.
+///
+/// The host doesn't charge for what the overlay answers, so the gas of those reads is kept until
+/// the contract takes it with `take_served_gas` and reports it as used.
+#[derive(Debug, Default)]
+pub struct StorageOverlay {
+    /// `None` marks an entry that was removed
+    entries: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+    /// Values the host returned for keys that were read ahead. `None` marks a missing value.
+    prefetched: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+    /// Gas of the reads answered here since `take_served_gas` was last called
+    served_gas: Cell<u64>,
+}
+
+impl StorageOverlay {
+    /// The value of `key` and the gas the host would charge for reading it,
+    /// or `None` if `key` wasn't changed in this simulation and wasn't read ahead.
+    fn read(&self, key: &[u8]) -> Option<(Option<Vec<u8>>, u64)> {
+        let value = match self.entries.get(key) {
+            Some(value) => value,
+            None => {
+                let value = self.prefetched.get(key)?;
+                runtime_stats::record_prefetch_hit();
+                value
+            }
+        };
+
+        let value_len = value.as_ref().map_or(0, Vec::len) as u64;
+        Some((
+            value.clone(),
+            self.serve(READ_COST_FLAT + READ_COST_PER_BYTE * value_len),
+        ))
+    }
+
+    /// Keep `gas_used` for a read the host didn't charge, see `take_served_gas`
+    fn serve(&self, gas_used: u64) -> u64 {
+        self.served_gas
+            .set(self.served_gas.get().saturating_add(gas_used));
+        gas_used
+    }
+
+    /// The gas of the reads the overlay answered since the last call. It's part of the gas the
+    /// reads returned, but unlike the rest the host didn't charge it.
+    pub fn take_served_gas(&self) -> u64 {
+        self.served_gas.replace(0)
+    }
+
+    /// Whether a read of `key` would be answered by the overlay
+    fn holds(&self, key: &[u8]) -> bool {
+        self.entries.contains_key(key) || self.prefetched.contains_key(key)
+    }
+
+    fn write(&mut self, key: &[u8], value: &[u8]) -> u64 {
+        self.set(key, Some(value.to_vec()));
+        WRITE_COST_FLAT + WRITE_COST_PER_BYTE * value.len() as u64
+    }
+
+    fn remove(&mut self, key: &[u8]) -> u64 {
+        self.set(key, None);
+        DELETE_COST
+    }
+
+    fn set(&mut self, key: &[u8], value: Option<Vec<u8>>) {
+        // The value read ahead is stale once the key changes
+        self.prefetched.remove(key);
+        self.entries.insert(key.to_vec(), value);
+    }
+
+    /// Take the final value of every entry that was changed, and forget the changes.
+    fn take_changes(&mut self) -> BTreeMap<Vec<u8>, Option<Vec<u8>>> {
+        std::mem::take(&mut self.entries)
+    }
+
+    /// Apply the changes in the range `[start, end)` to the entries the host listed in that range.
+    /// Entries added by the simulation are charged like the host charges for iterating over them.
+    fn merge_range(
+        &self,
+        start: &[u8],
+        end: &[u8],
+        mut entries: BTreeMap<Vec<u8>, Vec<u8>>,
+    ) -> (BTreeMap<Vec<u8>, Vec<u8>>, u64) {
+        let mut gas_used = 0_u64;
+        for (key, value) in self
+            .entries
+            .range::<[u8], _>((Bound::Included(start), Bound::Excluded(end)))
+        {
+            match value {
+                Some(value) => {
+                    if entries.insert(key.clone(), value.clone()).is_none() {
+                        gas_used = gas_used.saturating_add(
+                            ITER_NEXT_COST_FLAT
+                                + READ_COST_PER_BYTE * (key.len() + value.len()) as u64,
+                        );
+                    }
+                }
+                None => {
+                    entries.remove(key);
+                }
+            }
+        }
+        (entries, self.serve(gas_used))
+    }
+}
+
+/// Send the changes kept in `overlay` to the host, one ocall for every entry that changed. The
+/// changes were charged when they were made, so the gas the host reports here isn't charged again.
+pub fn flush_storage_overlay(
+    context: &Ctx,
+    overlay: &mut StorageOverlay,
+) -> Result<(), WasmEngineError> {
+    let changes = overlay.take_changes();
+    debug!("Flushing {} storage changes to the host", changes.len());
+
+    for (key, value) in changes {
+        match value {
+            Some(value) => write_db(context, &key, &value)?,
+            None => remove_db(context, &key)?,
+        };
+    }
+
+    Ok(())
+}
+
 pub fn write_encrypted_key(
     key: &[u8],
     value: &[u8],
     context: &Ctx,
+    mut overlay: Option<&mut StorageOverlay>,
     contract_key: &ContractKey,
 ) -> Result<u64, WasmEngineError> {
     // Get the state key from the key manager
@@ -23,102 +319,1247 @@ pub fn write_encrypted_key(
         scrambled_field_name
     );
 
-    let (ad, ad_used_gas) = derive_ad_for_field(&scrambled_field_name, &context)?;
+    let index_gas_used = write_key_index(
+        key,
+        &scrambled_field_name,
+        context,
+        overlay.as_deref_mut(),
+        contract_key,
+    )?;
+    let gas_used = write_encrypted_field(
+        &scrambled_field_name,
+        key,
+        value,
+        context,
+        overlay,
+        contract_key,
+    )?;
+
+    Ok(index_gas_used.saturating_add(gas_used))
+}
 
-    let encrypted_value = encrypt_key(&scrambled_field_name, value, contract_key, &ad)?;
+/// Read a value from the contract storage.
+///
+/// State written before a contract was migrated stays encrypted under the contract key that
+/// was used at the time, so if the value is not found under the current contract key we walk
+/// back through the keys the contract had before each of its migrations.
+pub fn read_encrypted_key(
+    key: &[u8],
+    context: &Ctx,
+    overlay: Option<&StorageOverlay>,
+    contract_key: &ContractKey,
+) -> Result<(Option<Vec<u8>>, u64), WasmEngineError> {
+    let mut current_key = *contract_key;
+    let mut total_gas_used = 0_u64;
 
-    let mut db_data: Vec<u8> = ad.to_vec();
-    db_data.extend_from_slice(encrypted_value.as_slice());
+    loop {
+        let scrambled_field_name = field_name_digest(key, &current_key);
 
-    // Write the new data as concat(ad, encrypted_val)
-    let write_used_gas = write_db(context, &scrambled_field_name, &db_data).map_err(|err| {
-        warn!(
-            "write_db() go an error from ocall_write_db, stopping wasm: {:?}",
-            err
+        info!(
+            "Reading from scrambled field name: {:?}",
+            scrambled_field_name
         );
-        err
-    })?;
 
-    Ok(ad_used_gas + write_used_gas)
+        let (value, gas_used) =
+            read_encrypted_field(&scrambled_field_name, key, context, overlay, &current_key)?;
+        total_gas_used = total_gas_used.saturating_add(gas_used);
+        if value.is_some() {
+            return Ok((value, total_gas_used));
+        }
+
+        let (previous_key, gas_used) = read_previous_contract_key(context, overlay, &current_key)?;
+        total_gas_used = total_gas_used.saturating_add(gas_used);
+        match previous_key {
+            Some(previous_key) => current_key = previous_key,
+            None => return Ok((None, total_gas_used)),
+        }
+    }
+}
+
+/// Read many values from the contract storage, the same as `read_encrypted_key` does for each of
+/// them. The values that aren't in the overlay are read with a single ocall for every contract key
+/// they're looked up under, instead of one ocall for every value.
+pub fn read_encrypted_keys(
+    keys: &[&[u8]],
+    context: &Ctx,
+    overlay: Option<&StorageOverlay>,
+    contract_key: &ContractKey,
+) -> Result<(Vec<Option<Vec<u8>>>, u64), WasmEngineError> {
+    let mut values = vec![None; keys.len()];
+    let mut pending: Vec<usize> = (0..keys.len()).collect();
+    let mut current_key = *contract_key;
+    let mut total_gas_used = 0_u64;
+
+    while !pending.is_empty() {
+        let scrambled_field_names: Vec<[u8; 32]> = pending
+            .iter()
+            .map(|&index| field_name_digest(keys[index], &current_key))
+            .collect();
+
+        let (raw_values, gas_used) = read_raw_multi(context, overlay, &scrambled_field_names)?;
+        total_gas_used = total_gas_used.saturating_add(gas_used);
+
+        let mut not_found = Vec::new();
+        for ((index, scrambled_field_name), raw_value) in pending
+            .into_iter()
+            .zip(scrambled_field_names.iter())
+            .zip(raw_values)
+        {
+            match raw_value {
+                Some(raw_value) => {
+                    values[index] = Some(decrypt_key(
+                        &KEY_MANAGER,
+                        scrambled_field_name,
+                        keys[index],
+                        &raw_value,
+                        &current_key,
+                    )?)
+                }
+                None => not_found.push(index),
+            }
+        }
+        pending = not_found;
+        if pending.is_empty() {
+            break;
+        }
+
+        // Look up the values that weren't found under the key the contract had before it was
+        // migrated, like `read_encrypted_key` does
+        let (previous_key, gas_used) = read_previous_contract_key(context, overlay, &current_key)?;
+        total_gas_used = total_gas_used.saturating_add(gas_used);
+        match previous_key {
+            Some(previous_key) => current_key = previous_key,
+            None => break,
+        }
+    }
+
+    Ok((values, total_gas_used))
 }
 
-pub fn read_encrypted_key(
+/// Read ahead the values of `keys`, which the contract is predicted to read, and keep them in
+/// `overlay` until it does. See `ReadAhead`.
+///
+/// The host doesn't charge gas for reading ahead. A value is charged like the host charges for
+/// reading it when the contract reads it, and not at all if the prediction was wrong. Values are
+/// only looked up under the current contract key: values written before the contract was migrated
+/// are read the usual way.
+pub fn prefetch_encrypted_keys(
+    keys: &[Vec<u8>],
+    context: &Ctx,
+    overlay: &mut StorageOverlay,
+    contract_key: &ContractKey,
+) -> Result<(), WasmEngineError> {
+    let scrambled_field_names: Vec<[u8; 32]> = keys
+        .iter()
+        .map(|key| field_name_digest(key, contract_key))
+        .filter(|scrambled_field_name| !overlay.holds(scrambled_field_name))
+        .collect();
+    if scrambled_field_names.is_empty() {
+        return Ok(());
+    }
+
+    let values = prefetch_db(
+        context,
+        &scrambled_field_names
+            .iter()
+            .map(|scrambled_field_name| &scrambled_field_name[..])
+            .collect::<Vec<_>>(),
+    )?;
+    for (scrambled_field_name, value) in scrambled_field_names.iter().zip(values) {
+        overlay
+            .prefetched
+            .insert(scrambled_field_name.to_vec(), value);
+    }
+
+    Ok(())
+}
+
+pub fn remove_encrypted_key(
     key: &[u8],
     context: &Ctx,
+    mut overlay: Option<&mut StorageOverlay>,
     contract_key: &ContractKey,
-) -> Result<(Option<Vec<u8>>, u64), WasmEngineError> {
-    let scrambled_field_name = field_name_digest(key, contract_key);
+    removed_storage: &mut RemovedStorage,
+) -> Result<u64, WasmEngineError> {
+    let mut current_key = *contract_key;
+    let mut total_gas_used = 0_u64;
+
+    // The value must also be removed from under the keys the contract had before it was
+    // migrated, or a later read would fall back to the stale copy.
+    loop {
+        let scrambled_field_name = field_name_digest(key, &current_key);
+
+        info!("Removing scrambled field name: {:?}", scrambled_field_name);
+
+        // Only entries that actually exist count towards the refund
+        let (value, gas_used) = read_raw(context, overlay.as_deref(), &scrambled_field_name)?;
+        total_gas_used = total_gas_used.saturating_add(gas_used);
+        if let Some(value) = value {
+            removed_storage.record(scrambled_field_name.len(), value.len());
+        }
+
+        // Call remove_db (this bubbles up to Tendermint via ocalls and FFI to Go code)
+        // fn remove_db(context: Ctx, key: &[u8]) {
+        let gas_used =
+            remove_raw(context, overlay.as_deref_mut(), &scrambled_field_name).map_err(|err| {
+                warn!(
+                    "remove_db() got an error from ocall_remove_db, stopping wasm: {:?}",
+                    err
+                );
+                err
+            })?;
+        total_gas_used = total_gas_used.saturating_add(gas_used);
+
+        let gas_used = remove_raw(
+            context,
+            overlay.as_deref_mut(),
+            &key_index_field_name(&scrambled_field_name),
+        )?;
+        total_gas_used = total_gas_used.saturating_add(gas_used);
+
+        let (previous_key, gas_used) =
+            read_previous_contract_key(context, overlay.as_deref(), &current_key)?;
+        total_gas_used = total_gas_used.saturating_add(gas_used);
+        match previous_key {
+            Some(previous_key) => current_key = previous_key,
+            None => return Ok(total_gas_used),
+        }
+    }
+}
+
+/// The key that the values of the storage namespace `namespace` are scrambled and encrypted with,
+/// instead of `contract_key`. Namespaced values are stored under other field names and with other
+/// encryption keys than the values of any other namespace, or of the storage outside namespaces,
+/// so a contract that reads a key in one namespace can never get a value written in another.
+pub fn namespaced_contract_key(contract_key: &ContractKey, namespace: &[u8]) -> ContractKey {
+    // Can't fail, as the output is far shorter than the most HKDF-SHA256 can derive
+    let derived = hkdf_sha256(
+        contract_key,
+        NAMESPACE_KDF_SALT,
+        namespace,
+        CONTRACT_KEY_LENGTH,
+    )
+    .unwrap();
+
+    let mut namespaced_key = [0u8; CONTRACT_KEY_LENGTH];
+    namespaced_key.copy_from_slice(&derived);
+    namespaced_key
+}
+
+/// Write a value to the namespace `namespace` of the contract storage.
+///
+/// Unlike `write_encrypted_key`, no key index is written, so namespaced values aren't listed by
+/// range queries or moved by key rotations. They stay readable after either.
+pub fn write_namespaced_key(
+    namespace: &[u8],
+    key: &[u8],
+    value: &[u8],
+    context: &Ctx,
+    overlay: Option<&mut StorageOverlay>,
+    contract_key: &ContractKey,
+) -> Result<u64, WasmEngineError> {
+    let namespaced_key = namespaced_contract_key(contract_key, namespace);
+    let scrambled_field_name = field_name_digest(key, &namespaced_key);
 
     info!(
-        "Reading from scrambled field name: {:?}",
+        "Writing to namespaced scrambled field name: {:?}",
         scrambled_field_name
     );
 
-    // Call read_db (this bubbles up to Tendermint via ocalls and FFI to Go code)
-    // This returns the value from Tendermint
-    match read_db(context, &scrambled_field_name) {
-        Ok((value, gas_used)) => match value {
-            Some(value) => match decrypt_key(&scrambled_field_name, &value, contract_key) {
-                Ok(decrypted) => Ok((Some(decrypted), gas_used)),
-                // This error case is why we have all the matches here.
-                // If we successfully collected a value, but failed to decrypt it, then we propagate that error.
-                Err(err) => Err(err),
-            },
-            None => Ok((None, gas_used)),
-        },
-        Err(err) => Err(err),
+    write_encrypted_field(
+        &scrambled_field_name,
+        key,
+        value,
+        context,
+        overlay,
+        &namespaced_key,
+    )
+}
+
+/// Read a value from the namespace `namespace` of the contract storage. Like `read_encrypted_key`,
+/// values written before the contract was migrated are found under the namespaces of the keys it
+/// had before.
+pub fn read_namespaced_key(
+    namespace: &[u8],
+    key: &[u8],
+    context: &Ctx,
+    overlay: Option<&StorageOverlay>,
+    contract_key: &ContractKey,
+) -> Result<(Option<Vec<u8>>, u64), WasmEngineError> {
+    let mut current_key = *contract_key;
+    let mut total_gas_used = 0_u64;
+
+    loop {
+        let namespaced_key = namespaced_contract_key(&current_key, namespace);
+        let scrambled_field_name = field_name_digest(key, &namespaced_key);
+
+        info!(
+            "Reading from namespaced scrambled field name: {:?}",
+            scrambled_field_name
+        );
+
+        let (value, gas_used) = read_encrypted_field(
+            &scrambled_field_name,
+            key,
+            context,
+            overlay,
+            &namespaced_key,
+        )?;
+        total_gas_used = total_gas_used.saturating_add(gas_used);
+        if value.is_some() {
+            return Ok((value, total_gas_used));
+        }
+
+        let (previous_key, gas_used) = read_previous_contract_key(context, overlay, &current_key)?;
+        total_gas_used = total_gas_used.saturating_add(gas_used);
+        match previous_key {
+            Some(previous_key) => current_key = previous_key,
+            None => return Ok((None, total_gas_used)),
+        }
+    }
+}
+
+/// Remove a value from the namespace `namespace` of the contract storage, including the copies
+/// written before the contract was migrated, like `remove_encrypted_key` does.
+pub fn remove_namespaced_key(
+    namespace: &[u8],
+    key: &[u8],
+    context: &Ctx,
+    mut overlay: Option<&mut StorageOverlay>,
+    contract_key: &ContractKey,
+    removed_storage: &mut RemovedStorage,
+) -> Result<u64, WasmEngineError> {
+    let mut current_key = *contract_key;
+    let mut total_gas_used = 0_u64;
+
+    loop {
+        let namespaced_key = namespaced_contract_key(&current_key, namespace);
+        let scrambled_field_name = field_name_digest(key, &namespaced_key);
+
+        info!(
+            "Removing namespaced scrambled field name: {:?}",
+            scrambled_field_name
+        );
+
+        let (value, gas_used) = read_raw(context, overlay.as_deref(), &scrambled_field_name)?;
+        total_gas_used = total_gas_used.saturating_add(gas_used);
+        if let Some(value) = value {
+            removed_storage.record(scrambled_field_name.len(), value.len());
+        }
+
+        total_gas_used = total_gas_used.saturating_add(remove_raw(
+            context,
+            overlay.as_deref_mut(),
+            &scrambled_field_name,
+        )?);
+
+        let (previous_key, gas_used) =
+            read_previous_contract_key(context, overlay.as_deref(), &current_key)?;
+        total_gas_used = total_gas_used.saturating_add(gas_used);
+        match previous_key {
+            Some(previous_key) => current_key = previous_key,
+            None => return Ok(total_gas_used),
+        }
+    }
+}
+
+/// Move a value the contract wrote outside of namespaces into the namespace `namespace`, for
+/// contracts that start keeping existing state in namespaces. A value the namespace already has
+/// for the key is overwritten. Returns whether there was a value to move.
+pub fn move_key_to_namespace(
+    namespace: &[u8],
+    key: &[u8],
+    context: &Ctx,
+    mut overlay: Option<&mut StorageOverlay>,
+    contract_key: &ContractKey,
+) -> Result<(bool, u64), WasmEngineError> {
+    let (value, mut total_gas_used) =
+        read_encrypted_key(key, context, overlay.as_deref(), contract_key)?;
+    let value = match value {
+        Some(value) => value,
+        None => return Ok((false, total_gas_used)),
+    };
+
+    total_gas_used = total_gas_used.saturating_add(write_namespaced_key(
+        namespace,
+        key,
+        &value,
+        context,
+        overlay.as_deref_mut(),
+        contract_key,
+    )?);
+    // Nothing is refunded for the entries removed here, since the value is written again
+    total_gas_used = total_gas_used.saturating_add(remove_encrypted_key(
+        key,
+        context,
+        overlay,
+        contract_key,
+        &mut RemovedStorage::default(),
+    )?);
+
+    Ok((true, total_gas_used))
+}
+
+/// List the plaintext keys the contract wrote in the range `[start, end)`, in ascending order.
+///
+/// This includes keys that were written before the contract was migrated. Values written before
+/// range queries were supported have no key index, so they are not listed.
+pub fn scan_encrypted_keys(
+    start: Option<&[u8]>,
+    end: Option<&[u8]>,
+    context: &Ctx,
+    overlay: Option<&StorageOverlay>,
+    contract_key: &ContractKey,
+) -> Result<(Vec<Vec<u8>>, u64), WasmEngineError> {
+    let mut total_gas_used = 0_u64;
+
+    // Index fields are encrypted with the contract key that was used when the value was written
+    let mut contract_keys = vec![*contract_key];
+    loop {
+        let (previous_key, gas_used) =
+            read_previous_contract_key(context, overlay, contract_keys.last().unwrap())?;
+        total_gas_used = total_gas_used.saturating_add(gas_used);
+        match previous_key {
+            Some(previous_key) => contract_keys.push(previous_key),
+            None => break,
+        }
+    }
+
+    let (iterator_id, gas_used) = scan_db(context, KEY_INDEX_PREFIX, KEY_INDEX_PREFIX_END)?;
+    total_gas_used = total_gas_used.saturating_add(gas_used);
+
+    let mut index_fields = BTreeMap::new();
+    loop {
+        let (next, gas_used) = next_db(context, iterator_id)?;
+        total_gas_used = total_gas_used.saturating_add(gas_used);
+
+        match next {
+            Some((index_field_name, value)) => index_fields.insert(index_field_name, value),
+            None => break,
+        };
+    }
+
+    if let Some(overlay) = overlay {
+        let (merged, gas_used) =
+            overlay.merge_range(KEY_INDEX_PREFIX, KEY_INDEX_PREFIX_END, index_fields);
+        index_fields = merged;
+        total_gas_used = total_gas_used.saturating_add(gas_used);
+    }
+
+    let mut keys = Vec::new();
+    for (index_field_name, value) in index_fields {
+        keys.push(decrypt_key_index(
+            &index_field_name,
+            &value,
+            &contract_keys,
+        )?);
+    }
+
+    Ok((keys_in_range(keys, start, end), total_gas_used))
+}
+
+/// Move a value from under the keys the contract had before a key rotation to under its current
+/// key. The key rotation linked the current key to the previous one, so the value stays readable
+/// whether or not it was moved yet.
+///
+/// If the contract wrote the value again since the rotation started, the newer value is kept.
+pub fn rotate_encrypted_key(
+    key: &[u8],
+    context: &Ctx,
+    contract_key: &ContractKey,
+    previous_contract_key: &ContractKey,
+) -> Result<u64, WasmEngineError> {
+    let scrambled_field_name = field_name_digest(key, contract_key);
+    let (current_value, mut total_gas_used) =
+        read_encrypted_field(&scrambled_field_name, key, context, None, contract_key)?;
+
+    if current_value.is_none() {
+        let (value, gas_used) = read_encrypted_key(key, context, None, previous_contract_key)?;
+        total_gas_used = total_gas_used.saturating_add(gas_used);
+        if let Some(value) = value {
+            total_gas_used = total_gas_used.saturating_add(write_encrypted_key(
+                key,
+                &value,
+                context,
+                None,
+                contract_key,
+            )?);
+        }
+    }
+
+    // Nothing is refunded for the entries removed here, since the value is written again
+    let gas_used = remove_encrypted_key(
+        key,
+        context,
+        None,
+        previous_contract_key,
+        &mut RemovedStorage::default(),
+    )?;
+
+    Ok(total_gas_used.saturating_add(gas_used))
+}
+
+/// Split the next chunk of a key rotation off the sorted keys that are left to rotate.
+/// Returns the chunk and the key the chunk after it starts from, which is empty after the last
+/// chunk. A key that starts a chunk is never empty, since it's larger than the keys before it.
+pub fn split_rotation_chunk(mut keys: Vec<Vec<u8>>, chunk_size: usize) -> (Vec<Vec<u8>>, Vec<u8>) {
+    if keys.len() <= chunk_size {
+        return (keys, vec![]);
+    }
+
+    let rest = keys.split_off(chunk_size);
+    let next_cursor = rest.into_iter().next().unwrap_or_default();
+    (keys, next_cursor)
+}
+
+/// Sort and deduplicate the keys, and keep only the ones in the range `[start, end)`.
+/// A key can be listed more than once if it was written both before and after a migration.
+fn keys_in_range(keys: Vec<Vec<u8>>, start: Option<&[u8]>, end: Option<&[u8]>) -> Vec<Vec<u8>> {
+    keys.into_iter()
+        .filter(|key| start.map_or(true, |start| key.as_slice() >= start))
+        .filter(|key| end.map_or(true, |end| key.as_slice() < end))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+/// Record the plaintext key of a value, unless it was already recorded.
+fn write_key_index(
+    key: &[u8],
+    scrambled_field_name: &[u8; 32],
+    context: &Ctx,
+    overlay: Option<&mut StorageOverlay>,
+    contract_key: &ContractKey,
+) -> Result<u64, WasmEngineError> {
+    let index_field_name = key_index_field_name(scrambled_field_name);
+
+    let (existing, read_gas_used) = read_raw(context, overlay.as_deref(), &index_field_name)?;
+    if existing.is_some() {
+        return Ok(read_gas_used);
+    }
+
+    // The field holds the key, so it's bound to its own name rather than to the key
+    let write_gas_used = write_encrypted_field(
+        &index_field_name,
+        &index_field_name,
+        key,
+        context,
+        overlay,
+        contract_key,
+    )?;
+    Ok(read_gas_used.saturating_add(write_gas_used))
+}
+
+/// Decrypt a key index field, and make sure it belongs to the key it was stored under.
+/// Otherwise the host could present the key of one value under the field name of another.
+fn decrypt_key_index(
+    index_field_name: &[u8],
+    value: &[u8],
+    contract_keys: &[ContractKey],
+) -> Result<Vec<u8>, WasmEngineError> {
+    if !index_field_name.starts_with(KEY_INDEX_PREFIX) || value.len() < 32 {
+        warn!("Got a malformed key index field from the host");
+        return Err(WasmEngineError::HostMisbehavior);
+    }
+    let scrambled_field_name = &index_field_name[KEY_INDEX_PREFIX.len()..];
+
+    for contract_key in contract_keys {
+        if let Ok(key) = decrypt_key(
+            &KEY_MANAGER,
+            index_field_name,
+            index_field_name,
+            value,
+            contract_key,
+        ) {
+            if constant_time_eq(&field_name_digest(&key, contract_key), scrambled_field_name) {
+                return Ok(key);
+            }
+        }
+    }
+
+    warn!("Failed to decrypt a key index field with any of the contract keys");
+    Err(WasmEngineError::DecryptionError)
+}
+
+fn key_index_field_name(scrambled_field_name: &[u8; 32]) -> Vec<u8> {
+    let mut field_name = KEY_INDEX_PREFIX.to_vec();
+    field_name.extend_from_slice(scrambled_field_name);
+    field_name
+}
+
+/// Record that state written under `previous_contract_key` belongs to the contract that now uses
+/// `contract_key`. This is called when a contract is migrated to new code.
+pub fn write_previous_contract_key(
+    context: &Ctx,
+    overlay: Option<&mut StorageOverlay>,
+    contract_key: &ContractKey,
+    previous_contract_key: &ContractKey,
+) -> Result<u64, WasmEngineError> {
+    let scrambled_field_name = previous_contract_key_digest(contract_key);
+
+    write_encrypted_field(
+        &scrambled_field_name,
+        PREVIOUS_CONTRACT_KEY_FIELD,
+        previous_contract_key,
+        context,
+        overlay,
+        contract_key,
+    )
+}
+
+pub fn read_previous_contract_key(
+    context: &Ctx,
+    overlay: Option<&StorageOverlay>,
+    contract_key: &ContractKey,
+) -> Result<(Option<ContractKey>, u64), WasmEngineError> {
+    let scrambled_field_name = previous_contract_key_digest(contract_key);
+
+    let (value, gas_used) = read_encrypted_field(
+        &scrambled_field_name,
+        PREVIOUS_CONTRACT_KEY_FIELD,
+        context,
+        overlay,
+        contract_key,
+    )?;
+    match value {
+        Some(value) if value.len() == CONTRACT_KEY_LENGTH => {
+            let mut previous_key = [0u8; CONTRACT_KEY_LENGTH];
+            previous_key.copy_from_slice(&value);
+            Ok((Some(previous_key), gas_used))
+        }
+        Some(_) => {
+            warn!("Found a malformed previous contract key in the contract storage");
+            Err(WasmEngineError::DecryptionError)
+        }
+        None => Ok((None, gas_used)),
+    }
+}
+
+/// Read how many bytes the contract stores, see `StorageUsage`. Until the contract changes its
+/// storage after a migration, the usage is found under the key it had before, like its values.
+/// A contract that didn't change its storage since the usage is tracked stores 0 bytes.
+pub fn read_storage_usage(
+    context: &Ctx,
+    overlay: Option<&StorageOverlay>,
+    contract_key: &ContractKey,
+) -> Result<(StorageUsage, u64), WasmEngineError> {
+    let mut current_key = *contract_key;
+    let mut total_gas_used = 0_u64;
+
+    loop {
+        let (value, gas_used) = read_encrypted_field(
+            &storage_usage_digest(&current_key),
+            STORAGE_USAGE_FIELD,
+            context,
+            overlay,
+            &current_key,
+        )?;
+        total_gas_used = total_gas_used.saturating_add(gas_used);
+        if let Some(value) = value {
+            return Ok((decode_storage_usage(&value)?, total_gas_used));
+        }
+
+        let (previous_key, gas_used) = read_previous_contract_key(context, overlay, &current_key)?;
+        total_gas_used = total_gas_used.saturating_add(gas_used);
+        match previous_key {
+            Some(previous_key) => current_key = previous_key,
+            None => return Ok((StorageUsage::default(), total_gas_used)),
+        }
+    }
+}
+
+/// Write how many bytes the contract stores under its current key
+pub fn write_storage_usage(
+    context: &Ctx,
+    overlay: Option<&mut StorageOverlay>,
+    contract_key: &ContractKey,
+    usage: &StorageUsage,
+) -> Result<u64, WasmEngineError> {
+    write_encrypted_field(
+        &storage_usage_digest(contract_key),
+        STORAGE_USAGE_FIELD,
+        &usage.bytes().to_be_bytes(),
+        context,
+        overlay,
+        contract_key,
+    )
+}
+
+/// Record that `signer` revoked its query permits named `permit_name`, under the current key
+pub fn write_query_permit_revocation(
+    context: &Ctx,
+    overlay: Option<&mut StorageOverlay>,
+    contract_key: &ContractKey,
+    signer: &[u8],
+    permit_name: &str,
+) -> Result<u64, WasmEngineError> {
+    write_encrypted_field(
+        &revoked_query_permit_digest(contract_key, signer, permit_name),
+        REVOKED_QUERY_PERMIT_FIELD,
+        &[1],
+        context,
+        overlay,
+        contract_key,
+    )
+}
+
+/// Record that the contract executed a tx message encrypted by `user_public_key` with `nonce`, at
+/// `height`, under the current key
+pub fn write_used_tx_nonce(
+    context: &Ctx,
+    overlay: Option<&mut StorageOverlay>,
+    contract_key: &ContractKey,
+    user_public_key: &[u8],
+    nonce: &[u8],
+    height: u64,
+) -> Result<u64, WasmEngineError> {
+    write_encrypted_field(
+        &used_tx_nonce_digest(contract_key, user_public_key, nonce),
+        USED_TX_NONCE_FIELD,
+        &height.to_be_bytes(),
+        context,
+        overlay,
+        contract_key,
+    )
+}
+
+/// Whether the contract executed a tx message encrypted by `user_public_key` with `nonce`. Messages
+/// executed before the contract was migrated are found under the key it had then, like its values.
+pub fn read_used_tx_nonce(
+    context: &Ctx,
+    overlay: Option<&StorageOverlay>,
+    contract_key: &ContractKey,
+    user_public_key: &[u8],
+    nonce: &[u8],
+) -> Result<(bool, u64), WasmEngineError> {
+    let mut current_key = *contract_key;
+    let mut total_gas_used = 0_u64;
+
+    loop {
+        let (value, gas_used) = read_encrypted_field(
+            &used_tx_nonce_digest(&current_key, user_public_key, nonce),
+            USED_TX_NONCE_FIELD,
+            context,
+            overlay,
+            &current_key,
+        )?;
+        total_gas_used = total_gas_used.saturating_add(gas_used);
+        if value.is_some() {
+            return Ok((true, total_gas_used));
+        }
+
+        let (previous_key, gas_used) = read_previous_contract_key(context, overlay, &current_key)?;
+        total_gas_used = total_gas_used.saturating_add(gas_used);
+        match previous_key {
+            Some(previous_key) => current_key = previous_key,
+            None => return Ok((false, total_gas_used)),
+        }
+    }
+}
+
+/// Whether `signer` revoked its query permits named `permit_name`. Revocations made before the
+/// contract was migrated are found under the key it had then, like its values.
+pub fn read_query_permit_revocation(
+    context: &Ctx,
+    overlay: Option<&StorageOverlay>,
+    contract_key: &ContractKey,
+    signer: &[u8],
+    permit_name: &str,
+) -> Result<(bool, u64), WasmEngineError> {
+    let mut current_key = *contract_key;
+    let mut total_gas_used = 0_u64;
+
+    loop {
+        let (value, gas_used) = read_encrypted_field(
+            &revoked_query_permit_digest(&current_key, signer, permit_name),
+            REVOKED_QUERY_PERMIT_FIELD,
+            context,
+            overlay,
+            &current_key,
+        )?;
+        total_gas_used = total_gas_used.saturating_add(gas_used);
+        if value.is_some() {
+            return Ok((true, total_gas_used));
+        }
+
+        let (previous_key, gas_used) = read_previous_contract_key(context, overlay, &current_key)?;
+        total_gas_used = total_gas_used.saturating_add(gas_used);
+        match previous_key {
+            Some(previous_key) => current_key = previous_key,
+            None => return Ok((false, total_gas_used)),
+        }
+    }
+}
+
+fn decode_storage_usage(value: &[u8]) -> Result<StorageUsage, WasmEngineError> {
+    if value.len() != 8 {
+        warn!("Found a malformed storage usage in the contract storage");
+        return Err(WasmEngineError::DecryptionError);
+    }
+
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(value);
+    Ok(StorageUsage::stored(u64::from_be_bytes(bytes)))
+}
+
+/// Encrypt `value` and write it under `scrambled_field_name`. `field_key` is the plaintext key the
+/// value is written under, which it's bound to.
+fn write_encrypted_field(
+    scrambled_field_name: &[u8],
+    field_key: &[u8],
+    value: &[u8],
+    context: &Ctx,
+    overlay: Option<&mut StorageOverlay>,
+    contract_key: &ContractKey,
+) -> Result<u64, WasmEngineError> {
+    let (ad, ad_used_gas) =
+        derive_ad_for_field(scrambled_field_name, &context, overlay.as_deref())?;
+
+    let encrypted_value = encrypt_key(
+        &KEY_MANAGER,
+        scrambled_field_name,
+        field_key,
+        value,
+        contract_key,
+        &ad,
+    )?;
+
+    let mut db_data: Vec<u8> = ad.to_vec();
+    db_data.extend_from_slice(encrypted_value.as_slice());
+
+    // Write the new data as concat(ad, version, key_generation, encrypted_val)
+    let write_used_gas =
+        write_raw(context, overlay, scrambled_field_name, &db_data).map_err(|err| {
+            warn!(
+                "write_db() go an error from ocall_write_db, stopping wasm: {:?}",
+                err
+            );
+            err
+        })?;
+
+    Ok(ad_used_gas.saturating_add(write_used_gas))
+}
+
+fn read_encrypted_field(
+    scrambled_field_name: &[u8; 32],
+    field_key: &[u8],
+    context: &Ctx,
+    overlay: Option<&StorageOverlay>,
+    contract_key: &ContractKey,
+) -> Result<(Option<Vec<u8>>, u64), WasmEngineError> {
+    // Call read_db (this bubbles up to Tendermint via ocalls and FFI to Go code)
+    // This returns the value from Tendermint
+    match read_raw(context, overlay, scrambled_field_name) {
+        Ok((value, gas_used)) => match value {
+            Some(value) => {
+                match decrypt_key(
+                    &KEY_MANAGER,
+                    scrambled_field_name,
+                    field_key,
+                    &value,
+                    contract_key,
+                ) {
+                    Ok(decrypted) => Ok((Some(decrypted), gas_used)),
+                    // This error case is why we have all the matches here.
+                    // If we successfully collected a value, but failed to decrypt it, then we propagate that error.
+                    Err(err) => Err(err),
+                }
+            }
+            None => Ok((None, gas_used)),
+        },
+        Err(err) => Err(err),
+    }
+}
+
+pub fn field_name_digest(field_name: &[u8], contract_key: &ContractKey) -> [u8; 32] {
+    let mut data = field_name.to_vec();
+    data.extend_from_slice(contract_key);
+
+    sha_256(&data)
+}
+
+fn previous_contract_key_digest(contract_key: &ContractKey) -> [u8; 32] {
+    let mut data = contract_key.to_vec();
+    data.extend_from_slice(PREVIOUS_CONTRACT_KEY_FIELD);
+
+    sha_256(&data)
+}
+
+fn storage_usage_digest(contract_key: &ContractKey) -> [u8; 32] {
+    let mut data = contract_key.to_vec();
+    data.extend_from_slice(STORAGE_USAGE_FIELD);
+
+    sha_256(&data)
+}
+
+/// The signer is prefixed with its length, so no other signer and permit name have the same digest
+fn revoked_query_permit_digest(
+    contract_key: &ContractKey,
+    signer: &[u8],
+    permit_name: &str,
+) -> [u8; 32] {
+    let mut data = contract_key.to_vec();
+    data.extend_from_slice(REVOKED_QUERY_PERMIT_FIELD);
+    data.push(signer.len() as u8);
+    data.extend_from_slice(signer);
+    data.extend_from_slice(permit_name.as_bytes());
+
+    sha_256(&data)
+}
+
+/// The public key and the nonce are always 32 bytes, so no other pair has the same digest
+fn used_tx_nonce_digest(
+    contract_key: &ContractKey,
+    user_public_key: &[u8],
+    nonce: &[u8],
+) -> [u8; 32] {
+    let mut data = contract_key.to_vec();
+    data.extend_from_slice(USED_TX_NONCE_FIELD);
+    data.extend_from_slice(user_public_key);
+    data.extend_from_slice(nonce);
+
+    sha_256(&data)
+}
+
+/// Write `value` to a reserved key through the storage ocalls, read it back, and remove it.
+/// Returns whether the value that was read back is the one that was written.
+pub fn storage_round_trip(context: &Ctx, value: &[u8]) -> Result<bool, WasmEngineError> {
+    write_db(context, HEALTH_CHECK_KEY, value)?;
+    let (read_back, _) = read_db(context, HEALTH_CHECK_KEY)?;
+    remove_db(context, HEALTH_CHECK_KEY)?;
+
+    Ok(read_back.as_deref() == Some(value))
+}
+
+/// Read up to `limit` raw entries of the contract storage, in the order of their keys, starting
+/// from the key `start`. These are the entries as the host stores them: scrambled field names and
+/// encrypted values, along with the key index and the links to previous contract keys.
+pub fn read_raw_entries(
+    context: &Ctx,
+    start: &[u8],
+    limit: usize,
+) -> Result<(Vec<(Vec<u8>, Vec<u8>)>, u64), WasmEngineError> {
+    // An empty end leaves the range open
+    let (iterator_id, mut total_gas_used) = scan_db(context, start, &[])?;
+
+    let mut entries = Vec::new();
+    while entries.len() < limit {
+        let (next, gas_used) = next_db(context, iterator_id)?;
+        total_gas_used = total_gas_used.saturating_add(gas_used);
+
+        match next {
+            Some(entry) => entries.push(entry),
+            None => break,
+        }
+    }
+
+    Ok((entries, total_gas_used))
+}
+
+/// Write a raw entry of the contract storage as it is, see `read_raw_entries`
+pub fn write_raw_entry(context: &Ctx, key: &[u8], value: &[u8]) -> Result<u64, WasmEngineError> {
+    write_db(context, key, value)
+}
+
+/// Read a raw value, from the overlay if it was changed in this simulation, or else from the host
+fn read_raw(
+    context: &Ctx,
+    overlay: Option<&StorageOverlay>,
+    key: &[u8],
+) -> Result<(Option<Vec<u8>>, u64), WasmEngineError> {
+    match overlay.map(|overlay| overlay.read(key)) {
+        Some(Some(result)) => Ok(result),
+        Some(None) => {
+            runtime_stats::record_prefetch_miss();
+            read_db(context, key)
+        }
+        None => read_db(context, key),
+    }
+}
+
+/// Read raw values from the overlay, and the ones that aren't there from the host in a single ocall
+fn read_raw_multi(
+    context: &Ctx,
+    overlay: Option<&StorageOverlay>,
+    keys: &[[u8; 32]],
+) -> Result<(Vec<Option<Vec<u8>>>, u64), WasmEngineError> {
+    let mut values = Vec::with_capacity(keys.len());
+    let mut total_gas_used = 0_u64;
+    let mut host_keys = Vec::new();
+    for (index, key) in keys.iter().enumerate() {
+        match overlay.and_then(|overlay| overlay.read(key)) {
+            Some((value, gas_used)) => {
+                values.push(value);
+                total_gas_used = total_gas_used.saturating_add(gas_used);
+            }
+            None => {
+                if overlay.is_some() {
+                    runtime_stats::record_prefetch_miss();
+                }
+                values.push(None);
+                host_keys.push((index, &key[..]));
+            }
+        }
+    }
+
+    if !host_keys.is_empty() {
+        let (host_values, gas_used) = read_db_multi(
+            context,
+            &host_keys.iter().map(|(_, key)| *key).collect::<Vec<_>>(),
+        )?;
+        total_gas_used = total_gas_used.saturating_add(gas_used);
+        for ((index, _), value) in host_keys.into_iter().zip(host_values) {
+            values[index] = value;
+        }
+    }
+
+    Ok((values, total_gas_used))
+}
+
+/// Write a raw value to the overlay when simulating, or else to the host
+fn write_raw(
+    context: &Ctx,
+    overlay: Option<&mut StorageOverlay>,
+    key: &[u8],
+    value: &[u8],
+) -> Result<u64, WasmEngineError> {
+    match overlay {
+        Some(overlay) => Ok(overlay.write(key, value)),
+        None => write_db(context, key, value),
+    }
+}
+
+/// Remove a raw value in the overlay when simulating, or else from the host
+fn remove_raw(
+    context: &Ctx,
+    overlay: Option<&mut StorageOverlay>,
+    key: &[u8],
+) -> Result<u64, WasmEngineError> {
+    match overlay {
+        Some(overlay) => Ok(overlay.remove(key)),
+        None => remove_db(context, key),
+    }
+}
+
+/// Safe wrapper around reads from the contract storage
+fn read_db(context: &Ctx, key: &[u8]) -> Result<(Option<Vec<u8>>, u64), WasmEngineError> {
+    metrics::record_ocall(Ocall::ReadDb);
+    let _span = trace_span!(Phase::Ocall(Ocall::ReadDb));
+    let mut ocall_return = OcallReturn::Success;
+    let mut enclave_buffer = std::mem::MaybeUninit::<EnclaveBuffer>::uninit();
+    let mut vm_err = UntrustedVmError::default();
+    let mut gas_used = 0_u64;
+    let value = unsafe {
+        let status = imports::ocall_read_db(
+            (&mut ocall_return) as *mut _,
+            context.unsafe_clone(),
+            (&mut vm_err) as *mut _,
+            (&mut gas_used) as *mut _,
+            enclave_buffer.as_mut_ptr(),
+            key.as_ptr(),
+            key.len(),
+        );
+        match status {
+            sgx_status_t::SGX_SUCCESS => { /* continue */ }
+            error_status => {
+                warn!(
+                    "read_db() got an error from ocall_read_db, stopping wasm: {:?}",
+                    error_status
+                );
+                return Err(WasmEngineError::FailedOcall(vm_err));
+            }
+        }
+
+        match ocall_return {
+            OcallReturn::Success => {
+                let enclave_buffer = enclave_buffer.assume_init();
+                exports::recover_buffer(enclave_buffer)?
+            }
+            OcallReturn::Failure => {
+                return Err(WasmEngineError::failed_ocall(vm_err));
+            }
+            OcallReturn::Panic => return Err(WasmEngineError::Panic),
+        }
+    };
+
+    Ok((value, gas_used))
+}
+
+/// Safe wrapper around reading many values from the raw contract storage in a single ocall.
+/// The keys are sent as sections, and the host answers with a value or a missing value for every
+/// key, see `encode_optional_sections`.
+fn read_db_multi(
+    context: &Ctx,
+    keys: &[&[u8]],
+) -> Result<(Vec<Option<Vec<u8>>>, u64), WasmEngineError> {
+    metrics::record_ocall(Ocall::ReadDbMulti);
+    let _span = trace_span!(Phase::Ocall(Ocall::ReadDbMulti));
+    let packed_keys = encode_sections(keys);
+
+    let mut ocall_return = OcallReturn::Success;
+    let mut enclave_buffer = std::mem::MaybeUninit::<EnclaveBuffer>::uninit();
+    let mut vm_err = UntrustedVmError::default();
+    let mut gas_used = 0_u64;
+    let packed_values = unsafe {
+        let status = imports::ocall_read_db_multi(
+            (&mut ocall_return) as *mut _,
+            context.unsafe_clone(),
+            (&mut vm_err) as *mut _,
+            (&mut gas_used) as *mut _,
+            enclave_buffer.as_mut_ptr(),
+            packed_keys.as_ptr(),
+            packed_keys.len(),
+        );
+        match status {
+            sgx_status_t::SGX_SUCCESS => { /* continue */ }
+            error_status => {
+                warn!(
+                    "read_db_multi() got an error from ocall_read_db_multi, stopping wasm: {:?}",
+                    error_status
+                );
+                return Err(WasmEngineError::FailedOcall(vm_err));
+            }
+        }
+
+        match ocall_return {
+            OcallReturn::Success => {
+                let enclave_buffer = enclave_buffer.assume_init();
+                exports::recover_buffer(enclave_buffer)?
+            }
+            OcallReturn::Failure => {
+                return Err(WasmEngineError::failed_ocall(vm_err));
+            }
+            OcallReturn::Panic => return Err(WasmEngineError::Panic),
+        }
+    };
+
+    match decode_optional_sections(&packed_values.unwrap_or_default()) {
+        Some(values) if values.len() == keys.len() => Ok((values, gas_used)),
+        _ => {
+            warn!("read_db_multi() got a malformed response from the host");
+            Err(WasmEngineError::HostMisbehavior)
+        }
     }
 }
 
-pub fn remove_encrypted_key(
-    key: &[u8],
-    context: &Ctx,
-    contract_key: &ContractKey,
-) -> Result<u64, WasmEngineError> {
-    let scrambled_field_name = field_name_digest(key, contract_key);
-
-    info!("Removing scrambled field name: {:?}", scrambled_field_name);
+/// Safe wrapper around reading values from the raw contract storage without being charged for
+/// them, see `prefetch_encrypted_keys`. Packed like `read_db_multi`.
+fn prefetch_db(context: &Ctx, keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>, WasmEngineError> {
+    metrics::record_ocall(Ocall::PrefetchDb);
+    let _span = trace_span!(Phase::Ocall(Ocall::PrefetchDb));
+    let packed_keys = encode_sections(keys);
 
-    // Call remove_db (this bubbles up to Tendermint via ocalls and FFI to Go code)
-    // fn remove_db(context: Ctx, key: &[u8]) {
-    let gas_used = remove_db(context, &scrambled_field_name).map_err(|err| {
-        warn!(
-            "remove_db() got an error from ocall_remove_db, stopping wasm: {:?}",
-            err
+    let mut ocall_return = OcallReturn::Success;
+    let mut enclave_buffer = std::mem::MaybeUninit::<EnclaveBuffer>::uninit();
+    let mut vm_err = UntrustedVmError::default();
+    let packed_values = unsafe {
+        let status = imports::ocall_prefetch_db(
+            (&mut ocall_return) as *mut _,
+            context.unsafe_clone(),
+            (&mut vm_err) as *mut _,
+            enclave_buffer.as_mut_ptr(),
+            packed_keys.as_ptr(),
+            packed_keys.len(),
         );
-        err
-    })?;
-    Ok(gas_used)
+        match status {
+            sgx_status_t::SGX_SUCCESS => { /* continue */ }
+            error_status => {
+                warn!(
+                    "prefetch_db() got an error from ocall_prefetch_db: {:?}",
+                    error_status
+                );
+                return Err(WasmEngineError::FailedOcall(vm_err));
+            }
+        }
+
+        match ocall_return {
+            OcallReturn::Success => {
+                let enclave_buffer = enclave_buffer.assume_init();
+                exports::recover_buffer(enclave_buffer)?
+            }
+            OcallReturn::Failure => {
+                return Err(WasmEngineError::failed_ocall(vm_err));
+            }
+            OcallReturn::Panic => return Err(WasmEngineError::Panic),
+        }
+    };
+
+    match decode_optional_sections(&packed_values.unwrap_or_default()) {
+        Some(values) if values.len() == keys.len() => Ok(values),
+        _ => {
+            warn!("prefetch_db() got a malformed response from the host");
+            Err(WasmEngineError::HostMisbehavior)
+        }
+    }
 }
 
-pub fn field_name_digest(field_name: &[u8], contract_key: &ContractKey) -> [u8; 32] {
-    let mut data = field_name.to_vec();
-    data.extend_from_slice(contract_key);
+/// Safe wrapper around opening an iterator over the raw contract storage.
+/// The iterator lives on the host until the end of the ecall.
+fn scan_db(context: &Ctx, start: &[u8], end: &[u8]) -> Result<(u32, u64), WasmEngineError> {
+    metrics::record_ocall(Ocall::DbScan);
+    let _span = trace_span!(Phase::Ocall(Ocall::DbScan));
+    let mut ocall_return = OcallReturn::Success;
+    let mut vm_err = UntrustedVmError::default();
+    let mut gas_used = 0_u64;
+    let mut iterator_id = 0_u32;
+    match unsafe {
+        imports::ocall_db_scan(
+            (&mut ocall_return) as *mut _,
+            context.unsafe_clone(),
+            (&mut vm_err) as *mut _,
+            (&mut gas_used) as *mut _,
+            (&mut iterator_id) as *mut _,
+            start.as_ptr(),
+            start.len(),
+            end.as_ptr(),
+            end.len(),
+        )
+    } {
+        sgx_status_t::SGX_SUCCESS => { /* continue */ }
+        error_status => {
+            warn!(
+                "scan_db() got an error from ocall_db_scan, stopping wasm: {:?}",
+                error_status
+            );
+            return Err(WasmEngineError::FailedOcall(vm_err));
+        }
+    }
 
-    sha_256(&data)
+    match ocall_return {
+        OcallReturn::Success => Ok((iterator_id, gas_used)),
+        OcallReturn::Failure => Err(WasmEngineError::FailedOcall(vm_err)),
+        OcallReturn::Panic => Err(WasmEngineError::Panic),
+    }
 }
 
-/// Safe wrapper around reads from the contract storage
-fn read_db(context: &Ctx, key: &[u8]) -> Result<(Option<Vec<u8>>, u64), WasmEngineError> {
+/// Safe wrapper around advancing an iterator over the raw contract storage.
+/// Returns `None` when the iterator is exhausted.
+fn next_db(
+    context: &Ctx,
+    iterator_id: u32,
+) -> Result<(Option<(Vec<u8>, Vec<u8>)>, u64), WasmEngineError> {
+    metrics::record_ocall(Ocall::DbNext);
+    let _span = trace_span!(Phase::Ocall(Ocall::DbNext));
     let mut ocall_return = OcallReturn::Success;
-    let mut enclave_buffer = std::mem::MaybeUninit::<EnclaveBuffer>::uninit();
+    let mut key_buffer = std::mem::MaybeUninit::<EnclaveBuffer>::uninit();
+    let mut value_buffer = std::mem::MaybeUninit::<EnclaveBuffer>::uninit();
     let mut vm_err = UntrustedVmError::default();
     let mut gas_used = 0_u64;
-    let value = unsafe {
-        let status = imports::ocall_read_db(
+    let kv = unsafe {
+        let status = imports::ocall_db_next(
             (&mut ocall_return) as *mut _,
             context.unsafe_clone(),
             (&mut vm_err) as *mut _,
             (&mut gas_used) as *mut _,
-            enclave_buffer.as_mut_ptr(),
-            key.as_ptr(),
-            key.len(),
+            iterator_id,
+            key_buffer.as_mut_ptr(),
+            value_buffer.as_mut_ptr(),
         );
         match status {
             sgx_status_t::SGX_SUCCESS => { /* continue */ }
             error_status => {
                 warn!(
-                    "read_db() got an error from ocall_read_db, stopping wasm: {:?}",
+                    "next_db() got an error from ocall_db_next, stopping wasm: {:?}",
                     error_status
                 );
                 return Err(WasmEngineError::FailedOcall(vm_err));
@@ -127,25 +1568,29 @@ fn read_db(context: &Ctx, key: &[u8]) -> Result<(Option<Vec<u8>>, u64), WasmEngi
 
         match ocall_return {
             OcallReturn::Success => {
-                let enclave_buffer = enclave_buffer.assume_init();
-                exports::recover_buffer(enclave_buffer)?
+                let key = exports::recover_buffer(key_buffer.assume_init())?;
+                let value = exports::recover_buffer(value_buffer.assume_init())?;
+                // An empty key marks the end of the iterator
+                key.map(|key| (key, value.unwrap_or_default()))
             }
             OcallReturn::Failure => {
-                return Err(WasmEngineError::FailedOcall(vm_err));
+                return Err(WasmEngineError::failed_ocall(vm_err));
             }
             OcallReturn::Panic => return Err(WasmEngineError::Panic),
         }
     };
 
-    Ok((value, gas_used))
+    Ok((kv, gas_used))
 }
 
 /// Safe wrapper around reads from the contract storage
 fn remove_db(context: &Ctx, key: &[u8]) -> Result<u64, WasmEngineError> {
+    metrics::record_ocall(Ocall::RemoveDb);
+    let _span = trace_span!(Phase::Ocall(Ocall::RemoveDb));
     let mut ocall_return = OcallReturn::Success;
     let mut vm_err = UntrustedVmError::default();
     let mut gas_used = 0_u64;
-    match unsafe {
+    let status = unsafe {
         imports::ocall_remove_db(
             (&mut ocall_return) as *mut _,
             context.unsafe_clone(),
@@ -154,7 +1599,11 @@ fn remove_db(context: &Ctx, key: &[u8]) -> Result<u64, WasmEngineError> {
             key.as_ptr(),
             key.len(),
         )
-    } {
+    };
+    // The host may have changed the state even if the ocall failed
+    invalidate_query_cache();
+
+    match status {
         sgx_status_t::SGX_SUCCESS => { /* continue */ }
         _error_status => return Err(WasmEngineError::FailedOcall(vm_err)),
     }
@@ -168,10 +1617,12 @@ fn remove_db(context: &Ctx, key: &[u8]) -> Result<u64, WasmEngineError> {
 
 /// Safe wrapper around writes to the contract storage
 fn write_db(context: &Ctx, key: &[u8], value: &[u8]) -> Result<u64, WasmEngineError> {
+    metrics::record_ocall(Ocall::WriteDb);
+    let _span = trace_span!(Phase::Ocall(Ocall::WriteDb));
     let mut ocall_return = OcallReturn::Success;
     let mut vm_err = UntrustedVmError::default();
     let mut gas_used = 0_u64;
-    match unsafe {
+    let status = unsafe {
         imports::ocall_write_db(
             (&mut ocall_return) as *mut _,
             context.unsafe_clone(),
@@ -182,7 +1633,11 @@ fn write_db(context: &Ctx, key: &[u8], value: &[u8]) -> Result<u64, WasmEngineEr
             value.as_ptr(),
             value.len(),
         )
-    } {
+    };
+    // The host may have changed the state even if the ocall failed
+    invalidate_query_cache();
+
+    match status {
         sgx_status_t::SGX_SUCCESS => { /* continue */ }
         _err_status => return Err(WasmEngineError::FailedOcall(vm_err)),
     }
@@ -197,8 +1652,9 @@ fn write_db(context: &Ctx, key: &[u8], value: &[u8]) -> Result<u64, WasmEngineEr
 fn derive_ad_for_field(
     field_name: &[u8],
     context: &Ctx,
+    overlay: Option<&StorageOverlay>,
 ) -> Result<([u8; 32], u64), WasmEngineError> {
-    let (old_value, gas_used) = read_db(context, field_name)?;
+    let (old_value, gas_used) = read_raw(context, overlay, field_name)?;
     let ad = sha_256(
         old_value
             .as_ref()
@@ -210,16 +1666,26 @@ fn derive_ad_for_field(
     Ok((ad, gas_used))
 }
 
+/// Encrypt `value` under the current consensus seed of `keychain`. The result is prefixed with
+/// `STATE_ENCRYPTION_VERSION` and the generation of that seed, so it can still be decrypted after
+/// the seed is rotated.
+///
+/// Besides `ad`, the value is authenticated with the contract and the plaintext `field_key` it's
+/// written under, see `bound_ad`. The host can't serve it as the value of another key, or of
+/// another contract, even if it also serves it under another field name.
 fn encrypt_key(
+    keychain: &Keychain,
     field_name: &[u8],
+    field_key: &[u8],
     value: &[u8],
     contract_key: &ContractKey,
     ad: &[u8],
 ) -> Result<Vec<u8>, WasmEngineError> {
-    let encryption_key = get_symmetrical_key(field_name, contract_key);
+    let generation = keychain.get_consensus_generation();
+    let encryption_key = get_symmetrical_key(keychain, generation, field_name, contract_key)?;
 
-    encryption_key
-        .encrypt_siv(&value, Some(&[ad]))
+    let encrypted_value = encryption_key
+        .encrypt_siv(&value, Some(&bound_ad(ad, contract_key, field_key)))
         .map_err(|err| {
             warn!(
                 "write_db() got an error while trying to encrypt the value {:?}, stopping wasm: {:?}",
@@ -227,20 +1693,81 @@ fn encrypt_key(
                 err
             );
             WasmEngineError::EncryptionError
-    })
+        })?;
+
+    let mut data = vec![STATE_ENCRYPTION_VERSION, generation];
+    data.extend_from_slice(&encrypted_value);
+    Ok(data)
+}
+
+/// The associated data of a value: the `ad` chained from the value it replaced, the version of
+/// its encryption, the half of the contract key that authenticates the address of the contract
+/// (see `generate_contract_id`), and the plaintext key of the value. The contract is bound through
+/// its key rather than its address since queries don't always get the address.
+fn bound_ad<'a>(ad: &'a [u8], contract_key: &'a ContractKey, field_key: &'a [u8]) -> [&'a [u8]; 4] {
+    [
+        ad,
+        &[STATE_ENCRYPTION_VERSION],
+        &contract_key[HASH_SIZE..],
+        field_key,
+    ]
 }
 
+/// Decrypt a value as stored by `write_encrypted_field`.
+///
+/// Values written before `STATE_ENCRYPTION_VERSION` aren't bound to their key, and are decrypted
+/// the way they were encrypted until they're written again. They start with the generation of
+/// their seed, or with nothing if they were written before seeds had generations, in which case
+/// they were encrypted under the first seed.
 fn decrypt_key(
+    keychain: &Keychain,
     field_name: &[u8],
+    field_key: &[u8],
     value: &[u8],
     contract_key: &ContractKey,
 ) -> Result<Vec<u8>, WasmEngineError> {
-    let decryption_key = get_symmetrical_key(field_name, contract_key);
+    if value.len() < 32 {
+        warn!(
+            "read_db() got a value too short to be encrypted for key {:?}",
+            String::from_utf8_lossy(&field_name),
+        );
+        return Err(WasmEngineError::DecryptionError);
+    }
 
     // Slice ad from `value`
     let (ad, encrypted_value) = value.split_at(32);
 
+    // The encryption of a legacy value may happen to start with the version too, so a versioned
+    // value that fails to decrypt is still tried as a legacy one
+    let versioned = encrypted_value.first() == Some(&STATE_ENCRYPTION_VERSION);
+    if versioned {
+        let bound_ad = bound_ad(ad, contract_key, field_key);
+        if let Some(decrypted) = decrypt_with_generation(
+            keychain,
+            field_name,
+            &encrypted_value[1..],
+            contract_key,
+            &bound_ad,
+        ) {
+            return Ok(decrypted);
+        }
+    }
+
+    if let Some(decrypted) =
+        decrypt_with_generation(keychain, field_name, encrypted_value, contract_key, &[ad])
+    {
+        return Ok(decrypted);
+    }
+
+    let decryption_key = get_symmetrical_key(keychain, 0, field_name, contract_key)?;
     decryption_key.decrypt_siv(&encrypted_value, Some(&[ad])).map_err(|err| {
+        if versioned {
+            warn!(
+                "read_db() got a value that isn't bound to key {:?}, stopping wasm",
+                String::from_utf8_lossy(&field_name),
+            );
+            return WasmEngineError::StateBindingMismatch;
+        }
         warn!(
             "read_db() got an error while trying to decrypt the value for key {:?}, stopping wasm: {:?}",
             String::from_utf8_lossy(&field_name),
@@ -250,11 +1777,584 @@ fn decrypt_key(
     })
 }
 
-fn get_symmetrical_key(field_name: &[u8], contract_key: &ContractKey) -> AESKey {
-    let consensus_state_ikm = KEY_MANAGER.get_consensus_state_ikm().unwrap();
+/// Decrypt a value that starts with the generation of the seed it was encrypted under
+fn decrypt_with_generation(
+    keychain: &Keychain,
+    field_name: &[u8],
+    value: &[u8],
+    contract_key: &ContractKey,
+    ad: &[&[u8]],
+) -> Option<Vec<u8>> {
+    let (&generation, encrypted_value) = value.split_first()?;
+    let decryption_key =
+        get_symmetrical_key(keychain, generation, field_name, contract_key).ok()?;
+    decryption_key.decrypt_siv(encrypted_value, Some(ad)).ok()
+}
+
+fn get_symmetrical_key(
+    keychain: &Keychain,
+    generation: KeyGeneration,
+    field_name: &[u8],
+    contract_key: &ContractKey,
+) -> Result<AESKey, WasmEngineError> {
+    let consensus_state_ikm = keychain
+        .get_consensus_state_ikm_of(generation)
+        .ok_or_else(|| {
+            warn!(
+                "Tried to access state encrypted under unknown key generation {}",
+                generation
+            );
+            WasmEngineError::DecryptionError
+        })?;
 
     // Derive the key to the specific field name
     let mut derivation_data = field_name.to_vec();
     derivation_data.extend_from_slice(contract_key.to_vec().as_slice());
-    consensus_state_ikm.derive_key_from_this(&derivation_data)
+    Ok(consensus_state_ikm.derive_key_from_this(&derivation_data))
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+    use crate::crypto::Seed;
+
+    pub fn test_keys_in_range_sorts_and_deduplicates() {
+        let keys = vec![
+            b"banana".to_vec(),
+            b"apple".to_vec(),
+            b"cherry".to_vec(),
+            b"apple".to_vec(),
+        ];
+
+        assert_eq!(
+            keys_in_range(keys, None, None),
+            vec![b"apple".to_vec(), b"banana".to_vec(), b"cherry".to_vec()]
+        );
+    }
+
+    pub fn test_keys_in_range_bounds() {
+        let keys: Vec<Vec<u8>> = (0u8..100).map(|i| vec![b'k', i]).collect();
+
+        let in_range = keys_in_range(keys.clone(), Some(&[b'k', 10]), Some(&[b'k', 20]));
+        assert_eq!(in_range, keys[10..20].to_vec());
+
+        let from_start = keys_in_range(keys.clone(), None, Some(&[b'k', 50]));
+        assert_eq!(from_start, keys[..50].to_vec());
+
+        let to_end = keys_in_range(keys.clone(), Some(&[b'k', 90]), None);
+        assert_eq!(to_end, keys[90..].to_vec());
+
+        assert!(keys_in_range(keys, Some(&[b'k', 20]), Some(&[b'k', 10])).is_empty());
+    }
+
+    pub fn test_key_index_prefix_end() {
+        let mut prefix_end = KEY_INDEX_PREFIX.to_vec();
+        *prefix_end.last_mut().unwrap() += 1;
+        assert_eq!(prefix_end, KEY_INDEX_PREFIX_END);
+    }
+
+    // The host is never reached in these tests, since every key they touch is in the overlay
+    fn unreachable_host() -> Ctx {
+        Ctx {
+            data: std::ptr::null_mut(),
+        }
+    }
+
+    pub fn test_storage_overlay_reads_its_writes() {
+        let context = unreachable_host();
+        let mut overlay = StorageOverlay::default();
+
+        write_raw(&context, Some(&mut overlay), b"key", b"value").unwrap();
+        let (value, _) = read_raw(&context, Some(&overlay), b"key").unwrap();
+        assert_eq!(value, Some(b"value".to_vec()));
+
+        write_raw(&context, Some(&mut overlay), b"key", b"other value").unwrap();
+        let (value, _) = read_raw(&context, Some(&overlay), b"key").unwrap();
+        assert_eq!(value, Some(b"other value".to_vec()));
+
+        remove_raw(&context, Some(&mut overlay), b"key").unwrap();
+        let (value, _) = read_raw(&context, Some(&overlay), b"key").unwrap();
+        assert_eq!(value, None);
+    }
+
+    pub fn test_storage_overlay_charges_like_the_host() {
+        let context = unreachable_host();
+        let mut overlay = StorageOverlay::default();
+        let value = [7u8; 100];
+
+        let gas_used = write_raw(&context, Some(&mut overlay), b"key", &value).unwrap();
+        assert_eq!(gas_used, WRITE_COST_FLAT + WRITE_COST_PER_BYTE * 100);
+
+        let (_, gas_used) = read_raw(&context, Some(&overlay), b"key").unwrap();
+        assert_eq!(gas_used, READ_COST_FLAT + READ_COST_PER_BYTE * 100);
+
+        let gas_used = remove_raw(&context, Some(&mut overlay), b"key").unwrap();
+        assert_eq!(gas_used, DELETE_COST);
+
+        let (_, gas_used) = read_raw(&context, Some(&overlay), b"key").unwrap();
+        assert_eq!(gas_used, READ_COST_FLAT);
+
+        // The host charged none of it, so the reads are left for the contract to report. The
+        // writes are charged by the host when they're flushed.
+        assert_eq!(
+            overlay.take_served_gas(),
+            2 * READ_COST_FLAT + READ_COST_PER_BYTE * 100
+        );
+        assert_eq!(overlay.take_served_gas(), 0);
+    }
+
+    pub fn test_read_raw_multi_from_overlay() {
+        let context = unreachable_host();
+        let mut overlay = StorageOverlay::default();
+        let keys = [[1u8; 32], [2u8; 32], [3u8; 32], [1u8; 32]];
+
+        write_raw(&context, Some(&mut overlay), &keys[0], b"value").unwrap();
+        write_raw(&context, Some(&mut overlay), &keys[1], b"").unwrap();
+        remove_raw(&context, Some(&mut overlay), &keys[2]).unwrap();
+
+        // Every key is in the overlay, so the host isn't called
+        let (values, gas_used) = read_raw_multi(&context, Some(&overlay), &keys).unwrap();
+        assert_eq!(
+            values,
+            vec![
+                Some(b"value".to_vec()),
+                Some(vec![]),
+                None,
+                Some(b"value".to_vec())
+            ]
+        );
+        assert_eq!(
+            gas_used,
+            4 * READ_COST_FLAT + 2 * READ_COST_PER_BYTE * b"value".len() as u64
+        );
+        assert_eq!(overlay.take_served_gas(), gas_used);
+
+        let (values, gas_used) = read_raw_multi(&context, Some(&overlay), &[]).unwrap();
+        assert!(values.is_empty());
+        assert_eq!(gas_used, 0);
+    }
+
+    pub fn test_storage_overlay_merge_range() {
+        let mut overlay = StorageOverlay::default();
+        overlay.write(b"b", b"new");
+        overlay.write(b"c", b"changed");
+        overlay.remove(b"d");
+        overlay.write(b"z", b"out of range");
+
+        let mut host_entries = BTreeMap::new();
+        host_entries.insert(b"a".to_vec(), b"host".to_vec());
+        host_entries.insert(b"c".to_vec(), b"host".to_vec());
+        host_entries.insert(b"d".to_vec(), b"host".to_vec());
+
+        let (merged, gas_used) = overlay.merge_range(b"a", b"e", host_entries);
+        let merged: Vec<(Vec<u8>, Vec<u8>)> = merged.into_iter().collect();
+        assert_eq!(
+            merged,
+            vec![
+                (b"a".to_vec(), b"host".to_vec()),
+                (b"b".to_vec(), b"new".to_vec()),
+                (b"c".to_vec(), b"changed".to_vec()),
+            ]
+        );
+        // Only the entry the host didn't list is charged, the host charged for the rest
+        assert_eq!(gas_used, ITER_NEXT_COST_FLAT + READ_COST_PER_BYTE * 4);
+        assert_eq!(overlay.take_served_gas(), gas_used);
+    }
+
+    pub fn test_storage_overlay_keeps_final_changes() {
+        let context = unreachable_host();
+        let mut overlay = StorageOverlay::default();
+
+        // A counter updated many times is sent to the host once, with its last value
+        for count in 0u8..100 {
+            write_raw(&context, Some(&mut overlay), b"counter", &[count]).unwrap();
+        }
+        // Removed, then written again
+        remove_raw(&context, Some(&mut overlay), b"config").unwrap();
+        write_raw(&context, Some(&mut overlay), b"config", b"new config").unwrap();
+        // Written, then removed
+        write_raw(&context, Some(&mut overlay), b"temp", b"value").unwrap();
+        remove_raw(&context, Some(&mut overlay), b"temp").unwrap();
+
+        let changes: Vec<(Vec<u8>, Option<Vec<u8>>)> = overlay.take_changes().into_iter().collect();
+        assert_eq!(
+            changes,
+            vec![
+                (b"config".to_vec(), Some(b"new config".to_vec())),
+                (b"counter".to_vec(), Some(vec![99])),
+                (b"temp".to_vec(), None),
+            ]
+        );
+
+        // Once taken, the changes are read from the host again
+        assert!(overlay.read(b"counter").is_none());
+        assert!(overlay.take_changes().is_empty());
+    }
+
+    pub fn test_prefetched_values_never_hide_changes() {
+        let context = unreachable_host();
+        let mut overlay = StorageOverlay::default();
+        let value = [7u8; 100];
+        overlay
+            .prefetched
+            .insert(b"key".to_vec(), Some(value.to_vec()));
+        overlay.prefetched.insert(b"missing".to_vec(), None);
+
+        // Read ahead values are charged like the host charges for them
+        let (read, gas_used) = read_raw(&context, Some(&overlay), b"key").unwrap();
+        assert_eq!(read, Some(value.to_vec()));
+        assert_eq!(gas_used, READ_COST_FLAT + READ_COST_PER_BYTE * 100);
+        let (read, gas_used) = read_raw(&context, Some(&overlay), b"missing").unwrap();
+        assert_eq!(read, None);
+        assert_eq!(gas_used, READ_COST_FLAT);
+        // and, as the host didn't charge for reading them ahead, left for the contract to report
+        assert_eq!(
+            overlay.take_served_gas(),
+            2 * READ_COST_FLAT + READ_COST_PER_BYTE * 100
+        );
+
+        // A change replaces the value that was read ahead, and it isn't back when the change is
+        // flushed
+        write_raw(&context, Some(&mut overlay), b"key", b"changed").unwrap();
+        let (read, _) = read_raw(&context, Some(&overlay), b"key").unwrap();
+        assert_eq!(read, Some(b"changed".to_vec()));
+
+        remove_raw(&context, Some(&mut overlay), b"missing").unwrap();
+        overlay.take_changes();
+        assert!(overlay.read(b"missing").is_none());
+    }
+
+    /// Account for writing `value_len` bytes to `key`, which had a value of `previous_len` bytes
+    fn write_usage(
+        usage: &mut StorageUsage,
+        key: &[u8],
+        previous_len: Option<usize>,
+        value_len: usize,
+        quota: Option<u64>,
+    ) -> Result<(), WasmEngineError> {
+        usage.change(
+            entry_len(key.len(), previous_len),
+            entry_len(key.len(), Some(value_len)),
+            quota,
+        )
+    }
+
+    pub fn test_storage_usage_fills_to_quota() {
+        let quota = Some(1_000);
+        let mut usage = StorageUsage::default();
+
+        // Every entry counts for its key and value, 4 + 96 bytes
+        for i in 0u32..10 {
+            write_usage(&mut usage, &i.to_be_bytes(), None, 96, quota).unwrap();
+        }
+        assert_eq!(usage.bytes(), 1_000);
+
+        // The 11th entry is the first that doesn't fit, by a single byte even when it's shorter
+        assert!(matches!(
+            write_usage(&mut usage, &10u32.to_be_bytes(), None, 96, quota),
+            Err(WasmEngineError::StorageQuotaExceeded {
+                usage: 1_100,
+                quota: 1_000
+            })
+        ));
+        assert!(matches!(
+            write_usage(&mut usage, &10u32.to_be_bytes(), None, 0, quota),
+            Err(WasmEngineError::StorageQuotaExceeded {
+                usage: 1_004,
+                quota: 1_000
+            })
+        ));
+        // A failed write changes nothing
+        assert_eq!(usage.bytes(), 1_000);
+
+        // Overwriting an entry with a value of the same size, or a smaller one, still works
+        write_usage(&mut usage, &0u32.to_be_bytes(), Some(96), 96, quota).unwrap();
+        write_usage(&mut usage, &0u32.to_be_bytes(), Some(96), 90, quota).unwrap();
+        assert_eq!(usage.bytes(), 994);
+        // Growing it back past the quota doesn't
+        assert!(write_usage(&mut usage, &0u32.to_be_bytes(), Some(90), 97, quota).is_err());
+        write_usage(&mut usage, &0u32.to_be_bytes(), Some(90), 96, quota).unwrap();
+
+        // Removing an entry makes room for another
+        usage
+            .change(entry_len(4, Some(96)), entry_len(4, None), quota)
+            .unwrap();
+        assert_eq!(usage.bytes(), 900);
+        write_usage(&mut usage, &10u32.to_be_bytes(), None, 96, quota).unwrap();
+        assert_eq!(usage.bytes(), 1_000);
+    }
+
+    pub fn test_storage_usage_above_lowered_quota() {
+        let mut usage = StorageUsage::stored(1_000);
+        let quota = Some(500);
+
+        // Nothing may grow, but everything may shrink, even while still above the quota
+        assert!(write_usage(&mut usage, b"key", None, 1, quota).is_err());
+        write_usage(&mut usage, b"key", Some(100), 50, quota).unwrap();
+        usage
+            .change(entry_len(3, Some(50)), entry_len(3, None), quota)
+            .unwrap();
+        assert_eq!(usage.bytes(), 897);
+
+        // Without a quota, nothing limits the change
+        write_usage(&mut usage, b"key", None, 1_000, None).unwrap();
+        assert_eq!(usage.bytes(), 1_900);
+    }
+
+    pub fn test_storage_usage_changes_are_tracked() {
+        let mut usage = StorageUsage::stored(10);
+        assert!(!usage.changed());
+
+        write_usage(&mut usage, b"key", None, 7, None).unwrap();
+        assert!(usage.changed());
+        usage.change(10, 0, None).unwrap();
+        // Back to what's stored, so there's nothing to write
+        assert!(!usage.changed());
+
+        // Entries written before the usage was tracked can't bring it below zero
+        usage.change(1_000, 0, None).unwrap();
+        assert_eq!(usage.bytes(), 0);
+    }
+
+    pub fn test_revoked_query_permit_digests_differ() {
+        let contract_key = [1u8; CONTRACT_KEY_LENGTH];
+        let digest = revoked_query_permit_digest(&contract_key, &[2u8; 20], "permit");
+
+        assert_eq!(
+            digest,
+            revoked_query_permit_digest(&contract_key, &[2u8; 20], "permit")
+        );
+        assert_ne!(
+            digest,
+            revoked_query_permit_digest(&contract_key, &[3u8; 20], "permit")
+        );
+        assert_ne!(
+            digest,
+            revoked_query_permit_digest(&contract_key, &[2u8; 20], "other permit")
+        );
+        assert_ne!(
+            digest,
+            revoked_query_permit_digest(&[4u8; CONTRACT_KEY_LENGTH], &[2u8; 20], "permit")
+        );
+        // The end of a signer can't be passed off as the start of a permit name
+        assert_ne!(
+            revoked_query_permit_digest(&contract_key, &[2u8; 21], "permit"),
+            revoked_query_permit_digest(&contract_key, &[2u8; 20], "\u{2}permit")
+        );
+    }
+
+    pub fn test_storage_usage_encoding() {
+        for bytes in [0, 1, 1_000_000, u64::MAX].iter() {
+            let usage = StorageUsage::stored(*bytes);
+            assert_eq!(
+                decode_storage_usage(&usage.bytes().to_be_bytes()).unwrap(),
+                usage
+            );
+        }
+
+        assert!(decode_storage_usage(&[0u8; 7]).is_err());
+        assert!(decode_storage_usage(&[0u8; 9]).is_err());
+    }
+
+    pub fn test_split_rotation_chunk() {
+        // Keys are listed the way `scan_encrypted_keys` returns them, sorted
+        let keys: Vec<Vec<u8>> = (0u16..1000).map(|i| i.to_be_bytes().to_vec()).collect();
+
+        let (first_chunk, cursor) = split_rotation_chunk(keys.clone(), 500);
+        assert_eq!(first_chunk, keys[..500].to_vec());
+        assert_eq!(cursor, keys[500]);
+
+        // The next chunk starts from the cursor
+        let rest = keys_in_range(keys.clone(), Some(&cursor), None);
+        let (second_chunk, cursor) = split_rotation_chunk(rest, 500);
+        assert_eq!(second_chunk, keys[500..].to_vec());
+        assert!(cursor.is_empty());
+
+        let (chunk, cursor) = split_rotation_chunk(vec![], 500);
+        assert!(chunk.is_empty());
+        assert!(cursor.is_empty());
+    }
+
+    fn keychain_of_generations(generations: u8) -> Keychain {
+        let seeds = (0..=generations)
+            .map(|generation| {
+                let mut seed = Seed::default();
+                seed.as_mut().copy_from_slice(&[generation + 1; 32]);
+                seed
+            })
+            .collect();
+        Keychain::from_consensus_seeds(seeds, None)
+    }
+
+    pub fn test_state_readable_after_seed_rotation() {
+        let contract_key = [3u8; CONTRACT_KEY_LENGTH];
+        let ad = [5u8; 32];
+
+        let before_rotation = keychain_of_generations(0);
+        let encrypted = encrypt_key(
+            &before_rotation,
+            b"field",
+            b"key",
+            b"value",
+            &contract_key,
+            &ad,
+        )
+        .unwrap();
+        assert_eq!(encrypted[..2], [STATE_ENCRYPTION_VERSION, 0]);
+        let mut stored = ad.to_vec();
+        stored.extend_from_slice(&encrypted);
+
+        let after_rotation = keychain_of_generations(1);
+        assert_eq!(
+            decrypt_key(&after_rotation, b"field", b"key", &stored, &contract_key).unwrap(),
+            b"value".to_vec()
+        );
+
+        // New writes use the new seed, which the nodes that didn't rotate can't read
+        let encrypted = encrypt_key(
+            &after_rotation,
+            b"field",
+            b"key",
+            b"new value",
+            &contract_key,
+            &ad,
+        )
+        .unwrap();
+        assert_eq!(encrypted[..2], [STATE_ENCRYPTION_VERSION, 1]);
+        let mut stored = ad.to_vec();
+        stored.extend_from_slice(&encrypted);
+        assert_eq!(
+            decrypt_key(&after_rotation, b"field", b"key", &stored, &contract_key).unwrap(),
+            b"new value".to_vec()
+        );
+        assert!(decrypt_key(&before_rotation, b"field", b"key", &stored, &contract_key).is_err());
+    }
+
+    pub fn test_state_without_key_generation_is_readable() {
+        let contract_key = [3u8; CONTRACT_KEY_LENGTH];
+        let ad = [5u8; 32];
+        let keychain = keychain_of_generations(2);
+
+        // Values written before seeds were rotated have no generation byte
+        let encrypted = get_symmetrical_key(&keychain, 0, b"field", &contract_key)
+            .unwrap()
+            .encrypt_siv(b"value", Some(&[&ad[..]]))
+            .unwrap();
+        let mut stored = ad.to_vec();
+        stored.extend_from_slice(&encrypted);
+
+        assert_eq!(
+            decrypt_key(&keychain, b"field", b"key", &stored, &contract_key).unwrap(),
+            b"value".to_vec()
+        );
+        assert!(decrypt_key(
+            &keychain,
+            b"field",
+            b"key",
+            &stored,
+            &[4u8; CONTRACT_KEY_LENGTH]
+        )
+        .is_err());
+    }
+
+    pub fn test_state_without_binding_is_readable() {
+        let contract_key = [3u8; CONTRACT_KEY_LENGTH];
+        let ad = [5u8; 32];
+        let keychain = keychain_of_generations(2);
+
+        // Values written before they were bound to their key start with the generation
+        let encrypted = get_symmetrical_key(&keychain, 1, b"field", &contract_key)
+            .unwrap()
+            .encrypt_siv(b"value", Some(&[&ad[..]]))
+            .unwrap();
+        let mut stored = ad.to_vec();
+        stored.push(1);
+        stored.extend_from_slice(&encrypted);
+
+        assert_eq!(
+            decrypt_key(&keychain, b"field", b"key", &stored, &contract_key).unwrap(),
+            b"value".to_vec()
+        );
+        // Any key reads them, as before
+        assert_eq!(
+            decrypt_key(&keychain, b"field", b"other key", &stored, &contract_key).unwrap(),
+            b"value".to_vec()
+        );
+    }
+
+    pub fn test_swapped_state_values_are_refused() {
+        let contract_key = [3u8; CONTRACT_KEY_LENGTH];
+        let ad = [5u8; 32];
+        let keychain = keychain_of_generations(0);
+
+        let stored_value = |field_key: &[u8], value: &[u8], contract_key: &ContractKey| {
+            let mut stored = ad.to_vec();
+            stored.extend_from_slice(
+                &encrypt_key(&keychain, b"field", field_key, value, contract_key, &ad).unwrap(),
+            );
+            stored
+        };
+        let stored_a = stored_value(b"a", b"value of a", &contract_key);
+        let stored_b = stored_value(b"b", b"value of b", &contract_key);
+
+        assert_eq!(
+            decrypt_key(&keychain, b"field", b"a", &stored_a, &contract_key).unwrap(),
+            b"value of a".to_vec()
+        );
+        // Even under the same field name and encryption key, a value only decrypts for its own key
+        assert!(matches!(
+            decrypt_key(&keychain, b"field", b"a", &stored_b, &contract_key),
+            Err(WasmEngineError::StateBindingMismatch)
+        ));
+        assert!(matches!(
+            decrypt_key(&keychain, b"field", b"b", &stored_a, &contract_key),
+            Err(WasmEngineError::StateBindingMismatch)
+        ));
+
+        // or for its own contract
+        let mut other_contract_key = contract_key;
+        other_contract_key[HASH_SIZE..].copy_from_slice(&[4u8; HASH_SIZE]);
+        let stored_other = stored_value(b"a", b"value of a", &other_contract_key);
+        assert!(matches!(
+            decrypt_key(&keychain, b"field", b"a", &stored_other, &contract_key),
+            Err(WasmEngineError::StateBindingMismatch)
+        ));
+    }
+
+    pub fn test_namespaces_are_isolated() {
+        let contract_key = [3u8; CONTRACT_KEY_LENGTH];
+        let ad = [5u8; 32];
+        let keychain = keychain_of_generations(0);
+
+        let key_a = namespaced_contract_key(&contract_key, b"a");
+        let key_b = namespaced_contract_key(&contract_key, b"b");
+        assert_eq!(key_a, namespaced_contract_key(&contract_key, b"a"));
+        assert_ne!(key_a, key_b);
+        assert_ne!(key_a, contract_key);
+        // The namespace is mixed with the contract key, not only appended to it
+        assert_ne!(
+            key_a,
+            namespaced_contract_key(&[4u8; CONTRACT_KEY_LENGTH], b"a")
+        );
+
+        // The same plaintext key is stored under another field name in every namespace
+        let field_a = field_name_digest(b"balance", &key_a);
+        let field_b = field_name_digest(b"balance", &key_b);
+        assert_ne!(field_a, field_b);
+        assert_ne!(field_a, field_name_digest(b"balance", &contract_key));
+
+        // A value written in namespace B can't be read in namespace A, even if the host serves it
+        // under the field name namespace A reads from
+        let encrypted =
+            encrypt_key(&keychain, &field_b, b"balance", b"value", &key_b, &ad).unwrap();
+        let mut stored = ad.to_vec();
+        stored.extend_from_slice(&encrypted);
+        assert_eq!(
+            decrypt_key(&keychain, &field_b, b"balance", &stored, &key_b).unwrap(),
+            b"value".to_vec()
+        );
+        assert!(decrypt_key(&keychain, &field_a, b"balance", &stored, &key_a).is_err());
+        assert!(decrypt_key(&keychain, &field_b, b"balance", &stored, &key_a).is_err());
+        assert!(decrypt_key(&keychain, &field_b, b"balance", &stored, &contract_key).is_err());
+    }
 }
@@ -0,0 +1,69 @@
+use std::cell::Cell;
+
+use log::*;
+use sgx_types::sgx_status_t;
+
+use super::errors::WasmEngineError;
+use crate::consts::EXECUTION_DEADLINE_CHECK_INTERVAL;
+use crate::imports;
+
+/// A wall-clock limit on an execution, on top of its gas limit, for contracts that are slow
+/// without using much gas.
+///
+/// The clock belongs to the host, which can make it say anything. That's fine as long as the
+/// deadline can only stop a contract that would otherwise keep running: it never adds gas, and a
+/// contract that runs out of gas stops the same with or without it.
+pub struct ExecutionDeadline {
+    /// On the monotonic clock of the host, in nanoseconds
+    deadline: u64,
+    /// The gas used when the clock was last read
+    checked_at_gas: Cell<u64>,
+}
+
+impl ExecutionDeadline {
+    /// Start the clock on an execution that may run for `timeout_ms` milliseconds
+    pub fn start(timeout_ms: u64) -> Result<Self, WasmEngineError> {
+        let now = monotonic_now()?;
+        Ok(Self {
+            deadline: now.saturating_add(timeout_ms.saturating_mul(1_000_000)),
+            checked_at_gas: Cell::new(0),
+        })
+    }
+
+    /// Read the clock once every `EXECUTION_DEADLINE_CHECK_INTERVAL` gas, and fail if the deadline
+    /// passed
+    pub fn check(&self, gas_used: u64) -> Result<(), WasmEngineError> {
+        let next_check = self
+            .checked_at_gas
+            .get()
+            .saturating_add(EXECUTION_DEADLINE_CHECK_INTERVAL);
+        if gas_used < next_check {
+            return Ok(());
+        }
+        self.checked_at_gas.set(gas_used);
+
+        let now = monotonic_now()?;
+        if now >= self.deadline {
+            debug!(
+                "Out of time! Deadline: {}, now: {}, gas used: {}",
+                self.deadline, now, gas_used
+            );
+            return Err(WasmEngineError::ExecutionTimeout);
+        }
+
+        Ok(())
+    }
+}
+
+/// The time on the monotonic clock of the host, in nanoseconds
+fn monotonic_now() -> Result<u64, WasmEngineError> {
+    let mut now = 0u64;
+    let status = unsafe { imports::ocall_monotonic_now(&mut now) };
+
+    if status != sgx_status_t::SGX_SUCCESS {
+        warn!("ocall_monotonic_now failed: {}", status);
+        return Err(WasmEngineError::HostMisbehavior);
+    }
+
+    Ok(now)
+}
@@ -3,7 +3,8 @@ use enclave_ffi_types::{EnclaveError, UntrustedVmError};
 use log::*;
 use wasmi::{Error as InterpreterError, HostError, TrapKind};
 
-use crate::exports::BufferRecoveryError;
+use crate::exports::{self, BufferRecoveryError};
+use crate::results::CallError;
 
 #[derive(Debug, Display)]
 #[non_exhaustive]
@@ -11,23 +12,59 @@ pub enum WasmEngineError {
     #[display(fmt = "FailedOcall")]
     FailedOcall(UntrustedVmError),
     HostMisbehavior,
+    /// The response to a query of another contract came from a contract with another code hash
+    QueryCodeHashMismatch,
     OutOfGas,
+    /// The contract used the gas left in the block, which was less than its own gas limit
+    OutOfBlockGas,
+    /// The contract ran past the deadline of `ExecutionDeadline`
+    ExecutionTimeout,
     Panic,
+    /// The contract panicked and reported it through `abort`
+    ContractAborted,
 
     EncryptionError,
     DecryptionError,
+    /// A value of the contract storage was encrypted for another key or another contract than the
+    /// one it was read for, or was changed. Either way the host didn't serve what was written.
+    StateBindingMismatch,
     SerializationError,
     DeserializationError,
     // This is for unexpected error while processing base32 data.
     Base32Error,
 
     MemoryAllocationError,
+    /// An allocation was retried after freeing the OOM safety buffer, and there wasn't enough
+    /// memory to restore it before the next step of the contract
+    OutOfMemory,
     MemoryReadError,
     MemoryWriteError,
     /// The contract attempted to write to storage during a query
     UnauthorizedWrite,
+    /// A write would have brought the contract storage past the quota the chain set, see
+    /// `StorageUsage`
+    #[display(fmt = "StorageQuotaExceeded")]
+    StorageQuotaExceeded {
+        usage: u64,
+        quota: u64,
+    },
+    /// The host tried to return a response larger than `MAX_OCALL_RESPONSE_SIZE`
+    ExceededOcallResponseSize,
 
     NonExistentImportFunction,
+
+    /// The contract passed an order other than ascending or descending to `db_scan`
+    InvalidIteratorOrder,
+    /// The contract passed an iterator id that `db_scan` did not return to `db_next`
+    IteratorDoesNotExist,
+    /// The contract passed malformed keys, or more than `MAX_READ_DB_MULTI_KEYS` keys, to
+    /// `db_read_multi`
+    InvalidReadDbMultiKeys,
+    /// The contract passed an empty namespace, or one longer than `MAX_DB_NAMESPACE_LENGTH`, to
+    /// one of the `db_*_ns` imports
+    InvalidDbNamespace,
+    /// The enclave has no consensus seed to derive the key of `secp256k1_generate_key` from
+    MissingSigningSecret,
 }
 
 impl HostError for WasmEngineError {}
@@ -40,22 +77,58 @@ impl From<WasmEngineError> for EnclaveError {
                 vm_error: UntrustedVmError { ptr: vm_error.ptr },
             },
             OutOfGas => EnclaveError::OutOfGas,
+            OutOfBlockGas => EnclaveError::OutOfBlockGas,
+            ExecutionTimeout => EnclaveError::ExecutionTimeout,
+            ContractAborted => EnclaveError::ContractPanicUnreachable,
             EncryptionError => EnclaveError::EncryptionError,
-            DecryptionError => EnclaveError::DecryptionError,
+            DecryptionError | StateBindingMismatch => EnclaveError::DecryptionError,
             MemoryAllocationError => EnclaveError::MemoryAllocationError,
+            OutOfMemory => EnclaveError::OutOfMemory,
             MemoryReadError => EnclaveError::MemoryReadError,
             MemoryWriteError => EnclaveError::MemoryWriteError,
             UnauthorizedWrite => EnclaveError::UnauthorizedWrite,
+            StorageQuotaExceeded { usage, quota } => {
+                EnclaveError::StorageQuotaExceeded { usage, quota }
+            }
+            ExceededOcallResponseSize => EnclaveError::ExceededOcallResponseSize,
             HostMisbehavior => EnclaveError::HostMisbehavior,
+            QueryCodeHashMismatch => EnclaveError::QueryCodeHashMismatch,
+            MissingSigningSecret => EnclaveError::InternalError,
             // Unexpected WasmEngineError variant
             _other => EnclaveError::Unknown,
         }
     }
 }
 
+impl From<WasmEngineError> for CallError {
+    fn from(engine_err: WasmEngineError) -> Self {
+        CallError::Enclave(engine_err.into())
+    }
+}
+
 impl From<BufferRecoveryError> for WasmEngineError {
-    fn from(_err: BufferRecoveryError) -> Self {
-        WasmEngineError::HostMisbehavior
+    fn from(err: BufferRecoveryError) -> Self {
+        match err {
+            BufferRecoveryError::UnknownBuffer | BufferRecoveryError::InvalidPointer => {
+                WasmEngineError::HostMisbehavior
+            }
+            BufferRecoveryError::ExceededOcallResponseSize => {
+                WasmEngineError::ExceededOcallResponseSize
+            }
+            BufferRecoveryError::OutOfMemory => WasmEngineError::OutOfMemory,
+            BufferRecoveryError::SafetyBufferUnavailable => WasmEngineError::MemoryAllocationError,
+        }
+    }
+}
+
+impl WasmEngineError {
+    /// The error of an ocall that returned `OcallReturn::Failure`. When the host failed to copy
+    /// the response of the ocall into the enclave, that is reported instead.
+    pub fn failed_ocall(vm_error: UntrustedVmError) -> Self {
+        match exports::take_allocation_failure() {
+            Some(err) => err.into(),
+            None => WasmEngineError::FailedOcall(vm_error),
+        }
     }
 }
 
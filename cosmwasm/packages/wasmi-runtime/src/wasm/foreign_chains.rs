@@ -0,0 +1,714 @@
+//! Verification of the headers of other Tendermint chains, so that contracts can trust what a
+//! bridge tells them about those chains.
+//!
+//! The enclave starts tracking a chain once governance approved a header of it, see
+//! `track_foreign_chain`. The enclave can't see the outcome of a vote, so it trusts the host to
+//! only pass headers of proposals that passed, the same way it trusts the first header of its own
+//! chain. From then on, headers of the chain are verified with the rules of Tendermint's light
+//! client:
+//! - A header must be newer than the last header verified for its chain, both in height and in
+//!   time, and not later than the block time of this chain, give or take
+//!   `FOREIGN_HEADER_MAX_CLOCK_DRIFT_SECS`.
+//! - Validators with more than two thirds of the voting power of its validator set signed it.
+//! - The header right after the last verified one must be signed by the validators that one
+//!   chose. A header that skips blocks must also be signed by more than a third of the voting
+//!   power of those validators, which it carries in `trusted_validators`.
+//! - The last verified header only vouches for newer ones within the trusting period of the
+//!   chain. Once it expires, the chain has to be tracked again through governance.
+//!
+//! What the enclave trusts about a chain is part of the state of this chain, so that every node
+//! verifies the same headers whenever it started its enclave. The enclave returns it as a
+//! `SealedForeignChain`, authenticated with a key derived from the consensus seed, the same way
+//! as the admin proof of contracts. Headers submitted with `submit_foreign_header` become the
+//! last verified header of the state they're submitted to, which the chain stores in place of the
+//! old one. The chain passes the states of the chains it tracks in the env, where contracts check
+//! headers against them with the `verify_foreign_header` import. That applies the same rules, but
+//! doesn't change what is trusted.
+//!
+//! Only the hashes of validator sets are kept, so the size of a state doesn't depend on the
+//! validators of its chain: the last `FOREIGN_CHAIN_HEADERS_KEPT` verified headers. The env holds
+//! at most `MAX_FOREIGN_CHAINS` states.
+
+use log::*;
+use serde::{Deserialize, Serialize};
+
+use enclave_ffi_types::EnclaveError;
+
+use crate::consts::{
+    FOREIGN_CHAIN_HEADERS_KEPT, FOREIGN_HEADER_MAX_CLOCK_DRIFT_SECS, MAX_FOREIGN_VALIDATORS,
+};
+use crate::cosmwasm::encoding::Binary;
+use crate::cosmwasm::types::{ForeignChainInit, ForeignHeader, HeaderTime, SealedForeignChain};
+use crate::crypto::{AESKey, AuthenticationCode, Hmac, Kdf, KEY_MANAGER};
+
+use super::block_header::header_hash;
+use super::light_client::{
+    validators_hash, verify_commit, verify_commit_trusting, verify_validators,
+};
+
+/// Tendermint's `MaxChainIDLen`
+const MAX_CHAIN_ID_LEN: usize = 50;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct VerifiedForeignHeader {
+    height: u64,
+    hash: Binary,
+    time: HeaderTime,
+    app_hash: Binary,
+    next_validators_hash: Binary,
+}
+
+/// What the enclave trusts about a chain, which is kept by the chain as a `SealedForeignChain`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct ForeignChainState {
+    chain_id: String,
+    trusting_period_seconds: u64,
+    /// The last verified headers, oldest first. New headers are verified against the newest one.
+    headers: Vec<VerifiedForeignHeader>,
+}
+
+impl ForeignChainState {
+    fn track(init: &ForeignChainInit) -> Result<Self, EnclaveError> {
+        let header = &init.header;
+        let chain_id = &header.chain_id;
+
+        if chain_id.is_empty() || chain_id.len() > MAX_CHAIN_ID_LEN {
+            warn!(
+                "got a foreign chain with an invalid chain id {:?}",
+                chain_id
+            );
+            return Err(EnclaveError::ValidationFailure);
+        }
+        if header.validators_hash.is_empty()
+            || header.next_validators_hash.is_empty()
+            || header.time.seconds < 0
+            || init.trusting_period_seconds == 0
+        {
+            warn!("got a malformed trusted header for chain {:?}", chain_id);
+            return Err(EnclaveError::ValidationFailure);
+        }
+
+        Ok(Self {
+            chain_id: chain_id.clone(),
+            trusting_period_seconds: init.trusting_period_seconds,
+            headers: vec![VerifiedForeignHeader {
+                height: header.height,
+                hash: Binary(header_hash(header).to_vec()),
+                time: header.time.clone(),
+                app_hash: header.app_hash.clone(),
+                next_validators_hash: header.next_validators_hash.clone(),
+            }],
+        })
+    }
+
+    /// The app hash of a header that was already verified
+    fn verified_app_hash(&self, height: u64, hash: &[u8]) -> Option<&[u8]> {
+        self.headers
+            .iter()
+            .find(|verified| verified.height == height && verified.hash.as_slice() == hash)
+            .map(|verified| verified.app_hash.as_slice())
+    }
+
+    /// Keep a header that is newer than all the kept ones
+    fn keep(&mut self, header: VerifiedForeignHeader) {
+        self.headers.push(header);
+        if self.headers.len() > FOREIGN_CHAIN_HEADERS_KEPT {
+            let oldest = self.headers.len() - FOREIGN_CHAIN_HEADERS_KEPT;
+            self.headers.drain(..oldest);
+        }
+    }
+
+    fn seal_with(&self, consensus_state_ikm: &AESKey) -> Result<SealedForeignChain, EnclaveError> {
+        let state = serde_json::to_vec(self).map_err(|err| {
+            error!(
+                "Could not serialize the state of a foreign chain: {:?}",
+                err
+            );
+            EnclaveError::FailedToSerialize
+        })?;
+        let proof = foreign_chain_proof_with(consensus_state_ikm, &state);
+
+        Ok(SealedForeignChain {
+            state: Binary(state),
+            proof: Binary(proof.to_vec()),
+        })
+    }
+
+    /// The state in `sealed`, if it was sealed under any of `consensus_state_ikms`
+    fn unseal_with(consensus_state_ikms: &[AESKey], sealed: &SealedForeignChain) -> Option<Self> {
+        let authentic = consensus_state_ikms.iter().any(|consensus_state_ikm| {
+            foreign_chain_proof_with(consensus_state_ikm, sealed.state.as_slice())
+                .verify(sealed.proof.as_slice())
+        });
+        if !authentic {
+            warn!("got the state of a foreign chain that the enclave didn't seal");
+            return None;
+        }
+
+        serde_json::from_slice(sealed.state.as_slice())
+            .map_err(|err| warn!("got a malformed state of a foreign chain: {:?}", err))
+            .ok()
+    }
+}
+
+fn foreign_chain_proof_with(consensus_state_ikm: &AESKey, state: &[u8]) -> AuthenticationCode {
+    let authentication_key = consensus_state_ikm.derive_key_from_this(b"foreign_chain");
+    AuthenticationCode::new(authentication_key.sign_sha_256(state))
+}
+
+/// Start verifying the headers of the chain of `init.header`, trusting that header as it is, and
+/// return the state the chain keeps for it. Tracking a chain that is already tracked starts over
+/// from the new header, which is how a chain whose trusted header expired is recovered.
+pub fn track_foreign_chain(init: &ForeignChainInit) -> Result<SealedForeignChain, EnclaveError> {
+    let state = ForeignChainState::track(init)?;
+    let consensus_state_ikm = KEY_MANAGER.get_consensus_state_ikm().map_err(|_err| {
+        warn!("Error extracting consensus_state_key");
+        EnclaveError::EnclaveNotInitialized
+    })?;
+
+    info!(
+        "Tracking chain {:?} from the header at height {}",
+        state.chain_id, init.header.height
+    );
+    state.seal_with(&consensus_state_ikm)
+}
+
+/// Verify a header of the chain of `sealed`, and return the state with it as the header newer ones
+/// are verified against. `now` is the block time of this chain, in seconds. Submitting a header
+/// that was already verified returns the state as it is.
+pub fn submit_foreign_header(
+    sealed: &SealedForeignChain,
+    submission: &ForeignHeader,
+    now: u64,
+) -> Result<SealedForeignChain, EnclaveError> {
+    let consensus_state_ikm = KEY_MANAGER.get_consensus_state_ikm().map_err(|_err| {
+        warn!("Error extracting consensus_state_key");
+        EnclaveError::EnclaveNotInitialized
+    })?;
+
+    submit_foreign_header_with(
+        &consensus_state_ikm,
+        &KEY_MANAGER.get_all_consensus_state_ikms(),
+        sealed,
+        submission,
+        now,
+    )
+}
+
+fn submit_foreign_header_with(
+    consensus_state_ikm: &AESKey,
+    consensus_state_ikms: &[AESKey],
+    sealed: &SealedForeignChain,
+    submission: &ForeignHeader,
+    now: u64,
+) -> Result<SealedForeignChain, EnclaveError> {
+    let header = &submission.header;
+
+    let mut state = ForeignChainState::unseal_with(consensus_state_ikms, sealed)
+        .ok_or(EnclaveError::ValidationFailure)?;
+    if header.chain_id != state.chain_id {
+        warn!(
+            "got a header of chain {:?} for the state of chain {:?}",
+            header.chain_id, state.chain_id
+        );
+        return Err(EnclaveError::ValidationFailure);
+    }
+
+    if state
+        .verified_app_hash(header.height, &header_hash(header))
+        .is_some()
+    {
+        return Ok(sealed.clone());
+    }
+
+    let verified = verify_newer_header(&state, submission, now)?;
+    debug!(
+        "Verified the header of chain {:?} at height {}",
+        header.chain_id, header.height
+    );
+    state.keep(verified);
+
+    state.seal_with(consensus_state_ikm)
+}
+
+/// Return the app hash of a header of `chain_id` if it's one of the headers verified in the state
+/// of that chain among `chains`, or if it can be verified against the newest of them at `now`, the
+/// block time of this chain in seconds. Nothing is kept either way.
+pub fn verify_foreign_header(
+    chains: &[SealedForeignChain],
+    chain_id: &str,
+    submission: &ForeignHeader,
+    now: u64,
+) -> Option<Vec<u8>> {
+    verify_foreign_header_with(
+        &KEY_MANAGER.get_all_consensus_state_ikms(),
+        chains,
+        chain_id,
+        submission,
+        now,
+    )
+}
+
+fn verify_foreign_header_with(
+    consensus_state_ikms: &[AESKey],
+    chains: &[SealedForeignChain],
+    chain_id: &str,
+    submission: &ForeignHeader,
+    now: u64,
+) -> Option<Vec<u8>> {
+    let header = &submission.header;
+    if header.chain_id != chain_id {
+        debug!(
+            "got a header of chain {:?} to verify as a header of {:?}",
+            header.chain_id, chain_id
+        );
+        return None;
+    }
+
+    let state = chains
+        .iter()
+        .filter_map(|sealed| ForeignChainState::unseal_with(consensus_state_ikms, sealed))
+        .find(|state| state.chain_id == chain_id)?;
+
+    if let Some(app_hash) = state.verified_app_hash(header.height, &header_hash(header)) {
+        return Some(app_hash.to_vec());
+    }
+
+    verify_newer_header(&state, submission, now)
+        .ok()
+        .map(|verified| verified.app_hash.0)
+}
+
+fn verify_newer_header(
+    chain: &ForeignChainState,
+    submission: &ForeignHeader,
+    now: u64,
+) -> Result<VerifiedForeignHeader, EnclaveError> {
+    let header = &submission.header;
+    let trusted = match chain.headers.last() {
+        Some(trusted) => trusted,
+        None => {
+            warn!(
+                "got the state of chain {:?} without headers",
+                chain.chain_id
+            );
+            return Err(EnclaveError::ValidationFailure);
+        }
+    };
+    let trusted_height = trusted.height;
+    if (trusted.time.seconds as i128) + (chain.trusting_period_seconds as i128) <= now as i128 {
+        warn!(
+            "the trusted header of chain {:?} at height {} expired, it has to be tracked again",
+            header.chain_id, trusted_height
+        );
+        return Err(EnclaveError::ValidationFailure);
+    }
+
+    if header.height <= trusted_height {
+        warn!(
+            "got a header of chain {:?} at height {}, not after the trusted header at height {}",
+            header.chain_id, header.height, trusted_height
+        );
+        return Err(EnclaveError::ValidationFailure);
+    }
+    if (header.time.seconds, header.time.nanos) <= (trusted.time.seconds, trusted.time.nanos) {
+        warn!(
+            "got a header of chain {:?} at height {} that isn't later than the trusted header",
+            header.chain_id, header.height
+        );
+        return Err(EnclaveError::ValidationFailure);
+    }
+    if header.time.seconds as i128 > now as i128 + FOREIGN_HEADER_MAX_CLOCK_DRIFT_SECS as i128 {
+        warn!(
+            "got a header of chain {:?} at height {} from the future",
+            header.chain_id, header.height
+        );
+        return Err(EnclaveError::ValidationFailure);
+    }
+
+    // Tendermint doesn't hash headers without validators
+    if header.validators_hash.is_empty() || header.next_validators_hash.is_empty() {
+        warn!(
+            "got a header of chain {:?} without validators hashes",
+            header.chain_id
+        );
+        return Err(EnclaveError::ValidationFailure);
+    }
+    if submission.validators.len() > MAX_FOREIGN_VALIDATORS
+        || submission.trusted_validators.len() > MAX_FOREIGN_VALIDATORS
+    {
+        warn!(
+            "got a header of chain {:?} with more than {} validators",
+            header.chain_id, MAX_FOREIGN_VALIDATORS
+        );
+        return Err(EnclaveError::ValidationFailure);
+    }
+
+    verify_validators(&submission.validators)?;
+    if validators_hash(&submission.validators)[..] != *header.validators_hash.as_slice() {
+        warn!(
+            "got validators for the header of chain {:?} at height {} that don't match its validators hash",
+            header.chain_id, header.height
+        );
+        return Err(EnclaveError::ValidationFailure);
+    }
+
+    let hash = header_hash(header);
+
+    if header.height == trusted_height.wrapping_add(1) {
+        if header.last_block_id.hash.as_slice() != trusted.hash.as_slice()
+            || header.validators_hash.as_slice() != trusted.next_validators_hash.as_slice()
+        {
+            warn!(
+                "got a header of chain {:?} at height {} that doesn't follow the trusted header",
+                header.chain_id, header.height
+            );
+            return Err(EnclaveError::ValidationFailure);
+        }
+    } else {
+        verify_validators(&submission.trusted_validators)?;
+        if validators_hash(&submission.trusted_validators)[..]
+            != *trusted.next_validators_hash.as_slice()
+        {
+            warn!(
+                "got trusted validators for chain {:?} that the trusted header didn't choose",
+                header.chain_id
+            );
+            return Err(EnclaveError::ValidationFailure);
+        }
+        verify_commit_trusting(
+            &header.chain_id,
+            header.height,
+            &hash,
+            &submission.commit,
+            &submission.trusted_validators,
+        )?;
+    }
+
+    verify_commit(
+        &header.chain_id,
+        header.height,
+        &hash,
+        &submission.commit,
+        &submission.validators,
+    )?;
+
+    Ok(VerifiedForeignHeader {
+        height: header.height,
+        hash: Binary(hash.to_vec()),
+        time: header.time.clone(),
+        app_hash: header.app_hash.clone(),
+        next_validators_hash: header.next_validators_hash.clone(),
+    })
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use ring::signature::Ed25519KeyPair;
+
+    use crate::cosmwasm::types::BlockHeader;
+    use crate::wasm::light_client::tests::{header, key_pairs, submission, validators};
+
+    const TRUSTING_PERIOD_SECONDS: u64 = 3_600;
+
+    fn consensus_state_ikm() -> AESKey {
+        AESKey::new_from_slice(&[7; 32])
+    }
+
+    /// The header of `chain_id` at `height`, signed by the first `signers` of `key_pairs`
+    fn foreign_header(
+        chain_id: &str,
+        height: u64,
+        last_block_hash: &[u8],
+        key_pairs: &[Ed25519KeyPair],
+        signers: usize,
+    ) -> ForeignHeader {
+        let mut header = header(height, last_block_hash, &validators(key_pairs));
+        header.chain_id = chain_id.to_string();
+        let signed = submission(header, key_pairs, signers);
+
+        ForeignHeader {
+            header: signed.header,
+            commit: signed.commit,
+            validators: signed.validators,
+            trusted_validators: vec![],
+        }
+    }
+
+    fn track_from(header: &BlockHeader) -> SealedForeignChain {
+        ForeignChainState::track(&ForeignChainInit {
+            header: header.clone(),
+            trusting_period_seconds: TRUSTING_PERIOD_SECONDS,
+        })
+        .unwrap()
+        .seal_with(&consensus_state_ikm())
+        .unwrap()
+    }
+
+    /// Track `chain_id` from its header at height 100, and return that header with the state the
+    /// chain keeps
+    fn track(chain_id: &str, key_pairs: &[Ed25519KeyPair]) -> (BlockHeader, SealedForeignChain) {
+        let mut header = header(100, &[0x11; 32], &validators(key_pairs));
+        header.chain_id = chain_id.to_string();
+        let sealed = track_from(&header);
+        (header, sealed)
+    }
+
+    /// Submit a header to the state the chain keeps, the way the chain stores the new state
+    fn submit(
+        sealed: &mut SealedForeignChain,
+        submission: &ForeignHeader,
+        now: u64,
+    ) -> Result<(), EnclaveError> {
+        *sealed = submit_foreign_header_with(
+            &consensus_state_ikm(),
+            &[consensus_state_ikm()],
+            sealed,
+            submission,
+            now,
+        )?;
+        Ok(())
+    }
+
+    fn verify(
+        sealed: &SealedForeignChain,
+        chain_id: &str,
+        submission: &ForeignHeader,
+        now: u64,
+    ) -> Option<Vec<u8>> {
+        verify_foreign_header_with(
+            &[consensus_state_ikm()],
+            std::slice::from_ref(sealed),
+            chain_id,
+            submission,
+            now,
+        )
+    }
+
+    /// A minute after the time of `header`
+    fn soon_after(header: &BlockHeader) -> u64 {
+        header.time.seconds as u64 + 60
+    }
+
+    fn other_key_pairs(seeds: &[u8]) -> Vec<Ed25519KeyPair> {
+        seeds
+            .iter()
+            .map(|seed| Ed25519KeyPair::from_seed_unchecked(&[*seed; 32]).unwrap())
+            .collect()
+    }
+
+    pub fn test_submit_foreign_headers() {
+        let chain_id = "foreign-submit-1";
+        let key_pairs = key_pairs();
+        let (trusted, mut sealed) = track(chain_id, &key_pairs);
+        let now = soon_after(&trusted);
+
+        // The header right after the trusted one
+        let next = foreign_header(chain_id, 101, &header_hash(&trusted), &key_pairs, 3);
+        assert_eq!(verify(&sealed, chain_id, &next, now), Some(vec![101; 32]));
+        submit(&mut sealed, &next, now).unwrap();
+        // Submitting it again changes nothing
+        let before = sealed.clone();
+        submit(&mut sealed, &next, now).unwrap();
+        assert_eq!(sealed, before);
+
+        // A header that skips blocks, vouched for by the validators the trusted header chose
+        let mut skipping = foreign_header(chain_id, 110, &[0x12; 32], &key_pairs, 4);
+        assert_eq!(verify(&sealed, chain_id, &skipping, now), None);
+        skipping.trusted_validators = validators(&key_pairs);
+        assert_eq!(
+            verify(&sealed, chain_id, &skipping, now),
+            Some(vec![110; 32])
+        );
+        submit(&mut sealed, &skipping, now).unwrap();
+
+        // Verified headers stay verified, but older headers can't be verified anymore
+        assert_eq!(verify(&sealed, chain_id, &next, now), Some(vec![101; 32]));
+        let mut older = foreign_header(chain_id, 105, &[0x12; 32], &key_pairs, 4);
+        older.trusted_validators = validators(&key_pairs);
+        assert_eq!(verify(&sealed, chain_id, &older, now), None);
+        assert!(submit(&mut sealed, &older, now).is_err());
+
+        // Only as a header of its own chain
+        assert_eq!(verify(&sealed, "foreign-other", &next, now), None);
+        let (_, mut other) = track("foreign-other", &key_pairs);
+        assert!(submit(&mut other, &next, now).is_err());
+    }
+
+    pub fn test_foreign_header_with_forged_signature() {
+        let chain_id = "foreign-forged-1";
+        let key_pairs = key_pairs();
+        let (trusted, mut sealed) = track(chain_id, &key_pairs);
+        let now = soon_after(&trusted);
+
+        let mut forged = foreign_header(chain_id, 101, &header_hash(&trusted), &key_pairs, 4);
+        forged.commit.signatures[1].signature.0[10] ^= 1;
+        assert_eq!(verify(&sealed, chain_id, &forged, now), None);
+        assert!(submit(&mut sealed, &forged, now).is_err());
+
+        // Another app hash under the signatures of the honest header
+        let honest = foreign_header(chain_id, 101, &header_hash(&trusted), &key_pairs, 4);
+        let mut forged = honest.clone();
+        forged.header.app_hash = Binary(vec![0x99; 32]);
+        assert_eq!(verify(&sealed, chain_id, &forged, now), None);
+
+        // Signed by a quarter of the voting power
+        let mut skipping = foreign_header(chain_id, 110, &[0x12; 32], &key_pairs, 1);
+        skipping.trusted_validators = validators(&key_pairs);
+        assert_eq!(verify(&sealed, chain_id, &skipping, now), None);
+
+        // The honest header is still accepted
+        submit(&mut sealed, &honest, now).unwrap();
+    }
+
+    pub fn test_foreign_validator_set_changes() {
+        let chain_id = "foreign-validators-1";
+        let key_pairs = key_pairs();
+        let (trusted, mut sealed) = track(chain_id, &key_pairs);
+        let now = soon_after(&trusted);
+
+        // The header after the trusted one must be signed by the validators it chose
+        let strangers = other_key_pairs(&[11, 12, 13, 14]);
+        let next = foreign_header(chain_id, 101, &header_hash(&trusted), &strangers, 4);
+        assert_eq!(verify(&sealed, chain_id, &next, now), None);
+
+        // Skipping to validators none of the trusted ones signed for
+        let mut skipping = foreign_header(chain_id, 110, &[0x12; 32], &strangers, 4);
+        skipping.trusted_validators = validators(&key_pairs);
+        assert_eq!(verify(&sealed, chain_id, &skipping, now), None);
+
+        // Trusted validators the trusted header didn't choose
+        skipping.trusted_validators = validators(&strangers);
+        assert_eq!(verify(&sealed, chain_id, &skipping, now), None);
+
+        // Half of the trusted voting power is more than a third
+        let mixed = other_key_pairs(&[1, 2, 13, 14]);
+        let mut skipping = foreign_header(chain_id, 110, &[0x12; 32], &mixed, 4);
+        skipping.trusted_validators = validators(&key_pairs);
+        submit(&mut sealed, &skipping, now).unwrap();
+
+        // From then on, the header after it is signed by the new validators
+        let next = foreign_header(chain_id, 111, &header_hash(&skipping.header), &mixed, 3);
+        submit(&mut sealed, &next, now).unwrap();
+        let next = foreign_header(chain_id, 112, &header_hash(&next.header), &key_pairs, 4);
+        assert!(submit(&mut sealed, &next, now).is_err());
+    }
+
+    pub fn test_foreign_header_times() {
+        let chain_id = "foreign-times-1";
+        let key_pairs = key_pairs();
+        let (trusted, mut sealed) = track(chain_id, &key_pairs);
+        let next = foreign_header(chain_id, 101, &header_hash(&trusted), &key_pairs, 4);
+
+        // The trusted header expired
+        let expired = trusted.time.seconds as u64 + TRUSTING_PERIOD_SECONDS;
+        assert_eq!(verify(&sealed, chain_id, &next, expired), None);
+        assert!(submit(&mut sealed, &next, expired).is_err());
+
+        // The header is later than the block time of this chain
+        let before = next.header.time.seconds as u64 - FOREIGN_HEADER_MAX_CLOCK_DRIFT_SECS - 1;
+        assert_eq!(verify(&sealed, chain_id, &next, before), None);
+        let within_drift = before + 1;
+        assert!(verify(&sealed, chain_id, &next, within_drift).is_some());
+
+        // The header isn't later than the trusted header
+        let mut late = trusted.clone();
+        late.time.seconds = next.header.time.seconds;
+        let late_sealed = track_from(&late);
+        let after_late = foreign_header(chain_id, 101, &header_hash(&late), &key_pairs, 4);
+        assert_eq!(
+            verify(&late_sealed, chain_id, &after_late, within_drift),
+            None
+        );
+
+        // Tracking the chain again recovers it
+        let (_, mut sealed) = track(chain_id, &key_pairs);
+        submit(&mut sealed, &next, expired - 1).unwrap();
+    }
+
+    pub fn test_sealed_foreign_chains() {
+        let chain_id = "foreign-sealed-1";
+        let key_pairs = key_pairs();
+        let (trusted, sealed) = track(chain_id, &key_pairs);
+        let now = soon_after(&trusted);
+        let next = foreign_header(chain_id, 101, &header_hash(&trusted), &key_pairs, 4);
+
+        // A host that changes what the enclave trusts
+        let mut state = ForeignChainState::unseal_with(&[consensus_state_ikm()], &sealed).unwrap();
+        state.trusting_period_seconds *= 1_000;
+        let mut tampered = sealed.clone();
+        tampered.state = Binary(serde_json::to_vec(&state).unwrap());
+        assert_eq!(verify(&tampered, chain_id, &next, now), None);
+        let mut submitted = tampered.clone();
+        assert!(submit(&mut submitted, &next, now).is_err());
+        assert_eq!(submitted, tampered);
+
+        // Or seals a state of its own
+        let forged = state.seal_with(&AESKey::new_from_slice(&[8; 32])).unwrap();
+        assert_eq!(verify(&forged, chain_id, &next, now), None);
+
+        // A state sealed under a consensus seed before the current one is resealed under it
+        let old_consensus_state_ikm = AESKey::new_from_slice(&[6; 32]);
+        let old = ForeignChainState::track(&ForeignChainInit {
+            header: trusted.clone(),
+            trusting_period_seconds: TRUSTING_PERIOD_SECONDS,
+        })
+        .unwrap()
+        .seal_with(&old_consensus_state_ikm)
+        .unwrap();
+        let resealed = submit_foreign_header_with(
+            &consensus_state_ikm(),
+            &[consensus_state_ikm(), old_consensus_state_ikm],
+            &old,
+            &next,
+            now,
+        )
+        .unwrap();
+        assert!(ForeignChainState::unseal_with(&[consensus_state_ikm()], &resealed).is_some());
+
+        // The state of another chain doesn't verify the headers of this one
+        let (_, other) = track("foreign-sealed-2", &key_pairs);
+        let chains = [other, sealed];
+        assert_eq!(
+            verify_foreign_header_with(
+                &[consensus_state_ikm()],
+                &chains[..1],
+                chain_id,
+                &next,
+                now
+            ),
+            None
+        );
+        assert_eq!(
+            verify_foreign_header_with(&[consensus_state_ikm()], &chains, chain_id, &next, now),
+            Some(vec![101; 32])
+        );
+    }
+
+    pub fn test_foreign_chains_are_bounded() {
+        let key_pairs = key_pairs();
+
+        let chain_id = "foreign-bounded-1";
+        let (mut last, mut sealed) = track(chain_id, &key_pairs);
+        let now = soon_after(&last) + FOREIGN_CHAIN_HEADERS_KEPT as u64 * 6;
+        for height in 101..=(101 + FOREIGN_CHAIN_HEADERS_KEPT as u64) {
+            let next = foreign_header(chain_id, height, &header_hash(&last), &key_pairs, 4);
+            submit(&mut sealed, &next, now).unwrap();
+            last = next.header;
+        }
+        let state = ForeignChainState::unseal_with(&[consensus_state_ikm()], &sealed).unwrap();
+        assert_eq!(state.headers.len(), FOREIGN_CHAIN_HEADERS_KEPT);
+        assert_eq!(state.headers[0].height, 102);
+
+        // Too long a chain id
+        let mut header = header(100, &[0x11; 32], &validators(&key_pairs));
+        header.chain_id = "x".repeat(MAX_CHAIN_ID_LEN + 1);
+        assert!(ForeignChainState::track(&ForeignChainInit {
+            header,
+            trusting_period_seconds: TRUSTING_PERIOD_SECONDS,
+        })
+        .is_err());
+    }
+}
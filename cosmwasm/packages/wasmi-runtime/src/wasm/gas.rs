@@ -1,5 +1,45 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+use enclave_ffi_types::EnclaveError;
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+
 pub use pwasm_utils::{inject_gas_counter, rules};
 
+use super::api_version::ApiVersion;
+
+/// Default fixed costs of the ocalls, used unless the chain sets others in the env
+pub const DEFAULT_OCALL_READ_DB_GAS: u64 = 2_000;
+pub const DEFAULT_OCALL_WRITE_DB_GAS: u64 = 4_000;
+pub const DEFAULT_OCALL_REMOVE_DB_GAS: u64 = 2_000;
+pub const DEFAULT_OCALL_QUERY_CHAIN_GAS: u64 = 10_000;
+
+/// Fixed gas charged for every round trip out of the enclave, on top of what the host charges
+/// for the operation itself. Otherwise a contract that makes many small storage calls pays far
+/// less than what they cost the node.
+///
+/// The chain sets these through `ocall_gas_costs` in the env, so they can change without
+/// upgrading the enclave.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct OcallGasCosts {
+    pub read_db: u64,
+    pub write_db: u64,
+    pub remove_db: u64,
+    pub query_chain: u64,
+}
+
+impl Default for OcallGasCosts {
+    fn default() -> Self {
+        OcallGasCosts {
+            read_db: DEFAULT_OCALL_READ_DB_GAS,
+            write_db: DEFAULT_OCALL_WRITE_DB_GAS,
+            remove_db: DEFAULT_OCALL_REMOVE_DB_GAS,
+            query_chain: DEFAULT_OCALL_QUERY_CHAIN_GAS,
+        }
+    }
+}
+
 /// Wasm cost table
 pub struct WasmCosts {
     /// Default opcode cost
@@ -30,6 +70,74 @@ pub struct WasmCosts {
     pub external_humanize_address: u32,
     /// Cost invoking canonicalize_address from WASM
     pub external_canonicalize_address: u32,
+    /// Cost invoking ed25519_verify from WASM
+    pub external_ed25519_verify: u32,
+    /// Base cost invoking ed25519_batch_verify from WASM. The signatures of a batch are verified
+    /// one at a time, so each of them also costs `external_ed25519_verify`.
+    pub external_ed25519_batch_verify_base: u32,
+    /// Cost invoking secp256k1_recover_pubkey from WASM
+    pub external_secp256k1_recover_pubkey: u32,
+    /// Cost invoking secp256k1_generate_key from WASM
+    pub external_secp256k1_generate_key: u32,
+    /// Cost invoking secp256k1_sign from WASM
+    pub external_secp256k1_sign: u32,
+    /// Cost invoking secp256k1_public_key from WASM
+    pub external_secp256k1_public_key: u32,
+    /// Cost invoking get_random from WASM
+    pub external_get_random: u32,
+    /// Cost invoking gas_left from WASM
+    pub external_gas_left: u32,
+    /// Cost invoking gas_limit from WASM
+    pub external_gas_limit: u32,
+    /// Cost invoking db_storage_usage from WASM, on top of reading the usage the first time
+    pub external_db_storage_usage: u32,
+    /// Base cost invoking debug from WASM
+    pub external_debug_base: u32,
+    /// Cost of every byte of the message passed to debug
+    pub external_debug_per_byte: u32,
+    /// Cost invoking addr_validate from WASM
+    pub external_addr_validate: u32,
+    /// Cost invoking addr_canonicalize from WASM
+    pub external_addr_canonicalize: u32,
+    /// Cost invoking addr_humanize from WASM
+    pub external_addr_humanize: u32,
+    /// Base cost invoking hash_sha256 from WASM
+    pub external_hash_sha256_base: u32,
+    /// Cost of every byte hashed by hash_sha256
+    pub external_hash_sha256_per_byte: u32,
+    /// Base cost invoking hash_keccak256 from WASM
+    pub external_hash_keccak256_base: u32,
+    /// Cost of every byte hashed by hash_keccak256
+    pub external_hash_keccak256_per_byte: u32,
+    /// Base cost invoking hash_ripemd160 from WASM
+    pub external_hash_ripemd160_base: u32,
+    /// Cost of every byte hashed by hash_ripemd160
+    pub external_hash_ripemd160_per_byte: u32,
+    /// Base cost invoking hkdf_sha256 from WASM
+    pub external_hkdf_sha256_base: u32,
+    /// Cost of every byte of the ikm, salt and info passed to hkdf_sha256
+    pub external_hkdf_sha256_per_input_byte: u32,
+    /// Cost of every byte derived by hkdf_sha256
+    pub external_hkdf_sha256_per_output_byte: u32,
+    /// Base cost invoking verify_foreign_header from WASM
+    pub external_verify_foreign_header_base: u32,
+    /// Cost of every byte of the header passed to verify_foreign_header
+    pub external_verify_foreign_header_per_byte: u32,
+    /// Cost of every signature verify_foreign_header may check
+    pub external_verify_foreign_header_per_signature: u32,
+    /// Cost invoking assert_contract_code_hash from WASM, on top of the ocall that fetches the
+    /// contract key
+    pub external_assert_contract_code_hash: u32,
+    /// Cost of every byte of the answer to a query_chain call
+    pub external_query_chain_per_byte: u32,
+    /// Gas refunded for every byte a contract removes from its storage
+    pub storage_refund_per_byte: u32,
+    /// At most `gas_used / max_refund_quotient` is refunded for removed storage
+    pub max_refund_quotient: u32,
+    /// The refund for removed storage never brings the gas used by an execution below this
+    pub min_gas_after_refund: u32,
+    /// Fixed costs of the ocalls
+    pub ocall: OcallGasCosts,
 }
 
 impl Default for WasmCosts {
@@ -49,10 +157,68 @@ impl Default for WasmCosts {
             opcodes_div: 8,
             external_humanize_address: 8192,
             external_canonicalize_address: 8192,
+            external_ed25519_verify: 20_000,
+            external_ed25519_batch_verify_base: 10_000,
+            external_secp256k1_recover_pubkey: 30_000,
+            external_secp256k1_generate_key: 5_000,
+            external_secp256k1_sign: 25_000,
+            external_secp256k1_public_key: 10_000,
+            external_get_random: 1_000,
+            external_gas_left: 100,
+            external_gas_limit: 100,
+            external_db_storage_usage: 100,
+            external_debug_base: 1_000,
+            external_debug_per_byte: 1,
+            external_addr_validate: 1_000,
+            external_addr_canonicalize: 1_000,
+            external_addr_humanize: 1_000,
+            external_hash_sha256_base: 500,
+            external_hash_sha256_per_byte: 2,
+            external_hash_keccak256_base: 500,
+            external_hash_keccak256_per_byte: 3,
+            external_hash_ripemd160_base: 500,
+            external_hash_ripemd160_per_byte: 3,
+            external_hkdf_sha256_base: 1_000,
+            external_hkdf_sha256_per_input_byte: 2,
+            external_hkdf_sha256_per_output_byte: 8,
+            external_verify_foreign_header_base: 20_000,
+            external_verify_foreign_header_per_byte: 1,
+            external_verify_foreign_header_per_signature: 12_000,
+            external_assert_contract_code_hash: 2_000,
+            external_query_chain_per_byte: 3,
+            storage_refund_per_byte: 30,
+            max_refund_quotient: 2,
+            min_gas_after_refund: 10_000,
+            ocall: OcallGasCosts::default(),
         }
     }
 }
 
+impl WasmCosts {
+    /// The costs charged to contracts built for this version of the CosmWasm API.
+    ///
+    /// Contracts built for CosmWasm 1.0 convert addresses only through `addr_canonicalize` and
+    /// `addr_humanize`, which do the work of `canonicalize_address` and `humanize_address` for
+    /// them, so they're charged the same. Contracts built for CosmWasm 0.10 keep paying what they
+    /// always did.
+    pub fn for_api_version(api_version: ApiVersion) -> Self {
+        let costs = WasmCosts::default();
+        match api_version {
+            ApiVersion::V0_10 => costs,
+            ApiVersion::V1 => WasmCosts {
+                external_addr_canonicalize: costs.external_canonicalize_address,
+                external_addr_humanize: costs.external_humanize_address,
+                ..costs
+            },
+        }
+    }
+}
+
+/// The costs `inject_gas_counter` meters a module with when it's loaded. The cost of a block is
+/// charged once, when the block starts, and a loop is charged again every time it branches back
+/// to its start. A block that a branch leaves early has still paid for all of it, so the totals
+/// aren't the ones counting instruction by instruction would give. This is the only metering
+/// contracts have ever been charged with, so there is no other scheme to keep them equal to.
 pub fn gas_rules(wasm_costs: &WasmCosts) -> rules::Set {
     rules::Set::new(wasm_costs.regular, {
         let mut vals = ::std::collections::BTreeMap::new();
@@ -81,6 +247,85 @@ pub fn gas_rules(wasm_costs: &WasmCosts) -> rules::Set {
     .with_grow_cost(wasm_costs.grow_mem)
 }
 
+/// Subtract the refund for `removed_bytes` of storage from the gas used by an execution.
+///
+/// The refund is capped, so an execution can't end up paying less than a fraction of the gas it
+/// used, or less than `min_gas_after_refund`.
+pub fn apply_storage_refund(gas_used: u64, removed_bytes: u64, wasm_costs: &WasmCosts) -> u64 {
+    let refund = removed_bytes
+        .saturating_mul(wasm_costs.storage_refund_per_byte as u64)
+        .min(gas_used / (wasm_costs.max_refund_quotient as u64).max(1));
+    // Don't charge more than what was used just because of the minimum
+    let floor = gas_used.min(wasm_costs.min_gas_after_refund as u64);
+
+    gas_used.saturating_sub(refund).max(floor)
+}
+
+thread_local! {
+    /// The gas used so far by the contract running on this thread, as `Engine::gas_used` would
+    /// report it. Kept outside of the contract instance so it can still be read after a panic
+    /// unwound the instance.
+    static METERED_GAS: Cell<Option<u64>> = Cell::new(None);
+}
+
+/// Forget the gas metered by a previous ecall. Called when an ecall starts.
+pub fn clear_metered_gas() {
+    METERED_GAS.with(|metered_gas| metered_gas.set(None));
+}
+
+pub fn record_metered_gas(gas_used: u64) {
+    METERED_GAS.with(|metered_gas| metered_gas.set(Some(gas_used)));
+}
+
+pub fn take_metered_gas() -> Option<u64> {
+    METERED_GAS.with(|metered_gas| metered_gas.take())
+}
+
+/// The gas charged for an ecall that panicked or ran out of memory.
+///
+/// This is the gas the contract was metered up to the failure, capped at `gas_limit`. If the
+/// ecall failed before a contract was instantiated, nothing was metered and all of `gas_limit`
+/// is charged.
+pub fn failed_ecall_gas(gas_limit: u64) -> u64 {
+    take_metered_gas()
+        .map(|gas_used| gas_used.min(gas_limit))
+        .unwrap_or(gas_limit)
+}
+
+/// The gas limit of queries the host didn't limit, as wasm gas. This is the default
+/// `query_gas_limit` of the node (3,000,000) times the gas multiplier of the host (100).
+pub const DEFAULT_QUERY_GAS_LIMIT: u64 = 300_000_000;
+
+static QUERY_GAS_LIMIT: AtomicU64 = AtomicU64::new(DEFAULT_QUERY_GAS_LIMIT);
+
+/// Set the limit of queries the host didn't limit, see `ecall_configure_runtime`.
+pub fn set_default_query_gas_limit(gas_limit: u64) {
+    QUERY_GAS_LIMIT.store(gas_limit, Ordering::Relaxed);
+}
+
+/// The gas limit a query actually runs with. A limit of 0 or `u64::MAX` means the host didn't
+/// limit the query, and an infinite loop in it would only stop when the counter overflows, so
+/// these get the limit the node configured instead.
+pub fn query_gas_limit(requested: u64) -> u64 {
+    match requested {
+        0 | u64::MAX => QUERY_GAS_LIMIT.load(Ordering::Relaxed),
+        requested => requested,
+    }
+}
+
+/// Fail an execution that was given no gas at all, before anything runs. Nothing is charged for it.
+///
+/// Queries never get here with a limit of 0, since `query_gas_limit` treats it as unlimited.
+pub fn check_gas_limit(gas_limit: u64, used_gas: &mut u64) -> Result<(), EnclaveError> {
+    if gas_limit == 0 {
+        debug!("Got a gas limit of 0, not running the contract");
+        *used_gas = 0;
+        return Err(EnclaveError::OutOfGas);
+    }
+
+    Ok(())
+}
+
 #[derive(Debug, Clone)]
 pub struct RuntimeWasmCosts {
     pub write_value: u64,
@@ -107,3 +352,184 @@ pub struct RuntimeGas {
     pub refund: u64,
     pub costs: RuntimeWasmCosts,
 }
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+    use crate::wasm::db::RemovedStorage;
+
+    pub fn test_ed25519_batch_verify_is_not_cheaper_than_single_calls() {
+        let costs = WasmCosts::default();
+
+        // The batch verifies each signature the way ed25519_verify does, so it can't be cheaper
+        for signatures in &[1u64, 50] {
+            let single_calls = costs.external_ed25519_verify as u64 * signatures;
+            let batch = costs.external_ed25519_batch_verify_base as u64
+                + costs.external_ed25519_verify as u64 * signatures;
+            assert!(batch >= single_calls);
+        }
+    }
+
+    pub fn test_address_conversion_costs_per_api_version() {
+        let v0_10 = WasmCosts::for_api_version(ApiVersion::V0_10);
+        let v1 = WasmCosts::for_api_version(ApiVersion::V1);
+
+        // Both versions pay the same to convert an address through the functions they have
+        assert_eq!(
+            v1.external_addr_canonicalize,
+            v0_10.external_canonicalize_address
+        );
+        assert_eq!(v1.external_addr_humanize, v0_10.external_humanize_address);
+
+        // Contracts built for CosmWasm 0.10 are charged as before
+        assert_eq!(
+            v0_10.external_addr_canonicalize,
+            WasmCosts::default().external_addr_canonicalize
+        );
+    }
+
+    pub fn test_storage_refund_write_then_remove_in_one_message() {
+        let costs = WasmCosts::default();
+
+        // Two 40 byte entries written and removed by the same message
+        let mut removed = RemovedStorage::default();
+        removed.record(32, 8);
+        removed.record(32, 8);
+        assert_eq!(removed.bytes(), 80);
+
+        // 80 bytes * 30 gas
+        assert_eq!(
+            apply_storage_refund(100_000, removed.bytes(), &costs),
+            97_600
+        );
+    }
+
+    pub fn test_storage_refund_remove_across_messages() {
+        let costs = WasmCosts::default();
+
+        // The first message only writes, so it gets no refund
+        let first = RemovedStorage::default();
+        assert_eq!(
+            apply_storage_refund(100_000, first.bytes(), &costs),
+            100_000
+        );
+
+        // The second message removes what the first one wrote
+        let mut second = RemovedStorage::default();
+        second.record(32, 8);
+        second.record(32, 8);
+        assert_eq!(apply_storage_refund(60_000, second.bytes(), &costs), 57_600);
+    }
+
+    pub fn test_ocall_gas_costs_from_env() {
+        let env: crate::cosmwasm::types::Env = serde_json::from_str(
+            r#"{
+                "block": {"height": 1, "time": 1, "chain_id": "secret-testnet"},
+                "message": {"sender": "sender", "sent_funds": []},
+                "contract": {"address": "contract"},
+                "contract_key": null,
+                "ocall_gas_costs": {"read_db": 5, "query_chain": 7}
+            }"#,
+        )
+        .unwrap();
+
+        // Costs the chain didn't set keep their defaults
+        assert_eq!(
+            env.ocall_gas_costs,
+            Some(OcallGasCosts {
+                read_db: 5,
+                write_db: DEFAULT_OCALL_WRITE_DB_GAS,
+                remove_db: DEFAULT_OCALL_REMOVE_DB_GAS,
+                query_chain: 7,
+            })
+        );
+
+        // The costs aren't passed on to the contract once they are taken out of the env
+        let mut env = env;
+        env.ocall_gas_costs.take();
+        let serialized = serde_json::to_string(&env).unwrap();
+        assert!(!serialized.contains("ocall_gas_costs"));
+    }
+
+    pub fn test_gas_is_charged_once_per_block() {
+        use parity_wasm::builder;
+        use parity_wasm::elements::{BlockType, Instruction, Instructions, ValueType};
+
+        let module = builder::module()
+            .function()
+            .signature()
+            .with_param(ValueType::I32)
+            .build()
+            .body()
+            .with_instructions(Instructions::new(vec![
+                Instruction::GetLocal(0),
+                Instruction::GetLocal(0),
+                Instruction::I32Mul,
+                Instruction::I32Const(3),
+                Instruction::I32Add,
+                Instruction::SetLocal(0),
+                Instruction::Loop(BlockType::NoResult),
+                Instruction::GetLocal(0),
+                Instruction::I32Const(1),
+                Instruction::I32Sub,
+                Instruction::SetLocal(0),
+                Instruction::End,
+                Instruction::End,
+            ]))
+            .build()
+            .build()
+            .build();
+
+        let module = inject_gas_counter(module, &gas_rules(&WasmCosts::default())).unwrap();
+
+        // The gas import is the only function the module imports
+        let body = &module.code_section().unwrap().bodies()[0];
+        let gas_calls = body
+            .code()
+            .elements()
+            .iter()
+            .filter(|instruction| **instruction == Instruction::Call(0))
+            .count();
+        // The function body and the loop are charged once each, not every instruction. The loop
+        // is charged at its start, so every iteration that branches back to it pays again.
+        assert_eq!(gas_calls, 2);
+    }
+
+    pub fn test_storage_refund_is_capped() {
+        let costs = WasmCosts::default();
+
+        // At most half of the gas used is refunded
+        assert_eq!(apply_storage_refund(100_000, 1_000_000, &costs), 50_000);
+        // The refund never goes below the minimum charge
+        assert_eq!(apply_storage_refund(15_000, 1_000_000, &costs), 10_000);
+        // Executions that used less than the minimum are not charged more
+        assert_eq!(apply_storage_refund(4_000, 1_000_000, &costs), 4_000);
+        assert_eq!(apply_storage_refund(0, 1_000_000, &costs), 0);
+        // No overflow with absurd inputs
+        assert_eq!(
+            apply_storage_refund(u64::MAX, u64::MAX, &costs),
+            u64::MAX - u64::MAX / 2
+        );
+
+        // A quotient of 0 is treated as 1, instead of dividing by zero
+        let costs = WasmCosts {
+            max_refund_quotient: 0,
+            ..WasmCosts::default()
+        };
+        assert_eq!(apply_storage_refund(100_000, 1_000_000, &costs), 10_000);
+    }
+
+    pub fn test_query_gas_limit_default() {
+        // Limits the host chose are kept
+        assert_eq!(query_gas_limit(10_500), 10_500);
+
+        // Unlimited queries get the default, until the node configures another one
+        assert_eq!(query_gas_limit(0), DEFAULT_QUERY_GAS_LIMIT);
+        assert_eq!(query_gas_limit(u64::MAX), DEFAULT_QUERY_GAS_LIMIT);
+
+        set_default_query_gas_limit(5_000);
+        assert_eq!(query_gas_limit(u64::MAX), 5_000);
+        assert_eq!(query_gas_limit(10_500), 10_500);
+        set_default_query_gas_limit(DEFAULT_QUERY_GAS_LIMIT);
+    }
+}
@@ -0,0 +1,223 @@
+//! Contracts may be uploaded gzipped. The enclave decompresses them itself, so the code hash it
+//! returns for an upload is the hash of the code that the uploaded bytes stand for, whatever the
+//! host does with them.
+//!
+//! Decompression is bounded, so a small upload that inflates to a huge output (a zip bomb) is
+//! rejected once it reaches the limit, without allocating more than the limit.
+use log::*;
+use miniz_oxide::inflate::{decompress_to_vec_with_limit, TINFLStatus};
+
+use enclave_ffi_types::{EnclaveError, InvalidWasmReason};
+
+use crate::consts::MAX_GZIP_EXPANSION;
+
+/// The magic bytes of gzip, followed by the only compression method it defines (deflate)
+const GZIP_MAGIC: [u8; 3] = [0x1f, 0x8b, 0x08];
+const GZIP_HEADER_LEN: usize = 10;
+/// The CRC-32 and the length of the decompressed data, both little-endian
+const GZIP_TRAILER_LEN: usize = 8;
+
+const FLAG_HEADER_CRC: u8 = 0x02;
+const FLAG_EXTRA: u8 = 0x04;
+const FLAG_NAME: u8 = 0x08;
+const FLAG_COMMENT: u8 = 0x10;
+
+/// Whether `code` is gzipped rather than a wasm module
+pub fn is_gzip(code: &[u8]) -> bool {
+    code.starts_with(&GZIP_MAGIC)
+}
+
+/// Decompress a gzipped contract. The output is limited to `MAX_GZIP_EXPANSION` times the size
+/// of the input, and to `max_code_size`, and `ContractTooLarge` is returned when it would grow
+/// past that.
+pub fn decompress_code(gzipped: &[u8], max_code_size: usize) -> Result<Vec<u8>, EnclaveError> {
+    let max_len = gzipped
+        .len()
+        .saturating_mul(MAX_GZIP_EXPANSION)
+        .min(max_code_size);
+
+    let deflated = deflate_stream(gzipped).ok_or_else(|| {
+        warn!("Got a gzipped contract with a malformed header");
+        malformed()
+    })?;
+    let trailer = &gzipped[gzipped.len() - GZIP_TRAILER_LEN..];
+    let expected_crc = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+    let expected_len = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]);
+
+    // The length in the trailer can't be trusted to be small, but one that is too large lets us
+    // skip decompressing
+    if expected_len as usize > max_len {
+        warn!(
+            "Gzipped contract decompresses to {} bytes, more than the limit of {} bytes",
+            expected_len, max_len
+        );
+        return Err(EnclaveError::ContractTooLarge);
+    }
+
+    let code = decompress_to_vec_with_limit(deflated, max_len).map_err(|status| match status {
+        TINFLStatus::HasMoreOutput => {
+            warn!(
+                "Gzipped contract decompresses to more than the limit of {} bytes",
+                max_len
+            );
+            EnclaveError::ContractTooLarge
+        }
+        status => {
+            warn!("Could not decompress a gzipped contract: {:?}", status);
+            malformed()
+        }
+    })?;
+
+    if code.len() as u32 != expected_len || crc32(&code) != expected_crc {
+        warn!("Gzipped contract does not match the length or checksum in its trailer");
+        return Err(malformed());
+    }
+
+    Ok(code)
+}
+
+fn malformed() -> EnclaveError {
+    EnclaveError::InvalidWasm {
+        reason: InvalidWasmReason::Malformed,
+    }
+}
+
+/// The deflate stream between the gzip header and trailer, or None if the header is malformed
+fn deflate_stream(gzipped: &[u8]) -> Option<&[u8]> {
+    if !is_gzip(gzipped) || gzipped.len() < GZIP_HEADER_LEN + GZIP_TRAILER_LEN {
+        return None;
+    }
+
+    let flags = gzipped[3];
+    let mut offset = GZIP_HEADER_LEN;
+    if flags & FLAG_EXTRA != 0 {
+        let extra_len = u16::from_le_bytes([*gzipped.get(offset)?, *gzipped.get(offset + 1)?]);
+        offset += 2 + extra_len as usize;
+    }
+    for &flag in &[FLAG_NAME, FLAG_COMMENT] {
+        if flags & flag != 0 {
+            // Zero terminated strings
+            offset += gzipped.get(offset..)?.iter().position(|&byte| byte == 0)? + 1;
+        }
+    }
+    if flags & FLAG_HEADER_CRC != 0 {
+        offset += 2;
+    }
+
+    gzipped.get(offset..gzipped.len() - GZIP_TRAILER_LEN)
+}
+
+/// The CRC-32 (IEEE) of `data`, as gzip uses it
+fn crc32(data: &[u8]) -> u32 {
+    let mut table = [0u32; 256];
+    for (index, entry) in table.iter_mut().enumerate() {
+        let mut crc = index as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                0xedb8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+        }
+        *entry = crc;
+    }
+
+    !data.iter().fold(!0u32, |crc, &byte| {
+        table[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use miniz_oxide::deflate::compress_to_vec;
+
+    use crate::consts::MAX_CODE_SIZE;
+    use crate::crypto::sha_256;
+    use crate::wasm::{validate_wasm_begin, validate_wasm_chunk, validate_wasm_end};
+
+    const CONTRACT: &[u8] = include_bytes!("../../testdata/env-versions/v0_10_env.wasm");
+
+    /// Gzip `data` the way `gzip` does, with the name of the file in the header
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut gzipped = vec![0x1f, 0x8b, 0x08, FLAG_NAME, 0, 0, 0, 0, 0, 0x03];
+        gzipped.extend_from_slice(b"contract.wasm\0");
+        gzipped.extend_from_slice(&compress_to_vec(data, 9));
+        gzipped.extend_from_slice(&crc32(data).to_le_bytes());
+        gzipped.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        gzipped
+    }
+
+    pub fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    pub fn test_gzipped_contract() {
+        let gzipped = gzip(CONTRACT);
+        assert!(is_gzip(&gzipped));
+        assert!(!is_gzip(CONTRACT));
+        assert_eq!(decompress_code(&gzipped, MAX_CODE_SIZE).unwrap(), CONTRACT);
+
+        // The code hash is of the contract, not of the upload
+        let session_id = 0x6219;
+        validate_wasm_begin(session_id).unwrap();
+        for (chunk_index, chunk) in gzipped.chunks(64).enumerate() {
+            validate_wasm_chunk(session_id, chunk_index as u32, chunk).unwrap();
+        }
+        assert_eq!(validate_wasm_end(session_id).unwrap(), sha_256(CONTRACT));
+    }
+
+    pub fn test_gzip_bomb() {
+        // About a kilobyte that inflates to a megabyte
+        let bomb = gzip(&vec![0u8; 1024 * 1024]);
+        assert!(bomb.len() * MAX_GZIP_EXPANSION < 1024 * 1024);
+        assert!(matches!(
+            decompress_code(&bomb, MAX_CODE_SIZE),
+            Err(EnclaveError::ContractTooLarge)
+        ));
+
+        // A trailer that understates the length doesn't get it past the limit
+        let mut lying_bomb = bomb.clone();
+        let len_offset = lying_bomb.len() - 4;
+        lying_bomb[len_offset..].copy_from_slice(&100u32.to_le_bytes());
+        assert!(matches!(
+            decompress_code(&lying_bomb, MAX_CODE_SIZE),
+            Err(EnclaveError::ContractTooLarge)
+        ));
+    }
+
+    pub fn test_gzip_max_code_size() {
+        let gzipped = gzip(CONTRACT);
+        assert!(matches!(
+            decompress_code(&gzipped, CONTRACT.len() - 1),
+            Err(EnclaveError::ContractTooLarge)
+        ));
+        assert!(decompress_code(&gzipped, CONTRACT.len() * 2).is_ok());
+    }
+
+    pub fn test_malformed_gzip() {
+        let gzipped = gzip(CONTRACT);
+        let is_malformed = |gzipped: &[u8]| {
+            matches!(
+                decompress_code(gzipped, MAX_CODE_SIZE),
+                Err(EnclaveError::InvalidWasm {
+                    reason: InvalidWasmReason::Malformed
+                })
+            )
+        };
+
+        assert!(is_malformed(&gzipped[..GZIP_HEADER_LEN]));
+
+        let mut bad_crc = gzipped.clone();
+        let crc_offset = bad_crc.len() - GZIP_TRAILER_LEN;
+        bad_crc[crc_offset] ^= 1;
+        assert!(is_malformed(&bad_crc));
+
+        // A name that is never terminated
+        let mut unterminated = gzipped[..GZIP_HEADER_LEN].to_vec();
+        unterminated.extend_from_slice(&[b'a'; 32]);
+        assert!(is_malformed(&unterminated));
+    }
+}
@@ -2,30 +2,276 @@
 /// the consensus_io_exchange_keypair and a user-generated key to create a symmetric key
 /// that is unique to the user and the enclave
 ///
-use super::types::{IoNonce, SecretMessage};
+use super::strict_json::from_contract_response;
+use super::types::{decrypt_message, IoNonce, SecretMessage};
 
+use crate::consts::{
+    MAX_RESPONSE_SIZE, MAX_RESULT_MESSAGES, MAX_RESULT_SIZE, MAX_VERIFY_INPUT_SIZE,
+};
 use crate::cosmwasm::encoding::Binary;
-use crate::cosmwasm::types::{CanonicalAddr, Coin, CosmosMsg, WasmMsg, WasmOutput};
-use crate::crypto::{AESKey, Ed25519PublicKey, Kdf, SIVEncryptable, KEY_MANAGER};
-use enclave_ffi_types::EnclaveError;
+use crate::cosmwasm::ibc::{IbcEndpoint, IbcReceiveOutput};
+use crate::cosmwasm::types::{
+    CanonicalAddr, Coin, ContractResult, CosmosMsg, LogAttribute, SigInfo, SubMsgExecutionResponse,
+    SubMsgResult, WasmMsg, WasmOutput,
+};
+use crate::crypto::{
+    sha_256, AESKey, AuthenticationCode, Ed25519PublicKey, Kdf, SIVEncryptable, HASH_SIZE,
+    KEY_MANAGER,
+};
+use crate::ecall_trace::Phase;
+use crate::results::CallError;
+use crate::trace_secret;
+use crate::trace_span;
+use enclave_ffi_types::{EnclaveError, InputVerificationStep, VerifyInputResult};
 use log::*;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 use sha2::Digest;
 
-pub fn calc_encryption_key(nonce: &IoNonce, user_public_key: &Ed25519PublicKey) -> AESKey {
-    let enclave_io_key = KEY_MANAGER.get_consensus_io_exchange_keypair().unwrap();
+/// Derives the key that a message with `nonce` and `user_public_key` is encrypted with.
+///
+/// Fails if the enclave has no io key to derive it from yet.
+pub fn calc_encryption_key(
+    nonce: &IoNonce,
+    user_public_key: &Ed25519PublicKey,
+) -> Result<AESKey, EnclaveError> {
+    let enclave_io_key = KEY_MANAGER
+        .get_consensus_io_exchange_keypair()
+        .map_err(|err| {
+            warn!(
+                "got an error while trying to derive the tx encryption key: {}",
+                err
+            );
+            EnclaveError::KeyDerivationFailed
+        })?;
 
     let tx_encryption_ikm = enclave_io_key.diffie_hellman(user_public_key);
 
     let tx_encryption_key = AESKey::new_from_slice(&tx_encryption_ikm).derive_key_from_this(nonce);
 
-    trace!("rust tx_encryption_key {:?}", tx_encryption_key.get());
+    trace_secret!("rust tx_encryption_key {:?}", tx_encryption_key.get());
+
+    Ok(tx_encryption_key)
+}
+
+/// Queries that contracts send to each other are encrypted with a key that is also bound to the
+/// code hash of the queried contract, and so are the responses. This way a contract can only
+/// decrypt responses from contracts with the code hash it asked for.
+pub fn calc_query_encryption_key(
+    nonce: &IoNonce,
+    user_public_key: &Ed25519PublicKey,
+    code_hash: &[u8; HASH_SIZE],
+) -> Result<AESKey, EnclaveError> {
+    Ok(bind_key_to_code_hash(
+        &calc_encryption_key(nonce, user_public_key)?,
+        code_hash,
+    ))
+}
+
+fn bind_key_to_code_hash(key: &AESKey, code_hash: &[u8; HASH_SIZE]) -> AESKey {
+    key.derive_key_from_this(code_hash)
+}
+
+/// Mixed into the key of log attributes that are encrypted to another account than the sender
+const LOG_RECIPIENT_KEY_LABEL: &[u8] = b"log recipient";
+
+/// Derives the key that a log attribute addressed to `recipient` is encrypted with, in a tx whose
+/// message was encrypted with `nonce`. `recipient` is the public key the recipient encrypts its
+/// own txs with, so it derives the key like the key of a tx with that nonce, and then derives
+/// `LOG_RECIPIENT_KEY_LABEL` from it. The nonce is public, it's the start of the message of the tx.
+///
+/// The label keeps a contract from using the attributes it logs to encrypt messages that look like
+/// they were sent by the recipient.
+pub fn calc_log_recipient_encryption_key(
+    nonce: &IoNonce,
+    recipient: &[u8],
+) -> Result<AESKey, EnclaveError> {
+    if recipient.len() != 32 {
+        warn!(
+            "Log recipient public key is {} bytes long, not 32",
+            recipient.len()
+        );
+        return Err(EnclaveError::FailedToDeserialize);
+    }
+    let mut recipient_public_key = Ed25519PublicKey::default();
+    recipient_public_key.copy_from_slice(recipient);
+
+    Ok(calc_encryption_key(nonce, &recipient_public_key)?
+        .derive_key_from_this(LOG_RECIPIENT_KEY_LABEL))
+}
+
+/// Who sent a query. Decides which key the response is encrypted with.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum QuerySender {
+    User,
+    Contract,
+}
+
+/// Decrypts a query sent to the contract with the code hash `code_hash`. Queries from other
+/// contracts are encrypted with the key bound to that code hash, and queries from users with the
+/// plain key of the user.
+pub fn decrypt_query(
+    secret_msg: &SecretMessage,
+    code_hash: &[u8; HASH_SIZE],
+) -> Result<(Vec<u8>, QuerySender), EnclaveError> {
+    let _span = trace_span!(Phase::DecryptInput);
+    decrypt_query_with_key(
+        &secret_msg.encryption_key()?,
+        &secret_msg.msg,
+        &secret_msg.associated_data(),
+        code_hash,
+    )
+}
+
+fn decrypt_query_with_key(
+    key: &AESKey,
+    ciphertext: &[u8],
+    ad: &[&[u8]],
+    code_hash: &[u8; HASH_SIZE],
+) -> Result<(Vec<u8>, QuerySender), EnclaveError> {
+    let contract_key = bind_key_to_code_hash(key, code_hash);
+    if let Ok(query) = decrypt_message(&contract_key, ciphertext, ad) {
+        return Ok((query, QuerySender::Contract));
+    }
+
+    let query = decrypt_message(key, ciphertext, ad).map_err(|err| {
+        error!("got an error while trying to decrypt the query: {}", err);
+        EnclaveError::from(err)
+    })?;
+
+    Ok((query, QuerySender::User))
+}
+
+/// Check that `msg`, the encrypted message of a tx, decrypts in this enclave, without executing
+/// it. `tx_public_key` is the public key the sender encrypted the message with. Only the length of
+/// the plaintext is reported, never the plaintext itself.
+pub fn verify_input(msg: &[u8], tx_public_key: &Ed25519PublicKey) -> VerifyInputResult {
+    let failed = |step| VerifyInputResult {
+        step,
+        plaintext_len: 0,
+    };
+
+    if msg.len() > MAX_VERIFY_INPUT_SIZE {
+        warn!(
+            "Message to verify is longer than {} bytes",
+            MAX_VERIFY_INPUT_SIZE
+        );
+        return failed(InputVerificationStep::Parse);
+    }
+    let secret_msg = match SecretMessage::from_slice(msg) {
+        Ok(secret_msg) => secret_msg,
+        Err(_) => return failed(InputVerificationStep::Parse),
+    };
+
+    if &secret_msg.user_public_key != tx_public_key {
+        debug!(
+            "Message to verify was encrypted by {:?}, not {:?}",
+            secret_msg.user_public_key, tx_public_key
+        );
+        return failed(InputVerificationStep::PublicKey);
+    }
+
+    // `calc_encryption_key` can't fail on a missing io key, since messages are only executed
+    // after the node was initialized
+    if KEY_MANAGER.get_consensus_io_exchange_keypair().is_err() {
+        return failed(InputVerificationStep::KeyDerivation);
+    }
+    let key = match secret_msg.encryption_key() {
+        Ok(key) => key,
+        Err(_) => return failed(InputVerificationStep::KeyDerivation),
+    };
+
+    match decrypt_message(&key, &secret_msg.msg, &secret_msg.associated_data()) {
+        Ok(plaintext) => VerifyInputResult {
+            step: InputVerificationStep::Decrypted,
+            plaintext_len: plaintext.len() as u64,
+        },
+        Err(err) => {
+            debug!("Message to verify could not be decrypted: {}", err);
+            failed(InputVerificationStep::Decryption)
+        }
+    }
+}
+
+/// The amount of random bytes handed to the contract at a time
+pub const RANDOM_BYTES_SIZE: usize = 32;
+
+/// Deterministic randomness for a single message sent to a contract.
+///
+/// Every node derives the same values from the consensus seed, but they can't be computed
+/// outside the enclave. Each call to `next_random` returns different bytes.
+pub struct MessageRandomness {
+    message_secret: AESKey,
+    counter: u64,
+}
+
+impl MessageRandomness {
+    pub fn new(
+        block_height: u64,
+        contract_addr: &CanonicalAddr,
+        secret_msg: &SecretMessage,
+        sig_info: &SigInfo,
+    ) -> Result<Self, EnclaveError> {
+        let randomness_secret = KEY_MANAGER
+            .get_consensus_randomness_secret()
+            .map_err(|_err| {
+                warn!("Error extracting consensus_randomness_secret");
+                EnclaveError::InternalError
+            })?;
+
+        Ok(Self::from_secret(
+            &randomness_secret,
+            block_height,
+            contract_addr,
+            secret_msg,
+            sig_info,
+        ))
+    }
+
+    /// The derivation path is bound to the message, so it can't be replayed by another message
+    /// in the same tx: messages of a tx share the signed tx bytes, but each one is encrypted with
+    /// its own nonce. Callbacks are bound to the callback signature of the contract that sent them.
+    fn from_secret(
+        randomness_secret: &AESKey,
+        block_height: u64,
+        contract_addr: &CanonicalAddr,
+        secret_msg: &SecretMessage,
+        sig_info: &SigInfo,
+    ) -> Self {
+        let authorization = match &sig_info.callback_sig {
+            Some(callback_sig) => callback_sig.as_slice(),
+            None => sig_info.sign_bytes.as_slice(),
+        };
+
+        let mut derivation_path = block_height.to_be_bytes().to_vec();
+        derivation_path.extend_from_slice(&sha_256(contract_addr.as_slice()));
+        derivation_path.extend_from_slice(&sha_256(&secret_msg.to_vec()));
+        derivation_path.extend_from_slice(&sha_256(authorization));
+
+        Self {
+            message_secret: randomness_secret.derive_key_from_this(&derivation_path),
+            counter: 0,
+        }
+    }
 
-    tx_encryption_key
+    pub fn next_random(&mut self) -> [u8; RANDOM_BYTES_SIZE] {
+        let random = self
+            .message_secret
+            .derive_key_from_this(&self.counter.to_be_bytes());
+        self.counter += 1;
+
+        *random.get()
+    }
 }
 
 fn encrypt_serializable<T>(key: &AESKey, val: &T) -> Result<String, EnclaveError>
+where
+    T: ?Sized + Serialize,
+{
+    encrypt_preserialized_string(key, &serialize_for_encryption(val)?)
+}
+
+fn serialize_for_encryption<T>(val: &T) -> Result<String, EnclaveError>
 where
     T: ?Sized + Serialize,
 {
@@ -36,22 +282,22 @@ where
 
     let trimmed = serialized.trim_start_matches('"').trim_end_matches('"');
 
-    let encrypted_data = key.encrypt_siv(trimmed.as_bytes(), None).map_err(|err| {
-        debug!(
-            "got an error while trying to encrypt output error {:?}: {}",
-            err, err
-        );
-        EnclaveError::EncryptionError
-    })?;
-
-    Ok(b64_encode(encrypted_data.as_slice()))
+    Ok(trimmed.to_string())
 }
 
 // use this to encrypt a String that has already been serialized.  When that is the case, if
 // encrypt_serializable is called instead, it will get double serialized, and any escaped
 // characters will be double escaped
 fn encrypt_preserialized_string(key: &AESKey, val: &str) -> Result<String, EnclaveError> {
-    let encrypted_data = key.encrypt_siv(val.as_bytes(), None).map_err(|err| {
+    encrypt_preserialized_string_with_ad(key, val, None)
+}
+
+fn encrypt_preserialized_string_with_ad(
+    key: &AESKey,
+    val: &str,
+    ad: Option<&[&[u8]]>,
+) -> Result<String, EnclaveError> {
+    let encrypted_data = key.encrypt_siv(val.as_bytes(), ad).map_err(|err| {
         debug!(
             "got an error while trying to encrypt output error {:?}: {}",
             err, err
@@ -66,24 +312,115 @@ fn b64_encode(data: &[u8]) -> String {
     base64::encode(data)
 }
 
+/// The first byte of the plaintext of an encrypted `data` field. The data used to be base64
+/// encoded before it was encrypted, and base64 never starts with this byte, so clients can tell
+/// the two formats apart.
+pub const RAW_DATA_VERSION: u8 = 1;
+
+/// Encrypts the `data` field of an output. The data is encrypted as it is, so it's only base64
+/// encoded once, when the output is serialized.
+fn encrypt_data(key: &AESKey, data: &[u8]) -> Result<Binary, EnclaveError> {
+    let mut plaintext = Vec::with_capacity(data.len() + 1);
+    plaintext.push(RAW_DATA_VERSION);
+    plaintext.extend_from_slice(data);
+
+    let encrypted_data = key.encrypt_siv(&plaintext, None).map_err(|err| {
+        debug!("got an error while trying to encrypt output data: {}", err);
+        EnclaveError::EncryptionError
+    })?;
+
+    Ok(Binary(encrypted_data))
+}
+
+fn check_response_size(output: &[u8]) -> Result<(), EnclaveError> {
+    if output.len() > MAX_RESPONSE_SIZE {
+        warn!(
+            "The contract returned a response of {} bytes, the maximum is {}",
+            output.len(),
+            MAX_RESPONSE_SIZE
+        );
+        return Err(EnclaveError::ResponseTooLarge);
+    }
+
+    Ok(())
+}
+
+/// How much a contract may return from one call. The chain sets these through `output_limits` in
+/// the env, so they can change without upgrading the enclave.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct OutputLimits {
+    /// The most messages and submessages together
+    pub max_messages: u32,
+    /// The largest output, before it's encrypted
+    pub max_bytes: u64,
+}
+
+impl Default for OutputLimits {
+    fn default() -> Self {
+        OutputLimits {
+            max_messages: MAX_RESULT_MESSAGES,
+            max_bytes: MAX_RESULT_SIZE,
+        }
+    }
+}
+
+/// Check the output of a contract against `limits`, before any work is spent on encrypting it.
+/// `output_len` is the length of the output as the contract returned it.
+fn check_output_limits(
+    output: &WasmOutput,
+    output_len: usize,
+    limits: &OutputLimits,
+) -> Result<(), EnclaveError> {
+    let messages = match output {
+        WasmOutput::OkObject { ok } => ok.messages.len() + ok.submessages.len(),
+        _ => 0,
+    };
+    let messages = messages.min(u32::MAX as usize) as u32;
+    let bytes = output_len as u64;
+
+    if messages > limits.max_messages || bytes > limits.max_bytes {
+        warn!(
+            "The contract returned {} messages in {} bytes, the maximum is {} messages in {} bytes",
+            messages, bytes, limits.max_messages, limits.max_bytes
+        );
+        return Err(EnclaveError::ResultTooLarge {
+            messages,
+            max_messages: limits.max_messages,
+            bytes,
+            max_bytes: limits.max_bytes,
+        });
+    }
+
+    Ok(())
+}
+
+/// Encrypts the output of a contract to the sender of the tx. The output must be strict JSON, see
+/// `strict_json`. When the chain passed the `execution_path` of the call, the log attributes are
+/// bound to it, see `encrypt_logs`, and it's returned in the output.
+///
+/// If the contract returned an error, the encrypted error is returned as `CallError::Contract`
+/// so it can be passed out of the enclave separately from successful outputs.
 pub fn encrypt_output(
     output: Vec<u8>,
     nonce: IoNonce,
     user_public_key: Ed25519PublicKey,
     contract_addr: &CanonicalAddr,
-) -> Result<Vec<u8>, EnclaveError> {
-    let key = calc_encryption_key(&nonce, &user_public_key);
+    limits: &OutputLimits,
+    execution_path: Option<&[u32]>,
+    reply_target: Option<&ReplyTarget>,
+) -> Result<Vec<u8>, CallError> {
+    let _span = trace_span!(Phase::EncryptOutput);
+    let key = calc_encryption_key(&nonce, &user_public_key)?;
 
-    trace!(
+    trace_secret!(
         "Output before encryption: {:?}",
         String::from_utf8_lossy(&output)
     );
 
-    let mut output: WasmOutput = serde_json::from_slice(&output).map_err(|err| {
-        warn!("got an error while trying to deserialize output bytes into json");
-        trace!("output: {:?} error: {:?}", output, err);
-        EnclaveError::FailedToDeserialize
-    })?;
+    let output_len = output.len();
+    let mut output: WasmOutput = from_contract_response(&output)?;
+    check_output_limits(&output, output_len, limits)?;
 
     match &mut output {
         WasmOutput::ErrObject { err } => {
@@ -105,13 +442,50 @@ pub fn encrypt_output(
                 }
             }
 
-            for log in &mut ok.log {
-                log.key = encrypt_preserialized_string(&key, &log.key)?;
-                log.value = encrypt_preserialized_string(&key, &log.value)?;
+            // Submessages are encrypted like messages, but the id is kept in plaintext so the
+            // module can route the reply back to the contract
+            for sub_msg in &mut ok.submessages {
+                if let CosmosMsg::Wasm(wasm_msg) = &mut sub_msg.msg {
+                    encrypt_wasm_msg(wasm_msg, nonce, user_public_key, contract_addr)?;
+                }
+
+                let calls_contract = matches!(sub_msg.msg, CosmosMsg::Wasm(_));
+                sub_msg.reply_sig = Some(Binary(
+                    create_reply_signature(
+                        contract_addr,
+                        sub_msg.id,
+                        calls_contract,
+                        &nonce,
+                        &user_public_key,
+                    )
+                    .to_vec(),
+                ));
             }
 
+            encrypt_logs(&key, &nonce, &mut ok.log, execution_path)?;
+            ok.execution_path = execution_path.map(<[u32]>::to_vec);
+
             if let Some(data) = &mut ok.data {
-                *data = Binary::from_base64(&encrypt_serializable(&key, data)?)?;
+                *data = encrypt_data(&key, data.as_slice())?;
+            }
+
+            if let Some(target) = reply_target {
+                // The data the module passes back in the result, as the reply will see it
+                let data = match &target.instantiated {
+                    Some(contract) => Some(contract.0.clone()),
+                    None => ok.data.clone(),
+                };
+                let result = SubMsgResult::Ok(SubMsgExecutionResponse { data });
+                ok.reply_result_sig = Some(Binary(
+                    create_reply_result_signature(
+                        &target.contract,
+                        target.id,
+                        &nonce,
+                        &user_public_key,
+                        &result,
+                    )
+                    .to_vec(),
+                ));
             }
         }
     };
@@ -125,10 +499,348 @@ pub fn encrypt_output(
         );
         EnclaveError::FailedToSerialize
     })?;
+    check_response_size(&encrypted_output)?;
+
+    if let WasmOutput::ErrObject { .. } = output {
+        return Err(CallError::Contract(encrypted_output));
+    }
 
     Ok(encrypted_output)
 }
 
+/// Encrypts the output of a query to whoever sent it.
+///
+/// Responses to contracts are encrypted with the key bound to the code hash of this contract, and
+/// the hex encoded code hash is prepended to the plaintext, so the querying contract can verify
+/// who answered it with `open_query_response`.
+pub fn encrypt_query_output(
+    output: Vec<u8>,
+    nonce: IoNonce,
+    user_public_key: Ed25519PublicKey,
+    sender: QuerySender,
+    code_hash: &[u8; HASH_SIZE],
+    limits: &OutputLimits,
+) -> Result<Vec<u8>, CallError> {
+    match sender {
+        QuerySender::User => encrypt_output(
+            output,
+            nonce,
+            user_public_key,
+            &CanonicalAddr(Binary(Vec::new())), // Not used for queries
+            limits,
+            None,
+            None,
+        ),
+        QuerySender::Contract => {
+            let key = calc_query_encryption_key(&nonce, &user_public_key, code_hash)?;
+            encrypt_contract_query_output(&key, code_hash, output)
+        }
+    }
+}
+
+fn encrypt_contract_query_output(
+    key: &AESKey,
+    code_hash: &[u8; HASH_SIZE],
+    output: Vec<u8>,
+) -> Result<Vec<u8>, CallError> {
+    trace_secret!(
+        "Query output before encryption: {:?}",
+        String::from_utf8_lossy(&output)
+    );
+
+    let mut output: WasmOutput = from_contract_response(&output)?;
+
+    match &mut output {
+        WasmOutput::ErrObject { err } => {
+            let encrypted_err =
+                seal_query_response(key, code_hash, &serialize_for_encryption(err)?)?;
+
+            // Putting the error inside a 'generic_err' envelope, so we can encrypt the error itself
+            *err = json!({"generic_err":{"msg":encrypted_err}});
+        }
+
+        WasmOutput::OkString { ok } => {
+            *ok = seal_query_response(key, code_hash, &serialize_for_encryption(ok)?)?;
+        }
+
+        WasmOutput::OkObject { .. } => {
+            warn!("query returned messages, which only handle can return");
+            return Err(EnclaveError::FailedToDeserialize.into());
+        }
+    };
+
+    let encrypted_output = serde_json::to_vec(&output).map_err(|err| {
+        debug!(
+            "got an error while trying to serialize query output json into bytes {:?}: {}",
+            output, err
+        );
+        EnclaveError::FailedToSerialize
+    })?;
+    check_response_size(&encrypted_output)?;
+
+    if let WasmOutput::ErrObject { .. } = output {
+        return Err(CallError::Contract(encrypted_output));
+    }
+
+    Ok(encrypted_output)
+}
+
+fn seal_query_response(
+    key: &AESKey,
+    code_hash: &[u8; HASH_SIZE],
+    response: &str,
+) -> Result<String, EnclaveError> {
+    let mut hash_prepended_response = hex::encode(code_hash);
+    hash_prepended_response.push_str(response);
+
+    encrypt_preserialized_string(key, &hash_prepended_response)
+}
+
+/// Decrypts the response to a query sent to a contract with the code hash `code_hash`, and
+/// verifies that it was encrypted by a contract with that code hash.
+///
+/// `key` is the key from `calc_query_encryption_key`, so a response encrypted by a contract with
+/// another code hash fails to decrypt. Both cases mean the host didn't deliver the query to the
+/// contract it was sent to.
+pub fn open_query_response(
+    key: &AESKey,
+    code_hash: &[u8; HASH_SIZE],
+    ciphertext: &[u8],
+) -> Result<Vec<u8>, EnclaveError> {
+    let plaintext = key.decrypt_siv(ciphertext, None).map_err(|err| {
+        warn!(
+            "query response was not encrypted for the queried code hash: {}",
+            err
+        );
+        EnclaveError::QueryCodeHashMismatch
+    })?;
+
+    let expected_hash = hex::encode(code_hash);
+    if !plaintext.starts_with(expected_hash.as_bytes()) {
+        warn!("query response was sent by a contract with another code hash");
+        return Err(EnclaveError::QueryCodeHashMismatch);
+    }
+
+    Ok(plaintext[expected_hash.len()..].to_vec())
+}
+
+/// The first associated data of log attributes that are bound to their ordinal, see
+/// `encrypt_logs`
+pub const LOG_ATTRIBUTE_AD_VERSION: u8 = 1;
+
+/// The key the module adds to the events of a contract for its address. The module drops
+/// plaintext attributes with this key, so they're dropped before their ordinals are counted.
+const RESERVED_LOG_KEY: &str = "contract_address";
+
+/// Encrypts the log attributes, except for the ones the contract marked as plaintext.
+/// Plaintext attributes are passed through exactly as the contract wrote them. The enclave never
+/// adds anything of its own to them, so they can't reveal anything the contract couldn't.
+///
+/// Attributes with a recipient are encrypted to it instead of the sender, see
+/// `calc_log_recipient_encryption_key`. An attribute that can't be encrypted to its recipient fails
+/// the call with `EnclaveError::EncryptionError`, so the recipient never misses an attribute without
+/// anyone noticing.
+///
+/// When the chain passed the `execution_path` of the call, every encrypted attribute is bound to
+/// its ordinal, which is the path and the index of the attribute in the output:
+///
+/// key = AES-SIV(key, plaintext key, [LOG_ATTRIBUTE_AD_VERSION, path, index, "key"])
+///
+/// and the same for the value, with "value". The path is each of its steps as 4 bytes big endian,
+/// and the index is 4 bytes big endian. A client reads the path from the event and counts the
+/// index, so an attribute that the host reorders, or emits again somewhere else, doesn't decrypt.
+fn encrypt_logs(
+    key: &AESKey,
+    nonce: &IoNonce,
+    logs: &mut Vec<LogAttribute>,
+    execution_path: Option<&[u32]>,
+) -> Result<(), EnclaveError> {
+    let path = execution_path.map(|path| {
+        let mut bytes = Vec::with_capacity(path.len() * 4);
+        for step in path {
+            bytes.extend_from_slice(&step.to_be_bytes());
+        }
+        bytes
+    });
+
+    let mut encrypted = Vec::with_capacity(logs.len());
+    for mut log in logs.drain(..) {
+        if log.plaintext && path.is_some() && log.key == RESERVED_LOG_KEY {
+            debug!("Dropping a plaintext log attribute with a reserved key");
+            continue;
+        }
+        if !log.plaintext {
+            let recipient_key = match &log.recipient {
+                Some(recipient) => {
+                    let recipient_key =
+                        calc_log_recipient_encryption_key(nonce, recipient.as_slice()).map_err(
+                            |_| {
+                                warn!("Can't encrypt a log attribute, its recipient has no key");
+                                EnclaveError::EncryptionError
+                            },
+                        )?;
+                    Some(recipient_key)
+                }
+                None => None,
+            };
+            let key = recipient_key.as_ref().unwrap_or(key);
+
+            match &path {
+                Some(path) => {
+                    let index = (encrypted.len() as u32).to_be_bytes();
+                    let ad = |field: &'static [u8]| {
+                        [
+                            &[LOG_ATTRIBUTE_AD_VERSION][..],
+                            &path[..],
+                            &index[..],
+                            field,
+                        ]
+                    };
+                    log.key =
+                        encrypt_preserialized_string_with_ad(key, &log.key, Some(&ad(b"key")))?;
+                    log.value =
+                        encrypt_preserialized_string_with_ad(key, &log.value, Some(&ad(b"value")))?;
+                }
+                None => {
+                    log.key = encrypt_preserialized_string(key, &log.key)?;
+                    log.value = encrypt_preserialized_string(key, &log.value)?;
+                }
+            }
+        }
+        encrypted.push(log);
+    }
+    *logs = encrypted;
+
+    Ok(())
+}
+
+/// Checks the output of an entry point that isn't encrypted to anyone, like the IBC channel
+/// handshake. The output is passed on in plaintext, so it can't have messages to other contracts,
+/// which are always encrypted.
+pub fn check_public_output(output: Vec<u8>, limits: &OutputLimits) -> Result<Vec<u8>, CallError> {
+    let output_len = output.len();
+    let output: WasmOutput = from_contract_response(&output)?;
+    check_output_limits(&output, output_len, limits)?;
+
+    if let WasmOutput::OkObject { ok } = &output {
+        let sends_wasm_msg = ok
+            .messages
+            .iter()
+            .chain(ok.submessages.iter().map(|sub_msg| &sub_msg.msg))
+            .any(|msg| matches!(msg, CosmosMsg::Wasm(_)));
+        // Submessages need a reply signature, which is bound to the sender of the tx
+        if sends_wasm_msg || !ok.submessages.is_empty() {
+            warn!("contract tried to send a message that can't be encrypted to anyone");
+            return Err(EnclaveError::UnencryptableMessage.into());
+        }
+    }
+
+    let serialized_output = serde_json::to_vec(&output).map_err(|err| {
+        debug!(
+            "got an error while trying to serialize output json into bytes {:?}: {}",
+            output, err
+        );
+        EnclaveError::FailedToSerialize
+    })?;
+
+    if let WasmOutput::ErrObject { .. } = output {
+        return Err(CallError::Contract(serialized_output));
+    }
+
+    Ok(serialized_output)
+}
+
+/// Encrypts the output of `ibc_packet_receive` to the sender of the packet, like `encrypt_output`
+/// does for a tx, and takes the acknowledgement out of it to be encrypted with `encrypt_ack`.
+///
+/// Returns the encrypted output and acknowledgement.
+pub fn encrypt_receive_output(
+    output: Vec<u8>,
+    nonce: IoNonce,
+    user_public_key: Ed25519PublicKey,
+    counterparty: &IbcEndpoint,
+    sequence: u64,
+    contract_addr: &CanonicalAddr,
+    limits: &OutputLimits,
+) -> Result<(Vec<u8>, Vec<u8>), CallError> {
+    let output: IbcReceiveOutput = from_contract_response(&output)?;
+
+    let (output, acknowledgement) = match output {
+        IbcReceiveOutput::ErrObject { err } => (WasmOutput::ErrObject { err }, Binary::default()),
+        IbcReceiveOutput::OkObject { ok } => (
+            WasmOutput::OkObject {
+                ok: ContractResult {
+                    submessages: vec![],
+                    messages: ok.messages,
+                    log: ok.log,
+                    data: None,
+                    execution_path: None,
+                    reply_result_sig: None,
+                },
+            },
+            ok.acknowledgement,
+        ),
+    };
+
+    let output = serde_json::to_vec(&output).map_err(|err| {
+        debug!(
+            "got an error while trying to serialize output json into bytes {:?}: {}",
+            output, err
+        );
+        EnclaveError::FailedToSerialize
+    })?;
+
+    // Errors of the contract are returned from here as `CallError::Contract`, so only packets
+    // that were received successfully get an acknowledgement
+    let output = encrypt_output(
+        output,
+        nonce,
+        user_public_key,
+        contract_addr,
+        limits,
+        None,
+        None,
+    )?;
+
+    let key = calc_encryption_key(&nonce, &user_public_key)?;
+    let acknowledgement = encrypt_ack(&key, acknowledgement.as_slice(), counterparty, sequence)?;
+
+    Ok((output, acknowledgement))
+}
+
+/// Encrypts the acknowledgement of a packet that was received by a secret contract.
+///
+/// The data of the packet is a `SecretMessage`, encrypted by the sender of the packet like the
+/// message of a tx. The acknowledgement is encrypted with the same key, which is derived from the
+/// nonce and public key of that message, so only the sender of the packet can read it. The
+/// counterparty end of the channel and the sequence of the packet are authenticated as associated
+/// data, so the acknowledgement can't be passed off as the one of another packet:
+///
+/// ack = AES-SIV(key, acknowledgement, [counterparty port_id, counterparty channel_id, sequence])
+///
+/// where the sequence is 8 bytes big endian. The ack is written as is, without base64.
+pub fn encrypt_ack(
+    key: &AESKey,
+    acknowledgement: &[u8],
+    counterparty: &IbcEndpoint,
+    sequence: u64,
+) -> Result<Vec<u8>, EnclaveError> {
+    let sequence = sequence.to_be_bytes();
+    let ad: [&[u8]; 3] = [
+        counterparty.port_id.as_bytes(),
+        counterparty.channel_id.as_bytes(),
+        &sequence,
+    ];
+
+    key.encrypt_siv(acknowledgement, Some(&ad)).map_err(|err| {
+        debug!(
+            "got an error while trying to encrypt acknowledgement {:?}: {}",
+            err, err
+        );
+        EnclaveError::EncryptionError
+    })
+}
+
 fn encrypt_wasm_msg(
     wasm_msg: &mut WasmMsg,
     nonce: IoNonce,
@@ -141,6 +853,7 @@ fn encrypt_wasm_msg(
             callback_code_hash,
             callback_sig,
             send,
+            funds,
             ..
         }
         | WasmMsg::Instantiate {
@@ -148,8 +861,22 @@ fn encrypt_wasm_msg(
             callback_code_hash,
             callback_sig,
             send,
+            funds,
             ..
         } => {
+            // The chain only reads `send`, so the funds of a CosmWasm 1.0 message are moved there
+            let is_v1 = match funds.take() {
+                Some(funds) if send.is_empty() => {
+                    *send = funds;
+                    true
+                }
+                Some(_) => {
+                    warn!("Contract sent a message with both send and funds");
+                    return Err(EnclaveError::MalformedContractResponse);
+                }
+                None => false,
+            };
+
             let mut hash_appended_msg = callback_code_hash.as_bytes().to_vec();
             hash_appended_msg.extend_from_slice(msg.as_slice());
 
@@ -162,28 +889,955 @@ fn encrypt_wasm_msg(
             msg_to_pass.encrypt_in_place()?;
             *msg = Binary::from(msg_to_pass.to_vec().as_slice());
 
-            *callback_sig = Some(create_callback_signature(contract_addr, &msg_to_pass, send));
+            *callback_sig = Some(match decode_code_hash(callback_code_hash) {
+                Some(code_hash) if is_v1 => {
+                    let signature =
+                        create_callback_signature_v1(contract_addr, &code_hash, &msg_to_pass, send);
+                    let mut versioned = vec![CALLBACK_SIG_V1];
+                    versioned.extend_from_slice(&signature.to_vec());
+                    versioned
+                }
+                // A message with a malformed code hash is rejected by whatever contract gets it
+                _ => create_callback_signature(contract_addr, &msg_to_pass, send).to_vec(),
+            });
         }
     }
 
     Ok(())
 }
 
+pub fn decode_code_hash(code_hash: &str) -> Option<[u8; HASH_SIZE]> {
+    let decoded = hex::decode(code_hash).ok()?;
+    if decoded.len() != HASH_SIZE {
+        return None;
+    }
+
+    let mut code_hash = [0u8; HASH_SIZE];
+    code_hash.copy_from_slice(&decoded);
+    Some(code_hash)
+}
+
+/// The first byte of the callback signatures of messages built with CosmWasm 1.0, which also
+/// cover the code hash of the contract the message is sent to. The signatures of older messages
+/// have no version byte, and are told apart by their length.
+pub const CALLBACK_SIG_V1: u8 = 1;
+
 pub fn create_callback_signature(
     contract_addr: &CanonicalAddr,
     msg_to_sign: &SecretMessage,
     funds_to_send: &[Coin],
-) -> Vec<u8> {
+) -> AuthenticationCode {
     // Hash(Enclave_secret | sender(current contract) | msg_to_pass | sent_funds)
-    let mut callback_sig_bytes = KEY_MANAGER
-        .get_consensus_callback_secret()
+    // The parts are hashed one by one, so the secret isn't copied into a buffer
+    let mut hasher = sha2::Sha256::new();
+    hasher.input(KEY_MANAGER.get_consensus_callback_secret().unwrap().get());
+    hasher.input(contract_addr.as_slice());
+    hasher.input(msg_to_sign.msg.as_slice());
+    hasher.input(serde_json::to_vec(funds_to_send).unwrap());
+
+    let mut signature = [0u8; HASH_SIZE];
+    signature.copy_from_slice(&hasher.result());
+    AuthenticationCode::new(signature)
+}
+
+/// Like `create_callback_signature`, and also binds the message to the code hash it's sent to, so
+/// it can only be passed to contracts with that code. The version byte keeps these signatures
+/// apart from the ones of `create_callback_signature`.
+pub fn create_callback_signature_v1(
+    contract_addr: &CanonicalAddr,
+    code_hash: &[u8; HASH_SIZE],
+    msg_to_sign: &SecretMessage,
+    funds_to_send: &[Coin],
+) -> AuthenticationCode {
+    // Hash(Enclave_secret | version | sender(current contract) | code_hash | msg_to_pass | funds)
+    let mut hasher = sha2::Sha256::new();
+    hasher.input(KEY_MANAGER.get_consensus_callback_secret().unwrap().get());
+    hasher.input(&[CALLBACK_SIG_V1]);
+    hasher.input(contract_addr.as_slice());
+    hasher.input(code_hash);
+    hasher.input(msg_to_sign.msg.as_slice());
+    hasher.input(serde_json::to_vec(funds_to_send).unwrap());
+
+    let mut signature = [0u8; HASH_SIZE];
+    signature.copy_from_slice(&hasher.result());
+    AuthenticationCode::new(signature)
+}
+
+/// Signs a submessage id, so that only the contract that sent the submessage can get a reply
+/// with that id, and only encrypted to the sender of the original tx.
+///
+/// `calls_contract` is whether the submessage calls a contract. The result of such a submessage
+/// is signed by the enclave that ran the contract, see `create_reply_result_signature`, so the
+/// reply can't be passed another result than the one the contract returned.
+pub fn create_reply_signature(
+    contract_addr: &CanonicalAddr,
+    id: u64,
+    calls_contract: bool,
+    nonce: &IoNonce,
+    user_public_key: &Ed25519PublicKey,
+) -> AuthenticationCode {
+    // Hash(Enclave_secret | "reply" | contract | id | calls_contract | nonce | user_public_key)
+    let mut hasher = sha2::Sha256::new();
+    hasher.input(KEY_MANAGER.get_consensus_callback_secret().unwrap().get());
+    hasher.input(b"reply");
+    hasher.input(contract_addr.as_slice());
+    hasher.input(&id.to_be_bytes());
+    hasher.input(&[calls_contract as u8]);
+    hasher.input(nonce);
+    hasher.input(user_public_key);
+
+    let mut signature = [0u8; HASH_SIZE];
+    signature.copy_from_slice(&hasher.result());
+    AuthenticationCode::new(signature)
+}
+
+/// The submessage that a call runs for, see `verify_reply_to`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplyTarget {
+    /// The contract that sent the submessage
+    pub contract: CanonicalAddr,
+    pub id: u64,
+    /// Set when the call instantiated this contract. The module then passes its address back as
+    /// the data of the result, rather than the data the contract returned.
+    pub instantiated: Option<CanonicalAddr>,
+}
+
+/// Signs the result of a submessage that called a contract, as the module passes it to the reply.
+/// Made by the enclave that ran the called contract, when it encrypts its output.
+pub fn create_reply_result_signature(
+    contract_addr: &CanonicalAddr,
+    id: u64,
+    nonce: &IoNonce,
+    user_public_key: &Ed25519PublicKey,
+    result: &SubMsgResult,
+) -> AuthenticationCode {
+    // The result is signed as it's serialized when it's passed to the reply
+    let result_hash = sha_256(&serde_json::to_vec(result).unwrap());
+
+    // Hash(Enclave_secret | "reply result" | contract | id | nonce | user_public_key | Hash(result))
+    let mut hasher = sha2::Sha256::new();
+    hasher.input(KEY_MANAGER.get_consensus_callback_secret().unwrap().get());
+    hasher.input(b"reply result");
+    hasher.input(contract_addr.as_slice());
+    hasher.input(&id.to_be_bytes());
+    hasher.input(nonce);
+    hasher.input(user_public_key);
+    hasher.input(&result_hash);
+
+    let mut signature = [0u8; HASH_SIZE];
+    signature.copy_from_slice(&hasher.result());
+    AuthenticationCode::new(signature)
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use crate::consts::CONSENSUS_RANDOMNESS_SECRET_DERIVE_ORDER;
+    use crate::cosmwasm::types::{CosmosSignature, PubKeyKind, SignMode};
+    use crate::crypto::secp256k1::Secp256k1PubKey;
+    use crate::crypto::{KeyPair, Seed};
+    use crate::wasm::types::MESSAGE_AD_VERSION;
+
+    fn randomness_secret(seed_bytes: &[u8; 32]) -> AESKey {
+        let mut seed = Seed::default();
+        seed.as_mut().copy_from_slice(seed_bytes);
+        seed.derive_key_from_this(&CONSENSUS_RANDOMNESS_SECRET_DERIVE_ORDER.to_be_bytes())
+    }
+
+    fn sig_info(sign_bytes: &[u8]) -> SigInfo {
+        SigInfo {
+            sign_bytes: Binary(sign_bytes.to_vec()),
+            signature: CosmosSignature::new(
+                PubKeyKind::Secp256k1(Secp256k1PubKey::new(vec![2u8; 33])),
+                Binary(vec![0u8; 64]),
+            ),
+            additional_signers: vec![],
+            callback_sig: None,
+            sign_mode: SignMode::LegacyAminoJson,
+        }
+    }
+
+    fn secret_msg(nonce: u8) -> SecretMessage {
+        SecretMessage {
+            nonce: [nonce; 32],
+            user_public_key: [7u8; 32],
+            msg: b"encrypted message".to_vec(),
+        }
+    }
+
+    pub fn test_message_randomness_agrees_across_nodes() {
+        let contract = CanonicalAddr(Binary(vec![1u8; 20]));
+        let tx = sig_info(b"tx bytes");
+
+        let mut node_a = MessageRandomness::from_secret(
+            &randomness_secret(&[3u8; 32]),
+            100,
+            &contract,
+            &secret_msg(1),
+            &tx,
+        );
+        let mut node_b = MessageRandomness::from_secret(
+            &randomness_secret(&[3u8; 32]),
+            100,
+            &contract,
+            &secret_msg(1),
+            &tx,
+        );
+
+        let first = node_a.next_random();
+        assert_eq!(first, node_b.next_random());
+        let second = node_a.next_random();
+        assert_eq!(second, node_b.next_random());
+        assert_ne!(first, second);
+
+        let mut other_seed = MessageRandomness::from_secret(
+            &randomness_secret(&[4u8; 32]),
+            100,
+            &contract,
+            &secret_msg(1),
+            &tx,
+        );
+        assert_ne!(first, other_seed.next_random());
+    }
+
+    pub fn test_message_randomness_differs_between_messages() {
+        let secret = randomness_secret(&[3u8; 32]);
+        let contract = CanonicalAddr(Binary(vec![1u8; 20]));
+        let tx = sig_info(b"tx bytes");
+
+        let first_msg =
+            MessageRandomness::from_secret(&secret, 100, &contract, &secret_msg(1), &tx)
+                .next_random();
+        let second_msg =
+            MessageRandomness::from_secret(&secret, 100, &contract, &secret_msg(2), &tx)
+                .next_random();
+        assert_ne!(first_msg, second_msg);
+
+        let next_block =
+            MessageRandomness::from_secret(&secret, 101, &contract, &secret_msg(1), &tx)
+                .next_random();
+        assert_ne!(first_msg, next_block);
+
+        let other_contract = MessageRandomness::from_secret(
+            &secret,
+            100,
+            &CanonicalAddr(Binary(vec![2u8; 20])),
+            &secret_msg(1),
+            &tx,
+        )
+        .next_random();
+        assert_ne!(first_msg, other_contract);
+    }
+
+    fn counterparty() -> IbcEndpoint {
+        IbcEndpoint {
+            port_id: "transfer".to_string(),
+            channel_id: "channel-3".to_string(),
+        }
+    }
+
+    pub fn test_encrypt_ack_is_bound_to_the_packet() {
+        let key = AESKey::new_from_slice(&[5u8; 32]);
+        let ack = encrypt_ack(&key, b"pong", &counterparty(), 9).unwrap();
+        assert_ne!(ack.as_slice(), b"pong");
+
+        let decrypt = |port_id: &str, channel_id: &str, sequence: u64| {
+            let sequence = sequence.to_be_bytes();
+            let ad: [&[u8]; 3] = [port_id.as_bytes(), channel_id.as_bytes(), &sequence];
+            key.decrypt_siv(&ack, Some(&ad))
+        };
+        assert_eq!(
+            decrypt("transfer", "channel-3", 9).unwrap(),
+            b"pong".to_vec()
+        );
+        assert!(decrypt("transfer", "channel-3", 10).is_err());
+        assert!(decrypt("transfer", "channel-4", 9).is_err());
+        assert!(decrypt("other", "channel-3", 9).is_err());
+
+        let other_key = AESKey::new_from_slice(&[6u8; 32]);
+        let sequence = 9u64.to_be_bytes();
+        let ad: [&[u8]; 3] = [b"transfer", b"channel-3", &sequence];
+        assert!(other_key.decrypt_siv(&ack, Some(&ad)).is_err());
+    }
+
+    fn contract_query_response(key: &AESKey, code_hash: &[u8; HASH_SIZE], output: &str) -> Vec<u8> {
+        let output = match encrypt_contract_query_output(key, code_hash, output.as_bytes().to_vec())
+        {
+            Ok(output) => output,
+            Err(CallError::Contract(output)) => output,
+            Err(CallError::Enclave(err)) => panic!("failed to encrypt query output: {}", err),
+        };
+
+        let ciphertext = match serde_json::from_slice(&output).unwrap() {
+            WasmOutput::OkString { ok } => ok,
+            WasmOutput::ErrObject { err } => {
+                err["generic_err"]["msg"].as_str().unwrap().to_string()
+            }
+            WasmOutput::OkObject { .. } => panic!("query output can't be an object"),
+        };
+        base64::decode(ciphertext).unwrap()
+    }
+
+    pub fn test_swapped_query_response_is_rejected() {
+        let user_key = AESKey::new_from_slice(&[5u8; 32]);
+        let queried_hash = [1u8; HASH_SIZE];
+        let other_hash = [2u8; HASH_SIZE];
+        let queried_key = bind_key_to_code_hash(&user_key, &queried_hash);
+        let other_key = bind_key_to_code_hash(&user_key, &other_hash);
+
+        let response = contract_query_response(&queried_key, &queried_hash, r#"{"Ok":"cG9uZw=="}"#);
+        assert_eq!(
+            open_query_response(&queried_key, &queried_hash, &response).unwrap(),
+            b"cG9uZw==".to_vec()
+        );
+
+        // The host answers with the response of a contract with another code hash
+        let swapped = contract_query_response(&other_key, &other_hash, r#"{"Ok":"cG9uZw=="}"#);
+        assert!(matches!(
+            open_query_response(&queried_key, &queried_hash, &swapped),
+            Err(EnclaveError::QueryCodeHashMismatch)
+        ));
+
+        let swapped_err = contract_query_response(
+            &other_key,
+            &other_hash,
+            r#"{"Err":{"generic_err":{"msg":"nope"}}}"#,
+        );
+        assert!(matches!(
+            open_query_response(&queried_key, &queried_hash, &swapped_err),
+            Err(EnclaveError::QueryCodeHashMismatch)
+        ));
+
+        // Even with the right key, the response must be sealed by the queried code hash
+        let mislabeled = contract_query_response(&queried_key, &other_hash, r#"{"Ok":"cG9uZw=="}"#);
+        assert!(matches!(
+            open_query_response(&queried_key, &queried_hash, &mislabeled),
+            Err(EnclaveError::QueryCodeHashMismatch)
+        ));
+
+        // Responses to users can't stand in for responses to contracts
+        let user_response = user_key.encrypt_siv(b"cG9uZw==", None).unwrap();
+        assert!(matches!(
+            open_query_response(&queried_key, &queried_hash, &user_response),
+            Err(EnclaveError::QueryCodeHashMismatch)
+        ));
+    }
+
+    pub fn test_decrypt_query_detects_the_sender() {
+        let user_key = AESKey::new_from_slice(&[5u8; 32]);
+        let queried_hash = [1u8; HASH_SIZE];
+        let other_hash = [2u8; HASH_SIZE];
+
+        let ad: [&[u8]; 3] = [&[MESSAGE_AD_VERSION], &[4u8; 32], &[7u8; 32]];
+
+        let from_user = user_key.encrypt_siv(b"query", None).unwrap();
+        let (query, sender) =
+            decrypt_query_with_key(&user_key, &from_user, &ad, &queried_hash).unwrap();
+        assert_eq!(query, b"query".to_vec());
+        assert_eq!(sender, QuerySender::User);
+
+        let from_contract = bind_key_to_code_hash(&user_key, &queried_hash)
+            .encrypt_siv(b"query", Some(&ad))
+            .unwrap();
+        let (query, sender) =
+            decrypt_query_with_key(&user_key, &from_contract, &ad, &queried_hash).unwrap();
+        assert_eq!(query, b"query".to_vec());
+        assert_eq!(sender, QuerySender::Contract);
+
+        // The host delivers the query to a contract with another code hash
+        assert!(matches!(
+            decrypt_query_with_key(&user_key, &from_contract, &ad, &other_hash),
+            Err(EnclaveError::DecryptionError)
+        ));
+        // or with the nonce of another query
+        let other_ad: [&[u8]; 3] = [&[MESSAGE_AD_VERSION], &[5u8; 32], &[7u8; 32]];
+        assert!(matches!(
+            decrypt_query_with_key(&user_key, &from_contract, &other_ad, &queried_hash),
+            Err(EnclaveError::DecryptionError)
+        ));
+    }
+
+    /// An encrypted message of a tx sent by `user`, as the client of a user would encrypt it to
+    /// the io key `enclave_io_public_key`
+    fn tx_input(user: &KeyPair, enclave_io_public_key: &Ed25519PublicKey) -> Vec<u8> {
+        let nonce = [4u8; 32];
+        let key = AESKey::new_from_slice(&user.diffie_hellman(enclave_io_public_key))
+            .derive_key_from_this(&nonce);
+
+        let mut msg = nonce.to_vec();
+        msg.extend_from_slice(&user.get_pubkey());
+        msg.extend_from_slice(&key.encrypt_siv(br#"{"transfer":{}}"#, None).unwrap());
+        msg
+    }
+
+    fn io_public_key() -> Ed25519PublicKey {
+        KEY_MANAGER
+            .get_consensus_io_exchange_keypair()
+            .unwrap()
+            .get_pubkey()
+    }
+
+    pub fn test_verify_input_decrypts() {
+        let user = KeyPair::new().unwrap();
+        let msg = tx_input(&user, &io_public_key());
+
+        assert_eq!(
+            verify_input(&msg, &user.get_pubkey()),
+            VerifyInputResult {
+                step: InputVerificationStep::Decrypted,
+                plaintext_len: br#"{"transfer":{}}"#.len() as u64,
+            }
+        );
+
+        // The message is encrypted by the sender, not by whoever asks
+        let other_user = KeyPair::new().unwrap();
+        assert_eq!(
+            verify_input(&msg, &other_user.get_pubkey()).step,
+            InputVerificationStep::PublicKey
+        );
+    }
+
+    pub fn test_verify_input_truncated() {
+        let user = KeyPair::new().unwrap();
+        let msg = tx_input(&user, &io_public_key());
+
+        let result = verify_input(&msg[..msg.len() - 1], &user.get_pubkey());
+        assert_eq!(result.step, InputVerificationStep::Decryption);
+        assert_eq!(result.plaintext_len, 0);
+
+        // Too short to hold the nonce, the public key and the tag
+        let result = verify_input(&msg[..70], &user.get_pubkey());
+        assert_eq!(result.step, InputVerificationStep::Parse);
+    }
+
+    pub fn test_verify_input_wrong_io_key() {
+        let user = KeyPair::new().unwrap();
+        // e.g. the io key of another network
+        let other_io_key = KeyPair::new().unwrap();
+        let msg = tx_input(&user, &other_io_key.get_pubkey());
+
+        let result = verify_input(&msg, &user.get_pubkey());
+        assert_eq!(result.step, InputVerificationStep::Decryption);
+        assert_eq!(result.plaintext_len, 0);
+    }
+
+    pub fn test_check_public_output() {
+        let bank_msg = br#"{"Ok":{"messages":[{"bank":{"send":{"from_address":"a","to_address":"b","amount":[]}}}],"log":[{"key":"k","value":"v"}],"data":null}}"#;
+        let output = check_public_output(bank_msg.to_vec(), &OutputLimits::default())
+            .ok()
+            .unwrap();
+        let output: WasmOutput = serde_json::from_slice(&output).unwrap();
+        assert_eq!(output, serde_json::from_slice(bank_msg).unwrap());
+
+        let wasm_msg = br#"{"Ok":{"messages":[{"wasm":{"execute":{"contract_addr":"c","callback_code_hash":"","msg":"","send":[]}}}],"log":[],"data":null}}"#;
+        assert!(matches!(
+            check_public_output(wasm_msg.to_vec(), &OutputLimits::default()),
+            Err(CallError::Enclave(EnclaveError::UnencryptableMessage))
+        ));
+
+        let submessage = br#"{"Ok":{"submessages":[{"id":1,"msg":{"bank":{"send":{"from_address":"a","to_address":"b","amount":[]}}},"gas_limit":null}],"messages":[],"log":[],"data":null}}"#;
+        assert!(matches!(
+            check_public_output(submessage.to_vec(), &OutputLimits::default()),
+            Err(CallError::Enclave(EnclaveError::UnencryptableMessage))
+        ));
+
+        let err = br#"{"Err":{"generic_err":{"msg":"nope"}}}"#;
+        assert!(matches!(
+            check_public_output(err.to_vec(), &OutputLimits::default()),
+            Err(CallError::Contract(_))
+        ));
+    }
+
+    pub fn test_encrypt_logs_keeps_plaintext_attributes() {
+        let key = AESKey::new_from_slice(&[9u8; 32]);
+        let mut logs: Vec<LogAttribute> = serde_json::from_str(
+            r#"[
+                {"key": "action", "value": "transfer", "plaintext": true},
+                {"key": "amount", "value": "100"}
+            ]"#,
+        )
+        .unwrap();
+
+        encrypt_logs(&key, &[4u8; 32], &mut logs, None).unwrap();
+
+        assert_eq!(logs[0].key, "action");
+        assert_eq!(logs[0].value, "transfer");
+
+        let decrypt = |ciphertext: &str| {
+            let ciphertext = base64::decode(ciphertext).unwrap();
+            key.decrypt_siv(&ciphertext, None).unwrap()
+        };
+        assert_eq!(decrypt(&logs[1].key), b"amount".to_vec());
+        assert_eq!(decrypt(&logs[1].value), b"100".to_vec());
+
+        // The chain gets the same format for both kinds of attributes
+        assert_eq!(
+            serde_json::to_value(&logs[0]).unwrap(),
+            json!({"key": "action", "value": "transfer"})
+        );
+    }
+
+    pub fn test_encrypt_init_output_logs() {
+        let contract = CanonicalAddr(Binary(vec![1u8; 20]));
+        let nonce = [4u8; 32];
+        let user_public_key = [7u8; 32];
+
+        // An InitResponse has no data field
+        let output = br#"{"Ok":{"messages":[],"log":[{"key":"action","value":"instantiated"},{"key":"owner","value":"secret1owner"}]}}"#;
+        let encrypted = encrypt_output(
+            output.to_vec(),
+            nonce,
+            user_public_key,
+            &contract,
+            &OutputLimits::default(),
+            None,
+            None,
+        )
+        .unwrap_or_else(|_| panic!("init output should be encrypted"));
+
+        let log = match serde_json::from_slice(&encrypted).unwrap() {
+            WasmOutput::OkObject { ok } => ok.log,
+            _ => panic!("init output should stay an object"),
+        };
+
+        // The sender decrypts the attributes with the key of the tx, in the order they were logged
+        let key = calc_encryption_key(&nonce, &user_public_key).unwrap();
+        let decrypt = |ciphertext: &str| {
+            let ciphertext = base64::decode(ciphertext).unwrap();
+            String::from_utf8(key.decrypt_siv(&ciphertext, None).unwrap()).unwrap()
+        };
+        let decrypted: Vec<(String, String)> = log
+            .iter()
+            .map(|attr| (decrypt(&attr.key), decrypt(&attr.value)))
+            .collect();
+        assert_eq!(
+            decrypted,
+            vec![
+                ("action".to_string(), "instantiated".to_string()),
+                ("owner".to_string(), "secret1owner".to_string()),
+            ]
+        );
+    }
+
+    pub fn test_log_attribute_round_trips_to_its_recipient() {
+        let contract = CanonicalAddr(Binary(vec![1u8; 20]));
+        let nonce = [4u8; 32];
+        let sender = KeyPair::new().unwrap();
+        let recipient = KeyPair::new().unwrap();
+
+        let recipient_public_key = Binary(recipient.get_pubkey().to_vec());
+        let output = serde_json::to_vec(&json!({"Ok": {"messages": [], "log": [
+            {"key": "order", "value": "filled", "recipient": recipient_public_key},
+            {"key": "action", "value": "fill"},
+        ], "data": null}}))
+        .unwrap();
+        let encrypted = encrypt_output(
+            output,
+            nonce,
+            sender.get_pubkey(),
+            &contract,
+            &OutputLimits::default(),
+            None,
+            None,
+        )
+        .unwrap_or_else(|_| panic!("attributes to a recipient shouldn't fail the call"));
+
+        let log = match serde_json::from_slice(&encrypted).unwrap() {
+            WasmOutput::OkObject { ok } => ok.log,
+            _ => panic!("handle output should stay an object"),
+        };
+        assert_eq!(log.len(), 2);
+
+        // What a client does with its own private key, the io public key of the enclave and the
+        // nonce of the tx that emitted the attribute
+        let client_key = |keys: &KeyPair| {
+            AESKey::new_from_slice(&keys.diffie_hellman(&io_public_key()))
+                .derive_key_from_this(&nonce)
+        };
+        let decrypt = |key: &AESKey, ciphertext: &str| {
+            key.decrypt_siv(&base64::decode(ciphertext).unwrap(), None)
+                .ok()
+                .map(|plaintext| String::from_utf8(plaintext).unwrap())
+        };
+
+        let recipient_key = client_key(&recipient).derive_key_from_this(LOG_RECIPIENT_KEY_LABEL);
+        assert_eq!(decrypt(&recipient_key, &log[0].key).unwrap(), "order");
+        assert_eq!(decrypt(&recipient_key, &log[0].value).unwrap(), "filled");
+        assert_eq!(decrypt(&recipient_key, &log[1].key), None);
+
+        let sender_key = client_key(&sender);
+        assert_eq!(decrypt(&sender_key, &log[0].value), None);
+        assert_eq!(decrypt(&sender_key, &log[1].key).unwrap(), "action");
+
+        // The recipient isn't part of the output, so the chain doesn't learn who it is
+        assert!(!String::from_utf8(encrypted).unwrap().contains("recipient"));
+    }
+
+    pub fn test_log_attribute_to_recipient_without_key_fails_the_call() {
+        let contract = CanonicalAddr(Binary(vec![1u8; 20]));
+        let sender = KeyPair::new().unwrap();
+
+        for recipient in &[Binary(vec![1u8; 3]), Binary(vec![0u8; 32])] {
+            let output = serde_json::to_vec(&json!({"Ok": {"messages": [], "log": [
+                {"key": "action", "value": "fill"},
+                {"key": "order", "value": "filled", "recipient": recipient},
+            ], "data": null}}))
+            .unwrap();
+            let result = encrypt_output(
+                output,
+                [4u8; 32],
+                sender.get_pubkey(),
+                &contract,
+                &OutputLimits::default(),
+                None,
+                None,
+            );
+            assert!(matches!(
+                result,
+                Err(CallError::Enclave(EnclaveError::EncryptionError))
+            ));
+        }
+    }
+
+    /// Encrypt the output of a call at `path` that logs `name` in plaintext, and then in an
+    /// encrypted attribute, and return the log attributes and the path from the output
+    fn encrypt_logs_at(path: &[u32], name: &str) -> (Vec<LogAttribute>, Option<Vec<u32>>) {
+        let contract = CanonicalAddr(Binary(vec![1u8; 20]));
+        let output = serde_json::to_vec(&json!({"Ok": {"messages": [], "log": [
+            {"key": "call", "value": name, "plaintext": true},
+            {"key": "contract_address", "value": "spoofed", "plaintext": true},
+            {"key": "emitted", "value": name},
+        ], "data": null, "execution_path": [9, 9]}}))
+        .unwrap();
+        let encrypted = encrypt_output(
+            output,
+            [4u8; 32],
+            [7u8; 32],
+            &contract,
+            &OutputLimits::default(),
+            Some(path),
+            None,
+        )
+        .unwrap_or_else(|_| panic!("the output should be encrypted"));
+
+        match serde_json::from_slice(&encrypted).unwrap() {
+            WasmOutput::OkObject { ok } => (ok.log, ok.execution_path),
+            _ => panic!("handle output should stay an object"),
+        }
+    }
+
+    /// What a client does to decrypt a log attribute, with the path of its event and the index of
+    /// the attribute in the event
+    fn decrypt_attribute(
+        path: &[u32],
+        index: u32,
+        attr: &LogAttribute,
+    ) -> Option<(String, String)> {
+        let key = calc_encryption_key(&[4u8; 32], &[7u8; 32]).unwrap();
+        let path: Vec<u8> = path
+            .iter()
+            .flat_map(|step| step.to_be_bytes().to_vec())
+            .collect();
+        let index = index.to_be_bytes();
+        let decrypt = |ciphertext: &str, field: &[u8]| {
+            let ad = [
+                &[LOG_ATTRIBUTE_AD_VERSION][..],
+                &path[..],
+                &index[..],
+                field,
+            ];
+            key.decrypt_siv(&base64::decode(ciphertext).ok()?, Some(&ad))
+                .ok()
+                .map(|plaintext| String::from_utf8(plaintext).unwrap())
+        };
+        Some((decrypt(&attr.key, b"key")?, decrypt(&attr.value, b"value")?))
+    }
+
+    pub fn test_log_attributes_are_ordered_across_submessages() {
+        // The module numbers submessage `i` of a call with `m` messages `m + 2 * i`, and the reply
+        // to it one more
+        let submessage = |messages: u32, i: u32| messages + 2 * i;
+        let reply = |messages: u32, i: u32| messages + 2 * i + 1;
+
+        // A message that returns a message M and the submessages A and B. A returns the submessage
+        // C, and every reply logs the name of the call it's a reply to again.
+        let calls: Vec<(Vec<u32>, &str)> = vec![
+            (vec![reply(1, 0)], "A"),
+            (vec![submessage(1, 0), reply(0, 0)], "C"),
+            (vec![], "root"),
+            (vec![submessage(1, 1)], "B"),
+            (vec![0], "M"),
+            (vec![submessage(1, 0), submessage(0, 0)], "C"),
+            (vec![reply(1, 1)], "B"),
+            (vec![submessage(1, 0)], "A"),
+        ];
+
+        // Whatever order the module emits the events in, they sort by path
+        let mut events: Vec<_> = calls
+            .iter()
+            .map(|(path, name)| {
+                let (log, execution_path) = encrypt_logs_at(path, name);
+                // Contracts can't pick their path
+                assert_eq!(execution_path.as_ref(), Some(path));
+                (path.clone(), log)
+            })
+            .collect();
+        events.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        let ordinals: Vec<(Vec<u32>, String)> = events
+            .iter()
+            .map(|(path, log)| {
+                // The reserved key was dropped, so the encrypted attribute is the second one
+                assert_eq!(log.len(), 2);
+                assert_eq!(log[0].key, "call");
+                let (key, name) = decrypt_attribute(path, 1, &log[1]).unwrap();
+                assert_eq!(key, "emitted");
+                assert_eq!(name, log[0].value);
+                (path.clone(), name)
+            })
+            .collect();
+        let depth_first: Vec<(Vec<u32>, String)> = vec![
+            (vec![], "root"),
+            (vec![0], "M"),
+            (vec![1], "A"),
+            (vec![1, 0], "C"),
+            (vec![1, 1], "C"),
+            (vec![2], "A"),
+            (vec![3], "B"),
+            (vec![4], "B"),
+        ]
+        .into_iter()
+        .map(|(path, name)| (path, name.to_string()))
+        .collect();
+        assert_eq!(ordinals, depth_first);
+
+        // The attribute of C that its reply emits again has the same key and plaintext, but is
+        // told apart from the original, and the host can't pass off one as the other
+        let (child, child_log) = &events[3];
+        let (reply_path, reply_log) = &events[4];
+        assert_ne!(child_log[1], reply_log[1]);
+        assert!(decrypt_attribute(reply_path, 1, &child_log[1]).is_none());
+        assert!(decrypt_attribute(child, 0, &child_log[1]).is_none());
+        assert!(decrypt_attribute(&[], 1, &child_log[1]).is_none());
+    }
+
+    /// What clients do with the `data` field of an output, for either format
+    fn decrypt_data(key: &AESKey, ciphertext: &[u8]) -> Vec<u8> {
+        let plaintext = key.decrypt_siv(ciphertext, None).unwrap();
+        match plaintext.split_first() {
+            Some((&RAW_DATA_VERSION, data)) => data.to_vec(),
+            _ => base64::decode(&plaintext).unwrap(),
+        }
+    }
+
+    fn handle_output_with_data(data: &[u8]) -> Vec<u8> {
+        serde_json::to_vec(&json!({
+            "Ok": {"messages": [], "log": [], "data": Binary(data.to_vec())}
+        }))
         .unwrap()
-        .get()
-        .to_vec();
+    }
 
-    callback_sig_bytes.extend(contract_addr.as_slice());
-    callback_sig_bytes.extend(msg_to_sign.msg.as_slice());
-    callback_sig_bytes.extend(serde_json::to_vec(funds_to_send).unwrap());
+    pub fn test_encrypt_output_data_round_trip() {
+        let contract = CanonicalAddr(Binary(vec![1u8; 20]));
+        let nonce = [4u8; 32];
+        let user_public_key = [7u8; 32];
+        let data: Vec<u8> = (0..1024 * 1024).map(|i| (i % 251) as u8).collect();
 
-    sha2::Sha256::digest(callback_sig_bytes.as_slice()).to_vec()
+        let encrypted = encrypt_output(
+            handle_output_with_data(&data),
+            nonce,
+            user_public_key,
+            &contract,
+            &OutputLimits::default(),
+            None,
+            None,
+        )
+        .unwrap_or_else(|_| panic!("the output should be encrypted"));
+
+        // The data is base64 encoded only once
+        assert!(encrypted.len() < data.len() / 3 * 4 + 1024);
+
+        let ciphertext = match serde_json::from_slice(&encrypted).unwrap() {
+            WasmOutput::OkObject { ok } => ok.data.unwrap(),
+            _ => panic!("handle output should stay an object"),
+        };
+        let key = calc_encryption_key(&nonce, &user_public_key).unwrap();
+        assert_eq!(decrypt_data(&key, ciphertext.as_slice()), data);
+    }
+
+    pub fn test_decrypt_old_output_data() {
+        let key = calc_encryption_key(&[4u8; 32], &[7u8; 32]).unwrap();
+        let data: Vec<u8> = (0..1024 * 1024).map(|i| (i % 251) as u8).collect();
+
+        // Outputs encrypted before the data was versioned have its base64 encoding as plaintext
+        let old_ciphertext =
+            base64::decode(&encrypt_serializable(&key, &Binary(data.clone())).unwrap()).unwrap();
+        assert_eq!(decrypt_data(&key, &old_ciphertext), data);
+
+        let ciphertext = encrypt_data(&key, &data).unwrap();
+        assert_eq!(decrypt_data(&key, ciphertext.as_slice()), data);
+        assert!(ciphertext.len() < old_ciphertext.len());
+    }
+
+    pub fn test_encrypt_output_too_large() {
+        let contract = CanonicalAddr(Binary(vec![1u8; 20]));
+        let data = vec![7u8; MAX_RESPONSE_SIZE];
+
+        // Without a limit on the output, the encrypted response is still capped
+        let unlimited = OutputLimits {
+            max_bytes: u64::MAX,
+            ..OutputLimits::default()
+        };
+        let result = encrypt_output(
+            handle_output_with_data(&data),
+            [4u8; 32],
+            [7u8; 32],
+            &contract,
+            &unlimited,
+            None,
+            None,
+        );
+        assert!(matches!(
+            result,
+            Err(CallError::Enclave(EnclaveError::ResponseTooLarge))
+        ));
+
+        // The default limit stops it before it's encrypted
+        let result = encrypt_output(
+            handle_output_with_data(&data),
+            [4u8; 32],
+            [7u8; 32],
+            &contract,
+            &OutputLimits::default(),
+            None,
+            None,
+        );
+        assert!(matches!(
+            result,
+            Err(CallError::Enclave(EnclaveError::ResultTooLarge { .. }))
+        ));
+    }
+
+    /// A handle output with `messages` bank messages and `submessages` submessages
+    fn handle_output_with_messages(messages: usize, submessages: usize) -> Vec<u8> {
+        let bank_msg =
+            json!({"bank": {"send": {"from_address": "a", "to_address": "b", "amount": []}}});
+        let submessages: Vec<_> = (0..submessages)
+            .map(|id| json!({"id": id, "msg": bank_msg, "gas_limit": null}))
+            .collect();
+        serde_json::to_vec(&json!({
+            "Ok": {
+                "messages": vec![bank_msg.clone(); messages],
+                "submessages": submessages,
+                "log": [],
+                "data": null
+            }
+        }))
+        .unwrap()
+    }
+
+    fn encrypt_with_limits(output: Vec<u8>, limits: &OutputLimits) -> Result<Vec<u8>, CallError> {
+        let contract = CanonicalAddr(Binary(vec![1u8; 20]));
+        encrypt_output(output, [4u8; 32], [7u8; 32], &contract, limits, None, None)
+    }
+
+    pub fn test_output_message_count_limit() {
+        let limits = OutputLimits {
+            max_messages: 3,
+            ..OutputLimits::default()
+        };
+
+        // Messages and submessages count together
+        assert!(encrypt_with_limits(handle_output_with_messages(3, 0), &limits).is_ok());
+        assert!(encrypt_with_limits(handle_output_with_messages(2, 1), &limits).is_ok());
+
+        let output = handle_output_with_messages(2, 2);
+        let bytes = output.len() as u64;
+        match encrypt_with_limits(output, &limits) {
+            Err(CallError::Enclave(EnclaveError::ResultTooLarge {
+                messages,
+                max_messages,
+                bytes: reported_bytes,
+                max_bytes,
+            })) => {
+                assert_eq!((messages, max_messages), (4, 3));
+                assert_eq!((reported_bytes, max_bytes), (bytes, MAX_RESULT_SIZE));
+            }
+            _ => panic!("4 messages should be over the limit of 3"),
+        }
+
+        // The limit also applies to outputs that aren't encrypted
+        assert!(check_public_output(handle_output_with_messages(3, 0), &limits).is_ok());
+        assert!(matches!(
+            check_public_output(handle_output_with_messages(4, 0), &limits),
+            Err(CallError::Enclave(EnclaveError::ResultTooLarge { .. }))
+        ));
+    }
+
+    pub fn test_output_size_limit() {
+        let output = handle_output_with_data(&[7u8; 1000]);
+        let bytes = output.len() as u64;
+
+        let limits = OutputLimits {
+            max_bytes: bytes,
+            ..OutputLimits::default()
+        };
+        assert!(encrypt_with_limits(output.clone(), &limits).is_ok());
+
+        let limits = OutputLimits {
+            max_bytes: bytes - 1,
+            ..OutputLimits::default()
+        };
+        match encrypt_with_limits(output, &limits) {
+            Err(CallError::Enclave(err @ EnclaveError::ResultTooLarge { .. })) => {
+                // Contract developers see both limits in the error
+                assert_eq!(
+                    err.to_string(),
+                    format!(
+                        "the contract returned 0 messages in {} bytes, the maximum is {} messages in {} bytes",
+                        bytes,
+                        MAX_RESULT_MESSAGES,
+                        bytes - 1
+                    )
+                );
+            }
+            _ => panic!("the output should be one byte over the limit"),
+        }
+    }
+
+    pub fn test_encrypt_output_rejects_malformed_response() {
+        // serde_json alone would take the second data, other parsers the first one
+        let output = br#"{"Ok":{"messages":[],"log":[],"data":"AQ==","data":"Ag=="}}"#;
+        assert!(matches!(
+            encrypt_with_limits(output.to_vec(), &OutputLimits::default()),
+            Err(CallError::Enclave(EnclaveError::MalformedContractResponse))
+        ));
+        assert!(matches!(
+            check_public_output(output.to_vec(), &OutputLimits::default()),
+            Err(CallError::Enclave(EnclaveError::MalformedContractResponse))
+        ));
+    }
+
+    pub fn test_output_limits_from_env() {
+        let env: crate::cosmwasm::types::Env = serde_json::from_str(
+            r#"{
+                "block": {"height": 1, "time": 1, "chain_id": "secret-testnet"},
+                "message": {"sender": "sender", "sent_funds": []},
+                "contract": {"address": "contract"},
+                "contract_key": null,
+                "output_limits": {"max_messages": 16}
+            }"#,
+        )
+        .unwrap();
+
+        // Limits the chain didn't set keep their defaults
+        assert_eq!(
+            env.output_limits,
+            Some(OutputLimits {
+                max_messages: 16,
+                max_bytes: MAX_RESULT_SIZE,
+            })
+        );
+    }
 }
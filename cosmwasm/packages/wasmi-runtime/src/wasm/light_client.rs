@@ -0,0 +1,622 @@
+//! Verification of the headers the chain submits to the enclave for every block, with the commit
+//! of the validators that signed them.
+//!
+//! A submitted header is only accepted if validators with more than two thirds of the voting
+//! power signed it, and the enclave tracks the validator set from one header to the next through
+//! `next_validators_hash`. The first header the enclave sees after it starts is trusted as it is,
+//! with whatever validator set it names, so the state is only as good as that first header and
+//! it's different on every node.
+//!
+//! Nothing that executions or registrations decide depends on this state: the header in the env
+//! is checked on its own, see `verify_block_header`, and registrations use the time of the block.
+
+use std::sync::SgxMutex;
+
+use lazy_static::lazy_static;
+use log::*;
+
+use enclave_ffi_types::EnclaveError;
+
+use crate::cosmwasm::types::{
+    BlockHeaderSubmission, BlockId, Commit, CommitSig, HeaderTime, Validator,
+};
+use crate::crypto::{ed25519_batch_verify, sha_256, PUBLIC_KEY_SIZE};
+
+use super::block_header::{
+    encode_bytes, encode_bytes_field, encode_varint_field, header_hash, merkle_root, BlockHash,
+};
+
+/// Tendermint's `BlockIDFlagCommit`, for validators that voted for the block
+const BLOCK_ID_FLAG_COMMIT: u8 = 2;
+/// Tendermint's `PrecommitType`
+const PRECOMMIT_TYPE: u64 = 2;
+/// Validator addresses are the first bytes of the hash of their public key
+const VALIDATOR_ADDRESS_SIZE: usize = 20;
+/// The prefix amino encodes `PubKeyEd25519` with
+const ED25519_PUB_KEY_AMINO_PREFIX: [u8; 4] = [0x16, 0x24, 0xde, 0x64];
+
+/// The last header the enclave verified
+#[derive(Debug, Clone, PartialEq)]
+struct TrustedBlock {
+    height: u64,
+    hash: BlockHash,
+    app_hash: Vec<u8>,
+    next_validators_hash: Vec<u8>,
+}
+
+lazy_static! {
+    static ref TRUSTED_BLOCK: SgxMutex<Option<TrustedBlock>> = SgxMutex::new(None);
+}
+
+/// Verify the header and commit of the next block, and keep it as the last verified header.
+/// Submitting the last verified header again is allowed, and changes nothing.
+pub fn submit_block_header(submission: &BlockHeaderSubmission) -> Result<(), EnclaveError> {
+    let mut trusted = TRUSTED_BLOCK.lock().unwrap();
+    let block = verify_submission(trusted.as_ref(), submission)?;
+
+    debug!("Verified the header of block {}", block.height);
+    *trusted = Some(block);
+
+    Ok(())
+}
+
+/// The height and app hash of the last header the enclave verified. The app hash is the state of
+/// the chain after the block before it.
+#[allow(dead_code)] // Not read, it isn't trusted enough to decide anything
+pub fn trusted_app_hash() -> Option<(u64, Vec<u8>)> {
+    let trusted = TRUSTED_BLOCK.lock().unwrap();
+    trusted
+        .as_ref()
+        .map(|block| (block.height, block.app_hash.clone()))
+}
+
+fn verify_submission(
+    trusted: Option<&TrustedBlock>,
+    submission: &BlockHeaderSubmission,
+) -> Result<TrustedBlock, EnclaveError> {
+    let header = &submission.header;
+
+    // Tendermint doesn't hash headers without validators
+    if header.validators_hash.is_empty() {
+        warn!("got a header without a validators hash");
+        return Err(EnclaveError::ValidationFailure);
+    }
+
+    let hash = header_hash(header);
+
+    if let Some(trusted) = trusted {
+        if header.height == trusted.height && hash == trusted.hash {
+            return Ok(trusted.clone());
+        }
+        if header.height != trusted.height.wrapping_add(1) {
+            warn!(
+                "got a header at height {}, after the header at height {}",
+                header.height, trusted.height
+            );
+            return Err(EnclaveError::ValidationFailure);
+        }
+        if header.last_block_id.hash.as_slice() != trusted.hash {
+            warn!(
+                "got a header at height {} that doesn't link to the trusted header",
+                header.height
+            );
+            return Err(EnclaveError::ValidationFailure);
+        }
+        if header.validators_hash.as_slice() != trusted.next_validators_hash.as_slice() {
+            warn!(
+                "got a header at height {} with validators the trusted header didn't choose",
+                header.height
+            );
+            return Err(EnclaveError::ValidationFailure);
+        }
+    }
+
+    verify_validators(&submission.validators)?;
+    if validators_hash(&submission.validators)[..] != *header.validators_hash.as_slice() {
+        warn!(
+            "got validators for the header at height {} that don't match its validators hash",
+            header.height
+        );
+        return Err(EnclaveError::ValidationFailure);
+    }
+
+    verify_commit(
+        &header.chain_id,
+        header.height,
+        &hash,
+        &submission.commit,
+        &submission.validators,
+    )?;
+
+    Ok(TrustedBlock {
+        height: header.height,
+        hash,
+        app_hash: header.app_hash.0.clone(),
+        next_validators_hash: header.next_validators_hash.0.clone(),
+    })
+}
+
+/// Check that every validator has an ed25519 key, positive voting power and the address of its key
+pub fn verify_validators(validators: &[Validator]) -> Result<(), EnclaveError> {
+    if validators.is_empty() {
+        warn!("got an empty validator set");
+        return Err(EnclaveError::ValidationFailure);
+    }
+
+    for validator in validators {
+        if validator.pub_key.as_slice().len() != PUBLIC_KEY_SIZE || validator.voting_power <= 0 {
+            warn!(
+                "got a malformed validator {}",
+                hex::encode(validator.address.as_slice())
+            );
+            return Err(EnclaveError::ValidationFailure);
+        }
+        if sha_256(validator.pub_key.as_slice())[..VALIDATOR_ADDRESS_SIZE]
+            != *validator.address.as_slice()
+        {
+            warn!(
+                "got a validator whose address {} isn't the address of its key",
+                hex::encode(validator.address.as_slice())
+            );
+            return Err(EnclaveError::ValidationFailure);
+        }
+    }
+
+    Ok(())
+}
+
+/// Check that validators with more than two thirds of the voting power signed the block with
+/// `block_hash`. Signatures of validators that voted nil or didn't vote aren't checked.
+pub fn verify_commit(
+    chain_id: &str,
+    height: u64,
+    block_hash: &BlockHash,
+    commit: &Commit,
+    validators: &[Validator],
+) -> Result<(), EnclaveError> {
+    if commit.height != height || commit.block_id.hash.as_slice() != block_hash {
+        warn!(
+            "got a commit of block {} for the header at height {}",
+            commit.height, height
+        );
+        return Err(EnclaveError::ValidationFailure);
+    }
+    if commit.signatures.len() != validators.len() {
+        warn!(
+            "got a commit with {} signatures for {} validators",
+            commit.signatures.len(),
+            validators.len()
+        );
+        return Err(EnclaveError::ValidationFailure);
+    }
+
+    let mut messages = vec![];
+    let mut signatures = vec![];
+    let mut public_keys = vec![];
+    let mut signed_power: u128 = 0;
+    let mut total_power: u128 = 0;
+
+    for (commit_sig, validator) in commit.signatures.iter().zip(validators) {
+        total_power += validator.voting_power as u128;
+
+        if commit_sig.block_id_flag != BLOCK_ID_FLAG_COMMIT {
+            continue;
+        }
+        if commit_sig.validator_address != validator.address {
+            warn!(
+                "got a signature of {} in the place of validator {}",
+                hex::encode(commit_sig.validator_address.as_slice()),
+                hex::encode(validator.address.as_slice())
+            );
+            return Err(EnclaveError::ValidationFailure);
+        }
+
+        messages.push(vote_sign_bytes(chain_id, commit, commit_sig));
+        signatures.push(commit_sig.signature.as_slice());
+        public_keys.push(validator.pub_key.as_slice());
+        signed_power += validator.voting_power as u128;
+    }
+
+    if signed_power * 3 <= total_power * 2 {
+        warn!(
+            "got a commit of block {} signed by {} of {} voting power",
+            height, signed_power, total_power
+        );
+        return Err(EnclaveError::ValidationFailure);
+    }
+
+    let messages: Vec<&[u8]> = messages.iter().map(Vec::as_slice).collect();
+    ed25519_batch_verify(&messages, &signatures, &public_keys).map_err(|err| {
+        warn!(
+            "got a commit of block {} with a bad signature: {}",
+            height, err
+        );
+        EnclaveError::ValidationFailure
+    })
+}
+
+/// Check that validators of `trusted_validators` with more than a third of their voting power
+/// signed the block with `block_hash`, the same as Tendermint's `VerifyCommitLightTrusting`.
+/// Signatures of validators that aren't trusted are skipped, so the commit may be of another
+/// validator set.
+pub fn verify_commit_trusting(
+    chain_id: &str,
+    height: u64,
+    block_hash: &BlockHash,
+    commit: &Commit,
+    trusted_validators: &[Validator],
+) -> Result<(), EnclaveError> {
+    if commit.height != height || commit.block_id.hash.as_slice() != block_hash {
+        warn!(
+            "got a commit of block {} for the header at height {}",
+            commit.height, height
+        );
+        return Err(EnclaveError::ValidationFailure);
+    }
+
+    let mut messages = vec![];
+    let mut signatures = vec![];
+    let mut public_keys = vec![];
+    let mut counted = vec![false; trusted_validators.len()];
+    let mut signed_power: u128 = 0;
+    let total_power: u128 = trusted_validators
+        .iter()
+        .map(|validator| validator.voting_power as u128)
+        .sum();
+
+    for commit_sig in &commit.signatures {
+        if commit_sig.block_id_flag != BLOCK_ID_FLAG_COMMIT {
+            continue;
+        }
+        let index = match trusted_validators
+            .iter()
+            .position(|validator| validator.address == commit_sig.validator_address)
+        {
+            Some(index) => index,
+            None => continue,
+        };
+        if counted[index] {
+            warn!(
+                "got a commit with two signatures of validator {}",
+                hex::encode(commit_sig.validator_address.as_slice())
+            );
+            return Err(EnclaveError::ValidationFailure);
+        }
+        counted[index] = true;
+
+        messages.push(vote_sign_bytes(chain_id, commit, commit_sig));
+        signatures.push(commit_sig.signature.as_slice());
+        public_keys.push(trusted_validators[index].pub_key.as_slice());
+        signed_power += trusted_validators[index].voting_power as u128;
+    }
+
+    if signed_power * 3 <= total_power {
+        warn!(
+            "got a commit of block {} signed by {} of {} trusted voting power",
+            height, signed_power, total_power
+        );
+        return Err(EnclaveError::ValidationFailure);
+    }
+
+    let messages: Vec<&[u8]> = messages.iter().map(Vec::as_slice).collect();
+    ed25519_batch_verify(&messages, &signatures, &public_keys).map_err(|err| {
+        warn!(
+            "got a commit of block {} with a bad signature: {}",
+            height, err
+        );
+        EnclaveError::ValidationFailure
+    })
+}
+
+/// The hash of a validator set, the same as Tendermint's `ValidatorSet.Hash`: the root of a
+/// merkle tree over the amino encoding of the public key and voting power of each validator.
+pub fn validators_hash(validators: &[Validator]) -> BlockHash {
+    let leaves: Vec<Vec<u8>> = validators
+        .iter()
+        .map(|validator| {
+            let mut pub_key = ED25519_PUB_KEY_AMINO_PREFIX.to_vec();
+            pub_key.extend_from_slice(&encode_bytes(validator.pub_key.as_slice()));
+
+            let mut simple_validator = vec![];
+            encode_message_field(1, &pub_key, &mut simple_validator);
+            encode_varint_field(2, validator.voting_power as u64, &mut simple_validator);
+            simple_validator
+        })
+        .collect();
+
+    merkle_root(&leaves)
+}
+
+/// What a validator signed when it voted for the block of the commit, the same as Tendermint's
+/// `VoteSignBytes`: the length-prefixed amino encoding of a `CanonicalVote`.
+fn vote_sign_bytes(chain_id: &str, commit: &Commit, commit_sig: &CommitSig) -> Vec<u8> {
+    let mut vote = vec![];
+    encode_varint_field(1, PRECOMMIT_TYPE, &mut vote);
+    encode_sfixed64_field(2, commit.height as i64, &mut vote);
+    encode_sfixed64_field(3, commit.round as i64, &mut vote);
+    encode_message_field(4, &canonical_block_id(&commit.block_id), &mut vote);
+    encode_message_field(5, &timestamp(&commit_sig.timestamp), &mut vote);
+    encode_bytes_field(6, chain_id.as_bytes(), &mut vote);
+
+    encode_bytes(&vote)
+}
+
+fn canonical_block_id(block_id: &BlockId) -> Vec<u8> {
+    let mut parts = vec![];
+    encode_bytes_field(1, block_id.parts.hash.as_slice(), &mut parts);
+    encode_varint_field(2, block_id.parts.total as u64, &mut parts);
+
+    let mut canonical = vec![];
+    encode_bytes_field(1, block_id.hash.as_slice(), &mut canonical);
+    encode_message_field(2, &parts, &mut canonical);
+    canonical
+}
+
+fn timestamp(time: &HeaderTime) -> Vec<u8> {
+    let mut timestamp = vec![];
+    encode_varint_field(1, time.seconds as u64, &mut timestamp);
+    encode_varint_field(2, time.nanos as i64 as u64, &mut timestamp);
+    timestamp
+}
+
+/// Structs are encoded even when they're empty
+fn encode_message_field(field_number: u8, message: &[u8], out: &mut Vec<u8>) {
+    out.push(field_number << 3 | 2);
+    out.extend_from_slice(&encode_bytes(message));
+}
+
+fn encode_sfixed64_field(field_number: u8, value: i64, out: &mut Vec<u8>) {
+    if value != 0 {
+        out.push(field_number << 3 | 1);
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use ring::signature::{Ed25519KeyPair, KeyPair as _};
+
+    use crate::cosmwasm::encoding::Binary;
+    use crate::cosmwasm::types::{BlockHeader, HeaderVersion, PartSetHeader};
+
+    const CHAIN_ID: &str = "secret-testnet";
+
+    pub fn key_pairs() -> Vec<Ed25519KeyPair> {
+        (1..=4)
+            .map(|i| Ed25519KeyPair::from_seed_unchecked(&[i; 32]).unwrap())
+            .collect()
+    }
+
+    pub fn validators(key_pairs: &[Ed25519KeyPair]) -> Vec<Validator> {
+        key_pairs
+            .iter()
+            .map(|key_pair| {
+                let pub_key = key_pair.public_key().as_ref().to_vec();
+                Validator {
+                    address: Binary(sha_256(&pub_key)[..VALIDATOR_ADDRESS_SIZE].to_vec()),
+                    pub_key: Binary(pub_key),
+                    voting_power: 10,
+                }
+            })
+            .collect()
+    }
+
+    pub fn header(height: u64, last_block_hash: &[u8], validators: &[Validator]) -> BlockHeader {
+        let validators_hash = Binary(validators_hash(validators).to_vec());
+        BlockHeader {
+            version: HeaderVersion { block: 10, app: 0 },
+            chain_id: CHAIN_ID.to_string(),
+            height,
+            time: HeaderTime {
+                seconds: 1_600_000_000 + height as i64 * 6,
+                nanos: 0,
+            },
+            last_block_id: BlockId {
+                hash: Binary(last_block_hash.to_vec()),
+                parts: PartSetHeader {
+                    total: 1,
+                    hash: Binary(vec![0x22; 32]),
+                },
+            },
+            last_commit_hash: Binary(vec![0x33; 32]),
+            data_hash: Binary(vec![]),
+            validators_hash: validators_hash.clone(),
+            next_validators_hash: validators_hash,
+            consensus_hash: Binary(vec![0x55; 32]),
+            app_hash: Binary(vec![height as u8; 32]),
+            last_results_hash: Binary(vec![]),
+            evidence_hash: Binary(vec![]),
+            proposer_address: Binary(vec![0x77; 20]),
+        }
+    }
+
+    /// A submission of the header, signed by the first `signers` of the validators
+    pub fn submission(
+        header: BlockHeader,
+        key_pairs: &[Ed25519KeyPair],
+        signers: usize,
+    ) -> BlockHeaderSubmission {
+        let validators = validators(key_pairs);
+        let mut commit = Commit {
+            height: header.height,
+            round: 1,
+            block_id: BlockId {
+                hash: Binary(header_hash(&header).to_vec()),
+                parts: PartSetHeader {
+                    total: 1,
+                    hash: Binary(vec![0x88; 32]),
+                },
+            },
+            signatures: vec![],
+        };
+
+        for (i, (key_pair, validator)) in key_pairs.iter().zip(&validators).enumerate() {
+            let mut commit_sig = CommitSig {
+                block_id_flag: 1,
+                validator_address: Binary(vec![]),
+                timestamp: HeaderTime::default(),
+                signature: Binary(vec![]),
+            };
+            if i < signers {
+                commit_sig.block_id_flag = BLOCK_ID_FLAG_COMMIT;
+                commit_sig.validator_address = validator.address.clone();
+                commit_sig.timestamp = HeaderTime {
+                    seconds: header.time.seconds + 1,
+                    nanos: 5_000 * i as i32,
+                };
+                let sign_bytes = vote_sign_bytes(&header.chain_id, &commit, &commit_sig);
+                commit_sig.signature = Binary(key_pair.sign(&sign_bytes).as_ref().to_vec());
+            }
+            commit.signatures.push(commit_sig);
+        }
+
+        BlockHeaderSubmission {
+            header,
+            commit,
+            validators,
+        }
+    }
+
+    fn rejected(result: Result<TrustedBlock, EnclaveError>) -> bool {
+        matches!(result, Err(EnclaveError::ValidationFailure))
+    }
+
+    /// A precommit for block 100, encoded field by field
+    pub fn test_vote_sign_bytes() {
+        let commit = Commit {
+            height: 100,
+            round: 1,
+            block_id: BlockId {
+                hash: Binary(vec![0x11; 32]),
+                parts: PartSetHeader {
+                    total: 1,
+                    hash: Binary(vec![0x22; 32]),
+                },
+            },
+            signatures: vec![],
+        };
+        let commit_sig = CommitSig {
+            block_id_flag: BLOCK_ID_FLAG_COMMIT,
+            validator_address: Binary(vec![0x33; 20]),
+            timestamp: HeaderTime {
+                seconds: 1_600_000_000,
+                nanos: 123_456_789,
+            },
+            signature: Binary(vec![]),
+        };
+
+        let mut expected = vec![0x08, 0x02];
+        expected.extend_from_slice(&[0x11, 100, 0, 0, 0, 0, 0, 0, 0]);
+        expected.extend_from_slice(&[0x19, 1, 0, 0, 0, 0, 0, 0, 0]);
+        expected.extend_from_slice(&[0x22, 0x48, 0x0a, 0x20]);
+        expected.extend_from_slice(&[0x11; 32]);
+        expected.extend_from_slice(&[0x12, 0x24, 0x0a, 0x20]);
+        expected.extend_from_slice(&[0x22; 32]);
+        expected.extend_from_slice(&[0x10, 0x01]);
+        expected.extend_from_slice(&[0x2a, 0x0b, 0x08, 0x80, 0xa0, 0xf8, 0xfa, 0x05]);
+        expected.extend_from_slice(&[0x10, 0x95, 0x9a, 0xef, 0x3a]);
+        expected.extend_from_slice(&[0x32, CHAIN_ID.len() as u8]);
+        expected.extend_from_slice(CHAIN_ID.as_bytes());
+        expected.insert(0, expected.len() as u8);
+
+        assert_eq!(vote_sign_bytes(CHAIN_ID, &commit, &commit_sig), expected);
+    }
+
+    pub fn test_submit_linked_headers() {
+        let key_pairs = key_pairs();
+        let validators = validators(&key_pairs);
+
+        let first = submission(header(100, &[0x11; 32], &validators), &key_pairs, 4);
+        let trusted = verify_submission(None, &first).unwrap();
+        assert_eq!(trusted.height, 100);
+        assert_eq!(trusted.hash, header_hash(&first.header));
+        assert_eq!(trusted.app_hash, vec![100; 32]);
+
+        // The same header can be submitted again
+        assert_eq!(verify_submission(Some(&trusted), &first).unwrap(), trusted);
+
+        // Three of four validators are more than two thirds of the voting power
+        let second = submission(header(101, &trusted.hash, &validators), &key_pairs, 3);
+        let trusted = verify_submission(Some(&trusted), &second).unwrap();
+        assert_eq!(trusted.height, 101);
+        assert_eq!(trusted.app_hash, vec![101; 32]);
+    }
+
+    pub fn test_submit_header_with_forged_signature() {
+        let key_pairs = key_pairs();
+        let validators = validators(&key_pairs);
+
+        let mut forged = submission(header(100, &[0x11; 32], &validators), &key_pairs, 4);
+        forged.commit.signatures[2].signature.0[0] ^= 1;
+        assert!(rejected(verify_submission(None, &forged)));
+
+        // A signature over another header
+        let honest = submission(header(100, &[0x11; 32], &validators), &key_pairs, 4);
+        let mut other_header = header(100, &[0x11; 32], &validators);
+        other_header.app_hash = Binary(vec![0x99; 32]);
+        let mut forged = submission(other_header, &key_pairs, 4);
+        forged.commit.signatures[0] = honest.commit.signatures[0].clone();
+        assert!(rejected(verify_submission(None, &forged)));
+
+        // A signature by a key that isn't in the validator set
+        let outsider = Ed25519KeyPair::from_seed_unchecked(&[9; 32]).unwrap();
+        let mut forged = submission(header(100, &[0x11; 32], &validators), &key_pairs, 4);
+        let sign_bytes = vote_sign_bytes(CHAIN_ID, &forged.commit, &forged.commit.signatures[1]);
+        forged.commit.signatures[1].signature =
+            Binary(outsider.sign(&sign_bytes).as_ref().to_vec());
+        assert!(rejected(verify_submission(None, &forged)));
+    }
+
+    pub fn test_submit_header_without_quorum() {
+        let key_pairs = key_pairs();
+        let validators = validators(&key_pairs);
+
+        // Two of four validators are not more than two thirds of the voting power
+        let unsigned = submission(header(100, &[0x11; 32], &validators), &key_pairs, 2);
+        assert!(rejected(verify_submission(None, &unsigned)));
+    }
+
+    pub fn test_submit_header_of_other_validators() {
+        let key_pairs = key_pairs();
+        let validators = validators(&key_pairs);
+        let first = submission(header(100, &[0x11; 32], &validators), &key_pairs, 4);
+        let trusted = verify_submission(None, &first).unwrap();
+
+        // A fork signed by validators the trusted header didn't choose
+        let fork_key_pairs: Vec<Ed25519KeyPair> = (11..=14)
+            .map(|i| Ed25519KeyPair::from_seed_unchecked(&[i; 32]).unwrap())
+            .collect();
+        let fork_validators = self::validators(&fork_key_pairs);
+        let fork = submission(
+            header(101, &trusted.hash, &fork_validators),
+            &fork_key_pairs,
+            4,
+        );
+        assert!(rejected(verify_submission(Some(&trusted), &fork)));
+
+        // Validators that don't match the validators hash of the header
+        let mut mismatched = submission(header(101, &trusted.hash, &validators), &key_pairs, 4);
+        mismatched.validators[0].voting_power = 100;
+        assert!(rejected(verify_submission(Some(&trusted), &mismatched)));
+    }
+
+    pub fn test_submit_unlinked_header() {
+        let key_pairs = key_pairs();
+        let validators = validators(&key_pairs);
+        let first = submission(header(100, &[0x11; 32], &validators), &key_pairs, 4);
+        let trusted = verify_submission(None, &first).unwrap();
+
+        let unlinked = submission(header(101, &[0x12; 32], &validators), &key_pairs, 4);
+        assert!(rejected(verify_submission(Some(&trusted), &unlinked)));
+
+        let skipped = submission(header(102, &trusted.hash, &validators), &key_pairs, 4);
+        assert!(rejected(verify_submission(Some(&trusted), &skipped)));
+
+        let mut conflicting = header(100, &[0x11; 32], &validators);
+        conflicting.app_hash = Binary(vec![0x99; 32]);
+        let conflicting = submission(conflicting, &key_pairs, 4);
+        assert!(rejected(verify_submission(Some(&trusted), &conflicting)));
+    }
+}
@@ -1,9 +1,14 @@
-use parity_wasm::elements::{MemoryType, Module};
+use parity_wasm::builder;
+use parity_wasm::elements::{
+    ImportCountType, Instruction, Internal, MemoryType, Module, Section, Signature, ValueType,
+};
 
 use log::*;
 
 use enclave_ffi_types::EnclaveError;
 
+use crate::consts::{LEGACY_WASM_MEMORY_PAGES, MAX_WASM_MEMORY_PAGES};
+
 pub fn validate_memory(p_modlue: &mut Module) -> Result<(), EnclaveError> {
     let memory_section = p_modlue
         .memory_section_mut()
@@ -36,12 +41,18 @@ pub fn validate_memory(p_modlue: &mut Module) -> Result<(), EnclaveError> {
         })?;
 
     let requested_initial_pages: u32 = memory_entry.limits().initial();
-    let maximum_allowed_pages: u32 = 192; // 12 MiB
+    let maximum_allowed_pages: u32 = memory_entry
+        .limits()
+        .maximum()
+        .map_or(MAX_WASM_MEMORY_PAGES, |maximum| {
+            maximum.min(MAX_WASM_MEMORY_PAGES)
+        });
 
-    if requested_initial_pages > maximum_allowed_pages {
+    if requested_initial_pages > maximum_allowed_pages.min(LEGACY_WASM_MEMORY_PAGES) {
         error!(
             "WASM Requested to initialize with {} pages, maximum allowed is {}",
-            requested_initial_pages, maximum_allowed_pages
+            requested_initial_pages,
+            maximum_allowed_pages.min(LEGACY_WASM_MEMORY_PAGES)
         );
         return Err(EnclaveError::CannotInitializeWasmMemory);
     }
@@ -53,3 +64,98 @@ pub fn validate_memory(p_modlue: &mut Module) -> Result<(), EnclaveError> {
 
     Ok(())
 }
+
+/// Replace every `memory.grow` in the contract with a call to the `memory_grow` import, so the
+/// contract is charged for every attempt to grow its memory, and attempts past the limit can be
+/// told apart from the enclave running out of memory. Runs before gas metering is injected, so the
+/// metering sees the call like any other.
+pub fn hook_memory_grow(module: Module) -> Module {
+    let grows_memory = module.code_section().map_or(false, |code| {
+        code.bodies().iter().any(|body| {
+            body.code()
+                .elements()
+                .iter()
+                .any(|instruction| matches!(instruction, Instruction::GrowMemory(_)))
+        })
+    });
+    if !grows_memory {
+        return module;
+    }
+
+    let signature = builder::signature()
+        .with_param(ValueType::I32)
+        .with_return_type(Some(ValueType::I32))
+        .build_sig();
+    let (mut module, hook_func) = push_function_import(module, "memory_grow", signature);
+
+    if let Some(code) = module.code_section_mut() {
+        for body in code.bodies_mut() {
+            for instruction in body.code_mut().elements_mut() {
+                if let Instruction::GrowMemory(_) = instruction {
+                    *instruction = Instruction::Call(hook_func);
+                }
+            }
+        }
+    }
+
+    module
+}
+
+/// Add an import of a function from "env" to the module, and return the module and the index of
+/// the new function.
+///
+/// The new import comes after the functions the module already imports, so every function the
+/// module defines moves up by one, and the references to them are updated.
+pub fn push_function_import(module: Module, field: &str, signature: Signature) -> (Module, u32) {
+    let new_func = module.import_count(ImportCountType::Function) as u32;
+
+    let mut module_builder = builder::from_module(module);
+    let new_signature = module_builder.push_signature(signature);
+    module_builder.push_import(
+        builder::import()
+            .module("env")
+            .field(field)
+            .external()
+            .func(new_signature)
+            .build(),
+    );
+    let mut module = module_builder.build();
+
+    for section in module.sections_mut() {
+        match section {
+            Section::Code(code) => {
+                for body in code.bodies_mut() {
+                    for instruction in body.code_mut().elements_mut() {
+                        if let Instruction::Call(func) = instruction {
+                            if *func >= new_func {
+                                *func += 1;
+                            }
+                        }
+                    }
+                }
+            }
+            Section::Export(exports) => {
+                for export in exports.entries_mut() {
+                    if let Internal::Function(func) = export.internal_mut() {
+                        if *func >= new_func {
+                            *func += 1;
+                        }
+                    }
+                }
+            }
+            Section::Element(elements) => {
+                for segment in elements.entries_mut() {
+                    for func in segment.members_mut() {
+                        if *func >= new_func {
+                            *func += 1;
+                        }
+                    }
+                }
+            }
+            Section::Start(func) if *func >= new_func => *func += 1,
+            _ => {}
+        }
+    }
+
+    (module, new_func)
+}
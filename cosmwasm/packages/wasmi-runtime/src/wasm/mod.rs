@@ -1,15 +1,58 @@
+mod addresses;
+mod api_version;
+mod block_header;
+mod contract_env;
 mod contract_operations;
 mod contract_validation;
 mod db;
+mod deadline;
 mod errors;
+mod foreign_chains;
 mod gas;
+mod gzip;
 mod io;
+mod light_client;
 mod memory;
+#[cfg(not(feature = "debug-print"))]
+mod module_artifact;
+pub mod module_cache;
+mod nonce_registry;
+mod prewarm;
+mod query_cache;
 mod query_chain;
+mod query_permit;
+mod query_replay;
+mod read_ahead;
 mod runtime;
+mod state_archive;
+mod strict_json;
+#[cfg(feature = "debug-print")]
+mod trap_info;
 mod types;
+mod validation_session;
+mod wasm_features;
 
-pub use contract_operations::{handle, init, query};
+pub use contract_operations::{
+    handle, ibc, ibc_packet_receive, init, migrate, query, reply, rotate_contract_key,
+    update_admin, IbcEntryPoint,
+};
+pub use db::storage_round_trip;
+pub use foreign_chains::{submit_foreign_header, track_foreign_chain};
+pub use gas::{
+    clear_metered_gas, failed_ecall_gas, query_gas_limit, set_default_query_gas_limit,
+    OcallGasCosts,
+};
+pub use io::{verify_input, OutputLimits};
+pub use light_client::submit_block_header;
+pub use prewarm::{prewarm_contracts, PrewarmBudget};
+pub use query_cache::set_query_cache_size;
+pub use state_archive::{export_contract_state, import_contract_state};
+#[cfg(feature = "debug-print")]
+pub use trap_info::{clear_trap_debug_info, take_trap_debug_info};
+pub use validation_session::{
+    max_contract_size, set_max_contract_size, validate_wasm_begin, validate_wasm_chunk,
+    validate_wasm_end,
+};
 
 #[cfg(feature = "test")]
 pub mod tests {
@@ -22,9 +65,270 @@ pub mod tests {
 
         count_failures!(failures, {
             types::tests::test_new_from_slice();
+            types::tests::test_decrypt_message_too_short();
+            types::tests::test_decrypt_message_with_wrong_key();
+            types::tests::test_message_is_bound_to_its_nonce_and_public_key();
+            types::tests::test_decrypt_corrupted_message();
+            io::tests::test_message_randomness_agrees_across_nodes();
+            io::tests::test_message_randomness_differs_between_messages();
+            io::tests::test_encrypt_logs_keeps_plaintext_attributes();
+            io::tests::test_encrypt_init_output_logs();
+            io::tests::test_log_attribute_round_trips_to_its_recipient();
+            io::tests::test_log_attribute_to_recipient_without_key_fails_the_call();
+            io::tests::test_log_attributes_are_ordered_across_submessages();
+            io::tests::test_encrypt_output_data_round_trip();
+            io::tests::test_decrypt_old_output_data();
+            io::tests::test_encrypt_output_too_large();
+            io::tests::test_encrypt_ack_is_bound_to_the_packet();
+            io::tests::test_check_public_output();
+            io::tests::test_swapped_query_response_is_rejected();
+            io::tests::test_decrypt_query_detects_the_sender();
+            io::tests::test_verify_input_decrypts();
+            io::tests::test_verify_input_truncated();
+            io::tests::test_verify_input_wrong_io_key();
+            io::tests::test_output_message_count_limit();
+            io::tests::test_output_size_limit();
+            io::tests::test_output_limits_from_env();
+            io::tests::test_encrypt_output_rejects_malformed_response();
+            state_archive::tests::test_archive_entries_verify();
+            state_archive::tests::test_tampered_archive_entries_are_refused();
+            state_archive::tests::test_mixed_archive_entries_are_refused();
+            state_archive::tests::test_snapshot_nonce_is_deterministic();
+            state_archive::tests::test_archive_chunk_round_trips_as_json();
+            strict_json::tests::test_strict_json_accepts_responses();
+            strict_json::tests::test_strict_json_rejects_duplicate_keys();
+            strict_json::tests::test_strict_json_rejects_deep_nesting();
+            strict_json::tests::test_strict_json_rejects_invalid_utf8();
+            strict_json::tests::test_strict_json_rejects_trailing_data();
+            strict_json::tests::test_strict_json_size_cap();
+            db::tests::test_keys_in_range_sorts_and_deduplicates();
+            db::tests::test_keys_in_range_bounds();
+            db::tests::test_key_index_prefix_end();
+            db::tests::test_storage_overlay_reads_its_writes();
+            db::tests::test_storage_overlay_charges_like_the_host();
+            db::tests::test_read_raw_multi_from_overlay();
+            db::tests::test_storage_overlay_merge_range();
+            db::tests::test_storage_overlay_keeps_final_changes();
+            db::tests::test_prefetched_values_never_hide_changes();
+            db::tests::test_storage_usage_fills_to_quota();
+            db::tests::test_storage_usage_above_lowered_quota();
+            db::tests::test_storage_usage_changes_are_tracked();
+            db::tests::test_storage_usage_encoding();
+            db::tests::test_revoked_query_permit_digests_differ();
+            db::tests::test_split_rotation_chunk();
+            db::tests::test_state_readable_after_seed_rotation();
+            db::tests::test_state_without_key_generation_is_readable();
+            db::tests::test_state_without_binding_is_readable();
+            db::tests::test_swapped_state_values_are_refused();
+            db::tests::test_namespaces_are_isolated();
+            read_ahead::tests::test_read_ahead_follows_a_walk();
+            read_ahead::tests::test_read_ahead_follows_steps_and_directions();
+            read_ahead::tests::test_read_ahead_follows_interleaved_walks();
+            read_ahead::tests::test_read_ahead_relearns_a_changed_step();
+            read_ahead::tests::test_read_ahead_is_bounded();
+            gas::tests::test_ed25519_batch_verify_is_not_cheaper_than_single_calls();
+            gas::tests::test_address_conversion_costs_per_api_version();
+            gas::tests::test_storage_refund_write_then_remove_in_one_message();
+            gas::tests::test_storage_refund_remove_across_messages();
+            gas::tests::test_storage_refund_is_capped();
+            gas::tests::test_gas_is_charged_once_per_block();
+            gas::tests::test_query_gas_limit_default();
+            runtime::sections::tests::test_decode_sections();
+            runtime::sections::tests::test_decode_sections_malformed();
+            runtime::sections::tests::test_encode_sections_round_trip();
+            runtime::sections::tests::test_optional_sections();
+            runtime::contract::tests::test_gas_breakdown_adds_up();
+            runtime::contract::tests::test_ocall_gas_counts_towards_the_limit();
+            runtime::contract::tests::test_panic_in_host_function_is_reported();
+            runtime::contract::tests::test_abort_is_reported_as_a_panic();
+            runtime::contract::tests::test_out_of_gas_mid_loop_gas();
+            runtime::contract::tests::test_out_of_block_gas();
+            runtime::contract::tests::test_out_of_gas_below_block_gas();
+            runtime::contract::tests::test_unreachable_gas();
+            runtime::contract::tests::test_infinite_loop_query_runs_out_of_gas();
+            runtime::contract::tests::test_execution_deadline_stops_spin_loop();
+            runtime::contract::tests::test_execution_deadline_never_adds_gas();
+            runtime::contract::tests::test_execution_timeout_only_applies_to_queries();
+            runtime::contract::tests::test_query_just_under_the_gas_limit();
+            runtime::contract::tests::test_panic_before_metering_gas();
+            runtime::contract::tests::test_gas_limit_sweep_never_panics();
+            runtime::contract::tests::test_zero_gas_limit_charges_nothing();
+            runtime::contract::tests::test_memory_grow_stops_at_the_limit();
+            runtime::contract::tests::test_memory_grow_stops_at_the_raised_limit();
+            runtime::contract::tests::test_memory_grow_runs_out_of_gas();
+            runtime::contract::tests::test_memory_limit_exceeded_error();
+            runtime::contract::tests::test_initial_memory_is_limited();
+            runtime::contract::tests::test_hash_known_vectors();
+            runtime::contract::tests::test_hash_empty_input();
+            runtime::contract::tests::test_hash_charges_per_byte();
+            runtime::contract::tests::test_hash_output_too_small();
+            runtime::contract::tests::test_hash_invalid_regions();
+            runtime::contract::tests::test_query_chain_answer_is_charged();
+            runtime::contract::tests::test_ed25519_batch_verify_is_charged_per_signature();
+            runtime::contract::tests::test_debug_is_charged_per_byte();
+            #[cfg(not(feature = "production"))]
+            runtime::contract::tests::test_debug_message_passes_through();
+            #[cfg(feature = "production")]
+            runtime::contract::tests::test_debug_message_is_redacted();
+            runtime::contract::tests::test_hkdf_sha256_rfc5869_vectors();
+            runtime::contract::tests::test_hkdf_sha256_output_length();
+            runtime::contract::tests::test_hkdf_sha256_charges_per_byte();
+            runtime::contract::tests::test_hkdf_sha256_invalid_regions();
+            runtime::contract::tests::test_secp256k1_sign_recovers_to_public_key();
+            runtime::contract::tests::test_secp256k1_signing_keys_are_per_contract();
+            runtime::contract::tests::test_secp256k1_generate_key_needs_an_authenticated_contract();
+            runtime::contract::tests::test_secp256k1_sign_errors();
+            runtime::contract::tests::test_secp256k1_generate_key_path_too_long();
+            runtime::contract::tests::test_gas_left_stops_batch_early();
+            runtime::contract::tests::test_gas_left_batch_finishes();
+            runtime::contract::tests::test_gas_left_counts_all_gas();
+            runtime::contract::tests::test_gas_limit_export();
+            contract_env::tests::test_v0_10_env_is_unchanged();
+            contract_env::tests::test_v1_env_shape();
+            contract_env::tests::test_timestamp_round_trip();
+            contract_env::tests::test_v0_10_contract_reads_its_env();
+            contract_env::tests::test_v1_contract_reads_its_env();
+            gas::tests::test_ocall_gas_costs_from_env();
+            query_chain::tests::test_unsupported_query_kind();
+            query_chain::tests::test_unsupported_query_is_visible_to_the_contract();
+            query_chain::tests::test_module_response_is_passed_as_is();
+            query_chain::tests::test_module_response_of_the_wrong_type();
+            query_chain::tests::test_module_error_is_passed_as_is();
+            module_cache::tests::test_lru_cache_evicts_least_recently_used();
+            module_cache::tests::test_lru_cache_skips_oversized_entries();
+            module_cache::tests::test_module_cache_different_hashes_dont_collide();
+            prewarm::tests::test_prewarm_skips_bad_entries();
+            prewarm::tests::test_prewarm_within_budget();
+            contract_validation::tests::test_verify_params_single_signer();
+            contract_validation::tests::test_verify_params_sender_is_additional_signer();
+            contract_validation::tests::test_verify_params_amino_multisig_sender();
+            contract_validation::tests::test_verify_params_multi_send_payload();
+            contract_validation::tests::test_verify_params_invalid_additional_signature();
+            contract_validation::tests::test_verify_params_sender_not_a_signer();
+            contract_validation::tests::test_verify_params_sent_funds();
+            contract_validation::tests::test_verify_params_sent_funds_mismatched_denom();
+            contract_validation::tests::test_verify_params_sent_funds_mismatched_amount();
+            contract_validation::tests::test_verify_params_sent_funds_injected_coin();
+            contract_validation::tests::test_verify_params_memo();
+            contract_validation::tests::test_verify_params_forged_memo();
+            contract_validation::tests::test_verify_params_memo_too_long();
+            contract_validation::tests::test_verify_params_sign_mode_direct();
+            contract_validation::tests::test_parse_direct_sign_doc();
+            contract_validation::tests::test_verify_params_sign_mode_direct_not_a_signer();
+            contract_validation::tests::test_verify_params_sign_mode_direct_forged_memo();
+            contract_validation::tests::test_verify_params_sign_mode_mismatch();
+            contract_validation::tests::test_fee_payer_without_granter_is_first_signer();
+            contract_validation::tests::test_fee_payer_with_granter_is_granter();
+            contract_validation::tests::test_fee_payer_named_in_fee();
+            contract_validation::tests::test_fee_payer_has_to_sign();
+            contract_validation::tests::test_fee_payer_amino_is_sender_of_first_msg();
+            contract_validation::tests::test_fee_payer_amino_single_signer();
+            contract_validation::tests::test_fee_payer_amino_is_signer_of_first_msg();
+            contract_validation::tests::test_fee_of_any_shape_verifies();
+            contract_validation::tests::test_fee_payer_not_taken_from_host();
+            contract_validation::tests::test_verify_key_rotation_params();
+            contract_validation::tests::test_verify_key_rotation_params_other_contract();
+            contract_validation::tests::test_verify_key_rotation_params_not_signed();
+            contract_validation::tests::test_verify_key_rotation_params_from_contract();
+            contract_validation::tests::test_verify_contract_code_hash();
+            contract_validation::tests::test_derive_contract_address();
+            contract_validation::tests::test_verify_contract_address();
+            contract_validation::tests::test_verify_params_instantiate_code_id();
+            contract_validation::tests::test_verify_params_instantiate_with_admin();
+            contract_validation::tests::test_verify_params_instantiate_without_admin();
+            contract_validation::tests::test_verify_contract_admin_authenticated();
+            contract_validation::tests::test_verify_contract_admin_host_lies_about_admin();
+            contract_validation::tests::test_verify_admin_update_params();
+            contract_validation::tests::test_verify_state_import_params();
+            contract_validation::tests::test_validate_msg_for_mismatched_code();
+            contract_validation::tests::test_verify_ibc_port();
+            contract_validation::tests::test_validate_contract_key_for_hash();
+            contract_validation::tests::test_contract_key_matches_code_hash();
+            contract_validation::tests::test_verify_reply();
+            contract_validation::tests::test_verify_reply_to();
+            contract_validation::tests::test_verify_callback_sig();
+            contract_validation::tests::test_verify_params_from_contract_with_funds();
+            validation_session::tests::test_chunk_out_of_order();
+            validation_session::tests::test_duplicate_session_id();
+            validation_session::tests::test_code_too_large();
+            validation_session::tests::test_expired_session();
+            validation_session::tests::test_invalid_wasm();
+            gzip::tests::test_crc32();
+            gzip::tests::test_gzipped_contract();
+            gzip::tests::test_gzip_bomb();
+            gzip::tests::test_gzip_max_code_size();
+            gzip::tests::test_malformed_gzip();
+            wasm_features::tests::test_fixtures_are_rejected_for_their_feature();
+            wasm_features::tests::test_valid_module_is_accepted();
+            wasm_features::tests::test_unreadable_module_is_left_to_the_parser();
+            wasm_features::tests::test_each_check_matches_only_its_feature();
+            wasm_features::tests::test_unreachable_offsets();
+            api_version::tests::test_detect_api_version();
+            api_version::tests::test_reject_unsupported_api_version();
+            api_version::tests::test_reject_imports_of_another_version();
+            api_version::tests::test_both_versions_run_side_by_side();
+            contract_operations::tests::test_start_function_is_rejected_when_validated();
+            contract_operations::tests::test_segments_that_dont_fit_fail_instantiation();
+            contract_operations::tests::test_segments_are_charged_before_instantiation();
+            contract_operations::tests::test_rotation_gas_reports_only_the_enclave_gas();
+            addresses::tests::test_addr_round_trip();
+            addresses::tests::test_addr_invalid_checksum();
+            addresses::tests::test_addr_case();
+            addresses::tests::test_addr_invalid_input();
+            query_permit::tests::test_query_permit_verified();
+            query_permit::tests::test_query_permit_expired();
+            query_permit::tests::test_query_permit_other_contract();
+            query_permit::tests::test_query_permit_other_chain();
+            query_permit::tests::test_query_permit_tampered();
+            query_permit::tests::test_permit_revocation();
+            query_permit::tests::test_take_query_permit();
+            query_cache::tests::test_query_cache_is_off_by_default();
+            query_cache::tests::test_query_cache_hit();
+            query_cache::tests::test_query_cache_only_keeps_client_queries();
+            query_cache::tests::test_query_cache_is_dropped_at_another_height();
+            query_cache::tests::test_query_cache_is_dropped_on_writes();
+            query_cache::tests::test_query_cache_limits();
+            query_replay::tests::test_take_replay_protection();
+            query_replay::tests::test_replayed_query_is_rejected();
+            query_replay::tests::test_query_outside_replay_window();
+            query_replay::tests::test_replay_window_too_long();
+            query_replay::tests::test_queries_during_txs_dont_use_up_nonces();
+            query_permit::tests::test_take_query_permit_malformed();
+            block_header::tests::test_header_hash();
+            block_header::tests::test_verify_block_header();
+            block_header::tests::test_verify_header_of_another_block();
+            light_client::tests::test_vote_sign_bytes();
+            light_client::tests::test_submit_linked_headers();
+            light_client::tests::test_submit_header_with_forged_signature();
+            light_client::tests::test_submit_header_without_quorum();
+            light_client::tests::test_submit_header_of_other_validators();
+            light_client::tests::test_submit_unlinked_header();
+            foreign_chains::tests::test_submit_foreign_headers();
+            foreign_chains::tests::test_foreign_header_with_forged_signature();
+            foreign_chains::tests::test_foreign_validator_set_changes();
+            foreign_chains::tests::test_foreign_header_times();
+            foreign_chains::tests::test_sealed_foreign_chains();
+            foreign_chains::tests::test_foreign_chains_are_bounded();
             // types::tests::test_msg_decrypt();
         });
 
+        #[cfg(not(feature = "debug-print"))]
+        count_failures!(failures, {
+            module_artifact::tests::test_artifact_round_trip();
+            module_artifact::tests::test_tampered_artifact_is_rejected();
+            module_artifact::tests::test_artifact_of_other_code_hash_is_rejected();
+            module_artifact::tests::test_artifact_of_other_enclave_is_rejected();
+            module_artifact::tests::test_artifact_header_round_trip();
+        });
+
+        #[cfg(feature = "debug-print")]
+        count_failures!(failures, {
+            trap_info::tests::test_strip_symbol_hash();
+            trap_info::tests::test_find_trap_locations();
+            trap_info::tests::test_hook_unreachable();
+            trap_info::tests::test_record_trap();
+        });
+
         if failures != 0 {
             panic!("{}: {} tests failed", file!(), failures);
         }
@@ -0,0 +1,271 @@
+//! The host keeps the modules the enclave analyzed, so that contracts don't have to be validated
+//! and instrumented again after the node restarts.
+//!
+//! An artifact is the instrumented module, sealed to this enclave. The code hash of the contract
+//! and the measurement of the enclave that made the artifact are sealed along with it as
+//! additional data, and an artifact is only used if both match. The host may hand the enclave
+//! anything, so artifacts that don't unseal or don't match are ignored, and the contract is
+//! analyzed again.
+
+use log::*;
+use parity_wasm::elements::{self, Module};
+use sgx_tse::rsgx_self_report;
+use sgx_types::sgx_status_t;
+
+use enclave_ffi_types::{EnclaveBuffer, EnclaveError, OcallReturn};
+
+use crate::crypto::{seal_to_enclave, unseal_from_enclave, HASH_SIZE};
+use crate::{exports, imports};
+
+use super::api_version::ApiVersion;
+use super::module_cache::CodeHash;
+
+/// Bumped whenever the layout of artifacts or the way modules are instrumented changes
+const ARTIFACT_FORMAT_VERSION: u8 = 1;
+
+const MEASUREMENT_SIZE: usize = 32;
+
+/// The MRENCLAVE of an enclave
+type Measurement = [u8; MEASUREMENT_SIZE];
+
+/// What an artifact was made for. This is the additional data of the sealed artifact.
+#[derive(Debug, PartialEq)]
+struct ArtifactHeader {
+    code_hash: CodeHash,
+    measurement: Measurement,
+    api_version: ApiVersion,
+}
+
+impl ArtifactHeader {
+    const SIZE: usize = 1 + HASH_SIZE + MEASUREMENT_SIZE + 1;
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(Self::SIZE);
+        bytes.push(ARTIFACT_FORMAT_VERSION);
+        bytes.extend_from_slice(&self.code_hash);
+        bytes.extend_from_slice(&self.measurement);
+        bytes.push(match self.api_version {
+            ApiVersion::V0_10 => 0,
+            ApiVersion::V1 => 1,
+        });
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != Self::SIZE || bytes[0] != ARTIFACT_FORMAT_VERSION {
+            return None;
+        }
+
+        let mut code_hash = CodeHash::default();
+        code_hash.copy_from_slice(&bytes[1..1 + HASH_SIZE]);
+        let mut measurement = Measurement::default();
+        measurement.copy_from_slice(&bytes[1 + HASH_SIZE..Self::SIZE - 1]);
+        let api_version = match bytes[Self::SIZE - 1] {
+            0 => ApiVersion::V0_10,
+            1 => ApiVersion::V1,
+            _ => return None,
+        };
+
+        Some(Self {
+            code_hash,
+            measurement,
+            api_version,
+        })
+    }
+}
+
+fn enclave_measurement() -> Measurement {
+    rsgx_self_report().body.mr_enclave.m
+}
+
+/// Seal the instrumented module of the contract with this code hash, for this enclave only
+pub fn seal_artifact(
+    code_hash: &CodeHash,
+    instrumented: Module,
+    api_version: ApiVersion,
+) -> Result<Vec<u8>, EnclaveError> {
+    seal_artifact_for(&enclave_measurement(), code_hash, instrumented, api_version)
+}
+
+fn seal_artifact_for(
+    measurement: &Measurement,
+    code_hash: &CodeHash,
+    instrumented: Module,
+    api_version: ApiVersion,
+) -> Result<Vec<u8>, EnclaveError> {
+    let header = ArtifactHeader {
+        code_hash: *code_hash,
+        measurement: *measurement,
+        api_version,
+    };
+    let wasm = elements::serialize(instrumented).map_err(|_| EnclaveError::FailedToSerialize)?;
+
+    seal_to_enclave(&header.to_bytes(), &wasm)
+}
+
+/// Open an artifact sealed by `seal_artifact`, and return the instrumented module and the API
+/// version of the contract. Returns `None` unless the artifact was sealed by this enclave for the
+/// contract with this code hash.
+pub fn open_artifact(code_hash: &CodeHash, sealed: &[u8]) -> Option<(Module, ApiVersion)> {
+    let (header, wasm) = unseal_from_enclave(sealed).ok()?;
+
+    let header = match ArtifactHeader::from_bytes(&header) {
+        Some(header) => header,
+        None => {
+            debug!("Ignoring a module artifact of an unknown format");
+            return None;
+        }
+    };
+    if header.code_hash != *code_hash {
+        debug!(
+            "Ignoring a module artifact made for code hash {:?} instead of {:?}",
+            header.code_hash, code_hash
+        );
+        return None;
+    }
+    if header.measurement != enclave_measurement() {
+        debug!("Ignoring a module artifact made by another enclave");
+        return None;
+    }
+
+    let module = elements::deserialize_buffer(&wasm).ok()?;
+
+    Some((module, header.api_version))
+}
+
+/// Hand the instrumented module of the contract to the host to keep. Failing to do so only means
+/// that the contract will be analyzed again after the node restarts.
+pub fn store(code_hash: &CodeHash, instrumented: Module, api_version: ApiVersion) {
+    let sealed = match seal_artifact(code_hash, instrumented, api_version) {
+        Ok(sealed) => sealed,
+        Err(err) => {
+            debug!("Failed to seal the module artifact: {}", err);
+            return;
+        }
+    };
+
+    let status = unsafe {
+        imports::ocall_store_module_artifact(code_hash.as_ptr(), sealed.as_ptr(), sealed.len())
+    };
+    if status != sgx_status_t::SGX_SUCCESS {
+        debug!("ocall_store_module_artifact failed: {}", status);
+    }
+}
+
+/// The instrumented module and API version of the contract, if the host kept a valid artifact of
+/// it
+pub fn load(code_hash: &CodeHash) -> Option<(Module, ApiVersion)> {
+    let sealed = load_sealed(code_hash)?;
+    open_artifact(code_hash, &sealed)
+}
+
+fn load_sealed(code_hash: &CodeHash) -> Option<Vec<u8>> {
+    let mut ocall_return = OcallReturn::Success;
+    let mut enclave_buffer = std::mem::MaybeUninit::<EnclaveBuffer>::uninit();
+
+    let status = unsafe {
+        imports::ocall_load_module_artifact(
+            (&mut ocall_return) as *mut _,
+            code_hash.as_ptr(),
+            enclave_buffer.as_mut_ptr(),
+        )
+    };
+    if status != sgx_status_t::SGX_SUCCESS {
+        debug!("ocall_load_module_artifact failed: {}", status);
+        return None;
+    }
+
+    match ocall_return {
+        OcallReturn::Success => unsafe { exports::recover_buffer(enclave_buffer.assume_init()) }
+            .unwrap_or_else(|err| {
+                debug!("Failed to recover the module artifact: {:?}", err);
+                None
+            }),
+        OcallReturn::Failure | OcallReturn::Panic => {
+            // A missing artifact only costs a recompile, whatever kept the host from sending it
+            exports::take_allocation_failure();
+            None
+        }
+    }
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    const CODE_HASH: CodeHash = [7; HASH_SIZE];
+
+    /// A module with nothing in it
+    fn empty_module() -> Module {
+        elements::deserialize_buffer(b"\0asm\x01\0\0\0").unwrap()
+    }
+
+    fn empty_module_artifact() -> Vec<u8> {
+        seal_artifact(&CODE_HASH, empty_module(), ApiVersion::V1).unwrap()
+    }
+
+    pub fn test_artifact_round_trip() {
+        let sealed = empty_module_artifact();
+
+        let (module, api_version) = open_artifact(&CODE_HASH, &sealed).unwrap();
+        assert_eq!(module, empty_module());
+        assert_eq!(api_version, ApiVersion::V1);
+    }
+
+    pub fn test_tampered_artifact_is_rejected() {
+        let sealed = empty_module_artifact();
+
+        // The sealed data ends with its MAC, the module and then the header
+        let mac_size = 16;
+        let wasm_size = elements::serialize(empty_module()).unwrap().len();
+        let authenticated = mac_size + wasm_size + ArtifactHeader::SIZE;
+
+        for index in sealed.len() - authenticated..sealed.len() {
+            let mut tampered = sealed.clone();
+            tampered[index] ^= 1;
+            assert!(
+                open_artifact(&CODE_HASH, &tampered).is_none(),
+                "artifact with byte {} flipped was accepted",
+                index
+            );
+        }
+
+        assert!(open_artifact(&CODE_HASH, &sealed[..sealed.len() - 1]).is_none());
+        assert!(open_artifact(&CODE_HASH, &[]).is_none());
+    }
+
+    pub fn test_artifact_of_other_code_hash_is_rejected() {
+        let sealed = empty_module_artifact();
+
+        assert!(open_artifact(&[8; HASH_SIZE], &sealed).is_none());
+    }
+
+    pub fn test_artifact_of_other_enclave_is_rejected() {
+        let mut measurement = enclave_measurement();
+        measurement[0] ^= 1;
+        let sealed =
+            seal_artifact_for(&measurement, &CODE_HASH, empty_module(), ApiVersion::V1).unwrap();
+
+        assert!(open_artifact(&CODE_HASH, &sealed).is_none());
+    }
+
+    pub fn test_artifact_header_round_trip() {
+        let header = ArtifactHeader {
+            code_hash: CODE_HASH,
+            measurement: [9; MEASUREMENT_SIZE],
+            api_version: ApiVersion::V0_10,
+        };
+        let bytes = header.to_bytes();
+
+        assert_eq!(bytes.len(), ArtifactHeader::SIZE);
+        assert_eq!(ArtifactHeader::from_bytes(&bytes), Some(header));
+
+        let mut other_format = bytes.clone();
+        other_format[0] = ARTIFACT_FORMAT_VERSION + 1;
+        assert_eq!(ArtifactHeader::from_bytes(&other_format), None);
+
+        let mut unknown_api_version = bytes;
+        unknown_api_version[ArtifactHeader::SIZE - 1] = 2;
+        assert_eq!(ArtifactHeader::from_bytes(&unknown_api_version), None);
+    }
+}
@@ -0,0 +1,265 @@
+use std::sync::{Arc, SgxMutex};
+
+use lazy_static::lazy_static;
+use log::*;
+
+use enclave_ffi_types::EnclaveError;
+
+use crate::consts::MODULE_CACHE_SIZE_BYTES;
+use crate::crypto::HASH_SIZE;
+use crate::runtime_stats;
+
+use super::api_version::ApiVersion;
+#[cfg(feature = "debug-print")]
+use super::trap_info::TrapLocations;
+
+pub type CodeHash = [u8; HASH_SIZE];
+
+/// A contract module that was already deserialized, validated and instrumented with gas metering,
+/// and is ready to be instantiated.
+#[derive(Clone)]
+pub struct AnalyzedModule {
+    pub module: Arc<wasmi::Module>,
+    /// The version of the CosmWasm API the contract was built for
+    pub api_version: ApiVersion,
+    /// How many bytes instantiating the module copies from its segments, which is charged before
+    /// it's instantiated
+    pub segment_bytes: u64,
+    /// Where each `unreachable` instruction of the contract is, see `trap_info`
+    #[cfg(feature = "debug-print")]
+    pub trap_locations: Arc<TrapLocations>,
+}
+
+struct CachedModule {
+    code_hash: CodeHash,
+    analyzed: AnalyzedModule,
+}
+
+/// A least-recently-used cache, bounded by the total size of its entries.
+///
+/// The amount of contracts that are called in a block is small, so entries are kept in a plain
+/// `Vec` ordered from the least recently used to the most recently used one.
+pub struct LruCache<K, V> {
+    entries: Vec<(K, V, usize)>,
+    max_bytes: usize,
+    used_bytes: usize,
+}
+
+impl<K: PartialEq, V> LruCache<K, V> {
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            entries: Vec::new(),
+            max_bytes,
+            used_bytes: 0,
+        }
+    }
+
+    /// Get an entry and mark it as the most recently used one
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        let index = self.entries.iter().position(|(k, _, _)| k == key)?;
+        let entry = self.entries.remove(index);
+        self.entries.push(entry);
+        self.entries.last().map(|(_, value, _)| value)
+    }
+
+    /// Insert an entry of `size` bytes, evicting the least recently used entries until it fits.
+    /// Entries that are larger than the whole cache are not inserted.
+    pub fn insert(&mut self, key: K, value: V, size: usize) {
+        self.remove(&key);
+
+        if size > self.max_bytes {
+            return;
+        }
+
+        while self.used_bytes + size > self.max_bytes && !self.entries.is_empty() {
+            let (_, _, evicted_size) = self.entries.remove(0);
+            self.used_bytes -= evicted_size;
+        }
+
+        self.entries.push((key, value, size));
+        self.used_bytes += size;
+    }
+
+    pub fn remove(&mut self, key: &K) {
+        if let Some(index) = self.entries.iter().position(|(k, _, _)| k == key) {
+            let (_, _, size) = self.entries.remove(index);
+            self.used_bytes -= size;
+        }
+    }
+
+    pub fn remove_least_recently_used(&mut self) {
+        if !self.entries.is_empty() {
+            let (_, _, size) = self.entries.remove(0);
+            self.used_bytes -= size;
+        }
+    }
+
+    /// Remove the entries for which `keep` returns false
+    pub fn retain<F: FnMut(&K, &V) -> bool>(&mut self, mut keep: F) {
+        let mut removed_bytes = 0;
+        self.entries.retain(|(key, value, size)| {
+            let kept = keep(key, value);
+            if !kept {
+                removed_bytes += size;
+            }
+            kept
+        });
+        self.used_bytes -= removed_bytes;
+    }
+
+    pub fn clear(&mut self) {
+        self.entries = Vec::new();
+        self.used_bytes = 0;
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn used_bytes(&self) -> usize {
+        self.used_bytes
+    }
+}
+
+lazy_static! {
+    static ref MODULE_CACHE: SgxMutex<LruCache<CodeHash, CachedModule>> =
+        SgxMutex::new(LruCache::new(MODULE_CACHE_SIZE_BYTES));
+}
+
+/// Return the analyzed module of the contract with this code hash, running `analyze` and caching
+/// its result if the module isn't cached yet.
+///
+/// `size` is the size of the contract bytecode, and is used to account for the entry's size in
+/// the cache.
+pub fn get_or_analyze<F>(
+    code_hash: &CodeHash,
+    size: usize,
+    analyze: F,
+) -> Result<AnalyzedModule, EnclaveError>
+where
+    F: FnOnce() -> Result<AnalyzedModule, EnclaveError>,
+{
+    {
+        let mut cache = MODULE_CACHE.lock().unwrap();
+        match cache.get(code_hash) {
+            Some(entry) if entry.code_hash == *code_hash => {
+                trace!("Using cached module for code hash {:?}", code_hash);
+                return Ok(entry.analyzed.clone());
+            }
+            Some(_) => {
+                warn!("Cached module does not match its code hash. Evicting it");
+                cache.remove(code_hash);
+            }
+            None => {}
+        }
+    }
+
+    // The lock isn't held while analyzing, as it may take a while
+    runtime_stats::record_module_cache_miss();
+    let analyzed = analyze()?;
+
+    MODULE_CACHE.lock().unwrap().insert(
+        *code_hash,
+        CachedModule {
+            code_hash: *code_hash,
+            analyzed: analyzed.clone(),
+        },
+        size,
+    );
+
+    Ok(analyzed)
+}
+
+/// Drop all the cached modules to free memory.
+///
+/// This is called from the allocation error hook, which may run while the cache is locked,
+/// so the cache is only cleared if it isn't in use.
+pub fn evict_under_memory_pressure() {
+    if let Ok(mut cache) = MODULE_CACHE.try_lock() {
+        cache.clear();
+    }
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use crate::crypto::sha_256;
+
+    // An empty module
+    const EMPTY_WASM: &[u8] = b"\0asm\x01\0\0\0";
+    // A module with a single `() -> ()` function type
+    const TYPE_SECTION_WASM: &[u8] = b"\0asm\x01\0\0\0\x01\x04\x01\x60\x00\x00";
+
+    pub fn test_lru_cache_evicts_least_recently_used() {
+        let mut cache = LruCache::new(10);
+        cache.insert(1, "a", 4);
+        cache.insert(2, "b", 4);
+
+        // Touch the first entry so the second one is evicted
+        assert_eq!(cache.get(&1), Some(&"a"));
+        cache.insert(3, "c", 4);
+
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.get(&1), Some(&"a"));
+        assert_eq!(cache.get(&3), Some(&"c"));
+        assert_eq!(cache.used_bytes(), 8);
+    }
+
+    pub fn test_lru_cache_skips_oversized_entries() {
+        let mut cache = LruCache::new(10);
+        cache.insert(1, "a", 4);
+        cache.insert(2, "b", 11);
+
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.get(&1), Some(&"a"));
+        assert_eq!(cache.len(), 1);
+    }
+
+    fn analyzed(wasm: &[u8], api_version: ApiVersion) -> AnalyzedModule {
+        AnalyzedModule {
+            module: Arc::new(wasmi::Module::from_buffer(wasm).unwrap()),
+            api_version,
+            segment_bytes: 0,
+            #[cfg(feature = "debug-print")]
+            trap_locations: Arc::default(),
+        }
+    }
+
+    pub fn test_module_cache_different_hashes_dont_collide() {
+        let first_hash = sha_256(EMPTY_WASM);
+        let second_hash = sha_256(TYPE_SECTION_WASM);
+        assert_ne!(first_hash, second_hash);
+
+        let first = get_or_analyze(&first_hash, EMPTY_WASM.len(), || {
+            Ok(analyzed(EMPTY_WASM, ApiVersion::V0_10))
+        })
+        .unwrap();
+        let second = get_or_analyze(&second_hash, TYPE_SECTION_WASM.len(), || {
+            Ok(analyzed(TYPE_SECTION_WASM, ApiVersion::V1))
+        })
+        .unwrap();
+        assert!(!Arc::ptr_eq(&first.module, &second.module));
+
+        // Both modules are served from the cache, each under its own hash
+        let cached_first = get_or_analyze(&first_hash, EMPTY_WASM.len(), || {
+            panic!("module should have been cached")
+        })
+        .unwrap();
+        let cached_second = get_or_analyze(&second_hash, TYPE_SECTION_WASM.len(), || {
+            panic!("module should have been cached")
+        })
+        .unwrap();
+        assert!(Arc::ptr_eq(&first.module, &cached_first.module));
+        assert!(Arc::ptr_eq(&second.module, &cached_second.module));
+        assert_eq!(cached_first.api_version, ApiVersion::V0_10);
+        assert_eq!(cached_second.api_version, ApiVersion::V1);
+
+        evict_under_memory_pressure();
+        assert!(MODULE_CACHE.lock().unwrap().is_empty());
+    }
+}
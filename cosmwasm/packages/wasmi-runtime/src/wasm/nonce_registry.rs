@@ -0,0 +1,55 @@
+//! Rejecting txs that reuse the encryption nonce of an earlier message.
+//!
+//! A client picks a fresh nonce for every message it encrypts, so a (public key, nonce) pair that
+//! shows up twice means a message was copied into another tx. The pair of every tx message a
+//! contract executes is kept in the storage of the contract, see `write_used_tx_nonce`, and a
+//! message whose pair the contract already saw fails with `EnclaveError::NonceReuse`.
+//!
+//! The pairs are part of the state of the chain, so every node rejects the same messages, also
+//! after it restarted or was restored from a snapshot, and a tx that fails leaves no pair behind.
+//! Pairs are kept for good, as dropping them after some blocks would be another change to the state
+//! that all nodes have to make the same. Checking and keeping a pair is charged like reading and
+//! writing an entry.
+//!
+//! Only the contract the message was sent to keeps its pair, so a message copied into a tx to
+//! another contract with the same code isn't caught. Simulated txs and messages sent by contracts,
+//! which carry the nonce of the tx that caused them, don't use up their nonce.
+
+use log::*;
+
+use enclave_ffi_types::EnclaveError;
+
+use crate::cosmwasm::types::{Env, SigInfo};
+
+use super::runtime::Engine;
+use super::types::SecretMessage;
+
+/// Reject `secret_msg` if the contract of `engine` executed a message with its public key and
+/// nonce before, and remember them otherwise.
+///
+/// Must only be called after the message was verified against the signed tx, so that a tx that
+/// copied the message of someone else can't use up their nonce.
+pub fn check_tx_nonce(
+    engine: &mut Engine,
+    env: &Env,
+    sig_info: &SigInfo,
+    secret_msg: &SecretMessage,
+) -> Result<(), EnclaveError> {
+    if env.simulation || sig_info.callback_sig.is_some() {
+        return Ok(());
+    }
+
+    if engine.is_tx_nonce_used(&secret_msg.user_public_key, &secret_msg.nonce)? {
+        debug!(
+            "got a message that reuses a nonce at height {}",
+            env.block.height
+        );
+        return Err(EnclaveError::NonceReuse);
+    }
+
+    engine.use_tx_nonce(
+        &secret_msg.user_public_key,
+        &secret_msg.nonce,
+        env.block.height,
+    )
+}
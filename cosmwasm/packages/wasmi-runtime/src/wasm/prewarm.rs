@@ -0,0 +1,218 @@
+//! Pre-warming the module cache after the node starts, so that the first call to each popular
+//! contract in a block doesn't pay for validating it.
+//!
+//! The host passes a list of entries and one buffer with the code of all of them. Every entry is
+//! checked on its own, so an entry that points outside of the code buffer, or code that doesn't
+//! hash to the code hash of its entry, only fails that entry.
+
+use std::time::{Duration, SystemTime};
+use std::untrusted::time::SystemTimeEx;
+
+use log::*;
+
+use enclave_ffi_types::{PrewarmStatus, PREWARM_ENTRY_SIZE};
+
+use crate::crypto::{sha_256, HASH_SIZE};
+
+use super::contract_operations::warm_code;
+use super::module_cache::CodeHash;
+
+/// How much work a call to `prewarm_contracts` may do. Entries that don't fit in what's left are
+/// skipped with `PrewarmStatus::OverBudget`.
+#[derive(Clone, Copy, Debug)]
+pub struct PrewarmBudget {
+    /// The total size of the contracts that may be loaded
+    pub max_bytes: u64,
+    /// The time after which no more contracts are loaded
+    pub max_duration: Duration,
+}
+
+struct PrewarmEntry {
+    code_hash: CodeHash,
+    offset: usize,
+    len: usize,
+}
+
+impl PrewarmEntry {
+    fn from_bytes(bytes: &[u8]) -> Self {
+        let mut code_hash = CodeHash::default();
+        code_hash.copy_from_slice(&bytes[..HASH_SIZE]);
+        let read_u32 = |at: usize| {
+            let mut value = [0u8; 4];
+            value.copy_from_slice(&bytes[at..at + 4]);
+            u32::from_be_bytes(value) as usize
+        };
+
+        Self {
+            code_hash,
+            offset: read_u32(HASH_SIZE),
+            len: read_u32(HASH_SIZE + 4),
+        }
+    }
+
+    fn code<'a>(&self, code: &'a [u8]) -> Option<&'a [u8]> {
+        let end = self.offset.checked_add(self.len)?;
+        code.get(self.offset..end)
+    }
+}
+
+/// Validate the contracts in `entries` and cache their modules, within `budget`. Returns the
+/// status of every entry, in order.
+///
+/// `entries` is a list of `PREWARM_ENTRY_SIZE` byte entries, and `code` holds the code they
+/// point to. Bytes after the last whole entry are ignored.
+pub fn prewarm_contracts(entries: &[u8], code: &[u8], budget: PrewarmBudget) -> Vec<PrewarmStatus> {
+    let started = SystemTime::now();
+    let mut bytes_left = budget.max_bytes;
+
+    entries
+        .chunks_exact(PREWARM_ENTRY_SIZE)
+        .map(|entry| {
+            let entry = PrewarmEntry::from_bytes(entry);
+            let contract = match entry.code(code) {
+                Some(contract) => contract,
+                None => {
+                    warn!(
+                        "Prewarm entry for code hash {:?} points outside of the code",
+                        entry.code_hash
+                    );
+                    return PrewarmStatus::InvalidEntry;
+                }
+            };
+            if sha_256(contract) != entry.code_hash {
+                warn!(
+                    "Code passed to prewarm code hash {:?} has another hash",
+                    entry.code_hash
+                );
+                return PrewarmStatus::CodeHashMismatch;
+            }
+
+            // If the clock went backwards no time is treated as spent
+            let out_of_time = started
+                .elapsed()
+                .map(|elapsed| elapsed >= budget.max_duration)
+                .unwrap_or(false);
+            if out_of_time || contract.len() as u64 > bytes_left {
+                debug!(
+                    "Not prewarming code hash {:?}, the budget ran out",
+                    entry.code_hash
+                );
+                return PrewarmStatus::OverBudget;
+            }
+
+            match warm_code(&entry.code_hash, contract) {
+                Ok(true) => {
+                    bytes_left -= contract.len() as u64;
+                    PrewarmStatus::Warmed
+                }
+                Ok(false) => PrewarmStatus::AlreadyCached,
+                Err(err) => {
+                    debug!("Failed to prewarm code hash {:?}: {}", entry.code_hash, err);
+                    PrewarmStatus::InvalidWasm
+                }
+            }
+        })
+        .collect()
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use crate::wasm::module_cache;
+
+    const V0_10_WASM: &[u8] = include_bytes!("../../testdata/api-versions/v0_10.wasm");
+    const V1_WASM: &[u8] = include_bytes!("../../testdata/api-versions/v1.wasm");
+    const UNKNOWN_VERSION_WASM: &[u8] =
+        include_bytes!("../../testdata/api-versions/unknown_version.wasm");
+
+    const UNLIMITED: PrewarmBudget = PrewarmBudget {
+        max_bytes: u64::MAX,
+        max_duration: Duration::from_secs(u64::MAX),
+    };
+
+    fn entry(code_hash: &CodeHash, offset: usize, len: usize) -> Vec<u8> {
+        let mut entry = code_hash.to_vec();
+        entry.extend_from_slice(&(offset as u32).to_be_bytes());
+        entry.extend_from_slice(&(len as u32).to_be_bytes());
+        entry
+    }
+
+    /// Pack contracts the way the host passes them to `ecall_prewarm_contracts`
+    fn pack(contracts: &[&[u8]]) -> (Vec<u8>, Vec<u8>) {
+        let mut entries = vec![];
+        let mut code = vec![];
+        for contract in contracts {
+            entries.extend(entry(&sha_256(contract), code.len(), contract.len()));
+            code.extend_from_slice(contract);
+        }
+        (entries, code)
+    }
+
+    pub fn test_prewarm_skips_bad_entries() {
+        module_cache::evict_under_memory_pressure();
+
+        let mut code = V0_10_WASM.to_vec();
+        code.extend_from_slice(UNKNOWN_VERSION_WASM);
+        code.extend_from_slice(V1_WASM);
+        let unknown_offset = V0_10_WASM.len();
+        let v1_offset = unknown_offset + UNKNOWN_VERSION_WASM.len();
+
+        let entries = [
+            entry(&sha_256(V0_10_WASM), 0, V0_10_WASM.len()),
+            // Runs past the end of the code
+            entry(&sha_256(V1_WASM), v1_offset, V1_WASM.len() + 1),
+            entry(&sha_256(V1_WASM), u32::MAX as usize, V1_WASM.len()),
+            // Points at another contract than its code hash
+            entry(&sha_256(V1_WASM), 0, V0_10_WASM.len()),
+            entry(
+                &sha_256(UNKNOWN_VERSION_WASM),
+                unknown_offset,
+                UNKNOWN_VERSION_WASM.len(),
+            ),
+            entry(&sha_256(V1_WASM), v1_offset, V1_WASM.len()),
+        ]
+        .concat();
+
+        assert_eq!(
+            prewarm_contracts(&entries, &code, UNLIMITED),
+            vec![
+                PrewarmStatus::Warmed,
+                PrewarmStatus::InvalidEntry,
+                PrewarmStatus::InvalidEntry,
+                PrewarmStatus::CodeHashMismatch,
+                PrewarmStatus::InvalidWasm,
+                PrewarmStatus::Warmed,
+            ]
+        );
+    }
+
+    pub fn test_prewarm_within_budget() {
+        module_cache::evict_under_memory_pressure();
+
+        let (entries, code) = pack(&[V0_10_WASM, V1_WASM]);
+        let budget = PrewarmBudget {
+            max_bytes: V0_10_WASM.len() as u64,
+            ..UNLIMITED
+        };
+        assert_eq!(
+            prewarm_contracts(&entries, &code, budget),
+            vec![PrewarmStatus::Warmed, PrewarmStatus::OverBudget]
+        );
+
+        // Contracts that are already cached don't use up the budget
+        assert_eq!(
+            prewarm_contracts(&entries, &code, budget),
+            vec![PrewarmStatus::AlreadyCached, PrewarmStatus::OverBudget]
+        );
+
+        let out_of_time = PrewarmBudget {
+            max_duration: Duration::from_secs(0),
+            ..UNLIMITED
+        };
+        assert_eq!(
+            prewarm_contracts(&entries, &code, out_of_time),
+            vec![PrewarmStatus::OverBudget, PrewarmStatus::OverBudget]
+        );
+    }
+}
@@ -0,0 +1,328 @@
+//! A cache of query results, for clients that send the same encrypted query many times in a block.
+//!
+//! Results are cached under the whole encrypted query, which includes its nonce and the public
+//! key of the client, together with the contract it was sent to and the block height. The
+//! encrypted result of a query only depends on those and on the state it ran on, so a hit returns
+//! the exact result and gas that running the query again would, without re-encrypting anything. A
+//! client that encrypts its query with a fresh nonce never shares a result with anyone, and always
+//! runs the query.
+//!
+//! Only queries that clients send to the node are cached, see `Env::client_query`. Queries that
+//! contracts make during a tx or during another query always run, since their results are part of
+//! what the outer call computes. A result is dropped as soon as anything is written to the
+//! storage of any contract, since a query can read other contracts through nested queries, and
+//! the whole cache is dropped when the height in the env changes. Queries sent with replay
+//! protection are never cached, since answering them twice is what they ask not to do.
+//!
+//! The cache is off until the host gives it a size, see `ecall_configure_runtime`.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::SgxMutex;
+
+use lazy_static::lazy_static;
+use log::*;
+
+use crate::consts::QUERY_CACHE_MAX_ENTRIES;
+use crate::cosmwasm::types::{Env, HumanAddr};
+use crate::crypto::{sha_256, HASH_SIZE};
+
+use super::module_cache::{CodeHash, LruCache};
+
+/// How many times the storage of a contract was written to since the enclave started
+static STATE_WRITES: AtomicU64 = AtomicU64::new(0);
+
+/// Drop every cached result. Called on every write to the storage of a contract.
+pub fn invalidate_query_cache() {
+    STATE_WRITES.fetch_add(1, Ordering::SeqCst);
+}
+
+/// The state a query runs on: the height it was sent at, and the writes it comes after
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct QueryState {
+    height: u64,
+    writes: u64,
+}
+
+impl QueryState {
+    fn current(height: u64) -> Self {
+        Self {
+            height,
+            writes: STATE_WRITES.load(Ordering::SeqCst),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct QueryCacheKey {
+    code_hash: CodeHash,
+    contract_address: HumanAddr,
+    msg_hash: [u8; HASH_SIZE],
+}
+
+impl QueryCacheKey {
+    /// The key of a query a client sent to the node, and the state it runs on. Other queries,
+    /// including the ones without an env, are never cached.
+    ///
+    /// `msg` is the query as the host passed it, with the contract key and the encrypted message
+    pub fn for_client_query(
+        code_hash: &CodeHash,
+        env: Option<&Env>,
+        msg: &[u8],
+    ) -> Option<(Self, QueryState)> {
+        let env = env.filter(|env| env.client_query)?;
+        let key = Self {
+            code_hash: *code_hash,
+            contract_address: env.contract.address.clone(),
+            msg_hash: sha_256(msg),
+        };
+        Some((key, QueryState::current(env.block.height)))
+    }
+}
+
+/// The result of a query, as it was returned to the host
+#[derive(Clone, Debug, PartialEq)]
+pub struct CachedQuery {
+    pub output: Vec<u8>,
+    pub used_gas: u64,
+}
+
+struct QueryCache {
+    state: QueryState,
+    entries: LruCache<QueryCacheKey, CachedQuery>,
+}
+
+impl QueryCache {
+    /// Drop the results that were computed on another state
+    fn move_to_state(&mut self, state: QueryState) {
+        if self.state != state {
+            self.entries.clear();
+            self.state = state;
+        }
+    }
+}
+
+lazy_static! {
+    /// A cache of 0 bytes never keeps a result
+    static ref QUERY_CACHE: SgxMutex<QueryCache> = SgxMutex::new(QueryCache {
+        state: QueryState { height: 0, writes: 0 },
+        entries: LruCache::new(0),
+    });
+}
+
+/// Change the most bytes of results the cache keeps, dropping what it has. 0 turns it off.
+pub fn set_query_cache_size(max_bytes: usize) {
+    let mut cache = QUERY_CACHE.lock().unwrap();
+    cache.entries = LruCache::new(max_bytes);
+}
+
+/// Return the result of a query that was already answered on `state`, if it fits in `gas_limit`.
+/// A query that ran out of gas the first time would do it again, so it's left to run.
+pub fn get_cached_query(
+    key: &QueryCacheKey,
+    state: QueryState,
+    gas_limit: u64,
+) -> Option<CachedQuery> {
+    let mut cache = QUERY_CACHE.lock().unwrap();
+    cache.move_to_state(state);
+
+    let cached = cache.entries.get(key)?;
+    if cached.used_gas > gas_limit {
+        return None;
+    }
+
+    trace!("Answering query from the cache at height {}", state.height);
+    Some(cached.clone())
+}
+
+/// Remember the result of a query that was answered on `state`. A result is dropped if anything
+/// was written while the query ran, since it may have read the state from before or after.
+pub fn cache_query(key: QueryCacheKey, state: QueryState, query: CachedQuery) {
+    if QueryState::current(state.height) != state {
+        return;
+    }
+
+    let mut cache = QUERY_CACHE.lock().unwrap();
+    cache.move_to_state(state);
+
+    if cache.entries.len() >= QUERY_CACHE_MAX_ENTRIES {
+        cache.entries.remove_least_recently_used();
+    }
+    let size = query.output.len() + std::mem::size_of::<(QueryCacheKey, CachedQuery)>();
+    cache.entries.insert(key, query, size);
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use crate::cosmwasm::types::{BlockInfo, ContractInfo, MessageInfo, TransactionInfo};
+
+    fn env_of(contract_address: &str, height: u64) -> Env {
+        Env {
+            block: BlockInfo {
+                height,
+                time: 1_000,
+                time_nanos: 0,
+                chain_id: "secret-testnet".to_string(),
+                hash: None,
+                proposer_address: None,
+            },
+            message: MessageInfo::default(),
+            contract: ContractInfo {
+                address: HumanAddr(contract_address.to_string()),
+                admin: None,
+            },
+            contract_key: None,
+            contract_code_hash: String::new(),
+            random: None,
+            transaction: TransactionInfo::default(),
+            query_sender: None,
+            ocall_gas_costs: None,
+            bech32_prefix: None,
+            instantiation: None,
+            block_header: None,
+            execution_timeout_ms: None,
+            output_limits: None,
+            block_gas_remaining: None,
+            storage_quota: None,
+            max_memory_pages: None,
+            simulation: false,
+            client_query: true,
+            admin_proof: None,
+            execution_path: None,
+            reply_to: None,
+            foreign_chains: vec![],
+        }
+    }
+
+    /// A query a client sent to the contract at `height`
+    fn query_at(msg: &[u8], height: u64) -> (QueryCacheKey, QueryState) {
+        QueryCacheKey::for_client_query(
+            &[1u8; HASH_SIZE],
+            Some(&env_of("secret1contract", height)),
+            msg,
+        )
+        .unwrap()
+    }
+
+    fn cached(output: &[u8], used_gas: u64) -> CachedQuery {
+        CachedQuery {
+            output: output.to_vec(),
+            used_gas,
+        }
+    }
+
+    fn cache(msg: &[u8], height: u64) {
+        let (key, state) = query_at(msg, height);
+        cache_query(key, state, cached(b"result", 100));
+    }
+
+    fn get(msg: &[u8], height: u64, gas_limit: u64) -> Option<CachedQuery> {
+        let (key, state) = query_at(msg, height);
+        get_cached_query(&key, state, gas_limit)
+    }
+
+    pub fn test_query_cache_is_off_by_default() {
+        set_query_cache_size(0);
+        cache(b"query", 10);
+        assert_eq!(get(b"query", 10, u64::MAX), None);
+    }
+
+    pub fn test_query_cache_hit() {
+        set_query_cache_size(1024 * 1024);
+        cache(b"query", 10);
+
+        assert_eq!(get(b"query", 10, u64::MAX), Some(cached(b"result", 100)));
+        // Another nonce makes another encrypted query
+        assert_eq!(get(b"query'", 10, u64::MAX), None);
+        // The same query of another contract, or of another code
+        let (other_contract, state) = QueryCacheKey::for_client_query(
+            &[1u8; HASH_SIZE],
+            Some(&env_of("secret1other", 10)),
+            b"query",
+        )
+        .unwrap();
+        assert_eq!(get_cached_query(&other_contract, state, u64::MAX), None);
+        let (other_code, state) = QueryCacheKey::for_client_query(
+            &[2u8; HASH_SIZE],
+            Some(&env_of("secret1contract", 10)),
+            b"query",
+        )
+        .unwrap();
+        assert_eq!(get_cached_query(&other_code, state, u64::MAX), None);
+
+        // A query that wouldn't fit in the gas limit isn't answered from the cache
+        assert_eq!(get(b"query", 10, 99), None);
+        assert!(get(b"query", 10, 100).is_some());
+
+        set_query_cache_size(0);
+    }
+
+    pub fn test_query_cache_only_keeps_client_queries() {
+        let mut env = env_of("secret1contract", 10);
+        env.client_query = false;
+        assert_eq!(
+            QueryCacheKey::for_client_query(&[1u8; HASH_SIZE], Some(&env), b"query"),
+            None
+        );
+        assert_eq!(
+            QueryCacheKey::for_client_query(&[1u8; HASH_SIZE], None, b"query"),
+            None
+        );
+    }
+
+    pub fn test_query_cache_is_dropped_at_another_height() {
+        set_query_cache_size(1024 * 1024);
+        cache(b"query", 10);
+
+        assert_eq!(get(b"query", 11, u64::MAX), None);
+        // Going back doesn't bring the result back
+        assert_eq!(get(b"query", 10, u64::MAX), None);
+
+        set_query_cache_size(0);
+    }
+
+    pub fn test_query_cache_is_dropped_on_writes() {
+        set_query_cache_size(1024 * 1024);
+        cache(b"query", 10);
+        assert!(get(b"query", 10, u64::MAX).is_some());
+
+        // A write to any contract may change what the query reads, within the same block
+        invalidate_query_cache();
+        assert_eq!(get(b"query", 10, u64::MAX), None);
+
+        // A query that ran while something was written isn't kept either
+        let (key, state) = query_at(b"query", 10);
+        invalidate_query_cache();
+        cache_query(key, state, cached(b"result", 100));
+        assert_eq!(get(b"query", 10, u64::MAX), None);
+
+        set_query_cache_size(0);
+    }
+
+    pub fn test_query_cache_limits() {
+        let entry_size = b"result".len() + std::mem::size_of::<(QueryCacheKey, CachedQuery)>();
+
+        // Bounded by bytes
+        set_query_cache_size(2 * entry_size);
+        for query in 0..3u8 {
+            cache(&[query], 10);
+        }
+        assert_eq!(get(&[0], 10, u64::MAX), None);
+        assert!(get(&[1], 10, u64::MAX).is_some());
+        assert!(get(&[2], 10, u64::MAX).is_some());
+
+        // Bounded by entries
+        set_query_cache_size(usize::MAX);
+        for query in 0..=QUERY_CACHE_MAX_ENTRIES {
+            cache(&query.to_le_bytes(), 10);
+        }
+        assert_eq!(
+            QUERY_CACHE.lock().unwrap().entries.len(),
+            QUERY_CACHE_MAX_ENTRIES
+        );
+        assert_eq!(get(&0usize.to_le_bytes(), 10, u64::MAX), None);
+
+        set_query_cache_size(0);
+    }
+}
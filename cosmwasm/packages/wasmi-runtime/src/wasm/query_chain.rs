@@ -1,20 +1,51 @@
 use super::errors::WasmEngineError;
-use crate::crypto::Ed25519PublicKey;
+use super::io::{calc_query_encryption_key, open_query_response};
+use crate::crypto::{AESKey, Ed25519PublicKey, SIVEncryptable, HASH_SIZE};
+use crate::ecall_trace::Phase;
+use crate::metrics::{self, Ocall};
 use crate::recursion_depth;
+use crate::trace_secret;
+use crate::trace_span;
 use crate::wasm::types::{IoNonce, SecretMessage};
 use crate::{exports, imports};
 
 use crate::cosmwasm::{
     encoding::Binary,
-    query::{QueryRequest, WasmQuery},
+    query::{
+        AllDelegationsResponse, BondedDenomResponse, BondedRatioResponse, DelegationResponse,
+        DistQuery, GovQuery, InflationResponse, MintQuery, ProposalsResponse, QueryRequest,
+        RewardsResponse, StakingQuery, UnbondingDelegationsResponse, ValidatorsResponse, WasmQuery,
+    },
     std_error::{StdError, StdResult},
     system_error::{SystemError, SystemResult},
 };
 
 use enclave_ffi_types::{Ctx, EnclaveBuffer, OcallReturn, UntrustedVmError};
 use log::*;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
 use sgx_types::sgx_status_t;
 
+/// The variants that can be queried in every module, by their names in a `QueryRequest`.
+/// Custom queries are left out, since they take any json and never fail to parse.
+const SUPPORTED_QUERIES: &[(&str, &[&str])] = &[
+    ("bank", &["balance", "all_balances"]),
+    (
+        "staking",
+        &[
+            "bonded_denom",
+            "all_delegations",
+            "delegation",
+            "validators",
+            "unbonding_delegations",
+        ],
+    ),
+    ("wasm", &["smart", "raw"]),
+    ("dist", &["rewards"]),
+    ("mint", &["inflation", "bonded_ratio"]),
+    ("gov", &["proposals"]),
+];
+
 pub fn encrypt_and_query_chain(
     query: &[u8],
     context: &Ctx,
@@ -31,11 +62,32 @@ pub fn encrypt_and_query_chain(
         Ok(query_struct) => query_struct,
         Err(err) => {
             *gas_used = 500; // Should we charge gas for this to prevent spam?
+            if let Some(kind) = unsupported_query_kind(query) {
+                return system_error_unsupported_request(kind);
+            }
             return system_error_invalid_request(query, err);
         }
     };
 
-    let is_encrypted = encrypt_query_request(&mut query_struct, nonce, user_public_key)?;
+    // Queries of other contracts are encrypted with a key that is bound to the code hash of the
+    // queried contract, so only a contract with that code hash can decrypt the query or encrypt
+    // a response that we accept
+    let queried_code_hash = match queried_code_hash(&query_struct) {
+        Ok(code_hash) => code_hash,
+        Err(err) => return system_error_invalid_request(query, err),
+    };
+    let query_key = match queried_code_hash {
+        Some(code_hash) => {
+            let key = calc_query_encryption_key(&nonce, &user_public_key, &code_hash)
+                .map_err(|_| WasmEngineError::EncryptionError)?;
+            Some((key, code_hash))
+        }
+        None => None,
+    };
+
+    if let Some((key, _)) = &query_key {
+        encrypt_query_request(&mut query_struct, key, nonce, user_public_key)?;
+    }
 
     let encrypted_query = serde_json::to_vec(&query_struct).map_err(|err| {
         // this should never happen
@@ -53,9 +105,10 @@ pub fn encrypt_and_query_chain(
     *gas_used = query_used_gas;
     let encrypted_answer_as_vec = result?;
 
-    if !is_encrypted {
-        return Ok(encrypted_answer_as_vec);
-    }
+    let (key, code_hash) = match query_key {
+        Some(query_key) => query_key,
+        None => return check_module_response(&query_struct, encrypted_answer_as_vec),
+    };
 
     // answer is QueryResult (Result<Result<Binary,StdError>,SystemError>) encoded by serde to bytes.
     // we need to:
@@ -80,7 +133,7 @@ pub fn encrypt_and_query_chain(
         Err(_) => encrypted_answer,
         // normal response from contract
         Ok(Ok(result)) => {
-            let decrypted = decrypt_query_response(query, result.0, nonce, user_public_key)?;
+            let decrypted = decrypt_query_response(query, result.0, &key, &code_hash)?;
             Ok(Ok(Binary(decrypted)))
         }
         // error response from contract, or critical error in called VM
@@ -101,7 +154,7 @@ pub fn encrypt_and_query_chain(
                     }
                     Ok(error) => {
                         let decrypted =
-                            decrypt_query_response_error(query, error, nonce, user_public_key)?;
+                            decrypt_query_response_error(query, error, &key, &code_hash)?;
                         match serde_json::from_slice::<StdError>(&decrypted) {
                             Ok(answer) => Ok(Err(answer)),
                             Err(err) => {
@@ -123,7 +176,7 @@ pub fn encrypt_and_query_chain(
         }
     };
 
-    trace!(
+    trace_secret!(
         "encrypt_and_query_chain() decrypted the answer to be: {:?}",
         answer
     );
@@ -136,12 +189,75 @@ pub fn encrypt_and_query_chain(
     Ok(answer_as_vec)
 }
 
+/// What the host stores for a contract: its contract key, and the hash of the code the contract
+/// info points to
+#[derive(Deserialize, Debug, Default)]
+pub struct ContractKeyAnswer {
+    #[serde(default)]
+    pub contract_key: Binary,
+    #[serde(default)]
+    pub code_hash: Binary,
+}
+
+/// What the host stored for the contract at `contract_addr`, or None if it has no contract there.
+/// The host can answer anything, so the caller must authenticate the key for the code hash.
+///
+/// Contracts can't make this query themselves, since it isn't part of the `QueryRequest` they
+/// pass to `query_chain`.
+pub fn query_contract_key(
+    context: &Ctx,
+    contract_addr: &str,
+    gas_used: &mut u64,
+    gas_limit: u64,
+) -> Result<Option<ContractKeyAnswer>, WasmEngineError> {
+    let query = serde_json::to_vec(&serde_json::json!({
+        "wasm": { "contract_key": { "contract_addr": contract_addr } }
+    }))
+    .map_err(|_| WasmEngineError::SerializationError)?;
+
+    let (result, query_used_gas) = query_chain(context, &query, gas_limit);
+    *gas_used = query_used_gas;
+    let answer = result?;
+
+    match serde_json::from_slice::<SystemResult<StdResult<Binary>>>(&answer) {
+        Ok(Ok(Ok(answer))) => {
+            match serde_json::from_slice::<ContractKeyAnswer>(answer.as_slice()) {
+                Ok(answer) if !answer.contract_key.is_empty() => Ok(Some(answer)),
+                Ok(_) => Ok(None),
+                Err(err) => {
+                    debug!(
+                        "query_contract_key() got a malformed answer from the host: {:?}",
+                        err
+                    );
+                    Ok(None)
+                }
+            }
+        }
+        Ok(answer) => {
+            debug!(
+                "query_contract_key() got an error from the host: {:?}",
+                answer
+            );
+            Ok(None)
+        }
+        Err(err) => {
+            debug!(
+                "query_contract_key() got a malformed answer from the host: {:?}",
+                err
+            );
+            Ok(None)
+        }
+    }
+}
+
 /// Safe wrapper around quering other contracts and modules
 fn query_chain(
     context: &Ctx,
     query: &[u8],
     gas_limit: u64,
 ) -> (Result<Vec<u8>, WasmEngineError>, u64) {
+    metrics::record_ocall(Ocall::QueryChain);
+    let _span = trace_span!(Phase::Ocall(Ocall::QueryChain));
     let mut ocall_return = OcallReturn::Success;
     let mut enclave_buffer = std::mem::MaybeUninit::<EnclaveBuffer>::uninit();
     let mut vm_err = UntrustedVmError::default();
@@ -179,7 +295,7 @@ fn query_chain(
                     Err(err) => return (Err(err.into()), gas_used),
                 }
             }
-            OcallReturn::Failure => return (Err(WasmEngineError::FailedOcall(vm_err)), gas_used),
+            OcallReturn::Failure => return (Err(WasmEngineError::failed_ocall(vm_err)), gas_used),
             OcallReturn::Panic => return (Err(WasmEngineError::Panic), gas_used),
         }
     };
@@ -231,6 +347,16 @@ where
     serialize_error_response(&answer)
 }
 
+fn system_error_unsupported_request(kind: String) -> Result<Vec<u8>, WasmEngineError> {
+    debug!(
+        "encrypt_and_query_chain() got an unsupported query: {}",
+        kind
+    );
+    let answer: SystemResult<StdResult<Binary>> = Err(SystemError::UnsupportedRequest { kind });
+
+    serialize_error_response(&answer)
+}
+
 fn serialize_error_response(
     answer: &SystemResult<StdResult<Binary>>,
 ) -> Result<Vec<u8>, WasmEngineError> {
@@ -246,13 +372,114 @@ fn serialize_error_response(
     })
 }
 
+/// Returns the module and variant a query asked for, if it failed to parse only because this
+/// enclave doesn't support them
+fn unsupported_query_kind(query: &[u8]) -> Option<String> {
+    let query: serde_json::Value = serde_json::from_slice(query).ok()?;
+    let (module, request) = single_entry(&query)?;
+    let variants = match SUPPORTED_QUERIES
+        .iter()
+        .find(|(name, _)| *name == module.as_str())
+    {
+        Some((_, variants)) => variants,
+        None => return Some(module.clone()),
+    };
+
+    let (variant, _) = single_entry(request)?;
+    if variants.contains(&variant.as_str()) {
+        None
+    } else {
+        Some(format!("{}/{}", module, variant))
+    }
+}
+
+/// The only entry of a json object, which is how serde encodes an enum variant
+fn single_entry(value: &serde_json::Value) -> Option<(&String, &serde_json::Value)> {
+    match value.as_object() {
+        Some(object) if object.len() == 1 => object.iter().next(),
+        _ => None,
+    }
+}
+
+/// Answers of modules are passed to the contract as they are, but only after we made sure they
+/// deserialize into the type the contract expects for its query
+fn check_module_response(
+    query_struct: &QueryRequest,
+    answer: Vec<u8>,
+) -> Result<Vec<u8>, WasmEngineError> {
+    let response = match serde_json::from_slice::<SystemResult<StdResult<Binary>>>(&answer) {
+        Ok(Ok(Ok(response))) => response,
+        // Errors from the module
+        Ok(_) => return Ok(answer),
+        Err(err) => return system_error_invalid_response(answer, err),
+    };
+
+    if let Err(err) = parse_module_response(query_struct, &response.0) {
+        debug!(
+            "encrypt_and_query_chain() got an answer that doesn't fit the query {:?}: {:?}",
+            query_struct, err
+        );
+        return system_error_invalid_response(response.0, err);
+    }
+
+    Ok(answer)
+}
+
+fn parse_module_response(
+    query_struct: &QueryRequest,
+    response: &[u8],
+) -> Result<(), serde_json::Error> {
+    match query_struct {
+        QueryRequest::Staking(query) => match query {
+            StakingQuery::BondedDenom {} => parse_as::<BondedDenomResponse>(response),
+            StakingQuery::AllDelegations { .. } => parse_as::<AllDelegationsResponse>(response),
+            StakingQuery::Delegation { .. } => parse_as::<DelegationResponse>(response),
+            StakingQuery::Validators {} => parse_as::<ValidatorsResponse>(response),
+            StakingQuery::UnbondingDelegations { .. } => {
+                parse_as::<UnbondingDelegationsResponse>(response)
+            }
+        },
+        QueryRequest::Dist(DistQuery::Rewards { .. }) => parse_as::<RewardsResponse>(response),
+        QueryRequest::Gov(GovQuery::Proposals {}) => parse_as::<ProposalsResponse>(response),
+        QueryRequest::Mint(MintQuery::Inflation {}) => parse_as::<InflationResponse>(response),
+        QueryRequest::Mint(MintQuery::BondedRatio {}) => parse_as::<BondedRatioResponse>(response),
+        // Bank and custom answers are up to the contract, and raw queries return stored bytes
+        _ => Ok(()),
+    }
+}
+
+fn parse_as<T: DeserializeOwned>(response: &[u8]) -> Result<(), serde_json::Error> {
+    serde_json::from_slice::<T>(response).map(|_| ())
+}
+
+/// Returns the code hash of the contract that is queried, if the query is for a contract
+fn queried_code_hash(query_struct: &QueryRequest) -> Result<Option<[u8; HASH_SIZE]>, String> {
+    let callback_code_hash = match query_struct {
+        QueryRequest::Wasm(WasmQuery::Smart {
+            callback_code_hash, ..
+        }) => callback_code_hash,
+        _ => return Ok(None),
+    };
+
+    let decoded = hex::decode(callback_code_hash).unwrap_or_default();
+    if decoded.len() != HASH_SIZE {
+        return Err(format!(
+            "callback_code_hash {:?} is not a hex encoded code hash",
+            callback_code_hash
+        ));
+    }
+
+    let mut code_hash = [0u8; HASH_SIZE];
+    code_hash.copy_from_slice(&decoded);
+    Ok(Some(code_hash))
+}
+
 fn encrypt_query_request(
     query_struct: &mut QueryRequest,
+    key: &AESKey,
     nonce: IoNonce,
     user_public_key: Ed25519PublicKey,
-) -> Result<bool, WasmEngineError> {
-    let mut is_encrypted = false;
-
+) -> Result<(), WasmEngineError> {
     // encrypt message
     if let QueryRequest::Wasm(WasmQuery::Smart {
         msg,
@@ -260,17 +487,16 @@ fn encrypt_query_request(
         ..
     }) = query_struct
     {
-        is_encrypted = true;
-
         let mut hash_appended_msg = callback_code_hash.clone().into_bytes();
         hash_appended_msg.extend_from_slice(&msg.0);
 
-        let mut encrypted_msg = SecretMessage {
-            msg: hash_appended_msg,
+        let mut secret_msg = SecretMessage {
+            msg: vec![],
             user_public_key,
             nonce,
         };
-        encrypted_msg.encrypt_in_place().map_err(|err| {
+        let ad = secret_msg.associated_data();
+        let encrypted_msg = key.encrypt_siv(&hash_appended_msg, Some(&ad)).map_err(|err| {
             debug!(
                 "encrypt_and_query_chain() got an error while trying to encrypt the request for query {:?}, stopping wasm: {:?}",
                 String::from_utf8_lossy(&msg.0),
@@ -279,34 +505,29 @@ fn encrypt_query_request(
 
             WasmEngineError::EncryptionError
         })?;
+        secret_msg.msg = encrypted_msg;
 
-        *msg = Binary(encrypted_msg.to_vec());
+        *msg = Binary(secret_msg.to_vec());
     };
 
-    Ok(is_encrypted)
+    Ok(())
 }
 
 fn decrypt_query_response(
     query: &[u8],
     response: Vec<u8>,
-    nonce: IoNonce,
-    user_public_key: Ed25519PublicKey,
+    key: &AESKey,
+    code_hash: &[u8; HASH_SIZE],
 ) -> Result<Vec<u8>, WasmEngineError> {
     // query response returns without nonce and user_public_key appended to it
     // because the sender is supposed to have them already
-    let as_secret_msg = SecretMessage {
-        nonce,
-        user_public_key,
-        msg: response,
-    };
-
-    let b64_decrypted = as_secret_msg.decrypt().map_err(|err| {
+    let b64_decrypted = open_query_response(key, code_hash, &response).map_err(|err| {
         debug!(
             "encrypt_and_query_chain() got an error while trying to decrypt the result for query {:?}, stopping wasm: {:?}",
             String::from_utf8_lossy(query),
             err
         );
-        WasmEngineError::DecryptionError
+        WasmEngineError::QueryCodeHashMismatch
     })?;
 
     base64::decode(&b64_decrypted).map_err(|err| {
@@ -321,21 +542,114 @@ fn decrypt_query_response(
 fn decrypt_query_response_error(
     query: &[u8],
     error: Vec<u8>,
-    nonce: IoNonce,
-    user_public_key: Ed25519PublicKey,
+    key: &AESKey,
+    code_hash: &[u8; HASH_SIZE],
 ) -> Result<Vec<u8>, WasmEngineError> {
-    let error_msg = SecretMessage {
-        nonce,
-        user_public_key,
-        msg: error,
-    };
-
-    error_msg.decrypt().map_err(|err| {
+    open_query_response(key, code_hash, &error).map_err(|err| {
         debug!(
             "encrypt_and_query_chain() got an error while trying to decrypt the inner error for query {:?}, stopping wasm: {:?}",
             String::from_utf8_lossy(&query),
             err
         );
-        WasmEngineError::DecryptionError
+        WasmEngineError::QueryCodeHashMismatch
     })
 }
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use crate::cosmwasm::types::HumanAddr;
+
+    fn module_answer(response: &str) -> Vec<u8> {
+        let answer: SystemResult<StdResult<Binary>> = Ok(Ok(Binary(response.as_bytes().to_vec())));
+        serde_json::to_vec(&answer).unwrap()
+    }
+
+    fn delegation_query() -> QueryRequest {
+        QueryRequest::Staking(StakingQuery::Delegation {
+            delegator: HumanAddr("secret1delegator".to_string()),
+            validator: HumanAddr("secretvaloper1validator".to_string()),
+        })
+    }
+
+    pub fn test_unsupported_query_kind() {
+        assert_eq!(
+            unsupported_query_kind(br#"{"ibc":{"port_id":{}}}"#),
+            Some("ibc".to_string())
+        );
+        assert_eq!(
+            unsupported_query_kind(br#"{"staking":{"validator_commission":{}}}"#),
+            Some("staking/validator_commission".to_string())
+        );
+        // Known variants with bad arguments are invalid, not unsupported
+        assert_eq!(
+            unsupported_query_kind(br#"{"staking":{"delegation":{"delegator":1}}}"#),
+            None
+        );
+        assert_eq!(unsupported_query_kind(b"not json"), None);
+        assert_eq!(unsupported_query_kind(br#"{"bank":{},"staking":{}}"#), None);
+    }
+
+    pub fn test_unsupported_query_is_visible_to_the_contract() {
+        let answer = system_error_unsupported_request("gov/votes".to_string()).unwrap();
+        let answer: SystemResult<StdResult<Binary>> = serde_json::from_slice(&answer).unwrap();
+
+        assert!(matches!(
+            answer,
+            Err(SystemError::UnsupportedRequest { kind }) if kind == "gov/votes"
+        ));
+    }
+
+    pub fn test_module_response_is_passed_as_is() {
+        let delegation = r#"{"delegation":{"delegator":"secret1delegator","validator":"secretvaloper1validator","amount":{"denom":"uscrt","amount":"80000"},"can_redelegate":{"denom":"uscrt","amount":"0"},"accumulated_rewards":{"denom":"uscrt","amount":"0"}}}"#;
+        let answer = module_answer(delegation);
+        assert_eq!(
+            check_module_response(&delegation_query(), answer.clone()).unwrap(),
+            answer
+        );
+
+        // No delegation
+        let answer = module_answer("{}");
+        assert_eq!(
+            check_module_response(&delegation_query(), answer.clone()).unwrap(),
+            answer
+        );
+
+        // Omitted empty lists
+        let answer = module_answer("{}");
+        let proposals = QueryRequest::Gov(GovQuery::Proposals {});
+        assert_eq!(
+            check_module_response(&proposals, answer.clone()).unwrap(),
+            answer
+        );
+
+        let validators = r#"{"validators":[{"address":"secretvaloper1validator","commission":"0.100000000000000000","max_commission":"0.200000000000000000","max_change_rate":"0.010000000000000000"}]}"#;
+        let answer = module_answer(validators);
+        let query = QueryRequest::Staking(StakingQuery::Validators {});
+        assert_eq!(
+            check_module_response(&query, answer.clone()).unwrap(),
+            answer
+        );
+    }
+
+    pub fn test_module_response_of_the_wrong_type() {
+        let answer = module_answer(r#"{"denom":"uscrt"}"#);
+        let answer = check_module_response(&delegation_query(), answer).unwrap();
+        let answer: SystemResult<StdResult<Binary>> = serde_json::from_slice(&answer).unwrap();
+
+        assert!(matches!(answer, Err(SystemError::InvalidResponse { .. })));
+    }
+
+    pub fn test_module_error_is_passed_as_is() {
+        let answer: SystemResult<StdResult<Binary>> = Ok(Err(StdError::GenericErr {
+            msg: "delegator not found".to_string(),
+        }));
+        let answer = serde_json::to_vec(&answer).unwrap();
+
+        assert_eq!(
+            check_module_response(&delegation_query(), answer.clone()).unwrap(),
+            answer
+        );
+    }
+}
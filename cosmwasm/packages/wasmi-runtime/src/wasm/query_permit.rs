@@ -0,0 +1,367 @@
+//! Query permits, which tell a contract who is querying it. Queries aren't signed like txs, so
+//! without a permit the contract only knows what the query says about its sender.
+//!
+//! A query with a permit is sent as `{"with_permit":{"permit":<QueryPermit>,"query":<query>}}`.
+//! The contract gets the inner query, and the address that signed the permit in `env.query_sender`
+//! if the permit is valid. Invalid permits don't fail the query, they only leave the sender out,
+//! so the contract decides what to answer to an unknown sender.
+//!
+//! A permit only counts for a contract whose key the enclave authenticated for its address, and
+//! until its signer revokes it. The signer revokes its permits with a name by sending the contract
+//! `{"revoke_query_permit":{"permit_name":<name>}}`. The contract gets the message like any other,
+//! and the revocation is only kept if the contract handles it successfully.
+
+use log::*;
+use serde::{Deserialize, Serialize};
+
+use crate::cosmwasm::types::{CanonicalAddr, CosmosSignature, Env, HumanAddr};
+use crate::crypto::traits::PubKey;
+
+use super::addresses::addr_humanize;
+
+/// The `type` of permit sign docs. Tx sign docs don't have a `type`, so a signature over one of
+/// them can't be presented as a permit.
+const PERMIT_SIGN_DOC_TYPE: &str = "query_permit";
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct QueryPermit {
+    pub params: PermitParams,
+    pub signature: CosmosSignature,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PermitParams {
+    /// The contracts that may see the signer of the permit
+    pub allowed_contracts: Vec<HumanAddr>,
+    pub chain_id: String,
+    /// The block time, in seconds since epoch, from which the permit isn't valid anymore
+    pub expiry: u64,
+    /// The name the signer revokes the permit by
+    pub permit_name: String,
+}
+
+/// The doc the signer of a permit signs. The fields are serialized in the order of their names,
+/// without whitespace, so every client that sorts the keys of the doc signs the same bytes.
+#[derive(Serialize)]
+struct PermitSignDoc<'a> {
+    allowed_contracts: &'a [HumanAddr],
+    chain_id: &'a str,
+    expiry: u64,
+    permit_name: &'a str,
+    #[serde(rename = "type")]
+    doc_type: &'a str,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WithPermit {
+    with_permit: PermitQuery,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PermitQuery {
+    // Parsed on its own, so a malformed permit still lets the query through
+    permit: serde_json::Value,
+    query: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RevokeQueryPermit {
+    revoke_query_permit: RevokedPermit,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RevokedPermit {
+    permit_name: String,
+}
+
+pub fn permit_sign_bytes(params: &PermitParams) -> Vec<u8> {
+    serde_json::to_vec(&PermitSignDoc {
+        allowed_contracts: &params.allowed_contracts,
+        chain_id: &params.chain_id,
+        expiry: params.expiry,
+        permit_name: &params.permit_name,
+        doc_type: PERMIT_SIGN_DOC_TYPE,
+    })
+    .unwrap()
+}
+
+/// Take the permit out of a query sent with one. Returns the query the contract should get, and
+/// the permit if the query had one that could be parsed. Queries without a permit are returned
+/// as they are.
+pub fn take_query_permit(msg: Vec<u8>) -> (Vec<u8>, Option<QueryPermit>) {
+    let with_permit: WithPermit = match serde_json::from_slice(&msg) {
+        Ok(with_permit) => with_permit,
+        Err(_) => return (msg, None),
+    };
+
+    let query = match serde_json::to_vec(&with_permit.with_permit.query) {
+        Ok(query) => query,
+        Err(err) => {
+            warn!(
+                "got an error while trying to serialize the query of a permit: {}",
+                err
+            );
+            return (msg, None);
+        }
+    };
+
+    let permit = serde_json::from_value(with_permit.with_permit.permit)
+        .map_err(|err| warn!("got a malformed query permit: {}", err))
+        .ok();
+
+    (query, permit)
+}
+
+/// The name of the permits that the sender of `msg` revokes, if `msg` revokes permits
+pub fn permit_revocation(msg: &[u8]) -> Option<String> {
+    serde_json::from_slice::<RevokeQueryPermit>(msg)
+        .ok()
+        .map(|revocation| revocation.revoke_query_permit.permit_name)
+}
+
+impl QueryPermit {
+    /// The address of the key that signed the permit, whether or not the signature is valid
+    pub fn signer(&self) -> CanonicalAddr {
+        self.signature.get_public_key().get_address()
+    }
+}
+
+/// The address that signed `permit`, if the permit is valid for a query of the contract in `env`.
+/// Addresses are humanized with `bech32_prefix`, the same as in the `addr_*` imports.
+pub fn verify_query_permit(
+    permit: &QueryPermit,
+    env: &Env,
+    bech32_prefix: &str,
+) -> Option<HumanAddr> {
+    let params = &permit.params;
+    if params.chain_id != env.block.chain_id {
+        warn!(
+            "got a query permit for chain {:?} on chain {:?}",
+            params.chain_id, env.block.chain_id
+        );
+        return None;
+    }
+
+    if env.block.time >= params.expiry {
+        warn!(
+            "got a query permit that expired at {}, the block time is {}",
+            params.expiry, env.block.time
+        );
+        return None;
+    }
+
+    if !params.allowed_contracts.contains(&env.contract.address) {
+        warn!(
+            "got a query permit that doesn't allow the contract {}",
+            env.contract.address
+        );
+        return None;
+    }
+
+    let pub_key = permit.signature.get_public_key();
+    if let Err(err) = pub_key.verify_bytes(
+        &permit_sign_bytes(params),
+        permit.signature.get_signature().as_slice(),
+    ) {
+        warn!(
+            "failed to verify the signature of a query permit: {:?}",
+            err
+        );
+        return None;
+    }
+
+    match addr_humanize(pub_key.get_address().as_slice(), bech32_prefix) {
+        Ok(sender) => Some(HumanAddr(sender)),
+        Err(err) => {
+            warn!(
+                "the signer of a query permit has an invalid address: {}",
+                err
+            );
+            None
+        }
+    }
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use crate::cosmwasm::encoding::Binary;
+    use crate::cosmwasm::types::{
+        BlockInfo, ContractInfo, MessageInfo, PubKeyKind, TransactionInfo,
+    };
+    use crate::crypto::secp256k1::Secp256k1PubKey;
+
+    const CHAIN_ID: &str = "secret-testnet";
+    const BLOCK_TIME: u64 = 1_000;
+
+    fn contract_address() -> HumanAddr {
+        HumanAddr("secret1qypqxpq9qcrsszg2pvxq6rs0zqg3yyc5a8chmq".to_string())
+    }
+
+    fn query_env() -> Env {
+        Env {
+            block: BlockInfo {
+                height: 1,
+                time: BLOCK_TIME,
+                time_nanos: 0,
+                chain_id: CHAIN_ID.to_string(),
+                hash: None,
+                proposer_address: None,
+            },
+            message: MessageInfo::default(),
+            contract: ContractInfo {
+                address: contract_address(),
+                admin: None,
+            },
+            contract_key: None,
+            contract_code_hash: String::new(),
+            random: None,
+            transaction: TransactionInfo::default(),
+            query_sender: None,
+            ocall_gas_costs: None,
+            bech32_prefix: None,
+            instantiation: None,
+            block_header: None,
+            execution_timeout_ms: None,
+            output_limits: None,
+            block_gas_remaining: None,
+            storage_quota: None,
+            max_memory_pages: None,
+            simulation: false,
+            client_query: false,
+            admin_proof: None,
+            execution_path: None,
+            reply_to: None,
+            foreign_chains: vec![],
+        }
+    }
+
+    fn params(expiry: u64) -> PermitParams {
+        PermitParams {
+            allowed_contracts: vec![contract_address()],
+            chain_id: CHAIN_ID.to_string(),
+            expiry,
+            permit_name: "balance".to_string(),
+        }
+    }
+
+    /// A permit signed with the secp256k1 key `secret`, and the address of that key
+    fn sign_permit(secret: u8, params: PermitParams) -> (QueryPermit, HumanAddr) {
+        use sha2::{Digest, Sha256};
+
+        let secp = secp256k1::Secp256k1::new();
+        let secret_key = secp256k1::SecretKey::from_slice(&[secret; 32]).unwrap();
+        let public_key = secp256k1::PublicKey::from_secret_key(&secp, &secret_key);
+        let message =
+            secp256k1::Message::from_slice(&Sha256::digest(&permit_sign_bytes(&params))).unwrap();
+        let signature = secp.sign(&message, &secret_key).serialize_compact();
+
+        let pub_key = PubKeyKind::Secp256k1(Secp256k1PubKey::new(public_key.serialize().to_vec()));
+        let address = HumanAddr(addr_humanize(pub_key.get_address().as_slice(), "secret").unwrap());
+
+        let permit = QueryPermit {
+            params,
+            signature: CosmosSignature::new(pub_key, Binary(signature.to_vec())),
+        };
+        (permit, address)
+    }
+
+    pub fn test_query_permit_verified() {
+        let (permit, signer) = sign_permit(1, params(BLOCK_TIME + 1));
+
+        assert_eq!(
+            verify_query_permit(&permit, &query_env(), "secret"),
+            Some(signer)
+        );
+    }
+
+    pub fn test_query_permit_expired() {
+        let (permit, _) = sign_permit(1, params(BLOCK_TIME));
+
+        assert_eq!(verify_query_permit(&permit, &query_env(), "secret"), None);
+    }
+
+    pub fn test_query_permit_other_contract() {
+        let mut params = params(BLOCK_TIME + 1);
+        params.allowed_contracts = vec![HumanAddr("secret1other".to_string())];
+        let (permit, _) = sign_permit(1, params);
+
+        assert_eq!(verify_query_permit(&permit, &query_env(), "secret"), None);
+    }
+
+    pub fn test_query_permit_other_chain() {
+        let mut params = params(BLOCK_TIME + 1);
+        params.chain_id = "secret-4".to_string();
+        let (permit, _) = sign_permit(1, params);
+
+        assert_eq!(verify_query_permit(&permit, &query_env(), "secret"), None);
+    }
+
+    pub fn test_query_permit_tampered() {
+        // The signer allowed the permit until the current block, and the expiry was moved later
+        let (mut permit, _) = sign_permit(1, params(BLOCK_TIME));
+        permit.params.expiry = BLOCK_TIME + 1_000;
+
+        assert_eq!(verify_query_permit(&permit, &query_env(), "secret"), None);
+
+        // A permit can't be renamed to escape its revocation
+        let (mut permit, _) = sign_permit(1, params(BLOCK_TIME + 1));
+        permit.params.permit_name = "renamed".to_string();
+
+        assert_eq!(verify_query_permit(&permit, &query_env(), "secret"), None);
+    }
+
+    pub fn test_permit_revocation() {
+        assert_eq!(
+            permit_revocation(br#"{"revoke_query_permit":{"permit_name":"balance"}}"#),
+            Some("balance".to_string())
+        );
+
+        // Other messages, including ones that only look like a revocation, revoke nothing
+        assert_eq!(permit_revocation(br#"{"transfer":{}}"#), None);
+        assert_eq!(
+            permit_revocation(br#"{"revoke_query_permit":{"permit_name":"balance","all":true}}"#),
+            None
+        );
+        assert_eq!(
+            permit_revocation(
+                br#"{"revoke_query_permit":{"permit_name":"balance"},"transfer":{}}"#
+            ),
+            None
+        );
+    }
+
+    pub fn test_take_query_permit() {
+        let (permit, _) = sign_permit(1, params(BLOCK_TIME + 1));
+        let msg = serde_json::to_vec(&serde_json::json!({
+            "with_permit": {
+                "permit": {
+                    "params": permit.params,
+                    "signature": permit.signature,
+                },
+                "query": {"balance": {}},
+            }
+        }))
+        .unwrap();
+
+        let (query, taken_permit) = take_query_permit(msg);
+        assert_eq!(query, br#"{"balance":{}}"#.to_vec());
+        assert_eq!(taken_permit, Some(permit));
+    }
+
+    pub fn test_take_query_permit_malformed() {
+        let msg = br#"{"with_permit":{"permit":{"params":{}},"query":{"balance":{}}}}"#.to_vec();
+        let (query, permit) = take_query_permit(msg);
+        assert_eq!(query, br#"{"balance":{}}"#.to_vec());
+        assert_eq!(permit, None);
+
+        // Queries without a permit get to the contract as they are
+        let msg = br#"{"with_permit":{},"balance":{}}"#.to_vec();
+        assert_eq!(take_query_permit(msg.clone()), (msg, None));
+    }
+}
@@ -0,0 +1,313 @@
+//! Replay protection for queries. Queries don't change state, but anyone who captured an encrypted
+//! query can send it again and watch how its encrypted answer changes over time.
+//!
+//! A client that doesn't want its query to be replayed sends it as
+//! `{"with_replay_protection":{"nonce":<base64>,"min_height":<u64>,"max_height":<u64>,"query":<query>}}`.
+//! The query is only answered while the block height in the env is between `min_height` and
+//! `max_height`, and only once for its nonce. Everything is inside the encrypted message, so the
+//! nonce and window of a captured query can't be changed. Queries without the envelope are
+//! answered as they always were.
+//!
+//! Every node keeps its own nonces, and the host chooses the env of a query, so this only stops
+//! replays against the node that answered the query while it tells the truth about the height.
+//! For the same reason only queries clients sent to the node use up their nonce. A contract can
+//! make the same query during a tx, which has to run the same on every node no matter which
+//! queries each of them answered before, so there only the window is checked.
+
+use std::sync::SgxMutex;
+
+use lazy_static::lazy_static;
+use log::*;
+use serde::Deserialize;
+
+use enclave_ffi_types::EnclaveError;
+
+use crate::consts::{
+    QUERY_REPLAY_MAX_WINDOW, QUERY_REPLAY_NONCE_SIZE, SEEN_QUERY_NONCES_CACHE_SIZE,
+};
+use crate::cosmwasm::encoding::Binary;
+use crate::cosmwasm::types::Env;
+
+use super::module_cache::LruCache;
+
+type QueryNonce = [u8; QUERY_REPLAY_NONCE_SIZE];
+
+lazy_static! {
+    /// The nonces of answered queries, with the last block height each of them may be answered in
+    static ref SEEN_QUERY_NONCES: SgxMutex<LruCache<QueryNonce, u64>> =
+        SgxMutex::new(LruCache::new(SEEN_QUERY_NONCES_CACHE_SIZE));
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WithReplayProtection {
+    with_replay_protection: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ReplayProtectedQuery {
+    nonce: Binary,
+    min_height: u64,
+    max_height: u64,
+    query: serde_json::Value,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReplayProtection {
+    pub nonce: QueryNonce,
+    pub min_height: u64,
+    pub max_height: u64,
+}
+
+/// Take the replay protection out of a query sent with it. Returns the query the contract should
+/// get, and the replay protection if the query had one. Queries without it are returned as they
+/// are.
+///
+/// Unlike a malformed permit, a malformed envelope fails the query, since the client asked for it
+/// not to be answered without the protection.
+pub fn take_replay_protection(
+    msg: Vec<u8>,
+) -> Result<(Vec<u8>, Option<ReplayProtection>), EnclaveError> {
+    let with_replay_protection: WithReplayProtection = match serde_json::from_slice(&msg) {
+        Ok(with_replay_protection) => with_replay_protection,
+        Err(_) => return Ok((msg, None)),
+    };
+
+    let protected: ReplayProtectedQuery =
+        serde_json::from_value(with_replay_protection.with_replay_protection).map_err(|err| {
+            warn!("got a query with malformed replay protection: {}", err);
+            EnclaveError::FailedToDeserialize
+        })?;
+
+    if protected.nonce.0.len() != QUERY_REPLAY_NONCE_SIZE {
+        warn!(
+            "got a query with a replay protection nonce of {} bytes",
+            protected.nonce.0.len()
+        );
+        return Err(EnclaveError::FailedToDeserialize);
+    }
+    let mut nonce = [0u8; QUERY_REPLAY_NONCE_SIZE];
+    nonce.copy_from_slice(&protected.nonce.0);
+
+    let query = serde_json::to_vec(&protected.query).map_err(|err| {
+        warn!(
+            "got an error while trying to serialize a replay protected query: {}",
+            err
+        );
+        EnclaveError::FailedToSerialize
+    })?;
+
+    Ok((
+        query,
+        Some(ReplayProtection {
+            nonce,
+            min_height: protected.min_height,
+            max_height: protected.max_height,
+        }),
+    ))
+}
+
+/// Check that a query sent with replay protection may be answered in the block of `env`, and for
+/// queries clients sent to the node, remember its nonce so it isn't answered again
+pub fn check_query_replay(
+    protection: &ReplayProtection,
+    env: Option<&Env>,
+) -> Result<(), EnclaveError> {
+    let (height, client_query) = match env {
+        Some(env) => (env.block.height, env.client_query),
+        None => {
+            warn!("got a replay protected query without an env");
+            return Err(EnclaveError::QueryOutsideReplayWindow);
+        }
+    };
+
+    if protection.max_height < protection.min_height
+        || protection.max_height - protection.min_height > QUERY_REPLAY_MAX_WINDOW
+    {
+        warn!(
+            "got a replay protected query with the window {}..={}, which is longer than {} blocks",
+            protection.min_height, protection.max_height, QUERY_REPLAY_MAX_WINDOW
+        );
+        return Err(EnclaveError::QueryOutsideReplayWindow);
+    }
+
+    if height < protection.min_height || height > protection.max_height {
+        debug!(
+            "got a replay protected query for the window {}..={} at height {}",
+            protection.min_height, protection.max_height, height
+        );
+        return Err(EnclaveError::QueryOutsideReplayWindow);
+    }
+
+    if !client_query {
+        return Ok(());
+    }
+
+    let mut seen_nonces = SEEN_QUERY_NONCES.lock().unwrap();
+    if let Some(&max_height) = seen_nonces.get(&protection.nonce) {
+        if height <= max_height {
+            debug!("got a replayed query");
+            return Err(EnclaveError::ReplayedQuery);
+        }
+    }
+
+    // Nonces of queries whose window ended can't be replayed anymore, so they make room first
+    if seen_nonces.len() >= SEEN_QUERY_NONCES_CACHE_SIZE {
+        seen_nonces.retain(|_, max_height| *max_height >= height);
+    }
+    seen_nonces.insert(protection.nonce, protection.max_height, 1);
+
+    Ok(())
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use crate::cosmwasm::types::{
+        BlockInfo, ContractInfo, HumanAddr, MessageInfo, TransactionInfo,
+    };
+
+    fn env_at(height: u64) -> Env {
+        Env {
+            block: BlockInfo {
+                height,
+                time: 1_000,
+                time_nanos: 0,
+                chain_id: "secret-testnet".to_string(),
+                hash: None,
+                proposer_address: None,
+            },
+            message: MessageInfo::default(),
+            contract: ContractInfo {
+                address: HumanAddr("secret1qypqxpq9qcrsszg2pvxq6rs0zqg3yyc5a8chmq".to_string()),
+                admin: None,
+            },
+            contract_key: None,
+            contract_code_hash: String::new(),
+            random: None,
+            transaction: TransactionInfo::default(),
+            query_sender: None,
+            ocall_gas_costs: None,
+            bech32_prefix: None,
+            instantiation: None,
+            block_header: None,
+            execution_timeout_ms: None,
+            output_limits: None,
+            block_gas_remaining: None,
+            storage_quota: None,
+            max_memory_pages: None,
+            simulation: false,
+            client_query: true,
+            admin_proof: None,
+            execution_path: None,
+            reply_to: None,
+            foreign_chains: vec![],
+        }
+    }
+
+    /// Every test uses its own nonces, since the nonces the enclave saw are kept between tests
+    fn replay_protection(nonce: u8, min_height: u64, max_height: u64) -> ReplayProtection {
+        ReplayProtection {
+            nonce: [nonce; QUERY_REPLAY_NONCE_SIZE],
+            min_height,
+            max_height,
+        }
+    }
+
+    pub fn test_take_replay_protection() {
+        let msg = format!(
+            r#"{{"with_replay_protection":{{"nonce":"{}","min_height":10,"max_height":20,"query":{{"balance":{{}}}}}}}}"#,
+            base64::encode(&[7u8; QUERY_REPLAY_NONCE_SIZE])
+        );
+        let (query, protection) = take_replay_protection(msg.into_bytes()).unwrap();
+        assert_eq!(query, br#"{"balance":{}}"#.to_vec());
+        assert_eq!(protection, Some(replay_protection(7, 10, 20)));
+
+        // Queries without replay protection are left as they are
+        let msg = br#"{"balance":{}}"#.to_vec();
+        let (query, protection) = take_replay_protection(msg.clone()).unwrap();
+        assert_eq!(query, msg);
+        assert_eq!(protection, None);
+
+        // The client asked for replay protection, so a malformed one fails the query
+        let msg = br#"{"with_replay_protection":{"nonce":"AAAA","min_height":10,"max_height":20,"query":{}}}"#;
+        assert!(matches!(
+            take_replay_protection(msg.to_vec()),
+            Err(EnclaveError::FailedToDeserialize)
+        ));
+    }
+
+    pub fn test_replayed_query_is_rejected() {
+        let protection = replay_protection(1, 10, 20);
+
+        assert!(check_query_replay(&protection, Some(&env_at(15))).is_ok());
+        assert!(matches!(
+            check_query_replay(&protection, Some(&env_at(15))),
+            Err(EnclaveError::ReplayedQuery)
+        ));
+        // Still within the window in a later block
+        assert!(matches!(
+            check_query_replay(&protection, Some(&env_at(20))),
+            Err(EnclaveError::ReplayedQuery)
+        ));
+
+        // Another nonce is another query
+        assert!(check_query_replay(&replay_protection(2, 10, 20), Some(&env_at(15))).is_ok());
+    }
+
+    pub fn test_query_outside_replay_window() {
+        let protection = replay_protection(3, 10, 20);
+
+        for height in &[9, 21] {
+            assert!(matches!(
+                check_query_replay(&protection, Some(&env_at(*height))),
+                Err(EnclaveError::QueryOutsideReplayWindow)
+            ));
+        }
+        assert!(matches!(
+            check_query_replay(&protection, None),
+            Err(EnclaveError::QueryOutsideReplayWindow)
+        ));
+
+        // Rejected queries don't use up their nonce
+        assert!(check_query_replay(&protection, Some(&env_at(10))).is_ok());
+    }
+
+    pub fn test_replay_window_too_long() {
+        let protection = replay_protection(4, 10, 11 + QUERY_REPLAY_MAX_WINDOW);
+        assert!(matches!(
+            check_query_replay(&protection, Some(&env_at(15))),
+            Err(EnclaveError::QueryOutsideReplayWindow)
+        ));
+
+        let protection = replay_protection(5, 20, 10);
+        assert!(matches!(
+            check_query_replay(&protection, Some(&env_at(15))),
+            Err(EnclaveError::QueryOutsideReplayWindow)
+        ));
+    }
+
+    pub fn test_queries_during_txs_dont_use_up_nonces() {
+        let protection = replay_protection(6, 10, 20);
+        let mut tx_env = env_at(15);
+        tx_env.client_query = false;
+
+        assert!(check_query_replay(&protection, Some(&tx_env)).is_ok());
+        assert!(check_query_replay(&protection, Some(&tx_env)).is_ok());
+        // The window still applies
+        tx_env.block.height = 21;
+        assert!(matches!(
+            check_query_replay(&protection, Some(&tx_env)),
+            Err(EnclaveError::QueryOutsideReplayWindow)
+        ));
+
+        // A client can still send it once
+        assert!(check_query_replay(&protection, Some(&env_at(15))).is_ok());
+        assert!(matches!(
+            check_query_replay(&protection, Some(&env_at(15))),
+            Err(EnclaveError::ReplayedQuery)
+        ));
+    }
+}
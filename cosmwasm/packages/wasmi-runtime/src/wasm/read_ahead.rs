@@ -0,0 +1,272 @@
+//! Predicts the storage keys a contract is about to read, so their values can be read from the
+//! host in a single ocall before the contract asks for them, see `prefetch_encrypted_keys`.
+//!
+//! Contracts that walk through a collection read keys that only differ in a counter at their end,
+//! e.g. the big-endian index of an item after the prefix of the collection. Once two reads in a
+//! row moved the counter by the same step, the next keys of the walk are predicted.
+//!
+//! The predictions only depend on the keys the contract read in the execution, so they are the
+//! same on every node. A wrong prediction costs the contract nothing, see `prefetch_encrypted_keys`.
+
+use log::*;
+
+/// How many keys ahead of the last read a walk is predicted
+const READ_AHEAD_DEPTH: u64 = 8;
+
+/// How many walks are followed at once. The one that was read from least recently is forgotten
+/// first.
+const MAX_ACCESS_PATTERNS: usize = 16;
+
+/// The most keys that are read ahead in a single execution. Reading ahead isn't charged, so this
+/// bounds what a contract can make the host read for free.
+const MAX_PREFETCHED_KEYS: usize = 1024;
+
+/// The most bytes at the end of a key that are read as its counter
+const MAX_COUNTER_LEN: usize = 8;
+
+/// A walk through keys that share a prefix, and end with a counter of the same length
+#[derive(Debug)]
+struct AccessPattern {
+    prefix: Vec<u8>,
+    counter_len: usize,
+    /// The counter of the last key that was read
+    last: u64,
+    /// How much the counter moved between the last two reads
+    step: Option<i64>,
+    /// The counter of the furthest key that was predicted with `step`
+    predicted_until: Option<u64>,
+}
+
+impl AccessPattern {
+    fn key(&self, counter: u64) -> Vec<u8> {
+        let mut key = self.prefix.clone();
+        key.extend_from_slice(&counter.to_be_bytes()[8 - self.counter_len..]);
+        key
+    }
+
+    fn max_counter(&self) -> u64 {
+        u64::MAX
+            .checked_shr(8 * (8 - self.counter_len) as u32)
+            .unwrap_or(0)
+    }
+
+    /// The counter `steps` steps of `step` after `counter`, if it fits in the counter
+    fn advance(&self, counter: u64, step: i64, steps: u64) -> Option<u64> {
+        let distance = (step.checked_abs()? as u64).checked_mul(steps)?;
+        let counter = if step > 0 {
+            counter.checked_add(distance)?
+        } else {
+            counter.checked_sub(distance)?
+        };
+        Some(counter).filter(|counter| *counter <= self.max_counter())
+    }
+
+    /// Follow the walk to `counter`, and return the counters of the keys to read ahead
+    fn observe(&mut self, counter: u64) -> Vec<u64> {
+        let step = counter.wrapping_sub(self.last) as i64;
+        if step == 0 {
+            return vec![];
+        }
+        self.last = counter;
+
+        if self.step != Some(step) {
+            self.step = Some(step);
+            self.predicted_until = None;
+            return vec![];
+        }
+
+        // Keys are read ahead in batches: a new batch is predicted once the walk is halfway
+        // through the last one, so most reads don't cause an ocall of their own
+        let start = match self.predicted_until {
+            Some(until) => {
+                let steps_left = until.wrapping_sub(counter) as i64 / step;
+                if steps_left > (READ_AHEAD_DEPTH / 2) as i64 {
+                    return vec![];
+                }
+                steps_left.max(0) as u64 + 1
+            }
+            None => 1,
+        };
+
+        let mut counters = vec![];
+        for steps in start..=READ_AHEAD_DEPTH {
+            match self.advance(counter, step, steps) {
+                Some(next) => counters.push(next),
+                None => break,
+            }
+        }
+        if let Some(last) = counters.last() {
+            self.predicted_until = Some(*last);
+        }
+        counters
+    }
+}
+
+/// Follows the keys a contract reads during an execution, and predicts the ones it reads next
+#[derive(Debug, Default)]
+pub struct ReadAhead {
+    /// The walks that were read from, least recently first
+    patterns: Vec<AccessPattern>,
+    prefetched_keys: usize,
+    disabled: bool,
+}
+
+impl ReadAhead {
+    /// Record that the contract read `key`, and return the keys it's predicted to read next
+    pub fn predict(&mut self, key: &[u8]) -> Vec<Vec<u8>> {
+        if self.disabled || self.prefetched_keys >= MAX_PREFETCHED_KEYS {
+            return vec![];
+        }
+
+        let counter_len = key.len().min(MAX_COUNTER_LEN);
+        let (prefix, counter_bytes) = key.split_at(key.len() - counter_len);
+        let mut counter = [0u8; 8];
+        counter[8 - counter_len..].copy_from_slice(counter_bytes);
+        let counter = u64::from_be_bytes(counter);
+
+        let position = self
+            .patterns
+            .iter()
+            .position(|pattern| pattern.counter_len == counter_len && pattern.prefix == prefix);
+        let mut pattern = match position {
+            Some(position) => self.patterns.remove(position),
+            None => {
+                if self.patterns.len() == MAX_ACCESS_PATTERNS {
+                    self.patterns.remove(0);
+                }
+                AccessPattern {
+                    prefix: prefix.to_vec(),
+                    counter_len,
+                    last: counter,
+                    step: None,
+                    predicted_until: None,
+                }
+            }
+        };
+
+        let mut keys: Vec<Vec<u8>> = pattern
+            .observe(counter)
+            .into_iter()
+            .map(|counter| pattern.key(counter))
+            .collect();
+        keys.truncate(MAX_PREFETCHED_KEYS - self.prefetched_keys);
+        self.prefetched_keys += keys.len();
+        self.patterns.push(pattern);
+
+        if !keys.is_empty() {
+            trace!("Reading ahead {} keys", keys.len());
+        }
+        keys
+    }
+
+    /// Stop reading ahead, e.g. because the host can't
+    pub fn disable(&mut self) {
+        self.disabled = true;
+    }
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    fn item_key(index: u32) -> Vec<u8> {
+        let mut key = b"\x00\x05items".to_vec();
+        key.extend_from_slice(&index.to_be_bytes());
+        key
+    }
+
+    pub fn test_read_ahead_follows_a_walk() {
+        let mut read_ahead = ReadAhead::default();
+
+        // The step is learned from the first two reads, and confirmed by the third
+        assert!(read_ahead.predict(&item_key(0)).is_empty());
+        assert!(read_ahead.predict(&item_key(1)).is_empty());
+        assert_eq!(
+            read_ahead.predict(&item_key(2)),
+            (3..=10).map(item_key).collect::<Vec<_>>()
+        );
+
+        // The next batch starts where the last one ended, once half of it was read
+        for index in 3..=5 {
+            assert!(read_ahead.predict(&item_key(index)).is_empty());
+        }
+        assert_eq!(
+            read_ahead.predict(&item_key(6)),
+            (11..=14).map(item_key).collect::<Vec<_>>()
+        );
+    }
+
+    pub fn test_read_ahead_follows_steps_and_directions() {
+        let mut read_ahead = ReadAhead::default();
+        read_ahead.predict(&item_key(100));
+        read_ahead.predict(&item_key(90));
+        assert_eq!(
+            read_ahead.predict(&item_key(80)),
+            (0..8)
+                .map(|step| item_key(70 - 10 * step))
+                .collect::<Vec<_>>()
+        );
+
+        // A walk that reaches the end of its counter stops there. Keys that are shorter than a
+        // counter are all counter.
+        let mut read_ahead = ReadAhead::default();
+        read_ahead.predict(&(u16::MAX - 4).to_be_bytes());
+        read_ahead.predict(&(u16::MAX - 3).to_be_bytes());
+        assert_eq!(
+            read_ahead.predict(&(u16::MAX - 2).to_be_bytes()),
+            vec![
+                (u16::MAX - 1).to_be_bytes().to_vec(),
+                u16::MAX.to_be_bytes().to_vec()
+            ]
+        );
+    }
+
+    pub fn test_read_ahead_follows_interleaved_walks() {
+        let mut read_ahead = ReadAhead::default();
+        let other_key = |index: u32| {
+            let mut key = b"\x00\x06owners".to_vec();
+            key.extend_from_slice(&index.to_be_bytes());
+            key
+        };
+
+        for index in 0..2 {
+            assert!(read_ahead.predict(&item_key(index)).is_empty());
+            assert!(read_ahead.predict(&other_key(index)).is_empty());
+            assert!(read_ahead.predict(b"config").is_empty());
+        }
+        assert_eq!(read_ahead.predict(&item_key(2)).len(), 8);
+        assert_eq!(read_ahead.predict(&other_key(2)).len(), 8);
+    }
+
+    pub fn test_read_ahead_relearns_a_changed_step() {
+        let mut read_ahead = ReadAhead::default();
+        read_ahead.predict(&item_key(0));
+        read_ahead.predict(&item_key(1));
+        assert!(!read_ahead.predict(&item_key(2)).is_empty());
+
+        // Reading a key again isn't a step
+        assert!(read_ahead.predict(&item_key(2)).is_empty());
+
+        // A different step has to be confirmed again
+        assert!(read_ahead.predict(&item_key(4)).is_empty());
+        assert_eq!(
+            read_ahead.predict(&item_key(6)),
+            (4..=11).map(|step| item_key(2 * step)).collect::<Vec<_>>()
+        );
+    }
+
+    pub fn test_read_ahead_is_bounded() {
+        let mut read_ahead = ReadAhead::default();
+        let mut predicted = 0;
+        for index in 0..10_000 {
+            predicted += read_ahead.predict(&item_key(index)).len();
+        }
+        assert_eq!(predicted, MAX_PREFETCHED_KEYS);
+
+        let mut read_ahead = ReadAhead::default();
+        read_ahead.disable();
+        for index in 0..10 {
+            assert!(read_ahead.predict(&item_key(index)).is_empty());
+        }
+    }
+}
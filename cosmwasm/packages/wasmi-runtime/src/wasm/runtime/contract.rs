@@ -1,22 +1,129 @@
+#[cfg(feature = "debug-print")]
+use std::sync::Arc;
+
 use bech32::{FromBase32, ToBase32};
 use log::*;
+use wasmi::memory_units::Pages;
 use wasmi::{Error as InterpreterError, MemoryInstance, MemoryRef, ModuleRef, RuntimeValue, Trap};
+use zeroize::Zeroizing;
 
 use enclave_ffi_types::Ctx;
 
-use crate::consts::BECH32_PREFIX_ACC_ADDR;
-use crate::crypto::Ed25519PublicKey;
-use crate::wasm::contract_validation::ContractKey;
-use crate::wasm::db::{read_encrypted_key, remove_encrypted_key, write_encrypted_key};
+use crate::consts::{
+    BECH32_PREFIX_ACC_ADDR, LEGACY_WASM_MEMORY_PAGES, MAX_DB_NAMESPACE_LENGTH,
+    MAX_READ_DB_MULTI_KEYS, MAX_SIGNING_KEY_PATH_LENGTH,
+};
+use crate::cosmwasm::encoding::Binary;
+use crate::cosmwasm::types::{
+    CanonicalAddr, ForeignHeader, ForeignHeaderVerification, SealedForeignChain,
+};
+use crate::crypto::secp256k1::{
+    secp256k1_recover_pubkey, ContractSigningKey, MESSAGE_HASH_SIZE,
+    SIGNATURE_SIZE as SECP256K1_SIGNATURE_SIZE,
+};
+use crate::crypto::{
+    ed25519_batch_verify, ed25519_verify, hkdf_sha256, keccak_256, ripemd_160, sha_256,
+    CryptoError, Ed25519PublicKey, HKDF_SHA256_MAX_OUTPUT_SIZE, KEY_MANAGER,
+};
+use crate::log_buffer;
+use crate::oom_handler;
+use crate::trace_secret;
+use crate::wasm::addresses::{addr_canonicalize, addr_humanize, addr_validate, AddressError};
+use crate::wasm::contract_validation::{contract_key_matches_code_hash, ContractKey};
+use crate::wasm::db::{
+    entry_len, flush_storage_overlay, move_key_to_namespace, prefetch_encrypted_keys,
+    read_encrypted_key, read_encrypted_keys, read_namespaced_key, read_query_permit_revocation,
+    read_storage_usage, read_used_tx_nonce, remove_encrypted_key, remove_namespaced_key,
+    scan_encrypted_keys, write_encrypted_key, write_namespaced_key, write_previous_contract_key,
+    write_query_permit_revocation, write_storage_usage, write_used_tx_nonce, RemovedStorage,
+    StorageOverlay, StorageUsage,
+};
+use crate::wasm::deadline::ExecutionDeadline;
 use crate::wasm::errors::WasmEngineError;
+use crate::wasm::foreign_chains::verify_foreign_header;
+use crate::wasm::io::{decode_code_hash, MessageRandomness};
+use crate::wasm::read_ahead::ReadAhead;
+use crate::wasm::runtime::debug_message::debug_line;
+use crate::wasm::runtime::sections::{decode_sections, encode_optional_sections};
 use crate::wasm::runtime::traits::WasmiApi;
-use crate::wasm::{gas::WasmCosts, query_chain::encrypt_and_query_chain, types::IoNonce};
+#[cfg(feature = "debug-print")]
+use crate::wasm::trap_info::TrapLocations;
+use crate::wasm::{
+    gas::{record_metered_gas, WasmCosts},
+    query_chain::{encrypt_and_query_chain, query_contract_key},
+    types::IoNonce,
+};
+
+/// Values returned to the contract by `ed25519_verify` and `ed25519_batch_verify`
+const ED25519_VERIFY_VALID: i32 = 0;
+const ED25519_VERIFY_INVALID: i32 = 1;
+const ED25519_VERIFY_MALFORMED_INPUT: i32 = 2;
+
+fn ed25519_verify_result(result: Result<(), CryptoError>) -> i32 {
+    match result {
+        Ok(()) => ED25519_VERIFY_VALID,
+        Err(CryptoError::VerificationError) => ED25519_VERIFY_INVALID,
+        Err(_) => ED25519_VERIFY_MALFORMED_INPUT,
+    }
+}
+
+/// Values returned to the contract by `secp256k1_recover_pubkey`
+const SECP256K1_RECOVER_SUCCESS: i32 = 0;
+const SECP256K1_RECOVER_INVALID_HASH_FORMAT: i32 = 1;
+const SECP256K1_RECOVER_INVALID_SIGNATURE_FORMAT: i32 = 2;
+const SECP256K1_RECOVER_INVALID_RECOVERY_PARAM: i32 = 3;
+const SECP256K1_RECOVER_FAILED: i32 = 4;
+
+/// Values returned to the contract by `secp256k1_sign` and `secp256k1_public_key`
+const SECP256K1_SIGN_SUCCESS: i32 = 0;
+const SECP256K1_SIGN_INVALID_KEY: i32 = 1;
+const SECP256K1_SIGN_INVALID_HASH_FORMAT: i32 = 2;
+const SECP256K1_SIGN_INVALID_OUTPUT: i32 = 3;
+const SECP256K1_SIGN_OUTPUT_TOO_SMALL: i32 = 4;
+
+/// Values of `order` passed by the contract to `db_scan`, as defined by cosmwasm-std
+const ORDER_ASCENDING: i32 = 1;
+const ORDER_DESCENDING: i32 = 2;
+
+/// The plaintext keys left to return from an iterator opened by `db_scan`.
+/// Values are read only when their key is reached, so they reflect writes made while iterating.
+struct KeyIterator {
+    keys: std::vec::IntoIter<Vec<u8>>,
+    descending: bool,
+}
+
+impl KeyIterator {
+    fn next_key(&mut self) -> Option<Vec<u8>> {
+        if self.descending {
+            self.keys.next_back()
+        } else {
+            self.keys.next()
+        }
+    }
+}
+
+/// Values returned to the contract by `hash_sha256`, `hash_keccak256` and `hash_ripemd160`
+const HASH_SUCCESS: i32 = 0;
+const HASH_INVALID_INPUT: i32 = 1;
+const HASH_INVALID_OUTPUT: i32 = 2;
+const HASH_OUTPUT_TOO_SMALL: i32 = 3;
+
+/// Values returned to the contract by `hkdf_sha256`
+const HKDF_SUCCESS: i32 = 0;
+const HKDF_INVALID_IKM: i32 = 1;
+const HKDF_INVALID_SALT: i32 = 2;
+const HKDF_INVALID_INFO: i32 = 3;
+const HKDF_INVALID_OUTPUT: i32 = 4;
+const HKDF_OUTPUT_TOO_SMALL: i32 = 5;
+const HKDF_INVALID_OUTPUT_LENGTH: i32 = 6;
 
 pub enum ContractOperation {
     Init,
     Handle,
     Query,
-    // Migrate. // not implemented
+    Migrate,
+    Reply,
+    Ibc,
 }
 
 #[allow(unused)]
@@ -29,9 +136,21 @@ impl ContractOperation {
         matches!(self, ContractOperation::Handle)
     }
 
-    fn is_query(&self) -> bool {
+    pub fn is_query(&self) -> bool {
         matches!(self, ContractOperation::Query)
     }
+
+    fn is_migrate(&self) -> bool {
+        matches!(self, ContractOperation::Migrate)
+    }
+
+    fn is_reply(&self) -> bool {
+        matches!(self, ContractOperation::Reply)
+    }
+
+    fn is_ibc(&self) -> bool {
+        matches!(self, ContractOperation::Ibc)
+    }
 }
 
 /// SecretContract maps function index to implementation
@@ -41,16 +160,74 @@ pub struct ContractInstance {
     pub context: Ctx,
     pub memory: MemoryRef,
     pub gas_limit: u64,
+    /// Set when `gas_limit` was lowered to the gas left in the block, so running out of it is
+    /// reported as `OutOfBlockGas`
+    pub gas_limited_by_block: bool,
     /// Gas used by wasmi
     pub gas_used: u64,
     /// Gas used by external services. This is tracked separately so we don't double-charge for external services later.
     pub gas_used_externally: u64,
+    /// Gas charged for storage: the part of `gas_used_externally` that the host charged for
+    /// storage ocalls, and `gas_used_by_overlay`
+    pub gas_used_by_storage: u64,
+    /// Gas of the storage reads that `storage_overlay` answered. The host didn't charge them, so
+    /// this is reported as used along with `gas_used`, like the reads the host charged are.
+    pub gas_used_by_overlay: u64,
+    /// Fixed gas charged for the round trips of ocalls. Unlike `gas_used_externally`, this isn't
+    /// charged by the host, so it's reported as used along with `gas_used`.
+    pub gas_used_by_ocalls: u64,
     pub gas_costs: WasmCosts,
     pub contract_key: ContractKey,
     pub module: ModuleRef,
     operation: ContractOperation,
     pub user_nonce: IoNonce,
     pub user_public_key: Ed25519PublicKey,
+    /// Not available in queries
+    randomness: Option<MessageRandomness>,
+    /// Iterators opened by `db_scan`. The iterator id is the index in this list plus one.
+    /// They are dropped with the instance, at the end of the ecall.
+    iterators: Vec<KeyIterator>,
+    /// Keys derived by `secp256k1_generate_key`, with ids like `iterators`
+    signing_keys: Vec<ContractSigningKey>,
+    /// The address of the contract, once the enclave authenticated its contract key for it. The
+    /// signing keys of the contract are derived from it.
+    pub contract_address: Option<CanonicalAddr>,
+    /// Storage removed during this execution, refunded if it succeeds
+    pub removed_storage: RemovedStorage,
+    /// Storage changes are kept here instead of being written. Not set for queries, which can't
+    /// write.
+    pub storage_overlay: Option<StorageOverlay>,
+    /// Whether the changes in `storage_overlay` are sent to the host, or discarded because the
+    /// execution is only simulated
+    pub flushes_storage: bool,
+    /// Predicts the keys the contract reads next, so they're read ahead into `storage_overlay`
+    read_ahead: ReadAhead,
+    /// How many bytes the contract stores. Read from its storage the first time it's needed, and
+    /// written back when the storage is flushed.
+    storage_usage: Option<StorageUsage>,
+    /// The most bytes the contract may store, see `StorageUsage`. Set by the chain through the env.
+    pub storage_quota: Option<u64>,
+    /// The prefix of the addresses handled by the `addr_*` imports. Set by the chain through the env.
+    pub bech32_prefix: String,
+    /// The most memory the contract may grow to, in pages. `LEGACY_WASM_MEMORY_PAGES` unless the
+    /// chain raised it through the env.
+    pub max_memory_pages: u32,
+    /// Set when the contract was refused memory past `max_memory_pages`
+    pub exceeded_memory_limit: bool,
+    /// Stops the contract once it ran for longer than the node allows. Set by the node through the
+    /// env.
+    pub execution_deadline: Option<ExecutionDeadline>,
+    /// The block time in the env, in seconds. Headers of other chains are verified at this time.
+    pub block_time: Option<u64>,
+    /// What the enclave trusts about the chains the chain tracks, which headers of other chains are
+    /// verified against. Set by the chain through the env.
+    pub foreign_chains: Vec<SealedForeignChain>,
+    /// Where each `unreachable` instruction of the contract is
+    #[cfg(feature = "debug-print")]
+    pub trap_locations: Arc<TrapLocations>,
+    /// The location the contract passed to `trap_location` right before it trapped
+    #[cfg(feature = "debug-print")]
+    pub trap_location: Option<u32>,
 }
 
 impl ContractInstance {
@@ -64,6 +241,7 @@ impl ContractInstance {
         operation: ContractOperation,
         user_nonce: IoNonce,
         user_public_key: Ed25519PublicKey,
+        randomness: Option<MessageRandomness>,
     ) -> Self {
         let memory = (&*module)
             .export_by_name("memory")
@@ -72,18 +250,44 @@ impl ContractInstance {
             .cloned()
             .expect("'memory' export should be of memory type");
 
+        record_metered_gas(0);
+
         Self {
             context,
             memory,
             gas_limit,
+            gas_limited_by_block: false,
             gas_used: 0,
             gas_used_externally: 0,
+            gas_used_by_storage: 0,
+            gas_used_by_overlay: 0,
+            gas_used_by_ocalls: 0,
             gas_costs,
             contract_key,
             module,
             operation,
             user_nonce,
             user_public_key,
+            randomness,
+            iterators: Vec::new(),
+            signing_keys: Vec::new(),
+            contract_address: None,
+            removed_storage: RemovedStorage::default(),
+            storage_overlay: None,
+            flushes_storage: false,
+            read_ahead: ReadAhead::default(),
+            storage_usage: None,
+            storage_quota: None,
+            bech32_prefix: BECH32_PREFIX_ACC_ADDR.to_string(),
+            max_memory_pages: LEGACY_WASM_MEMORY_PAGES,
+            exceeded_memory_limit: false,
+            execution_deadline: None,
+            block_time: None,
+            foreign_chains: Vec::new(),
+            #[cfg(feature = "debug-print")]
+            trap_locations: Arc::default(),
+            #[cfg(feature = "debug-print")]
+            trap_location: None,
         }
     }
 
@@ -116,6 +320,64 @@ impl ContractInstance {
         self.get_memory().get(ptr, len as usize)
     }
 
+    /// Like `extract_vector`, but an empty region may have a null buffer pointer
+    fn extract_possibly_empty_vector(&self, vec_ptr_ptr: u32) -> Result<Vec<u8>, InterpreterError> {
+        let len: u32 = self.get_memory().get_value(vec_ptr_ptr.saturating_add(8))?;
+        if len == 0 {
+            return Ok(vec![]);
+        }
+
+        self.extract_vector_inner(vec_ptr_ptr)
+    }
+
+    /// The buffer pointer and capacity of the region at `vec_ptr_ptr`
+    fn region_buffer(&self, vec_ptr_ptr: u32) -> Result<(u32, u32), InterpreterError> {
+        let ptr: u32 = self.get_memory().get_value(vec_ptr_ptr)?;
+        let cap: u32 = self.get_memory().get_value(vec_ptr_ptr.saturating_add(4))?;
+
+        Ok((ptr, cap))
+    }
+
+    /// Like `extract_vector`, but a null region pointer is read as `None`
+    fn extract_optional_vector(
+        &self,
+        vec_ptr_ptr: i32,
+    ) -> Result<Option<Vec<u8>>, WasmEngineError> {
+        if vec_ptr_ptr == 0 {
+            return Ok(None);
+        }
+        self.extract_vector(vec_ptr_ptr as u32).map(Some)
+    }
+
+    /// Read the namespace passed to one of the `db_*_ns` imports
+    fn extract_namespace(
+        &self,
+        function: &str,
+        namespace_ptr_ptr: i32,
+    ) -> Result<Vec<u8>, WasmEngineError> {
+        let namespace = self
+            .extract_vector(namespace_ptr_ptr as u32)
+            .map_err(|err| {
+                debug!(
+                    "{}() error while trying to read the namespace from wasm memory",
+                    function
+                );
+                err
+            })?;
+
+        if namespace.is_empty() || namespace.len() > MAX_DB_NAMESPACE_LENGTH {
+            debug!(
+                "{}() was called with a namespace of {} bytes, which isn't between 1 and {}",
+                function,
+                namespace.len(),
+                MAX_DB_NAMESPACE_LENGTH
+            );
+            return Err(WasmEngineError::InvalidDbNamespace);
+        }
+
+        Ok(namespace)
+    }
+
     pub fn allocate(&mut self, len: u32) -> Result<u32, WasmEngineError> {
         self.allocate_inner(len).map_err(|err| {
             debug!("Failed to allocate {} bytes in wasm: {}", len, err);
@@ -202,40 +464,578 @@ impl ContractInstance {
         self.write_to_allocated_memory(buffer, ptr_to_region_in_wasm_vm)
     }
 
+    /// Send the storage changes made so far to the host, unless the execution is simulated
+    pub fn flush_storage(&mut self) -> Result<(), WasmEngineError> {
+        self.persist_storage_usage()?;
+        match self.storage_overlay.as_mut() {
+            Some(overlay) if self.flushes_storage => flush_storage_overlay(&self.context, overlay),
+            _ => Ok(()),
+        }
+    }
+
     /// Track gas used inside wasmi
+    /// Link the state of this contract to the contract key it had before being migrated
+    pub fn link_previous_contract_key(
+        &mut self,
+        previous_contract_key: &ContractKey,
+    ) -> Result<(), WasmEngineError> {
+        let gas_used = write_previous_contract_key(
+            &self.context,
+            self.storage_overlay.as_mut(),
+            &self.contract_key,
+            previous_contract_key,
+        )?;
+        self.use_ocall_gas(self.gas_costs.ocall.write_db)?;
+        self.use_storage_gas(gas_used)
+    }
+
+    /// Record that `signer` revoked its query permits named `permit_name`, see `query_permit`
+    pub fn revoke_query_permit(
+        &mut self,
+        signer: &CanonicalAddr,
+        permit_name: &str,
+    ) -> Result<(), WasmEngineError> {
+        let gas_used = write_query_permit_revocation(
+            &self.context,
+            self.storage_overlay.as_mut(),
+            &self.contract_key,
+            signer.as_slice(),
+            permit_name,
+        )?;
+        self.use_ocall_gas(self.gas_costs.ocall.write_db)?;
+        self.use_storage_gas(gas_used)
+    }
+
+    /// Record that the contract executed the tx message encrypted by `user_public_key` with
+    /// `nonce`, see `nonce_registry`
+    pub fn use_tx_nonce(
+        &mut self,
+        user_public_key: &Ed25519PublicKey,
+        nonce: &IoNonce,
+        height: u64,
+    ) -> Result<(), WasmEngineError> {
+        let gas_used = write_used_tx_nonce(
+            &self.context,
+            self.storage_overlay.as_mut(),
+            &self.contract_key,
+            user_public_key,
+            nonce,
+            height,
+        )?;
+        self.use_ocall_gas(self.gas_costs.ocall.write_db)?;
+        self.use_storage_gas(gas_used)
+    }
+
+    /// Whether the contract executed a tx message encrypted by `user_public_key` with `nonce`
+    pub fn is_tx_nonce_used(
+        &mut self,
+        user_public_key: &Ed25519PublicKey,
+        nonce: &IoNonce,
+    ) -> Result<bool, WasmEngineError> {
+        let (used, gas_used) = read_used_tx_nonce(
+            &self.context,
+            self.storage_overlay.as_ref(),
+            &self.contract_key,
+            user_public_key,
+            nonce,
+        )?;
+        self.use_ocall_gas(self.gas_costs.ocall.read_db)?;
+        self.use_storage_gas(gas_used)?;
+        Ok(used)
+    }
+
+    /// Whether `signer` revoked its query permits named `permit_name`
+    pub fn is_query_permit_revoked(
+        &mut self,
+        signer: &CanonicalAddr,
+        permit_name: &str,
+    ) -> Result<bool, WasmEngineError> {
+        let (revoked, gas_used) = read_query_permit_revocation(
+            &self.context,
+            self.storage_overlay.as_ref(),
+            &self.contract_key,
+            signer.as_slice(),
+            permit_name,
+        )?;
+        self.use_ocall_gas(self.gas_costs.ocall.read_db)?;
+        self.use_storage_gas(gas_used)?;
+        Ok(revoked)
+    }
+
+    /// Read ahead the keys the contract is predicted to read after `key`, see `ReadAhead`. The
+    /// values are kept in the storage overlay, so queries, which don't have one, don't read ahead.
+    /// This costs no gas, and a failure only means that the contract reads the keys the usual way.
+    fn prefetch_after(&mut self, key: &[u8]) {
+        let overlay = match self.storage_overlay.as_mut() {
+            Some(overlay) => overlay,
+            None => return,
+        };
+
+        let keys = self.read_ahead.predict(key);
+        if keys.is_empty() {
+            return;
+        }
+        if let Err(err) = prefetch_encrypted_keys(&keys, &self.context, overlay, &self.contract_key)
+        {
+            debug!(
+                "Couldn't read ahead, the contract reads its keys one by one from now on: {}",
+                err
+            );
+            self.read_ahead.disable();
+        }
+    }
+
+    /// How many bytes the contract stores, read from its storage the first time it's needed
+    fn storage_usage(&mut self) -> Result<StorageUsage, WasmEngineError> {
+        if let Some(usage) = self.storage_usage {
+            return Ok(usage);
+        }
+
+        let (usage, gas_used) = read_storage_usage(
+            &self.context,
+            self.storage_overlay.as_ref(),
+            &self.contract_key,
+        )?;
+        self.use_ocall_gas(self.gas_costs.ocall.read_db)?;
+        self.use_storage_gas(gas_used)?;
+
+        self.storage_usage = Some(usage);
+        Ok(usage)
+    }
+
+    /// Account for entries of `removed` bytes being replaced with entries of `added` bytes, see
+    /// `StorageUsage::change`. Called before the change is written, so a change that would bring
+    /// the contract past its quota is never made.
+    fn change_storage_usage(&mut self, removed: u64, added: u64) -> Result<(), WasmEngineError> {
+        let mut usage = self.storage_usage()?;
+        usage.change(removed, added, self.storage_quota)?;
+        self.storage_usage = Some(usage);
+        Ok(())
+    }
+
+    /// Account for the value of `key` in `namespace`, or outside of namespaces if it's `None`,
+    /// being replaced with a value of `value_len` bytes, or removed if that's `None`.
+    ///
+    /// Storage is only accounted for while the chain sets a quota. Reading the value that is
+    /// replaced costs an ocall, which contracts on chains without a quota shouldn't pay for.
+    fn change_entry_storage_usage(
+        &mut self,
+        namespace: Option<&[u8]>,
+        key: &[u8],
+        value_len: Option<usize>,
+    ) -> Result<(), WasmEngineError> {
+        if self.storage_quota.is_none() {
+            return Ok(());
+        }
+
+        let previous_len = self.stored_value_len(namespace, key)?;
+        self.change_storage_usage(
+            entry_len(key.len(), previous_len),
+            entry_len(key.len(), value_len),
+        )
+    }
+
+    /// The length of the value the contract stores for `key` in `namespace`, or outside of
+    /// namespaces if it's `None`
+    fn stored_value_len(
+        &mut self,
+        namespace: Option<&[u8]>,
+        key: &[u8],
+    ) -> Result<Option<usize>, WasmEngineError> {
+        let (value, gas_used) = match namespace {
+            Some(namespace) => read_namespaced_key(
+                namespace,
+                key,
+                &self.context,
+                self.storage_overlay.as_ref(),
+                &self.contract_key,
+            )?,
+            None => read_encrypted_key(
+                key,
+                &self.context,
+                self.storage_overlay.as_ref(),
+                &self.contract_key,
+            )?,
+        };
+        self.use_ocall_gas(self.gas_costs.ocall.read_db)?;
+        self.use_storage_gas(gas_used)?;
+
+        Ok(value.map(|value| value.len()))
+    }
+
+    /// Write the storage usage back to the contract storage, if the contract changed it. It only
+    /// changes while the chain sets a quota, see `change_entry_storage_usage`.
+    fn persist_storage_usage(&mut self) -> Result<(), WasmEngineError> {
+        let usage = match self.storage_usage {
+            Some(usage) if self.storage_quota.is_some() && usage.changed() => usage,
+            _ => return Ok(()),
+        };
+
+        let gas_used = write_storage_usage(
+            &self.context,
+            self.storage_overlay.as_mut(),
+            &self.contract_key,
+            &usage,
+        )?;
+        self.use_ocall_gas(self.gas_costs.ocall.write_db)?;
+        self.use_storage_gas(gas_used)?;
+
+        self.storage_usage = Some(StorageUsage::stored(usage.bytes()));
+        Ok(())
+    }
+
+    /// Write the value in the region at `value_ptr_ptr` for the key in the region at
+    /// `state_key_ptr_ptr`, for `write_db_index` and `try_write_db_index`
+    fn write_db(
+        &mut self,
+        state_key_ptr_ptr: i32,
+        value_ptr_ptr: i32,
+    ) -> Result<(), WasmEngineError> {
+        if self.operation.is_query() {
+            return Err(WasmEngineError::UnauthorizedWrite);
+        }
+
+        let state_key_name = self
+            .extract_vector(state_key_ptr_ptr as u32)
+            .map_err(|err| {
+                debug!("write_db() error while trying to read state_key_name from wasm memory");
+                err
+            })?;
+        let value = self.extract_vector(value_ptr_ptr as u32).map_err(|err| {
+            debug!("write_db() error while trying to read value from wasm memory");
+            err
+        })?;
+
+        trace!(
+            "write_db() was called from WASM code with state_key_name: {:?} value: {:?}",
+            String::from_utf8_lossy(&state_key_name),
+            String::from_utf8_lossy(&value),
+        );
+
+        self.change_entry_storage_usage(None, &state_key_name, Some(value.len()))?;
+
+        let used_gas = write_encrypted_key(
+            &state_key_name,
+            &value,
+            &self.context,
+            self.storage_overlay.as_mut(),
+            &self.contract_key,
+        )
+        .map_err(|err| {
+            debug!(
+                "write_db() error while trying to write the value to state: {:?}",
+                err
+            );
+            err
+        })?;
+        self.use_ocall_gas(self.gas_costs.ocall.write_db)?;
+        self.use_storage_gas(used_gas)?;
+
+        Ok(())
+    }
+
+    /// Like `write_db`, to the storage namespace in the region at `namespace_ptr_ptr`
+    fn write_db_ns(
+        &mut self,
+        namespace_ptr_ptr: i32,
+        state_key_ptr_ptr: i32,
+        value_ptr_ptr: i32,
+    ) -> Result<(), WasmEngineError> {
+        if self.operation.is_query() {
+            return Err(WasmEngineError::UnauthorizedWrite);
+        }
+
+        let namespace = self.extract_namespace("db_write_ns", namespace_ptr_ptr)?;
+        let state_key_name = self
+            .extract_vector(state_key_ptr_ptr as u32)
+            .map_err(|err| {
+                debug!("db_write_ns() error while trying to read state_key_name from wasm memory");
+                err
+            })?;
+        let value = self.extract_vector(value_ptr_ptr as u32).map_err(|err| {
+            debug!("db_write_ns() error while trying to read value from wasm memory");
+            err
+        })?;
+
+        trace_secret!(
+            "db_write_ns() was called from WASM code with namespace: {:?} state_key_name: {:?} value: {:?}",
+            String::from_utf8_lossy(&namespace),
+            String::from_utf8_lossy(&state_key_name),
+            String::from_utf8_lossy(&value),
+        );
+
+        self.change_entry_storage_usage(Some(&namespace), &state_key_name, Some(value.len()))?;
+
+        let used_gas = write_namespaced_key(
+            &namespace,
+            &state_key_name,
+            &value,
+            &self.context,
+            self.storage_overlay.as_mut(),
+            &self.contract_key,
+        )?;
+        self.use_ocall_gas(self.gas_costs.ocall.write_db)?;
+        self.use_storage_gas(used_gas)?;
+
+        Ok(())
+    }
+
+    /// What the `db_try_write*` imports return: 0 when the write succeeded, and a region with
+    /// the error when it would have brought the contract storage past its quota. Other errors
+    /// still stop the execution.
+    fn storage_quota_result(
+        &mut self,
+        function: &str,
+        result: Result<(), WasmEngineError>,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        match result {
+            Ok(()) => Ok(Some(RuntimeValue::I32(0))),
+            Err(WasmEngineError::StorageQuotaExceeded { usage, quota }) => {
+                let error = format!(
+                    "storage quota exceeded: the write would bring the contract storage to {} bytes, past its quota of {}",
+                    usage, quota
+                );
+                debug!(
+                    "{}() returned an error to the contract: {}",
+                    function, error
+                );
+                Ok(Some(RuntimeValue::I32(
+                    self.write_to_memory(error.as_bytes())? as i32,
+                )))
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
     fn use_gas(&mut self, gas_amount: u64) -> Result<(), WasmEngineError> {
         self.gas_used = self.gas_used.saturating_add(gas_amount);
         self.check_gas_usage()
     }
 
+    /// Modules don't charge for the size of their answers, which are all copied into the enclave
+    fn charge_query_answer(&mut self, answer_len: usize) -> Result<(), WasmEngineError> {
+        self.use_gas(
+            (self.gas_costs.external_query_chain_per_byte as u64).saturating_mul(answer_len as u64),
+        )
+    }
+
     /// Track gas used by external services (e.g. storage)
     fn use_gas_externally(&mut self, gas_amount: u64) -> Result<(), WasmEngineError> {
         self.gas_used_externally = self.gas_used_externally.saturating_add(gas_amount);
         self.check_gas_usage()
     }
 
+    /// Track gas charged for a storage operation. The host charged it, except for the reads the
+    /// storage overlay answered, which are tracked as used in the enclave.
+    fn use_storage_gas(&mut self, gas_amount: u64) -> Result<(), WasmEngineError> {
+        let served = self
+            .storage_overlay
+            .as_ref()
+            .map_or(0, StorageOverlay::take_served_gas)
+            .min(gas_amount);
+        self.gas_used_by_storage = self.gas_used_by_storage.saturating_add(gas_amount);
+        self.gas_used_by_overlay = self.gas_used_by_overlay.saturating_add(served);
+        self.use_gas_externally(gas_amount - served)
+    }
+
+    /// Track the fixed gas of an ocall round trip
+    fn use_ocall_gas(&mut self, gas_amount: u64) -> Result<(), WasmEngineError> {
+        self.gas_used_by_ocalls = self.gas_used_by_ocalls.saturating_add(gas_amount);
+        self.check_gas_usage()
+    }
+
+    /// The key `secp256k1_generate_key` returned `key_id` for
+    fn signing_key(&self, function: &str, key_id: i32) -> Option<&ContractSigningKey> {
+        let key = (key_id as u32 as usize)
+            .checked_sub(1)
+            .and_then(|index| self.signing_keys.get(index));
+        if key.is_none() {
+            debug!(
+                "{}() got the key id {}, which secp256k1_generate_key didn't return",
+                function, key_id
+            );
+        }
+
+        key
+    }
+
+    /// Check that the region at `output_ptr_ptr` can take `len` bytes
+    fn signing_output_status(&self, function: &str, output_ptr_ptr: u32, len: usize) -> i32 {
+        match self.region_buffer(output_ptr_ptr) {
+            Ok((0, _)) => {
+                debug!("{}() got an output region with a null pointer", function);
+                SECP256K1_SIGN_INVALID_OUTPUT
+            }
+            Ok((_, cap)) if (cap as usize) < len => {
+                debug!(
+                    "{}() got an output region of {} bytes for {} bytes",
+                    function, cap, len
+                );
+                SECP256K1_SIGN_OUTPUT_TOO_SMALL
+            }
+            Ok(_) => SECP256K1_SIGN_SUCCESS,
+            Err(err) => {
+                debug!("{}() could not read the output region: {:?}", function, err);
+                SECP256K1_SIGN_INVALID_OUTPUT
+            }
+        }
+    }
+
+    /// Hash the region at `input_ptr_ptr` into the region at `output_ptr_ptr`, and return the
+    /// status to the contract. Regions that can't be used are reported to the contract instead of
+    /// stopping it, so it can tell a bad input from a buffer it should make larger.
+    #[allow(clippy::too_many_arguments)]
+    fn hash(
+        &mut self,
+        function: &str,
+        hash: fn(&[u8]) -> Vec<u8>,
+        base_cost: u32,
+        per_byte_cost: u32,
+        input_ptr_ptr: i32,
+        output_ptr_ptr: i32,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        self.use_gas(base_cost as u64)?;
+
+        let input = match self.extract_possibly_empty_vector(input_ptr_ptr as u32) {
+            Ok(input) => input,
+            Err(err) => {
+                debug!("{}() could not read the input region: {:?}", function, err);
+                return Ok(Some(RuntimeValue::I32(HASH_INVALID_INPUT)));
+            }
+        };
+        self.use_gas((per_byte_cost as u64).saturating_mul(input.len() as u64))?;
+
+        let digest = hash(&input);
+
+        let output_ptr_ptr = output_ptr_ptr as u32;
+        match self.region_buffer(output_ptr_ptr) {
+            Ok((0, _)) => {
+                debug!("{}() got an output region with a null pointer", function);
+                return Ok(Some(RuntimeValue::I32(HASH_INVALID_OUTPUT)));
+            }
+            Ok((_, output_cap)) if (output_cap as usize) < digest.len() => {
+                debug!(
+                    "{}() got an output region of {} bytes for a {} bytes digest",
+                    function,
+                    output_cap,
+                    digest.len()
+                );
+                return Ok(Some(RuntimeValue::I32(HASH_OUTPUT_TOO_SMALL)));
+            }
+            Ok(_) => {}
+            Err(err) => {
+                debug!("{}() could not read the output region: {:?}", function, err);
+                return Ok(Some(RuntimeValue::I32(HASH_INVALID_OUTPUT)));
+            }
+        }
+
+        if let Err(err) = self.write_to_allocated_memory_inner(&digest, output_ptr_ptr) {
+            debug!("{}() could not write the digest: {:?}", function, err);
+            return Ok(Some(RuntimeValue::I32(HASH_INVALID_OUTPUT)));
+        }
+
+        Ok(Some(RuntimeValue::I32(HASH_SUCCESS)))
+    }
+
+    /// Return the error of an `addr_*` import to the contract, as a region holding its message
+    fn address_error(
+        &mut self,
+        function: &str,
+        err: AddressError,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        debug!("{}() returned an error to the contract: {}", function, err);
+        Ok(Some(RuntimeValue::I32(
+            self.write_to_memory(err.to_string().as_bytes())? as i32,
+        )))
+    }
+
     fn check_gas_usage(&self) -> Result<(), WasmEngineError> {
+        // Keep the gas used up to here, in case the ecall panics before reporting it
+        record_metered_gas(
+            self.gas_used
+                .saturating_add(self.gas_used_by_overlay)
+                .saturating_add(self.gas_used_by_ocalls),
+        );
+
         // Check if new amount is bigger than gas limit
         // If is above the limit, halt execution
         if self.is_gas_depleted() {
             debug!(
-                "Out of gas! Gas limit: {}, gas used: {}, gas used externally: {}",
-                self.gas_limit, self.gas_used, self.gas_used_externally
+                "Out of gas! Gas limit: {}, gas used: {}, gas used externally: {}, gas used by the storage overlay: {}, gas used by ocalls: {}",
+                self.gas_limit,
+                self.gas_used,
+                self.gas_used_externally,
+                self.gas_used_by_overlay,
+                self.gas_used_by_ocalls
             );
-            Err(WasmEngineError::OutOfGas)
-        } else {
-            Ok(())
+            return Err(if self.gas_limited_by_block {
+                WasmEngineError::OutOfBlockGas
+            } else {
+                WasmEngineError::OutOfGas
+            });
+        }
+
+        // A failed allocation may have been retried with the memory of the safety buffer. The
+        // contract only goes on if the buffer can be restored, so the next one can be too
+        oom_handler::restore_spent_safety_buffer().map_err(|_err| {
+            warn!("Not enough memory to restore the OOM safety buffer, stopping the contract");
+            WasmEngineError::OutOfMemory
+        })?;
+
+        // The clock of the host can only stop the contract earlier
+        match &self.execution_deadline {
+            Some(deadline) => deadline.check(
+                self.gas_used
+                    .saturating_add(self.gas_used_externally)
+                    .saturating_add(self.gas_used_by_overlay)
+                    .saturating_add(self.gas_used_by_ocalls),
+            ),
+            None => Ok(()),
+        }
+    }
+
+    /// Charge for copying the segments of the module when it was instantiated, which happens
+    /// before the gas metering in the contract runs
+    pub fn use_instantiation_gas(&mut self, gas_amount: u64) -> Result<(), WasmEngineError> {
+        self.use_gas(gas_amount)
+    }
+
+    /// Stop the query once it ran for longer than `timeout_ms`. How long a contract runs depends on
+    /// the machine, so nodes would disagree on the result of txs that are stopped this way, and
+    /// the timeout is ignored outside of queries.
+    pub fn set_execution_timeout(&mut self, timeout_ms: u64) -> Result<(), WasmEngineError> {
+        if !self.operation.is_query() {
+            warn!("Ignoring an execution timeout outside of a query");
+            return Ok(());
+        }
+        self.execution_deadline = Some(ExecutionDeadline::start(timeout_ms)?);
+        Ok(())
+    }
+
+    /// Stop the contract once it used the gas left in the block, if that's less than its own
+    /// limit. When both are the same, running out of gas is the fault of the tx.
+    pub fn limit_to_block_gas(&mut self, block_gas_remaining: u64) {
+        if block_gas_remaining < self.gas_limit {
+            self.gas_limit = block_gas_remaining;
+            self.gas_limited_by_block = true;
         }
     }
 
     fn is_gas_depleted(&self) -> bool {
-        self.gas_limit < self.gas_used.saturating_add(self.gas_used_externally)
+        self.gas_limit
+            < self
+                .gas_used
+                .saturating_add(self.gas_used_externally)
+                .saturating_add(self.gas_used_by_overlay)
+                .saturating_add(self.gas_used_by_ocalls)
     }
 
     fn gas_left(&self) -> u64 {
         self.gas_limit
             .saturating_sub(self.gas_used)
             .saturating_sub(self.gas_used_externally)
+            .saturating_sub(self.gas_used_by_overlay)
+            .saturating_sub(self.gas_used_by_ocalls)
     }
 }
 
@@ -259,9 +1059,15 @@ impl WasmiApi for ContractInstance {
 
         // Call read_db (this bubbles up to Tendermint via ocalls and FFI to Go code)
         // This returns the value from Tendermint
-        let (value, gas_used) =
-            read_encrypted_key(&state_key_name, &self.context, &self.contract_key)?;
-        self.use_gas_externally(gas_used)?;
+        let (value, gas_used) = read_encrypted_key(
+            &state_key_name,
+            &self.context,
+            self.storage_overlay.as_ref(),
+            &self.contract_key,
+        )?;
+        self.use_ocall_gas(self.gas_costs.ocall.read_db)?;
+        self.use_storage_gas(gas_used)?;
+        self.prefetch_after(&state_key_name);
 
         let value = match value {
             None => return Ok(Some(RuntimeValue::I32(0))),
@@ -287,19 +1093,76 @@ impl WasmiApi for ContractInstance {
     }
 
     /// Args:
-    /// 1. "key" to delete from Tendermint (buffer of bytes)
-    /// key is a pointer to a region "struct" of "pointer" and "length"
-    /// A Region looks like { ptr: u32, len: u32 }
-    fn remove_db_index(&mut self, state_key_ptr_ptr: i32) -> Result<Option<RuntimeValue>, Trap> {
-        if self.operation.is_query() {
-            return Err(WasmEngineError::UnauthorizedWrite.into());
-        }
+    /// 1. The keys to read, as a list encoded as sections (see `decode_sections`)
+    ///
+    /// Returns a pointer to a region with the values of the keys, in the same order, as a list of
+    /// sections encoded with `encode_optional_sections`. Missing keys and empty values are told
+    /// apart by the first byte of their section.
+    ///
+    /// Every key is charged like the host charges a read, but the keys are read with a single
+    /// ocall, so the ocall is only charged once.
+    fn read_db_multi_index(&mut self, keys_ptr_ptr: i32) -> Result<Option<RuntimeValue>, Trap> {
+        let keys = self.extract_vector(keys_ptr_ptr as u32).map_err(|err| {
+            debug!("read_db_multi() error while trying to read the keys from wasm memory");
+            err
+        })?;
 
-        let state_key_name = self
-            .extract_vector(state_key_ptr_ptr as u32)
-            .map_err(|err| {
-                debug!("remove_db() error while trying to read state_key_name from wasm memory");
-                err
+        let keys = match decode_sections(&keys) {
+            Some(keys) if keys.len() <= MAX_READ_DB_MULTI_KEYS => keys,
+            Some(keys) => {
+                debug!(
+                    "read_db_multi() was called with {} keys, more than the {} allowed",
+                    keys.len(),
+                    MAX_READ_DB_MULTI_KEYS
+                );
+                return Err(WasmEngineError::InvalidReadDbMultiKeys.into());
+            }
+            None => {
+                debug!("read_db_multi() was called with malformed keys");
+                return Err(WasmEngineError::InvalidReadDbMultiKeys.into());
+            }
+        };
+
+        trace!(
+            "read_db_multi() was called from WASM code with {} keys",
+            keys.len()
+        );
+
+        let (values, gas_used) = read_encrypted_keys(
+            &keys,
+            &self.context,
+            self.storage_overlay.as_ref(),
+            &self.contract_key,
+        )?;
+        self.use_ocall_gas(self.gas_costs.ocall.read_db)?;
+        self.use_storage_gas(gas_used)?;
+
+        let packed_values = encode_optional_sections(&values);
+        let ptr_to_region_in_wasm_vm = self.write_to_memory(&packed_values).map_err(|err| {
+            debug!(
+                "read_db_multi() error while trying to allocate {} bytes for the values",
+                packed_values.len(),
+            );
+            err
+        })?;
+
+        Ok(Some(RuntimeValue::I32(ptr_to_region_in_wasm_vm as i32)))
+    }
+
+    /// Args:
+    /// 1. "key" to delete from Tendermint (buffer of bytes)
+    /// key is a pointer to a region "struct" of "pointer" and "length"
+    /// A Region looks like { ptr: u32, len: u32 }
+    fn remove_db_index(&mut self, state_key_ptr_ptr: i32) -> Result<Option<RuntimeValue>, Trap> {
+        if self.operation.is_query() {
+            return Err(WasmEngineError::UnauthorizedWrite.into());
+        }
+
+        let state_key_name = self
+            .extract_vector(state_key_ptr_ptr as u32)
+            .map_err(|err| {
+                debug!("remove_db() error while trying to read state_key_name from wasm memory");
+                err
             })?;
 
         trace!(
@@ -307,9 +1170,18 @@ impl WasmiApi for ContractInstance {
             String::from_utf8_lossy(&state_key_name)
         );
 
+        self.change_entry_storage_usage(None, &state_key_name, None)?;
+
         // Call remove_db (this bubbles up to Tendermint via ocalls and FFI to Go code)
-        let gas_used = remove_encrypted_key(&state_key_name, &self.context, &self.contract_key)?;
-        self.use_gas_externally(gas_used)?;
+        let gas_used = remove_encrypted_key(
+            &state_key_name,
+            &self.context,
+            self.storage_overlay.as_mut(),
+            &self.contract_key,
+            &mut self.removed_storage,
+        )?;
+        self.use_ocall_gas(self.gas_costs.ocall.remove_db)?;
+        self.use_storage_gas(gas_used)?;
 
         Ok(None)
     }
@@ -323,40 +1195,306 @@ impl WasmiApi for ContractInstance {
         &mut self,
         state_key_ptr_ptr: i32,
         value_ptr_ptr: i32,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        self.write_db(state_key_ptr_ptr, value_ptr_ptr)?;
+
+        Ok(None)
+    }
+
+    /// Like `write_db`, but a write past the storage quota returns an error to the contract
+    /// instead of failing the execution, see `storage_quota_result`
+    fn try_write_db_index(
+        &mut self,
+        state_key_ptr_ptr: i32,
+        value_ptr_ptr: i32,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        let result = self.write_db(state_key_ptr_ptr, value_ptr_ptr);
+        self.storage_quota_result("db_try_write", result)
+    }
+    /// Like `read_db`, from the storage namespace in the region at `namespace_ptr_ptr`. Values
+    /// in a namespace are encrypted with a key derived from it, so they can only be read from the
+    /// namespace they were written to.
+    fn read_db_ns_index(
+        &mut self,
+        namespace_ptr_ptr: i32,
+        state_key_ptr_ptr: i32,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        let namespace = self.extract_namespace("db_read_ns", namespace_ptr_ptr)?;
+        let state_key_name = self
+            .extract_vector(state_key_ptr_ptr as u32)
+            .map_err(|err| {
+                debug!("db_read_ns() error while trying to read state_key_name from wasm memory");
+                err
+            })?;
+
+        trace_secret!(
+            "db_read_ns() was called from WASM code with namespace: {:?} state_key_name: {:?}",
+            String::from_utf8_lossy(&namespace),
+            String::from_utf8_lossy(&state_key_name)
+        );
+
+        let (value, gas_used) = read_namespaced_key(
+            &namespace,
+            &state_key_name,
+            &self.context,
+            self.storage_overlay.as_ref(),
+            &self.contract_key,
+        )?;
+        self.use_ocall_gas(self.gas_costs.ocall.read_db)?;
+        self.use_storage_gas(gas_used)?;
+
+        let value = match value {
+            None => return Ok(Some(RuntimeValue::I32(0))),
+            Some(value) => value,
+        };
+
+        let ptr_to_region_in_wasm_vm = self.write_to_memory(&value).map_err(|err| {
+            debug!(
+                "db_read_ns() error while trying to allocate {} bytes for the value",
+                value.len(),
+            );
+            err
+        })?;
+
+        Ok(Some(RuntimeValue::I32(ptr_to_region_in_wasm_vm as i32)))
+    }
+
+    /// Like `write_db`, to the storage namespace in the region at `namespace_ptr_ptr`
+    fn write_db_ns_index(
+        &mut self,
+        namespace_ptr_ptr: i32,
+        state_key_ptr_ptr: i32,
+        value_ptr_ptr: i32,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        self.write_db_ns(namespace_ptr_ptr, state_key_ptr_ptr, value_ptr_ptr)?;
+
+        Ok(None)
+    }
+
+    /// Like `db_write_ns`, but a write past the storage quota returns an error to the contract
+    /// instead of failing the execution, see `storage_quota_result`
+    fn try_write_db_ns_index(
+        &mut self,
+        namespace_ptr_ptr: i32,
+        state_key_ptr_ptr: i32,
+        value_ptr_ptr: i32,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        let result = self.write_db_ns(namespace_ptr_ptr, state_key_ptr_ptr, value_ptr_ptr);
+        self.storage_quota_result("db_try_write_ns", result)
+    }
+
+    /// Like `remove_db`, from the storage namespace in the region at `namespace_ptr_ptr`
+    fn remove_db_ns_index(
+        &mut self,
+        namespace_ptr_ptr: i32,
+        state_key_ptr_ptr: i32,
     ) -> Result<Option<RuntimeValue>, Trap> {
         if self.operation.is_query() {
             return Err(WasmEngineError::UnauthorizedWrite.into());
         }
 
+        let namespace = self.extract_namespace("db_remove_ns", namespace_ptr_ptr)?;
         let state_key_name = self
             .extract_vector(state_key_ptr_ptr as u32)
             .map_err(|err| {
-                debug!("write_db() error while trying to read state_key_name from wasm memory");
+                debug!("db_remove_ns() error while trying to read state_key_name from wasm memory");
                 err
             })?;
-        let value = self.extract_vector(value_ptr_ptr as u32).map_err(|err| {
-            debug!("write_db() error while trying to read value from wasm memory");
+
+        trace_secret!(
+            "db_remove_ns() was called from WASM code with namespace: {:?} state_key_name: {:?}",
+            String::from_utf8_lossy(&namespace),
+            String::from_utf8_lossy(&state_key_name)
+        );
+
+        self.change_entry_storage_usage(Some(&namespace), &state_key_name, None)?;
+
+        let gas_used = remove_namespaced_key(
+            &namespace,
+            &state_key_name,
+            &self.context,
+            self.storage_overlay.as_mut(),
+            &self.contract_key,
+            &mut self.removed_storage,
+        )?;
+        self.use_ocall_gas(self.gas_costs.ocall.remove_db)?;
+        self.use_storage_gas(gas_used)?;
+
+        Ok(None)
+    }
+
+    /// Args:
+    /// 1. The namespace to move the value to
+    /// 2. The key of a value the contract wrote with `write_db`
+    ///
+    /// Moves the value into the namespace, where it's read with `db_read_ns`. Returns 1 if the
+    /// value was moved, and 0 if the contract has no value for the key.
+    fn move_db_to_ns_index(
+        &mut self,
+        namespace_ptr_ptr: i32,
+        state_key_ptr_ptr: i32,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        if self.operation.is_query() {
+            return Err(WasmEngineError::UnauthorizedWrite.into());
+        }
+
+        let namespace = self.extract_namespace("db_move_to_ns", namespace_ptr_ptr)?;
+        let state_key_name = self
+            .extract_vector(state_key_ptr_ptr as u32)
+            .map_err(|err| {
+                debug!(
+                    "db_move_to_ns() error while trying to read state_key_name from wasm memory"
+                );
+                err
+            })?;
+
+        trace_secret!(
+            "db_move_to_ns() was called from WASM code with namespace: {:?} state_key_name: {:?}",
+            String::from_utf8_lossy(&namespace),
+            String::from_utf8_lossy(&state_key_name)
+        );
+
+        // The value replaces the one the namespace has for the key, if any
+        if self.storage_quota.is_some() {
+            let value_len = self.stored_value_len(None, &state_key_name)?;
+            if value_len.is_some() {
+                let namespaced_value_len =
+                    self.stored_value_len(Some(&namespace), &state_key_name)?;
+                let moved_len = entry_len(state_key_name.len(), value_len);
+                self.change_storage_usage(
+                    moved_len.saturating_add(entry_len(state_key_name.len(), namespaced_value_len)),
+                    moved_len,
+                )?;
+            }
+        }
+
+        let (moved, gas_used) = move_key_to_namespace(
+            &namespace,
+            &state_key_name,
+            &self.context,
+            self.storage_overlay.as_mut(),
+            &self.contract_key,
+        )?;
+        self.use_ocall_gas(self.gas_costs.ocall.read_db)?;
+        if moved {
+            self.use_ocall_gas(self.gas_costs.ocall.write_db)?;
+            self.use_ocall_gas(self.gas_costs.ocall.remove_db)?;
+        }
+        self.use_storage_gas(gas_used)?;
+
+        Ok(Some(RuntimeValue::I32(moved as i32)))
+    }
+
+    /// Args:
+    /// 1. "start" key of the range, inclusive (buffer of bytes), or 0 for no lower bound
+    /// 2. "end" key of the range, exclusive (buffer of bytes), or 0 for no upper bound
+    /// 3. "order" 1 for ascending, 2 for descending
+    ///
+    /// Returns the id of an iterator, to be read by `db_next`.
+    fn db_scan_index(
+        &mut self,
+        start_ptr_ptr: i32,
+        end_ptr_ptr: i32,
+        order: i32,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        let descending = match order {
+            ORDER_ASCENDING => false,
+            ORDER_DESCENDING => true,
+            _ => {
+                debug!("db_scan() got an invalid order {}", order);
+                return Err(WasmEngineError::InvalidIteratorOrder.into());
+            }
+        };
+
+        let start = self.extract_optional_vector(start_ptr_ptr).map_err(|err| {
+            debug!("db_scan() error while trying to read start from wasm memory");
+            err
+        })?;
+        let end = self.extract_optional_vector(end_ptr_ptr).map_err(|err| {
+            debug!("db_scan() error while trying to read end from wasm memory");
             err
         })?;
 
         trace!(
-            "write_db() was called from WASM code with state_key_name: {:?} value: {:?}",
-            String::from_utf8_lossy(&state_key_name),
-            String::from_utf8_lossy(&value),
+            "db_scan() was called from WASM code with start: {:?} end: {:?} order: {}",
+            start.as_ref().map(|start| String::from_utf8_lossy(start)),
+            end.as_ref().map(|end| String::from_utf8_lossy(end)),
+            order,
         );
 
-        let used_gas =
-            write_encrypted_key(&state_key_name, &value, &self.context, &self.contract_key)
-                .map_err(|err| {
-                    debug!(
-                        "write_db() error while trying to write the value to state: {:?}",
-                        err
-                    );
-                    err
-                })?;
-        self.use_gas_externally(used_gas)?;
+        let (keys, gas_used) = scan_encrypted_keys(
+            start.as_deref(),
+            end.as_deref(),
+            &self.context,
+            self.storage_overlay.as_ref(),
+            &self.contract_key,
+        )?;
+        // Scanning is a single round trip, like a read
+        self.use_ocall_gas(self.gas_costs.ocall.read_db)?;
+        self.use_storage_gas(gas_used)?;
 
-        Ok(None)
+        self.iterators.push(KeyIterator {
+            keys: keys.into_iter(),
+            descending,
+        });
+
+        Ok(Some(RuntimeValue::I32(self.iterators.len() as i32)))
+    }
+
+    /// Args:
+    /// 1. "iterator_id" as returned by `db_scan`
+    ///
+    /// Returns a pointer to a region with the next `value || key || key_len` of the iterator,
+    /// where `key_len` is a 4 bytes big endian integer. An empty key means the iterator is exhausted.
+    fn db_next_index(&mut self, iterator_id: i32) -> Result<Option<RuntimeValue>, Trap> {
+        let iterator = (iterator_id as usize)
+            .checked_sub(1)
+            .and_then(|index| self.iterators.get_mut(index))
+            .ok_or_else(|| {
+                debug!(
+                    "db_next() was called with an unknown iterator {}",
+                    iterator_id
+                );
+                WasmEngineError::IteratorDoesNotExist
+            })?;
+
+        let (key, value) = match iterator.next_key() {
+            Some(key) => {
+                let (value, gas_used) = read_encrypted_key(
+                    &key,
+                    &self.context,
+                    self.storage_overlay.as_ref(),
+                    &self.contract_key,
+                )?;
+                self.use_ocall_gas(self.gas_costs.ocall.read_db)?;
+                self.use_storage_gas(gas_used)?;
+                // The value may have been removed since the iterator was opened
+                (key, value.unwrap_or_default())
+            }
+            None => (Vec::new(), Vec::new()),
+        };
+
+        trace!(
+            "db_next() got key: {:?} value with len {}",
+            String::from_utf8_lossy(&key),
+            value.len()
+        );
+
+        let key_len = (key.len() as u32).to_be_bytes();
+        let mut kv = value;
+        kv.reserve(key.len() + key_len.len());
+        kv.extend_from_slice(&key);
+        kv.extend_from_slice(&key_len);
+
+        let ptr_to_region_in_wasm_vm = self.write_to_memory(&kv).map_err(|err| {
+            debug!(
+                "db_next() error while trying to allocate {} bytes for the key and value",
+                kv.len(),
+            );
+            err
+        })?;
+
+        Ok(Some(RuntimeValue::I32(ptr_to_region_in_wasm_vm as i32)))
     }
 
     /// Args:
@@ -507,6 +1645,106 @@ impl WasmiApi for ContractInstance {
         Ok(Some(RuntimeValue::I32(0)))
     }
 
+    /// Args:
+    /// 1. "human" address to validate (string)
+    /// Returns 0 if the address is valid and in its normalized form, otherwise a pointer to a
+    /// region holding the error message.
+    fn addr_validate_index(&mut self, human_ptr_ptr: i32) -> Result<Option<RuntimeValue>, Trap> {
+        self.use_gas_externally(self.gas_costs.external_addr_validate as u64)?;
+
+        let human = self.extract_vector(human_ptr_ptr as u32).map_err(|err| {
+            debug!("addr_validate() error while trying to read human address from wasm memory");
+            err
+        })?;
+
+        let human = match std::str::from_utf8(&human) {
+            Ok(human) => human,
+            Err(_) => return self.address_error("addr_validate", AddressError::InvalidUtf8),
+        };
+
+        match addr_validate(human, &self.bech32_prefix) {
+            // return 0 == ok
+            Ok(()) => Ok(Some(RuntimeValue::I32(0))),
+            Err(err) => self.address_error("addr_validate", err),
+        }
+    }
+
+    /// Args:
+    /// 1. "human" to convert to canonical address (string)
+    /// 2. "canonical" a buffer to write the result into (buffer of bytes)
+    /// Unlike `canonicalize_address`, the input isn't trimmed.
+    fn addr_canonicalize_index(
+        &mut self,
+        human_ptr_ptr: i32,
+        canonical_ptr_ptr: i32,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        self.use_gas_externally(self.gas_costs.external_addr_canonicalize as u64)?;
+
+        let human = self.extract_vector(human_ptr_ptr as u32).map_err(|err| {
+            debug!("addr_canonicalize() error while trying to read human address from wasm memory");
+            err
+        })?;
+
+        let human = match std::str::from_utf8(&human) {
+            Ok(human) => human,
+            Err(_) => return self.address_error("addr_canonicalize", AddressError::InvalidUtf8),
+        };
+
+        let canonical = match addr_canonicalize(human, &self.bech32_prefix) {
+            Ok(canonical) => canonical,
+            Err(err) => return self.address_error("addr_canonicalize", err),
+        };
+
+        self.write_to_allocated_memory(&canonical, canonical_ptr_ptr as u32)
+            .map_err(|err| {
+                debug!(
+                    "addr_canonicalize() error while trying to write the answer {:?} to the destination buffer",
+                    canonical,
+                );
+                err
+            })?;
+
+        // return 0 == ok
+        Ok(Some(RuntimeValue::I32(0)))
+    }
+
+    /// Args:
+    /// 1. "canonical" to convert to human address (buffer of bytes)
+    /// 2. "human" a buffer to write the result (humanized string) into (buffer of bytes)
+    fn addr_humanize_index(
+        &mut self,
+        canonical_ptr_ptr: i32,
+        human_ptr_ptr: i32,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        self.use_gas_externally(self.gas_costs.external_addr_humanize as u64)?;
+
+        let canonical = self
+            .extract_vector(canonical_ptr_ptr as u32)
+            .map_err(|err| {
+                debug!(
+                    "addr_humanize() error while trying to read canonical address from wasm memory",
+                );
+                err
+            })?;
+
+        let human = match addr_humanize(&canonical, &self.bech32_prefix) {
+            Ok(human) => human,
+            Err(err) => return self.address_error("addr_humanize", err),
+        };
+
+        self.write_to_allocated_memory(human.as_bytes(), human_ptr_ptr as u32)
+            .map_err(|err| {
+                debug!(
+                    "addr_humanize() error while trying to write the answer {:?} to the destination buffer",
+                    human,
+                );
+                err
+            })?;
+
+        // return 0 == ok
+        Ok(Some(RuntimeValue::I32(0)))
+    }
+
     // stub, for now
     fn query_chain_index(&mut self, query_ptr_ptr: i32) -> Result<Option<RuntimeValue>, Trap> {
         let query_buffer = self.extract_vector(query_ptr_ptr as u32).map_err(|err| {
@@ -519,6 +1757,11 @@ impl WasmiApi for ContractInstance {
             String::from_utf8_lossy(&query_buffer)
         );
 
+        // Charged before the query, so the queried contract only gets the gas that's left
+        self.use_ocall_gas(self.gas_costs.ocall.query_chain)?;
+        // The query may reach this contract, which must see the changes made so far
+        self.flush_storage()?;
+
         // Call query_chain (this bubbles up to x/compute via ocalls and FFI to Go code)
         // Returns the value from x/compute
         let mut gas_used: u64 = 0;
@@ -538,6 +1781,7 @@ impl WasmiApi for ContractInstance {
         );
 
         self.use_gas_externally(gas_used)?;
+        self.charge_query_answer(answer.len())?;
 
         let ptr_to_region_in_wasm_vm = self.write_to_memory(&answer).map_err(|err| {
             debug!(
@@ -551,23 +1795,2082 @@ impl WasmiApi for ContractInstance {
         Ok(Some(RuntimeValue::I32(ptr_to_region_in_wasm_vm as i32)))
     }
 
-    fn gas_index(&mut self, gas_amount: i32) -> Result<Option<RuntimeValue>, Trap> {
-        self.use_gas(gas_amount as u64)?;
-        Ok(None)
-    }
+    /// Args:
+    /// 1. The message that was signed
+    /// 2. The 64 bytes signature
+    /// 3. The 32 bytes public key
+    ///
+    /// Returns 0 if the signature is valid, 1 if it isn't, and 2 if the input is malformed.
+    fn ed25519_verify_index(
+        &mut self,
+        message_ptr_ptr: i32,
+        signature_ptr_ptr: i32,
+        public_key_ptr_ptr: i32,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        self.use_gas(self.gas_costs.external_ed25519_verify as u64)?;
 
-    #[cfg(feature = "debug-print")]
-    fn debug_print_index(&self, message_ptr_ptr: i32) -> Result<Option<RuntimeValue>, Trap> {
-        let message_buffer = self.extract_vector(message_ptr_ptr as u32).map_err(|err| {
-            debug!("debug_print() error while trying to read message from wasm memory",);
+        let message = self.extract_vector(message_ptr_ptr as u32).map_err(|err| {
+            debug!("ed25519_verify() error while trying to read message from wasm memory");
             err
         })?;
-
-        let message =
-            String::from_utf8(message_buffer).unwrap_or_else(|err| hex::encode(err.into_bytes()));
+        let signature = self
+            .extract_vector(signature_ptr_ptr as u32)
+            .map_err(|err| {
+                debug!("ed25519_verify() error while trying to read signature from wasm memory");
+                err
+            })?;
+        let public_key = self
+            .extract_vector(public_key_ptr_ptr as u32)
+            .map_err(|err| {
+                debug!("ed25519_verify() error while trying to read public key from wasm memory");
+                err
+            })?;
+
+        let result = ed25519_verify(&message, &signature, &public_key);
+        trace!("ed25519_verify() result: {:?}", result);
+
+        Ok(Some(RuntimeValue::I32(ed25519_verify_result(result))))
+    }
+
+    /// Args:
+    /// 1. The signed messages
+    /// 2. The 64 bytes signatures
+    /// 3. The 32 bytes public keys
+    ///
+    /// Each argument is a list encoded as sections (see `decode_sections`). A single message or
+    /// a single public key may be used for all signatures.
+    ///
+    /// Returns 0 if all the signatures are valid, 1 if any of them isn't, and 2 if the input is
+    /// malformed.
+    fn ed25519_batch_verify_index(
+        &mut self,
+        messages_ptr_ptr: i32,
+        signatures_ptr_ptr: i32,
+        public_keys_ptr_ptr: i32,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        self.use_gas(self.gas_costs.external_ed25519_batch_verify_base as u64)?;
+
+        let messages = self
+            .extract_vector(messages_ptr_ptr as u32)
+            .map_err(|err| {
+                debug!(
+                    "ed25519_batch_verify() error while trying to read messages from wasm memory"
+                );
+                err
+            })?;
+        let signatures = self
+            .extract_vector(signatures_ptr_ptr as u32)
+            .map_err(|err| {
+                debug!(
+                    "ed25519_batch_verify() error while trying to read signatures from wasm memory"
+                );
+                err
+            })?;
+        let public_keys = self
+            .extract_vector(public_keys_ptr_ptr as u32)
+            .map_err(|err| {
+                debug!(
+                    "ed25519_batch_verify() error while trying to read public keys from wasm memory"
+                );
+                err
+            })?;
+
+        let (messages, signatures, public_keys) = match (
+            decode_sections(&messages),
+            decode_sections(&signatures),
+            decode_sections(&public_keys),
+        ) {
+            (Some(messages), Some(signatures), Some(public_keys)) => {
+                (messages, signatures, public_keys)
+            }
+            _ => {
+                debug!("ed25519_batch_verify() got malformed sections");
+                return Ok(Some(RuntimeValue::I32(ED25519_VERIFY_MALFORMED_INPUT)));
+            }
+        };
+
+        // Each signature is verified on its own, see `ed25519_batch_verify`
+        self.use_gas(
+            (self.gas_costs.external_ed25519_verify as u64).saturating_mul(signatures.len() as u64),
+        )?;
+
+        let result = ed25519_batch_verify(&messages, &signatures, &public_keys);
+        trace!(
+            "ed25519_batch_verify() result for {} signatures: {:?}",
+            signatures.len(),
+            result
+        );
+
+        Ok(Some(RuntimeValue::I32(ed25519_verify_result(result))))
+    }
+
+    /// Args:
+    /// 1. The 32 bytes hash of the signed message
+    /// 2. The 64 bytes `r || s` signature
+    /// 3. The recovery param, 0 to 3
+    /// 4. A region of at least 65 bytes, where the uncompressed public key is written
+    ///
+    /// Returns 0 on success, 1 if the hash is malformed, 2 if the signature is malformed or its
+    /// r or s are out of range, 3 if the recovery param is invalid and 4 if no public key can be
+    /// recovered.
+    fn secp256k1_recover_pubkey_index(
+        &mut self,
+        message_hash_ptr_ptr: i32,
+        signature_ptr_ptr: i32,
+        recovery_param: i32,
+        public_key_ptr_ptr: i32,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        self.use_gas_externally(self.gas_costs.external_secp256k1_recover_pubkey as u64)?;
+
+        let message_hash = self
+            .extract_vector(message_hash_ptr_ptr as u32)
+            .map_err(|err| {
+                debug!(
+                    "secp256k1_recover_pubkey() error while trying to read message hash from wasm memory"
+                );
+                err
+            })?;
+        let signature = self
+            .extract_vector(signature_ptr_ptr as u32)
+            .map_err(|err| {
+                debug!(
+                    "secp256k1_recover_pubkey() error while trying to read signature from wasm memory"
+                );
+                err
+            })?;
+
+        if message_hash.len() != MESSAGE_HASH_SIZE {
+            debug!(
+                "secp256k1_recover_pubkey() got a message hash of length {}",
+                message_hash.len()
+            );
+            return Ok(Some(RuntimeValue::I32(
+                SECP256K1_RECOVER_INVALID_HASH_FORMAT,
+            )));
+        }
+        if signature.len() != SECP256K1_SIGNATURE_SIZE {
+            debug!(
+                "secp256k1_recover_pubkey() got a signature of length {}",
+                signature.len()
+            );
+            return Ok(Some(RuntimeValue::I32(
+                SECP256K1_RECOVER_INVALID_SIGNATURE_FORMAT,
+            )));
+        }
+        let recovery_id = match secp256k1::recovery::RecoveryId::from_i32(recovery_param) {
+            Ok(recovery_id) => recovery_id,
+            Err(_) => {
+                debug!(
+                    "secp256k1_recover_pubkey() got an invalid recovery param {}",
+                    recovery_param
+                );
+                return Ok(Some(RuntimeValue::I32(
+                    SECP256K1_RECOVER_INVALID_RECOVERY_PARAM,
+                )));
+            }
+        };
+
+        let mut hash_array = [0u8; MESSAGE_HASH_SIZE];
+        hash_array.copy_from_slice(&message_hash);
+        let mut signature_array = [0u8; SECP256K1_SIGNATURE_SIZE];
+        signature_array.copy_from_slice(&signature);
+
+        let public_key = match secp256k1_recover_pubkey(&hash_array, &signature_array, recovery_id)
+        {
+            Ok(public_key) => public_key,
+            Err(CryptoError::ParsingError) => {
+                return Ok(Some(RuntimeValue::I32(
+                    SECP256K1_RECOVER_INVALID_SIGNATURE_FORMAT,
+                )));
+            }
+            Err(_) => return Ok(Some(RuntimeValue::I32(SECP256K1_RECOVER_FAILED))),
+        };
+
+        self.write_to_allocated_memory(&public_key, public_key_ptr_ptr as u32)
+            .map_err(|err| {
+                debug!(
+                    "secp256k1_recover_pubkey() error while trying to write the public key to the destination buffer"
+                );
+                err
+            })?;
+
+        Ok(Some(RuntimeValue::I32(SECP256K1_RECOVER_SUCCESS)))
+    }
+
+    /// Args:
+    /// 1. A region holding the derivation path, at most 64 bytes, which may be empty
+    ///
+    /// Returns the id of the signing key of this contract for the path, or 0 if the path can't be
+    /// read or is too long, or the address of the contract isn't authenticated. The key is
+    /// derived from the consensus seed and the address of the contract, so no other contract can
+    /// sign with it, and it stays the same when the contract is migrated or its keys are rotated.
+    /// It never leaves the enclave, and its id is only valid until the end of the execution.
+    fn secp256k1_generate_key_index(
+        &mut self,
+        path_ptr_ptr: i32,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        self.use_gas(self.gas_costs.external_secp256k1_generate_key as u64)?;
+
+        let path = match self.extract_possibly_empty_vector(path_ptr_ptr as u32) {
+            Ok(path) => path,
+            Err(err) => {
+                debug!(
+                    "secp256k1_generate_key() could not read the path region: {:?}",
+                    err
+                );
+                return Ok(Some(RuntimeValue::I32(0)));
+            }
+        };
+        if path.len() > MAX_SIGNING_KEY_PATH_LENGTH {
+            debug!(
+                "secp256k1_generate_key() got a path of {} bytes, the most allowed is {}",
+                path.len(),
+                MAX_SIGNING_KEY_PATH_LENGTH
+            );
+            return Ok(Some(RuntimeValue::I32(0)));
+        }
+        let contract_address = match &self.contract_address {
+            Some(contract_address) => contract_address,
+            None => {
+                debug!("secp256k1_generate_key() was called for an unauthenticated contract");
+                return Ok(Some(RuntimeValue::I32(0)));
+            }
+        };
+
+        let signing_secret = KEY_MANAGER.get_consensus_signing_secret().map_err(|_err| {
+            warn!("Error extracting consensus_signing_secret");
+            WasmEngineError::MissingSigningSecret
+        })?;
+        self.signing_keys.push(ContractSigningKey::derive(
+            &signing_secret,
+            contract_address.as_slice(),
+            &path,
+        ));
+
+        Ok(Some(RuntimeValue::I32(self.signing_keys.len() as i32)))
+    }
+
+    /// Args:
+    /// 1. A key id returned by `secp256k1_generate_key`
+    /// 2. The 32 bytes hash to sign
+    /// 3. A region of at least 65 bytes, where the `r || s` signature and the recovery id are
+    ///    written
+    ///
+    /// Returns 0 on success, 1 if the key id is unknown, 2 if the hash is malformed, 3 if the
+    /// output region can't be written and 4 if it's too small. The nonce is derived as in
+    /// RFC 6979, so every node makes the same signature.
+    fn secp256k1_sign_index(
+        &mut self,
+        key_id: i32,
+        message_hash_ptr_ptr: i32,
+        signature_ptr_ptr: i32,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        self.use_gas(self.gas_costs.external_secp256k1_sign as u64)?;
+
+        let message_hash = match self.extract_vector(message_hash_ptr_ptr as u32) {
+            Ok(message_hash) if message_hash.len() == MESSAGE_HASH_SIZE => message_hash,
+            Ok(message_hash) => {
+                debug!(
+                    "secp256k1_sign() got a message hash of length {}",
+                    message_hash.len()
+                );
+                return Ok(Some(RuntimeValue::I32(SECP256K1_SIGN_INVALID_HASH_FORMAT)));
+            }
+            Err(_) => return Ok(Some(RuntimeValue::I32(SECP256K1_SIGN_INVALID_HASH_FORMAT))),
+        };
+
+        let status = self.signing_output_status(
+            "secp256k1_sign",
+            signature_ptr_ptr as u32,
+            SECP256K1_SIGNATURE_SIZE + 1,
+        );
+        if status != SECP256K1_SIGN_SUCCESS {
+            return Ok(Some(RuntimeValue::I32(status)));
+        }
+
+        let mut hash_array = [0u8; MESSAGE_HASH_SIZE];
+        hash_array.copy_from_slice(&message_hash);
+        let signed = match self.signing_key("secp256k1_sign", key_id) {
+            Some(key) => key.sign(&hash_array),
+            None => return Ok(Some(RuntimeValue::I32(SECP256K1_SIGN_INVALID_KEY))),
+        };
+        let (signature, recovery_id) = match signed {
+            Ok(signed) => signed,
+            Err(_) => return Ok(Some(RuntimeValue::I32(SECP256K1_SIGN_INVALID_HASH_FORMAT))),
+        };
+
+        let mut output = signature.to_vec();
+        output.push(recovery_id);
+        self.write_to_allocated_memory(&output, signature_ptr_ptr as u32)
+            .map_err(|err| {
+                debug!(
+                    "secp256k1_sign() error while trying to write the signature to the destination buffer"
+                );
+                err
+            })?;
+
+        Ok(Some(RuntimeValue::I32(SECP256K1_SIGN_SUCCESS)))
+    }
+
+    /// Args:
+    /// 1. A key id returned by `secp256k1_generate_key`
+    /// 2. A region of at least 65 bytes, where the uncompressed public key is written
+    ///
+    /// Returns 0 on success, 1 if the key id is unknown, 3 if the output region can't be written
+    /// and 4 if it's too small.
+    fn secp256k1_public_key_index(
+        &mut self,
+        key_id: i32,
+        public_key_ptr_ptr: i32,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        self.use_gas(self.gas_costs.external_secp256k1_public_key as u64)?;
+
+        let public_key = match self.signing_key("secp256k1_public_key", key_id) {
+            Some(key) => key.public_key(),
+            None => return Ok(Some(RuntimeValue::I32(SECP256K1_SIGN_INVALID_KEY))),
+        };
+
+        let status = self.signing_output_status(
+            "secp256k1_public_key",
+            public_key_ptr_ptr as u32,
+            public_key.len(),
+        );
+        if status != SECP256K1_SIGN_SUCCESS {
+            return Ok(Some(RuntimeValue::I32(status)));
+        }
+
+        self.write_to_allocated_memory(&public_key, public_key_ptr_ptr as u32)
+            .map_err(|err| {
+                debug!(
+                    "secp256k1_public_key() error while trying to write the public key to the destination buffer"
+                );
+                err
+            })?;
+
+        Ok(Some(RuntimeValue::I32(SECP256K1_SIGN_SUCCESS)))
+    }
+
+    /// Returns a pointer to a region with 32 random bytes, which are different on each call.
+    /// Returns 0 in queries, which don't get randomness.
+    fn get_random_index(&mut self) -> Result<Option<RuntimeValue>, Trap> {
+        self.use_gas_externally(self.gas_costs.external_get_random as u64)?;
+
+        let random = match &mut self.randomness {
+            Some(randomness) => randomness.next_random(),
+            None => {
+                debug!("get_random() was called from a query");
+                return Ok(Some(RuntimeValue::I32(0)));
+            }
+        };
+
+        let ptr_to_region_in_wasm_vm = self.write_to_memory(&random).map_err(|err| {
+            debug!("get_random() error while trying to allocate the random bytes");
+            err
+        })?;
+
+        Ok(Some(RuntimeValue::I32(ptr_to_region_in_wasm_vm as i32)))
+    }
+
+    /// Returns the gas the contract has left, after charging for this call.
+    ///
+    /// It's computed from the same counters as the out of gas check: the gas charged by the
+    /// metering and the host functions, the fixed gas of ocalls, and the gas the host charged for
+    /// storage and queries. All of them are the same on every node, so the value is too.
+    fn gas_left_index(&mut self) -> Result<Option<RuntimeValue>, Trap> {
+        self.use_gas_externally(self.gas_costs.external_gas_left as u64)?;
+
+        Ok(Some(RuntimeValue::I64(
+            self.gas_left().min(i64::MAX as u64) as i64,
+        )))
+    }
+
+    /// Returns the gas limit the contract was called with, in the same units as `gas_left`
+    fn gas_limit_index(&mut self) -> Result<Option<RuntimeValue>, Trap> {
+        self.use_gas_externally(self.gas_costs.external_gas_limit as u64)?;
+
+        Ok(Some(RuntimeValue::I64(
+            self.gas_limit.min(i64::MAX as u64) as i64
+        )))
+    }
+
+    /// Returns how many bytes the contract stores, with the changes it made so far, see
+    /// `StorageUsage`. Without a quota, that's what it stored when the chain last set one.
+    fn db_storage_usage_index(&mut self) -> Result<Option<RuntimeValue>, Trap> {
+        self.use_gas_externally(self.gas_costs.external_db_storage_usage as u64)?;
+        let usage = self.storage_usage()?;
+
+        Ok(Some(RuntimeValue::I64(
+            usage.bytes().min(i64::MAX as u64) as i64
+        )))
+    }
+
+    /// Args:
+    /// 1. A region holding the data to hash, which may be empty
+    /// 2. A region of at least 32 bytes, where the digest is written
+    ///
+    /// Returns 0 on success, 1 if the input region can't be read, 2 if the output region can't be
+    /// written and 3 if the output region is too small for the digest.
+    fn hash_sha256_index(
+        &mut self,
+        input_ptr_ptr: i32,
+        output_ptr_ptr: i32,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        self.hash(
+            "hash_sha256",
+            |data| sha_256(data).to_vec(),
+            self.gas_costs.external_hash_sha256_base,
+            self.gas_costs.external_hash_sha256_per_byte,
+            input_ptr_ptr,
+            output_ptr_ptr,
+        )
+    }
+
+    /// Like `hash_sha256`, with the Keccak-256 used by Ethereum
+    fn hash_keccak256_index(
+        &mut self,
+        input_ptr_ptr: i32,
+        output_ptr_ptr: i32,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        self.hash(
+            "hash_keccak256",
+            |data| keccak_256(data).to_vec(),
+            self.gas_costs.external_hash_keccak256_base,
+            self.gas_costs.external_hash_keccak256_per_byte,
+            input_ptr_ptr,
+            output_ptr_ptr,
+        )
+    }
+
+    /// Like `hash_sha256`, with RIPEMD-160. The output region needs only 20 bytes.
+    fn hash_ripemd160_index(
+        &mut self,
+        input_ptr_ptr: i32,
+        output_ptr_ptr: i32,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        self.hash(
+            "hash_ripemd160",
+            |data| ripemd_160(data).to_vec(),
+            self.gas_costs.external_hash_ripemd160_base,
+            self.gas_costs.external_hash_ripemd160_per_byte,
+            input_ptr_ptr,
+            output_ptr_ptr,
+        )
+    }
+
+    /// Args:
+    /// 1. A region holding the input key material
+    /// 2. A region holding the salt, which may be empty
+    /// 3. A region holding the info, which may be empty
+    /// 4. A region of at least `output_len` bytes, where the output key material is written
+    /// 5. The amount of bytes to derive, from 1 to 8160
+    ///
+    /// Returns 0 on success, 1, 2 or 3 if the ikm, salt or info region can't be read, 4 if the
+    /// output region can't be written, 5 if it's too small and 6 if `output_len` isn't allowed.
+    ///
+    /// The ikm is usually a secret of the contract, so neither it nor the output are ever logged.
+    fn hkdf_sha256_index(
+        &mut self,
+        ikm_ptr_ptr: i32,
+        salt_ptr_ptr: i32,
+        info_ptr_ptr: i32,
+        output_ptr_ptr: i32,
+        output_len: i32,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        self.use_gas(self.gas_costs.external_hkdf_sha256_base as u64)?;
+
+        // Like region lengths, the output length is unsigned
+        let output_len = output_len as u32 as usize;
+        if output_len == 0 || output_len > HKDF_SHA256_MAX_OUTPUT_SIZE {
+            debug!(
+                "hkdf_sha256() was asked for {} bytes, which isn't between 1 and {}",
+                output_len, HKDF_SHA256_MAX_OUTPUT_SIZE
+            );
+            return Ok(Some(RuntimeValue::I32(HKDF_INVALID_OUTPUT_LENGTH)));
+        }
+        self.use_gas(
+            (self.gas_costs.external_hkdf_sha256_per_output_byte as u64)
+                .saturating_mul(output_len as u64),
+        )?;
+
+        let mut inputs = Vec::with_capacity(3);
+        for (ptr_ptr, name, status) in &[
+            (ikm_ptr_ptr, "ikm", HKDF_INVALID_IKM),
+            (salt_ptr_ptr, "salt", HKDF_INVALID_SALT),
+            (info_ptr_ptr, "info", HKDF_INVALID_INFO),
+        ] {
+            match self.extract_possibly_empty_vector(*ptr_ptr as u32) {
+                Ok(input) => inputs.push(Zeroizing::new(input)),
+                Err(err) => {
+                    debug!(
+                        "hkdf_sha256() could not read the {} region: {:?}",
+                        name, err
+                    );
+                    return Ok(Some(RuntimeValue::I32(*status)));
+                }
+            }
+        }
+        let input_len: usize = inputs.iter().map(|input| input.len()).sum();
+        self.use_gas(
+            (self.gas_costs.external_hkdf_sha256_per_input_byte as u64)
+                .saturating_mul(input_len as u64),
+        )?;
+
+        let output_ptr_ptr = output_ptr_ptr as u32;
+        match self.region_buffer(output_ptr_ptr) {
+            Ok((0, _)) => {
+                debug!("hkdf_sha256() got an output region with a null pointer");
+                return Ok(Some(RuntimeValue::I32(HKDF_INVALID_OUTPUT)));
+            }
+            Ok((_, output_cap)) if (output_cap as usize) < output_len => {
+                debug!(
+                    "hkdf_sha256() got an output region of {} bytes for {} bytes of output",
+                    output_cap, output_len
+                );
+                return Ok(Some(RuntimeValue::I32(HKDF_OUTPUT_TOO_SMALL)));
+            }
+            Ok(_) => {}
+            Err(err) => {
+                debug!("hkdf_sha256() could not read the output region: {:?}", err);
+                return Ok(Some(RuntimeValue::I32(HKDF_INVALID_OUTPUT)));
+            }
+        }
+
+        let okm = match hkdf_sha256(&inputs[0], &inputs[1], &inputs[2], output_len) {
+            Ok(okm) => okm,
+            Err(err) => {
+                debug!("hkdf_sha256() failed to derive the output: {:?}", err);
+                return Ok(Some(RuntimeValue::I32(HKDF_INVALID_OUTPUT_LENGTH)));
+            }
+        };
+
+        // Not `write_to_allocated_memory`, which logs the buffer it failed to write
+        if let Err(err) = self.write_to_allocated_memory_inner(&okm, output_ptr_ptr) {
+            debug!("hkdf_sha256() could not write the output: {:?}", err);
+            return Ok(Some(RuntimeValue::I32(HKDF_INVALID_OUTPUT)));
+        }
+
+        Ok(Some(RuntimeValue::I32(HKDF_SUCCESS)))
+    }
+
+    /// Args:
+    /// 1. A region holding the id of a chain the enclave tracks the headers of
+    /// 2. A region holding a JSON `ForeignHeader` of that chain
+    ///
+    /// Returns a pointer to a region holding a JSON `ForeignHeaderVerification`, with the app hash
+    /// of the header if it verifies against what the enclave trusts about the chain in the env, see
+    /// `verify_foreign_header`. Headers that don't verify, are malformed or are of a chain that
+    /// isn't tracked aren't verified.
+    fn verify_foreign_header_index(
+        &mut self,
+        chain_id_ptr_ptr: i32,
+        header_ptr_ptr: i32,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        self.use_gas_externally(self.gas_costs.external_verify_foreign_header_base as u64)?;
+
+        let chain_id = self
+            .extract_vector(chain_id_ptr_ptr as u32)
+            .map_err(|err| {
+                debug!("verify_foreign_header() error while trying to read the chain id");
+                err
+            })?;
+        let header = self.extract_vector(header_ptr_ptr as u32).map_err(|err| {
+            debug!("verify_foreign_header() error while trying to read the header");
+            err
+        })?;
+        self.use_gas_externally(
+            (self.gas_costs.external_verify_foreign_header_per_byte as u64)
+                .saturating_mul(header.len() as u64),
+        )?;
+
+        let app_hash = match (
+            String::from_utf8(chain_id),
+            serde_json::from_slice::<ForeignHeader>(&header),
+            self.block_time,
+        ) {
+            (Ok(chain_id), Ok(header), Some(now)) => {
+                // Headers that skip blocks may be checked against two validator sets
+                let checked_sets = if header.trusted_validators.is_empty() {
+                    1
+                } else {
+                    2
+                };
+                self.use_gas_externally(
+                    (self.gas_costs.external_verify_foreign_header_per_signature as u64)
+                        .saturating_mul((header.commit.signatures.len() * checked_sets) as u64),
+                )?;
+                verify_foreign_header(&self.foreign_chains, &chain_id, &header, now)
+            }
+            (_, Err(err), _) => {
+                debug!("verify_foreign_header() got a malformed header: {:?}", err);
+                None
+            }
+            _ => {
+                debug!("verify_foreign_header() got a malformed chain id, or no block time");
+                None
+            }
+        };
+        trace!(
+            "verify_foreign_header() verified the header: {}",
+            app_hash.is_some()
+        );
+
+        let verification = ForeignHeaderVerification {
+            verified: app_hash.is_some(),
+            app_hash: app_hash.map(Binary),
+        };
+        // Serializing these types can't fail
+        let verification = serde_json::to_vec(&verification).unwrap();
+
+        let ptr_to_region_in_wasm_vm = self.write_to_memory(&verification).map_err(|err| {
+            debug!("verify_foreign_header() error while trying to allocate the result");
+            err
+        })?;
+
+        Ok(Some(RuntimeValue::I32(ptr_to_region_in_wasm_vm as i32)))
+    }
+
+    /// Args:
+    /// 1. The address of a contract
+    /// 2. The code hash the contract is expected to run, hex encoded
+    ///
+    /// Returns 1 if the contract info of the address points to code with that hash and the
+    /// enclave authenticated the contract key for it, and 0 if not, or if there's no contract at
+    /// the address.
+    fn assert_contract_code_hash_index(
+        &mut self,
+        contract_addr_ptr_ptr: i32,
+        code_hash_ptr_ptr: i32,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        self.use_gas(self.gas_costs.external_assert_contract_code_hash as u64)?;
+
+        let contract_addr = self
+            .extract_vector(contract_addr_ptr_ptr as u32)
+            .map_err(|err| {
+                debug!("assert_contract_code_hash() error while trying to read the address");
+                err
+            })?;
+        let code_hash = self
+            .extract_vector(code_hash_ptr_ptr as u32)
+            .map_err(|err| {
+                debug!("assert_contract_code_hash() error while trying to read the code hash");
+                err
+            })?;
+
+        let contract_addr = match String::from_utf8(contract_addr) {
+            Ok(contract_addr) => contract_addr,
+            Err(_) => {
+                debug!("assert_contract_code_hash() got an address that isn't UTF-8");
+                return Ok(Some(RuntimeValue::I32(0)));
+            }
+        };
+        let canonical_addr = match addr_canonicalize(&contract_addr, &self.bech32_prefix) {
+            Ok(canonical_addr) => canonical_addr,
+            Err(err) => {
+                debug!(
+                    "assert_contract_code_hash() got an invalid address: {}",
+                    err
+                );
+                return Ok(Some(RuntimeValue::I32(0)));
+            }
+        };
+        let code_hash = match std::str::from_utf8(&code_hash)
+            .ok()
+            .and_then(decode_code_hash)
+        {
+            Some(code_hash) => code_hash,
+            None => {
+                debug!("assert_contract_code_hash() got a malformed code hash");
+                return Ok(Some(RuntimeValue::I32(0)));
+            }
+        };
+
+        self.use_ocall_gas(self.gas_costs.ocall.query_chain)?;
+        let mut gas_used: u64 = 0;
+        let answer = query_contract_key(
+            &self.context,
+            &contract_addr,
+            &mut gas_used,
+            self.gas_left(),
+        )?;
+        self.use_gas_externally(gas_used)?;
+
+        let matches = match answer {
+            Some(answer) => contract_key_matches_code_hash(
+                Some(answer.contract_key.as_slice()),
+                answer.code_hash.as_slice(),
+                &canonical_addr,
+                &code_hash,
+            ),
+            None => false,
+        };
+        trace!(
+            "assert_contract_code_hash() checked {} against the code hash: {}",
+            contract_addr,
+            matches
+        );
+
+        Ok(Some(RuntimeValue::I32(matches as i32)))
+    }
+
+    /// Grow the memory like `memory.grow` does, but never past `max_memory_pages`. Every
+    /// attempt is charged, whether or not the memory grew.
+    fn memory_grow_index(&mut self, pages: i32) -> Result<Option<RuntimeValue>, Trap> {
+        // Like the operand of `memory.grow`, the number of pages is unsigned
+        let pages = pages as u32;
+        self.use_gas((pages as u64).saturating_mul(self.gas_costs.grow_mem as u64))?;
+
+        let current_pages = self.get_memory().current_size().0 as u32;
+        if current_pages.saturating_add(pages) > self.max_memory_pages {
+            warn!(
+                "The contract tried to grow its memory from {} to {} pages, past the limit of {} pages. \
+                 The contract was refused, the enclave didn't run out of memory",
+                current_pages,
+                current_pages.saturating_add(pages),
+                self.max_memory_pages
+            );
+            self.exceeded_memory_limit = true;
+            return Ok(Some(RuntimeValue::I32(-1)));
+        }
+
+        match self.get_memory().grow(Pages(pages as usize)) {
+            Ok(previous_pages) => Ok(Some(RuntimeValue::I32(previous_pages.0 as i32))),
+            Err(err) => {
+                debug!(
+                    "memory_grow() failed to grow the memory by {} pages: {:?}",
+                    pages, err
+                );
+                Ok(Some(RuntimeValue::I32(-1)))
+            }
+        }
+    }
+
+    fn gas_index(&mut self, gas_amount: i32) -> Result<Option<RuntimeValue>, Trap> {
+        self.use_gas(gas_amount as u64)?;
+        Ok(None)
+    }
+
+    /// Contracts built for CosmWasm 1.0 call this when they panic, instead of reaching an
+    /// `unreachable` instruction. The message may contain secrets, so it's only logged when
+    /// tracing secrets.
+    fn abort_index(&self, message_ptr_ptr: i32) -> Result<Option<RuntimeValue>, Trap> {
+        let message_buffer = self.extract_vector(message_ptr_ptr as u32).map_err(|err| {
+            debug!("abort() error while trying to read message from wasm memory");
+            err
+        })?;
+
+        let message = String::from_utf8_lossy(&message_buffer);
+        trace_secret!("abort: {:?}", message);
+        warn!("The contract aborted");
+
+        Err(WasmEngineError::ContractAborted.into())
+    }
+
+    /// Contracts built for CosmWasm 1.0 call this to print a debug message. Production enclaves
+    /// only log an id of the message, see `debug_message`. The message is charged by the byte, so
+    /// printing isn't free in either build.
+    fn debug_index(&mut self, message_ptr_ptr: i32) -> Result<Option<RuntimeValue>, Trap> {
+        self.use_gas(self.gas_costs.external_debug_base as u64)?;
+
+        let message = self
+            .extract_possibly_empty_vector(message_ptr_ptr as u32)
+            .map_err(|err| {
+                debug!("debug() error while trying to read message from wasm memory");
+                err
+            })?;
+        self.use_gas(
+            (self.gas_costs.external_debug_per_byte as u64).saturating_mul(message.len() as u64),
+        )?;
+
+        log_buffer::write_line(&debug_line(&message));
+
+        Ok(None)
+    }
+
+    #[cfg(feature = "debug-print")]
+    fn debug_print_index(&self, message_ptr_ptr: i32) -> Result<Option<RuntimeValue>, Trap> {
+        let message_buffer = self.extract_vector(message_ptr_ptr as u32).map_err(|err| {
+            debug!("debug_print() error while trying to read message from wasm memory",);
+            err
+        })?;
+
+        let message =
+            String::from_utf8(message_buffer).unwrap_or_else(|err| hex::encode(err.into_bytes()));
 
         info!("debug_print: {:?}", message);
 
         Ok(None)
     }
+
+    #[cfg(feature = "debug-print")]
+    fn trap_location_index(&mut self, location: i32) -> Result<Option<RuntimeValue>, Trap> {
+        self.trap_location = Some(location as u32);
+        Ok(None)
+    }
+
+    /// Lets tests see what happens when a host function panics
+    #[cfg(feature = "test")]
+    fn test_panic_index(&self) -> Result<Option<RuntimeValue>, Trap> {
+        panic!("test_panic was called by the contract");
+    }
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use std::panic;
+    use wasmi::{ImportsBuilder, ModuleInstance};
+
+    use enclave_ffi_types::EnclaveError;
+
+    use crate::consts::MAX_WASM_MEMORY_PAGES;
+    use crate::log_buffer::LogBatch;
+    use crate::panic_report;
+    use crate::wasm::api_version::ApiVersion;
+    use crate::wasm::errors::wasmi_error_to_enclave_error;
+    use crate::wasm::runtime::{create_builder, Engine, WasmiImportResolver};
+
+    const V0_10_RESOLVER: WasmiImportResolver = WasmiImportResolver {
+        api_version: ApiVersion::V0_10,
+    };
+
+    // A module that only exports a memory, which is all `ContractInstance` needs
+    const MEMORY_WASM: &[u8] = b"\0asm\x01\0\0\0\x05\x03\x01\x00\x01\x07\x0a\x01\x06memory\x02\x00";
+
+    // (module
+    //   (import "env" "test_panic" (func $test_panic))
+    //   (memory (export "memory") 1)
+    //   (func (export "run") call $test_panic))
+    const TEST_PANIC_WASM: &[u8] = b"\0asm\x01\0\0\0\x01\x04\x01\x60\0\0\x02\x12\x01\x03env\x0atest_panic\0\0\x03\x02\x01\0\x05\x03\x01\0\x01\x07\x10\x02\x03run\0\x01\x06memory\x02\0\x0a\x06\x01\x04\0\x10\0\x0b";
+
+    // (module
+    //   (import "env" "gas" (func $gas (param i32)))
+    //   (memory (export "memory") 1)
+    //   (func (export "loop") (loop (call $gas (i32.const 1000)) (br 0)))
+    //   (func (export "trap") (call $gas (i32.const 1500)) unreachable))
+    const GAS_WASM: &[u8] = b"\0asm\x01\0\0\0\x01\x08\x02\x60\x01\x7f\0\x60\0\0\x02\x0b\x01\x03env\x03gas\0\0\x03\x03\x02\x01\x01\x05\x03\x01\0\x01\x07\x18\x03\x06memory\x02\0\x04loop\0\x01\x04trap\0\x02\x0a\x17\x02\x0c\0\x03\x40\x41\xe8\x07\x10\0\x0c\0\x0b\x0b\x08\0\x41\xdc\x0b\x10\0\0\x0b";
+
+    // (module
+    //   (import "env" "gas" (func $gas (param i32)))
+    //   (memory (export "memory") 1)
+    //   (func (export "query") (param i32) (call $gas (local.get 0))))
+    const QUERY_GAS_WASM: &[u8] = b"\0asm\x01\0\0\0\x01\x05\x01\x60\x01\x7f\0\x02\x0b\x01\x03env\x03gas\0\0\x03\x02\x01\0\x05\x03\x01\0\x01\x07\x12\x02\x06memory\x02\0\x05query\0\x01\x0a\x08\x01\x06\0\x20\0\x10\0\x0b";
+
+    // (module
+    //   (memory (export "memory") 1)
+    //   (func $hog (export "hog") (result i32)
+    //     (loop (br_if 0 (i32.ne (memory.grow (i32.const 64)) (i32.const -1))))
+    //     (memory.size))
+    //   (func (export "handle") (param i32 i32) (result i32) (drop (call $hog)) unreachable))
+    const MEMORY_HOG_WASM: &[u8] = b"\0asm\x01\0\0\0\x01\x0b\x02\x60\x02\x7f\x7f\x01\x7f\x60\0\x01\x7f\x03\x03\x02\x01\0\x05\x03\x01\0\x01\x07\x19\x03\x06memory\x02\0\x03hog\0\0\x06handle\0\x01\x0a\x1a\x02\x11\0\x03\x40\x41\xc0\0\x40\0\x41\x7f\x47\x0d\0\x0b\x3f\0\x0b\x06\0\x10\0\x1a\0\x0b";
+
+    // (module
+    //   (import "env" "gas" (func $gas (param i32)))
+    //   (import "env" "gas_left" (func $gas_left (result i64)))
+    //   (import "env" "gas_limit" (func $gas_limit (result i64)))
+    //   (memory (export "memory") 1)
+    //   (func (export "batch") (param $items i32) (param $threshold i64) (result i32)
+    //     (local $cursor i32)
+    //     (block $done
+    //       (loop $next
+    //         (br_if $done (i32.ge_u (local.get $cursor) (local.get $items)))
+    //         (br_if $done (i64.lt_u (call $gas_left) (local.get $threshold)))
+    //         (call $gas (i32.const 1000))
+    //         (local.set $cursor (i32.add (local.get $cursor) (i32.const 1)))
+    //         (br $next)))
+    //     (local.get $cursor))
+    //   (func (export "limit") (result i64) call $gas_limit))
+    const BATCH_WASM: &[u8] = b"\0asm\x01\0\0\0\x01\x0f\x03\x60\x01\x7f\0\x60\0\x01\x7e\x60\x02\x7f\x7e\x01\x7f\x02\x2a\x03\x03env\x03gas\0\0\x03env\x08gas_left\0\x01\x03env\x09gas_limit\0\x01\x03\x03\x02\x02\x01\x05\x03\x01\0\x01\x07\x1a\x03\x06memory\x02\0\x05batch\0\x03\x05limit\0\x04\x0a\x2f\x02\x28\x01\x01\x7f\x02\x40\x03\x40\x20\x02\x20\0\x4f\x0d\x01\x10\x01\x20\x01\x54\x0d\x01\x41\xe8\x07\x10\0\x20\x02\x41\x01\x6a\x21\x02\x0c\0\x0b\x0b\x20\x02\x0b\x04\0\x10\x02\x0b";
+
+    // Derives the HKDF-SHA256 test cases of RFC 5869, see testdata/hkdf/README.md
+    const HKDF_WASM: &[u8] = include_bytes!("../../../testdata/hkdf/hkdf.wasm");
+    const HKDF_OUTPUT_REGION: u32 = 0x10;
+
+    fn instance(gas_limit: u64) -> (ContractInstance, ModuleRef) {
+        let module = wasmi::Module::from_buffer(MEMORY_WASM).unwrap();
+        let module = ModuleInstance::new(&module, &ImportsBuilder::default())
+            .unwrap()
+            .assert_no_start();
+        (instance_of(module.clone(), gas_limit), module)
+    }
+
+    fn instance_of(module: ModuleRef, gas_limit: u64) -> ContractInstance {
+        ContractInstance::new(
+            Ctx {
+                data: std::ptr::null_mut(),
+            },
+            module,
+            gas_limit,
+            WasmCosts::default(),
+            [0u8; 64],
+            ContractOperation::Handle,
+            [0u8; 32],
+            [0u8; 32],
+            None,
+        )
+    }
+
+    pub fn test_gas_breakdown_adds_up() {
+        let gas_limit = 10_000_000;
+        let (mut instance, module) = instance(gas_limit);
+        let costs = instance.gas_costs.ocall;
+
+        // The gas a contract doing 3 reads, 2 writes, a remove and a query is charged
+        instance.use_gas(50_000).unwrap();
+        for _ in 0..3 {
+            instance.use_ocall_gas(costs.read_db).unwrap();
+            instance.use_storage_gas(1_000).unwrap();
+        }
+        for _ in 0..2 {
+            instance.use_ocall_gas(costs.write_db).unwrap();
+            instance.use_storage_gas(3_000).unwrap();
+        }
+        instance.use_ocall_gas(costs.remove_db).unwrap();
+        instance.use_storage_gas(500).unwrap();
+        instance.use_ocall_gas(costs.query_chain).unwrap();
+
+        let total = gas_limit - instance.gas_left();
+        let engine = Engine::new(instance, module, ApiVersion::V0_10);
+        let breakdown = engine.gas_breakdown();
+
+        assert_eq!(breakdown.wasm, 50_000);
+        assert_eq!(breakdown.storage, 3 * 1_000 + 2 * 3_000 + 500);
+        assert_eq!(
+            breakdown.ocall,
+            3 * costs.read_db + 2 * costs.write_db + costs.remove_db + costs.query_chain
+        );
+        assert_eq!(total, breakdown.wasm + breakdown.storage + breakdown.ocall);
+        // The host already charged the storage gas, so it's not reported again
+        assert_eq!(
+            engine.gas_used_after_refund(),
+            breakdown.wasm + breakdown.ocall
+        );
+    }
+
+    pub fn test_ocall_gas_counts_towards_the_limit() {
+        let (mut instance, _module) = instance(10_000);
+        let read_db = 6_000;
+
+        instance.use_ocall_gas(read_db).unwrap();
+        assert!(matches!(
+            instance.use_ocall_gas(read_db),
+            Err(WasmEngineError::OutOfGas)
+        ));
+    }
+
+    pub fn test_panic_in_host_function_is_reported() {
+        let module = wasmi::Module::from_buffer(TEST_PANIC_WASM).unwrap();
+        let module = ModuleInstance::new(&module, &create_builder(&V0_10_RESOLVER))
+            .unwrap()
+            .assert_no_start();
+        let mut instance = instance_of(module.clone(), 10_000);
+
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            module.invoke_export("run", &[], &mut instance)
+        }));
+        assert!(result.is_err());
+        panic_report::record_ecall("ecall_handle");
+
+        let report = panic_report::last_report().unwrap();
+        assert_eq!(report.ecall, "ecall_handle");
+        assert_eq!(report.message, "test_panic was called by the contract");
+        assert!(report.location.contains("contract.rs"));
+        assert!(!report.summary().contains(&report.message));
+
+        let sealed = panic_report::seal_report(&report).unwrap();
+        assert!(!sealed
+            .windows(report.message.len())
+            .any(|window| window == report.message.as_bytes()));
+        assert_eq!(panic_report::unseal_report(&sealed).unwrap(), report);
+
+        // A report can't be unsealed after it was tampered with
+        let mut tampered = sealed;
+        let last = tampered.len() - 1;
+        tampered[last] ^= 1;
+        assert!(panic_report::unseal_report(&tampered).is_err());
+    }
+
+    pub fn test_abort_is_reported_as_a_panic() {
+        let module =
+            wasmi::Module::from_buffer(include_bytes!("../../../testdata/api-versions/v1.wasm"))
+                .unwrap();
+        let resolver = WasmiImportResolver {
+            api_version: ApiVersion::V1,
+        };
+        let module = ModuleInstance::new(&module, &create_builder(&resolver))
+            .unwrap()
+            .assert_no_start();
+        let mut instance = instance_of(module.clone(), 10_000);
+
+        let err = module.invoke_export("run", &[], &mut instance).unwrap_err();
+        assert!(matches!(
+            wasmi_error_to_enclave_error(err),
+            EnclaveError::ContractPanicUnreachable
+        ));
+    }
+
+    fn run_gas_wasm(export: &str, gas_limit: u64) -> ContractInstance {
+        let module = wasmi::Module::from_buffer(GAS_WASM).unwrap();
+        let module = ModuleInstance::new(&module, &create_builder(&V0_10_RESOLVER))
+            .unwrap()
+            .assert_no_start();
+        let mut instance = instance_of(module.clone(), gas_limit);
+
+        assert!(module.invoke_export(export, &[], &mut instance).is_err());
+        instance
+    }
+
+    pub fn test_out_of_gas_mid_loop_gas() {
+        crate::wasm::gas::clear_metered_gas();
+        let instance = run_gas_wasm("loop", 10_500);
+
+        // The 11th iteration charges 1000 gas past the limit and stops the contract
+        assert_eq!(instance.gas_used, 11_000);
+        assert_eq!(crate::wasm::gas::failed_ecall_gas(10_500), 10_500);
+    }
+
+    fn run_gas_wasm_in_block(
+        gas_limit: u64,
+        block_gas_remaining: u64,
+    ) -> (ContractInstance, EnclaveError) {
+        let module = wasmi::Module::from_buffer(GAS_WASM).unwrap();
+        let module = ModuleInstance::new(&module, &create_builder(&V0_10_RESOLVER))
+            .unwrap()
+            .assert_no_start();
+        let mut instance = instance_of(module.clone(), gas_limit);
+        instance.limit_to_block_gas(block_gas_remaining);
+
+        let err = module
+            .invoke_export("loop", &[], &mut instance)
+            .unwrap_err();
+        (instance, wasmi_error_to_enclave_error(err))
+    }
+
+    pub fn test_out_of_block_gas() {
+        crate::wasm::gas::clear_metered_gas();
+        let (instance, err) = run_gas_wasm_in_block(100_000, 10_500);
+
+        // The block had less gas left than the tx, so the block is what ran out
+        assert!(matches!(err, EnclaveError::OutOfBlockGas));
+        assert_eq!(instance.gas_limit, 10_500);
+        // The gas is reported as metered when the contract stopped, below the limit of the tx
+        assert_eq!(instance.gas_used, 11_000);
+        assert_eq!(crate::wasm::gas::failed_ecall_gas(100_000), 11_000);
+    }
+
+    pub fn test_out_of_gas_below_block_gas() {
+        crate::wasm::gas::clear_metered_gas();
+        let (instance, err) = run_gas_wasm_in_block(10_500, 100_000);
+
+        assert!(matches!(err, EnclaveError::OutOfGas));
+        assert_eq!(instance.gas_limit, 10_500);
+        assert_eq!(instance.gas_used, 11_000);
+        assert_eq!(crate::wasm::gas::failed_ecall_gas(10_500), 10_500);
+
+        // When both limits are the same, the tx asked for no more than the block had left
+        let (_instance, err) = run_gas_wasm_in_block(10_500, 10_500);
+        assert!(matches!(err, EnclaveError::OutOfGas));
+    }
+
+    pub fn test_unreachable_gas() {
+        crate::wasm::gas::clear_metered_gas();
+        let instance = run_gas_wasm("trap", 10_500);
+
+        // Only the gas used before the unreachable instruction is charged
+        assert_eq!(instance.gas_used, 1_500);
+        assert_eq!(crate::wasm::gas::failed_ecall_gas(10_500), 1_500);
+        // The metered gas is only charged once
+        assert_eq!(crate::wasm::gas::failed_ecall_gas(10_500), 10_500);
+    }
+
+    pub fn test_infinite_loop_query_runs_out_of_gas() {
+        let gas_limit = crate::wasm::gas::query_gas_limit(u64::MAX);
+        let module = wasmi::Module::from_buffer(GAS_WASM).unwrap();
+        let module = ModuleInstance::new(&module, &create_builder(&V0_10_RESOLVER))
+            .unwrap()
+            .assert_no_start();
+        let mut instance = instance_of(module.clone(), gas_limit);
+
+        // An unlimited query stops at the default limit, with its own error
+        let err = module
+            .invoke_export("loop", &[], &mut instance)
+            .unwrap_err();
+        assert!(matches!(
+            wasmi_error_to_enclave_error(err),
+            EnclaveError::OutOfGas
+        ));
+        assert_eq!(gas_limit, crate::wasm::gas::DEFAULT_QUERY_GAS_LIMIT);
+        assert_eq!(instance.gas_used, gas_limit + 1_000);
+    }
+
+    pub fn test_execution_deadline_stops_spin_loop() {
+        crate::wasm::gas::clear_metered_gas();
+        let gas_limit = u64::MAX / 2;
+        let module = wasmi::Module::from_buffer(GAS_WASM).unwrap();
+        let module = ModuleInstance::new(&module, &create_builder(&V0_10_RESOLVER))
+            .unwrap()
+            .assert_no_start();
+        let mut instance = instance_of(module.clone(), gas_limit);
+        instance.execution_deadline = Some(ExecutionDeadline::start(1).unwrap());
+
+        let err = module
+            .invoke_export("loop", &[], &mut instance)
+            .unwrap_err();
+        assert!(matches!(
+            wasmi_error_to_enclave_error(err),
+            EnclaveError::ExecutionTimeout
+        ));
+        // The clock is only read every so often, and the gas used until then is still charged
+        assert!(instance.gas_used >= crate::consts::EXECUTION_DEADLINE_CHECK_INTERVAL);
+        assert_eq!(
+            crate::wasm::gas::failed_ecall_gas(gas_limit),
+            instance.gas_used
+        );
+    }
+
+    pub fn test_execution_timeout_only_applies_to_queries() {
+        let module = wasmi::Module::from_buffer(GAS_WASM).unwrap();
+        let module = ModuleInstance::new(&module, &create_builder(&V0_10_RESOLVER))
+            .unwrap()
+            .assert_no_start();
+
+        // A tx runs the same on every node however long it takes
+        let mut instance = instance_of(module.clone(), 10_500);
+        instance.set_execution_timeout(1).unwrap();
+        assert!(instance.execution_deadline.is_none());
+
+        let mut instance = instance_of(module, 10_500);
+        instance.operation = ContractOperation::Query;
+        instance.set_execution_timeout(1).unwrap();
+        assert!(instance.execution_deadline.is_some());
+    }
+
+    pub fn test_execution_deadline_never_adds_gas() {
+        let module = wasmi::Module::from_buffer(GAS_WASM).unwrap();
+        let module = ModuleInstance::new(&module, &create_builder(&V0_10_RESOLVER))
+            .unwrap()
+            .assert_no_start();
+        let mut instance = instance_of(module.clone(), 10_500);
+        instance.execution_deadline = Some(ExecutionDeadline::start(60_000).unwrap());
+
+        // A contract that runs out of gas before its deadline stops the same as without one
+        let err = module
+            .invoke_export("loop", &[], &mut instance)
+            .unwrap_err();
+        assert!(matches!(
+            wasmi_error_to_enclave_error(err),
+            EnclaveError::OutOfGas
+        ));
+        assert_eq!(instance.gas_used, 11_000);
+    }
+
+    pub fn test_query_just_under_the_gas_limit() {
+        let module = wasmi::Module::from_buffer(QUERY_GAS_WASM).unwrap();
+        let module = ModuleInstance::new(&module, &create_builder(&V0_10_RESOLVER))
+            .unwrap()
+            .assert_no_start();
+
+        let mut instance = instance_of(module.clone(), 10_500);
+        assert!(module
+            .invoke_export("query", &[RuntimeValue::I32(10_500)], &mut instance)
+            .is_ok());
+        assert_eq!(instance.gas_used, 10_500);
+
+        // One more unit of gas is too much
+        let mut instance = instance_of(module.clone(), 10_500);
+        let err = module
+            .invoke_export("query", &[RuntimeValue::I32(10_501)], &mut instance)
+            .unwrap_err();
+        assert!(matches!(
+            wasmi_error_to_enclave_error(err),
+            EnclaveError::OutOfGas
+        ));
+        assert_eq!(instance.gas_used, 10_501);
+    }
+
+    pub fn test_panic_before_metering_gas() {
+        crate::wasm::gas::clear_metered_gas();
+        assert_eq!(crate::wasm::gas::failed_ecall_gas(10_500), 10_500);
+
+        // A contract that panics before it used any gas is charged nothing
+        let module = wasmi::Module::from_buffer(TEST_PANIC_WASM).unwrap();
+        let module = ModuleInstance::new(&module, &create_builder(&V0_10_RESOLVER))
+            .unwrap()
+            .assert_no_start();
+        let mut instance = instance_of(module.clone(), 10_500);
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            module.invoke_export("run", &[], &mut instance)
+        }));
+        assert!(result.is_err());
+        assert_eq!(crate::wasm::gas::failed_ecall_gas(10_500), 0);
+    }
+
+    /// Gas limits at the edges of the u64 range, and around every power of two in between
+    fn gas_limit_sweep() -> Vec<u64> {
+        let mut limits = vec![0, 1, 2, u64::MAX - 1, u64::MAX];
+        for bit in 1..64 {
+            let power = 1u64 << bit;
+            limits.extend_from_slice(&[power - 1, power, power + 1]);
+        }
+        limits
+    }
+
+    pub fn test_gas_limit_sweep_never_panics() {
+        let module = wasmi::Module::from_buffer(QUERY_GAS_WASM).unwrap();
+        let module = ModuleInstance::new(&module, &create_builder(&V0_10_RESOLVER))
+            .unwrap()
+            .assert_no_start();
+
+        // -1 is charged as u64::MAX, the most a contract can ask for in one call
+        let amounts = [0, 1, 10_500, i32::MAX, -1];
+        for gas_limit in gas_limit_sweep() {
+            for &amount in amounts.iter() {
+                let mut instance = instance_of(module.clone(), gas_limit);
+                let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+                    let result =
+                        module.invoke_export("query", &[RuntimeValue::I32(amount)], &mut instance);
+                    // Charge the same amount again, to push the counter past u64::MAX
+                    let again =
+                        module.invoke_export("query", &[RuntimeValue::I32(amount)], &mut instance);
+                    (result, again)
+                }));
+                let (result, again) = match result {
+                    Ok(results) => results,
+                    Err(_) => panic!("gas limit {} panicked charging {}", gas_limit, amount),
+                };
+
+                let charged = amount as u64;
+                match result {
+                    Ok(_) => assert!(charged <= gas_limit),
+                    Err(err) => {
+                        assert!(charged > gas_limit);
+                        assert!(matches!(
+                            wasmi_error_to_enclave_error(err),
+                            EnclaveError::OutOfGas
+                        ));
+                    }
+                }
+                match again {
+                    Ok(_) => assert!(charged.saturating_add(charged) <= gas_limit),
+                    Err(err) => assert!(matches!(
+                        wasmi_error_to_enclave_error(err),
+                        EnclaveError::OutOfGas
+                    )),
+                }
+                assert_eq!(instance.gas_used, charged.saturating_add(charged));
+
+                let engine = Engine::new(instance, module.clone(), ApiVersion::V0_10);
+                assert!(engine.gas_used_after_refund() <= engine.gas_used());
+            }
+        }
+    }
+
+    pub fn test_zero_gas_limit_charges_nothing() {
+        let mut used_gas = 10_500;
+        assert!(matches!(
+            crate::wasm::gas::check_gas_limit(0, &mut used_gas),
+            Err(EnclaveError::OutOfGas)
+        ));
+        assert_eq!(used_gas, 0);
+
+        for gas_limit in gas_limit_sweep().into_iter().filter(|limit| *limit != 0) {
+            let mut used_gas = 0;
+            assert!(crate::wasm::gas::check_gas_limit(gas_limit, &mut used_gas).is_ok());
+            assert_eq!(used_gas, 0);
+        }
+    }
+
+    /// Load `MEMORY_HOG_WASM` the way contracts are loaded, with its memory limited and its
+    /// `memory.grow` instructions hooked
+    fn memory_hog(gas_limit: u64) -> (ContractInstance, ModuleRef) {
+        let mut module = parity_wasm::elements::deserialize_buffer(MEMORY_HOG_WASM).unwrap();
+        crate::wasm::memory::validate_memory(&mut module).unwrap();
+        let module = crate::wasm::memory::hook_memory_grow(module);
+
+        let module = wasmi::Module::from_parity_wasm_module(module).unwrap();
+        let module = ModuleInstance::new(&module, &create_builder(&V0_10_RESOLVER))
+            .unwrap()
+            .assert_no_start();
+        (instance_of(module.clone(), gas_limit), module)
+    }
+
+    pub fn test_memory_grow_stops_at_the_limit() {
+        let (mut instance, module) = memory_hog(10_000_000);
+
+        // The contract grows 64 pages at a time, from 1 page, until it's refused
+        let result = module.invoke_export("hog", &[], &mut instance).unwrap();
+        assert_eq!(result, Some(RuntimeValue::I32(129)));
+        assert!(instance.exceeded_memory_limit);
+        assert!(instance.get_memory().current_size().0 as u32 <= LEGACY_WASM_MEMORY_PAGES);
+
+        // The refused attempt is charged too
+        assert_eq!(
+            instance.gas_used,
+            4 * 64 * instance.gas_costs.grow_mem as u64
+        );
+    }
+
+    pub fn test_memory_grow_stops_at_the_raised_limit() {
+        let (mut instance, module) = memory_hog(10_000_000);
+        instance.max_memory_pages = MAX_WASM_MEMORY_PAGES;
+
+        let result = module.invoke_export("hog", &[], &mut instance).unwrap();
+        assert_eq!(result, Some(RuntimeValue::I32(449)));
+        assert!(instance.exceeded_memory_limit);
+        assert!(instance.get_memory().current_size().0 as u32 <= MAX_WASM_MEMORY_PAGES);
+
+        assert_eq!(
+            instance.gas_used,
+            8 * 64 * instance.gas_costs.grow_mem as u64
+        );
+    }
+
+    pub fn test_memory_grow_runs_out_of_gas() {
+        let (mut instance, module) = memory_hog(1_000_000);
+
+        let result = module.invoke_export("hog", &[], &mut instance);
+        assert!(result.is_err());
+        assert!(!instance.exceeded_memory_limit);
+        assert!(instance.gas_used > instance.gas_limit);
+    }
+
+    pub fn test_memory_limit_exceeded_error() {
+        for &max_memory_pages in &[LEGACY_WASM_MEMORY_PAGES, MAX_WASM_MEMORY_PAGES] {
+            let (instance, module) = memory_hog(10_000_000);
+            let mut engine = Engine::new(instance, module, ApiVersion::V0_10);
+            engine.set_max_memory_pages(max_memory_pages);
+
+            // The contract panics once it can't get more memory
+            assert!(matches!(
+                engine.handle(0, None, 0),
+                Err(EnclaveError::ContractMemoryLimitExceeded)
+            ));
+        }
+    }
+
+    pub fn test_initial_memory_is_limited() {
+        for &(initial_pages, valid) in &[
+            (LEGACY_WASM_MEMORY_PAGES, true),
+            (LEGACY_WASM_MEMORY_PAGES + 1, false),
+            (MAX_WASM_MEMORY_PAGES + 1, false),
+        ] {
+            let mut module: parity_wasm::elements::Module =
+                parity_wasm::elements::deserialize_buffer(MEMORY_HOG_WASM).unwrap();
+            *module.memory_section_mut().unwrap().entries_mut() =
+                vec![parity_wasm::elements::MemoryType::new(initial_pages, None)];
+
+            let result = crate::wasm::memory::validate_memory(&mut module);
+            assert_eq!(result.is_ok(), valid, "{} initial pages", initial_pages);
+            if valid {
+                let maximum = module.memory_section().unwrap().entries()[0]
+                    .limits()
+                    .maximum();
+                assert_eq!(maximum, Some(MAX_WASM_MEMORY_PAGES));
+            }
+        }
+    }
+
+    fn set_region(instance: &ContractInstance, region: u32, ptr: u32, cap: u32, len: u32) {
+        let memory = instance.get_memory();
+        memory.set_value(region, ptr).unwrap();
+        memory.set_value(region + 4, cap).unwrap();
+        memory.set_value(region + 8, len).unwrap();
+    }
+
+    const INPUT_REGION: u32 = 0x10;
+    const OUTPUT_REGION: u32 = 0x20;
+    const INPUT: u32 = 0x100;
+    const OUTPUT: u32 = 0x200;
+
+    /// Hash `input` with the `hash_*` import `function`, into an output buffer of `output_cap`
+    fn call_hash(
+        function: &str,
+        input: &[u8],
+        output_cap: u32,
+    ) -> (i32, Vec<u8>, ContractInstance) {
+        let (mut instance, _module) = instance(10_000_000);
+        instance.get_memory().set(INPUT, input).unwrap();
+        set_region(
+            &instance,
+            INPUT_REGION,
+            INPUT,
+            input.len() as u32,
+            input.len() as u32,
+        );
+        set_region(&instance, OUTPUT_REGION, OUTPUT, output_cap, 0);
+
+        let result = match function {
+            "hash_sha256" => instance.hash_sha256_index(INPUT_REGION as i32, OUTPUT_REGION as i32),
+            "hash_keccak256" => {
+                instance.hash_keccak256_index(INPUT_REGION as i32, OUTPUT_REGION as i32)
+            }
+            "hash_ripemd160" => {
+                instance.hash_ripemd160_index(INPUT_REGION as i32, OUTPUT_REGION as i32)
+            }
+            _ => unreachable!(),
+        };
+        let status = match result.unwrap() {
+            Some(RuntimeValue::I32(status)) => status,
+            other => panic!("{} returned {:?}", function, other),
+        };
+
+        let output_len: u32 = instance.get_memory().get_value(OUTPUT_REGION + 8).unwrap();
+        let output = instance
+            .get_memory()
+            .get(OUTPUT, output_len as usize)
+            .unwrap();
+        (status, output, instance)
+    }
+
+    pub fn test_hash_known_vectors() {
+        let vectors: &[(&str, &[u8], &str)] = &[
+            (
+                "hash_sha256",
+                b"abc",
+                "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            ),
+            (
+                "hash_keccak256",
+                b"abc",
+                "4e03657aea45a94fc7d47ba826c8d667c0d1e6e33a64a036ec44f58fa12d6c45",
+            ),
+            (
+                "hash_ripemd160",
+                b"abc",
+                "8eb208f7e05d987a9b044a8e98c6b087f15a0bfc",
+            ),
+        ];
+
+        for (function, input, digest) in vectors {
+            let (status, output, _) = call_hash(function, input, 32);
+            assert_eq!(status, HASH_SUCCESS, "{}", function);
+            assert_eq!(hex::encode(output), *digest, "{}", function);
+        }
+    }
+
+    pub fn test_hash_empty_input() {
+        let (status, output, hashed) = call_hash("hash_sha256", b"", 32);
+        assert_eq!(status, HASH_SUCCESS);
+        assert_eq!(
+            hex::encode(output),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        // Only the base cost is charged
+        assert_eq!(
+            hashed.gas_used,
+            hashed.gas_costs.external_hash_sha256_base as u64
+        );
+
+        // An empty region doesn't need a buffer
+        let (mut instance, _module) = instance(10_000_000);
+        set_region(&instance, INPUT_REGION, 0, 0, 0);
+        set_region(&instance, OUTPUT_REGION, OUTPUT, 32, 0);
+        let result = instance
+            .hash_keccak256_index(INPUT_REGION as i32, OUTPUT_REGION as i32)
+            .unwrap();
+        assert_eq!(result, Some(RuntimeValue::I32(HASH_SUCCESS)));
+    }
+
+    pub fn test_hash_charges_per_byte() {
+        let input = [7u8; 1000];
+        let (status, _, instance) = call_hash("hash_keccak256", &input, 32);
+        assert_eq!(status, HASH_SUCCESS);
+        assert_eq!(
+            instance.gas_used,
+            instance.gas_costs.external_hash_keccak256_base as u64
+                + instance.gas_costs.external_hash_keccak256_per_byte as u64 * 1000
+        );
+    }
+
+    pub fn test_query_chain_answer_is_charged() {
+        let (mut instance, _module) = instance(10_000_000);
+        instance.charge_query_answer(1000).unwrap();
+        assert_eq!(
+            instance.gas_used,
+            instance.gas_costs.external_query_chain_per_byte as u64 * 1000
+        );
+        assert_eq!(instance.gas_used_externally, 0);
+    }
+
+    pub fn test_hash_output_too_small() {
+        let (status, output, _) = call_hash("hash_sha256", b"abc", 31);
+        assert_eq!(status, HASH_OUTPUT_TOO_SMALL);
+        assert!(output.is_empty());
+
+        // A RIPEMD-160 digest fits in 20 bytes
+        let (status, output, _) = call_hash("hash_ripemd160", b"abc", 20);
+        assert_eq!(status, HASH_SUCCESS);
+        assert_eq!(output.len(), 20);
+    }
+
+    pub fn test_hash_invalid_regions() {
+        let (mut instance, _module) = instance(10_000_000);
+        let memory_size = instance.get_memory().current_size().0 as u32 * 65536;
+        set_region(&instance, OUTPUT_REGION, OUTPUT, 32, 0);
+
+        // An input spanning the rest of the memory can be hashed
+        set_region(
+            &instance,
+            INPUT_REGION,
+            INPUT,
+            memory_size - INPUT,
+            memory_size - INPUT,
+        );
+        let expected = sha_256(
+            &instance
+                .get_memory()
+                .get(INPUT, (memory_size - INPUT) as usize)
+                .unwrap(),
+        );
+        let result = instance
+            .hash_sha256_index(INPUT_REGION as i32, OUTPUT_REGION as i32)
+            .unwrap();
+        assert_eq!(result, Some(RuntimeValue::I32(HASH_SUCCESS)));
+        assert_eq!(
+            instance.get_memory().get(OUTPUT, 32).unwrap(),
+            expected.to_vec()
+        );
+
+        // One byte more is out of bounds
+        set_region(
+            &instance,
+            INPUT_REGION,
+            INPUT,
+            memory_size - INPUT + 1,
+            memory_size - INPUT + 1,
+        );
+        let result = instance
+            .hash_sha256_index(INPUT_REGION as i32, OUTPUT_REGION as i32)
+            .unwrap();
+        assert_eq!(result, Some(RuntimeValue::I32(HASH_INVALID_INPUT)));
+
+        // As is the largest region length
+        set_region(&instance, INPUT_REGION, INPUT, u32::MAX, u32::MAX);
+        let result = instance
+            .hash_sha256_index(INPUT_REGION as i32, OUTPUT_REGION as i32)
+            .unwrap();
+        assert_eq!(result, Some(RuntimeValue::I32(HASH_INVALID_INPUT)));
+
+        // And regions that are themselves outside of the memory
+        let result = instance
+            .hash_sha256_index(-16, OUTPUT_REGION as i32)
+            .unwrap();
+        assert_eq!(result, Some(RuntimeValue::I32(HASH_INVALID_INPUT)));
+
+        set_region(&instance, INPUT_REGION, INPUT, 3, 3);
+        let result = instance
+            .hash_sha256_index(INPUT_REGION as i32, -16)
+            .unwrap();
+        assert_eq!(result, Some(RuntimeValue::I32(HASH_INVALID_OUTPUT)));
+
+        // An output region without a buffer, or with a buffer past the end of the memory
+        set_region(&instance, OUTPUT_REGION, 0, 32, 0);
+        let result = instance
+            .hash_sha256_index(INPUT_REGION as i32, OUTPUT_REGION as i32)
+            .unwrap();
+        assert_eq!(result, Some(RuntimeValue::I32(HASH_INVALID_OUTPUT)));
+
+        set_region(&instance, OUTPUT_REGION, memory_size - 16, 32, 0);
+        let result = instance
+            .hash_sha256_index(INPUT_REGION as i32, OUTPUT_REGION as i32)
+            .unwrap();
+        assert_eq!(result, Some(RuntimeValue::I32(HASH_INVALID_OUTPUT)));
+    }
+
+    pub fn test_ed25519_batch_verify_is_charged_per_signature() {
+        use crate::wasm::runtime::sections::encode_sections;
+        use ring::signature::{Ed25519KeyPair, KeyPair as _};
+
+        let key_pair = Ed25519KeyPair::from_seed_unchecked(&[7; 32]).unwrap();
+        let messages: Vec<Vec<u8>> = (0..3u8).map(|i| vec![i; 10]).collect();
+        let signatures: Vec<Vec<u8>> = messages
+            .iter()
+            .map(|message| key_pair.sign(message).as_ref().to_vec())
+            .collect();
+        let public_keys = vec![key_pair.public_key().as_ref().to_vec()];
+
+        let (mut instance, _module) = instance(10_000_000);
+        let inputs = [
+            (0x10, 0x1000, encode_sections(&messages)),
+            (0x20, 0x2000, encode_sections(&signatures)),
+            (0x30, 0x3000, encode_sections(&public_keys)),
+        ];
+        for (region, ptr, input) in inputs.iter() {
+            instance.get_memory().set(*ptr, input).unwrap();
+            let len = input.len() as u32;
+            set_region(&instance, *region, *ptr, len, len);
+        }
+
+        let result = instance
+            .ed25519_batch_verify_index(0x10, 0x20, 0x30)
+            .unwrap();
+        assert_eq!(result, Some(RuntimeValue::I32(ED25519_VERIFY_VALID)));
+
+        // Every signature costs as much as verifying it alone, and the gas is reported as used
+        assert_eq!(
+            instance.gas_used,
+            instance.gas_costs.external_ed25519_batch_verify_base as u64
+                + 3 * instance.gas_costs.external_ed25519_verify as u64
+        );
+        assert_eq!(instance.gas_used_externally, 0);
+    }
+
+    /// Print `message` with the `debug` import, and return the line it logged
+    fn call_debug(instance: &mut ContractInstance, message: &[u8]) -> String {
+        instance.get_memory().set(INPUT, message).unwrap();
+        set_region(
+            instance,
+            INPUT_REGION,
+            INPUT,
+            message.len() as u32,
+            message.len() as u32,
+        );
+
+        let _batch = LogBatch::begin();
+        let result = instance.debug_index(INPUT_REGION as i32).unwrap();
+        assert_eq!(result, None);
+        let lines = crate::log_buffer::tests::batched_lines().unwrap();
+        String::from_utf8(lines).unwrap()
+    }
+
+    pub fn test_debug_is_charged_per_byte() {
+        let (mut printed, _module) = instance(10_000_000);
+        call_debug(&mut printed, &[b'a'; 1000]);
+        // The host doesn't charge for printing, so the gas is reported as used
+        assert_eq!(
+            printed.gas_used,
+            printed.gas_costs.external_debug_base as u64
+                + printed.gas_costs.external_debug_per_byte as u64 * 1000
+        );
+        assert_eq!(printed.gas_used_externally, 0);
+
+        // An empty message only costs the base
+        let (mut instance, _module) = instance(10_000_000);
+        call_debug(&mut instance, b"");
+        assert_eq!(
+            instance.gas_used,
+            instance.gas_costs.external_debug_base as u64
+        );
+    }
+
+    #[cfg(not(feature = "production"))]
+    pub fn test_debug_message_passes_through() {
+        let (mut instance, _module) = instance(10_000_000);
+        assert_eq!(
+            call_debug(&mut instance, b"balance of secret1abc: 100\nuscrt"),
+            "contract debug: \"balance of secret1abc: 100\\nuscrt\"\n"
+        );
+    }
+
+    #[cfg(feature = "production")]
+    pub fn test_debug_message_is_redacted() {
+        let (mut instance, _module) = instance(10_000_000);
+        let line = call_debug(&mut instance, b"balance of secret1abc: 100");
+        assert!(line.starts_with("contract debug: [redacted "));
+        assert!(!line.contains("secret1abc"));
+
+        // The same message always gets the same id, and other messages other ids
+        assert_eq!(
+            call_debug(&mut instance, b"balance of secret1abc: 100"),
+            line
+        );
+        assert_ne!(
+            call_debug(&mut instance, b"balance of secret1abc: 101"),
+            line
+        );
+    }
+
+    /// Derive `output_len` bytes of RFC 5869 test case `case` from inside `HKDF_WASM`
+    fn hkdf_case(case: u32, output_len: i32) -> (i32, Vec<u8>, ContractInstance) {
+        let module = wasmi::Module::from_buffer(HKDF_WASM).unwrap();
+        let module = ModuleInstance::new(&module, &create_builder(&V0_10_RESOLVER))
+            .unwrap()
+            .assert_no_start();
+        let mut instance = instance_of(module.clone(), 10_000_000);
+
+        let export = format!("rfc5869_case_{}", case);
+        let status = match module
+            .invoke_export(&export, &[RuntimeValue::I32(output_len)], &mut instance)
+            .unwrap()
+        {
+            Some(RuntimeValue::I32(status)) => status,
+            other => panic!("{} returned {:?}", export, other),
+        };
+
+        let memory = instance.get_memory();
+        let output_ptr: u32 = memory.get_value(HKDF_OUTPUT_REGION).unwrap();
+        let output_len: u32 = memory.get_value(HKDF_OUTPUT_REGION + 8).unwrap();
+        let output = memory.get(output_ptr, output_len as usize).unwrap();
+        (status, output, instance)
+    }
+
+    pub fn test_hkdf_sha256_rfc5869_vectors() {
+        let vectors: &[(u32, i32, &str)] = &[
+            (
+                1,
+                42,
+                "3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf34007208d5b887185865",
+            ),
+            (
+                2,
+                82,
+                "b11e398dc80327a1c8e7f78c596a49344f012eda2d4efad8a050cc4c19afa97c59045a99cac7827271cb41c65e590e09da3275600c2f09b8367793a9aca3db71cc30c58179ec3e87c14c01d5c1f3434f1d87",
+            ),
+            // Without salt and info
+            (
+                3,
+                42,
+                "8da4e775a563c18f715f802a063c5a31b8a11f5c5ee1879ec3454e5f3c738d2d9d201395faa4b61a96c8",
+            ),
+        ];
+
+        for (case, output_len, okm) in vectors {
+            let (status, output, _) = hkdf_case(*case, *output_len);
+            assert_eq!(status, HKDF_SUCCESS, "case {}", case);
+            assert_eq!(hex::encode(output), *okm, "case {}", case);
+        }
+    }
+
+    pub fn test_hkdf_sha256_output_length() {
+        // At most 255 blocks of SHA-256 can be derived
+        let (status, output, _) = hkdf_case(1, 8160);
+        assert_eq!(status, HKDF_SUCCESS);
+        assert_eq!(output.len(), 8160);
+        assert_eq!(
+            hex::encode(&output[..42]),
+            "3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf34007208d5b887185865"
+        );
+
+        for output_len in &[0, 8161, -1] {
+            let (status, output, instance) = hkdf_case(1, *output_len);
+            assert_eq!(status, HKDF_INVALID_OUTPUT_LENGTH, "{}", output_len);
+            assert!(output.is_empty());
+            // Only the base cost is charged
+            assert_eq!(
+                instance.gas_used,
+                instance.gas_costs.external_hkdf_sha256_base as u64
+            );
+        }
+    }
+
+    pub fn test_hkdf_sha256_charges_per_byte() {
+        let (_, _, short) = hkdf_case(1, 32);
+        let (_, _, long) = hkdf_case(1, 64);
+        assert_eq!(
+            long.gas_used - short.gas_used,
+            32 * long.gas_costs.external_hkdf_sha256_per_output_byte as u64
+        );
+
+        // 22 bytes of ikm, 13 of salt and 10 of info
+        assert_eq!(
+            short.gas_used,
+            short.gas_costs.external_hkdf_sha256_base as u64
+                + 45 * short.gas_costs.external_hkdf_sha256_per_input_byte as u64
+                + 32 * short.gas_costs.external_hkdf_sha256_per_output_byte as u64
+        );
+    }
+
+    pub fn test_hkdf_sha256_invalid_regions() {
+        let (mut instance, _module) = instance(10_000_000);
+        instance.get_memory().set(INPUT, &[0x0b; 22]).unwrap();
+        set_region(&instance, INPUT_REGION, INPUT, 22, 22);
+        set_region(&instance, OUTPUT_REGION, OUTPUT, 16, 0);
+        let mut derive = |ikm: i32, salt: i32, info: i32, output: i32| {
+            instance
+                .hkdf_sha256_index(ikm, salt, info, output, 42)
+                .unwrap()
+        };
+
+        assert_eq!(
+            derive(-16, 0, 0, OUTPUT_REGION as i32),
+            Some(RuntimeValue::I32(HKDF_INVALID_IKM))
+        );
+        assert_eq!(
+            derive(INPUT_REGION as i32, -16, 0, OUTPUT_REGION as i32),
+            Some(RuntimeValue::I32(HKDF_INVALID_SALT))
+        );
+        assert_eq!(
+            derive(INPUT_REGION as i32, 0, -16, OUTPUT_REGION as i32),
+            Some(RuntimeValue::I32(HKDF_INVALID_INFO))
+        );
+        assert_eq!(
+            derive(INPUT_REGION as i32, 0, 0, -16),
+            Some(RuntimeValue::I32(HKDF_INVALID_OUTPUT))
+        );
+        // The output region only has room for 16 bytes
+        assert_eq!(
+            derive(INPUT_REGION as i32, 0, 0, OUTPUT_REGION as i32),
+            Some(RuntimeValue::I32(HKDF_OUTPUT_TOO_SMALL))
+        );
+    }
+
+    const PATH: u32 = 0x300;
+    const PATH_REGION: u32 = 0x30;
+
+    /// Derive the key of `path` in a new instance for the contract at `contract_address` with
+    /// `contract_key`, and return its id
+    fn signing_instance_with_key(
+        contract_address: [u8; 20],
+        contract_key: [u8; 64],
+        path: &[u8],
+    ) -> (i32, ContractInstance) {
+        let (mut instance, _module) = instance(10_000_000);
+        instance.contract_key = contract_key;
+        instance.contract_address = Some(CanonicalAddr(Binary(contract_address.to_vec())));
+        instance.get_memory().set(PATH, path).unwrap();
+        set_region(
+            &instance,
+            PATH_REGION,
+            PATH,
+            path.len() as u32,
+            path.len() as u32,
+        );
+
+        let key_id = match instance
+            .secp256k1_generate_key_index(PATH_REGION as i32)
+            .unwrap()
+        {
+            Some(RuntimeValue::I32(key_id)) => key_id,
+            other => panic!("secp256k1_generate_key returned {:?}", other),
+        };
+        (key_id, instance)
+    }
+
+    fn signing_instance(contract_address: [u8; 20], path: &[u8]) -> (i32, ContractInstance) {
+        signing_instance_with_key(contract_address, [1u8; 64], path)
+    }
+
+    fn signing_public_key(instance: &mut ContractInstance, key_id: i32) -> (i32, Vec<u8>) {
+        set_region(instance, OUTPUT_REGION, OUTPUT, 65, 0);
+        let status = match instance
+            .secp256k1_public_key_index(key_id, OUTPUT_REGION as i32)
+            .unwrap()
+        {
+            Some(RuntimeValue::I32(status)) => status,
+            other => panic!("secp256k1_public_key returned {:?}", other),
+        };
+
+        let output_len: u32 = instance.get_memory().get_value(OUTPUT_REGION + 8).unwrap();
+        let output = instance
+            .get_memory()
+            .get(OUTPUT, output_len as usize)
+            .unwrap();
+        (status, output)
+    }
+
+    fn sign(
+        instance: &mut ContractInstance,
+        key_id: i32,
+        message_hash: &[u8],
+        output_cap: u32,
+    ) -> (i32, Vec<u8>) {
+        instance.get_memory().set(INPUT, message_hash).unwrap();
+        set_region(
+            instance,
+            INPUT_REGION,
+            INPUT,
+            message_hash.len() as u32,
+            message_hash.len() as u32,
+        );
+        set_region(instance, OUTPUT_REGION, OUTPUT, output_cap, 0);
+        let status = match instance
+            .secp256k1_sign_index(key_id, INPUT_REGION as i32, OUTPUT_REGION as i32)
+            .unwrap()
+        {
+            Some(RuntimeValue::I32(status)) => status,
+            other => panic!("secp256k1_sign returned {:?}", other),
+        };
+
+        let output_len: u32 = instance.get_memory().get_value(OUTPUT_REGION + 8).unwrap();
+        let output = instance
+            .get_memory()
+            .get(OUTPUT, output_len as usize)
+            .unwrap();
+        (status, output)
+    }
+
+    pub fn test_secp256k1_sign_recovers_to_public_key() {
+        let (key_id, mut instance) = signing_instance([1u8; 20], b"bridge");
+        assert_eq!(key_id, 1);
+        let (status, public_key) = signing_public_key(&mut instance, key_id);
+        assert_eq!(status, SECP256K1_SIGN_SUCCESS);
+        assert_eq!(public_key.len(), 65);
+
+        let message_hash = [0x42u8; MESSAGE_HASH_SIZE];
+        let (status, output) = sign(&mut instance, key_id, &message_hash, 65);
+        assert_eq!(status, SECP256K1_SIGN_SUCCESS);
+        assert_eq!(output.len(), 65);
+
+        let mut signature = [0u8; SECP256K1_SIGNATURE_SIZE];
+        signature.copy_from_slice(&output[..SECP256K1_SIGNATURE_SIZE]);
+        let recovery_id =
+            secp256k1::recovery::RecoveryId::from_i32(output[SECP256K1_SIGNATURE_SIZE] as i32)
+                .unwrap();
+        let recovered = secp256k1_recover_pubkey(&message_hash, &signature, recovery_id).unwrap();
+        assert_eq!(recovered.to_vec(), public_key);
+
+        // The same path gives the same key in another execution
+        let (_, mut again) = signing_instance([1u8; 20], b"bridge");
+        assert_eq!(signing_public_key(&mut again, 1).1, public_key);
+        assert_eq!(sign(&mut again, 1, &message_hash, 65).1, output);
+
+        // The host doesn't charge for signing, so it's reported as used by the contract
+        assert_eq!(instance.gas_used, 5_000 + 10_000 + 25_000);
+        assert_eq!(instance.gas_used_externally, 0);
+    }
+
+    pub fn test_secp256k1_signing_keys_are_per_contract() {
+        let (_, mut first) = signing_instance([1u8; 20], b"bridge");
+        let (_, mut other_contract) = signing_instance([2u8; 20], b"bridge");
+        let (_, mut other_path) = signing_instance([1u8; 20], b"bridge2");
+
+        let public_key = signing_public_key(&mut first, 1).1;
+        assert_ne!(signing_public_key(&mut other_contract, 1).1, public_key);
+        assert_ne!(signing_public_key(&mut other_path, 1).1, public_key);
+
+        // A migrated contract, or one whose contract key was rotated, keeps its keys
+        let (_, mut other_contract_key) =
+            signing_instance_with_key([1u8; 20], [2u8; 64], b"bridge");
+        assert_eq!(signing_public_key(&mut other_contract_key, 1).1, public_key);
+    }
+
+    pub fn test_secp256k1_generate_key_needs_an_authenticated_contract() {
+        let (_, mut instance) = signing_instance([1u8; 20], b"bridge");
+        instance.contract_address = None;
+        let key_id = instance
+            .secp256k1_generate_key_index(PATH_REGION as i32)
+            .unwrap();
+        assert_eq!(key_id, Some(RuntimeValue::I32(0)));
+        assert_eq!(instance.signing_keys.len(), 1);
+    }
+
+    pub fn test_secp256k1_sign_errors() {
+        let (key_id, mut instance) = signing_instance([1u8; 20], b"bridge");
+        let message_hash = [0x42u8; MESSAGE_HASH_SIZE];
+
+        for unknown in &[0, 2, -1] {
+            let (status, output) = sign(&mut instance, *unknown, &message_hash, 65);
+            assert_eq!(status, SECP256K1_SIGN_INVALID_KEY, "{}", unknown);
+            assert!(output.is_empty());
+            assert_eq!(
+                signing_public_key(&mut instance, *unknown).0,
+                SECP256K1_SIGN_INVALID_KEY
+            );
+        }
+
+        let (status, _) = sign(&mut instance, key_id, &message_hash[..31], 65);
+        assert_eq!(status, SECP256K1_SIGN_INVALID_HASH_FORMAT);
+        let (status, _) = sign(&mut instance, key_id, &[0x42u8; 33], 65);
+        assert_eq!(status, SECP256K1_SIGN_INVALID_HASH_FORMAT);
+
+        // The recovery id doesn't fit
+        let (status, output) = sign(&mut instance, key_id, &message_hash, 64);
+        assert_eq!(status, SECP256K1_SIGN_OUTPUT_TOO_SMALL);
+        assert!(output.is_empty());
+
+        let status = instance
+            .secp256k1_sign_index(key_id, INPUT_REGION as i32, -16)
+            .unwrap();
+        assert_eq!(
+            status,
+            Some(RuntimeValue::I32(SECP256K1_SIGN_INVALID_OUTPUT))
+        );
+    }
+
+    pub fn test_secp256k1_generate_key_path_too_long() {
+        let (key_id, _) = signing_instance([1u8; 20], &[7u8; MAX_SIGNING_KEY_PATH_LENGTH]);
+        assert_eq!(key_id, 1);
+        let (key_id, instance) =
+            signing_instance([1u8; 20], &[7u8; MAX_SIGNING_KEY_PATH_LENGTH + 1]);
+        assert_eq!(key_id, 0);
+        assert!(instance.signing_keys.is_empty());
+    }
+
+    /// Run the batch of `BATCH_WASM` over `items` items, and return the cursor it stopped at
+    fn run_batch(gas_limit: u64, items: i32, threshold: i64) -> (i32, ContractInstance) {
+        let module = wasmi::Module::from_buffer(BATCH_WASM).unwrap();
+        let module = ModuleInstance::new(&module, &create_builder(&V0_10_RESOLVER))
+            .unwrap()
+            .assert_no_start();
+        let mut instance = instance_of(module.clone(), gas_limit);
+
+        let cursor = module
+            .invoke_export(
+                "batch",
+                &[RuntimeValue::I32(items), RuntimeValue::I64(threshold)],
+                &mut instance,
+            )
+            .unwrap();
+        match cursor {
+            Some(RuntimeValue::I32(cursor)) => (cursor, instance),
+            other => panic!("batch returned {:?}", other),
+        }
+    }
+
+    pub fn test_gas_left_stops_batch_early() {
+        let gas_limit = 100_000;
+        let threshold = 20_000;
+        let (cursor, instance) = run_batch(gas_limit, 1_000, threshold as i64);
+
+        // Every item costs 1000 gas, plus the gas_left call before it
+        let per_item = 1_000 + instance.gas_costs.external_gas_left as u64;
+        let mut expected = 0;
+        while gas_limit - expected * per_item - instance.gas_costs.external_gas_left as u64
+            >= threshold
+        {
+            expected += 1;
+        }
+        assert_eq!(cursor as u64, expected);
+        assert!(cursor > 0);
+        assert!(instance.gas_left() < threshold);
+        assert!(instance.gas_left() > 0);
+
+        // The next call continues from the cursor with the same result on every run
+        let (again, rerun) = run_batch(gas_limit, 1_000, threshold as i64);
+        assert_eq!(again, cursor);
+        assert_eq!(rerun.gas_used, instance.gas_used);
+        assert_eq!(rerun.gas_used_externally, instance.gas_used_externally);
+    }
+
+    pub fn test_gas_left_batch_finishes() {
+        let (cursor, instance) = run_batch(100_000, 10, 20_000);
+        assert_eq!(cursor, 10);
+        assert_eq!(instance.gas_used, 10_000);
+        // One gas_left call before every item. The batch sees there are no more items before it
+        // would call gas_left again, so there's no extra call at the end.
+        assert_eq!(
+            instance.gas_used_externally,
+            10 * instance.gas_costs.external_gas_left as u64
+        );
+    }
+
+    pub fn test_gas_left_counts_all_gas() {
+        let (mut instance, _module) = instance(100_000);
+        instance.use_gas(10_000).unwrap();
+        instance.use_storage_gas(5_000).unwrap();
+        instance.use_ocall_gas(3_000).unwrap();
+
+        let gas_left = instance.gas_left_index().unwrap();
+        let expected = 100_000 - 18_000 - instance.gas_costs.external_gas_left as i64;
+        assert_eq!(gas_left, Some(RuntimeValue::I64(expected)));
+
+        let gas_limit = instance.gas_limit_index().unwrap();
+        assert_eq!(gas_limit, Some(RuntimeValue::I64(100_000)));
+    }
+
+    pub fn test_gas_limit_export() {
+        let module = wasmi::Module::from_buffer(BATCH_WASM).unwrap();
+        let module = ModuleInstance::new(&module, &create_builder(&V0_10_RESOLVER))
+            .unwrap()
+            .assert_no_start();
+        let mut instance = instance_of(module.clone(), 123_456);
+
+        let gas_limit = module.invoke_export("limit", &[], &mut instance).unwrap();
+        assert_eq!(gas_limit, Some(RuntimeValue::I64(123_456)));
+
+        // A limit too large for an i64 is reported as the largest one
+        let mut unlimited = instance_of(module.clone(), u64::MAX);
+        let gas_limit = module.invoke_export("limit", &[], &mut unlimited).unwrap();
+        assert_eq!(gas_limit, Some(RuntimeValue::I64(i64::MAX)));
+    }
 }
@@ -0,0 +1,63 @@
+//! The line that is logged when a contract calls `debug`.
+//!
+//! Contracts print debug messages while they're developed, and the messages often hold the
+//! amounts and addresses of their users. Production enclaves never pass a message to the host:
+//! the line they log only holds an id of the message, so an operator can still tell which calls
+//! printed the same message. Other builds log the message itself. Which of the two a build does
+//! is decided when it's compiled, the host can't change it.
+
+#[cfg(feature = "production")]
+use lazy_static::lazy_static;
+#[cfg(feature = "production")]
+use log::*;
+#[cfg(feature = "production")]
+use zeroize::Zeroizing;
+
+#[cfg(feature = "production")]
+use crate::crypto::{rand_slice, AESKey, Hmac, SymmetricKey};
+
+/// Starts the line of every debug message, so they can be told apart from the lines of the enclave
+pub const DEBUG_LINE_PREFIX: &str = "contract debug: ";
+
+/// How many bytes of the HMAC of a message are kept as its id
+#[cfg(feature = "production")]
+const MESSAGE_ID_SIZE: usize = 8;
+
+#[cfg(feature = "production")]
+lazy_static! {
+    /// Keys the ids of messages. It's drawn when the enclave starts and never leaves it, so an id
+    /// can't be matched against guesses of the message, like every amount a user may have sent.
+    static ref MESSAGE_ID_KEY: Option<AESKey> = {
+        let mut key: Zeroizing<SymmetricKey> = Zeroizing::new([0u8; 32]);
+        match rand_slice(&mut *key) {
+            Ok(()) => Some(AESKey::new_from_slice(&key)),
+            Err(err) => {
+                warn!("Failed to draw the key of debug message ids: {:?}", err);
+                None
+            }
+        }
+    };
+}
+
+/// The line that is logged for `message`: its id
+#[cfg(feature = "production")]
+pub fn debug_line(message: &[u8]) -> String {
+    match MESSAGE_ID_KEY.as_ref() {
+        Some(key) => format!(
+            "{}[redacted {}]",
+            DEBUG_LINE_PREFIX,
+            hex::encode(&key.sign_sha_256(message)[..MESSAGE_ID_SIZE])
+        ),
+        None => format!("{}[redacted]", DEBUG_LINE_PREFIX),
+    }
+}
+
+/// The line that is logged for `message`: the message, quoted so it stays on one line
+#[cfg(not(feature = "production"))]
+pub fn debug_line(message: &[u8]) -> String {
+    format!(
+        "{}{:?}",
+        DEBUG_LINE_PREFIX,
+        String::from_utf8_lossy(message)
+    )
+}
@@ -1,26 +1,222 @@
 use log::*;
-use wasmi::{ModuleRef, RuntimeValue};
+use wasmi::{Error as InterpreterError, ModuleRef, RuntimeValue};
 
 use super::contract::ContractInstance;
+use crate::consts::{LEGACY_WASM_MEMORY_PAGES, MAX_WASM_MEMORY_PAGES};
+use crate::cosmwasm::types::{CanonicalAddr, SealedForeignChain};
+use crate::crypto::Ed25519PublicKey;
+use crate::ecall_trace::Phase;
+use crate::trace_span;
+use crate::wasm::api_version::ApiVersion;
+use crate::wasm::contract_validation::ContractKey;
+use crate::wasm::db::StorageOverlay;
 use crate::wasm::errors::{wasmi_error_to_enclave_error, WasmEngineError};
+use crate::wasm::gas::{apply_storage_refund, OcallGasCosts};
+use crate::wasm::io::OutputLimits;
+#[cfg(feature = "debug-print")]
+use crate::wasm::trap_info::{self, TrapLocations};
+use crate::wasm::types::IoNonce;
+#[cfg(feature = "debug-print")]
+use std::sync::Arc;
+#[cfg(feature = "debug-print")]
+use wasmi::TrapKind;
 
-use enclave_ffi_types::EnclaveError;
+use enclave_ffi_types::{EnclaveError, GasBreakdown};
 
 pub struct Engine {
     contract_instance: ContractInstance,
     module: ModuleRef,
+    api_version: ApiVersion,
+    output_limits: OutputLimits,
+    execution_path: Option<Vec<u32>>,
 }
 
 impl Engine {
-    pub fn new(contract_instance: ContractInstance, module: ModuleRef) -> Self {
+    pub fn new(
+        contract_instance: ContractInstance,
+        module: ModuleRef,
+        api_version: ApiVersion,
+    ) -> Self {
         Self {
             contract_instance,
             module,
+            api_version,
+            output_limits: OutputLimits::default(),
+            execution_path: None,
         }
     }
 
+    /// The version of the CosmWasm API the contract was built for, which decides the shape of the
+    /// env and the entry points it's called through
+    pub fn api_version(&self) -> ApiVersion {
+        self.api_version
+    }
+
     pub fn gas_used(&self) -> u64 {
-        self.contract_instance.gas_used
+        self.contract_instance
+            .gas_used
+            .saturating_add(self.contract_instance.gas_used_by_overlay)
+            .saturating_add(self.contract_instance.gas_used_by_ocalls)
+    }
+
+    /// The gas used, minus the refund for storage the contract removed.
+    /// Only use this if the execution succeeded, since otherwise nothing was removed.
+    pub fn gas_used_after_refund(&self) -> u64 {
+        let breakdown = self.gas_breakdown();
+        breakdown
+            .wasm
+            .saturating_add(self.contract_instance.gas_used_by_overlay)
+            .saturating_add(breakdown.ocall)
+    }
+
+    /// Where the gas used by a successful execution was spent. The refund for removed storage is
+    /// taken from the wasm gas, so the ocall gas is always paid in full.
+    pub fn gas_breakdown(&self) -> GasBreakdown {
+        GasBreakdown {
+            wasm: apply_storage_refund(
+                self.contract_instance.gas_used,
+                self.contract_instance.removed_storage.bytes(),
+                &self.contract_instance.gas_costs,
+            ),
+            storage: self.contract_instance.gas_used_by_storage,
+            ocall: self.contract_instance.gas_used_by_ocalls,
+        }
+    }
+
+    /// Use the ocall costs the chain set in the env instead of the defaults
+    pub fn set_ocall_gas_costs(&mut self, ocall_gas_costs: OcallGasCosts) {
+        self.contract_instance.gas_costs.ocall = ocall_gas_costs;
+    }
+
+    /// Use the address prefix the chain set in the env instead of the default
+    pub fn set_bech32_prefix(&mut self, bech32_prefix: String) {
+        self.contract_instance.bech32_prefix = bech32_prefix;
+    }
+
+    /// Stop the query once it ran for longer than `timeout_ms`, as the node set in the env
+    pub fn set_execution_timeout(&mut self, timeout_ms: u64) -> Result<(), WasmEngineError> {
+        self.contract_instance.set_execution_timeout(timeout_ms)
+    }
+
+    /// Run with the gas left in the block as the gas limit, if the chain set less in the env than
+    /// the ecall was given
+    pub fn limit_to_block_gas(&mut self, block_gas_remaining: u64) {
+        self.contract_instance
+            .limit_to_block_gas(block_gas_remaining);
+    }
+
+    /// Refuse writes that would bring the contract past `storage_quota` bytes of storage, as the
+    /// chain set in the env
+    pub fn set_storage_quota(&mut self, storage_quota: u64) {
+        self.contract_instance.storage_quota = Some(storage_quota);
+    }
+
+    /// Let the contract grow its memory up to `max_memory_pages`, as the chain set in the env. Never
+    /// less than `LEGACY_WASM_MEMORY_PAGES` nor more than `MAX_WASM_MEMORY_PAGES`.
+    pub fn set_max_memory_pages(&mut self, max_memory_pages: u32) {
+        self.contract_instance.max_memory_pages = max_memory_pages
+            .max(LEGACY_WASM_MEMORY_PAGES)
+            .min(MAX_WASM_MEMORY_PAGES);
+    }
+
+    /// The address the contract key was authenticated for, which the signing keys of the
+    /// contract are derived from
+    pub fn set_contract_address(&mut self, contract_address: CanonicalAddr) {
+        self.contract_instance.contract_address = Some(contract_address);
+    }
+
+    /// Verify the headers of other chains at the block time in the env
+    pub fn set_block_time(&mut self, block_time: u64) {
+        self.contract_instance.block_time = Some(block_time);
+    }
+
+    /// Verify the headers of other chains against what the chain passed in the env
+    pub fn set_foreign_chains(&mut self, foreign_chains: Vec<SealedForeignChain>) {
+        self.contract_instance.foreign_chains = foreign_chains;
+    }
+
+    /// Report where the contract trapped, through the locations found when it was loaded
+    #[cfg(feature = "debug-print")]
+    pub fn set_trap_locations(&mut self, trap_locations: Arc<TrapLocations>) {
+        self.contract_instance.trap_locations = trap_locations;
+    }
+
+    /// Use the output limits the chain set in the env instead of the defaults
+    pub fn set_output_limits(&mut self, output_limits: OutputLimits) {
+        self.output_limits = output_limits;
+    }
+
+    /// How much the contract may return, checked before its output is encrypted
+    pub fn output_limits(&self) -> &OutputLimits {
+        &self.output_limits
+    }
+
+    /// Bind the log attributes of the contract to where the call is in its tx, see `encrypt_logs`
+    pub fn set_execution_path(&mut self, execution_path: Vec<u32>) {
+        self.execution_path = Some(execution_path);
+    }
+
+    pub fn execution_path(&self) -> Option<&[u32]> {
+        self.execution_path.as_deref()
+    }
+
+    pub fn bech32_prefix(&self) -> &str {
+        &self.contract_instance.bech32_prefix
+    }
+
+    /// Keep storage changes in the enclave instead of writing them, for simulations
+    pub fn use_storage_overlay(&mut self) {
+        self.contract_instance.storage_overlay = Some(StorageOverlay::default());
+        self.contract_instance.flushes_storage = false;
+    }
+
+    /// Keep storage changes in the enclave until `flush_storage`, so a key that changed many times
+    /// is written once
+    pub fn buffer_storage_writes(&mut self) {
+        self.contract_instance.storage_overlay = Some(StorageOverlay::default());
+        self.contract_instance.flushes_storage = true;
+    }
+
+    /// Write the storage changes kept by `buffer_storage_writes`. Called when the execution
+    /// succeeded, since the changes of a failed execution are discarded anyway.
+    pub fn flush_storage(&mut self) -> Result<(), EnclaveError> {
+        self.contract_instance
+            .flush_storage()
+            .map_err(EnclaveError::from)
+    }
+
+    /// A contract that panics after it was refused memory past its limit most likely panicked
+    /// because of it, so it gets an error that says so
+    fn call_error(&self, err: InterpreterError) -> EnclaveError {
+        #[cfg(feature = "debug-print")]
+        self.record_trap(&err);
+
+        match wasmi_error_to_enclave_error(err) {
+            EnclaveError::ContractPanicUnreachable
+                if self.contract_instance.exceeded_memory_limit =>
+            {
+                warn!("The contract panicked after it was refused memory past its limit");
+                EnclaveError::ContractMemoryLimitExceeded
+            }
+            err => err,
+        }
+    }
+
+    /// Keep the debug info of the trap that stopped the contract, if it trapped
+    #[cfg(feature = "debug-print")]
+    fn record_trap(&self, err: &InterpreterError) {
+        if let InterpreterError::Trap(trap) = err {
+            // Only `unreachable` instructions report their location
+            let location = match trap.kind() {
+                TrapKind::Unreachable => {
+                    self.contract_instance.trap_location.and_then(|location| {
+                        self.contract_instance.trap_locations.get(location as usize)
+                    })
+                }
+                _ => None,
+            };
+            trap_info::record_trap(trap.kind(), location.map(String::as_str));
+        }
     }
 
     pub fn write_to_memory(&mut self, buffer: &[u8]) -> Result<u32, WasmEngineError> {
@@ -31,24 +227,85 @@ impl Engine {
         self.contract_instance.extract_vector(vec_ptr_ptr)
     }
 
-    pub fn init(&mut self, env_ptr: u32, msg_ptr: u32) -> Result<u32, EnclaveError> {
-        info!("Invoking init() in wasm");
+    pub fn link_previous_contract_key(
+        &mut self,
+        previous_contract_key: &ContractKey,
+    ) -> Result<(), WasmEngineError> {
+        self.contract_instance
+            .link_previous_contract_key(previous_contract_key)
+    }
+
+    pub fn revoke_query_permit(
+        &mut self,
+        signer: &CanonicalAddr,
+        permit_name: &str,
+    ) -> Result<(), EnclaveError> {
+        self.contract_instance
+            .revoke_query_permit(signer, permit_name)
+            .map_err(EnclaveError::from)
+    }
+
+    pub fn use_tx_nonce(
+        &mut self,
+        user_public_key: &Ed25519PublicKey,
+        nonce: &IoNonce,
+        height: u64,
+    ) -> Result<(), EnclaveError> {
+        self.contract_instance
+            .use_tx_nonce(user_public_key, nonce, height)
+            .map_err(EnclaveError::from)
+    }
+
+    pub fn is_tx_nonce_used(
+        &mut self,
+        user_public_key: &Ed25519PublicKey,
+        nonce: &IoNonce,
+    ) -> Result<bool, EnclaveError> {
+        self.contract_instance
+            .is_tx_nonce_used(user_public_key, nonce)
+            .map_err(EnclaveError::from)
+    }
+
+    pub fn is_query_permit_revoked(
+        &mut self,
+        signer: &CanonicalAddr,
+        permit_name: &str,
+    ) -> Result<bool, EnclaveError> {
+        self.contract_instance
+            .is_query_permit_revoked(signer, permit_name)
+            .map_err(EnclaveError::from)
+    }
+
+    /// Contracts built for CosmWasm 1.0 are called through `instantiate`, and get the message
+    /// info at `info_ptr` between the env and the message
+    pub fn init(
+        &mut self,
+        env_ptr: u32,
+        info_ptr: Option<u32>,
+        msg_ptr: u32,
+    ) -> Result<u32, EnclaveError> {
+        let export = match self.api_version {
+            ApiVersion::V0_10 => "init",
+            ApiVersion::V1 => "instantiate",
+        };
+        info!("Invoking {}() in wasm", export);
+        let _span = trace_span!(Phase::Execute);
 
         match self
             .module
             .invoke_export(
-                "init",
-                &[
-                    RuntimeValue::I32(env_ptr as i32),
-                    RuntimeValue::I32(msg_ptr as i32),
-                ],
+                export,
+                &entry_point_args(env_ptr, info_ptr, msg_ptr),
                 &mut self.contract_instance,
             )
-            .map_err(wasmi_error_to_enclave_error)?
+            .map_err(|err| self.call_error(err))?
         {
             Some(RuntimeValue::I32(offset)) => Ok(offset as u32),
             other => {
-                warn!("init method returned value which wasn't u32: {:?}", other);
+                warn!(
+                    "{} method returned value which wasn't u32: {:?}",
+                    export, other
+                );
                 Err(EnclaveError::FailedFunctionCall)
             }
         }
@@ -71,8 +328,20 @@ impl Engine {
         //result
     }
 
-    pub fn handle(&mut self, env_ptr: u32, msg_ptr: u32) -> Result<u32, EnclaveError> {
-        info!("Invoking handle() in wasm");
+    /// Contracts built for CosmWasm 1.0 are called through `execute`, and get the message info
+    /// at `info_ptr` between the env and the message
+    pub fn handle(
+        &mut self,
+        env_ptr: u32,
+        info_ptr: Option<u32>,
+        msg_ptr: u32,
+    ) -> Result<u32, EnclaveError> {
+        let export = match self.api_version {
+            ApiVersion::V0_10 => "handle",
+            ApiVersion::V1 => "execute",
+        };
+        info!("Invoking {}() in wasm", export);
+        let _span = trace_span!(Phase::Execute);
 
         // Itzik: leaving this here as an example in case we will want to do something like this in the future
 
@@ -103,40 +372,146 @@ impl Engine {
         match self
             .module
             .invoke_export(
-                "handle",
+                export,
+                &entry_point_args(env_ptr, info_ptr, msg_ptr),
+                &mut self.contract_instance,
+            )
+            .map_err(|err| self.call_error(err))?
+        {
+            Some(RuntimeValue::I32(offset)) => Ok(offset as u32),
+            other => {
+                warn!(
+                    "{} method returned value which wasn't u32: {:?}",
+                    export, other
+                );
+                Err(EnclaveError::FailedFunctionCall)
+            }
+        }
+    }
+
+    /// Check whether the contract's `query` export takes an `env` argument before the `msg`
+    pub fn query_accepts_env(&self) -> bool {
+        self.module
+            .export_by_name("query")
+            .and_then(|export| {
+                export
+                    .as_func()
+                    .map(|func| func.signature().params().len() == 2)
+            })
+            .unwrap_or(false)
+    }
+
+    pub fn query(&mut self, env_ptr: Option<u32>, msg_ptr: u32) -> Result<u32, EnclaveError> {
+        info!("Invoking query() in wasm");
+        let _span = trace_span!(Phase::Execute);
+
+        let args = match env_ptr {
+            Some(env_ptr) => vec![
+                RuntimeValue::I32(env_ptr as i32),
+                RuntimeValue::I32(msg_ptr as i32),
+            ],
+            None => vec![RuntimeValue::I32(msg_ptr as i32)],
+        };
+
+        match self
+            .module
+            .invoke_export("query", &args, &mut self.contract_instance)
+            .map_err(|err| self.call_error(err))?
+        {
+            Some(RuntimeValue::I32(offset)) => Ok(offset as u32),
+            other => {
+                warn!("query method returned value which wasn't u32: {:?}", other);
+                Err(EnclaveError::FailedFunctionCall)
+            }
+        }
+    }
+
+    pub fn migrate(&mut self, env_ptr: u32, msg_ptr: u32) -> Result<u32, EnclaveError> {
+        info!("Invoking migrate() in wasm");
+        let _span = trace_span!(Phase::Execute);
+
+        match self
+            .module
+            .invoke_export(
+                "migrate",
                 &[
                     RuntimeValue::I32(env_ptr as i32),
                     RuntimeValue::I32(msg_ptr as i32),
                 ],
                 &mut self.contract_instance,
             )
-            .map_err(wasmi_error_to_enclave_error)?
+            .map_err(|err| self.call_error(err))?
         {
             Some(RuntimeValue::I32(offset)) => Ok(offset as u32),
             other => {
-                warn!("handle method returned value which wasn't u32: {:?}", other);
+                warn!(
+                    "migrate method returned value which wasn't u32: {:?}",
+                    other
+                );
                 Err(EnclaveError::FailedFunctionCall)
             }
         }
     }
 
-    pub fn query(&mut self, msg_ptr: u32) -> Result<u32, EnclaveError> {
-        info!("Invoking query() in wasm");
+    pub fn reply(&mut self, env_ptr: u32, msg_ptr: u32) -> Result<u32, EnclaveError> {
+        info!("Invoking reply() in wasm");
+        let _span = trace_span!(Phase::Execute);
 
         match self
             .module
             .invoke_export(
-                "query",
-                &[RuntimeValue::I32(msg_ptr as i32)],
+                "reply",
+                &[
+                    RuntimeValue::I32(env_ptr as i32),
+                    RuntimeValue::I32(msg_ptr as i32),
+                ],
                 &mut self.contract_instance,
             )
-            .map_err(wasmi_error_to_enclave_error)?
+            .map_err(|err| self.call_error(err))?
         {
             Some(RuntimeValue::I32(offset)) => Ok(offset as u32),
             other => {
-                warn!("query method returned value which wasn't u32: {:?}", other);
+                warn!("reply method returned value which wasn't u32: {:?}", other);
+                Err(EnclaveError::FailedFunctionCall)
+            }
+        }
+    }
+
+    /// Invoke one of the IBC entry points, which all take an env and a message
+    pub fn ibc(&mut self, export: &str, env_ptr: u32, msg_ptr: u32) -> Result<u32, EnclaveError> {
+        info!("Invoking {}() in wasm", export);
+        let _span = trace_span!(Phase::Execute);
+
+        match self
+            .module
+            .invoke_export(
+                export,
+                &[
+                    RuntimeValue::I32(env_ptr as i32),
+                    RuntimeValue::I32(msg_ptr as i32),
+                ],
+                &mut self.contract_instance,
+            )
+            .map_err(|err| self.call_error(err))?
+        {
+            Some(RuntimeValue::I32(offset)) => Ok(offset as u32),
+            other => {
+                warn!(
+                    "{} method returned value which wasn't u32: {:?}",
+                    export, other
+                );
                 Err(EnclaveError::FailedFunctionCall)
             }
         }
     }
 }
+
+/// The arguments of `init` and `handle`, with the message info only if the contract takes it
+fn entry_point_args(env_ptr: u32, info_ptr: Option<u32>, msg_ptr: u32) -> Vec<RuntimeValue> {
+    let mut args = vec![RuntimeValue::I32(env_ptr as i32)];
+    if let Some(info_ptr) = info_ptr {
+        args.push(RuntimeValue::I32(info_ptr as i32));
+    }
+    args.push(RuntimeValue::I32(msg_ptr as i32));
+    args
+}
@@ -15,6 +15,41 @@ pub enum HostFunctions {
     HumanizeAddressIndex = 4,
     GasIndex = 5,
     QueryChainIndex = 6,
+    Ed25519VerifyIndex = 7,
+    Ed25519BatchVerifyIndex = 8,
+    Secp256k1RecoverPubkeyIndex = 9,
+    GetRandomIndex = 10,
+    DbScanIndex = 11,
+    DbNextIndex = 12,
+    AddrValidateIndex = 13,
+    AddrCanonicalizeIndex = 14,
+    AddrHumanizeIndex = 15,
+    HashSha256Index = 16,
+    HashKeccak256Index = 17,
+    HashRipemd160Index = 18,
+    MemoryGrowIndex = 19,
+    ReadDbMultiIndex = 20,
+    AbortIndex = 21,
+    HkdfSha256Index = 22,
+    ReadDbNsIndex = 23,
+    WriteDbNsIndex = 24,
+    RemoveDbNsIndex = 25,
+    MoveDbToNsIndex = 26,
+    Secp256k1GenerateKeyIndex = 27,
+    Secp256k1SignIndex = 28,
+    Secp256k1PublicKeyIndex = 29,
+    GasLeftIndex = 30,
+    GasLimitIndex = 31,
+    VerifyForeignHeaderIndex = 32,
+    AssertContractCodeHashIndex = 33,
+    DbStorageUsageIndex = 34,
+    DebugIndex = 35,
+    TryWriteDbIndex = 36,
+    TryWriteDbNsIndex = 37,
+    #[cfg(feature = "debug-print")]
+    TrapLocationIndex = 252,
+    #[cfg(feature = "test")]
+    TestPanicIndex = 253,
     #[cfg(feature = "debug-print")]
     DebugPrintIndex = 254,
     Unknown,
@@ -34,6 +69,65 @@ impl From<usize> for HostFunctions {
             }
             x if x == HostFunctions::GasIndex as usize => HostFunctions::GasIndex,
             x if x == HostFunctions::QueryChainIndex as usize => HostFunctions::QueryChainIndex,
+            x if x == HostFunctions::Ed25519VerifyIndex as usize => {
+                HostFunctions::Ed25519VerifyIndex
+            }
+            x if x == HostFunctions::Ed25519BatchVerifyIndex as usize => {
+                HostFunctions::Ed25519BatchVerifyIndex
+            }
+            x if x == HostFunctions::Secp256k1RecoverPubkeyIndex as usize => {
+                HostFunctions::Secp256k1RecoverPubkeyIndex
+            }
+            x if x == HostFunctions::GetRandomIndex as usize => HostFunctions::GetRandomIndex,
+            x if x == HostFunctions::DbScanIndex as usize => HostFunctions::DbScanIndex,
+            x if x == HostFunctions::DbNextIndex as usize => HostFunctions::DbNextIndex,
+            x if x == HostFunctions::AddrValidateIndex as usize => HostFunctions::AddrValidateIndex,
+            x if x == HostFunctions::AddrCanonicalizeIndex as usize => {
+                HostFunctions::AddrCanonicalizeIndex
+            }
+            x if x == HostFunctions::AddrHumanizeIndex as usize => HostFunctions::AddrHumanizeIndex,
+            x if x == HostFunctions::HashSha256Index as usize => HostFunctions::HashSha256Index,
+            x if x == HostFunctions::HashKeccak256Index as usize => {
+                HostFunctions::HashKeccak256Index
+            }
+            x if x == HostFunctions::HashRipemd160Index as usize => {
+                HostFunctions::HashRipemd160Index
+            }
+            x if x == HostFunctions::MemoryGrowIndex as usize => HostFunctions::MemoryGrowIndex,
+            x if x == HostFunctions::ReadDbMultiIndex as usize => HostFunctions::ReadDbMultiIndex,
+            x if x == HostFunctions::AbortIndex as usize => HostFunctions::AbortIndex,
+            x if x == HostFunctions::HkdfSha256Index as usize => HostFunctions::HkdfSha256Index,
+            x if x == HostFunctions::ReadDbNsIndex as usize => HostFunctions::ReadDbNsIndex,
+            x if x == HostFunctions::WriteDbNsIndex as usize => HostFunctions::WriteDbNsIndex,
+            x if x == HostFunctions::RemoveDbNsIndex as usize => HostFunctions::RemoveDbNsIndex,
+            x if x == HostFunctions::MoveDbToNsIndex as usize => HostFunctions::MoveDbToNsIndex,
+            x if x == HostFunctions::Secp256k1GenerateKeyIndex as usize => {
+                HostFunctions::Secp256k1GenerateKeyIndex
+            }
+            x if x == HostFunctions::Secp256k1SignIndex as usize => {
+                HostFunctions::Secp256k1SignIndex
+            }
+            x if x == HostFunctions::Secp256k1PublicKeyIndex as usize => {
+                HostFunctions::Secp256k1PublicKeyIndex
+            }
+            x if x == HostFunctions::GasLeftIndex as usize => HostFunctions::GasLeftIndex,
+            x if x == HostFunctions::GasLimitIndex as usize => HostFunctions::GasLimitIndex,
+            x if x == HostFunctions::VerifyForeignHeaderIndex as usize => {
+                HostFunctions::VerifyForeignHeaderIndex
+            }
+            x if x == HostFunctions::AssertContractCodeHashIndex as usize => {
+                HostFunctions::AssertContractCodeHashIndex
+            }
+            x if x == HostFunctions::DbStorageUsageIndex as usize => {
+                HostFunctions::DbStorageUsageIndex
+            }
+            x if x == HostFunctions::DebugIndex as usize => HostFunctions::DebugIndex,
+            x if x == HostFunctions::TryWriteDbIndex as usize => HostFunctions::TryWriteDbIndex,
+            x if x == HostFunctions::TryWriteDbNsIndex as usize => HostFunctions::TryWriteDbNsIndex,
+            #[cfg(feature = "debug-print")]
+            x if x == HostFunctions::TrapLocationIndex as usize => HostFunctions::TrapLocationIndex,
+            #[cfg(feature = "test")]
+            x if x == HostFunctions::TestPanicIndex as usize => HostFunctions::TestPanicIndex,
             #[cfg(feature = "debug-print")]
             x if x == HostFunctions::DebugPrintIndex as usize => HostFunctions::DebugPrintIndex,
             _ => HostFunctions::Unknown,
@@ -65,6 +159,16 @@ impl Externals for ContractInstance {
                 })?;
                 self.read_db_index(key)
             }
+            HostFunctions::ReadDbMultiIndex => {
+                let keys: i32 = args.nth_checked(0).map_err(|err| {
+                    warn!(
+                        "read_db_multi() error reading arguments, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+                self.read_db_multi_index(keys)
+            }
             HostFunctions::RemoveDbIndex => {
                 let key: i32 = args.nth_checked(0).map_err(|err| {
                     warn!(
@@ -88,6 +192,63 @@ impl Externals for ContractInstance {
 
                 self.write_db_index(key, value)
             }
+            HostFunctions::TryWriteDbIndex => {
+                let key: i32 = args.nth_checked(0).map_err(|err| {
+                    warn!(
+                        "db_try_write() error reading first argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+
+                let value: i32 = args.nth_checked(1).map_err(|err| {
+                    warn!(
+                        "db_try_write() error reading second argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+
+                self.try_write_db_index(key, value)
+            }
+            // fn db_scan(start: *const c_void, end: *const c_void, order: i32) -> u32;
+            HostFunctions::DbScanIndex => {
+                let start: i32 = args.nth_checked(0).map_err(|err| {
+                    warn!(
+                        "db_scan() error reading first argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+                let end: i32 = args.nth_checked(1).map_err(|err| {
+                    warn!(
+                        "db_scan() error reading second argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+                let order: i32 = args.nth_checked(2).map_err(|err| {
+                    warn!(
+                        "db_scan() error reading third argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+
+                self.db_scan_index(start, end, order)
+            }
+            // fn db_next(iterator_id: u32) -> *mut c_void;
+            HostFunctions::DbNextIndex => {
+                let iterator_id: i32 = args.nth_checked(0).map_err(|err| {
+                    warn!(
+                        "db_next() error reading arguments, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+
+                self.db_next_index(iterator_id)
+            }
             HostFunctions::CanonicalizeAddressIndex => {
                 let human: i32 = args.nth_checked(0).map_err(|err| {
                     warn!(
@@ -121,6 +282,280 @@ impl Externals for ContractInstance {
 
                 self.humanize_address_index(canonical, human)
             }
+            // fn addr_validate(human: *const c_void) -> i32;
+            HostFunctions::AddrValidateIndex => {
+                let human: i32 = args.nth_checked(0).map_err(|err| {
+                    warn!(
+                        "addr_validate() error reading argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+
+                self.addr_validate_index(human)
+            }
+            // fn addr_canonicalize(human: *const c_void, canonical: *mut c_void) -> i32;
+            HostFunctions::AddrCanonicalizeIndex => {
+                let human: i32 = args.nth_checked(0).map_err(|err| {
+                    warn!(
+                        "addr_canonicalize() error reading first argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+
+                let canonical: i32 = args.nth_checked(1).map_err(|err| {
+                    warn!(
+                        "addr_canonicalize() error reading second argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+
+                self.addr_canonicalize_index(human, canonical)
+            }
+            // fn addr_humanize(canonical: *const c_void, human: *mut c_void) -> i32;
+            HostFunctions::AddrHumanizeIndex => {
+                let canonical: i32 = args.nth_checked(0).map_err(|err| {
+                    warn!(
+                        "addr_humanize() error reading first argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+
+                let human: i32 = args.nth_checked(1).map_err(|err| {
+                    warn!(
+                        "addr_humanize() error reading second argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+
+                self.addr_humanize_index(canonical, human)
+            }
+            HostFunctions::HashSha256Index => {
+                let input: i32 = args.nth_checked(0).map_err(|err| {
+                    warn!(
+                        "hash_sha256() error reading first argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+
+                let output: i32 = args.nth_checked(1).map_err(|err| {
+                    warn!(
+                        "hash_sha256() error reading second argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+
+                self.hash_sha256_index(input, output)
+            }
+            HostFunctions::HashKeccak256Index => {
+                let input: i32 = args.nth_checked(0).map_err(|err| {
+                    warn!(
+                        "hash_keccak256() error reading first argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+
+                let output: i32 = args.nth_checked(1).map_err(|err| {
+                    warn!(
+                        "hash_keccak256() error reading second argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+
+                self.hash_keccak256_index(input, output)
+            }
+            HostFunctions::HashRipemd160Index => {
+                let input: i32 = args.nth_checked(0).map_err(|err| {
+                    warn!(
+                        "hash_ripemd160() error reading first argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+
+                let output: i32 = args.nth_checked(1).map_err(|err| {
+                    warn!(
+                        "hash_ripemd160() error reading second argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+
+                self.hash_ripemd160_index(input, output)
+            }
+            HostFunctions::ReadDbNsIndex => {
+                let namespace: i32 = args.nth_checked(0).map_err(|err| {
+                    warn!(
+                        "db_read_ns() error reading first argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+
+                let state_key: i32 = args.nth_checked(1).map_err(|err| {
+                    warn!(
+                        "db_read_ns() error reading second argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+
+                self.read_db_ns_index(namespace, state_key)
+            }
+            HostFunctions::WriteDbNsIndex => {
+                let namespace: i32 = args.nth_checked(0).map_err(|err| {
+                    warn!(
+                        "db_write_ns() error reading first argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+
+                let state_key: i32 = args.nth_checked(1).map_err(|err| {
+                    warn!(
+                        "db_write_ns() error reading second argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+
+                let value: i32 = args.nth_checked(2).map_err(|err| {
+                    warn!(
+                        "db_write_ns() error reading third argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+
+                self.write_db_ns_index(namespace, state_key, value)
+            }
+            HostFunctions::TryWriteDbNsIndex => {
+                let namespace: i32 = args.nth_checked(0).map_err(|err| {
+                    warn!(
+                        "db_try_write_ns() error reading first argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+
+                let state_key: i32 = args.nth_checked(1).map_err(|err| {
+                    warn!(
+                        "db_try_write_ns() error reading second argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+
+                let value: i32 = args.nth_checked(2).map_err(|err| {
+                    warn!(
+                        "db_try_write_ns() error reading third argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+
+                self.try_write_db_ns_index(namespace, state_key, value)
+            }
+            HostFunctions::RemoveDbNsIndex => {
+                let namespace: i32 = args.nth_checked(0).map_err(|err| {
+                    warn!(
+                        "db_remove_ns() error reading first argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+
+                let state_key: i32 = args.nth_checked(1).map_err(|err| {
+                    warn!(
+                        "db_remove_ns() error reading second argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+
+                self.remove_db_ns_index(namespace, state_key)
+            }
+            HostFunctions::MoveDbToNsIndex => {
+                let namespace: i32 = args.nth_checked(0).map_err(|err| {
+                    warn!(
+                        "db_move_to_ns() error reading first argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+
+                let state_key: i32 = args.nth_checked(1).map_err(|err| {
+                    warn!(
+                        "db_move_to_ns() error reading second argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+
+                self.move_db_to_ns_index(namespace, state_key)
+            }
+            HostFunctions::HkdfSha256Index => {
+                let ikm: i32 = args.nth_checked(0).map_err(|err| {
+                    warn!(
+                        "hkdf_sha256() error reading first argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+
+                let salt: i32 = args.nth_checked(1).map_err(|err| {
+                    warn!(
+                        "hkdf_sha256() error reading second argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+
+                let info: i32 = args.nth_checked(2).map_err(|err| {
+                    warn!(
+                        "hkdf_sha256() error reading third argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+
+                let output: i32 = args.nth_checked(3).map_err(|err| {
+                    warn!(
+                        "hkdf_sha256() error reading fourth argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+
+                let output_len: i32 = args.nth_checked(4).map_err(|err| {
+                    warn!(
+                        "hkdf_sha256() error reading fifth argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+
+                self.hkdf_sha256_index(ikm, salt, info, output, output_len)
+            }
+            HostFunctions::MemoryGrowIndex => {
+                let pages: i32 = args.nth_checked(0).map_err(|err| {
+                    warn!(
+                        "memory_grow() error reading argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+
+                self.memory_grow_index(pages)
+            }
             HostFunctions::QueryChainIndex => {
                 let query: i32 = args.nth_checked(0).map_err(|err| {
                     warn!(
@@ -132,6 +567,205 @@ impl Externals for ContractInstance {
 
                 self.query_chain_index(query)
             }
+            HostFunctions::Ed25519VerifyIndex => {
+                let message: i32 = args.nth_checked(0).map_err(|err| {
+                    warn!(
+                        "ed25519_verify() error reading first argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+                let signature: i32 = args.nth_checked(1).map_err(|err| {
+                    warn!(
+                        "ed25519_verify() error reading second argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+                let public_key: i32 = args.nth_checked(2).map_err(|err| {
+                    warn!(
+                        "ed25519_verify() error reading third argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+
+                self.ed25519_verify_index(message, signature, public_key)
+            }
+            HostFunctions::Ed25519BatchVerifyIndex => {
+                let messages: i32 = args.nth_checked(0).map_err(|err| {
+                    warn!(
+                        "ed25519_batch_verify() error reading first argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+                let signatures: i32 = args.nth_checked(1).map_err(|err| {
+                    warn!(
+                        "ed25519_batch_verify() error reading second argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+                let public_keys: i32 = args.nth_checked(2).map_err(|err| {
+                    warn!(
+                        "ed25519_batch_verify() error reading third argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+
+                self.ed25519_batch_verify_index(messages, signatures, public_keys)
+            }
+            HostFunctions::Secp256k1RecoverPubkeyIndex => {
+                let message_hash: i32 = args.nth_checked(0).map_err(|err| {
+                    warn!(
+                        "secp256k1_recover_pubkey() error reading first argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+                let signature: i32 = args.nth_checked(1).map_err(|err| {
+                    warn!(
+                        "secp256k1_recover_pubkey() error reading second argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+                let recovery_param: i32 = args.nth_checked(2).map_err(|err| {
+                    warn!(
+                        "secp256k1_recover_pubkey() error reading third argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+                let public_key: i32 = args.nth_checked(3).map_err(|err| {
+                    warn!(
+                        "secp256k1_recover_pubkey() error reading fourth argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+
+                self.secp256k1_recover_pubkey_index(
+                    message_hash,
+                    signature,
+                    recovery_param,
+                    public_key,
+                )
+            }
+            HostFunctions::Secp256k1GenerateKeyIndex => {
+                let path: i32 = args.nth_checked(0).map_err(|err| {
+                    warn!(
+                        "secp256k1_generate_key() error reading argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+
+                self.secp256k1_generate_key_index(path)
+            }
+            HostFunctions::Secp256k1SignIndex => {
+                let key_id: i32 = args.nth_checked(0).map_err(|err| {
+                    warn!(
+                        "secp256k1_sign() error reading first argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+                let message_hash: i32 = args.nth_checked(1).map_err(|err| {
+                    warn!(
+                        "secp256k1_sign() error reading second argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+                let signature: i32 = args.nth_checked(2).map_err(|err| {
+                    warn!(
+                        "secp256k1_sign() error reading third argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+
+                self.secp256k1_sign_index(key_id, message_hash, signature)
+            }
+            HostFunctions::Secp256k1PublicKeyIndex => {
+                let key_id: i32 = args.nth_checked(0).map_err(|err| {
+                    warn!(
+                        "secp256k1_public_key() error reading first argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+                let public_key: i32 = args.nth_checked(1).map_err(|err| {
+                    warn!(
+                        "secp256k1_public_key() error reading second argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+
+                self.secp256k1_public_key_index(key_id, public_key)
+            }
+            HostFunctions::GetRandomIndex => self.get_random_index(),
+            HostFunctions::GasLeftIndex => self.gas_left_index(),
+            HostFunctions::GasLimitIndex => self.gas_limit_index(),
+            HostFunctions::DbStorageUsageIndex => self.db_storage_usage_index(),
+            HostFunctions::VerifyForeignHeaderIndex => {
+                let chain_id: i32 = args.nth_checked(0).map_err(|err| {
+                    warn!(
+                        "verify_foreign_header() error reading first argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+
+                let header: i32 = args.nth_checked(1).map_err(|err| {
+                    warn!(
+                        "verify_foreign_header() error reading second argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+
+                self.verify_foreign_header_index(chain_id, header)
+            }
+            HostFunctions::AssertContractCodeHashIndex => {
+                let contract_addr: i32 = args.nth_checked(0).map_err(|err| {
+                    warn!(
+                        "assert_contract_code_hash() error reading first argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+
+                let code_hash: i32 = args.nth_checked(1).map_err(|err| {
+                    warn!(
+                        "assert_contract_code_hash() error reading second argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+
+                self.assert_contract_code_hash_index(contract_addr, code_hash)
+            }
+            HostFunctions::AbortIndex => {
+                let message: i32 = args.nth_checked(0).map_err(|err| {
+                    warn!("abort() error reading argument, stopping wasm: {:?}", err);
+                    err
+                })?;
+
+                self.abort_index(message)
+            }
+            HostFunctions::DebugIndex => {
+                let message: i32 = args.nth_checked(0).map_err(|err| {
+                    warn!("debug() error reading argument, stopping wasm: {:?}", err);
+                    err
+                })?;
+
+                self.debug_index(message)
+            }
             HostFunctions::GasIndex => {
                 let gas_amount: i32 = args.nth_checked(0).map_err(|err| {
                     warn!("gas() error reading arguments, stopping wasm: {:?}", err);
@@ -140,6 +774,20 @@ impl Externals for ContractInstance {
                 self.gas_index(gas_amount)
             }
             #[cfg(feature = "debug-print")]
+            HostFunctions::TrapLocationIndex => {
+                let location: i32 = args.nth_checked(0).map_err(|err| {
+                    warn!(
+                        "trap_location() error reading argument, stopping wasm: {:?}",
+                        err
+                    );
+                    err
+                })?;
+
+                self.trap_location_index(location)
+            }
+            #[cfg(feature = "test")]
+            HostFunctions::TestPanicIndex => self.test_panic_index(),
+            #[cfg(feature = "debug-print")]
             HostFunctions::DebugPrintIndex => {
                 let message: i32 = args.nth_checked(0).map_err(|err| {
                     warn!(
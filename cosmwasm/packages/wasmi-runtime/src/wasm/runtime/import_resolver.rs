@@ -4,6 +4,7 @@ use wasmi::{
 };
 
 use super::externals::HostFunctions;
+use crate::wasm::api_version::ApiVersion;
 
 pub fn create_builder(resolver: &dyn ModuleImportResolver) -> ImportsBuilder {
     ImportsBuilder::new().with_resolver("env", resolver)
@@ -12,8 +13,11 @@ pub fn create_builder(resolver: &dyn ModuleImportResolver) -> ImportsBuilder {
 /// EnigmaImportResolver maps function name to its function signature and also to function index in Runtime
 /// When instansiating a module we give it this resolver
 /// When invoking a function inside the module we can give it different runtimes (which we probably won't do)
+/// Only the functions of the CosmWasm API version the contract was built for are resolved.
 #[derive(Debug, Clone)]
-pub struct WasmiImportResolver {}
+pub struct WasmiImportResolver {
+    pub api_version: ApiVersion,
+}
 
 /// These functions should be available to invoke from wasm code
 /// These should pass the request up to go-cosmwasm:
@@ -28,12 +32,25 @@ impl ModuleImportResolver for WasmiImportResolver {
         func_name: &str,
         _signature: &Signature,
     ) -> Result<FuncRef, InterpreterError> {
+        if !self.api_version.resolves(func_name) {
+            return Err(InterpreterError::Function(format!(
+                "WASM VM doesn't export function with name {} to contracts built for CosmWasm API {:?}",
+                func_name, self.api_version
+            )));
+        }
+
         let func_ref = match func_name {
             // fn read_db(key: *const c_void, value: *mut c_void) -> i32;
             "db_read" => FuncInstance::alloc_host(
                 Signature::new(&[ValueType::I32][..], Some(ValueType::I32)),
                 HostFunctions::ReadDbIndex.into(),
             ),
+            // Optional, only imported by contracts that read many keys at once
+            // fn db_read_multi(keys: *const c_void) -> i32;
+            "db_read_multi" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32][..], Some(ValueType::I32)),
+                HostFunctions::ReadDbMultiIndex.into(),
+            ),
             // fn write_db(key: *const c_void, value: *mut c_void);
             "db_write" => FuncInstance::alloc_host(
                 Signature::new(&[ValueType::I32, ValueType::I32][..], None),
@@ -44,6 +61,46 @@ impl ModuleImportResolver for WasmiImportResolver {
                 Signature::new(&[ValueType::I32][..], None),
                 HostFunctions::RemoveDbIndex.into(),
             ),
+            // Optional, only imported by contracts that keep state in namespaces
+            // fn db_read_ns(namespace: *const c_void, key: *const c_void) -> i32;
+            "db_read_ns" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32, ValueType::I32][..], Some(ValueType::I32)),
+                HostFunctions::ReadDbNsIndex.into(),
+            ),
+            // fn db_write_ns(namespace: *const c_void, key: *const c_void, value: *mut c_void);
+            "db_write_ns" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32, ValueType::I32, ValueType::I32][..], None),
+                HostFunctions::WriteDbNsIndex.into(),
+            ),
+            // fn db_remove_ns(namespace: *const c_void, key: *const c_void);
+            "db_remove_ns" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32, ValueType::I32][..], None),
+                HostFunctions::RemoveDbNsIndex.into(),
+            ),
+            // fn db_move_to_ns(namespace: *const c_void, key: *const c_void) -> i32;
+            "db_move_to_ns" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32, ValueType::I32][..], Some(ValueType::I32)),
+                HostFunctions::MoveDbToNsIndex.into(),
+            ),
+            // Optional, only imported by contracts that handle going over the storage quota
+            // fn db_try_write(key: *const c_void, value: *mut c_void) -> i32;
+            "db_try_write" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32, ValueType::I32][..], Some(ValueType::I32)),
+                HostFunctions::TryWriteDbIndex.into(),
+            ),
+            // fn db_try_write_ns(namespace: *const c_void, key: *const c_void, value: *mut c_void) -> i32;
+            "db_try_write_ns" => FuncInstance::alloc_host(
+                Signature::new(
+                    &[ValueType::I32, ValueType::I32, ValueType::I32][..],
+                    Some(ValueType::I32),
+                ),
+                HostFunctions::TryWriteDbNsIndex.into(),
+            ),
+            // fn db_storage_usage() -> u64;
+            "db_storage_usage" => FuncInstance::alloc_host(
+                Signature::new(&[][..], Some(ValueType::I64)),
+                HostFunctions::DbStorageUsageIndex.into(),
+            ),
             // fn canonicalize_address(human: *const c_void, canonical: *mut c_void) -> i32;
             "canonicalize_address" => FuncInstance::alloc_host(
                 Signature::new(&[ValueType::I32, ValueType::I32][..], Some(ValueType::I32)),
@@ -54,10 +111,172 @@ impl ModuleImportResolver for WasmiImportResolver {
                 Signature::new(&[ValueType::I32, ValueType::I32][..], Some(ValueType::I32)),
                 HostFunctions::HumanizeAddressIndex.into(),
             ),
+            // fn addr_validate(human: *const c_void) -> i32;
+            "addr_validate" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32][..], Some(ValueType::I32)),
+                HostFunctions::AddrValidateIndex.into(),
+            ),
+            // fn addr_canonicalize(human: *const c_void, canonical: *mut c_void) -> i32;
+            "addr_canonicalize" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32, ValueType::I32][..], Some(ValueType::I32)),
+                HostFunctions::AddrCanonicalizeIndex.into(),
+            ),
+            // fn addr_humanize(canonical: *const c_void, human: *mut c_void) -> i32;
+            "addr_humanize" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32, ValueType::I32][..], Some(ValueType::I32)),
+                HostFunctions::AddrHumanizeIndex.into(),
+            ),
             "query_chain" => FuncInstance::alloc_host(
                 Signature::new(&[ValueType::I32][..], Some(ValueType::I32)),
                 HostFunctions::QueryChainIndex.into(),
             ),
+            // fn ed25519_verify(message: *const c_void, signature: *const c_void, public_key: *const c_void) -> i32;
+            "ed25519_verify" => FuncInstance::alloc_host(
+                Signature::new(
+                    &[ValueType::I32, ValueType::I32, ValueType::I32][..],
+                    Some(ValueType::I32),
+                ),
+                HostFunctions::Ed25519VerifyIndex.into(),
+            ),
+            // fn ed25519_batch_verify(messages: *const c_void, signatures: *const c_void, public_keys: *const c_void) -> i32;
+            "ed25519_batch_verify" => FuncInstance::alloc_host(
+                Signature::new(
+                    &[ValueType::I32, ValueType::I32, ValueType::I32][..],
+                    Some(ValueType::I32),
+                ),
+                HostFunctions::Ed25519BatchVerifyIndex.into(),
+            ),
+            // fn secp256k1_recover_pubkey(message_hash: *const c_void, signature: *const c_void, recovery_param: u32, public_key: *mut c_void) -> i32;
+            "secp256k1_recover_pubkey" => FuncInstance::alloc_host(
+                Signature::new(
+                    &[
+                        ValueType::I32,
+                        ValueType::I32,
+                        ValueType::I32,
+                        ValueType::I32,
+                    ][..],
+                    Some(ValueType::I32),
+                ),
+                HostFunctions::Secp256k1RecoverPubkeyIndex.into(),
+            ),
+            // fn secp256k1_generate_key(path: *const c_void) -> u32;
+            "secp256k1_generate_key" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32][..], Some(ValueType::I32)),
+                HostFunctions::Secp256k1GenerateKeyIndex.into(),
+            ),
+            // fn secp256k1_sign(key_id: u32, message_hash: *const c_void, signature: *mut c_void) -> i32;
+            "secp256k1_sign" => FuncInstance::alloc_host(
+                Signature::new(
+                    &[ValueType::I32, ValueType::I32, ValueType::I32][..],
+                    Some(ValueType::I32),
+                ),
+                HostFunctions::Secp256k1SignIndex.into(),
+            ),
+            // fn secp256k1_public_key(key_id: u32, public_key: *mut c_void) -> i32;
+            "secp256k1_public_key" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32, ValueType::I32][..], Some(ValueType::I32)),
+                HostFunctions::Secp256k1PublicKeyIndex.into(),
+            ),
+            // fn db_scan(start: *const c_void, end: *const c_void, order: i32) -> u32;
+            "db_scan" => FuncInstance::alloc_host(
+                Signature::new(
+                    &[ValueType::I32, ValueType::I32, ValueType::I32][..],
+                    Some(ValueType::I32),
+                ),
+                HostFunctions::DbScanIndex.into(),
+            ),
+            // fn db_next(iterator_id: u32) -> *mut c_void;
+            "db_next" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32][..], Some(ValueType::I32)),
+                HostFunctions::DbNextIndex.into(),
+            ),
+            // fn get_random() -> *mut c_void;
+            "get_random" => FuncInstance::alloc_host(
+                Signature::new(&[][..], Some(ValueType::I32)),
+                HostFunctions::GetRandomIndex.into(),
+            ),
+            // fn gas_left() -> u64;
+            "gas_left" => FuncInstance::alloc_host(
+                Signature::new(&[][..], Some(ValueType::I64)),
+                HostFunctions::GasLeftIndex.into(),
+            ),
+            // fn gas_limit() -> u64;
+            "gas_limit" => FuncInstance::alloc_host(
+                Signature::new(&[][..], Some(ValueType::I64)),
+                HostFunctions::GasLimitIndex.into(),
+            ),
+            // fn hash_sha256(input: *const c_void, output: *mut c_void) -> i32;
+            "hash_sha256" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32, ValueType::I32][..], Some(ValueType::I32)),
+                HostFunctions::HashSha256Index.into(),
+            ),
+            // fn hash_keccak256(input: *const c_void, output: *mut c_void) -> i32;
+            "hash_keccak256" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32, ValueType::I32][..], Some(ValueType::I32)),
+                HostFunctions::HashKeccak256Index.into(),
+            ),
+            // fn hash_ripemd160(input: *const c_void, output: *mut c_void) -> i32;
+            "hash_ripemd160" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32, ValueType::I32][..], Some(ValueType::I32)),
+                HostFunctions::HashRipemd160Index.into(),
+            ),
+            // fn hkdf_sha256(ikm: *const c_void, salt: *const c_void, info: *const c_void, output: *mut c_void, output_len: u32) -> i32;
+            "hkdf_sha256" => FuncInstance::alloc_host(
+                Signature::new(
+                    &[
+                        ValueType::I32,
+                        ValueType::I32,
+                        ValueType::I32,
+                        ValueType::I32,
+                        ValueType::I32,
+                    ][..],
+                    Some(ValueType::I32),
+                ),
+                HostFunctions::HkdfSha256Index.into(),
+            ),
+            // fn verify_foreign_header(chain_id: *const c_void, header: *const c_void) -> u32;
+            "verify_foreign_header" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32, ValueType::I32][..], Some(ValueType::I32)),
+                HostFunctions::VerifyForeignHeaderIndex.into(),
+            ),
+            // fn assert_contract_code_hash(contract_addr: *const c_void, code_hash: *const c_void) -> u32;
+            "assert_contract_code_hash" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32, ValueType::I32][..], Some(ValueType::I32)),
+                HostFunctions::AssertContractCodeHashIndex.into(),
+            ),
+            // Only imported by contracts built for CosmWasm 1.0
+            // fn abort(message: *const c_void);
+            "abort" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32][..], None),
+                HostFunctions::AbortIndex.into(),
+            ),
+            // Only imported by contracts built for CosmWasm 1.0
+            // fn debug(message: *const c_void);
+            "debug" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32][..], None),
+                HostFunctions::DebugIndex.into(),
+            ),
+            // Not imported by contracts, `memory.grow` instructions are replaced with it when the
+            // contract is loaded
+            // fn memory_grow(pages: i32) -> i32;
+            "memory_grow" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32][..], Some(ValueType::I32)),
+                HostFunctions::MemoryGrowIndex.into(),
+            ),
+            // Not imported by contracts, their `unreachable` instructions are preceded with it
+            // when the contract is loaded
+            // fn trap_location(location: i32);
+            #[cfg(feature = "debug-print")]
+            "trap_location" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32][..], None),
+                HostFunctions::TrapLocationIndex.into(),
+            ),
+            // fn test_panic();
+            #[cfg(feature = "test")]
+            "test_panic" => FuncInstance::alloc_host(
+                Signature::new(&[][..], None),
+                HostFunctions::TestPanicIndex.into(),
+            ),
             #[cfg(feature = "debug-print")]
             "debug_print" => FuncInstance::alloc_host(
                 Signature::new(&[ValueType::I32][..], None),
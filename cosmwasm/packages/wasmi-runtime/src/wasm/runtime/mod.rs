@@ -1,7 +1,9 @@
-mod contract;
+pub mod contract;
+mod debug_message;
 mod engine;
 mod externals;
 mod import_resolver;
+pub mod sections;
 pub mod traits;
 
 pub use contract::{ContractInstance, ContractOperation};
@@ -0,0 +1,123 @@
+/// Decode a list of byte slices that a contract packed into a single buffer.
+///
+/// The encoding is the one cosmwasm uses for passing lists through a single region: each section
+/// is followed by its length as a big-endian u32, so the buffer is read from the end.
+/// Returns `None` if the buffer is malformed.
+pub fn decode_sections(data: &[u8]) -> Option<Vec<&[u8]>> {
+    let mut sections = Vec::new();
+    let mut remaining = data;
+
+    while !remaining.is_empty() {
+        if remaining.len() < 4 {
+            return None;
+        }
+        let (rest, len_bytes) = remaining.split_at(remaining.len() - 4);
+        let len =
+            u32::from_be_bytes([len_bytes[0], len_bytes[1], len_bytes[2], len_bytes[3]]) as usize;
+        if len > rest.len() {
+            return None;
+        }
+        let (rest, section) = rest.split_at(rest.len() - len);
+        sections.push(section);
+        remaining = rest;
+    }
+
+    sections.reverse();
+    Some(sections)
+}
+
+/// Encode a list of byte slices the way `decode_sections` decodes them
+pub fn encode_sections<T: AsRef<[u8]>>(sections: &[T]) -> Vec<u8> {
+    let mut data = Vec::new();
+    for section in sections {
+        let section = section.as_ref();
+        data.extend_from_slice(section);
+        data.extend_from_slice(&(section.len() as u32).to_be_bytes());
+    }
+    data
+}
+
+/// Prefix of a section that holds a value, see `encode_optional_sections`
+const VALUE_PRESENT: u8 = 1;
+/// A section that stands for a missing value, see `encode_optional_sections`
+const VALUE_MISSING: u8 = 0;
+
+/// Encode a list of optional values as sections. Each section starts with a byte that tells if the
+/// value is there, so a missing value can be told apart from an empty one.
+pub fn encode_optional_sections(values: &[Option<Vec<u8>>]) -> Vec<u8> {
+    let sections: Vec<Vec<u8>> = values
+        .iter()
+        .map(|value| match value {
+            Some(value) => {
+                let mut section = vec![VALUE_PRESENT];
+                section.extend_from_slice(value);
+                section
+            }
+            None => vec![VALUE_MISSING],
+        })
+        .collect();
+    encode_sections(&sections)
+}
+
+/// Decode a list of optional values encoded with `encode_optional_sections`.
+/// Returns `None` if the buffer is malformed.
+pub fn decode_optional_sections(data: &[u8]) -> Option<Vec<Option<Vec<u8>>>> {
+    decode_sections(data)?
+        .into_iter()
+        .map(|section| match section.split_first() {
+            Some((&VALUE_PRESENT, value)) => Some(Some(value.to_vec())),
+            Some((&VALUE_MISSING, rest)) if rest.is_empty() => Some(None),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    pub fn test_decode_sections() {
+        let data = b"hello\x00\x00\x00\x05\x00\x00\x00\x00ab\x00\x00\x00\x02";
+        let sections = decode_sections(data).unwrap();
+        assert_eq!(sections, vec![&b"hello"[..], &b""[..], &b"ab"[..]]);
+
+        assert_eq!(decode_sections(b"").unwrap(), Vec::<&[u8]>::new());
+    }
+
+    pub fn test_decode_sections_malformed() {
+        // Truncated length suffix
+        assert!(decode_sections(b"\x00\x02").is_none());
+        // Length larger than the data before it
+        assert!(decode_sections(b"ab\x00\x00\x00\x03").is_none());
+        // Length that would overflow when added to the offset
+        assert!(decode_sections(b"ab\xff\xff\xff\xff").is_none());
+    }
+
+    pub fn test_encode_sections_round_trip() {
+        let sections: Vec<&[u8]> = vec![b"hello", b"", b"ab"];
+        let data = encode_sections(&sections);
+        assert_eq!(
+            data,
+            b"hello\x00\x00\x00\x05\x00\x00\x00\x00ab\x00\x00\x00\x02".to_vec()
+        );
+        assert_eq!(decode_sections(&data).unwrap(), sections);
+    }
+
+    pub fn test_optional_sections() {
+        // A missing value and an empty value are encoded differently
+        let values = vec![Some(b"value".to_vec()), None, Some(vec![])];
+        let data = encode_optional_sections(&values);
+        assert_eq!(
+            data,
+            b"\x01value\x00\x00\x00\x06\x00\x00\x00\x00\x01\x01\x00\x00\x00\x01".to_vec()
+        );
+        assert_eq!(decode_optional_sections(&data).unwrap(), values);
+
+        // A section without the presence byte
+        assert!(decode_optional_sections(b"\x00\x00\x00\x00").is_none());
+        // A missing value with data after the presence byte
+        assert!(decode_optional_sections(b"\x00a\x00\x00\x00\x02").is_none());
+        // An unknown presence byte
+        assert!(decode_optional_sections(b"\x02\x00\x00\x00\x01").is_none());
+    }
+}
@@ -4,6 +4,8 @@ use wasmi::{RuntimeValue, Trap};
 pub trait WasmiApi {
     fn read_db_index(&mut self, state_key_ptr_ptr: i32) -> Result<Option<RuntimeValue>, Trap>;
 
+    fn read_db_multi_index(&mut self, keys_ptr_ptr: i32) -> Result<Option<RuntimeValue>, Trap>;
+
     fn remove_db_index(&mut self, state_key_ptr_ptr: i32) -> Result<Option<RuntimeValue>, Trap>;
 
     fn write_db_index(
@@ -12,6 +14,21 @@ pub trait WasmiApi {
         value_ptr_ptr: i32,
     ) -> Result<Option<RuntimeValue>, Trap>;
 
+    fn try_write_db_index(
+        &mut self,
+        state_key_ptr_ptr: i32,
+        value_ptr_ptr: i32,
+    ) -> Result<Option<RuntimeValue>, Trap>;
+
+    fn db_scan_index(
+        &mut self,
+        start_ptr_ptr: i32,
+        end_ptr_ptr: i32,
+        order: i32,
+    ) -> Result<Option<RuntimeValue>, Trap>;
+
+    fn db_next_index(&mut self, iterator_id: i32) -> Result<Option<RuntimeValue>, Trap>;
+
     fn canonicalize_address_index(
         &mut self,
         canonical_ptr_ptr: i32,
@@ -24,10 +41,152 @@ pub trait WasmiApi {
         human_ptr_ptr: i32,
     ) -> Result<Option<RuntimeValue>, Trap>;
 
+    fn addr_validate_index(&mut self, human_ptr_ptr: i32) -> Result<Option<RuntimeValue>, Trap>;
+
+    fn addr_canonicalize_index(
+        &mut self,
+        human_ptr_ptr: i32,
+        canonical_ptr_ptr: i32,
+    ) -> Result<Option<RuntimeValue>, Trap>;
+
+    fn addr_humanize_index(
+        &mut self,
+        canonical_ptr_ptr: i32,
+        human_ptr_ptr: i32,
+    ) -> Result<Option<RuntimeValue>, Trap>;
+
     fn query_chain_index(&mut self, query_ptr_ptr: i32) -> Result<Option<RuntimeValue>, Trap>;
 
+    fn ed25519_verify_index(
+        &mut self,
+        message_ptr_ptr: i32,
+        signature_ptr_ptr: i32,
+        public_key_ptr_ptr: i32,
+    ) -> Result<Option<RuntimeValue>, Trap>;
+
+    fn ed25519_batch_verify_index(
+        &mut self,
+        messages_ptr_ptr: i32,
+        signatures_ptr_ptr: i32,
+        public_keys_ptr_ptr: i32,
+    ) -> Result<Option<RuntimeValue>, Trap>;
+
+    fn secp256k1_recover_pubkey_index(
+        &mut self,
+        message_hash_ptr_ptr: i32,
+        signature_ptr_ptr: i32,
+        recovery_param: i32,
+        public_key_ptr_ptr: i32,
+    ) -> Result<Option<RuntimeValue>, Trap>;
+
+    fn get_random_index(&mut self) -> Result<Option<RuntimeValue>, Trap>;
+
+    fn gas_left_index(&mut self) -> Result<Option<RuntimeValue>, Trap>;
+
+    fn gas_limit_index(&mut self) -> Result<Option<RuntimeValue>, Trap>;
+
+    fn db_storage_usage_index(&mut self) -> Result<Option<RuntimeValue>, Trap>;
+
+    fn hash_sha256_index(
+        &mut self,
+        input_ptr_ptr: i32,
+        output_ptr_ptr: i32,
+    ) -> Result<Option<RuntimeValue>, Trap>;
+
+    fn hash_keccak256_index(
+        &mut self,
+        input_ptr_ptr: i32,
+        output_ptr_ptr: i32,
+    ) -> Result<Option<RuntimeValue>, Trap>;
+
+    fn hash_ripemd160_index(
+        &mut self,
+        input_ptr_ptr: i32,
+        output_ptr_ptr: i32,
+    ) -> Result<Option<RuntimeValue>, Trap>;
+
+    fn read_db_ns_index(
+        &mut self,
+        namespace_ptr_ptr: i32,
+        state_key_ptr_ptr: i32,
+    ) -> Result<Option<RuntimeValue>, Trap>;
+
+    fn write_db_ns_index(
+        &mut self,
+        namespace_ptr_ptr: i32,
+        state_key_ptr_ptr: i32,
+        value_ptr_ptr: i32,
+    ) -> Result<Option<RuntimeValue>, Trap>;
+
+    fn try_write_db_ns_index(
+        &mut self,
+        namespace_ptr_ptr: i32,
+        state_key_ptr_ptr: i32,
+        value_ptr_ptr: i32,
+    ) -> Result<Option<RuntimeValue>, Trap>;
+
+    fn remove_db_ns_index(
+        &mut self,
+        namespace_ptr_ptr: i32,
+        state_key_ptr_ptr: i32,
+    ) -> Result<Option<RuntimeValue>, Trap>;
+
+    fn move_db_to_ns_index(
+        &mut self,
+        namespace_ptr_ptr: i32,
+        state_key_ptr_ptr: i32,
+    ) -> Result<Option<RuntimeValue>, Trap>;
+
+    fn secp256k1_generate_key_index(
+        &mut self,
+        path_ptr_ptr: i32,
+    ) -> Result<Option<RuntimeValue>, Trap>;
+
+    fn secp256k1_sign_index(
+        &mut self,
+        key_id: i32,
+        message_hash_ptr_ptr: i32,
+        signature_ptr_ptr: i32,
+    ) -> Result<Option<RuntimeValue>, Trap>;
+
+    fn secp256k1_public_key_index(
+        &mut self,
+        key_id: i32,
+        public_key_ptr_ptr: i32,
+    ) -> Result<Option<RuntimeValue>, Trap>;
+
+    fn hkdf_sha256_index(
+        &mut self,
+        ikm_ptr_ptr: i32,
+        salt_ptr_ptr: i32,
+        info_ptr_ptr: i32,
+        output_ptr_ptr: i32,
+        output_len: i32,
+    ) -> Result<Option<RuntimeValue>, Trap>;
+
+    fn verify_foreign_header_index(
+        &mut self,
+        chain_id_ptr_ptr: i32,
+        header_ptr_ptr: i32,
+    ) -> Result<Option<RuntimeValue>, Trap>;
+
+    fn assert_contract_code_hash_index(
+        &mut self,
+        contract_addr_ptr_ptr: i32,
+        code_hash_ptr_ptr: i32,
+    ) -> Result<Option<RuntimeValue>, Trap>;
+
+    fn memory_grow_index(&mut self, pages: i32) -> Result<Option<RuntimeValue>, Trap>;
+
     fn gas_index(&mut self, gas_amount: i32) -> Result<Option<RuntimeValue>, Trap>;
 
+    fn abort_index(&self, message_ptr_ptr: i32) -> Result<Option<RuntimeValue>, Trap>;
+
+    fn debug_index(&mut self, message_ptr_ptr: i32) -> Result<Option<RuntimeValue>, Trap>;
+
     #[cfg(feature = "debug-print")]
     fn debug_print_index(&self, message: i32) -> Result<Option<RuntimeValue>, Trap>;
+
+    #[cfg(feature = "debug-print")]
+    fn trap_location_index(&mut self, location: i32) -> Result<Option<RuntimeValue>, Trap>;
 }
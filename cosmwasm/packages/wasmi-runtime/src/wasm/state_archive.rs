@@ -0,0 +1,420 @@
+//! Export and import of the storage of a contract, for backups that can be restored later.
+//!
+//! An archive holds the raw entries of the contract storage as the host stores them: scrambled
+//! field names and values that are still encrypted, along with the key index that range queries
+//! use and the links to the keys the contract had before it was migrated. Nothing is decrypted on
+//! the way out, so an archive reveals no more than the storage of the node does.
+//!
+//! Every entry carries a MAC under a key derived from the consensus state key, the contract key
+//! and a snapshot nonce. The nonce is the height of the block the export started at, followed by a
+//! hash of that height and the contract address, so every node that exports a contract at the same
+//! height produces the same archive. An import checks the MAC of every entry in a chunk before it
+//! writes any of them, so it refuses archives that were changed, that mix entries of other
+//! contracts, or that mix entries of other snapshots into a chunk.
+//!
+//! Only the admin of the contract may import, in a tx it signed with the exact chunk, the same way
+//! it migrates the contract. The chain keeps the height of the last snapshot that was imported into
+//! every contract and refuses older ones, so an import can't roll the contract back past it.
+//!
+//! Large storages are exported and imported in chunks. An export returns the raw key the next
+//! chunk starts from, and chunks are imported independently of each other, so either can resume
+//! after the last chunk that went through. An archive can't be imported after the contract key
+//! was rotated or the consensus seed was, since the MACs were made with the keys of the time.
+
+use log::*;
+use serde::{Deserialize, Serialize};
+
+use enclave_ffi_types::{Ctx, EnclaveError};
+
+use crate::cosmwasm::encoding::Binary;
+use crate::cosmwasm::types::{CanonicalAddr, Env, HumanAddr, SigInfo};
+use crate::crypto::{sha_256, AESKey, AuthenticationCode, Hmac, Kdf, KEY_MANAGER};
+use crate::results::ExportContractStateSuccess;
+
+use super::contract_validation::{
+    extract_contract_code_hash, extract_contract_key, validate_contract_key_for_hash,
+    verify_contract_admin, verify_state_import_params, ContractKey,
+};
+use super::db::{read_raw_entries, write_raw_entry};
+
+pub const SNAPSHOT_NONCE_SIZE: usize = 32;
+const SNAPSHOT_HEIGHT_SIZE: usize = 8;
+
+/// An entry of the contract storage, as the host stores it
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ArchiveEntry {
+    pub key: Binary,
+    pub value: Binary,
+    /// See `entry_mac`
+    pub mac: Binary,
+}
+
+/// A chunk of an archive, as it is returned by an export and passed to an import
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ArchiveChunk {
+    pub contract_address: HumanAddr,
+    pub snapshot_nonce: Binary,
+    pub entries: Vec<ArchiveEntry>,
+}
+
+/// Export up to `chunk_size` entries of the storage of the contract in `env`, starting from the raw
+/// key `cursor`.
+///
+/// An export starts with an empty cursor and an empty snapshot nonce, and takes the snapshot at
+/// the height of the block in `env`. The chunks after the first one must be exported with the
+/// nonce it returned, so they can be imported together. The cursor of the next chunk is empty
+/// after the last one.
+pub fn export_contract_state(
+    context: Ctx,
+    env: &[u8],
+    cursor: &[u8],
+    snapshot_nonce: &[u8],
+    chunk_size: u32,
+) -> Result<ExportContractStateSuccess, EnclaveError> {
+    let parsed_env = parse_env(env)?;
+    let contract_key = authenticate_contract(&parsed_env)?;
+    let canonical_contract_address = canonical_address(&parsed_env.contract.address)?;
+
+    if chunk_size == 0 {
+        warn!("Tried to export contract state in empty chunks");
+        return Err(EnclaveError::ValidationFailure);
+    }
+
+    let snapshot_nonce = if snapshot_nonce.is_empty() && cursor.is_empty() {
+        snapshot_nonce_for(&canonical_contract_address, parsed_env.block.height)
+    } else {
+        let snapshot_height = snapshot_height_of(&canonical_contract_address, snapshot_nonce)
+            .ok_or_else(|| {
+                warn!("Tried to continue a contract state export without its snapshot nonce");
+                EnclaveError::ValidationFailure
+            })?;
+        if snapshot_height > parsed_env.block.height {
+            warn!("Tried to export a contract state snapshot of a future block");
+            return Err(EnclaveError::ValidationFailure);
+        }
+        snapshot_nonce_for(&canonical_contract_address, snapshot_height)
+    };
+
+    // One more entry than the chunk holds tells where the next chunk starts
+    let (mut raw_entries, _) = read_raw_entries(&context, cursor, chunk_size as usize + 1)?;
+    let next_cursor = if raw_entries.len() > chunk_size as usize {
+        raw_entries.pop().map(|(key, _)| key).unwrap_or_default()
+    } else {
+        vec![]
+    };
+
+    let mac_key = archive_mac_key(&contract_key, &snapshot_nonce)?;
+    let entries = raw_entries
+        .into_iter()
+        .map(|(key, value)| ArchiveEntry {
+            mac: Binary(entry_mac(&mac_key, &key, &value).to_vec()),
+            key: Binary(key),
+            value: Binary(value),
+        })
+        .collect::<Vec<_>>();
+    info!(
+        "Exporting {} entries of the contract storage",
+        entries.len()
+    );
+
+    let chunk = ArchiveChunk {
+        contract_address: parsed_env.contract.address,
+        snapshot_nonce: Binary(snapshot_nonce.to_vec()),
+        entries,
+    };
+    let chunk = serde_json::to_vec(&chunk).map_err(|err| {
+        warn!(
+            "got an error while trying to serialize a contract state chunk: {}",
+            err
+        );
+        EnclaveError::FailedToSerialize
+    })?;
+
+    Ok(ExportContractStateSuccess {
+        snapshot_nonce,
+        chunk,
+        next_cursor,
+    })
+}
+
+/// Write the entries of a chunk of an archive back to the storage of the contract in `env`.
+/// Returns how many entries were written.
+///
+/// Nothing is written unless the sender is the authenticated admin of the contract and signed an
+/// import of this chunk, the chunk was exported from this contract, and every one of its entries
+/// is the one that was exported. That the snapshot isn't older than the last one imported is
+/// checked by the chain, which keeps the height of that one.
+pub fn import_contract_state(
+    context: Ctx,
+    env: &[u8],
+    sig_info: &[u8],
+    chunk: &[u8],
+) -> Result<u32, EnclaveError> {
+    let parsed_env = parse_env(env)?;
+    let contract_key = authenticate_contract(&parsed_env)?;
+    let canonical_contract_address = canonical_address(&parsed_env.contract.address)?;
+
+    let parsed_sig_info: SigInfo = serde_json::from_slice(sig_info).map_err(|err| {
+        warn!(
+            "got an error while trying to deserialize sig info input bytes into json {:?}: {}",
+            String::from_utf8_lossy(&sig_info),
+            err
+        );
+        EnclaveError::FailedToDeserialize
+    })?;
+
+    // Verify env parameters against the signed tx
+    verify_state_import_params(&parsed_sig_info, &parsed_env, chunk)?;
+
+    // Only the admin of the contract may import, like it's the only one that may migrate it
+    verify_contract_admin(&parsed_env)?;
+
+    let chunk: ArchiveChunk = serde_json::from_slice(chunk).map_err(|err| {
+        warn!(
+            "got an error while trying to deserialize a contract state chunk: {}",
+            err
+        );
+        EnclaveError::FailedToDeserialize
+    })?;
+
+    if chunk.contract_address != parsed_env.contract.address {
+        warn!("Tried to import the state of another contract");
+        return Err(EnclaveError::TamperedStateArchive);
+    }
+    match snapshot_height_of(&canonical_contract_address, chunk.snapshot_nonce.as_slice()) {
+        Some(snapshot_height) if snapshot_height <= parsed_env.block.height => {}
+        _ => {
+            warn!("Tried to import a contract state chunk with a malformed snapshot nonce");
+            return Err(EnclaveError::TamperedStateArchive);
+        }
+    }
+
+    let mac_key = archive_mac_key(&contract_key, chunk.snapshot_nonce.as_slice())?;
+    verify_entries(&mac_key, &chunk.entries)?;
+
+    info!(
+        "Importing {} entries of the contract storage",
+        chunk.entries.len()
+    );
+    for entry in &chunk.entries {
+        write_raw_entry(&context, entry.key.as_slice(), entry.value.as_slice())?;
+    }
+
+    Ok(chunk.entries.len() as u32)
+}
+
+fn parse_env(env: &[u8]) -> Result<Env, EnclaveError> {
+    serde_json::from_slice(env).map_err(|err| {
+        warn!(
+            "got an error while trying to deserialize env input bytes into json {:?}: {}",
+            String::from_utf8_lossy(&env),
+            err
+        );
+        EnclaveError::FailedToDeserialize
+    })
+}
+
+fn canonical_address(contract_address: &HumanAddr) -> Result<CanonicalAddr, EnclaveError> {
+    CanonicalAddr::from_human(contract_address).map_err(|err| {
+        warn!(
+            "got an error while trying to deserialize the contract address {:?}: {}",
+            contract_address, err
+        );
+        EnclaveError::FailedToDeserialize
+    })
+}
+
+/// Check that the contract key in `env` belongs to the contract and its code, and return it
+fn authenticate_contract(env: &Env) -> Result<ContractKey, EnclaveError> {
+    let contract_key = extract_contract_key(env)?;
+    let canonical_contract_address = canonical_address(&env.contract.address)?;
+
+    let contract_hash = extract_contract_code_hash(env)?;
+
+    if !validate_contract_key_for_hash(
+        &contract_key,
+        &(canonical_contract_address.0).0,
+        &contract_hash,
+    ) {
+        warn!("Contract key does not match the code of the contract");
+        return Err(EnclaveError::FailedContractAuthentication);
+    }
+
+    Ok(contract_key)
+}
+
+/// The nonce of the snapshot of a contract at `snapshot_height`: the big endian height, followed
+/// by a hash of the contract address and the height, so a nonce can't be moved to another contract
+fn snapshot_nonce_for(
+    contract_address: &CanonicalAddr,
+    snapshot_height: u64,
+) -> [u8; SNAPSHOT_NONCE_SIZE] {
+    let mut hash_data = b"contract_state_snapshot".to_vec();
+    hash_data.extend_from_slice(contract_address.as_slice());
+    hash_data.extend_from_slice(&snapshot_height.to_be_bytes());
+
+    let mut snapshot_nonce = [0u8; SNAPSHOT_NONCE_SIZE];
+    snapshot_nonce[..SNAPSHOT_HEIGHT_SIZE].copy_from_slice(&snapshot_height.to_be_bytes());
+    snapshot_nonce[SNAPSHOT_HEIGHT_SIZE..]
+        .copy_from_slice(&sha_256(&hash_data)[..SNAPSHOT_NONCE_SIZE - SNAPSHOT_HEIGHT_SIZE]);
+    snapshot_nonce
+}
+
+/// The height of the snapshot of `snapshot_nonce`, or None if it isn't a nonce of this contract
+fn snapshot_height_of(contract_address: &CanonicalAddr, snapshot_nonce: &[u8]) -> Option<u64> {
+    if snapshot_nonce.len() != SNAPSHOT_NONCE_SIZE {
+        return None;
+    }
+    let mut height_bytes = [0u8; SNAPSHOT_HEIGHT_SIZE];
+    height_bytes.copy_from_slice(&snapshot_nonce[..SNAPSHOT_HEIGHT_SIZE]);
+    let snapshot_height = u64::from_be_bytes(height_bytes);
+
+    if snapshot_nonce_for(contract_address, snapshot_height)[..] != *snapshot_nonce {
+        return None;
+    }
+    Some(snapshot_height)
+}
+
+fn archive_mac_key(
+    contract_key: &ContractKey,
+    snapshot_nonce: &[u8],
+) -> Result<AESKey, EnclaveError> {
+    let consensus_state_ikm = KEY_MANAGER.get_consensus_state_ikm().map_err(|_err| {
+        warn!("Error extracting consensus_state_key");
+        EnclaveError::EnclaveNotInitialized
+    })?;
+
+    Ok(archive_mac_key_with(
+        &consensus_state_ikm,
+        contract_key,
+        snapshot_nonce,
+    ))
+}
+
+fn archive_mac_key_with(
+    consensus_state_ikm: &AESKey,
+    contract_key: &ContractKey,
+    snapshot_nonce: &[u8],
+) -> AESKey {
+    let mut derivation_data = b"contract_state_archive".to_vec();
+    derivation_data.extend_from_slice(contract_key);
+    derivation_data.extend_from_slice(snapshot_nonce);
+    consensus_state_ikm.derive_key_from_this(&derivation_data)
+}
+
+/// The key is length prefixed, so it can't be confused with the value that follows it
+fn entry_mac(mac_key: &AESKey, key: &[u8], value: &[u8]) -> AuthenticationCode {
+    let mut input_data = (key.len() as u64).to_be_bytes().to_vec();
+    input_data.extend_from_slice(key);
+    input_data.extend_from_slice(value);
+    AuthenticationCode::new(mac_key.sign_sha_256(&input_data))
+}
+
+fn verify_entries(mac_key: &AESKey, entries: &[ArchiveEntry]) -> Result<(), EnclaveError> {
+    for entry in entries {
+        let mac = entry_mac(mac_key, entry.key.as_slice(), entry.value.as_slice());
+        if !mac.verify(entry.mac.as_slice()) {
+            warn!("An entry of a contract state archive failed to authenticate");
+            return Err(EnclaveError::TamperedStateArchive);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    fn entries_with(mac_key: &AESKey, count: u8) -> Vec<ArchiveEntry> {
+        (0..count)
+            .map(|index| {
+                let (key, value) = (vec![index; 32], vec![index; 48]);
+                ArchiveEntry {
+                    mac: Binary(entry_mac(mac_key, &key, &value).to_vec()),
+                    key: Binary(key),
+                    value: Binary(value),
+                }
+            })
+            .collect()
+    }
+
+    pub fn test_archive_entries_verify() {
+        let mac_key = archive_mac_key_with(&AESKey::new_from_slice(&[1; 32]), &[2; 64], &[3; 32]);
+        let entries = entries_with(&mac_key, 10);
+
+        assert!(verify_entries(&mac_key, &entries).is_ok());
+        assert!(verify_entries(&mac_key, &[]).is_ok());
+    }
+
+    pub fn test_tampered_archive_entries_are_refused() {
+        let mac_key = archive_mac_key_with(&AESKey::new_from_slice(&[1; 32]), &[2; 64], &[3; 32]);
+
+        let mut changed_value = entries_with(&mac_key, 10);
+        changed_value[5].value.0[0] ^= 1;
+        let mut changed_key = entries_with(&mac_key, 10);
+        changed_key[5].key.0[0] ^= 1;
+        // The MAC of another entry
+        let mut swapped_mac = entries_with(&mac_key, 10);
+        swapped_mac[5].mac = swapped_mac[6].mac.clone();
+        // Bytes moved from the end of the key to the start of the value
+        let mut moved_boundary = entries_with(&mac_key, 10);
+        let last_key_byte = moved_boundary[5].key.0.pop().unwrap();
+        moved_boundary[5].value.0.insert(0, last_key_byte);
+
+        for entries in &[changed_value, changed_key, swapped_mac, moved_boundary] {
+            assert_eq!(
+                format!("{:?}", verify_entries(&mac_key, entries)),
+                format!("{:?}", Err::<(), _>(EnclaveError::TamperedStateArchive))
+            );
+        }
+    }
+
+    pub fn test_mixed_archive_entries_are_refused() {
+        let consensus_state_ikm = AESKey::new_from_slice(&[1; 32]);
+        let mac_key = archive_mac_key_with(&consensus_state_ikm, &[2; 64], &[3; 32]);
+        let other_contract = archive_mac_key_with(&consensus_state_ikm, &[4; 64], &[3; 32]);
+        let other_snapshot = archive_mac_key_with(&consensus_state_ikm, &[2; 64], &[5; 32]);
+
+        for other_key in &[other_contract, other_snapshot] {
+            let mut mixed = entries_with(&mac_key, 10);
+            mixed.extend(entries_with(other_key, 1));
+            assert!(verify_entries(&mac_key, &mixed).is_err());
+        }
+    }
+
+    pub fn test_snapshot_nonce_is_deterministic() {
+        let contract = CanonicalAddr(Binary(vec![1; 20]));
+        let other_contract = CanonicalAddr(Binary(vec![2; 20]));
+
+        // Every node derives the same nonce for a snapshot, and reads its height back from it
+        let snapshot_nonce = snapshot_nonce_for(&contract, 1234);
+        assert_eq!(snapshot_nonce, snapshot_nonce_for(&contract, 1234));
+        assert_eq!(snapshot_height_of(&contract, &snapshot_nonce), Some(1234));
+
+        // Other heights and contracts get other nonces
+        assert_ne!(snapshot_nonce, snapshot_nonce_for(&contract, 1235));
+        assert_ne!(snapshot_nonce, snapshot_nonce_for(&other_contract, 1234));
+
+        // A nonce of another contract, or with a height that was changed, isn't accepted
+        assert_eq!(snapshot_height_of(&other_contract, &snapshot_nonce), None);
+        let mut later = snapshot_nonce;
+        later[SNAPSHOT_HEIGHT_SIZE - 1] ^= 1;
+        assert_eq!(snapshot_height_of(&contract, &later), None);
+        assert_eq!(snapshot_height_of(&contract, &snapshot_nonce[1..]), None);
+    }
+
+    pub fn test_archive_chunk_round_trips_as_json() {
+        let mac_key = archive_mac_key_with(&AESKey::new_from_slice(&[1; 32]), &[2; 64], &[3; 32]);
+        let chunk = ArchiveChunk {
+            contract_address: HumanAddr("secret1contract".to_string()),
+            snapshot_nonce: Binary(vec![3; 32]),
+            entries: entries_with(&mac_key, 3),
+        };
+
+        let json = serde_json::to_vec(&chunk).unwrap();
+        let parsed: ArchiveChunk = serde_json::from_slice(&json).unwrap();
+        assert_eq!(parsed, chunk);
+        assert!(verify_entries(&mac_key, &parsed.entries).is_ok());
+    }
+}
@@ -0,0 +1,199 @@
+//! Strict parsing of the JSON responses of contracts.
+//!
+//! serde_json keeps the last value of a duplicated key, while other parsers keep the first one or
+//! fail, so a response with duplicate keys could mean one thing to the enclave and another to the
+//! clients that decrypt it. Responses are checked here before they're parsed: they must be valid
+//! UTF-8, have no duplicate keys and be nested at most `MAX_RESPONSE_JSON_DEPTH` levels deep.
+//! Responses that aren't fail the call with `EnclaveError::MalformedContractResponse`, which is
+//! the contract's fault rather than the enclave's.
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+use log::*;
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, Deserializer, Error as _, MapAccess, SeqAccess,
+    Visitor,
+};
+
+use enclave_ffi_types::EnclaveError;
+
+use crate::consts::{MAX_RESPONSE_JSON_DEPTH, MAX_RESPONSE_JSON_SIZE};
+use crate::trace_secret;
+
+/// Parse `response`, the raw output of a contract, after checking that it's strict JSON
+pub fn from_contract_response<T: DeserializeOwned>(response: &[u8]) -> Result<T, EnclaveError> {
+    check_contract_response(response)?;
+
+    serde_json::from_slice(response).map_err(|err| {
+        warn!("got an error while trying to deserialize output bytes into json");
+        trace_secret!("output: {:?} error: {:?}", response, err);
+        EnclaveError::FailedToDeserialize
+    })
+}
+
+fn check_contract_response(response: &[u8]) -> Result<(), EnclaveError> {
+    if response.len() > MAX_RESPONSE_JSON_SIZE {
+        warn!(
+            "The contract returned a response of {} bytes, the most that is parsed is {}",
+            response.len(),
+            MAX_RESPONSE_JSON_SIZE
+        );
+        return Err(EnclaveError::ResponseTooLarge);
+    }
+
+    let mut deserializer = serde_json::Deserializer::from_slice(response);
+    StrictValue { depth: 0 }
+        .deserialize(&mut deserializer)
+        .and_then(|()| deserializer.end())
+        .map_err(|err| {
+            warn!("The contract returned a malformed response: {}", err);
+            EnclaveError::MalformedContractResponse
+        })
+}
+
+/// Visits a JSON value without keeping it, and fails on what serde_json would let through
+#[derive(Clone, Copy)]
+struct StrictValue {
+    depth: usize,
+}
+
+impl StrictValue {
+    fn nested<E: de::Error>(self) -> Result<Self, E> {
+        if self.depth >= MAX_RESPONSE_JSON_DEPTH {
+            return Err(E::custom(format!(
+                "nested deeper than {} levels",
+                MAX_RESPONSE_JSON_DEPTH
+            )));
+        }
+
+        Ok(StrictValue {
+            depth: self.depth + 1,
+        })
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for StrictValue {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for StrictValue {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a JSON value")
+    }
+
+    fn visit_bool<E>(self, _value: bool) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_i64<E>(self, _value: i64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_u64<E>(self, _value: u64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_f64<E>(self, _value: f64) -> Result<(), E> {
+        Ok(())
+    }
+
+    // serde_json only hands out strings that are valid UTF-8
+    fn visit_str<E>(self, _value: &str) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_unit<E>(self) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        let element = self.nested()?;
+        while seq.next_element_seed(element)?.is_some() {}
+
+        Ok(())
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        let value = self.nested()?;
+        let mut keys = BTreeSet::new();
+        while let Some(key) = map.next_key::<String>()? {
+            if keys.contains(&key) {
+                return Err(A::Error::custom(format!("duplicate key `{}`", key)));
+            }
+            map.next_value_seed(value)?;
+            keys.insert(key);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use serde_json::Value;
+
+    fn nested_arrays(depth: usize) -> Vec<u8> {
+        format!("{}{}", "[".repeat(depth), "]".repeat(depth)).into_bytes()
+    }
+
+    fn assert_malformed(response: &[u8]) {
+        assert!(matches!(
+            from_contract_response::<Value>(response),
+            Err(EnclaveError::MalformedContractResponse)
+        ));
+    }
+
+    pub fn test_strict_json_accepts_responses() {
+        let response = r#"{"Ok":{"messages":[],"log":[{"key":"a","value":"é"}],"data":null}}"#;
+        let response = response.as_bytes();
+        let parsed: Value = from_contract_response(response).unwrap();
+        assert_eq!(parsed, serde_json::from_slice::<Value>(response).unwrap());
+
+        // The same key may appear in different objects
+        from_contract_response::<Value>(br#"[{"key":1},{"key":2,"nested":{"key":3}}]"#).unwrap();
+        from_contract_response::<Value>(&nested_arrays(MAX_RESPONSE_JSON_DEPTH)).unwrap();
+    }
+
+    pub fn test_strict_json_rejects_duplicate_keys() {
+        assert_malformed(br#"{"Ok":{"data":"AQ==","data":"Ag=="}}"#);
+        assert_malformed(br#"{"Ok":{"log":[{"key":"a","key":"b","value":"c"}]}}"#);
+        // Escapes don't hide a duplicate
+        assert_malformed(br#"{"Ok":1,"\u004fk":2}"#);
+    }
+
+    pub fn test_strict_json_rejects_deep_nesting() {
+        assert_malformed(&nested_arrays(MAX_RESPONSE_JSON_DEPTH + 1));
+        assert_malformed(&nested_arrays(200));
+        let deep_object = format!("{}1{}", r#"{"a":"#.repeat(200), "}".repeat(200));
+        assert_malformed(deep_object.as_bytes());
+    }
+
+    pub fn test_strict_json_rejects_invalid_utf8() {
+        assert_malformed(b"{\"Ok\":\"\xff\xfe\"}");
+        assert_malformed(b"{\"\xc3\x28\":1}");
+        // A lone surrogate can't be turned into UTF-8 either
+        assert_malformed(br#"{"Ok":"\ud800"}"#);
+    }
+
+    pub fn test_strict_json_rejects_trailing_data() {
+        assert_malformed(br#"{"Ok":"a"}{"Ok":"b"}"#);
+        assert_malformed(b"not json");
+    }
+
+    pub fn test_strict_json_size_cap() {
+        let response = format!(r#"{{"Ok":"{}"}}"#, "a".repeat(MAX_RESPONSE_JSON_SIZE));
+        assert!(matches!(
+            from_contract_response::<Value>(response.as_bytes()),
+            Err(EnclaveError::ResponseTooLarge)
+        ));
+    }
+}
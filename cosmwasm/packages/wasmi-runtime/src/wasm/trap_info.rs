@@ -0,0 +1,255 @@
+//! Debug info for contracts that trap, so developers on local networks can tell where their
+//! contract failed. Only built with the `debug-print` feature, production enclaves neither
+//! instrument contracts nor return this info.
+//!
+//! wasmi doesn't report where a trap happened, so every `unreachable` instruction of the contract
+//! is preceded by a call to the `trap_location` import, with the index of its location. The
+//! locations are resolved through the name section of the contract when it's loaded.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+use log::*;
+use parity_wasm::builder;
+use parity_wasm::elements::{ImportCountType, Instruction, Module, ValueType};
+use wasmi::TrapKind;
+
+use super::memory::push_function_import;
+use super::wasm_features::unreachable_offsets;
+
+/// The import that instrumented `unreachable` instructions call
+pub const TRAP_LOCATION_IMPORT: &str = "trap_location";
+
+/// Where each `unreachable` instruction of a contract is, in the order they appear in the module,
+/// such as "transfer::execute at offset 0x1a2"
+pub type TrapLocations = Vec<String>;
+
+thread_local! {
+    /// The debug info of the trap that stopped the contract running on this thread
+    static TRAP_DEBUG_INFO: RefCell<Option<String>> = RefCell::new(None);
+}
+
+/// Describe where each `unreachable` instruction of the module is. `wasm` is the contract the
+/// module was deserialized from, and is used for the offsets of the instructions.
+///
+/// This must run before the module is instrumented, as instrumenting it shifts the functions.
+pub fn find_trap_locations(module: &Module, wasm: &[u8]) -> TrapLocations {
+    let names = function_names(module);
+    let offsets = unreachable_offsets(wasm);
+    let imported_functions = module.import_count(ImportCountType::Function) as u32;
+
+    let bodies = module.code_section().map_or(&[][..], |code| code.bodies());
+    let mut locations = TrapLocations::new();
+    for (defined, body) in bodies.iter().enumerate() {
+        let index = imported_functions + defined as u32;
+        let name = names
+            .get(&index)
+            .cloned()
+            .unwrap_or_else(|| format!("function #{}", index));
+        let function_offsets = offsets.as_ref().and_then(|offsets| offsets.get(defined));
+
+        let unreachables = body
+            .code()
+            .elements()
+            .iter()
+            .filter(|instruction| matches!(instruction, Instruction::Unreachable));
+        for (nth, _) in unreachables.enumerate() {
+            match function_offsets.and_then(|offsets| offsets.get(nth)) {
+                Some(offset) => locations.push(format!("{} at offset {:#x}", name, offset)),
+                // The walk of the module stopped early, so only the function is known
+                None => locations.push(name.clone()),
+            }
+        }
+    }
+
+    locations
+}
+
+/// The names of the functions in the name section of the module, without the hashes rustc
+/// appends to them
+fn function_names(module: &Module) -> BTreeMap<u32, String> {
+    let module = match module.clone().parse_names() {
+        Ok(module) => module,
+        Err((_, module)) => {
+            debug!("Couldn't parse the name section of the contract");
+            module
+        }
+    };
+
+    module
+        .names_section()
+        .and_then(|names| names.functions())
+        .map(|functions| {
+            functions
+                .names()
+                .iter()
+                .map(|(index, name)| (index, strip_symbol_hash(name).to_string()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Turn "transfer::execute::h0123456789abcdef" into "transfer::execute"
+fn strip_symbol_hash(name: &str) -> &str {
+    match name.rfind("::h") {
+        Some(start)
+            if name.len() - start == 19
+                && name[start + 3..].chars().all(|c| c.is_ascii_hexdigit()) =>
+        {
+            &name[..start]
+        }
+        _ => name,
+    }
+}
+
+/// Precede the `n`th `unreachable` instruction of the module with a call to `trap_location(n)`.
+///
+/// Runs after gas metering is injected, so the calls aren't charged, and the gas used by a
+/// contract is the same whether its enclave reports traps or not.
+pub fn hook_unreachable(module: Module) -> Module {
+    let has_unreachable = module.code_section().map_or(false, |code| {
+        code.bodies().iter().any(|body| {
+            body.code()
+                .elements()
+                .iter()
+                .any(|instruction| matches!(instruction, Instruction::Unreachable))
+        })
+    });
+    if !has_unreachable {
+        return module;
+    }
+
+    let signature = builder::signature().with_param(ValueType::I32).build_sig();
+    let (mut module, hook_func) = push_function_import(module, TRAP_LOCATION_IMPORT, signature);
+
+    let mut location = 0_i32;
+    if let Some(code) = module.code_section_mut() {
+        for body in code.bodies_mut() {
+            let instructions = body.code_mut().elements_mut();
+            let mut hooked = Vec::with_capacity(instructions.len());
+            for instruction in instructions.drain(..) {
+                if let Instruction::Unreachable = instruction {
+                    hooked.push(Instruction::I32Const(location));
+                    hooked.push(Instruction::Call(hook_func));
+                    location += 1;
+                }
+                hooked.push(instruction);
+            }
+            *instructions = hooked;
+        }
+    }
+
+    module
+}
+
+/// Keep the debug info of a trap that stopped the contract, such as
+/// "trap: unreachable in transfer::execute at offset 0x1a2". Errors returned by host functions
+/// aren't traps of the contract, so they're ignored.
+pub fn record_trap(kind: &TrapKind, location: Option<&str>) {
+    let trap = match kind {
+        TrapKind::Unreachable => "unreachable",
+        TrapKind::MemoryAccessOutOfBounds => "out of bounds memory access",
+        TrapKind::TableAccessOutOfBounds => "out of bounds table access",
+        TrapKind::ElemUninitialized => "uninitialized table element",
+        TrapKind::DivisionByZero => "integer divide by zero",
+        TrapKind::InvalidConversionToInt => "invalid conversion to integer",
+        TrapKind::StackOverflow => "call stack exhausted",
+        TrapKind::UnexpectedSignature => "indirect call signature mismatch",
+        _ => return,
+    };
+
+    let debug_info = match location {
+        Some(location) => format!("trap: {} in {}", trap, location),
+        None => format!("trap: {}", trap),
+    };
+    debug!("{}", debug_info);
+    TRAP_DEBUG_INFO.with(|info| *info.borrow_mut() = Some(debug_info));
+}
+
+/// Take the debug info of the trap that stopped the contract, if it trapped
+pub fn take_trap_debug_info() -> Option<String> {
+    TRAP_DEBUG_INFO.with(|info| info.borrow_mut().take())
+}
+
+/// Forget the trap of a previous ecall. Called when an ecall starts.
+pub fn clear_trap_debug_info() {
+    TRAP_DEBUG_INFO.with(|info| *info.borrow_mut() = None);
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use parity_wasm::elements;
+
+    // Imports `db_read`, and defines a named function and an anonymous one, which both trap
+    const TRAPPING_WASM: &[u8] = include_bytes!("../../testdata/trap-info/trapping.wasm");
+
+    pub fn test_strip_symbol_hash() {
+        assert_eq!(
+            strip_symbol_hash("transfer::execute::h0123456789abcdef"),
+            "transfer::execute"
+        );
+        assert_eq!(strip_symbol_hash("transfer::execute"), "transfer::execute");
+        assert_eq!(strip_symbol_hash("handler::hash"), "handler::hash");
+    }
+
+    pub fn test_find_trap_locations() {
+        let module: Module = elements::deserialize_buffer(TRAPPING_WASM).unwrap();
+        let locations = find_trap_locations(&module, TRAPPING_WASM);
+
+        assert_eq!(
+            locations,
+            vec![
+                "transfer::execute at offset 0x3c".to_string(),
+                "function #2 at offset 0x40".to_string(),
+            ]
+        );
+    }
+
+    pub fn test_hook_unreachable() {
+        let module: Module = elements::deserialize_buffer(TRAPPING_WASM).unwrap();
+        let module = hook_unreachable(module);
+
+        // The import comes after db_read, so the functions of the module move up by one
+        let bodies = module.code_section().unwrap().bodies();
+        assert_eq!(
+            bodies[0].code().elements(),
+            &[
+                Instruction::Nop,
+                Instruction::I32Const(0),
+                Instruction::Call(1),
+                Instruction::Unreachable,
+                Instruction::End,
+            ][..]
+        );
+        assert_eq!(
+            bodies[1].code().elements(),
+            &[
+                Instruction::I32Const(1),
+                Instruction::Call(1),
+                Instruction::Unreachable,
+                Instruction::End,
+            ][..]
+        );
+    }
+
+    pub fn test_record_trap() {
+        clear_trap_debug_info();
+        record_trap(
+            &TrapKind::Unreachable,
+            Some("transfer::execute at offset 0x1a2"),
+        );
+        assert_eq!(
+            take_trap_debug_info().as_deref(),
+            Some("trap: unreachable in transfer::execute at offset 0x1a2")
+        );
+        assert_eq!(take_trap_debug_info(), None);
+
+        record_trap(&TrapKind::DivisionByZero, None);
+        assert_eq!(
+            take_trap_debug_info().as_deref(),
+            Some("trap: integer divide by zero")
+        );
+    }
+}
@@ -1,6 +1,8 @@
 // use crate::cosmwasm::types::CosmosMsg;
 
-use crate::crypto::{AESKey, Ed25519PublicKey, SIVEncryptable};
+use crate::crypto::{AESKey, CryptoError, Ed25519PublicKey, SIVEncryptable};
+use crate::ecall_trace::Phase;
+use crate::trace_span;
 use crate::wasm::io::calc_encryption_key;
 use enclave_ffi_types::EnclaveError;
 use log::*;
@@ -8,6 +10,21 @@ use serde::{Deserialize, Serialize};
 
 pub type IoNonce = [u8; 32];
 
+/// The version of the associated data that messages are encrypted with, see
+/// `SecretMessage::associated_data`
+pub const MESSAGE_AD_VERSION: u8 = 1;
+
+/// Decrypt the ciphertext of a message, which is bound to `ad` unless the client that encrypted it
+/// predates associated data. Those messages are still accepted.
+pub fn decrypt_message(
+    key: &AESKey,
+    ciphertext: &[u8],
+    ad: &[&[u8]],
+) -> Result<Vec<u8>, CryptoError> {
+    key.decrypt_siv(ciphertext, Some(ad))
+        .or_else(|_| key.decrypt_siv(ciphertext, None))
+}
+
 #[derive(Serialize, Deserialize, PartialEq, Debug)]
 pub struct SecretMessage {
     pub nonce: IoNonce,
@@ -18,8 +35,8 @@ pub struct SecretMessage {
 impl SecretMessage {
     pub fn encrypt_in_place(&mut self) -> Result<(), EnclaveError> {
         self.msg = self
-            .encryption_key()
-            .encrypt_siv(self.msg.as_slice(), None)
+            .encryption_key()?
+            .encrypt_siv(self.msg.as_slice(), Some(&self.associated_data()))
             .map_err(|err| {
                 error!("got an error while trying to encrypt the msg: {}", err);
                 EnclaveError::EncryptionError
@@ -29,18 +46,25 @@ impl SecretMessage {
     }
 
     pub fn decrypt(&self) -> Result<Vec<u8>, EnclaveError> {
-        let key = self.encryption_key();
+        let _span = trace_span!(Phase::DecryptInput);
+        let key = self.encryption_key()?;
 
-        // pass
-        let msg = key.decrypt_siv(self.msg.as_slice(), None).map_err(|err| {
+        let msg = decrypt_message(&key, &self.msg, &self.associated_data()).map_err(|err| {
             error!("got an error while trying to decrypt the msg: {}", err);
-            EnclaveError::DecryptionError
+            EnclaveError::from(err)
         })?;
 
         Ok(msg)
     }
 
-    pub fn encryption_key(&self) -> AESKey {
+    /// Binds the ciphertext of the message to the nonce and public key it's sent with:
+    ///
+    /// msg = AES-SIV(key, plaintext, [MESSAGE_AD_VERSION, nonce, user_public_key])
+    pub fn associated_data(&self) -> [&[u8]; 3] {
+        [&[MESSAGE_AD_VERSION], &self.nonce, &self.user_public_key]
+    }
+
+    pub fn encryption_key(&self) -> Result<AESKey, EnclaveError> {
         calc_encryption_key(&self.nonce, &self.user_public_key)
     }
 
@@ -74,7 +98,7 @@ impl SecretMessage {
                 "Encrypted message length {:?} is too short. Cannot parse",
                 msg.len()
             );
-            return Err(EnclaveError::DecryptionError);
+            return Err(EnclaveError::CiphertextTooShort);
         };
 
         let mut nonce = [0u8; 32];
@@ -130,6 +154,88 @@ pub mod tests {
         assert_eq!(secret_msg, msg_from_slice);
     }
 
+    fn encrypted_msg(plaintext: &[u8]) -> SecretMessage {
+        let mut secret_msg = SecretMessage {
+            nonce: [4u8; 32],
+            user_public_key: [7u8; 32],
+            msg: plaintext.to_vec(),
+        };
+        secret_msg.encrypt_in_place().unwrap();
+        secret_msg
+    }
+
+    pub fn test_decrypt_message_too_short() {
+        // Shorter than a nonce, a public key and an empty ciphertext
+        assert!(matches!(
+            SecretMessage::from_slice(&[1u8; 70]),
+            Err(EnclaveError::CiphertextTooShort)
+        ));
+        // Enough for a nonce and a public key, but the ciphertext is shorter than its tag
+        let mut secret_msg = encrypted_msg(b"{\"nop\":{}}");
+        secret_msg.msg.truncate(10);
+        assert!(matches!(
+            secret_msg.decrypt(),
+            Err(EnclaveError::CiphertextTooShort)
+        ));
+    }
+
+    pub fn test_decrypt_message_with_wrong_key() {
+        let mut other_nonce = encrypted_msg(b"{\"nop\":{}}");
+        other_nonce.nonce = [5u8; 32];
+        assert!(matches!(
+            other_nonce.decrypt(),
+            Err(EnclaveError::DecryptionError)
+        ));
+
+        let mut other_public_key = encrypted_msg(b"{\"nop\":{}}");
+        other_public_key.user_public_key = [8u8; 32];
+        assert!(matches!(
+            other_public_key.decrypt(),
+            Err(EnclaveError::DecryptionError)
+        ));
+    }
+
+    pub fn test_message_is_bound_to_its_nonce_and_public_key() {
+        let secret_msg = encrypted_msg(b"{\"nop\":{}}");
+        let key = secret_msg.encryption_key().unwrap();
+        assert!(key.decrypt_siv(&secret_msg.msg, None).is_err());
+
+        // The ciphertext of another message doesn't decrypt under this one's nonce and public key
+        let mut other = SecretMessage {
+            nonce: [5u8; 32],
+            user_public_key: [7u8; 32],
+            msg: b"{\"nop\":{}}".to_vec(),
+        };
+        other.encrypt_in_place().unwrap();
+        let mut swapped = encrypted_msg(b"{\"nop\":{}}");
+        swapped.msg = other.msg;
+        assert!(matches!(
+            swapped.decrypt(),
+            Err(EnclaveError::DecryptionError)
+        ));
+
+        // Messages of clients that don't bind them are still accepted
+        let mut legacy = encrypted_msg(b"");
+        legacy.msg = key.encrypt_siv(b"{\"nop\":{}}", None).unwrap();
+        assert_eq!(legacy.decrypt().unwrap(), b"{\"nop\":{}}".to_vec());
+    }
+
+    pub fn test_decrypt_corrupted_message() {
+        let mut secret_msg = encrypted_msg(b"{\"nop\":{}}");
+        let last = secret_msg.msg.len() - 1;
+        secret_msg.msg[last] ^= 1;
+        assert!(matches!(
+            secret_msg.decrypt(),
+            Err(EnclaveError::DecryptionError)
+        ));
+
+        // And the message decrypts when it's left alone
+        assert_eq!(
+            encrypted_msg(b"{\"nop\":{}}").decrypt().unwrap(),
+            b"{\"nop\":{}}".to_vec()
+        );
+    }
+
     // This is commented out because it's trying to modify KEY_MANAGER which is immutable.
     // // todo: fix test vectors to actually work
     // pub fn test_msg_decrypt() {
@@ -0,0 +1,282 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::SgxMutex;
+use std::time::{Duration, SystemTime};
+use std::untrusted::time::SystemTimeEx;
+
+use lazy_static::lazy_static;
+use log::*;
+
+use enclave_ffi_types::EnclaveError;
+
+use crate::consts::{MAX_CODE_SIZE, WASM_VALIDATION_SESSION_TTL_SECS};
+use crate::crypto::{sha_256, Sha256};
+
+use super::contract_operations::validate_code;
+use super::gzip::{decompress_code, is_gzip};
+use super::module_cache::CodeHash;
+
+static MAX_CONTRACT_SIZE: AtomicUsize = AtomicUsize::new(MAX_CODE_SIZE);
+
+/// The largest contract the enclave validates or runs
+pub fn max_contract_size() -> usize {
+    MAX_CONTRACT_SIZE.load(Ordering::Relaxed)
+}
+
+/// Change the largest contract the enclave accepts, see `ecall_configure_runtime`
+pub fn set_max_contract_size(max_contract_size: usize) {
+    MAX_CONTRACT_SIZE.store(max_contract_size, Ordering::Relaxed);
+}
+
+/// A contract that is being uploaded to the enclave in chunks
+struct ValidationSession {
+    code: Vec<u8>,
+    hasher: Sha256,
+    next_chunk: u32,
+    last_used: SystemTime,
+}
+
+impl ValidationSession {
+    fn new(now: SystemTime) -> Self {
+        Self {
+            code: Vec::new(),
+            hasher: Sha256::new(),
+            next_chunk: 0,
+            last_used: now,
+        }
+    }
+
+    fn is_expired(&self, now: SystemTime) -> bool {
+        // If the clock went backwards the session is treated as fresh
+        now.duration_since(self.last_used)
+            .map(|age| age > Duration::from_secs(WASM_VALIDATION_SESSION_TTL_SECS))
+            .unwrap_or(false)
+    }
+}
+
+type Sessions = HashMap<u64, ValidationSession>;
+
+lazy_static! {
+    static ref SESSIONS: SgxMutex<Sessions> = SgxMutex::new(HashMap::new());
+}
+
+/// Drop the sessions that were abandoned by the host, so they don't hold on to enclave memory.
+fn purge_expired(sessions: &mut Sessions, now: SystemTime) {
+    sessions.retain(|session_id, session| {
+        let expired = session.is_expired(now);
+        if expired {
+            debug!("Dropping expired wasm validation session {}", session_id);
+        }
+        !expired
+    });
+}
+
+fn begin(sessions: &mut Sessions, session_id: u64, now: SystemTime) -> Result<(), EnclaveError> {
+    purge_expired(sessions, now);
+
+    if sessions.contains_key(&session_id) {
+        warn!("Wasm validation session {} already exists", session_id);
+        return Err(EnclaveError::WasmValidationSessionExists);
+    }
+    sessions.insert(session_id, ValidationSession::new(now));
+
+    Ok(())
+}
+
+fn add_chunk(
+    sessions: &mut Sessions,
+    session_id: u64,
+    chunk_index: u32,
+    chunk: &[u8],
+    now: SystemTime,
+) -> Result<(), EnclaveError> {
+    purge_expired(sessions, now);
+
+    let session = sessions.get_mut(&session_id).ok_or_else(|| {
+        warn!("Wasm validation session {} was not found", session_id);
+        EnclaveError::WasmValidationSessionNotFound
+    })?;
+
+    // An out of order chunk is rejected without touching the session, so the host can resend
+    // the right one.
+    if chunk_index != session.next_chunk {
+        warn!(
+            "Got chunk {} of wasm validation session {}, expected chunk {}",
+            chunk_index, session_id, session.next_chunk
+        );
+        return Err(EnclaveError::WasmChunkOutOfOrder);
+    }
+
+    if session.code.len() + chunk.len() > max_contract_size() {
+        warn!(
+            "Wasm validation session {} exceeded the max code size of {} bytes",
+            session_id,
+            max_contract_size()
+        );
+        sessions.remove(&session_id);
+        return Err(EnclaveError::ContractTooLarge);
+    }
+
+    session.hasher.update(chunk);
+    session.code.extend_from_slice(chunk);
+    session.next_chunk += 1;
+    session.last_used = now;
+
+    Ok(())
+}
+
+fn end(
+    sessions: &mut Sessions,
+    session_id: u64,
+    now: SystemTime,
+) -> Result<ValidationSession, EnclaveError> {
+    purge_expired(sessions, now);
+
+    sessions.remove(&session_id).ok_or_else(|| {
+        warn!("Wasm validation session {} was not found", session_id);
+        EnclaveError::WasmValidationSessionNotFound
+    })
+}
+
+/// Start a new chunked validation session. Fails if a live session with this id already exists.
+pub fn validate_wasm_begin(session_id: u64) -> Result<(), EnclaveError> {
+    begin(&mut SESSIONS.lock().unwrap(), session_id, SystemTime::now())
+}
+
+/// Append the next chunk of the contract to the session.
+/// Chunks are numbered from 0, and must arrive in order.
+pub fn validate_wasm_chunk(
+    session_id: u64,
+    chunk_index: u32,
+    chunk: &[u8],
+) -> Result<(), EnclaveError> {
+    add_chunk(
+        &mut SESSIONS.lock().unwrap(),
+        session_id,
+        chunk_index,
+        chunk,
+        SystemTime::now(),
+    )
+}
+
+/// Close the session, validate the contract it received, and return its code hash. A gzipped
+/// contract is decompressed first, and the hash is of the code it decompresses to.
+pub fn validate_wasm_end(session_id: u64) -> Result<CodeHash, EnclaveError> {
+    // The lock isn't held while validating, as it may take a while
+    let session = end(&mut SESSIONS.lock().unwrap(), session_id, SystemTime::now())?;
+
+    if is_gzip(&session.code) {
+        let code = decompress_code(&session.code, max_contract_size())?;
+        let code_hash = sha_256(&code);
+        validate_code(&code_hash, &code)?;
+        return Ok(code_hash);
+    }
+
+    let code_hash = session.hasher.finish();
+    validate_code(&code_hash, &session.code)?;
+
+    Ok(code_hash)
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use enclave_ffi_types::InvalidWasmReason;
+
+    pub fn test_chunk_out_of_order() {
+        let now = SystemTime::now();
+        let mut sessions = Sessions::new();
+        begin(&mut sessions, 1, now).unwrap();
+
+        add_chunk(&mut sessions, 1, 0, b"first", now).unwrap();
+        assert!(matches!(
+            add_chunk(&mut sessions, 1, 2, b"third", now),
+            Err(EnclaveError::WasmChunkOutOfOrder)
+        ));
+        assert!(matches!(
+            add_chunk(&mut sessions, 1, 0, b"first", now),
+            Err(EnclaveError::WasmChunkOutOfOrder)
+        ));
+
+        // The rejected chunks didn't change the session
+        add_chunk(&mut sessions, 1, 1, b"second", now).unwrap();
+        let session = end(&mut sessions, 1, now).unwrap();
+        assert_eq!(session.code, b"firstsecond".to_vec());
+        assert_eq!(session.hasher.finish(), sha_256(b"firstsecond"));
+    }
+
+    pub fn test_duplicate_session_id() {
+        let now = SystemTime::now();
+        let mut sessions = Sessions::new();
+        begin(&mut sessions, 1, now).unwrap();
+        add_chunk(&mut sessions, 1, 0, b"first", now).unwrap();
+
+        assert!(matches!(
+            begin(&mut sessions, 1, now),
+            Err(EnclaveError::WasmValidationSessionExists)
+        ));
+
+        // The existing session wasn't reset
+        add_chunk(&mut sessions, 1, 1, b"second", now).unwrap();
+
+        // Once the session is closed, its id can be used again
+        end(&mut sessions, 1, now).unwrap();
+        begin(&mut sessions, 1, now).unwrap();
+    }
+
+    pub fn test_code_too_large() {
+        let now = SystemTime::now();
+        let mut sessions = Sessions::new();
+        begin(&mut sessions, 1, now).unwrap();
+
+        let chunk = vec![0u8; MAX_CODE_SIZE / 2];
+        add_chunk(&mut sessions, 1, 0, &chunk, now).unwrap();
+        add_chunk(&mut sessions, 1, 1, &chunk, now).unwrap();
+        assert!(matches!(
+            add_chunk(&mut sessions, 1, 2, &[0u8], now),
+            Err(EnclaveError::ContractTooLarge)
+        ));
+
+        // The session is dropped along with the code it accumulated
+        assert!(sessions.is_empty());
+        assert!(matches!(
+            end(&mut sessions, 1, now).err(),
+            Some(EnclaveError::WasmValidationSessionNotFound)
+        ));
+    }
+
+    pub fn test_expired_session() {
+        let now = SystemTime::now();
+        let later = now + Duration::from_secs(WASM_VALIDATION_SESSION_TTL_SECS + 1);
+        let mut sessions = Sessions::new();
+        begin(&mut sessions, 1, now).unwrap();
+
+        assert!(matches!(
+            add_chunk(&mut sessions, 1, 0, b"first", later),
+            Err(EnclaveError::WasmValidationSessionNotFound)
+        ));
+
+        // An expired session doesn't block its id
+        begin(&mut sessions, 1, later).unwrap();
+    }
+
+    pub fn test_invalid_wasm() {
+        let session_id = 0x7e57;
+        validate_wasm_begin(session_id).unwrap();
+        validate_wasm_chunk(session_id, 0, b"\0asm").unwrap();
+        validate_wasm_chunk(session_id, 1, b"not really wasm").unwrap();
+
+        assert!(matches!(
+            validate_wasm_end(session_id),
+            Err(EnclaveError::InvalidWasm {
+                reason: InvalidWasmReason::Malformed
+            })
+        ));
+        // The session is closed even though validation failed
+        assert!(matches!(
+            validate_wasm_end(session_id),
+            Err(EnclaveError::WasmValidationSessionNotFound)
+        ));
+    }
+}
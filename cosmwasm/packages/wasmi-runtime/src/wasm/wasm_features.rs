@@ -0,0 +1,742 @@
+//! Rejects contracts that use wasm proposals whose behavior isn't the same on every CPU, or that
+//! wasmi doesn't run the same way as other engines, and contracts with a start function, which
+//! would run before any of their gas is metered.
+//!
+//! The module is walked before it's deserialized, so that a contract using one of these proposals
+//! is rejected with the reason, rather than with whatever the parser makes of an opcode it
+//! doesn't know. Anything the walker can't read is left for the parser to reject.
+//!
+//! The same walk finds where the `unreachable` instructions of a contract are, for the debug info
+//! of contracts that trap.
+
+use log::*;
+
+use enclave_ffi_types::{EnclaveError, InvalidWasmReason};
+
+const WASM_HEADER: &[u8] = b"\0asm\x01\0\0\0";
+
+const SECTION_TYPE: u8 = 1;
+const SECTION_IMPORT: u8 = 2;
+const SECTION_TABLE: u8 = 4;
+const SECTION_MEMORY: u8 = 5;
+const SECTION_GLOBAL: u8 = 6;
+const SECTION_START: u8 = 8;
+const SECTION_ELEMENT: u8 = 9;
+const SECTION_CODE: u8 = 10;
+const SECTION_DATA: u8 = 11;
+const SECTION_DATA_COUNT: u8 = 12;
+
+const TYPE_I32: u8 = 0x7f;
+const TYPE_I64: u8 = 0x7e;
+const TYPE_F32: u8 = 0x7d;
+const TYPE_F64: u8 = 0x7c;
+const TYPE_V128: u8 = 0x7b;
+const TYPE_FUNCREF: u8 = 0x70;
+const TYPE_EXTERNREF: u8 = 0x6f;
+const BLOCK_TYPE_EMPTY: u8 = 0x40;
+
+const LIMITS_HAS_MAX: u8 = 0x01;
+const LIMITS_SHARED: u8 = 0x02;
+
+const PREFIX_MISC: u8 = 0xfc;
+const PREFIX_SIMD: u8 = 0xfd;
+const PREFIX_THREADS: u8 = 0xfe;
+
+/// A part of a module that one of the checks may object to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Construct {
+    /// A section, by its id
+    Section(u8),
+    /// A value type, wherever it appears: function types, locals, globals, block types and
+    /// typed `select`s
+    ValueType(u8),
+    /// The element type of a table
+    TableElementType(u8),
+    /// A table, by its index, counting imported tables
+    Table(u32),
+    /// The flags of the limits of a memory
+    MemoryLimits(u8),
+    /// The flags of an element segment
+    ElementSegment(u32),
+    /// The flags of a data segment
+    DataSegment(u32),
+    /// An instruction, by its opcode
+    Instruction(u8),
+    /// An instruction behind a prefix opcode, by the prefix and the opcode that follows it
+    PrefixedInstruction(u8, u32),
+    /// The table that a `call_indirect` calls through
+    CallIndirectTable(u32),
+    /// The start of the body of a function the module defines, by its index among the functions
+    /// the module defines
+    FunctionBody(u32),
+    /// An `unreachable` instruction, by its offset in the module
+    Unreachable(usize),
+}
+
+/// A proposal that contracts can't use, and how to recognize it
+struct FeatureCheck {
+    reason: InvalidWasmReason,
+    is_used_by: fn(&Construct) -> bool,
+}
+
+/// To forbid another proposal, give it a reason and add a check for it here
+const FEATURE_CHECKS: &[FeatureCheck] = &[
+    FeatureCheck {
+        reason: InvalidWasmReason::FloatingPoint,
+        is_used_by: uses_floating_point,
+    },
+    FeatureCheck {
+        reason: InvalidWasmReason::Simd,
+        is_used_by: uses_simd,
+    },
+    FeatureCheck {
+        reason: InvalidWasmReason::Threads,
+        is_used_by: uses_threads,
+    },
+    FeatureCheck {
+        reason: InvalidWasmReason::ReferenceTypes,
+        is_used_by: uses_reference_types,
+    },
+    FeatureCheck {
+        reason: InvalidWasmReason::BulkMemory,
+        is_used_by: uses_bulk_memory,
+    },
+    FeatureCheck {
+        reason: InvalidWasmReason::StartFunction,
+        is_used_by: uses_start_function,
+    },
+];
+
+/// Walk the module, and reject it with `EnclaveError::InvalidWasm` if it uses a forbidden
+/// proposal
+pub fn reject_forbidden_features(wasm: &[u8]) -> Result<(), EnclaveError> {
+    let result = walk_module(wasm, &mut |construct| {
+        let check = FEATURE_CHECKS
+            .iter()
+            .find(|check| (check.is_used_by)(&construct));
+        if let Some(check) = check {
+            warn!("Rejecting contract: {} ({:?})", check.reason, construct);
+            return Err(EnclaveError::InvalidWasm {
+                reason: check.reason,
+            });
+        }
+        Ok(())
+    });
+
+    match result {
+        Ok(()) => Ok(()),
+        Err(Stop::Rejected(err)) => Err(err),
+        Err(Stop::Unreadable) => {
+            debug!("Couldn't walk the whole contract, leaving it for the parser to validate");
+            Ok(())
+        }
+    }
+}
+
+/// The offsets in the module of the `unreachable` instructions of each function the module
+/// defines, in the order of the functions. `None` if the module couldn't be walked.
+pub fn unreachable_offsets(wasm: &[u8]) -> Option<Vec<Vec<usize>>> {
+    let mut offsets: Vec<Vec<usize>> = Vec::new();
+    let result = walk_module(wasm, &mut |construct| {
+        match construct {
+            Construct::FunctionBody(_) => offsets.push(Vec::new()),
+            Construct::Unreachable(offset) => {
+                if let Some(function) = offsets.last_mut() {
+                    function.push(offset);
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    });
+
+    result.ok().map(|()| offsets)
+}
+
+fn uses_floating_point(construct: &Construct) -> bool {
+    match *construct {
+        Construct::ValueType(TYPE_F32) | Construct::ValueType(TYPE_F64) => true,
+        // loads, stores and consts
+        Construct::Instruction(0x2a)
+        | Construct::Instruction(0x2b)
+        | Construct::Instruction(0x38)
+        | Construct::Instruction(0x39)
+        | Construct::Instruction(0x43)
+        | Construct::Instruction(0x44) => true,
+        // comparisons
+        Construct::Instruction(0x5b..=0x66) => true,
+        // arithmetic
+        Construct::Instruction(0x8b..=0xa6) => true,
+        // truncations, conversions, demotions, promotions and reinterpretations
+        Construct::Instruction(0xa8..=0xab) | Construct::Instruction(0xae..=0xbf) => true,
+        // non-trapping truncations
+        Construct::PrefixedInstruction(PREFIX_MISC, 0..=7) => true,
+        _ => false,
+    }
+}
+
+fn uses_simd(construct: &Construct) -> bool {
+    match *construct {
+        Construct::ValueType(TYPE_V128) => true,
+        Construct::PrefixedInstruction(PREFIX_SIMD, _) => true,
+        _ => false,
+    }
+}
+
+fn uses_threads(construct: &Construct) -> bool {
+    match *construct {
+        Construct::MemoryLimits(flags) => flags & LIMITS_SHARED != 0,
+        Construct::PrefixedInstruction(PREFIX_THREADS, _) => true,
+        _ => false,
+    }
+}
+
+fn uses_reference_types(construct: &Construct) -> bool {
+    match *construct {
+        Construct::ValueType(TYPE_FUNCREF) | Construct::ValueType(TYPE_EXTERNREF) => true,
+        Construct::TableElementType(element_type) => element_type != TYPE_FUNCREF,
+        Construct::Table(index) | Construct::CallIndirectTable(index) => index != 0,
+        // typed select, table.get, table.set, ref.null, ref.is_null and ref.func
+        Construct::Instruction(0x1c)
+        | Construct::Instruction(0x25)
+        | Construct::Instruction(0x26)
+        | Construct::Instruction(0xd0..=0xd2) => true,
+        // table.grow, table.size and table.fill
+        Construct::PrefixedInstruction(PREFIX_MISC, 15..=17) => true,
+        _ => false,
+    }
+}
+
+fn uses_bulk_memory(construct: &Construct) -> bool {
+    match *construct {
+        Construct::Section(SECTION_DATA_COUNT) => true,
+        // passive and declarative segments, and segments of expressions
+        Construct::ElementSegment(flags) | Construct::DataSegment(flags) => flags != 0,
+        // memory.init, data.drop, memory.copy, memory.fill, table.init, elem.drop and table.copy
+        Construct::PrefixedInstruction(PREFIX_MISC, 8..=14) => true,
+        _ => false,
+    }
+}
+
+/// A start function runs when the module is instantiated, before the contract is called and its
+/// gas can be metered
+fn uses_start_function(construct: &Construct) -> bool {
+    *construct == Construct::Section(SECTION_START)
+}
+
+/// Why the walk stopped before the end of the module
+enum Stop {
+    Rejected(EnclaveError),
+    Unreadable,
+}
+
+impl From<EnclaveError> for Stop {
+    fn from(err: EnclaveError) -> Self {
+        Stop::Rejected(err)
+    }
+}
+
+type Visit<'a> = dyn FnMut(Construct) -> Result<(), EnclaveError> + 'a;
+
+struct Reader<'a> {
+    data: &'a [u8],
+    /// The offset of `data` in the module
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8], offset: usize) -> Self {
+        Self { data, offset }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn peek_byte(&self) -> Result<u8, Stop> {
+        self.data.first().copied().ok_or(Stop::Unreadable)
+    }
+
+    fn read_byte(&mut self) -> Result<u8, Stop> {
+        let byte = self.peek_byte()?;
+        self.data = &self.data[1..];
+        self.offset += 1;
+        Ok(byte)
+    }
+
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], Stop> {
+        if len > self.data.len() {
+            return Err(Stop::Unreadable);
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    fn read_u32(&mut self) -> Result<u32, Stop> {
+        let mut value = 0_u32;
+        for shift in (0..35).step_by(7) {
+            let byte = self.read_byte()?;
+            value |= u32::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(Stop::Unreadable)
+    }
+
+    /// Skip a signed LEB128 integer of up to 64 bits
+    fn skip_signed(&mut self) -> Result<(), Stop> {
+        for _ in 0..10 {
+            if self.read_byte()? & 0x80 == 0 {
+                return Ok(());
+            }
+        }
+        Err(Stop::Unreadable)
+    }
+
+    /// Split off the next `len` bytes as a reader of their own
+    fn sub_reader(&mut self, len: u32) -> Result<Reader<'a>, Stop> {
+        let offset = self.offset;
+        Ok(Reader::new(self.read_bytes(len as usize)?, offset))
+    }
+
+    /// Skip a name, or any other vector of bytes
+    fn skip_bytes(&mut self) -> Result<(), Stop> {
+        let len = self.read_u32()?;
+        self.read_bytes(len as usize).map(|_| ())
+    }
+}
+
+fn walk_module(wasm: &[u8], visit: &mut Visit) -> Result<(), Stop> {
+    let mut reader = Reader::new(wasm, 0);
+    if reader.read_bytes(WASM_HEADER.len())? != WASM_HEADER {
+        return Err(Stop::Unreadable);
+    }
+
+    let mut tables = 0_u32;
+    let mut functions = 0_u32;
+    while !reader.is_empty() {
+        let id = reader.read_byte()?;
+        let len = reader.read_u32()?;
+        let mut section = reader.sub_reader(len)?;
+        visit(Construct::Section(id))?;
+
+        match id {
+            SECTION_TYPE => walk_vec(&mut section, |section| {
+                if section.read_byte()? != 0x60 {
+                    return Err(Stop::Unreadable);
+                }
+                // parameters, then results
+                walk_vec(section, |section| walk_value_type(section, visit))?;
+                walk_vec(section, |section| walk_value_type(section, visit))
+            })?,
+            SECTION_IMPORT => walk_vec(&mut section, |section| {
+                section.skip_bytes()?;
+                section.skip_bytes()?;
+                match section.read_byte()? {
+                    0x00 => section.read_u32().map(|_| ()),
+                    0x01 => walk_table_type(section, &mut tables, visit),
+                    0x02 => walk_memory_type(section, visit),
+                    0x03 => walk_global_type(section, visit),
+                    _ => Err(Stop::Unreadable),
+                }
+            })?,
+            SECTION_TABLE => walk_vec(&mut section, |section| {
+                walk_table_type(section, &mut tables, visit)
+            })?,
+            SECTION_MEMORY => walk_vec(&mut section, |section| walk_memory_type(section, visit))?,
+            SECTION_GLOBAL => walk_vec(&mut section, |section| {
+                walk_global_type(section, visit)?;
+                walk_expr(section, visit)
+            })?,
+            SECTION_ELEMENT => walk_vec(&mut section, |section| {
+                let flags = section.read_u32()?;
+                visit(Construct::ElementSegment(flags))?;
+                if flags != 0 {
+                    return Err(Stop::Unreadable);
+                }
+                walk_expr(section, visit)?;
+                walk_vec(section, |section| section.read_u32().map(|_| ()))
+            })?,
+            SECTION_CODE => walk_vec(&mut section, |section| {
+                visit(Construct::FunctionBody(functions))?;
+                functions += 1;
+
+                let len = section.read_u32()?;
+                let mut body = section.sub_reader(len)?;
+                walk_vec(&mut body, |body| {
+                    body.read_u32()?;
+                    walk_value_type(body, visit)
+                })?;
+                walk_expr(&mut body, visit)
+            })?,
+            SECTION_DATA => walk_vec(&mut section, |section| {
+                let flags = section.read_u32()?;
+                visit(Construct::DataSegment(flags))?;
+                if flags != 0 {
+                    return Err(Stop::Unreadable);
+                }
+                walk_expr(section, visit)?;
+                section.skip_bytes()
+            })?,
+            // Nothing in the other sections depends on a proposal
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+fn walk_vec<'a, F>(reader: &mut Reader<'a>, mut walk_item: F) -> Result<(), Stop>
+where
+    F: FnMut(&mut Reader<'a>) -> Result<(), Stop>,
+{
+    let count = reader.read_u32()?;
+    for _ in 0..count {
+        walk_item(reader)?;
+    }
+    Ok(())
+}
+
+fn walk_value_type(reader: &mut Reader, visit: &mut Visit) -> Result<(), Stop> {
+    let value_type = reader.read_byte()?;
+    visit(Construct::ValueType(value_type))?;
+    Ok(())
+}
+
+fn walk_table_type(reader: &mut Reader, tables: &mut u32, visit: &mut Visit) -> Result<(), Stop> {
+    visit(Construct::TableElementType(reader.read_byte()?))?;
+    visit(Construct::Table(*tables))?;
+    *tables += 1;
+
+    let flags = reader.read_byte()?;
+    walk_limits(reader, flags)
+}
+
+fn walk_memory_type(reader: &mut Reader, visit: &mut Visit) -> Result<(), Stop> {
+    let flags = reader.read_byte()?;
+    visit(Construct::MemoryLimits(flags))?;
+    walk_limits(reader, flags)
+}
+
+fn walk_limits(reader: &mut Reader, flags: u8) -> Result<(), Stop> {
+    if flags & !(LIMITS_HAS_MAX | LIMITS_SHARED) != 0 {
+        return Err(Stop::Unreadable);
+    }
+    reader.read_u32()?;
+    if flags & LIMITS_HAS_MAX != 0 {
+        reader.read_u32()?;
+    }
+    Ok(())
+}
+
+fn walk_global_type(reader: &mut Reader, visit: &mut Visit) -> Result<(), Stop> {
+    walk_value_type(reader, visit)?;
+    // mutability
+    reader.read_byte()?;
+    Ok(())
+}
+
+/// Walk instructions up to and including the `end` that closes the expression
+fn walk_expr(reader: &mut Reader, visit: &mut Visit) -> Result<(), Stop> {
+    let mut depth = 0_u32;
+    loop {
+        let offset = reader.offset;
+        let opcode = reader.read_byte()?;
+        visit(Construct::Instruction(opcode))?;
+        if opcode == 0x00 {
+            visit(Construct::Unreachable(offset))?;
+        }
+
+        match opcode {
+            // block, loop and if
+            0x02..=0x04 => {
+                depth += 1;
+                walk_block_type(reader, visit)?;
+            }
+            // end
+            0x0b => {
+                if depth == 0 {
+                    return Ok(());
+                }
+                depth -= 1;
+            }
+            // unreachable, nop, else, return, drop and select
+            0x00 | 0x01 | 0x05 | 0x0f | 0x1a | 0x1b => {}
+            // br, br_if, call, local.*, global.*, table.get, table.set and ref.func
+            0x0c | 0x0d | 0x10 | 0x20..=0x26 | 0xd2 => {
+                reader.read_u32()?;
+            }
+            // br_table
+            0x0e => {
+                walk_vec(reader, |reader| reader.read_u32().map(|_| ()))?;
+                reader.read_u32()?;
+            }
+            // call_indirect
+            0x11 => {
+                reader.read_u32()?;
+                visit(Construct::CallIndirectTable(reader.read_u32()?))?;
+            }
+            // typed select
+            0x1c => walk_vec(reader, |reader| walk_value_type(reader, visit))?,
+            // loads and stores: alignment and offset
+            0x28..=0x3e => {
+                reader.read_u32()?;
+                reader.read_u32()?;
+            }
+            // memory.size, memory.grow and ref.null
+            0x3f | 0x40 | 0xd0 => {
+                reader.read_byte()?;
+            }
+            // i32.const and i64.const
+            0x41 | 0x42 => reader.skip_signed()?,
+            // f32.const and f64.const
+            0x43 => {
+                reader.read_bytes(4)?;
+            }
+            0x44 => {
+                reader.read_bytes(8)?;
+            }
+            // numeric instructions, and ref.is_null
+            0x45..=0xc4 | 0xd1 => {}
+            PREFIX_MISC => walk_misc_instruction(reader, visit)?,
+            PREFIX_SIMD | PREFIX_THREADS => {
+                visit(Construct::PrefixedInstruction(opcode, reader.read_u32()?))?;
+                // The immediates of these aren't decoded, as they are all rejected
+                return Err(Stop::Unreadable);
+            }
+            _ => return Err(Stop::Unreadable),
+        }
+    }
+}
+
+fn walk_block_type(reader: &mut Reader, visit: &mut Visit) -> Result<(), Stop> {
+    match reader.peek_byte()? {
+        BLOCK_TYPE_EMPTY => {
+            reader.read_byte()?;
+        }
+        TYPE_I32 | TYPE_I64 | TYPE_F32 | TYPE_F64 | TYPE_V128 | TYPE_FUNCREF | TYPE_EXTERNREF => {
+            walk_value_type(reader, visit)?
+        }
+        // the index of a function type, from the multi-value proposal
+        _ => reader.skip_signed()?,
+    }
+    Ok(())
+}
+
+fn walk_misc_instruction(reader: &mut Reader, visit: &mut Visit) -> Result<(), Stop> {
+    let opcode = reader.read_u32()?;
+    visit(Construct::PrefixedInstruction(PREFIX_MISC, opcode))?;
+
+    match opcode {
+        // non-trapping truncations
+        0..=7 => {}
+        // memory.init
+        8 => {
+            reader.read_u32()?;
+            reader.read_byte()?;
+        }
+        // data.drop, elem.drop, table.grow, table.size and table.fill
+        9 | 13 | 15..=17 => {
+            reader.read_u32()?;
+        }
+        // memory.copy
+        10 => {
+            reader.read_bytes(2)?;
+        }
+        // memory.fill
+        11 => {
+            reader.read_byte()?;
+        }
+        // table.init and table.copy
+        12 | 14 => {
+            reader.read_u32()?;
+            reader.read_u32()?;
+        }
+        _ => return Err(Stop::Unreadable),
+    }
+    Ok(())
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    fn rejection_reason(wasm: &[u8]) -> Option<InvalidWasmReason> {
+        match reject_forbidden_features(wasm) {
+            Ok(()) => None,
+            Err(EnclaveError::InvalidWasm { reason }) => Some(reason),
+            Err(err) => panic!("unexpected error {:?}", err),
+        }
+    }
+
+    pub fn test_fixtures_are_rejected_for_their_feature() {
+        let fixtures: &[(&[u8], InvalidWasmReason)] = &[
+            (
+                include_bytes!("../../testdata/wasm-features/floating_point.wasm"),
+                InvalidWasmReason::FloatingPoint,
+            ),
+            (
+                include_bytes!("../../testdata/wasm-features/simd.wasm"),
+                InvalidWasmReason::Simd,
+            ),
+            (
+                include_bytes!("../../testdata/wasm-features/threads.wasm"),
+                InvalidWasmReason::Threads,
+            ),
+            (
+                include_bytes!("../../testdata/wasm-features/reference_types.wasm"),
+                InvalidWasmReason::ReferenceTypes,
+            ),
+            (
+                include_bytes!("../../testdata/wasm-features/bulk_memory.wasm"),
+                InvalidWasmReason::BulkMemory,
+            ),
+            (
+                include_bytes!("../../testdata/wasm-features/start_function.wasm"),
+                InvalidWasmReason::StartFunction,
+            ),
+        ];
+
+        for (wasm, reason) in fixtures {
+            assert_eq!(rejection_reason(wasm), Some(*reason));
+        }
+
+        // The reason is part of the error that the sender of the contract sees
+        let err =
+            reject_forbidden_features(include_bytes!("../../testdata/wasm-features/simd.wasm"))
+                .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "tried to load invalid wasm code: SIMD instructions and types are not allowed"
+        );
+    }
+
+    pub fn test_valid_module_is_accepted() {
+        assert_eq!(
+            rejection_reason(include_bytes!("../../testdata/wasm-features/valid.wasm")),
+            None
+        );
+    }
+
+    pub fn test_unreadable_module_is_left_to_the_parser() {
+        assert_eq!(rejection_reason(b""), None);
+        assert_eq!(rejection_reason(b"not really wasm"), None);
+        // A code section that ends in the middle of a function
+        assert_eq!(
+            rejection_reason(b"\0asm\x01\0\0\0\x0a\x04\x01\x05\x00\x41"),
+            None
+        );
+    }
+
+    pub fn test_unreachable_offsets() {
+        // (module
+        //   (func)
+        //   (func unreachable)
+        //   (func nop unreachable unreachable))
+        let wasm = b"\0asm\x01\0\0\0\x01\x04\x01\x60\0\0\x03\x04\x03\0\0\0\x0a\x0e\x03\x02\0\x0b\x03\0\0\x0b\x05\0\x01\0\0\x0b";
+        assert_eq!(
+            unreachable_offsets(wasm),
+            Some(vec![vec![], vec![0x1b], vec![0x20, 0x21]])
+        );
+
+        assert_eq!(unreachable_offsets(b"not really wasm"), None);
+    }
+
+    pub fn test_each_check_matches_only_its_feature() {
+        let samples = [
+            (
+                InvalidWasmReason::FloatingPoint,
+                Construct::ValueType(TYPE_F64),
+            ),
+            (
+                InvalidWasmReason::FloatingPoint,
+                Construct::Instruction(0x92),
+            ), // f32.add
+            (
+                InvalidWasmReason::FloatingPoint,
+                Construct::PrefixedInstruction(PREFIX_MISC, 0), // i32.trunc_sat_f32_s
+            ),
+            (InvalidWasmReason::Simd, Construct::ValueType(TYPE_V128)),
+            (
+                InvalidWasmReason::Simd,
+                Construct::PrefixedInstruction(PREFIX_SIMD, 0x0c),
+            ),
+            (
+                InvalidWasmReason::Threads,
+                Construct::MemoryLimits(LIMITS_SHARED | LIMITS_HAS_MAX),
+            ),
+            (
+                InvalidWasmReason::Threads,
+                Construct::PrefixedInstruction(PREFIX_THREADS, 0x03), // atomic.fence
+            ),
+            (InvalidWasmReason::ReferenceTypes, Construct::Table(1)),
+            (
+                InvalidWasmReason::ReferenceTypes,
+                Construct::TableElementType(TYPE_EXTERNREF),
+            ),
+            (
+                InvalidWasmReason::ReferenceTypes,
+                Construct::Instruction(0xd0),
+            ), // ref.null
+            (
+                InvalidWasmReason::ReferenceTypes,
+                Construct::PrefixedInstruction(PREFIX_MISC, 16), // table.size
+            ),
+            (
+                InvalidWasmReason::BulkMemory,
+                Construct::Section(SECTION_DATA_COUNT),
+            ),
+            (InvalidWasmReason::BulkMemory, Construct::DataSegment(1)),
+            (
+                InvalidWasmReason::BulkMemory,
+                Construct::PrefixedInstruction(PREFIX_MISC, 10), // memory.copy
+            ),
+            (
+                InvalidWasmReason::StartFunction,
+                Construct::Section(SECTION_START),
+            ),
+        ];
+
+        for (reason, construct) in samples.iter() {
+            for check in FEATURE_CHECKS {
+                assert_eq!(
+                    (check.is_used_by)(construct),
+                    check.reason == *reason,
+                    "{:?} for {:?}",
+                    construct,
+                    check.reason
+                );
+            }
+        }
+
+        // MVP integer constructs are allowed by every check
+        let allowed = [
+            Construct::Section(SECTION_CODE),
+            Construct::ValueType(TYPE_I32),
+            Construct::ValueType(TYPE_I64),
+            Construct::TableElementType(TYPE_FUNCREF),
+            Construct::Table(0),
+            Construct::MemoryLimits(LIMITS_HAS_MAX),
+            Construct::ElementSegment(0),
+            Construct::DataSegment(0),
+            Construct::Instruction(0x6a), // i32.add
+            Construct::Instruction(0xa7), // i32.wrap_i64
+            Construct::Instruction(0xad), // i64.extend_i32_u
+            Construct::CallIndirectTable(0),
+            Construct::FunctionBody(0),
+            Construct::Unreachable(0),
+        ];
+        for construct in allowed.iter() {
+            assert!(
+                FEATURE_CHECKS
+                    .iter()
+                    .all(|check| !(check.is_used_by)(construct)),
+                "{:?}",
+                construct
+            );
+        }
+    }
+}
@@ -0,0 +1,4 @@
+;; A contract built for a version of the CosmWasm API that isn't supported
+(module
+  (memory (export "memory") 1)
+  (func (export "interface_version_9")))
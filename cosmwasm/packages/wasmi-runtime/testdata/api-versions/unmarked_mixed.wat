@@ -0,0 +1,5 @@
+;; A contract without a version marker that imports functions of both versions
+(module
+  (import "env" "canonicalize_address" (func $canonicalize_address (param i32 i32) (result i32)))
+  (import "env" "abort" (func $abort (param i32)))
+  (memory (export "memory") 1))
@@ -0,0 +1,4 @@
+;; A contract without a version marker that imports a function only CosmWasm 1.0 provides
+(module
+  (import "env" "abort" (func $abort (param i32)))
+  (memory (export "memory") 1))
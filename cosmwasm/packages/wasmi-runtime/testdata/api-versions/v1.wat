@@ -0,0 +1,14 @@
+;; A contract built for CosmWasm 1.0, which converts addresses through the `addr_*` functions and
+;; reports its panics through `abort`. `run` aborts with the message "contract panicked", which is
+;; described by the region at address 0.
+(module
+  (import "env" "db_read" (func $db_read (param i32) (result i32)))
+  (import "env" "addr_canonicalize" (func $addr_canonicalize (param i32 i32) (result i32)))
+  (import "env" "abort" (func $abort (param i32)))
+  (memory (export "memory") 1)
+  ;; offset 16, capacity 17, length 17
+  (data (i32.const 0) "\10\00\00\00\11\00\00\00\11\00\00\00")
+  (data (i32.const 16) "contract panicked")
+  (func (export "interface_version_8"))
+  (func (export "run")
+    (call $abort (i32.const 0))))
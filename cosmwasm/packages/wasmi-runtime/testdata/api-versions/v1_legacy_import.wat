@@ -0,0 +1,5 @@
+;; A contract built for CosmWasm 1.0 that imports a function only CosmWasm 0.10 provides
+(module
+  (import "env" "canonicalize_address" (func $canonicalize_address (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (func (export "interface_version_8")))
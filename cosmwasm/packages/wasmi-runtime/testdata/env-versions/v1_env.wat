@@ -0,0 +1,19 @@
+;; A contract built for CosmWasm 1.0 whose `instantiate` returns the env it was called with, and
+;; whose `execute` returns the message info
+(module
+  (memory (export "memory") 1)
+  (global $next (mut i32) (i32.const 1024))
+  (func (export "interface_version_8"))
+  ;; A bump allocator, which puts the region right before its buffer
+  (func (export "allocate") (param $size i32) (result i32)
+    (local $region i32)
+    (local.set $region (global.get $next))
+    (i32.store (local.get $region) (i32.add (local.get $region) (i32.const 12)))
+    (i32.store offset=4 (local.get $region) (local.get $size))
+    (i32.store offset=8 (local.get $region) (i32.const 0))
+    (global.set $next (i32.add (i32.add (local.get $region) (local.get $size)) (i32.const 12)))
+    (local.get $region))
+  (func (export "instantiate") (param $env i32) (param $info i32) (param $msg i32) (result i32)
+    (local.get $env))
+  (func (export "execute") (param $env i32) (param $info i32) (param $msg i32) (result i32)
+    (local.get $info)))
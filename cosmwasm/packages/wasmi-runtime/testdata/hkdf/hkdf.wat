@@ -0,0 +1,34 @@
+(module
+  ;; fn hkdf_sha256(ikm: *const c_void, salt: *const c_void, info: *const c_void,
+  ;;                output: *mut c_void, output_len: u32) -> i32;
+  (import "env" "hkdf_sha256" (func $hkdf_sha256 (param i32 i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+
+  ;; The output region, with a buffer of 8192 bytes at 0x1000
+  (data (i32.const 0x10) "\00\10\00\00\00\20\00\00\00\00\00\00")
+
+  ;; RFC 5869 A.1 "Basic test case", the regions of ikm, salt and info at 0x20, 0x2c and 0x38
+  (data (i32.const 0x20) "\00\01\00\00\16\00\00\00\16\00\00\00\20\01\00\00\0d\00\00\00\0d\00\00\00\30\01\00\00\0a\00\00\00\0a\00\00\00")
+  (data (i32.const 0x100) "\0b\0b\0b\0b\0b\0b\0b\0b\0b\0b\0b\0b\0b\0b\0b\0b\0b\0b\0b\0b\0b\0b")
+  (data (i32.const 0x120) "\00\01\02\03\04\05\06\07\08\09\0a\0b\0c")
+  (data (i32.const 0x130) "\f0\f1\f2\f3\f4\f5\f6\f7\f8\f9")
+
+  ;; RFC 5869 A.2 "Test with longer inputs/outputs", the regions of ikm, salt and info at 0x50, 0x5c and 0x68
+  (data (i32.const 0x50) "\40\01\00\00\50\00\00\00\50\00\00\00\90\01\00\00\50\00\00\00\50\00\00\00\e0\01\00\00\50\00\00\00\50\00\00\00")
+  (data (i32.const 0x140) "\00\01\02\03\04\05\06\07\08\09\0a\0b\0c\0d\0e\0f\10\11\12\13\14\15\16\17\18\19\1a\1b\1c\1d\1e\1f\20\21\22\23\24\25\26\27\28\29\2a\2b\2c\2d\2e\2f\30\31\32\33\34\35\36\37\38\39\3a\3b\3c\3d\3e\3f\40\41\42\43\44\45\46\47\48\49\4a\4b\4c\4d\4e\4f")
+  (data (i32.const 0x190) "\60\61\62\63\64\65\66\67\68\69\6a\6b\6c\6d\6e\6f\70\71\72\73\74\75\76\77\78\79\7a\7b\7c\7d\7e\7f\80\81\82\83\84\85\86\87\88\89\8a\8b\8c\8d\8e\8f\90\91\92\93\94\95\96\97\98\99\9a\9b\9c\9d\9e\9f\a0\a1\a2\a3\a4\a5\a6\a7\a8\a9\aa\ab\ac\ad\ae\af")
+  (data (i32.const 0x1e0) "\b0\b1\b2\b3\b4\b5\b6\b7\b8\b9\ba\bb\bc\bd\be\bf\c0\c1\c2\c3\c4\c5\c6\c7\c8\c9\ca\cb\cc\cd\ce\cf\d0\d1\d2\d3\d4\d5\d6\d7\d8\d9\da\db\dc\dd\de\df\e0\e1\e2\e3\e4\e5\e6\e7\e8\e9\ea\eb\ec\ed\ee\ef\f0\f1\f2\f3\f4\f5\f6\f7\f8\f9\fa\fb\fc\fd\fe\ff")
+
+  ;; RFC 5869 A.3 "Test with zero-length salt/info", the regions of ikm, salt and info at 0x80, 0x8c and 0x98
+  (data (i32.const 0x80) "\30\02\00\00\16\00\00\00\16\00\00\00\00\00\00\00\00\00\00\00\00\00\00\00\00\00\00\00\00\00\00\00\00\00\00\00")
+  (data (i32.const 0x230) "\0b\0b\0b\0b\0b\0b\0b\0b\0b\0b\0b\0b\0b\0b\0b\0b\0b\0b\0b\0b\0b\0b")
+
+  (func (export "rfc5869_case_1") (param $output_len i32) (result i32)
+    (call $hkdf_sha256
+      (i32.const 0x20) (i32.const 0x2c) (i32.const 0x38) (i32.const 0x10) (local.get $output_len)))
+  (func (export "rfc5869_case_2") (param $output_len i32) (result i32)
+    (call $hkdf_sha256
+      (i32.const 0x50) (i32.const 0x5c) (i32.const 0x68) (i32.const 0x10) (local.get $output_len)))
+  (func (export "rfc5869_case_3") (param $output_len i32) (result i32)
+    (call $hkdf_sha256
+      (i32.const 0x80) (i32.const 0x8c) (i32.const 0x98) (i32.const 0x10) (local.get $output_len))))
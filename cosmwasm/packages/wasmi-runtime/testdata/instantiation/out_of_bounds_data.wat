@@ -0,0 +1,4 @@
+;; A data segment that ends past the end of the memory
+(module
+  (memory (export "memory") 1)
+  (data (i32.const 65535) "hi"))
@@ -0,0 +1,6 @@
+;; An element segment that starts past the end of the table
+(module
+  (memory (export "memory") 1)
+  (table 1 funcref)
+  (elem (i32.const 1) 0)
+  (func))
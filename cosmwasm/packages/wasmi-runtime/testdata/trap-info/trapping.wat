@@ -0,0 +1,7 @@
+(module
+  (import "env" "db_read" (func $db_read (param i32) (result i32)))
+  (func $transfer::execute::h0123456789abcdef (export "handle")
+    nop
+    unreachable)
+  (func
+    unreachable))
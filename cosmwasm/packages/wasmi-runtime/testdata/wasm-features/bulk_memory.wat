@@ -0,0 +1,7 @@
+(module
+  (memory 1)
+  (func
+    i32.const 0
+    i32.const 0
+    i32.const 0
+    memory.fill))
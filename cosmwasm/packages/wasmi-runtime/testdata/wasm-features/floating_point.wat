@@ -0,0 +1,4 @@
+(module
+  (func
+    f32.const 0
+    drop))
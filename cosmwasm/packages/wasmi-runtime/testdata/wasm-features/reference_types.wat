@@ -0,0 +1,3 @@
+(module
+  (table 0 externref)
+  (func))
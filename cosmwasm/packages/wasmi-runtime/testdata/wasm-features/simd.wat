@@ -0,0 +1,4 @@
+(module
+  (func
+    v128.const i64x2 0 0
+    drop))
@@ -0,0 +1,5 @@
+;; Runs a function when the module is instantiated
+(module
+  (memory (export "memory") 1)
+  (start 0)
+  (func))
@@ -0,0 +1,29 @@
+;; Uses only integer MVP features, in every section that is checked for forbidden features
+(module
+  (type $void (func))
+  (type $read (func (param i32) (result i64)))
+  (import "env" "db_read" (func (type $read)))
+  (table 2 funcref)
+  (memory 1 16)
+  (global (mut i32) (i32.const 42))
+  (export "run" (func 1))
+  (elem (i32.const 0) 1 2)
+  (func (type $void))
+  (func (type $read) (local i32)
+    block
+      local.get 1
+      br_table 0 0
+    end
+    i32.const 0
+    i32.load
+    i32.const 0
+    call_indirect (type $read)
+    i64.const 1
+    i64.add
+    drop
+    memory.size
+    drop
+    global.get 0
+    local.set 1
+    i64.const 0)
+  (data (i32.const 0) "hi"))
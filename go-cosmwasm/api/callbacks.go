@@ -10,6 +10,7 @@ typedef GoResult (*read_db_fn)(db_t *ptr, gas_meter_t *gas_meter, uint64_t *used
 typedef GoResult (*write_db_fn)(db_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer key, Buffer val, Buffer *errOut);
 typedef GoResult (*remove_db_fn)(db_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer key, Buffer *errOut);
 typedef GoResult (*scan_db_fn)(db_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer start, Buffer end, int32_t order, GoIter *out, Buffer *errOut);
+typedef GoResult (*prefetch_db_fn)(db_t *ptr, Buffer key, Buffer *val, Buffer *errOut);
 // iterator
 typedef GoResult (*next_db_fn)(iterator_t idx, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer *key, Buffer *val, Buffer *errOut);
 // and api
@@ -22,6 +23,7 @@ GoResult cGet_cgo(db_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer
 GoResult cSet_cgo(db_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer key, Buffer val, Buffer *errOut);
 GoResult cDelete_cgo(db_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer key, Buffer *errOut);
 GoResult cScan_cgo(db_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer start, Buffer end, int32_t order, GoIter *out, Buffer *errOut);
+GoResult cPrefetch_cgo(db_t *ptr, Buffer key, Buffer *val, Buffer *errOut);
 // iterator
 GoResult cNext_cgo(iterator_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer *key, Buffer *val, Buffer *errOut);
 // api
@@ -107,11 +109,19 @@ type KVStore interface {
 	ReverseIterator(start, end []byte) dbm.Iterator
 }
 
+// UnmeteredReader is implemented by stores that can read a key without charging gas for it.
+// The enclave reads ahead the keys it predicts a contract will read, and charges the contract
+// itself when it does. It doesn't read ahead from stores that don't implement this.
+type UnmeteredReader interface {
+	GetUnmetered(key []byte) []byte
+}
+
 var db_vtable = C.DB_vtable{
-	read_db:   (C.read_db_fn)(C.cGet_cgo),
-	write_db:  (C.write_db_fn)(C.cSet_cgo),
-	remove_db: (C.remove_db_fn)(C.cDelete_cgo),
-	scan_db:   (C.scan_db_fn)(C.cScan_cgo),
+	read_db:     (C.read_db_fn)(C.cGet_cgo),
+	write_db:    (C.write_db_fn)(C.cSet_cgo),
+	remove_db:   (C.remove_db_fn)(C.cDelete_cgo),
+	scan_db:     (C.scan_db_fn)(C.cScan_cgo),
+	prefetch_db: (C.prefetch_db_fn)(C.cPrefetch_cgo),
 }
 
 type DBState struct {
@@ -184,6 +194,30 @@ func cGet(ptr *C.db_t, gasMeter *C.gas_meter_t, usedGas *u64, key C.Buffer, val
 	return C.GoResult_Ok
 }
 
+//export cPrefetch
+func cPrefetch(ptr *C.db_t, key C.Buffer, val *C.Buffer, errOut *C.Buffer) (ret C.GoResult) {
+	defer recoverPanic(&ret)
+	if ptr == nil || val == nil {
+		// we received an invalid pointer
+		return C.GoResult_BadArgument
+	}
+
+	kv := *(*KVStore)(unsafe.Pointer(ptr))
+	reader, ok := kv.(UnmeteredReader)
+	if !ok {
+		*errOut = allocateRust([]byte("the store can't read without charging gas"))
+		return C.GoResult_Other
+	}
+
+	// v will equal nil when the key is missing, like in cGet
+	v := reader.GetUnmetered(receiveSlice(key))
+	if v != nil {
+		*val = allocateRust(v)
+	}
+
+	return C.GoResult_Ok
+}
+
 //export cSet
 func cSet(ptr *C.db_t, gasMeter *C.gas_meter_t, usedGas *C.uint64_t, key C.Buffer, val C.Buffer, errOut *C.Buffer) (ret C.GoResult) {
 	defer recoverPanic(&ret)
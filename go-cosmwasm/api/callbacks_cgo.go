@@ -11,6 +11,7 @@ GoResult cSet(db_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer key,
 GoResult cGet(db_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer key, Buffer *val, Buffer *errOut);
 GoResult cDelete(db_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer key, Buffer *errOut);
 GoResult cScan(db_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer start, Buffer end, int32_t order, GoIter *out, Buffer *errOut);
+GoResult cPrefetch(db_t *ptr, Buffer key, Buffer *val, Buffer *errOut);
 // imports (iterator)
 GoResult cNext(iterator_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer *key, Buffer *val, Buffer *errOut);
 // imports (api)
@@ -32,6 +33,9 @@ GoResult cDelete_cgo(db_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buff
 GoResult cScan_cgo(db_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer start, Buffer end, int32_t order, GoIter *out, Buffer *errOut) {
 	return cScan(ptr, gas_meter, used_gas, start, end, order, out, errOut);
 }
+GoResult cPrefetch_cgo(db_t *ptr, Buffer key, Buffer *val, Buffer *errOut) {
+	return cPrefetch(ptr, key, val, errOut);
+}
 
 // Gateway functions (iterator)
 GoResult cNext_cgo(iterator_t *ptr, gas_meter_t *gas_meter, uint64_t *used_gas, Buffer *key, Buffer *val, Buffer *errOut) {
@@ -106,6 +106,10 @@ type KVStore interface {
 	Delete(key []byte)
 }
 
+type UnmeteredReader interface {
+	GetUnmetered(key []byte) []byte
+}
+
 //
 //	// Iterator over a domain of keys in ascending order. End is exclusive.
 //	// Start must be less than end, or the Iterator is invalid.
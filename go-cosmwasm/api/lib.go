@@ -7,6 +7,7 @@ package api
 import "C"
 
 import (
+	"encoding/json"
 	"fmt"
 	"syscall"
 
@@ -37,6 +38,123 @@ func HealthCheck() ([]byte, error) {
 	return receiveVector(res), nil
 }
 
+// HealthCheckDeep checks that the enclave can unseal its keys, encrypt, access storage and load its
+// attestation certificate, and reports which of these work
+func HealthCheckDeep() (*types.HealthCheckDeep, error) {
+	errmsg := C.Buffer{}
+
+	res, err := C.get_health_check_deep(&errmsg)
+	if err != nil {
+		return nil, errorWithMessage(err, errmsg)
+	}
+
+	var health types.HealthCheckDeep
+	err = json.Unmarshal(receiveVector(res), &health)
+	if err != nil {
+		return nil, err
+	}
+	return &health, nil
+}
+
+// VerifyInput checks whether msg, the encrypted message of a tx, decrypts in the enclave, without
+// executing it. txPubKey is the public key the sender encrypted the message with. The plaintext is
+// never returned, only its length and the step that failed if it didn't decrypt
+func VerifyInput(msg []byte, txPubKey []byte) (*types.InputVerification, error) {
+	errmsg := C.Buffer{}
+	msgSlice := sendSlice(msg)
+	defer freeAfterSend(msgSlice)
+	txPubKeySlice := sendSlice(txPubKey)
+	defer freeAfterSend(txPubKeySlice)
+
+	res, err := C.verify_input(msgSlice, txPubKeySlice, &errmsg)
+	if err != nil {
+		return nil, errorWithMessage(err, errmsg)
+	}
+
+	var verification types.InputVerification
+	err = json.Unmarshal(receiveVector(res), &verification)
+	if err != nil {
+		return nil, err
+	}
+	return &verification, nil
+}
+
+// GetRuntimeStats returns information about the memory usage of the enclave
+func GetRuntimeStats() (*types.RuntimeStats, error) {
+	errmsg := C.Buffer{}
+
+	res, err := C.get_runtime_stats(&errmsg)
+	if err != nil {
+		return nil, errorWithMessage(err, errmsg)
+	}
+
+	var stats types.RuntimeStats
+	err = json.Unmarshal(receiveVector(res), &stats)
+	if err != nil {
+		return nil, err
+	}
+	return &stats, nil
+}
+
+// ExportMetrics returns the metrics of the enclave in the Prometheus text format, to be served
+// from the metrics endpoint of the node
+func ExportMetrics() ([]byte, error) {
+	errmsg := C.Buffer{}
+
+	res, err := C.export_metrics(&errmsg)
+	if err != nil {
+		return nil, errorWithMessage(err, errmsg)
+	}
+	return receiveVector(res), nil
+}
+
+// GetEcallTraces returns the traces of the last maxEcalls ecalls the enclave ran while tracing was
+// turned on with ConfigureEnclaveRuntime
+func GetEcallTraces(maxEcalls uint32) (*types.EcallTraces, error) {
+	errmsg := C.Buffer{}
+
+	res, err := C.get_ecall_trace(u32(maxEcalls), &errmsg)
+	if err != nil {
+		return nil, errorWithMessage(err, errmsg)
+	}
+	return types.ParseEcallTraces(receiveVector(res))
+}
+
+// GetEnclaveCapabilities returns the version of the enclave and the optional features it supports
+func GetEnclaveCapabilities() (*types.EnclaveCapabilities, error) {
+	errmsg := C.Buffer{}
+
+	res, err := C.get_enclave_capabilities(&errmsg)
+	if err != nil {
+		return nil, errorWithMessage(err, errmsg)
+	}
+
+	var capabilities types.EnclaveCapabilities
+	err = json.Unmarshal(receiveVector(res), &capabilities)
+	if err != nil {
+		return nil, err
+	}
+	return &capabilities, nil
+}
+
+// GetLastPanicReport returns the last panic in the enclave, or nil if it never panicked since it
+// was started
+func GetLastPanicReport() (*types.PanicReport, error) {
+	errmsg := C.Buffer{}
+
+	res, err := C.get_last_panic_report(&errmsg)
+	if err != nil {
+		return nil, errorWithMessage(err, errmsg)
+	}
+
+	var report *types.PanicReport
+	err = json.Unmarshal(receiveVector(res), &report)
+	if err != nil {
+		return nil, err
+	}
+	return report, nil
+}
+
 func InitBootstrap(spid []byte, apiKey []byte) ([]byte, error) {
 	errmsg := C.Buffer{}
 	spidSlice := sendSlice(spid)
@@ -51,6 +169,25 @@ func InitBootstrap(spid []byte, apiKey []byte) ([]byte, error) {
 	return receiveVector(res), nil
 }
 
+// ConfigureEnclaveRuntime changes the maximum log level of the enclave, without restarting it.
+// Levels are 0 (off) to 5 (trace), and are capped at what the enclave was built with.
+// types.EnclaveLogLevelKeep keeps the level the enclave has.
+// A nonzero defaultQueryGasLimit (in wasm gas) also changes the limit of queries that are sent
+// with a gas limit of 0 or math.MaxUint64.
+// A nonzero maxContractSize changes the largest contract, in bytes, the enclave accepts.
+// A nonzero queryCacheSize changes how many bytes of query results the enclave caches within a
+// block, and math.MaxUint64 turns the cache off.
+// ecallTracing is one of types.EcallTracingKeep, types.EcallTracingOn and types.EcallTracingOff.
+func ConfigureEnclaveRuntime(maxLogLevel uint8, defaultQueryGasLimit uint64, maxContractSize uint64, queryCacheSize uint64, ecallTracing uint8) error {
+	errmsg := C.Buffer{}
+
+	_, err := C.configure_enclave_runtime(u8(maxLogLevel), u64(defaultQueryGasLimit), u64(maxContractSize), u64(queryCacheSize), u8(ecallTracing), &errmsg)
+	if err != nil {
+		return errorWithMessage(err, errmsg)
+	}
+	return nil
+}
+
 func LoadSeedToEnclave(masterCert []byte, seed []byte) (bool, error) {
 	pkSlice := sendSlice(masterCert)
 	defer freeAfterSend(pkSlice)
@@ -65,6 +202,82 @@ func LoadSeedToEnclave(masterCert []byte, seed []byte) (bool, error) {
 	return true, nil
 }
 
+// SubmitNewSeed hands a signed consensus seed rotation to the enclave. The enclave seals the new
+// seed, and uses it for the blocks from the activation height of the rotation on.
+func SubmitNewSeed(rotation []byte) (bool, error) {
+	rotationSlice := sendSlice(rotation)
+	defer freeAfterSend(rotationSlice)
+	errmsg := C.Buffer{}
+
+	_, err := C.submit_new_seed(rotationSlice, &errmsg)
+	if err != nil {
+		return false, errorWithMessage(err, errmsg)
+	}
+	return true, nil
+}
+
+// SubmitBlockHeader hands the header of the next block to the enclave, with the commit and
+// validators that signed it. The enclave verifies the signatures, and keeps the app hash of the
+// header as the trusted state of the chain
+func SubmitBlockHeader(submission types.BlockHeaderSubmission) error {
+	submissionBytes, err := json.Marshal(submission)
+	if err != nil {
+		return err
+	}
+	submissionSlice := sendSlice(submissionBytes)
+	defer freeAfterSend(submissionSlice)
+	errmsg := C.Buffer{}
+
+	_, err = C.submit_block_header(submissionSlice, &errmsg)
+	if err != nil {
+		return errorWithMessage(err, errmsg)
+	}
+	return nil
+}
+
+// TrackForeignChain starts verifying the headers of another chain for contracts, from a header of
+// it that governance approved. It returns what the enclave trusts about the chain, which the chain
+// keeps and passes back with every header of it. Tracking a chain again starts over from the new
+// header
+func TrackForeignChain(init types.ForeignChainInit) ([]byte, error) {
+	initBytes, err := json.Marshal(init)
+	if err != nil {
+		return nil, err
+	}
+	initSlice := sendSlice(initBytes)
+	defer freeAfterSend(initSlice)
+	errmsg := C.Buffer{}
+
+	res, err := C.track_foreign_chain(initSlice, &errmsg)
+	if err != nil {
+		return nil, errorWithMessage(err, errmsg)
+	}
+	return receiveVector(res), nil
+}
+
+// SubmitForeignHeader hands a header of a tracked chain to the enclave, with the commit and
+// validators that signed it, along with what the enclave trusts about the chain as TrackForeignChain
+// or the last SubmitForeignHeader returned it. Once the enclave verified the header, it returns
+// the state to keep instead, against which newer headers of the chain are verified. now is the
+// time of the current block, in seconds
+func SubmitForeignHeader(sealedChain []byte, submission types.ForeignHeader, now uint64) ([]byte, error) {
+	submissionBytes, err := json.Marshal(submission)
+	if err != nil {
+		return nil, err
+	}
+	sealedChainSlice := sendSlice(sealedChain)
+	defer freeAfterSend(sealedChainSlice)
+	submissionSlice := sendSlice(submissionBytes)
+	defer freeAfterSend(submissionSlice)
+	errmsg := C.Buffer{}
+
+	res, err := C.submit_foreign_header(sealedChainSlice, submissionSlice, u64(now), &errmsg)
+	if err != nil {
+		return nil, errorWithMessage(err, errmsg)
+	}
+	return receiveVector(res), nil
+}
+
 type Querier = types.Querier
 
 func InitCache(dataDir string, supportedFeatures string, cacheSize uint64) (Cache, error) {
@@ -107,6 +320,31 @@ func GetCode(cache Cache, code_id []byte) ([]byte, error) {
 	return receiveVector(code), nil
 }
 
+// PrewarmContracts validates the contracts of codeIds and caches their modules in the enclave, so
+// that the first call to each of them doesn't pay for it. The enclave stops after maxBytes of code
+// or maxMillis milliseconds. Returns the status of every code id, in order
+func PrewarmContracts(cache Cache, codeIds [][]byte, maxBytes uint64, maxMillis uint64) ([]string, error) {
+	var ids []byte
+	for _, id := range codeIds {
+		ids = append(ids, id...)
+	}
+	idsSlice := sendSlice(ids)
+	defer freeAfterSend(idsSlice)
+	errmsg := C.Buffer{}
+
+	res, err := C.prewarm_contracts(cache.ptr, idsSlice, u64(maxBytes), u64(maxMillis), &errmsg)
+	if err != nil {
+		return nil, errorWithMessage(err, errmsg)
+	}
+
+	var statuses []string
+	err = json.Unmarshal(receiveVector(res), &statuses)
+	if err != nil {
+		return nil, err
+	}
+	return statuses, nil
+}
+
 func Instantiate(
 	cache Cache,
 	code_id []byte,
@@ -160,6 +398,53 @@ func Handle(
 	querier *Querier,
 	gasLimit uint64,
 	sigInfo []byte,
+	simulate bool,
+) ([]byte, uint64, types.GasBreakdown, error) {
+	id := sendSlice(code_id)
+	defer freeAfterSend(id)
+	p := sendSlice(params)
+	defer freeAfterSend(p)
+	m := sendSlice(msg)
+	defer freeAfterSend(m)
+
+	// set up a new stack frame to handle iterators
+	counter := startContract()
+	defer endContract(counter)
+
+	dbState := buildDBState(store, counter)
+	db := buildDB(&dbState, gasMeter)
+	s := sendSlice(sigInfo)
+	defer freeAfterSend(s)
+	a := buildAPI(api)
+	q := buildQuerier(querier)
+	var gasUsed u64
+	var gasBreakdown C.GasBreakdown
+	errmsg := C.Buffer{}
+
+	res, err := C.handle(cache.ptr, id, p, m, db, a, q, u64(gasLimit), &gasUsed, &errmsg, s, C.bool(simulate), &gasBreakdown)
+	if err != nil && err.(syscall.Errno) != C.ErrnoValue_Success {
+		// Depending on the nature of the error, `gasUsed` will either have a meaningful value, or just 0.
+		return nil, uint64(gasUsed), types.GasBreakdown{}, errorWithMessage(err, errmsg)
+	}
+	breakdown := types.GasBreakdown{
+		Wasm:    uint64(gasBreakdown.wasm),
+		Storage: uint64(gasBreakdown.storage),
+		Ocall:   uint64(gasBreakdown.ocall),
+	}
+	return receiveVector(res), uint64(gasUsed), breakdown, nil
+}
+
+func Migrate(
+	cache Cache,
+	code_id []byte,
+	params []byte,
+	msg []byte,
+	gasMeter *GasMeter,
+	store KVStore,
+	api *GoAPI,
+	querier *Querier,
+	gasLimit uint64,
+	sigInfo []byte,
 ) ([]byte, uint64, error) {
 	id := sendSlice(code_id)
 	defer freeAfterSend(id)
@@ -174,6 +459,7 @@ func Handle(
 
 	dbState := buildDBState(store, counter)
 	db := buildDB(&dbState, gasMeter)
+
 	s := sendSlice(sigInfo)
 	defer freeAfterSend(s)
 	a := buildAPI(api)
@@ -181,7 +467,7 @@ func Handle(
 	var gasUsed u64
 	errmsg := C.Buffer{}
 
-	res, err := C.handle(cache.ptr, id, p, m, db, a, q, u64(gasLimit), &gasUsed, &errmsg, s)
+	res, err := C.migrate(cache.ptr, id, p, m, db, a, q, u64(gasLimit), &gasUsed, &errmsg, s)
 	if err != nil && err.(syscall.Errno) != C.ErrnoValue_Success {
 		// Depending on the nature of the error, `gasUsed` will either have a meaningful value, or just 0.
 		return nil, uint64(gasUsed), errorWithMessage(err, errmsg)
@@ -189,8 +475,47 @@ func Handle(
 	return receiveVector(res), uint64(gasUsed), nil
 }
 
-func Migrate(
+func Reply(
+	cache Cache,
+	code_id []byte,
+	params []byte,
+	msg []byte,
+	gasMeter *GasMeter,
+	store KVStore,
+	api *GoAPI,
+	querier *Querier,
+	gasLimit uint64,
+) ([]byte, uint64, error) {
+	id := sendSlice(code_id)
+	defer freeAfterSend(id)
+	p := sendSlice(params)
+	defer freeAfterSend(p)
+	m := sendSlice(msg)
+	defer freeAfterSend(m)
+
+	// set up a new stack frame to handle iterators
+	counter := startContract()
+	defer endContract(counter)
+
+	dbState := buildDBState(store, counter)
+	db := buildDB(&dbState, gasMeter)
+
+	a := buildAPI(api)
+	q := buildQuerier(querier)
+	var gasUsed u64
+	errmsg := C.Buffer{}
+
+	res, err := C.reply(cache.ptr, id, p, m, db, a, q, u64(gasLimit), &gasUsed, &errmsg)
+	if err != nil && err.(syscall.Errno) != C.ErrnoValue_Success {
+		// Depending on the nature of the error, `gasUsed` will either have a meaningful value, or just 0.
+		return nil, uint64(gasUsed), errorWithMessage(err, errmsg)
+	}
+	return receiveVector(res), uint64(gasUsed), nil
+}
+
+func IBCCall(
 	cache Cache,
+	entryPoint uint8,
 	code_id []byte,
 	params []byte,
 	msg []byte,
@@ -213,12 +538,178 @@ func Migrate(
 
 	dbState := buildDBState(store, counter)
 	db := buildDB(&dbState, gasMeter)
+
+	a := buildAPI(api)
+	q := buildQuerier(querier)
+	var gasUsed u64
+	errmsg := C.Buffer{}
+
+	res, err := C.ibc_call(cache.ptr, C.uint8_t(entryPoint), id, p, m, db, a, q, u64(gasLimit), &gasUsed, &errmsg)
+	if err != nil && err.(syscall.Errno) != C.ErrnoValue_Success {
+		// Depending on the nature of the error, `gasUsed` will either have a meaningful value, or just 0.
+		return nil, uint64(gasUsed), errorWithMessage(err, errmsg)
+	}
+	return receiveVector(res), uint64(gasUsed), nil
+}
+
+// IBCPacketReceive returns the output of the contract and the encrypted acknowledgement of the packet
+func IBCPacketReceive(
+	cache Cache,
+	code_id []byte,
+	params []byte,
+	msg []byte,
+	gasMeter *GasMeter,
+	store KVStore,
+	api *GoAPI,
+	querier *Querier,
+	gasLimit uint64,
+) ([]byte, []byte, uint64, error) {
+	id := sendSlice(code_id)
+	defer freeAfterSend(id)
+	p := sendSlice(params)
+	defer freeAfterSend(p)
+	m := sendSlice(msg)
+	defer freeAfterSend(m)
+
+	// set up a new stack frame to handle iterators
+	counter := startContract()
+	defer endContract(counter)
+
+	dbState := buildDBState(store, counter)
+	db := buildDB(&dbState, gasMeter)
+
+	a := buildAPI(api)
+	q := buildQuerier(querier)
+	var gasUsed u64
+	ack := C.Buffer{}
+	errmsg := C.Buffer{}
+
+	res, err := C.ibc_packet_receive(cache.ptr, id, p, m, db, a, q, u64(gasLimit), &gasUsed, &ack, &errmsg)
+	if err != nil && err.(syscall.Errno) != C.ErrnoValue_Success {
+		// Depending on the nature of the error, `gasUsed` will either have a meaningful value, or just 0.
+		return nil, nil, uint64(gasUsed), errorWithMessage(err, errmsg)
+	}
+	return receiveVector(res), receiveVector(ack), uint64(gasUsed), nil
+}
+
+func RotateContractKey(
+	cache Cache,
+	code_id []byte,
+	params []byte,
+	gasMeter *GasMeter,
+	store KVStore,
+	api *GoAPI,
+	querier *Querier,
+	gasLimit uint64,
+	sigInfo []byte,
+	cursor []byte,
+	chunkSize uint32,
+) ([]byte, uint64, error) {
+	id := sendSlice(code_id)
+	defer freeAfterSend(id)
+	p := sendSlice(params)
+	defer freeAfterSend(p)
+
+	// set up a new stack frame to handle iterators
+	counter := startContract()
+	defer endContract(counter)
+
+	dbState := buildDBState(store, counter)
+	db := buildDB(&dbState, gasMeter)
+
+	s := sendSlice(sigInfo)
+	defer freeAfterSend(s)
+	c := sendSlice(cursor)
+	defer freeAfterSend(c)
+	a := buildAPI(api)
+	q := buildQuerier(querier)
+	var gasUsed u64
+	errmsg := C.Buffer{}
+
+	res, err := C.rotate_contract_key(cache.ptr, id, p, db, a, q, u64(gasLimit), &gasUsed, &errmsg, s, c, C.uint32_t(chunkSize))
+	if err != nil && err.(syscall.Errno) != C.ErrnoValue_Success {
+		// Depending on the nature of the error, `gasUsed` will either have a meaningful value, or just 0.
+		return nil, uint64(gasUsed), errorWithMessage(err, errmsg)
+	}
+	return receiveVector(res), uint64(gasUsed), nil
+}
+
+func ExportContractState(
+	cache Cache,
+	code_id []byte,
+	params []byte,
+	gasMeter *GasMeter,
+	store KVStore,
+	api *GoAPI,
+	querier *Querier,
+	gasLimit uint64,
+	cursor []byte,
+	snapshotNonce []byte,
+	chunkSize uint32,
+) ([]byte, uint64, error) {
+	id := sendSlice(code_id)
+	defer freeAfterSend(id)
+	p := sendSlice(params)
+	defer freeAfterSend(p)
+
+	// set up a new stack frame to handle iterators
+	counter := startContract()
+	defer endContract(counter)
+
+	dbState := buildDBState(store, counter)
+	db := buildDB(&dbState, gasMeter)
+
+	c := sendSlice(cursor)
+	defer freeAfterSend(c)
+	n := sendSlice(snapshotNonce)
+	defer freeAfterSend(n)
+	a := buildAPI(api)
+	q := buildQuerier(querier)
+	var gasUsed u64
+	errmsg := C.Buffer{}
+
+	res, err := C.export_contract_state(cache.ptr, id, p, db, a, q, u64(gasLimit), &gasUsed, &errmsg, c, n, C.uint32_t(chunkSize))
+	if err != nil && err.(syscall.Errno) != C.ErrnoValue_Success {
+		// Depending on the nature of the error, `gasUsed` will either have a meaningful value, or just 0.
+		return nil, uint64(gasUsed), errorWithMessage(err, errmsg)
+	}
+	return receiveVector(res), uint64(gasUsed), nil
+}
+
+func ImportContractState(
+	cache Cache,
+	code_id []byte,
+	params []byte,
+	gasMeter *GasMeter,
+	store KVStore,
+	api *GoAPI,
+	querier *Querier,
+	gasLimit uint64,
+	sigInfo []byte,
+	chunk []byte,
+) ([]byte, uint64, error) {
+	id := sendSlice(code_id)
+	defer freeAfterSend(id)
+	p := sendSlice(params)
+	defer freeAfterSend(p)
+	s := sendSlice(sigInfo)
+	defer freeAfterSend(s)
+	c := sendSlice(chunk)
+	defer freeAfterSend(c)
+
+	// set up a new stack frame to handle iterators
+	counter := startContract()
+	defer endContract(counter)
+
+	dbState := buildDBState(store, counter)
+	db := buildDB(&dbState, gasMeter)
+
 	a := buildAPI(api)
 	q := buildQuerier(querier)
 	var gasUsed u64
 	errmsg := C.Buffer{}
 
-	res, err := C.migrate(cache.ptr, id, p, m, db, a, q, u64(gasLimit), &gasUsed, &errmsg)
+	res, err := C.import_contract_state(cache.ptr, id, p, db, a, q, u64(gasLimit), &gasUsed, &errmsg, s, c)
 	if err != nil && err.(syscall.Errno) != C.ErrnoValue_Success {
 		// Depending on the nature of the error, `gasUsed` will either have a meaningful value, or just 0.
 		return nil, uint64(gasUsed), errorWithMessage(err, errmsg)
@@ -226,6 +717,25 @@ func Migrate(
 	return receiveVector(res), uint64(gasUsed), nil
 }
 
+// UpdateAdmin has the enclave check that the admin of the contract in params is replaced by its
+// current admin, and returns the proof of newAdmin to store with the contract. An empty newAdmin
+// clears the admin
+func UpdateAdmin(params []byte, sigInfo []byte, newAdmin []byte) ([]byte, error) {
+	p := sendSlice(params)
+	defer freeAfterSend(p)
+	s := sendSlice(sigInfo)
+	defer freeAfterSend(s)
+	a := sendSlice(newAdmin)
+	defer freeAfterSend(a)
+	errmsg := C.Buffer{}
+
+	res, err := C.update_admin(p, s, a, &errmsg)
+	if err != nil && err.(syscall.Errno) != C.ErrnoValue_Success {
+		return nil, errorWithMessage(err, errmsg)
+	}
+	return receiveVector(res), nil
+}
+
 func Query(
 	cache Cache,
 	code_id []byte,
@@ -235,11 +745,14 @@ func Query(
 	api *GoAPI,
 	querier *Querier,
 	gasLimit uint64,
+	params []byte,
 ) ([]byte, uint64, error) {
 	id := sendSlice(code_id)
 	defer freeAfterSend(id)
 	m := sendSlice(msg)
 	defer freeAfterSend(m)
+	p := sendSlice(params)
+	defer freeAfterSend(p)
 
 	// set up a new stack frame to handle iterators
 	counter := startContract()
@@ -252,7 +765,7 @@ func Query(
 	var gasUsed u64
 	errmsg := C.Buffer{}
 
-	res, err := C.query(cache.ptr, id, m, db, a, q, u64(gasLimit), &gasUsed, &errmsg)
+	res, err := C.query(cache.ptr, id, m, db, a, q, u64(gasLimit), &gasUsed, &errmsg, p)
 	if err != nil && err.(syscall.Errno) != C.ErrnoValue_Success {
 		// Depending on the nature of the error, `gasUsed` will either have a meaningful value, or just 0.
 		return nil, uint64(gasUsed), errorWithMessage(err, errmsg)
@@ -285,11 +798,69 @@ func CreateAttestationReport(spid []byte, apiKey []byte) (bool, error) {
 	return true, nil
 }
 
-func GetEncryptedSeed(cert []byte) ([]byte, error) {
+// GetAttestationBundle returns the registration public key of the node and the attestation report it
+// registered with, as a protobuf encoded AttestationBundle
+func GetAttestationBundle() ([]byte, error) {
+	errmsg := C.Buffer{}
+
+	res, err := C.get_attestation_bundle(&errmsg)
+	if err != nil {
+		return nil, errorWithMessage(err, errmsg)
+	}
+	return receiveVector(res), nil
+}
+
+// PinDecommissionOperator pins the ed25519 public key of the only operator who may decommission the
+// node. It must be done while the node is set up, before it gets the consensus seed.
+func PinDecommissionOperator(operatorPublicKey []byte) error {
+	errmsg := C.Buffer{}
+	keySlice := sendSlice(operatorPublicKey)
+	defer freeAfterSend(keySlice)
+
+	_, err := C.pin_decommission_operator(keySlice, &errmsg)
+	if err != nil {
+		return errorWithMessage(err, errmsg)
+	}
+	return nil
+}
+
+// DecommissionArm starts decommissioning the node for the operator with the given ed25519 public
+// key, which must be the pinned one. It returns the challenge the operator has to sign, and the public key the record of the
+// erasure will be signed with.
+func DecommissionArm(operatorPublicKey []byte) ([]byte, []byte, error) {
+	errmsg := C.Buffer{}
+	keySlice := sendSlice(operatorPublicKey)
+	defer freeAfterSend(keySlice)
+
+	res, err := C.decommission_arm(keySlice, &errmsg)
+	if err != nil {
+		return nil, nil, errorWithMessage(err, errmsg)
+	}
+	armed := receiveVector(res)
+	return armed[:32], armed[32:], nil
+}
+
+// DecommissionConfirm erases the keys of the node, given the operator's signature of the challenge
+// of DecommissionArm. It returns the signed record of the erasure, as JSON.
+func DecommissionConfirm(signature []byte) ([]byte, error) {
+	errmsg := C.Buffer{}
+	signatureSlice := sendSlice(signature)
+	defer freeAfterSend(signatureSlice)
+
+	res, err := C.decommission_confirm(signatureSlice, &errmsg)
+	if err != nil {
+		return nil, errorWithMessage(err, errmsg)
+	}
+	return receiveVector(res), nil
+}
+
+// GetEncryptedSeed authenticates the node that registers with cert in a block at blockTime, in
+// seconds since the epoch, and returns the seed encrypted for it
+func GetEncryptedSeed(cert []byte, blockTime uint64) ([]byte, error) {
 	errmsg := C.Buffer{}
 	certSlice := sendSlice(cert)
 	defer freeAfterSend(certSlice)
-	res, err := C.get_encrypted_seed(certSlice, &errmsg)
+	res, err := C.get_encrypted_seed(certSlice, u64(blockTime), &errmsg)
 	if err != nil {
 		return nil, errorWithMessage(err, errmsg)
 	}
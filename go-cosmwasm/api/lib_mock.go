@@ -33,6 +33,38 @@ func HealthCheck() ([]byte, error) {
 	return nil, nil
 }
 
+func HealthCheckDeep() (*types.HealthCheckDeep, error) {
+	return nil, nil
+}
+
+func VerifyInput(msg []byte, txPubKey []byte) (*types.InputVerification, error) {
+	return nil, nil
+}
+
+func GetRuntimeStats() (*types.RuntimeStats, error) {
+	return nil, nil
+}
+
+func ExportMetrics() ([]byte, error) {
+	return nil, nil
+}
+
+func GetEcallTraces(maxEcalls uint32) (*types.EcallTraces, error) {
+	return &types.EcallTraces{}, nil
+}
+
+func GetEnclaveCapabilities() (*types.EnclaveCapabilities, error) {
+	return &types.EnclaveCapabilities{}, nil
+}
+
+func GetLastPanicReport() (*types.PanicReport, error) {
+	return nil, nil
+}
+
+func ConfigureEnclaveRuntime(maxLogLevel uint8, defaultQueryGasLimit uint64, maxContractSize uint64, queryCacheSize uint64, ecallTracing uint8) error {
+	return nil
+}
+
 func InitBootstrap() ([]byte, error) {
 	//errmsg := C.Buffer{}
 	//
@@ -58,6 +90,22 @@ func LoadSeedToEnclave(masterCert []byte, seed []byte) (bool, error) {
 	return true, nil
 }
 
+func SubmitNewSeed(rotation []byte) (bool, error) {
+	return true, nil
+}
+
+func SubmitBlockHeader(submission types.BlockHeaderSubmission) error {
+	return nil
+}
+
+func TrackForeignChain(init types.ForeignChainInit) ([]byte, error) {
+	return []byte("{}"), nil
+}
+
+func SubmitForeignHeader(sealedChain []byte, submission types.ForeignHeader, now uint64) ([]byte, error) {
+	return sealedChain, nil
+}
+
 type Querier = types.Querier
 
 func InitCache(dataDir string, supportedFeatures string, cacheSize uint64) (Cache, error) {
@@ -90,6 +138,10 @@ func Create(cache Cache, wasm []byte) ([]byte, error) {
 	return nil, nil
 }
 
+func PrewarmContracts(cache Cache, codeIds [][]byte, maxBytes uint64, maxMillis uint64) ([]string, error) {
+	return nil, nil
+}
+
 func GetCode(cache Cache, code_id []byte) ([]byte, error) {
 	//id := sendSlice(code_id)
 	//defer freeAfterSend(id)
@@ -145,7 +197,8 @@ func Handle(
 	querier *Querier,
 	gasLimit uint64,
 	sigInfo []byte,
-) ([]byte, uint64, error) {
+	simulate bool,
+) ([]byte, uint64, types.GasBreakdown, error) {
 	//id := sendSlice(code_id)
 	//defer freeAfterSend(id)
 	//p := sendSlice(params)
@@ -163,7 +216,7 @@ func Handle(
 	//	return nil, uint64(gasUsed), errorWithMessage(err, errmsg)
 	//}
 	//return receiveVector(res), uint64(gasUsed), nil
-	return nil, 0, nil
+	return nil, 0, types.GasBreakdown{}, nil
 }
 
 func Migrate(
@@ -197,6 +250,100 @@ func Migrate(
 	return nil, 0, nil
 }
 
+func Reply(
+	cache Cache,
+	code_id []byte,
+	params []byte,
+	msg []byte,
+	gasMeter *GasMeter,
+	store KVStore,
+	api *GoAPI,
+	querier *Querier,
+	gasLimit uint64,
+) ([]byte, uint64, error) {
+	return nil, 0, nil
+}
+
+func IBCCall(
+	cache Cache,
+	entryPoint uint8,
+	code_id []byte,
+	params []byte,
+	msg []byte,
+	gasMeter *GasMeter,
+	store KVStore,
+	api *GoAPI,
+	querier *Querier,
+	gasLimit uint64,
+) ([]byte, uint64, error) {
+	return nil, 0, nil
+}
+
+func IBCPacketReceive(
+	cache Cache,
+	code_id []byte,
+	params []byte,
+	msg []byte,
+	gasMeter *GasMeter,
+	store KVStore,
+	api *GoAPI,
+	querier *Querier,
+	gasLimit uint64,
+) ([]byte, []byte, uint64, error) {
+	return nil, nil, 0, nil
+}
+
+func RotateContractKey(
+	cache Cache,
+	code_id []byte,
+	params []byte,
+	gasMeter *GasMeter,
+	store KVStore,
+	api *GoAPI,
+	querier *Querier,
+	gasLimit uint64,
+	sigInfo []byte,
+	cursor []byte,
+	chunkSize uint32,
+) ([]byte, uint64, error) {
+	return nil, 0, nil
+}
+
+func ExportContractState(
+	cache Cache,
+	code_id []byte,
+	params []byte,
+	gasMeter *GasMeter,
+	store KVStore,
+	api *GoAPI,
+	querier *Querier,
+	gasLimit uint64,
+	cursor []byte,
+	snapshotNonce []byte,
+	chunkSize uint32,
+) ([]byte, uint64, error) {
+	return nil, 0, nil
+}
+
+func ImportContractState(
+	cache Cache,
+	code_id []byte,
+	params []byte,
+	gasMeter *GasMeter,
+	store KVStore,
+	api *GoAPI,
+	querier *Querier,
+	gasLimit uint64,
+	sigInfo []byte,
+	chunk []byte,
+) ([]byte, uint64, error) {
+	return nil, 0, nil
+}
+
+func UpdateAdmin(params []byte, sigInfo []byte, newAdmin []byte) ([]byte, error) {
+	return nil, nil
+}
+
 func Query(
 	cache Cache,
 	code_id []byte,
@@ -246,11 +393,27 @@ func CreateAttestationReport() (bool, error) {
 	return true, nil
 }
 
-func GetEncryptedSeed(cert []byte) ([]byte, error) {
+func GetAttestationBundle() ([]byte, error) {
+	return nil, nil
+}
+
+func PinDecommissionOperator(operatorPublicKey []byte) error {
+	return nil
+}
+
+func DecommissionArm(operatorPublicKey []byte) ([]byte, []byte, error) {
+	return nil, nil, nil
+}
+
+func DecommissionConfirm(signature []byte) ([]byte, error) {
+	return nil, nil
+}
+
+func GetEncryptedSeed(cert []byte, blockTime uint64) ([]byte, error) {
 	//errmsg := C.Buffer{}
 	//certSlice := sendSlice(cert)
 	//defer freeAfterSend(certSlice)
-	//res, err := C.get_encrypted_seed(certSlice, &errmsg)
+	//res, err := C.get_encrypted_seed(certSlice, u64(blockTime), &errmsg)
 	//if err != nil {
 	//	return nil, errorWithMessage(err, errmsg)
 	//}
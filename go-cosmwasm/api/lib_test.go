@@ -57,6 +57,27 @@ func TestCreateAndGet(t *testing.T) {
 	require.Equal(t, wasm, code)
 }
 
+func TestPrewarmContracts(t *testing.T) {
+	cache, cleanup := withCache(t)
+	defer cleanup()
+
+	wasm, err := ioutil.ReadFile("./testdata/hackatom.wasm")
+	require.NoError(t, err)
+	id, err := Create(cache, wasm)
+	require.NoError(t, err)
+	unknown := make([]byte, 32)
+
+	statuses, err := PrewarmContracts(cache, [][]byte{id, unknown}, 1<<30, 10000)
+	require.NoError(t, err)
+	require.Len(t, statuses, 2)
+	require.Contains(t, []string{types.PrewarmWarmed, types.PrewarmAlreadyCached}, statuses[0])
+	require.Equal(t, types.PrewarmNotFound, statuses[1])
+
+	statuses, err = PrewarmContracts(cache, [][]byte{id}, 1<<30, 10000)
+	require.NoError(t, err)
+	require.Equal(t, []string{types.PrewarmAlreadyCached}, statuses)
+}
+
 func TestCreateFailsWithBadData(t *testing.T) {
 	cache, cleanup := withCache(t)
 	defer cleanup()
@@ -154,7 +175,7 @@ func TestHandle(t *testing.T) {
 	params, err = json.Marshal(mockEnv("fred"))
 	require.NoError(t, err)
 	start = time.Now()
-	res, cost, err = Handle(cache, id, params, []byte(`{"release":{}}`), &igasMeter2, store, api, &querier, 100000000, nil)
+	res, cost, _, err = Handle(cache, id, params, []byte(`{"release":{}}`), &igasMeter2, store, api, &querier, 100000000, nil, false)
 	diff = time.Now().Sub(start)
 	require.NoError(t, err)
 	assert.Equal(t, uint64(0x19c40), cost)
@@ -211,7 +232,7 @@ func TestHandleCpuLoop(t *testing.T) {
 	params, err = json.Marshal(mockEnv("fred"))
 	require.NoError(t, err)
 	start = time.Now()
-	res, cost, err = Handle(cache, id, params, []byte(`{"cpu_loop":{}}`), &igasMeter2, store, api, &querier, maxGas, nil)
+	res, cost, _, err = Handle(cache, id, params, []byte(`{"cpu_loop":{}}`), &igasMeter2, store, api, &querier, maxGas, nil, false)
 	diff = time.Now().Sub(start)
 	require.Error(t, err)
 	assert.Equal(t, cost, maxGas)
@@ -247,7 +268,7 @@ func TestHandleStorageLoop(t *testing.T) {
 	params, err = json.Marshal(mockEnv("fred"))
 	require.NoError(t, err)
 	start := time.Now()
-	res, cost, err = Handle(cache, id, params, []byte(`{"storage_loop":{}}`), &igasMeter2, store, api, &querier, maxGas, nil)
+	res, cost, _, err = Handle(cache, id, params, []byte(`{"storage_loop":{}}`), &igasMeter2, store, api, &querier, maxGas, nil, false)
 	diff := time.Now().Sub(start)
 	require.Error(t, err)
 	t.Logf("StorageLoop Time (%d gas): %s\n", cost, diff)
@@ -286,7 +307,7 @@ func TestHandleUserErrorsInApiCalls(t *testing.T) {
 	params, err = json.Marshal(mockEnv("fred"))
 	require.NoError(t, err)
 	failingApi := NewMockFailureAPI()
-	res, _, err = Handle(cache, id, params, []byte(`{"user_errors_in_api_calls":{}}`), &igasMeter2, store, failingApi, &querier, maxGas, nil)
+	res, _, _, err = Handle(cache, id, params, []byte(`{"user_errors_in_api_calls":{}}`), &igasMeter2, store, failingApi, &querier, maxGas, nil, false)
 	require.NoError(t, err)
 	requireOkResponse(t, res, 0)
 }
@@ -314,7 +335,7 @@ func TestMigrate(t *testing.T) {
 
 	// verifier is fred
 	query := []byte(`{"verifier":{}}`)
-	data, _, err := Query(cache, id, query, &igasMeter, store, api, &querier, 100000000)
+	data, _, err := Query(cache, id, query, &igasMeter, store, api, &querier, 100000000, nil)
 	require.NoError(t, err)
 	var qres types.QueryResponse
 	err = json.Unmarshal(data, &qres)
@@ -326,11 +347,11 @@ func TestMigrate(t *testing.T) {
 	// we use the same code blob as we are testing hackatom self-migration
 	params, err = json.Marshal(mockEnv("fred"))
 	require.NoError(t, err)
-	res, _, err = Migrate(cache, id, params, []byte(`{"verifier":"alice"}`), &igasMeter, store, api, &querier, 100000000)
+	res, _, err = Migrate(cache, id, params, []byte(`{"verifier":"alice"}`), &igasMeter, store, api, &querier, 100000000, nil)
 	require.NoError(t, err)
 
 	// should update verifier to alice
-	data, _, err = Query(cache, id, query, &igasMeter, store, api, &querier, 100000000)
+	data, _, err = Query(cache, id, query, &igasMeter, store, api, &querier, 100000000, nil)
 	require.NoError(t, err)
 	var qres2 types.QueryResponse
 	err = json.Unmarshal(data, &qres2)
@@ -431,7 +452,7 @@ func exec(t *testing.T, cache Cache, id []byte, signer types.HumanAddress, store
 	igasMeter := GasMeter(gasMeter)
 	params, err := json.Marshal(mockEnv(signer))
 	require.NoError(t, err)
-	res, cost, err := Handle(cache, id, params, []byte(`{"release":{}}`), &igasMeter, store, api, &querier, 100000000, nil)
+	res, cost, _, err := Handle(cache, id, params, []byte(`{"release":{}}`), &igasMeter, store, api, &querier, 100000000, nil, false)
 	require.NoError(t, err)
 	assert.Equal(t, gasExpected, cost)
 
@@ -464,7 +485,7 @@ func TestQuery(t *testing.T) {
 	igasMeter2 := GasMeter(gasMeter2)
 	store.SetGasMeter(gasMeter2)
 	query := []byte(`{"Raw":{"val":"config"}}`)
-	data, _, err := Query(cache, id, query, &igasMeter2, store, api, &querier, 100000000)
+	data, _, err := Query(cache, id, query, &igasMeter2, store, api, &querier, 100000000, nil)
 	require.NoError(t, err)
 	var badResp types.QueryResponse
 	err = json.Unmarshal(data, &badResp)
@@ -481,7 +502,7 @@ func TestQuery(t *testing.T) {
 	igasMeter3 := GasMeter(gasMeter3)
 	store.SetGasMeter(gasMeter3)
 	query = []byte(`{"verifier":{}}`)
-	data, _, err = Query(cache, id, query, &igasMeter3, store, api, &querier, 100000000)
+	data, _, err = Query(cache, id, query, &igasMeter3, store, api, &querier, 100000000, nil)
 	require.NoError(t, err)
 	var qres types.QueryResponse
 	err = json.Unmarshal(data, &qres)
@@ -526,7 +547,7 @@ func TestQueueIterator(t *testing.T) {
 	// query the sum
 	gasMeter4 := NewMockGasMeter(100000000)
 	query := []byte(`{"sum":{}}`)
-	data, _, err := Query(cache, id, query, &gasMeter4, &store, api, &querier, 100000000)
+	data, _, err := Query(cache, id, query, &gasMeter4, &store, api, &querier, 100000000, nil)
 	require.NoError(t, err)
 	var qres types.QueryResponse
 	err = json.Unmarshal(data, &qres)
@@ -537,7 +558,7 @@ func TestQueueIterator(t *testing.T) {
 	// query reduce (multiple iterators at once)
 	gasMeter5 := NewMockGasMeter(100000000)
 	query = []byte(`{"reducer":{}}`)
-	data, _, err = Query(cache, id, query, &gasMeter5, &store, api, &querier, 100000000)
+	data, _, err = Query(cache, id, query, &gasMeter5, &store, api, &querier, 100000000, nil)
 	require.NoError(t, err)
 	var reduced types.QueryResponse
 	err = json.Unmarshal(data, &reduced)
@@ -564,7 +585,7 @@ func TestHackatomQuerier(t *testing.T) {
 	// make a valid query to the other address
 	query := []byte(`{"other_balance":{"address":"foobar"}}`)
 	// TODO The query happens before the contract is initialized. How is this legal?
-	data, _, err := Query(cache, id, query, &igasMeter, store, api, &querier, 100000000)
+	data, _, err := Query(cache, id, query, &igasMeter, store, api, &querier, 100000000, nil)
 	require.NoError(t, err)
 	var qres types.QueryResponse
 	err = json.Unmarshal(data, &qres)
@@ -595,7 +616,7 @@ func TestCustomReflectQuerier(t *testing.T) {
 
 	// make a valid query to the other address
 	query := []byte(`{"reflect_custom":{"text":"small Frys :)"}}`)
-	data, _, err := Query(cache, id, query, &igasMeter, store, api, &querier, 100000000)
+	data, _, err := Query(cache, id, query, &igasMeter, store, api, &querier, 100000000, nil)
 	require.NoError(t, err)
 	var qres types.QueryResponse
 	err = json.Unmarshal(data, &qres)
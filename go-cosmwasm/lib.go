@@ -1,6 +1,7 @@
 package cosmwasm
 
 import (
+	"encoding/binary"
 	"encoding/json"
 	"fmt"
 
@@ -26,11 +27,15 @@ type Querier = types.Querier
 // GasMeter is a read-only version of the sdk gas meter
 type GasMeter = api.GasMeter
 
+// UnmeteredReader is implemented by stores the enclave can read ahead from, see api.UnmeteredReader
+type UnmeteredReader = api.UnmeteredReader
+
 // Wasmer is the main entry point to this library.
 // You should create an instance with it's own subdirectory to manage state inside,
 // and call it for all cosmwasm code related actions.
 type Wasmer struct {
-	cache api.Cache
+	cache        api.Cache
+	capabilities types.EnclaveCapabilities
 }
 
 // NewWasmer creates an new binding, with the given dataDir where
@@ -43,7 +48,17 @@ func NewWasmer(dataDir string, supportedFeatures string, cacheSize uint64) (*Was
 	if err != nil {
 		return nil, err
 	}
-	return &Wasmer{cache: cache}, nil
+	capabilities, err := api.GetEnclaveCapabilities()
+	if err != nil {
+		api.ReleaseCache(cache)
+		return nil, err
+	}
+	return &Wasmer{cache: cache, capabilities: *capabilities}, nil
+}
+
+// EnclaveCapabilities returns the version of the enclave and the optional features it supports
+func (w *Wasmer) EnclaveCapabilities() types.EnclaveCapabilities {
+	return w.capabilities
 }
 
 // Cleanup should be called when no longer using this to free resources on the rust-side
@@ -76,6 +91,25 @@ func (w *Wasmer) GetCode(code CodeID) (WasmCode, error) {
 	return api.GetCode(w.cache, code)
 }
 
+// PrewarmContracts validates the given codes and caches them in the enclave, so that the first call
+// to each of them after the node starts doesn't pay for it. It stops after maxBytes of code or
+// maxMillis milliseconds, and returns the status of every code, in order.
+//
+// This is outside of consensus, codes that fail to prewarm are validated again when they're called
+func (w *Wasmer) PrewarmContracts(codes []CodeID, maxBytes uint64, maxMillis uint64) ([]string, error) {
+	ids := make([][]byte, len(codes))
+	for i, code := range codes {
+		ids[i] = code
+	}
+	return api.PrewarmContracts(w.cache, ids, maxBytes, maxMillis)
+}
+
+// ConfigureEnclaveRuntime changes the settings of the enclave this node runs contracts with, see
+// api.ConfigureEnclaveRuntime
+func (w *Wasmer) ConfigureEnclaveRuntime(maxLogLevel uint8, defaultQueryGasLimit uint64, maxContractSize uint64, queryCacheSize uint64, ecallTracing uint8) error {
+	return api.ConfigureEnclaveRuntime(maxLogLevel, defaultQueryGasLimit, maxContractSize, queryCacheSize, ecallTracing)
+}
+
 // Instantiate will create a new contract based on the given codeID.
 // We can set the initMsg (contract "genesis") here, and it then receives
 // an account and address and can be invoked (Execute) many times.
@@ -84,6 +118,9 @@ func (w *Wasmer) GetCode(code CodeID) (WasmCode, error) {
 //
 // Under the hood, we may recompile the wasm, use a cached native compile, or even use a cached instance
 // for performance.
+//
+// Along with the contract key, the enclave returns the proof of the admin that the sender signed,
+// which has to be passed back in the env whenever the enclave checks the admin.
 func (w *Wasmer) Instantiate(
 	code CodeID,
 	env types.Env,
@@ -94,33 +131,34 @@ func (w *Wasmer) Instantiate(
 	gasMeter GasMeter,
 	gasLimit uint64,
 	sigInfo types.VerificationInfo,
-) (*types.InitResponse, []byte, uint64, error) {
+) (*types.InitResponse, []byte, []byte, uint64, error) {
 	paramBin, err := json.Marshal(env)
 	if err != nil {
-		return nil, nil, 0, err
+		return nil, nil, nil, 0, err
 	}
 
 	sigInfoBin, err := json.Marshal(sigInfo)
 	if err != nil {
-		return nil, nil, 0, err
+		return nil, nil, nil, 0, err
 	}
 
 	data, gasUsed, err := api.Instantiate(w.cache, code, paramBin, initMsg, &gasMeter, store, &goapi, &querier, gasLimit, sigInfoBin)
 	if err != nil {
-		return nil, nil, gasUsed, err
+		return nil, nil, nil, gasUsed, err
 	}
 
 	key := data[0:64]
+	adminProof := data[64:96]
 	var resp types.InitResult
-	err = json.Unmarshal(data[64:], &resp)
+	err = json.Unmarshal(data[96:], &resp)
 	if err != nil {
-		return nil, nil, gasUsed, err
+		return nil, nil, nil, gasUsed, err
 	}
 
 	if resp.Err != nil {
-		return nil, nil, gasUsed, fmt.Errorf("%v", resp.Err)
+		return nil, nil, nil, gasUsed, fmt.Errorf("%v", resp.Err)
 	}
-	return resp.Ok, key, gasUsed, nil
+	return resp.Ok, key, adminProof, gasUsed, nil
 }
 
 // Execute calls a given contract. Since the only difference between contracts with the same CodeID is the
@@ -129,6 +167,11 @@ func (w *Wasmer) Instantiate(
 //
 // The caller is responsible for passing the correct `store` (which must have been initialized exactly once),
 // and setting the env with relevent info on this instance (address, balance, etc)
+//
+// When simulate is set, the contract runs and is charged as usual, but the enclave discards its
+// storage changes instead of writing them to `store`.
+//
+// On success, the returned GasBreakdown shows where the gas of the execution was spent.
 func (w *Wasmer) Execute(
 	code CodeID,
 	env types.Env,
@@ -139,33 +182,203 @@ func (w *Wasmer) Execute(
 	gasMeter GasMeter,
 	gasLimit uint64,
 	sigInfo types.VerificationInfo,
-) (*types.HandleResponse, uint64, error) {
+	simulate bool,
+) (*types.HandleResponse, uint64, types.GasBreakdown, error) {
 	paramBin, err := json.Marshal(env)
 	if err != nil {
-		return nil, 0, err
+		return nil, 0, types.GasBreakdown{}, err
 	}
 	sigInfoBin, err := json.Marshal(sigInfo)
 	if err != nil {
-		return nil, 0, err
+		return nil, 0, types.GasBreakdown{}, err
 	}
 
-	data, gasUsed, err := api.Handle(w.cache, code, paramBin, executeMsg, &gasMeter, store, &goapi, &querier, gasLimit, sigInfoBin)
+	data, gasUsed, gasBreakdown, err := api.Handle(w.cache, code, paramBin, executeMsg, &gasMeter, store, &goapi, &querier, gasLimit, sigInfoBin, simulate)
 	if err != nil {
-		return nil, gasUsed, err
+		return nil, gasUsed, types.GasBreakdown{}, err
 	}
 
 	var resp types.HandleResult
 	err = json.Unmarshal(data, &resp)
 
 	if err != nil {
-		return nil, gasUsed, err
+		return nil, gasUsed, types.GasBreakdown{}, err
 	}
 
 	if resp.Err != nil {
-		return nil, gasUsed, fmt.Errorf("%v", resp.Err)
+		return nil, gasUsed, types.GasBreakdown{}, fmt.Errorf("%v", resp.Err)
 	}
 
-	return resp.Ok, gasUsed, nil
+	return resp.Ok, gasUsed, gasBreakdown, nil
+}
+
+// RotateContractKey re-encrypts up to chunkSize entries of the contract's storage under a new contract key,
+// starting from cursor. An empty cursor starts a new rotation.
+//
+// It returns the new contract key, which the contract must use from now on, and the cursor to continue
+// the rotation from, which is empty once all the storage was re-encrypted.
+func (w *Wasmer) RotateContractKey(
+	code CodeID,
+	env types.Env,
+	store KVStore,
+	goapi GoAPI,
+	querier Querier,
+	gasMeter GasMeter,
+	gasLimit uint64,
+	sigInfo types.VerificationInfo,
+	cursor []byte,
+	chunkSize uint32,
+) ([]byte, []byte, uint64, error) {
+	paramBin, err := json.Marshal(env)
+	if err != nil {
+		return nil, nil, 0, err
+	}
+
+	sigInfoBin, err := json.Marshal(sigInfo)
+	if err != nil {
+		return nil, nil, 0, err
+	}
+
+	data, gasUsed, err := api.RotateContractKey(w.cache, code, paramBin, &gasMeter, store, &goapi, &querier, gasLimit, sigInfoBin, cursor, chunkSize)
+	if err != nil {
+		return nil, nil, gasUsed, err
+	}
+
+	key := data[0:64]
+	nextCursor := data[64:]
+	return key, nextCursor, gasUsed, nil
+}
+
+// ExportContractState exports up to chunkSize entries of the contract's storage, starting from cursor,
+// along with a MAC over every entry that only the enclave can verify. An empty cursor and snapshotNonce
+// start a new export at the height of the block in env, and the following chunks must be exported with
+// the snapshot nonce it returned. The nonce only depends on the contract and that height, so every node
+// exports the same chunks.
+//
+// It returns the snapshot nonce, the chunk and the cursor to continue the export from, which is empty
+// once all the storage was exported.
+func (w *Wasmer) ExportContractState(
+	code CodeID,
+	env types.Env,
+	store KVStore,
+	goapi GoAPI,
+	querier Querier,
+	gasMeter GasMeter,
+	gasLimit uint64,
+	cursor []byte,
+	snapshotNonce []byte,
+	chunkSize uint32,
+) ([]byte, []byte, []byte, uint64, error) {
+	paramBin, err := json.Marshal(env)
+	if err != nil {
+		return nil, nil, nil, 0, err
+	}
+
+	data, gasUsed, err := api.ExportContractState(w.cache, code, paramBin, &gasMeter, store, &goapi, &querier, gasLimit, cursor, snapshotNonce, chunkSize)
+	if err != nil {
+		return nil, nil, nil, gasUsed, err
+	}
+
+	if len(data) < 36 {
+		return nil, nil, nil, gasUsed, fmt.Errorf("export of contract state returned %d bytes", len(data))
+	}
+	nonce := data[0:32]
+	chunkLen := uint64(binary.BigEndian.Uint32(data[32:36]))
+	if uint64(len(data)-36) < chunkLen {
+		return nil, nil, nil, gasUsed, fmt.Errorf("export of contract state returned a truncated chunk")
+	}
+	chunk := data[36 : 36+chunkLen]
+	nextCursor := data[36+chunkLen:]
+	return nonce, chunk, nextCursor, gasUsed, nil
+}
+
+// ImportContractState verifies a chunk that ExportContractState returned for the same contract, and
+// writes its entries back to the contract's storage. The admin of the contract must have signed the
+// import in the tx of sigInfo. It returns how many entries were written.
+func (w *Wasmer) ImportContractState(
+	code CodeID,
+	env types.Env,
+	store KVStore,
+	goapi GoAPI,
+	querier Querier,
+	gasMeter GasMeter,
+	gasLimit uint64,
+	sigInfo types.VerificationInfo,
+	chunk []byte,
+) (uint32, uint64, error) {
+	paramBin, err := json.Marshal(env)
+	if err != nil {
+		return 0, 0, err
+	}
+
+	sigInfoBin, err := json.Marshal(sigInfo)
+	if err != nil {
+		return 0, 0, err
+	}
+
+	data, gasUsed, err := api.ImportContractState(w.cache, code, paramBin, &gasMeter, store, &goapi, &querier, gasLimit, sigInfoBin, chunk)
+	if err != nil {
+		return 0, gasUsed, err
+	}
+
+	if len(data) != 4 {
+		return 0, gasUsed, fmt.Errorf("import of contract state returned %d bytes", len(data))
+	}
+	return binary.BigEndian.Uint32(data), gasUsed, nil
+}
+
+// UpdateAdmin has the enclave check that the admin of a contract, passed in env along with its
+// proof, is replaced by that admin. The returned proof of newAdmin replaces the stored one. A nil
+// newAdmin clears the admin of the contract.
+func (w *Wasmer) UpdateAdmin(env types.Env, sigInfo types.VerificationInfo, newAdmin []byte) ([]byte, error) {
+	paramBin, err := json.Marshal(env)
+	if err != nil {
+		return nil, err
+	}
+
+	sigInfoBin, err := json.Marshal(sigInfo)
+	if err != nil {
+		return nil, err
+	}
+
+	return api.UpdateAdmin(paramBin, sigInfoBin, newAdmin)
+}
+
+// SubmitNewSeed hands a signed rotation of the consensus seed to the enclave, which seals the new
+// seed and uses it from the activation height of the rotation on
+func (w *Wasmer) SubmitNewSeed(rotation []byte) error {
+	_, err := api.SubmitNewSeed(rotation)
+	return err
+}
+
+// ExportMetrics returns the metrics of the enclave in the Prometheus text format
+func (w *Wasmer) ExportMetrics() ([]byte, error) {
+	return api.ExportMetrics()
+}
+
+// EcallTraces returns the traces of the last maxEcalls ecalls the enclave ran while tracing was on
+func (w *Wasmer) EcallTraces(maxEcalls uint32) (*types.EcallTraces, error) {
+	return api.GetEcallTraces(maxEcalls)
+}
+
+// SubmitBlockHeader hands a header of the chain to the enclave, with the commit and validators that
+// signed it. The enclave only accepts the header after the last one it verified
+func (w *Wasmer) SubmitBlockHeader(submission types.BlockHeaderSubmission) error {
+	return api.SubmitBlockHeader(submission)
+}
+
+// TrackForeignChain starts verifying the headers of another chain for contracts, from a header of
+// it that governance approved. It returns what the enclave trusts about the chain, for the chain to
+// keep
+func (w *Wasmer) TrackForeignChain(init types.ForeignChainInit) ([]byte, error) {
+	return api.TrackForeignChain(init)
+}
+
+// SubmitForeignHeader verifies a header of a tracked chain against what the enclave trusts about
+// the chain, and returns the state to keep instead, with the header as the one newer headers are
+// verified against
+func (w *Wasmer) SubmitForeignHeader(sealedChain []byte, submission types.ForeignHeader, now uint64) ([]byte, error) {
+	return api.SubmitForeignHeader(sealedChain, submission, now)
 }
 
 // Query allows a client to execute a contract-specific query. If the result is not empty, it should be
@@ -173,6 +386,7 @@ func (w *Wasmer) Execute(
 // The meaning of path and data can be determined by the code. Path is the suffix of the abci.QueryRequest.Path
 func (w *Wasmer) Query(
 	code CodeID,
+	env types.Env,
 	queryMsg []byte,
 	store KVStore,
 	goapi GoAPI,
@@ -180,7 +394,12 @@ func (w *Wasmer) Query(
 	gasMeter GasMeter,
 	gasLimit uint64,
 ) ([]byte, uint64, error) {
-	data, gasUsed, err := api.Query(w.cache, code, queryMsg, &gasMeter, store, &goapi, &querier, gasLimit)
+	paramBin, err := json.Marshal(env)
+	if err != nil {
+		return nil, 0, err
+	}
+
+	data, gasUsed, err := api.Query(w.cache, code, queryMsg, &gasMeter, store, &goapi, &querier, gasLimit, paramBin)
 	if err != nil {
 		return nil, gasUsed, err
 	}
@@ -196,6 +415,122 @@ func (w *Wasmer) Query(
 	return resp.Ok, gasUsed, nil
 }
 
+// Reply passes the result of a submessage back to the contract that sent it.
+// The reply is a types.SignedReply, with the signature the enclave set on the submessage, so the
+// enclave can verify that the contract really asked for it.
+func (w *Wasmer) Reply(
+	code CodeID,
+	env types.Env,
+	reply []byte,
+	store KVStore,
+	goapi GoAPI,
+	querier Querier,
+	gasMeter GasMeter,
+	gasLimit uint64,
+) (*types.HandleResponse, uint64, error) {
+	if err := w.capabilities.RequireEntryPoint(types.EntryPointReply, "submessages"); err != nil {
+		return nil, 0, err
+	}
+
+	paramBin, err := json.Marshal(env)
+	if err != nil {
+		return nil, 0, err
+	}
+
+	data, gasUsed, err := api.Reply(w.cache, code, paramBin, reply, &gasMeter, store, &goapi, &querier, gasLimit)
+	if err != nil {
+		return nil, gasUsed, err
+	}
+
+	var resp types.HandleResult
+	err = json.Unmarshal(data, &resp)
+	if err != nil {
+		return nil, gasUsed, err
+	}
+	if resp.Err != nil {
+		return nil, gasUsed, fmt.Errorf("%v", resp.Err)
+	}
+	return resp.Ok, gasUsed, nil
+}
+
+// IBCCall calls one of the IBC entry points of a contract that aren't encrypted, like
+// ibc_channel_open. entryPoint is one of the types.IBC* entry point constants, and msg is the
+// JSON encoded types.IBCChannel, types.IBCAcknowledgement or types.IBCPacket that it expects.
+// The contract can't send messages to other contracts from these entry points.
+func (w *Wasmer) IBCCall(
+	code CodeID,
+	entryPoint uint8,
+	env types.Env,
+	msg []byte,
+	store KVStore,
+	goapi GoAPI,
+	querier Querier,
+	gasMeter GasMeter,
+	gasLimit uint64,
+) (*types.HandleResponse, uint64, error) {
+	if err := w.capabilities.RequireEntryPoint(types.EntryPointIBC, "IBC"); err != nil {
+		return nil, 0, err
+	}
+
+	paramBin, err := json.Marshal(env)
+	if err != nil {
+		return nil, 0, err
+	}
+
+	data, gasUsed, err := api.IBCCall(w.cache, entryPoint, code, paramBin, msg, &gasMeter, store, &goapi, &querier, gasLimit)
+	if err != nil {
+		return nil, gasUsed, err
+	}
+
+	var resp types.HandleResult
+	err = json.Unmarshal(data, &resp)
+	if err != nil {
+		return nil, gasUsed, err
+	}
+	if resp.Err != nil {
+		return nil, gasUsed, fmt.Errorf("%v", resp.Err)
+	}
+	return resp.Ok, gasUsed, nil
+}
+
+// IBCPacketReceive passes a packet that was sent to the port of a contract to the contract.
+// packet is the JSON encoded types.IBCPacket. Besides the response of the contract, the
+// acknowledgement of the packet is returned, encrypted to the sender of the packet.
+func (w *Wasmer) IBCPacketReceive(
+	code CodeID,
+	env types.Env,
+	packet []byte,
+	store KVStore,
+	goapi GoAPI,
+	querier Querier,
+	gasMeter GasMeter,
+	gasLimit uint64,
+) (*types.HandleResponse, []byte, uint64, error) {
+	if err := w.capabilities.RequireEntryPoint(types.EntryPointIBC, "IBC"); err != nil {
+		return nil, nil, 0, err
+	}
+
+	paramBin, err := json.Marshal(env)
+	if err != nil {
+		return nil, nil, 0, err
+	}
+
+	data, ack, gasUsed, err := api.IBCPacketReceive(w.cache, code, paramBin, packet, &gasMeter, store, &goapi, &querier, gasLimit)
+	if err != nil {
+		return nil, nil, gasUsed, err
+	}
+
+	var resp types.HandleResult
+	err = json.Unmarshal(data, &resp)
+	if err != nil {
+		return nil, nil, gasUsed, err
+	}
+	if resp.Err != nil {
+		return nil, nil, gasUsed, fmt.Errorf("%v", resp.Err)
+	}
+	return resp.Ok, ack, gasUsed, nil
+}
+
 // Migrate will migrate an existing contract to a new code binary.
 // This takes storage of the data from the original contract and the CodeID of the new contract that should
 // replace it. This allows it to run a migration step if needed, or return an error if unable to migrate
@@ -211,23 +546,35 @@ func (w *Wasmer) Migrate(
 	querier Querier,
 	gasMeter GasMeter,
 	gasLimit uint64,
-) (*types.MigrateResponse, uint64, error) {
+	sigInfo types.VerificationInfo,
+) (*types.MigrateResponse, []byte, uint64, error) {
+	if err := w.capabilities.RequireEntryPoint(types.EntryPointMigrate, "migrations"); err != nil {
+		return nil, nil, 0, err
+	}
+
 	paramBin, err := json.Marshal(env)
 	if err != nil {
-		return nil, 0, err
+		return nil, nil, 0, err
+	}
+
+	sigInfoBin, err := json.Marshal(sigInfo)
+	if err != nil {
+		return nil, nil, 0, err
 	}
-	data, gasUsed, err := api.Migrate(w.cache, code, paramBin, migrateMsg, &gasMeter, store, &goapi, &querier, gasLimit)
+
+	data, gasUsed, err := api.Migrate(w.cache, code, paramBin, migrateMsg, &gasMeter, store, &goapi, &querier, gasLimit, sigInfoBin)
 	if err != nil {
-		return nil, gasUsed, err
+		return nil, nil, gasUsed, err
 	}
 
+	key := data[0:64]
 	var resp types.MigrateResult
-	err = json.Unmarshal(data, &resp)
+	err = json.Unmarshal(data[64:], &resp)
 	if err != nil {
-		return nil, gasUsed, err
+		return nil, nil, gasUsed, err
 	}
 	if resp.Err != nil {
-		return nil, gasUsed, fmt.Errorf("%v", resp.Err)
+		return nil, nil, gasUsed, fmt.Errorf("%v", resp.Err)
 	}
-	return resp.Ok, gasUsed, nil
+	return resp.Ok, key, gasUsed, nil
 }
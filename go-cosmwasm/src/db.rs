@@ -39,6 +39,8 @@ pub struct DB_vtable {
         *mut GoIter,
         *mut Buffer,
     ) -> i32,
+    // like read_db, but without charging gas
+    pub prefetch_db: extern "C" fn(*mut db_t, Buffer, *mut Buffer, *mut Buffer) -> i32,
 }
 
 #[repr(C)]
@@ -89,6 +91,40 @@ impl Storage for DB {
         (Ok(value), gas_info)
     }
 
+    fn get_unmetered(&self, key: &[u8]) -> FfiResult<Option<Vec<u8>>> {
+        let key_buf = Buffer::from_vec(key.to_vec());
+        let mut result_buf = Buffer::default();
+        let mut err = Buffer::default();
+        let go_result: GoResult = (self.vtable.prefetch_db)(
+            self.state,
+            key_buf,
+            &mut result_buf as *mut Buffer,
+            &mut err as *mut Buffer,
+        )
+        .into();
+        let gas_info = GasInfo::free();
+        let _key = unsafe { key_buf.consume() };
+
+        let default = || {
+            format!(
+                "Failed to read ahead a key in the db: {}",
+                String::from_utf8_lossy(key)
+            )
+        };
+        unsafe {
+            if let Err(err) = go_result.into_ffi_result(err, default) {
+                return (Err(err), gas_info);
+            }
+        }
+
+        let value = if result_buf.ptr.is_null() {
+            None
+        } else {
+            Some(unsafe { result_buf.consume() })
+        };
+        (Ok(value), gas_info)
+    }
+
     /// Allows iteration over a set of key/value pairs, either forwards or backwards.
     ///
     /// The bound `start` is inclusive and `end` is exclusive.
@@ -20,13 +20,24 @@ use crate::error::{clear_error, handle_c_error, set_error, Error};
 
 use cosmwasm_sgx_vm::untrusted_init_bootstrap;
 use cosmwasm_sgx_vm::{
-    call_handle_raw, call_init_raw, call_migrate_raw, call_query_raw, features_from_csv, Checksum,
-    CosmCache, Extern,
+    call_export_contract_state_raw, call_handle_raw, call_handle_simulate_raw,
+    call_ibc_packet_receive_raw, call_ibc_raw, call_import_contract_state_raw, call_init_raw,
+    call_migrate_raw, call_query_raw, call_reply_raw, call_rotate_contract_key_raw,
+    features_from_csv, update_admin as call_update_admin, Checksum, CosmCache, Extern,
+    IbcEntryPoint,
 };
 use cosmwasm_sgx_vm::{
-    create_attestation_report_u, untrusted_get_encrypted_seed, untrusted_health_check,
-    untrusted_init_node, untrusted_key_gen,
+    create_attestation_report_u, untrusted_configure_runtime, untrusted_decommission_arm,
+    untrusted_decommission_confirm, untrusted_export_metrics, untrusted_get_attestation_bundle,
+    untrusted_get_capabilities, untrusted_get_encrypted_seed, untrusted_get_last_panic_report,
+    untrusted_get_runtime_stats, untrusted_get_trace, untrusted_health_check,
+    untrusted_health_check_deep, untrusted_init_node, untrusted_key_gen,
+    untrusted_pin_decommission_operator, untrusted_prewarm_contracts,
+    untrusted_submit_block_header, untrusted_submit_foreign_header, untrusted_submit_new_seed,
+    untrusted_track_foreign_chain, untrusted_verify_input, HealthCheckDeepResult,
+    InputVerificationStep,
 };
+use cosmwasm_std::Binary;
 
 use ctor::ctor;
 use log::*;
@@ -39,6 +50,14 @@ fn init_logger() {
 #[repr(C)]
 pub struct cache_t {}
 
+/// Where the gas used by a successful execution was spent, see `handle`
+#[repr(C)]
+pub struct GasBreakdown {
+    pub wasm: u64,
+    pub storage: u64,
+    pub ocall: u64,
+}
+
 fn to_cache(ptr: *mut cache_t) -> Option<&'static mut CosmCache<DB, GoApi, GoQuerier>> {
     if ptr.is_null() {
         None
@@ -48,6 +67,16 @@ fn to_cache(ptr: *mut cache_t) -> Option<&'static mut CosmCache<DB, GoApi, GoQue
     }
 }
 
+/// Like `to_cache`, for calls that may run concurrently, like queries
+fn to_shared_cache(ptr: *mut cache_t) -> Option<&'static CosmCache<DB, GoApi, GoQuerier>> {
+    if ptr.is_null() {
+        None
+    } else {
+        let c = unsafe { &*(ptr as *const CosmCache<DB, GoApi, GoQuerier>) };
+        Some(c)
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn get_health_check(err: Option<&mut Buffer>) -> Buffer {
     match untrusted_health_check() {
@@ -63,7 +92,206 @@ pub extern "C" fn get_health_check(err: Option<&mut Buffer>) -> Buffer {
 }
 
 #[no_mangle]
-pub extern "C" fn get_encrypted_seed(cert: Buffer, err: Option<&mut Buffer>) -> Buffer {
+pub extern "C" fn get_health_check_deep(err: Option<&mut Buffer>) -> Buffer {
+    match untrusted_health_check_deep() {
+        Err(e) => {
+            set_error(Error::enclave_err(e.to_string()), err);
+            Buffer::default()
+        }
+        Ok(health) => {
+            clear_error();
+            let passed = |subsystem: u32| health.passed & subsystem != 0;
+            let health = serde_json::json!({
+                "seed": passed(HealthCheckDeepResult::SEED),
+                "encryption": passed(HealthCheckDeepResult::ENCRYPTION),
+                "storage": passed(HealthCheckDeepResult::STORAGE),
+                "registration_cert": passed(HealthCheckDeepResult::REGISTRATION_CERT),
+                "seed_status": health.seed_status.to_string(),
+            });
+            Buffer::from_vec(health.to_string().into_bytes())
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn verify_input(
+    msg: Buffer,
+    tx_public_key: Buffer,
+    err: Option<&mut Buffer>,
+) -> Buffer {
+    let msg_slice = match unsafe { msg.read() } {
+        None => {
+            set_error(Error::empty_arg("msg"), err);
+            return Buffer::default();
+        }
+        Some(r) => r,
+    };
+
+    let tx_public_key_slice = match unsafe { tx_public_key.read() } {
+        None => {
+            set_error(Error::empty_arg("tx_public_key"), err);
+            return Buffer::default();
+        }
+        Some(r) => r,
+    };
+
+    match untrusted_verify_input(msg_slice, tx_public_key_slice) {
+        Err(e) => {
+            set_error(Error::enclave_err(e.to_string()), err);
+            Buffer::default()
+        }
+        Ok(result) => {
+            clear_error();
+            let failed_step = match result.step {
+                InputVerificationStep::Decrypted => "",
+                InputVerificationStep::Parse => "parse",
+                InputVerificationStep::PublicKey => "public_key",
+                InputVerificationStep::KeyDerivation => "key_derivation",
+                InputVerificationStep::Decryption => "decryption",
+            };
+            let result = serde_json::json!({
+                "decrypted": result.step == InputVerificationStep::Decrypted,
+                "plaintext_len": result.plaintext_len,
+                "failed_step": failed_step,
+                "error": match result.step {
+                    InputVerificationStep::Decrypted => String::new(),
+                    step => step.to_string(),
+                },
+            });
+            Buffer::from_vec(result.to_string().into_bytes())
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn get_runtime_stats(err: Option<&mut Buffer>) -> Buffer {
+    match untrusted_get_runtime_stats() {
+        Err(e) => {
+            set_error(Error::enclave_err(e.to_string()), err);
+            Buffer::default()
+        }
+        Ok(stats) => {
+            clear_error();
+            let stats = serde_json::json!({
+                "allocated_bytes": stats.allocated_bytes,
+                "peak_allocated_bytes": stats.peak_allocated_bytes,
+                "safety_buffer_chunks": stats.safety_buffer_chunks,
+                "safety_buffer_target_chunks": stats.safety_buffer_target_chunks,
+                "allocate_stack_len": stats.allocate_stack_len,
+                "panics_caught": stats.panics_caught,
+                "module_cache_misses": stats.module_cache_misses,
+                "prefetch_hits": stats.prefetch_hits,
+                "prefetch_misses": stats.prefetch_misses,
+            });
+            Buffer::from_vec(stats.to_string().into_bytes())
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn export_metrics(err: Option<&mut Buffer>) -> Buffer {
+    match untrusted_export_metrics() {
+        Err(e) => {
+            set_error(Error::enclave_err(e.to_string()), err);
+            Buffer::default()
+        }
+        Ok(metrics) => {
+            clear_error();
+            Buffer::from_vec(metrics.into_bytes())
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn get_ecall_trace(max_ecalls: u32, err: Option<&mut Buffer>) -> Buffer {
+    match untrusted_get_trace(max_ecalls) {
+        Err(e) => {
+            set_error(Error::enclave_err(e.to_string()), err);
+            Buffer::default()
+        }
+        Ok(trace) => {
+            clear_error();
+            Buffer::from_vec(trace)
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn get_enclave_capabilities(err: Option<&mut Buffer>) -> Buffer {
+    match untrusted_get_capabilities() {
+        Err(e) => {
+            set_error(Error::enclave_err(e.to_string()), err);
+            Buffer::default()
+        }
+        Ok(capabilities) => {
+            clear_error();
+            let capabilities = serde_json::json!({
+                "version_major": capabilities.version_major,
+                "version_minor": capabilities.version_minor,
+                "version_patch": capabilities.version_patch,
+                "cosmwasm_api_min": capabilities.cosmwasm_api_min,
+                "cosmwasm_api_max": capabilities.cosmwasm_api_max,
+                "entry_points": capabilities.entry_points,
+                "host_functions": capabilities.host_functions,
+            });
+            Buffer::from_vec(capabilities.to_string().into_bytes())
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn get_last_panic_report(err: Option<&mut Buffer>) -> Buffer {
+    match untrusted_get_last_panic_report() {
+        Err(e) => {
+            set_error(Error::enclave_err(e.to_string()), err);
+            Buffer::default()
+        }
+        Ok(report) => {
+            clear_error();
+            let report = report.map(|report| {
+                serde_json::json!({
+                    "summary": report.summary,
+                    "sealed_report": Binary(report.sealed_report),
+                })
+            });
+            Buffer::from_vec(serde_json::json!(report).to_string().into_bytes())
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn configure_enclave_runtime(
+    max_log_level: u8,
+    default_query_gas_limit: u64,
+    max_contract_size: u64,
+    query_cache_size: u64,
+    ecall_tracing: u8,
+    err: Option<&mut Buffer>,
+) -> bool {
+    match untrusted_configure_runtime(
+        max_log_level,
+        default_query_gas_limit,
+        max_contract_size,
+        query_cache_size,
+        ecall_tracing,
+    ) {
+        Err(e) => {
+            set_error(Error::enclave_err(e.to_string()), err);
+            false
+        }
+        Ok(()) => {
+            clear_error();
+            true
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn get_encrypted_seed(
+    cert: Buffer,
+    block_time: u64,
+    err: Option<&mut Buffer>,
+) -> Buffer {
     trace!("Called get_encrypted_seed");
     let cert_slice = match unsafe { cert.read() } {
         None => {
@@ -73,7 +301,7 @@ pub extern "C" fn get_encrypted_seed(cert: Buffer, err: Option<&mut Buffer>) ->
         Some(r) => r,
     };
     trace!("Hello from right before untrusted_get_encrypted_seed");
-    match untrusted_get_encrypted_seed(cert_slice) {
+    match untrusted_get_encrypted_seed(cert_slice, block_time) {
         Err(e) => {
             // An error happened in the SGX sdk.
             set_error(Error::enclave_err(e.to_string()), err);
@@ -160,6 +388,106 @@ pub extern "C" fn init_node(
     }
 }
 
+#[no_mangle]
+pub extern "C" fn submit_new_seed(rotation: Buffer, err: Option<&mut Buffer>) -> bool {
+    let rotation_slice = match unsafe { rotation.read() } {
+        None => {
+            set_error(Error::empty_arg("rotation"), err);
+            return false;
+        }
+        Some(r) => r,
+    };
+
+    match untrusted_submit_new_seed(rotation_slice) {
+        Ok(_) => {
+            clear_error();
+            true
+        }
+        Err(e) => {
+            set_error(Error::enclave_err(e.to_string()), err);
+            false
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn submit_block_header(submission: Buffer, err: Option<&mut Buffer>) -> bool {
+    let submission_slice = match unsafe { submission.read() } {
+        None => {
+            set_error(Error::empty_arg("submission"), err);
+            return false;
+        }
+        Some(r) => r,
+    };
+
+    match untrusted_submit_block_header(submission_slice) {
+        Ok(()) => {
+            clear_error();
+            true
+        }
+        Err(e) => {
+            set_error(Error::enclave_err(e.to_string()), err);
+            false
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn track_foreign_chain(init: Buffer, err: Option<&mut Buffer>) -> Buffer {
+    let init_slice = match unsafe { init.read() } {
+        None => {
+            set_error(Error::empty_arg("init"), err);
+            return Buffer::default();
+        }
+        Some(r) => r,
+    };
+
+    match untrusted_track_foreign_chain(init_slice) {
+        Err(e) => {
+            set_error(Error::enclave_err(e.to_string()), err);
+            Buffer::default()
+        }
+        Ok(sealed_chain) => {
+            clear_error();
+            Buffer::from_vec(sealed_chain)
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn submit_foreign_header(
+    sealed_chain: Buffer,
+    submission: Buffer,
+    now: u64,
+    err: Option<&mut Buffer>,
+) -> Buffer {
+    let sealed_chain_slice = match unsafe { sealed_chain.read() } {
+        None => {
+            set_error(Error::empty_arg("sealed_chain"), err);
+            return Buffer::default();
+        }
+        Some(r) => r,
+    };
+    let submission_slice = match unsafe { submission.read() } {
+        None => {
+            set_error(Error::empty_arg("submission"), err);
+            return Buffer::default();
+        }
+        Some(r) => r,
+    };
+
+    match untrusted_submit_foreign_header(sealed_chain_slice, submission_slice, now) {
+        Err(e) => {
+            set_error(Error::enclave_err(e.to_string()), err);
+            Buffer::default()
+        }
+        Ok(new_sealed_chain) => {
+            clear_error();
+            Buffer::from_vec(new_sealed_chain)
+        }
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn create_attestation_report(
     spid: Buffer,
@@ -190,6 +518,94 @@ pub extern "C" fn create_attestation_report(
     true
 }
 
+#[no_mangle]
+pub extern "C" fn get_attestation_bundle(err: Option<&mut Buffer>) -> Buffer {
+    match untrusted_get_attestation_bundle() {
+        Err(e) => {
+            set_error(Error::enclave_err(e.to_string()), err);
+            Buffer::default()
+        }
+        Ok(bundle) => {
+            clear_error();
+            Buffer::from_vec(bundle)
+        }
+    }
+}
+
+/// Returns the challenge the operator has to sign, followed by the public key the record of the
+/// erasure will be signed with
+#[no_mangle]
+pub extern "C" fn decommission_arm(
+    operator_public_key: Buffer,
+    err: Option<&mut Buffer>,
+) -> Buffer {
+    let operator_public_key = match unsafe { operator_public_key.read() } {
+        None => {
+            set_error(Error::empty_arg("operator_public_key"), err);
+            return Buffer::default();
+        }
+        Some(r) => r,
+    };
+
+    match untrusted_decommission_arm(operator_public_key) {
+        Err(e) => {
+            set_error(Error::enclave_err(e.to_string()), err);
+            Buffer::default()
+        }
+        Ok((challenge, erasure_public_key)) => {
+            clear_error();
+            Buffer::from_vec([challenge, erasure_public_key].concat())
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn pin_decommission_operator(
+    operator_public_key: Buffer,
+    err: Option<&mut Buffer>,
+) -> bool {
+    let operator_public_key = match unsafe { operator_public_key.read() } {
+        None => {
+            set_error(Error::empty_arg("operator_public_key"), err);
+            return false;
+        }
+        Some(r) => r,
+    };
+
+    match untrusted_pin_decommission_operator(operator_public_key) {
+        Err(e) => {
+            set_error(Error::enclave_err(e.to_string()), err);
+            false
+        }
+        Ok(()) => {
+            clear_error();
+            true
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn decommission_confirm(signature: Buffer, err: Option<&mut Buffer>) -> Buffer {
+    let signature = match unsafe { signature.read() } {
+        None => {
+            set_error(Error::empty_arg("signature"), err);
+            return Buffer::default();
+        }
+        Some(r) => r,
+    };
+
+    match untrusted_decommission_confirm(signature) {
+        Err(e) => {
+            set_error(Error::enclave_err(e.to_string()), err);
+            Buffer::default()
+        }
+        Ok(erasure_record) => {
+            clear_error();
+            Buffer::from_vec(erasure_record)
+        }
+    }
+}
+
 fn to_extern(storage: DB, api: GoApi, querier: GoQuerier) -> Extern<DB, GoApi, GoQuerier> {
     Extern {
         storage,
@@ -226,10 +642,13 @@ static FEATURES_ARG: &str = "supported_features";
 static CACHE_ARG: &str = "cache";
 static WASM_ARG: &str = "wasm";
 static CODE_ID_ARG: &str = "code_id";
+static CODE_IDS_ARG: &str = "code_ids";
 static MSG_ARG: &str = "msg";
 static PARAMS_ARG: &str = "params";
 static GAS_USED_ARG: &str = "gas_used";
 static SIG_INFO_ARG: &str = "sig_info";
+static ACKNOWLEDGEMENT_ARG: &str = "acknowledgement";
+static CHUNK_ARG: &str = "chunk";
 
 fn do_init_cache(
     data_dir: Buffer,
@@ -297,17 +716,97 @@ fn do_get_code(cache: &mut CosmCache<DB, GoApi, GoQuerier>, id: Buffer) -> Resul
     Ok(wasm)
 }
 
+/// Validate the contracts in `code_ids`, a concatenation of 32 byte code ids, and cache their
+/// modules in the enclave, within `max_bytes` of code and `max_millis` milliseconds. Returns a JSON
+/// list with the status of every code id, in order. Code ids that aren't stored are "not found".
 #[no_mangle]
-pub extern "C" fn instantiate(
+pub extern "C" fn prewarm_contracts(
     cache: *mut cache_t,
-    contract_id: Buffer,
-    params: Buffer,
-    msg: Buffer,
-    db: DB,
-    api: GoApi,
-    querier: GoQuerier,
-    gas_limit: u64,
-    gas_used: Option<&mut u64>,
+    code_ids: Buffer,
+    max_bytes: u64,
+    max_millis: u64,
+    err: Option<&mut Buffer>,
+) -> Buffer {
+    let r = match to_cache(cache) {
+        Some(c) => catch_unwind(AssertUnwindSafe(move || {
+            do_prewarm_contracts(c, code_ids, max_bytes, max_millis)
+        }))
+        .unwrap_or_else(|_| Err(Error::panic())),
+        None => Err(Error::empty_arg(CACHE_ARG)),
+    };
+    let data = handle_c_error(r, err);
+    Buffer::from_vec(data)
+}
+
+fn do_prewarm_contracts(
+    cache: &mut CosmCache<DB, GoApi, GoQuerier>,
+    code_ids: Buffer,
+    max_bytes: u64,
+    max_millis: u64,
+) -> Result<Vec<u8>, Error> {
+    let code_ids = unsafe { code_ids.read() }.ok_or_else(|| Error::empty_arg(CODE_IDS_ARG))?;
+    if code_ids.len() % 32 != 0 {
+        return Err(Error::vm_err(format!(
+            "{} must be a list of 32 byte code ids, got {} bytes",
+            CODE_IDS_ARG,
+            code_ids.len()
+        )));
+    }
+
+    // Code ids that aren't stored are left out of the ecall, and reported as not found
+    let mut stored = vec![];
+    let mut wasms = vec![];
+    for code_id in code_ids.chunks_exact(32) {
+        let id: Checksum = code_id.try_into()?;
+        match cache.load_wasm(&id) {
+            Ok(wasm) => {
+                let mut code_hash = [0u8; 32];
+                code_hash.copy_from_slice(code_id);
+                stored.push(true);
+                wasms.push((code_hash, wasm));
+            }
+            Err(e) => {
+                debug!("Not prewarming code id {}: {}", id.to_hex(), e);
+                stored.push(false);
+            }
+        }
+    }
+
+    let contracts: Vec<([u8; 32], &[u8])> = wasms
+        .iter()
+        .map(|(code_hash, wasm)| (*code_hash, wasm.as_slice()))
+        .collect();
+    let mut statuses = untrusted_prewarm_contracts(&contracts, max_bytes, max_millis)
+        .map_err(|e| Error::enclave_err(e.to_string()))?
+        .into_iter();
+
+    let statuses: Vec<String> = stored
+        .into_iter()
+        .map(|stored| {
+            if stored {
+                statuses
+                    .next()
+                    .map(|status| status.to_string())
+                    .unwrap_or_default()
+            } else {
+                "not found".to_string()
+            }
+        })
+        .collect();
+    Ok(serde_json::to_vec(&statuses).unwrap_or_default())
+}
+
+#[no_mangle]
+pub extern "C" fn instantiate(
+    cache: *mut cache_t,
+    contract_id: Buffer,
+    params: Buffer,
+    msg: Buffer,
+    db: DB,
+    api: GoApi,
+    querier: GoQuerier,
+    gas_limit: u64,
+    gas_used: Option<&mut u64>,
     err: Option<&mut Buffer>,
     sig_info: Buffer,
 ) -> Buffer {
@@ -375,11 +874,24 @@ pub extern "C" fn handle(
     gas_used: Option<&mut u64>,
     err: Option<&mut Buffer>,
     sig_info: Buffer,
+    simulate: bool,
+    gas_breakdown: Option<&mut GasBreakdown>,
 ) -> Buffer {
     let r = match to_cache(cache) {
         Some(c) => catch_unwind(AssertUnwindSafe(move || {
             do_handle(
-                c, code_id, params, msg, db, api, querier, gas_limit, gas_used, sig_info,
+                c,
+                code_id,
+                params,
+                msg,
+                db,
+                api,
+                querier,
+                gas_limit,
+                gas_used,
+                sig_info,
+                simulate,
+                gas_breakdown,
             )
         }))
         .unwrap_or_else(|_| Err(Error::panic())),
@@ -400,6 +912,8 @@ fn do_handle(
     gas_limit: u64,
     gas_used: Option<&mut u64>,
     sig_info: Buffer,
+    simulate: bool,
+    gas_breakdown: Option<&mut GasBreakdown>,
 ) -> Result<Vec<u8>, Error> {
     let gas_used = gas_used.ok_or_else(|| Error::empty_arg(GAS_USED_ARG))?;
     let code_id: Checksum = unsafe { code_id.read() }
@@ -412,8 +926,20 @@ fn do_handle(
     let deps = to_extern(db, api, querier);
     let mut instance = cache.get_instance(&code_id, deps, gas_limit)?;
     // We only check this result after reporting gas usage and returning the instance into the cache.
-    let res = call_handle_raw(&mut instance, params, msg, sig_info);
+    let res = if simulate {
+        call_handle_simulate_raw(&mut instance, params, msg, sig_info)
+    } else {
+        call_handle_raw(&mut instance, params, msg, sig_info)
+    };
     *gas_used = instance.create_gas_report().used_internally;
+    // The breakdown is optional, so callers that don't display it can pass null
+    if let (Some(gas_breakdown), Some(breakdown)) = (gas_breakdown, instance.get_gas_breakdown()) {
+        *gas_breakdown = GasBreakdown {
+            wasm: breakdown.wasm,
+            storage: breakdown.storage,
+            ocall: breakdown.ocall,
+        };
+    }
     instance.recycle();
     Ok(res?)
 }
@@ -430,6 +956,7 @@ pub extern "C" fn migrate(
     gas_limit: u64,
     gas_used: Option<&mut u64>,
     err: Option<&mut Buffer>,
+    sig_info: Buffer,
 ) -> Buffer {
     let r = match to_cache(cache) {
         Some(c) => catch_unwind(AssertUnwindSafe(move || {
@@ -443,6 +970,7 @@ pub extern "C" fn migrate(
                 querier,
                 gas_limit,
                 gas_used,
+                sig_info,
             )
         }))
         .unwrap_or_else(|_| Err(Error::panic())),
@@ -462,6 +990,7 @@ fn do_migrate(
     querier: GoQuerier,
     gas_limit: u64,
     gas_used: Option<&mut u64>,
+    sig_info: Buffer,
 ) -> Result<Vec<u8>, Error> {
     let gas_used = gas_used.ok_or_else(|| Error::empty_arg(GAS_USED_ARG))?;
     let code_id: Checksum = unsafe { code_id.read() }
@@ -469,20 +998,22 @@ fn do_migrate(
         .try_into()?;
     let params = unsafe { params.read() }.ok_or_else(|| Error::empty_arg(PARAMS_ARG))?;
     let msg = unsafe { msg.read() }.ok_or_else(|| Error::empty_arg(MSG_ARG))?;
+    let sig_info = unsafe { sig_info.read() }.ok_or_else(|| Error::empty_arg(SIG_INFO_ARG))?;
 
     let deps = to_extern(db, api, querier);
     let mut instance = cache.get_instance(&code_id, deps, gas_limit)?;
     // We only check this result after reporting gas usage and returning the instance into the cache.
-    let res = call_migrate_raw(&mut instance, params, msg);
+    let res = call_migrate_raw(&mut instance, params, msg, sig_info);
     *gas_used = instance.create_gas_report().used_internally;
     instance.recycle();
     Ok(res?)
 }
 
 #[no_mangle]
-pub extern "C" fn query(
+pub extern "C" fn reply(
     cache: *mut cache_t,
     code_id: Buffer,
+    params: Buffer,
     msg: Buffer,
     db: DB,
     api: GoApi,
@@ -493,7 +1024,9 @@ pub extern "C" fn query(
 ) -> Buffer {
     let r = match to_cache(cache) {
         Some(c) => catch_unwind(AssertUnwindSafe(move || {
-            do_query(c, code_id, msg, db, api, querier, gas_limit, gas_used)
+            do_reply(
+                c, code_id, params, msg, db, api, querier, gas_limit, gas_used,
+            )
         }))
         .unwrap_or_else(|_| Err(Error::panic())),
         None => Err(Error::empty_arg(CACHE_ARG)),
@@ -502,26 +1035,429 @@ pub extern "C" fn query(
     Buffer::from_vec(data)
 }
 
-fn do_query(
+fn do_reply(
+    cache: &mut CosmCache<DB, GoApi, GoQuerier>,
+    code_id: Buffer,
+    params: Buffer,
+    msg: Buffer,
+    db: DB,
+    api: GoApi,
+    querier: GoQuerier,
+    gas_limit: u64,
+    gas_used: Option<&mut u64>,
+) -> Result<Vec<u8>, Error> {
+    let gas_used = gas_used.ok_or_else(|| Error::empty_arg(GAS_USED_ARG))?;
+    let code_id: Checksum = unsafe { code_id.read() }
+        .ok_or_else(|| Error::empty_arg(CODE_ID_ARG))?
+        .try_into()?;
+    let params = unsafe { params.read() }.ok_or_else(|| Error::empty_arg(PARAMS_ARG))?;
+    let msg = unsafe { msg.read() }.ok_or_else(|| Error::empty_arg(MSG_ARG))?;
+
+    let deps = to_extern(db, api, querier);
+    let mut instance = cache.get_instance(&code_id, deps, gas_limit)?;
+    // We only check this result after reporting gas usage and returning the instance into the cache.
+    let res = call_reply_raw(&mut instance, params, msg);
+    *gas_used = instance.create_gas_report().used_internally;
+    instance.recycle();
+    Ok(res?)
+}
+
+/// The values of `entry_point` in `ibc_call`. Must be kept in sync with the constants in
+/// types/ibc.go
+fn to_ibc_entry_point(entry_point: u8) -> Result<IbcEntryPoint, Error> {
+    match entry_point {
+        0 => Ok(IbcEntryPoint::ChannelOpen),
+        1 => Ok(IbcEntryPoint::ChannelConnect),
+        2 => Ok(IbcEntryPoint::ChannelClose),
+        3 => Ok(IbcEntryPoint::PacketAck),
+        4 => Ok(IbcEntryPoint::PacketTimeout),
+        other => Err(Error::vm_err(format!("Unknown IBC entry point {}", other))),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn ibc_call(
+    cache: *mut cache_t,
+    entry_point: u8,
+    code_id: Buffer,
+    params: Buffer,
+    msg: Buffer,
+    db: DB,
+    api: GoApi,
+    querier: GoQuerier,
+    gas_limit: u64,
+    gas_used: Option<&mut u64>,
+    err: Option<&mut Buffer>,
+) -> Buffer {
+    let r = match to_cache(cache) {
+        Some(c) => catch_unwind(AssertUnwindSafe(move || {
+            do_ibc_call(
+                c,
+                entry_point,
+                code_id,
+                params,
+                msg,
+                db,
+                api,
+                querier,
+                gas_limit,
+                gas_used,
+            )
+        }))
+        .unwrap_or_else(|_| Err(Error::panic())),
+        None => Err(Error::empty_arg(CACHE_ARG)),
+    };
+    let data = handle_c_error(r, err);
+    Buffer::from_vec(data)
+}
+
+fn do_ibc_call(
+    cache: &mut CosmCache<DB, GoApi, GoQuerier>,
+    entry_point: u8,
+    code_id: Buffer,
+    params: Buffer,
+    msg: Buffer,
+    db: DB,
+    api: GoApi,
+    querier: GoQuerier,
+    gas_limit: u64,
+    gas_used: Option<&mut u64>,
+) -> Result<Vec<u8>, Error> {
+    let gas_used = gas_used.ok_or_else(|| Error::empty_arg(GAS_USED_ARG))?;
+    let entry_point = to_ibc_entry_point(entry_point)?;
+    let code_id: Checksum = unsafe { code_id.read() }
+        .ok_or_else(|| Error::empty_arg(CODE_ID_ARG))?
+        .try_into()?;
+    let params = unsafe { params.read() }.ok_or_else(|| Error::empty_arg(PARAMS_ARG))?;
+    let msg = unsafe { msg.read() }.ok_or_else(|| Error::empty_arg(MSG_ARG))?;
+
+    let deps = to_extern(db, api, querier);
+    let mut instance = cache.get_instance(&code_id, deps, gas_limit)?;
+    // We only check this result after reporting gas usage and returning the instance into the cache.
+    let res = call_ibc_raw(&mut instance, entry_point, params, msg);
+    *gas_used = instance.create_gas_report().used_internally;
+    instance.recycle();
+    Ok(res?)
+}
+
+/// Like `ibc_call`, but the encrypted acknowledgement of the packet is also written to `ack`
+#[no_mangle]
+pub extern "C" fn ibc_packet_receive(
+    cache: *mut cache_t,
+    code_id: Buffer,
+    params: Buffer,
+    msg: Buffer,
+    db: DB,
+    api: GoApi,
+    querier: GoQuerier,
+    gas_limit: u64,
+    gas_used: Option<&mut u64>,
+    ack: Option<&mut Buffer>,
+    err: Option<&mut Buffer>,
+) -> Buffer {
+    let r = match to_cache(cache) {
+        Some(c) => catch_unwind(AssertUnwindSafe(move || {
+            do_ibc_packet_receive(
+                c, code_id, params, msg, db, api, querier, gas_limit, gas_used, ack,
+            )
+        }))
+        .unwrap_or_else(|_| Err(Error::panic())),
+        None => Err(Error::empty_arg(CACHE_ARG)),
+    };
+    let data = handle_c_error(r, err);
+    Buffer::from_vec(data)
+}
+
+fn do_ibc_packet_receive(
+    cache: &mut CosmCache<DB, GoApi, GoQuerier>,
+    code_id: Buffer,
+    params: Buffer,
+    msg: Buffer,
+    db: DB,
+    api: GoApi,
+    querier: GoQuerier,
+    gas_limit: u64,
+    gas_used: Option<&mut u64>,
+    ack: Option<&mut Buffer>,
+) -> Result<Vec<u8>, Error> {
+    let gas_used = gas_used.ok_or_else(|| Error::empty_arg(GAS_USED_ARG))?;
+    let ack = ack.ok_or_else(|| Error::empty_arg(ACKNOWLEDGEMENT_ARG))?;
+    let code_id: Checksum = unsafe { code_id.read() }
+        .ok_or_else(|| Error::empty_arg(CODE_ID_ARG))?
+        .try_into()?;
+    let params = unsafe { params.read() }.ok_or_else(|| Error::empty_arg(PARAMS_ARG))?;
+    let msg = unsafe { msg.read() }.ok_or_else(|| Error::empty_arg(MSG_ARG))?;
+
+    let deps = to_extern(db, api, querier);
+    let mut instance = cache.get_instance(&code_id, deps, gas_limit)?;
+    // We only check this result after reporting gas usage and returning the instance into the cache.
+    let res = call_ibc_packet_receive_raw(&mut instance, params, msg);
+    *gas_used = instance.create_gas_report().used_internally;
+    instance.recycle();
+    let (output, acknowledgement) = res?;
+    *ack = Buffer::from_vec(acknowledgement);
+    Ok(output)
+}
+
+#[no_mangle]
+pub extern "C" fn rotate_contract_key(
+    cache: *mut cache_t,
+    code_id: Buffer,
+    params: Buffer,
+    db: DB,
+    api: GoApi,
+    querier: GoQuerier,
+    gas_limit: u64,
+    gas_used: Option<&mut u64>,
+    err: Option<&mut Buffer>,
+    sig_info: Buffer,
+    cursor: Buffer,
+    chunk_size: u32,
+) -> Buffer {
+    let r = match to_cache(cache) {
+        Some(c) => catch_unwind(AssertUnwindSafe(move || {
+            do_rotate_contract_key(
+                c, code_id, params, db, api, querier, gas_limit, gas_used, sig_info, cursor,
+                chunk_size,
+            )
+        }))
+        .unwrap_or_else(|_| Err(Error::panic())),
+        None => Err(Error::empty_arg(CACHE_ARG)),
+    };
+    let data = handle_c_error(r, err);
+    Buffer::from_vec(data)
+}
+
+fn do_rotate_contract_key(
+    cache: &mut CosmCache<DB, GoApi, GoQuerier>,
+    code_id: Buffer,
+    params: Buffer,
+    db: DB,
+    api: GoApi,
+    querier: GoQuerier,
+    gas_limit: u64,
+    gas_used: Option<&mut u64>,
+    sig_info: Buffer,
+    cursor: Buffer,
+    chunk_size: u32,
+) -> Result<Vec<u8>, Error> {
+    let gas_used = gas_used.ok_or_else(|| Error::empty_arg(GAS_USED_ARG))?;
+    let code_id: Checksum = unsafe { code_id.read() }
+        .ok_or_else(|| Error::empty_arg(CODE_ID_ARG))?
+        .try_into()?;
+    let params = unsafe { params.read() }.ok_or_else(|| Error::empty_arg(PARAMS_ARG))?;
+    let sig_info = unsafe { sig_info.read() }.ok_or_else(|| Error::empty_arg(SIG_INFO_ARG))?;
+    // An empty cursor starts a new rotation
+    let cursor = unsafe { cursor.read() }.unwrap_or_default();
+
+    let deps = to_extern(db, api, querier);
+    let mut instance = cache.get_instance(&code_id, deps, gas_limit)?;
+    // We only check this result after reporting gas usage and returning the instance into the cache.
+    let res = call_rotate_contract_key_raw(&mut instance, params, sig_info, cursor, chunk_size);
+    *gas_used = instance.create_gas_report().used_internally;
+    instance.recycle();
+    Ok(res?)
+}
+
+#[no_mangle]
+pub extern "C" fn export_contract_state(
+    cache: *mut cache_t,
+    code_id: Buffer,
+    params: Buffer,
+    db: DB,
+    api: GoApi,
+    querier: GoQuerier,
+    gas_limit: u64,
+    gas_used: Option<&mut u64>,
+    err: Option<&mut Buffer>,
+    cursor: Buffer,
+    snapshot_nonce: Buffer,
+    chunk_size: u32,
+) -> Buffer {
+    let r = match to_cache(cache) {
+        Some(c) => catch_unwind(AssertUnwindSafe(move || {
+            do_export_contract_state(
+                c,
+                code_id,
+                params,
+                db,
+                api,
+                querier,
+                gas_limit,
+                gas_used,
+                cursor,
+                snapshot_nonce,
+                chunk_size,
+            )
+        }))
+        .unwrap_or_else(|_| Err(Error::panic())),
+        None => Err(Error::empty_arg(CACHE_ARG)),
+    };
+    let data = handle_c_error(r, err);
+    Buffer::from_vec(data)
+}
+
+fn do_export_contract_state(
     cache: &mut CosmCache<DB, GoApi, GoQuerier>,
     code_id: Buffer,
+    params: Buffer,
+    db: DB,
+    api: GoApi,
+    querier: GoQuerier,
+    gas_limit: u64,
+    gas_used: Option<&mut u64>,
+    cursor: Buffer,
+    snapshot_nonce: Buffer,
+    chunk_size: u32,
+) -> Result<Vec<u8>, Error> {
+    let gas_used = gas_used.ok_or_else(|| Error::empty_arg(GAS_USED_ARG))?;
+    let code_id: Checksum = unsafe { code_id.read() }
+        .ok_or_else(|| Error::empty_arg(CODE_ID_ARG))?
+        .try_into()?;
+    let params = unsafe { params.read() }.ok_or_else(|| Error::empty_arg(PARAMS_ARG))?;
+    // An empty cursor and nonce start a new export
+    let cursor = unsafe { cursor.read() }.unwrap_or_default();
+    let snapshot_nonce = unsafe { snapshot_nonce.read() }.unwrap_or_default();
+
+    let deps = to_extern(db, api, querier);
+    let mut instance = cache.get_instance(&code_id, deps, gas_limit)?;
+    // We only check this result after reporting gas usage and returning the instance into the cache.
+    let res =
+        call_export_contract_state_raw(&mut instance, params, cursor, snapshot_nonce, chunk_size);
+    *gas_used = instance.create_gas_report().used_internally;
+    instance.recycle();
+    Ok(res?)
+}
+
+/// Returns how many entries were imported, as a big endian u32
+#[no_mangle]
+pub extern "C" fn import_contract_state(
+    cache: *mut cache_t,
+    code_id: Buffer,
+    params: Buffer,
+    db: DB,
+    api: GoApi,
+    querier: GoQuerier,
+    gas_limit: u64,
+    gas_used: Option<&mut u64>,
+    err: Option<&mut Buffer>,
+    sig_info: Buffer,
+    chunk: Buffer,
+) -> Buffer {
+    let r = match to_cache(cache) {
+        Some(c) => catch_unwind(AssertUnwindSafe(move || {
+            do_import_contract_state(
+                c, code_id, params, db, api, querier, gas_limit, gas_used, sig_info, chunk,
+            )
+        }))
+        .unwrap_or_else(|_| Err(Error::panic())),
+        None => Err(Error::empty_arg(CACHE_ARG)),
+    };
+    let data = handle_c_error(r, err);
+    Buffer::from_vec(data)
+}
+
+fn do_import_contract_state(
+    cache: &mut CosmCache<DB, GoApi, GoQuerier>,
+    code_id: Buffer,
+    params: Buffer,
+    db: DB,
+    api: GoApi,
+    querier: GoQuerier,
+    gas_limit: u64,
+    gas_used: Option<&mut u64>,
+    sig_info: Buffer,
+    chunk: Buffer,
+) -> Result<Vec<u8>, Error> {
+    let gas_used = gas_used.ok_or_else(|| Error::empty_arg(GAS_USED_ARG))?;
+    let code_id: Checksum = unsafe { code_id.read() }
+        .ok_or_else(|| Error::empty_arg(CODE_ID_ARG))?
+        .try_into()?;
+    let params = unsafe { params.read() }.ok_or_else(|| Error::empty_arg(PARAMS_ARG))?;
+    let sig_info = unsafe { sig_info.read() }.ok_or_else(|| Error::empty_arg(SIG_INFO_ARG))?;
+    let chunk = unsafe { chunk.read() }.ok_or_else(|| Error::empty_arg(CHUNK_ARG))?;
+
+    let deps = to_extern(db, api, querier);
+    let mut instance = cache.get_instance(&code_id, deps, gas_limit)?;
+    // We only check this result after reporting gas usage and returning the instance into the cache.
+    let res = call_import_contract_state_raw(&mut instance, params, sig_info, chunk);
+    *gas_used = instance.create_gas_report().used_internally;
+    instance.recycle();
+    Ok(res?.to_be_bytes().to_vec())
+}
+
+#[no_mangle]
+pub extern "C" fn update_admin(
+    params: Buffer,
+    sig_info: Buffer,
+    new_admin: Buffer,
+    err: Option<&mut Buffer>,
+) -> Buffer {
+    let r = catch_unwind(AssertUnwindSafe(move || {
+        do_update_admin(params, sig_info, new_admin)
+    }))
+    .unwrap_or_else(|_| Err(Error::panic()));
+    let data = handle_c_error(r, err);
+    Buffer::from_vec(data)
+}
+
+fn do_update_admin(params: Buffer, sig_info: Buffer, new_admin: Buffer) -> Result<Vec<u8>, Error> {
+    let params = unsafe { params.read() }.ok_or_else(|| Error::empty_arg(PARAMS_ARG))?;
+    let sig_info = unsafe { sig_info.read() }.ok_or_else(|| Error::empty_arg(SIG_INFO_ARG))?;
+    // An empty admin clears the admin of the contract
+    let new_admin = unsafe { new_admin.read() }.unwrap_or_default();
+
+    let admin_proof = call_update_admin(params, sig_info, new_admin)?;
+    Ok(admin_proof.to_vec())
+}
+
+#[no_mangle]
+pub extern "C" fn query(
+    cache: *mut cache_t,
+    code_id: Buffer,
+    msg: Buffer,
+    db: DB,
+    api: GoApi,
+    querier: GoQuerier,
+    gas_limit: u64,
+    gas_used: Option<&mut u64>,
+    err: Option<&mut Buffer>,
+    env: Buffer,
+) -> Buffer {
+    let r = match to_shared_cache(cache) {
+        Some(c) => catch_unwind(AssertUnwindSafe(move || {
+            do_query(c, code_id, msg, db, api, querier, gas_limit, gas_used, env)
+        }))
+        .unwrap_or_else(|_| Err(Error::panic())),
+        None => Err(Error::empty_arg(CACHE_ARG)),
+    };
+    let data = handle_c_error(r, err);
+    Buffer::from_vec(data)
+}
+
+fn do_query(
+    cache: &CosmCache<DB, GoApi, GoQuerier>,
+    code_id: Buffer,
     msg: Buffer,
     db: DB,
     api: GoApi,
     querier: GoQuerier,
     gas_limit: u64,
     gas_used: Option<&mut u64>,
+    env: Buffer,
 ) -> Result<Vec<u8>, Error> {
     let gas_used = gas_used.ok_or_else(|| Error::empty_arg(GAS_USED_ARG))?;
     let code_id: Checksum = unsafe { code_id.read() }
         .ok_or_else(|| Error::empty_arg(CODE_ID_ARG))?
         .try_into()?;
     let msg = unsafe { msg.read() }.ok_or_else(|| Error::empty_arg(MSG_ARG))?;
+    // The env is optional for queries
+    let env = unsafe { env.read() }.unwrap_or_default();
 
     let deps = to_extern(db, api, querier);
     let mut instance = cache.get_instance(&code_id, deps, gas_limit)?;
     // We only check this result after reporting gas usage and returning the instance into the cache.
-    let res = call_query_raw(&mut instance, msg);
+    let res = call_query_raw(&mut instance, env, msg);
     *gas_used = instance.create_gas_report().used_internally;
     instance.recycle();
     Ok(res?)
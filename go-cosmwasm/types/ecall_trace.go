@@ -0,0 +1,149 @@
+package types
+
+import (
+	"encoding/binary"
+	"fmt"
+	"strings"
+	"time"
+)
+
+// The settings of ecall tracing that api.ConfigureEnclaveRuntime takes
+const (
+	EcallTracingKeep uint8 = iota
+	EcallTracingOn
+	EcallTracingOff
+)
+
+// ecallTraceFormatVersion is the only version of the trace format this node can read
+const ecallTraceFormatVersion = 1
+
+// The phases of ocalls start at ocallPhaseBase, in the order of ocallPhaseNames
+const ocallPhaseBase = 16
+
+var phaseNames = []string{"ecall", "nested_ecall", "decrypt_input", "load_module", "instantiate", "execute", "encrypt_output"}
+
+var ocallPhaseNames = []string{"read_db", "read_db_multi", "write_db", "remove_db", "db_scan", "db_next", "query_chain", "write_log", "prefetch_db"}
+
+// EcallTraces are the traces of the last ecalls the enclave ran while tracing was on
+type EcallTraces struct {
+	// SpansRecorded is the amount of spans the enclave recorded since it was started
+	SpansRecorded uint64
+	// SpansDropped is the amount of spans that didn't fit in the trace of their ecall
+	SpansDropped uint64
+	// Ecalls are the traces, oldest first
+	Ecalls []EcallTrace
+}
+
+// EcallTrace is what the enclave did during one ecall
+type EcallTrace struct {
+	Ecall string
+	// DroppedSpans is the amount of spans of this ecall that weren't kept
+	DroppedSpans uint32
+	// Spans are in the order they were opened. The first one is the whole ecall
+	Spans []TraceSpan
+}
+
+// TraceSpan is one phase of an ecall
+type TraceSpan struct {
+	Phase uint8
+	// Depth is the amount of spans the span is nested in. Its parent is the last span before it
+	// with a depth of one less
+	Depth uint8
+	// Start is the time since the ecall began
+	Start    time.Duration
+	Duration time.Duration
+}
+
+// PhaseName is the name of the phase of the span, e.g. "execute" or "ocall:read_db"
+func (s TraceSpan) PhaseName() string {
+	if int(s.Phase) < len(phaseNames) {
+		return phaseNames[s.Phase]
+	}
+	if s.Phase >= ocallPhaseBase && int(s.Phase-ocallPhaseBase) < len(ocallPhaseNames) {
+		return "ocall:" + ocallPhaseNames[s.Phase-ocallPhaseBase]
+	}
+	return fmt.Sprintf("unknown(%d)", s.Phase)
+}
+
+// traceReader reads the big-endian integers of an exported trace
+type traceReader struct {
+	data []byte
+	err  error
+}
+
+func (r *traceReader) next(n int) []byte {
+	if r.err != nil {
+		return make([]byte, n)
+	}
+	if len(r.data) < n {
+		r.err = fmt.Errorf("ecall trace is truncated")
+		return make([]byte, n)
+	}
+	next := r.data[:n]
+	r.data = r.data[n:]
+	return next
+}
+
+func (r *traceReader) u8() uint8 { return r.next(1)[0] }
+func (r *traceReader) u16() uint16 { return binary.BigEndian.Uint16(r.next(2)) }
+func (r *traceReader) u32() uint32 { return binary.BigEndian.Uint32(r.next(4)) }
+func (r *traceReader) u64() uint64 { return binary.BigEndian.Uint64(r.next(8)) }
+
+// ParseEcallTraces reads the traces that the enclave exports from ecall_get_trace
+func ParseEcallTraces(data []byte) (*EcallTraces, error) {
+	r := &traceReader{data: data}
+	if version := r.u8(); r.err == nil && version != ecallTraceFormatVersion {
+		return nil, fmt.Errorf("unknown ecall trace format version %d", version)
+	}
+
+	traces := &EcallTraces{
+		SpansRecorded: r.u64(),
+		SpansDropped:  r.u64(),
+	}
+	ecallCount := r.u32()
+	for i := uint32(0); i < ecallCount && r.err == nil; i++ {
+		ecall := EcallTrace{Ecall: string(r.next(int(r.u8())))}
+		ecall.DroppedSpans = r.u32()
+		spanCount := r.u16()
+		for j := uint16(0); j < spanCount && r.err == nil; j++ {
+			ecall.Spans = append(ecall.Spans, TraceSpan{
+				Phase:    r.u8(),
+				Depth:    r.u8(),
+				Start:    time.Duration(r.u64()),
+				Duration: time.Duration(r.u64()),
+			})
+		}
+		traces.Ecalls = append(traces.Ecalls, ecall)
+	}
+
+	if r.err != nil {
+		return nil, r.err
+	}
+	if len(r.data) != 0 {
+		return nil, fmt.Errorf("ecall trace has %d unexpected trailing bytes", len(r.data))
+	}
+	return traces, nil
+}
+
+// String renders every ecall as a tree of its spans, with when each started and how long it took
+func (t EcallTraces) String() string {
+	var out strings.Builder
+	fmt.Fprintf(&out, "%d ecalls, %d spans recorded, %d dropped\n", len(t.Ecalls), t.SpansRecorded, t.SpansDropped)
+	for _, ecall := range t.Ecalls {
+		out.WriteString(ecall.String())
+	}
+	return out.String()
+}
+
+func (e EcallTrace) String() string {
+	var out strings.Builder
+	out.WriteString(e.Ecall)
+	if e.DroppedSpans != 0 {
+		fmt.Fprintf(&out, " (%d spans dropped)", e.DroppedSpans)
+	}
+	out.WriteString("\n")
+	for _, span := range e.Spans {
+		fmt.Fprintf(&out, "  %s%-*s +%-12v %v\n", strings.Repeat("  ", int(span.Depth)), 24-2*int(span.Depth), span.PhaseName(), span.Start, span.Duration)
+	}
+	return out.String()
+}
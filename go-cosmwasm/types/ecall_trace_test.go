@@ -0,0 +1,84 @@
+package types
+
+import (
+	"encoding/binary"
+	"testing"
+	"time"
+
+	"github.com/stretchr/testify/assert"
+	"github.com/stretchr/testify/require"
+)
+
+func appendUint(out []byte, size int, value uint64) []byte {
+	var buf [8]byte
+	binary.BigEndian.PutUint64(buf[:], value)
+	return append(out, buf[8-size:]...)
+}
+
+// encodeSpan writes a span the way the enclave exports it
+func encodeSpan(out []byte, phase uint8, depth uint8, start uint64, duration uint64) []byte {
+	out = append(out, phase, depth)
+	out = appendUint(out, 8, start)
+	return appendUint(out, 8, duration)
+}
+
+func exportedTrace() []byte {
+	out := []byte{ecallTraceFormatVersion}
+	out = appendUint(out, 8, 40)
+	out = appendUint(out, 8, 2)
+	out = appendUint(out, 4, 1)
+
+	out = append(out, byte(len("ecall_handle")))
+	out = append(out, "ecall_handle"...)
+	out = appendUint(out, 4, 2)
+	out = appendUint(out, 2, 3)
+	out = encodeSpan(out, 0, 0, 0, 5000)
+	out = encodeSpan(out, 5, 1, 1000, 3000)
+	return encodeSpan(out, ocallPhaseBase, 2, 1500, 700)
+}
+
+func TestParseEcallTraces(t *testing.T) {
+	traces, err := ParseEcallTraces(exportedTrace())
+	require.NoError(t, err)
+
+	assert.Equal(t, uint64(40), traces.SpansRecorded)
+	assert.Equal(t, uint64(2), traces.SpansDropped)
+	require.Len(t, traces.Ecalls, 1)
+
+	ecall := traces.Ecalls[0]
+	assert.Equal(t, "ecall_handle", ecall.Ecall)
+	assert.Equal(t, uint32(2), ecall.DroppedSpans)
+	require.Len(t, ecall.Spans, 3)
+	assert.Equal(t, "ecall", ecall.Spans[0].PhaseName())
+	assert.Equal(t, "execute", ecall.Spans[1].PhaseName())
+	assert.Equal(t, "ocall:read_db", ecall.Spans[2].PhaseName())
+	assert.Equal(t, uint8(2), ecall.Spans[2].Depth)
+	assert.Equal(t, 1500*time.Nanosecond, ecall.Spans[2].Start)
+	assert.Equal(t, 700*time.Nanosecond, ecall.Spans[2].Duration)
+
+	rendered := traces.String()
+	assert.Contains(t, rendered, "ecall_handle (2 spans dropped)\n")
+	assert.Contains(t, rendered, "      ocall:read_db")
+}
+
+func TestParseEcallTracesRejectsMalformedTraces(t *testing.T) {
+	trace := exportedTrace()
+
+	_, err := ParseEcallTraces(trace[:len(trace)-1])
+	assert.EqualError(t, err, "ecall trace is truncated")
+
+	_, err = ParseEcallTraces(append(trace, 0))
+	assert.EqualError(t, err, "ecall trace has 1 unexpected trailing bytes")
+
+	trace[0] = ecallTraceFormatVersion + 1
+	_, err = ParseEcallTraces(trace)
+	assert.EqualError(t, err, "unknown ecall trace format version 2")
+
+	_, err = ParseEcallTraces(nil)
+	assert.EqualError(t, err, "ecall trace is truncated")
+}
+
+func TestUnknownPhaseName(t *testing.T) {
+	assert.Equal(t, "unknown(9)", TraceSpan{Phase: 9}.PhaseName())
+	assert.Equal(t, "unknown(40)", TraceSpan{Phase: 40}.PhaseName())
+}
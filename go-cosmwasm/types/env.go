@@ -1,5 +1,7 @@
 package types
 
+import "encoding/json"
+
 //---------- Env ---------
 
 // Env defines the state of the blockchain environment this contract is
@@ -12,6 +14,188 @@ type Env struct {
 	Message  MessageInfo  `json:"message"`
 	Contract ContractInfo `json:"contract"`
 	Key      ContractKey  `json:"contract_key"`
+	// hex encoded code hash of the contract. On executions and queries the enclave rejects the env
+	// if it doesn't match the code it runs. On migrations it is the hash of the code the contract
+	// is migrating from
+	CodeHash string `json:"contract_code_hash,omitempty"`
+	// Transaction holds the memo of the tx, which the enclave verifies against the signed tx.
+	// Empty for messages sent by contracts
+	Transaction TransactionInfo `json:"transaction"`
+	// OcallGasCosts overrides the gas the enclave charges for every ocall. The enclave uses its
+	// defaults when this is nil, and doesn't pass it on to the contract
+	OcallGasCosts *OcallGasCosts `json:"ocall_gas_costs,omitempty"`
+	// Bech32Prefix is the prefix of the account addresses of the chain, used by the address
+	// functions the enclave provides to contracts. The enclave defaults to "secret" when empty
+	Bech32Prefix string `json:"bech32_prefix,omitempty"`
+	// Instantiation is the code a new contract is instantiated from. Only set on init, where the
+	// enclave checks it against the message the sender signed
+	Instantiation *ContractInstantiation `json:"instantiation,omitempty"`
+	// BlockHeader is the header of the current block. The enclave checks it against Block, and
+	// passes its hash and proposer to the contract in Block
+	BlockHeader *BlockHeader `json:"block_header,omitempty"`
+	// ExecutionTimeoutMs makes the enclave stop a query with an ExecutionTimeout error once it ran
+	// for longer, in milliseconds. The gas used until then is still charged. 0 disables it, and the
+	// enclave ignores it outside of queries
+	ExecutionTimeoutMs uint64 `json:"execution_timeout_ms,omitempty"`
+	// OutputLimits overrides how many messages and bytes a contract may return from one call. The
+	// enclave uses its defaults when this is nil, and doesn't pass it on to the contract
+	OutputLimits *OutputLimits `json:"output_limits,omitempty"`
+	// BlockGasRemaining is the gas left in the block, in the gas of the enclave. When it's less than
+	// the gas limit of the call, the enclave stops the contract there with an OutOfBlockGas error
+	// instead of OutOfGas. 0 leaves the gas limit of the call as is
+	BlockGasRemaining uint64 `json:"block_gas_remaining,omitempty"`
+	// StorageQuota is the most bytes a contract may store, counting the keys and values of its
+	// entries. A write past it returns an error to contracts that write with db_try_write, and
+	// fails the call with a StorageQuotaExceeded error otherwise. Writes that don't grow the
+	// storage always succeed. 0 leaves the storage of contracts unlimited and unaccounted for
+	StorageQuota uint64 `json:"storage_quota,omitempty"`
+	// MaxMemoryPages is the most memory a contract may grow to, in 64KiB pages. The enclave keeps
+	// its legacy limit of 192 pages when this is 0, and never allows more than 512
+	MaxMemoryPages uint32 `json:"max_memory_pages,omitempty"`
+	// Simulation is set when the tx only runs to be simulated, so the enclave doesn't remember the
+	// encryption nonce of its message and the tx can still be broadcast with the same message
+	Simulation bool `json:"simulation,omitempty"`
+	// ClientQuery is set on the queries clients send to the node, as opposed to queries contracts
+	// make during txs. The enclave only uses up the nonce of a replay protected query on these
+	ClientQuery bool `json:"client_query,omitempty"`
+	// AdminProof is the proof the enclave returned for the admin of the contract when it was set.
+	// The enclave only trusts Contract.Admin when it matches this proof
+	AdminProof []byte `json:"admin_proof,omitempty"`
+	// ExecutionPath is where the call is in the tx, as the steps from the message of the tx to it.
+	// Message i of a call is step i. Submessage i of a call that returned m messages is step m+2i,
+	// and the reply to it step m+2i+1, so sorting paths orders calls depth first. The enclave binds
+	// the encrypted log attributes to the path and their index, and leaves them as they are when nil
+	ExecutionPath []uint32 `json:"execution_path"`
+	// ReplyTo is set when the call runs for a submessage of a contract, or is the reply of a
+	// contract that runs for one. The enclave then signs the result of the call for the reply to
+	// that submessage
+	ReplyTo *ReplyTo `json:"reply_to,omitempty"`
+	// ForeignChains are what the enclave trusts about the chains the chain tracks, as
+	// TrackForeignChain and SubmitForeignHeader returned them. Contracts verify the headers of
+	// those chains against them
+	ForeignChains []json.RawMessage `json:"foreign_chains,omitempty"`
+}
+
+// ReplyTo is the submessage a call runs for, as the contract that sent it got it from the enclave
+type ReplyTo struct {
+	ID       uint64 `json:"id"`
+	Contract string `json:"contract"`
+	// ReplySig is the SubMsg.ReplySig of the submessage
+	ReplySig []byte `json:"reply_sig"`
+}
+
+// BlockHeader is a Tendermint block header, which the enclave hashes the same as Tendermint does
+type BlockHeader struct {
+	Version            HeaderVersion `json:"version"`
+	ChainID            string        `json:"chain_id"`
+	Height             uint64        `json:"height"`
+	Time               HeaderTime    `json:"time"`
+	LastBlockID        BlockID       `json:"last_block_id"`
+	LastCommitHash     []byte        `json:"last_commit_hash,omitempty"`
+	DataHash           []byte        `json:"data_hash,omitempty"`
+	ValidatorsHash     []byte        `json:"validators_hash,omitempty"`
+	NextValidatorsHash []byte        `json:"next_validators_hash,omitempty"`
+	ConsensusHash      []byte        `json:"consensus_hash,omitempty"`
+	AppHash            []byte        `json:"app_hash,omitempty"`
+	LastResultsHash    []byte        `json:"last_results_hash,omitempty"`
+	EvidenceHash       []byte        `json:"evidence_hash,omitempty"`
+	ProposerAddress    []byte        `json:"proposer_address,omitempty"`
+}
+
+type HeaderVersion struct {
+	Block uint64 `json:"block"`
+	App   uint64 `json:"app"`
+}
+
+type HeaderTime struct {
+	Seconds int64 `json:"seconds"`
+	Nanos   int32 `json:"nanos"`
+}
+
+type BlockID struct {
+	Hash  []byte        `json:"hash,omitempty"`
+	Parts PartSetHeader `json:"parts"`
+}
+
+type PartSetHeader struct {
+	Total uint32 `json:"total"`
+	Hash  []byte `json:"hash,omitempty"`
+}
+
+// BlockHeaderSubmission is the header of a block, with the commit and validators that signed it.
+// The enclave only accepts it if validators with more than two thirds of the voting power signed
+// the header, and they are the validators the header before it chose
+type BlockHeaderSubmission struct {
+	Header BlockHeader `json:"header"`
+	Commit Commit      `json:"commit"`
+	// Validators are in the order of the signatures in Commit
+	Validators []Validator `json:"validators"`
+}
+
+// ForeignHeader is a header of another chain, with the commit and validators that signed it
+type ForeignHeader struct {
+	Header BlockHeader `json:"header"`
+	Commit Commit      `json:"commit"`
+	// Validators are in the order of the signatures in Commit
+	Validators []Validator `json:"validators"`
+	// TrustedValidators are the validators the last header the enclave verified chose for the block
+	// after it. They are only needed for headers that skip blocks
+	TrustedValidators []Validator `json:"trusted_validators,omitempty"`
+}
+
+// ForeignChainInit is a header of another chain that governance approved, from which the enclave
+// starts verifying the headers of that chain
+type ForeignChainInit struct {
+	Header                BlockHeader `json:"header"`
+	TrustingPeriodSeconds uint64      `json:"trusting_period_seconds"`
+}
+
+type Commit struct {
+	Height     uint64      `json:"height"`
+	Round      uint32      `json:"round"`
+	BlockID    BlockID     `json:"block_id"`
+	Signatures []CommitSig `json:"signatures"`
+}
+
+// CommitSig is the precommit of one validator. BlockIDFlag is Tendermint's BlockIDFlag
+type CommitSig struct {
+	BlockIDFlag      uint8      `json:"block_id_flag"`
+	ValidatorAddress []byte     `json:"validator_address,omitempty"`
+	Timestamp        HeaderTime `json:"timestamp"`
+	Signature        []byte     `json:"signature,omitempty"`
+}
+
+// Validator is a validator with an ed25519 consensus key
+type Validator struct {
+	Address     []byte `json:"address"`
+	PubKey      []byte `json:"pub_key"`
+	VotingPower int64  `json:"voting_power"`
+}
+
+// ContractInstantiation is the code a new contract is instantiated from
+type ContractInstantiation struct {
+	CodeID uint64 `json:"code_id"`
+}
+
+type TransactionInfo struct {
+	Memo string `json:"memo"`
+}
+
+// OcallGasCosts is the fixed gas charged for a round trip out of the enclave, on top of what the
+// operation itself costs
+type OcallGasCosts struct {
+	ReadDb     uint64 `json:"read_db"`
+	WriteDb    uint64 `json:"write_db"`
+	RemoveDb   uint64 `json:"remove_db"`
+	QueryChain uint64 `json:"query_chain"`
+}
+
+// OutputLimits is how much a contract may return from one call. Outputs with more messages and
+// submessages than MaxMessages, or more bytes than MaxBytes before they are encrypted, fail with an
+// error that states both limits. A zero field keeps the default of the enclave
+type OutputLimits struct {
+	MaxMessages uint32 `json:"max_messages,omitempty"`
+	MaxBytes    uint64 `json:"max_bytes,omitempty"`
 }
 
 type ContractKey string
@@ -20,8 +204,11 @@ type BlockInfo struct {
 	// block height this transaction is executed
 	Height uint64 `json:"height"`
 	// time in seconds since unix epoch - since cosmwasm 0.3
-	Time    uint64 `json:"time"`
-	ChainID string `json:"chain_id"`
+	Time uint64 `json:"time"`
+	// the nanoseconds of the block time past Time. Only contracts built for CosmWasm 1.0 see
+	// them, as part of their nanosecond timestamp
+	TimeNanos uint64 `json:"time_nanos,omitempty"`
+	ChainID   string `json:"chain_id"`
 }
 
 type MessageInfo struct {
@@ -34,4 +221,6 @@ type MessageInfo struct {
 type ContractInfo struct {
 	// binary encoding of sdk.AccAddress of the contract, to be used when sending messages
 	Address HumanAddress `json:"address"`
+	// address allowed to migrate the contract. Only set on migrations and contract key rotations
+	Admin HumanAddress `json:"admin,omitempty"`
 }
@@ -0,0 +1,61 @@
+package types
+
+//------- IBC -------------
+
+// The entry points that are called with api.IBCCall. Must be kept in sync with
+// `to_ibc_entry_point` in src/lib.rs
+const (
+	IBCChannelOpen    uint8 = 0
+	IBCChannelConnect uint8 = 1
+	IBCChannelClose   uint8 = 2
+	IBCPacketAck      uint8 = 3
+	IBCPacketTimeout  uint8 = 4
+)
+
+// IBCPortPrefix is followed by the address of a contract to form the port of the contract
+const IBCPortPrefix = "wasm."
+
+// IBCEndpoint is the port and channel of one end of a channel
+type IBCEndpoint struct {
+	PortID    string `json:"port_id"`
+	ChannelID string `json:"channel_id"`
+}
+
+// Values of IBCChannel.Order
+const (
+	IBCOrderUnordered = "unordered"
+	IBCOrderOrdered   = "ordered"
+)
+
+// IBCChannel is passed to the channel entry points of a contract
+type IBCChannel struct {
+	// Endpoint is the end of the channel on this chain
+	Endpoint             IBCEndpoint `json:"endpoint"`
+	CounterpartyEndpoint IBCEndpoint `json:"counterparty_endpoint"`
+	Order                string      `json:"order"`
+	Version              string      `json:"version"`
+	ConnectionID         string      `json:"connection_id"`
+}
+
+type IBCTimeoutHeight struct {
+	RevisionNumber uint64 `json:"revision_number"`
+	TimeoutHeight  uint64 `json:"timeout_height"`
+}
+
+// IBCPacket is passed to ibc_packet_receive and ibc_packet_timeout.
+// When it's received, Data is a secret message encrypted to the contract, like the msg of a
+// MsgExecuteContract, and the enclave decrypts it before the contract gets it
+type IBCPacket struct {
+	Data             []byte            `json:"data"`
+	Src              IBCEndpoint       `json:"src"`
+	Dest             IBCEndpoint       `json:"dest"`
+	Sequence         uint64            `json:"sequence"`
+	TimeoutHeight    *IBCTimeoutHeight `json:"timeout_height"`
+	TimeoutTimestamp *uint64           `json:"timeout_timestamp"`
+}
+
+// IBCAcknowledgement is passed to ibc_packet_ack, for a packet the contract sent
+type IBCAcknowledgement struct {
+	Acknowledgement []byte    `json:"acknowledgement"`
+	OriginalPacket  IBCPacket `json:"original_packet"`
+}
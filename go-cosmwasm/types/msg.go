@@ -14,12 +14,19 @@ type HandleResult struct {
 
 // HandleResponse defines the return value on a successful handle
 type HandleResponse struct {
+	// Submessages are like Messages, but their result is passed back to the contract
+	Submessages []SubMsg `json:"submessages"`
 	// Messages comes directly from the contract and is it's request for action
 	Messages []CosmosMsg `json:"messages"`
 	// base64-encoded bytes to return as ABCI.Data field
 	Data []byte `json:"data"`
 	// log message to return over abci interface
 	Log []LogAttribute `json:"log"`
+	// ExecutionPath is the Env.ExecutionPath the encrypted log attributes are bound to
+	ExecutionPath []uint32 `json:"execution_path,omitempty"`
+	// ReplyResultSig is the enclave's signature of the result of the call for the reply to the
+	// submessage in Env.ReplyTo. It must be passed back as SignedReply.ResultSig
+	ReplyResultSig []byte `json:"reply_result_sig,omitempty"`
 }
 
 // InitResult is the raw response from the handle call
@@ -30,10 +37,17 @@ type InitResult struct {
 
 // InitResponse defines the return value on a successful handle
 type InitResponse struct {
+	// Submessages are like Messages, but their result is passed back to the contract
+	Submessages []SubMsg `json:"submessages"`
 	// Messages comes directly from the contract and is it's request for action
 	Messages []CosmosMsg `json:"messages"`
 	// log message to return over abci interface
 	Log []LogAttribute `json:"log"`
+	// ExecutionPath is the Env.ExecutionPath the encrypted log attributes are bound to
+	ExecutionPath []uint32 `json:"execution_path,omitempty"`
+	// ReplyResultSig is the enclave's signature of the result of the call for the reply to the
+	// submessage in Env.ReplyTo. It must be passed back as SignedReply.ResultSig
+	ReplyResultSig []byte `json:"reply_result_sig,omitempty"`
 }
 
 // MigrateResult is the raw response from the handle call
@@ -50,6 +64,58 @@ type MigrateResponse struct {
 	Data []byte `json:"data"`
 	// log message to return over abci interface
 	Log []LogAttribute `json:"log"`
+	// ExecutionPath is the Env.ExecutionPath the encrypted log attributes are bound to
+	ExecutionPath []uint32 `json:"execution_path,omitempty"`
+}
+
+// Values of SubMsg.ReplyOn
+const (
+	ReplyAlways  = "always"
+	ReplyError   = "error"
+	ReplySuccess = "success"
+	ReplyNever   = "never"
+)
+
+// SubMsg is a message that the contract wants to get the result of, via a call to its reply entry point
+type SubMsg struct {
+	// ID is chosen by the contract and is passed back in the Reply
+	ID  uint64    `json:"id"`
+	Msg CosmosMsg `json:"msg"`
+	// GasLimit optionally limits the gas the submessage may use
+	GasLimit *uint64 `json:"gas_limit,omitempty"`
+	// ReplyOn is one of ReplyAlways, ReplyError, ReplySuccess or ReplyNever
+	ReplyOn string `json:"reply_on"`
+	// ReplySig is set by the enclave and must be passed back with the reply
+	ReplySig []byte `json:"reply_sig"`
+}
+
+// Reply is the result of a submessage, as passed to the contract
+type Reply struct {
+	ID     uint64       `json:"id"`
+	Result SubMsgResult `json:"result"`
+}
+
+// SubMsgResult is an rust enum and only (exactly) one of the fields should be set
+type SubMsgResult struct {
+	Ok    *SubMsgExecutionResponse `json:"ok,omitempty"`
+	Error string                   `json:"error,omitempty"`
+}
+
+type SubMsgExecutionResponse struct {
+	Data []byte `json:"data"`
+}
+
+// SignedReply is the message passed to the enclave's reply entry point.
+// The enclave verifies ReplySig and encrypts the output of the reply with Nonce and UserPublicKey,
+// which are those of the message that sent the submessage. When the submessage called a contract,
+// an Ok Result is only accepted with the ResultSig the enclave signed it with for that call.
+type SignedReply struct {
+	ID            uint64       `json:"id"`
+	Result        SubMsgResult `json:"result"`
+	ReplySig      []byte       `json:"reply_sig"`
+	ResultSig     []byte       `json:"result_sig,omitempty"`
+	Nonce         []byte       `json:"nonce"`
+	UserPublicKey []byte       `json:"user_public_key"`
 }
 
 // LogAttribute
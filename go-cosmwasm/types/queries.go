@@ -97,7 +97,7 @@ type StakingQuery struct {
 	Validators           *ValidatorsQuery         `json:"validators,omitempty"`
 	AllDelegations       *AllDelegationsQuery     `json:"all_delegations,omitempty"`
 	Delegation           *DelegationQuery         `json:"delegation,omitempty"`
-	UnBondingDelegations *UnbondingDeletionsQuery `json:"unbonding_delegations, omitempty"`
+	UnBondingDelegations *UnbondingDeletionsQuery `json:"unbonding_delegations,omitempty"`
 	BondedDenom          *struct{}                `json:"bonded_denom,omitempty"`
 }
 
@@ -215,8 +215,10 @@ type BondedDenomResponse struct {
 }
 
 type WasmQuery struct {
-	Smart *SmartQuery `json:"smart,omitempty"`
-	Raw   *RawQuery   `json:"raw,omitempty"`
+	Smart       *SmartQuery       `json:"smart,omitempty"`
+	Raw         *RawQuery         `json:"raw,omitempty"`
+	// ContractKey is only made by the enclave, for the assert_contract_code_hash import
+	ContractKey *ContractKeyQuery `json:"contract_key,omitempty"`
 }
 
 // SmartQuery respone is raw bytes ([]byte)
@@ -231,6 +233,19 @@ type RawQuery struct {
 	Key          []byte `json:"key"`
 }
 
+// ContractKeyQuery response is a ContractKeyResponse
+type ContractKeyQuery struct {
+	ContractAddr string `json:"contract_addr"`
+}
+
+// ContractKeyResponse has the contract key stored for the contract and the hash of the code it
+// runs, or is empty if there's no contract at the address. The enclave only trusts the code hash if
+// it authenticated the key for it
+type ContractKeyResponse struct {
+	ContractKey []byte `json:"contract_key,omitempty"`
+	CodeHash    []byte `json:"code_hash,omitempty"`
+}
+
 type DistQuery struct {
 	Rewards *RewardsQuery `json:"rewards,omitempty"`
 }
@@ -2,6 +2,8 @@ package types
 
 import (
 	"encoding/json"
+	"fmt"
+	"math"
 	"strconv"
 
 	"github.com/enigmampc/cosmos-sdk/x/auth"
@@ -63,5 +65,186 @@ func (o OutOfGasError) Error() string {
 type VerificationInfo struct {
 	Bytes             []byte            `json:"sign_bytes"`
 	Signature         auth.StdSignature `json:"signature"`
-	CallbackSignature []byte            `json:"callback_sig"` // Optional
+	AdditionalSigners []SignerInfo      `json:"additional_signers,omitempty"` // Optional
+	CallbackSignature []byte            `json:"callback_sig"`                 // Optional
+	SignMode          SignMode          `json:"sign_mode,omitempty"`          // Optional, defaults to SignModeLegacyAminoJSON
+}
+
+// SignMode is how the signers of a tx encoded the sign docs they signed
+type SignMode string
+
+const (
+	// SignModeLegacyAminoJSON means the sign bytes are the amino JSON StdSignDoc of the tx
+	SignModeLegacyAminoJSON SignMode = "SIGN_MODE_LEGACY_AMINO_JSON"
+	// SignModeDirect means the sign bytes are the protobuf SignDoc of the tx
+	SignModeDirect SignMode = "SIGN_MODE_DIRECT"
+)
+
+// SignerInfo is the signature of a single tx signer, along with the bytes that this signer signed
+type SignerInfo struct {
+	Bytes     []byte            `json:"sign_bytes"`
+	Signature auth.StdSignature `json:"signature"`
+}
+
+// HealthCheckDeep reports which subsystems of the enclave passed the deep health check
+type HealthCheckDeep struct {
+	// Seed is true if the consensus seed was unsealed and a key could be derived from it
+	Seed bool `json:"seed"`
+	// Encryption is true if a value survived encryption and decryption with the derived key
+	Encryption bool `json:"encryption"`
+	// Storage is true if a value survived a write and a read through the storage interface of the enclave
+	Storage bool `json:"storage"`
+	// RegistrationCert is true if the attestation certificate of the node could be loaded
+	RegistrationCert bool `json:"registration_cert"`
+	// SeedStatus is "ready" if the consensus seed could be unsealed, or else why it couldn't:
+	// "missing file", "wrong measurement", "corrupted blob" or "decommissioned". Contracts can't run
+	// unless it's ready
+	SeedStatus string `json:"seed_status"`
+}
+
+// InputVerification describes whether the encrypted message of a tx decrypts in the enclave
+type InputVerification struct {
+	// Decrypted is true if the message decrypted
+	Decrypted bool `json:"decrypted"`
+	// PlaintextLen is the length of the decrypted message, or 0 if it didn't decrypt
+	PlaintextLen uint64 `json:"plaintext_len"`
+	// FailedStep is the step that failed: "parse", "public_key", "key_derivation" or "decryption".
+	// It is empty if the message decrypted
+	FailedStep string `json:"failed_step"`
+	// Error describes why the step failed
+	Error string `json:"error"`
+}
+
+// GasBreakdown describes where the gas used by a successful execution was spent.
+// The gas the enclave reports as used is Wasm + Ocall. Storage was charged to the gas meter while
+// the contract accessed its storage, so the execution cost the sum of all three.
+type GasBreakdown struct {
+	// Wasm is the gas used by wasm instructions, minus the refund for storage the contract removed
+	Wasm uint64 `json:"wasm"`
+	// Storage is the gas charged for the storage operations of the contract
+	Storage uint64 `json:"storage"`
+	// Ocall is the fixed gas charged for every round trip out of the enclave
+	Ocall uint64 `json:"ocall"`
+}
+
+// Total is the gas the execution cost
+func (g GasBreakdown) Total() uint64 {
+	return g.Wasm + g.Storage + g.Ocall
+}
+
+// RuntimeStats describes the memory usage of the enclave
+type RuntimeStats struct {
+	// AllocatedBytes is the amount of bytes currently allocated on the enclave heap
+	AllocatedBytes uint64 `json:"allocated_bytes"`
+	// PeakAllocatedBytes is the most bytes that were allocated on the enclave heap at once since
+	// the stats were last read
+	PeakAllocatedBytes uint64 `json:"peak_allocated_bytes"`
+	// SafetyBufferChunks is the amount of 1 KiB chunks held by the OOM safety buffer
+	SafetyBufferChunks uint64 `json:"safety_buffer_chunks"`
+	// SafetyBufferTargetChunks is the amount of 1 KiB chunks the OOM safety buffer tries to hold
+	SafetyBufferTargetChunks uint64 `json:"safety_buffer_target_chunks"`
+	// AllocateStackLen is the amount of enclave buffers that were allocated for ocalls and not released yet
+	AllocateStackLen uint64 `json:"allocate_stack_len"`
+	// PanicsCaught is the amount of panics caught in the enclave since it was started
+	PanicsCaught uint64 `json:"panics_caught"`
+	// ModuleCacheMisses is the amount of times the enclave had to validate a contract because its
+	// module wasn't cached
+	ModuleCacheMisses uint64 `json:"module_cache_misses"`
+	// PrefetchHits is the amount of storage reads the enclave answered with a value it read ahead
+	PrefetchHits uint64 `json:"prefetch_hits"`
+	// PrefetchMisses is the amount of storage reads that went to the host in executions that read
+	// ahead
+	PrefetchMisses uint64 `json:"prefetch_misses"`
+}
+
+// Statuses returned by PrewarmContracts for every code id
+const (
+	PrewarmWarmed           = "warmed"
+	PrewarmAlreadyCached    = "already cached"
+	PrewarmInvalidEntry     = "invalid entry"
+	PrewarmCodeHashMismatch = "code hash mismatch"
+	PrewarmInvalidWasm      = "invalid wasm"
+	PrewarmOverBudget       = "over budget"
+	PrewarmNotFound         = "not found"
+)
+
+// PanicReport describes the last panic in the enclave
+type PanicReport struct {
+	// Summary is which ecall panicked and where, without the panic message
+	Summary string `json:"summary"`
+	// SealedReport holds the panic message, location and backtrace, sealed to the identity of the
+	// enclave. Only the enclave that panicked can unseal it
+	SealedReport []byte `json:"sealed_report"`
+}
+
+// EnclaveLogLevelKeep is the log level that makes api.ConfigureEnclaveRuntime keep the level the
+// enclave has
+const EnclaveLogLevelKeep uint8 = math.MaxUint8
+
+// EnclaveLogLevels are the log levels api.ConfigureEnclaveRuntime takes, by their index
+var EnclaveLogLevels = []string{"off", "error", "warn", "info", "debug", "trace"}
+
+// The optional entry points of contracts an enclave can call, as bits of EnclaveCapabilities.EntryPoints
+const (
+	EntryPointMigrate uint32 = 1 << iota
+	EntryPointReply
+	EntryPointIBC
+)
+
+// The optional imports an enclave can offer to contracts, as bits of EnclaveCapabilities.HostFunctions
+const (
+	HostFunctionAddr uint32 = 1 << iota
+	HostFunctionDbIterator
+	HostFunctionDbReadMulti
+	HostFunctionSignatures
+	HostFunctionHashes
+	HostFunctionRandom
+	HostFunctionMemoryGrow
+	HostFunctionHkdf
+	HostFunctionDbNamespaces
+	HostFunctionSecp256k1Signing
+	HostFunctionGasLeft
+	HostFunctionForeignHeaders
+	HostFunctionContractCodeHash
+	HostFunctionDbStorageUsage
+)
+
+// EnclaveCapabilities describes the version of the enclave and the features it supports.
+// Enclaves that predate it report 0 for everything, and enclaves that predate a field report 0
+// for that field, which means the feature isn't supported
+type EnclaveCapabilities struct {
+	VersionMajor uint32 `json:"version_major"`
+	VersionMinor uint32 `json:"version_minor"`
+	VersionPatch uint32 `json:"version_patch"`
+	// CosmWasmAPIMin and CosmWasmAPIMax are the oldest and newest cosmwasm_vm_version_* exports of
+	// contracts the enclave runs
+	CosmWasmAPIMin uint32 `json:"cosmwasm_api_min"`
+	CosmWasmAPIMax uint32 `json:"cosmwasm_api_max"`
+	// EntryPoints holds the EntryPoint* bits of the entry points the enclave can call
+	EntryPoints uint32 `json:"entry_points"`
+	// HostFunctions holds the HostFunction* bits of the imports the enclave offers to contracts
+	HostFunctions uint32 `json:"host_functions"`
+}
+
+// Version is the semver of the enclave
+func (c EnclaveCapabilities) Version() string {
+	return fmt.Sprintf("%d.%d.%d", c.VersionMajor, c.VersionMinor, c.VersionPatch)
+}
+
+// RequireEntryPoint fails if the enclave can't call the given EntryPoint* entry point.
+// feature names what needs it in the error, e.g. "submessages"
+func (c EnclaveCapabilities) RequireEntryPoint(entryPoint uint32, feature string) error {
+	if c.EntryPoints&entryPoint != entryPoint {
+		return fmt.Errorf("enclave too old for %s", feature)
+	}
+	return nil
+}
+
+// RequireHostFunction fails if the enclave doesn't offer the given HostFunction* imports.
+// feature names what needs them in the error
+func (c EnclaveCapabilities) RequireHostFunction(hostFunction uint32, feature string) error {
+	if c.HostFunctions&hostFunction != hostFunction {
+		return fmt.Errorf("enclave too old for %s", feature)
+	}
+	return nil
 }
@@ -0,0 +1,37 @@
+package types
+
+import (
+	"encoding/json"
+	"testing"
+
+	"github.com/stretchr/testify/assert"
+	"github.com/stretchr/testify/require"
+)
+
+func TestEnclaveCapabilitiesFromOlderEnclave(t *testing.T) {
+	// an enclave that didn't report host functions yet
+	var capabilities EnclaveCapabilities
+	err := json.Unmarshal([]byte(`{"version_major":1,"version_minor":2,"version_patch":3,"cosmwasm_api_min":3,"cosmwasm_api_max":3,"entry_points":3}`), &capabilities)
+	require.NoError(t, err)
+
+	assert.Equal(t, "1.2.3", capabilities.Version())
+	assert.NoError(t, capabilities.RequireEntryPoint(EntryPointReply, "submessages"))
+	assert.EqualError(t, capabilities.RequireEntryPoint(EntryPointIBC, "IBC"), "enclave too old for IBC")
+	assert.EqualError(t, capabilities.RequireHostFunction(HostFunctionDbReadMulti, "db_read_multi"), "enclave too old for db_read_multi")
+
+	// an enclave that predates the capabilities
+	err = json.Unmarshal([]byte(`{"version_major":0,"version_minor":0,"version_patch":0,"cosmwasm_api_min":0,"cosmwasm_api_max":0,"entry_points":0,"host_functions":0}`), &capabilities)
+	require.NoError(t, err)
+	assert.EqualError(t, capabilities.RequireEntryPoint(EntryPointReply, "submessages"), "enclave too old for submessages")
+}
+
+func TestEnclaveCapabilitiesFromNewerEnclave(t *testing.T) {
+	// fields and bits this node doesn't know about are ignored
+	var capabilities EnclaveCapabilities
+	err := json.Unmarshal([]byte(`{"version_major":2,"version_minor":0,"version_patch":0,"cosmwasm_api_min":3,"cosmwasm_api_max":4,"entry_points":15,"host_functions":255,"unknown_field":7}`), &capabilities)
+	require.NoError(t, err)
+
+	assert.Equal(t, uint32(4), capabilities.CosmWasmAPIMax)
+	assert.NoError(t, capabilities.RequireEntryPoint(EntryPointMigrate|EntryPointReply|EntryPointIBC, "everything"))
+	assert.NoError(t, capabilities.RequireHostFunction(HostFunctionAddr|HostFunctionMemoryGrow, "everything"))
+}
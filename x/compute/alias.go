@@ -16,6 +16,7 @@ const (
 	TStoreKey                     = types.TStoreKey
 	QuerierRoute                  = types.QuerierRoute
 	RouterKey                     = types.RouterKey
+	DefaultParamspace             = types.DefaultParamspace
 	MaxWasmSize                   = types.MaxWasmSize
 	MaxLabelSize                  = types.MaxLabelSize
 	BuildTagRegexp                = types.BuildTagRegexp
@@ -41,7 +42,8 @@ const (
 var (
 	// functions aliases
 	// ConvertToProposals        = types.ConvertToProposals
-	// DefaultParams             = types.DefaultParams
+	DefaultParams             = types.DefaultParams
+	ParamKeyTable             = types.ParamKeyTable
 	RegisterCodec             = types.RegisterCodec
 	ValidateGenesis           = types.ValidateGenesis
 	GetCodeKey                = types.GetCodeKey
@@ -65,6 +67,7 @@ var (
 	EncodeStakingMsg          = keeper.EncodeStakingMsg
 	EncodeWasmMsg             = keeper.EncodeWasmMsg
 	NewKeeper                 = keeper.NewKeeper
+	NewRPCHeaderSource        = keeper.NewRPCHeaderSource
 	NewQuerier                = keeper.NewQuerier
 	DefaultQueryPlugins       = keeper.DefaultQueryPlugins
 	BankQuerier               = keeper.BankQuerier
@@ -89,6 +92,7 @@ var (
 	ErrNotFound          = types.ErrNotFound
 	ErrQueryFailed       = types.ErrQueryFailed
 	ErrInvalidMsg        = types.ErrInvalidMsg
+	ErrKeyRotationFailed = types.ErrKeyRotationFailed
 	KeyLastCodeID        = types.KeyLastCodeID
 	KeyLastInstanceID    = types.KeyLastInstanceID
 	CodeKeyPrefix        = types.CodeKeyPrefix
@@ -96,16 +100,22 @@ var (
 	ContractStorePrefix  = types.ContractStorePrefix
 	// EnableAllProposals   = types.EnableAllProposals
 	// DisableAllProposals  = types.DisableAllProposals
+
+	// foreign chains
+	NewForeignChainProposalHandler = keeper.NewForeignChainProposalHandler
+	ErrForeignChainFailed          = types.ErrForeignChainFailed
 )
 
 type (
 	// ProposalType            = types.ProposalType
 	GenesisState            = types.GenesisState
+	Params                  = types.Params
 	Code                    = types.Code
 	Contract                = types.Contract
 	MsgStoreCode            = types.MsgStoreCode
 	MsgInstantiateContract  = types.MsgInstantiateContract
 	MsgExecuteContract      = types.MsgExecuteContract
+	MsgRotateContractKey    = types.MsgRotateContractKey
 	Model                   = types.Model
 	CodeInfo                = types.CodeInfo
 	ContractInfo            = types.ContractInfo
@@ -125,7 +135,12 @@ type (
 	QueryHandler            = keeper.QueryHandler
 	CustomQuerier           = keeper.CustomQuerier
 	QueryPlugins            = keeper.QueryPlugins
-	// MsgMigrateContract      = types.MsgMigrateContract
-	// MsgUpdateAdmin          = types.MsgUpdateAdmin
-	// MsgClearAdmin           = types.MsgClearAdmin
+	MsgMigrateContract      = types.MsgMigrateContract
+	MsgUpdateAdmin          = types.MsgUpdateAdmin
+	MsgClearAdmin           = types.MsgClearAdmin
+	MsgImportContractState  = types.MsgImportContractState
+
+	// foreign chains
+	MsgSubmitForeignHeader    = types.MsgSubmitForeignHeader
+	TrackForeignChainProposal = types.TrackForeignChainProposal
 )
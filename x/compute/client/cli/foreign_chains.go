@@ -0,0 +1,110 @@
+package cli
+
+import (
+	"bufio"
+	"encoding/json"
+	"io/ioutil"
+	"strconv"
+
+	"github.com/spf13/cobra"
+	"github.com/spf13/viper"
+
+	"github.com/enigmampc/cosmos-sdk/client/context"
+	"github.com/enigmampc/cosmos-sdk/codec"
+	sdk "github.com/enigmampc/cosmos-sdk/types"
+	"github.com/enigmampc/cosmos-sdk/x/auth"
+	"github.com/enigmampc/cosmos-sdk/x/auth/client/utils"
+	govcli "github.com/enigmampc/cosmos-sdk/x/gov/client/cli"
+	govtypes "github.com/enigmampc/cosmos-sdk/x/gov/types"
+
+	wasmTypes "github.com/enigmampc/SecretNetwork/go-cosmwasm/types"
+	"github.com/enigmampc/SecretNetwork/x/compute/internal/types"
+)
+
+// SubmitForeignHeaderCmd hands a header of a tracked chain to the enclave
+func SubmitForeignHeaderCmd(cdc *codec.Codec) *cobra.Command {
+	cmd := &cobra.Command{
+		Use:   "submit-foreign-header [header_file]",
+		Short: "Submit a header of a chain that contracts verify the headers of",
+		Long: `Submit a header of a chain that contracts verify the headers of, as a JSON ForeignHeader with the commit
+and validators that signed it. A header that skips blocks also needs the validators the last verified header of the
+chain chose, in "trusted_validators". Once the enclave verified the header, newer headers of the chain are verified
+against it, so submitting headers keeps the chain within its trusting period.`,
+		Args: cobra.ExactArgs(1),
+		RunE: func(cmd *cobra.Command, args []string) error {
+			inBuf := bufio.NewReader(cmd.InOrStdin())
+			txBldr := auth.NewTxBuilderFromCLI(inBuf).WithTxEncoder(utils.GetTxEncoder(cdc))
+			cliCtx := context.NewCLIContextWithInput(inBuf).WithCodec(cdc)
+
+			submissionBytes, err := ioutil.ReadFile(args[0])
+			if err != nil {
+				return err
+			}
+			var submission wasmTypes.ForeignHeader
+			if err := json.Unmarshal(submissionBytes, &submission); err != nil {
+				return err
+			}
+
+			msg := types.MsgSubmitForeignHeader{
+				Sender:     cliCtx.GetFromAddress(),
+				Submission: submission,
+			}
+			if err := msg.ValidateBasic(); err != nil {
+				return err
+			}
+			return utils.GenerateOrBroadcastMsgs(cliCtx, txBldr, []sdk.Msg{msg})
+		},
+	}
+	return cmd
+}
+
+// ProposalTrackForeignChainCmd proposes that contracts verify the headers of another chain
+func ProposalTrackForeignChainCmd(cdc *codec.Codec) *cobra.Command {
+	cmd := &cobra.Command{
+		Use:   "track-foreign-chain [header_file] [trusting_period_seconds] --title [text] --description [text] --deposit [coins]",
+		Short: "Submit a proposal to verify the headers of another chain for contracts",
+		Long: `Submit a proposal to verify the headers of another chain for contracts, starting from the header in
+header_file, as a JSON BlockHeader. The header is trusted as it is, so voters must check that it is a header of the
+chain. A verified header vouches for newer ones for trusting_period_seconds, which must be less than the unbonding
+period of the chain. Proposing a chain that is already tracked starts over from the new header.`,
+		Args: cobra.ExactArgs(2),
+		RunE: func(cmd *cobra.Command, args []string) error {
+			inBuf := bufio.NewReader(cmd.InOrStdin())
+			txBldr := auth.NewTxBuilderFromCLI(inBuf).WithTxEncoder(utils.GetTxEncoder(cdc))
+			cliCtx := context.NewCLIContextWithInput(inBuf).WithCodec(cdc)
+
+			headerBytes, err := ioutil.ReadFile(args[0])
+			if err != nil {
+				return err
+			}
+			var header wasmTypes.BlockHeader
+			if err := json.Unmarshal(headerBytes, &header); err != nil {
+				return err
+			}
+			trustingPeriod, err := strconv.ParseUint(args[1], 10, 64)
+			if err != nil {
+				return err
+			}
+			deposit, err := sdk.ParseCoins(viper.GetString(govcli.FlagDeposit))
+			if err != nil {
+				return err
+			}
+
+			content := types.TrackForeignChainProposal{
+				Title:                 viper.GetString(govcli.FlagTitle),
+				Description:           viper.GetString(govcli.FlagDescription),
+				Header:                header,
+				TrustingPeriodSeconds: trustingPeriod,
+			}
+			msg := govtypes.NewMsgSubmitProposal(content, deposit, cliCtx.GetFromAddress())
+			if err := msg.ValidateBasic(); err != nil {
+				return err
+			}
+			return utils.GenerateOrBroadcastMsgs(cliCtx, txBldr, []sdk.Msg{msg})
+		},
+	}
+	cmd.Flags().String(govcli.FlagTitle, "", "title of proposal")
+	cmd.Flags().String(govcli.FlagDescription, "", "description of proposal")
+	cmd.Flags().String(govcli.FlagDeposit, "", "deposit of proposal")
+	return cmd
+}
@@ -8,6 +8,8 @@ import (
 	"errors"
 	"fmt"
 	"io/ioutil"
+	"math"
+	"path/filepath"
 	"strconv"
 
 	"github.com/spf13/viper"
@@ -49,6 +51,8 @@ func GetQueryCmd(cdc *codec.Codec) *cobra.Command {
 		GetQueryDecryptTxCmd(cdc),
 		GetCmdQueryLabel(cdc),
 		GetCmdCodeHashByContract(cdc),
+		GetCmdExportContractState(cdc),
+		GetCmdEcallTraces(cdc),
 		CmdDecryptText(cdc),
 		// GetCmdGetContractHistory(cdc),
 	)...)
@@ -133,6 +137,99 @@ func GetCmdCodeHashByContract(cdc *codec.Codec) *cobra.Command {
 	}
 }
 
+// GetCmdExportContractState exports the storage of a contract, one chunk per file
+func GetCmdExportContractState(cdc *codec.Codec) *cobra.Command {
+	cmd := &cobra.Command{
+		Use:   "export-state [bech32_address] [output_dir]",
+		Short: "Export the storage of a contract, for a backup its admin can import later",
+		Long: `Export the storage of a contract, for a backup its admin can import later with "tx compute import-state".
+The storage is written to the output dir one chunk per file, chunk-0.json, chunk-1.json and so on. The values
+stay encrypted, and every entry carries a MAC of the enclave, so the chunks can only be imported back into the
+same contract, unchanged. The export is a snapshot at the height of the block it started at, and any node
+exports the same chunks at that height.`,
+		Args: cobra.ExactArgs(2),
+		RunE: func(cmd *cobra.Command, args []string) error {
+			cliCtx := context.NewCLIContext().WithCodec(cdc)
+
+			if _, err := sdk.AccAddressFromBech32(args[0]); err != nil {
+				return err
+			}
+
+			route := fmt.Sprintf("custom/%s/%s/%s", types.QuerierRoute, keeper.QueryExportState, args[0])
+			request := keeper.ExportStateRequest{ChunkSize: viper.GetUint32(flagChunkSize)}
+			for index := 0; ; index++ {
+				requestBz, err := json.Marshal(request)
+				if err != nil {
+					return err
+				}
+				res, height, err := cliCtx.QueryWithData(route, requestBz)
+				if err != nil {
+					return fmt.Errorf("error exporting contract state: %s", err)
+				}
+				// every chunk must come from the same state
+				if index == 0 {
+					cliCtx = cliCtx.WithHeight(height)
+				}
+
+				var response keeper.ExportStateResponse
+				if err := json.Unmarshal(res, &response); err != nil {
+					return err
+				}
+				if err := ioutil.WriteFile(filepath.Join(args[1], fmt.Sprintf("chunk-%d.json", index)), response.Chunk, 0o600); err != nil {
+					return err
+				}
+				if len(response.NextCursor) == 0 {
+					fmt.Printf("exported %d chunks to %s\n", index+1, args[1])
+					return nil
+				}
+
+				request.Cursor = response.NextCursor
+				request.SnapshotNonce = response.SnapshotNonce
+			}
+		},
+	}
+	cmd.Flags().Uint32(flagChunkSize, 1000, "How many entries of the storage to export in each chunk")
+	return cmd
+}
+
+// GetCmdEcallTraces prints the traces of the last ecalls the enclave of the node ran
+func GetCmdEcallTraces(cdc *codec.Codec) *cobra.Command {
+	return &cobra.Command{
+		Use:   "ecall-traces [max-ecalls]",
+		Short: "Print where the enclave of the node spent its time during the last ecalls",
+		Long: `Print the traces of the last ecalls the enclave of the node ran while tracing was turned on with
+ecall_tracing in the [wasm] section of its app.toml. Every ecall is printed as a tree of its phases and ocalls,
+with when each started and how long it took. The traces are read from the node the command queries, since every
+node traces its own enclave. Tracing slows the enclave down, so only turn it on while looking into its performance.`,
+		Args: cobra.RangeArgs(0, 1),
+		RunE: func(cmd *cobra.Command, args []string) error {
+			cliCtx := context.NewCLIContext().WithCodec(cdc)
+
+			maxEcalls := uint64(math.MaxUint32)
+			if len(args) == 1 {
+				var err error
+				maxEcalls, err = strconv.ParseUint(args[0], 10, 32)
+				if err != nil {
+					return fmt.Errorf("invalid amount of ecalls %s: %s", args[0], err)
+				}
+			}
+
+			route := fmt.Sprintf("custom/%s/%s/%d", types.QuerierRoute, keeper.QueryEcallTraces, maxEcalls)
+			res, _, err := cliCtx.Query(route)
+			if err != nil {
+				return fmt.Errorf("error querying the ecall traces: %s", err)
+			}
+
+			var traces cosmwasmTypes.EcallTraces
+			if err := json.Unmarshal(res, &traces); err != nil {
+				return err
+			}
+			fmt.Print(traces.String())
+			return nil
+		},
+	}
+}
+
 // GetCmdListContractByCode lists all wasm code uploaded for given code id
 func GetCmdListContractByCode(cdc *codec.Codec) *cobra.Command {
 	return &cobra.Command{
@@ -357,18 +454,12 @@ func GetQueryDecryptTxCmd(cdc *amino.Codec) *cobra.Command {
 					return fmt.Errorf("error while trying to decode the encrypted output data from hex string: %w", err)
 				}
 
-				dataPlaintextB64Bz, err := wasmCtx.Decrypt(dataOutputCipherBz, nonce)
+				dataPlaintext, err := wasmCtx.DecryptData(dataOutputCipherBz, nonce)
 				if err != nil {
 					return fmt.Errorf("error while trying to decrypt the output data: %w", err)
 				}
-				dataPlaintextB64 := string(dataPlaintextB64Bz)
-				answer.OutputData = dataPlaintextB64
-
-				dataPlaintext, err := base64.StdEncoding.DecodeString(dataPlaintextB64)
-				if err != nil {
-					return fmt.Errorf("error while trying to decode the decrypted output data from base64: %w", err)
-				}
 
+				answer.OutputData = base64.StdEncoding.EncodeToString(dataPlaintext)
 				answer.OutputDataAsString = string(dataPlaintext)
 			}
 
@@ -377,36 +468,9 @@ func GetQueryDecryptTxCmd(cdc *amino.Codec) *cobra.Command {
 			for _, l := range result.Logs {
 				for _, e := range l.Events {
 					if e.Type == "wasm" {
-						for i, a := range e.Attributes {
-							if a.Key != "contract_address" {
-								// key
-								if a.Key != "" {
-									keyCiphertext, err := base64.StdEncoding.DecodeString(a.Key)
-									if err != nil {
-										return fmt.Errorf("error while trying to decode the log key '%s' from base64: %w", a.Key, err)
-									}
-									keyPlaintext, err := wasmCtx.Decrypt(keyCiphertext, nonce)
-									if err != nil {
-										return fmt.Errorf("error while trying to decrypt the log key '%s' from base64: %w", a.Key, err)
-									}
-									a.Key = string(keyPlaintext)
-								}
-
-								// value
-								if a.Value != "" {
-									valueCiphertext, err := base64.StdEncoding.DecodeString(a.Value)
-									if err != nil {
-										return fmt.Errorf("error while trying to decode the log value '%s' from base64: %w", a.Value, err)
-									}
-									valuePlaintext, err := wasmCtx.Decrypt(valueCiphertext, nonce)
-									if err != nil {
-										return fmt.Errorf("error while trying to decrypt the log value '%s' from base64: %w", a.Value, err)
-									}
-									a.Value = string(valuePlaintext)
-								}
-
-								e.Attributes[i] = a
-							}
+						e.Attributes, err = wasmCtx.DecryptWasmEvent(e.Attributes, nonce)
+						if err != nil {
+							return err
 						}
 						answer.OutputLogs = append(answer.OutputLogs, e)
 					}
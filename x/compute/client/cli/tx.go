@@ -36,6 +36,7 @@ const (
 	flagProposalType           = "type"
 	flagIoMasterKey            = "enclave-key"
 	flagCodeHash               = "code-hash"
+	flagChunkSize              = "chunk-size"
 	// flagAdmin                  = "admin"
 )
 
@@ -52,6 +53,8 @@ func GetTxCmd(cdc *codec.Codec) *cobra.Command {
 		StoreCodeCmd(cdc),
 		InstantiateContractCmd(cdc),
 		ExecuteContractCmd(cdc),
+		ImportContractStateCmd(cdc),
+		SubmitForeignHeaderCmd(cdc),
 		// Currently not supporting these commands
 		//MigrateContractCmd(cdc),
 		//UpdateContractAdminCmd(cdc),
@@ -386,3 +389,40 @@ func GetCodeHashByContractAddr(cliCtx context.CLIContext, contractAddr sdk.AccAd
 
 	return []byte(hex.EncodeToString(res)), nil
 }
+
+// ImportContractStateCmd writes a chunk of an export of the storage of a contract back to it
+func ImportContractStateCmd(cdc *codec.Codec) *cobra.Command {
+	cmd := &cobra.Command{
+		Use:   "import-state [contract_addr_bech32] [chunk_file]",
+		Short: "Import a chunk that \"query compute export-state\" exported back into the contract",
+		Long: `Import a chunk that "query compute export-state" exported back into the contract. Only the admin of the
+contract may import, and only chunks of the same contract that weren't changed. A snapshot older than the last one
+imported into the contract is refused.`,
+		Args: cobra.ExactArgs(2),
+		RunE: func(cmd *cobra.Command, args []string) error {
+			inBuf := bufio.NewReader(cmd.InOrStdin())
+			txBldr := auth.NewTxBuilderFromCLI(inBuf).WithTxEncoder(utils.GetTxEncoder(cdc))
+			cliCtx := context.NewCLIContextWithInput(inBuf).WithCodec(cdc)
+
+			contractAddr, err := sdk.AccAddressFromBech32(args[0])
+			if err != nil {
+				return err
+			}
+			chunk, err := ioutil.ReadFile(args[1])
+			if err != nil {
+				return err
+			}
+
+			msg := types.MsgImportContractState{
+				Sender:   cliCtx.GetFromAddress(),
+				Contract: contractAddr,
+				Chunk:    chunk,
+			}
+			if err := msg.ValidateBasic(); err != nil {
+				return err
+			}
+			return utils.GenerateOrBroadcastMsgs(cliCtx, txBldr, []sdk.Msg{msg})
+		},
+	}
+	return cmd
+}
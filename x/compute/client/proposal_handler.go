@@ -1,5 +1,11 @@
 package client
 
+import (
+	"github.com/enigmampc/SecretNetwork/x/compute/client/cli"
+	"github.com/enigmampc/SecretNetwork/x/compute/client/rest"
+	govclient "github.com/enigmampc/cosmos-sdk/x/gov/client"
+)
+
 /*
 import (
 	"github.com/enigmampc/SecretNetwork/x/compute/client/cli"
@@ -16,3 +22,6 @@ var ProposalHandlers = []govclient.ProposalHandler{
 	govclient.NewProposalHandler(cli.ProposalClearContractAdminCmd, rest.ClearContractAdminProposalHandler),
 }
 */
+
+// TrackForeignChainProposalHandler is the cli and rest handler of TrackForeignChainProposal
+var TrackForeignChainProposalHandler = govclient.NewProposalHandler(cli.ProposalTrackForeignChainCmd, rest.TrackForeignChainProposalHandler)
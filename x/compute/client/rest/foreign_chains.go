@@ -0,0 +1,58 @@
+package rest
+
+import (
+	"net/http"
+
+	"github.com/enigmampc/cosmos-sdk/client/context"
+	sdk "github.com/enigmampc/cosmos-sdk/types"
+	"github.com/enigmampc/cosmos-sdk/types/rest"
+	"github.com/enigmampc/cosmos-sdk/x/auth/client/utils"
+	"github.com/enigmampc/cosmos-sdk/x/gov"
+	govrest "github.com/enigmampc/cosmos-sdk/x/gov/client/rest"
+
+	wasmTypes "github.com/enigmampc/SecretNetwork/go-cosmwasm/types"
+	"github.com/enigmampc/SecretNetwork/x/compute/internal/types"
+)
+
+type TrackForeignChainProposalJsonReq struct {
+	BaseReq rest.BaseReq `json:"base_req" yaml:"base_req"`
+
+	Title       string `json:"title" yaml:"title"`
+	Description string `json:"description" yaml:"description"`
+
+	Proposer sdk.AccAddress `json:"proposer" yaml:"proposer"`
+	Deposit  sdk.Coins      `json:"deposit" yaml:"deposit"`
+
+	Header                wasmTypes.BlockHeader `json:"header" yaml:"header"`
+	TrustingPeriodSeconds uint64                `json:"trusting_period_seconds" yaml:"trusting_period_seconds"`
+}
+
+// TrackForeignChainProposalHandler submits a TrackForeignChainProposal through the REST server
+func TrackForeignChainProposalHandler(cliCtx context.CLIContext) govrest.ProposalRESTHandler {
+	return govrest.ProposalRESTHandler{
+		SubRoute: "track_foreign_chain",
+		Handler: func(w http.ResponseWriter, r *http.Request) {
+			var req TrackForeignChainProposalJsonReq
+			if !rest.ReadRESTReq(w, r, cliCtx.Codec, &req) {
+				return
+			}
+
+			content := types.TrackForeignChainProposal{
+				Title:                 req.Title,
+				Description:           req.Description,
+				Header:                req.Header,
+				TrustingPeriodSeconds: req.TrustingPeriodSeconds,
+			}
+			msg := gov.NewMsgSubmitProposal(content, req.Deposit, req.Proposer)
+			if err := msg.ValidateBasic(); err != nil {
+				rest.WriteErrorResponse(w, http.StatusBadRequest, err.Error())
+				return
+			}
+			baseReq := req.BaseReq.Sanitize()
+			if !baseReq.ValidateBasic(w) {
+				return
+			}
+			utils.WriteGenerateStdTxResponse(w, cliCtx, baseReq, []sdk.Msg{msg})
+		},
+	}
+}
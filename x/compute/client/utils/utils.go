@@ -6,6 +6,7 @@ import (
 	"crypto/rand"
 	"crypto/sha256"
 	"encoding/base64"
+	"encoding/binary"
 	"encoding/hex"
 	"encoding/json"
 	"fmt"
@@ -17,10 +18,12 @@ import (
 	"strings"
 
 	cosmwasmTypes "github.com/enigmampc/SecretNetwork/go-cosmwasm/types"
+	"github.com/enigmampc/SecretNetwork/x/compute/internal/types"
 	regtypes "github.com/enigmampc/SecretNetwork/x/registration"
 	ra "github.com/enigmampc/SecretNetwork/x/registration/remote_attestation"
 
 	"github.com/enigmampc/cosmos-sdk/client/context"
+	sdk "github.com/enigmampc/cosmos-sdk/types"
 	"github.com/miscreant/miscreant.go"
 	"golang.org/x/crypto/curve25519"
 	"golang.org/x/crypto/hkdf"
@@ -230,7 +233,138 @@ func (ctx WASMContext) Decrypt(ciphertext []byte, nonce []byte) ([]byte, error)
 		return []byte{}, nil
 	}
 
+	return ctx.decryptWithAD(ciphertext, nonce, []byte{})
+}
+
+// decryptWithAD decrypts ciphertext that was encrypted for nonce with the associated data ad
+func (ctx WASMContext) decryptWithAD(ciphertext []byte, nonce []byte, ad ...[]byte) ([]byte, error) {
+	txSenderPrivKey, _, err := ctx.GetTxSenderKeyPair()
+	if err != nil {
+		return nil, err
+	}
+
+	txEncryptionKey, err := ctx.getTxEncryptionKey(txSenderPrivKey, nonce)
+	if err != nil {
+		return nil, err
+	}
+
+	cipher, err := miscreant.NewAESCMACSIV(txEncryptionKey)
+	if err != nil {
+		return nil, err
+	}
+
+	return cipher.Open(nil, ciphertext, ad...)
+}
+
+// logAttributeADVersion is the first associated data of the log attributes that the enclave bound
+// to their place in the tx, LOG_ATTRIBUTE_AD_VERSION in the enclave
+const logAttributeADVersion = 1
+
+// decryptLogField decrypts the key or value of a log attribute. When the attribute is bound to its
+// place in the tx, executionPath is the path of the call that emitted it and index is its position
+// in the output of the call. Otherwise executionPath is nil
+func (ctx WASMContext) decryptLogField(field string, name string, nonce []byte, executionPath []uint32, index uint32) (string, error) {
+	if field == "" {
+		return field, nil
+	}
+
+	ciphertext, err := base64.StdEncoding.DecodeString(field)
+	if err != nil {
+		return "", fmt.Errorf("error while trying to decode the log %s '%s' from base64: %w", name, field, err)
+	}
+
+	var plaintext []byte
+	if executionPath == nil {
+		plaintext, err = ctx.Decrypt(ciphertext, nonce)
+	} else {
+		plaintext, err = ctx.decryptWithAD(ciphertext, nonce, logAttributeAD(executionPath, index, name)...)
+	}
+	if err != nil {
+		return "", fmt.Errorf("error while trying to decrypt the log %s '%s': %w", name, field, err)
+	}
+	return string(plaintext), nil
+}
+
+// logAttributeAD is the associated data that binds the key or value of a log attribute to its
+// place in the tx: [version, path steps as u32 BE, index as u32 BE, "key" or "value"]
+func logAttributeAD(executionPath []uint32, index uint32, name string) [][]byte {
+	path := make([]byte, 4*len(executionPath))
+	for i, step := range executionPath {
+		binary.BigEndian.PutUint32(path[4*i:], step)
+	}
+	indexBz := make([]byte, 4)
+	binary.BigEndian.PutUint32(indexBz, index)
+
+	return [][]byte{{logAttributeADVersion}, path, indexBz, []byte(name)}
+}
+
+// DecryptWasmEvent decrypts the attributes of a "wasm" event that were encrypted with nonce. The
+// event may hold the attributes of several contract calls. The attributes of each call start with
+// its plaintext contract_address, and its execution_path if the enclave bound the attributes of
+// the call to their place in the tx. These are returned as they are, and the path is used to
+// decrypt the attributes after them, so an attribute that was moved or repeated doesn't decrypt.
+// Attributes that don't decrypt, like the ones the contract emitted in plaintext, are returned as
+// they are too
+func (ctx WASMContext) DecryptWasmEvent(attributes []sdk.Attribute, nonce []byte) ([]sdk.Attribute, error) {
+	decrypted := make([]sdk.Attribute, 0, len(attributes))
+	var executionPath []uint32
+	var index uint32
+	// the module only tags a call with its path right after its address
+	afterContractAddr := false
+	for _, attr := range attributes {
+		switch {
+		case attr.Key == types.AttributeKeyContractAddr:
+			executionPath = nil
+			index = 0
+			afterContractAddr = true
+			decrypted = append(decrypted, attr)
+			continue
+		case attr.Key == types.AttributeKeyExecutionPath && afterContractAddr:
+			if err := json.Unmarshal([]byte(attr.Value), &executionPath); err != nil {
+				return nil, fmt.Errorf("error while trying to parse the execution path '%s': %w", attr.Value, err)
+			}
+			if executionPath == nil {
+				return nil, fmt.Errorf("execution path is null")
+			}
+		default:
+			key, keyErr := ctx.decryptLogField(attr.Key, "key", nonce, executionPath, index)
+			value, valueErr := ctx.decryptLogField(attr.Value, "value", nonce, executionPath, index)
+			if keyErr == nil && valueErr == nil {
+				attr = sdk.Attribute{Key: key, Value: value}
+			}
+			index++
+		}
+		afterContractAddr = false
+		decrypted = append(decrypted, attr)
+	}
+	return decrypted, nil
+}
+
+// rawDataVersion is the first byte of the plaintext of an encrypted data field. Older enclaves
+// encrypted the base64 encoding of the data, which never starts with this byte
+const rawDataVersion = 1
+
+// DecryptData decrypts the data field of a contract output, in the format of any enclave version
+func (ctx WASMContext) DecryptData(data []byte, nonce []byte) ([]byte, error) {
+	plaintext, err := ctx.Decrypt(data, nonce)
+	if err != nil {
+		return nil, err
+	}
+
+	if len(plaintext) > 0 && plaintext[0] == rawDataVersion {
+		return plaintext[1:], nil
+	}
+	return base64.StdEncoding.DecodeString(string(plaintext))
+}
+
+// DecryptIBCAcknowledgement decrypts the acknowledgement that a secret contract wrote for a
+// packet. nonce is the nonce of the encrypted data of the packet, and srcPort, srcChannel and
+// sequence identify the packet, as seen from the chain that sent it
+func (ctx WASMContext) DecryptIBCAcknowledgement(ack []byte, nonce []byte, srcPort string, srcChannel string, sequence uint64) ([]byte, error) {
 	txSenderPrivKey, _, err := ctx.GetTxSenderKeyPair()
+	if err != nil {
+		return nil, err
+	}
 
 	txEncryptionKey, err := ctx.getTxEncryptionKey(txSenderPrivKey, nonce)
 	if err != nil {
@@ -242,7 +376,10 @@ func (ctx WASMContext) Decrypt(ciphertext []byte, nonce []byte) ([]byte, error)
 		return nil, err
 	}
 
-	return cipher.Open(nil, ciphertext, []byte{})
+	sequenceBz := make([]byte, 8)
+	binary.BigEndian.PutUint64(sequenceBz, sequence)
+
+	return cipher.Open(nil, ack, []byte(srcPort), []byte(srcChannel), sequenceBz)
 }
 
 func (ctx WASMContext) DecryptError(errString string, msgType string, nonce []byte) (cosmwasmTypes.StdError, error) {
@@ -268,6 +405,10 @@ func (ctx WASMContext) DecryptError(errString string, msgType string, nonce []by
 	return stdErr, nil
 }
 
+// messageAdVersion is the first associated data of an encrypted message, followed by its nonce and
+// the public key of the sender. Enclaves still accept messages without associated data
+const messageAdVersion = 1
+
 func encryptData(aesEncryptionKey []byte, txSenderPubKey []byte, plaintext []byte, nonce []byte) ([]byte, error) {
 	cipher, err := miscreant.NewAESCMACSIV(aesEncryptionKey)
 	if err != nil {
@@ -275,7 +416,8 @@ func encryptData(aesEncryptionKey []byte, txSenderPubKey []byte, plaintext []byt
 		return nil, err
 	}
 
-	ciphertext, err := cipher.Seal(nil, plaintext, []byte{})
+	// The enclave binds the message to the nonce and the public key it was sent with
+	ciphertext, err := cipher.Seal(nil, plaintext, []byte{messageAdVersion}, nonce, txSenderPubKey)
 	if err != nil {
 		log.Println(err)
 		return nil, err
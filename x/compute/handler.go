@@ -21,14 +21,18 @@ func NewHandler(k Keeper) sdk.Handler {
 			return handleInstantiate(ctx, k, &msg)
 		case MsgExecuteContract:
 			return handleExecute(ctx, k, &msg)
-			/*
-				case MsgMigrateContract:
-					return handleMigration(ctx, k, &msg)
-				case MsgUpdateAdmin:
-					return handleUpdateContractAdmin(ctx, k, &msg)
-				case MsgClearAdmin:
-					return handleClearContractAdmin(ctx, k, &msg)
-			*/
+		case MsgRotateContractKey:
+			return handleRotateContractKey(ctx, k, &msg)
+		case MsgMigrateContract:
+			return handleMigration(ctx, k, &msg)
+		case MsgUpdateAdmin:
+			return handleUpdateContractAdmin(ctx, k, &msg)
+		case MsgClearAdmin:
+			return handleClearContractAdmin(ctx, k, &msg)
+		case MsgImportContractState:
+			return handleImportContractState(ctx, k, &msg)
+		case MsgSubmitForeignHeader:
+			return handleSubmitForeignHeader(ctx, k, &msg)
 		default:
 			errMsg := fmt.Sprintf("unrecognized wasm message type: %T", msg)
 			return nil, sdkerrors.Wrap(sdkerrors.ErrUnknownRequest, errMsg)
@@ -75,7 +79,7 @@ func handleStoreCode(ctx sdk.Context, k Keeper, msg *MsgStoreCode) (*sdk.Result,
 }
 
 func handleInstantiate(ctx sdk.Context, k Keeper, msg *MsgInstantiateContract) (*sdk.Result, error) {
-	contractAddr, err := k.Instantiate(ctx, msg.CodeID, msg.Sender, msg.InitMsg, msg.Label, msg.InitFunds, msg.CallbackSignature)
+	contractAddr, err := k.Instantiate(ctx, msg.CodeID, msg.Sender, msg.Admin, msg.InitMsg, msg.Label, msg.InitFunds, msg.CallbackSignature)
 	if err != nil {
 		return nil, err
 	}
@@ -89,9 +93,8 @@ func handleInstantiate(ctx sdk.Context, k Keeper, msg *MsgInstantiateContract) (
 		sdk.NewAttribute(types.AttributeKeyContract, contractAddr.String()),
 	)
 
-	// TODO Assaf:
-	// also need to parse here output events and pass them to Tendermint
-	// but k.Instantiate() doesn't return any output data right now, just contractAddr
+	// the events the contract logged during init were already emitted by the keeper, encrypted
+	// to the sender like the events of an execute
 
 	return &sdk.Result{
 		Data:   contractAddr,
@@ -125,7 +128,31 @@ func handleExecute(ctx sdk.Context, k Keeper, msg *MsgExecuteContract) (*sdk.Res
 	return res, nil
 }
 
-/*
+func handleRotateContractKey(ctx sdk.Context, k Keeper, msg *MsgRotateContractKey) (*sdk.Result, error) {
+	err := msg.ValidateBasic()
+	if err != nil {
+		return nil, err
+	}
+
+	done, err := k.RotateContractKey(ctx, msg.Contract, msg.Sender, msg.ChunkSize)
+	if err != nil {
+		return nil, err
+	}
+
+	events := filterMessageEvents(ctx.EventManager())
+	ourEvent := sdk.NewEvent(
+		sdk.EventTypeMessage,
+		sdk.NewAttribute(sdk.AttributeKeyModule, ModuleName),
+		sdk.NewAttribute(types.AttributeKeySigner, msg.Sender.String()),
+		sdk.NewAttribute(types.AttributeKeyContract, msg.Contract.String()),
+		sdk.NewAttribute(types.AttributeKeyKeyRotationDone, fmt.Sprintf("%t", done)),
+	)
+
+	return &sdk.Result{
+		Events: append(events, ourEvent),
+	}, nil
+}
+
 func handleMigration(ctx sdk.Context, k Keeper, msg *MsgMigrateContract) (*sdk.Result, error) {
 	res, err := k.Migrate(ctx, msg.Contract, msg.Sender, msg.CodeID, msg.MigrateMsg) // for MsgMigrateContract, there is only one signer which is msg.Sender (https://github.com/enigmampc/SecretNetwork/blob/d7813792fa07b93a10f0885eaa4c5e0a0a698854/x/compute/internal/types/msg.go#L228-L230)
 	if err != nil {
@@ -174,4 +201,53 @@ func handleClearContractAdmin(ctx sdk.Context, k Keeper, msg *MsgClearAdmin) (*s
 		Events: append(events, ourEvent),
 	}, nil
 }
-*/
+
+func handleImportContractState(ctx sdk.Context, k Keeper, msg *MsgImportContractState) (*sdk.Result, error) {
+	err := msg.ValidateBasic()
+	if err != nil {
+		return nil, err
+	}
+
+	imported, err := k.ImportContractState(ctx, msg.Contract, msg.Sender, msg.Chunk)
+	if err != nil {
+		return nil, err
+	}
+
+	events := filterMessageEvents(ctx.EventManager())
+	ourEvent := sdk.NewEvent(
+		sdk.EventTypeMessage,
+		sdk.NewAttribute(sdk.AttributeKeyModule, ModuleName),
+		sdk.NewAttribute(types.AttributeKeySigner, msg.Sender.String()),
+		sdk.NewAttribute(types.AttributeKeyContract, msg.Contract.String()),
+		sdk.NewAttribute(types.AttributeKeyImportedEntries, fmt.Sprintf("%d", imported)),
+	)
+
+	return &sdk.Result{
+		Events: append(events, ourEvent),
+	}, nil
+}
+
+func handleSubmitForeignHeader(ctx sdk.Context, k Keeper, msg *MsgSubmitForeignHeader) (*sdk.Result, error) {
+	err := msg.ValidateBasic()
+	if err != nil {
+		return nil, err
+	}
+
+	err = k.SubmitForeignHeader(ctx, msg.Submission)
+	if err != nil {
+		return nil, err
+	}
+
+	events := filterMessageEvents(ctx.EventManager())
+	ourEvent := sdk.NewEvent(
+		sdk.EventTypeMessage,
+		sdk.NewAttribute(sdk.AttributeKeyModule, ModuleName),
+		sdk.NewAttribute(types.AttributeKeySigner, msg.Sender.String()),
+		sdk.NewAttribute(types.AttributeKeyForeignChainID, msg.Submission.Header.ChainID),
+		sdk.NewAttribute(types.AttributeKeyForeignHeight, fmt.Sprintf("%d", msg.Submission.Header.Height)),
+	)
+
+	return &sdk.Result{
+		Events: append(events, ourEvent),
+	}, nil
+}
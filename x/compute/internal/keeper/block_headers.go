@@ -0,0 +1,132 @@
+package keeper
+
+import (
+	"fmt"
+
+	sdk "github.com/enigmampc/cosmos-sdk/types"
+	"github.com/tendermint/tendermint/libs/log"
+	rpchttp "github.com/tendermint/tendermint/rpc/client/http"
+	tmtypes "github.com/tendermint/tendermint/types"
+
+	"github.com/enigmampc/SecretNetwork/x/compute/internal/types"
+)
+
+// maxHeaderCatchUp is the most headers submitBlockHeaders hands to the enclave in one round. The
+// enclave only accepts the header after the last one it verified, so a node that fell behind
+// catches up over a few rounds instead of one long one.
+const maxHeaderCatchUp = 10
+
+// headerRPCTimeoutSeconds bounds how long a round waits for Tendermint to return a header
+const headerRPCTimeoutSeconds = 5
+
+// SignedHeaderSource returns the header of a block of this chain, with the commit and validators
+// that signed it. The app only gets the header of the current block, which isn't signed until the
+// next block, so the signed headers come from the Tendermint of the node.
+type SignedHeaderSource interface {
+	SignedHeader(height int64) (tmtypes.SignedHeader, *tmtypes.ValidatorSet, error)
+}
+
+// rpcHeaderSource reads signed headers from the RPC of a Tendermint node
+type rpcHeaderSource struct {
+	client *rpchttp.HTTP
+}
+
+// NewRPCHeaderSource returns a SignedHeaderSource that reads the headers from the Tendermint RPC
+// at rpcAddr, which is the RPC of the node itself
+func NewRPCHeaderSource(rpcAddr string) (SignedHeaderSource, error) {
+	client, err := rpchttp.NewWithTimeout(rpcAddr, "/websocket", headerRPCTimeoutSeconds)
+	if err != nil {
+		return nil, err
+	}
+	return rpcHeaderSource{client: client}, nil
+}
+
+func (s rpcHeaderSource) SignedHeader(height int64) (tmtypes.SignedHeader, *tmtypes.ValidatorSet, error) {
+	commit, err := s.client.Commit(&height)
+	if err != nil {
+		return tmtypes.SignedHeader{}, nil, err
+	}
+	// the commit of the latest block is only the one this node saw, which may have other signatures
+	// than the one in the next block
+	if !commit.CanonicalCommit {
+		return tmtypes.SignedHeader{}, nil, fmt.Errorf("no canonical commit for height %d yet", height)
+	}
+
+	// validators are returned in the order of the signatures of the commit
+	var validators []*tmtypes.Validator
+	for page := 1; ; page++ {
+		result, err := s.client.Validators(&height, page, 100)
+		if err != nil {
+			return tmtypes.SignedHeader{}, nil, err
+		}
+		validators = append(validators, result.Validators...)
+		if len(result.Validators) == 0 || len(validators) >= result.Total {
+			break
+		}
+	}
+
+	return commit.SignedHeader, &tmtypes.ValidatorSet{Validators: validators}, nil
+}
+
+// SetHeaderSource sets where the signed headers are read from, and starts submitting them to the
+// enclave in the background. Without a source, no headers are submitted.
+func (k *Keeper) SetHeaderSource(source SignedHeaderSource, logger log.Logger) {
+	k.headerSource = source
+	k.headerHeights = make(chan int64, 1)
+	go func(k Keeper) {
+		for last := range k.headerHeights {
+			k.submitBlockHeaders(logger, last)
+		}
+	}(*k)
+}
+
+// SubmitBlockHeaders asks for the signed headers up to the previous block to be handed to the
+// enclave. The commit of a block is part of the block after it, so the previous block is the last
+// one that is signed. The headers are read and submitted in the background, since reading them
+// waits on the RPC of the node, and what happens there only concerns the enclave of this node
+// and never changes the state of the chain. It never blocks: while a round is waiting to start,
+// the heights of the blocks after it are dropped, and the next block asks again.
+func (k Keeper) SubmitBlockHeaders(ctx sdk.Context) {
+	if k.headerSource == nil {
+		return
+	}
+	last := ctx.BlockHeight() - 1
+	if last < 1 {
+		return
+	}
+	select {
+	case k.headerHeights <- last:
+	default:
+	}
+}
+
+// submitBlockHeaders hands the signed headers up to last to the enclave, starting after the last
+// header it submitted
+func (k Keeper) submitBlockHeaders(logger log.Logger, last int64) {
+	// the enclave trusts the first header it gets after it starts, so a node starts at the last one
+	from := *k.submittedHeaderHeight + 1
+	if *k.submittedHeaderHeight == 0 || from > last {
+		from = last
+	}
+	if last-from >= maxHeaderCatchUp {
+		last = from + maxHeaderCatchUp - 1
+	}
+
+	for height := from; height <= last; height++ {
+		signedHeader, validators, err := k.headerSource.SignedHeader(height)
+		if err != nil {
+			logger.Error("failed to read the signed block header", "height", height, "error", err)
+			return
+		}
+		submission := types.NewBlockHeaderSubmission(signedHeader, validators)
+		if submission == nil {
+			logger.Error("the enclave can't verify the block header", "height", height)
+			return
+		}
+		if err := k.wasmer.SubmitBlockHeader(*submission); err != nil {
+			logger.Error("the enclave rejected the block header", "height", height, "error", err)
+			return
+		}
+		*k.submittedHeaderHeight = height
+	}
+}
@@ -0,0 +1,178 @@
+package keeper
+
+import (
+	"encoding/base64"
+	"fmt"
+	"io/ioutil"
+	"os"
+	"testing"
+	"time"
+
+	"github.com/stretchr/testify/require"
+	tmtypes "github.com/tendermint/tendermint/types"
+)
+
+// fakeHeaderSource is a chain of one validator that signs every header, up to latest. It records
+// the heights it was asked for.
+type fakeHeaderSource struct {
+	latest     int64
+	requested  []int64
+	privVal    tmtypes.MockPV
+	validators *tmtypes.ValidatorSet
+	headers    map[int64]tmtypes.SignedHeader
+}
+
+func newFakeHeaderSource(latest int64) *fakeHeaderSource {
+	privVal := tmtypes.NewMockPV()
+	return &fakeHeaderSource{
+		latest:     latest,
+		privVal:    privVal,
+		validators: tmtypes.NewValidatorSet([]*tmtypes.Validator{tmtypes.NewValidator(privVal.PrivKey.PubKey(), 10)}),
+		headers:    make(map[int64]tmtypes.SignedHeader),
+	}
+}
+
+func (s *fakeHeaderSource) SignedHeader(height int64) (tmtypes.SignedHeader, *tmtypes.ValidatorSet, error) {
+	s.requested = append(s.requested, height)
+	if height > s.latest {
+		return tmtypes.SignedHeader{}, nil, fmt.Errorf("no commit for height %d yet", height)
+	}
+	signedHeader, err := s.signedHeader(height)
+	return signedHeader, s.validators, err
+}
+
+func (s *fakeHeaderSource) signedHeader(height int64) (tmtypes.SignedHeader, error) {
+	if signedHeader, ok := s.headers[height]; ok {
+		return signedHeader, nil
+	}
+
+	header := tmtypes.Header{
+		ChainID:            "header-chain",
+		Height:             height,
+		Time:               time.Unix(1_600_000_000+height, 0).UTC(),
+		ValidatorsHash:     s.validators.Hash(),
+		NextValidatorsHash: s.validators.Hash(),
+		ProposerAddress:    s.validators.Validators[0].Address,
+	}
+	if height > 1 {
+		previous, err := s.signedHeader(height - 1)
+		if err != nil {
+			return tmtypes.SignedHeader{}, err
+		}
+		header.LastBlockID = tmtypes.BlockID{Hash: previous.Hash()}
+	}
+
+	voteSet := tmtypes.NewVoteSet(header.ChainID, height, 0, tmtypes.PrecommitType, s.validators)
+	commit, err := tmtypes.MakeCommit(tmtypes.BlockID{Hash: header.Hash()}, height, 0, voteSet, []tmtypes.PrivValidator{s.privVal}, header.Time)
+	if err != nil {
+		return tmtypes.SignedHeader{}, err
+	}
+	signedHeader := tmtypes.SignedHeader{Header: &header, Commit: commit}
+	s.headers[height] = signedHeader
+	return signedHeader, nil
+}
+
+func heights(from int64, to int64) []int64 {
+	var heights []int64
+	for height := from; height <= to; height++ {
+		heights = append(heights, height)
+	}
+	return heights
+}
+
+func TestSubmitBlockHeaders(t *testing.T) {
+	tempDir, err := ioutil.TempDir("", "wasm")
+	require.NoError(t, err)
+	defer os.RemoveAll(tempDir)
+	ctx, keepers := CreateTestInput(t, false, tempDir, SupportedFeatures, nil, nil)
+	keeper := keepers.WasmKeeper
+
+	// without a source nothing is submitted
+	keeper.SubmitBlockHeaders(ctx.WithBlockHeight(5))
+	require.Equal(t, int64(0), *keeper.submittedHeaderHeight)
+
+	source := newFakeHeaderSource(100)
+	keeper.headerSource = source
+
+	// a node starts at the last header
+	keeper.submitBlockHeaders(ctx.Logger(), 4)
+	require.Equal(t, []int64{4}, source.requested)
+	require.Equal(t, int64(4), *keeper.submittedHeaderHeight)
+
+	// and goes on from the last header it submitted, a few headers per round
+	source.requested = nil
+	keeper.submitBlockHeaders(ctx.Logger(), 29)
+	require.Equal(t, heights(5, 4+maxHeaderCatchUp), source.requested)
+	require.Equal(t, int64(4+maxHeaderCatchUp), *keeper.submittedHeaderHeight)
+
+	// a header that can't be read is tried again in the next round
+	source.latest = 20
+	source.requested = nil
+	keeper.submitBlockHeaders(ctx.Logger(), 30)
+	require.Equal(t, heights(5+maxHeaderCatchUp, 21), source.requested)
+	require.Equal(t, int64(20), *keeper.submittedHeaderHeight)
+
+	source.latest = 100
+	source.requested = nil
+	keeper.submitBlockHeaders(ctx.Logger(), 31)
+	require.Equal(t, heights(21, 30), source.requested)
+	require.Equal(t, int64(30), *keeper.submittedHeaderHeight)
+}
+
+// blockingHeaderSource reports the heights it's asked for, and doesn't answer until it's released
+type blockingHeaderSource struct {
+	requested chan int64
+	release   chan struct{}
+}
+
+func (s blockingHeaderSource) SignedHeader(height int64) (tmtypes.SignedHeader, *tmtypes.ValidatorSet, error) {
+	s.requested <- height
+	<-s.release
+	return tmtypes.SignedHeader{}, nil, fmt.Errorf("no commit for height %d yet", height)
+}
+
+func TestSubmitBlockHeadersInBackground(t *testing.T) {
+	tempDir, err := ioutil.TempDir("", "wasm")
+	require.NoError(t, err)
+	defer os.RemoveAll(tempDir)
+	ctx, keepers := CreateTestInput(t, false, tempDir, SupportedFeatures, nil, nil)
+	keeper := keepers.WasmKeeper
+
+	source := blockingHeaderSource{requested: make(chan int64, 100), release: make(chan struct{})}
+	keeper.SetHeaderSource(source, ctx.Logger())
+	defer close(source.release)
+
+	// the first block has no signed header before it, and the blocks after it don't wait for the
+	// source to answer
+	for height := int64(1); height <= 20; height++ {
+		keeper.SubmitBlockHeaders(ctx.WithBlockHeight(height))
+	}
+
+	select {
+	case height := <-source.requested:
+		require.Equal(t, int64(1), height)
+	case <-time.After(10 * time.Second):
+		t.Fatal("no header was read")
+	}
+}
+
+func TestEnvBlockHash(t *testing.T) {
+	ctx, keeper, tempDir, codeID, _, walletA, privKeyA, _, _ := setupTest(t, "./testdata/test-contract/contract.wasm")
+	defer os.RemoveAll(tempDir)
+
+	addr, _, initErr := initHelper(t, keeper, ctx, codeID, walletA, privKeyA, `{"nop":{}}`, true, defaultGasForTests)
+	require.Empty(t, initErr)
+
+	// no header of this chain was ever submitted to the enclave, and contracts still get the hash
+	// of the block they run in, the same as on any other node
+	source := newFakeHeaderSource(3)
+	for _, height := range []int64{3, 2} {
+		signedHeader, _, err := source.SignedHeader(height)
+		require.NoError(t, err)
+		blockCtx := ctx.WithBlockHeader(tmtypes.TM2PB.Header(signedHeader.Header))
+
+		data, queryErr := queryHelper(t, keeper, blockCtx, addr, `{"block_hash":{}}`, true, defaultGasForTests)
+		require.Empty(t, queryErr)
+		require.Equal(t, fmt.Sprintf(`"%s"`, base64.StdEncoding.EncodeToString(signedHeader.Hash())), data)
+	}
+}
@@ -0,0 +1,93 @@
+package keeper
+
+import (
+	"bytes"
+	"sort"
+	"sync"
+
+	"github.com/prometheus/client_golang/prometheus"
+	dto "github.com/prometheus/client_model/go"
+	"github.com/prometheus/common/expfmt"
+)
+
+// enclaveMetricsUpDesc is 1 when the metrics of the enclave were read, and 0 when the enclave
+// failed to export them. The scrape of the node goes on either way.
+var enclaveMetricsUpDesc = prometheus.NewDesc(
+	"secret_enclave_metrics_up",
+	"Whether the metrics of the enclave could be read",
+	nil, nil,
+)
+
+var registerEnclaveMetricsOnce sync.Once
+
+// enclaveMetricsCollector serves the metrics the enclave keeps, which it exports in the
+// Prometheus text format, from the metrics endpoint of the node. It's an unchecked collector, as
+// the series depend on what the enclave reports.
+type enclaveMetricsCollector struct {
+	export func() ([]byte, error)
+}
+
+// RegisterEnclaveMetrics adds the metrics of the enclave to the metrics Tendermint serves when
+// instrumentation.prometheus is set. The enclave is shared by the whole process, so the metrics
+// are only registered once.
+func (k Keeper) RegisterEnclaveMetrics() {
+	registerEnclaveMetricsOnce.Do(func() {
+		prometheus.MustRegister(enclaveMetricsCollector{export: k.wasmer.ExportMetrics})
+	})
+}
+
+// Describe sends nothing, which makes the collector unchecked
+func (c enclaveMetricsCollector) Describe(chan<- *prometheus.Desc) {}
+
+func (c enclaveMetricsCollector) Collect(ch chan<- prometheus.Metric) {
+	families, err := c.gather()
+	if err != nil {
+		ch <- prometheus.MustNewConstMetric(enclaveMetricsUpDesc, prometheus.GaugeValue, 0)
+		return
+	}
+	ch <- prometheus.MustNewConstMetric(enclaveMetricsUpDesc, prometheus.GaugeValue, 1)
+
+	for _, family := range families {
+		for _, metric := range family.Metric {
+			ch <- newEnclaveMetric(family, metric)
+		}
+	}
+}
+
+func (c enclaveMetricsCollector) gather() (map[string]*dto.MetricFamily, error) {
+	text, err := c.export()
+	if err != nil {
+		return nil, err
+	}
+	var parser expfmt.TextParser
+	return parser.TextToMetricFamilies(bytes.NewReader(text))
+}
+
+// enclaveMetric is a sample the enclave exported, as it was parsed
+type enclaveMetric struct {
+	desc   *prometheus.Desc
+	metric *dto.Metric
+}
+
+func newEnclaveMetric(family *dto.MetricFamily, metric *dto.Metric) enclaveMetric {
+	sort.Slice(metric.Label, func(i, j int) bool {
+		return metric.Label[i].GetName() < metric.Label[j].GetName()
+	})
+	labelNames := make([]string, len(metric.Label))
+	for i, label := range metric.Label {
+		labelNames[i] = label.GetName()
+	}
+	return enclaveMetric{
+		desc:   prometheus.NewDesc(family.GetName(), family.GetHelp(), labelNames, nil),
+		metric: metric,
+	}
+}
+
+func (m enclaveMetric) Desc() *prometheus.Desc {
+	return m.desc
+}
+
+func (m enclaveMetric) Write(out *dto.Metric) error {
+	*out = *m.metric
+	return nil
+}
@@ -0,0 +1,114 @@
+package keeper
+
+import (
+	"fmt"
+	"os"
+	"testing"
+
+	"github.com/prometheus/client_golang/prometheus"
+	dto "github.com/prometheus/client_model/go"
+	"github.com/stretchr/testify/require"
+)
+
+const exportedEnclaveMetrics = `# HELP secret_enclave_ecalls_total Ecalls the enclave ran
+# TYPE secret_enclave_ecalls_total counter
+secret_enclave_ecalls_total{ecall="handle"} 3
+secret_enclave_ecalls_total{ecall="query"} 1
+# HELP secret_enclave_ecall_failures_total Ecalls that failed, by error
+# TYPE secret_enclave_ecall_failures_total counter
+secret_enclave_ecall_failures_total{ecall="handle",error="OutOfGas"} 2
+# HELP secret_enclave_ecall_gas Gas used by contract ecalls
+# TYPE secret_enclave_ecall_gas histogram
+secret_enclave_ecall_gas_bucket{ecall="handle",le="100000"} 1
+secret_enclave_ecall_gas_bucket{ecall="handle",le="+Inf"} 3
+secret_enclave_ecall_gas_sum{ecall="handle"} 450000
+secret_enclave_ecall_gas_count{ecall="handle"} 3
+# HELP secret_enclave_allocated_bytes Bytes allocated in the enclave
+# TYPE secret_enclave_allocated_bytes gauge
+secret_enclave_allocated_bytes 4096
+`
+
+// gatherEnclaveMetrics scrapes the collector the way the metrics endpoint of the node does
+func gatherEnclaveMetrics(t *testing.T, collector enclaveMetricsCollector) map[string]*dto.MetricFamily {
+	registry := prometheus.NewRegistry()
+	registry.MustRegister(collector)
+	families, err := registry.Gather()
+	require.NoError(t, err)
+
+	byName := make(map[string]*dto.MetricFamily)
+	for _, family := range families {
+		byName[family.GetName()] = family
+	}
+	return byName
+}
+
+func metricWithLabels(t *testing.T, family *dto.MetricFamily, labels map[string]string) *dto.Metric {
+	require.NotNil(t, family)
+	for _, metric := range family.Metric {
+		if len(metric.Label) != len(labels) {
+			continue
+		}
+		matches := true
+		for _, label := range metric.Label {
+			if labels[label.GetName()] != label.GetValue() {
+				matches = false
+			}
+		}
+		if matches {
+			return metric
+		}
+	}
+	require.Fail(t, "no metric with the labels", "%s %v", family.GetName(), labels)
+	return nil
+}
+
+func TestEnclaveMetricsAreServed(t *testing.T) {
+	families := gatherEnclaveMetrics(t, enclaveMetricsCollector{export: func() ([]byte, error) {
+		return []byte(exportedEnclaveMetrics), nil
+	}})
+
+	require.Equal(t, float64(1), families["secret_enclave_metrics_up"].Metric[0].GetGauge().GetValue())
+
+	ecalls := families["secret_enclave_ecalls_total"]
+	require.Equal(t, dto.MetricType_COUNTER, ecalls.GetType())
+	require.Equal(t, "Ecalls the enclave ran", ecalls.GetHelp())
+	require.Equal(t, float64(3), metricWithLabels(t, ecalls, map[string]string{"ecall": "handle"}).GetCounter().GetValue())
+	require.Equal(t, float64(1), metricWithLabels(t, ecalls, map[string]string{"ecall": "query"}).GetCounter().GetValue())
+
+	failures := families["secret_enclave_ecall_failures_total"]
+	failure := metricWithLabels(t, failures, map[string]string{"ecall": "handle", "error": "OutOfGas"})
+	require.Equal(t, float64(2), failure.GetCounter().GetValue())
+
+	gas := metricWithLabels(t, families["secret_enclave_ecall_gas"], map[string]string{"ecall": "handle"}).GetHistogram()
+	require.Equal(t, uint64(3), gas.GetSampleCount())
+	require.Equal(t, float64(450000), gas.GetSampleSum())
+	require.Equal(t, uint64(1), gas.Bucket[0].GetCumulativeCount())
+
+	require.Equal(t, float64(4096), families["secret_enclave_allocated_bytes"].Metric[0].GetGauge().GetValue())
+}
+
+func TestEnclaveMetricsExportFailure(t *testing.T) {
+	families := gatherEnclaveMetrics(t, enclaveMetricsCollector{export: func() ([]byte, error) {
+		return nil, fmt.Errorf("the enclave is gone")
+	}})
+
+	// the node still serves its other metrics, and reports that the enclave's are missing
+	require.Len(t, families, 1)
+	require.Equal(t, float64(0), families["secret_enclave_metrics_up"].Metric[0].GetGauge().GetValue())
+}
+
+func TestEnclaveMetricsOfKeeper(t *testing.T) {
+	ctx, keeper, tempDir, codeID, _, walletA, privKeyA, _, _ := setupTest(t, "./testdata/test-contract/contract.wasm")
+	defer os.RemoveAll(tempDir)
+
+	before := gatherEnclaveMetrics(t, enclaveMetricsCollector{export: keeper.wasmer.ExportMetrics})
+	_, _, initErr := initHelper(t, keeper, ctx, codeID, walletA, privKeyA, `{"nop":{}}`, true, defaultGasForTests)
+	require.Empty(t, initErr)
+	after := gatherEnclaveMetrics(t, enclaveMetricsCollector{export: keeper.wasmer.ExportMetrics})
+
+	require.Equal(t, float64(1), after["secret_enclave_metrics_up"].Metric[0].GetGauge().GetValue())
+	inits := func(families map[string]*dto.MetricFamily) float64 {
+		return metricWithLabels(t, families["secret_enclave_ecalls_total"], map[string]string{"ecall": "init"}).GetCounter().GetValue()
+	}
+	require.Equal(t, inits(before)+1, inits(after))
+}
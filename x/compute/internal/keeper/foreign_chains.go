@@ -0,0 +1,76 @@
+package keeper
+
+import (
+	"encoding/json"
+
+	wasmTypes "github.com/enigmampc/SecretNetwork/go-cosmwasm/types"
+	"github.com/enigmampc/cosmos-sdk/store/prefix"
+	sdk "github.com/enigmampc/cosmos-sdk/types"
+	sdkerrors "github.com/enigmampc/cosmos-sdk/types/errors"
+	govtypes "github.com/enigmampc/cosmos-sdk/x/gov/types"
+
+	"github.com/enigmampc/SecretNetwork/x/compute/internal/types"
+)
+
+// NewForeignChainProposalHandler starts tracking the chains of the TrackForeignChainProposals that
+// passed
+func NewForeignChainProposalHandler(k Keeper) govtypes.Handler {
+	return func(ctx sdk.Context, content govtypes.Content) error {
+		switch c := content.(type) {
+		case types.TrackForeignChainProposal:
+			return k.TrackForeignChain(ctx, c.ForeignChainInit())
+		default:
+			return sdkerrors.Wrapf(sdkerrors.ErrUnknownRequest, "unrecognized compute proposal content type: %T", c)
+		}
+	}
+}
+
+// TrackForeignChain makes contracts able to verify the headers of the chain of init.Header, starting
+// from that header. The enclave only trusts the header because governance approved it, so this is
+// only called for proposals that passed. Tracking a chain again starts over from the new header.
+func (k Keeper) TrackForeignChain(ctx sdk.Context, init wasmTypes.ForeignChainInit) error {
+	store := ctx.KVStore(k.storeKey)
+	key := types.GetForeignChainKey(init.Header.ChainID)
+	if !store.Has(key) && len(k.foreignChains(ctx)) >= types.MaxForeignChains {
+		return sdkerrors.Wrapf(types.ErrLimit, "already tracking %d chains", types.MaxForeignChains)
+	}
+
+	sealedChain, err := k.wasmer.TrackForeignChain(init)
+	if err != nil {
+		return sdkerrors.Wrap(types.ErrForeignChainFailed, err.Error())
+	}
+	store.Set(key, sealedChain)
+	return nil
+}
+
+// SubmitForeignHeader has the enclave verify a header of a tracked chain against the last header it
+// verified for the chain, at the time of the current block. Newer headers of the chain are then
+// verified against it, for contracts and for the next submission.
+func (k Keeper) SubmitForeignHeader(ctx sdk.Context, submission wasmTypes.ForeignHeader) error {
+	store := ctx.KVStore(k.storeKey)
+	key := types.GetForeignChainKey(submission.Header.ChainID)
+	sealedChain := store.Get(key)
+	if sealedChain == nil {
+		return sdkerrors.Wrapf(types.ErrNotFound, "chain %s isn't tracked", submission.Header.ChainID)
+	}
+
+	newSealedChain, err := k.wasmer.SubmitForeignHeader(sealedChain, submission, uint64(ctx.BlockTime().Unix()))
+	if err != nil {
+		return sdkerrors.Wrap(types.ErrForeignChainFailed, err.Error())
+	}
+	store.Set(key, newSealedChain)
+	return nil
+}
+
+// foreignChains returns what the enclave trusts about every tracked chain, for the env of contracts
+func (k Keeper) foreignChains(ctx sdk.Context) []json.RawMessage {
+	prefixStore := prefix.NewStore(ctx.KVStore(k.storeKey), types.ForeignChainPrefix)
+	iter := prefixStore.Iterator(nil, nil)
+	defer iter.Close()
+
+	var foreignChains []json.RawMessage
+	for ; iter.Valid(); iter.Next() {
+		foreignChains = append(foreignChains, iter.Value())
+	}
+	return foreignChains
+}
@@ -0,0 +1,104 @@
+package keeper
+
+import (
+	"fmt"
+	"io/ioutil"
+	"os"
+	"testing"
+	"time"
+
+	wasmTypes "github.com/enigmampc/SecretNetwork/go-cosmwasm/types"
+	sdk "github.com/enigmampc/cosmos-sdk/types"
+	"github.com/stretchr/testify/require"
+
+	"github.com/enigmampc/SecretNetwork/x/compute/internal/types"
+)
+
+func foreignHeader(t *testing.T, source *fakeHeaderSource, height int64) wasmTypes.ForeignHeader {
+	signedHeader, validators, err := source.SignedHeader(height)
+	require.NoError(t, err)
+	submission := types.NewForeignHeader(signedHeader, validators, nil)
+	require.NotNil(t, submission)
+	return *submission
+}
+
+func TestForeignChains(t *testing.T) {
+	tempDir, err := ioutil.TempDir("", "wasm")
+	require.NoError(t, err)
+	defer os.RemoveAll(tempDir)
+	ctx, keepers := CreateTestInput(t, false, tempDir, SupportedFeatures, nil, nil)
+	keeper := keepers.WasmKeeper
+	// the headers of the source are a second apart from 1_600_000_000
+	ctx = ctx.WithBlockTime(time.Unix(1_600_000_100, 0))
+
+	source := newFakeHeaderSource(10)
+	first := foreignHeader(t, source, 1)
+	key := types.GetForeignChainKey(first.Header.ChainID)
+
+	// a header of a chain that isn't tracked is refused
+	err = keeper.SubmitForeignHeader(ctx, foreignHeader(t, source, 2))
+	require.True(t, types.ErrNotFound.Is(err), err)
+
+	// a proposal that passed starts tracking the chain from its header
+	proposal := types.TrackForeignChainProposal{
+		Title:                 "Track",
+		Description:           "header-chain",
+		Header:                first.Header,
+		TrustingPeriodSeconds: 3600,
+	}
+	require.NoError(t, proposal.ValidateBasic())
+	handler := NewForeignChainProposalHandler(keeper)
+	require.NoError(t, handler(ctx, proposal))
+	tracked := ctx.KVStore(keeper.storeKey).Get(key)
+	require.NotEmpty(t, tracked)
+
+	// contracts get what the enclave trusts about the chain
+	env := keeper.newEnv(ctx, sdk.AccAddress{}, nil, sdk.AccAddress{}, nil)
+	require.Len(t, env.ForeignChains, 1)
+	require.Equal(t, tracked, []byte(env.ForeignChains[0]))
+
+	// the next header is verified against the tracked one and the chain goes on from it
+	require.NoError(t, keeper.SubmitForeignHeader(ctx, foreignHeader(t, source, 2)))
+	verified := ctx.KVStore(keeper.storeKey).Get(key)
+	require.NotEqual(t, tracked, verified)
+
+	// a header that its commit didn't sign is refused and changes nothing
+	forged := foreignHeader(t, source, 3)
+	forged.Header.AppHash = []byte("forged app hash")
+	err = keeper.SubmitForeignHeader(ctx, forged)
+	require.True(t, types.ErrForeignChainFailed.Is(err), err)
+	require.Equal(t, verified, ctx.KVStore(keeper.storeKey).Get(key))
+
+	// so is a header after the trusting period of the last verified one
+	err = keeper.SubmitForeignHeader(ctx.WithBlockTime(time.Unix(1_600_000_002+3600, 0)), foreignHeader(t, source, 3))
+	require.True(t, types.ErrForeignChainFailed.Is(err), err)
+	require.Equal(t, verified, ctx.KVStore(keeper.storeKey).Get(key))
+
+	require.NoError(t, keeper.SubmitForeignHeader(ctx, foreignHeader(t, source, 3)))
+}
+
+func TestForeignChainsAreBounded(t *testing.T) {
+	tempDir, err := ioutil.TempDir("", "wasm")
+	require.NoError(t, err)
+	defer os.RemoveAll(tempDir)
+	ctx, keepers := CreateTestInput(t, false, tempDir, SupportedFeatures, nil, nil)
+	keeper := keepers.WasmKeeper
+
+	init := wasmTypes.ForeignChainInit{
+		Header:                foreignHeader(t, newFakeHeaderSource(1), 1).Header,
+		TrustingPeriodSeconds: 3600,
+	}
+	for i := 0; i < types.MaxForeignChains; i++ {
+		init.Header.ChainID = fmt.Sprintf("chain-%d", i)
+		require.NoError(t, keeper.TrackForeignChain(ctx, init))
+	}
+
+	init.Header.ChainID = "one-chain-too-many"
+	err = keeper.TrackForeignChain(ctx, init)
+	require.True(t, types.ErrLimit.Is(err), err)
+
+	// a tracked chain can still be tracked again
+	init.Header.ChainID = "chain-0"
+	require.NoError(t, keeper.TrackForeignChain(ctx, init))
+	require.Len(t, keeper.foreignChains(ctx), types.MaxForeignChains)
+}
@@ -46,7 +46,11 @@ func InitGenesis(ctx sdk.Context, keeper Keeper, data types.GenesisState) error
 	if keeper.peekAutoIncrementID(ctx, types.KeyLastInstanceID) <= uint64(maxContractID) {
 		return sdkerrors.Wrapf(types.ErrInvalid, "seq %s must be greater %d ", string(types.KeyLastInstanceID), maxContractID)
 	}
-	//keeper.setParams(ctx, data.Params)
+	params := types.DefaultParams()
+	if data.Params != nil {
+		params = *data.Params
+	}
+	keeper.setParams(ctx, params)
 
 	return nil
 }
@@ -55,7 +59,8 @@ func InitGenesis(ctx sdk.Context, keeper Keeper, data types.GenesisState) error
 func ExportGenesis(ctx sdk.Context, keeper Keeper) types.GenesisState {
 	var genState types.GenesisState
 
-	//genState.Params = keeper.GetParams(ctx)
+	params := keeper.GetParams(ctx)
+	genState.Params = &params
 
 	keeper.IterateCodeInfos(ctx, func(codeID uint64, info types.CodeInfo) bool {
 		bytecode, err := keeper.GetByteCode(ctx, codeID)
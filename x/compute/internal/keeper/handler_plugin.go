@@ -257,39 +257,48 @@ func EncodeWasmMsg(sender sdk.AccAddress, msg *wasmTypes.WasmMsg) ([]sdk.Msg, er
 }
 
 func (h MessageHandler) Dispatch(ctx sdk.Context, contractAddr sdk.AccAddress, msg wasmTypes.CosmosMsg) error {
+	_, err := h.DispatchWithData(ctx, contractAddr, msg)
+	return err
+}
+
+// DispatchWithData dispatches a message like Dispatch, and returns the data of the last sdk message
+// it was encoded to. This is what is passed back to contracts in replies to submessages.
+func (h MessageHandler) DispatchWithData(ctx sdk.Context, contractAddr sdk.AccAddress, msg wasmTypes.CosmosMsg) ([]byte, error) {
 	sdkMsgs, err := h.encoders.Encode(contractAddr, msg)
 	if err != nil {
-		return err
+		return nil, err
 	}
+	var data []byte
 	for _, sdkMsg := range sdkMsgs {
-		if err := h.handleSdkMessage(ctx, contractAddr, sdkMsg); err != nil {
-			return err
+		data, err = h.handleSdkMessage(ctx, contractAddr, sdkMsg)
+		if err != nil {
+			return nil, err
 		}
 	}
-	return nil
+	return data, nil
 }
 
-func (h MessageHandler) handleSdkMessage(ctx sdk.Context, contractAddr sdk.Address, msg sdk.Msg) error {
+func (h MessageHandler) handleSdkMessage(ctx sdk.Context, contractAddr sdk.Address, msg sdk.Msg) ([]byte, error) {
 	// make sure this account can send it
 	for _, acct := range msg.GetSigners() {
 		if !acct.Equals(contractAddr) {
-			return sdkerrors.Wrap(sdkerrors.ErrUnauthorized, "contract doesn't have permission")
+			return nil, sdkerrors.Wrap(sdkerrors.ErrUnauthorized, "contract doesn't have permission")
 		}
 	}
 
 	// find the handler and execute it
 	handler := h.router.Route(ctx, msg.Route())
 	if handler == nil {
-		return sdkerrors.Wrap(sdkerrors.ErrUnknownRequest, msg.Route())
+		return nil, sdkerrors.Wrap(sdkerrors.ErrUnknownRequest, msg.Route())
 	}
 	res, err := handler(ctx, msg)
 	if err != nil {
-		return err
+		return nil, err
 	}
 	// redispatch all events, (type sdk.EventTypeMessage will be filtered out in the handler)
 	ctx.EventManager().EmitEvents(res.Events)
 
-	return nil
+	return res.Data, nil
 }
 
 func convertWasmCoinsToSdkCoins(coins []wasmTypes.Coin) (sdk.Coins, error) {
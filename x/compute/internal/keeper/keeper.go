@@ -2,9 +2,13 @@ package keeper
 
 import (
 	"bytes"
+	"crypto/sha256"
 	"encoding/base64"
 	"encoding/binary"
+	"encoding/hex"
+	"encoding/json"
 	"fmt"
+	"strings"
 
 	"path/filepath"
 
@@ -25,6 +29,7 @@ import (
 	"github.com/enigmampc/cosmos-sdk/x/auth"
 	authtypes "github.com/enigmampc/cosmos-sdk/x/auth/types"
 	"github.com/enigmampc/cosmos-sdk/x/bank"
+	"github.com/enigmampc/cosmos-sdk/x/params"
 	"github.com/enigmampc/cosmos-sdk/x/staking"
 
 	"github.com/enigmampc/SecretNetwork/x/compute/internal/types"
@@ -62,26 +67,41 @@ type Keeper struct {
 	messenger    MessageHandler
 	// queryGasLimit is the max wasm gas that can be spent on executing a query with a contract
 	queryGasLimit uint64
+	// queryTimeoutMs stops queries that clients send to the node after they ran for that many
+	// milliseconds. 0 disables it
+	queryTimeoutMs uint64
+	// prewarmMaxBytes and prewarmTimeoutMs are the budget of PrewarmContracts
+	prewarmMaxBytes  uint64
+	prewarmTimeoutMs uint64
 	// authZPolicy   AuthorizationPolicy
-	//paramSpace    subspace.Subspace
+	paramSpace params.Subspace
+	// submittedSeedRotation is the last seed rotation this node handed to its enclave
+	submittedSeedRotation *string
+	// headerSource, headerHeights and submittedHeaderHeight are used by SubmitBlockHeaders. Only
+	// the goroutine that submits the headers touches submittedHeaderHeight
+	headerSource          SignedHeaderSource
+	headerHeights         chan int64
+	submittedHeaderHeight *int64
 }
 
 // NewKeeper creates a new contract Keeper instance
 // If customEncoders is non-nil, we can use this to override some of the message handler, especially custom
-func NewKeeper(cdc *codec.Codec, storeKey sdk.StoreKey, accountKeeper auth.AccountKeeper,
+func NewKeeper(cdc *codec.Codec, storeKey sdk.StoreKey, paramSpace params.Subspace, accountKeeper auth.AccountKeeper,
 	bankKeeper *bank.Keeper, govKeeper *gov.Keeper, distKeeper *distr.Keeper, mintKeeper *mint.Keeper, stakingKeeper *staking.Keeper,
 	router sdk.Router, homeDir string, wasmConfig types.WasmConfig, supportedFeatures string, customEncoders *MessageEncoders, customPlugins *QueryPlugins) Keeper {
 	wasmer, err := wasm.NewWasmer(filepath.Join(homeDir, "wasm"), supportedFeatures, wasmConfig.CacheSize)
 	if err != nil {
 		panic(err)
 	}
+	err = configureEnclaveRuntime(wasmer, wasmConfig)
+	if err != nil {
+		panic(err)
+	}
 
-	/*
-		// set KeyTable if it has not already been set
-		if !paramSpace.HasKeyTable() {
-			paramSpace = paramSpace.WithKeyTable(types.ParamKeyTable())
-		}
-	*/
+	// set KeyTable if it has not already been set
+	if !paramSpace.HasKeyTable() {
+		paramSpace = paramSpace.WithKeyTable(types.ParamKeyTable())
+	}
 
 	keeper := Keeper{
 		storeKey:      storeKey,
@@ -91,13 +111,55 @@ func NewKeeper(cdc *codec.Codec, storeKey sdk.StoreKey, accountKeeper auth.Accou
 		bankKeeper:    *bankKeeper,
 		messenger:     NewMessageHandler(router, customEncoders),
 		queryGasLimit: wasmConfig.SmartQueryGasLimit,
+
+		queryTimeoutMs:   wasmConfig.QueryTimeoutMs,
+		prewarmMaxBytes:  wasmConfig.PrewarmMaxBytes,
+		prewarmTimeoutMs: wasmConfig.PrewarmTimeoutMs,
 		// authZPolicy:   DefaultAuthorizationPolicy{},
-		//paramSpace:    paramSpace,
+		paramSpace: paramSpace,
+
+		submittedSeedRotation: new(string),
+		submittedHeaderHeight: new(int64),
 	}
 	keeper.queryPlugins = DefaultQueryPlugins(govKeeper, distKeeper, mintKeeper, bankKeeper, stakingKeeper, &keeper).Merge(customPlugins)
 	return keeper
 }
 
+// configureEnclaveRuntime applies the enclave settings of the node config to the enclave of this
+// node. Settings that aren't in the config keep what the enclave started with.
+func configureEnclaveRuntime(wasmer *wasm.Wasmer, wasmConfig types.WasmConfig) error {
+	maxLogLevel := wasmTypes.EnclaveLogLevelKeep
+	if wasmConfig.EnclaveLogLevel != "" {
+		found := false
+		for level, name := range wasmTypes.EnclaveLogLevels {
+			if name == strings.ToLower(wasmConfig.EnclaveLogLevel) {
+				maxLogLevel = uint8(level)
+				found = true
+			}
+		}
+		if !found {
+			return fmt.Errorf("unknown enclave log level %s. Expected one of: %s", wasmConfig.EnclaveLogLevel, strings.Join(wasmTypes.EnclaveLogLevels, ", "))
+		}
+	}
+
+	ecallTracing := wasmTypes.EcallTracingKeep
+	if wasmConfig.EcallTracing {
+		ecallTracing = wasmTypes.EcallTracingOn
+	}
+
+	err := wasmer.ConfigureEnclaveRuntime(maxLogLevel, wasmConfig.SmartQueryGasLimit*GasMultiplier, wasmConfig.MaxContractSize, wasmConfig.QueryCacheSize, ecallTracing)
+	if err != nil {
+		return fmt.Errorf("failed to configure the enclave: %w", err)
+	}
+	return nil
+}
+
+// EcallTraces returns the traces of the last maxEcalls ecalls the enclave of this node ran while
+// ecall_tracing was on. Every node traces its own enclave, so they aren't part of the chain state.
+func (k Keeper) EcallTraces(maxEcalls uint32) (*wasmTypes.EcallTraces, error) {
+	return k.wasmer.EcallTraces(maxEcalls)
+}
+
 /*
 func (k Keeper) getUploadAccessConfig(ctx sdk.Context) types.AccessConfig {
 	var a types.AccessConfig
@@ -110,18 +172,55 @@ func (k Keeper) getInstantiateAccessConfig(ctx sdk.Context) types.AccessType {
 	k.paramSpace.Get(ctx, types.ParamStoreKeyInstantiateAccess, &a)
 	return a
 }
+*/
 
-// GetParams returns the total set of wasm parameters.
+// GetParams returns the total set of compute parameters. Chains that didn't set a parameter yet,
+// like ones that started before it was added, get its default.
 func (k Keeper) GetParams(ctx sdk.Context) types.Params {
-	var params types.Params
-	k.paramSpace.GetParamSet(ctx, &params)
+	params := types.DefaultParams()
+	for _, pair := range params.ParamSetPairs() {
+		k.paramSpace.GetIfExists(ctx, pair.Key, pair.Value)
+	}
 	return params
 }
 
 func (k Keeper) setParams(ctx sdk.Context, ps types.Params) {
 	k.paramSpace.SetParamSet(ctx, &ps)
 }
-*/
+
+// newEnv is types.NewEnv with the parameters of the chain that the enclave gets in the env
+func (k Keeper) newEnv(ctx sdk.Context, creator sdk.AccAddress, deposit sdk.Coins, contractAddr sdk.AccAddress, contractKey []byte) wasmTypes.Env {
+	env := types.NewEnv(ctx, creator, deposit, contractAddr, contractKey)
+	params := k.GetParams(ctx)
+	env.OcallGasCosts = &params.OcallGasCosts
+	env.MaxMemoryPages = params.MaxMemoryPages
+	env.StorageQuota = params.StorageQuota
+	if params.OutputLimits != (wasmTypes.OutputLimits{}) {
+		env.OutputLimits = &params.OutputLimits
+	}
+	env.ForeignChains = k.foreignChains(ctx)
+	return env
+}
+
+// SubmitSeedRotation hands the seed rotation set by governance to the enclave, until the rotation
+// is activated. The enclave switches to the new seed at the activation height by itself, so what
+// happens here only concerns this node and never changes the state of the chain.
+func (k Keeper) SubmitSeedRotation(ctx sdk.Context) {
+	rotation := k.GetParams(ctx).SeedRotation
+	activationHeight, ok, err := types.SeedRotationActivationHeight(rotation)
+	if err != nil || !ok || ctx.BlockHeight() >= activationHeight || *k.submittedSeedRotation == rotation {
+		return
+	}
+
+	if err := k.wasmer.SubmitNewSeed([]byte(rotation)); err != nil {
+		// a node the rotation has no seed for can't execute contracts from the activation height on
+		ctx.Logger().Error("failed to submit the consensus seed rotation to the enclave",
+			"activation_height", activationHeight, "error", err)
+		return
+	}
+	*k.submittedSeedRotation = rotation
+	ctx.Logger().Info("submitted the consensus seed rotation to the enclave", "activation_height", activationHeight)
+}
 
 // Create uploads and compiles a WASM contract, returning a short identifier for the contract
 func (k Keeper) Create(ctx sdk.Context, creator sdk.AccAddress, wasmCode []byte, source string, builder string) (codeID uint64, err error) {
@@ -134,11 +233,14 @@ func (k Keeper) Create(ctx sdk.Context, creator sdk.AccAddress, wasmCode []byte,
 			return 0, sdkerrors.Wrap(sdkerrors.ErrUnauthorized, "can not create code")
 		}
 	*/
-	wasmCode, err = uncompress(wasmCode)
+	// The upload goes to the enclave as it is, and the enclave decompresses it itself, so the code
+	// hash it returns is of the code the upload stands for. It's only decompressed here to charge
+	// for the size of that code.
+	uncompressed, err := uncompress(wasmCode)
 	if err != nil {
 		return 0, sdkerrors.Wrap(types.ErrCreateFailed, err.Error())
 	}
-	ctx.GasMeter().ConsumeGas(CompileCost*uint64(len(wasmCode)), "Compiling WASM Bytecode")
+	ctx.GasMeter().ConsumeGas(CompileCost*uint64(len(uncompressed)), "Compiling WASM Bytecode")
 
 	codeHash, err := k.wasmer.Create(wasmCode)
 	if err != nil {
@@ -190,7 +292,8 @@ func GetSignerSignature(signer exported.Account, tx auth.StdTx) (authtypes.StdSi
 	return authtypes.StdSignature{}, fmt.Errorf("could not find signer signature")
 }
 
-func (k Keeper) GetSignerInfo(ctx sdk.Context, signer sdk.AccAddress) (authtypes.StdSignature, []byte, error) {
+// GetSignerInfo returns the signature of signer on the tx, the bytes it signed, and how it encoded them
+func (k Keeper) GetSignerInfo(ctx sdk.Context, signer sdk.AccAddress) (authtypes.StdSignature, []byte, wasmTypes.SignMode, error) {
 	var defaultSignature = authtypes.StdSignature{
 		PubKey:    secp256k1.PubKeySecp256k1{},
 		Signature: []byte{},
@@ -202,30 +305,86 @@ func (k Keeper) GetSignerInfo(ctx sdk.Context, signer sdk.AccAddress) (authtypes
 	txBytes := ctx.TxBytes()
 	err := k.cdc.UnmarshalBinaryLengthPrefixed(txBytes, &tx)
 	if err != nil {
-		return defaultSignature, nil, sdkerrors.Wrap(types.ErrInstantiateFailed, fmt.Sprintf("Unable to decode transaction from bytes: %s", err.Error()))
+		if directTx, directErr := decodeDirectTx(txBytes); directErr == nil {
+			signerSig, signBytes, err := k.getDirectSignerInfo(ctx, directTx, signer)
+			if err != nil {
+				return defaultSignature, nil, "", err
+			}
+			return signerSig, signBytes, wasmTypes.SignModeDirect, nil
+		}
+		return defaultSignature, nil, "", sdkerrors.Wrap(types.ErrInstantiateFailed, fmt.Sprintf("Unable to decode transaction from bytes: %s", err.Error()))
 	}
 
 	// Get sign bytes for the message creator
 	signerAcc, err := auth.GetSignerAcc(ctx, k.accountKeeper, signer) // for MsgInstantiateContract, there is only one signer which is msg.Sender (https://github.com/enigmampc/SecretNetwork/blob/d7813792fa07b93a10f0885eaa4c5e0a0a698854/x/compute/internal/types/msg.go#L192-L194)
 	if err != nil {
-		return defaultSignature, nil, sdkerrors.Wrap(types.ErrInstantiateFailed, fmt.Sprintf("Unable to retrieve account by address: %s", err.Error()))
+		return defaultSignature, nil, "", sdkerrors.Wrap(types.ErrInstantiateFailed, fmt.Sprintf("Unable to retrieve account by address: %s", err.Error()))
 	}
 
 	signerSig, err := GetSignerSignature(signerAcc, tx)
 	if err != nil {
-		return defaultSignature, nil, sdkerrors.Wrap(types.ErrInstantiateFailed, fmt.Sprintf("Message sender: %v is not found in the tx signer set: %v, callback signature not provided", signer, tx.Signatures))
+		return defaultSignature, nil, "", sdkerrors.Wrap(types.ErrInstantiateFailed, fmt.Sprintf("Message sender: %v is not found in the tx signer set: %v, callback signature not provided", signer, tx.Signatures))
 	}
 
 	signBytes := GetSignBytes(ctx, signerAcc, tx)
 
-	return signerSig, signBytes, nil
+	return signerSig, signBytes, wasmTypes.SignModeLegacyAminoJSON, nil
 }
 
-func (k Keeper) importCode(ctx sdk.Context, codeID uint64, codeInfo types.CodeInfo, wasmCode []byte) error {
-	wasmCode, err := uncompress(wasmCode)
+// GetTxMemo returns the memo of the tx being executed
+func (k Keeper) GetTxMemo(ctx sdk.Context) (string, error) {
+	tx := authtypes.StdTx{}
+	txBytes := ctx.TxBytes()
+	err := k.cdc.UnmarshalBinaryLengthPrefixed(txBytes, &tx)
 	if err != nil {
-		return sdkerrors.Wrap(types.ErrCreateFailed, err.Error())
+		if directTx, directErr := decodeDirectTx(txBytes); directErr == nil {
+			return directTx.memo, nil
+		}
+		return "", sdkerrors.Wrap(types.ErrInstantiateFailed, fmt.Sprintf("Unable to decode transaction from bytes: %s", err.Error()))
 	}
+
+	return tx.Memo, nil
+}
+
+// GetAdditionalSignersInfo returns the signatures and sign bytes of all the tx signers other than signer
+func (k Keeper) GetAdditionalSignersInfo(ctx sdk.Context, signer sdk.AccAddress) ([]wasmTypes.SignerInfo, error) {
+	tx := authtypes.StdTx{}
+	txBytes := ctx.TxBytes()
+	err := k.cdc.UnmarshalBinaryLengthPrefixed(txBytes, &tx)
+	if err != nil {
+		if directTx, directErr := decodeDirectTx(txBytes); directErr == nil {
+			return k.getDirectAdditionalSignersInfo(ctx, directTx, signer)
+		}
+		return nil, sdkerrors.Wrap(types.ErrInstantiateFailed, fmt.Sprintf("Unable to decode transaction from bytes: %s", err.Error()))
+	}
+
+	var signersInfo []wasmTypes.SignerInfo
+	for _, txSigner := range tx.GetSigners() {
+		if txSigner.Equals(signer) {
+			continue
+		}
+
+		signerAcc, err := auth.GetSignerAcc(ctx, k.accountKeeper, txSigner)
+		if err != nil {
+			return nil, sdkerrors.Wrap(types.ErrInstantiateFailed, fmt.Sprintf("Unable to retrieve account by address: %s", err.Error()))
+		}
+
+		signerSig, err := GetSignerSignature(signerAcc, tx)
+		if err != nil {
+			return nil, sdkerrors.Wrap(types.ErrInstantiateFailed, fmt.Sprintf("Tx signer: %v is not found in the tx signature set: %v", txSigner, tx.Signatures))
+		}
+
+		signersInfo = append(signersInfo, wasmTypes.SignerInfo{
+			Bytes:     GetSignBytes(ctx, signerAcc, tx),
+			Signature: signerSig,
+		})
+	}
+
+	return signersInfo, nil
+}
+
+func (k Keeper) importCode(ctx sdk.Context, codeID uint64, codeInfo types.CodeInfo, wasmCode []byte) error {
+	// Like in Create, a gzipped upload is decompressed by the enclave
 	newCodeHash, err := k.wasmer.Create(wasmCode)
 	if err != nil {
 		return sdkerrors.Wrap(types.ErrCreateFailed, err.Error())
@@ -245,13 +404,7 @@ func (k Keeper) importCode(ctx sdk.Context, codeID uint64, codeInfo types.CodeIn
 }
 
 // Instantiate creates an instance of a WASM contract
-func (k Keeper) Instantiate(ctx sdk.Context, codeID uint64, creator /* , admin */ sdk.AccAddress, initMsg []byte, label string, deposit sdk.Coins, callbackSig []byte) (sdk.AccAddress, error) {
-	/*
-		return k.instantiate(ctx, codeID, creator admin,, initMsg, label, deposit, callbackSig)
-		}
-
-		func (k Keeper) instantiate(ctx sdk.Context, codeID uint64, creator , admin sdk.AccAddress, initMsg []byte, label string, deposit sdk.Coins, callbackSig []byte) (sdk.AccAddress, error) {
-	*/
+func (k Keeper) Instantiate(ctx sdk.Context, codeID uint64, creator, admin sdk.AccAddress, initMsg []byte, label string, deposit sdk.Coins, callbackSig []byte) (sdk.AccAddress, error) {
 	ctx.GasMeter().ConsumeGas(InstanceCost, "Loading CosmWasm module: init")
 
 	signerSig := authtypes.StdSignature{
@@ -259,17 +412,33 @@ func (k Keeper) Instantiate(ctx sdk.Context, codeID uint64, creator /* , admin *
 		Signature: []byte{},
 	}
 	signBytes := []byte{}
+	var signMode wasmTypes.SignMode
+	var additionalSigners []wasmTypes.SignerInfo
+	// messages sent by contracts don't have a memo
+	memo := ""
 	var err error
 
 	// If no callback signature - we should send the actual msg sender sign bytes and signature
 	if callbackSig == nil {
-		signerSig, signBytes, err = k.GetSignerInfo(ctx, creator)
+		signerSig, signBytes, signMode, err = k.GetSignerInfo(ctx, creator)
+		if err != nil {
+			return nil, err
+		}
+
+		additionalSigners, err = k.GetAdditionalSignersInfo(ctx, creator)
+		if err != nil {
+			return nil, err
+		}
+
+		memo, err = k.GetTxMemo(ctx)
 		if err != nil {
 			return nil, err
 		}
 	}
 
 	verificationInfo := types.NewVerificationInfo(signBytes, signerSig, callbackSig)
+	verificationInfo.AdditionalSigners = additionalSigners
+	verificationInfo.SignMode = signMode
 
 	// create contract address
 
@@ -280,7 +449,17 @@ func (k Keeper) Instantiate(ctx sdk.Context, codeID uint64, creator /* , admin *
 		return nil, sdkerrors.Wrap(types.ErrAccountExists, label)
 	}
 
-	contractAddress := k.generateContractAddress(ctx, codeID)
+	// get contact info
+
+	bz := store.Get(types.GetCodeKey(codeID))
+	if bz == nil {
+		return nil, sdkerrors.Wrap(types.ErrNotFound, "code")
+	}
+
+	var codeInfo types.CodeInfo
+	k.cdc.MustUnmarshalBinaryBare(bz, &codeInfo)
+
+	contractAddress := k.generateContractAddress(ctx, creator, codeInfo.CodeHash, initMsg)
 	existingAcct := k.accountKeeper.GetAccount(ctx, contractAddress)
 	if existingAcct != nil {
 		return nil, sdkerrors.Wrap(types.ErrAccountExists, existingAcct.GetAddress().String())
@@ -302,27 +481,25 @@ func (k Keeper) Instantiate(ctx sdk.Context, codeID uint64, creator /* , admin *
 		k.accountKeeper.SetAccount(ctx, contractAccount)
 	}
 
-	// get contact info
-
-	bz := store.Get(types.GetCodeKey(codeID))
-	if bz == nil {
-		return nil, sdkerrors.Wrap(types.ErrNotFound, "code")
-	}
-
-	var codeInfo types.CodeInfo
-	k.cdc.MustUnmarshalBinaryBare(bz, &codeInfo)
-
 	// if !authZ.CanInstantiateContract(codeInfo.InstantiateConfig, creator) {
 	// 	return nil, sdkerrors.Wrap(sdkerrors.ErrUnauthorized, "can not instantiate")
 	// }
 
 	// prepare params for contract instantiate call
-	params := types.NewEnv(ctx, creator, deposit, contractAddress, nil)
+	params := k.newEnv(ctx, creator, deposit, contractAddress, nil)
+	params.BlockGasRemaining = blockGasForContract(ctx)
+	params.Transaction.Memo = memo
+	// the enclave checks that this is the code the sender instantiated
+	params.Instantiation = &wasmTypes.ContractInstantiation{CodeID: codeID}
+	params.ExecutionPath = executionPath(ctx)
+	submessage := submessageReplyOf(ctx)
+	if submessage != nil {
+		params.ReplyTo = &submessage.ReplyTo
+	}
 
 	// create prefixed data store
 	// 0x03 | contractAddress (sdk.AccAddress)
-	prefixStoreKey := types.GetContractStorePrefixKey(contractAddress)
-	prefixStore := prefix.NewStore(ctx.KVStore(k.storeKey), prefixStoreKey)
+	prefixStore := k.contractStore(ctx, contractAddress)
 
 	// prepare querier
 	querier := QueryHandler{
@@ -332,24 +509,26 @@ func (k Keeper) Instantiate(ctx sdk.Context, codeID uint64, creator /* , admin *
 
 	// instantiate wasm contract
 	gas := gasForContract(ctx)
-	res, key, gasUsed, err := k.wasmer.Instantiate(codeInfo.CodeHash, params, initMsg, prefixStore, cosmwasmAPI, querier, ctx.GasMeter(), gas, verificationInfo)
+	res, key, adminProof, gasUsed, err := k.wasmer.Instantiate(codeInfo.CodeHash, params, initMsg, prefixStore, cosmwasmAPI, querier, ctx.GasMeter(), gas, verificationInfo)
 	consumeGas(ctx, gasUsed)
 	if err != nil {
 		return contractAddress, sdkerrors.Wrap(types.ErrInstantiateFailed, err.Error())
 	}
 
 	// emit all events from this contract itself
-	events := types.ParseEvents(res.Log, contractAddress)
+	events := types.ParseEvents(res.Log, contractAddress, params.ExecutionPath)
 	ctx.EventManager().EmitEvents(events)
 
 	// persist instance
 	createdAt := types.NewAbsoluteTxPosition(ctx)
-	instance := types.NewContractInfo(codeID, creator /* admin, */, label, createdAt)
+	instance := types.NewContractInfo(codeID, creator, admin, label, createdAt)
 	store.Set(types.GetContractAddressKey(contractAddress), k.cdc.MustMarshalBinaryBare(instance))
 
 	fmt.Printf("Storing key: %s for account %s\n", key, contractAddress)
 
 	store.Set(types.GetContractEnclaveKey(contractAddress), key)
+	// the enclave only trusts the admin of the contract along with this proof
+	store.Set(types.GetContractAdminProofKey(contractAddress), adminProof)
 
 	store.Set(types.GetContractLabelPrefix(label), contractAddress)
 
@@ -358,12 +537,32 @@ func (k Keeper) Instantiate(ctx sdk.Context, codeID uint64, creator /* , admin *
 		return nil, err
 	}
 
+	// the result of an instantiate is the address of the contract, so replies can't override it
+	_, _, err = k.dispatchSubmessages(ctx, contractAddress, initMsg, len(res.Messages), res.Submessages, nil)
+	if err != nil {
+		return nil, err
+	}
+	if submessage != nil {
+		submessage.ResultSig = res.ReplyResultSig
+	}
+
 	// k.appendToContractHistory(ctx, contractAddress, instance.InitialHistory(initMsg))
 	return contractAddress, nil
 }
 
 // Execute executes the contract instance
 func (k Keeper) Execute(ctx sdk.Context, contractAddress sdk.AccAddress, caller sdk.AccAddress, msg []byte, coins sdk.Coins, callbackSig []byte) (*sdk.Result, error) {
+	return k.execute(ctx, contractAddress, caller, msg, coins, callbackSig, false)
+}
+
+// SimulateExecute runs the contract like Execute does, and charges it the same gas for running, but the
+// enclave discards the changes the contract makes to its storage. The funds aren't sent, and the messages
+// the contract returns aren't dispatched. The data in the result is encrypted to the sender.
+func (k Keeper) SimulateExecute(ctx sdk.Context, contractAddress sdk.AccAddress, caller sdk.AccAddress, msg []byte, coins sdk.Coins) (*sdk.Result, error) {
+	return k.execute(ctx, contractAddress, caller, msg, coins, nil, true)
+}
+
+func (k Keeper) execute(ctx sdk.Context, contractAddress sdk.AccAddress, caller sdk.AccAddress, msg []byte, coins sdk.Coins, callbackSig []byte, simulate bool) (*sdk.Result, error) {
 	ctx.GasMeter().ConsumeGas(InstanceCost, "Loading CosmWasm module: execute")
 
 	signerSig := authtypes.StdSignature{
@@ -371,16 +570,32 @@ func (k Keeper) Execute(ctx sdk.Context, contractAddress sdk.AccAddress, caller
 		Signature: []byte{},
 	}
 	signBytes := []byte{}
+	var signMode wasmTypes.SignMode
+	var additionalSigners []wasmTypes.SignerInfo
+	// messages sent by contracts don't have a memo
+	memo := ""
 	var err error
 
 	if callbackSig == nil {
-		signerSig, signBytes, err = k.GetSignerInfo(ctx, caller)
+		signerSig, signBytes, signMode, err = k.GetSignerInfo(ctx, caller)
+		if err != nil {
+			return nil, err
+		}
+
+		additionalSigners, err = k.GetAdditionalSignersInfo(ctx, caller)
+		if err != nil {
+			return nil, err
+		}
+
+		memo, err = k.GetTxMemo(ctx)
 		if err != nil {
 			return nil, err
 		}
 	}
 
 	verificationInfo := types.NewVerificationInfo(signBytes, signerSig, callbackSig)
+	verificationInfo.AdditionalSigners = additionalSigners
+	verificationInfo.SignMode = signMode
 
 	codeInfo, prefixStore, err := k.contractInstance(ctx, contractAddress)
 	if err != nil {
@@ -390,7 +605,7 @@ func (k Keeper) Execute(ctx sdk.Context, contractAddress sdk.AccAddress, caller
 	store := ctx.KVStore(k.storeKey)
 
 	// add more funds
-	if !coins.IsZero() {
+	if !coins.IsZero() && !simulate {
 		if k.bankKeeper.BlacklistedAddr(caller) {
 			return nil, sdkerrors.Wrap(sdkerrors.ErrInvalidAddress, "blocked address can not be used")
 		}
@@ -403,7 +618,16 @@ func (k Keeper) Execute(ctx sdk.Context, contractAddress sdk.AccAddress, caller
 
 	contractKey := store.Get(types.GetContractEnclaveKey(contractAddress))
 	fmt.Printf("Contract Execute: Got contract Key for contract %s: %s\n", contractAddress, base64.StdEncoding.EncodeToString(contractKey))
-	params := types.NewEnv(ctx, caller, coins, contractAddress, contractKey)
+	params := k.newEnv(ctx, caller, coins, contractAddress, contractKey)
+	params.BlockGasRemaining = blockGasForContract(ctx)
+	params.Transaction.Memo = memo
+	// the enclave checks this against the code it gets
+	params.CodeHash = hex.EncodeToString(codeInfo.CodeHash)
+	params.ExecutionPath = executionPath(ctx)
+	submessage := submessageReplyOf(ctx)
+	if submessage != nil {
+		params.ReplyTo = &submessage.ReplyTo
+	}
 	fmt.Printf("Contract Execute: key from params %s \n", params.Key)
 
 	// prepare querier
@@ -413,13 +637,27 @@ func (k Keeper) Execute(ctx sdk.Context, contractAddress sdk.AccAddress, caller
 	}
 
 	gas := gasForContract(ctx)
-	res, gasUsed, execErr := k.wasmer.Execute(codeInfo.CodeHash, params, msg, prefixStore, cosmwasmAPI, querier, gasMeter(ctx), gas, verificationInfo)
+	res, gasUsed, gasBreakdown, execErr := k.wasmer.Execute(codeInfo.CodeHash, params, msg, prefixStore, cosmwasmAPI, querier, gasMeter(ctx), gas, verificationInfo, simulate)
 	consumeGas(ctx, gasUsed)
 
 	if execErr != nil {
 		return nil, sdkerrors.Wrap(types.ErrExecuteFailed, execErr.Error())
 	}
 
+	ctx.EventManager().EmitEvent(sdk.NewEvent(
+		types.EventTypeGasBreakdown,
+		sdk.NewAttribute(types.AttributeKeyContract, contractAddress.String()),
+		sdk.NewAttribute(types.AttributeKeyGasWasm, fmt.Sprintf("%d", gasBreakdown.Wasm)),
+		sdk.NewAttribute(types.AttributeKeyGasStorage, fmt.Sprintf("%d", gasBreakdown.Storage)),
+		sdk.NewAttribute(types.AttributeKeyGasOcall, fmt.Sprintf("%d", gasBreakdown.Ocall)),
+	))
+
+	if simulate {
+		return &sdk.Result{
+			Data: res.Data,
+		}, nil
+	}
+
 	//var res wasmTypes.CosmosResponse
 	//err = json.Unmarshal(res, &res)
 	//if err != nil {
@@ -427,7 +665,7 @@ func (k Keeper) Execute(ctx sdk.Context, contractAddress sdk.AccAddress, caller
 	//}
 
 	// emit all events from this contract itself
-	events := types.ParseEvents(res.Log, contractAddress)
+	events := types.ParseEvents(res.Log, contractAddress, params.ExecutionPath)
 	ctx.EventManager().EmitEvents(events)
 
 	// TODO: capture events here as well
@@ -436,38 +674,251 @@ func (k Keeper) Execute(ctx sdk.Context, contractAddress sdk.AccAddress, caller
 		return nil, err
 	}
 
+	data, resultSig := res.Data, res.ReplyResultSig
+	replyData, replySig, err := k.dispatchSubmessages(ctx, contractAddress, msg, len(res.Messages), res.Submessages, params.ReplyTo)
+	if err != nil {
+		return nil, err
+	}
+	// a reply may override the data of the contract, and then signs it in its place
+	if replyData != nil {
+		data, resultSig = replyData, replySig
+	}
+	if submessage != nil {
+		submessage.ResultSig = resultSig
+	}
+
 	return &sdk.Result{
-		Data: res.Data,
+		Data: data,
 	}, nil
 }
 
-/*
-// We don't use this function currently. It's here for upstream compatibility
-// Migrate allows to upgrade a contract to a new code with data migration.
-func (k Keeper) Migrate(ctx sdk.Context, contractAddress sdk.AccAddress, caller sdk.AccAddress, newCodeID uint64, msg []byte) (*sdk.Result, error) {
-	return k.migrate(ctx, contractAddress, caller, newCodeID, msg, k.authZPolicy)
+// RotateContractKey re-encrypts up to chunkSize entries of the contract's storage under a new contract key.
+// Only the creator of the contract may rotate its key. The first call starts the rotation and switches
+// the contract to the new key right away; the enclave keeps reading the entries that weren't moved yet
+// with the previous key. Returns whether all the entries were re-encrypted, otherwise the rotation
+// continues from where it stopped on the next call.
+func (k Keeper) RotateContractKey(ctx sdk.Context, contractAddress sdk.AccAddress, caller sdk.AccAddress, chunkSize uint32) (bool, error) {
+	ctx.GasMeter().ConsumeGas(InstanceCost, "Loading CosmWasm module: rotate contract key")
+
+	signerSig, signBytes, signMode, err := k.GetSignerInfo(ctx, caller)
+	if err != nil {
+		return false, err
+	}
+
+	additionalSigners, err := k.GetAdditionalSignersInfo(ctx, caller)
+	if err != nil {
+		return false, err
+	}
+
+	verificationInfo := types.NewVerificationInfo(signBytes, signerSig, nil)
+	verificationInfo.AdditionalSigners = additionalSigners
+	verificationInfo.SignMode = signMode
+
+	contractInfo := k.GetContractInfo(ctx, contractAddress)
+	if contractInfo == nil {
+		return false, sdkerrors.Wrap(sdkerrors.ErrInvalidRequest, "unknown contract")
+	}
+
+	codeInfo, prefixStore, err := k.contractInstance(ctx, contractAddress)
+	if err != nil {
+		return false, err
+	}
+
+	store := ctx.KVStore(k.storeKey)
+	contractKey := store.Get(types.GetContractEnclaveKey(contractAddress))
+	cursor := store.Get(types.GetContractKeyRotationCursorKey(contractAddress))
+
+	var noDeposit sdk.Coins
+	params := k.newEnv(ctx, caller, noDeposit, contractAddress, contractKey)
+	params.BlockGasRemaining = blockGasForContract(ctx)
+	// the enclave verifies the admin and authenticates the contract key against the code of the contract.
+	// Key rotations stay with the creator, which has no admin proof, so it's passed without one
+	params.Contract.Admin = contractInfo.Creator.String()
+	params.CodeHash = hex.EncodeToString(codeInfo.CodeHash)
+
+	// prepare querier
+	querier := QueryHandler{
+		Ctx:     ctx,
+		Plugins: k.queryPlugins,
+	}
+
+	gas := gasForContract(ctx)
+	newContractKey, nextCursor, gasUsed, err := k.wasmer.RotateContractKey(codeInfo.CodeHash, params, prefixStore, cosmwasmAPI, querier, gasMeter(ctx), gas, verificationInfo, cursor, chunkSize)
+	consumeGas(ctx, gasUsed)
+	if err != nil {
+		return false, sdkerrors.Wrap(types.ErrKeyRotationFailed, err.Error())
+	}
+
+	store.Set(types.GetContractEnclaveKey(contractAddress), newContractKey)
+	if len(nextCursor) == 0 {
+		store.Delete(types.GetContractKeyRotationCursorKey(contractAddress))
+		return true, nil
+	}
+	store.Set(types.GetContractKeyRotationCursorKey(contractAddress), nextCursor)
+	return false, nil
 }
 
-func (k Keeper) migrate(ctx sdk.Context, contractAddress sdk.AccAddress, caller sdk.AccAddress, newCodeID uint64, msg []byte, authZ AuthorizationPolicy) (*sdk.Result, error) {
+// ExportContractState exports up to chunkSize entries of the contract's storage, starting from cursor,
+// for a backup. An empty cursor and snapshotNonce start a new export, a snapshot at the height of the
+// current block; the following chunks of the same export are requested with the snapshot nonce it
+// returned. The nonce is derived from the contract and that height, so every node exports the same
+// chunks. Every entry comes with a MAC of the enclave, so the chunks can only be imported back into the
+// same contract, unchanged.
+// Returns the snapshot nonce, the chunk and the cursor to continue from, which is empty at the end.
+func (k Keeper) ExportContractState(ctx sdk.Context, contractAddress sdk.AccAddress, cursor []byte, snapshotNonce []byte, chunkSize uint32) ([]byte, []byte, []byte, error) {
+	ctx.GasMeter().ConsumeGas(InstanceCost, "Loading CosmWasm module: export contract state")
+
+	contractInfo := k.GetContractInfo(ctx, contractAddress)
+	if contractInfo == nil {
+		return nil, nil, nil, sdkerrors.Wrap(sdkerrors.ErrInvalidRequest, "unknown contract")
+	}
+
+	codeInfo, prefixStore, err := k.contractInstance(ctx, contractAddress)
+	if err != nil {
+		return nil, nil, nil, err
+	}
+
+	contractKey := ctx.KVStore(k.storeKey).Get(types.GetContractEnclaveKey(contractAddress))
+
+	var noDeposit sdk.Coins
+	params := k.newEnv(ctx, contractInfo.Creator, noDeposit, contractAddress, contractKey)
+	params.BlockGasRemaining = blockGasForContract(ctx)
+	params.CodeHash = hex.EncodeToString(codeInfo.CodeHash)
+
+	querier := QueryHandler{
+		Ctx:     ctx,
+		Plugins: k.queryPlugins,
+	}
+
+	gas := gasForContract(ctx)
+	nonce, chunk, nextCursor, gasUsed, err := k.wasmer.ExportContractState(codeInfo.CodeHash, params, prefixStore, cosmwasmAPI, querier, gasMeter(ctx), gas, cursor, snapshotNonce, chunkSize)
+	consumeGas(ctx, gasUsed)
+	if err != nil {
+		return nil, nil, nil, sdkerrors.Wrap(types.ErrStateExportFailed, err.Error())
+	}
+
+	return nonce, chunk, nextCursor, nil
+}
+
+// ImportContractState writes a chunk that ExportContractState returned back to the storage of the
+// contract. Like a migration, only the admin of the contract may import into it, in a tx it signed with
+// the chunk, and the enclave checks the admin against its proof. The enclave refuses chunks of other
+// contracts and chunks that were changed, before writing any of their entries. A snapshot older than the
+// last one imported into the contract is refused, so the contract can't be rolled back past it.
+// Returns how many entries were written.
+func (k Keeper) ImportContractState(ctx sdk.Context, contractAddress sdk.AccAddress, caller sdk.AccAddress, chunk []byte) (uint32, error) {
+	ctx.GasMeter().ConsumeGas(InstanceCost, "Loading CosmWasm module: import contract state")
+
+	signerSig, signBytes, signMode, err := k.GetSignerInfo(ctx, caller)
+	if err != nil {
+		return 0, err
+	}
+
+	additionalSigners, err := k.GetAdditionalSignersInfo(ctx, caller)
+	if err != nil {
+		return 0, err
+	}
+
+	verificationInfo := types.NewVerificationInfo(signBytes, signerSig, nil)
+	verificationInfo.AdditionalSigners = additionalSigners
+	verificationInfo.SignMode = signMode
+
+	contractInfo := k.GetContractInfo(ctx, contractAddress)
+	if contractInfo == nil {
+		return 0, sdkerrors.Wrap(sdkerrors.ErrInvalidRequest, "unknown contract")
+	}
+	if !contractInfo.Admin.Equals(caller) {
+		return 0, sdkerrors.Wrap(sdkerrors.ErrUnauthorized, "can not import contract state")
+	}
+
+	snapshotHeight, err := snapshotHeightOfChunk(chunk)
+	if err != nil {
+		return 0, sdkerrors.Wrap(types.ErrStateImportFailed, err.Error())
+	}
+	store := ctx.KVStore(k.storeKey)
+	if lastHeightBz := store.Get(types.GetContractStateSnapshotKey(contractAddress)); lastHeightBz != nil {
+		lastHeight := binary.BigEndian.Uint64(lastHeightBz)
+		if snapshotHeight < lastHeight {
+			return 0, sdkerrors.Wrapf(types.ErrStateImportFailed, "snapshot of height %d is older than the last one imported, of height %d", snapshotHeight, lastHeight)
+		}
+	}
+
+	codeInfo, prefixStore, err := k.contractInstance(ctx, contractAddress)
+	if err != nil {
+		return 0, err
+	}
+
+	contractKey := store.Get(types.GetContractEnclaveKey(contractAddress))
+
+	var noDeposit sdk.Coins
+	params := k.newEnv(ctx, caller, noDeposit, contractAddress, contractKey)
+	params.BlockGasRemaining = blockGasForContract(ctx)
+	// the enclave verifies the admin against its proof
+	params.Contract.Admin = contractInfo.Admin.String()
+	params.AdminProof = store.Get(types.GetContractAdminProofKey(contractAddress))
+	params.CodeHash = hex.EncodeToString(codeInfo.CodeHash)
+
+	querier := QueryHandler{
+		Ctx:     ctx,
+		Plugins: k.queryPlugins,
+	}
+
+	gas := gasForContract(ctx)
+	imported, gasUsed, err := k.wasmer.ImportContractState(codeInfo.CodeHash, params, prefixStore, cosmwasmAPI, querier, gasMeter(ctx), gas, verificationInfo, chunk)
+	consumeGas(ctx, gasUsed)
+	if err != nil {
+		return 0, sdkerrors.Wrap(types.ErrStateImportFailed, err.Error())
+	}
+
+	store.Set(types.GetContractStateSnapshotKey(contractAddress), sdk.Uint64ToBigEndian(snapshotHeight))
+	return imported, nil
+}
+
+// snapshotHeightOfChunk reads the height of the snapshot a chunk of an export belongs to, which starts
+// its snapshot nonce. The enclave checks the nonce against the contract and the MACs of the chunk.
+func snapshotHeightOfChunk(chunk []byte) (uint64, error) {
+	var parsed struct {
+		SnapshotNonce []byte `json:"snapshot_nonce"`
+	}
+	if err := json.Unmarshal(chunk, &parsed); err != nil {
+		return 0, err
+	}
+	if len(parsed.SnapshotNonce) != 32 {
+		return 0, fmt.Errorf("malformed snapshot nonce")
+	}
+	return binary.BigEndian.Uint64(parsed.SnapshotNonce[:8]), nil
+}
+
+// Migrate upgrades a contract to new code and runs the migrate entry point of the new code with msg.
+// Only the admin of the contract may migrate it. The enclave checks the admin against the proof it
+// returned when the admin was set, so the admin in the contract info alone doesn't let anyone migrate.
+func (k Keeper) Migrate(ctx sdk.Context, contractAddress sdk.AccAddress, caller sdk.AccAddress, newCodeID uint64, msg []byte) (*sdk.Result, error) {
 	ctx.GasMeter().ConsumeGas(InstanceCost, "Loading CosmWasm module: migrate")
-	_ = authtypes.StdSignature{
-		PubKey:    secp256k1.PubKeySecp256k1{},
-		Signature: []byte{},
+
+	signerSig, signBytes, signMode, err := k.GetSignerInfo(ctx, caller)
+	if err != nil {
+		return nil, err
 	}
-	_ = []byte{}
 
-	tx := authtypes.StdTx{}
-	txBytes := ctx.TxBytes()
-	err := k.cdc.UnmarshalBinaryLengthPrefixed(txBytes, &tx)
+	additionalSigners, err := k.GetAdditionalSignersInfo(ctx, caller)
+	if err != nil {
+		return nil, err
+	}
+
+	memo, err := k.GetTxMemo(ctx)
 	if err != nil {
-		return &sdk.Result{}, sdkerrors.Wrap(types.ErrInstantiateFailed, fmt.Sprintf("Unable to decode transaction from bytes: %s", err.Error()))
+		return nil, err
 	}
 
+	verificationInfo := types.NewVerificationInfo(signBytes, signerSig, nil)
+	verificationInfo.AdditionalSigners = additionalSigners
+	verificationInfo.SignMode = signMode
+
 	contractInfo := k.GetContractInfo(ctx, contractAddress)
 	if contractInfo == nil {
 		return nil, sdkerrors.Wrap(sdkerrors.ErrInvalidRequest, "unknown contract")
 	}
-	if !authZ.CanModifyContract(contractInfo.Admin, caller) {
+	if !contractInfo.Admin.Equals(caller) {
 		return nil, sdkerrors.Wrap(sdkerrors.ErrUnauthorized, "can not migrate")
 	}
 
@@ -476,11 +927,24 @@ func (k Keeper) migrate(ctx sdk.Context, contractAddress sdk.AccAddress, caller
 		return nil, sdkerrors.Wrap(sdkerrors.ErrInvalidRequest, "unknown code")
 	}
 
+	codeInfo, prefixStore, err := k.contractInstance(ctx, contractAddress)
+	if err != nil {
+		return nil, err
+	}
+
 	store := ctx.KVStore(k.storeKey)
 	contractKey := store.Get(types.GetContractEnclaveKey(contractAddress))
 
 	var noDeposit sdk.Coins
-	params := types.NewEnv(ctx, caller, noDeposit, contractAddress, contractKey)
+	params := k.newEnv(ctx, caller, noDeposit, contractAddress, contractKey)
+	params.BlockGasRemaining = blockGasForContract(ctx)
+	params.Transaction.Memo = memo
+	// the enclave verifies the admin against its proof, and re-authenticates the contract key
+	// against the code the contract is migrating from
+	params.Contract.Admin = contractInfo.Admin.String()
+	params.AdminProof = store.Get(types.GetContractAdminProofKey(contractAddress))
+	params.CodeHash = hex.EncodeToString(codeInfo.CodeHash)
+	params.ExecutionPath = executionPath(ctx)
 
 	// prepare querier
 	querier := QueryHandler{
@@ -488,21 +952,20 @@ func (k Keeper) migrate(ctx sdk.Context, contractAddress sdk.AccAddress, caller
 		Plugins: k.queryPlugins,
 	}
 
-	prefixStoreKey := types.GetContractStorePrefixKey(contractAddress)
-	prefixStore := prefix.NewStore(ctx.KVStore(k.storeKey), prefixStoreKey)
 	gas := gasForContract(ctx)
-	res, gasUsed, err := k.wasmer.Migrate(newCodeInfo.CodeHash, params, msg, &prefixStore, cosmwasmAPI, &querier, gasMeter(ctx), gas)
+	res, newContractKey, gasUsed, err := k.wasmer.Migrate(newCodeInfo.CodeHash, params, msg, prefixStore, cosmwasmAPI, querier, gasMeter(ctx), gas, verificationInfo)
 	consumeGas(ctx, gasUsed)
 	if err != nil {
 		return nil, sdkerrors.Wrap(types.ErrMigrationFailed, err.Error())
 	}
 
+	store.Set(types.GetContractEnclaveKey(contractAddress), newContractKey)
+
 	// emit all events from this contract itself
-	events := types.ParseEvents(res.Log, contractAddress)
+	events := types.ParseEvents(res.Log, contractAddress, params.ExecutionPath)
 	ctx.EventManager().EmitEvents(events)
 
-	historyEntry := contractInfo.AddMigration(ctx, newCodeID, msg)
-	k.appendToContractHistory(ctx, contractAddress, historyEntry)
+	contractInfo.CodeID = newCodeID
 	k.setContractInfo(ctx, contractAddress, contractInfo)
 
 	if err := k.dispatchMessages(ctx, contractAddress, res.Messages); err != nil {
@@ -516,46 +979,77 @@ func (k Keeper) migrate(ctx sdk.Context, contractAddress sdk.AccAddress, caller
 
 // UpdateContractAdmin sets the admin value on the ContractInfo. It must be a valid address (use ClearContractAdmin to remove it)
 func (k Keeper) UpdateContractAdmin(ctx sdk.Context, contractAddress sdk.AccAddress, caller sdk.AccAddress, newAdmin sdk.AccAddress) error {
-	return k.setContractAdmin(ctx, contractAddress, caller, newAdmin, k.authZPolicy)
+	return k.setContractAdmin(ctx, contractAddress, caller, newAdmin)
 }
 
 // ClearContractAdmin sets the admin value on the ContractInfo to nil, to disable further migrations/ updates.
 func (k Keeper) ClearContractAdmin(ctx sdk.Context, contractAddress sdk.AccAddress, caller sdk.AccAddress) error {
-	return k.setContractAdmin(ctx, contractAddress, caller, nil, k.authZPolicy)
+	return k.setContractAdmin(ctx, contractAddress, caller, nil)
 }
 
-func (k Keeper) setContractAdmin(ctx sdk.Context, contractAddress, caller, newAdmin sdk.AccAddress, authZ AuthorizationPolicy) error {
+// setContractAdmin replaces the admin of the contract with newAdmin, or removes it if newAdmin is nil.
+// The enclave checks that the caller is the current admin and signed this update, and returns the
+// proof of the new admin, which migrations are checked against from now on.
+func (k Keeper) setContractAdmin(ctx sdk.Context, contractAddress, caller, newAdmin sdk.AccAddress) error {
 	contractInfo := k.GetContractInfo(ctx, contractAddress)
 	if contractInfo == nil {
 		return sdkerrors.Wrap(sdkerrors.ErrInvalidRequest, "unknown contract")
 	}
-	if !authZ.CanModifyContract(contractInfo.Admin, caller) {
+	if !contractInfo.Admin.Equals(caller) {
 		return sdkerrors.Wrap(sdkerrors.ErrUnauthorized, "can not modify contract")
 	}
+
+	signerSig, signBytes, signMode, err := k.GetSignerInfo(ctx, caller)
+	if err != nil {
+		return err
+	}
+
+	additionalSigners, err := k.GetAdditionalSignersInfo(ctx, caller)
+	if err != nil {
+		return err
+	}
+
+	verificationInfo := types.NewVerificationInfo(signBytes, signerSig, nil)
+	verificationInfo.AdditionalSigners = additionalSigners
+	verificationInfo.SignMode = signMode
+
+	store := ctx.KVStore(k.storeKey)
+
+	var noDeposit sdk.Coins
+	params := k.newEnv(ctx, caller, noDeposit, contractAddress, nil)
+	params.Contract.Admin = contractInfo.Admin.String()
+	params.AdminProof = store.Get(types.GetContractAdminProofKey(contractAddress))
+
+	// a nil new admin clears the admin in the enclave as well
+	var signedNewAdmin []byte
+	if newAdmin != nil {
+		signedNewAdmin = []byte(newAdmin.String())
+	}
+	adminProof, err := k.wasmer.UpdateAdmin(params, verificationInfo, signedNewAdmin)
+	if err != nil {
+		return sdkerrors.Wrap(sdkerrors.ErrUnauthorized, err.Error())
+	}
+
 	contractInfo.Admin = newAdmin
 	k.setContractInfo(ctx, contractAddress, contractInfo)
+	store.Set(types.GetContractAdminProofKey(contractAddress), adminProof)
 	return nil
 }
 
-func (k Keeper) appendToContractHistory(ctx sdk.Context, contractAddr sdk.AccAddress, newEntries ...types.ContractCodeHistoryEntry) {
-	entries := append(k.GetContractHistory(ctx, contractAddr), newEntries...)
-	prefixStore := prefix.NewStore(ctx.KVStore(k.storeKey), types.ContractHistoryStorePrefix)
-	prefixStore.Set(contractAddr, k.cdc.MustMarshalBinaryBare(&entries))
+// QuerySmart queries the smart contract itself. Contracts query other contracts through it, both
+// during txs and during queries.
+func (k Keeper) QuerySmart(ctx sdk.Context, contractAddr sdk.AccAddress, req []byte, useDefaultGasLimit bool) ([]byte, error) {
+	return k.querySmart(ctx, contractAddr, req, useDefaultGasLimit, false)
 }
 
-func (k Keeper) GetContractHistory(ctx sdk.Context, contractAddr sdk.AccAddress) []types.ContractCodeHistoryEntry {
-	prefixStore := prefix.NewStore(ctx.KVStore(k.storeKey), types.ContractHistoryStorePrefix)
-	var entries []types.ContractCodeHistoryEntry
-	bz := prefixStore.Get(contractAddr)
-	if bz != nil {
-		k.cdc.MustUnmarshalBinaryBare(bz, &entries)
-	}
-	return entries
+// querySmartFromClient is QuerySmart for the queries clients send to the node. How long a query
+// runs depends on the machine, so only these are stopped after queryTimeoutMs, and only these use
+// up the nonces of replay protected queries. A query during a tx has to run the same on every node.
+func (k Keeper) querySmartFromClient(ctx sdk.Context, contractAddr sdk.AccAddress, req []byte) ([]byte, error) {
+	return k.querySmart(ctx, contractAddr, req, false, true)
 }
-*/
 
-// QuerySmart queries the smart contract itself.
-func (k Keeper) QuerySmart(ctx sdk.Context, contractAddr sdk.AccAddress, req []byte, useDefaultGasLimit bool) ([]byte, error) {
+func (k Keeper) querySmart(ctx sdk.Context, contractAddr sdk.AccAddress, req []byte, useDefaultGasLimit bool, fromClient bool) ([]byte, error) {
 	if useDefaultGasLimit {
 		ctx = ctx.WithGasMeter(sdk.NewGasMeter(k.queryGasLimit))
 	}
@@ -576,9 +1070,24 @@ func (k Keeper) QuerySmart(ctx sdk.Context, contractAddr sdk.AccAddress, req []b
 	// 0x01 | codeID (uint64) -> ContractInfo
 	contractKey := store.Get(types.GetContractEnclaveKey(contractAddr))
 
-	queryResult, gasUsed, qErr := k.wasmer.Query(codeInfo.CodeHash, append(contractKey[:], req[:]...), prefixStore, cosmwasmAPI, querier, gasMeter(ctx), gasForContract(ctx))
+	// queries have no sender or funds, but contracts may want to see the block info
+	var noSender sdk.AccAddress
+	var noDeposit sdk.Coins
+	params := k.newEnv(ctx, noSender, noDeposit, contractAddr, nil)
+	if fromClient {
+		params.ExecutionTimeoutMs = k.queryTimeoutMs
+		params.ClientQuery = true
+	}
+	// the enclave checks this against the code it gets
+	params.CodeHash = hex.EncodeToString(codeInfo.CodeHash)
+
+	queryResult, gasUsed, qErr := k.wasmer.Query(codeInfo.CodeHash, params, append(contractKey[:], req[:]...), prefixStore, cosmwasmAPI, querier, gasMeter(ctx), gasForContract(ctx))
 	consumeGas(ctx, gasUsed)
 
+	if _, ok := qErr.(wasmTypes.OutOfGasError); ok {
+		// not a failure of the enclave, the contract just didn't finish within the limit
+		return nil, sdkerrors.Wrap(types.ErrQueryFailed, fmt.Sprintf("query exceeded gas (used %d wasm gas)", gasUsed))
+	}
 	if qErr != nil {
 		return nil, sdkerrors.Wrap(types.ErrQueryFailed, qErr.Error())
 	}
@@ -604,25 +1113,45 @@ func (k Keeper) QueryRaw(ctx sdk.Context, contractAddress sdk.AccAddress, key []
 	return result
 }
 
-func (k Keeper) contractInstance(ctx sdk.Context, contractAddress sdk.AccAddress) (types.CodeInfo, prefix.Store, error) {
+func (k Keeper) contractInstance(ctx sdk.Context, contractAddress sdk.AccAddress) (types.CodeInfo, contractStore, error) {
 	store := ctx.KVStore(k.storeKey)
 
 	contractBz := store.Get(types.GetContractAddressKey(contractAddress))
 	if contractBz == nil {
-		return types.CodeInfo{}, prefix.Store{}, sdkerrors.Wrap(types.ErrNotFound, "contract")
+		return types.CodeInfo{}, contractStore{}, sdkerrors.Wrap(types.ErrNotFound, "contract")
 	}
 	var contract types.ContractInfo
 	k.cdc.MustUnmarshalBinaryBare(contractBz, &contract)
 
 	contractInfoBz := store.Get(types.GetCodeKey(contract.CodeID))
 	if contractInfoBz == nil {
-		return types.CodeInfo{}, prefix.Store{}, sdkerrors.Wrap(types.ErrNotFound, "contract info")
+		return types.CodeInfo{}, contractStore{}, sdkerrors.Wrap(types.ErrNotFound, "contract info")
 	}
 	var codeInfo types.CodeInfo
 	k.cdc.MustUnmarshalBinaryBare(contractInfoBz, &codeInfo)
+	return codeInfo, k.contractStore(ctx, contractAddress), nil
+}
+
+// contractStore is the storage of a contract as the enclave sees it. Reads are charged, except
+// the ones the enclave makes to read ahead, see wasm.UnmeteredReader
+type contractStore struct {
+	prefix.Store
+	unmetered prefix.Store
+}
+
+var _ wasm.UnmeteredReader = contractStore{}
+
+func (s contractStore) GetUnmetered(key []byte) []byte {
+	return s.unmetered.Get(key)
+}
+
+// 0x03 | contractAddress (sdk.AccAddress)
+func (k Keeper) contractStore(ctx sdk.Context, contractAddress sdk.AccAddress) contractStore {
 	prefixStoreKey := types.GetContractStorePrefixKey(contractAddress)
-	prefixStore := prefix.NewStore(ctx.KVStore(k.storeKey), prefixStoreKey)
-	return codeInfo, prefixStore, nil
+	return contractStore{
+		Store:     prefix.NewStore(ctx.KVStore(k.storeKey), prefixStoreKey),
+		unmetered: prefix.NewStore(ctx.MultiStore().GetKVStore(k.storeKey), prefixStoreKey),
+	}
 }
 
 func (k Keeper) GetContractKey(ctx sdk.Context, contractAddress sdk.AccAddress) []byte {
@@ -734,6 +1263,39 @@ func (k Keeper) IterateCodeInfos(ctx sdk.Context, cb func(uint64, types.CodeInfo
 	}
 }
 
+// PrewarmContracts validates the stored codes, in the order they were stored, and caches them in the
+// enclave until the prewarm budget runs out. It's meant to be called once when the node starts, and
+// doesn't change any state, so codes that fail to prewarm are only logged
+func (k Keeper) PrewarmContracts(ctx sdk.Context) {
+	if k.prewarmMaxBytes == 0 {
+		return
+	}
+
+	var codeHashes []wasm.CodeID
+	k.IterateCodeInfos(ctx, func(_ uint64, codeInfo types.CodeInfo) bool {
+		codeHashes = append(codeHashes, codeInfo.CodeHash)
+		return false
+	})
+	if len(codeHashes) == 0 {
+		return
+	}
+
+	statuses, err := k.wasmer.PrewarmContracts(codeHashes, k.prewarmMaxBytes, k.prewarmTimeoutMs)
+	if err != nil {
+		ctx.Logger().Error("failed to prewarm contracts", "error", err.Error())
+		return
+	}
+
+	counts := make(map[string]int)
+	for i, status := range statuses {
+		counts[status]++
+		if status != wasmTypes.PrewarmWarmed && status != wasmTypes.PrewarmAlreadyCached && status != wasmTypes.PrewarmOverBudget {
+			ctx.Logger().Info("failed to prewarm contract", "code_hash", hex.EncodeToString(codeHashes[i]), "status", status)
+		}
+	}
+	ctx.Logger().Info("prewarmed contracts", "statuses", counts)
+}
+
 func (k Keeper) GetByteCode(ctx sdk.Context, codeID uint64) ([]byte, error) {
 	store := ctx.KVStore(k.storeKey)
 	var codeInfo types.CodeInfo
@@ -745,15 +1307,317 @@ func (k Keeper) GetByteCode(ctx sdk.Context, codeID uint64) ([]byte, error) {
 	return k.wasmer.GetCode(codeInfo.CodeHash)
 }
 
+// executionPathKey is the key of the context value that holds where in the tx the contracts called
+// with the context run, as the Env.ExecutionPath they are passed
+type executionPathKey struct{}
+
+// executionPath returns the path of the calls made with ctx. Calls that aren't made by a contract
+// are the root of the tx, with an empty path
+func executionPath(ctx sdk.Context) []uint32 {
+	if path, ok := ctx.Value(executionPathKey{}).([]uint32); ok {
+		return path
+	}
+	return []uint32{}
+}
+
+// withExecutionStep returns the context of a message or reply that is a step of the call made with ctx
+func withExecutionStep(ctx sdk.Context, step int) sdk.Context {
+	parent := executionPath(ctx)
+	// copied, so the paths of siblings never share their backing array
+	path := make([]uint32, len(parent), len(parent)+1)
+	copy(path, parent)
+	ctx = ctx.WithValue(executionPathKey{}, append(path, uint32(step)))
+	// the steps of a call don't run for the submessage the call runs for
+	return ctx.WithValue(submessageReplyKey{}, (*submessageReply)(nil))
+}
+
+// submessageReplyKey is the key of the context value that holds the submessage a contract called with the
+// context runs for
+type submessageReplyKey struct{}
+
+// submessageReply is a submessage that calls a contract. The contract passes ReplyTo to the enclave, and
+// sets ResultSig to the signature the enclave returned for its result, for the reply to the submessage
+type submessageReply struct {
+	ReplyTo   wasmTypes.ReplyTo
+	ResultSig []byte
+}
+
+// submessageReplyOf returns the submessage the contract called with ctx runs for, if any
+func submessageReplyOf(ctx sdk.Context) *submessageReply {
+	submessage, _ := ctx.Value(submessageReplyKey{}).(*submessageReply)
+	return submessage
+}
+
 func (k Keeper) dispatchMessages(ctx sdk.Context, contractAddr sdk.AccAddress, msgs []wasmTypes.CosmosMsg) error {
-	for _, msg := range msgs {
-		if err := k.messenger.Dispatch(ctx, contractAddr, msg); err != nil {
+	for i, msg := range msgs {
+		if err := k.messenger.Dispatch(withExecutionStep(ctx, i), contractAddr, msg); err != nil {
 			return err
 		}
 	}
 	return nil
 }
 
+// dispatchSubmessages executes the submessages of a contract, and passes their results back to it
+// as requested by their ReplyOn. encryptedMsg is the message the contract was called with: replies are
+// authenticated and encrypted with its nonce and public key.
+// Returns the data of the last reply that set any, with the signature the enclave returned for it when
+// replyTo is set. replyTo is the submessage the contract runs for, and is passed on to the replies.
+// numMsgs is how many messages the contract returned along with the submessages: submessage i is
+// step numMsgs+2i of the execution path, and the reply to it the step after.
+func (k Keeper) dispatchSubmessages(ctx sdk.Context, contractAddr sdk.AccAddress, encryptedMsg []byte, numMsgs int, submsgs []wasmTypes.SubMsg, replyTo *wasmTypes.ReplyTo) ([]byte, []byte, error) {
+	if len(submsgs) == 0 {
+		return nil, nil, nil
+	}
+	if len(encryptedMsg) < 64 {
+		return nil, nil, sdkerrors.Wrap(types.ErrInvalidMsg, "message is too short to contain a nonce and a public key")
+	}
+	nonce := encryptedMsg[0:32]
+	userPublicKey := encryptedMsg[32:64]
+
+	var data, dataSig []byte
+	for i, msg := range submsgs {
+		step := numMsgs + 2*i
+		// each submessage runs in its own cache, so a failed one can be reverted without
+		// reverting the whole tx
+		subCtx, commit := ctx.CacheContext()
+		subCtx = withExecutionStep(subCtx.WithEventManager(sdk.NewEventManager()), step)
+
+		msgData, resultSig, err := k.dispatchSubmessage(subCtx, contractAddr, msg)
+		if err == nil {
+			commit()
+			ctx.EventManager().EmitEvents(subCtx.EventManager().Events())
+		}
+
+		switch msg.ReplyOn {
+		case wasmTypes.ReplySuccess, wasmTypes.ReplyNever:
+			if err != nil {
+				return nil, nil, err
+			}
+			if msg.ReplyOn == wasmTypes.ReplyNever {
+				continue
+			}
+		case wasmTypes.ReplyError:
+			if err == nil {
+				continue
+			}
+		}
+
+		var result wasmTypes.SubMsgResult
+		if err == nil {
+			result.Ok = &wasmTypes.SubMsgExecutionResponse{Data: msgData}
+		} else {
+			result.Error = err.Error()
+		}
+
+		replyData, replySig, err := k.reply(withExecutionStep(ctx, step+1), contractAddr, wasmTypes.SignedReply{
+			ID:            msg.ID,
+			Result:        result,
+			ReplySig:      msg.ReplySig,
+			ResultSig:     resultSig,
+			Nonce:         nonce,
+			UserPublicKey: userPublicKey,
+		}, replyTo)
+		if err != nil {
+			return nil, nil, err
+		}
+		if replyData != nil {
+			data, dataSig = replyData, replySig
+		}
+	}
+	return data, dataSig, nil
+}
+
+// dispatchSubmessage executes a single submessage, within its gas limit if it has one. When the
+// submessage calls a contract, the signature the enclave returned for its result is returned with it
+func (k Keeper) dispatchSubmessage(ctx sdk.Context, contractAddr sdk.AccAddress, msg wasmTypes.SubMsg) (data []byte, resultSig []byte, err error) {
+	var submessage *submessageReply
+	if msg.Msg.Wasm != nil {
+		submessage = &submessageReply{ReplyTo: wasmTypes.ReplyTo{
+			ID:       msg.ID,
+			Contract: contractAddr.String(),
+			ReplySig: msg.ReplySig,
+		}}
+	}
+	ctx = ctx.WithValue(submessageReplyKey{}, submessage)
+	signedResult := func(data []byte, err error) ([]byte, []byte, error) {
+		if err != nil || submessage == nil {
+			return data, nil, err
+		}
+		return data, submessage.ResultSig, nil
+	}
+
+	if msg.GasLimit == nil {
+		return signedResult(k.messenger.DispatchWithData(ctx, contractAddr, msg.Msg))
+	}
+
+	limitedCtx := ctx.WithGasMeter(sdk.NewGasMeter(*msg.GasLimit))
+	defer func() {
+		if r := recover(); r != nil {
+			// only running out of the submessage's own gas limit is an error of the submessage
+			if _, ok := r.(sdk.ErrorOutOfGas); !ok {
+				panic(r)
+			}
+			data, resultSig = nil, nil
+			err = sdkerrors.Wrap(sdkerrors.ErrOutOfGas, "submessage hit its gas limit")
+		}
+		// the gas used by the submessage is charged to the tx either way
+		ctx.GasMeter().ConsumeGas(limitedCtx.GasMeter().GasConsumedToLimit(), "submessage")
+	}()
+
+	return signedResult(k.messenger.DispatchWithData(limitedCtx, contractAddr, msg.Msg))
+}
+
+// reply passes the result of a submessage back to the contract that sent it. replyTo is the submessage
+// the contract runs for, if any: the data of the reply is then returned with the signature the enclave
+// returned for it
+func (k Keeper) reply(ctx sdk.Context, contractAddress sdk.AccAddress, reply wasmTypes.SignedReply, replyTo *wasmTypes.ReplyTo) ([]byte, []byte, error) {
+	ctx.GasMeter().ConsumeGas(InstanceCost, "Loading CosmWasm module: reply")
+
+	codeInfo, prefixStore, err := k.contractInstance(ctx, contractAddress)
+	if err != nil {
+		return nil, nil, err
+	}
+
+	store := ctx.KVStore(k.storeKey)
+	contractKey := store.Get(types.GetContractEnclaveKey(contractAddress))
+	params := k.newEnv(ctx, contractAddress, sdk.NewCoins(), contractAddress, contractKey)
+	params.BlockGasRemaining = blockGasForContract(ctx)
+	params.ExecutionPath = executionPath(ctx)
+	params.ReplyTo = replyTo
+
+	// prepare querier
+	querier := QueryHandler{
+		Ctx:     ctx,
+		Plugins: k.queryPlugins,
+	}
+
+	replyBz, err := json.Marshal(reply)
+	if err != nil {
+		return nil, nil, err
+	}
+
+	gas := gasForContract(ctx)
+	res, gasUsed, replyErr := k.wasmer.Reply(codeInfo.CodeHash, params, replyBz, prefixStore, cosmwasmAPI, querier, gasMeter(ctx), gas)
+	consumeGas(ctx, gasUsed)
+
+	if replyErr != nil {
+		return nil, nil, sdkerrors.Wrap(types.ErrReplyFailed, replyErr.Error())
+	}
+
+	// emit all events from this contract itself
+	events := types.ParseEvents(res.Log, contractAddress, params.ExecutionPath)
+	ctx.EventManager().EmitEvents(events)
+
+	err = k.dispatchMessages(ctx, contractAddress, res.Messages)
+	if err != nil {
+		return nil, nil, err
+	}
+
+	// the output of the reply is encrypted with the same nonce and public key, so its submessages
+	// are replied to the same way
+	encryptedMsg := append(append([]byte{}, reply.Nonce...), reply.UserPublicKey...)
+	replyData, replySig, err := k.dispatchSubmessages(ctx, contractAddress, encryptedMsg, len(res.Messages), res.Submessages, replyTo)
+	if err != nil {
+		return nil, nil, err
+	}
+	if replyData != nil {
+		return replyData, replySig, nil
+	}
+
+	return res.Data, res.ReplyResultSig, nil
+}
+
+// IBCCall calls one of the IBC entry points of a contract that aren't encrypted, like
+// ibc_channel_open. msg is a wasmTypes.IBCChannel, wasmTypes.IBCAcknowledgement or
+// wasmTypes.IBCPacket, depending on the entry point, and must be for the port of the contract.
+func (k Keeper) IBCCall(ctx sdk.Context, contractAddress sdk.AccAddress, entryPoint uint8, msg interface{}) error {
+	ctx.GasMeter().ConsumeGas(InstanceCost, "Loading CosmWasm module: ibc")
+
+	codeInfo, prefixStore, err := k.contractInstance(ctx, contractAddress)
+	if err != nil {
+		return err
+	}
+
+	msgBz, err := json.Marshal(msg)
+	if err != nil {
+		return err
+	}
+
+	params := k.ibcEnv(ctx, contractAddress, codeInfo)
+	querier := QueryHandler{
+		Ctx:     ctx,
+		Plugins: k.queryPlugins,
+	}
+
+	gas := gasForContract(ctx)
+	res, gasUsed, ibcErr := k.wasmer.IBCCall(codeInfo.CodeHash, entryPoint, params, msgBz, prefixStore, cosmwasmAPI, querier, gasMeter(ctx), gas)
+	consumeGas(ctx, gasUsed)
+
+	if ibcErr != nil {
+		return sdkerrors.Wrap(types.ErrIBCFailed, ibcErr.Error())
+	}
+
+	// emit all events from this contract itself
+	events := types.ParseEvents(res.Log, contractAddress, nil)
+	ctx.EventManager().EmitEvents(events)
+
+	return k.dispatchMessages(ctx, contractAddress, res.Messages)
+}
+
+// IBCPacketReceive passes a packet that was sent to the port of a contract to the contract, and
+// returns the acknowledgement to write for the packet. The data of the packet is an encrypted
+// secret message, like the msg of a MsgExecuteContract, and the acknowledgement is encrypted to
+// its sender.
+func (k Keeper) IBCPacketReceive(ctx sdk.Context, contractAddress sdk.AccAddress, packet wasmTypes.IBCPacket) ([]byte, error) {
+	ctx.GasMeter().ConsumeGas(InstanceCost, "Loading CosmWasm module: ibc_packet_receive")
+
+	codeInfo, prefixStore, err := k.contractInstance(ctx, contractAddress)
+	if err != nil {
+		return nil, err
+	}
+
+	packetBz, err := json.Marshal(packet)
+	if err != nil {
+		return nil, err
+	}
+
+	params := k.ibcEnv(ctx, contractAddress, codeInfo)
+	querier := QueryHandler{
+		Ctx:     ctx,
+		Plugins: k.queryPlugins,
+	}
+
+	gas := gasForContract(ctx)
+	res, ack, gasUsed, ibcErr := k.wasmer.IBCPacketReceive(codeInfo.CodeHash, params, packetBz, prefixStore, cosmwasmAPI, querier, gasMeter(ctx), gas)
+	consumeGas(ctx, gasUsed)
+
+	if ibcErr != nil {
+		return nil, sdkerrors.Wrap(types.ErrIBCFailed, ibcErr.Error())
+	}
+
+	// emit all events from this contract itself
+	events := types.ParseEvents(res.Log, contractAddress, nil)
+	ctx.EventManager().EmitEvents(events)
+
+	err = k.dispatchMessages(ctx, contractAddress, res.Messages)
+	if err != nil {
+		return nil, err
+	}
+
+	return ack, nil
+}
+
+// ibcEnv is the env of the IBC entry points. Packets are relayed by anyone, so the contract is
+// set as the sender, like in replies
+func (k Keeper) ibcEnv(ctx sdk.Context, contractAddress sdk.AccAddress, codeInfo types.CodeInfo) wasmTypes.Env {
+	store := ctx.KVStore(k.storeKey)
+	contractKey := store.Get(types.GetContractEnclaveKey(contractAddress))
+	params := k.newEnv(ctx, contractAddress, sdk.NewCoins(), contractAddress, contractKey)
+	params.BlockGasRemaining = blockGasForContract(ctx)
+	params.CodeHash = hex.EncodeToString(codeInfo.CodeHash)
+	return params
+}
+
 func gasForContract(ctx sdk.Context) uint64 {
 	meter := ctx.GasMeter()
 	remaining := (meter.Limit() - meter.GasConsumed()) * GasMultiplier
@@ -763,6 +1627,28 @@ func gasForContract(ctx sdk.Context) uint64 {
 	return remaining
 }
 
+// blockGasForContract is the gas left in the block for the contract, or 0 when the block has no gas
+// limit. The block gas meter is only charged once the tx is done, so the gas the tx used so far is
+// taken out here
+func blockGasForContract(ctx sdk.Context) uint64 {
+	blockMeter := ctx.BlockGasMeter()
+	if blockMeter == nil || blockMeter.Limit() == 0 {
+		return 0
+	}
+	blockRemaining := blockMeter.Limit() - blockMeter.GasConsumedToLimit()
+	txUsed := ctx.GasMeter().GasConsumedToLimit()
+	if txUsed >= blockRemaining {
+		// 0 would lift the limit, so the contract gets as little as can be passed
+		return 1
+	}
+	// clamped before it's multiplied, so a huge block limit doesn't wrap around to little gas
+	remaining := blockRemaining - txUsed
+	if remaining > MaxGas/GasMultiplier {
+		return MaxGas
+	}
+	return remaining * GasMultiplier
+}
+
 func consumeGas(ctx sdk.Context, gas uint64) {
 	consumed := (gas / GasMultiplier) + 1
 	ctx.GasMeter().ConsumeGas(consumed, "wasm contract")
@@ -772,18 +1658,28 @@ func consumeGas(ctx sdk.Context, gas uint64) {
 	}
 }
 
-// generates a contract address from codeID + instanceID
-func (k Keeper) generateContractAddress(ctx sdk.Context, codeID uint64) sdk.AccAddress {
-	instanceID := k.autoIncrementID(ctx, types.KeyLastInstanceID)
-	return contractAddress(codeID, instanceID)
+// generateContractAddress returns the address of the contract creator instantiates with initMsg
+// from the code with codeHash. Instance ids are still counted, so that genesis files keep their
+// sequences.
+func (k Keeper) generateContractAddress(ctx sdk.Context, creator sdk.AccAddress, codeHash []byte, initMsg []byte) sdk.AccAddress {
+	k.autoIncrementID(ctx, types.KeyLastInstanceID)
+	return contractAddress(creator, codeHash, initMsg)
 }
 
-func contractAddress(codeID, instanceID uint64) sdk.AccAddress {
-	// NOTE: It is possible to get a duplicate address if either codeID or instanceID
-	// overflow 32 bits. This is highly improbable, but something that could be refactored.
-	contractID := codeID<<32 + instanceID
-	return addrFromUint64(contractID)
-
+// contractAddress must stay the same as derive_contract_address in the enclave, which derives the
+// address from the creator, code hash and init message it verified, and rejects contracts at any
+// other address. Instantiating the same code with the same encrypted message twice fails, as the
+// second contract would get the address of the first.
+func contractAddress(creator sdk.AccAddress, codeHash []byte, initMsg []byte) sdk.AccAddress {
+	initMsgHash := sha256.Sum256(initMsg)
+
+	hash := sha256.New()
+	hash.Write([]byte("contract_instance"))
+	hash.Write([]byte{byte(len(creator))})
+	hash.Write(creator)
+	hash.Write(codeHash)
+	hash.Write(initMsgHash[:])
+	return sdk.AccAddress(hash.Sum(nil)[:sdk.AddrLen])
 }
 
 func (k Keeper) GetNextCodeID(ctx sdk.Context) uint64 {
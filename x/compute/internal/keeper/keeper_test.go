@@ -1,11 +1,13 @@
 package keeper
 
 import (
+	"bytes"
 	"encoding/binary"
 	"encoding/hex"
 	"encoding/json"
 	"fmt"
 	"io/ioutil"
+	"math"
 	"os"
 	"path/filepath"
 	"testing"
@@ -14,7 +16,9 @@ import (
 	authtypes "github.com/enigmampc/cosmos-sdk/x/auth/types"
 	"github.com/tendermint/tendermint/crypto/secp256k1"
 
+	wasm "github.com/enigmampc/SecretNetwork/go-cosmwasm"
 	"github.com/enigmampc/SecretNetwork/go-cosmwasm/api"
+	wasmTypes "github.com/enigmampc/SecretNetwork/go-cosmwasm/types"
 	eng "github.com/enigmampc/SecretNetwork/types"
 	wasmUtils "github.com/enigmampc/SecretNetwork/x/compute/client/utils"
 	"github.com/enigmampc/SecretNetwork/x/compute/internal/types"
@@ -67,6 +71,146 @@ func TestNewKeeper(t *testing.T) {
 	require.NotNil(t, keepers.WasmKeeper)
 }
 
+func TestConfigureEnclaveRuntime(t *testing.T) {
+	tempDir, err := ioutil.TempDir("", "wasm")
+	require.NoError(t, err)
+	defer os.RemoveAll(tempDir)
+	wasmer, err := wasm.NewWasmer(filepath.Join(tempDir, "wasm"), SupportedFeatures, 0)
+	require.NoError(t, err)
+
+	wasmConfig := types.DefaultWasmConfig()
+	require.NoError(t, configureEnclaveRuntime(wasmer, wasmConfig))
+
+	wasmConfig.EnclaveLogLevel = "Info"
+	wasmConfig.QueryCacheSize = 1 << 20
+	require.NoError(t, configureEnclaveRuntime(wasmer, wasmConfig))
+
+	wasmConfig.EnclaveLogLevel = "verbose"
+	require.Error(t, configureEnclaveRuntime(wasmer, wasmConfig))
+}
+
+func TestParamsInEnv(t *testing.T) {
+	tempDir, err := ioutil.TempDir("", "wasm")
+	require.NoError(t, err)
+	defer os.RemoveAll(tempDir)
+	ctx, keepers := CreateTestInput(t, false, tempDir, SupportedFeatures, nil, nil)
+	keeper := keepers.WasmKeeper
+	contractAddr := sdk.AccAddress([]byte("contract-address----"))
+
+	// a chain that never set the params gets the defaults of the enclave
+	require.Equal(t, types.DefaultParams(), keeper.GetParams(ctx))
+	env := keeper.newEnv(ctx, nil, nil, contractAddr, nil)
+	require.Equal(t, types.DefaultParams().OcallGasCosts, *env.OcallGasCosts)
+
+	params := types.DefaultParams()
+	params.OcallGasCosts.ReadDb = 7_000
+	keeper.setParams(ctx, params)
+	require.Equal(t, params, keeper.GetParams(ctx))
+	env = keeper.newEnv(ctx, nil, nil, contractAddr, nil)
+	require.Equal(t, uint64(7_000), env.OcallGasCosts.ReadDb)
+
+	params.OcallGasCosts.WriteDb = 0
+	require.Error(t, params.ValidateBasic())
+}
+
+func TestSeedRotationParam(t *testing.T) {
+	tempDir, err := ioutil.TempDir("", "wasm")
+	require.NoError(t, err)
+	defer os.RemoveAll(tempDir)
+	ctx, keepers := CreateTestInput(t, false, tempDir, SupportedFeatures, nil, nil)
+	keeper := keepers.WasmKeeper
+
+	params := types.DefaultParams()
+	params.SeedRotation = `{"generation":1,"activation_height":100,"encrypted_seeds":[]}`
+	require.NoError(t, params.ValidateBasic())
+	height, ok, err := types.SeedRotationActivationHeight(params.SeedRotation)
+	require.NoError(t, err)
+	require.True(t, ok)
+	require.Equal(t, int64(100), height)
+
+	// a rotation that was activated isn't handed to the enclave anymore
+	keeper.setParams(ctx, params)
+	keeper.SubmitSeedRotation(ctx.WithBlockHeight(100))
+	require.Equal(t, "", *keeper.submittedSeedRotation)
+
+	for _, rotation := range []string{
+		`{"generation":0,"activation_height":100}`,
+		`{"generation":1}`,
+		`not a rotation`,
+	} {
+		params.SeedRotation = rotation
+		require.Error(t, params.ValidateBasic(), rotation)
+	}
+}
+
+func TestMaxMemoryPagesParam(t *testing.T) {
+	tempDir, err := ioutil.TempDir("", "wasm")
+	require.NoError(t, err)
+	defer os.RemoveAll(tempDir)
+	ctx, keepers := CreateTestInput(t, false, tempDir, SupportedFeatures, nil, nil)
+	keeper := keepers.WasmKeeper
+	contractAddr := sdk.AccAddress([]byte("contract-address----"))
+
+	// until governance raises the limit, the enclave keeps its legacy one
+	env := keeper.newEnv(ctx, nil, nil, contractAddr, nil)
+	require.Equal(t, uint32(0), env.MaxMemoryPages)
+
+	params := types.DefaultParams()
+	params.MaxMemoryPages = types.MaxMemoryPagesLimit
+	require.NoError(t, params.ValidateBasic())
+	keeper.setParams(ctx, params)
+	env = keeper.newEnv(ctx, nil, nil, contractAddr, nil)
+	require.Equal(t, uint32(types.MaxMemoryPagesLimit), env.MaxMemoryPages)
+
+	for _, pages := range []uint32{1, types.LegacyMaxMemoryPages - 1, types.MaxMemoryPagesLimit + 1} {
+		params.MaxMemoryPages = pages
+		require.Error(t, params.ValidateBasic(), pages)
+	}
+}
+
+func TestOutputLimitsParam(t *testing.T) {
+	tempDir, err := ioutil.TempDir("", "wasm")
+	require.NoError(t, err)
+	defer os.RemoveAll(tempDir)
+	ctx, keepers := CreateTestInput(t, false, tempDir, SupportedFeatures, nil, nil)
+	keeper := keepers.WasmKeeper
+	contractAddr := sdk.AccAddress([]byte("contract-address----"))
+
+	// until governance sets limits, the enclave keeps its defaults
+	env := keeper.newEnv(ctx, nil, nil, contractAddr, nil)
+	require.Nil(t, env.OutputLimits)
+
+	params := types.DefaultParams()
+	params.OutputLimits = wasmTypes.OutputLimits{MaxMessages: 16}
+	require.NoError(t, params.ValidateBasic())
+	keeper.setParams(ctx, params)
+	env = keeper.newEnv(ctx, nil, nil, contractAddr, nil)
+	require.Equal(t, &wasmTypes.OutputLimits{MaxMessages: 16}, env.OutputLimits)
+
+	params.OutputLimits.MaxBytes = types.MaxOutputBytesLimit + 1
+	require.Error(t, params.ValidateBasic())
+}
+
+func TestStorageQuotaParam(t *testing.T) {
+	tempDir, err := ioutil.TempDir("", "wasm")
+	require.NoError(t, err)
+	defer os.RemoveAll(tempDir)
+	ctx, keepers := CreateTestInput(t, false, tempDir, SupportedFeatures, nil, nil)
+	keeper := keepers.WasmKeeper
+	contractAddr := sdk.AccAddress([]byte("contract-address----"))
+
+	// until governance sets a quota, the storage of contracts isn't limited
+	env := keeper.newEnv(ctx, nil, nil, contractAddr, nil)
+	require.Equal(t, uint64(0), env.StorageQuota)
+
+	params := types.DefaultParams()
+	params.StorageQuota = 1 << 20
+	require.NoError(t, params.ValidateBasic())
+	keeper.setParams(ctx, params)
+	env = keeper.newEnv(ctx, nil, nil, contractAddr, nil)
+	require.Equal(t, uint64(1<<20), env.StorageQuota)
+}
+
 func TestCreate(t *testing.T) {
 	tempDir, err := ioutil.TempDir("", "wasm")
 	require.NoError(t, err)
@@ -361,9 +505,9 @@ func TestInstantiate(t *testing.T) {
 	ctx = ctx.WithTxBytes(txBytes)
 
 	// create with no balance is also legal
-	contractAddr, err := keeper.Instantiate(ctx, contractID, creator /* , nil */, initMsgBz, "demo contract 1", nil, nil)
+	contractAddr, err := keeper.Instantiate(ctx, contractID, creator, nil, initMsgBz, "demo contract 1", nil, nil)
 	require.NoError(t, err)
-	require.Equal(t, "secret18vd8fpwxzck93qlwghaj6arh4p7c5n8978vsyg", contractAddr.String())
+	require.Equal(t, contractAddress(creator, key, initMsgBz), contractAddr)
 
 	gasAfter := ctx.GasMeter().GasConsumed()
 	require.Greater(t, gasAfter-gasBefore, uint64(20000))
@@ -377,7 +521,7 @@ func TestInstantiate(t *testing.T) {
 	require.Equal(t, info.Label, "demo contract 1")
 
 	// test that creating again with the same label will fail
-	_, err = keeper.Instantiate(ctx, contractID, creator /* , nil */, initMsgBz, "demo contract 1", nil, nil)
+	_, err = keeper.Instantiate(ctx, contractID, creator, nil, initMsgBz, "demo contract 1", nil, nil)
 	require.Error(t, err)
 
 	/*
@@ -555,7 +699,7 @@ func TestInstantiateWithNonExistingCodeID(t *testing.T) {
 
 	ctx = ctx.WithTxBytes(txBytes)
 
-	addr, err := keeper.Instantiate(ctx, nonExistingCodeID, creator /* , nil */, initMsgBz, "demo contract 2", nil, nil)
+	addr, err := keeper.Instantiate(ctx, nonExistingCodeID, creator, nil, initMsgBz, "demo contract 2", nil, nil)
 	require.True(t, types.ErrNotFound.Is(err), err)
 	require.Nil(t, addr)
 }
@@ -600,11 +744,11 @@ func TestExecute(t *testing.T) {
 
 	ctx = PrepareInitSignedTx(t, keeper, ctx, creator, creatorPrivKey, initMsgBz, contractID, deposit)
 	// create with no balance is also legal
-	addr, err := keeper.Instantiate(ctx, contractID, creator /* , nil */, initMsgBz, "demo contract 1", deposit, nil)
+	addr, err := keeper.Instantiate(ctx, contractID, creator, nil, initMsgBz, "demo contract 1", deposit, nil)
 
 	require.NoError(t, err)
 
-	require.Equal(t, "secret18vd8fpwxzck93qlwghaj6arh4p7c5n8978vsyg", addr.String())
+	require.Equal(t, contractAddress(creator, key, initMsgBz), addr)
 
 	// ensure bob doesn't exist
 	bobAcct := accKeeper.GetAccount(ctx, bob)
@@ -898,7 +1042,7 @@ func TestExecuteWithCpuLoop(t *testing.T) {
 
 	ctx = ctx.WithTxBytes(txBytes)
 
-	addr, err := keeper.Instantiate(ctx, contractID, creator /* , nil */, msgBz, "demo contract 5", deposit, nil)
+	addr, err := keeper.Instantiate(ctx, contractID, creator, nil, msgBz, "demo contract 5", deposit, nil)
 	require.NoError(t, err)
 
 	// make sure we set a limit before calling
@@ -1472,3 +1616,76 @@ func keyPubAddr() (crypto.PrivKey, crypto.PubKey, sdk.AccAddress) {
 	addr := sdk.AccAddress(pub.Address())
 	return key, pub, addr
 }
+
+// The enclave derives the same addresses, see test_derive_contract_address in the enclave
+func TestContractAddress(t *testing.T) {
+	specs := []struct {
+		creator  sdk.AccAddress
+		codeHash []byte
+		initMsg  string
+		expected string
+	}{
+		{bytes.Repeat([]byte{1}, 20), make([]byte, 32), "", "secret1vwe256uasx3447tv4jhzry8xxrtd3pvtyxdxch"},
+		{bytes.Repeat([]byte{1}, 20), make([]byte, 32), "init", "secret1mc39zgkzgaq2dn4k5k0hztpjpwth9f4cvw7qjn"},
+		{bytes.Repeat([]byte{2}, 20), make([]byte, 32), "init", "secret163uatd6vp8p0luxxq2h8s7vfde2l6vsg3tkch5"},
+		{bytes.Repeat([]byte{2}, 20), bytes.Repeat([]byte{7}, 32), "init", "secret15v7tau6az309jkpeeecj38ffsuwsswxucju3yn"},
+		{bytes.Repeat([]byte{3}, 32), bytes.Repeat([]byte{7}, 32), "init", "secret1s7nw7kgqt228jr2k2lgd7jpdlhsy6tm20527um"},
+	}
+	for _, spec := range specs {
+		assert.Equal(t, spec.expected, contractAddress(spec.creator, spec.codeHash, []byte(spec.initMsg)).String())
+	}
+}
+
+func TestBlockGasForContract(t *testing.T) {
+	specs := map[string]struct {
+		blockLimit uint64
+		blockUsed  uint64
+		txUsed     uint64
+		expected   uint64
+	}{
+		"no block limit":            {0, 0, 100, 0},
+		"block gas left":            {1000, 200, 300, 500 * GasMultiplier},
+		"tx used the rest":          {1000, 200, 800, 1},
+		"tx used more than left":    {1000, 900, 800, 1},
+		"more than a contract gets": {MaxGas, 0, 0, MaxGas},
+		"would overflow":            {math.MaxUint64, 0, 0, MaxGas},
+		"would overflow after use":  {math.MaxUint64, math.MaxUint64 / 2, 0, MaxGas},
+	}
+	for msg, spec := range specs {
+		t.Run(msg, func(t *testing.T) {
+			blockMeter := sdk.NewGasMeter(spec.blockLimit)
+			blockMeter.ConsumeGas(spec.blockUsed, "block")
+			txMeter := sdk.NewInfiniteGasMeter()
+			txMeter.ConsumeGas(spec.txUsed, "tx")
+			ctx := sdk.Context{}.WithBlockGasMeter(blockMeter).WithGasMeter(txMeter)
+			assert.Equal(t, spec.expected, blockGasForContract(ctx))
+		})
+	}
+}
+
+// The enclave expects the same paths, see test_log_attributes_are_ordered_across_submessages in the
+// enclave
+func TestExecutionPath(t *testing.T) {
+	root := sdk.NewContext(nil, abci.Header{}, false, nil)
+	require.Equal(t, []uint32{}, executionPath(root))
+
+	// the root returns one message M and the submessages A and B, and A returns the submessage C
+	m := withExecutionStep(root, 0)
+	a := withExecutionStep(root, 1+2*0)
+	replyA := withExecutionStep(root, 1+2*0+1)
+	b := withExecutionStep(root, 1+2*1)
+	replyB := withExecutionStep(root, 1+2*1+1)
+	c := withExecutionStep(a, 0+2*0)
+	replyC := withExecutionStep(a, 0+2*0+1)
+
+	depthFirst := [][]uint32{{0}, {1}, {1, 0}, {1, 1}, {2}, {3}, {4}}
+	paths := [][]uint32{}
+	for _, ctx := range []sdk.Context{m, a, c, replyC, replyA, b, replyB} {
+		paths = append(paths, executionPath(ctx))
+	}
+	assert.Equal(t, depthFirst, paths)
+
+	// the children of a call don't change its path or the paths of each other
+	assert.Equal(t, []uint32{1}, executionPath(a))
+	assert.Equal(t, []uint32{}, executionPath(root))
+}
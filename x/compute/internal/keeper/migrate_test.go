@@ -0,0 +1,202 @@
+package keeper
+
+import (
+	"encoding/hex"
+	"fmt"
+	"io/ioutil"
+	"os"
+	"testing"
+
+	sdk "github.com/enigmampc/cosmos-sdk/types"
+	"github.com/stretchr/testify/require"
+	"github.com/tendermint/tendermint/crypto"
+	"github.com/tendermint/tendermint/libs/log"
+
+	"github.com/enigmampc/SecretNetwork/x/compute/internal/types"
+)
+
+// freshContext has the same storage as ctx with a new event manager and gas meter
+func freshContext(ctx sdk.Context) sdk.Context {
+	return sdk.NewContext(
+		ctx.MultiStore(),
+		ctx.BlockHeader(),
+		ctx.IsCheckTx(),
+		log.NewNopLogger(),
+	).WithGasMeter(sdk.NewGasMeter(defaultGasForTests))
+}
+
+func encryptForCode(t *testing.T, keeper Keeper, ctx sdk.Context, codeID uint64, msg string) []byte {
+	secretMsg := types.SecretMsg{
+		CodeHash: []byte(hex.EncodeToString(keeper.GetCodeInfo(ctx, codeID).CodeHash)),
+		Msg:      []byte(msg),
+	}
+	msgBz, err := wasmCtx.Encrypt(secretMsg.Serialize())
+	require.NoError(t, err)
+	return msgBz
+}
+
+func initWithAdminHelper(t *testing.T, keeper Keeper, ctx sdk.Context, codeID uint64, creator sdk.AccAddress, creatorPrivKey crypto.PrivKey, admin sdk.AccAddress, label string) sdk.AccAddress {
+	initMsgBz := encryptForCode(t, keeper, ctx, codeID, `{"nop":{}}`)
+	ctx = PrepareInitWithAdminSignedTx(t, keeper, freshContext(ctx), creator, creatorPrivKey, initMsgBz, codeID, nil, admin)
+	contractAddress, err := keeper.Instantiate(ctx, codeID, creator, admin, initMsgBz, label, nil, nil)
+	require.NoError(t, err)
+	return contractAddress
+}
+
+// migrateHelper migrates the contract to codeID and returns the decrypted data of the migration
+func migrateHelper(t *testing.T, keeper Keeper, ctx sdk.Context, contractAddress sdk.AccAddress, txSender sdk.AccAddress, senderPrivKey crypto.PrivKey, codeID uint64, migrateMsg string) ([]byte, error) {
+	migrateMsgBz := encryptForCode(t, keeper, ctx, codeID, migrateMsg)
+	nonce := migrateMsgBz[0:32]
+
+	ctx = PrepareMigrateSignedTx(t, keeper, freshContext(ctx), txSender, senderPrivKey, migrateMsgBz, contractAddress, codeID)
+	res, err := keeper.Migrate(ctx, contractAddress, txSender, codeID, migrateMsgBz)
+	if err != nil {
+		return nil, err
+	}
+	return getDecryptedData(t, res.Data, nonce), nil
+}
+
+func updateAdminHelper(t *testing.T, keeper Keeper, ctx sdk.Context, contractAddress sdk.AccAddress, txSender sdk.AccAddress, senderPrivKey crypto.PrivKey, newAdmin sdk.AccAddress) error {
+	ctx = PrepareUpdateAdminSignedTx(t, keeper, freshContext(ctx), txSender, senderPrivKey, contractAddress, newAdmin)
+	if newAdmin == nil {
+		return keeper.ClearContractAdmin(ctx, contractAddress, txSender)
+	}
+	return keeper.UpdateContractAdmin(ctx, contractAddress, txSender, newAdmin)
+}
+
+func TestMigrate(t *testing.T) {
+	ctx, keeper, tempDir, codeID, _, walletA, privKeyA, walletB, privKeyB := setupTest(t, "./testdata/test-contract/contract.wasm")
+	defer os.RemoveAll(tempDir)
+
+	contractAddress := initWithAdminHelper(t, keeper, ctx, codeID, walletA, privKeyA, walletA, "migrated contract")
+	require.Equal(t, walletA, keeper.GetContractInfo(ctx, contractAddress).Admin)
+
+	_, _, execErr := execHelper(t, keeper, ctx, contractAddress, walletA, privKeyA, `{"set_state":{"key":"before","value":"kept"}}`, true, defaultGasForTests, 0)
+	require.Empty(t, execErr)
+	oldContractKey := keeper.GetContractKey(ctx, contractAddress)
+
+	// only the admin may migrate the contract
+	_, err := migrateHelper(t, keeper, ctx, contractAddress, walletB, privKeyB, codeID, `{"move_state":{"from":"before","to":"after"}}`)
+	require.Error(t, err)
+	require.Contains(t, err.Error(), "can not migrate")
+
+	// the new code reads the state the contract wrote before the migration
+	data, err := migrateHelper(t, keeper, ctx, contractAddress, walletA, privKeyA, codeID, `{"move_state":{"from":"before","to":"after"}}`)
+	require.NoError(t, err)
+	require.Equal(t, "kept", string(data))
+	require.NotEqual(t, oldContractKey, keeper.GetContractKey(ctx, contractAddress))
+
+	data, _, execErr = execHelper(t, keeper, ctx, contractAddress, walletA, privKeyA, `{"get_state":{"key":"after"}}`, true, defaultGasForTests, 0)
+	require.Empty(t, execErr)
+	require.Equal(t, "kept", string(data))
+	data, _, execErr = execHelper(t, keeper, ctx, contractAddress, walletA, privKeyA, `{"get_state":{"key":"before"}}`, true, defaultGasForTests, 0)
+	require.Empty(t, execErr)
+	require.Empty(t, data)
+}
+
+func TestMigrateWithoutAdmin(t *testing.T) {
+	ctx, keeper, tempDir, codeID, _, walletA, privKeyA, _, _ := setupTest(t, "./testdata/test-contract/contract.wasm")
+	defer os.RemoveAll(tempDir)
+
+	contractAddress := initWithAdminHelper(t, keeper, ctx, codeID, walletA, privKeyA, nil, "contract without admin")
+
+	// not even the creator may migrate a contract without an admin
+	_, err := migrateHelper(t, keeper, ctx, contractAddress, walletA, privKeyA, codeID, `{"move_state":{"from":"before","to":"after"}}`)
+	require.Error(t, err)
+	require.Contains(t, err.Error(), "can not migrate")
+}
+
+func TestMigrateWithAdminTheHostMadeUp(t *testing.T) {
+	ctx, keeper, tempDir, codeID, _, walletA, privKeyA, walletB, privKeyB := setupTest(t, "./testdata/test-contract/contract.wasm")
+	defer os.RemoveAll(tempDir)
+
+	contractAddress := initWithAdminHelper(t, keeper, ctx, codeID, walletA, privKeyA, walletA, "migrated contract")
+	_, _, execErr := execHelper(t, keeper, ctx, contractAddress, walletA, privKeyA, `{"set_state":{"key":"before","value":"kept"}}`, true, defaultGasForTests, 0)
+	require.Empty(t, execErr)
+
+	// a host that writes another admin into the contract info still has the proof of the real one
+	contractInfo := keeper.GetContractInfo(ctx, contractAddress)
+	contractInfo.Admin = walletB
+	keeper.setContractInfo(ctx, contractAddress, contractInfo)
+
+	_, err := migrateHelper(t, keeper, ctx, contractAddress, walletB, privKeyB, codeID, `{"move_state":{"from":"before","to":"after"}}`)
+	require.Error(t, err)
+	require.Contains(t, err.Error(), types.ErrMigrationFailed.Error())
+}
+
+func TestUpdateContractAdmin(t *testing.T) {
+	ctx, keeper, tempDir, codeID, _, walletA, privKeyA, walletB, privKeyB := setupTest(t, "./testdata/test-contract/contract.wasm")
+	defer os.RemoveAll(tempDir)
+
+	contractAddress := initWithAdminHelper(t, keeper, ctx, codeID, walletA, privKeyA, walletA, "contract with a new admin")
+	_, _, execErr := execHelper(t, keeper, ctx, contractAddress, walletA, privKeyA, `{"set_state":{"key":"before","value":"kept"}}`, true, defaultGasForTests, 0)
+	require.Empty(t, execErr)
+
+	// only the admin may replace itself
+	err := updateAdminHelper(t, keeper, ctx, contractAddress, walletB, privKeyB, walletB)
+	require.Error(t, err)
+
+	oldAdminProof := ctx.KVStore(keeper.storeKey).Get(types.GetContractAdminProofKey(contractAddress))
+	err = updateAdminHelper(t, keeper, ctx, contractAddress, walletA, privKeyA, walletB)
+	require.NoError(t, err)
+	require.Equal(t, walletB, keeper.GetContractInfo(ctx, contractAddress).Admin)
+	require.NotEqual(t, oldAdminProof, ctx.KVStore(keeper.storeKey).Get(types.GetContractAdminProofKey(contractAddress)))
+
+	// the enclave accepts the new admin, and no longer the old one
+	_, err = migrateHelper(t, keeper, ctx, contractAddress, walletA, privKeyA, codeID, `{"move_state":{"from":"before","to":"after"}}`)
+	require.Error(t, err)
+	data, err := migrateHelper(t, keeper, ctx, contractAddress, walletB, privKeyB, codeID, `{"move_state":{"from":"before","to":"after"}}`)
+	require.NoError(t, err)
+	require.Equal(t, "kept", string(data))
+
+	// once the admin is cleared, the contract can't be migrated anymore
+	err = updateAdminHelper(t, keeper, ctx, contractAddress, walletB, privKeyB, nil)
+	require.NoError(t, err)
+	require.Empty(t, keeper.GetContractInfo(ctx, contractAddress).Admin)
+
+	_, err = migrateHelper(t, keeper, ctx, contractAddress, walletB, privKeyB, codeID, `{"move_state":{"from":"after","to":"again"}}`)
+	require.Error(t, err)
+}
+
+func TestAssertCodeHashAfterMigration(t *testing.T) {
+	ctx, keeper, tempDir, codeID, codeHash, walletA, privKeyA, _, _ := setupTest(t, "./testdata/test-contract/contract.wasm")
+	defer os.RemoveAll(tempDir)
+
+	// the same code with an empty custom section, so it has another hash
+	wasmCode, err := ioutil.ReadFile("./testdata/test-contract/contract.wasm")
+	require.NoError(t, err)
+	newCodeID, err := keeper.Create(ctx, walletA, append(wasmCode, 0x00, 0x05, 0x04, 'n', 'e', 'x', 't'), "", "")
+	require.NoError(t, err)
+	newCodeHash := hex.EncodeToString(keeper.GetCodeInfo(ctx, newCodeID).CodeHash)
+	require.NotEqual(t, codeHash, newCodeHash)
+
+	checker := initWithAdminHelper(t, keeper, ctx, codeID, walletA, privKeyA, nil, "checker")
+	migrated := initWithAdminHelper(t, keeper, ctx, codeID, walletA, privKeyA, walletA, "migrated contract")
+	_, _, execErr := execHelper(t, keeper, ctx, migrated, walletA, privKeyA, `{"set_state":{"key":"before","value":"kept"}}`, true, defaultGasForTests, 0)
+	require.Empty(t, execErr)
+
+	assertCodeHash := func(contractAddress sdk.AccAddress, hash string) string {
+		data, _, execErr := execHelper(t, keeper, ctx, checker, walletA, privKeyA, fmt.Sprintf(`{"assert_code_hash":{"contract_addr":"%s","code_hash":"%s"}}`, contractAddress, hash), true, defaultGasForTests, 0)
+		require.Empty(t, execErr)
+		return string(data)
+	}
+
+	require.Equal(t, "true", assertCodeHash(migrated, codeHash))
+	require.Equal(t, "false", assertCodeHash(migrated, newCodeHash))
+	// no contract at the address
+	require.Equal(t, "false", assertCodeHash(sdk.AccAddress(make([]byte, 20)), codeHash))
+
+	oldContractKey := keeper.GetContractKey(ctx, migrated)
+	_, err = migrateHelper(t, keeper, ctx, migrated, walletA, privKeyA, newCodeID, `{"move_state":{"from":"before","to":"after"}}`)
+	require.NoError(t, err)
+
+	// the code hash from before the migration is stale
+	require.Equal(t, "false", assertCodeHash(migrated, codeHash))
+	require.Equal(t, "true", assertCodeHash(migrated, newCodeHash))
+
+	// a host that writes back the key from before the migration doesn't make the stale hash match,
+	// since the contract info points to the new code
+	ctx.KVStore(keeper.storeKey).Set(types.GetContractEnclaveKey(migrated), oldContractKey)
+	require.Equal(t, "false", assertCodeHash(migrated, codeHash))
+	require.Equal(t, "false", assertCodeHash(migrated, newCodeHash))
+}
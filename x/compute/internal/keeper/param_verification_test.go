@@ -179,7 +179,7 @@ func TestMultipleSigners(t *testing.T) {
 
 	ctx = prepareInitSignedTxMultipleMsgs(t, keeper, ctx, []sdk.AccAddress{walletA, walletB}, []crypto.PrivKey{privKeyA, privKeyB}, []sdk.Msg{sdkMsgA, sdkMsgB}, codeID)
 
-	contractAddressA, err := keeper.Instantiate(ctx, codeID, walletA /* nil,*/, initMsgBz, "demo contract 1", sdk.NewCoins(sdk.NewInt64Coin("denom", 0)), nil)
+	contractAddressA, err := keeper.Instantiate(ctx, codeID, walletA, nil, initMsgBz, "demo contract 1", sdk.NewCoins(sdk.NewInt64Coin("denom", 0)), nil)
 	if err != nil {
 		err = extractInnerError(t, err, nonce, true)
 	}
@@ -197,7 +197,7 @@ func TestMultipleSigners(t *testing.T) {
 		wasmEvents,
 	)
 
-	contractAddressB, err := keeper.Instantiate(ctx, codeID, walletB /* nil,*/, initMsgBz, "demo contract 2", sdk.NewCoins(sdk.NewInt64Coin("denom", 0)), nil)
+	contractAddressB, err := keeper.Instantiate(ctx, codeID, walletB, nil, initMsgBz, "demo contract 2", sdk.NewCoins(sdk.NewInt64Coin("denom", 0)), nil)
 	if err != nil {
 		err = extractInnerError(t, err, nonce, false)
 	}
@@ -241,7 +241,7 @@ func TestWrongSigner(t *testing.T) {
 
 	ctx = prepareInitSignedTxMultipleMsgs(t, keeper, ctx, []sdk.AccAddress{walletB}, []crypto.PrivKey{privKeyB}, []sdk.Msg{sdkMsgA}, codeID)
 
-	_, err = keeper.Instantiate(ctx, codeID, walletA /* nil,*/, initMsgBz, "some label", sdk.NewCoins(sdk.NewInt64Coin("denom", 0)), nil)
+	_, err = keeper.Instantiate(ctx, codeID, walletA, nil, initMsgBz, "some label", sdk.NewCoins(sdk.NewInt64Coin("denom", 0)), nil)
 	if err != nil {
 		err = extractInnerError(t, err, nonce, false)
 	}
@@ -276,7 +276,7 @@ func TestMultiSig(t *testing.T) {
 
 			multisigAddr := multisigTxCreator(t, &ctx, keeper, i+1, j+1, i+1, sdkMsg)
 
-			contractAddressA, err := keeper.Instantiate(ctx, codeID, multisigAddr /* nil, */, initMsgBz, label, sdk.NewCoins(sdk.NewInt64Coin("denom", 0)), nil)
+			contractAddressA, err := keeper.Instantiate(ctx, codeID, multisigAddr, nil, initMsgBz, label, sdk.NewCoins(sdk.NewInt64Coin("denom", 0)), nil)
 			if err != nil {
 				err = extractInnerError(t, err, nonce, true)
 			}
@@ -328,7 +328,7 @@ func TestMultiSigThreshold(t *testing.T) {
 
 			multisigAddr := multisigTxCreator(t, &ctx, keeper, i+1, j+1, j+1, sdkMsg)
 
-			contractAddressA, err := keeper.Instantiate(ctx, codeID, multisigAddr /* nil,*/, initMsgBz, label, sdk.NewCoins(sdk.NewInt64Coin("denom", 0)), nil)
+			contractAddressA, err := keeper.Instantiate(ctx, codeID, multisigAddr, nil, initMsgBz, label, sdk.NewCoins(sdk.NewInt64Coin("denom", 0)), nil)
 			if err != nil {
 				err = extractInnerError(t, err, nonce, true)
 			}
@@ -377,7 +377,7 @@ func TestMultiSigThresholdNotMet(t *testing.T) {
 
 	multisigAddr := multisigTxCreator(t, &ctx, keeper, 3, 2, 1, sdkMsg)
 
-	_, err = keeper.Instantiate(ctx, codeID, multisigAddr /* nil,*/, initMsgBz, "demo contract 1", sdk.NewCoins(sdk.NewInt64Coin("denom", 0)), nil)
+	_, err = keeper.Instantiate(ctx, codeID, multisigAddr, nil, initMsgBz, "demo contract 1", sdk.NewCoins(sdk.NewInt64Coin("denom", 0)), nil)
 	if err != nil {
 		err = extractInnerError(t, err, nonce, false)
 	}
@@ -594,7 +594,7 @@ func TestMultiSigInMultiSig(t *testing.T) {
 		ctx,
 		codeID,
 		sdk.AccAddress(multimultisigPubkey.Address()),
-		/* nil, */
+		nil,
 		initMsgBz,
 		"demo contract 1",
 		sdk.NewCoins(sdk.NewInt64Coin("denom", 0)),
@@ -688,7 +688,7 @@ func TestMultiSigInMultiSigDifferentOrder(t *testing.T) {
 		ctx,
 		codeID,
 		sdk.AccAddress(multimultisigPubkey.Address()),
-		/* nil, */
+		nil,
 		initMsgBz,
 		"demo contract 1",
 		sdk.NewCoins(sdk.NewInt64Coin("denom", 0)),
@@ -746,7 +746,7 @@ func TestInvalidKeyType(t *testing.T) {
 
 	ctx = prepareInitSignedTxMultipleMsgs(t, keeper, ctx, []sdk.AccAddress{edAddr}, []crypto.PrivKey{edKey}, []sdk.Msg{sdkMsg}, codeID)
 
-	_, err = keeper.Instantiate(ctx, codeID, edAddr /* nil,*/, initMsgBz, "demo contract 1", sdk.NewCoins(sdk.NewInt64Coin("denom", 0)), nil)
+	_, err = keeper.Instantiate(ctx, codeID, edAddr, nil, initMsgBz, "demo contract 1", sdk.NewCoins(sdk.NewInt64Coin("denom", 0)), nil)
 	require.Contains(t, err.Error(), "failed to verify transaction signature")
 
 	ctx, keeper, tempDir, codeID, _, _, _, _, _ = setupTest(t, "./testdata/test-contract/contract.wasm")
@@ -762,7 +762,7 @@ func TestInvalidKeyType(t *testing.T) {
 
 	ctx = prepareInitSignedTxMultipleMsgs(t, keeper, ctx, []sdk.AccAddress{srAddr}, []crypto.PrivKey{srKey}, []sdk.Msg{sdkMsg}, codeID)
 
-	_, err = keeper.Instantiate(ctx, codeID, srAddr /* , nil */, initMsgBz, "demo contract 1", sdk.NewCoins(sdk.NewInt64Coin("denom", 0)), nil)
+	_, err = keeper.Instantiate(ctx, codeID, srAddr, nil, initMsgBz, "demo contract 1", sdk.NewCoins(sdk.NewInt64Coin("denom", 0)), nil)
 	require.Contains(t, err.Error(), "failed to verify transaction signature")
 }
 
@@ -832,7 +832,7 @@ func TestInvalidKeyTypeInMultisig(t *testing.T) {
 		ctx,
 		codeID,
 		sdk.AccAddress(multisigPubkey.Address()),
-		/* nil, */
+		nil,
 		initMsgBz,
 		"demo contract 1",
 		sdk.NewCoins(sdk.NewInt64Coin("denom", 0)),
@@ -858,7 +858,7 @@ func TestWrongFundsNoFunds(t *testing.T) {
 
 	ctx = PrepareInitSignedTx(t, keeper, ctx, walletA, privKeyA, initMsgBz, codeID, nil)
 
-	_, err = keeper.Instantiate(ctx, codeID, walletA /* nil,*/, initMsgBz, "demo contract 1", sdk.NewCoins(sdk.NewInt64Coin("denom", 1000)), nil)
+	_, err = keeper.Instantiate(ctx, codeID, walletA, nil, initMsgBz, "demo contract 1", sdk.NewCoins(sdk.NewInt64Coin("denom", 1000)), nil)
 	if err != nil {
 		err = extractInnerError(t, err, nonce, false)
 	}
@@ -882,7 +882,7 @@ func TestWrongFundsSomeFunds(t *testing.T) {
 
 	ctx = PrepareInitSignedTx(t, keeper, ctx, walletA, privKeyA, initMsgBz, codeID, sdk.NewCoins(sdk.NewInt64Coin("denom", 200)))
 
-	_, err = keeper.Instantiate(ctx, codeID, walletA /* nil,*/, initMsgBz, "demo contract 1", sdk.NewCoins(sdk.NewInt64Coin("denom", 1000)), nil)
+	_, err = keeper.Instantiate(ctx, codeID, walletA, nil, initMsgBz, "demo contract 1", sdk.NewCoins(sdk.NewInt64Coin("denom", 1000)), nil)
 	if err != nil {
 		err = extractInnerError(t, err, nonce, false)
 	}
@@ -916,7 +916,7 @@ func TestWrongMessage(t *testing.T) {
 
 	ctx = PrepareInitSignedTx(t, keeper, ctx, walletA, privKeyA, initMsgBz, codeID, nil)
 
-	_, err = keeper.Instantiate(ctx, codeID, walletA /* nil, */, notTheRealMsgBz, "demo contract 1", sdk.NewCoins(sdk.NewInt64Coin("denom", 1000)), nil)
+	_, err = keeper.Instantiate(ctx, codeID, walletA, nil, notTheRealMsgBz, "demo contract 1", sdk.NewCoins(sdk.NewInt64Coin("denom", 1000)), nil)
 	if err != nil {
 		err = extractInnerError(t, err, nonce, false)
 	}
@@ -22,6 +22,8 @@ const (
 	QueryContractAddress    = "label"
 	QueryContractKey        = "contract-key"
 	QueryContractHash       = "contract-hash"
+	QueryExportState        = "export-state"
+	QueryEcallTraces        = "ecall-traces"
 	// QueryContractHistory    = "contract-history"
 )
 
@@ -66,6 +68,10 @@ func NewQuerier(keeper Keeper) sdk.Querier {
 			return queryContractKey(ctx, path[1], req, keeper)
 		case QueryContractHash:
 			return queryContractHash(ctx, path[1], req, keeper)
+		case QueryExportState:
+			return queryExportState(ctx, path[1], req, keeper)
+		case QueryEcallTraces:
+			return queryEcallTraces(path[1], keeper)
 		default:
 			return nil, sdkerrors.Wrap(sdkerrors.ErrUnknownRequest, "unknown data query endpoint")
 		}
@@ -139,7 +145,7 @@ func queryContractState(ctx sdk.Context, bech string, req abci.RequestQuery, kee
 		return nil, sdkerrors.Wrap(sdkerrors.ErrInvalidAddress, bech)
 	}
 
-	return keeper.QuerySmart(ctx, contractAddr, req.Data, false)
+	return keeper.querySmartFromClient(ctx, contractAddr, req.Data)
 }
 
 type GetCodeResponse struct {
@@ -273,3 +279,64 @@ func queryContractHash(ctx sdk.Context, address string, req abci.RequestQuery, k
 
 	return keeper.GetCodeInfo(ctx, res.CodeID).CodeHash, nil
 }
+
+// ExportStateRequest asks for the next chunk of an export of the storage of a contract. The first chunk
+// is asked for with an empty cursor and snapshot nonce.
+type ExportStateRequest struct {
+	Cursor        []byte `json:"cursor"`
+	SnapshotNonce []byte `json:"snapshot_nonce"`
+	ChunkSize     uint32 `json:"chunk_size"`
+}
+
+// ExportStateResponse is a chunk of an export, along with what to ask for the next one with. The next
+// cursor is empty after the last chunk.
+type ExportStateResponse struct {
+	SnapshotNonce []byte `json:"snapshot_nonce"`
+	Chunk         []byte `json:"chunk"`
+	NextCursor    []byte `json:"next_cursor"`
+}
+
+func queryExportState(ctx sdk.Context, address string, req abci.RequestQuery, keeper Keeper) ([]byte, error) {
+	contractAddr, err := sdk.AccAddressFromBech32(address)
+	if err != nil {
+		return nil, sdkerrors.Wrap(sdkerrors.ErrInvalidAddress, address)
+	}
+
+	var request ExportStateRequest
+	if err := json.Unmarshal(req.Data, &request); err != nil {
+		return nil, sdkerrors.Wrap(sdkerrors.ErrJSONUnmarshal, err.Error())
+	}
+
+	snapshotNonce, chunk, nextCursor, err := keeper.ExportContractState(ctx, contractAddr, request.Cursor, request.SnapshotNonce, request.ChunkSize)
+	if err != nil {
+		return nil, err
+	}
+
+	bz, err := json.Marshal(ExportStateResponse{
+		SnapshotNonce: snapshotNonce,
+		Chunk:         chunk,
+		NextCursor:    nextCursor,
+	})
+	if err != nil {
+		return nil, sdkerrors.Wrap(sdkerrors.ErrJSONMarshal, err.Error())
+	}
+	return bz, nil
+}
+
+func queryEcallTraces(maxEcalls string, keeper Keeper) ([]byte, error) {
+	amount, err := strconv.ParseUint(maxEcalls, 10, 32)
+	if err != nil {
+		return nil, sdkerrors.Wrapf(sdkerrors.ErrInvalidRequest, "invalid amount of ecalls %s", maxEcalls)
+	}
+
+	traces, err := keeper.EcallTraces(uint32(amount))
+	if err != nil {
+		return nil, sdkerrors.Wrap(types.ErrInvalid, err.Error())
+	}
+
+	bz, err := json.Marshal(traces)
+	if err != nil {
+		return nil, sdkerrors.Wrap(sdkerrors.ErrJSONMarshal, err.Error())
+	}
+	return bz, nil
+}
@@ -12,6 +12,7 @@ import (
 	authtypes "github.com/enigmampc/cosmos-sdk/x/auth/types"
 	"github.com/tendermint/tendermint/crypto"
 
+	wasmTypes "github.com/enigmampc/SecretNetwork/go-cosmwasm/types"
 	"github.com/enigmampc/SecretNetwork/x/compute/internal/types"
 	sdk "github.com/enigmampc/cosmos-sdk/types"
 	sdkErrors "github.com/enigmampc/cosmos-sdk/types/errors"
@@ -61,7 +62,7 @@ func TestQueryContractLabel(t *testing.T) {
 
 	ctx = PrepareInitSignedTx(t, keeper, ctx, creator, privCreator, initMsgBz, contractID, deposit)
 
-	addr, err := keeper.Instantiate(ctx, contractID, creator /* nil,*/, initMsgBz, label, deposit, nil)
+	addr, err := keeper.Instantiate(ctx, contractID, creator, nil, initMsgBz, label, deposit, nil)
 	require.NoError(t, err)
 
 	// this gets us full error, not redacted sdk.Error
@@ -154,7 +155,7 @@ func TestQueryContractState(t *testing.T) {
 
 	initMsgBz, err = wasmCtx.Encrypt(msg.Serialize())
 
-	addr, err := keeper.Instantiate(ctx, contractID, creator /* nil,*/, initMsgBz, "demo contract to query", deposit, nil)
+	addr, err := keeper.Instantiate(ctx, contractID, creator, nil, initMsgBz, "demo contract to query", deposit, nil)
 	require.NoError(t, err)
 
 	contractModel := []types.Model{
@@ -329,7 +330,7 @@ func TestListContractByCodeOrdering(t *testing.T) {
 
 		ctx = ctx.WithTxBytes(txBytes)
 
-		_, err = keeper.Instantiate(ctx, codeID, creator /* nil,*/, initMsgBz, fmt.Sprintf("contract %d", i), topUp, nil)
+		_, err = keeper.Instantiate(ctx, codeID, creator, nil, initMsgBz, fmt.Sprintf("contract %d", i), topUp, nil)
 		require.NoError(t, err)
 	}
 
@@ -457,3 +458,34 @@ func TestQueryContractHistory(t *testing.T) {
 	}
 }
 */
+
+func TestQueryEcallTraces(t *testing.T) {
+	ctx, keeper, tempDir, codeID, _, walletA, privKeyA, _, _ := setupTest(t, "./testdata/test-contract/contract.wasm")
+	defer os.RemoveAll(tempDir)
+
+	wasmConfig := types.DefaultWasmConfig()
+	wasmConfig.EcallTracing = true
+	require.NoError(t, configureEnclaveRuntime(keeper.wasmer, wasmConfig))
+	// the enclave is shared by every test
+	defer func() {
+		err := keeper.wasmer.ConfigureEnclaveRuntime(wasmTypes.EnclaveLogLevelKeep, wasmConfig.SmartQueryGasLimit*GasMultiplier, 0, 0, wasmTypes.EcallTracingOff)
+		require.NoError(t, err)
+	}()
+
+	_, _, initErr := initHelper(t, keeper, ctx, codeID, walletA, privKeyA, `{"nop":{}}`, true, defaultGasForTests)
+	require.Empty(t, initErr)
+
+	// the node serves the traces of its own enclave
+	q := NewQuerier(keeper)
+	res, err := q(ctx, []string{QueryEcallTraces, "1"}, abci.RequestQuery{})
+	require.NoError(t, err)
+
+	var traces wasmTypes.EcallTraces
+	require.NoError(t, json.Unmarshal(res, &traces))
+	require.Len(t, traces.Ecalls, 1)
+	require.NotEmpty(t, traces.Ecalls[0].Spans)
+	require.NotZero(t, traces.SpansRecorded)
+
+	_, err = q(ctx, []string{QueryEcallTraces, "many"}, abci.RequestQuery{})
+	require.Error(t, err)
+}
@@ -326,7 +326,7 @@ func StakingQuerier(keeper *staking.Keeper) func(ctx sdk.Context, request *wasmT
 
 			delegator, err := sdk.AccAddressFromBech32(request.UnBondingDelegations.Delegator)
 			if err != nil {
-				return nil, sdkerrors.Wrap(sdkerrors.ErrInvalidAddress, request.Delegation.Delegator)
+				return nil, sdkerrors.Wrap(sdkerrors.ErrInvalidAddress, request.UnBondingDelegations.Delegator)
 			}
 
 			unbondingDelegations := keeper.GetAllUnbondingDelegations(ctx, delegator)
@@ -440,6 +440,18 @@ func WasmQuerier(wasm *Keeper) func(ctx sdk.Context, request *wasmTypes.WasmQuer
 			// TODO: do we want to change the return value?
 			return json.Marshal(models)
 		}
+		if request.ContractKey != nil {
+			addr, err := sdk.AccAddressFromBech32(request.ContractKey.ContractAddr)
+			if err != nil {
+				return nil, sdkerrors.Wrap(sdkerrors.ErrInvalidAddress, request.ContractKey.ContractAddr)
+			}
+			var res wasmTypes.ContractKeyResponse
+			if contractKey := wasm.GetContractKey(ctx, addr); contractKey != nil {
+				res.ContractKey = contractKey
+				res.CodeHash = wasm.GetContractHash(ctx, addr)
+			}
+			return json.Marshal(res)
+		}
 		return nil, wasmTypes.UnsupportedRequest{Kind: "unknown WasmQuery variant"}
 	}
 }
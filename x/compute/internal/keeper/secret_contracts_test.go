@@ -1,19 +1,25 @@
 package keeper
 
 import (
+	"crypto/rand"
 	"encoding/base64"
+	"encoding/binary"
 	"encoding/hex"
 	"encoding/json"
 	"fmt"
 	"io/ioutil"
 	"os"
 	"regexp"
+	"strconv"
 	"testing"
+	"time"
 
+	"github.com/enigmampc/SecretNetwork/go-cosmwasm/api"
 	"github.com/enigmampc/SecretNetwork/x/compute/internal/types"
 	"github.com/tendermint/tendermint/crypto"
 
 	cosmwasm "github.com/enigmampc/SecretNetwork/go-cosmwasm/types"
+	"github.com/enigmampc/cosmos-sdk/store/prefix"
 	sdk "github.com/enigmampc/cosmos-sdk/types"
 	"github.com/stretchr/testify/require"
 	"github.com/tendermint/tendermint/libs/log"
@@ -68,37 +74,29 @@ func setupTest(t *testing.T, wasmPath string) (sdk.Context, Keeper, string, uint
 	return ctx, keeper, tempDir, codeID, codeHash, walletA, privKeyA, walletB, privKeyB
 }
 
+func eventAttributes(e sdk.Event) []sdk.Attribute {
+	attributes := []sdk.Attribute{}
+	for _, attr := range e.Attributes {
+		attributes = append(attributes, sdk.NewAttribute(string(attr.Key), string(attr.Value)))
+	}
+	return attributes
+}
+
 // getDecryptedWasmEvents gets all "wasm" events and decrypt what's necessary
-// Returns all "wasm" events, including from contract callbacks
+// Returns all "wasm" events, including from contract callbacks, without their execution_path
 func getDecryptedWasmEvents(t *testing.T, ctx sdk.Context, nonce []byte) []ContractEvent {
 	events := ctx.EventManager().Events()
 	var res []ContractEvent
 	for _, e := range events {
 		if e.Type == "wasm" {
-			newEvent := []cosmwasm.LogAttribute{}
-			for _, oldLog := range e.Attributes {
-				newLog := cosmwasm.LogAttribute{
-					Key:   string(oldLog.Key),
-					Value: string(oldLog.Value),
-				}
+			attributes, err := wasmCtx.DecryptWasmEvent(eventAttributes(e), nonce)
+			require.NoError(t, err)
 
-				if newLog.Key != "contract_address" {
-					// key
-					keyCipherBz, err := base64.StdEncoding.DecodeString(newLog.Key)
-					require.NoError(t, err)
-					keyPlainBz, err := wasmCtx.Decrypt(keyCipherBz, nonce)
-					require.NoError(t, err)
-					newLog.Key = string(keyPlainBz)
-
-					// value
-					valueCipherBz, err := base64.StdEncoding.DecodeString(newLog.Value)
-					require.NoError(t, err)
-					valuePlainBz, err := wasmCtx.Decrypt(valueCipherBz, nonce)
-					require.NoError(t, err)
-					newLog.Value = string(valuePlainBz)
+			newEvent := []cosmwasm.LogAttribute{}
+			for _, attr := range attributes {
+				if attr.Key != types.AttributeKeyExecutionPath {
+					newEvent = append(newEvent, cosmwasm.LogAttribute{Key: attr.Key, Value: attr.Value})
 				}
-
-				newEvent = append(newEvent, newLog)
 			}
 			res = append(res, newEvent)
 		}
@@ -113,10 +111,7 @@ func getDecryptedData(t *testing.T, data []byte, nonce []byte) []byte {
 		return data
 	}
 
-	dataPlaintextBase64, err := wasmCtx.Decrypt(data, nonce)
-	require.NoError(t, err)
-
-	dataPlaintext, err := base64.StdEncoding.DecodeString(string(dataPlaintextBase64))
+	dataPlaintext, err := wasmCtx.DecryptData(data, nonce)
 	require.NoError(t, err)
 
 	return dataPlaintext
@@ -316,7 +311,7 @@ func initHelperImpl(t *testing.T, keeper Keeper, ctx sdk.Context, codeID uint64,
 
 	ctx = PrepareInitSignedTx(t, keeper, ctx, creator, creatorPrivKey, initMsgBz, codeID, sdk.NewCoins(sdk.NewInt64Coin("denom", coin)))
 	// make the label a random base64 string, because why not?
-	contractAddress, err := keeper.Instantiate(ctx, codeID, creator /* nil,*/, initMsgBz, base64.RawURLEncoding.EncodeToString(nonce), sdk.NewCoins(sdk.NewInt64Coin("denom", coin)), nil)
+	contractAddress, err := keeper.Instantiate(ctx, codeID, creator, nil, initMsgBz, base64.RawURLEncoding.EncodeToString(nonce), sdk.NewCoins(sdk.NewInt64Coin("denom", coin)), nil)
 
 	if wasmCallCount < 0 {
 		// default, just check that at least 1 call happend
@@ -465,6 +460,51 @@ func TestEmptyLogKeyValue(t *testing.T) {
 	)
 }
 
+func TestLogAttributesAreBoundToTheirPlace(t *testing.T) {
+	ctx, keeper, tempDir, codeID, _, walletA, privKeyA, _, _ := setupTest(t, "./testdata/test-contract/contract.wasm")
+	defer os.RemoveAll(tempDir)
+
+	contractAddress, _, initErr := initHelper(t, keeper, ctx, codeID, walletA, privKeyA, `{"nop":{}}`, true, defaultGasForTests)
+	require.Empty(t, initErr)
+
+	events, nonce := execRawEventsHelper(t, keeper, ctx, contractAddress, walletA, privKeyA, `{"empty_log_key_value":{}}`)
+	require.Len(t, events, 1)
+	attributes := eventAttributes(events[0])
+	require.Len(t, attributes, 4)
+	require.Equal(t, sdk.NewAttribute(types.AttributeKeyExecutionPath, "[]"), attributes[1])
+
+	decrypted, err := wasmCtx.DecryptWasmEvent(attributes, nonce)
+	require.NoError(t, err)
+	require.Equal(t,
+		[]sdk.Attribute{
+			attributes[0],
+			attributes[1],
+			{Key: "my value is empty", Value: ""},
+			{Key: "", Value: "my key is empty"},
+		},
+		decrypted,
+	)
+
+	// attributes that are moved within the call don't decrypt
+	swapped := []sdk.Attribute{attributes[0], attributes[1], attributes[3], attributes[2]}
+	decryptedSwapped, err := wasmCtx.DecryptWasmEvent(swapped, nonce)
+	require.NoError(t, err)
+	require.Equal(t, swapped, decryptedSwapped)
+
+	// and neither does one that is repeated under another call
+	repeated := append(append([]sdk.Attribute{}, attributes...),
+		attributes[0], sdk.NewAttribute(types.AttributeKeyExecutionPath, "[0]"), attributes[2])
+	decryptedRepeated, err := wasmCtx.DecryptWasmEvent(repeated, nonce)
+	require.NoError(t, err)
+	require.Equal(t, append(append([]sdk.Attribute{}, decrypted...), repeated[4:]...), decryptedRepeated)
+
+	// the attributes of every call in a merged event decrypt with their own path
+	merged := append(append([]sdk.Attribute{}, attributes...), attributes...)
+	decryptedMerged, err := wasmCtx.DecryptWasmEvent(merged, nonce)
+	require.NoError(t, err)
+	require.Equal(t, append(append([]sdk.Attribute{}, decrypted...), decrypted...), decryptedMerged)
+}
+
 func TestEmptyData(t *testing.T) {
 	ctx, keeper, tempDir, codeID, _, walletA, privKeyA, _, _ := setupTest(t, "./testdata/test-contract/contract.wasm")
 	defer os.RemoveAll(tempDir)
@@ -812,10 +852,11 @@ func TestInitNotEncryptedInputError(t *testing.T) {
 	ctx = PrepareInitSignedTx(t, keeper, ctx, walletA, privKey, initMsg, codeID, nil)
 
 	// init
-	_, err := keeper.Instantiate(ctx, codeID, walletA /* nil, */, initMsg, "some label", sdk.NewCoins(sdk.NewInt64Coin("denom", 0)), nil)
+	_, err := keeper.Instantiate(ctx, codeID, walletA, nil, initMsg, "some label", sdk.NewCoins(sdk.NewInt64Coin("denom", 0)), nil)
 	require.Error(t, err)
 
 	require.Contains(t, err.Error(), "failed to decrypt data")
+	require.Contains(t, err.Error(), "encrypted message is too short")
 }
 
 func TestExecuteNotEncryptedInputError(t *testing.T) {
@@ -840,6 +881,7 @@ func TestExecuteNotEncryptedInputError(t *testing.T) {
 	require.Error(t, err)
 
 	require.Contains(t, err.Error(), "failed to decrypt data")
+	require.Contains(t, err.Error(), "encrypted message is too short")
 }
 
 func TestQueryNotEncryptedInputError(t *testing.T) {
@@ -853,6 +895,7 @@ func TestQueryNotEncryptedInputError(t *testing.T) {
 	require.Error(t, err)
 
 	require.Contains(t, err.Error(), "failed to decrypt data")
+	require.Contains(t, err.Error(), "encrypted message is too short")
 }
 
 func TestInitNoLogs(t *testing.T) {
@@ -918,6 +961,170 @@ func TestExecCallbackToInit(t *testing.T) {
 	require.Equal(t, "🍆🥑🍄", string(data))
 }
 
+func TestExecSubmsgInstantiateReply(t *testing.T) {
+	ctx, keeper, tempDir, codeID, codeHash, walletA, privKeyA, _, _ := setupTest(t, "./testdata/test-contract/contract.wasm")
+	defer os.RemoveAll(tempDir)
+
+	contractAddress, _, initErr := initHelper(t, keeper, ctx, codeID, walletA, privKeyA, `{"nop":{}}`, true, defaultGasForTests)
+	require.Empty(t, initErr)
+
+	// instantiate a second contract via a submessage, and store its address in the reply
+	_, execEvents, execErr := execHelper(t, keeper, ctx, contractAddress, walletA, privKeyA, fmt.Sprintf(`{"instantiate_via_submsg":{"code_id":%d,"code_hash":"%s"}}`, codeID, codeHash), true, defaultGasForTests, 0)
+	require.Empty(t, execErr)
+
+	require.Equal(t, 1, len(execEvents))
+	require.Equal(t, "contract_address", execEvents[0][0].Key)
+	secondContractAddress := execEvents[0][0].Value
+	require.NotEqual(t, contractAddress.String(), secondContractAddress)
+
+	data, _, execErr := execHelper(t, keeper, ctx, contractAddress, walletA, privKeyA, `{"get_state":{"key":"instantiated_address"}}`, true, defaultGasForTests, 0)
+	require.Empty(t, execErr)
+	require.Equal(t, secondContractAddress, string(data))
+}
+
+func TestExecSubmsgReplyOnErrorRevertsOnlyTheSubmsg(t *testing.T) {
+	ctx, keeper, tempDir, codeID, codeHash, walletA, privKeyA, _, _ := setupTest(t, "./testdata/test-contract/contract.wasm")
+	defer os.RemoveAll(tempDir)
+
+	contractAddress, _, initErr := initHelper(t, keeper, ctx, codeID, walletA, privKeyA, `{"nop":{}}`, true, defaultGasForTests)
+	require.Empty(t, initErr)
+
+	// the contract writes, then executes itself with a submessage that writes and fails
+	data, _, execErr := execHelper(t, keeper, ctx, contractAddress, walletA, privKeyA, fmt.Sprintf(`{"failing_submsg":{"code_hash":"%s"}}`, codeHash), true, defaultGasForTests, 0)
+	require.Empty(t, execErr)
+	require.Equal(t, "replied", string(data))
+
+	// the writes of the submessage are reverted, and the ones of the contract and its reply are kept
+	for key, expected := range map[string]string{"parent": "written", "child": "", "replied": "written"} {
+		data, _, execErr = execHelper(t, keeper, ctx, contractAddress, walletA, privKeyA, fmt.Sprintf(`{"get_state":{"key":"%s"}}`, key), true, defaultGasForTests, 0)
+		require.Empty(t, execErr)
+		require.Equal(t, expected, string(data), key)
+	}
+}
+
+func TestIBCPacketReceiveLoopback(t *testing.T) {
+	ctx, keeper, tempDir, codeID, _, walletA, privKeyA, _, _ := setupTest(t, "./testdata/test-contract/contract.wasm")
+	defer os.RemoveAll(tempDir)
+
+	contractAddress, _, initErr := initHelper(t, keeper, ctx, codeID, walletA, privKeyA, `{"nop":{}}`, true, defaultGasForTests)
+	require.Empty(t, initErr)
+
+	data, err := testEncrypt(t, keeper, ctx, contractAddress, 0, []byte("ping"))
+	require.NoError(t, err)
+	nonce := data[0:32]
+
+	packet := cosmwasm.IBCPacket{
+		Data:     data,
+		Src:      cosmwasm.IBCEndpoint{PortID: "wasm.counterparty", ChannelID: "channel-3"},
+		Dest:     cosmwasm.IBCEndpoint{PortID: cosmwasm.IBCPortPrefix + contractAddress.String(), ChannelID: "channel-0"},
+		Sequence: 7,
+	}
+
+	ctx = ctx.WithEventManager(sdk.NewEventManager())
+	ack, err := keeper.IBCPacketReceive(ctx, contractAddress, packet)
+	require.NoError(t, err)
+
+	// the ack is encrypted to the sender of the packet, and bound to the packet
+	plainAck, err := wasmCtx.DecryptIBCAcknowledgement(ack, nonce, packet.Src.PortID, packet.Src.ChannelID, packet.Sequence)
+	require.NoError(t, err)
+	require.Equal(t, "ack:ping", string(plainAck))
+
+	_, err = wasmCtx.DecryptIBCAcknowledgement(ack, nonce, packet.Src.PortID, packet.Src.ChannelID, packet.Sequence+1)
+	require.Error(t, err)
+
+	events := getDecryptedWasmEvents(t, ctx, nonce)
+	require.Equal(t,
+		[]ContractEvent{
+			{
+				{Key: "contract_address", Value: contractAddress.String()},
+				{Key: "ibc_packet_sequence", Value: "7"},
+			},
+		},
+		events,
+	)
+
+	// the contract got the decrypted data of the packet
+	stored, _, execErr := execHelper(t, keeper, ctx, contractAddress, walletA, privKeyA, `{"get_state":{"key":"ibc_packet_data"}}`, true, defaultGasForTests, 0)
+	require.Empty(t, execErr)
+	require.Equal(t, "ping", string(stored))
+
+	// a packet that isn't for the port of the contract is rejected
+	packet.Dest.PortID = "transfer"
+	_, err = keeper.IBCPacketReceive(ctx, contractAddress, packet)
+	require.Error(t, err)
+	require.Contains(t, err.Error(), "IBC message is not for the port of the contract")
+}
+
+// wrap the gas meter to add up the gas charged for running contracts
+type WasmGasSumMeter struct {
+	sdk.GasMeter
+	wasmGas uint64
+}
+
+func (m *WasmGasSumMeter) ConsumeGas(amount sdk.Gas, descriptor string) {
+	if descriptor == "wasm contract" {
+		m.wasmGas += amount
+	}
+	m.GasMeter.ConsumeGas(amount, descriptor)
+}
+
+// execute a signed message, and return the gas the contract was charged for running it
+func wasmGasForExecute(t *testing.T, keeper Keeper, ctx sdk.Context, contractAddress sdk.AccAddress, txSender sdk.AccAddress, senderPrivKey crypto.PrivKey, execMsg string, simulate bool) uint64 {
+	hashStr := hex.EncodeToString(keeper.GetContractHash(ctx, contractAddress))
+
+	msg := types.SecretMsg{
+		CodeHash: []byte(hashStr),
+		Msg:      []byte(execMsg),
+	}
+
+	execMsgBz, err := wasmCtx.Encrypt(msg.Serialize())
+	require.NoError(t, err)
+
+	gasMeter := &WasmGasSumMeter{GasMeter: sdk.NewGasMeter(defaultGasForTests)}
+	ctx = sdk.NewContext(
+		ctx.MultiStore(),
+		ctx.BlockHeader(),
+		ctx.IsCheckTx(),
+		log.NewNopLogger(),
+	).WithGasMeter(gasMeter)
+
+	ctx = PrepareExecSignedTx(t, keeper, ctx, txSender, senderPrivKey, execMsgBz, contractAddress, sdk.NewCoins())
+	if simulate {
+		_, err = keeper.SimulateExecute(ctx, contractAddress, txSender, execMsgBz, sdk.NewCoins())
+	} else {
+		_, err = keeper.Execute(ctx, contractAddress, txSender, execMsgBz, sdk.NewCoins(), nil)
+	}
+	require.NoError(t, err)
+	require.NotZero(t, gasMeter.wasmGas)
+
+	return gasMeter.wasmGas
+}
+
+func TestSimulateExecute(t *testing.T) {
+	ctx, keeper, tempDir, codeID, _, walletA, privKeyA, _, _ := setupTest(t, "./testdata/test-contract/contract.wasm")
+	defer os.RemoveAll(tempDir)
+
+	contractAddress, _, initErr := initHelper(t, keeper, ctx, codeID, walletA, privKeyA, `{"nop":{}}`, true, defaultGasForTests)
+	require.Empty(t, initErr)
+
+	setState := `{"set_state":{"key":"banana","value":"🍌"}}`
+	getState := `{"get_state":{"key":"banana"}}`
+
+	simulatedGas := wasmGasForExecute(t, keeper, ctx, contractAddress, walletA, privKeyA, setState, true)
+
+	// the simulation didn't change the state
+	data, _, execErr := execHelper(t, keeper, ctx, contractAddress, walletA, privKeyA, getState, true, defaultGasForTests, 0)
+	require.Empty(t, execErr)
+	require.Empty(t, data)
+
+	realGas := wasmGasForExecute(t, keeper, ctx, contractAddress, walletA, privKeyA, setState, false)
+	require.Equal(t, realGas, simulatedGas)
+
+	data, _, execErr = execHelper(t, keeper, ctx, contractAddress, walletA, privKeyA, getState, true, defaultGasForTests, 0)
+	require.Empty(t, execErr)
+	require.Equal(t, "🍌", string(data))
+}
+
 func TestInitCallbackToInit(t *testing.T) {
 	ctx, keeper, tempDir, codeID, codeHash, walletA, privKeyA, _, _ := setupTest(t, "./testdata/test-contract/contract.wasm")
 	defer os.RemoveAll(tempDir)
@@ -1047,6 +1254,204 @@ func TestState(t *testing.T) {
 	require.Empty(t, data)
 }
 
+func rotateContractKeyHelper(t *testing.T, keeper Keeper, ctx sdk.Context, contractAddress sdk.AccAddress, txSender sdk.AccAddress, senderPrivKey crypto.PrivKey, chunkSize uint32) (bool, error) {
+	ctx = sdk.NewContext(
+		ctx.MultiStore(),
+		ctx.BlockHeader(),
+		ctx.IsCheckTx(),
+		log.NewNopLogger(),
+	).WithGasMeter(sdk.NewGasMeter(100_000_000))
+
+	ctx = PrepareRotateContractKeySignedTx(t, keeper, ctx, txSender, senderPrivKey, contractAddress, chunkSize)
+	return keeper.RotateContractKey(ctx, contractAddress, txSender, chunkSize)
+}
+
+func TestRotateContractKey(t *testing.T) {
+	ctx, keeper, tempDir, codeID, _, walletA, privKeyA, walletB, privKeyB := setupTest(t, "./testdata/test-contract/contract.wasm")
+	defer os.RemoveAll(tempDir)
+
+	contractAddress, _, initErr := initHelper(t, keeper, ctx, codeID, walletA, privKeyA, `{"nop":{}}`, true, defaultGasForTests)
+	require.Empty(t, initErr)
+
+	const entries = 1000
+	for i := 0; i < entries; i++ {
+		_, _, execErr := execHelper(t, keeper, ctx, contractAddress, walletA, privKeyA, fmt.Sprintf(`{"set_state":{"key":"key%d","value":"value%d"}}`, i, i), true, defaultGasForTests, 0)
+		require.Empty(t, execErr)
+	}
+
+	// only the creator may rotate the key of the contract
+	_, err := rotateContractKeyHelper(t, keeper, ctx, contractAddress, walletB, privKeyB, entries/2)
+	require.Error(t, err)
+	require.Contains(t, err.Error(), "contract key rotation was not requested by the contract admin")
+
+	oldContractKey := keeper.GetContractKey(ctx, contractAddress)
+
+	done, err := rotateContractKeyHelper(t, keeper, ctx, contractAddress, walletA, privKeyA, entries/2)
+	require.NoError(t, err)
+	require.False(t, done)
+	newContractKey := keeper.GetContractKey(ctx, contractAddress)
+	require.NotEqual(t, oldContractKey, newContractKey)
+
+	// halfway through the rotation, the contract still reads all of its storage
+	for _, i := range []int{0, entries / 2, entries - 1} {
+		data, _, execErr := execHelper(t, keeper, ctx, contractAddress, walletA, privKeyA, fmt.Sprintf(`{"get_state":{"key":"key%d"}}`, i), true, defaultGasForTests, 0)
+		require.Empty(t, execErr)
+		require.Equal(t, fmt.Sprintf("value%d", i), string(data))
+	}
+
+	done, err = rotateContractKeyHelper(t, keeper, ctx, contractAddress, walletA, privKeyA, entries/2)
+	require.NoError(t, err)
+	require.True(t, done)
+	require.Equal(t, newContractKey, keeper.GetContractKey(ctx, contractAddress))
+
+	for i := 0; i < entries; i++ {
+		data, _, execErr := execHelper(t, keeper, ctx, contractAddress, walletA, privKeyA, fmt.Sprintf(`{"get_state":{"key":"key%d"}}`, i), true, defaultGasForTests, 0)
+		require.Empty(t, execErr)
+		require.Equal(t, fmt.Sprintf("value%d", i), string(data))
+	}
+}
+
+// stateArchiveChunk is the JSON of a chunk that the enclave exports
+type stateArchiveChunk struct {
+	ContractAddress string `json:"contract_address"`
+	SnapshotNonce   []byte `json:"snapshot_nonce"`
+	Entries         []struct {
+		Key   []byte `json:"key"`
+		Value []byte `json:"value"`
+		Mac   []byte `json:"mac"`
+	} `json:"entries"`
+}
+
+func exportContractStateHelper(t *testing.T, keeper Keeper, ctx sdk.Context, contractAddress sdk.AccAddress, chunkSize uint32) [][]byte {
+	var chunks [][]byte
+	var cursor, snapshotNonce []byte
+	for {
+		nonce, chunk, nextCursor, err := keeper.ExportContractState(ctx, contractAddress, cursor, snapshotNonce, chunkSize)
+		require.NoError(t, err)
+		if snapshotNonce != nil {
+			require.Equal(t, snapshotNonce, nonce)
+		}
+		snapshotNonce = nonce
+		chunks = append(chunks, chunk)
+		if len(nextCursor) == 0 {
+			return chunks
+		}
+		cursor = nextCursor
+	}
+}
+
+// importContractStateHelper imports chunk in a tx that txSender signed with signedChunk
+func importContractStateHelper(t *testing.T, keeper Keeper, ctx sdk.Context, contractAddress sdk.AccAddress, txSender sdk.AccAddress, senderPrivKey crypto.PrivKey, signedChunk []byte, chunk []byte) (uint32, error) {
+	ctx = PrepareImportContractStateSignedTx(t, keeper, freshContext(ctx), txSender, senderPrivKey, contractAddress, signedChunk)
+	return keeper.ImportContractState(ctx, contractAddress, txSender, chunk)
+}
+
+func TestExportImportContractState(t *testing.T) {
+	ctx, keeper, tempDir, codeID, _, walletA, privKeyA, walletB, privKeyB := setupTest(t, "./testdata/test-contract/contract.wasm")
+	defer os.RemoveAll(tempDir)
+
+	contractAddress := initWithAdminHelper(t, keeper, ctx, codeID, walletA, privKeyA, walletA, "exported contract")
+	otherContractAddress := initWithAdminHelper(t, keeper, ctx, codeID, walletA, privKeyA, walletA, "other exported contract")
+
+	const entries = 10_000
+	for i := 0; i < entries; i++ {
+		_, _, execErr := execHelper(t, keeper, ctx, contractAddress, walletA, privKeyA, fmt.Sprintf(`{"set_state":{"key":"key%d","value":"value%d"}}`, i, i), true, defaultGasForTests, 0)
+		require.Empty(t, execErr)
+	}
+	_, _, execErr := execHelper(t, keeper, ctx, otherContractAddress, walletA, privKeyA, `{"set_state":{"key":"key0","value":"other"}}`, true, defaultGasForTests, 0)
+	require.Empty(t, execErr)
+
+	chunks := exportContractStateHelper(t, keeper, ctx, contractAddress, 1000)
+	require.Greater(t, len(chunks), 1)
+	otherChunks := exportContractStateHelper(t, keeper, ctx, otherContractAddress, 1000)
+
+	// the snapshot is taken at the current height, and any node exports the same chunks at that height
+	require.Equal(t, chunks, exportContractStateHelper(t, keeper, ctx, contractAddress, 1000))
+	var parsed stateArchiveChunk
+	require.NoError(t, json.Unmarshal(chunks[0], &parsed))
+	require.Equal(t, uint64(ctx.BlockHeight()), binary.BigEndian.Uint64(parsed.SnapshotNonce[:8]))
+
+	// wipe the storage of the contract
+	contractStore := prefix.NewStore(ctx.KVStore(keeper.storeKey), types.GetContractStorePrefixKey(contractAddress))
+	var keys [][]byte
+	iter := contractStore.Iterator(nil, nil)
+	for ; iter.Valid(); iter.Next() {
+		keys = append(keys, iter.Key())
+	}
+	iter.Close()
+	for _, key := range keys {
+		contractStore.Delete(key)
+	}
+	data, _, execErr := execHelper(t, keeper, ctx, contractAddress, walletA, privKeyA, `{"get_state":{"key":"key0"}}`, true, defaultGasForTests, 0)
+	require.Empty(t, execErr)
+	require.Empty(t, data)
+
+	// only the admin may import the state of the contract
+	_, err := importContractStateHelper(t, keeper, ctx, contractAddress, walletB, privKeyB, chunks[0], chunks[0])
+	require.Error(t, err)
+	require.Contains(t, err.Error(), "can not import contract state")
+
+	// and only the chunk it signed
+	_, err = importContractStateHelper(t, keeper, ctx, contractAddress, walletA, privKeyA, chunks[1], chunks[0])
+	require.Error(t, err)
+	require.Contains(t, err.Error(), types.ErrStateImportFailed.Error())
+
+	// the archive of another contract is refused
+	_, err = importContractStateHelper(t, keeper, ctx, contractAddress, walletA, privKeyA, otherChunks[0], otherChunks[0])
+	require.Error(t, err)
+	require.Contains(t, err.Error(), "contract state archive was tampered with or belongs to another contract")
+
+	// so is a chunk that was changed after it was exported
+	var tampered stateArchiveChunk
+	require.NoError(t, json.Unmarshal(chunks[0], &tampered))
+	tampered.Entries[0].Value[0] ^= 1
+	tamperedChunk, err := json.Marshal(tampered)
+	require.NoError(t, err)
+	_, err = importContractStateHelper(t, keeper, ctx, contractAddress, walletA, privKeyA, tamperedChunk, tamperedChunk)
+	require.Error(t, err)
+	require.Contains(t, err.Error(), "contract state archive was tampered with or belongs to another contract")
+	require.False(t, contractStore.Iterator(nil, nil).Valid())
+
+	imported := 0
+	for _, chunk := range chunks {
+		count, err := importContractStateHelper(t, keeper, ctx, contractAddress, walletA, privKeyA, chunk, chunk)
+		require.NoError(t, err)
+		imported += int(count)
+	}
+	require.Equal(t, len(keys), imported)
+
+	for i := 0; i < entries; i++ {
+		data, _, execErr := execHelper(t, keeper, ctx, contractAddress, walletA, privKeyA, fmt.Sprintf(`{"get_state":{"key":"key%d"}}`, i), true, defaultGasForTests, 0)
+		require.Empty(t, execErr)
+		require.Equal(t, fmt.Sprintf("value%d", i), string(data))
+	}
+
+	// once a later snapshot was imported, the older one can't roll the contract back
+	laterCtx := ctx.WithBlockHeight(ctx.BlockHeight() + 1)
+	laterChunks := exportContractStateHelper(t, keeper, laterCtx, contractAddress, 1000)
+	require.NotEqual(t, chunks[0], laterChunks[0])
+	_, err = importContractStateHelper(t, keeper, laterCtx, contractAddress, walletA, privKeyA, laterChunks[0], laterChunks[0])
+	require.NoError(t, err)
+	_, err = importContractStateHelper(t, keeper, laterCtx, contractAddress, walletA, privKeyA, chunks[0], chunks[0])
+	require.Error(t, err)
+	require.Contains(t, err.Error(), "older than the last one imported")
+}
+
+func TestImportContractStateWithoutAdmin(t *testing.T) {
+	ctx, keeper, tempDir, codeID, _, walletA, privKeyA, _, _ := setupTest(t, "./testdata/test-contract/contract.wasm")
+	defer os.RemoveAll(tempDir)
+
+	contractAddress := initWithAdminHelper(t, keeper, ctx, codeID, walletA, privKeyA, nil, "contract without admin")
+	_, _, execErr := execHelper(t, keeper, ctx, contractAddress, walletA, privKeyA, `{"set_state":{"key":"key0","value":"value0"}}`, true, defaultGasForTests, 0)
+	require.Empty(t, execErr)
+
+	// not even the creator may import into a contract without an admin
+	chunks := exportContractStateHelper(t, keeper, ctx, contractAddress, 1000)
+	_, err := importContractStateHelper(t, keeper, ctx, contractAddress, walletA, privKeyA, chunks[0], chunks[0])
+	require.Error(t, err)
+	require.Contains(t, err.Error(), "can not import contract state")
+}
+
 func TestCanonicalizeAddressErrors(t *testing.T) {
 	ctx, keeper, tempDir, codeID, _, walletA, privKeyA, _, _ := setupTest(t, "./testdata/test-contract/contract.wasm")
 	defer os.RemoveAll(tempDir)
@@ -1317,6 +1722,19 @@ func TestQueryRecursionLimitEnforcedInQueries(t *testing.T) {
 	require.Nil(t, err.GenericErr)
 }
 
+func TestQueryRecursionUpToLimit(t *testing.T) {
+	ctx, keeper, tempDir, codeID, codeHash, walletA, privKeyA, _, _ := setupTest(t, "./testdata/test-contract/contract.wasm")
+	defer os.RemoveAll(tempDir)
+
+	addr, _, err := initHelper(t, keeper, ctx, codeID, walletA, privKeyA, `{"nop":{}}`, true, defaultGasForTests)
+	require.Empty(t, err)
+
+	// The top level query and 9 nested queries add up to the recursion limit of 10
+	data, err := queryHelper(t, keeper, ctx, addr, fmt.Sprintf(`{"send_external_query_depth_counter":{"to":"%s","depth":9,"code_hash":"%s"}}`, addr.String(), codeHash), true, defaultGasForTests)
+	require.Empty(t, err)
+	require.Equal(t, "9", data)
+}
+
 func TestQueryRecursionLimitEnforcedInHandles(t *testing.T) {
 	ctx, keeper, tempDir, codeID, codeHash, walletA, privKeyA, _, _ := setupTest(t, "./testdata/test-contract/contract.wasm")
 	defer os.RemoveAll(tempDir)
@@ -1354,6 +1772,104 @@ func TestQueryRecursionLimitEnforcedInInits(t *testing.T) {
 	}, events)
 }
 
+func TestQueryCanSeeBlockHeight(t *testing.T) {
+	ctx, keeper, tempDir, codeID, _, walletA, privKeyA, _, _ := setupTest(t, "./testdata/test-contract/contract.wasm")
+	defer os.RemoveAll(tempDir)
+
+	addr, _, initErr := initHelper(t, keeper, ctx, codeID, walletA, privKeyA, `{"nop":{}}`, true, defaultGasForTests)
+	require.Empty(t, initErr)
+
+	ctx = ctx.WithBlockHeight(ctx.BlockHeight() + 1)
+
+	data, queryErr := queryHelper(t, keeper, ctx, addr, `{"block_height":{}}`, true, defaultGasForTests)
+	require.Empty(t, queryErr)
+	require.Equal(t, fmt.Sprintf("%d", ctx.BlockHeight()), data)
+}
+
+func TestReplayProtectedQueryIsAnsweredOnce(t *testing.T) {
+	ctx, keeper, tempDir, codeID, _, walletA, privKeyA, _, _ := setupTest(t, "./testdata/test-contract/contract.wasm")
+	defer os.RemoveAll(tempDir)
+
+	addr, _, initErr := initHelper(t, keeper, ctx, codeID, walletA, privKeyA, `{"nop":{}}`, true, defaultGasForTests)
+	require.Empty(t, initErr)
+
+	nonce := make([]byte, 32)
+	_, err := rand.Read(nonce)
+	require.NoError(t, err)
+	query := fmt.Sprintf(
+		`{"with_replay_protection":{"nonce":"%s","min_height":%d,"max_height":%d,"query":{"block_height":{}}}}`,
+		base64.StdEncoding.EncodeToString(nonce), ctx.BlockHeight(), ctx.BlockHeight()+10,
+	)
+	queryBz, err := testEncrypt(t, keeper, ctx, addr, 0, []byte(query))
+	require.NoError(t, err)
+
+	// queries during txs have to be answered the same on every node, so they don't use up the nonce
+	for i := 0; i < 2; i++ {
+		_, err = keeper.QuerySmart(ctx, addr, queryBz, false)
+		require.NoError(t, err)
+	}
+
+	_, err = keeper.querySmartFromClient(ctx, addr, queryBz)
+	require.NoError(t, err)
+
+	// the same ciphertext, captured and sent again within its window
+	ctx = ctx.WithBlockHeight(ctx.BlockHeight() + 1)
+	_, err = keeper.querySmartFromClient(ctx, addr, queryBz)
+	require.Error(t, err)
+	require.Contains(t, err.Error(), "query was already answered and can't be replayed")
+}
+
+func TestReplayProtectedQueryOutsideWindow(t *testing.T) {
+	ctx, keeper, tempDir, codeID, _, walletA, privKeyA, _, _ := setupTest(t, "./testdata/test-contract/contract.wasm")
+	defer os.RemoveAll(tempDir)
+
+	addr, _, initErr := initHelper(t, keeper, ctx, codeID, walletA, privKeyA, `{"nop":{}}`, true, defaultGasForTests)
+	require.Empty(t, initErr)
+
+	nonce := make([]byte, 32)
+	_, err := rand.Read(nonce)
+	require.NoError(t, err)
+	query := fmt.Sprintf(
+		`{"with_replay_protection":{"nonce":"%s","min_height":%d,"max_height":%d,"query":{"block_height":{}}}}`,
+		base64.StdEncoding.EncodeToString(nonce), ctx.BlockHeight()+1, ctx.BlockHeight()+10,
+	)
+	queryBz, err := testEncrypt(t, keeper, ctx, addr, 0, []byte(query))
+	require.NoError(t, err)
+
+	_, err = keeper.querySmartFromClient(ctx, addr, queryBz)
+	require.Error(t, err)
+	require.Contains(t, err.Error(), "query was sent outside of its block height window")
+}
+
+func TestTxMessageWithReusedNonceIsRejected(t *testing.T) {
+	ctx, keeper, tempDir, codeID, _, walletA, privKeyA, walletB, privKeyB := setupTest(t, "./testdata/test-contract/contract.wasm")
+	defer os.RemoveAll(tempDir)
+
+	addr, _, initErr := initHelper(t, keeper, ctx, codeID, walletA, privKeyA, `{"nop":{}}`, true, defaultGasForTests)
+	require.Empty(t, initErr)
+
+	execMsgBz, err := testEncrypt(t, keeper, ctx, addr, 0, []byte(`{"no_logs":{}}`))
+	require.NoError(t, err)
+
+	ctx = ctx.WithGasMeter(sdk.NewGasMeter(defaultGasForTests))
+	execCtx := PrepareExecSignedTx(t, keeper, ctx, walletA, privKeyA, execMsgBz, addr, sdk.NewCoins())
+	_, err = keeper.Execute(execCtx, addr, walletA, execMsgBz, sdk.NewCoins(), nil)
+	require.NoError(t, err)
+
+	// the used nonce is in the state of the chain, so the copied message fails in any later block
+	// and on every node, whoever signs the tx it is copied into
+	ctx = ctx.WithBlockHeight(ctx.BlockHeight() + 100)
+	for _, signer := range []struct {
+		addr    sdk.AccAddress
+		privKey crypto.PrivKey
+	}{{walletA, privKeyA}, {walletB, privKeyB}} {
+		execCtx = PrepareExecSignedTx(t, keeper, ctx, signer.addr, signer.privKey, execMsgBz, addr, sdk.NewCoins())
+		_, err = keeper.Execute(execCtx, addr, signer.addr, execMsgBz, sdk.NewCoins(), nil)
+		require.Error(t, err)
+		require.Contains(t, err.Error(), "message reuses the encryption nonce of an earlier message")
+	}
+}
+
 func TestWriteToStorageDuringQuery(t *testing.T) {
 	ctx, keeper, tempDir, codeID, _, walletA, privKeyA, _, _ := setupTest(t, "./testdata/test-contract/contract.wasm")
 	defer os.RemoveAll(tempDir)
@@ -1698,12 +2214,21 @@ func TestWasmTooHighInitialMemoryStaticFail(t *testing.T) {
 }
 
 func TestWasmWithFloatingPoints(t *testing.T) {
-	ctx, keeper, tempDir, codeID, _, walletA, privKeyA, _, _ := setupTest(t, "./testdata/test-contract/contract_with_floats.wasm")
+	tempDir, err := ioutil.TempDir("", "wasm")
 	defer os.RemoveAll(tempDir)
+	require.NoError(t, err)
+	ctx, keepers := CreateTestInput(t, false, tempDir, SupportedFeatures, nil, nil)
+	accKeeper, keeper := keepers.AccountKeeper, keepers.WasmKeeper
 
-	_, _, err := initHelper(t, keeper, ctx, codeID, walletA, privKeyA, `{"nop":{}}`, false, defaultGasForTests)
-	require.NotNil(t, err.GenericErr)
-	require.Equal(t, "instantiate contract failed: Execution error: Enclave: found floating point operation in module code", err.GenericErr.Msg)
+	walletA, _ := createFakeFundedAccount(ctx, accKeeper, sdk.NewCoins(sdk.NewInt64Coin("denom", 1)))
+
+	wasmCode, err := ioutil.ReadFile("./testdata/test-contract/contract_with_floats.wasm")
+	require.NoError(t, err)
+
+	// the enclave rejects the contract when it's uploaded, with the reason
+	_, err = keeper.Create(ctx, walletA, wasmCode, "", "")
+	require.Error(t, err)
+	require.Equal(t, "create contract failed: Execution error: Enclave: tried to load invalid wasm code: floating point operations are not allowed", err.Error())
 }
 
 func TestCodeHashInvalid(t *testing.T) {
@@ -1714,7 +2239,7 @@ func TestCodeHashInvalid(t *testing.T) {
 	enc, _ := wasmCtx.Encrypt(initMsg)
 
 	ctx = PrepareInitSignedTx(t, keeper, ctx, walletA, privWalletA, enc, codeID, sdk.NewCoins(sdk.NewInt64Coin("denom", 0)))
-	_, err := keeper.Instantiate(ctx, codeID, walletA /* nil, */, enc, "some label", sdk.NewCoins(sdk.NewInt64Coin("denom", 0)), nil)
+	_, err := keeper.Instantiate(ctx, codeID, walletA, nil, enc, "some label", sdk.NewCoins(sdk.NewInt64Coin("denom", 0)), nil)
 	require.Error(t, err)
 	require.Contains(t, err.Error(), "failed to validate transaction")
 }
@@ -1727,7 +2252,7 @@ func TestCodeHashEmpty(t *testing.T) {
 	enc, _ := wasmCtx.Encrypt(initMsg)
 
 	ctx = PrepareInitSignedTx(t, keeper, ctx, walletA, privWalletA, enc, codeID, sdk.NewCoins(sdk.NewInt64Coin("denom", 0)))
-	_, err := keeper.Instantiate(ctx, codeID, walletA /* nil, */, enc, "some label", sdk.NewCoins(sdk.NewInt64Coin("denom", 0)), nil)
+	_, err := keeper.Instantiate(ctx, codeID, walletA, nil, enc, "some label", sdk.NewCoins(sdk.NewInt64Coin("denom", 0)), nil)
 	require.Error(t, err)
 	require.Contains(t, err.Error(), "failed to validate transaction")
 }
@@ -1740,7 +2265,7 @@ func TestCodeHashNotHex(t *testing.T) {
 	enc, _ := wasmCtx.Encrypt(initMsg)
 
 	ctx = PrepareInitSignedTx(t, keeper, ctx, walletA, privWalletA, enc, codeID, sdk.NewCoins(sdk.NewInt64Coin("denom", 0)))
-	_, err := keeper.Instantiate(ctx, codeID, walletA /* nil, */, enc, "some label", sdk.NewCoins(sdk.NewInt64Coin("denom", 0)), nil)
+	_, err := keeper.Instantiate(ctx, codeID, walletA, nil, enc, "some label", sdk.NewCoins(sdk.NewInt64Coin("denom", 0)), nil)
 	require.Error(t, err)
 	require.Contains(t, err.Error(), "failed to validate transaction")
 }
@@ -1754,7 +2279,7 @@ func TestCodeHashTooSmall(t *testing.T) {
 	enc, _ := wasmCtx.Encrypt(initMsg)
 
 	ctx = PrepareInitSignedTx(t, keeper, ctx, walletA, privWalletA, enc, codeID, sdk.NewCoins(sdk.NewInt64Coin("denom", 0)))
-	_, err := keeper.Instantiate(ctx, codeID, walletA /* nil, */, enc, "some label", sdk.NewCoins(sdk.NewInt64Coin("denom", 0)), nil)
+	_, err := keeper.Instantiate(ctx, codeID, walletA, nil, enc, "some label", sdk.NewCoins(sdk.NewInt64Coin("denom", 0)), nil)
 	require.Error(t, err)
 	require.Contains(t, err.Error(), "failed to validate transaction")
 }
@@ -1768,7 +2293,7 @@ func TestCodeHashTooBig(t *testing.T) {
 	enc, _ := wasmCtx.Encrypt(initMsg)
 
 	ctx = PrepareInitSignedTx(t, keeper, ctx, walletA, privWalletA, enc, codeID, sdk.NewCoins(sdk.NewInt64Coin("denom", 0)))
-	_, err := keeper.Instantiate(ctx, codeID, walletA /* nil, */, enc, "some label", sdk.NewCoins(sdk.NewInt64Coin("denom", 0)), nil)
+	_, err := keeper.Instantiate(ctx, codeID, walletA, nil, enc, "some label", sdk.NewCoins(sdk.NewInt64Coin("denom", 0)), nil)
 	require.Error(t, err)
 
 	initErr := extractInnerError(t, err, enc[0:32], true)
@@ -1787,7 +2312,7 @@ func TestCodeHashWrong(t *testing.T) {
 	enc, _ := wasmCtx.Encrypt(initMsg)
 
 	ctx = PrepareInitSignedTx(t, keeper, ctx, walletA, privWalletA, enc, codeID, sdk.NewCoins(sdk.NewInt64Coin("denom", 0)))
-	_, err := keeper.Instantiate(ctx, codeID, walletA /* nil, */, enc, "some label", sdk.NewCoins(sdk.NewInt64Coin("denom", 0)), nil)
+	_, err := keeper.Instantiate(ctx, codeID, walletA, nil, enc, "some label", sdk.NewCoins(sdk.NewInt64Coin("denom", 0)), nil)
 	require.Error(t, err)
 	require.Contains(t, err.Error(), "failed to validate transaction")
 }
@@ -2225,3 +2750,380 @@ func TestCodeHashQueryCallQuery(t *testing.T) {
 		)
 	})
 }
+
+// execRawEventsHelper executes a message and returns the events it emitted as they are on chain,
+// along with the nonce that encrypted them
+func execRawEventsHelper(t *testing.T, keeper Keeper, ctx sdk.Context, contractAddress sdk.AccAddress, txSender sdk.AccAddress, senderPrivKey crypto.PrivKey, execMsg string) (sdk.Events, []byte) {
+	hashStr := hex.EncodeToString(keeper.GetContractHash(ctx, contractAddress))
+
+	msg := types.SecretMsg{
+		CodeHash: []byte(hashStr),
+		Msg:      []byte(execMsg),
+	}
+
+	execMsgBz, err := wasmCtx.Encrypt(msg.Serialize())
+	require.NoError(t, err)
+	nonce := execMsgBz[0:32]
+
+	// reset the event manager, so we only get the events of this call
+	ctx = sdk.NewContext(
+		ctx.MultiStore(),
+		ctx.BlockHeader(),
+		ctx.IsCheckTx(),
+		log.NewNopLogger(),
+	).WithGasMeter(sdk.NewGasMeter(defaultGasForTests))
+
+	ctx = PrepareExecSignedTx(t, keeper, ctx, txSender, senderPrivKey, execMsgBz, contractAddress, sdk.NewCoins())
+	_, err = keeper.Execute(ctx, contractAddress, txSender, execMsgBz, sdk.NewCoins(), nil)
+	require.NoError(t, err)
+
+	return ctx.EventManager().Events(), nonce
+}
+
+func execGasBreakdownHelper(t *testing.T, keeper Keeper, ctx sdk.Context, contractAddress sdk.AccAddress, txSender sdk.AccAddress, senderPrivKey crypto.PrivKey, execMsg string) cosmwasm.GasBreakdown {
+	events, _ := execRawEventsHelper(t, keeper, ctx, contractAddress, txSender, senderPrivKey, execMsg)
+
+	for _, e := range events {
+		if e.Type != types.EventTypeGasBreakdown {
+			continue
+		}
+
+		var breakdown cosmwasm.GasBreakdown
+		for _, attr := range e.Attributes {
+			value, err := strconv.ParseUint(string(attr.Value), 10, 64)
+			switch string(attr.Key) {
+			case types.AttributeKeyGasWasm:
+				breakdown.Wasm = value
+			case types.AttributeKeyGasStorage:
+				breakdown.Storage = value
+			case types.AttributeKeyGasOcall:
+				breakdown.Ocall = value
+			default:
+				continue
+			}
+			require.NoError(t, err)
+		}
+		return breakdown
+	}
+
+	require.Fail(t, "no gas breakdown event was emitted")
+	return cosmwasm.GasBreakdown{}
+}
+
+func TestGasBreakdown(t *testing.T) {
+	ctx, keeper, tempDir, codeID, _, walletA, privKeyA, _, _ := setupTest(t, "./testdata/test-contract/contract.wasm")
+	defer os.RemoveAll(tempDir)
+
+	contractAddress, _, initErr := initHelper(t, keeper, ctx, codeID, walletA, privKeyA, `{"nop":{}}`, true, defaultGasForTests)
+	require.Empty(t, initErr)
+
+	// a call that doesn't touch storage makes no ocalls
+	breakdown := execGasBreakdownHelper(t, keeper, ctx, contractAddress, walletA, privKeyA, `{"no_logs":{}}`)
+	require.NotZero(t, breakdown.Wasm)
+	require.Zero(t, breakdown.Storage)
+	require.Zero(t, breakdown.Ocall)
+
+	breakdown = execGasBreakdownHelper(t, keeper, ctx, contractAddress, walletA, privKeyA, `{"set_state":{"key":"a","value":"b"}}`)
+	require.NotZero(t, breakdown.Wasm)
+	require.NotZero(t, breakdown.Storage)
+	require.NotZero(t, breakdown.Ocall)
+}
+
+func TestAddrRoundTrip(t *testing.T) {
+	ctx, keeper, tempDir, codeID, _, walletA, privKeyA, _, _ := setupTest(t, "./testdata/test-contract/contract.wasm")
+	defer os.RemoveAll(tempDir)
+
+	contractAddress, _, initErr := initHelper(t, keeper, ctx, codeID, walletA, privKeyA, `{"nop":{}}`, true, defaultGasForTests)
+	require.Empty(t, initErr)
+
+	data, _, execErr := execHelper(t, keeper, ctx, contractAddress, walletA, privKeyA, `{"addr_round_trip":{}}`, true, defaultGasForTests, 0)
+	require.Empty(t, execErr)
+	require.Equal(t, walletA.String(), string(data))
+
+	_, _, execErr = execHelper(t, keeper, ctx, contractAddress, walletA, privKeyA, fmt.Sprintf(`{"addr_validate":{"addr":"%s"}}`, walletA.String()), true, defaultGasForTests, 0)
+	require.Empty(t, execErr)
+
+	// the error is returned to the contract, instead of stopping it
+	addr := []byte(walletA.String())
+	addr[len(addr)-1] ^= 1
+	_, _, execErr = execHelper(t, keeper, ctx, contractAddress, walletA, privKeyA, fmt.Sprintf(`{"addr_validate":{"addr":"%s"}}`, addr), true, defaultGasForTests, 0)
+	require.NotNil(t, execErr.GenericErr)
+	require.Contains(t, execErr.GenericErr.Msg, "addr_validate errored")
+
+	// BIP-173 doesn't allow mixed case
+	addr = []byte(walletA.String())
+	addr[0] = 'S'
+	_, _, execErr = execHelper(t, keeper, ctx, contractAddress, walletA, privKeyA, fmt.Sprintf(`{"addr_validate":{"addr":"%s"}}`, addr), true, defaultGasForTests, 0)
+	require.NotNil(t, execErr.GenericErr)
+	require.Contains(t, execErr.GenericErr.Msg, "mixed-case")
+}
+
+func TestPlaintextLogs(t *testing.T) {
+	ctx, keeper, tempDir, codeID, _, walletA, privKeyA, _, _ := setupTest(t, "./testdata/test-contract/contract.wasm")
+	defer os.RemoveAll(tempDir)
+
+	contractAddress, _, initErr := initHelper(t, keeper, ctx, codeID, walletA, privKeyA, `{"nop":{}}`, true, defaultGasForTests)
+	require.Empty(t, initErr)
+
+	events, nonce := execRawEventsHelper(t, keeper, ctx, contractAddress, walletA, privKeyA, `{"transfer_with_public_action":{"amount":100}}`)
+
+	var attributes []sdk.Attribute
+	for _, e := range events {
+		if e.Type == types.CustomEventType {
+			attributes = append(attributes, eventAttributes(e)...)
+		}
+	}
+	// contract_address, execution_path and the two attributes of the contract
+	require.Len(t, attributes, 4)
+
+	// the action is public
+	require.Equal(t, sdk.NewAttribute("action", "transfer"), attributes[2])
+
+	// the amount is only readable by the sender
+	decrypted, err := wasmCtx.DecryptWasmEvent(attributes, nonce)
+	require.NoError(t, err)
+	require.Equal(t, sdk.NewAttribute("action", "transfer"), decrypted[2])
+	require.Equal(t, sdk.NewAttribute("amount", "100"), decrypted[3])
+}
+
+func TestReadDbMulti(t *testing.T) {
+	ctx, keeper, tempDir, codeID, _, walletA, privKeyA, _, _ := setupTest(t, "./testdata/test-contract/contract.wasm")
+	defer os.RemoveAll(tempDir)
+
+	contractAddress, _, initErr := initHelper(t, keeper, ctx, codeID, walletA, privKeyA, `{"nop":{}}`, true, defaultGasForTests)
+	require.Empty(t, initErr)
+
+	var keys []string
+	for i := 0; i < 20; i++ {
+		key := fmt.Sprintf("key%d", i)
+		_, _, execErr := execHelper(t, keeper, ctx, contractAddress, walletA, privKeyA, fmt.Sprintf(`{"set_state":{"key":"%s","value":"value%d"}}`, key, i), true, defaultGasForTests, 0)
+		require.Empty(t, execErr)
+		keys = append(keys, key)
+	}
+	_, _, execErr := execHelper(t, keeper, ctx, contractAddress, walletA, privKeyA, `{"set_state":{"key":"empty","value":""}}`, true, defaultGasForTests, 0)
+	require.Empty(t, execErr)
+
+	// missing keys and empty values are told apart
+	for _, batched := range []bool{true, false} {
+		data, _, execErr := execHelper(t, keeper, ctx, contractAddress, walletA, privKeyA, fmt.Sprintf(`{"get_state_multi":{"keys":["key0","missing","empty","key19"],"batched":%t}}`, batched), true, defaultGasForTests, 0)
+		require.Empty(t, execErr)
+		require.Equal(t, `["value0",null,"","value19"]`, string(data))
+	}
+
+	// every key read on its own is an ocall, while the batch is a single one
+	keysJson, err := json.Marshal(keys)
+	require.NoError(t, err)
+	batched := execGasBreakdownHelper(t, keeper, ctx, contractAddress, walletA, privKeyA, fmt.Sprintf(`{"get_state_multi":{"keys":%s,"batched":true}}`, keysJson))
+	each := execGasBreakdownHelper(t, keeper, ctx, contractAddress, walletA, privKeyA, fmt.Sprintf(`{"get_state_multi":{"keys":%s,"batched":false}}`, keysJson))
+	require.NotZero(t, batched.Ocall)
+	require.Equal(t, uint64(len(keys))*batched.Ocall, each.Ocall)
+	require.Equal(t, batched.Storage, each.Storage)
+}
+
+func TestReadAhead(t *testing.T) {
+	ctx, keeper, tempDir, codeID, _, walletA, privKeyA, _, _ := setupTest(t, "./testdata/test-contract/contract.wasm")
+	defer os.RemoveAll(tempDir)
+
+	contractAddress, _, initErr := initHelper(t, keeper, ctx, codeID, walletA, privKeyA, `{"nop":{}}`, true, defaultGasForTests)
+	require.Empty(t, initErr)
+
+	const count = 64
+	_, _, execErr := execHelper(t, keeper, ctx, contractAddress, walletA, privKeyA, fmt.Sprintf(`{"write_items":{"count":%d}}`, count), true, defaultGasForTests, 0)
+	require.Empty(t, execErr)
+
+	// a walk through the items, and the same items in an order that has no step to follow
+	var sequential, shuffled []int
+	for i := 0; i < count; i++ {
+		sequential = append(sequential, i)
+		shuffled = append(shuffled, i*37%count)
+	}
+
+	readItems := func(indexes []int) (string, uint64, cosmwasm.GasBreakdown) {
+		indexesJson, err := json.Marshal(indexes)
+		require.NoError(t, err)
+		msg := fmt.Sprintf(`{"read_items":{"indexes":%s}}`, indexesJson)
+
+		before, err := api.GetRuntimeStats()
+		require.NoError(t, err)
+		start := time.Now()
+		data, _, execErr := execHelper(t, keeper, ctx, contractAddress, walletA, privKeyA, msg, true, defaultGasForTests, 0)
+		elapsed := time.Since(start)
+		require.Empty(t, execErr)
+		after, err := api.GetRuntimeStats()
+		require.NoError(t, err)
+
+		hits := after.PrefetchHits - before.PrefetchHits
+		t.Logf("read %d items in %v, %d of them read ahead", len(indexes), elapsed, hits)
+		return string(data), hits, execGasBreakdownHelper(t, keeper, ctx, contractAddress, walletA, privKeyA, msg)
+	}
+
+	expectedItems := func(indexes []int) string {
+		var items []string
+		for _, index := range indexes {
+			items = append(items, fmt.Sprintf("item %d", index))
+		}
+		itemsJson, err := json.Marshal(items)
+		require.NoError(t, err)
+		return string(itemsJson)
+	}
+
+	// values read ahead are the same as the ones read on their own
+	sequentialData, sequentialHits, sequentialGas := readItems(sequential)
+	shuffledData, shuffledHits, shuffledGas := readItems(shuffled)
+	require.Equal(t, expectedItems(sequential), sequentialData)
+	require.Equal(t, expectedItems(shuffled), shuffledData)
+
+	// all but the reads that learn the walk are answered from the values read ahead
+	require.GreaterOrEqual(t, sequentialHits, uint64(count-4))
+	require.Less(t, shuffledHits, sequentialHits)
+
+	// which only saves time: the reads cost the same gas as the ones sent to the host
+	require.NotZero(t, sequentialGas.Storage)
+	require.Equal(t, shuffledGas.Storage, sequentialGas.Storage)
+	require.Equal(t, shuffledGas.Ocall, sequentialGas.Ocall)
+}
+
+// queryOnBranch runs `{"receive_external_query":{"num":num}}` on a branch of the state, so it can
+// run next to other calls, and returns the answer and the gas it used
+func queryOnBranch(keeper Keeper, ctx sdk.Context, contractAddr sdk.AccAddress, num int) (string, uint64, error) {
+	gasMeter := sdk.NewGasMeter(defaultGasForTests)
+	ctx = sdk.NewContext(
+		ctx.MultiStore().CacheMultiStore(),
+		ctx.BlockHeader(),
+		ctx.IsCheckTx(),
+		log.NewNopLogger(),
+	).WithGasMeter(gasMeter)
+
+	msg := types.SecretMsg{
+		CodeHash: []byte(hex.EncodeToString(keeper.GetContractHash(ctx, contractAddr))),
+		Msg:      []byte(fmt.Sprintf(`{"receive_external_query":{"num":%d}}`, num)),
+	}
+	queryBz, err := wasmCtx.Encrypt(msg.Serialize())
+	if err != nil {
+		return "", 0, err
+	}
+	nonce := queryBz[0:32]
+
+	resultCipherBz, err := keeper.QuerySmart(ctx, contractAddr, queryBz, false)
+	if err != nil {
+		return "", 0, err
+	}
+	resultPlainBz, err := wasmCtx.Decrypt(resultCipherBz, nonce)
+	if err != nil {
+		return "", 0, err
+	}
+	resultBz, err := base64.StdEncoding.DecodeString(string(resultPlainBz))
+	if err != nil {
+		return "", 0, err
+	}
+
+	return string(resultBz), gasMeter.GasConsumed(), nil
+}
+
+func TestConcurrentQueriesDuringExec(t *testing.T) {
+	ctx, keeper, tempDir, codeID, codeHash, walletA, privKeyA, _, _ := setupTest(t, "./testdata/test-contract/contract.wasm")
+	defer os.RemoveAll(tempDir)
+
+	addr, _, initErr := initHelper(t, keeper, ctx, codeID, walletA, privKeyA, `{"nop":{}}`, true, defaultGasForTests)
+	require.Empty(t, initErr)
+
+	// The gas of every query when it runs alone
+	const queries = 16
+	var expectedGas [queries]uint64
+	for num := 0; num < queries; num++ {
+		_, gas, err := queryOnBranch(keeper, ctx, addr, num)
+		require.NoError(t, err)
+		expectedGas[num] = gas
+	}
+
+	// A long execution, that goes as deep into queries as the enclave allows
+	execCtx := ctx.WithMultiStore(ctx.MultiStore().CacheMultiStore())
+	execData := make(chan []byte, 1)
+	go func() {
+		data, _, err := execHelper(t, keeper, execCtx, addr, walletA, privKeyA, fmt.Sprintf(`{"send_external_query_recursion_limit":{"to":"%s","code_hash":"%s", "depth":1}}`, addr.String(), codeHash), true, defaultGasForTests, 0)
+		require.Nil(t, err.GenericErr)
+		execData <- data
+	}()
+
+	type queryResult struct {
+		num    int
+		answer string
+		gas    uint64
+		err    error
+	}
+	results := make(chan queryResult, queries)
+	for num := 0; num < queries; num++ {
+		go func(num int) {
+			answer, gas, err := queryOnBranch(keeper, ctx, addr, num)
+			results <- queryResult{num, answer, gas, err}
+		}(num)
+	}
+
+	timeout := time.After(time.Minute)
+	for i := 0; i < queries; i++ {
+		select {
+		case result := <-results:
+			require.NoError(t, result.err)
+			require.Equal(t, strconv.Itoa(result.num+1), result.answer)
+			require.Equal(t, expectedGas[result.num], result.gas)
+		case <-timeout:
+			t.Fatal("the queries didn't finish, they may be deadlocked with the execution")
+		}
+	}
+
+	select {
+	case data := <-execData:
+		// The queries didn't count towards the recursion depth of the execution
+		require.Equal(t, "\"Recursion limit was correctly enforced\"", string(data))
+	case <-timeout:
+		t.Fatal("the execution didn't finish, it may be deadlocked with the queries")
+	}
+}
+
+func TestStorageQuota(t *testing.T) {
+	ctx, keeper, tempDir, codeID, _, walletA, privKeyA, _, _ := setupTest(t, "./testdata/test-contract/contract.wasm")
+	defer os.RemoveAll(tempDir)
+
+	type fillStorageResponse struct {
+		Written uint32 `json:"written"`
+		Usage   uint64 `json:"usage"`
+		Error   string `json:"error"`
+	}
+	fillStorage := func(contractAddress sdk.AccAddress, maxEntries int) fillStorageResponse {
+		// every write costs several thousand gas
+		data, _, execErr := execHelper(t, keeper, ctx, contractAddress, walletA, privKeyA, fmt.Sprintf(`{"fill_storage":{"value_len":96,"max_entries":%d}}`, maxEntries), true, 5*defaultGasForTests, 0)
+		require.Empty(t, execErr)
+		var response fillStorageResponse
+		require.NoError(t, json.Unmarshal(data, &response))
+		return response
+	}
+
+	// without a quota, contracts write as much as they like and nothing is accounted for
+	unlimited, _, initErr := initHelper(t, keeper, ctx, codeID, walletA, privKeyA, `{"nop":{}}`, true, defaultGasForTests)
+	require.Empty(t, initErr)
+	require.Equal(t, fillStorageResponse{Written: 20}, fillStorage(unlimited, 20))
+
+	params := keeper.GetParams(ctx)
+	params.StorageQuota = 1_000
+	keeper.setParams(ctx, params)
+
+	// every entry counts for its 4 byte key and 96 byte value, so the 11th write is the first one
+	// past the quota, and the contract gets the error instead of failing
+	limited, _, initErr := initHelper(t, keeper, ctx, codeID, walletA, privKeyA, `{"nop":{}}`, true, defaultGasForTests)
+	require.Empty(t, initErr)
+	response := fillStorage(limited, 20)
+	require.Equal(t, uint32(10), response.Written)
+	require.Equal(t, uint64(1_000), response.Usage)
+	require.Contains(t, response.Error, "storage quota exceeded")
+	require.Contains(t, response.Error, "1100 bytes, past its quota of 1000")
+
+	// the failed write changed nothing, so overwriting the entries works until the same write
+	response = fillStorage(limited, 20)
+	require.Equal(t, uint32(10), response.Written)
+	require.Equal(t, uint64(1_000), response.Usage)
+
+	// entries written before the quota was set aren't counted
+	response = fillStorage(unlimited, 30)
+	require.Equal(t, uint32(30), response.Written)
+	require.Equal(t, uint64(1_000), response.Usage)
+}
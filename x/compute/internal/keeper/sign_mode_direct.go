@@ -0,0 +1,271 @@
+package keeper
+
+import (
+	"bytes"
+	"encoding/binary"
+	"fmt"
+
+	"github.com/enigmampc/SecretNetwork/x/compute/internal/types"
+	sdk "github.com/enigmampc/cosmos-sdk/types"
+	sdkerrors "github.com/enigmampc/cosmos-sdk/types/errors"
+	"github.com/enigmampc/cosmos-sdk/x/auth"
+	"github.com/enigmampc/cosmos-sdk/x/auth/exported"
+	authtypes "github.com/enigmampc/cosmos-sdk/x/auth/types"
+	"github.com/tendermint/tendermint/crypto/secp256k1"
+
+	wasmTypes "github.com/enigmampc/SecretNetwork/go-cosmwasm/types"
+)
+
+// Txs signed with SIGN_MODE_DIRECT are the protobuf TxRaw of cosmos.tx.v1beta1 instead of an amino
+// StdTx. Only what's needed to hand the sign docs to the enclave is decoded here, the enclave
+// parses and verifies the sign docs itself.
+
+const secp256k1PubKeyTypeURL = "/cosmos.crypto.secp256k1.PubKey"
+
+// directTx is a tx signed with SIGN_MODE_DIRECT
+type directTx struct {
+	bodyBytes     []byte
+	authInfoBytes []byte
+	memo          string
+	signers       []directSigner
+}
+
+// directSigner is a signer in the auth info of a directTx, with its signature from the TxRaw
+type directSigner struct {
+	pubKey    secp256k1.PubKeySecp256k1
+	signature []byte
+}
+
+// decodeDirectTx decodes a TxRaw. Only secp256k1 signers are supported, as multisig signers sign
+// with amino even in txs that are otherwise signed with SIGN_MODE_DIRECT.
+func decodeDirectTx(txBytes []byte) (directTx, error) {
+	var tx directTx
+	var signatures [][]byte
+	err := forEachProtoField(txBytes, func(field uint64, value []byte) error {
+		switch field {
+		case 1:
+			tx.bodyBytes = value
+		case 2:
+			tx.authInfoBytes = value
+		case 3:
+			signatures = append(signatures, value)
+		}
+		return nil
+	})
+	if err != nil {
+		return directTx{}, fmt.Errorf("malformed tx: %w", err)
+	}
+
+	err = forEachProtoField(tx.bodyBytes, func(field uint64, value []byte) error {
+		if field == 2 {
+			tx.memo = string(value)
+		}
+		return nil
+	})
+	if err != nil {
+		return directTx{}, fmt.Errorf("malformed tx body: %w", err)
+	}
+
+	err = forEachProtoField(tx.authInfoBytes, func(field uint64, value []byte) error {
+		if field != 1 {
+			return nil
+		}
+		pubKey, err := decodeSignerInfoPubKey(value)
+		if err != nil {
+			return err
+		}
+		tx.signers = append(tx.signers, directSigner{pubKey: pubKey})
+		return nil
+	})
+	if err != nil {
+		return directTx{}, fmt.Errorf("malformed tx auth info: %w", err)
+	}
+
+	if len(tx.signers) == 0 || len(signatures) != len(tx.signers) {
+		return directTx{}, fmt.Errorf("tx has %d signers and %d signatures", len(tx.signers), len(signatures))
+	}
+	for i := range tx.signers {
+		tx.signers[i].signature = signatures[i]
+	}
+	return tx, nil
+}
+
+// decodeSignerInfoPubKey decodes the public key of a SignerInfo, which is a secp256k1 PubKey
+// wrapped in an Any
+func decodeSignerInfoPubKey(signerInfo []byte) (secp256k1.PubKeySecp256k1, error) {
+	var typeURL string
+	var key []byte
+	err := forEachProtoField(signerInfo, func(field uint64, value []byte) error {
+		if field != 1 {
+			return nil
+		}
+		return forEachProtoField(value, func(field uint64, value []byte) error {
+			switch field {
+			case 1:
+				typeURL = string(value)
+			case 2:
+				return forEachProtoField(value, func(field uint64, value []byte) error {
+					if field == 1 {
+						key = value
+					}
+					return nil
+				})
+			}
+			return nil
+		})
+	})
+	if err != nil {
+		return secp256k1.PubKeySecp256k1{}, err
+	}
+
+	var pubKey secp256k1.PubKeySecp256k1
+	if typeURL != secp256k1PubKeyTypeURL || len(key) != len(pubKey) {
+		return secp256k1.PubKeySecp256k1{}, fmt.Errorf("unsupported signer public key of type %q", typeURL)
+	}
+	copy(pubKey[:], key)
+	return pubKey, nil
+}
+
+// signBytes returns the SignDoc that acc signed. Like GetSignBytes, the account number is 0 at
+// genesis.
+func (tx directTx) signBytes(ctx sdk.Context, acc exported.Account) []byte {
+	var accNum uint64
+	if ctx.BlockHeight() != 0 {
+		accNum = acc.GetAccountNumber()
+	}
+	return encodeDirectSignDoc(tx.bodyBytes, tx.authInfoBytes, ctx.ChainID(), accNum)
+}
+
+// signer returns the signer of the tx with the address addr
+func (tx directTx) signer(addr sdk.AccAddress) (directSigner, bool) {
+	for _, signer := range tx.signers {
+		if bytes.Equal(signer.pubKey.Address(), addr) {
+			return signer, true
+		}
+	}
+	return directSigner{}, false
+}
+
+// getDirectSignerInfo is GetSignerInfo for a tx signed with SIGN_MODE_DIRECT
+func (k Keeper) getDirectSignerInfo(ctx sdk.Context, tx directTx, signer sdk.AccAddress) (authtypes.StdSignature, []byte, error) {
+	signerAcc, err := auth.GetSignerAcc(ctx, k.accountKeeper, signer)
+	if err != nil {
+		return authtypes.StdSignature{}, nil, sdkerrors.Wrap(types.ErrInstantiateFailed, fmt.Sprintf("Unable to retrieve account by address: %s", err.Error()))
+	}
+
+	directSigner, ok := tx.signer(signer)
+	if !ok {
+		return authtypes.StdSignature{}, nil, sdkerrors.Wrap(types.ErrInstantiateFailed, fmt.Sprintf("Message sender: %v is not found in the tx signer set, callback signature not provided", signer))
+	}
+
+	signature := authtypes.StdSignature{
+		PubKey:    directSigner.pubKey,
+		Signature: directSigner.signature,
+	}
+	return signature, tx.signBytes(ctx, signerAcc), nil
+}
+
+// getDirectAdditionalSignersInfo is GetAdditionalSignersInfo for a tx signed with SIGN_MODE_DIRECT
+func (k Keeper) getDirectAdditionalSignersInfo(ctx sdk.Context, tx directTx, signer sdk.AccAddress) ([]wasmTypes.SignerInfo, error) {
+	var signersInfo []wasmTypes.SignerInfo
+	for _, txSigner := range tx.signers {
+		txSignerAddr := sdk.AccAddress(txSigner.pubKey.Address())
+		if txSignerAddr.Equals(signer) {
+			continue
+		}
+
+		signerAcc, err := auth.GetSignerAcc(ctx, k.accountKeeper, txSignerAddr)
+		if err != nil {
+			return nil, sdkerrors.Wrap(types.ErrInstantiateFailed, fmt.Sprintf("Unable to retrieve account by address: %s", err.Error()))
+		}
+
+		signersInfo = append(signersInfo, wasmTypes.SignerInfo{
+			Bytes: tx.signBytes(ctx, signerAcc),
+			Signature: authtypes.StdSignature{
+				PubKey:    txSigner.pubKey,
+				Signature: txSigner.signature,
+			},
+		})
+	}
+
+	return signersInfo, nil
+}
+
+// encodeDirectSignDoc encodes a cosmos.tx.v1beta1.SignDoc
+func encodeDirectSignDoc(bodyBytes []byte, authInfoBytes []byte, chainID string, accountNumber uint64) []byte {
+	var signDoc []byte
+	signDoc = appendProtoBytes(signDoc, 1, bodyBytes)
+	signDoc = appendProtoBytes(signDoc, 2, authInfoBytes)
+	signDoc = appendProtoBytes(signDoc, 3, []byte(chainID))
+	signDoc = appendProtoVarint(signDoc, 4, accountNumber)
+	return signDoc
+}
+
+// forEachProtoField calls fn with every length delimited field of the protobuf message msg, in
+// the order they were encoded. Fields of other wire types are skipped.
+func forEachProtoField(msg []byte, fn func(field uint64, value []byte) error) error {
+	for len(msg) > 0 {
+		key, n := binary.Uvarint(msg)
+		if n <= 0 {
+			return fmt.Errorf("malformed field key")
+		}
+		msg = msg[n:]
+
+		switch wireType := key & 7; wireType {
+		case 0:
+			if _, n = binary.Uvarint(msg); n <= 0 {
+				return fmt.Errorf("malformed varint of field %d", key>>3)
+			}
+			msg = msg[n:]
+		case 1, 5:
+			size := 8
+			if wireType == 5 {
+				size = 4
+			}
+			if len(msg) < size {
+				return fmt.Errorf("truncated field %d", key>>3)
+			}
+			msg = msg[size:]
+		case 2:
+			length, n := binary.Uvarint(msg)
+			if n <= 0 || length > uint64(len(msg)-n) {
+				return fmt.Errorf("truncated field %d", key>>3)
+			}
+			value := msg[n : n+int(length)]
+			msg = msg[n+int(length):]
+			if err := fn(key>>3, value); err != nil {
+				return err
+			}
+		default:
+			return fmt.Errorf("unsupported wire type %d of field %d", wireType, key>>3)
+		}
+	}
+	return nil
+}
+
+// appendProtoBytes appends a length delimited field to a protobuf message. Like proto3 encoders
+// do, empty values are left out.
+func appendProtoBytes(msg []byte, field uint64, value []byte) []byte {
+	if len(value) == 0 {
+		return msg
+	}
+	msg = appendUvarint(msg, field<<3|2)
+	msg = appendUvarint(msg, uint64(len(value)))
+	return append(msg, value...)
+}
+
+// appendProtoVarint appends a varint field to a protobuf message. Like proto3 encoders do, 0 is
+// left out.
+func appendProtoVarint(msg []byte, field uint64, value uint64) []byte {
+	if value == 0 {
+		return msg
+	}
+	msg = appendUvarint(msg, field<<3)
+	return appendUvarint(msg, value)
+}
+
+func appendUvarint(msg []byte, value uint64) []byte {
+	var buf [binary.MaxVarintLen64]byte
+	n := binary.PutUvarint(buf[:], value)
+	return append(msg, buf[:n]...)
+}
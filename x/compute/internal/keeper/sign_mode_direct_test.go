@@ -0,0 +1,113 @@
+package keeper
+
+import (
+	"fmt"
+	"os"
+	"testing"
+
+	wasmTypes "github.com/enigmampc/SecretNetwork/go-cosmwasm/types"
+	sdk "github.com/enigmampc/cosmos-sdk/types"
+	"github.com/enigmampc/cosmos-sdk/x/auth"
+	"github.com/stretchr/testify/require"
+	"github.com/tendermint/tendermint/crypto"
+	"github.com/tendermint/tendermint/crypto/secp256k1"
+)
+
+// prepareExecDirectSignedTx is PrepareExecSignedTx for a tx that sender signed with SIGN_MODE_DIRECT,
+// encoded the way protobuf signing clients encode it
+func prepareExecDirectSignedTx(t *testing.T, keeper Keeper, ctx sdk.Context, sender sdk.AccAddress, privKey crypto.PrivKey, encMsg []byte, contract sdk.AccAddress, memo string) sdk.Context {
+	senderAcc, err := auth.GetSignerAcc(ctx, keeper.accountKeeper, sender)
+	require.NoError(t, err)
+
+	var msg []byte
+	msg = appendProtoBytes(msg, 1, sender)
+	msg = appendProtoBytes(msg, 2, contract)
+	msg = appendProtoBytes(msg, 3, encMsg)
+	var anyMsg []byte
+	anyMsg = appendProtoBytes(anyMsg, 1, []byte("/secret.compute.v1beta1.MsgExecuteContract"))
+	anyMsg = appendProtoBytes(anyMsg, 2, msg)
+	var body []byte
+	body = appendProtoBytes(body, 1, anyMsg)
+	body = appendProtoBytes(body, 2, []byte(memo))
+
+	pubKey := privKey.PubKey().(secp256k1.PubKeySecp256k1)
+	var protoPubKey []byte
+	protoPubKey = appendProtoBytes(protoPubKey, 1, pubKey[:])
+	var anyPubKey []byte
+	anyPubKey = appendProtoBytes(anyPubKey, 1, []byte(secp256k1PubKeyTypeURL))
+	anyPubKey = appendProtoBytes(anyPubKey, 2, protoPubKey)
+	var signerInfo []byte
+	signerInfo = appendProtoBytes(signerInfo, 1, anyPubKey)
+	signerInfo = appendProtoVarint(signerInfo, 3, senderAcc.GetSequence())
+	var fee []byte
+	fee = appendProtoVarint(fee, 2, 200_000)
+	var authInfo []byte
+	authInfo = appendProtoBytes(authInfo, 1, signerInfo)
+	authInfo = appendProtoBytes(authInfo, 2, fee)
+
+	signature, err := privKey.Sign(encodeDirectSignDoc(body, authInfo, ctx.ChainID(), senderAcc.GetAccountNumber()))
+	require.NoError(t, err)
+
+	var txRaw []byte
+	txRaw = appendProtoBytes(txRaw, 1, body)
+	txRaw = appendProtoBytes(txRaw, 2, authInfo)
+	txRaw = appendProtoBytes(txRaw, 3, signature)
+	return ctx.WithTxBytes(txRaw)
+}
+
+func TestSignModeDirect(t *testing.T) {
+	ctx, keeper, tempDir, codeID, _, walletA, privKeyA, walletB, _ := setupTest(t, "./testdata/erc20.wasm")
+	defer os.RemoveAll(tempDir)
+
+	initMsg := fmt.Sprintf(`{"decimals":10,"initial_balances":[{"address":"%s","amount":"108"},{"address":"%s","amount":"53"}],"name":"ReuvenPersonalRustCoin","symbol":"RPRC"}`, walletA.String(), walletB.String())
+	contractAddress, _, initErr := initHelper(t, keeper, ctx, codeID, walletA, privKeyA, initMsg, true, defaultGasForTests)
+	require.Empty(t, initErr)
+
+	execMsgBz, err := testEncrypt(t, keeper, ctx, contractAddress, 0,
+		[]byte(fmt.Sprintf(`{"transfer":{"amount":"10","recipient":"%s"}}`, walletB.String())))
+	require.NoError(t, err)
+	ctx = ctx.WithGasMeter(sdk.NewGasMeter(defaultGasForTests))
+	ctx = prepareExecDirectSignedTx(t, keeper, ctx, walletA, privKeyA, execMsgBz, contractAddress, "direct")
+
+	// the tx is handed to the enclave as a protobuf sign doc
+	signerSig, signBytes, signMode, err := keeper.GetSignerInfo(ctx, walletA)
+	require.NoError(t, err)
+	require.Equal(t, wasmTypes.SignModeDirect, signMode)
+	require.True(t, signerSig.PubKey.VerifyBytes(signBytes, signerSig.Signature))
+	memo, err := keeper.GetTxMemo(ctx)
+	require.NoError(t, err)
+	require.Equal(t, "direct", memo)
+	additionalSigners, err := keeper.GetAdditionalSignersInfo(ctx, walletA)
+	require.NoError(t, err)
+	require.Empty(t, additionalSigners)
+
+	_, err = keeper.Execute(ctx, contractAddress, walletA, execMsgBz, sdk.NewCoins(), nil)
+	require.NoError(t, err)
+
+	qRes, qErr := queryHelper(t, keeper, ctx, contractAddress, fmt.Sprintf(`{"balance":{"address":"%s"}}`, walletA.String()), true, defaultGasForTests)
+	require.Empty(t, qErr)
+	require.JSONEq(t, `{"balance":"98"}`, qRes)
+
+	// the enclave only executes the message that was signed
+	otherMsgBz, err := testEncrypt(t, keeper, ctx, contractAddress, 0,
+		[]byte(fmt.Sprintf(`{"transfer":{"amount":"50","recipient":"%s"}}`, walletB.String())))
+	require.NoError(t, err)
+	_, err = keeper.Execute(ctx, contractAddress, walletA, otherMsgBz, sdk.NewCoins(), nil)
+	require.Error(t, err)
+
+	// and a signer that isn't a signer of the tx has no sign doc
+	_, _, _, err = keeper.GetSignerInfo(ctx, walletB)
+	require.Error(t, err)
+}
+
+func TestDecodeDirectTxRejectsMalformedTxs(t *testing.T) {
+	for _, txBytes := range [][]byte{
+		nil,
+		{0x0a, 0x05, 0x01},
+		// a body without signers
+		appendProtoBytes(nil, 1, []byte{0x12, 0x00}),
+	} {
+		_, err := decodeDirectTx(txBytes)
+		require.Error(t, err, txBytes)
+	}
+}
@@ -14,6 +14,7 @@ import (
 	"github.com/stretchr/testify/assert"
 
 	"github.com/stretchr/testify/require"
+	abci "github.com/tendermint/tendermint/abci/types"
 
 	sdk "github.com/enigmampc/cosmos-sdk/types"
 )
@@ -124,7 +125,7 @@ func TestInitializeStaking(t *testing.T) {
 	require.NoError(t, err)
 
 	ctx = PrepareInitSignedTx(t, keeper, ctx, creator, creatorPrivKey, initBz, stakingID, nil)
-	stakingAddr, err := keeper.Instantiate(ctx, stakingID, creator /* , nil */, initBz, "staking derivates - DRV", nil, nil)
+	stakingAddr, err := keeper.Instantiate(ctx, stakingID, creator, nil, initBz, "staking derivates - DRV", nil, nil)
 	require.NoError(t, err)
 	require.NotEmpty(t, stakingAddr)
 
@@ -213,7 +214,7 @@ func initializeStaking(t *testing.T) initInfo {
 	require.NoError(t, err)
 
 	ctx = PrepareInitSignedTx(t, keeper, ctx, creator, creatorPrivKey, initBz, stakingID, nil)
-	stakingAddr, err := keeper.Instantiate(ctx, stakingID, creator /* , nil */, initBz, "staking derivates - DRV", nil, nil)
+	stakingAddr, err := keeper.Instantiate(ctx, stakingID, creator, nil, initBz, "staking derivates - DRV", nil, nil)
 	require.NoError(t, err)
 	require.NotEmpty(t, stakingAddr)
 
@@ -282,6 +283,49 @@ func TestBonding(t *testing.T) {
 	assertSupply(t, ctx, keeper, contractAddr, "80000", sdk.NewInt64Coin("stake", 80000))
 }
 
+func TestContractSeesDelegationLikeCLI(t *testing.T) {
+	initInfo := initializeStaking(t)
+	defer initInfo.cleanup()
+	ctx, valAddr, contractAddr := initInfo.ctx, initInfo.valAddr, initInfo.contractAddr
+	keeper, stakingKeeper, accKeeper := initInfo.wasmKeeper, initInfo.stakingKeeper, initInfo.accKeeper
+
+	full := sdk.NewCoins(sdk.NewInt64Coin("stake", 160000))
+	funds := sdk.NewCoins(sdk.NewInt64Coin("stake", 80000))
+	bob, privBob := createFakeFundedAccount(ctx, accKeeper, full)
+
+	bond := StakingHandleMsg{
+		Bond: &struct{}{},
+	}
+	bondBz, err := json.Marshal(bond)
+	require.NoError(t, err)
+	bondBz, err = testEncrypt(t, keeper, ctx, contractAddr, 0, bondBz)
+	require.NoError(t, err)
+	ctx = PrepareExecSignedTx(t, keeper, ctx, bob, privBob, bondBz, contractAddr, funds)
+	_, err = keeper.Execute(ctx, contractAddr, bob, bondBz, funds, nil)
+	require.NoError(t, err)
+
+	// the contract reads its own delegation with a staking query
+	query := StakingQueryMsg{Investment: &struct{}{}}
+	queryBz, err := json.Marshal(query)
+	require.NoError(t, err)
+	res, qErr := queryHelper(t, keeper, ctx, contractAddr, string(queryBz), true, defaultGasForTests)
+	require.Empty(t, qErr)
+	var invest InvestmentResponse
+	err = json.Unmarshal([]byte(res), &invest)
+	require.NoError(t, err)
+
+	// the CLI reads it through the querier of the staking module
+	params := staking.NewQueryBondsParams(contractAddr, valAddr)
+	req := abci.RequestQuery{Data: staking.ModuleCdc.MustMarshalJSON(params)}
+	cliRes, err := staking.NewQuerier(stakingKeeper)(ctx, []string{staking.QueryDelegation}, req)
+	require.NoError(t, err)
+	var delegation staking.DelegationResponse
+	staking.ModuleCdc.MustUnmarshalJSON(cliRes, &delegation)
+
+	assert.Equal(t, delegation.Balance, invest.StakedTokens)
+	assert.Equal(t, sdk.NewInt64Coin("stake", 80000), invest.StakedTokens)
+}
+
 func TestUnbonding(t *testing.T) {
 	initInfo := initializeStaking(t)
 	defer initInfo.cleanup()
@@ -195,7 +195,7 @@ func CreateTestInput(t *testing.T, isCheckTx bool, tempDir string, supportedFeat
 
 	mintKeeper := mint.NewKeeper(cdc, mintStore, paramsKeeper.Subspace(mint.DefaultParamspace), stakingKeeper, supplyKeeper, auth.FeeCollectorName)
 	mintKeeper.SetMinter(ctx, mint.DefaultInitialMinter())
-	keeper := NewKeeper(cdc, keyContract, accountKeeper, &bk, &govKeeper, &distKeeper, &mintKeeper, &stakingKeeper, router, tempDir, wasmConfig, supportedFeatures, encoders, queriers)
+	keeper := NewKeeper(cdc, keyContract, paramsKeeper.Subspace(wasmtypes.DefaultParamspace), accountKeeper, &bk, &govKeeper, &distKeeper, &mintKeeper, &stakingKeeper, router, tempDir, wasmConfig, supportedFeatures, encoders, queriers)
 	// add wasm handler so we can loop-back (contracts calling contracts)
 	router.AddRoute(wasmtypes.RouterKey, TestHandler(keeper))
 
@@ -236,7 +236,7 @@ func TestHandler(k Keeper) sdk.Handler {
 }
 
 func handleInstantiate(ctx sdk.Context, k Keeper, msg *wasmtypes.MsgInstantiateContract) (*sdk.Result, error) {
-	contractAddr, err := k.Instantiate(ctx, msg.CodeID, msg.Sender /* msg.Admin, */, msg.InitMsg, msg.Label, msg.InitFunds, msg.CallbackSignature)
+	contractAddr, err := k.Instantiate(ctx, msg.CodeID, msg.Sender, msg.Admin, msg.InitMsg, msg.Label, msg.InitFunds, msg.CallbackSignature)
 	if err != nil {
 		return nil, err
 	}
@@ -258,12 +258,16 @@ func handleExecute(ctx sdk.Context, k Keeper, msg *wasmtypes.MsgExecuteContract)
 }
 
 func PrepareInitSignedTx(t *testing.T, keeper Keeper, ctx sdk.Context, creator sdk.AccAddress, privKey crypto.PrivKey, encMsg []byte, codeID uint64, funds sdk.Coins) sdk.Context {
+	return PrepareInitWithAdminSignedTx(t, keeper, ctx, creator, privKey, encMsg, codeID, funds, nil)
+}
+
+func PrepareInitWithAdminSignedTx(t *testing.T, keeper Keeper, ctx sdk.Context, creator sdk.AccAddress, privKey crypto.PrivKey, encMsg []byte, codeID uint64, funds sdk.Coins, admin sdk.AccAddress) sdk.Context {
 	creatorAcc, err := auth.GetSignerAcc(ctx, keeper.accountKeeper, creator)
 	require.NoError(t, err)
 
 	tx := authtypes.NewTestTx(ctx, []sdk.Msg{wasmtypes.MsgInstantiateContract{
-		Sender: creator,
-		// Admin:     nil,
+		Sender:    creator,
+		Admin:     admin,
 		CodeID:    codeID,
 		Label:     "demo contract 1",
 		InitMsg:   encMsg,
@@ -298,3 +302,70 @@ func PrepareExecSignedTx(t *testing.T, keeper Keeper, ctx sdk.Context, sender sd
 
 	return ctx.WithTxBytes(txBytes)
 }
+
+func PrepareRotateContractKeySignedTx(t *testing.T, keeper Keeper, ctx sdk.Context, sender sdk.AccAddress, privKey crypto.PrivKey, contract sdk.AccAddress, chunkSize uint32) sdk.Context {
+	senderAcc, err := auth.GetSignerAcc(ctx, keeper.accountKeeper, sender)
+	require.NoError(t, err)
+
+	tx := authtypes.NewTestTx(ctx, []sdk.Msg{wasmtypes.MsgRotateContractKey{
+		Sender:    sender,
+		Contract:  contract,
+		ChunkSize: chunkSize,
+	}}, []crypto.PrivKey{privKey}, []uint64{senderAcc.GetAccountNumber()}, []uint64{senderAcc.GetSequence() - 1}, authtypes.StdFee{
+		Amount: nil,
+		Gas:    0,
+	})
+
+	txBytes, err := keeper.cdc.MarshalBinaryLengthPrefixed(tx)
+	require.NoError(t, err)
+
+	return ctx.WithTxBytes(txBytes)
+}
+
+func PrepareMigrateSignedTx(t *testing.T, keeper Keeper, ctx sdk.Context, sender sdk.AccAddress, privKey crypto.PrivKey, encMsg []byte, contract sdk.AccAddress, codeID uint64) sdk.Context {
+	return prepareSignedTx(t, keeper, ctx, sender, privKey, wasmtypes.MsgMigrateContract{
+		Sender:     sender,
+		Contract:   contract,
+		CodeID:     codeID,
+		MigrateMsg: encMsg,
+	})
+}
+
+// PrepareUpdateAdminSignedTx signs a MsgUpdateAdmin to newAdmin, or a MsgClearAdmin if newAdmin is nil
+func PrepareUpdateAdminSignedTx(t *testing.T, keeper Keeper, ctx sdk.Context, sender sdk.AccAddress, privKey crypto.PrivKey, contract sdk.AccAddress, newAdmin sdk.AccAddress) sdk.Context {
+	if newAdmin == nil {
+		return prepareSignedTx(t, keeper, ctx, sender, privKey, wasmtypes.MsgClearAdmin{
+			Sender:   sender,
+			Contract: contract,
+		})
+	}
+	return prepareSignedTx(t, keeper, ctx, sender, privKey, wasmtypes.MsgUpdateAdmin{
+		Sender:   sender,
+		NewAdmin: newAdmin,
+		Contract: contract,
+	})
+}
+
+// PrepareImportContractStateSignedTx signs a MsgImportContractState of chunk
+func PrepareImportContractStateSignedTx(t *testing.T, keeper Keeper, ctx sdk.Context, sender sdk.AccAddress, privKey crypto.PrivKey, contract sdk.AccAddress, chunk []byte) sdk.Context {
+	return prepareSignedTx(t, keeper, ctx, sender, privKey, wasmtypes.MsgImportContractState{
+		Sender:   sender,
+		Contract: contract,
+		Chunk:    chunk,
+	})
+}
+
+func prepareSignedTx(t *testing.T, keeper Keeper, ctx sdk.Context, sender sdk.AccAddress, privKey crypto.PrivKey, msg sdk.Msg) sdk.Context {
+	senderAcc, err := auth.GetSignerAcc(ctx, keeper.accountKeeper, sender)
+	require.NoError(t, err)
+
+	tx := authtypes.NewTestTx(ctx, []sdk.Msg{msg}, []crypto.PrivKey{privKey}, []uint64{senderAcc.GetAccountNumber()}, []uint64{senderAcc.GetSequence() - 1}, authtypes.StdFee{
+		Amount: nil,
+		Gas:    0,
+	})
+
+	txBytes, err := keeper.cdc.MarshalBinaryLengthPrefixed(tx)
+	require.NoError(t, err)
+
+	return ctx.WithTxBytes(txBytes)
+}
@@ -1,9 +1,11 @@
 use cosmwasm_storage::PrefixedStorage;
 
 use cosmwasm_std::{
-    log, to_binary, Api, BankMsg, Binary, Coin, CosmosMsg, Env, Extern, HandleResponse,
-    HandleResult, HumanAddr, InitResponse, InitResult, Querier, QueryRequest, QueryResult,
-    ReadonlyStorage, StdError, StdResult, Storage, Uint128, WasmMsg, WasmQuery,
+    log, plaintext_log, to_binary, Api, BankMsg, Binary, CanonicalAddr, Coin, CosmosMsg, Env,
+    Extern, HandleResponse, HandleResult, HumanAddr, IbcPacket, IbcReceiveResponse,
+    IbcReceiveResult, InitResponse, InitResult, MigrateResponse, MigrateResult, Querier,
+    QueryRequest, QueryResult, ReadonlyStorage, Reply, ReplyOn, StdError, StdResult, Storage,
+    SubMsg, SubMsgExecutionResponse, SubMsgResult, Uint128, WasmMsg, WasmQuery,
 };
 
 /////////////////////////////// Messages ///////////////////////////////
@@ -113,9 +115,19 @@ pub enum HandleMsg {
     GetState {
         key: String,
     },
+    GetStateMulti {
+        keys: Vec<String>,
+        batched: bool,
+    },
     RemoveState {
         key: String,
     },
+    WriteItems {
+        count: u32,
+    },
+    ReadItems {
+        indexes: Vec<u32>,
+    },
     TestCanonicalizeAddressErrors {},
     Panic {},
     AllocateOnHeap {
@@ -201,6 +213,40 @@ pub enum HandleMsg {
         code_hash: String,
         msg: String,
     },
+    InstantiateViaSubmsg {
+        code_id: u64,
+        code_hash: String,
+    },
+    FailingSubmsg {
+        code_hash: String,
+    },
+    SetStateAndFail {
+        key: String,
+        value: String,
+    },
+    AddrRoundTrip {},
+    AddrValidate {
+        addr: String,
+    },
+    TransferWithPublicAction {
+        amount: u64,
+    },
+    AssertCodeHash {
+        contract_addr: HumanAddr,
+        code_hash: String,
+    },
+    FillStorage {
+        value_len: u32,
+        max_entries: u32,
+    },
+}
+
+/// How far `HandleMsg::FillStorage` got: the entries it wrote before a write failed, if one did
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FillStorageResponse {
+    pub written: u32,
+    pub usage: u64,
+    pub error: String,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -234,6 +280,15 @@ pub enum QueryMsg {
         code_hash: String,
         msg: String,
     },
+    BlockHeight {},
+    BlockHash {},
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MigrateMsg {
+    /// Moves the value of a key that was set before the migration to another key
+    MoveState { from: String, to: String },
 }
 
 /////////////////////////////// Init ///////////////////////////////
@@ -245,6 +300,7 @@ pub fn init<S: Storage, A: Api, Q: Querier>(
 ) -> InitResult {
     match msg {
         InitMsg::Nop {} => Ok(InitResponse {
+            submessages: vec![],
             messages: vec![],
             log: vec![log("init", "🌈")],
         }),
@@ -271,6 +327,7 @@ pub fn init<S: Storage, A: Api, Q: Querier>(
             depth,
             code_hash,
         } => Ok(InitResponse {
+            submessages: vec![],
             messages: vec![],
             log: vec![log(
                 format!(
@@ -285,6 +342,7 @@ pub fn init<S: Storage, A: Api, Q: Querier>(
             depth,
             code_hash,
         } => Ok(InitResponse {
+            submessages: vec![],
             messages: vec![],
             log: vec![log(
                 "message",
@@ -297,6 +355,7 @@ pub fn init<S: Storage, A: Api, Q: Querier>(
             label,
             msg,
         } => Ok(InitResponse {
+            submessages: vec![],
             messages: vec![CosmosMsg::Wasm(WasmMsg::Instantiate {
                 code_id,
                 callback_code_hash: code_hash,
@@ -311,6 +370,7 @@ pub fn init<S: Storage, A: Api, Q: Querier>(
             code_hash,
             msg,
         } => Ok(InitResponse {
+            submessages: vec![],
             messages: vec![CosmosMsg::Wasm(WasmMsg::Execute {
                 contract_addr: addr,
                 callback_code_hash: code_hash,
@@ -336,6 +396,7 @@ pub fn init<S: Storage, A: Api, Q: Querier>(
                 })?;
 
             Ok(InitResponse {
+                submessages: vec![],
                 messages: vec![],
                 log: vec![log("c", format!("{}", answer))],
             })
@@ -360,6 +421,7 @@ fn map_string_to_error(error_type: String) -> StdError {
 
 fn init_with_callback_contract_error(contract_addr: HumanAddr, code_hash: String) -> InitResponse {
     InitResponse {
+        submessages: vec![],
         messages: vec![CosmosMsg::Wasm(WasmMsg::Execute {
             contract_addr: contract_addr.clone(),
             callback_code_hash: code_hash,
@@ -372,6 +434,7 @@ fn init_with_callback_contract_error(contract_addr: HumanAddr, code_hash: String
 
 fn init_callback_bad_params(contract_addr: HumanAddr, code_hash: String) -> InitResponse {
     InitResponse {
+        submessages: vec![],
         messages: vec![CosmosMsg::Wasm(WasmMsg::Execute {
             contract_addr: contract_addr.clone(),
             callback_code_hash: code_hash,
@@ -389,6 +452,7 @@ fn init_with_callback<S: Storage, A: Api, Q: Querier>(
     code_hash: String,
 ) -> InitResponse {
     InitResponse {
+        submessages: vec![],
         messages: vec![CosmosMsg::Wasm(WasmMsg::Execute {
             callback_code_hash: code_hash,
             contract_addr: contract_addr.clone(),
@@ -406,6 +470,7 @@ pub fn init_callback_to_init<S: Storage, A: Api, Q: Querier>(
     code_hash: String,
 ) -> InitResponse {
     InitResponse {
+        submessages: vec![],
         messages: vec![CosmosMsg::Wasm(WasmMsg::Instantiate {
             code_id,
             msg: Binary::from("{\"nop\":{}}".as_bytes().to_vec()),
@@ -457,7 +522,10 @@ pub fn handle<S: Storage, A: Api, Q: Querier>(
         } => Ok(exec_with_callback_contract_error(contract_addr, code_hash)),
         HandleMsg::SetState { key, value } => Ok(set_state(deps, key, value)),
         HandleMsg::GetState { key } => Ok(get_state(deps, key)),
+        HandleMsg::GetStateMulti { keys, batched } => get_state_multi(deps, keys, batched),
         HandleMsg::RemoveState { key } => Ok(remove_state(deps, key)),
+        HandleMsg::WriteItems { count } => Ok(write_items(deps, count)),
+        HandleMsg::ReadItems { indexes } => read_items(deps, indexes),
         HandleMsg::TestCanonicalizeAddressErrors {} => test_canonicalize_address_errors(deps),
         HandleMsg::Panic {} => panic!("panic in exec"),
         HandleMsg::AllocateOnHeap { bytes } => Ok(allocate_on_heap(bytes as usize)),
@@ -465,6 +533,7 @@ pub fn handle<S: Storage, A: Api, Q: Querier>(
             Ok(pass_null_pointer_to_imports_should_throw(deps, pass_type))
         }
         HandleMsg::SendExternalQuery { to, code_hash } => Ok(HandleResponse {
+            submessages: vec![],
             messages: vec![],
             log: vec![],
             data: Some(vec![send_external_query(deps, to, code_hash)].into()),
@@ -474,6 +543,7 @@ pub fn handle<S: Storage, A: Api, Q: Querier>(
             code_hash,
             depth,
         } => Ok(HandleResponse {
+            submessages: vec![],
             messages: vec![],
             log: vec![],
             data: Some(
@@ -488,6 +558,7 @@ pub fn handle<S: Storage, A: Api, Q: Querier>(
             code_hash,
             depth,
         } => Ok(HandleResponse {
+            submessages: vec![],
             messages: vec![],
             log: vec![],
             data: Some(to_binary(&send_external_query_recursion_limit(
@@ -507,11 +578,13 @@ pub fn handle<S: Storage, A: Api, Q: Querier>(
             send_external_query_bad_abi_receiver(deps, to, code_hash)
         }
         HandleMsg::LogMsgSender {} => Ok(HandleResponse {
+            submessages: vec![],
             messages: vec![],
             log: vec![log("msg.sender", env.message.sender.to_string())],
             data: None,
         }),
         HandleMsg::CallbackToLogMsgSender { to, code_hash } => Ok(HandleResponse {
+            submessages: vec![],
             messages: vec![CosmosMsg::Wasm(WasmMsg::Execute {
                 contract_addr: to.clone(),
                 callback_code_hash: code_hash,
@@ -522,6 +595,7 @@ pub fn handle<S: Storage, A: Api, Q: Querier>(
             data: None,
         }),
         HandleMsg::DepositToContract {} => Ok(HandleResponse {
+            submessages: vec![],
             messages: vec![],
             log: vec![],
             data: Some(to_binary(&env.message.sent_funds).unwrap()),
@@ -532,6 +606,7 @@ pub fn handle<S: Storage, A: Api, Q: Querier>(
             to,
             denom,
         } => Ok(HandleResponse {
+            submessages: vec![],
             messages: vec![CosmosMsg::Bank(BankMsg::Send {
                 from_address: from,
                 to_address: to,
@@ -549,6 +624,7 @@ pub fn handle<S: Storage, A: Api, Q: Querier>(
             code_id,
             code_hash,
         } => Ok(HandleResponse {
+            submessages: vec![],
             messages: vec![CosmosMsg::Wasm(WasmMsg::Instantiate {
                 msg: Binary("{\"nop\":{}}".as_bytes().to_vec()),
                 code_id: code_id,
@@ -568,6 +644,7 @@ pub fn handle<S: Storage, A: Api, Q: Querier>(
             to,
             code_hash,
         } => Ok(HandleResponse {
+            submessages: vec![],
             messages: vec![CosmosMsg::Wasm(WasmMsg::Execute {
                 msg: Binary("{\"no_data\":{}}".as_bytes().to_vec()),
                 contract_addr: to,
@@ -584,12 +661,14 @@ pub fn handle<S: Storage, A: Api, Q: Querier>(
             thread::sleep(time::Duration::from_millis(ms));
 
             Ok(HandleResponse {
+                submessages: vec![],
                 messages: vec![],
                 log: vec![],
                 data: None,
             })
         }
         HandleMsg::WithFloats { x, y } => Ok(HandleResponse {
+            submessages: vec![],
             messages: vec![],
             log: vec![],
             data: Some(use_floats(x, y)),
@@ -600,6 +679,7 @@ pub fn handle<S: Storage, A: Api, Q: Querier>(
             label,
             msg,
         } => Ok(HandleResponse {
+            submessages: vec![],
             messages: vec![CosmosMsg::Wasm(WasmMsg::Instantiate {
                 code_id,
                 callback_code_hash: code_hash,
@@ -615,6 +695,7 @@ pub fn handle<S: Storage, A: Api, Q: Querier>(
             code_hash,
             msg,
         } => Ok(HandleResponse {
+            submessages: vec![],
             messages: vec![CosmosMsg::Wasm(WasmMsg::Execute {
                 contract_addr: addr,
                 callback_code_hash: code_hash,
@@ -641,12 +722,235 @@ pub fn handle<S: Storage, A: Api, Q: Querier>(
                 })?;
 
             Ok(HandleResponse {
+                submessages: vec![],
                 messages: vec![],
                 log: vec![log("c", format!("{}", answer))],
                 data: None,
             })
         }
+        HandleMsg::InstantiateViaSubmsg { code_id, code_hash } => Ok(HandleResponse {
+            submessages: vec![SubMsg {
+                id: INSTANTIATE_REPLY_ID,
+                msg: CosmosMsg::Wasm(WasmMsg::Instantiate {
+                    code_id,
+                    msg: Binary::from("{\"nop\":{}}".as_bytes().to_vec()),
+                    callback_code_hash: code_hash,
+                    send: vec![],
+                    label: String::from("instantiated via submsg"),
+                }),
+                gas_limit: None,
+                reply_on: ReplyOn::Success,
+            }],
+            messages: vec![],
+            log: vec![],
+            data: None,
+        }),
+        HandleMsg::FailingSubmsg { code_hash } => Ok(failing_submsg(deps, env, code_hash)),
+        HandleMsg::SetStateAndFail { key, value } => {
+            set_state(deps, key, value);
+            Err(StdError::generic_err("failed after writing"))
+        }
+        HandleMsg::AddrRoundTrip {} => addr_round_trip(deps, env),
+        HandleMsg::AddrValidate { addr } => {
+            deps.api.addr_validate(&addr)?;
+            Ok(HandleResponse::default())
+        }
+        HandleMsg::TransferWithPublicAction { amount } => Ok(HandleResponse {
+            submessages: vec![],
+            messages: vec![],
+            log: vec![plaintext_log("action", "transfer"), log("amount", amount)],
+            data: None,
+        }),
+        HandleMsg::AssertCodeHash {
+            contract_addr,
+            code_hash,
+        } => Ok(HandleResponse {
+            submessages: vec![],
+            messages: vec![],
+            log: vec![],
+            data: Some(to_binary(&assert_code_hash(&contract_addr, &code_hash))?),
+        }),
+        HandleMsg::FillStorage {
+            value_len,
+            max_entries,
+        } => Ok(HandleResponse {
+            submessages: vec![],
+            messages: vec![],
+            log: vec![],
+            data: Some(to_binary(&fill_storage(value_len, max_entries))?),
+        }),
+    }
+}
+
+/// Write values of `value_len` bytes to the keys 0, 1, 2... as big endian u32, until a write
+/// fails or `max_entries` were written
+#[cfg(target_arch = "wasm32")]
+fn fill_storage(value_len: u32, max_entries: u32) -> FillStorageResponse {
+    let mut storage = cosmwasm_std::ExternalStorage::new();
+    let value = vec![0xAA; value_len as usize];
+
+    let mut written = 0;
+    let mut error = String::new();
+    while written < max_entries {
+        if let Err(err) = storage.try_set(&written.to_be_bytes(), &value) {
+            error = err.to_string();
+            break;
+        }
+        written += 1;
+    }
+
+    FillStorageResponse {
+        written,
+        usage: cosmwasm_std::ExternalStorageUsage::bytes(),
+        error,
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn fill_storage(_value_len: u32, _max_entries: u32) -> FillStorageResponse {
+    FillStorageResponse {
+        written: 0,
+        usage: 0,
+        error: String::new(),
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn assert_code_hash(contract_addr: &HumanAddr, code_hash: &str) -> bool {
+    cosmwasm_std::ExternalContracts::assert_code_hash(contract_addr, code_hash)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn assert_code_hash(_contract_addr: &HumanAddr, _code_hash: &str) -> bool {
+    false
+}
+
+fn addr_round_trip<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    env: Env,
+) -> HandleResult {
+    let sender = deps.api.addr_validate(env.message.sender.as_str())?;
+    let canonical = deps.api.addr_canonicalize(sender.as_str())?;
+    let human = deps.api.addr_humanize(&canonical)?;
+
+    if human != env.message.sender {
+        return Err(StdError::generic_err(format!(
+            "sender {} came back as {}",
+            env.message.sender, human
+        )));
     }
+
+    Ok(HandleResponse {
+        submessages: vec![],
+        messages: vec![],
+        log: vec![],
+        data: Some(Binary::from(human.as_str().as_bytes().to_vec())),
+    })
+}
+
+/////////////////////////////// Reply ///////////////////////////////
+
+const INSTANTIATE_REPLY_ID: u64 = 1;
+const FAILING_REPLY_ID: u64 = 2;
+
+/// Write to storage, then execute a submessage that writes and fails, with a reply on its error
+fn failing_submsg<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    code_hash: String,
+) -> HandleResponse {
+    set_state(deps, String::from("parent"), String::from("written"));
+
+    HandleResponse {
+        submessages: vec![SubMsg {
+            id: FAILING_REPLY_ID,
+            msg: CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: env.contract.address,
+                callback_code_hash: code_hash,
+                msg: Binary::from(
+                    r#"{"set_state_and_fail":{"key":"child","value":"written"}}"#.as_bytes(),
+                ),
+                send: vec![],
+            }),
+            gas_limit: None,
+            reply_on: ReplyOn::Error,
+        }],
+        messages: vec![],
+        log: vec![],
+        data: None,
+    }
+}
+
+pub fn reply<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    _env: Env,
+    reply: Reply,
+) -> HandleResult {
+    match (reply.id, reply.result) {
+        (INSTANTIATE_REPLY_ID, SubMsgResult::Ok(SubMsgExecutionResponse { data: Some(data) })) => {
+            // The data of an instantiation is the address of the new contract
+            let address = deps.api.human_address(&CanonicalAddr(data))?;
+
+            let mut store = PrefixedStorage::new(b"my_prefix", &mut deps.storage);
+            store.set(b"instantiated_address", address.as_str().as_bytes());
+
+            Ok(HandleResponse::default())
+        }
+        (FAILING_REPLY_ID, SubMsgResult::Error(_)) => {
+            set_state(deps, String::from("replied"), String::from("written"));
+
+            Ok(HandleResponse {
+                data: Some(Binary::from("replied".as_bytes())),
+                ..HandleResponse::default()
+            })
+        }
+        (id, result) => Err(StdError::generic_err(format!(
+            "unexpected reply {}: {:?}",
+            id, result
+        ))),
+    }
+}
+
+pub fn migrate<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    _env: Env,
+    msg: MigrateMsg,
+) -> MigrateResult {
+    match msg {
+        MigrateMsg::MoveState { from, to } => {
+            let mut store = PrefixedStorage::new(b"my_prefix", &mut deps.storage);
+            let value = store
+                .get(from.as_bytes())
+                .ok_or_else(|| StdError::not_found(from.clone()))?;
+            store.set(to.as_bytes(), &value);
+            store.remove(from.as_bytes());
+
+            Ok(MigrateResponse {
+                messages: vec![],
+                log: vec![log("moved", to)],
+                data: Some(Binary(value)),
+            })
+        }
+    }
+}
+
+/// Stores the data of the packet, and acknowledges it with the data prefixed by "ack:"
+pub fn ibc_packet_receive<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    _env: Env,
+    packet: IbcPacket,
+) -> IbcReceiveResult {
+    let mut store = PrefixedStorage::new(b"my_prefix", &mut deps.storage);
+    store.set(b"ibc_packet_data", packet.data.as_slice());
+
+    let mut acknowledgement = b"ack:".to_vec();
+    acknowledgement.extend_from_slice(packet.data.as_slice());
+
+    Ok(IbcReceiveResponse {
+        acknowledgement: Binary(acknowledgement),
+        messages: vec![],
+        log: vec![log("ibc_packet_sequence", packet.sequence)],
+    })
 }
 
 #[cfg(feature = "with_floats")]
@@ -728,8 +1032,8 @@ fn send_external_query_recursion_limit<S: Storage, A: Api, Q: Querier>(
             ),
         }));
 
-    // 5 is the current recursion limit.
-    if depth != 5 {
+    // 10 is the current recursion limit.
+    if depth != 10 {
         result
     } else {
         match result {
@@ -781,6 +1085,7 @@ fn send_external_query_stderror<S: Storage, A: Api, Q: Querier>(
 
     match answer {
         Ok(wtf) => Ok(HandleResponse {
+            submessages: vec![],
             messages: vec![],
             log: vec![],
             data: Some(wtf),
@@ -806,6 +1111,7 @@ fn send_external_query_bad_abi<S: Storage, A: Api, Q: Querier>(
 
     match answer {
         Ok(wtf) => Ok(HandleResponse {
+            submessages: vec![],
             messages: vec![],
             log: vec![],
             data: Some(wtf),
@@ -829,6 +1135,7 @@ fn send_external_query_bad_abi_receiver<S: Storage, A: Api, Q: Querier>(
 
     match answer {
         Ok(wtf) => Ok(HandleResponse {
+            submessages: vec![],
             messages: vec![],
             log: vec![log("wtf", wtf)],
             data: None,
@@ -839,6 +1146,7 @@ fn send_external_query_bad_abi_receiver<S: Storage, A: Api, Q: Querier>(
 
 fn exec_callback_bad_params(contract_addr: HumanAddr, code_hash: String) -> HandleResponse {
     HandleResponse {
+        submessages: vec![],
         messages: vec![CosmosMsg::Wasm(WasmMsg::Execute {
             contract_addr: contract_addr.clone(),
             callback_code_hash: code_hash,
@@ -859,6 +1167,7 @@ pub fn a<S: Storage, A: Api, Q: Querier>(
     y: u8,
 ) -> HandleResponse {
     HandleResponse {
+        submessages: vec![],
         messages: vec![CosmosMsg::Wasm(WasmMsg::Execute {
             contract_addr: contract_addr.clone(),
             callback_code_hash: code_hash.clone(),
@@ -887,6 +1196,7 @@ pub fn b<S: Storage, A: Api, Q: Querier>(
     y: u8,
 ) -> HandleResponse {
     HandleResponse {
+        submessages: vec![],
         messages: vec![CosmosMsg::Wasm(WasmMsg::Execute {
             contract_addr: contract_addr.clone(),
             callback_code_hash: code_hash,
@@ -909,6 +1219,7 @@ pub fn c<S: Storage, A: Api, Q: Querier>(
     y: u8,
 ) -> HandleResponse {
     HandleResponse {
+        submessages: vec![],
         messages: vec![],
         log: vec![log("watermelon", "🍉")],
         data: Some(Binary(vec![x + y])),
@@ -920,6 +1231,7 @@ pub fn empty_log_key_value<S: Storage, A: Api, Q: Querier>(
     _env: Env,
 ) -> HandleResponse {
     HandleResponse {
+        submessages: vec![],
         messages: vec![],
         log: vec![log("my value is empty", ""), log("", "my key is empty")],
         data: None,
@@ -931,6 +1243,7 @@ pub fn empty_data<S: Storage, A: Api, Q: Querier>(
     _env: Env,
 ) -> HandleResponse {
     HandleResponse {
+        submessages: vec![],
         messages: vec![],
         log: vec![],
         data: Some(Binary(vec![])),
@@ -942,6 +1255,7 @@ pub fn unicode_data<S: Storage, A: Api, Q: Querier>(
     _env: Env,
 ) -> HandleResponse {
     HandleResponse {
+        submessages: vec![],
         messages: vec![],
         log: vec![],
         data: Some(Binary("🍆🥑🍄".as_bytes().to_vec())),
@@ -953,6 +1267,7 @@ pub fn no_data<S: Storage, A: Api, Q: Querier>(
     _env: Env,
 ) -> HandleResponse {
     HandleResponse {
+        submessages: vec![],
         messages: vec![],
         log: vec![],
         data: None,
@@ -966,6 +1281,7 @@ pub fn exec_callback_to_init<S: Storage, A: Api, Q: Querier>(
     code_hash: String,
 ) -> HandleResponse {
     HandleResponse {
+        submessages: vec![],
         messages: vec![CosmosMsg::Wasm(WasmMsg::Instantiate {
             code_id,
             msg: Binary::from("{\"nop\":{}}".as_bytes().to_vec()),
@@ -983,6 +1299,7 @@ fn exec_with_callback_contract_error(
     code_hash: String,
 ) -> HandleResponse {
     HandleResponse {
+        submessages: vec![],
         messages: vec![CosmosMsg::Wasm(WasmMsg::Execute {
             contract_addr: contract_addr.clone(),
             callback_code_hash: code_hash,
@@ -1006,6 +1323,7 @@ fn allocate_on_heap(bytes: usize) -> HandleResponse {
         data: Some(Binary("😅".as_bytes().to_vec())),
         log: vec![],
         messages: vec![],
+        submessages: vec![],
     }
 }
 
@@ -1020,11 +1338,38 @@ fn get_state<S: Storage, A: Api, Q: Querier>(
             data: Some(Binary(value)),
             log: vec![],
             messages: vec![],
+            submessages: vec![],
         },
         None => HandleResponse::default(),
     }
 }
 
+fn get_state_multi<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    keys: Vec<String>,
+    batched: bool,
+) -> HandleResult {
+    let store = PrefixedStorage::new(b"my_prefix", &mut deps.storage);
+    let keys: Vec<&[u8]> = keys.iter().map(|key| key.as_bytes()).collect();
+
+    let values = if batched {
+        store.get_multi(&keys)
+    } else {
+        keys.iter().map(|key| store.get(key)).collect()
+    };
+    let values: Vec<Option<String>> = values
+        .into_iter()
+        .map(|value| value.map(|value| String::from_utf8(value).unwrap()))
+        .collect();
+
+    Ok(HandleResponse {
+        data: Some(to_binary(&values)?),
+        log: vec![],
+        messages: vec![],
+        submessages: vec![],
+    })
+}
+
 fn set_state<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     key: String,
@@ -1044,6 +1389,39 @@ fn remove_state<S: Storage, A: Api, Q: Querier>(
     HandleResponse::default()
 }
 
+fn write_items<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    count: u32,
+) -> HandleResponse {
+    let mut store = PrefixedStorage::new(b"items", &mut deps.storage);
+    for index in 0..count {
+        store.set(&index.to_be_bytes(), format!("item {}", index).as_bytes());
+    }
+    HandleResponse::default()
+}
+
+fn read_items<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    indexes: Vec<u32>,
+) -> HandleResult {
+    let store = PrefixedStorage::new(b"items", &mut deps.storage);
+    let values: Vec<Option<String>> = indexes
+        .iter()
+        .map(|index| {
+            store
+                .get(&index.to_be_bytes())
+                .map(|value| String::from_utf8(value).unwrap())
+        })
+        .collect();
+
+    Ok(HandleResponse {
+        data: Some(to_binary(&values)?),
+        log: vec![],
+        messages: vec![],
+        submessages: vec![],
+    })
+}
+
 #[allow(invalid_value)]
 #[allow(unused_must_use)]
 fn pass_null_pointer_to_imports_should_throw<S: Storage, A: Api, Q: Querier>(
@@ -1154,6 +1532,7 @@ fn test_canonicalize_address_errors<S: Storage, A: Api, Q: Querier>(
         data: Some(Binary("🤟".as_bytes().to_vec())),
         log: vec![],
         messages: vec![],
+        submessages: vec![],
     })
 }
 
@@ -1161,9 +1540,12 @@ fn test_canonicalize_address_errors<S: Storage, A: Api, Q: Querier>(
 
 pub fn query<S: Storage, A: Api, Q: Querier>(
     deps: &Extern<S, A, Q>,
+    env: Env,
     _msg: QueryMsg,
 ) -> QueryResult {
     match _msg {
+        QueryMsg::BlockHeight {} => to_binary(&env.block.height),
+        QueryMsg::BlockHash {} => to_binary(&env.block.hash),
         QueryMsg::ContractError { error_type } => Err(map_string_to_error(error_type)),
         QueryMsg::Panic {} => panic!("panic in query"),
         QueryMsg::ReceiveExternalQuery { num } => {
@@ -4,7 +4,8 @@ pub mod contract;
 mod wasm {
     use super::contract;
     use cosmwasm_std::{
-        do_handle, do_init, do_query, ExternalApi, ExternalQuerier, ExternalStorage,
+        do_handle, do_ibc_packet_receive, do_init, do_migrate, do_query_with_env, do_reply,
+        ExternalApi, ExternalQuerier, ExternalStorage,
     };
 
     #[no_mangle]
@@ -26,9 +27,37 @@ mod wasm {
     }
 
     #[no_mangle]
-    extern "C" fn query(msg_ptr: u32) -> u32 {
-        do_query(
+    extern "C" fn query(env_ptr: u32, msg_ptr: u32) -> u32 {
+        do_query_with_env(
             &contract::query::<ExternalStorage, ExternalApi, ExternalQuerier>,
+            env_ptr,
+            msg_ptr,
+        )
+    }
+
+    #[no_mangle]
+    extern "C" fn migrate(env_ptr: u32, msg_ptr: u32) -> u32 {
+        do_migrate(
+            &contract::migrate::<ExternalStorage, ExternalApi, ExternalQuerier>,
+            env_ptr,
+            msg_ptr,
+        )
+    }
+
+    #[no_mangle]
+    extern "C" fn reply(env_ptr: u32, msg_ptr: u32) -> u32 {
+        do_reply(
+            &contract::reply::<ExternalStorage, ExternalApi, ExternalQuerier>,
+            env_ptr,
+            msg_ptr,
+        )
+    }
+
+    #[no_mangle]
+    extern "C" fn ibc_packet_receive(env_ptr: u32, msg_ptr: u32) -> u32 {
+        do_ibc_packet_receive(
+            &contract::ibc_packet_receive::<ExternalStorage, ExternalApi, ExternalQuerier>,
+            env_ptr,
             msg_ptr,
         )
     }
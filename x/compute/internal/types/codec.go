@@ -10,11 +10,14 @@ func RegisterCodec(cdc *codec.Codec) {
 	cdc.RegisterConcrete(MsgStoreCode{}, "wasm/MsgStoreCode", nil)
 	cdc.RegisterConcrete(MsgInstantiateContract{}, "wasm/MsgInstantiateContract", nil)
 	cdc.RegisterConcrete(MsgExecuteContract{}, "wasm/MsgExecuteContract", nil)
+	cdc.RegisterConcrete(MsgRotateContractKey{}, "wasm/MsgRotateContractKey", nil)
+	cdc.RegisterConcrete(MsgMigrateContract{}, "wasm/MsgMigrateContract", nil)
+	cdc.RegisterConcrete(MsgUpdateAdmin{}, "wasm/MsgUpdateAdmin", nil)
+	cdc.RegisterConcrete(MsgClearAdmin{}, "wasm/MsgClearAdmin", nil)
+	cdc.RegisterConcrete(MsgImportContractState{}, "wasm/MsgImportContractState", nil)
+	cdc.RegisterConcrete(MsgSubmitForeignHeader{}, "wasm/MsgSubmitForeignHeader", nil)
+	cdc.RegisterConcrete(TrackForeignChainProposal{}, "wasm/TrackForeignChainProposal", nil)
 	/*
-		cdc.RegisterConcrete(MsgMigrateContract{}, "wasm/MsgMigrateContract", nil)
-		cdc.RegisterConcrete(MsgUpdateAdmin{}, "wasm/MsgUpdateAdmin", nil)
-		cdc.RegisterConcrete(MsgClearAdmin{}, "wasm/MsgClearAdmin", nil)
-
 		cdc.RegisterConcrete(StoreCodeProposal{}, "wasm/StoreCodeProposal", nil)
 		cdc.RegisterConcrete(InstantiateContractProposal{}, "wasm/InstantiateContractProposal", nil)
 		cdc.RegisterConcrete(MigrateContractProposal{}, "wasm/MigrateContractProposal", nil)
@@ -54,10 +54,28 @@ var (
 
 	// ErrCreateFailed error for wasm code that has already been uploaded or failed
 	ErrCreateFailed = sdkErrors.Register(DefaultCodespace, 15, "create contract failed")
+
+	// ErrReplyFailed error for rust reply contract failure
+	ErrReplyFailed = sdkErrors.Register(DefaultCodespace, 16, "reply contract failed")
+
+	// ErrKeyRotationFailed error for a contract key rotation the enclave refused or failed
+	ErrKeyRotationFailed = sdkErrors.Register(DefaultCodespace, 17, "rotate contract key failed")
+
+	// ErrIBCFailed error for rust failure in one of the IBC entry points of a contract
+	ErrIBCFailed = sdkErrors.Register(DefaultCodespace, 18, "ibc contract call failed")
+
+	// ErrStateExportFailed error for an export of contract state the enclave refused or failed
+	ErrStateExportFailed = sdkErrors.Register(DefaultCodespace, 19, "export contract state failed")
+
+	// ErrStateImportFailed error for an import of contract state the enclave refused or failed
+	ErrStateImportFailed = sdkErrors.Register(DefaultCodespace, 20, "import contract state failed")
+
+	// ErrForeignChainFailed error for a header of another chain, or a chain to track, the enclave refused
+	ErrForeignChainFailed = sdkErrors.Register(DefaultCodespace, 21, "foreign chain failed")
 )
 
 func IsEncryptedErrorCode(code uint32) bool {
-	return 5 >= code && code > 1
+	return (5 >= code && code > 1) || code == 16
 }
 
 func ErrContainsQueryError(err error) bool {
@@ -74,3 +92,9 @@ func ContainsEnclaveError(str string) bool {
 func ContainsEncryptedString(str string) bool {
 	return strings.Contains(str, "encrypted: ")
 }
+
+// ContainsInvalidPointerError is true if the enclave refused a call because the node passed it a
+// pointer outside of the memory of the node. This is a bug in the node, not in the contract.
+func ContainsInvalidPointerError(str string) bool {
+	return strings.Contains(str, "the host passed an invalid pointer")
+}
@@ -0,0 +1,31 @@
+package types
+
+import (
+	"testing"
+
+	"github.com/stretchr/testify/assert"
+)
+
+func TestContainsInvalidPointerError(t *testing.T) {
+	specs := map[string]struct {
+		src string
+		exp bool
+	}{
+		"contract": {src: "the host passed an invalid pointer to the contract", exp: true},
+		"env":      {src: "the host passed an invalid pointer to the env", exp: true},
+		"msg":      {src: "the host passed an invalid pointer to the message", exp: true},
+		"sig info": {src: "the host passed an invalid pointer to the signature info", exp: true},
+		"used gas": {src: "the host passed an invalid pointer to the used gas", exp: true},
+		"wrapped": {
+			src: "execute contract failed: Enclave: the host passed an invalid pointer to the env",
+			exp: true,
+		},
+		"contract failure": {src: "calling a function in the contract failed for an unexpected reason"},
+		"empty":            {src: ""},
+	}
+	for msg, spec := range specs {
+		t.Run(msg, func(t *testing.T) {
+			assert.Equal(t, spec.exp, ContainsInvalidPointerError(spec.src))
+		})
+	}
+}
@@ -0,0 +1,161 @@
+package types
+
+import (
+	"fmt"
+
+	wasmTypes "github.com/enigmampc/SecretNetwork/go-cosmwasm/types"
+	sdk "github.com/enigmampc/cosmos-sdk/types"
+	sdkerrors "github.com/enigmampc/cosmos-sdk/types/errors"
+	govtypes "github.com/enigmampc/cosmos-sdk/x/gov/types"
+	tmtypes "github.com/tendermint/tendermint/types"
+)
+
+// MaxForeignChains is the most chains the chain tracks the headers of. What the enclave trusts
+// about each of them is passed to every contract call, and the enclave refuses an env with more.
+const MaxForeignChains = 32
+
+// maxChainIDLen is Tendermint's MaxChainIDLen
+const maxChainIDLen = 50
+
+// ProposalTypeTrackForeignChain is the type of TrackForeignChainProposal
+const ProposalTypeTrackForeignChain = "TrackForeignChain"
+
+func init() { // register new content types with the sdk
+	govtypes.RegisterProposalType(ProposalTypeTrackForeignChain)
+	govtypes.RegisterProposalTypeCodec(TrackForeignChainProposal{}, "wasm/TrackForeignChainProposal")
+}
+
+// TrackForeignChainProposal makes contracts able to verify the headers of another chain, from a
+// header of it that governance trusts. Once it passed, anyone can submit newer headers of the
+// chain with MsgSubmitForeignHeader. Tracking a chain again starts over from the new header, which
+// is how a chain whose last verified header is older than its trusting period is recovered.
+type TrackForeignChainProposal struct {
+	Title       string `json:"title" yaml:"title"`
+	Description string `json:"description" yaml:"description"`
+	// Header is trusted as it is
+	Header wasmTypes.BlockHeader `json:"header" yaml:"header"`
+	// TrustingPeriodSeconds is for how long after its time a verified header vouches for newer ones
+	TrustingPeriodSeconds uint64 `json:"trusting_period_seconds" yaml:"trusting_period_seconds"`
+}
+
+var _ govtypes.Content = TrackForeignChainProposal{}
+
+func (p TrackForeignChainProposal) GetTitle() string { return p.Title }
+
+func (p TrackForeignChainProposal) GetDescription() string { return p.Description }
+
+func (p TrackForeignChainProposal) ProposalRoute() string { return RouterKey }
+
+func (p TrackForeignChainProposal) ProposalType() string { return ProposalTypeTrackForeignChain }
+
+func (p TrackForeignChainProposal) ValidateBasic() error {
+	if err := govtypes.ValidateAbstract(p); err != nil {
+		return err
+	}
+	return validateForeignChainInit(p.ForeignChainInit())
+}
+
+func (p TrackForeignChainProposal) String() string {
+	return fmt.Sprintf(`Track Foreign Chain Proposal:
+  Title:           %s
+  Description:     %s
+  Chain ID:        %s
+  Height:          %d
+  Trusting Period: %ds
+`, p.Title, p.Description, p.Header.ChainID, p.Header.Height, p.TrustingPeriodSeconds)
+}
+
+// ForeignChainInit is what the enclave starts tracking the chain from
+func (p TrackForeignChainProposal) ForeignChainInit() wasmTypes.ForeignChainInit {
+	return wasmTypes.ForeignChainInit{
+		Header:                p.Header,
+		TrustingPeriodSeconds: p.TrustingPeriodSeconds,
+	}
+}
+
+func validateForeignChainInit(init wasmTypes.ForeignChainInit) error {
+	if err := validateForeignChainID(init.Header.ChainID); err != nil {
+		return err
+	}
+	if len(init.Header.ValidatorsHash) == 0 || len(init.Header.NextValidatorsHash) == 0 {
+		return sdkerrors.Wrap(ErrEmpty, "validators hash")
+	}
+	if init.Header.Time.Seconds < 0 {
+		return sdkerrors.Wrap(ErrInvalid, "header time")
+	}
+	if init.TrustingPeriodSeconds == 0 {
+		return sdkerrors.Wrap(ErrEmpty, "trusting period")
+	}
+	return nil
+}
+
+func validateForeignChainID(chainID string) error {
+	if len(chainID) == 0 {
+		return sdkerrors.Wrap(ErrEmpty, "chain id")
+	}
+	if len(chainID) > maxChainIDLen {
+		return sdkerrors.Wrap(ErrLimit, "chain id")
+	}
+	return nil
+}
+
+// NewForeignHeader translates a header of another chain for the enclave, with the validators that
+// signed it. trustedValidators are the validators the last header the enclave verified chose, which
+// are only needed for headers that skip blocks and may be nil otherwise
+func NewForeignHeader(signedHeader tmtypes.SignedHeader, validators *tmtypes.ValidatorSet, trustedValidators *tmtypes.ValidatorSet) *wasmTypes.ForeignHeader {
+	submission := NewBlockHeaderSubmission(signedHeader, validators)
+	if submission == nil {
+		return nil
+	}
+	foreignHeader := wasmTypes.ForeignHeader{
+		Header:     submission.Header,
+		Commit:     submission.Commit,
+		Validators: submission.Validators,
+	}
+
+	if trustedValidators != nil {
+		trusted := NewBlockHeaderSubmission(signedHeader, trustedValidators)
+		if trusted == nil {
+			return nil
+		}
+		foreignHeader.TrustedValidators = trusted.Validators
+	}
+	return &foreignHeader
+}
+
+// MsgSubmitForeignHeader hands a header of a tracked chain to the enclave, which verifies it
+// against the last header it verified for that chain. Newer headers are then verified against it,
+// so relayers keep the chain within its trusting period by submitting headers. Anyone may send it.
+type MsgSubmitForeignHeader struct {
+	Sender     sdk.AccAddress          `json:"sender" yaml:"sender"`
+	Submission wasmTypes.ForeignHeader `json:"submission" yaml:"submission"`
+}
+
+func (msg MsgSubmitForeignHeader) Route() string {
+	return RouterKey
+}
+
+func (msg MsgSubmitForeignHeader) Type() string {
+	return "submit-foreign-header"
+}
+
+func (msg MsgSubmitForeignHeader) ValidateBasic() error {
+	if err := sdk.VerifyAddressFormat(msg.Sender); err != nil {
+		return sdkerrors.Wrap(err, "sender")
+	}
+	if err := validateForeignChainID(msg.Submission.Header.ChainID); err != nil {
+		return err
+	}
+	if len(msg.Submission.Commit.Signatures) == 0 {
+		return sdkerrors.Wrap(ErrEmpty, "commit")
+	}
+	return nil
+}
+
+func (msg MsgSubmitForeignHeader) GetSignBytes() []byte {
+	return sdk.MustSortJSON(ModuleCdc.MustMarshalJSON(msg))
+}
+
+func (msg MsgSubmitForeignHeader) GetSigners() []sdk.AccAddress {
+	return []sdk.AccAddress{msg.Sender}
+}
@@ -19,18 +19,20 @@ func (s Sequence) ValidateBasic() error {
 
 // GenesisState is the struct representation of the export genesis
 type GenesisState struct {
-	// Params    Params     `json:"params"`
+	// Params are left out of genesis files that were exported before the module had any, and
+	// default to DefaultParams
+	Params    *Params    `json:"params,omitempty"`
 	Codes     []Code     `json:"codes,omitempty"`
 	Contracts []Contract `json:"contracts,omitempty"`
 	Sequences []Sequence `json:"sequences,omitempty"`
 }
 
 func (s GenesisState) ValidateBasic() error {
-	/*
+	if s.Params != nil {
 		if err := s.Params.ValidateBasic(); err != nil {
 			return sdkerrors.Wrap(err, "params")
 		}
-	*/
+	}
 	for i := range s.Codes {
 		if err := s.Codes[i].ValidateBasic(); err != nil {
 			return sdkerrors.Wrapf(err, "code: %d", i)
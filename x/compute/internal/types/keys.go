@@ -27,8 +27,24 @@ const ( // event attributes
 	AttributeKeyContract = "contract_address"
 	AttributeKeyCodeID   = "code_id"
 	AttributeKeySigner   = "signer"
+	// AttributeKeyKeyRotationDone is false while a contract key rotation has entries left to re-encrypt
+	AttributeKeyKeyRotationDone = "key_rotation_done"
+	// AttributeKeyImportedEntries is how many entries of the contract storage a state import wrote
+	AttributeKeyImportedEntries = "imported_entries"
+	// AttributeKeyForeignChainID and AttributeKeyForeignHeight are the chain and height of a header of
+	// another chain the enclave verified
+	AttributeKeyForeignChainID = "foreign_chain_id"
+	AttributeKeyForeignHeight  = "foreign_height"
+	// AttributeKeyGasWasm, AttributeKeyGasStorage and AttributeKeyGasOcall break down the gas used by an execution,
+	// in the units reported by the enclave
+	AttributeKeyGasWasm    = "gas_wasm"
+	AttributeKeyGasStorage = "gas_storage"
+	AttributeKeyGasOcall   = "gas_ocall"
 )
 
+// EventTypeGasBreakdown is emitted for every successful contract execution
+const EventTypeGasBreakdown = "gas_breakdown"
+
 // nolint
 var (
 	CodeKeyPrefix       = []byte{0x01}
@@ -38,6 +54,14 @@ var (
 	// ContractHistoryStorePrefix = []byte{0x05}
 	ContractEnclaveIdPrefix = []byte{0x06}
 	ContractLabelPrefix     = []byte{0x07}
+	// ContractKeyRotationCursorPrefix holds where an unfinished contract key rotation continues from
+	ContractKeyRotationCursorPrefix = []byte{0x08}
+	// ContractAdminProofPrefix holds the proof the enclave authenticates the admin of a contract with
+	ContractAdminProofPrefix = []byte{0x09}
+	// ContractStateSnapshotPrefix holds the height of the last snapshot imported into a contract
+	ContractStateSnapshotPrefix = []byte{0x0a}
+	// ForeignChainPrefix holds what the enclave trusts about the chains contracts verify headers of
+	ForeignChainPrefix = []byte{0x0b}
 
 	KeyLastCodeID     = append(SequenceKeyPrefix, []byte("lastCodeId")...)
 	KeyLastInstanceID = append(SequenceKeyPrefix, []byte("lastContractId")...)
@@ -63,6 +87,26 @@ func GetContractEnclaveKey(addr sdk.AccAddress) []byte {
 	return append(ContractEnclaveIdPrefix, addr...)
 }
 
+// GetContractKeyRotationCursorKey returns the key for the cursor of an unfinished contract key rotation
+func GetContractKeyRotationCursorKey(addr sdk.AccAddress) []byte {
+	return append(ContractKeyRotationCursorPrefix, addr...)
+}
+
+// GetContractAdminProofKey returns the key for the proof of the admin of the contract
+func GetContractAdminProofKey(addr sdk.AccAddress) []byte {
+	return append(ContractAdminProofPrefix, addr...)
+}
+
+// GetContractStateSnapshotKey returns the key for the height of the last snapshot imported into the contract
+func GetContractStateSnapshotKey(addr sdk.AccAddress) []byte {
+	return append(ContractStateSnapshotPrefix, addr...)
+}
+
+// GetForeignChainKey returns the key of what the enclave trusts about another chain
+func GetForeignChainKey(chainID string) []byte {
+	return append(ForeignChainPrefix, []byte(chainID)...)
+}
+
 // GetContractStorePrefixKey returns the store prefix for the WASM contract instance
 func GetContractStorePrefixKey(addr sdk.AccAddress) []byte {
 	return append(ContractStorePrefix, addr...)
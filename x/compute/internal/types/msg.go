@@ -62,7 +62,7 @@ func (msg MsgStoreCode) GetSigners() []sdk.AccAddress {
 type MsgInstantiateContract struct {
 	Sender sdk.AccAddress `json:"sender" yaml:"sender"`
 	// Admin is an optional address that can execute migrations
-	// Admin sdk.AccAddress `json:"admin,omitempty" yaml:"admin"`
+	Admin sdk.AccAddress `json:"admin,omitempty" yaml:"admin"`
 	// This field is only used for callbacks constructed with this message type
 	CallbackCodeHash  string    `json:"callback_code_hash" yaml:"callback_code_hash"`
 	CodeID            uint64    `json:"code_id" yaml:"code_id"`
@@ -97,16 +97,11 @@ func (msg MsgInstantiateContract) ValidateBasic() error {
 		return sdkerrors.ErrInvalidCoins
 	}
 
-	/*
-		if len(msg.Admin) != 0 {
-			if err := sdk.VerifyAddressFormat(msg.Admin); err != nil {
-				return err
-			}
+	if len(msg.Admin) != 0 {
+		if err := sdk.VerifyAddressFormat(msg.Admin); err != nil {
+			return err
 		}
-		if !json.Valid(msg.InitMsg) {
-			return sdkerrors.Wrap(ErrInvalid, "init msg json")
-		}
-	*/
+	}
 	return nil
 }
 
@@ -162,12 +157,49 @@ func (msg MsgExecuteContract) GetSigners() []sdk.AccAddress {
 	return []sdk.AccAddress{msg.Sender}
 }
 
-/*
+// MsgRotateContractKey re-encrypts the storage of a contract under a new contract key. Every message
+// moves up to ChunkSize entries, so a contract with a large storage is rotated over several messages.
+type MsgRotateContractKey struct {
+	Sender    sdk.AccAddress `json:"sender" yaml:"sender"`
+	Contract  sdk.AccAddress `json:"contract" yaml:"contract"`
+	ChunkSize uint32         `json:"chunk_size" yaml:"chunk_size"`
+}
+
+func (msg MsgRotateContractKey) Route() string {
+	return RouterKey
+}
+
+func (msg MsgRotateContractKey) Type() string {
+	return "rotate-contract-key"
+}
+
+func (msg MsgRotateContractKey) ValidateBasic() error {
+	if err := sdk.VerifyAddressFormat(msg.Sender); err != nil {
+		return sdkerrors.Wrap(err, "sender")
+	}
+	if err := sdk.VerifyAddressFormat(msg.Contract); err != nil {
+		return sdkerrors.Wrap(err, "contract")
+	}
+	if msg.ChunkSize == 0 {
+		return sdkerrors.Wrap(sdkerrors.ErrInvalidRequest, "chunk_size is required")
+	}
+	return nil
+}
+
+func (msg MsgRotateContractKey) GetSignBytes() []byte {
+	return sdk.MustSortJSON(ModuleCdc.MustMarshalJSON(msg))
+}
+
+func (msg MsgRotateContractKey) GetSigners() []sdk.AccAddress {
+	return []sdk.AccAddress{msg.Sender}
+}
+
 type MsgMigrateContract struct {
-	Sender     sdk.AccAddress  `json:"sender" yaml:"sender"`
-	Contract   sdk.AccAddress  `json:"contract" yaml:"contract"`
-	CodeID     uint64          `json:"code_id" yaml:"code_id"`
-	MigrateMsg json.RawMessage `json:"msg" yaml:"msg"`
+	Sender   sdk.AccAddress `json:"sender" yaml:"sender"`
+	Contract sdk.AccAddress `json:"contract" yaml:"contract"`
+	CodeID   uint64         `json:"code_id" yaml:"code_id"`
+	// MigrateMsg is encrypted for the contract, like the msg of MsgExecuteContract
+	MigrateMsg []byte `json:"msg" yaml:"msg"`
 }
 
 func (msg MsgMigrateContract) Route() string {
@@ -188,10 +220,9 @@ func (msg MsgMigrateContract) ValidateBasic() error {
 	if err := sdk.VerifyAddressFormat(msg.Contract); err != nil {
 		return sdkerrors.Wrap(err, "contract")
 	}
-	if !json.Valid(msg.MigrateMsg) {
-		return sdkerrors.Wrap(ErrInvalid, "migrate msg json")
+	if len(msg.MigrateMsg) == 0 {
+		return sdkerrors.Wrap(ErrEmpty, "migrate msg")
 	}
-
 	return nil
 }
 
@@ -271,4 +302,41 @@ func (msg MsgClearAdmin) GetSignBytes() []byte {
 func (msg MsgClearAdmin) GetSigners() []sdk.AccAddress {
 	return []sdk.AccAddress{msg.Sender}
 }
-*/
+
+// MsgImportContractState writes a chunk of an export of the contract's storage back to it. Only the
+// admin of the contract may send it.
+type MsgImportContractState struct {
+	Sender   sdk.AccAddress `json:"sender" yaml:"sender"`
+	Contract sdk.AccAddress `json:"contract" yaml:"contract"`
+	// Chunk is a chunk of the archive, as the export returned it
+	Chunk []byte `json:"chunk" yaml:"chunk"`
+}
+
+func (msg MsgImportContractState) Route() string {
+	return RouterKey
+}
+
+func (msg MsgImportContractState) Type() string {
+	return "import-contract-state"
+}
+
+func (msg MsgImportContractState) ValidateBasic() error {
+	if err := sdk.VerifyAddressFormat(msg.Sender); err != nil {
+		return sdkerrors.Wrap(err, "sender")
+	}
+	if err := sdk.VerifyAddressFormat(msg.Contract); err != nil {
+		return sdkerrors.Wrap(err, "contract")
+	}
+	if len(msg.Chunk) == 0 {
+		return sdkerrors.Wrap(ErrEmpty, "chunk")
+	}
+	return nil
+}
+
+func (msg MsgImportContractState) GetSignBytes() []byte {
+	return sdk.MustSortJSON(ModuleCdc.MustMarshalJSON(msg))
+}
+
+func (msg MsgImportContractState) GetSigners() []sdk.AccAddress {
+	return []sdk.AccAddress{msg.Sender}
+}
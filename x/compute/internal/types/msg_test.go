@@ -1,6 +1,7 @@
 package types
 
 import (
+	"bytes"
 	"strings"
 	"testing"
 
@@ -191,9 +192,20 @@ func TestInstantiateContractValidation(t *testing.T) {
 				Label:     "foo",
 				InitMsg:   []byte(`{"some": "data"}`),
 				InitFunds: sdk.Coins{sdk.Coin{Denom: "foobar", Amount: sdk.NewInt(200)}},
+				Admin:     goodAddress,
 			},
 			valid: true,
 		},
+		"bad admin": {
+			msg: MsgInstantiateContract{
+				Sender:  goodAddress,
+				CodeID:  1,
+				Label:   "foo",
+				InitMsg: []byte("{}"),
+				Admin:   badAddress,
+			},
+			valid: false,
+		},
 		"negative funds": {
 			msg: MsgInstantiateContract{
 				Sender:  goodAddress,
@@ -348,7 +360,6 @@ func TestExecuteContractValidation(t *testing.T) {
 	}
 }
 
-/*
 func TestMsgUpdateAdministrator(t *testing.T) {
 	badAddress, err := sdk.AccAddressFromHex("012345")
 	require.NoError(t, err)
@@ -526,15 +537,6 @@ func TestMsgMigrateContract(t *testing.T) {
 			},
 			expErr: true,
 		},
-		"non json migrateMsg": {
-			src: MsgMigrateContract{
-				Sender:     goodAddress,
-				Contract:   anotherGoodAddress,
-				CodeID:     1,
-				MigrateMsg: []byte("invalid json"),
-			},
-			expErr: true,
-		},
 		"empty migrateMsg": {
 			src: MsgMigrateContract{
 				Sender:   goodAddress,
@@ -555,4 +557,3 @@ func TestMsgMigrateContract(t *testing.T) {
 		})
 	}
 }
-*/
@@ -1,5 +1,190 @@
 package types
 
+import (
+	"encoding/json"
+	"fmt"
+	"math"
+
+	"gopkg.in/yaml.v2"
+
+	wasmTypes "github.com/enigmampc/SecretNetwork/go-cosmwasm/types"
+	paramtypes "github.com/enigmampc/cosmos-sdk/x/params/subspace"
+)
+
+const (
+	// DefaultParamspace for params keeper
+	DefaultParamspace = ModuleName
+)
+
+// Parameter keys
+var (
+	ParamStoreKeyOcallGasCosts  = []byte("OcallGasCosts")
+	ParamStoreKeySeedRotation   = []byte("SeedRotation")
+	ParamStoreKeyMaxMemoryPages = []byte("MaxMemoryPages")
+	ParamStoreKeyOutputLimits   = []byte("OutputLimits")
+	ParamStoreKeyStorageQuota   = []byte("StorageQuota")
+)
+
+const (
+	// LegacyMaxMemoryPages is the most memory contracts may grow to while MaxMemoryPages isn't set,
+	// in 64KiB pages
+	LegacyMaxMemoryPages = 192
+	// MaxMemoryPagesLimit is the most MaxMemoryPages may be set to, as the enclave can't give
+	// contracts more memory
+	MaxMemoryPagesLimit = 512
+	// MaxOutputBytesLimit is the most OutputLimits.MaxBytes may be set to. Encryption grows the
+	// output by about a third, and the enclave returns at most 4MiB.
+	MaxOutputBytesLimit = 3 * 1024 * 1024
+)
+
+// Params are the parameters of the compute module that the enclave gets in the env of every call.
+// They are changed with parameter change proposals.
+type Params struct {
+	// OcallGasCosts is the fixed gas the enclave charges for every round trip out of it
+	OcallGasCosts wasmTypes.OcallGasCosts `json:"ocall_gas_costs" yaml:"ocall_gas_costs"`
+	// SeedRotation is the signed rotation of the consensus seed that nodes hand to their enclave
+	// before its activation height, as the JSON the enclave reads. Empty when there is none.
+	SeedRotation string `json:"seed_rotation" yaml:"seed_rotation"`
+	// MaxMemoryPages is the most memory a contract may grow to, in 64KiB pages. 0 keeps the limit
+	// of LegacyMaxMemoryPages, so raising it is a change of the chain that all nodes make at the
+	// same height.
+	MaxMemoryPages uint32 `json:"max_memory_pages" yaml:"max_memory_pages"`
+	// OutputLimits is how many messages and bytes a contract may return from one call. A zero field
+	// keeps the default of the enclave, which is 128 messages in 2MiB.
+	OutputLimits wasmTypes.OutputLimits `json:"output_limits" yaml:"output_limits"`
+	// StorageQuota is the most bytes a contract may store, counting the keys and values it writes
+	// while the quota is set. 0 leaves the storage of contracts unlimited, and the enclave doesn't
+	// account for it then.
+	StorageQuota uint64 `json:"storage_quota" yaml:"storage_quota"`
+}
+
+// seedRotationHeader is the part of a seed rotation the chain reads. The enclave verifies the rest.
+type seedRotationHeader struct {
+	Generation       uint8  `json:"generation"`
+	ActivationHeight uint64 `json:"activation_height"`
+}
+
+// ParamKeyTable returns the parameter key table.
+func ParamKeyTable() paramtypes.KeyTable {
+	return paramtypes.NewKeyTable().RegisterParamSet(&Params{})
+}
+
+// DefaultParams returns the parameters the enclave uses when the chain doesn't set any
+func DefaultParams() Params {
+	return Params{
+		OcallGasCosts: wasmTypes.OcallGasCosts{
+			ReadDb:     2_000,
+			WriteDb:    4_000,
+			RemoveDb:   2_000,
+			QueryChain: 10_000,
+		},
+	}
+}
+
+func (p Params) String() string {
+	out, _ := yaml.Marshal(p)
+	return string(out)
+}
+
+// ParamSetPairs returns the parameter set pairs.
+func (p *Params) ParamSetPairs() paramtypes.ParamSetPairs {
+	return paramtypes.ParamSetPairs{
+		paramtypes.NewParamSetPair(ParamStoreKeyOcallGasCosts, &p.OcallGasCosts, validateOcallGasCosts),
+		paramtypes.NewParamSetPair(ParamStoreKeySeedRotation, &p.SeedRotation, validateSeedRotation),
+		paramtypes.NewParamSetPair(ParamStoreKeyMaxMemoryPages, &p.MaxMemoryPages, validateMaxMemoryPages),
+		paramtypes.NewParamSetPair(ParamStoreKeyOutputLimits, &p.OutputLimits, validateOutputLimits),
+		paramtypes.NewParamSetPair(ParamStoreKeyStorageQuota, &p.StorageQuota, validateStorageQuota),
+	}
+}
+
+// ValidateBasic performs basic validation on compute parameters.
+func (p Params) ValidateBasic() error {
+	if err := validateOcallGasCosts(p.OcallGasCosts); err != nil {
+		return err
+	}
+	if err := validateSeedRotation(p.SeedRotation); err != nil {
+		return err
+	}
+	if err := validateMaxMemoryPages(p.MaxMemoryPages); err != nil {
+		return err
+	}
+	if err := validateOutputLimits(p.OutputLimits); err != nil {
+		return err
+	}
+	return validateStorageQuota(p.StorageQuota)
+}
+
+// SeedRotationActivationHeight returns the height from which the enclave uses the seed of
+// rotation. ok is false when there is no rotation.
+func SeedRotationActivationHeight(rotation string) (height int64, ok bool, err error) {
+	if rotation == "" {
+		return 0, false, nil
+	}
+	var header seedRotationHeader
+	if err := json.Unmarshal([]byte(rotation), &header); err != nil {
+		return 0, false, fmt.Errorf("malformed seed rotation: %w", err)
+	}
+	if header.Generation == 0 {
+		return 0, false, fmt.Errorf("seed rotation to generation 0, which is the first seed")
+	}
+	if header.ActivationHeight == 0 || header.ActivationHeight > math.MaxInt64 {
+		return 0, false, fmt.Errorf("invalid activation height of seed rotation: %d", header.ActivationHeight)
+	}
+	return int64(header.ActivationHeight), true, nil
+}
+
+func validateOcallGasCosts(i interface{}) error {
+	v, ok := i.(wasmTypes.OcallGasCosts)
+	if !ok {
+		return fmt.Errorf("invalid parameter type: %T", i)
+	}
+	// a free ocall would let contracts make as many as they like
+	if v.ReadDb == 0 || v.WriteDb == 0 || v.RemoveDb == 0 || v.QueryChain == 0 {
+		return fmt.Errorf("ocall gas costs must not be zero: %+v", v)
+	}
+	return nil
+}
+
+func validateSeedRotation(i interface{}) error {
+	v, ok := i.(string)
+	if !ok {
+		return fmt.Errorf("invalid parameter type: %T", i)
+	}
+	_, _, err := SeedRotationActivationHeight(v)
+	return err
+}
+
+func validateMaxMemoryPages(i interface{}) error {
+	v, ok := i.(uint32)
+	if !ok {
+		return fmt.Errorf("invalid parameter type: %T", i)
+	}
+	// lowering the limit would break contracts that already use more memory
+	if v != 0 && (v < LegacyMaxMemoryPages || v > MaxMemoryPagesLimit) {
+		return fmt.Errorf("max memory pages must be 0 or between %d and %d: %d",
+			LegacyMaxMemoryPages, MaxMemoryPagesLimit, v)
+	}
+	return nil
+}
+
+func validateOutputLimits(i interface{}) error {
+	v, ok := i.(wasmTypes.OutputLimits)
+	if !ok {
+		return fmt.Errorf("invalid parameter type: %T", i)
+	}
+	if v.MaxBytes > MaxOutputBytesLimit {
+		return fmt.Errorf("max output bytes must be at most %d: %d", MaxOutputBytesLimit, v.MaxBytes)
+	}
+	return nil
+}
+
+func validateStorageQuota(i interface{}) error {
+	if _, ok := i.(uint64); !ok {
+		return fmt.Errorf("invalid parameter type: %T", i)
+	}
+	return nil
+}
+
 /*
 import (
 	"fmt"
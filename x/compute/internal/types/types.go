@@ -2,10 +2,14 @@ package types
 
 import (
 	"encoding/base64"
+	"encoding/json"
 
 	sdkerrors "github.com/enigmampc/cosmos-sdk/types/errors"
 	"github.com/enigmampc/cosmos-sdk/x/auth"
+	abci "github.com/tendermint/tendermint/abci/types"
+	"github.com/tendermint/tendermint/crypto/ed25519"
 	tmBytes "github.com/tendermint/tendermint/libs/bytes"
+	tmtypes "github.com/tendermint/tendermint/types"
 
 	wasmTypes "github.com/enigmampc/SecretNetwork/go-cosmwasm/types"
 	sdk "github.com/enigmampc/cosmos-sdk/types"
@@ -13,6 +17,8 @@ import (
 
 const defaultLRUCacheSize = uint64(0)
 const defaultQueryGasLimit = uint64(3000000)
+const defaultQueryTimeoutMs = uint64(10000)
+const defaultPrewarmTimeoutMs = uint64(30000)
 
 // base64 of a 64 byte key
 type ContractKey string
@@ -102,14 +108,17 @@ type ContractInfo struct {
 	// never show this in query results, just use for sorting
 	// (Note: when using json tag "-" amino refused to serialize it...)
 	Created *AbsoluteTxPosition `json:"created,omitempty"`
+	// Admin is the address that can migrate the contract, if any. It's the last field so that
+	// contract infos stored before it was added still decode.
+	Admin sdk.AccAddress `json:"admin,omitempty"`
 }
 
 // NewContractInfo creates a new instance of a given WASM contract info
-func NewContractInfo(codeID uint64, creator /* , admin */ sdk.AccAddress, label string, createdAt *AbsoluteTxPosition) ContractInfo {
+func NewContractInfo(codeID uint64, creator, admin sdk.AccAddress, label string, createdAt *AbsoluteTxPosition) ContractInfo {
 	return ContractInfo{
 		CodeID:  codeID,
 		Creator: creator,
-		// Admin:   admin,
+		Admin:   admin,
 		Label:   label,
 		Created: createdAt,
 	}
@@ -121,13 +130,11 @@ func (c *ContractInfo) ValidateBasic() error {
 	if err := sdk.VerifyAddressFormat(c.Creator); err != nil {
 		return sdkerrors.Wrap(err, "creator")
 	}
-	/*
-		if c.Admin != nil {
-			if err := sdk.VerifyAddressFormat(c.Admin); err != nil {
-				return sdkerrors.Wrap(err, "admin")
-			}
+	if c.Admin != nil {
+		if err := sdk.VerifyAddressFormat(c.Admin); err != nil {
+			return sdkerrors.Wrap(err, "admin")
 		}
-	*/
+	}
 	if err := validateLabel(c.Label); err != nil {
 		return sdkerrors.Wrap(err, "label")
 	}
@@ -210,9 +217,10 @@ func NewEnv(ctx sdk.Context, creator sdk.AccAddress, deposit sdk.Coins, contract
 	}
 	env := wasmTypes.Env{
 		Block: wasmTypes.BlockInfo{
-			Height:  uint64(ctx.BlockHeight()),
-			Time:    uint64(ctx.BlockTime().Unix()),
-			ChainID: ctx.ChainID(),
+			Height:    uint64(ctx.BlockHeight()),
+			Time:      uint64(ctx.BlockTime().Unix()),
+			TimeNanos: uint64(ctx.BlockTime().Nanosecond()),
+			ChainID:   ctx.ChainID(),
 		},
 		Message: wasmTypes.MessageInfo{
 			Sender:    creator.String(),
@@ -221,11 +229,106 @@ func NewEnv(ctx sdk.Context, creator sdk.AccAddress, deposit sdk.Coins, contract
 		Contract: wasmTypes.ContractInfo{
 			Address: contractAddr.String(),
 		},
-		Key: wasmTypes.ContractKey(base64.StdEncoding.EncodeToString(contractKey)),
+		Key:          wasmTypes.ContractKey(base64.StdEncoding.EncodeToString(contractKey)),
+		Bech32Prefix: sdk.GetConfig().GetBech32AccountAddrPrefix(),
+		BlockHeader:  NewBlockHeader(ctx.BlockHeader()),
+		// Txs are simulated on the state of CheckTx
+		Simulation: ctx.IsCheckTx(),
 	}
 	return env
 }
 
+// NewBlockHeader translates the header of a block for the enclave. Headers without a validators hash
+// don't have a hash in Tendermint, so they're left out
+func NewBlockHeader(header abci.Header) *wasmTypes.BlockHeader {
+	if len(header.ValidatorsHash) == 0 {
+		return nil
+	}
+	return &wasmTypes.BlockHeader{
+		Version: wasmTypes.HeaderVersion{
+			Block: header.Version.Block,
+			App:   header.Version.App,
+		},
+		ChainID: header.ChainID,
+		Height:  uint64(header.Height),
+		Time: wasmTypes.HeaderTime{
+			Seconds: header.Time.Unix(),
+			Nanos:   int32(header.Time.Nanosecond()),
+		},
+		LastBlockID: wasmTypes.BlockID{
+			Hash: header.LastBlockId.Hash,
+			Parts: wasmTypes.PartSetHeader{
+				Total: uint32(header.LastBlockId.PartsHeader.Total),
+				Hash:  header.LastBlockId.PartsHeader.Hash,
+			},
+		},
+		LastCommitHash:     header.LastCommitHash,
+		DataHash:           header.DataHash,
+		ValidatorsHash:     header.ValidatorsHash,
+		NextValidatorsHash: header.NextValidatorsHash,
+		ConsensusHash:      header.ConsensusHash,
+		AppHash:            header.AppHash,
+		LastResultsHash:    header.LastResultsHash,
+		EvidenceHash:       header.EvidenceHash,
+		ProposerAddress:    header.ProposerAddress,
+	}
+}
+
+// NewBlockHeaderSubmission converts a header, the commit that signed it and the validators of the
+// header for api.SubmitBlockHeader. It returns nil for headers that Tendermint doesn't hash, or if
+// a validator doesn't have an ed25519 key, as the enclave only checks ed25519 signatures
+func NewBlockHeaderSubmission(signedHeader tmtypes.SignedHeader, validators *tmtypes.ValidatorSet) *wasmTypes.BlockHeaderSubmission {
+	if signedHeader.Header == nil || signedHeader.Commit == nil {
+		return nil
+	}
+	header := NewBlockHeader(tmtypes.TM2PB.Header(signedHeader.Header))
+	if header == nil {
+		return nil
+	}
+
+	commit := signedHeader.Commit
+	submission := wasmTypes.BlockHeaderSubmission{
+		Header: *header,
+		Commit: wasmTypes.Commit{
+			Height: uint64(commit.Height),
+			Round:  uint32(commit.Round),
+			BlockID: wasmTypes.BlockID{
+				Hash: commit.BlockID.Hash,
+				Parts: wasmTypes.PartSetHeader{
+					Total: uint32(commit.BlockID.PartsHeader.Total),
+					Hash:  commit.BlockID.PartsHeader.Hash,
+				},
+			},
+		},
+	}
+
+	for _, sig := range commit.Signatures {
+		submission.Commit.Signatures = append(submission.Commit.Signatures, wasmTypes.CommitSig{
+			BlockIDFlag:      uint8(sig.BlockIDFlag),
+			ValidatorAddress: sig.ValidatorAddress,
+			Timestamp: wasmTypes.HeaderTime{
+				Seconds: sig.Timestamp.Unix(),
+				Nanos:   int32(sig.Timestamp.Nanosecond()),
+			},
+			Signature: sig.Signature,
+		})
+	}
+
+	for _, validator := range validators.Validators {
+		pubKey, ok := validator.PubKey.(ed25519.PubKeyEd25519)
+		if !ok {
+			return nil
+		}
+		submission.Validators = append(submission.Validators, wasmTypes.Validator{
+			Address:     validator.Address,
+			PubKey:      pubKey[:],
+			VotingPower: validator.VotingPower,
+		})
+	}
+
+	return &submission
+}
+
 // NewWasmCoins translates between Cosmos SDK coins and Wasm coins
 func NewWasmCoins(cosmosCoins sdk.Coins) (wasmCoins []wasmTypes.Coin) {
 	for _, coin := range cosmosCoins {
@@ -240,14 +343,22 @@ func NewWasmCoins(cosmosCoins sdk.Coins) (wasmCoins []wasmTypes.Coin) {
 
 const CustomEventType = "wasm"
 const AttributeKeyContractAddr = "contract_address"
+const AttributeKeyExecutionPath = "execution_path"
 
-// ParseEvents converts wasm LogAttributes into an sdk.Events (with 0 or 1 elements)
-func ParseEvents(logs []wasmTypes.LogAttribute, contractAddr sdk.AccAddress) sdk.Events {
+// ParseEvents converts wasm LogAttributes into an sdk.Events (with 0 or 1 elements). executionPath
+// is the path the enclave bound the encrypted attributes to, which the event is tagged with unless
+// it's nil, so clients can verify and order the events of a tx
+func ParseEvents(logs []wasmTypes.LogAttribute, contractAddr sdk.AccAddress, executionPath []uint32) sdk.Events {
 	if len(logs) == 0 {
 		return nil
 	}
 	// we always tag with the contract address issuing this event
 	attrs := []sdk.Attribute{sdk.NewAttribute(AttributeKeyContractAddr, contractAddr.String())}
+	if executionPath != nil {
+		// a []uint32 always marshals
+		path, _ := json.Marshal(executionPath)
+		attrs = append(attrs, sdk.NewAttribute(AttributeKeyExecutionPath, string(path)))
+	}
 	for _, l := range logs {
 		// and reserve the contract_address key for our use (not contract)
 		if l.Key != AttributeKeyContractAddr {
@@ -262,6 +373,26 @@ func ParseEvents(logs []wasmTypes.LogAttribute, contractAddr sdk.AccAddress) sdk
 type WasmConfig struct {
 	SmartQueryGasLimit uint64 `mapstructure:"query_gas_limit"`
 	CacheSize          uint64 `mapstructure:"lru_size"`
+	// QueryTimeoutMs stops queries that clients send to the node after they ran for that many
+	// milliseconds, on top of their gas limit. Txs, and the queries contracts make during them,
+	// are never stopped this way, as nodes would disagree on their result. 0 disables it
+	QueryTimeoutMs uint64 `mapstructure:"query_timeout_ms"`
+	// PrewarmMaxBytes is how much contract code is validated and cached in the enclave when the node
+	// starts, so that the first calls to contracts after a restart aren't slower. 0 disables it
+	PrewarmMaxBytes uint64 `mapstructure:"prewarm_max_bytes"`
+	// PrewarmTimeoutMs stops prewarming contracts after that many milliseconds
+	PrewarmTimeoutMs uint64 `mapstructure:"prewarm_timeout_ms"`
+	// EnclaveLogLevel is the most verbose level the enclave logs at: off, error, warn, info, debug
+	// or trace. Empty keeps the level the enclave starts with
+	EnclaveLogLevel string `mapstructure:"enclave_log_level"`
+	// MaxContractSize is the largest contract, in bytes, the enclave accepts. 0 keeps its limit
+	MaxContractSize uint64 `mapstructure:"max_contract_size"`
+	// QueryCacheSize is how many bytes of query results the enclave caches within a block. 0 leaves
+	// the cache off
+	QueryCacheSize uint64 `mapstructure:"query_cache_size"`
+	// EcallTracing makes the enclave trace its ecalls, which the ecall-traces query of the node
+	// returns. Tracing slows the enclave down, so it's off by default
+	EcallTracing bool `mapstructure:"ecall_tracing"`
 }
 
 // DefaultWasmConfig returns the default settings for WasmConfig
@@ -269,6 +400,8 @@ func DefaultWasmConfig() WasmConfig {
 	return WasmConfig{
 		SmartQueryGasLimit: defaultQueryGasLimit,
 		CacheSize:          defaultLRUCacheSize,
+		QueryTimeoutMs:     defaultQueryTimeoutMs,
+		PrewarmTimeoutMs:   defaultPrewarmTimeoutMs,
 	}
 }
 
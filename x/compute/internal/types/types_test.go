@@ -1,11 +1,19 @@
 package types
 
 import (
+	"bytes"
+	"encoding/hex"
 	"strings"
 	"testing"
+	"time"
 
 	sdk "github.com/enigmampc/cosmos-sdk/types"
 	"github.com/stretchr/testify/require"
+	abci "github.com/tendermint/tendermint/abci/types"
+	tmtypes "github.com/tendermint/tendermint/types"
+	"github.com/tendermint/tendermint/version"
+
+	wasmTypes "github.com/enigmampc/SecretNetwork/go-cosmwasm/types"
 )
 
 func TestContractInfoValidateBasic(t *testing.T) {
@@ -122,3 +130,110 @@ func TestCodeInfoValidateBasic(t *testing.T) {
 		})
 	}
 }
+
+// TestBlockHeaderHash checks the header the enclave hashes in test_header_hash against Tendermint
+func TestBlockHeaderHash(t *testing.T) {
+	header := abci.Header{
+		Version: abci.Version{Block: 10},
+		ChainID: "secret-testnet",
+		Height:  100,
+		Time:    time.Unix(1600000600, 123456789).UTC(),
+		LastBlockId: abci.BlockID{
+			Hash:        bytes.Repeat([]byte{0x11}, 32),
+			PartsHeader: abci.PartSetHeader{Total: 1, Hash: bytes.Repeat([]byte{0x22}, 32)},
+		},
+		LastCommitHash:     bytes.Repeat([]byte{0x33}, 32),
+		ValidatorsHash:     bytes.Repeat([]byte{0x44}, 32),
+		NextValidatorsHash: bytes.Repeat([]byte{0x44}, 32),
+		ConsensusHash:      bytes.Repeat([]byte{0x55}, 32),
+		AppHash:            bytes.Repeat([]byte{0x66}, 32),
+		ProposerAddress:    bytes.Repeat([]byte{0x77}, 20),
+	}
+
+	wasmHeader := NewBlockHeader(header)
+	require.NotNil(t, wasmHeader)
+	require.Equal(t, uint64(100), wasmHeader.Height)
+	require.Equal(t, int64(1600000600), wasmHeader.Time.Seconds)
+	require.Equal(t, int32(123456789), wasmHeader.Time.Nanos)
+	require.Equal(t, uint32(1), wasmHeader.LastBlockID.Parts.Total)
+
+	tmHeader := tmtypes.Header{
+		Version: version.Consensus{
+			Block: version.Protocol(wasmHeader.Version.Block),
+			App:   version.Protocol(wasmHeader.Version.App),
+		},
+		ChainID: wasmHeader.ChainID,
+		Height:  int64(wasmHeader.Height),
+		Time:    time.Unix(wasmHeader.Time.Seconds, int64(wasmHeader.Time.Nanos)).UTC(),
+		LastBlockID: tmtypes.BlockID{
+			Hash: wasmHeader.LastBlockID.Hash,
+			PartsHeader: tmtypes.PartSetHeader{
+				Total: int(wasmHeader.LastBlockID.Parts.Total),
+				Hash:  wasmHeader.LastBlockID.Parts.Hash,
+			},
+		},
+		LastCommitHash:     wasmHeader.LastCommitHash,
+		DataHash:           wasmHeader.DataHash,
+		ValidatorsHash:     wasmHeader.ValidatorsHash,
+		NextValidatorsHash: wasmHeader.NextValidatorsHash,
+		ConsensusHash:      wasmHeader.ConsensusHash,
+		AppHash:            wasmHeader.AppHash,
+		LastResultsHash:    wasmHeader.LastResultsHash,
+		EvidenceHash:       wasmHeader.EvidenceHash,
+		ProposerAddress:    wasmHeader.ProposerAddress,
+	}
+	require.Equal(t, "057a1cb16ca5bdb2b9811534ed685688edc4201b8b4411774c4de117f1ff5db3", hex.EncodeToString(tmHeader.Hash()))
+
+	// Headers that Tendermint doesn't hash aren't passed to the enclave
+	require.Nil(t, NewBlockHeader(abci.Header{ChainID: "secret-testnet", Height: 100}))
+}
+
+func TestParseEventsExecutionPath(t *testing.T) {
+	contract := sdk.AccAddress(bytes.Repeat([]byte{0x01}, 20))
+	logs := []wasmTypes.LogAttribute{
+		{Key: "action", Value: "transfer"},
+		{Key: AttributeKeyContractAddr, Value: "spoofed"},
+		{Key: "amount", Value: "10"},
+	}
+
+	specs := map[string]struct {
+		path     []uint32
+		expAttrs []sdk.Attribute
+	}{
+		"no path": {
+			path: nil,
+			expAttrs: []sdk.Attribute{
+				sdk.NewAttribute(AttributeKeyContractAddr, contract.String()),
+				sdk.NewAttribute("action", "transfer"),
+				sdk.NewAttribute("amount", "10"),
+			},
+		},
+		"root": {
+			path: []uint32{},
+			expAttrs: []sdk.Attribute{
+				sdk.NewAttribute(AttributeKeyContractAddr, contract.String()),
+				sdk.NewAttribute(AttributeKeyExecutionPath, "[]"),
+				sdk.NewAttribute("action", "transfer"),
+				sdk.NewAttribute("amount", "10"),
+			},
+		},
+		"reply to a submessage of a submessage": {
+			path: []uint32{1, 1},
+			expAttrs: []sdk.Attribute{
+				sdk.NewAttribute(AttributeKeyContractAddr, contract.String()),
+				sdk.NewAttribute(AttributeKeyExecutionPath, "[1,1]"),
+				sdk.NewAttribute("action", "transfer"),
+				sdk.NewAttribute("amount", "10"),
+			},
+		},
+	}
+	for msg, spec := range specs {
+		t.Run(msg, func(t *testing.T) {
+			events := ParseEvents(logs, contract, spec.path)
+			require.Len(t, events, 1)
+			require.Equal(t, sdk.NewEvent(CustomEventType, spec.expAttrs...), events[0])
+		})
+	}
+
+	require.Nil(t, ParseEvents(nil, contract, []uint32{0}))
+}
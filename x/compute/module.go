@@ -37,8 +37,9 @@ func (AppModuleBasic) RegisterCodec(cdc *codec.Codec) {
 // DefaultGenesis returns default genesis state as raw bytes for the compute
 // module.
 func (AppModuleBasic) DefaultGenesis() json.RawMessage {
+	params := DefaultParams()
 	return ModuleCdc.MustMarshalJSON(&GenesisState{
-		// Params: DefaultParams(),
+		Params: &params,
 	})
 }
 
@@ -130,7 +131,10 @@ func (am AppModule) ExportGenesis(ctx sdk.Context) json.RawMessage {
 }
 
 // BeginBlock returns the begin blocker for the compute module.
-func (am AppModule) BeginBlock(_ sdk.Context, _ abci.RequestBeginBlock) {}
+func (am AppModule) BeginBlock(ctx sdk.Context, _ abci.RequestBeginBlock) {
+	am.keeper.SubmitSeedRotation(ctx)
+	am.keeper.SubmitBlockHeaders(ctx)
+}
 
 // EndBlock returns the end blocker for the compute module. It returns no validator
 // updates.
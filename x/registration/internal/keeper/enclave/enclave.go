@@ -10,6 +10,6 @@ func (Api) LoadSeed(masterCert []byte, seed []byte) (bool, error) {
 	return api.LoadSeedToEnclave(masterCert, seed)
 }
 
-func (Api) GetEncryptedSeed(masterCert []byte) ([]byte, error) {
-	return api.GetEncryptedSeed(masterCert)
+func (Api) GetEncryptedSeed(masterCert []byte, blockTime uint64) ([]byte, error) {
+	return api.GetEncryptedSeed(masterCert, blockTime)
 }
@@ -2,5 +2,7 @@ package keeper
 
 type EnclaveInterface interface {
 	LoadSeed(masterCert []byte, seed []byte) (bool, error)
-	GetEncryptedSeed(masterCert []byte) ([]byte, error)
+	// GetEncryptedSeed authenticates the node with the certificate it registers with, in a block at
+	// blockTime, in seconds since the epoch
+	GetEncryptedSeed(masterCert []byte, blockTime uint64) ([]byte, error)
 }
@@ -97,7 +97,9 @@ func (k Keeper) RegisterNode(ctx sdk.Context, certificate ra.Certificate) ([]byt
 			return k.getRegistrationInfo(ctx, publicKey).EncryptedSeed, nil
 		}
 		log.Debug("After getRegistrationInfo")
-		encSeed, err = k.enclave.GetEncryptedSeed(certificate)
+		// the collateral of the certificate is checked at the time of the block, which every node
+		// agrees on, unlike anything the enclave of this node learned on its own
+		encSeed, err = k.enclave.GetEncryptedSeed(certificate, uint64(ctx.BlockTime().Unix()))
 		log.Debug("After GetEncryptedSeed")
 		if err != nil {
 			// return 0, sdkerrors.Wrap(err, "cosmwasm create")
@@ -1,8 +1,11 @@
 package keeper
 
 import (
+	"crypto/sha256"
+	"errors"
 	sdk "github.com/enigmampc/cosmos-sdk/types"
 	eng "github.com/enigmampc/SecretNetwork/types"
+	"github.com/enigmampc/SecretNetwork/x/registration/internal/keeper/mock"
 	"github.com/enigmampc/SecretNetwork/x/registration/internal/types"
 	ra "github.com/enigmampc/SecretNetwork/x/registration/remote_attestation"
 	"github.com/stretchr/testify/require"
@@ -10,6 +13,7 @@ import (
 	"os"
 	"path/filepath"
 	"testing"
+	"time"
 )
 
 func init() {
@@ -91,3 +95,60 @@ func TestKeeper_RegisterNode(t *testing.T) {
 	require.NoError(t, err)
 
 }
+
+// collateralEnclave checks the collateral of a registration at the time it's given, like the
+// enclave does. Nothing it saw before changes the result.
+type collateralEnclave struct {
+	mock.MockEnclaveApi
+	nextUpdate uint64
+	checkedAt  []uint64
+}
+
+func (e *collateralEnclave) GetEncryptedSeed(cert []byte, blockTime uint64) ([]byte, error) {
+	e.checkedAt = append(e.checkedAt, blockTime)
+	if blockTime >= e.nextUpdate {
+		return nil, errors.New("the collateral is due to be updated")
+	}
+	seed := sha256.Sum256(cert)
+	return seed[:], nil
+}
+
+func TestKeeper_RegisterNodeAtBlockTime(t *testing.T) {
+	cert, err := ioutil.ReadFile("../../testdata/attestation_cert_sw")
+	require.NoError(t, err)
+
+	blockTime := time.Unix(1_715_299_200, 0)
+	nextUpdate := uint64(blockTime.Add(time.Hour).Unix())
+
+	// a node that just started, and one whose enclave already checked registrations in other blocks
+	register := func(enclave *collateralEnclave, at time.Time) ([]byte, error) {
+		tempDir, err := ioutil.TempDir("", "reg")
+		require.NoError(t, err)
+		defer os.RemoveAll(tempDir)
+		ctx, regKeeper := CreateTestInput(t, false, tempDir, true)
+		regKeeper.enclave = enclave
+		return regKeeper.RegisterNode(ctx.WithBlockTime(at), cert)
+	}
+	restarted := &collateralEnclave{nextUpdate: nextUpdate}
+	running := &collateralEnclave{nextUpdate: nextUpdate}
+	for _, earlier := range []time.Time{blockTime.Add(-time.Hour), blockTime.Add(-time.Minute)} {
+		_, err = register(running, earlier)
+		require.NoError(t, err)
+	}
+
+	// both check the collateral at the time of the block, and agree
+	restartedSeed, err := register(restarted, blockTime)
+	require.NoError(t, err)
+	runningSeed, err := register(running, blockTime)
+	require.NoError(t, err)
+	require.Equal(t, restartedSeed, runningSeed)
+	require.Equal(t, uint64(blockTime.Unix()), restarted.checkedAt[len(restarted.checkedAt)-1])
+	require.Equal(t, uint64(blockTime.Unix()), running.checkedAt[len(running.checkedAt)-1])
+
+	// and once the collateral is stale on chain, both refuse it
+	stale := blockTime.Add(2 * time.Hour)
+	_, err = register(restarted, stale)
+	require.True(t, types.ErrAuthenticateFailed.Is(err), err)
+	_, err = register(running, stale)
+	require.True(t, types.ErrAuthenticateFailed.Is(err), err)
+}
@@ -8,6 +8,6 @@ func (MockEnclaveApi) LoadSeed(masterCert []byte, seed []byte) (bool, error) {
 	return true, nil
 }
 
-func (MockEnclaveApi) GetEncryptedSeed(masterCert []byte) ([]byte, error) {
+func (MockEnclaveApi) GetEncryptedSeed(masterCert []byte, blockTime uint64) ([]byte, error) {
 	return []byte(""), nil
 }